@@ -0,0 +1,341 @@
+//! Control socket between the CLI and the process serving a mount.
+//!
+//! Each serving process listens on a Unix socket in the runtime state
+//! directory, or on a named pipe on Windows, named after its mount point
+//! like its other state files. A client connects, writes one
+//! [`ControlRequest`] as a line of JSON and reads one [`ControlResponse`]
+//! line back:
+//!
+//! ```text
+//! > {"version":1,"request":{"request":"configure","compression":true}}
+//! < {"version":1,"response":{"response":"configured","eviction_policy":"Lru","compression":true}}
+//! ```
+//!
+//! Both lines carry the [`PROTOCOL_VERSION`] they were written with, and a
+//! process answers a request of another version with an error naming its
+//! own, so a CLI and a daemon from different releases fail clearly instead
+//! of misreading each other. Within a version, fields may only be added
+//! with a default.
+
+use crate::daemon::{InspectAnswer, InspectQuery, MountStateFiles};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use shadowfs_core::handles::HandleSummary;
+use shadowfs_core::override_store::EvictionPolicy;
+use shadowfs_core::stats::MountResources;
+use shadowfs_core::types::MountRecord;
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Version of the request and response schema.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// How long a client waits for the serving process to answer.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request line a serving process reads.
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// What a client asks the serving process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// The mount record, current resources and store settings
+    Status,
+    
+    /// One of the reports of `shadowfs debug`, `shadowfs gc` and
+    /// `shadowfs stats`
+    Inspect { query: InspectQuery },
+    
+    /// Change store settings of the running mount; settings left out stay
+    Configure {
+        #[serde(default)]
+        eviction_policy: Option<EvictionPolicy>,
+        
+        #[serde(default)]
+        compression: Option<bool>,
+    },
+    
+    /// Checkpoint the write-ahead log into the snapshot now
+    Snapshot,
+    
+    /// Unmount and exit
+    Unmount,
+}
+
+/// Answer of the serving process to a [`ControlRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum ControlResponse {
+    /// State of the mount
+    Status(Box<MountStatus>),
+    
+    /// Report answering the query
+    Inspected { answer: InspectAnswer },
+    
+    /// Store settings after the change
+    Configured { eviction_policy: EvictionPolicy, compression: bool },
+    
+    /// Overrides written to the snapshot
+    Snapshotted { entries: usize },
+    
+    /// The process is unmounting and will exit
+    Unmounting,
+    
+    /// Why the request was not carried out
+    Error { message: String },
+}
+
+/// State of a running mount, answering [`ControlRequest::Status`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountStatus {
+    /// Record the mount was started with
+    pub record: MountRecord,
+    
+    /// Resources of the serving process, sampled for this answer
+    pub resources: MountResources,
+    
+    /// Policy choosing what is evicted under memory pressure
+    pub eviction_policy: EvictionPolicy,
+    
+    /// Whether large overrides are compressed
+    pub compression: bool,
+    
+    /// Handles open on the mount
+    #[serde(default)]
+    pub handles: HandleSummary,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RequestFrame {
+    version: u32,
+    request: ControlRequest,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResponseFrame {
+    version: u32,
+    response: ControlResponse,
+}
+
+/// Read first, so frames of another version are recognised even when the
+/// rest of them does not parse.
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+/// Answers control requests for the mount `state` belongs to with
+/// `handler` until the task is aborted.
+pub async fn serve<H, F>(state: &MountStateFiles, handler: H) -> Result<()>
+where
+    H: Fn(ControlRequest) -> F + Clone + Send + 'static,
+    F: Future<Output = ControlResponse> + Send + 'static,
+{
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        
+        // A socket left behind by a process that did not exit cleanly
+        // would make the bind fail
+        let _ = std::fs::remove_file(&state.control_socket);
+        let listener = tokio::net::UnixListener::bind(&state.control_socket)
+            .with_context(|| format!("Failed to listen on {}", state.control_socket.display()))?;
+        std::fs::set_permissions(&state.control_socket, std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to restrict {}", state.control_socket.display()))?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            shadowfs_core::task::spawn("control-connection", answer(stream, handler.clone()));
+        }
+    }
+    
+    #[cfg(windows)]
+    {
+        use tokio::net::windows::named_pipe::ServerOptions;
+        
+        let name = pipe_name(state);
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&name)
+            .with_context(|| format!("Failed to create pipe {}", name))?;
+        loop {
+            server.connect().await?;
+            // The next instance is created before this one is handed off,
+            // so a client never finds no pipe to connect to
+            let connected = std::mem::replace(&mut server, ServerOptions::new().create(&name)?);
+            shadowfs_core::task::spawn("control-connection", answer(connected, handler.clone()));
+        }
+    }
+    
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (state, handler);
+        anyhow::bail!("The control socket is not supported on this platform")
+    }
+}
+
+/// Reads one request from `stream` and writes the answer of `handler`.
+async fn answer<S, H, F>(stream: S, handler: H)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Fn(ControlRequest) -> F,
+    F: Future<Output = ControlResponse>,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut line = String::new();
+    if let Err(e) = BufReader::new(reader).take(MAX_REQUEST_BYTES).read_line(&mut line).await {
+        tracing::warn!("Failed to read a control request: {}", e);
+        return;
+    }
+    let response = match parse_request(&line) {
+        Ok(request) => handler(request).await,
+        Err(message) => ControlResponse::Error { message },
+    };
+    if let Err(e) = write_frame(&mut writer, &ResponseFrame { version: PROTOCOL_VERSION, response }).await {
+        tracing::warn!("Failed to answer a control request: {}", e);
+    }
+}
+
+fn parse_request(line: &str) -> std::result::Result<ControlRequest, String> {
+    let version: Version = serde_json::from_str(line).map_err(|e| format!("Malformed request: {}", e))?;
+    if version.version != PROTOCOL_VERSION {
+        return Err(format!(
+            "Request uses control protocol version {}, but this process speaks version {}",
+            version.version, PROTOCOL_VERSION
+        ));
+    }
+    let frame: RequestFrame = serde_json::from_str(line).map_err(|e| format!("Malformed request: {}", e))?;
+    Ok(frame.request)
+}
+
+/// Sends `request` to the process serving the mount `state` belongs to.
+///
+/// Returns `None` if no process is listening, such as when it has exited.
+pub async fn request(state: &MountStateFiles, request: ControlRequest) -> Result<Option<ControlResponse>> {
+    let exchange = async {
+        let Some(stream) = connect(state).await? else {
+            return Ok(None);
+        };
+        let (reader, mut writer) = tokio::io::split(stream);
+        write_frame(&mut writer, &RequestFrame { version: PROTOCOL_VERSION, request }).await?;
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await?;
+        let version: Version = serde_json::from_str(&line).context("Malformed answer from the serving process")?;
+        if version.version != PROTOCOL_VERSION {
+            anyhow::bail!(
+                "The serving process speaks control protocol version {}, but this shadowfs speaks version {}; \
+                 remount with the same release",
+                version.version,
+                PROTOCOL_VERSION
+            );
+        }
+        let frame: ResponseFrame = serde_json::from_str(&line).context("Malformed answer from the serving process")?;
+        Ok(Some(frame.response))
+    };
+    tokio::time::timeout(CONTROL_TIMEOUT, exchange)
+        .await
+        .context("The serving process did not answer")?
+}
+
+async fn write_frame<W: AsyncWrite + Unpin, T: Serialize>(writer: &mut W, frame: &T) -> std::io::Result<()> {
+    let mut data = serde_json::to_vec(frame)?;
+    data.push(b'\n');
+    writer.write_all(&data).await?;
+    writer.shutdown().await
+}
+
+#[cfg(unix)]
+async fn connect(state: &MountStateFiles) -> Result<Option<tokio::net::UnixStream>> {
+    match tokio::net::UnixStream::connect(&state.control_socket).await {
+        Ok(stream) => Ok(Some(stream)),
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused) => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to connect to {}", state.control_socket.display())),
+    }
+}
+
+#[cfg(windows)]
+async fn connect(state: &MountStateFiles) -> Result<Option<tokio::net::windows::named_pipe::NamedPipeClient>> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+    
+    /// Returned while every instance of the pipe is serving another client
+    const ERROR_PIPE_BUSY: i32 = 231;
+    
+    let name = pipe_name(state);
+    loop {
+        match ClientOptions::new().open(&name) {
+            Ok(client) => return Ok(Some(client)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to connect to {}", name)),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn connect(_state: &MountStateFiles) -> Result<Option<tokio::io::DuplexStream>> {
+    Ok(None)
+}
+
+/// Returns the named pipe standing in for the control socket on Windows,
+/// where pipes live in a namespace of their own.
+#[cfg(windows)]
+fn pipe_name(state: &MountStateFiles) -> String {
+    let stem = state.control_socket.file_stem().unwrap_or_default().to_string_lossy();
+    format!(r"\\.\pipe\shadowfs-{}", stem)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    
+    #[tokio::test]
+    async fn test_request_round_trip() {
+        let dir = std::env::temp_dir().join(format!("shadowfs-control-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut state = MountStateFiles::for_mount_point(Path::new("/mnt/control"));
+        state.control_socket = dir.join("control.sock");
+        
+        assert!(request(&state, ControlRequest::Snapshot).await.unwrap().is_none());
+        
+        let server = tokio::spawn({
+            let state = state.clone();
+            async move {
+                serve(&state, |request| async move {
+                    match request {
+                        ControlRequest::Configure { eviction_policy, compression } => ControlResponse::Configured {
+                            eviction_policy: eviction_policy.unwrap_or(EvictionPolicy::Lru),
+                            compression: compression.unwrap_or(false),
+                        },
+                        _ => ControlResponse::Unmounting,
+                    }
+                })
+                .await
+            }
+        });
+        while !state.control_socket.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        
+        let configure = ControlRequest::Configure { eviction_policy: Some(EvictionPolicy::TwoQueue), compression: None };
+        let response = request(&state, configure).await.unwrap().unwrap();
+        assert!(matches!(
+            response,
+            ControlResponse::Configured { eviction_policy: EvictionPolicy::TwoQueue, compression: false }
+        ));
+        
+        // A request of another version is refused with the version spoken
+        let mut stream = tokio::net::UnixStream::connect(&state.control_socket).await.unwrap();
+        stream.write_all(b"{\"version\":2,\"request\":{\"request\":\"anything\"}}\n").await.unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).await.unwrap();
+        let frame: ResponseFrame = serde_json::from_str(&line).unwrap();
+        assert!(matches!(frame.response, ControlResponse::Error { message } if message.contains("version 1")));
+        
+        server.abort();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,533 @@
+//! Background mount processes and the state files used to find them.
+//!
+//! Each mount is served by one `shadowfs mount` process. While it runs, the
+//! process keeps a JSON [`MountRecord`] and a pidfile in the runtime state
+//! directory, where `shadowfs status` and `shadowfs unmount` find it, and
+//! answers requests on a control socket next to them (see
+//! [`crate::control`]): for its status, the reports of `shadowfs debug`,
+//! `shadowfs gc` and `shadowfs stats`, setting changes, snapshots and
+//! unmounting. The override store of each mount is logged to a write-ahead
+//! log next to the record, which is checkpointed into a snapshot as it
+//! grows, so other commands can inspect its overrides without talking to
+//! the serving process, and the process periodically writes a resource
+//! report that `shadowfs status` falls back on, and a statistics record
+//! that `shadowfs stats snapshot` saves. `shadowfs rules` edits a rules
+//! file that the process applies and reports rule hits for.
+//! Mounts sharing a memory budget publish a memory claim, from which each of
+//! them works out its share of the budget. `shadowfs hydrate` leaves a
+//! hydration request for the process, which reports its progress back, and
+//! `shadowfs trace` leaves the globs of the paths the process traces.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use shadowfs_core::override_store::{EvictionSimulation, GarbageCollection, HydrationSummary, PathReport};
+use shadowfs_core::progress::ProgressUpdate;
+use shadowfs_core::rebalance::MemoryClaim;
+use shadowfs_core::stats::{LatencySummary, LiveStats, MountResources, StatsRecord};
+use shadowfs_core::types::{MountRecord, ShadowPath};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Returns the directory holding state files for running mounts.
+///
+/// `SHADOWFS_RUNTIME_DIR` overrides the default of `$XDG_RUNTIME_DIR/shadowfs`,
+/// falling back to a per-user directory under the system temp dir.
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("SHADOWFS_RUNTIME_DIR") {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join("shadowfs");
+    }
+    std::env::temp_dir().join(format!("shadowfs-{}", current_uid()))
+}
+
+/// Returns the file holding the API tokens issued with `shadowfs token`.
+pub fn tokens_file() -> PathBuf {
+    state_dir().join("tokens.json")
+}
+
+/// A subtree `shadowfs hydrate` asks the serving process to hydrate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HydrationRequest {
+    /// Identifier the serving process reports progress under
+    pub id: Uuid,
+    
+    /// Path in the mount of the subtree
+    pub path: ShadowPath,
+}
+
+/// What `shadowfs debug`, `shadowfs gc` and `shadowfs stats` ask the
+/// serving process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "query", rename_all = "snake_case")]
+pub enum InspectQuery {
+    /// Everything known about one path in the mount
+    Path { path: ShadowPath },
+    
+    /// What each eviction policy would evict to free `target_bytes`
+    Eviction { target_bytes: u64 },
+    
+    /// Drop the overrides that no longer hide anything in the source
+    CollectGarbage,
+    
+    /// Latency percentiles and distribution of every operation type
+    Latencies,
+    
+    /// Memory, cache, eviction, hot path and latency figures for the
+    /// live dashboard
+    Live,
+}
+
+/// Report answering an [`InspectQuery`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "answer", rename_all = "snake_case")]
+pub enum InspectAnswer {
+    /// What the process knows about the path
+    Path(Box<PathReport>),
+    
+    /// What each eviction policy would evict
+    Eviction(EvictionSimulation),
+    
+    /// What was dropped
+    Garbage(GarbageCollection),
+    
+    /// Latencies keyed by operation name
+    Latencies(BTreeMap<String, LatencySummary>),
+    
+    /// Current state of the mount
+    Live(Box<LiveStats>),
+}
+
+/// Progress of a hydration, reported by the serving process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HydrationStatus {
+    /// Identifier of the request being served
+    pub id: Uuid,
+    
+    /// Files copied so far
+    pub progress: ProgressUpdate,
+    
+    /// Summary of the hydration, or why it failed, once it has ended
+    pub outcome: Option<std::result::Result<HydrationSummary, String>>,
+}
+
+/// Paths of the state files belonging to one mount point.
+#[derive(Debug, Clone)]
+pub struct MountStateFiles {
+    /// JSON mount record
+    pub record: PathBuf,
+    
+    /// Pidfile of the serving process
+    pub pid_file: PathBuf,
+    
+    /// Log file used when running as a daemon
+    pub log_file: PathBuf,
+    
+    /// Write-ahead log of the mount's override store, read by `shadowfs diff`
+    pub wal_file: PathBuf,
+    
+    /// Snapshot the write-ahead log is checkpointed into
+    pub snapshot_file: PathBuf,
+    
+    /// Latest resource report of the serving process, read by `shadowfs status`
+    pub resources_file: PathBuf,
+    
+    /// Latest statistics record of the serving process, read by `shadowfs stats`
+    pub stats_file: PathBuf,
+    
+    /// Rules edited by `shadowfs rules` and applied by the serving process
+    pub rules_file: PathBuf,
+    
+    /// Hits of each rule, reported by the serving process
+    pub rule_hits_file: PathBuf,
+    
+    /// Latest memory claim of a mount rebalancing a shared memory budget
+    pub memory_file: PathBuf,
+    
+    /// Hydration requested by `shadowfs hydrate`, removed to cancel it
+    pub hydrate_file: PathBuf,
+    
+    /// Progress of the latest hydration, reported by the serving process
+    pub hydrate_status_file: PathBuf,
+    
+    /// Globs of the paths traced by the serving process, set by `shadowfs trace`
+    pub trace_file: PathBuf,
+    
+    /// Socket the serving process answers control requests on
+    pub control_socket: PathBuf,
+}
+
+impl MountStateFiles {
+    /// Returns the state files for `mount_point`, which should be canonical.
+    pub fn for_mount_point(mount_point: &Path) -> Self {
+        let mut hasher = DefaultHasher::new();
+        mount_point.hash(&mut hasher);
+        let stem = format!("mount-{:016x}", hasher.finish());
+        let dir = state_dir();
+        
+        Self {
+            record: dir.join(format!("{}.json", stem)),
+            pid_file: dir.join(format!("{}.pid", stem)),
+            log_file: dir.join(format!("{}.log", stem)),
+            wal_file: dir.join(format!("{}.wal", stem)),
+            snapshot_file: dir.join(format!("{}.snapshot", stem)),
+            resources_file: dir.join(format!("{}.resources", stem)),
+            stats_file: dir.join(format!("{}.stats", stem)),
+            rules_file: dir.join(format!("{}.rules", stem)),
+            rule_hits_file: dir.join(format!("{}.rule-hits", stem)),
+            memory_file: dir.join(format!("{}.memory", stem)),
+            hydrate_file: dir.join(format!("{}.hydrate", stem)),
+            hydrate_status_file: dir.join(format!("{}.hydrate-status", stem)),
+            trace_file: dir.join(format!("{}.trace", stem)),
+            control_socket: dir.join(format!("{}.sock", stem)),
+        }
+    }
+    
+    /// Writes the mount record and pidfile for the current process.
+    pub fn write(&self, record: &MountRecord, pid_file: Option<&Path>) -> Result<()> {
+        std::fs::create_dir_all(state_dir()).context("Failed to create runtime state directory")?;
+        std::fs::write(&self.record, serde_json::to_vec_pretty(record)?)
+            .with_context(|| format!("Failed to write {}", self.record.display()))?;
+        
+        let pid = format!("{}\n", record.process_id);
+        std::fs::write(&self.pid_file, &pid)
+            .with_context(|| format!("Failed to write {}", self.pid_file.display()))?;
+        if let Some(extra) = pid_file {
+            std::fs::write(extra, &pid)
+                .with_context(|| format!("Failed to write {}", extra.display()))?;
+        }
+        Ok(())
+    }
+    
+    /// Replaces the resource report of the mount.
+    pub fn write_resources(&self, resources: &MountResources) -> Result<()> {
+        // Written to a temporary file first so readers never see a partial report
+        let partial = self.resources_file.with_extension("resources.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(resources)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.resources_file)
+            .with_context(|| format!("Failed to write {}", self.resources_file.display()))
+    }
+    
+    /// Reads the latest resource report, if the serving process wrote one.
+    pub fn read_resources(&self) -> Option<MountResources> {
+        let data = std::fs::read(&self.resources_file).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+    
+    /// Replaces the statistics record of the serving process.
+    pub fn write_stats(&self, record: &StatsRecord) -> Result<()> {
+        let partial = self.stats_file.with_extension("stats.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(record)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.stats_file)
+            .with_context(|| format!("Failed to write {}", self.stats_file.display()))
+    }
+    
+    /// Reads the latest statistics record, if the serving process wrote one.
+    pub fn read_stats(&self) -> Option<StatsRecord> {
+        let data = std::fs::read(&self.stats_file).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+    
+    /// Replaces the report of how many files each rule replaced.
+    pub fn write_rule_hits(&self, hits: &BTreeMap<u64, u64>) -> Result<()> {
+        let partial = self.rule_hits_file.with_extension("rule-hits.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(hits)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.rule_hits_file)
+            .with_context(|| format!("Failed to write {}", self.rule_hits_file.display()))
+    }
+    
+    /// Reads the hits of each rule, empty if the serving process reported none.
+    pub fn read_rule_hits(&self) -> BTreeMap<u64, u64> {
+        std::fs::read(&self.rule_hits_file)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+    
+    /// Replaces the memory claim of the mount.
+    pub fn write_memory_claim(&self, claim: &MemoryClaim) -> Result<()> {
+        let partial = self.memory_file.with_extension("memory.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(claim)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.memory_file)
+            .with_context(|| format!("Failed to write {}", self.memory_file.display()))
+    }
+    
+    /// Reads the latest memory claim, if the mount rebalances memory.
+    pub fn read_memory_claim(&self) -> Option<MemoryClaim> {
+        let data = std::fs::read(&self.memory_file).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+    
+    /// Asks the serving process to hydrate a subtree.
+    pub fn write_hydration_request(&self, request: &HydrationRequest) -> Result<()> {
+        let partial = self.hydrate_file.with_extension("hydrate.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(request)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.hydrate_file)
+            .with_context(|| format!("Failed to write {}", self.hydrate_file.display()))
+    }
+    
+    /// Reads the pending hydration request, if any.
+    pub fn read_hydration_request(&self) -> Option<HydrationRequest> {
+        let data = std::fs::read(&self.hydrate_file).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+    
+    /// Withdraws the hydration request, cancelling it if it is still running.
+    pub fn remove_hydration_request(&self) {
+        let _ = std::fs::remove_file(&self.hydrate_file);
+    }
+    
+    /// Replaces the progress report of the latest hydration.
+    pub fn write_hydration_status(&self, status: &HydrationStatus) -> Result<()> {
+        let partial = self.hydrate_status_file.with_extension("hydrate-status.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(status)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.hydrate_status_file)
+            .with_context(|| format!("Failed to write {}", self.hydrate_status_file.display()))
+    }
+    
+    /// Reads the progress of the latest hydration, if one was served.
+    pub fn read_hydration_status(&self) -> Option<HydrationStatus> {
+        let data = std::fs::read(&self.hydrate_status_file).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+    
+    /// Replaces the globs of the paths the serving process traces; an empty
+    /// list stops tracing.
+    pub fn write_trace_globs(&self, globs: &[String]) -> Result<()> {
+        std::fs::create_dir_all(state_dir()).context("Failed to create runtime state directory")?;
+        let partial = self.trace_file.with_extension("trace.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(globs)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.trace_file)
+            .with_context(|| format!("Failed to write {}", self.trace_file.display()))
+    }
+    
+    /// Reads the globs of the traced paths, empty if none are set.
+    pub fn read_trace_globs(&self) -> Vec<String> {
+        std::fs::read(&self.trace_file)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+    
+    /// Removes the state files, ignoring ones that are already gone.
+    pub fn remove(&self, pid_file: Option<&Path>) {
+        let _ = std::fs::remove_file(&self.record);
+        let _ = std::fs::remove_file(&self.pid_file);
+        let _ = std::fs::remove_file(&self.wal_file);
+        let _ = std::fs::remove_file(&self.snapshot_file);
+        let _ = std::fs::remove_file(&self.resources_file);
+        let _ = std::fs::remove_file(&self.stats_file);
+        let _ = std::fs::remove_file(&self.rules_file);
+        let _ = std::fs::remove_file(&self.rule_hits_file);
+        let _ = std::fs::remove_file(&self.memory_file);
+        let _ = std::fs::remove_file(&self.hydrate_file);
+        let _ = std::fs::remove_file(&self.hydrate_status_file);
+        let _ = std::fs::remove_file(&self.trace_file);
+        let _ = std::fs::remove_file(&self.control_socket);
+        if let Some(extra) = pid_file {
+            let _ = std::fs::remove_file(extra);
+        }
+    }
+}
+
+/// Loads all mount records from the state directory.
+pub fn list_records() -> Vec<MountRecord> {
+    let Ok(entries) = std::fs::read_dir(state_dir()) else {
+        return Vec::new();
+    };
+    
+    let mut records: Vec<MountRecord> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| std::fs::read(entry.path()).ok())
+        .filter_map(|data| serde_json::from_slice(&data).ok())
+        .collect();
+    records.sort_by(|a, b| a.target.cmp(&b.target));
+    records
+}
+
+pub use shadowfs_core::registry::is_process_alive;
+
+/// Asks the process serving a mount to unmount and exit.
+pub fn request_shutdown(pid: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        // SAFETY: sending SIGTERM has no memory-safety preconditions
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to signal process {}", pid));
+        }
+        Ok(())
+    }
+    
+    #[cfg(not(unix))]
+    {
+        anyhow::bail!("Stopping background mount process {} is not supported on this platform yet", pid)
+    }
+}
+
+fn current_uid() -> u32 {
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid cannot fail and has no preconditions
+        unsafe { libc::getuid() }
+    }
+    
+    #[cfg(not(unix))]
+    {
+        0
+    }
+}
+
+/// Write end of the pipe the daemon uses to tell the launching process it is ready.
+#[cfg(unix)]
+pub struct ReadyNotifier {
+    fd: libc::c_int,
+}
+
+#[cfg(unix)]
+impl ReadyNotifier {
+    /// Reports whether the mount succeeded; the launching process exits with the result.
+    pub fn notify(self, ok: bool) {
+        let byte = [if ok { b'1' } else { b'0' }];
+        // SAFETY: fd is the write end of a pipe owned by this notifier
+        unsafe {
+            libc::write(self.fd, byte.as_ptr().cast(), 1);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ReadyNotifier {
+    fn drop(&mut self) {
+        // SAFETY: fd is owned by this notifier and closed exactly once
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Detaches the current process from the terminal.
+///
+/// Must be called before any threads (including the Tokio runtime) are
+/// started. The launching process blocks until the daemon reports readiness
+/// through the returned notifier and then exits with a matching status, so
+/// mount errors still reach the user's terminal.
+#[cfg(unix)]
+pub fn daemonize(log_file: &Path) -> Result<ReadyNotifier> {
+    use std::os::unix::io::AsRawFd;
+    
+    if let Some(parent) = log_file.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create runtime state directory")?;
+    }
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .with_context(|| format!("Failed to open {}", log_file.display()))?;
+    let null = std::fs::OpenOptions::new().read(true).open("/dev/null")?;
+    
+    let mut fds = [0 as libc::c_int; 2];
+    // SAFETY: fds has room for the two descriptors pipe() writes
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create readiness pipe");
+    }
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+    
+    // SAFETY: the process is still single-threaded, so fork() is sound
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("Failed to fork"),
+        0 => {}
+        _ => {
+            // SAFETY: closing our copy of the write end so EOF is seen if the daemon dies
+            unsafe { libc::close(write_fd) };
+            wait_for_ready(read_fd, log_file);
+        }
+    }
+    
+    // SAFETY: called in the forked child, which is not a process group leader
+    if unsafe { libc::setsid() } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create session");
+    }
+    
+    // Fork again so the daemon can never reacquire a controlling terminal
+    // SAFETY: still single-threaded
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("Failed to fork"),
+        0 => {}
+        // SAFETY: _exit skips destructors that belong to the grandchild
+        _ => unsafe { libc::_exit(0) },
+    }
+    
+    // SAFETY: all descriptors are valid and owned by this process
+    unsafe {
+        libc::close(read_fd);
+        libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO);
+        libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO);
+    }
+    std::env::set_current_dir("/")?;
+    
+    Ok(ReadyNotifier { fd: write_fd })
+}
+
+/// Placeholder notifier on platforms without daemon support.
+#[cfg(not(unix))]
+pub struct ReadyNotifier;
+
+#[cfg(not(unix))]
+impl ReadyNotifier {
+    /// Does nothing; there is no launching process to notify.
+    pub fn notify(self, _ok: bool) {}
+}
+
+/// Daemon mode is only available on Unix platforms.
+#[cfg(not(unix))]
+pub fn daemonize(_log_file: &Path) -> Result<ReadyNotifier> {
+    anyhow::bail!("--daemon is only supported on Unix platforms")
+}
+
+/// Blocks the launching process until the daemon reports readiness, then exits.
+#[cfg(unix)]
+fn wait_for_ready(read_fd: libc::c_int, log_file: &Path) -> ! {
+    let mut byte = [0u8; 1];
+    // SAFETY: read_fd is the read end of the readiness pipe
+    let read = unsafe { libc::read(read_fd, byte.as_mut_ptr().cast(), 1) };
+    if read == 1 && byte[0] == b'1' {
+        std::process::exit(0);
+    }
+    eprintln!("Mount failed; see {} for details", log_file.display());
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_state_files_are_stable_per_mount_point() {
+        let a = MountStateFiles::for_mount_point(Path::new("/mnt/a"));
+        let again = MountStateFiles::for_mount_point(Path::new("/mnt/a"));
+        let b = MountStateFiles::for_mount_point(Path::new("/mnt/b"));
+        
+        assert_eq!(a.record, again.record);
+        assert_ne!(a.record, b.record);
+        assert_eq!(a.record.with_extension("pid"), a.pid_file);
+    }
+    
+    #[test]
+    fn test_current_process_is_alive() {
+        assert!(is_process_alive(std::process::id()));
+    }
+}
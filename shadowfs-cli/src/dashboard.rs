@@ -0,0 +1,192 @@
+//! Live terminal dashboard of `shadowfs stats`.
+//!
+//! The dashboard asks the serving process for a [`LiveStats`] sample every
+//! refresh interval and redraws memory use and pressure, the cache hit
+//! rate, the eviction rate since the previous sample, the hottest paths and
+//! the latency percentiles of every operation served. It runs on the
+//! alternate screen until `q`, Esc or Ctrl-C is pressed.
+
+use crate::{format_bytes, format_micros};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::widgets::{Block, Gauge, Row, Sparkline, Table};
+use ratatui::{DefaultTerminal, Frame};
+use shadowfs_core::stats::LiveStats;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Eviction rates kept for the sparkline, one per refresh.
+const EVICTION_HISTORY: usize = 120;
+
+/// Memory pressure from which the memory gauge turns red.
+const HIGH_PRESSURE: f64 = 0.9;
+
+/// What the dashboard shows, updated with each sample.
+struct Dashboard {
+    mount_point: PathBuf,
+    latest: LiveStats,
+    eviction_rate: f64,
+    eviction_rates: VecDeque<u64>,
+}
+
+impl Dashboard {
+    fn new(mount_point: &Path, first: LiveStats) -> Self {
+        Self {
+            mount_point: mount_point.to_path_buf(),
+            latest: first,
+            eviction_rate: 0.0,
+            eviction_rates: VecDeque::with_capacity(EVICTION_HISTORY),
+        }
+    }
+    
+    fn update(&mut self, sample: LiveStats) {
+        self.eviction_rate = sample.eviction_rate(&self.latest);
+        if self.eviction_rates.len() == EVICTION_HISTORY {
+            self.eviction_rates.pop_front();
+        }
+        self.eviction_rates.push_back(self.eviction_rate.round() as u64);
+        self.latest = sample;
+    }
+    
+    fn draw(&self, frame: &mut Frame) {
+        let [gauges, evictions, tables] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(5),
+            Constraint::Min(0),
+        ])
+        .areas(frame.area());
+        let [memory, cache] = Layout::horizontal([Constraint::Percentage(50); 2]).areas(gauges);
+        let [hot_paths, latencies] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(tables);
+        
+        let stats = &self.latest;
+        let pressure = stats.memory_pressure.clamp(0.0, 1.0);
+        let title = format!(" {} (q to quit) ", self.mount_point.display());
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title(title).title_bottom(" memory "))
+                .gauge_style(if pressure >= HIGH_PRESSURE { Color::Red } else { Color::Green })
+                .ratio(pressure)
+                .label(format!(
+                    "{} of {} ({:.0}%)",
+                    format_bytes(stats.memory_bytes),
+                    format_bytes(stats.memory_limit_bytes),
+                    pressure * 100.0
+                )),
+            memory,
+        );
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title(" cache hit rate "))
+                .gauge_style(Color::Cyan)
+                .ratio(stats.cache_hit_rate.clamp(0.0, 1.0))
+                .label(format!(
+                    "{:.1}% of {} lookups",
+                    stats.cache_hit_rate * 100.0,
+                    stats.cache_hits + stats.cache_misses
+                )),
+            cache,
+        );
+        
+        let evictions_title = format!(
+            " evictions: {:.1}/s, {} in total ",
+            self.eviction_rate, stats.evictions
+        );
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title(evictions_title))
+                .style(Style::default().fg(Color::Yellow))
+                .data(self.eviction_rates.iter().copied()),
+            evictions,
+        );
+        
+        self.draw_hot_paths(frame, hot_paths);
+        self.draw_latencies(frame, latencies);
+    }
+    
+    fn draw_hot_paths(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.latest.hot_paths.iter().map(|hot| {
+            Row::new([hot.path.to_string(), hot.accesses.to_string(), format_bytes(hot.bytes)])
+        });
+        let table = Table::new(rows, [Constraint::Fill(1), Constraint::Length(10), Constraint::Length(11)])
+            .header(Row::new(["path", "accesses", "bytes"]).bold())
+            .block(Block::bordered().title(" hot paths "));
+        frame.render_widget(table, area);
+    }
+    
+    fn draw_latencies(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.latest.latencies.iter()
+            .filter(|(_, summary)| summary.count > 0)
+            .map(|(name, summary)| {
+                Row::new([
+                    name.clone(),
+                    summary.count.to_string(),
+                    format_micros(summary.p50_micros),
+                    format_micros(summary.p90_micros),
+                    format_micros(summary.p99_micros),
+                    format_micros(summary.p999_micros),
+                    format_micros(summary.max_micros),
+                ])
+            });
+        let widths = [Constraint::Min(9), Constraint::Length(8)]
+            .into_iter()
+            .chain([Constraint::Length(8); 5]);
+        let table = Table::new(rows, widths)
+            .header(Row::new(["operation", "count", "p50", "p90", "p99", "p99.9", "max"]).bold())
+            .block(Block::bordered().title(" latencies "));
+        frame.render_widget(table, area);
+    }
+}
+
+/// Shows the dashboard of the mount at `mount_point`, taking a new sample
+/// with `sample` every `interval`, until the user quits.
+pub async fn run<F, Fut>(mount_point: &Path, interval: Duration, mut sample: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<LiveStats>>,
+{
+    let mut dashboard = Dashboard::new(mount_point, sample().await?);
+    let mut terminal = ratatui::init();
+    let result = serve(&mut terminal, &mut dashboard, interval, sample).await;
+    ratatui::restore();
+    result
+}
+
+async fn serve<F, Fut>(
+    terminal: &mut DefaultTerminal,
+    dashboard: &mut Dashboard,
+    interval: Duration,
+    mut sample: F,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<LiveStats>>,
+{
+    loop {
+        terminal.draw(|frame| dashboard.draw(frame))?;
+        if tokio::task::block_in_place(|| quit_requested(interval))? {
+            return Ok(());
+        }
+        dashboard.update(sample().await?);
+    }
+}
+
+/// Waits up to `timeout` for a key asking to quit.
+fn quit_requested(timeout: Duration) -> std::io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if !event::poll(remaining)? {
+            break;
+        }
+        if let Event::Key(key) = event::read()? {
+            let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+            if key.kind == KeyEventKind::Press && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
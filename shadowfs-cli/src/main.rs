@@ -1,7 +1,97 @@
-use clap::{Parser, Subcommand};
-use anyhow::Result;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use anyhow::{Context, Result};
+use shadowfs_core::atime::AtimePolicy;
+use shadowfs_core::determinism::Determinism;
+use shadowfs_core::error::ShadowError;
+use shadowfs_core::handles::HandleTable;
+use shadowfs_core::hidden::{HiddenFiles, HiddenLookups};
+use shadowfs_core::mount_manager::{ExpiryEvent, ExpiryReason, MountLimits, MountManager, ProviderFactory};
+use shadowfs_core::override_store::{
+    transforms, AlertConfig, BackgroundEvictor, ChainTransformer, ChecksumManifest, CommitOptions, DiffKind, EvictionPolicy,
+    EvictorConfig, ManifestKey, MergedArchiveFormat, OverrideRule, OverrideSnapshot, OverrideStore, OverrideStoreConfig,
+    PathReport, PathState, ReadTransforms, RulePriority, RuleSet, TransformChain, WriteAheadLog,
+};
+use shadowfs_core::metrics::StatsRegistry;
+use shadowfs_core::mount_paths;
+use shadowfs_core::platform::{Preflight, PreflightOptions};
+use shadowfs_core::profile::MountProfile;
+use shadowfs_core::progress::{CancelHandle, ConsoleProgress, Progress, ProgressTracker};
+use shadowfs_core::registry::FileMountRegistry;
+use shadowfs_core::rebalance::{self, divide_budget, MemoryClaim, RebalanceEvent, REBALANCE_TOLERANCE};
+use shadowfs_core::rule_book::{RuleBook, RuleBookSync};
+use shadowfs_core::path_limits::PathLimits;
+use shadowfs_core::quota::Quota;
+use shadowfs_core::source_cache::{SourceCacheConfig, SourceFileCache};
+use shadowfs_core::listing_cache::{ListingCache, ListingCacheConfig};
+use shadowfs_core::stats::{FileSystemStats, LatencySummary, LiveStats, MountResources, StatsChange, StatsRecord};
+use shadowfs_core::supervision::{Component, ComponentState, FailureMode, FailurePolicy, Subsystem};
+use shadowfs_core::tokens::{TokenRegistry, TokenScope};
+use shadowfs_core::trace::{PathTraceFilter, TRACE_TARGET};
+use shadowfs_core::traits::FileSystemProvider;
+use shadowfs_core::types::{
+    parse_size, ConfigProfile, ConfigRule, MemoryPriority, MountExpiry, MountOptions, MountRecord, OverrideConfig, Platform,
+    ShadowConfig, ShadowPath,
+};
+use shadowfs_core::virtual_fs::VirtualFs;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+use uuid::Uuid;
+
+mod control;
+mod dashboard;
+mod daemon;
+#[cfg(feature = "otlp")]
+mod telemetry;
+
+use control::{ControlRequest, ControlResponse, MountStatus};
+use daemon::{HydrationRequest, HydrationStatus, InspectAnswer, InspectQuery, MountStateFiles, ReadyNotifier};
+
+/// Environment variable holding the API token of an automation client.
+const TOKEN_ENV: &str = "SHADOWFS_TOKEN";
+
+/// How long `unmount` waits for the serving process to exit.
+const UNMOUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a mount queued by `--queue-timeout` checks the other mounts again.
+const ADMISSION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a serving process applies changes to its rules file.
+const RULES_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often a serving process applies changes to the globs of traced paths.
+const TRACE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a serving process checks for a hydration request, and
+/// `shadowfs hydrate` for its progress.
+const HYDRATE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often a mount started with `--rebalance-memory` works out its share
+/// of the memory budget.
+const REBALANCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A rebalancing mount keeps at least this fraction of its `--max-memory`.
+const REBALANCE_FLOOR_DIVISOR: u64 = 4;
+
+/// How often a serving process refreshes its resource report.
+const RESOURCES_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often a serving process checks the size of its write-ahead log.
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often a serving process drops overrides that no longer hide
+/// anything in the source.
+const GC_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Size past which the write-ahead log is compacted into the snapshot.
+const MAX_WAL_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How often a serving process snapshots its store whatever the log size.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(3600);
 
 #[derive(Parser)]
 #[command(name = "shadowfs")]
@@ -17,12 +107,114 @@ enum Commands {
     /// Mount a shadowfs filesystem
     Mount {
         /// Source directory to shadow
-        #[arg(short, long)]
-        source: String,
+        #[arg(short, long, required_unless_present = "profile")]
+        source: Option<String>,
         
         /// Mount point for the virtual filesystem
-        #[arg(short, long)]
-        mount: String,
+        #[arg(short, long, required_unless_present = "profile")]
+        mount: Option<String>,
+        
+        /// Take what the command line leaves out from this profile of the
+        /// configuration file
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+        
+        /// Detach from the terminal and keep serving the mount in the background
+        #[arg(long)]
+        daemon: bool,
+        
+        /// Additional file to write the serving process id to
+        #[arg(long)]
+        pid_file: Option<PathBuf>,
+        
+        /// Reject every write, so the source can be exposed to untrusted tools
+        #[arg(long)]
+        read_only: bool,
+        
+        /// Promise that the source will not change while mounted, such as a
+        /// CI checkout, so it is not watched and its metadata and content
+        /// stay cached far longer
+        #[arg(long)]
+        immutable_source: bool,
+        
+        /// Report timestamps frozen at SOURCE_DATE_EPOCH, inode numbers
+        /// derived from paths, root ownership and git-style permissions, so
+        /// builds inside the mount are reproducible
+        #[arg(long)]
+        deterministic: bool,
+        
+        /// Which reads update access times: noatime, relatime or strictatime
+        #[arg(long, value_name = "POLICY", default_value = "relatime")]
+        atime: AtimePolicy,
+        
+        /// Leave entries whose name starts with a dot out of listings
+        #[arg(long)]
+        hide_dotfiles: bool,
+        
+        /// Leave entries Windows marks hidden or system out of listings
+        #[arg(long)]
+        hide_system_files: bool,
+        
+        /// Leave paths matching this glob out of listings, such as '*.pyc'
+        #[arg(long, value_name = "GLOB")]
+        hide: Vec<String>,
+        
+        /// Make hidden entries impossible to open or look up by path too
+        #[arg(long)]
+        hide_from_lookups: bool,
+        
+        /// Write, read and delete a probe file through the mount once it is
+        /// up, and unmount again if that fails
+        #[arg(long)]
+        self_test: bool,
+        
+        /// Unmount when one of these subsystems fails instead of carrying on
+        /// degraded; with no list, applies to all of them
+        #[arg(long, value_name = "SUBSYSTEM", num_args = 0.., value_delimiter = ',', value_parser = parse_subsystem)]
+        fail_fast: Option<Vec<Subsystem>>,
+        
+        /// Unmount after this long, such as 90m, 12h or 2d
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        ttl: Option<Duration>,
+        
+        /// Unmount after this long without any filesystem operations
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        idle_timeout: Option<Duration>,
+        
+        /// Most memory the overrides of this mount may take, such as 512M or 2G
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_memory: Option<u64>,
+        
+        #[command(flatten)]
+        limits: Box<LimitArgs>,
+        
+        /// Refuse to mount while this many mounts are active
+        #[arg(long, value_name = "COUNT")]
+        max_mounts: Option<usize>,
+        
+        /// Refuse to mount if the override memory of all mounts would exceed this
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        memory_budget: Option<u64>,
+        
+        /// Wait this long for other mounts to go away instead of refusing at once
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        queue_timeout: Option<Duration>,
+        
+        /// Shift the memory budget between rebalancing mounts by priority and activity
+        #[arg(long, requires = "memory_budget")]
+        rebalance_memory: bool,
+        
+        /// Weight of this mount when memory is rebalanced: low, normal, high or a number
+        #[arg(long, value_name = "PRIORITY", default_value = "normal", value_parser = parse_memory_priority)]
+        memory_priority: MemoryPriority,
+        
+        /// Rewrite source files matching GLOB with a sed expression as they
+        /// are read, such as '*.env=s/=.*/=***/'; may be repeated
+        #[arg(long = "transform", value_name = "GLOB=SED", value_parser = parse_transform)]
+        transforms: Vec<(String, String)>,
+        
+        #[command(flatten)]
+        statistics: Box<StatisticsArgs>,
     },
     
     /// Unmount a shadowfs filesystem
@@ -31,49 +223,730 @@ enum Commands {
         mount: String,
     },
     
-    /// Show status of mounted filesystems
-    Status,
+    /// Show status of mounted filesystems, and of mounts left behind by
+    /// processes that exited without unmounting
+    Status {
+        /// Remove the records and state of mounts whose process is gone
+        #[arg(long)]
+        clean: bool,
+    },
+    
+    /// Change store settings of a running mount
+    Config {
+        /// Mount point of the running mount
+        mount: String,
+        
+        /// What to evict under memory pressure: lru, lfu, fifo, size-weighted or 2q
+        #[arg(long, value_name = "POLICY", value_parser = parse_eviction_policy)]
+        eviction_policy: Option<EvictionPolicy>,
+        
+        /// Whether to compress large overrides written from now on
+        #[arg(long, value_name = "on|off", value_parser = parse_switch)]
+        compression: Option<bool>,
+    },
+    
+    /// Write the overrides of a running mount to its snapshot now, instead
+    /// of when its write-ahead log next grows or the snapshot interval ends
+    Snapshot {
+        /// Mount point of the running mount
+        mount: String,
+    },
+    
+    /// Show overrides that differ from the source directory
+    Diff {
+        /// Mount point to compare
+        mount: String,
+        
+        /// Include source and override sizes
+        #[arg(long)]
+        stat: bool,
+        
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+    },
+    
+    /// Write what a mount shows to an archive, without reading the mount
+    ///
+    /// The source directory is read directly with the overrides applied
+    /// on top, so the archive matches the mount even where the kernel
+    /// mount would be slow to walk. Read transforms are not applied.
+    Export {
+        /// Mount point to export
+        mount: String,
+        
+        /// Archive file to write
+        output: PathBuf,
+        
+        /// Archive format
+        #[arg(long, value_enum, default_value_t = ArchiveFormat::TarGz)]
+        format: ArchiveFormat,
+    },
+    
+    /// Write a checksum manifest of what a mount shows
+    ///
+    /// Lists every file with its size and BLAKE3 hash as JSON, so that an
+    /// artifact exported from the mount can be checked with verify-checksums.
+    /// With a key file the manifest is signed, and checking the signature
+    /// takes the same key file.
+    Checksums {
+        /// Mount point to list
+        mount: String,
+        
+        /// File to write the manifest to instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        
+        /// Print `b3sum` lines for `b3sum --check` instead of JSON
+        #[arg(long, conflicts_with = "key_file")]
+        b3sum: bool,
+        
+        /// File holding the secret to sign the manifest with
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    
+    /// Check a directory, such as an extracted export, against a checksum manifest
+    VerifyChecksums {
+        /// Manifest written by `shadowfs checksums`
+        manifest: PathBuf,
+        
+        /// Directory to check
+        directory: PathBuf,
+        
+        /// File holding the secret the manifest was signed with
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    
+    /// Copy a subtree of the source directory into a running mount
+    ///
+    /// Hydrated files are served from memory from then on, so they stay
+    /// available when the source is slow or out of reach, such as a network
+    /// share before going offline. Paths with overrides are left alone.
+    Hydrate {
+        /// Mount point to hydrate
+        mount: String,
+        
+        /// Directory or file to hydrate, under the mount point or relative
+        /// to its root
+        #[arg(default_value = "/")]
+        path: String,
+        
+        /// Only show how much would be copied
+        #[arg(long)]
+        estimate: bool,
+    },
+    
+    /// Write the overrides of a mount back to its source directory
+    Commit {
+        /// Mount point whose overrides to commit
+        mount: String,
+        
+        /// Save replaced source files so the commit can be rolled back
+        #[arg(long)]
+        backup: bool,
+    },
+    
+    /// Undo the most recent commit made with --backup
+    RollbackCommit {
+        /// Mount point whose last commit to undo
+        mount: String,
+    },
+    
+    /// Try a dotfiles repository on top of your home directory
+    ///
+    /// Mounts a shadow of the home directory seeded with the repository's
+    /// files, runs a shell inside it and shows what changed once the shell
+    /// exits. Nothing is written to the real home directory.
+    TryDotfiles {
+        /// Dotfiles repository to seed the overrides from
+        repo: PathBuf,
+        
+        /// Home directory to shadow (defaults to $HOME)
+        #[arg(long)]
+        home: Option<PathBuf>,
+        
+        /// Where to mount the shadowed home (defaults to a temporary directory)
+        #[arg(long)]
+        mount: Option<PathBuf>,
+        
+        /// Shell to run inside the mount (defaults to $SHELL)
+        #[arg(long)]
+        shell: Option<String>,
+    },
+    
+    /// Drop tombstones and empty directories of a running mount that no
+    /// longer hide anything in the source
+    ///
+    /// The serving process also does this every ten minutes.
+    Gc {
+        /// Mount point of the running mount
+        mount: String,
+        
+        /// Print what was dropped as JSON
+        #[arg(long)]
+        json: bool,
+    },
     
-    /// Run tests on the filesystem
+    /// Check the shadow layer over a directory, without mounting it
+    ///
+    /// Mounts the directory in process, without FUSE, ProjFS or FSKit, and
+    /// lists, reads, writes, creates, renames and deletes through it. The
+    /// directory itself is never changed.
     Test {
-        /// Mount point to test
+        /// Directory to run the checks over
+        source: PathBuf,
+    },
+    
+    /// Manage API tokens for automation clients such as CI systems
+    ///
+    /// A client sets SHADOWFS_TOKEN to its token, and every command it runs
+    /// is then checked against the token's scopes.
+    Token {
+        #[command(subcommand)]
+        command: TokenCommand,
+    },
+    
+    /// Manage the mount profiles of the configuration file
+    ///
+    /// The file is config.toml in the configuration directory, or the file
+    /// SHADOWFS_CONFIG names.
+    Profiles {
+        #[command(subcommand)]
+        command: ProfilesCommand,
+    },
+    
+    /// Manage the rules replacing matching source files of a running mount
+    ///
+    /// A rule applies to files read for the first time after it is added,
+    /// within a few seconds; files already read keep their content.
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+    
+    /// Log every operation on the paths of a running mount matching globs
+    ///
+    /// Traced operations are logged at debug level whatever the log level
+    /// of the rest of the mount, to the log of the serving process. Without
+    /// globs, shows the globs currently traced.
+    Trace {
+        /// Mount point to trace
+        mount: String,
+        
+        /// Globs matched against paths in the mount, such as '/src/*.lock';
+        /// they replace the globs traced so far
+        globs: Vec<String>,
+        
+        /// Stop tracing
+        #[arg(long, conflicts_with = "globs")]
+        off: bool,
+    },
+    
+    /// Inspect the internal state of a running mount
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommand,
+    },
+    
+    /// Show a live dashboard of a running mount: memory, cache hit rate,
+    /// hot paths, evictions and latencies; or save and compare operation,
+    /// latency and cache statistics of mounts
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Stats {
+        #[command(subcommand)]
+        command: Option<StatsCommand>,
+        
+        /// Mount point whose statistics to show
+        #[arg(required = true)]
+        mount: Option<String>,
+        
+        /// Print the latency percentiles once, drawing how the latencies of
+        /// every operation are distributed, instead of the dashboard
+        #[arg(long, conflicts_with = "json")]
+        histogram: bool,
+        
+        /// Print the current statistics once as JSON instead of the dashboard
+        #[arg(long)]
+        json: bool,
+        
+        /// How often the dashboard refreshes
+        #[arg(long, value_name = "DURATION", default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+}
+
+/// What `shadowfs mount` lets the mount hold.
+#[derive(Args)]
+struct LimitArgs {
+    /// Most files and directories the mount may hold
+    #[arg(long, value_name = "COUNT")]
+    max_files: Option<u64>,
+    
+    /// Largest file that may be written, such as 100M
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+    
+    /// Most bytes of file content the mount may hold, however much of it
+    /// is compressed or spilled to disk
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_total_bytes: Option<u64>,
+    
+    /// Most files and directories one directory of the mount may hold
+    #[arg(long, value_name = "COUNT")]
+    max_directory_entries: Option<u64>,
+    
+    /// Most components a path in the mount may have below its root
+    #[arg(long, value_name = "COUNT")]
+    max_path_depth: Option<u64>,
+}
+
+impl LimitArgs {
+    /// Returns the file count and size limits asked for.
+    fn quota(&self) -> Quota {
+        Quota {
+            max_files: self.max_files,
+            max_file_size: self.max_file_size,
+            max_total_bytes: self.max_total_bytes,
+        }
+    }
+    
+    /// Returns the tree limits asked for, with names limited as the
+    /// platform's backend limits them.
+    fn path_limits(&self) -> PathLimits {
+        PathLimits {
+            max_directory_entries: self.max_directory_entries,
+            max_path_depth: self.max_path_depth,
+            ..PathLimits::for_platform(Platform::current())
+        }
+    }
+}
+
+/// Where `shadowfs mount` reports the statistics of the mount.
+#[derive(Args)]
+struct StatisticsArgs {
+    /// Save the statistics of the whole session to FILE on unmount, for
+    /// comparing runs with `shadowfs stats diff`
+    #[arg(long, value_name = "FILE")]
+    record_stats: Option<PathBuf>,
+    
+    /// Serve Prometheus metrics of the mount at http://ADDR/metrics,
+    /// such as 127.0.0.1:9184
+    #[arg(long, value_name = "ADDR")]
+    metrics_addr: Option<SocketAddr>,
+}
+
+/// Subcommands of `shadowfs debug`.
+#[derive(Subcommand)]
+enum DebugCommand {
+    /// Show everything the mount knows about one path: its override and
+    /// how it is stored and cached, the source file underneath, source
+    /// conflicts and its latest logged changes
+    Path {
+        /// Mount point holding the path
+        mount: String,
+        
+        /// Path under the mount point or relative to its root
+        path: String,
+        
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Show which overrides each eviction policy would evict to free some
+    /// memory, without evicting anything
+    Eviction {
+        /// Mount point of the running mount
+        mount: String,
+        
+        /// Memory to free, such as 64MiB
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        target: u64,
+        
+        /// Victims listed per policy
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        
+        /// Print the simulation as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Subcommands of `shadowfs stats`.
+#[derive(Subcommand)]
+enum StatsCommand {
+    /// Save the current statistics of a running mount
+    Snapshot {
+        /// Mount point whose statistics to save
+        mount: String,
+        
+        /// File to write the statistics to (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Compare two saved statistics snapshots or recorded sessions
+    Diff {
+        /// Statistics of the baseline run
+        before: PathBuf,
+        
+        /// Statistics of the run to compare with the baseline
+        after: PathBuf,
+        
+        /// Output format
+        #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+        format: StatsFormat,
+    },
+}
+
+/// Subcommands of `shadowfs rules`.
+#[derive(Subcommand)]
+enum RulesCommand {
+    /// List the rules of a mount and how many files each replaced
+    List {
+        /// Mount point whose rules to list
+        mount: String,
+    },
+    
+    /// Add a rule replacing the source files matching a glob
+    Add {
+        /// Mount point to add the rule to
+        mount: String,
+        
+        /// Glob matched against paths in the mount, such as '*.env'
+        glob: String,
+        
+        /// Content of matching files; ${path}, ${filename}, ${parent},
+        /// ${extension} and ${timestamp} are expanded
+        #[arg(long, required_unless_present = "template_file", conflicts_with = "template_file")]
+        template: Option<String>,
+        
+        /// File holding the content of matching files
+        #[arg(long, value_name = "FILE")]
+        template_file: Option<PathBuf>,
+        
+        /// Rules with higher priorities are tried first
+        #[arg(long, default_value_t = RulePriority::MEDIUM.0)]
+        priority: u32,
+    },
+    
+    /// Remove a rule; files it already replaced keep their content
+    Remove {
+        /// Mount point to remove the rule from
         mount: String,
+        
+        /// Id of the rule, as shown by `shadowfs rules list`
+        id: u64,
+    },
+}
+
+/// Subcommands of `shadowfs profiles`.
+#[derive(Subcommand)]
+enum ProfilesCommand {
+    /// List the profiles and their settings
+    List,
+    
+    /// Add a profile, or change the settings given of an existing one
+    Add {
+        /// Name to select the profile with `mount --profile`
+        name: String,
+        
+        /// Source directory to shadow
+        #[arg(short, long)]
+        source: Option<PathBuf>,
+        
+        /// Mount point for the virtual filesystem
+        #[arg(short, long)]
+        mount: Option<PathBuf>,
+        
+        /// Most memory the overrides may take, such as 512M or 2G
+        #[arg(long, value_name = "SIZE", value_parser = parse_size_setting)]
+        memory_limit: Option<String>,
+        
+        /// Eviction policy: lru, lfu, fifo, size-weighted or 2q
+        #[arg(long, value_name = "POLICY", value_parser = parse_eviction_policy)]
+        eviction_policy: Option<EvictionPolicy>,
+        
+        /// Reject every write
+        #[arg(long)]
+        read_only: bool,
+        
+        /// Replace source files matching GLOB with TEMPLATE, as `rules add`
+        /// does; may be repeated, replacing the rules of the profile
+        #[arg(long = "rule", value_name = "GLOB=TEMPLATE", value_parser = parse_rule)]
+        rules: Vec<(String, String)>,
+    },
+    
+    /// Remove a profile
+    Remove {
+        /// Name of the profile
+        name: String,
+    },
+}
+
+/// Subcommands of `shadowfs token`.
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Issue a token and print its secret, which is not shown again
+    Issue {
+        /// Name to list the token under, such as the CI system using it
+        name: String,
+        
+        /// What the token allows: read (status, diff and export), mount_control
+        /// (mount, unmount, test, hydrate and rules) and commit (commit
+        /// and rollback-commit)
+        #[arg(long, value_name = "SCOPE", required = true, value_delimiter = ',', value_parser = parse_scope)]
+        scope: Vec<TokenScope>,
+        
+        /// Lifetime of the token, such as 90m, 12h or 30d; without it the
+        /// token works until revoked
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        expires_in: Option<Duration>,
+    },
+    
+    /// List issued tokens
+    List,
+    
+    /// Revoke a token so it stops working immediately
+    Revoke {
+        /// Id of the token, as shown by `shadowfs token list`
+        id: Uuid,
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "shadowfs=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+/// Archive formats of `shadowfs export`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ArchiveFormat {
+    /// Uncompressed tar archive
+    Tar,
+    
+    /// Tar archive compressed with gzip
+    #[value(name = "tar.gz")]
+    TarGz,
+    
+    /// Zip archive
+    Zip,
+}
+
+impl From<ArchiveFormat> for MergedArchiveFormat {
+    fn from(format: ArchiveFormat) -> Self {
+        match format {
+            ArchiveFormat::Tar => Self::Tar,
+            ArchiveFormat::TarGz => Self::TarGz,
+            ArchiveFormat::Zip => Self::Zip,
+        }
+    }
+}
+
+/// Output formats of `shadowfs stats diff`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StatsFormat {
+    /// One line per metric with both values and the change
+    Text,
+    
+    /// A JSON array of changes, for scripting
+    Json,
+}
+
+/// Output formats of `shadowfs diff`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DiffFormat {
+    /// One line per path, prefixed with A, M or D
+    Text,
+    
+    /// A JSON array of entries, for scripting
+    Json,
+}
+
+fn main() -> Result<()> {
+    let mut cli = Cli::parse();
+    let profile = apply_profile(&mut cli.command)?;
+    
+    // Paths are resolved and the process detached before any threads exist:
+    // forking a running Tokio runtime is not sound.
+    let mut ready = None;
+    if let Commands::Mount { source: Some(source), mount: Some(mount), daemon: true, .. } = &cli.command {
+        let mount_point = resolve_path(mount)?;
+        resolve_path(source)?;
+        ready = Some(daemon::daemonize(&MountStateFiles::for_mount_point(&mount_point).log_file)?);
+    }
+    
+    let runtime = shadowfs_core::task::runtime("runtime").context("Failed to start async runtime")?;
+    let _runtime = runtime.enter();
     
-    let cli = Cli::parse();
+    // Initialize tracing; the filter applies to log output only, as
+    // tokio-console and the OTLP exporter filter on their own. Traced
+    // operations are only logged for the paths `shadowfs trace` selects, so
+    // their target is always enabled
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "shadowfs=info".into())
+        .add_directive(format!("{}=debug", TRACE_TARGET).parse().expect("valid trace directive"));
+    let registry = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(filter));
+    #[cfg(feature = "tokio-console")]
+    let registry = registry.with(console_subscriber::spawn());
+    #[cfg(feature = "otlp")]
+    let (otlp, _exporter) = telemetry::otlp_layer()?.unzip();
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp);
+    registry.init();
+    
+    runtime.block_on(run(cli, profile, ready))
+}
+
+/// Fills in what `mount --profile` leaves out on the command line from the
+/// profile, and returns the profile for the settings that have no flag.
+fn apply_profile(command: &mut Commands) -> Result<Option<ConfigProfile>> {
+    let Commands::Mount { profile: Some(name), source, mount, read_only, max_memory, .. } = command else {
+        return Ok(None);
+    };
+    let path = ShadowConfig::default_path();
+    let config = ShadowConfig::load(&path)?;
+    let Some(profile) = config.profiles.get(name.as_str()) else {
+        anyhow::bail!("No profile named {} in {}", name, path.display());
+    };
     
+    let display = |path: &Option<PathBuf>| path.as_ref().map(|path| path.display().to_string());
+    *source = source.take().or_else(|| display(&profile.source));
+    *mount = mount.take().or_else(|| display(&profile.mount_point));
+    *read_only |= profile.read_only.unwrap_or(false);
+    *max_memory = match *max_memory {
+        Some(bytes) => Some(bytes),
+        None => profile.memory_limit_bytes()?,
+    };
+    Ok(Some(profile.clone()))
+}
+
+async fn run(cli: Cli, profile: Option<ConfigProfile>, ready: Option<ReadyNotifier>) -> Result<()> {
     // Detect platform
     let platform = detect_platform();
     info!("Detected platform: {}", platform);
     
+    if let Ok(secret) = std::env::var(TOKEN_ENV) {
+        authorize_command(&cli.command, &secret)?;
+    }
+    
     match cli.command {
-        Commands::Mount { source, mount } => {
+        Commands::Mount {
+            source, mount, pid_file, read_only, immutable_source, deterministic, atime, hide_dotfiles, hide_system_files,
+            hide, hide_from_lookups, self_test, fail_fast, ttl, idle_timeout, max_memory, limits, max_mounts, memory_budget, queue_timeout, rebalance_memory, memory_priority,
+            transforms, statistics, ..
+        } => {
+            let source = source.context("No source given on the command line or in the profile")?;
+            let mount = mount.context("No mount point given on the command line or in the profile")?;
             info!("Mounting {} to {}", source, mount);
-            mount_filesystem(&source, &mount).await?;
+            let max_memory = max_memory.map_or(OverrideStoreConfig::default().max_memory, |bytes| bytes as usize);
+            let options = MountOptions {
+                read_only,
+                failure_policy: fail_fast.map_or_else(FailurePolicy::degrade, fail_fast_policy),
+                expiry: MountExpiry { ttl, idle_timeout, ..MountExpiry::default() },
+                override_config: OverrideConfig::default().with_max_memory(max_memory),
+                memory_priority,
+                immutable_source,
+                limits: limits.quota(),
+                path_limits: limits.path_limits(),
+                determinism: deterministic.then(Determinism::from_env).transpose()?,
+                atime,
+                hidden_files: HiddenFiles {
+                    dotfiles: hide_dotfiles,
+                    hidden_attribute: hide_system_files,
+                    system_attribute: hide_system_files,
+                    globs: hide,
+                    lookups: if hide_from_lookups { HiddenLookups::NotFound } else { HiddenLookups::Resolve },
+                },
+                self_test,
+                ..MountOptions::default()
+            };
+            let settings = ServeSettings {
+                limits: MountLimits { max_mounts, memory_budget, queue_timeout },
+                rebalance: memory_budget.filter(|_| rebalance_memory),
+                read_transforms: read_transforms(&transforms)?,
+                record_stats: statistics.record_stats,
+                metrics_addr: statistics.metrics_addr,
+                eviction_policy: profile.as_ref().map(ConfigProfile::eviction_policy).transpose()?.flatten(),
+                rules: profile.map(|profile| profile.rules).unwrap_or_default(),
+            };
+            mount_filesystem(&source, &mount, pid_file.as_deref(), options, settings, ready).await?;
         }
         Commands::Unmount { mount } => {
             info!("Unmounting {}", mount);
             unmount_filesystem(&mount).await?;
         }
-        Commands::Status => {
+        Commands::Status { clean } => {
             info!("Checking filesystem status");
-            show_status().await?;
+            show_status(clean).await?;
+        }
+        Commands::Config { mount, eviction_policy, compression } => {
+            configure_mount(&mount, eviction_policy, compression).await?;
+        }
+        Commands::Snapshot { mount } => {
+            snapshot_mount(&mount).await?;
+        }
+        Commands::Diff { mount, stat, format } => {
+            info!("Comparing {} with its source", mount);
+            diff_filesystem(&mount, stat, format).await?;
+        }
+        Commands::Export { mount, output, format } => {
+            info!("Exporting {} to {}", mount, output.display());
+            export_filesystem(&mount, &output, format.into()).await?;
+        }
+        Commands::Checksums { mount, output, b3sum, key_file } => {
+            info!("Listing checksums of {}", mount);
+            write_checksums(&mount, output.as_deref(), b3sum, key_file.as_deref()).await?;
+        }
+        Commands::VerifyChecksums { manifest, directory, key_file } => {
+            info!("Checking {} against {}", directory.display(), manifest.display());
+            verify_checksums(&manifest, &directory, key_file.as_deref())?;
+        }
+        Commands::Hydrate { mount, path, estimate } => {
+            info!("Hydrating {} of {}", path, mount);
+            hydrate_filesystem(&mount, &path, estimate).await?;
         }
-        Commands::Test { mount } => {
-            info!("Testing filesystem at {}", mount);
-            test_filesystem(&mount).await?;
+        Commands::Commit { mount, backup } => {
+            info!("Committing overrides of {}", mount);
+            commit_filesystem(&mount, backup).await?;
+        }
+        Commands::RollbackCommit { mount } => {
+            info!("Rolling back last commit of {}", mount);
+            rollback_commit(&mount).await?;
+        }
+        Commands::TryDotfiles { repo, home, mount, shell } => {
+            info!("Trying dotfiles from {}", repo.display());
+            try_dotfiles(&repo, home, mount, shell).await?;
+        }
+        Commands::Test { source } => {
+            info!("Testing the shadow layer over {}", source.display());
+            test_filesystem(&source).await?;
+        }
+        Commands::Token { command } => {
+            manage_tokens(command)?;
+        }
+        Commands::Profiles { command } => {
+            manage_profiles(command)?;
+        }
+        Commands::Gc { mount, json } => {
+            collect_garbage(&mount, json).await?;
+        }
+        Commands::Rules { command } => {
+            manage_rules(command)?;
+        }
+        Commands::Trace { mount, globs, off } => {
+            trace_paths(&mount, globs, off)?;
+        }
+        Commands::Debug { command: DebugCommand::Path { mount, path, json } } => {
+            debug_path(&mount, &path, json).await?;
+        }
+        Commands::Debug { command: DebugCommand::Eviction { mount, target, limit, json } } => {
+            debug_eviction(&mount, target, limit, json).await?;
+        }
+        Commands::Stats { command: Some(command), .. } => {
+            manage_stats(command)?;
+        }
+        Commands::Stats { command: None, mount, histogram, json, interval } => {
+            show_stats(&mount.unwrap_or_default(), histogram, json, interval).await?;
         }
     }
     
@@ -94,41 +967,2216 @@ fn detect_platform() -> &'static str {
     return "Unsupported";
 }
 
-async fn mount_filesystem(_source: &str, _mount: &str) -> Result<()> {
-    #[cfg(windows)]
-    {
-        // TODO: Implement Windows ProjFS mounting
-        anyhow::bail!("Windows mounting not yet implemented");
-    }
-    
-    #[cfg(target_os = "macos")]
-    {
-        // TODO: Implement macOS FSKit mounting
-        anyhow::bail!("macOS mounting not yet implemented");
-    }
-    
-    #[cfg(target_os = "linux")]
-    {
-        // TODO: Implement Linux FUSE mounting
-        anyhow::bail!("Linux mounting not yet implemented");
-    }
-    
-    #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
-    anyhow::bail!("Platform not supported");
+/// Parses a subsystem name given to `--fail-fast`.
+fn parse_subsystem(name: &str) -> std::result::Result<Subsystem, String> {
+    Subsystem::from_name(name).ok_or_else(|| {
+        let names: Vec<_> = Subsystem::ALL.iter().map(Subsystem::name).collect();
+        format!("unknown subsystem '{}', expected one of: {}", name, names.join(", "))
+    })
 }
 
-async fn unmount_filesystem(_mount: &str) -> Result<()> {
-    // TODO: Implement unmounting for each platform
-    anyhow::bail!("Unmounting not yet implemented");
+/// Parses a policy given to `--eviction-policy`.
+fn parse_eviction_policy(name: &str) -> std::result::Result<EvictionPolicy, String> {
+    EvictionPolicy::from_name(name)
+        .ok_or_else(|| format!("unknown eviction policy '{}', expected lru, lfu, fifo, size-weighted or 2q", name))
 }
 
-async fn show_status() -> Result<()> {
-    // TODO: Implement status display
-    println!("No filesystems currently mounted");
-    Ok(())
+/// Parses `on` or `off`.
+fn parse_switch(value: &str) -> std::result::Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, not '{}'", value)),
+    }
+}
+
+/// Parses a scope name given to `token issue --scope`.
+fn parse_scope(name: &str) -> std::result::Result<TokenScope, String> {
+    TokenScope::from_name(name).ok_or_else(|| {
+        let names: Vec<_> = TokenScope::ALL.iter().map(TokenScope::name).collect();
+        format!("unknown scope '{}', expected one of: {}", name, names.join(", "))
+    })
 }
 
-async fn test_filesystem(_mount: &str) -> Result<()> {
-    // TODO: Implement filesystem tests
-    anyhow::bail!("Testing not yet implemented");
+/// Parses a priority given to `mount --memory-priority`.
+fn parse_memory_priority(value: &str) -> std::result::Result<MemoryPriority, String> {
+    match value {
+        "low" => Ok(MemoryPriority::LOW),
+        "normal" => Ok(MemoryPriority::NORMAL),
+        "high" => Ok(MemoryPriority::HIGH),
+        _ => value.parse().map(MemoryPriority).map_err(|_| {
+            format!("invalid priority '{}', expected low, normal, high or a number", value)
+        }),
+    }
+}
+
+/// Parses a `GLOB=SED` pair given to `mount --transform`, checking the
+/// sed expression.
+fn parse_transform(value: &str) -> std::result::Result<(String, String), String> {
+    let (glob, expression) = value.split_once('=')
+        .ok_or_else(|| format!("invalid transform '{}', expected GLOB=SED", value))?;
+    match transforms::sed(expression) {
+        Ok(_) => Ok((glob.to_string(), expression.to_string())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Builds the read transforms given with `mount --transform`, if any.
+fn read_transforms(pairs: &[(String, String)]) -> Result<Option<Arc<ReadTransforms>>> {
+    if pairs.is_empty() {
+        return Ok(None);
+    }
+    let read_transforms = ReadTransforms::new();
+    for (glob, expression) in pairs {
+        let sed = transforms::sed(expression)?;
+        let transformer = ChainTransformer::new(expression.clone(), TransformChain::new().add_transform(sed));
+        read_transforms.register(OverrideRule::Glob(glob.clone()), Arc::new(transformer));
+    }
+    Ok(Some(Arc::new(read_transforms)))
+}
+
+/// Parses a duration such as `45m`, `12h` or `30d`.
+fn parse_duration(value: &str) -> std::result::Result<Duration, String> {
+    let split = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (count, unit) = value.split_at(split);
+    let count: u64 = count.parse().map_err(|_| format!("invalid duration '{}'", value))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid duration '{}', expected a number followed by s, m, h or d", value)),
+    };
+    count.checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{}' is too long", value))
+}
+
+/// Checks a size kept as written in a profile, such as `512M`.
+fn parse_size_setting(value: &str) -> std::result::Result<String, String> {
+    parse_size(value).map(|_| value.to_string())
+}
+
+/// Parses a `GLOB=TEMPLATE` rule of `profiles add --rule`.
+fn parse_rule(value: &str) -> std::result::Result<(String, String), String> {
+    value.split_once('=')
+        .map(|(glob, template)| (glob.to_string(), template.to_string()))
+        .ok_or_else(|| format!("invalid rule '{}', expected GLOB=TEMPLATE", value))
+}
+
+/// Checks that the token in `SHADOWFS_TOKEN` allows `command`.
+///
+/// Token management itself needs direct access to the tokens file and is
+/// never allowed through a token.
+fn authorize_command(command: &Commands, secret: &str) -> Result<()> {
+    let scope = match command {
+        Commands::Trace { globs, off, .. } if globs.is_empty() && !off => TokenScope::Read,
+        Commands::Status { clean: false }
+        | Commands::Diff { .. }
+        | Commands::Export { .. }
+        | Commands::Checksums { .. }
+        | Commands::VerifyChecksums { .. }
+        | Commands::Stats { .. }
+        | Commands::Debug { .. }
+        | Commands::Rules { command: RulesCommand::List { .. } }
+        | Commands::Profiles { command: ProfilesCommand::List } => TokenScope::Read,
+        Commands::Mount { .. }
+        | Commands::Unmount { .. }
+        | Commands::Status { clean: true }
+        | Commands::TryDotfiles { .. }
+        | Commands::Test { .. }
+        | Commands::Hydrate { .. }
+        | Commands::Gc { .. }
+        | Commands::Config { .. }
+        | Commands::Snapshot { .. }
+        | Commands::Trace { .. }
+        | Commands::Rules { .. }
+        | Commands::Profiles { .. } => TokenScope::MountControl,
+        Commands::Commit { .. } | Commands::RollbackCommit { .. } => TokenScope::Commit,
+        Commands::Token { .. } => anyhow::bail!("Tokens cannot be managed with {} set", TOKEN_ENV),
+    };
+    let registry = TokenRegistry::load(&daemon::tokens_file())?;
+    let token = registry.authorize(secret, scope)?;
+    info!("Authorized token '{}' for {}", token.name, scope);
+    Ok(())
+}
+
+/// Runs a `shadowfs token` subcommand.
+fn manage_tokens(command: TokenCommand) -> Result<()> {
+    let path = daemon::tokens_file();
+    let registry = TokenRegistry::load(&path)?;
+    match command {
+        TokenCommand::Issue { name, scope, expires_in } => {
+            registry.purge_expired();
+            let token = registry.issue(name, scope, expires_in);
+            std::fs::create_dir_all(daemon::state_dir()).context("Failed to create runtime state directory")?;
+            registry.save(&path)?;
+            println!("Issued token {} ({})", token.info.id, token.info.name);
+            println!("{}", token.secret);
+            eprintln!("Store the secret now; it cannot be shown again");
+        }
+        TokenCommand::List => {
+            let tokens = registry.list();
+            if tokens.is_empty() {
+                println!("No tokens issued");
+            }
+            let now = SystemTime::now();
+            for token in tokens {
+                let scopes: Vec<_> = token.scopes.iter().map(TokenScope::name).collect();
+                let expiry = match token.expires {
+                    None => "never expires".to_string(),
+                    Some(_) if token.is_expired(now) => "expired".to_string(),
+                    Some(expires) => format!(
+                        "expires in {}",
+                        format_duration(expires.duration_since(now).unwrap_or_default())
+                    ),
+                };
+                println!("{}  {}  [{}]  {}", token.id, token.name, scopes.join(","), expiry);
+            }
+        }
+        TokenCommand::Revoke { id } => {
+            if !registry.revoke(id) {
+                anyhow::bail!("No token with id {}", id);
+            }
+            registry.save(&path)?;
+            println!("Revoked token {}", id);
+        }
+    }
+    Ok(())
+}
+
+/// Runs a `shadowfs profiles` subcommand.
+fn manage_profiles(command: ProfilesCommand) -> Result<()> {
+    let path = ShadowConfig::default_path();
+    match command {
+        ProfilesCommand::List => {
+            let config = ShadowConfig::load(&path)?;
+            if config.profiles.is_empty() {
+                println!("No profiles in {}", path.display());
+            }
+            for (name, profile) in &config.profiles {
+                let mut settings = Vec::new();
+                if let Some(source) = &profile.source {
+                    settings.push(format!("source {}", source.display()));
+                }
+                if let Some(mount_point) = &profile.mount_point {
+                    settings.push(format!("mount point {}", mount_point.display()));
+                }
+                if let Some(memory_limit) = &profile.memory_limit {
+                    settings.push(format!("memory limit {}", memory_limit));
+                }
+                if let Some(policy) = &profile.eviction_policy {
+                    settings.push(format!("eviction policy {}", policy));
+                }
+                if profile.read_only == Some(true) {
+                    settings.push("read-only".to_string());
+                }
+                println!("{}  {}", name, settings.join(", "));
+                for rule in &profile.rules {
+                    println!("    rule {}  priority {}", rule.glob, rule.priority);
+                }
+            }
+        }
+        ProfilesCommand::Add { name, source, mount, memory_limit, eviction_policy, read_only, rules } => {
+            // Environment overrides apply to this process only and are not saved
+            let mut config = ShadowConfig::load_file(&path)?;
+            let current_dir = std::env::current_dir().context("Cannot access the current directory")?;
+            let existed = config.profiles.contains_key(&name);
+            config.profiles.entry(name.clone()).or_default().merge(ConfigProfile {
+                source: source.map(|source| current_dir.join(source)),
+                mount_point: mount.map(|mount| current_dir.join(mount)),
+                memory_limit,
+                eviction_policy: eviction_policy.map(|policy| policy.name().to_string()),
+                read_only: read_only.then_some(true),
+                rules: rules.into_iter()
+                    .map(|(glob, template)| ConfigRule { glob, template, priority: 0 })
+                    .collect(),
+            });
+            config.save(&path)?;
+            println!("{} profile {} in {}", if existed { "Updated" } else { "Added" }, name, path.display());
+        }
+        ProfilesCommand::Remove { name } => {
+            let mut config = ShadowConfig::load_file(&path)?;
+            if config.profiles.remove(&name).is_none() {
+                anyhow::bail!("No profile named {} in {}", name, path.display());
+            }
+            config.save(&path)?;
+            println!("Removed profile {}", name);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the rules of a mount profile missing from the rules of the mount.
+fn add_profile_rules(state: &MountStateFiles, rules: &[ConfigRule]) -> Result<()> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let mut book = RuleBook::load(&state.rules_file)?;
+    for rule in rules {
+        let present = book.rules.iter().any(|existing| existing.glob == rule.glob && existing.template == rule.template);
+        if !present {
+            book.add(rule.glob.clone(), rule.template.clone(), rule.priority);
+        }
+    }
+    book.save(&state.rules_file)?;
+    Ok(())
+}
+
+/// Runs a `shadowfs rules` subcommand.
+fn manage_rules(command: RulesCommand) -> Result<()> {
+    let mount = match &command {
+        RulesCommand::List { mount } | RulesCommand::Add { mount, .. } | RulesCommand::Remove { mount, .. } => mount,
+    };
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    find_record(&mount_point).with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+    let state = MountStateFiles::for_mount_point(&mount_point);
+    let mut book = RuleBook::load(&state.rules_file)?;
+    
+    match command {
+        RulesCommand::List { .. } => {
+            if book.rules.is_empty() {
+                println!("No rules for {}", mount_point.display());
+            }
+            let hits = state.read_rule_hits();
+            let mut rules = book.rules;
+            rules.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+            for rule in rules {
+                let replaced = hits.get(&rule.id).copied().unwrap_or(0);
+                let template = rule.template.lines().next().unwrap_or_default();
+                println!("{}  {}  priority {}  {} replaced  {:?}", rule.id, rule.glob, rule.priority, replaced, template);
+            }
+        }
+        RulesCommand::Add { glob, template, template_file, priority, .. } => {
+            let template = match (template, template_file) {
+                (Some(template), _) => template,
+                (None, Some(file)) => std::fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?,
+                (None, None) => unreachable!("clap requires --template or --template-file"),
+            };
+            let id = book.add(glob, template, priority);
+            book.save(&state.rules_file)?;
+            println!("Added rule {}", id);
+        }
+        RulesCommand::Remove { id, .. } => {
+            if !book.remove(id) {
+                anyhow::bail!("No rule with id {}", id);
+            }
+            book.save(&state.rules_file)?;
+            println!("Removed rule {}", id);
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the globs of the paths a running mount traces, or shows them.
+fn trace_paths(mount: &str, globs: Vec<String>, off: bool) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    find_record(&mount_point).with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+    let state = MountStateFiles::for_mount_point(&mount_point);
+    
+    if globs.is_empty() && !off {
+        let current = state.read_trace_globs();
+        if current.is_empty() {
+            println!("Not tracing any paths of {}", mount_point.display());
+        }
+        for glob in current {
+            println!("{}", glob);
+        }
+        return Ok(());
+    }
+    state.write_trace_globs(&globs)?;
+    if globs.is_empty() {
+        println!("Stopped tracing {}", mount_point.display());
+    } else {
+        println!(
+            "Tracing {} of {}; the serving process logs their operations under {}",
+            globs.join(", "),
+            mount_point.display(),
+            TRACE_TARGET
+        );
+    }
+    Ok(())
+}
+
+fn manage_stats(command: StatsCommand) -> Result<()> {
+    match command {
+        StatsCommand::Snapshot { mount, output } => {
+            let mount_point = std::fs::canonicalize(&mount).unwrap_or_else(|_| PathBuf::from(&mount));
+            let record = find_record(&mount_point)
+                .with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+            if !daemon::is_process_alive(record.process_id) {
+                anyhow::bail!("Process {} serving {} is gone", record.process_id, mount_point.display());
+            }
+            let stats = MountStateFiles::for_mount_point(&mount_point)
+                .read_stats()
+                .with_context(|| format!("{} has not reported statistics yet", mount_point.display()))?;
+            let data = serde_json::to_vec_pretty(&stats)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, data).with_context(|| format!("Failed to write {}", path.display()))?;
+                    println!("Saved the statistics of {} to {}", mount_point.display(), path.display());
+                }
+                None => println!("{}", String::from_utf8_lossy(&data)),
+            }
+        }
+        StatsCommand::Diff { before, after, format } => {
+            let changes = read_stats_record(&before)?.diff(&read_stats_record(&after)?);
+            match format {
+                StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&changes)?),
+                StatsFormat::Text => print_stats_changes(&changes),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_stats_record(path: &Path) -> Result<StatsRecord> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_slice(&data).with_context(|| format!("{} is not a shadowfs statistics file", path.display()))
+}
+
+/// Prints one aligned line per metric: both values and the change between them.
+fn print_stats_changes(changes: &[StatsChange]) {
+    let width = changes.iter().map(|change| change.metric.len()).max().unwrap_or(0);
+    println!("{:width$}  {:>14}  {:>14}  change", "metric", "before", "after", width = width);
+    for change in changes {
+        let relative = match change.relative() {
+            Some(relative) => format!(" ({:+.1}%)", relative * 100.0),
+            None if change.delta() != 0.0 => " (new)".to_string(),
+            None => String::new(),
+        };
+        println!(
+            "{:width$}  {:>14}  {:>14}  {}{}",
+            change.metric,
+            format_metric(change.before),
+            format_metric(change.after),
+            format_metric_delta(change.delta()),
+            relative,
+            width = width
+        );
+    }
+}
+
+/// Formats counts without decimals and rates with three.
+fn format_metric(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.3}", value)
+    }
+}
+
+fn format_metric_delta(delta: f64) -> String {
+    if delta > 0.0 {
+        format!("+{}", format_metric(delta))
+    } else {
+        format_metric(delta)
+    }
+}
+
+/// Formats a duration in its largest whole unit.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Returns the policy failing fast for `subsystems`, or for all subsystems
+/// if none are listed.
+fn fail_fast_policy(subsystems: Vec<Subsystem>) -> FailurePolicy {
+    if subsystems.is_empty() {
+        return FailurePolicy::fail_fast();
+    }
+    subsystems.into_iter().fold(FailurePolicy::degrade(), |policy, subsystem| {
+        policy.with(subsystem, FailureMode::FailFast)
+    })
+}
+
+/// Resolves a user-supplied path to an absolute, canonical path.
+fn resolve_path(path: &str) -> Result<PathBuf> {
+    std::fs::canonicalize(path).with_context(|| format!("Cannot access {}", path))
+}
+
+/// Returns the factory creating this platform's filesystem provider.
+///
+/// Providers that count the operations they serve record them in `stats`.
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+fn provider_factory(stats: Arc<FileSystemStats>) -> Result<ProviderFactory> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Arc::new(move |store| {
+            Arc::new(shadowfs_linux::FuseProvider::with_stats(store, Arc::clone(&stats)))
+                as Arc<dyn shadowfs_core::traits::FileSystemProvider>
+        }))
+    }
+    
+    #[cfg(windows)]
+    {
+        // TODO: Implement Windows ProjFS mounting
+        anyhow::bail!("Windows mounting not yet implemented");
+    }
+    
+    #[cfg(target_os = "macos")]
+    {
+        // The FSKit module serves the volume and counts its operations itself
+        Ok(Arc::new(|store| {
+            Arc::new(shadowfs_macos::fskit::FSKitHostProvider::new(store))
+                as Arc<dyn shadowfs_core::traits::FileSystemProvider>
+        }))
+    }
+    
+    #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+    anyhow::bail!("Platform not supported");
+}
+
+/// Settings of a serving process that are not kept in its mount record.
+struct ServeSettings {
+    /// Limits the mount is admitted under
+    limits: MountLimits,
+    
+    /// Memory budget shared with the other mounts, with `--rebalance-memory`
+    rebalance: Option<u64>,
+    
+    /// Transformers rewriting source files as they are read
+    read_transforms: Option<Arc<ReadTransforms>>,
+    
+    /// File the statistics of the session are saved to on unmount
+    record_stats: Option<PathBuf>,
+    
+    /// Address Prometheus metrics are served on
+    metrics_addr: Option<SocketAddr>,
+    
+    /// Eviction policy of the store, from the profile
+    eviction_policy: Option<EvictionPolicy>,
+    
+    /// Rules added to the rules of the mount, from the profile
+    rules: Vec<ConfigRule>,
+}
+
+/// Mounts `source` at `mount` and serves it until Ctrl-C or SIGTERM, or
+/// until a subsystem fails under a fail-fast policy. With a `rebalance`
+/// budget, the memory limit of the mount follows its share of the budget.
+async fn mount_filesystem(
+    source: &str,
+    mount: &str,
+    pid_file: Option<&Path>,
+    options: MountOptions,
+    settings: ServeSettings,
+    ready: Option<ReadyNotifier>,
+) -> Result<()> {
+    let priority = options.memory_priority;
+    let stats = Arc::new(FileSystemStats::new());
+    let (manager, state, record) = match start_mount(source, mount, pid_file, options, &settings, &stats).await {
+        Ok(started) => started,
+        Err(e) => {
+            if let Some(ready) = ready {
+                ready.notify(false);
+            }
+            return Err(e);
+        }
+    };
+    
+    let daemonized = ready.is_some();
+    if let Some(ready) = ready {
+        ready.notify(true);
+    }
+    
+    if !daemonized {
+        println!("Mounted {} at {}; press Ctrl-C to unmount", source, mount);
+    }
+    
+    let supervisor = manager.supervisor();
+    let store = manager.store();
+    let components = [
+        supervisor.supervise(Component::StatsTicker, {
+            let (store, state, stats) = (Arc::clone(&store), state.clone(), Arc::clone(&stats));
+            move || report_resources(Arc::clone(&store), state.clone(), Arc::clone(&stats))
+        }),
+        supervisor.supervise(Component::Compactor, {
+            let (store, state) = (Arc::clone(&store), state.clone());
+            move || compact_wal(Arc::clone(&store), state.clone())
+        }),
+        supervisor.supervise(Component::PersistenceWriter, {
+            let (store, state) = (Arc::clone(&store), state.clone());
+            move || write_snapshots(Arc::clone(&store), state.clone())
+        }),
+    ];
+    let rules = store.rule_set().map(|rule_set| {
+        shadowfs_core::task::spawn("rule-sync", sync_rules(rule_set, state.clone()))
+    });
+    let trace_sync = store.trace_filter().map(|filter| {
+        shadowfs_core::task::spawn("trace-sync", sync_trace(filter, state.clone()))
+    });
+    let hydration = shadowfs_core::task::spawn(
+        "hydrate",
+        serve_hydration(Arc::clone(&store), state.clone(), resolve_path(source)?),
+    );
+    let unmount_requested = Arc::new(tokio::sync::Notify::new());
+    // Providers without a context have no handles to report
+    let mount_point = resolve_path(mount)?;
+    let handles = match manager.get_provider(&mount_point).await.and_then(|provider| provider.context(&mount_point)) {
+        Some(context) => context.handles,
+        None => Arc::new(HandleTable::new()),
+    };
+    let control = shadowfs_core::task::spawn("control", serve_control(Arc::new(ControlContext {
+        store: Arc::clone(&store),
+        stats: Arc::clone(&stats),
+        handles,
+        state: state.clone(),
+        source: resolve_path(source)?,
+        mount_point,
+        unmount_requested: Arc::clone(&unmount_requested),
+    })));
+    let gc = shadowfs_core::task::spawn(
+        "gc",
+        collect_garbage_periodically(Arc::clone(&store), resolve_path(source)?),
+    );
+    let metrics = settings.metrics_addr.map(|addr| {
+        let registry = Arc::new(StatsRegistry::new());
+        registry.register(mount, Arc::clone(&store), Some(Arc::clone(&stats)));
+        shadowfs_core::task::spawn("metrics", async move {
+            if let Err(e) = registry.serve(addr).await {
+                warn!("Stopped serving metrics on {}: {}", addr, e);
+            }
+        })
+    });
+    let rebalancer = settings.rebalance.map(|budget| {
+        let task = rebalance_memory(Arc::clone(&store), state.clone(), mount.to_string(), budget, priority);
+        shadowfs_core::task::spawn("memory-rebalance", task)
+    });
+    let evictor = BackgroundEvictor::spawn(Arc::clone(&store), EvictorConfig::default());
+    let expiry_warnings = shadowfs_core::task::spawn("expiry-warnings", log_expiry_warnings(manager.subscribe_expiry()));
+    let failure = tokio::select! {
+        result = wait_for_shutdown() => {
+            result?;
+            None
+        }
+        failure = supervisor.wait_for_fail_fast() => Some(failure),
+        (_, reason) = manager.wait_for_expiry() => {
+            info!("Mount expired because {}, unmounting {}", reason, mount);
+            None
+        }
+        _ = unmount_requested.notified() => None,
+    };
+    drop(components);
+    evictor.stop();
+    expiry_warnings.abort();
+    hydration.abort();
+    control.abort();
+    gc.abort();
+    if let Some(rules) = rules {
+        rules.abort();
+    }
+    if let Some(trace_sync) = trace_sync {
+        trace_sync.abort();
+    }
+    if let Some(rebalancer) = rebalancer {
+        rebalancer.abort();
+    }
+    if let Some(metrics) = metrics {
+        metrics.abort();
+    }
+    match &failure {
+        Some(failure) => error!("{}; unmounting {}", failure, mount),
+        None => info!("Shutting down, unmounting {}", mount),
+    }
+    
+    let failures = manager.unmount_all().await;
+    state.remove(pid_file);
+    forget_mount(&FileMountRegistry::in_config_dir(), &record);
+    if let Some(path) = &settings.record_stats {
+        let record = StatsRecord::collect(&stats, &store);
+        match serde_json::to_vec_pretty(&record).map_err(anyhow::Error::from)
+            .and_then(|data| std::fs::write(path, data).map_err(anyhow::Error::from))
+        {
+            Ok(()) => info!("Saved the statistics of the session to {}", path.display()),
+            Err(e) => warn!("Failed to save statistics to {}: {:#}", path.display(), e),
+        }
+    }
+    
+    if let Some(failure) = failure {
+        anyhow::bail!("Unmounted {} after a failure: {}", mount, failure);
+    }
+    if let Some((mount_point, e)) = failures.into_iter().next() {
+        anyhow::bail!("Failed to unmount {}: {}", mount_point.display(), e);
+    }
+    Ok(())
+}
+
+/// Logs the warnings sent before a mount expires.
+async fn log_expiry_warnings(mut events: tokio::sync::broadcast::Receiver<ExpiryEvent>) {
+    while let Ok(event) = events.recv().await {
+        if let ExpiryEvent::Warning { mount_point, reason, remaining } = event {
+            let unless = if reason == ExpiryReason::Idle { " unless it is used" } else { "" };
+            warn!(
+                "{} will be unmounted in {}s{}",
+                mount_point.display(),
+                remaining.as_secs().max(1),
+                unless
+            );
+        }
+    }
+}
+
+/// Mounts the filesystem and records it in the runtime state directory
+/// and the mount registry.
+async fn start_mount(
+    source: &str,
+    mount: &str,
+    pid_file: Option<&Path>,
+    options: MountOptions,
+    settings: &ServeSettings,
+    stats: &Arc<FileSystemStats>,
+) -> Result<(MountManager, MountStateFiles, MountRecord)> {
+    let source = resolve_path(source)?;
+    let mount_point = resolve_path(mount)?;
+    
+    let state = MountStateFiles::for_mount_point(&mount_point);
+    if let Some(existing) = find_record(&mount_point) {
+        if daemon::is_process_alive(existing.process_id) {
+            anyhow::bail!(
+                "{} is already mounted by process {}",
+                mount_point.display(),
+                existing.process_id
+            );
+        }
+        state.remove(None);
+    }
+    
+    // A crashed mount of this mount point is replaced; others are pointed out
+    let registry = FileMountRegistry::in_config_dir();
+    let (replaced, orphans): (Vec<_>, Vec<_>) = registry.stale()
+        .into_iter()
+        .partition(|orphan| Path::new(&orphan.target) == mount_point);
+    for orphan in &replaced {
+        forget_mount(&registry, orphan);
+    }
+    if !orphans.is_empty() {
+        warn!(
+            "{} mounts were left behind by processes that exited without unmounting; \
+             `shadowfs status` lists them and `shadowfs status --clean` removes them",
+            orphans.len()
+        );
+    }
+    
+    let report = Preflight::for_mount(&source, &PreflightOptions::default()).run();
+    for issue in report.warnings() {
+        warn!("Preflight: {}", issue.message());
+    }
+    report.into_result()?;
+    admit_mount(&mount_point, &options, &settings.limits).await?;
+    
+    let defaults = OverrideStoreConfig::default();
+    let store = Arc::new(OverrideStore::new(OverrideStoreConfig {
+        max_memory: options.override_config.max_memory_bytes,
+        eviction_policy: settings.eviction_policy.unwrap_or(defaults.eviction_policy),
+        ..defaults
+    }));
+    std::fs::create_dir_all(daemon::state_dir()).context("Failed to create runtime state directory")?;
+    store.enable_wal(&state.wal_file)
+        .with_context(|| format!("Failed to create {}", state.wal_file.display()))?;
+    store.set_rule_set(Arc::new(RuleSet::new()));
+    add_profile_rules(&state, &settings.rules)?;
+    store.set_trace_filter(Arc::new(PathTraceFilter::new()));
+    // Only a source that does not change is safe to memory-map
+    let source_cache = if options.immutable_source {
+        SourceCacheConfig::for_immutable_source()
+    } else {
+        SourceCacheConfig::default()
+    };
+    store.set_source_cache(Arc::new(SourceFileCache::new(source_cache)));
+    // Listings of a source that does not change only go stale through overrides
+    if options.immutable_source {
+        store.set_listing_cache(Arc::new(ListingCache::new(ListingCacheConfig::default())));
+    }
+    if let Some(read_transforms) = &settings.read_transforms {
+        store.set_read_transforms(Arc::clone(read_transforms));
+    }
+    
+    let manager = MountManager::new(store, provider_factory(Arc::clone(stats))?);
+    manager.mount(&source, &mount_point, options.clone()).await
+        .with_context(|| format!("Failed to mount {}", mount_point.display()))?;
+    
+    let record = MountRecord::new(
+        source.display().to_string(),
+        mount_point.display().to_string(),
+        options,
+        std::process::id(),
+    )
+    .with_snapshot_path(state.snapshot_file.clone());
+    if let Err(e) = state.write(&record, pid_file) {
+        manager.unmount_all().await;
+        state.remove(pid_file);
+        return Err(e);
+    }
+    // Without the registry a crash goes unnoticed after a reboot, which is
+    // no reason to refuse the mount
+    if let Err(e) = registry.register_record(&record) {
+        warn!("Failed to register the mount in {}: {}", registry.dir().display(), e);
+    }
+    
+    Ok((manager, state, record))
+}
+
+/// Removes `record` from the mount registry, warning if that fails.
+fn forget_mount(registry: &FileMountRegistry, record: &MountRecord) {
+    if let Err(e) = registry.unregister_record(record.id) {
+        warn!("Failed to remove the record of {} from {}: {}", record.target, registry.dir().display(), e);
+    }
+}
+
+/// Checks the mounts served by other processes against `limits`, waiting
+/// up to their queue timeout for room if they do not admit this one.
+async fn admit_mount(mount_point: &Path, options: &MountOptions, limits: &MountLimits) -> Result<()> {
+    let reserved = options.override_config.max_memory_bytes as u64;
+    let deadline = limits.queue_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    let mut queued = false;
+    loop {
+        let active: Vec<u64> = daemon::list_records()
+            .into_iter()
+            .filter(|record| daemon::is_process_alive(record.process_id))
+            .map(|record| record.options.override_config.max_memory_bytes as u64)
+            .collect();
+        let rejection = match limits.admit(mount_point, &active, reserved) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        match deadline {
+            Some(deadline) if tokio::time::Instant::now() < deadline => {
+                if !queued {
+                    info!("{}; waiting for other mounts to go away", rejection);
+                    queued = true;
+                }
+                tokio::time::sleep(ADMISSION_POLL_INTERVAL).await;
+            }
+            _ => return Err(rejection.into()),
+        }
+    }
+}
+
+/// Applies changes to the rules file of the mount every [`RULES_INTERVAL`]
+/// and reports how many files each rule replaced.
+async fn sync_rules(rule_set: Arc<RuleSet>, state: MountStateFiles) {
+    let mut sync = RuleBookSync::new(rule_set);
+    let mut interval = tokio::time::interval(RULES_INTERVAL);
+    let mut reported = None;
+    let mut last_error = None;
+    loop {
+        interval.tick().await;
+        match RuleBook::load(&state.rules_file) {
+            Ok(book) => {
+                let (added, removed) = sync.apply(&book);
+                if added + removed > 0 {
+                    info!("Rules updated: {} added, {} removed", added, removed);
+                }
+                last_error = None;
+            }
+            Err(e) => {
+                let message = e.to_string();
+                if last_error.as_ref() != Some(&message) {
+                    warn!("Keeping the current rules: {}", message);
+                    last_error = Some(message);
+                }
+            }
+        }
+        
+        let hits = sync.hits();
+        if reported.as_ref() != Some(&hits) {
+            match state.write_rule_hits(&hits) {
+                Ok(()) => reported = Some(hits),
+                Err(e) => warn!("Failed to report rule hits: {:#}", e),
+            }
+        }
+    }
+}
+
+/// Applies changes to the globs of traced paths every [`TRACE_INTERVAL`].
+async fn sync_trace(filter: Arc<PathTraceFilter>, state: MountStateFiles) {
+    let mut interval = tokio::time::interval(TRACE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let globs = state.read_trace_globs();
+        if globs == filter.globs() {
+            continue;
+        }
+        if globs.is_empty() {
+            info!("Stopped tracing paths");
+        } else {
+            info!("Tracing paths matching {}", globs.join(", "));
+        }
+        filter.set_globs(globs);
+    }
+}
+
+/// Hydrates the subtrees `shadowfs hydrate` asks for, checking for a
+/// request every [`HYDRATE_POLL_INTERVAL`] and reporting progress as often.
+///
+/// Withdrawing the request cancels the hydration; the files copied until
+/// then stay hydrated.
+async fn serve_hydration(store: Arc<OverrideStore>, state: MountStateFiles, source: PathBuf) {
+    let mut interval = tokio::time::interval(HYDRATE_POLL_INTERVAL);
+    let mut served = None;
+    loop {
+        interval.tick().await;
+        let Some(request) = state.read_hydration_request() else { continue };
+        if served == Some(request.id) {
+            continue;
+        }
+        served = Some(request.id);
+        info!("Hydrating {}", request.path);
+        
+        let cancel = CancelHandle::new();
+        let tracker = Arc::new(ProgressTracker::with_cancel_handle("hydrate", cancel.clone()));
+        let mut hydration = shadowfs_core::task::spawn_blocking("hydrate", {
+            let (store, source, tracker, path) = (Arc::clone(&store), source.clone(), Arc::clone(&tracker), request.path.clone());
+            move || store.hydrate_with_progress(&source, &path, tracker.as_ref())
+        });
+        let outcome = loop {
+            tokio::select! {
+                result = &mut hydration => {
+                    break match result {
+                        Ok(result) => result.map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                }
+                _ = interval.tick() => {
+                    if state.read_hydration_request().map(|pending| pending.id) != Some(request.id) {
+                        cancel.cancel();
+                    }
+                    let status = HydrationStatus { id: request.id, progress: tracker.snapshot(), outcome: None };
+                    if let Err(e) = state.write_hydration_status(&status) {
+                        warn!("Failed to report hydration progress: {:#}", e);
+                    }
+                }
+            }
+        };
+        
+        match &outcome {
+            Ok(summary) => info!("Hydrated {}: {} files, {}", request.path, summary.files, format_bytes(summary.bytes)),
+            Err(e) => warn!("Failed to hydrate {}: {}", request.path, e),
+        }
+        let status = HydrationStatus { id: request.id, progress: tracker.snapshot(), outcome: Some(outcome) };
+        if let Err(e) = state.write_hydration_status(&status) {
+            warn!("Failed to report hydration progress: {:#}", e);
+        }
+    }
+}
+
+/// What the control socket of a mount answers from.
+struct ControlContext {
+    store: Arc<OverrideStore>,
+    stats: Arc<FileSystemStats>,
+    handles: Arc<HandleTable>,
+    state: MountStateFiles,
+    source: PathBuf,
+    mount_point: PathBuf,
+    
+    /// Woken to unmount and exit
+    unmount_requested: Arc<tokio::sync::Notify>,
+}
+
+impl ControlContext {
+    async fn answer(&self, request: ControlRequest) -> ControlResponse {
+        match request {
+            ControlRequest::Status => match find_record(&self.mount_point) {
+                Some(record) => {
+                    let config = self.store.get_config();
+                    ControlResponse::Status(Box::new(MountStatus {
+                        record,
+                        resources: MountResources::collect(&self.store),
+                        eviction_policy: config.eviction_policy,
+                        compression: config.enable_compression,
+                        handles: self.handles.summary(),
+                    }))
+                }
+                None => ControlResponse::Error { message: "The mount record is gone".to_string() },
+            },
+            ControlRequest::Inspect { query } => ControlResponse::Inspected { answer: self.inspect(&query) },
+            ControlRequest::Configure { eviction_policy, compression } => {
+                let mut config = self.store.get_config();
+                config.eviction_policy = eviction_policy.unwrap_or(config.eviction_policy);
+                config.enable_compression = compression.unwrap_or(config.enable_compression);
+                let (eviction_policy, compression) = (config.eviction_policy, config.enable_compression);
+                match self.store.update_config(config) {
+                    Ok(()) => {
+                        info!("Eviction policy is now {:?}, compression {}", eviction_policy, on_off(compression));
+                        ControlResponse::Configured { eviction_policy, compression }
+                    }
+                    Err(e) => ControlResponse::Error { message: e.to_string() },
+                }
+            }
+            ControlRequest::Snapshot => match checkpoint(&self.store, &self.state).await {
+                Ok(()) => ControlResponse::Snapshotted { entries: self.store.entry_count() },
+                Err(message) => ControlResponse::Error { message },
+            },
+            ControlRequest::Unmount => {
+                info!("Unmount requested over the control socket");
+                self.unmount_requested.notify_one();
+                ControlResponse::Unmounting
+            }
+        }
+    }
+    
+    fn inspect(&self, query: &InspectQuery) -> InspectAnswer {
+        let (store, source) = (&self.store, &self.source);
+        match query {
+            InspectQuery::Path { path } => InspectAnswer::Path(Box::new(store.inspect_path(path, Some(source)))),
+            InspectQuery::Eviction { target_bytes } => {
+                InspectAnswer::Eviction(store.simulate_eviction(usize::try_from(*target_bytes).unwrap_or(usize::MAX)))
+            }
+            InspectQuery::CollectGarbage => InspectAnswer::Garbage(store.collect_garbage(source)),
+            InspectQuery::Latencies => InspectAnswer::Latencies(self.stats.latency_summaries()),
+            InspectQuery::Live => InspectAnswer::Live(Box::new(LiveStats::collect(&self.stats, store))),
+        }
+    }
+}
+
+/// Answers the requests on the control socket of the mount until aborted.
+async fn serve_control(context: Arc<ControlContext>) {
+    let state = context.state.clone();
+    let handler = move |request| {
+        let context = Arc::clone(&context);
+        async move { context.answer(request).await }
+    };
+    if let Err(e) = control::serve(&state, handler).await {
+        warn!("Stopped answering control requests: {:#}", e);
+    }
+}
+
+/// Drops overrides that no longer hide anything in `source` every
+/// [`GC_INTERVAL`].
+async fn collect_garbage_periodically(store: Arc<OverrideStore>, source: PathBuf) {
+    let start = tokio::time::Instant::now() + GC_INTERVAL;
+    let mut interval = tokio::time::interval_at(start, GC_INTERVAL);
+    loop {
+        interval.tick().await;
+        let (store, source) = (Arc::clone(&store), source.clone());
+        match shadowfs_core::task::spawn_blocking("gc", move || store.collect_garbage(&source)).await {
+            Ok(collection) if collection.removed() > 0 => info!(
+                "Dropped {} tombstones and {} directories, freeing {}",
+                collection.tombstones,
+                collection.directories,
+                format_bytes(collection.freed_bytes as u64)
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to collect garbage: {}", e),
+        }
+    }
+}
+
+/// Works out the share of `budget` this mount gets every
+/// [`REBALANCE_INTERVAL`] and moves its memory limit to it.
+///
+/// Each rebalancing mount publishes a [`MemoryClaim`] and divides the budget
+/// between the claims of all live mounts the same way, so together they
+/// stay within it. Mounts that do not rebalance keep the memory they were
+/// mounted with, which is taken off the budget first. The mounts run their
+/// rounds independently, so for up to one interval the limits may add up
+/// to a little more than the budget.
+async fn rebalance_memory(
+    store: Arc<OverrideStore>,
+    state: MountStateFiles,
+    mount: String,
+    budget: u64,
+    priority: MemoryPriority,
+) {
+    let floor = store.get_config().max_memory as u64 / REBALANCE_FLOOR_DIVISOR;
+    let mut interval = tokio::time::interval(REBALANCE_INTERVAL);
+    let mut lookups = rebalance::lookups(&store);
+    loop {
+        interval.tick().await;
+        let current = rebalance::lookups(&store);
+        let activity = current.saturating_sub(lookups);
+        lookups = current;
+        if let Err(e) = state.write_memory_claim(&MemoryClaim { priority, activity, floor }) {
+            warn!("Failed to publish memory claim: {:#}", e);
+            continue;
+        }
+        
+        let mut claims = Vec::new();
+        let mut own = None;
+        let mut fixed = 0u64;
+        for record in daemon::list_records().into_iter().filter(|record| daemon::is_process_alive(record.process_id)) {
+            let files = MountStateFiles::for_mount_point(Path::new(&record.target));
+            match files.read_memory_claim() {
+                Some(claim) => {
+                    if files.memory_file == state.memory_file {
+                        own = Some(claims.len());
+                    }
+                    claims.push(claim);
+                }
+                None => fixed += record.options.override_config.max_memory_bytes as u64,
+            }
+        }
+        let Some(own) = own else { continue };
+        let limit = divide_budget(budget.saturating_sub(fixed), &claims)[own];
+        let previous = store.get_config().max_memory as u64;
+        if (previous.abs_diff(limit) as f64) < budget as f64 * REBALANCE_TOLERANCE {
+            continue;
+        }
+        match store.set_memory_limit(limit as usize) {
+            Ok(evicted) => {
+                let event = RebalanceEvent { name: mount.clone(), previous, limit, evicted: evicted as u64, activity };
+                info!("Rebalanced memory: {}", event);
+            }
+            Err(e) => warn!("Failed to rebalance memory: {}", e),
+        }
+    }
+}
+
+/// Writes the resource report and statistics record of the mount every [`RESOURCES_INTERVAL`].
+///
+/// Fails on the first report that cannot be written, so the supervisor
+/// restarts the ticker with backoff.
+async fn report_resources(
+    store: Arc<OverrideStore>,
+    state: MountStateFiles,
+    stats: Arc<FileSystemStats>,
+) -> std::result::Result<(), String> {
+    let mut interval = tokio::time::interval(RESOURCES_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.write_resources(&MountResources::collect(&store)) {
+            warn!("Failed to write resource report: {:#}", e);
+            return Err(format!("writing the resource report failed: {:#}", e));
+        }
+        if let Err(e) = state.write_stats(&StatsRecord::collect(&stats, &store)) {
+            warn!("Failed to write statistics record: {:#}", e);
+            return Err(format!("writing the statistics record failed: {:#}", e));
+        }
+    }
+}
+
+/// Checkpoints the write-ahead log into the snapshot once it grows past
+/// [`MAX_WAL_BYTES`].
+async fn compact_wal(store: Arc<OverrideStore>, state: MountStateFiles) -> std::result::Result<(), String> {
+    let mut interval = tokio::time::interval(COMPACTION_INTERVAL);
+    loop {
+        interval.tick().await;
+        let wal_bytes = std::fs::metadata(&state.wal_file).map_or(0, |metadata| metadata.len());
+        if wal_bytes > MAX_WAL_BYTES {
+            checkpoint(&store, &state).await?;
+        }
+    }
+}
+
+/// Checkpoints the write-ahead log into the snapshot every [`SNAPSHOT_INTERVAL`].
+async fn write_snapshots(store: Arc<OverrideStore>, state: MountStateFiles) -> std::result::Result<(), String> {
+    let start = tokio::time::Instant::now() + SNAPSHOT_INTERVAL;
+    let mut interval = tokio::time::interval_at(start, SNAPSHOT_INTERVAL);
+    loop {
+        interval.tick().await;
+        checkpoint(&store, &state).await?;
+    }
+}
+
+/// Writes the snapshot of the mount and truncates its write-ahead log.
+async fn checkpoint(store: &Arc<OverrideStore>, state: &MountStateFiles) -> std::result::Result<(), String> {
+    let (store, snapshot_file) = (Arc::clone(store), state.snapshot_file.clone());
+    let result = shadowfs_core::task::spawn_blocking("checkpoint", move || store.checkpoint(&snapshot_file))
+        .await
+        .map_err(|e| e.to_string())?;
+    result.map_err(|e| {
+        warn!("Failed to checkpoint {}: {}", state.wal_file.display(), e);
+        format!("checkpointing {} failed: {}", state.wal_file.display(), e)
+    })
+}
+
+/// Waits until the process is asked to stop.
+async fn wait_for_shutdown() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    
+    Ok(())
+}
+
+/// Finds the recorded mount at `mount_point`, if any.
+fn find_record(mount_point: &Path) -> Option<MountRecord> {
+    daemon::list_records()
+        .into_iter()
+        .find(|record| Path::new(&record.target) == mount_point)
+}
+
+async fn unmount_filesystem(mount: &str) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    let record = find_record(&mount_point)
+        .with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+    let state = MountStateFiles::for_mount_point(&mount_point);
+    
+    if !daemon::is_process_alive(record.process_id) {
+        warn!("Process {} serving {} is gone; removing stale state", record.process_id, mount);
+        state.remove(None);
+        forget_mount(&FileMountRegistry::in_config_dir(), &record);
+        return Ok(());
+    }
+    
+    // A process not answering on its control socket is signalled instead
+    match control::request(&state, ControlRequest::Unmount).await {
+        Ok(Some(ControlResponse::Unmounting)) => {}
+        Ok(Some(response)) => anyhow::bail!("Unexpected answer from the serving process: {:?}", response),
+        Ok(None) => daemon::request_shutdown(record.process_id)?,
+        Err(e) => {
+            warn!("{:#}; signalling process {} instead", e, record.process_id);
+            daemon::request_shutdown(record.process_id)?;
+        }
+    }
+    
+    let deadline = tokio::time::Instant::now() + UNMOUNT_TIMEOUT;
+    while daemon::is_process_alive(record.process_id) {
+        if tokio::time::Instant::now() >= deadline {
+            anyhow::bail!(
+                "Process {} did not exit within {:?}; {} may still be mounted",
+                record.process_id,
+                UNMOUNT_TIMEOUT,
+                mount_point.display()
+            );
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    
+    println!("Unmounted {}", mount_point.display());
+    Ok(())
+}
+
+async fn show_status(clean: bool) -> Result<()> {
+    let records = daemon::list_records();
+    // Records the runtime state lost, as it does on a reboot, still show up
+    // in the registry
+    let registry = FileMountRegistry::in_config_dir();
+    let orphans: Vec<MountRecord> = registry.stale()
+        .into_iter()
+        .filter(|orphan| !records.iter().any(|record| record.id == orphan.id))
+        .collect();
+    if records.is_empty() && orphans.is_empty() {
+        println!("No filesystems currently mounted");
+        return Ok(());
+    }
+    
+    let mut total = MountResourceTotals::default();
+    let mut stale = 0;
+    for record in records {
+        let alive = daemon::is_process_alive(record.process_id);
+        let state = if alive { "running" } else { "stale" };
+        let access = match (record.options.read_only, record.options.immutable_source) {
+            (true, true) => ", read-only, immutable source",
+            (true, false) => ", read-only",
+            (false, true) => ", immutable source",
+            (false, false) => "",
+        };
+        let expiry = match record.options.expiry.ttl {
+            Some(ttl) => {
+                let remaining = (record.created_at + ttl).duration_since(SystemTime::now()).unwrap_or_default();
+                format!(", expires in {}", format_duration(remaining))
+            }
+            None => String::new(),
+        };
+        println!(
+            "{} <- {} (pid {}, {}{}{})",
+            record.target, record.source, record.process_id, state, access, expiry
+        );
+        
+        if !alive {
+            if clean {
+                MountStateFiles::for_mount_point(Path::new(&record.target)).remove(None);
+                forget_mount(&registry, &record);
+                println!("    removed");
+            }
+            stale += 1;
+            continue;
+        }
+        
+        // The last resource report stands in for a process not answering
+        let files = MountStateFiles::for_mount_point(Path::new(&record.target));
+        let status = match control::request(&files, ControlRequest::Status).await {
+            Ok(Some(ControlResponse::Status(status))) => Some(*status),
+            _ => None,
+        };
+        let resources = match &status {
+            Some(status) => Some(status.resources.clone()),
+            None => files.read_resources().map(|mut resources| {
+                resources.refresh_process();
+                resources
+            }),
+        };
+        if let Some(resources) = resources {
+            println!("    {}", format_resources(&resources));
+            total.add(&resources);
+        }
+        if let Some(status) = status {
+            println!("    eviction policy {:?}, compression {}", status.eviction_policy, on_off(status.compression));
+            let handles = status.handles;
+            println!("    {} open handles on {} files, {} writable", handles.handles, handles.files, handles.writable);
+        }
+    }
+    if total.mounts > 1 {
+        println!(
+            "Total for {} mounts: {} threads, {} open files, {} resident, {} in stores",
+            total.mounts,
+            total.threads,
+            total.open_handles,
+            format_bytes(total.rss_bytes),
+            format_bytes(total.store_memory_bytes)
+        );
+    }
+    
+    for orphan in orphans {
+        println!("{} <- {} (pid {}, orphaned)", orphan.target, orphan.source, orphan.process_id);
+        if let Some(snapshot) = orphan.snapshot_path.as_ref().filter(|path| path.exists()) {
+            println!("    overrides last snapshotted to {}", snapshot.display());
+        }
+        if clean {
+            forget_mount(&registry, &orphan);
+            println!("    removed");
+        }
+        stale += 1;
+    }
+    if stale > 0 && !clean {
+        println!("Run `shadowfs status --clean` to remove the {} mounts left behind by exited processes", stale);
+    }
+    Ok(())
+}
+
+/// Changes store settings of the running mount at `mount` and prints the
+/// settings it ends up with.
+async fn configure_mount(mount: &str, eviction_policy: Option<EvictionPolicy>, compression: Option<bool>) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    let request = ControlRequest::Configure { eviction_policy, compression };
+    match control_request(&mount_point, request).await? {
+        Some(ControlResponse::Configured { eviction_policy, compression }) => {
+            println!(
+                "{}: eviction policy {:?}, compression {}",
+                mount_point.display(),
+                eviction_policy,
+                on_off(compression)
+            );
+            Ok(())
+        }
+        Some(response) => anyhow::bail!("Unexpected answer from the serving process: {:?}", response),
+        None => anyhow::bail!("Settings can only be changed while {} is mounted", mount_point.display()),
+    }
+}
+
+/// Asks the process serving `mount` to write its snapshot now.
+async fn snapshot_mount(mount: &str) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    match control_request(&mount_point, ControlRequest::Snapshot).await? {
+        Some(ControlResponse::Snapshotted { entries }) => {
+            println!("Wrote {} overrides of {} to its snapshot", entries, mount_point.display());
+            Ok(())
+        }
+        Some(response) => anyhow::bail!("Unexpected answer from the serving process: {:?}", response),
+        None => anyhow::bail!("The process serving {} is gone", mount_point.display()),
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled { "on" } else { "off" }
+}
+
+/// Resources summed over the mounts listed by `shadowfs status`.
+#[derive(Default)]
+struct MountResourceTotals {
+    mounts: usize,
+    threads: u64,
+    open_handles: u64,
+    rss_bytes: u64,
+    store_memory_bytes: u64,
+}
+
+impl MountResourceTotals {
+    fn add(&mut self, resources: &MountResources) {
+        self.mounts += 1;
+        self.threads += resources.process.threads.unwrap_or(0);
+        self.open_handles += resources.process.open_handles.unwrap_or(0);
+        self.rss_bytes += resources.process.rss_bytes.unwrap_or(0);
+        self.store_memory_bytes += resources.store_memory_bytes;
+    }
+}
+
+/// Formats a resource report as one line of `shadowfs status` output.
+fn format_resources(resources: &MountResources) -> String {
+    let count = |value: Option<u64>| value.map_or_else(|| "?".to_string(), |v| v.to_string());
+    let restarts: Vec<_> = resources.components.iter()
+        .filter(|status| status.restarts > 0 || status.state == ComponentState::Failed)
+        .map(|status| match status.state {
+            ComponentState::Failed => format!("{} failed after {}", status.component, status.restarts),
+            _ => format!("{} {}", status.component, status.restarts),
+        })
+        .collect();
+    let restarts = if restarts.is_empty() {
+        String::new()
+    } else {
+        format!("; restarts: {}", restarts.join(", "))
+    };
+    format!(
+        "{} threads, {} open files, {} resident; store {} in {} overrides, {} spilled; kernel cache up to {}{}",
+        count(resources.process.threads),
+        count(resources.process.open_handles),
+        resources.process.rss_bytes.map_or_else(|| "?".to_string(), format_bytes),
+        format_bytes(resources.store_memory_bytes),
+        resources.store_entries,
+        format_bytes(resources.spilled_bytes),
+        format_bytes(resources.kernel_cache_estimate_bytes),
+        restarts
+    )
+}
+
+/// Formats a byte count with a binary unit.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Rebuilds the override store of a running mount from its snapshot and
+/// write-ahead log.
+fn load_mount_store(mount: &str) -> Result<(MountRecord, OverrideStore)> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    let record = find_record(&mount_point)
+        .with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+    let state = MountStateFiles::for_mount_point(&mount_point);
+    
+    let store = OverrideStore::with_defaults();
+    if state.snapshot_file.exists() {
+        let data = std::fs::read(&state.snapshot_file)
+            .with_context(|| format!("Failed to read {}", state.snapshot_file.display()))?;
+        OverrideSnapshot::decode(&data)
+            .and_then(|snapshot| snapshot.apply_to_store(&store))
+            .with_context(|| format!("Failed to read {}", state.snapshot_file.display()))?;
+    }
+    if state.wal_file.exists() {
+        WriteAheadLog::replay(&state.wal_file, &store)
+            .with_context(|| format!("Failed to read {}", state.wal_file.display()))?;
+    }
+    Ok((record, store))
+}
+
+/// Runs a blocking store operation with a progress bar, cancelling it on Ctrl-C.
+async fn run_with_progress<T, F>(operation: &'static str, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&ConsoleProgress) -> shadowfs_core::error::Result<T> + Send + 'static,
+{
+    let cancel = CancelHandle::new();
+    let ctrl_c = cancel.cancel_on_ctrl_c();
+    let result = shadowfs_core::task::spawn_blocking(operation, move || {
+        let progress = ConsoleProgress::with_cancel_handle(operation, cancel);
+        f(&progress)
+    })
+    .await;
+    ctrl_c.abort();
+    Ok(result??)
+}
+
+async fn diff_filesystem(mount: &str, stat: bool, format: DiffFormat) -> Result<()> {
+    let (record, store) = load_mount_store(mount)?;
+    let source = PathBuf::from(&record.source);
+    let diff = run_with_progress("diff", move |progress| {
+        store.diff_against_source_with_progress(&source, progress)
+    })
+    .await?;
+    
+    if format == DiffFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+    
+    for entry in &diff {
+        let suffix = if entry.is_directory { "/" } else { "" };
+        if stat {
+            println!(
+                "{}  {}{}  {} -> {}",
+                entry.kind.code(),
+                entry.path,
+                suffix,
+                format_size(entry.source_size),
+                format_size(entry.override_size)
+            );
+        } else {
+            println!("{}  {}{}", entry.kind.code(), entry.path, suffix);
+        }
+    }
+    
+    if stat {
+        let count = |kind| diff.iter().filter(|entry| entry.kind == kind).count();
+        println!(
+            "{} added, {} modified, {} deleted",
+            count(DiffKind::Added),
+            count(DiffKind::Modified),
+            count(DiffKind::Deleted)
+        );
+    }
+    Ok(())
+}
+
+/// Archives the merged view of a mount to `output`, removing the partial
+/// archive if the export fails or is cancelled.
+async fn export_filesystem(mount: &str, output: &Path, format: MergedArchiveFormat) -> Result<()> {
+    let (record, store) = load_mount_store(mount)?;
+    let source = PathBuf::from(&record.source);
+    let file = std::fs::File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let result = run_with_progress("export", move |progress| {
+        store.export_merged_with_progress(&source, std::io::BufWriter::new(file), format, progress)
+    })
+    .await;
+    let summary = match result {
+        Ok(summary) => summary,
+        Err(e) => {
+            let _ = std::fs::remove_file(output);
+            return Err(e.context(format!("Failed to export {}", record.target)));
+        }
+    };
+    
+    println!(
+        "Exported {} to {}: {} files, {} directories and {} symlinks, {} ({} from overrides)",
+        record.target,
+        output.display(),
+        summary.files,
+        summary.directories,
+        summary.symlinks,
+        format_bytes(summary.bytes),
+        summary.overridden
+    );
+    Ok(())
+}
+
+/// Writes the checksum manifest of a mount's merged view to `output` or
+/// standard output, signed with the secret in `key_file` if given.
+async fn write_checksums(mount: &str, output: Option<&Path>, b3sum: bool, key_file: Option<&Path>) -> Result<()> {
+    let key = key_file.map(read_manifest_key).transpose()?;
+    let (record, store) = load_mount_store(mount)?;
+    let source = PathBuf::from(&record.source);
+    let mut manifest = run_with_progress("checksums", move |progress| {
+        store.checksum_manifest_with_progress(&source, progress)
+    })
+    .await
+    .with_context(|| format!("Failed to list checksums of {}", record.target))?;
+    if let Some(key) = &key {
+        manifest.sign(key);
+    }
+    
+    let text = if b3sum {
+        manifest.to_b3sum()
+    } else {
+        serde_json::to_string_pretty(&manifest)? + "\n"
+    };
+    match output {
+        Some(output) => {
+            std::fs::write(output, text).with_context(|| format!("Failed to write {}", output.display()))?;
+            eprintln!("Wrote checksums of {} files of {} to {}", manifest.entries.len(), record.target, output.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// Checks `directory` against a checksum manifest, and its signature
+/// against the secret in `key_file` if given.
+fn verify_checksums(manifest: &Path, directory: &Path, key_file: Option<&Path>) -> Result<()> {
+    let json = std::fs::read_to_string(manifest).with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let manifest: ChecksumManifest = serde_json::from_str(&json)
+        .with_context(|| format!("{} is not a checksum manifest", manifest.display()))?;
+    if let Some(key_file) = key_file {
+        manifest.verify_signature(&read_manifest_key(key_file)?)?;
+        println!("Signature OK");
+    } else if manifest.signature.is_some() {
+        warn!("The manifest is signed; pass --key-file to check the signature");
+    }
+    
+    let mismatches = manifest.verify_directory(directory)
+        .with_context(|| format!("Failed to check {}", directory.display()))?;
+    for mismatch in &mismatches {
+        println!("{}", mismatch);
+    }
+    if !mismatches.is_empty() {
+        anyhow::bail!("{} differs from the manifest in {} paths", directory.display(), mismatches.len());
+    }
+    println!("{} matches all {} entries", directory.display(), manifest.entries.len());
+    Ok(())
+}
+
+fn read_manifest_key(key_file: &Path) -> Result<ManifestKey> {
+    let secret = std::fs::read(key_file).with_context(|| format!("Failed to read key file {}", key_file.display()))?;
+    anyhow::ensure!(!secret.is_empty(), "Key file {} is empty", key_file.display());
+    Ok(ManifestKey::from_secret(&secret))
+}
+
+/// Shows how much hydrating `path` of a running mount copies, then has the
+/// serving process copy it unless `estimate_only` is set.
+async fn hydrate_filesystem(mount: &str, path: &str, estimate_only: bool) -> Result<()> {
+    let (record, store) = load_mount_store(mount)?;
+    let mount_point = PathBuf::from(&record.target);
+    let state = MountStateFiles::for_mount_point(&mount_point);
+    let path = mount_path(&mount_point, path);
+    
+    let mut estimate = store.estimate_hydration(Path::new(&record.source), &path)
+        .with_context(|| format!("Cannot hydrate {}", path))?;
+    let used = state.read_resources().map_or(0, |resources| resources.store_memory_bytes);
+    estimate.memory_available = (record.options.override_config.max_memory_bytes as u64).saturating_sub(used);
+    println!(
+        "{}: {} files in {} directories, {} to copy; {} paths already in the mount",
+        path,
+        estimate.files,
+        estimate.directories,
+        format_bytes(estimate.bytes),
+        estimate.already_present
+    );
+    if !estimate.fits_in_memory() {
+        println!(
+            "The mount has {} of memory left, so older entries will be evicted to make room",
+            format_bytes(estimate.memory_available)
+        );
+    }
+    if estimate_only {
+        return Ok(());
+    }
+    if !daemon::is_process_alive(record.process_id) {
+        anyhow::bail!("Process {} serving {} is gone", record.process_id, mount_point.display());
+    }
+    
+    let request = HydrationRequest { id: Uuid::new_v4(), path: path.clone() };
+    state.write_hydration_request(&request)?;
+    let progress = ConsoleProgress::new("hydrate");
+    let mut shown = shadowfs_core::progress::ProgressUpdate::default();
+    let mut interval = tokio::time::interval(HYDRATE_POLL_INTERVAL);
+    let outcome = loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                state.remove_hydration_request();
+                anyhow::bail!("Hydration cancelled; the files copied so far stay hydrated");
+            }
+        }
+        if !daemon::is_process_alive(record.process_id) {
+            anyhow::bail!("Process {} serving {} exited while hydrating", record.process_id, mount_point.display());
+        }
+        let Some(status) = state.read_hydration_status().filter(|status| status.id == request.id) else {
+            continue;
+        };
+        if shown.items_total.is_none() {
+            progress.set_total(status.progress.items_total, status.progress.bytes_total);
+        }
+        progress.advance(
+            status.progress.items_done.saturating_sub(shown.items_done),
+            status.progress.bytes_done.saturating_sub(shown.bytes_done),
+        );
+        if let Some(current) = &status.progress.current_path {
+            progress.set_current_path(current);
+        }
+        shown = status.progress;
+        if let Some(outcome) = status.outcome {
+            break outcome;
+        }
+    };
+    progress.finish();
+    state.remove_hydration_request();
+    
+    let summary = outcome.map_err(anyhow::Error::msg).with_context(|| format!("Failed to hydrate {}", path))?;
+    println!(
+        "Hydrated {}: {} files and {} directories, {}",
+        path,
+        summary.files,
+        summary.directories,
+        format_bytes(summary.bytes)
+    );
+    Ok(())
+}
+
+/// Returns the path in the mount of `path`, given either under the mount
+/// point or relative to the mount root.
+fn mount_path(mount_point: &Path, path: &str) -> ShadowPath {
+    let host = std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    match host.strip_prefix(mount_point) {
+        Ok(relative) => ShadowPath::from(Path::new("/").join(relative)),
+        Err(_) => ShadowPath::from(Path::new("/").join(path.trim_start_matches('/'))),
+    }
+}
+
+/// Sends `request` to the process serving `mount_point`, failing with the
+/// message of an error it answers with.
+///
+/// # Returns
+/// None if the process is gone
+async fn control_request(mount_point: &Path, request: ControlRequest) -> Result<Option<ControlResponse>> {
+    let record = find_record(mount_point)
+        .with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+    if !daemon::is_process_alive(record.process_id) {
+        return Ok(None);
+    }
+    
+    let response = control::request(&MountStateFiles::for_mount_point(mount_point), request).await?
+        .with_context(|| format!("Process {} serving {} is not listening for requests", record.process_id, mount_point.display()))?;
+    match response {
+        ControlResponse::Error { message } => anyhow::bail!("Process {} refused: {}", record.process_id, message),
+        response => Ok(Some(response)),
+    }
+}
+
+/// Asks the process serving `mount_point` a `shadowfs debug` question.
+///
+/// # Returns
+/// None if the process is gone
+async fn ask_serving_process(mount_point: &Path, query: InspectQuery) -> Result<Option<InspectAnswer>> {
+    match control_request(mount_point, ControlRequest::Inspect { query }).await? {
+        Some(ControlResponse::Inspected { answer }) => Ok(Some(answer)),
+        Some(response) => anyhow::bail!("Unexpected answer from the serving process: {:?}", response),
+        None => {
+            warn!("The process serving {} is gone; showing its persisted store", mount_point.display());
+            Ok(None)
+        }
+    }
+}
+
+/// Shows what a running mount knows about `path`, or what its persisted
+/// store holds if the serving process is gone.
+async fn debug_path(mount: &str, path: &str, json: bool) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    let path = mount_path(&mount_point, path);
+    
+    let report = match ask_serving_process(&mount_point, InspectQuery::Path { path: path.clone() }).await? {
+        Some(InspectAnswer::Path(report)) => *report,
+        Some(answer) => anyhow::bail!("Unexpected answer from the serving process: {:?}", answer),
+        None => {
+            let (record, store) = load_mount_store(mount)?;
+            store.inspect_path(&path, Some(Path::new(&record.source)))
+        }
+    };
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_path_report(&report);
+    }
+    Ok(())
+}
+
+async fn collect_garbage(mount: &str, json: bool) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    let record = find_record(&mount_point)
+        .with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+    if !daemon::is_process_alive(record.process_id) {
+        anyhow::bail!("Process {} serving {} is gone", record.process_id, mount_point.display());
+    }
+    let collection = match ask_serving_process(&mount_point, InspectQuery::CollectGarbage).await? {
+        Some(InspectAnswer::Garbage(collection)) => collection,
+        Some(answer) => anyhow::bail!("Unexpected answer from the serving process: {:?}", answer),
+        None => anyhow::bail!("Process {} serving {} exited", record.process_id, mount_point.display()),
+    };
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&collection)?);
+    } else {
+        println!(
+            "Dropped {} tombstones and {} directories, freeing {}",
+            collection.tombstones,
+            collection.directories,
+            format_bytes(collection.freed_bytes as u64)
+        );
+    }
+    Ok(())
+}
+
+/// Shows the live dashboard of a running mount, or prints its statistics
+/// once as JSON, or its latencies as a table when asked for `histogram` or
+/// when stdout is not a terminal.
+async fn show_stats(mount: &str, histogram: bool, json: bool, interval: Duration) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    if json {
+        let stats = live_stats(&mount_point).await?;
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        Ok(())
+    } else if histogram || !std::io::stdout().is_terminal() {
+        show_latencies(&mount_point, histogram).await
+    } else {
+        dashboard::run(&mount_point, interval, || live_stats(&mount_point)).await
+    }
+}
+
+/// Asks the process serving `mount_point` for its current statistics.
+async fn live_stats(mount_point: &Path) -> Result<LiveStats> {
+    match ask_serving_process(mount_point, InspectQuery::Live).await? {
+        Some(InspectAnswer::Live(stats)) => Ok(*stats),
+        Some(answer) => anyhow::bail!("Unexpected answer from the serving process: {:?}", answer),
+        None => anyhow::bail!("Statistics are only kept while {} is mounted", mount_point.display()),
+    }
+}
+
+/// Shows the latency percentiles of every operation a running mount has
+/// served, and with `histogram` how they are distributed.
+async fn show_latencies(mount_point: &Path, histogram: bool) -> Result<()> {
+    let latencies = match ask_serving_process(mount_point, InspectQuery::Latencies).await? {
+        Some(InspectAnswer::Latencies(latencies)) => latencies,
+        Some(answer) => anyhow::bail!("Unexpected answer from the serving process: {:?}", answer),
+        None => anyhow::bail!("Latencies are only kept while {} is mounted", mount_point.display()),
+    };
+    
+    let served: Vec<(&String, &LatencySummary)> = latencies.iter().filter(|(_, summary)| summary.count > 0).collect();
+    if served.is_empty() {
+        println!("{} has not served any operations yet", mount_point.display());
+        return Ok(());
+    }
+    println!(
+        "{:<10} {:>10} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "operation", "count", "p50", "p90", "p99", "p99.9", "max"
+    );
+    for (name, summary) in &served {
+        println!(
+            "{:<10} {:>10} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            name,
+            summary.count,
+            format_micros(summary.p50_micros),
+            format_micros(summary.p90_micros),
+            format_micros(summary.p99_micros),
+            format_micros(summary.p999_micros),
+            format_micros(summary.max_micros)
+        );
+    }
+    if histogram {
+        for (name, summary) in &served {
+            println!();
+            println!("{}:", name);
+            print_latency_histogram(summary);
+        }
+    }
+    Ok(())
+}
+
+/// Longest bar drawn by [`print_latency_histogram`].
+const HISTOGRAM_WIDTH: u64 = 50;
+
+/// Draws one bar per power-of-two bucket, scaled to the fullest bucket;
+/// every bucket holding anything gets at least one mark.
+fn print_latency_histogram(summary: &LatencySummary) {
+    let fullest = summary.buckets.iter().map(|bucket| bucket.count).max().unwrap_or(0).max(1);
+    for bucket in &summary.buckets {
+        let bar = (bucket.count * HISTOGRAM_WIDTH / fullest).max(1);
+        println!(
+            "  < {:>8} {:>10} ({:>5.1}%) {}",
+            format_micros(bucket.below_micros),
+            bucket.count,
+            bucket.count as f64 * 100.0 / summary.count as f64,
+            "#".repeat(bar as usize)
+        );
+    }
+}
+
+/// Formats a latency in microseconds in the largest unit that keeps it
+/// above one.
+fn format_micros(micros: u64) -> String {
+    match micros {
+        0..=999 => format!("{}µs", micros),
+        1_000..=999_999 => format!("{:.1}ms", micros as f64 / 1_000.0),
+        _ => format!("{:.2}s", micros as f64 / 1_000_000.0),
+    }
+}
+
+async fn debug_eviction(mount: &str, target: u64, limit: usize, json: bool) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    
+    let simulation = match ask_serving_process(&mount_point, InspectQuery::Eviction { target_bytes: target }).await? {
+        Some(InspectAnswer::Eviction(simulation)) => simulation,
+        Some(answer) => anyhow::bail!("Unexpected answer from the serving process: {:?}", answer),
+        None => {
+            let (_, store) = load_mount_store(mount)?;
+            store.simulate_eviction(usize::try_from(target).unwrap_or(usize::MAX))
+        }
+    };
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&simulation)?);
+        return Ok(());
+    }
+    println!(
+        "Freeing {} of {} resident, configured policy {:?}{}",
+        format_bytes(simulation.target_bytes as u64),
+        format_bytes(simulation.resident_bytes as u64),
+        simulation.configured_policy,
+        if simulation.spills { " (spills to disk in LRU order instead)" } else { "" }
+    );
+    for policy in &simulation.policies {
+        println!();
+        println!(
+            "{:?}: {} overrides, {}{}",
+            policy.policy,
+            policy.victims.len(),
+            format_bytes(policy.freed_bytes as u64),
+            if policy.reaches(simulation.target_bytes) { "" } else { ", short of the target" }
+        );
+        for victim in policy.victims.iter().take(limit) {
+            println!("  {:>10}  {}", format_bytes(victim.bytes as u64), victim.path);
+        }
+        if policy.victims.len() > limit {
+            println!("  ... and {} more", policy.victims.len() - limit);
+        }
+    }
+    Ok(())
+}
+
+fn print_path_report(report: &PathReport) {
+    let ago = |time: SystemTime| format!("{} ago", format_duration(SystemTime::now().duration_since(time).unwrap_or_default()));
+    let state = match report.state {
+        PathState::File => "file override",
+        PathState::Directory => "directory override",
+        PathState::Deleted => "deleted",
+        PathState::Delta => "source file with written ranges",
+        PathState::Source => "no override",
+    };
+    println!("{}: {}", report.path, state);
+    if let Some(content) = &report.content {
+        println!(
+            "  content: {}, {} stored{}, hash {}",
+            format_bytes(content.size),
+            format_bytes(content.stored_bytes),
+            if content.compressed { " compressed" } else { "" },
+            content.content_hash
+        );
+    }
+    if let Some(delta) = &report.delta {
+        println!(
+            "  delta: {} long, {} ranges holding {}, written {}",
+            format_bytes(delta.len),
+            delta.extent_count,
+            format_bytes(delta.stored_bytes),
+            ago(delta.modified)
+        );
+    }
+    if let (Some(created), Some(accessed)) = (report.created_at, report.last_accessed) {
+        println!("  created {}, last accessed {}", ago(created), ago(accessed));
+    }
+    if report.state != PathState::Source {
+        let residency = if report.spilled { "spilled to disk" } else { "in memory" };
+        println!("  residency: {}{}", residency, if report.hot_cached { ", in the hot cache" } else { "" });
+    }
+    if report.hard_links.len() > 1 {
+        let links: Vec<String> = report.hard_links.iter().map(ToString::to_string).collect();
+        println!("  hard links: {}", links.join(", "));
+    }
+    match &report.source {
+        Some(source) => println!(
+            "  source: {:?}, {}, modified {}{}",
+            source.file_type,
+            format_bytes(source.size),
+            ago(source.modified),
+            if report.source_cached { ", open in the source cache" } else { "" }
+        ),
+        None => println!("  source: missing"),
+    }
+    if let Some(original) = &report.original_metadata {
+        println!("  source when overridden: {}, modified {}", format_bytes(original.size), ago(original.modified));
+    }
+    if let Some(conflict) = &report.conflict {
+        println!("  conflict: source {:?} {}", conflict.kind, ago(conflict.detected_at));
+    }
+    if report.recent_operations.is_empty() {
+        println!("  no changes in the write-ahead log");
+    } else {
+        println!("  recent changes:");
+        for operation in &report.recent_operations {
+            println!("    {:>4}  {}", ago(operation.timestamp), operation.description);
+        }
+    }
+}
+
+fn format_size(size: Option<u64>) -> String {
+    size.map_or_else(|| "-".to_string(), |bytes| format!("{} B", bytes))
+}
+
+async fn commit_filesystem(mount: &str, backup: bool) -> Result<()> {
+    let (record, store) = load_mount_store(mount)?;
+    let source = PathBuf::from(&record.source);
+    let options = if backup {
+        CommitOptions::with_default_backup(&source)
+    } else {
+        CommitOptions::default()
+    };
+    
+    let commit_source = source.clone();
+    let summary = run_with_progress("commit", move |progress| {
+        store.commit(&commit_source, &options, progress)
+    })
+    .await
+    .map_err(|e| match e.downcast_ref::<ShadowError>() {
+        // Resuming cannot help until the source accepts writes again
+        Some(ShadowError::SourceNotWritable { .. }) => e.context("Commit is unavailable"),
+        _ => e.context("Commit did not finish; run it again to resume"),
+    })?;
+    
+    println!(
+        "Committed to {}: {} files written, {} directories created, {} removed",
+        source.display(),
+        summary.files_written,
+        summary.directories_created,
+        summary.paths_removed
+    );
+    if summary.resumed > 0 {
+        println!("{} paths were already applied by an earlier run", summary.resumed);
+    }
+    if let Some(backup_set) = summary.backup_set {
+        println!("Previous versions saved in {}", backup_set.display());
+    }
+    Ok(())
+}
+
+async fn rollback_commit(mount: &str) -> Result<()> {
+    let mount_point = std::fs::canonicalize(mount).unwrap_or_else(|_| PathBuf::from(mount));
+    let record = find_record(&mount_point)
+        .with_context(|| format!("No shadowfs mount at {}", mount_point.display()))?;
+    let source = PathBuf::from(&record.source);
+    
+    let summary = shadowfs_core::override_store::rollback_commit(
+        &source,
+        &CommitOptions::with_default_backup(&source),
+    )
+    .with_context(|| format!("No commit backup to roll back in {}", source.display()))?;
+    
+    println!(
+        "Rolled back {}: {} paths restored, {} removed",
+        summary.backup_set.display(),
+        summary.restored,
+        summary.removed
+    );
+    Ok(())
+}
+
+async fn try_dotfiles(
+    repo: &Path,
+    home: Option<PathBuf>,
+    mount: Option<PathBuf>,
+    shell: Option<String>,
+) -> Result<()> {
+    let profile = MountProfile::dotfiles();
+    let repo = std::fs::canonicalize(repo)
+        .with_context(|| format!("Cannot access {}", repo.display()))?;
+    let home = match home.or_else(|| std::env::var_os("HOME").map(PathBuf::from)) {
+        Some(home) => std::fs::canonicalize(&home)
+            .with_context(|| format!("Cannot access {}", home.display()))?,
+        None => anyhow::bail!("HOME is not set; pass --home"),
+    };
+    let shell = shell
+        .or_else(|| std::env::var("SHELL").ok())
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    
+    let created_mount_point = mount.is_none();
+    let mount_point = mount.unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("shadowfs-home-{}", std::process::id()))
+    });
+    std::fs::create_dir_all(&mount_point)
+        .with_context(|| format!("Failed to create {}", mount_point.display()))?;
+    
+    let store = Arc::new(OverrideStore::with_defaults());
+    let seed_store = Arc::clone(&store);
+    let seed_profile = profile.clone();
+    let seeded = run_with_progress("seed", move |progress| {
+        seed_profile.seed(&seed_store, &repo, progress)
+    })
+    .await?;
+    println!("Seeded {} files ({} bytes) from the dotfiles repository", seeded.files, seeded.bytes);
+    
+    let manager = MountManager::new(Arc::clone(&store), provider_factory(Arc::new(FileSystemStats::new()))?);
+    manager.mount(&home, &mount_point, MountOptions::default()).await
+        .with_context(|| format!("Failed to mount {}", mount_point.display()))?;
+    
+    println!("Starting {} with HOME={}; exit the shell to see what changed", shell, mount_point.display());
+    let status = tokio::process::Command::new(&shell)
+        .env("HOME", &mount_point)
+        .current_dir(&mount_point)
+        .status()
+        .await;
+    
+    let diff = store.diff_against_source(&home).map(|diff| profile.filter_diff(diff));
+    let failures = manager.unmount_all().await;
+    if created_mount_point && failures.is_empty() {
+        let _ = std::fs::remove_dir(&mount_point);
+    }
+    
+    status.with_context(|| format!("Failed to run {}", shell))?;
+    let diff = diff?;
+    if diff.is_empty() {
+        println!("No changes compared to {}", home.display());
+    } else {
+        println!("Changes compared to {} (discarded):", home.display());
+        for entry in &diff {
+            let suffix = if entry.is_directory { "/" } else { "" };
+            println!("{}  {}{}", entry.kind.code(), entry.path, suffix);
+        }
+    }
+    
+    if let Some((mount_point, e)) = failures.into_iter().next() {
+        anyhow::bail!("Failed to unmount {}: {}", mount_point.display(), e);
+    }
+    Ok(())
+}
+
+/// Runs the checks of `shadowfs test` through an in-process mount of `source`.
+async fn test_filesystem(source: &Path) -> Result<()> {
+    let source = std::fs::canonicalize(source).with_context(|| format!("Cannot open {}", source.display()))?;
+    let store = Arc::new(OverrideStore::with_defaults());
+    // A handful of lookups always has a low hit rate
+    store.update_alert_config(AlertConfig { alerts_enabled: false, ..AlertConfig::default() });
+    let manager = MountManager::new(store, VirtualFs::factory());
+    let mount_point = PathBuf::from("/shadowfs-test");
+    manager.mount(&source, &mount_point, MountOptions::default()).await?;
+    let provider = manager.get_provider(&mount_point).await.context("The in-process mount is gone")?;
+    let test = SelfTest {
+        provider,
+        mount_point: mount_point.clone(),
+        source: source.clone(),
+        scratch: ShadowPath::from(format!("/.shadowfs-test-{}", std::process::id()).as_str()),
+    };
+    
+    let mut outcomes = vec![("list the source", test.list().await)];
+    if let Some(file) = test.sample_file()? {
+        outcomes.push(("read a source file", test.read(&file).await));
+        outcomes.push(("override a source file", test.overwrite(&file).await));
+    }
+    outcomes.push(("create a directory and a file", test.create().await));
+    outcomes.push(("rename a file", test.rename().await));
+    outcomes.push(("delete a file and a directory", test.delete().await));
+    outcomes.push(("leave the source untouched", test.source_untouched()));
+    outcomes.push(("unmount", manager.unmount(&mount_point).await.map(drop).map_err(Into::into)));
+    
+    let mut failed = 0;
+    for (check, outcome) in &outcomes {
+        match outcome {
+            Ok(()) => println!("ok      {}", check),
+            Err(e) => {
+                failed += 1;
+                println!("FAILED  {}: {:#}", check, e);
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} checks failed", failed, outcomes.len());
+    }
+    println!("All {} checks passed over {}", outcomes.len(), source.display());
+    Ok(())
+}
+
+/// Content `shadowfs test` writes through the mount.
+const TEST_PROBE: &[u8] = b"written by shadowfs test\n";
+
+/// The checks of `shadowfs test`, run through one in-process mount.
+struct SelfTest {
+    provider: Arc<dyn FileSystemProvider>,
+    mount_point: PathBuf,
+    source: PathBuf,
+    /// Directory the checks create, which the source does not have
+    scratch: ShadowPath,
+}
+
+impl SelfTest {
+    /// Lists the root through the mount and compares it with the source.
+    async fn list(&self) -> Result<()> {
+        let listed: Vec<String> = self.provider
+            .read_directory(&self.mount_point, &ShadowPath::from("/"))
+            .await?
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        let mut expected = Vec::new();
+        for entry in std::fs::read_dir(&self.source)? {
+            expected.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        expected.sort();
+        anyhow::ensure!(listed == expected, "the mount lists {} entries, the source has {}", listed.len(), expected.len());
+        Ok(())
+    }
+    
+    /// Returns the first regular file at the top of the source, by name.
+    fn sample_file(&self) -> Result<Option<ShadowPath>> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&self.source)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        Ok(files.into_iter().min().map(|name| ShadowPath::from("/").join(&name)))
+    }
+    
+    /// Reads `file` through the mount and compares it with the source.
+    async fn read(&self, file: &ShadowPath) -> Result<()> {
+        let on_disk = std::fs::read(self.source_path(file))?;
+        let read = self.provider.read(&self.mount_point, file, 0, on_disk.len()).await?;
+        anyhow::ensure!(read == on_disk, "{} reads differently through the mount", file);
+        Ok(())
+    }
+    
+    /// Overwrites the start of `file` through the mount and checks that the
+    /// source keeps its content.
+    async fn overwrite(&self, file: &ShadowPath) -> Result<()> {
+        let before = std::fs::read(self.source_path(file))?;
+        self.provider.write(&self.mount_point, file, 0, TEST_PROBE).await?;
+        let read = self.provider.read(&self.mount_point, file, 0, TEST_PROBE.len()).await?;
+        anyhow::ensure!(read == TEST_PROBE, "{} does not show what was written", file);
+        anyhow::ensure!(std::fs::read(self.source_path(file))? == before, "writing {} changed the source", file);
+        Ok(())
+    }
+    
+    /// Creates the scratch directory and a file in it.
+    async fn create(&self) -> Result<()> {
+        self.provider.create_directory(&self.mount_point, &self.scratch).await?;
+        let file = self.scratch.join("probe.txt");
+        self.provider.create_file(&self.mount_point, &file, TEST_PROBE.into()).await?;
+        let read = self.provider.read(&self.mount_point, &file, 0, TEST_PROBE.len()).await?;
+        anyhow::ensure!(read == TEST_PROBE, "{} does not hold what it was created with", file);
+        Ok(())
+    }
+    
+    /// Renames the file [`SelfTest::create`] made.
+    async fn rename(&self) -> Result<()> {
+        let (from, to) = (self.scratch.join("probe.txt"), self.scratch.join("renamed.txt"));
+        self.provider.rename(&self.mount_point, &from, &to).await?;
+        anyhow::ensure!(self.provider.metadata(&self.mount_point, &from).await.is_err(), "{} is still there", from);
+        let size = self.provider.metadata(&self.mount_point, &to).await?.size;
+        anyhow::ensure!(size == TEST_PROBE.len() as u64, "{} has {} bytes after the rename", to, size);
+        Ok(())
+    }
+    
+    /// Deletes the renamed file and the scratch directory.
+    async fn delete(&self) -> Result<()> {
+        self.provider.delete(&self.mount_point, &self.scratch.join("renamed.txt")).await?;
+        self.provider.delete(&self.mount_point, &self.scratch).await?;
+        let gone = self.provider.metadata(&self.mount_point, &self.scratch).await.is_err();
+        anyhow::ensure!(gone, "{} is still there after deleting it", self.scratch);
+        Ok(())
+    }
+    
+    /// Checks that nothing the checks created reached the source.
+    fn source_untouched(&self) -> Result<()> {
+        let scratch = self.source_path(&self.scratch);
+        anyhow::ensure!(!scratch.exists(), "{} was created in the source", scratch.display());
+        Ok(())
+    }
+    
+    fn source_path(&self, path: &ShadowPath) -> PathBuf {
+        mount_paths::source_path(&self.source, path)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+//! Export of spans to an OpenTelemetry collector over OTLP.
+
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Environment variable with the address of the collector, such as
+/// `http://localhost:4317`; spans are only exported when it is set.
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Environment variable selecting the spans exported, in `RUST_LOG` syntax.
+pub const FILTER_ENV: &str = "SHADOWFS_OTLP_FILTER";
+
+/// Spans exported unless [`FILTER_ENV`] is set: mounts and the provider
+/// callbacks under them. The spans of each store operation are at trace
+/// level.
+const DEFAULT_FILTER: &str = "shadowfs=debug";
+
+/// Flushes the spans not yet exported when dropped.
+pub struct Exporter(TracerProvider);
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            eprintln!("Failed to export the last spans: {}", e);
+        }
+    }
+}
+
+/// Returns a layer exporting spans to the collector [`ENDPOINT_ENV`]
+/// names, or `None` if it is not set.
+///
+/// Spans are sent in batches from a task, so this must be called within
+/// the Tokio runtime, which must outlive the [`Exporter`].
+pub fn otlp_layer<S>() -> Result<Option<(impl Layer<S>, Exporter)>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if std::env::var_os(ENDPOINT_ENV).is_none() {
+        return Ok(None);
+    }
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .build()
+        .context("Failed to create the OTLP exporter")?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", "shadowfs")]))
+        .build();
+    let filter = EnvFilter::try_from_env(FILTER_ENV).unwrap_or_else(|_| DEFAULT_FILTER.into());
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("shadowfs"))
+        .with_filter(filter);
+    Ok(Some((layer, Exporter(provider))))
+}
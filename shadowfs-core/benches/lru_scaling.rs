@@ -0,0 +1,103 @@
+//! Scalability of recording accesses in the LRU tracker.
+//!
+//! Every lookup of the override store records an access, so the tracker
+//! sits on the hottest path of a busy mount. This benchmark records
+//! accesses to a shared working set from 1 to 32 threads, with the sharded
+//! [`LruTracker`] and with a tracker guarding one `IndexMap` with one mutex,
+//! as the store used to, and prints the throughput of each.
+//!
+//! ```text
+//! cargo bench -p shadowfs-core --bench lru_scaling
+//! ```
+
+use indexmap::IndexMap;
+use shadowfs_core::override_store::LruTracker;
+use shadowfs_core::types::ShadowPath;
+use std::collections::HashMap;
+use std::sync::{Arc, Barrier, Mutex};
+use std::time::{Duration, Instant};
+
+/// Paths accesses are spread over
+const WORKING_SET: usize = 4096;
+
+/// Accesses each thread records per run
+const ACCESSES_PER_THREAD: usize = 200_000;
+
+/// Thread counts measured
+const THREADS: [usize; 6] = [1, 2, 4, 8, 16, 32];
+
+/// Something that accesses can be recorded in from many threads.
+trait Tracker: Send + Sync {
+    fn record_access(&self, path: &ShadowPath);
+}
+
+impl Tracker for LruTracker {
+    fn record_access(&self, path: &ShadowPath) {
+        LruTracker::record_access(self, path);
+    }
+}
+
+/// The tracker as it was: the access order and counts each behind one mutex.
+#[derive(Default)]
+struct GlobalMutexTracker {
+    order: Mutex<IndexMap<ShadowPath, Instant>>,
+    counts: Mutex<HashMap<ShadowPath, u64>>,
+}
+
+impl Tracker for GlobalMutexTracker {
+    fn record_access(&self, path: &ShadowPath) {
+        *self.counts.lock().unwrap().entry(path.clone()).or_insert(0) += 1;
+        let mut order = self.order.lock().unwrap();
+        order.shift_remove(path);
+        order.insert(path.clone(), Instant::now());
+    }
+}
+
+/// Records accesses from `threads` threads at once and returns how long it took.
+fn run(tracker: Arc<dyn Tracker>, paths: &Arc<Vec<ShadowPath>>, threads: usize) -> Duration {
+    let start = Arc::new(Barrier::new(threads + 1));
+    let handles: Vec<_> = (0..threads)
+        .map(|thread| {
+            let (tracker, paths, start) = (Arc::clone(&tracker), Arc::clone(paths), Arc::clone(&start));
+            std::thread::spawn(move || {
+                start.wait();
+                // Each thread walks the working set with its own stride
+                let stride = 2 * thread + 1;
+                for access in 0..ACCESSES_PER_THREAD {
+                    tracker.record_access(&paths[(thread + access * stride) % paths.len()]);
+                }
+            })
+        })
+        .collect();
+    
+    start.wait();
+    let began = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    began.elapsed()
+}
+
+fn main() {
+    let paths: Arc<Vec<ShadowPath>> = Arc::new(
+        (0..WORKING_SET)
+            .map(|i| ShadowPath::from(format!("/src/module{}/file{}.rs", i % 64, i)))
+            .collect(),
+    );
+    
+    println!("{:>8} {:>16} {:>16} {:>8}", "threads", "sharded Mops/s", "mutex Mops/s", "speedup");
+    for threads in THREADS {
+        let accesses = (threads * ACCESSES_PER_THREAD) as f64;
+        let sharded = run(Arc::new(LruTracker::new()), &paths, threads);
+        let global = run(Arc::new(GlobalMutexTracker::default()), &paths, threads);
+        let sharded_rate = accesses / sharded.as_secs_f64() / 1e6;
+        let global_rate = accesses / global.as_secs_f64() / 1e6;
+        println!(
+            "{:>8} {:>16.2} {:>16.2} {:>7.1}x",
+            threads,
+            sharded_rate,
+            global_rate,
+            sharded_rate / global_rate,
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Per-path access control for the shadow layer.
+//!
+//! An [`AccessPolicy`] is an ordered list of [`AccessRule`]s, each matching
+//! paths by prefix or glob and allowing or denying some of the
+//! [`AccessOperation`]s. For a given path and operation the last matching
+//! rule decides, so broad rules go first and exceptions after them:
+//!
+//! ```rust
+//! use shadowfs_core::access::{AccessOperation, AccessPolicy, AccessRule};
+//! use shadowfs_core::types::ShadowPath;
+//!
+//! let policy = AccessPolicy::allow_all()
+//!     .with_rule(AccessRule::deny_prefix("/etc", &[AccessOperation::Write, AccessOperation::Delete]))
+//!     .with_rule(AccessRule::allow_glob("/etc/*.local", &[AccessOperation::Write]));
+//!
+//! assert!(!policy.is_allowed(&ShadowPath::from("/etc/hosts"), AccessOperation::Write));
+//! assert!(policy.is_allowed(&ShadowPath::from("/etc/hosts.local"), AccessOperation::Write));
+//! ```
+//!
+//! Once attached with [`OverrideStore::set_access_policy`], the store checks
+//! writes and deletes itself and fails them with
+//! [`ShadowError::AccessDenied`]. Reads of the source tree never go through
+//! the store, so providers call [`OverrideStore::check_access`] before
+//! serving them. Every denial is recorded in the policy's [`AccessAuditLog`].
+
+use crate::error::{access_denied, ShadowError};
+use crate::override_store::{OverrideRule, OverrideStore};
+use crate::types::{current_time, ShadowPath};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Default number of denials kept by an [`AccessAuditLog`].
+pub const DEFAULT_AUDIT_CAPACITY: usize = 1024;
+
+/// An operation subject to access control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AccessOperation {
+    /// Reading file content or listing a directory
+    Read,
+    /// Creating or modifying a file or directory
+    Write,
+    /// Deleting a file or directory
+    Delete,
+}
+
+impl AccessOperation {
+    fn bit(self) -> u8 {
+        match self {
+            AccessOperation::Read => 1,
+            AccessOperation::Write => 2,
+            AccessOperation::Delete => 4,
+        }
+    }
+}
+
+impl fmt::Display for AccessOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessOperation::Read => write!(f, "read"),
+            AccessOperation::Write => write!(f, "write"),
+            AccessOperation::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+/// Whether a rule grants or refuses its operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessEffect {
+    /// The operations are permitted
+    Allow,
+    /// The operations fail with [`ShadowError::AccessDenied`]
+    Deny,
+}
+
+/// Which paths a rule applies to.
+#[derive(Debug, Clone)]
+pub enum AccessPattern {
+    /// The path and everything below it, compared component by component
+    Prefix(ShadowPath),
+    /// Paths matching a glob; `*` also matches across directory separators
+    Glob(String),
+}
+
+impl AccessPattern {
+    /// Tests if `path` matches the pattern.
+    pub fn matches(&self, path: &ShadowPath) -> bool {
+        match self {
+            AccessPattern::Prefix(prefix) => path.as_path().starts_with(prefix.as_path()),
+            AccessPattern::Glob(pattern) => OverrideRule::Glob(pattern.clone()).matches(path),
+        }
+    }
+}
+
+impl fmt::Display for AccessPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessPattern::Prefix(prefix) => write!(f, "{}", prefix),
+            AccessPattern::Glob(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+/// A single allow or deny rule.
+#[derive(Debug, Clone)]
+pub struct AccessRule {
+    /// Paths the rule applies to
+    pub pattern: AccessPattern,
+    
+    /// Whether matching operations are allowed or denied
+    pub effect: AccessEffect,
+    
+    operations: u8,
+}
+
+impl AccessRule {
+    /// Creates a rule applying `effect` to `operations` on paths matching `pattern`.
+    pub fn new(pattern: AccessPattern, effect: AccessEffect, operations: &[AccessOperation]) -> Self {
+        Self {
+            pattern,
+            effect,
+            operations: operations.iter().fold(0, |bits, op| bits | op.bit()),
+        }
+    }
+    
+    /// Allows `operations` on `prefix` and everything below it.
+    pub fn allow_prefix(prefix: impl Into<ShadowPath>, operations: &[AccessOperation]) -> Self {
+        Self::new(AccessPattern::Prefix(prefix.into()), AccessEffect::Allow, operations)
+    }
+    
+    /// Denies `operations` on `prefix` and everything below it.
+    pub fn deny_prefix(prefix: impl Into<ShadowPath>, operations: &[AccessOperation]) -> Self {
+        Self::new(AccessPattern::Prefix(prefix.into()), AccessEffect::Deny, operations)
+    }
+    
+    /// Allows `operations` on paths matching the glob `pattern`.
+    pub fn allow_glob(pattern: impl Into<String>, operations: &[AccessOperation]) -> Self {
+        Self::new(AccessPattern::Glob(pattern.into()), AccessEffect::Allow, operations)
+    }
+    
+    /// Denies `operations` on paths matching the glob `pattern`.
+    pub fn deny_glob(pattern: impl Into<String>, operations: &[AccessOperation]) -> Self {
+        Self::new(AccessPattern::Glob(pattern.into()), AccessEffect::Deny, operations)
+    }
+    
+    /// Checks whether the rule covers `operation`.
+    pub fn covers(&self, operation: AccessOperation) -> bool {
+        self.operations & operation.bit() != 0
+    }
+    
+    /// Checks whether the rule decides `operation` on `path`.
+    pub fn applies_to(&self, path: &ShadowPath, operation: AccessOperation) -> bool {
+        self.covers(operation) && self.pattern.matches(path)
+    }
+}
+
+/// A denied operation recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeniedAccess {
+    /// Path the operation targeted
+    pub path: ShadowPath,
+    
+    /// Operation that was refused
+    pub operation: AccessOperation,
+    
+    /// Pattern of the deciding rule, or `None` when the default effect applied
+    pub rule: Option<String>,
+    
+    /// When the operation was refused
+    pub at: SystemTime,
+}
+
+/// Bounded log of denied operations; the oldest records are dropped first.
+#[derive(Debug)]
+pub struct AccessAuditLog {
+    records: Mutex<VecDeque<DeniedAccess>>,
+    capacity: usize,
+    total: AtomicU64,
+}
+
+impl Default for AccessAuditLog {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_AUDIT_CAPACITY)
+    }
+}
+
+impl AccessAuditLog {
+    /// Creates a log keeping the last `capacity` denials.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_AUDIT_CAPACITY))),
+            capacity,
+            total: AtomicU64::new(0),
+        }
+    }
+    
+    /// Records a denial.
+    pub fn record(&self, denial: DeniedAccess) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(denial);
+    }
+    
+    /// Returns the retained denials, oldest first.
+    pub fn entries(&self) -> Vec<DeniedAccess> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+    
+    /// Returns and forgets the retained denials, oldest first.
+    pub fn take(&self) -> Vec<DeniedAccess> {
+        self.records.lock().unwrap().drain(..).collect()
+    }
+    
+    /// Returns the number of denials ever recorded, including dropped ones.
+    pub fn total_denied(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
+
+/// Ordered allow and deny rules with an audit log of denials.
+#[derive(Debug)]
+pub struct AccessPolicy {
+    rules: Vec<AccessRule>,
+    default_effect: AccessEffect,
+    audit: AccessAuditLog,
+}
+
+impl AccessPolicy {
+    /// Creates a policy applying `default_effect` where no rule matches.
+    pub fn new(default_effect: AccessEffect) -> Self {
+        Self {
+            rules: Vec::new(),
+            default_effect,
+            audit: AccessAuditLog::default(),
+        }
+    }
+    
+    /// Creates a policy allowing everything no rule denies.
+    pub fn allow_all() -> Self {
+        Self::new(AccessEffect::Allow)
+    }
+    
+    /// Creates a policy denying everything no rule allows.
+    pub fn deny_all() -> Self {
+        Self::new(AccessEffect::Deny)
+    }
+    
+    /// Appends a rule; later rules take precedence over earlier ones.
+    pub fn with_rule(mut self, rule: AccessRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+    
+    /// Replaces the audit log, for example to change its capacity.
+    pub fn with_audit_log(mut self, audit: AccessAuditLog) -> Self {
+        self.audit = audit;
+        self
+    }
+    
+    /// Returns the rules in evaluation order.
+    pub fn rules(&self) -> &[AccessRule] {
+        &self.rules
+    }
+    
+    /// Returns the log of denied operations.
+    pub fn audit_log(&self) -> &AccessAuditLog {
+        &self.audit
+    }
+    
+    /// Returns the last rule deciding `operation` on `path`, if any.
+    pub fn matching_rule(&self, path: &ShadowPath, operation: AccessOperation) -> Option<&AccessRule> {
+        self.rules.iter().rev().find(|rule| rule.applies_to(path, operation))
+    }
+    
+    /// Checks whether `operation` on `path` is allowed, without auditing.
+    pub fn is_allowed(&self, path: &ShadowPath, operation: AccessOperation) -> bool {
+        let effect = self.matching_rule(path, operation)
+            .map(|rule| rule.effect)
+            .unwrap_or(self.default_effect);
+        effect == AccessEffect::Allow
+    }
+    
+    /// Checks `operation` on `path`, recording it in the audit log if denied.
+    ///
+    /// # Returns
+    /// `Ok(())` if allowed, or `AccessDenied` naming the path and operation
+    pub fn check(&self, path: &ShadowPath, operation: AccessOperation) -> Result<(), ShadowError> {
+        let rule = self.matching_rule(path, operation);
+        let effect = rule.map(|rule| rule.effect).unwrap_or(self.default_effect);
+        if effect == AccessEffect::Allow {
+            return Ok(());
+        }
+        
+        self.audit.record(DeniedAccess {
+            path: path.clone(),
+            operation,
+            rule: rule.map(|rule| rule.pattern.to_string()),
+            at: current_time(),
+        });
+        Err(access_denied(path.clone(), operation.to_string()))
+    }
+}
+
+impl OverrideStore {
+    /// Enforces `policy` on every write and delete made through the store.
+    pub fn set_access_policy(&self, policy: Arc<AccessPolicy>) {
+        *self.access.write().unwrap() = Some(policy);
+    }
+    
+    /// Stops enforcing access rules, returning the previous policy.
+    pub fn clear_access_policy(&self) -> Option<Arc<AccessPolicy>> {
+        self.access.write().unwrap().take()
+    }
+    
+    /// Returns the enforced access policy, if any.
+    pub fn access_policy(&self) -> Option<Arc<AccessPolicy>> {
+        self.access.read().unwrap().clone()
+    }
+    
+    /// Checks `operation` on `path` against the attached policy.
+    ///
+    /// Providers call this for reads, which the store does not see when they
+    /// are served from the source tree.
+    ///
+    /// # Returns
+    /// `Ok(())` if no policy is attached or it allows the operation
+    pub fn check_access(&self, path: &ShadowPath, operation: AccessOperation) -> Result<(), ShadowError> {
+        match self.access.read().unwrap().as_ref() {
+            Some(policy) => policy.check(path, operation),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_last_matching_rule_wins() {
+        let policy = AccessPolicy::allow_all()
+            .with_rule(AccessRule::deny_prefix("/secrets", &[AccessOperation::Read, AccessOperation::Write]))
+            .with_rule(AccessRule::allow_glob("/secrets/*.pub", &[AccessOperation::Read]));
+        
+        assert!(!policy.is_allowed(&ShadowPath::from("/secrets/id_rsa"), AccessOperation::Read));
+        assert!(policy.is_allowed(&ShadowPath::from("/secrets/id_rsa.pub"), AccessOperation::Read));
+        assert!(!policy.is_allowed(&ShadowPath::from("/secrets/id_rsa.pub"), AccessOperation::Write));
+        assert!(policy.is_allowed(&ShadowPath::from("/secrets/id_rsa"), AccessOperation::Delete));
+        // Prefixes match whole components only
+        assert!(policy.is_allowed(&ShadowPath::from("/secretsfile"), AccessOperation::Read));
+        
+        let policy = AccessPolicy::deny_all()
+            .with_rule(AccessRule::allow_prefix("/tmp", &[AccessOperation::Write]));
+        assert!(policy.is_allowed(&ShadowPath::from("/tmp/out.log"), AccessOperation::Write));
+        assert!(!policy.is_allowed(&ShadowPath::from("/home/out.log"), AccessOperation::Write));
+    }
+    
+    #[test]
+    fn test_store_enforces_policy_and_audits() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/etc/hosts"), Bytes::from("old"), None).unwrap();
+        let policy = Arc::new(
+            AccessPolicy::allow_all()
+                .with_rule(AccessRule::deny_prefix("/etc", &[AccessOperation::Write, AccessOperation::Delete]))
+                .with_audit_log(AccessAuditLog::with_capacity(1)),
+        );
+        store.set_access_policy(Arc::clone(&policy));
+        
+        let result = store.insert_file(ShadowPath::from("/etc/hosts"), Bytes::from("new"), None);
+        assert!(matches!(result, Err(ShadowError::AccessDenied { operation, .. }) if operation == "write"));
+        let result = store.mark_deleted(ShadowPath::from("/etc/hosts"));
+        assert!(matches!(result, Err(ShadowError::AccessDenied { operation, .. }) if operation == "delete"));
+        assert!(store.delete_directory_recursive(&ShadowPath::from("/etc")).is_err());
+        store.insert_file(ShadowPath::from("/home/notes.txt"), Bytes::from("ok"), None).unwrap();
+        assert!(store.check_access(&ShadowPath::from("/etc/hosts"), AccessOperation::Read).is_ok());
+        
+        let data = store.get(&ShadowPath::from("/etc/hosts")).unwrap().get_file_data().unwrap().unwrap();
+        assert_eq!(data, Bytes::from("old"));
+        
+        let audit = policy.audit_log();
+        assert_eq!(audit.total_denied(), 3);
+        let denials = audit.take();
+        assert_eq!(denials.len(), 1);
+        assert_eq!(denials[0].path, ShadowPath::from("/etc"));
+        assert_eq!(denials[0].operation, AccessOperation::Delete);
+        assert_eq!(denials[0].rule.as_deref(), Some("/etc"));
+        
+        assert!(store.clear_access_policy().is_some());
+        store.mark_deleted(ShadowPath::from("/etc/hosts")).unwrap();
+    }
+}
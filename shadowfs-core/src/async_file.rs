@@ -0,0 +1,371 @@
+//! Tokio file handles to the shadow layer.
+//!
+//! [`MountContext::open`], and [`FileSystemProvider::open`] for a mount of a
+//! provider, return a [`ShadowFile`]: a cursor over one file implementing
+//! `AsyncRead`, `AsyncWrite` and `AsyncSeek`, so async code can use the
+//! layer directly, with `tokio::io::copy`, codecs or `AsyncReadExt`, and no
+//! OS mount.
+//!
+//! Reads are served in chunks of [`BUFFER_SIZE`] and consecutive writes are
+//! gathered into one write to the store. Writing a source file stores only
+//! the ranges written, copying nothing of the source until the store
+//! compacts the file; the source itself is never changed. Buffered writes
+//! reach the store when the file is flushed, read from, seeked to its end,
+//! shut down or dropped; flush or shut the file down to see their errors.
+//! Changes made to the file through other handles show once the chunk read
+//! ahead is used up.
+//!
+//! The store is in memory, so operations complete when polled. Reads that
+//! fall through to the source read it in place, which on a local disk is
+//! short enough not to warrant a blocking thread per read.
+//!
+//! [`FileSystemProvider::open`]: crate::traits::FileSystemProvider::open
+//!
+//! ```rust
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::passthrough::MountContext;
+//! use shadowfs_core::types::{OpenFlags, ShadowPath};
+//! use std::sync::Arc;
+//! use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let source = tempfile::tempdir()?;
+//! std::fs::write(source.path().join("log.txt"), "started\n")?;
+//! let mount = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false);
+//!
+//! let mut file = mount.open(&ShadowPath::from("/log.txt"), OpenFlags::READ | OpenFlags::APPEND)?;
+//! file.write_all(b"finished\n").await?;
+//! file.seek(SeekFrom::Start(0)).await?;
+//! let mut log = String::new();
+//! file.read_to_string(&mut log).await?;
+//! assert_eq!(log, "started\nfinished\n");
+//! assert_eq!(std::fs::read_to_string(source.path().join("log.txt"))?, "started\n");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::compat::io_error;
+use crate::error::{self, Result, ShadowError};
+use crate::passthrough::MountContext;
+use crate::types::{FileHandle, FileMetadata, FileType, OpenFlags, ShadowPath};
+use bytes::Bytes;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+/// Bytes read ahead and written behind by a [`ShadowFile`].
+pub const BUFFER_SIZE: usize = 64 * 1024;
+
+impl MountContext {
+    /// Opens the file at `path` for async reads and writes.
+    ///
+    /// `flags` are those of `open(2)`: [`OpenFlags::CREATE`] creates a
+    /// missing file, [`OpenFlags::EXCLUSIVE`] with it fails if the file
+    /// exists, [`OpenFlags::TRUNCATE`] empties it and [`OpenFlags::APPEND`]
+    /// writes at its end. Creating, truncating and appending need
+    /// [`OpenFlags::WRITE`] or [`OpenFlags::APPEND`]; no access flag at all
+    /// opens for reading.
+    pub fn open(&self, path: &ShadowPath, flags: OpenFlags) -> Result<ShadowFile> {
+        let writes = flags.contains(OpenFlags::WRITE) || flags.contains(OpenFlags::APPEND);
+        let reads = flags.contains(OpenFlags::READ) || !writes;
+        if !writes && (flags.contains(OpenFlags::CREATE) || flags.contains(OpenFlags::TRUNCATE)) {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!("creating or truncating {path} needs write access"),
+            });
+        }
+        if writes {
+            self.check_writable(path, "open for writing")?;
+        }
+        
+        if flags.contains(OpenFlags::CREATE) && flags.contains(OpenFlags::EXCLUSIVE) {
+            self.create_file(path, Bytes::new())?;
+        } else {
+            match self.metadata(path) {
+                Ok(metadata) if writes && metadata.file_type == FileType::Directory => {
+                    return Err(error::is_a_directory(path.clone()));
+                }
+                Ok(_) if flags.contains(OpenFlags::TRUNCATE) => self.truncate(path, 0)?,
+                Ok(_) => {}
+                Err(ShadowError::NotFound { .. }) if flags.contains(OpenFlags::CREATE) => {
+                    self.create_file(path, Bytes::new())?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(ShadowFile {
+            context: self.clone(),
+            handle: self.handles.open(path.clone(), flags),
+            path: path.clone(),
+            position: 0,
+            read: reads,
+            write: writes,
+            append: flags.contains(OpenFlags::APPEND),
+            read_buffer: Bytes::new(),
+            read_offset: 0,
+            write_buffer: Vec::new(),
+            write_offset: 0,
+        })
+    }
+}
+
+/// An open file of the shadow layer with a cursor, read ahead and written
+/// behind; see the [module documentation](self).
+pub struct ShadowFile {
+    context: MountContext,
+    handle: FileHandle,
+    path: ShadowPath,
+    position: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+    
+    /// Bytes of the file from `read_offset` on, as last read
+    read_buffer: Bytes,
+    read_offset: u64,
+    
+    /// Bytes written at `write_offset` and not yet stored
+    write_buffer: Vec<u8>,
+    write_offset: u64,
+}
+
+impl ShadowFile {
+    /// Returns the path the file was opened at.
+    pub fn path(&self) -> &ShadowPath {
+        &self.path
+    }
+    
+    /// Returns the handle the file is registered under in the
+    /// [`HandleTable`](crate::handles::HandleTable) of its mount.
+    pub fn handle(&self) -> FileHandle {
+        self.handle
+    }
+    
+    /// Returns the position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+    
+    /// Returns the metadata of the file, buffered writes included.
+    pub fn metadata(&mut self) -> Result<FileMetadata> {
+        self.flush_writes()?;
+        self.context.metadata(&self.path)
+    }
+    
+    /// Cuts the file to `len` bytes or extends it with zeros, leaving the
+    /// cursor where it is.
+    pub fn set_len(&mut self, len: u64) -> Result<()> {
+        self.check_access(self.write, "write")?;
+        self.flush_writes()?;
+        self.read_buffer = Bytes::new();
+        self.context.truncate(&self.path, len)
+    }
+    
+    /// Stores the buffered writes.
+    pub fn sync_all(&mut self) -> Result<()> {
+        self.flush_writes()
+    }
+    
+    fn check_access(&self, allowed: bool, operation: &str) -> Result<()> {
+        if allowed {
+            Ok(())
+        } else {
+            Err(error::permission_denied(self.path.clone(), format!("{operation} a file not opened to {operation}")))
+        }
+    }
+    
+    fn flush_writes(&mut self) -> Result<()> {
+        if self.write_buffer.is_empty() {
+            return Ok(());
+        }
+        self.context.write(&self.path, self.write_offset, &self.write_buffer)?;
+        self.write_buffer.clear();
+        Ok(())
+    }
+    
+    fn read_at_cursor(&mut self, buf: &mut ReadBuf<'_>) -> Result<()> {
+        self.check_access(self.read, "read")?;
+        self.flush_writes()?;
+        let buffered = self.position.checked_sub(self.read_offset)
+            .filter(|&skip| skip < self.read_buffer.len() as u64);
+        let available = match buffered {
+            Some(skip) => self.read_buffer.slice(skip as usize..),
+            None => {
+                let size = buf.remaining().max(BUFFER_SIZE);
+                self.read_buffer = self.context.read(&self.path, self.position, size)?;
+                self.read_offset = self.position;
+                self.read_buffer.clone()
+            }
+        };
+        
+        let len = available.len().min(buf.remaining());
+        buf.put_slice(&available[..len]);
+        self.position += len as u64;
+        Ok(())
+    }
+    
+    fn write_at_cursor(&mut self, data: &[u8]) -> Result<usize> {
+        self.check_access(self.write, "write")?;
+        let buffered_end = self.write_offset + self.write_buffer.len() as u64;
+        if self.append && self.write_buffer.is_empty() {
+            self.position = self.context.metadata(&self.path)?.size;
+        } else if self.append {
+            self.position = buffered_end;
+        }
+        if !self.write_buffer.is_empty() && self.position != buffered_end {
+            self.flush_writes()?;
+        }
+        
+        if self.write_buffer.is_empty() {
+            self.write_offset = self.position;
+        }
+        self.write_buffer.extend_from_slice(data);
+        self.read_buffer = Bytes::new();
+        self.position += data.len() as u64;
+        if self.write_buffer.len() >= BUFFER_SIZE {
+            self.flush_writes()?;
+        }
+        Ok(data.len())
+    }
+    
+    fn seek_position(&mut self, pos: SeekFrom) -> Result<Option<u64>> {
+        Ok(match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.metadata()?.size.checked_add_signed(offset),
+        })
+    }
+}
+
+impl AsyncRead for ShadowFile {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().read_at_cursor(buf).map_err(io_error))
+    }
+}
+
+impl AsyncWrite for ShadowFile {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write_at_cursor(buf).map_err(io_error))
+    }
+    
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().flush_writes().map_err(io_error))
+    }
+    
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().flush_writes().map_err(io_error))
+    }
+}
+
+impl AsyncSeek for ShadowFile {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let file = self.get_mut();
+        file.position = file.seek_position(pos).map_err(io_error)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative or overflowing position")
+        })?;
+        Ok(())
+    }
+    
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.position))
+    }
+}
+
+impl Drop for ShadowFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush_writes() {
+            tracing::warn!("Lost buffered writes to {} on drop: {}", self.path, e);
+        }
+        self.context.handles.close(self.handle);
+    }
+}
+
+impl std::fmt::Debug for ShadowFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShadowFile")
+            .field("path", &self.path)
+            .field("position", &self.position)
+            .field("read", &self.read)
+            .field("write", &self.write)
+            .field("buffered", &self.write_buffer.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::OverrideStore;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    #[tokio::test]
+    async fn test_buffered_copy_on_write() {
+        let source = TempDir::new().unwrap();
+        let original = "0123456789".repeat(20_000);
+        std::fs::write(source.path().join("data.bin"), &original).unwrap();
+        let mount = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false);
+        
+        let mut file = mount.open(&path("/data.bin"), OpenFlags::READ | OpenFlags::WRITE).unwrap();
+        file.seek(SeekFrom::Start(100_000)).await.unwrap();
+        for _ in 0..4 {
+            file.write_all(b"ab").await.unwrap();
+        }
+        // Buffered until the read
+        assert_eq!(mount.read(&path("/data.bin"), 100_000, 2).unwrap(), "01");
+        let mut head = [0u8; 4];
+        file.read_exact(&mut head).await.unwrap();
+        assert_eq!(&head, b"8901");
+        assert_eq!(mount.read(&path("/data.bin"), 100_000, 2).unwrap(), "ab");
+        
+        file.seek(SeekFrom::Start(99_998)).await.unwrap();
+        let mut window = [0u8; 12];
+        file.read_exact(&mut window).await.unwrap();
+        assert_eq!(&window, b"89abababab89");
+        assert_eq!(file.seek(SeekFrom::End(-1)).await.unwrap(), original.len() as u64 - 1);
+        
+        let mut all = Vec::new();
+        file.seek(SeekFrom::Start(0)).await.unwrap();
+        file.read_to_end(&mut all).await.unwrap();
+        assert_eq!(all.len(), original.len());
+        assert_eq!(std::fs::read_to_string(source.path().join("data.bin")).unwrap(), original);
+    }
+    
+    #[tokio::test]
+    async fn test_open_flags() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("a.txt"), "source").unwrap();
+        let mount = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false);
+        
+        let mut file = mount.open(&path("/a.txt"), OpenFlags::empty()).unwrap();
+        assert!(file.write_all(b"x").await.is_err());
+        assert!(mount.open(&path("/b.txt"), OpenFlags::READ).is_err());
+        assert!(mount.open(&path("/b.txt"), OpenFlags::CREATE).is_err());
+        assert!(mount.open(&path("/a.txt"), OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE).is_err());
+        
+        let mut file = mount.open(&path("/b.txt"), OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        assert!(mount.handles.get(file.handle()).unwrap().can_write());
+        file.write_all(b"new").await.unwrap();
+        let mut contents = String::new();
+        assert!(file.read_to_string(&mut contents).await.is_err());
+        drop(file);
+        assert_eq!(mount.handles.open_on(&path("/b.txt")), 0);
+        assert_eq!(mount.read(&path("/b.txt"), 0, 10).unwrap(), "new");
+        
+        let mut file = mount.open(&path("/a.txt"), OpenFlags::WRITE | OpenFlags::TRUNCATE).unwrap();
+        file.write_all(b"short").await.unwrap();
+        file.shutdown().await.unwrap();
+        file.set_len(2).unwrap();
+        assert_eq!(mount.read(&path("/a.txt"), 0, 10).unwrap(), "sh");
+        
+        let read_only = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), true);
+        assert!(matches!(read_only.open(&path("/a.txt"), OpenFlags::WRITE), Err(ShadowError::ReadOnlyFilesystem { .. })));
+        assert!(read_only.open(&path("/a.txt"), OpenFlags::READ).is_ok());
+    }
+}
@@ -0,0 +1,192 @@
+//! Access-time policies emulating the `noatime`, `relatime` and
+//! `strictatime` mount options.
+//!
+//! Every lookup of an override through [`OverrideStore::get`] is an access,
+//! and storing a new access time on each one turns every read of a hot file
+//! into a write to the cache line of its entry. The [`AtimePolicy`] of the
+//! store decides which accesses store one, and providers report the access
+//! time the store holds ([`OverrideEntry::accessed`]) as the atime of the
+//! override, so what the kernel sees follows the policy too.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use shadowfs_core::atime::AtimePolicy;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::types::ShadowPath;
+//!
+//! let store = OverrideStore::with_defaults();
+//! store.set_atime_policy(AtimePolicy::NoAtime);
+//!
+//! let path = ShadowPath::from("/a.txt");
+//! store.insert_file(path.clone(), Bytes::from("a"), None).unwrap();
+//! let created = store.get(&path).unwrap().accessed();
+//! assert_eq!(store.get(&path).unwrap().accessed(), created);
+//! ```
+
+use crate::override_store::{OverrideEntry, OverrideStore};
+use crate::types::{current_time, FileMetadata};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime};
+
+/// How long an access time stays current under [`AtimePolicy::Relatime`].
+pub const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// When accesses to an override store a new access time.
+///
+/// Policies are ordered from the fewest updates to the most; mounts sharing
+/// a store keep access times as often as the greatest of theirs asks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AtimePolicy {
+    /// Never; the access time stays the time the override was written
+    NoAtime,
+    
+    /// When the access time is not later than the modification time or is
+    /// older than [`RELATIME_INTERVAL`], as Linux does by default
+    #[default]
+    Relatime,
+    
+    /// On every access
+    Strict,
+}
+
+impl AtimePolicy {
+    /// Returns the access time to store for an access `now` to an override
+    /// last accessed at `accessed` and modified at `modified`, or `None` to
+    /// keep the one it has. Times are seconds since the Unix epoch.
+    pub fn updated(self, accessed: u64, modified: u64, now: u64) -> Option<u64> {
+        let stale = match self {
+            Self::NoAtime => false,
+            Self::Relatime => accessed <= modified || now.saturating_sub(accessed) >= RELATIME_INTERVAL.as_secs(),
+            Self::Strict => accessed != now,
+        };
+        stale.then_some(now)
+    }
+    
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::NoAtime,
+            2 => Self::Strict,
+            _ => Self::Relatime,
+        }
+    }
+}
+
+impl fmt::Display for AtimePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoAtime => "noatime",
+            Self::Relatime => "relatime",
+            Self::Strict => "strictatime",
+        })
+    }
+}
+
+impl FromStr for AtimePolicy {
+    type Err = String;
+    
+    /// Parses the name of the mount option, `noatime`, `relatime` or
+    /// `strictatime`, or `strict` for short.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "noatime" => Ok(Self::NoAtime),
+            "relatime" => Ok(Self::Relatime),
+            "strictatime" | "strict" => Ok(Self::Strict),
+            _ => Err(format!("unknown atime policy '{}', expected noatime, relatime or strictatime", value)),
+        }
+    }
+}
+
+/// Returns `time` in whole seconds since the Unix epoch.
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+impl OverrideStore {
+    /// Decides which accesses store a new access time from now on.
+    pub fn set_atime_policy(&self, policy: AtimePolicy) {
+        self.atime_policy.store(policy as u8, Ordering::Relaxed);
+    }
+    
+    /// Returns when accesses store a new access time.
+    pub fn atime_policy(&self) -> AtimePolicy {
+        AtimePolicy::from_u8(self.atime_policy.load(Ordering::Relaxed))
+    }
+    
+    /// Records an access to `entry` if the policy asks for it.
+    pub(crate) fn record_access(&self, entry: &OverrideEntry) {
+        let policy = self.atime_policy();
+        if policy == AtimePolicy::NoAtime {
+            return;
+        }
+        let accessed = entry.last_accessed.load(Ordering::Relaxed);
+        let modified = seconds(entry.override_metadata.modified);
+        if let Some(now) = policy.updated(accessed, modified, seconds(current_time())) {
+            entry.last_accessed.store(now, Ordering::Relaxed);
+        }
+    }
+}
+
+impl OverrideEntry {
+    /// Returns the last access time the store recorded, to the second.
+    pub fn accessed(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.last_accessed.load(Ordering::Relaxed))
+    }
+    
+    /// Returns the metadata of the override with the access time the store
+    /// recorded, as providers report it.
+    pub fn metadata(&self) -> FileMetadata {
+        let mut metadata = self.override_metadata.clone();
+        metadata.accessed = self.accessed();
+        metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ShadowPath;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_policies_decide_which_accesses_update() {
+        let day = RELATIME_INTERVAL.as_secs();
+        // Accessed after the last change, an hour ago
+        let (accessed, modified, now) = (10 * day, 10 * day - 60, 10 * day + 3600);
+        assert_eq!(AtimePolicy::NoAtime.updated(accessed, modified, now), None);
+        assert_eq!(AtimePolicy::Relatime.updated(accessed, modified, now), None);
+        assert_eq!(AtimePolicy::Strict.updated(accessed, modified, now), Some(now));
+        
+        // Relatime catches up after a change and after a day
+        assert_eq!(AtimePolicy::Relatime.updated(accessed, accessed, now), Some(now));
+        assert_eq!(AtimePolicy::Relatime.updated(accessed, modified, accessed + day), Some(accessed + day));
+        
+        for policy in [AtimePolicy::NoAtime, AtimePolicy::Relatime, AtimePolicy::Strict] {
+            assert_eq!(policy.to_string().parse::<AtimePolicy>(), Ok(policy));
+        }
+        assert!("sometimes".parse::<AtimePolicy>().is_err());
+    }
+    
+    #[test]
+    fn test_store_applies_the_policy() {
+        let store = OverrideStore::with_defaults();
+        assert_eq!(store.atime_policy(), AtimePolicy::Relatime);
+        let path = ShadowPath::from("/a.txt");
+        store.insert_file(path.clone(), Bytes::from("a"), None).unwrap();
+        let entry = store.get(&path).unwrap();
+        
+        store.set_atime_policy(AtimePolicy::NoAtime);
+        entry.last_accessed.store(1, Ordering::Relaxed);
+        store.get(&path);
+        assert_eq!(entry.accessed(), SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+        
+        // An access time before the last change is stale under relatime
+        store.set_atime_policy(AtimePolicy::Relatime);
+        store.get(&path);
+        assert!(entry.accessed() >= entry.override_metadata.modified - Duration::from_secs(1));
+        assert_eq!(store.get(&path).unwrap().metadata().accessed, entry.accessed());
+    }
+}
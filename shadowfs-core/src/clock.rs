@@ -0,0 +1,119 @@
+//! The coarse monotonic clock the store orders events and measures ages by.
+//!
+//! NTP stepping the wall clock or a machine resuming from suspend moves
+//! `SystemTime` by minutes or hours at once. Ordering FIFO eviction by it
+//! would evict the wrong entries, access intervals of hot paths would come
+//! out negative or huge, and an idle mount would expire the moment the
+//! clock jumped forward. Anything that compares two points in time reads
+//! [`now`] instead: milliseconds since the process first read the clock,
+//! which never go backwards. The wall clock is read only to report when
+//! something happened, through [`wall_now`] and [`to_wall`].
+//!
+//! Time the machine spends suspended does not advance the clock on Linux
+//! and macOS, so ages and timeouts measure time the process could run.
+//!
+//! ```rust
+//! use shadowfs_core::clock;
+//!
+//! let start = clock::now();
+//! assert!(clock::now() >= start);
+//! assert!(clock::to_wall(start) <= clock::wall_now());
+//! ```
+
+use crate::types::current_time;
+use std::sync::OnceLock;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::Instant;
+use std::time::{Duration, SystemTime};
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use web_time::Instant;
+
+/// Returns the instant readings are counted from.
+fn base() -> Instant {
+    static BASE: OnceLock<Instant> = OnceLock::new();
+    *BASE.get_or_init(Instant::now)
+}
+
+/// Returns the current reading of the clock, in milliseconds since the
+/// process first read it.
+pub fn now() -> u64 {
+    let reading = base().elapsed().as_millis() as u64;
+    #[cfg(test)]
+    let reading = reading + testing::ADVANCED.with(|advanced| advanced.get());
+    reading
+}
+
+/// Returns how long ago `reading` was taken.
+pub fn elapsed(reading: u64) -> Duration {
+    Duration::from_millis(now().saturating_sub(reading))
+}
+
+/// Returns the current wall-clock time, for reporting.
+pub fn wall_now() -> SystemTime {
+    let now = current_time();
+    #[cfg(test)]
+    let now = {
+        let skew = testing::WALL_SKEW.with(|skew| skew.get());
+        let by = Duration::from_millis(skew.unsigned_abs());
+        if skew < 0 { now - by } else { now + by }
+    };
+    now
+}
+
+/// Returns the wall-clock time `reading` was taken at, as the wall clock
+/// reads now.
+///
+/// The result follows the wall clock: after it jumps, earlier readings are
+/// reported relative to the new time rather than the one they were taken at.
+pub fn to_wall(reading: u64) -> SystemTime {
+    wall_now().checked_sub(elapsed(reading)).unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Hooks moving the clocks of the calling thread in tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::cell::Cell;
+    use std::time::Duration;
+    
+    thread_local! {
+        pub(super) static ADVANCED: Cell<u64> = const { Cell::new(0) };
+        pub(super) static WALL_SKEW: Cell<i64> = const { Cell::new(0) };
+    }
+    
+    /// Moves the monotonic clock ahead, as if `by` passed.
+    pub(crate) fn advance(by: Duration) {
+        ADVANCED.with(|advanced| advanced.set(advanced.get() + by.as_millis() as u64));
+    }
+    
+    /// Steps the wall clock by `millis`, as NTP or a resume from suspend
+    /// would, without moving the monotonic clock.
+    pub(crate) fn jump_wall_clock(millis: i64) {
+        WALL_SKEW.with(|skew| skew.set(skew.get() + millis));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const HOUR: i64 = 3_600_000;
+    
+    #[test]
+    fn test_wall_clock_jumps_do_not_move_readings() {
+        let reading = now();
+        let reported = to_wall(reading);
+        
+        testing::jump_wall_clock(-HOUR);
+        assert!(elapsed(reading) < Duration::from_secs(60));
+        assert!(now() >= reading);
+        // Reports follow the wall clock back
+        let shifted = reported.duration_since(to_wall(reading)).unwrap();
+        assert!(shifted >= Duration::from_secs(3599) && shifted <= Duration::from_secs(3601));
+        
+        testing::jump_wall_clock(2 * HOUR);
+        assert!(elapsed(reading) < Duration::from_secs(60));
+        
+        testing::advance(Duration::from_secs(90));
+        assert!(elapsed(reading) >= Duration::from_secs(90));
+    }
+}
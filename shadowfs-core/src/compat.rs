@@ -0,0 +1,618 @@
+//! A `std::fs`-shaped interface to the shadow layer.
+//!
+//! [`ShadowStdFs`] offers the functions of `std::fs`, with [`File`],
+//! [`OpenOptions`], [`ReadDir`] and [`Metadata`] standing in for their std
+//! namesakes and every error an `io::Error`. Code written against `std::fs`,
+//! or against a filesystem trait such as the one of the `vfs` crate, can be
+//! pointed at a shadow layer by swapping the calls, with no OS mount: the
+//! layer may be a [`VirtualFs`](crate::virtual_fs::VirtualFs) mount as well
+//! as a FUSE, ProjFS or FSKit one.
+//!
+//! Paths are relative to the root of the layer; a leading `/` is optional.
+//! Writes reach the override store only, never the source.
+//!
+//! ```rust
+//! use shadowfs_core::compat::ShadowStdFs;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::passthrough::MountContext;
+//! use std::io::{Read, Write};
+//! use std::sync::Arc;
+//!
+//! let source = tempfile::tempdir().unwrap();
+//! std::fs::write(source.path().join("config.toml"), "debug = false\n").unwrap();
+//! let fs = ShadowStdFs::new(MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false));
+//!
+//! let mut file = fs.options().append(true).open("config.toml")?;
+//! file.write_all(b"verbose = true\n")?;
+//! assert_eq!(fs.read_to_string("/config.toml")?, "debug = false\nverbose = true\n");
+//!
+//! fs.create_dir_all("target/debug")?;
+//! fs.write("target/debug/app", b"\x7fELF")?;
+//! let names: Vec<String> = fs.read_dir("target/debug")?
+//!     .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+//!     .collect::<Result<_, _>>()?;
+//! assert_eq!(names, ["app"]);
+//! assert!(!source.path().join("target").exists());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::error::{self, ShadowError};
+use crate::passthrough::MountContext;
+use crate::traits::FileSystemProvider;
+use crate::types::{DirectoryEntry, FileMetadata, FilePermissions, FileType, ShadowPath};
+use bytes::Bytes;
+use std::ffi::OsString;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The shadow layer of one mount, behind an interface mirroring `std::fs`.
+#[derive(Clone)]
+pub struct ShadowStdFs {
+    context: MountContext,
+}
+
+impl ShadowStdFs {
+    /// Serves the mount `context` belongs to.
+    pub fn new(context: MountContext) -> Self {
+        Self { context }
+    }
+    
+    /// Serves the mount of `provider` at `mount_point`, if the provider
+    /// exposes a context for it.
+    pub fn from_provider(provider: &dyn FileSystemProvider, mount_point: &Path) -> Option<Self> {
+        provider.context(mount_point).map(Self::new)
+    }
+    
+    /// Returns the context of the mount.
+    pub fn context(&self) -> &MountContext {
+        &self.context
+    }
+    
+    /// Returns options to open a file with, all off; see `std::fs::OpenOptions`.
+    pub fn options(&self) -> OpenOptions<'_> {
+        OpenOptions {
+            fs: self,
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+        }
+    }
+    
+    /// Opens the file at `path` for reading, like `std::fs::File::open`.
+    pub fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        self.options().read(true).open(path)
+    }
+    
+    /// Opens the file at `path` for writing, creating or truncating it,
+    /// like `std::fs::File::create`.
+    pub fn create(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        self.options().write(true).create(true).truncate(true).open(path)
+    }
+    
+    /// Reads the whole file at `path`.
+    pub fn read(&self, path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let mut file = self.open(path)?;
+        let mut data = Vec::with_capacity(file.metadata()?.len() as usize);
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+    
+    /// Reads the whole file at `path`, which must be UTF-8.
+    pub fn read_to_string(&self, path: impl AsRef<Path>) -> io::Result<String> {
+        String::from_utf8(self.read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+    
+    /// Replaces the content of the file at `path`, creating it if needed.
+    pub fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        let contents = Bytes::copy_from_slice(contents.as_ref());
+        self.context.write_file(&shadow_path(path.as_ref()), contents).map_err(io_error)
+    }
+    
+    /// Returns the metadata of `path`, without following symlinks.
+    pub fn metadata(&self, path: impl AsRef<Path>) -> io::Result<Metadata> {
+        self.context.metadata(&shadow_path(path.as_ref())).map(Metadata).map_err(io_error)
+    }
+    
+    /// Returns whether `path` exists, failing on errors other than
+    /// [`ShadowError::NotFound`].
+    pub fn try_exists(&self, path: impl AsRef<Path>) -> io::Result<bool> {
+        match self.context.metadata(&shadow_path(path.as_ref())) {
+            Ok(_) => Ok(true),
+            Err(ShadowError::NotFound { .. }) => Ok(false),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+    
+    /// Returns whether `path` exists, counting errors as not.
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        self.try_exists(path).unwrap_or(false)
+    }
+    
+    /// Lists the directory at `path`, sorted by name. Entry paths are
+    /// `path` joined with their names, as with `std::fs::read_dir`.
+    pub fn read_dir(&self, path: impl AsRef<Path>) -> io::Result<ReadDir> {
+        let entries = self.context.read_directory(&shadow_path(path.as_ref())).map_err(io_error)?;
+        Ok(ReadDir { directory: path.as_ref().to_path_buf(), entries: entries.into_iter() })
+    }
+    
+    /// Creates the directory `path`, whose parent must exist.
+    pub fn create_dir(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.context.create_directory(&shadow_path(path.as_ref())).map_err(io_error)
+    }
+    
+    /// Creates the directory `path` and any missing parents.
+    pub fn create_dir_all(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = shadow_path(path.as_ref());
+        let mut missing = Vec::new();
+        let mut ancestor = Some(path.clone());
+        while let Some(dir) = ancestor {
+            match self.context.metadata(&dir) {
+                Ok(metadata) if metadata.file_type == FileType::Directory => break,
+                Ok(_) => return Err(io_error(error::not_a_directory(dir))),
+                Err(ShadowError::NotFound { .. }) => {}
+                Err(e) => return Err(io_error(e)),
+            }
+            ancestor = dir.parent();
+            missing.push(dir);
+        }
+        
+        for dir in missing.into_iter().rev() {
+            match self.context.create_directory(&dir) {
+                // Created by someone else in between
+                Ok(()) | Err(ShadowError::AlreadyExists { .. }) => {}
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        Ok(())
+    }
+    
+    /// Removes the file at `path`.
+    pub fn remove_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = shadow_path(path.as_ref());
+        if self.context.metadata(&path).map_err(io_error)?.file_type == FileType::Directory {
+            return Err(io_error(error::is_a_directory(path)));
+        }
+        self.context.delete(&path).map_err(io_error)
+    }
+    
+    /// Removes the empty directory at `path`.
+    pub fn remove_dir(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = shadow_path(path.as_ref());
+        if self.context.metadata(&path).map_err(io_error)?.file_type != FileType::Directory {
+            return Err(io_error(error::not_a_directory(path)));
+        }
+        self.context.delete(&path).map_err(io_error)
+    }
+    
+    /// Removes the directory at `path` and everything below it.
+    pub fn remove_dir_all(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = shadow_path(path.as_ref());
+        for entry in self.context.read_directory(&path).map_err(io_error)? {
+            let child = path.join(&entry.name);
+            if entry.is_directory() {
+                self.remove_dir_all(child.as_path())?;
+            } else {
+                self.context.delete(&child).map_err(io_error)?;
+            }
+        }
+        self.context.delete(&path).map_err(io_error)
+    }
+    
+    /// Moves `from` to `to`, replacing a file there.
+    pub fn rename(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+        self.context.rename(&shadow_path(from.as_ref()), &shadow_path(to.as_ref())).map_err(io_error)
+    }
+    
+    /// Copies the content of the file `from` to `to`, replacing a file there.
+    ///
+    /// # Returns
+    /// Number of bytes copied
+    pub fn copy(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
+        let data = self.read(from)?;
+        self.write(to, &data)?;
+        Ok(data.len() as u64)
+    }
+}
+
+/// How [`ShadowStdFs::options`] opens a file; see `std::fs::OpenOptions`.
+#[derive(Clone)]
+pub struct OpenOptions<'a> {
+    fs: &'a ShadowStdFs,
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+impl OpenOptions<'_> {
+    /// Opens for reading.
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+    
+    /// Opens for writing.
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+    
+    /// Opens for writing at the end of the file, wherever the cursor is.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+    
+    /// Empties an existing file.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+    
+    /// Creates the file if it does not exist.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+    
+    /// Creates the file, failing if it exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+    
+    /// Opens the file at `path` with these options.
+    pub fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        let writes = self.write || self.append;
+        if (self.truncate || self.create || self.create_new) && !writes {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "creating or truncating a file needs write access"));
+        }
+        
+        let context = &self.fs.context;
+        let path = shadow_path(path.as_ref());
+        if self.create_new {
+            context.create_file(&path, Bytes::new()).map_err(io_error)?;
+        } else {
+            match context.metadata(&path) {
+                Ok(metadata) if writes && metadata.file_type == FileType::Directory => {
+                    return Err(io_error(error::is_a_directory(path)));
+                }
+                Ok(_) if self.truncate => context.truncate(&path, 0).map_err(io_error)?,
+                Ok(_) => {}
+                Err(ShadowError::NotFound { .. }) if self.create => {
+                    context.create_file(&path, Bytes::new()).map_err(io_error)?;
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        
+        Ok(File {
+            context: context.clone(),
+            path,
+            position: 0,
+            read: self.read,
+            write: writes,
+            append: self.append,
+        })
+    }
+}
+
+/// An open file of the shadow layer, with a cursor; see `std::fs::File`.
+///
+/// Every read and write goes to the layer, so there is nothing to flush.
+pub struct File {
+    context: MountContext,
+    path: ShadowPath,
+    position: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+impl File {
+    /// Returns the path the file was opened at.
+    pub fn path(&self) -> &ShadowPath {
+        &self.path
+    }
+    
+    /// Returns the metadata of the file.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        self.context.metadata(&self.path).map(Metadata).map_err(io_error)
+    }
+    
+    /// Cuts the file to `size` bytes or extends it with zeros, leaving the
+    /// cursor where it is.
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        if !self.write {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "file not opened for writing"));
+        }
+        self.context.truncate(&self.path, size).map_err(io_error)
+    }
+    
+    /// Does nothing: writes reach the store as they are made.
+    pub fn sync_all(&self) -> io::Result<()> {
+        Ok(())
+    }
+    
+    /// Does nothing: writes reach the store as they are made.
+    pub fn sync_data(&self) -> io::Result<()> {
+        Ok(())
+    }
+    
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.read {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "file not opened for reading"));
+        }
+        let data = self.context.read(&self.path, self.position, buf.len()).map_err(io_error)?;
+        buf[..data.len()].copy_from_slice(&data);
+        self.position += data.len() as u64;
+        Ok(data.len())
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.write {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "file not opened for writing"));
+        }
+        if self.append {
+            self.position = self.len()?;
+        }
+        let written = self.context.write(&self.path, self.position, buf).map_err(io_error)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.len()?.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative or overflowing position")
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl std::fmt::Debug for File {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("File")
+            .field("path", &self.path)
+            .field("position", &self.position)
+            .field("read", &self.read)
+            .field("write", &self.write)
+            .finish()
+    }
+}
+
+/// Metadata of a path of the shadow layer; see `std::fs::Metadata`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata(FileMetadata);
+
+impl Metadata {
+    /// Returns the type of the entry.
+    pub fn file_type(&self) -> FileType {
+        self.0.file_type
+    }
+    
+    /// Returns true for a directory.
+    pub fn is_dir(&self) -> bool {
+        self.0.file_type == FileType::Directory
+    }
+    
+    /// Returns true for a regular file.
+    pub fn is_file(&self) -> bool {
+        self.0.file_type == FileType::File
+    }
+    
+    /// Returns true for a symlink.
+    pub fn is_symlink(&self) -> bool {
+        self.0.file_type == FileType::Symlink
+    }
+    
+    /// Returns the size of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.0.size
+    }
+    
+    /// Returns true for an empty file.
+    pub fn is_empty(&self) -> bool {
+        self.0.size == 0
+    }
+    
+    /// Returns the permissions of the entry.
+    pub fn permissions(&self) -> FilePermissions {
+        self.0.permissions
+    }
+    
+    /// Returns the last modification time.
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        Ok(self.0.modified)
+    }
+    
+    /// Returns the last access time.
+    pub fn accessed(&self) -> io::Result<SystemTime> {
+        Ok(self.0.accessed)
+    }
+    
+    /// Returns the creation time.
+    pub fn created(&self) -> io::Result<SystemTime> {
+        Ok(self.0.created)
+    }
+    
+    /// Returns the metadata as the shadow layer reports it.
+    pub fn as_file_metadata(&self) -> &FileMetadata {
+        &self.0
+    }
+}
+
+impl From<Metadata> for FileMetadata {
+    fn from(metadata: Metadata) -> Self {
+        metadata.0
+    }
+}
+
+/// Entries of a directory of the shadow layer; see `std::fs::ReadDir`.
+///
+/// The listing is taken when the directory is read, so the entries never
+/// fail.
+#[derive(Debug)]
+pub struct ReadDir {
+    directory: PathBuf,
+    entries: std::vec::IntoIter<DirectoryEntry>,
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|entry| Ok(DirEntry {
+            path: self.directory.join(&entry.name),
+            entry,
+        }))
+    }
+    
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+/// An entry of a directory of the shadow layer; see `std::fs::DirEntry`.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    path: PathBuf,
+    entry: DirectoryEntry,
+}
+
+impl DirEntry {
+    /// Returns the directory the entry was listed from joined with its name.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+    
+    /// Returns the name of the entry.
+    pub fn file_name(&self) -> OsString {
+        OsString::from(&self.entry.name)
+    }
+    
+    /// Returns the metadata of the entry as it was listed.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        Ok(Metadata(self.entry.metadata.clone()))
+    }
+    
+    /// Returns the type of the entry.
+    pub fn file_type(&self) -> io::Result<FileType> {
+        Ok(self.entry.metadata.file_type)
+    }
+}
+
+/// Returns `path`, relative to the root of the layer, as a shadow path.
+fn shadow_path(path: &Path) -> ShadowPath {
+    ShadowPath::new(Path::new("/").join(path))
+}
+
+/// Returns `error` as an `io::Error` of the closest kind, keeping it as the source.
+pub(crate) fn io_error(error: ShadowError) -> io::Error {
+    let kind = match error {
+        ShadowError::IoError { source } => return source,
+        ShadowError::NotFound { .. } => io::ErrorKind::NotFound,
+        ShadowError::AlreadyExists { .. } => io::ErrorKind::AlreadyExists,
+        ShadowError::PermissionDenied { .. }
+        | ShadowError::AccessDenied { .. }
+        | ShadowError::ReadOnlyFilesystem { .. }
+        | ShadowError::Unauthorized { .. } => io::ErrorKind::PermissionDenied,
+        ShadowError::InvalidPath { .. } | ShadowError::InvalidConfiguration { .. } => io::ErrorKind::InvalidInput,
+        ShadowError::Unsupported { .. } => io::ErrorKind::Unsupported,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mount_manager::MountManager;
+    use crate::override_store::OverrideStore;
+    use crate::types::MountOptions;
+    use crate::virtual_fs::VirtualFs;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    
+    #[tokio::test]
+    async fn test_std_operations_over_virtual_mount() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "pub fn old() {}").unwrap();
+        let manager = MountManager::new(Arc::new(OverrideStore::with_defaults()), VirtualFs::factory());
+        let mount = Path::new("/virtual/mount");
+        manager.mount(source.path(), mount, MountOptions::default()).await.unwrap();
+        let provider = manager.get_provider(mount).await.unwrap();
+        let fs = ShadowStdFs::from_provider(provider.as_ref(), mount).unwrap();
+        
+        let mut file = fs.options().read(true).write(true).open("src/lib.rs").unwrap();
+        file.seek(SeekFrom::Start(7)).unwrap();
+        file.write_all(b"new").unwrap();
+        assert_eq!(file.seek(SeekFrom::End(-2)).unwrap(), 13);
+        let mut tail = String::new();
+        file.read_to_string(&mut tail).unwrap();
+        assert_eq!(tail, "{}");
+        assert!(file.seek(SeekFrom::Current(-20)).is_err());
+        assert_eq!(fs.read_to_string("/src/lib.rs").unwrap(), "pub fn new() {}");
+        
+        assert_eq!(fs.copy("src/lib.rs", "src/copy.rs").unwrap(), 15);
+        fs.rename("src/copy.rs", "copy.rs").unwrap();
+        let paths: Vec<PathBuf> = fs.read_dir("src").unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(paths, [PathBuf::from("src/lib.rs")]);
+        assert!(fs.metadata("copy.rs").unwrap().is_file());
+        
+        fs.remove_dir_all("src").unwrap();
+        assert!(!fs.exists("src/lib.rs"));
+        assert_eq!(std::fs::read_to_string(source.path().join("src/lib.rs")).unwrap(), "pub fn old() {}");
+    }
+    
+    #[test]
+    fn test_open_options_and_errors() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("a.txt"), "a").unwrap();
+        let fs = ShadowStdFs::new(MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false));
+        
+        let kind = |result: io::Result<File>| result.unwrap_err().kind();
+        assert_eq!(kind(fs.open("missing.txt")), io::ErrorKind::NotFound);
+        assert_eq!(kind(fs.options().create_new(true).write(true).open("a.txt")), io::ErrorKind::AlreadyExists);
+        assert_eq!(kind(fs.options().create(true).open("b.txt")), io::ErrorKind::InvalidInput);
+        assert_eq!(fs.open("a.txt").unwrap().write(b"b").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        
+        fs.create("a.txt").unwrap().write_all(b"truncated").unwrap();
+        assert_eq!(fs.read("a.txt").unwrap(), b"truncated");
+        fs.options().write(true).open("a.txt").unwrap().set_len(5).unwrap();
+        assert_eq!(fs.read("a.txt").unwrap(), b"trunc");
+        fs.options().write(true).create(true).open("b.txt").unwrap();
+        assert!(fs.metadata("b.txt").unwrap().is_empty());
+        
+        fs.create_dir_all("x/y/z").unwrap();
+        assert!(fs.metadata("x/y").unwrap().is_dir());
+        assert!(fs.create_dir_all("a.txt/y").is_err());
+        assert!(fs.remove_dir("x").is_err());
+        assert!(fs.remove_file("x").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Reproducible views of a mount.
+//!
+//! Builds record more of the filesystem than the content they read:
+//! archivers store timestamps, owners and modes, and tools that key hash
+//! tables by inode or walk directories in the order the kernel lists them
+//! emit their output in that order. A mount with a [`Determinism`] reports
+//! the same for the same content on every machine. Every timestamp is
+//! frozen at one epoch, taken from `SOURCE_DATE_EPOCH` when it is set;
+//! inode numbers are derived from the path instead of the order paths were
+//! first looked up in; every entry has the same owner; permissions are
+//! reduced to the executable bit, as git records them; and directories
+//! list their entries sorted by name.
+//!
+//! ```rust
+//! use shadowfs_core::determinism::Determinism;
+//! use shadowfs_core::types::{FileMetadata, ShadowPath};
+//! use std::time::{Duration, SystemTime};
+//!
+//! let determinism = Determinism::new(1_700_000_000);
+//! let mut metadata = FileMetadata::default();
+//! determinism.apply(&ShadowPath::from("/src/main.rs"), &mut metadata);
+//!
+//! assert_eq!(metadata.modified, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+//! assert_eq!(metadata.permissions.to_unix_mode(), 0o644);
+//! assert_eq!(
+//!     Determinism::inode(&ShadowPath::from("/src/main.rs")),
+//!     Determinism::inode(&ShadowPath::from("/src/main.rs")),
+//! );
+//! ```
+
+use crate::error::{Result, ShadowError};
+use crate::types::{FilePermissions, FileMetadata, FileType, PlatformMetadata, ShadowPath};
+use std::time::{Duration, SystemTime};
+
+/// Environment variable reproducible builds take their timestamp from.
+pub const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
+
+/// Epoch of a deterministic mount when `SOURCE_DATE_EPOCH` is not set,
+/// 1980-01-01, the earliest time zip archives can store.
+pub const DEFAULT_EPOCH_SECS: u64 = 315_532_800;
+
+/// Inode number of the root of a deterministic mount.
+pub const ROOT_INODE: u64 = 1;
+
+/// Windows attribute set whenever a file is written, whoever wrote it.
+const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+
+/// What a deterministic mount reports in place of the metadata that
+/// differs between machines and runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Determinism {
+    /// Time every timestamp reports, in seconds since the Unix epoch
+    pub epoch_secs: u64,
+    
+    /// Owner every entry reports
+    pub uid: u32,
+    
+    /// Group every entry reports
+    pub gid: u32,
+}
+
+impl Default for Determinism {
+    fn default() -> Self {
+        Self::new(DEFAULT_EPOCH_SECS)
+    }
+}
+
+impl Determinism {
+    /// Creates a determinism freezing timestamps at `epoch_secs`, with
+    /// every entry owned by root.
+    pub fn new(epoch_secs: u64) -> Self {
+        Self { epoch_secs, uid: 0, gid: 0 }
+    }
+    
+    /// Creates a determinism freezing timestamps at `SOURCE_DATE_EPOCH`, or
+    /// at [`DEFAULT_EPOCH_SECS`] if it is not set.
+    ///
+    /// Fails if the variable is set to anything but a number of seconds,
+    /// as the reproducible builds specification asks.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(SOURCE_DATE_EPOCH_ENV) {
+            Ok(value) => parse_epoch(&value).map(Self::new),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+    
+    /// Returns the time every timestamp reports.
+    pub fn epoch(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.epoch_secs)
+    }
+    
+    /// Returns the inode number of `path`, which depends on nothing but the
+    /// path: [`ROOT_INODE`] for the root and a hash of the path, never 0 or
+    /// the root's, for anything else.
+    pub fn inode(path: &ShadowPath) -> u64 {
+        if path.as_path().parent().is_none() {
+            return ROOT_INODE;
+        }
+        
+        // FNV-1a, which is stable across releases and platforms
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in path.to_string().bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        hash.max(ROOT_INODE + 1)
+    }
+    
+    /// Returns the permissions an entry of `file_type` reports in place of
+    /// `permissions`: 0o755 for directories and executables, 0o777 for
+    /// symlinks and 0o644 for anything else.
+    pub fn permissions(file_type: FileType, permissions: FilePermissions) -> FilePermissions {
+        FilePermissions::from_unix_mode(match file_type {
+            FileType::Directory => 0o755,
+            FileType::Symlink => 0o777,
+            FileType::File if permissions.is_executable() => 0o755,
+            FileType::File => 0o644,
+        })
+    }
+    
+    /// Replaces what `metadata` of the entry at `path` holds that differs
+    /// between machines: timestamps, permissions, the inode number and link
+    /// count of directories, and the Windows archive attribute.
+    pub fn apply(&self, path: &ShadowPath, metadata: &mut FileMetadata) {
+        let epoch = self.epoch();
+        metadata.created = epoch;
+        metadata.modified = epoch;
+        metadata.accessed = epoch;
+        metadata.permissions = Self::permissions(metadata.file_type, metadata.permissions);
+        match &mut metadata.platform_specific {
+            PlatformMetadata::Linux { inode, nlink } => {
+                *inode = Self::inode(path);
+                if metadata.file_type == FileType::Directory {
+                    *nlink = 2;
+                }
+            }
+            PlatformMetadata::Windows { attributes, .. } => *attributes &= !FILE_ATTRIBUTE_ARCHIVE,
+            PlatformMetadata::MacOS { .. } => {}
+        }
+    }
+}
+
+/// Parses a `SOURCE_DATE_EPOCH` value.
+fn parse_epoch(value: &str) -> Result<u64> {
+    value.trim().parse().map_err(|_| ShadowError::InvalidConfiguration {
+        message: format!("{} must be a number of seconds, not {:?}", SOURCE_DATE_EPOCH_ENV, value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::OverrideStore;
+    use crate::passthrough::MountContext;
+    use bytes::Bytes;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::Arc;
+    
+    #[test]
+    fn test_inodes_depend_only_on_the_path() {
+        assert_eq!(Determinism::inode(&ShadowPath::from("/")), ROOT_INODE);
+        let a = Determinism::inode(&ShadowPath::from("/a.txt"));
+        assert_eq!(a, Determinism::inode(&ShadowPath::from("/a.txt")));
+        assert_ne!(a, Determinism::inode(&ShadowPath::from("/b.txt")));
+        assert!(a > ROOT_INODE);
+        
+        assert_eq!(parse_epoch("1700000000").unwrap(), 1_700_000_000);
+        assert!(matches!(parse_epoch("yesterday"), Err(ShadowError::InvalidConfiguration { .. })));
+    }
+    
+    /// Two checkouts of the same tree, made at different times with
+    /// different modes, look identical through deterministic mounts, as a
+    /// build tarring up the mount would see them.
+    #[test]
+    fn test_checkouts_look_identical() {
+        let determinism = Determinism::new(1_700_000_000);
+        let checkouts = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let mounts: Vec<MountContext> = checkouts.iter()
+            .zip([0o600, 0o664])
+            .map(|(checkout, mode)| {
+                let source = checkout.path();
+                std::fs::create_dir(source.join("src")).unwrap();
+                std::fs::write(source.join("src/main.rs"), "fn main() {}").unwrap();
+                std::fs::write(source.join("src/lib.rs"), "").unwrap();
+                std::fs::set_permissions(source.join("src/lib.rs"), std::fs::Permissions::from_mode(mode)).unwrap();
+                std::thread::sleep(Duration::from_millis(10));
+                
+                let context = MountContext::new(Arc::new(OverrideStore::with_defaults()), source, false)
+                    .with_determinism(determinism);
+                context.create_file(&ShadowPath::from("/src/generated.rs"), Bytes::from("// generated")).unwrap();
+                context
+            })
+            .collect();
+        
+        let listings: Vec<_> = mounts.iter()
+            .map(|context| context.read_directory(&ShadowPath::from("/src")).unwrap())
+            .collect();
+        assert_eq!(listings[0], listings[1]);
+        let names: Vec<&str> = listings[0].iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["generated.rs", "lib.rs", "main.rs"]);
+        assert!(listings[0].iter().all(|entry| entry.metadata.modified == determinism.epoch()));
+        assert_eq!(listings[0][1].metadata.permissions.to_unix_mode(), 0o644);
+        
+        let root: Vec<FileMetadata> = mounts.iter()
+            .map(|context| context.metadata(&ShadowPath::from("/")).unwrap())
+            .collect();
+        assert_eq!(root[0], root[1]);
+    }
+}
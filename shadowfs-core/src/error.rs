@@ -1,182 +1,14 @@
 //! Error types for the ShadowFS system.
+//! 
+//! [`ShadowError`] and [`Platform`] are defined in `shadowfs-types` so that
+//! code without std can use them; this module re-exports them alongside the
+//! std-only helpers.
+
+pub use shadowfs_types::error::{LimitKind, Platform, ShadowError};
+pub use shadowfs_types::wire::{ErrorCategory, ErrorCode, WireError};
 
 use crate::types::ShadowPath;
 use std::fmt;
-use thiserror::Error;
-
-/// Represents the platform where the error occurred.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Platform {
-    Windows,
-    MacOS,
-    Linux,
-}
-
-impl fmt::Display for Platform {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Platform::Windows => write!(f, "Windows"),
-            Platform::MacOS => write!(f, "macOS"),
-            Platform::Linux => write!(f, "Linux"),
-        }
-    }
-}
-
-/// Comprehensive error type for all ShadowFS operations.
-#[derive(Debug, Error)]
-pub enum ShadowError {
-    /// File or directory not found.
-    #[error("Path not found: {path}")]
-    NotFound { 
-        path: ShadowPath 
-    },
-
-    /// Permission denied for the operation.
-    #[error("Permission denied for operation '{operation}' on path: {path}")]
-    PermissionDenied { 
-        path: ShadowPath, 
-        operation: String 
-    },
-
-    /// File or directory already exists.
-    #[error("Path already exists: {path}")]
-    AlreadyExists { 
-        path: ShadowPath 
-    },
-
-    /// Expected a directory but found something else.
-    #[error("Not a directory: {path}")]
-    NotADirectory { 
-        path: ShadowPath 
-    },
-
-    /// Expected a file but found a directory.
-    #[error("Is a directory: {path}")]
-    IsADirectory { 
-        path: ShadowPath 
-    },
-
-    /// Invalid path provided.
-    #[error("Invalid path '{path}': {reason}")]
-    InvalidPath { 
-        path: String, 
-        reason: String 
-    },
-
-    /// I/O error from the underlying system.
-    #[error("I/O error: {source}")]
-    IoError {
-        #[source]
-        source: std::io::Error,
-    },
-
-    /// Platform-specific error.
-    #[error("Platform error on {platform}: {message} (code: {code:?})")]
-    PlatformError { 
-        platform: Platform, 
-        message: String, 
-        code: Option<i32> 
-    },
-
-    /// Override store is full.
-    #[error("Override store is full: current size {current_size} bytes, maximum {max_size} bytes")]
-    OverrideStoreFull { 
-        current_size: usize, 
-        max_size: usize 
-    },
-
-    /// Mount point is not mounted.
-    #[error("Mount point not mounted: {mount_point}")]
-    NotMounted { 
-        mount_point: ShadowPath 
-    },
-
-    /// Feature not supported.
-    #[error("Unsupported feature: {feature}")]
-    Unsupported { 
-        feature: String 
-    },
-
-    /// Invalid configuration.
-    #[error("Invalid configuration: {message}")]
-    InvalidConfiguration { 
-        message: String 
-    },
-}
-
-impl ShadowError {
-    /// Creates a ShadowError from an io::Error with context about the path.
-    /// This provides more specific error mapping than the generic From trait.
-    pub fn from_io_error(error: std::io::Error, path: Option<&ShadowPath>) -> Self {
-        use std::io::ErrorKind;
-        
-        match error.kind() {
-            ErrorKind::NotFound => {
-                if let Some(p) = path {
-                    ShadowError::NotFound { path: p.clone() }
-                } else {
-                    ShadowError::IoError { source: error }
-                }
-            }
-            ErrorKind::PermissionDenied => {
-                if let Some(p) = path {
-                    ShadowError::PermissionDenied { 
-                        path: p.clone(), 
-                        operation: "access".to_string() 
-                    }
-                } else {
-                    ShadowError::IoError { source: error }
-                }
-            }
-            ErrorKind::AlreadyExists => {
-                if let Some(p) = path {
-                    ShadowError::AlreadyExists { path: p.clone() }
-                } else {
-                    ShadowError::IoError { source: error }
-                }
-            }
-            ErrorKind::InvalidInput | ErrorKind::InvalidData => {
-                if let Some(p) = path {
-                    ShadowError::InvalidPath { 
-                        path: p.to_string(), 
-                        reason: error.to_string() 
-                    }
-                } else {
-                    ShadowError::InvalidPath {
-                        path: String::new(),
-                        reason: error.to_string()
-                    }
-                }
-            }
-            _ => ShadowError::IoError { source: error }
-        }
-    }
-
-    /// Creates a ShadowError from an io::Error for a specific operation.
-    pub fn from_io_error_with_operation(
-        error: std::io::Error, 
-        path: &ShadowPath, 
-        operation: &str
-    ) -> Self {
-        use std::io::ErrorKind;
-        
-        match error.kind() {
-            ErrorKind::PermissionDenied => {
-                ShadowError::PermissionDenied { 
-                    path: path.clone(), 
-                    operation: operation.to_string() 
-                }
-            }
-            _ => Self::from_io_error(error, Some(path))
-        }
-    }
-}
-
-impl From<std::io::Error> for ShadowError {
-    fn from(error: std::io::Error) -> Self {
-        Self::from_io_error(error, None)
-    }
-}
 
 /// Result type alias for ShadowFS operations.
 pub type Result<T> = std::result::Result<T, ShadowError>;
@@ -295,6 +127,128 @@ pub fn unsupported(feature: impl Into<String>) -> ShadowError {
     ShadowError::Unsupported { feature: feature.into() }
 }
 
+/// Helper function to create a Cancelled error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::cancelled;
+/// 
+/// let err = cancelled("snapshot restore");
+/// ```
+pub fn cancelled(operation: impl Into<String>) -> ShadowError {
+    ShadowError::Cancelled { operation: operation.into() }
+}
+
+/// Helper function to create an AccessDenied error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::access_denied;
+/// 
+/// let err = access_denied(ShadowPath::from("/etc/hosts"), "write");
+/// ```
+pub fn access_denied(path: ShadowPath, operation: impl Into<String>) -> ShadowError {
+    ShadowError::AccessDenied { path, operation: operation.into() }
+}
+
+/// Helper function to create a ReadOnlyFilesystem error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::read_only_filesystem;
+/// 
+/// let err = read_only_filesystem(ShadowPath::from("/file.txt"), "write");
+/// ```
+pub fn read_only_filesystem(path: ShadowPath, operation: impl Into<String>) -> ShadowError {
+    ShadowError::ReadOnlyFilesystem { path, operation: operation.into() }
+}
+
+/// Helper function to create a DirectoryNotEmpty error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::directory_not_empty;
+/// 
+/// let err = directory_not_empty(ShadowPath::from("/src"));
+/// ```
+pub fn directory_not_empty(path: ShadowPath) -> ShadowError {
+    ShadowError::DirectoryNotEmpty { path }
+}
+
+/// Helper function to create a Conflict error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::conflict;
+/// 
+/// let err = conflict(ShadowPath::from("/etc/hosts"), "changed in both stores");
+/// ```
+pub fn conflict(path: ShadowPath, reason: impl Into<String>) -> ShadowError {
+    ShadowError::Conflict { path, reason: reason.into() }
+}
+
+/// Helper function to create an Unauthorized error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::unauthorized;
+/// 
+/// let err = unauthorized("commit", "token expired");
+/// ```
+pub fn unauthorized(operation: impl Into<String>, reason: impl Into<String>) -> ShadowError {
+    ShadowError::Unauthorized { operation: operation.into(), reason: reason.into() }
+}
+
+/// Helper function to create a MountLimitReached error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::mount_limit_reached;
+/// use shadowfs_core::types::ShadowPath;
+/// 
+/// let err = mount_limit_reached(ShadowPath::from("/mnt/ci"), "4 of 4 mounts active");
+/// ```
+pub fn mount_limit_reached(mount_point: ShadowPath, reason: impl Into<String>) -> ShadowError {
+    ShadowError::MountLimitReached { mount_point, reason: reason.into() }
+}
+
+/// Helper function to create a SourceNotWritable error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::source_not_writable;
+/// use shadowfs_core::types::ShadowPath;
+/// 
+/// let err = source_not_writable(ShadowPath::from("/src/repo"), "permission denied");
+/// ```
+pub fn source_not_writable(path: ShadowPath, reason: impl Into<String>) -> ShadowError {
+    ShadowError::SourceNotWritable { path, reason: reason.into() }
+}
+
+/// Helper function to create a QuotaExceeded error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::quota_exceeded;
+/// 
+/// let err = quota_exceeded(ShadowPath::from("/tmp/a.txt"), "1001 files would exceed the limit of 1000");
+/// ```
+pub fn quota_exceeded(path: ShadowPath, reason: impl Into<String>) -> ShadowError {
+    ShadowError::QuotaExceeded { path, reason: reason.into() }
+}
+
+/// Helper function to create a LimitExceeded error.
+/// 
+/// # Example
+/// ```ignore
+/// use shadowfs_core::error::{limit_exceeded, LimitKind};
+/// 
+/// let err = limit_exceeded(ShadowPath::from("/tmp/cache"), LimitKind::DirectoryEntries, 10001, 10000);
+/// ```
+pub fn limit_exceeded(path: ShadowPath, limit: LimitKind, actual: u64, max: u64) -> ShadowError {
+    ShadowError::LimitExceeded { path, limit, actual, max }
+}
+
 /// Helper function to create an OverrideStoreFull error.
 /// 
 /// # Example
@@ -454,135 +408,6 @@ impl<T> ErrorContext<T> for std::io::Result<T> {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_error_display() {
-        let path = ShadowPath::from("/test/file.txt");
-        
-        // Test NotFound
-        let err = ShadowError::NotFound { path: path.clone() };
-        assert_eq!(err.to_string(), "Path not found: /test/file.txt");
-
-        // Test PermissionDenied
-        let err = ShadowError::PermissionDenied { 
-            path: path.clone(), 
-            operation: "write".to_string() 
-        };
-        assert_eq!(err.to_string(), "Permission denied for operation 'write' on path: /test/file.txt");
-
-        // Test AlreadyExists
-        let err = ShadowError::AlreadyExists { path: path.clone() };
-        assert_eq!(err.to_string(), "Path already exists: /test/file.txt");
-
-        // Test NotADirectory
-        let err = ShadowError::NotADirectory { path: path.clone() };
-        assert_eq!(err.to_string(), "Not a directory: /test/file.txt");
-
-        // Test IsADirectory
-        let err = ShadowError::IsADirectory { path: path.clone() };
-        assert_eq!(err.to_string(), "Is a directory: /test/file.txt");
-
-        // Test InvalidPath
-        let err = ShadowError::InvalidPath { 
-            path: "//invalid//path".to_string(), 
-            reason: "contains double slashes".to_string() 
-        };
-        assert_eq!(err.to_string(), "Invalid path '//invalid//path': contains double slashes");
-
-        // Test PlatformError
-        let err = ShadowError::PlatformError { 
-            platform: Platform::Windows, 
-            message: "Access denied".to_string(), 
-            code: Some(5) 
-        };
-        assert_eq!(err.to_string(), "Platform error on Windows: Access denied (code: Some(5))");
-
-        // Test OverrideStoreFull
-        let err = ShadowError::OverrideStoreFull { 
-            current_size: 1048576, 
-            max_size: 1048576 
-        };
-        assert_eq!(err.to_string(), "Override store is full: current size 1048576 bytes, maximum 1048576 bytes");
-
-        // Test NotMounted
-        let err = ShadowError::NotMounted { 
-            mount_point: ShadowPath::from("/mnt/shadow") 
-        };
-        assert_eq!(err.to_string(), "Mount point not mounted: /mnt/shadow");
-
-        // Test Unsupported
-        let err = ShadowError::Unsupported { 
-            feature: "symbolic links".to_string() 
-        };
-        assert_eq!(err.to_string(), "Unsupported feature: symbolic links");
-    }
-
-    #[test]
-    fn test_io_error_conversion() {
-        // Test basic conversion without path
-        let io_err = std::io::Error::new(std::io::ErrorKind::Other, "generic error");
-        let shadow_err: ShadowError = io_err.into();
-        assert!(matches!(shadow_err, ShadowError::IoError { .. }));
-    }
-
-    #[test]
-    fn test_io_error_conversion_with_path() {
-        let path = ShadowPath::from("/test/file.txt");
-
-        // Test NotFound conversion
-        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
-        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
-        assert!(matches!(shadow_err, ShadowError::NotFound { path: p } if p == path));
-
-        // Test PermissionDenied conversion
-        let io_err = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
-        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
-        assert!(matches!(
-            shadow_err, 
-            ShadowError::PermissionDenied { path: p, operation } 
-            if p == path && operation == "access"
-        ));
-
-        // Test AlreadyExists conversion
-        let io_err = std::io::Error::new(std::io::ErrorKind::AlreadyExists, "exists");
-        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
-        assert!(matches!(shadow_err, ShadowError::AlreadyExists { path: p } if p == path));
-
-        // Test InvalidInput conversion
-        let io_err = std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid");
-        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
-        assert!(matches!(
-            shadow_err, 
-            ShadowError::InvalidPath { path: p, .. } 
-            if p == path.to_string()
-        ));
-    }
-
-    #[test]
-    fn test_io_error_with_operation() {
-        let path = ShadowPath::from("/test/file.txt");
-        
-        // Test PermissionDenied with custom operation
-        let io_err = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
-        let shadow_err = ShadowError::from_io_error_with_operation(io_err, &path, "write");
-        assert!(matches!(
-            shadow_err, 
-            ShadowError::PermissionDenied { path: p, operation } 
-            if p == path && operation == "write"
-        ));
-
-        // Test other error kinds fallback to from_io_error
-        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
-        let shadow_err = ShadowError::from_io_error_with_operation(io_err, &path, "read");
-        assert!(matches!(shadow_err, ShadowError::NotFound { path: p } if p == path));
-    }
-
-    #[test]
-    fn test_platform_display() {
-        assert_eq!(Platform::Windows.to_string(), "Windows");
-        assert_eq!(Platform::MacOS.to_string(), "macOS");
-        assert_eq!(Platform::Linux.to_string(), "Linux");
-    }
-
     #[test]
     fn test_error_helper_functions() {
         // Test not_found
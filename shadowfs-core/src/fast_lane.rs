@@ -0,0 +1,194 @@
+//! Sampled bookkeeping for hot small reads.
+//!
+//! Every lookup through [`OverrideStore::get`] counts a cache hit, updates
+//! the hot path table and moves the path to the recent end of the LRU order,
+//! taking two mutexes on the way. On metadata-heavy workloads, where the
+//! same small files are looked up over and over, that bookkeeping costs more
+//! than the lookup. A [`FastLane`] lets lookups of small overrides already in
+//! the hot cache do it for one in [`FastLane::sample_every`] of them, and
+//! counts each sampled lookup that many times.
+//!
+//! What the sampling costs in accuracy:
+//!
+//! - Hit counts, the hit rate and the access counts and bytes of hot paths
+//!   are estimates. They are exact on average, but a path looked up fewer
+//!   than `sample_every` times may show none or a whole sample's worth.
+//! - The average access interval of a hot path is measured between sampled
+//!   lookups and spread over the lookups each stands for.
+//! - A path moves to the recent end of the LRU order only on sampled
+//!   lookups, so under LRU and 2Q it can look up to `sample_every - 1`
+//!   lookups colder than it is. It is still in the hot cache, so it is among
+//!   the most recently used already; LFU counts it by its weight.
+//! - Access times and the last activity of the store, which decides idle
+//!   timeouts, stay exact.
+//!
+//! [`OverrideStore::fast_lane_stats`] tells how many lookups took the lane
+//! and how many of them were sampled.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use shadowfs_core::fast_lane::FastLane;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::types::ShadowPath;
+//!
+//! let store = OverrideStore::with_defaults();
+//! let path = ShadowPath::from("/Cargo.toml");
+//! store.insert_file(path.clone(), Bytes::from("[package]"), None).unwrap();
+//! store.get(&path);
+//!
+//! store.set_fast_lane(FastLane { sample_every: 16, ..FastLane::default() });
+//! for _ in 0..64 {
+//!     store.get(&path);
+//! }
+//! let lane = store.fast_lane_stats();
+//! assert_eq!(lane.sampled * 16, lane.reads);
+//! ```
+
+use crate::override_store::{OverrideEntry, OverrideStore};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Largest override taking the fast lane unless set otherwise.
+pub const DEFAULT_FAST_LANE_MAX_SIZE: u64 = 64 * 1024;
+
+/// Which lookups sample their bookkeeping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastLane {
+    /// One in this many fast-lane lookups does the bookkeeping; 1 turns
+    /// the lane off
+    pub sample_every: u32,
+    
+    /// Largest override, in bytes, whose lookups take the lane
+    pub max_size: u64,
+}
+
+impl FastLane {
+    /// Returns the lane turned off, with every lookup doing its bookkeeping.
+    pub fn off() -> Self {
+        Self { sample_every: 1, max_size: DEFAULT_FAST_LANE_MAX_SIZE }
+    }
+    
+    /// Returns true if lookups may skip their bookkeeping.
+    pub fn is_enabled(&self) -> bool {
+        self.sample_every > 1
+    }
+}
+
+impl Default for FastLane {
+    fn default() -> Self {
+        Self::off()
+    }
+}
+
+/// How many lookups took the fast lane.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FastLaneStats {
+    /// Lookups of hot small overrides while the lane was on
+    pub reads: u64,
+    
+    /// Of those, the lookups that did their bookkeeping
+    pub sampled: u64,
+}
+
+/// The fast lane of a store and its counters.
+#[derive(Debug)]
+pub(crate) struct FastLaneState {
+    sample_every: AtomicU32,
+    max_size: AtomicU64,
+    reads: AtomicU64,
+    sampled: AtomicU64,
+}
+
+impl Default for FastLaneState {
+    fn default() -> Self {
+        let lane = FastLane::off();
+        Self {
+            sample_every: AtomicU32::new(lane.sample_every),
+            max_size: AtomicU64::new(lane.max_size),
+            reads: AtomicU64::new(0),
+            sampled: AtomicU64::new(0),
+        }
+    }
+}
+
+impl OverrideStore {
+    /// Samples the bookkeeping of hot small lookups as `lane` says from now on.
+    pub fn set_fast_lane(&self, lane: FastLane) {
+        self.fast_lane.sample_every.store(lane.sample_every.max(1), Ordering::Relaxed);
+        self.fast_lane.max_size.store(lane.max_size, Ordering::Relaxed);
+    }
+    
+    /// Returns which lookups sample their bookkeeping.
+    pub fn fast_lane(&self) -> FastLane {
+        FastLane {
+            sample_every: self.fast_lane.sample_every.load(Ordering::Relaxed),
+            max_size: self.fast_lane.max_size.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// Returns how many lookups took the fast lane so far.
+    pub fn fast_lane_stats(&self) -> FastLaneStats {
+        FastLaneStats {
+            reads: self.fast_lane.reads.load(Ordering::Relaxed),
+            sampled: self.fast_lane.sampled.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// Returns how many lookups a hot-cache hit on `entry` counts as: 1
+    /// outside the lane, 0 for a lookup skipping its bookkeeping and
+    /// `sample_every` for a sampled one.
+    pub(crate) fn fast_lane_weight(&self, entry: &OverrideEntry) -> u64 {
+        let every = self.fast_lane.sample_every.load(Ordering::Relaxed);
+        if every <= 1 || entry.override_metadata.size > self.fast_lane.max_size.load(Ordering::Relaxed) {
+            return 1;
+        }
+        let read = self.fast_lane.reads.fetch_add(1, Ordering::Relaxed);
+        if read % u64::from(every) != 0 {
+            return 0;
+        }
+        self.fast_lane.sampled.fetch_add(1, Ordering::Relaxed);
+        u64::from(every)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ShadowPath;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_sampled_counts_stay_accurate() {
+        let store = OverrideStore::with_defaults();
+        let small = ShadowPath::from("/small.txt");
+        let large = ShadowPath::from("/large.bin");
+        store.insert_file(small.clone(), Bytes::from("small"), None).unwrap();
+        store.insert_file(large.clone(), Bytes::from(vec![0u8; 100]), None).unwrap();
+        store.get(&small);
+        store.get(&large);
+        let before = store.get_stats_snapshot().cache_hits;
+        
+        store.set_fast_lane(FastLane { sample_every: 8, max_size: 64 });
+        for _ in 0..800 {
+            store.get(&small);
+            store.get(&large);
+        }
+        
+        // Only the small file took the lane, and the counts come out the same
+        assert_eq!(store.fast_lane_stats(), FastLaneStats { reads: 800, sampled: 100 });
+        assert_eq!(store.get_stats_snapshot().cache_hits - before, 1600);
+        let hot = store.get_hot_paths(2);
+        let hot_count = |path: &ShadowPath| hot.iter().find(|(p, _)| p == path).unwrap().1.access_count;
+        assert_eq!(hot_count(&small), hot_count(&large));
+        
+        // LFU sees the same frequencies, but the small file was last sampled
+        // a few lookups ago and looks colder to LRU
+        let lru_count = |path: &ShadowPath| store.lru_tracker.get_access_stats(path).unwrap().access_count;
+        assert_eq!(lru_count(&small), lru_count(&large));
+        assert!(lru_count(&small) > 800);
+        assert_eq!(store.lru_tracker.get_least_recently_used(2), [small.clone(), large.clone()]);
+        
+        store.set_fast_lane(FastLane::off());
+        store.get(&small);
+        assert_eq!(store.fast_lane_stats().reads, 800);
+    }
+}
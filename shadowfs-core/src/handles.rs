@@ -0,0 +1,272 @@
+//! Open files of a mount and the handles they are known by.
+//!
+//! Every provider hands out a handle for each open, and each needs the same
+//! state behind it: the path, the flags it was opened with, a position for
+//! sequential reads and writes, and how many handles share it. A
+//! [`HandleTable`] keeps that state in one place per mount, so the
+//! providers register their handles there and `shadowfs status` can count
+//! them.
+//!
+//! [`open`](HandleTable::open) registers an [`OpenFile`] under a new
+//! handle. [`dup`](HandleTable::dup) gives an open file a second handle
+//! sharing its position and flags, the way `dup(2)` does, and
+//! [`close`](HandleTable::close) forgets a handle; the open file goes away
+//! with its last handle. Writes through a file opened with
+//! [`OpenFlags::APPEND`] go to the end of the file whatever offset they
+//! were made at; see [`OpenFile::write_offset`].
+//!
+//! ```rust
+//! use shadowfs_core::handles::HandleTable;
+//! use shadowfs_core::types::{OpenFlags, ShadowPath};
+//!
+//! let table = HandleTable::new();
+//! let handle = table.open(ShadowPath::from("/build.log"), OpenFlags::WRITE | OpenFlags::APPEND);
+//! let duplicate = table.dup(handle).unwrap();
+//!
+//! // Appends land at the end of the file, wherever the writer thinks it is
+//! let file = table.get(duplicate).unwrap();
+//! assert_eq!(file.write_offset(0, 128), 128);
+//!
+//! assert_eq!(table.close(handle).unwrap().handle_count(), 1);
+//! assert_eq!(table.summary().handles, 1);
+//! ```
+
+use crate::types::{FileHandle, OpenFlags, ShadowPath};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A file opened through a mount, shared by the handles duplicated from
+/// the one it was opened with.
+#[derive(Debug)]
+pub struct OpenFile {
+    path: ShadowPath,
+    flags: AtomicU32,
+    position: AtomicU64,
+    handles: AtomicUsize,
+    opened_at: SystemTime,
+}
+
+impl OpenFile {
+    /// Returns the path the file was opened at.
+    pub fn path(&self) -> &ShadowPath {
+        &self.path
+    }
+    
+    /// Returns the flags the file is open with.
+    pub fn flags(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.flags.load(Ordering::Relaxed))
+    }
+    
+    /// Turns appending on or off, as `fcntl(F_SETFL)` may; the other flags
+    /// are fixed at open.
+    pub fn set_append(&self, append: bool) {
+        if append {
+            self.flags.fetch_or(OpenFlags::APPEND.bits(), Ordering::Relaxed);
+        } else {
+            self.flags.fetch_and(!OpenFlags::APPEND.bits(), Ordering::Relaxed);
+        }
+    }
+    
+    /// Returns true if the file may be read: it was opened to read, or with
+    /// no access flag at all.
+    pub fn can_read(&self) -> bool {
+        let flags = self.flags();
+        flags.contains(OpenFlags::READ) || !self.can_write()
+    }
+    
+    /// Returns true if the file was opened to write or to append.
+    pub fn can_write(&self) -> bool {
+        let flags = self.flags();
+        flags.contains(OpenFlags::WRITE) || flags.contains(OpenFlags::APPEND)
+    }
+    
+    /// Returns the position sequential reads and writes continue from.
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed)
+    }
+    
+    /// Moves the position to `position`.
+    pub fn seek(&self, position: u64) {
+        self.position.store(position, Ordering::Relaxed);
+    }
+    
+    /// Returns the offset a write made at `offset` goes to in a file
+    /// `file_len` bytes long: its end if the file is appended to, `offset`
+    /// otherwise.
+    pub fn write_offset(&self, offset: u64, file_len: u64) -> u64 {
+        if self.flags().contains(OpenFlags::APPEND) {
+            file_len
+        } else {
+            offset
+        }
+    }
+    
+    /// Returns the number of handles sharing the file; 0 once it is closed.
+    pub fn handle_count(&self) -> usize {
+        self.handles.load(Ordering::Relaxed)
+    }
+    
+    /// Returns when the file was opened.
+    pub fn opened_at(&self) -> SystemTime {
+        self.opened_at
+    }
+}
+
+/// Counts of the handles open on a mount, as `shadowfs status` reports them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HandleSummary {
+    /// Open handles, duplicates included
+    pub handles: usize,
+    
+    /// Open files, each shared by one or more handles
+    pub files: usize,
+    
+    /// Open files that may be written to
+    pub writable: usize,
+}
+
+/// The open files of a mount by handle; see the [module documentation](self).
+#[derive(Debug)]
+pub struct HandleTable {
+    files: Mutex<HashMap<FileHandle, Arc<OpenFile>>>,
+    next: AtomicU64,
+}
+
+impl Default for HandleTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HandleTable {
+    /// Creates a table with no open files.
+    pub fn new() -> Self {
+        Self {
+            files: Mutex::new(HashMap::new()),
+            // Handle 0 is the invalid handle
+            next: AtomicU64::new(1),
+        }
+    }
+    
+    /// Registers the file at `path`, opened with `flags`, under a new handle.
+    ///
+    /// The table only keeps the state of the open; checking that the file
+    /// may be opened is up to the caller.
+    pub fn open(&self, path: ShadowPath, flags: OpenFlags) -> FileHandle {
+        let file = Arc::new(OpenFile {
+            path,
+            flags: AtomicU32::new(flags.bits()),
+            position: AtomicU64::new(0),
+            handles: AtomicUsize::new(1),
+            opened_at: SystemTime::now(),
+        });
+        let handle = self.allocate();
+        self.files.lock().unwrap().insert(handle, file);
+        handle
+    }
+    
+    /// Gives the file open under `handle` a new handle sharing its
+    /// position and flags, or returns `None` if `handle` is not open.
+    pub fn dup(&self, handle: FileHandle) -> Option<FileHandle> {
+        let mut files = self.files.lock().unwrap();
+        let file = Arc::clone(files.get(&handle)?);
+        file.handles.fetch_add(1, Ordering::Relaxed);
+        let duplicate = self.allocate();
+        files.insert(duplicate, file);
+        Some(duplicate)
+    }
+    
+    /// Returns the file open under `handle`.
+    pub fn get(&self, handle: FileHandle) -> Option<Arc<OpenFile>> {
+        self.files.lock().unwrap().get(&handle).cloned()
+    }
+    
+    /// Closes `handle`, returning the file it was open on, or `None` if it
+    /// was not open. The file is closed for good once its
+    /// [`handle_count`](OpenFile::handle_count) is 0.
+    pub fn close(&self, handle: FileHandle) -> Option<Arc<OpenFile>> {
+        let file = self.files.lock().unwrap().remove(&handle)?;
+        file.handles.fetch_sub(1, Ordering::Relaxed);
+        Some(file)
+    }
+    
+    /// Returns the number of open handles.
+    pub fn len(&self) -> usize {
+        self.files.lock().unwrap().len()
+    }
+    
+    /// Returns true if no handle is open.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Returns the number of handles open on `path`.
+    pub fn open_on(&self, path: &ShadowPath) -> usize {
+        self.files.lock().unwrap().values().filter(|file| file.path() == path).count()
+    }
+    
+    /// Counts the open handles and files.
+    pub fn summary(&self) -> HandleSummary {
+        let files = self.files.lock().unwrap();
+        let mut summary = HandleSummary { handles: files.len(), ..HandleSummary::default() };
+        let mut seen = std::collections::HashSet::new();
+        for file in files.values() {
+            if seen.insert(Arc::as_ptr(file)) {
+                summary.files += 1;
+                summary.writable += usize::from(file.can_write());
+            }
+        }
+        summary
+    }
+    
+    fn allocate(&self) -> FileHandle {
+        FileHandle::new(self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_duplicates_share_the_open_file() {
+        let table = HandleTable::new();
+        let path = ShadowPath::from("/data.bin");
+        let reader = table.open(path.clone(), OpenFlags::READ);
+        let writer = table.open(path.clone(), OpenFlags::WRITE);
+        let duplicate = table.dup(writer).unwrap();
+        assert!(reader.is_valid() && reader != writer && writer != duplicate);
+        
+        // Duplicates move together; separate opens do not
+        table.get(duplicate).unwrap().seek(42);
+        assert_eq!(table.get(writer).unwrap().position(), 42);
+        assert_eq!(table.get(reader).unwrap().position(), 0);
+        assert!(table.get(reader).unwrap().can_read());
+        assert!(!table.get(writer).unwrap().can_read());
+        assert_eq!(table.summary(), HandleSummary { handles: 3, files: 2, writable: 1 });
+        assert_eq!(table.open_on(&path), 3);
+        
+        // The open file outlives the handle it was opened with
+        assert_eq!(table.close(writer).unwrap().handle_count(), 1);
+        assert!(table.close(writer).is_none());
+        assert!(table.dup(writer).is_none());
+        assert_eq!(table.get(duplicate).unwrap().position(), 42);
+        assert_eq!(table.close(duplicate).unwrap().handle_count(), 0);
+        assert_eq!(table.summary(), HandleSummary { handles: 1, files: 1, writable: 0 });
+    }
+    
+    #[test]
+    fn test_appends_go_to_the_end() {
+        let table = HandleTable::new();
+        let file = table.get(table.open(ShadowPath::from("/log"), OpenFlags::APPEND)).unwrap();
+        assert!(file.can_write());
+        assert_eq!(file.write_offset(3, 100), 100);
+        
+        file.set_append(false);
+        assert_eq!(file.write_offset(3, 100), 3);
+        assert!(file.can_read());
+    }
+}
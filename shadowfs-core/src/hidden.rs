@@ -0,0 +1,195 @@
+//! Hiding dotfiles, Windows hidden and system files and chosen paths from a mount.
+//!
+//! A workspace handed to end users reads better without the `.git`
+//! directory, editor droppings and the `desktop.ini` files Windows keeps
+//! next to everything. A mount with [`HiddenFiles`] leaves the entries they
+//! match out of directory listings. Whether the entries can still be
+//! opened, stat'ed and looked up by path is a choice of its own:
+//! [`HiddenLookups::Resolve`] keeps them reachable for tools that know they
+//! are there, [`HiddenLookups::NotFound`] makes them, and everything below
+//! a hidden directory, not exist.
+//!
+//! Hiding only changes what the mount shows. Hidden entries stay in the
+//! source and the store, count against quotas and keep a directory from
+//! being removed while it holds them.
+//!
+//! ```rust
+//! use shadowfs_core::hidden::{HiddenFiles, HiddenLookups};
+//! use shadowfs_core::types::ShadowPath;
+//!
+//! let hidden = HiddenFiles::dotfiles().with_glob("*.pyc").with_lookups(HiddenLookups::NotFound);
+//!
+//! assert!(hidden.hides(&ShadowPath::from("/.git"), None));
+//! assert!(hidden.hides(&ShadowPath::from("/src/cache.pyc"), None));
+//! assert!(!hidden.hides(&ShadowPath::from("/src/main.py"), None));
+//! assert!(hidden.hides_lookup(&ShadowPath::from("/.git/config"), None));
+//! ```
+
+use crate::override_store::OverrideRule;
+use crate::types::{DirectoryEntry, FileMetadata, PlatformMetadata, ShadowPath};
+
+/// Windows attribute of files Explorer hides unless asked to show them.
+pub const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+
+/// Windows attribute of files belonging to the operating system.
+pub const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+
+/// Whether entries left out of listings can still be reached by path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HiddenLookups {
+    /// Hidden entries are only left out of listings; opening or looking
+    /// one up by path finds it
+    #[default]
+    Resolve,
+    
+    /// Hidden entries, and everything below a hidden directory, do not
+    /// exist for the mount
+    NotFound,
+}
+
+/// Which entries a mount hides. The default hides nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HiddenFiles {
+    /// Hide entries whose name starts with a dot
+    pub dotfiles: bool,
+    
+    /// Hide entries with the Windows hidden attribute
+    pub hidden_attribute: bool,
+    
+    /// Hide entries with the Windows system attribute
+    pub system_attribute: bool,
+    
+    /// Glob patterns of paths to hide, matched against the whole path of
+    /// an entry, such as `*.pyc` or `*/node_modules`
+    pub globs: Vec<String>,
+    
+    /// Whether hidden entries can still be reached by path
+    pub lookups: HiddenLookups,
+}
+
+impl HiddenFiles {
+    /// Hides entries whose name starts with a dot.
+    pub fn dotfiles() -> Self {
+        Self { dotfiles: true, ..Self::default() }
+    }
+    
+    /// Hides entries Windows marks hidden or system, as Explorer does by default.
+    pub fn windows_hidden() -> Self {
+        Self { hidden_attribute: true, system_attribute: true, ..Self::default() }
+    }
+    
+    /// Also hides the paths matching `pattern`.
+    pub fn with_glob(mut self, pattern: impl Into<String>) -> Self {
+        self.globs.push(pattern.into());
+        self
+    }
+    
+    /// Sets whether hidden entries can still be reached by path.
+    pub fn with_lookups(mut self, lookups: HiddenLookups) -> Self {
+        self.lookups = lookups;
+        self
+    }
+    
+    /// Returns true if nothing is hidden.
+    pub fn is_empty(&self) -> bool {
+        !self.dotfiles && !self.hidden_attribute && !self.system_attribute && self.globs.is_empty()
+    }
+    
+    /// Returns true if listings leave out the entry at `path`.
+    ///
+    /// Attributes are only checked when `metadata` is given; without it,
+    /// only the name and the globs decide.
+    pub fn hides(&self, path: &ShadowPath, metadata: Option<&FileMetadata>) -> bool {
+        self.hides_name(path) || metadata.is_some_and(|metadata| match metadata.platform_specific {
+            PlatformMetadata::Windows { attributes, .. } => self.hides_attributes(attributes),
+            _ => false,
+        })
+    }
+    
+    /// Returns true if looking up `path` by name finds nothing: lookups are
+    /// [`HiddenLookups::NotFound`] and the entry, or one of its parents by
+    /// name or glob, is hidden.
+    pub fn hides_lookup(&self, path: &ShadowPath, metadata: Option<&FileMetadata>) -> bool {
+        if self.lookups != HiddenLookups::NotFound || self.is_empty() {
+            return false;
+        }
+        if self.hides(path, metadata) {
+            return true;
+        }
+        let mut ancestor = path.parent();
+        while let Some(parent) = ancestor {
+            if self.hides_name(&parent) {
+                return true;
+            }
+            ancestor = parent.parent();
+        }
+        false
+    }
+    
+    /// Leaves the hidden entries out of `entries`, the listing of `directory`.
+    pub fn filter(&self, directory: &ShadowPath, mut entries: Vec<DirectoryEntry>) -> Vec<DirectoryEntry> {
+        if !self.is_empty() {
+            entries.retain(|entry| !self.hides(&directory.join(&entry.name), Some(&entry.metadata)));
+        }
+        entries
+    }
+    
+    /// Returns true if an entry with the Windows file `attributes` is
+    /// hidden, whatever its name.
+    pub fn hides_attributes(&self, attributes: u32) -> bool {
+        (self.hidden_attribute && attributes & FILE_ATTRIBUTE_HIDDEN != 0)
+            || (self.system_attribute && attributes & FILE_ATTRIBUTE_SYSTEM != 0)
+    }
+    
+    fn hides_name(&self, path: &ShadowPath) -> bool {
+        let name = path.file_name().unwrap_or_default();
+        (self.dotfiles && name.starts_with('.') && name != "." && name != "..")
+            || self.globs.iter().any(|pattern| OverrideRule::Glob(pattern.clone()).matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FileType;
+    
+    fn windows(attributes: u32) -> FileMetadata {
+        FileMetadata {
+            file_type: FileType::File,
+            platform_specific: PlatformMetadata::Windows { attributes, reparse_tag: None, streams: Vec::new() },
+            ..FileMetadata::default()
+        }
+    }
+    
+    #[test]
+    fn test_listings_leave_out_hidden_entries() {
+        let hidden = HiddenFiles::windows_hidden().with_glob("*/target");
+        let directory = ShadowPath::from("/project");
+        let entries = vec![
+            DirectoryEntry::new("desktop.ini".to_string(), windows(FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM)),
+            DirectoryEntry::new("notes.txt".to_string(), windows(FILE_ATTRIBUTE_HIDDEN)),
+            DirectoryEntry::new("target".to_string(), FileMetadata::default()),
+            DirectoryEntry::new(".env".to_string(), FileMetadata::default()),
+            DirectoryEntry::new("src".to_string(), windows(0)),
+        ];
+        
+        let names: Vec<String> = hidden.filter(&directory, entries).into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, [".env", "src"]);
+        assert!(HiddenFiles::default().is_empty());
+    }
+    
+    #[test]
+    fn test_lookups_follow_the_choice() {
+        let resolve = HiddenFiles::dotfiles();
+        let path = ShadowPath::from("/.git/config");
+        assert!(!resolve.hides_lookup(&path, None));
+        
+        let not_found = resolve.with_lookups(HiddenLookups::NotFound);
+        assert!(not_found.hides_lookup(&path, None));
+        assert!(not_found.hides_lookup(&ShadowPath::from("/.git"), None));
+        assert!(!not_found.hides_lookup(&ShadowPath::from("/src/git"), None));
+        assert!(!not_found.hides_lookup(&ShadowPath::from("/"), None));
+    }
+}
@@ -0,0 +1,361 @@
+//! Idempotency keys for daemon requests that change state.
+//!
+//! A client that sends a mount, commit or apply request and loses the
+//! connection before the reply cannot tell whether the daemon acted on it.
+//! Sending the request again with the same [`IdempotencyKey`] makes the retry
+//! safe: the daemon looks the key up in an [`IdempotencyCache`] and replies
+//! with the recorded result instead of mounting or committing a second time.
+//!
+//! Results are kept for [`IdempotencyConfig::ttl`], up to
+//! [`IdempotencyConfig::max_entries`] keys, in memory only; a restarted
+//! daemon treats every key as new. Errors are recorded in their
+//! [`WireError`] form, except retryable ones, whose keys are released so the
+//! retry runs the request again.
+
+use crate::error::{ShadowError, WireError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest key accepted, in bytes.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 256;
+
+/// Client-chosen key identifying one request across its retries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    /// Creates a key, which must be non-empty printable ASCII of at most
+    /// [`MAX_IDEMPOTENCY_KEY_LEN`] bytes, such as a UUID.
+    pub fn new(key: impl Into<String>) -> Result<Self, ShadowError> {
+        let key = key.into();
+        let valid = !key.is_empty()
+            && key.len() <= MAX_IDEMPOTENCY_KEY_LEN
+            && key.bytes().all(|b| b.is_ascii_graphic());
+        if !valid {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!("invalid idempotency key '{}'", key.escape_default()),
+            });
+        }
+        Ok(Self(key))
+    }
+    
+    /// Returns the key as sent by the client.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for IdempotencyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = ShadowError;
+    
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        Self::new(key)
+    }
+}
+
+impl From<IdempotencyKey> for String {
+    fn from(key: IdempotencyKey) -> Self {
+        key.0
+    }
+}
+
+/// Limits of an [`IdempotencyCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotencyConfig {
+    /// How long a finished request's result is replayed to retries
+    pub ttl: Duration,
+    
+    /// Most keys kept; the oldest finished ones are dropped first
+    pub max_entries: usize,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(24 * 60 * 60),
+            max_entries: 10_000,
+        }
+    }
+}
+
+/// What to do with a request, as decided by [`IdempotencyCache::begin`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim<T> {
+    /// The key is new: run the request, then record its result with
+    /// [`IdempotencyCache::complete`]
+    Run,
+    /// A request with this key already finished; reply with its result
+    Replay(Result<T, WireError>),
+    /// A request with this key is still running; the client should retry later
+    InProgress,
+}
+
+#[derive(Debug)]
+enum State<T> {
+    Running,
+    Done(Result<T, WireError>),
+}
+
+#[derive(Debug)]
+struct Record<T> {
+    /// Description of the request, so a key reused for another request is caught
+    request: String,
+    state: State<T>,
+    updated: Instant,
+}
+
+/// Recent idempotency keys of a daemon, with the results of their requests.
+#[derive(Debug)]
+pub struct IdempotencyCache<T> {
+    config: IdempotencyConfig,
+    records: Mutex<HashMap<IdempotencyKey, Record<T>>>,
+}
+
+impl<T: Clone> IdempotencyCache<T> {
+    /// Creates an empty cache.
+    pub fn new(config: IdempotencyConfig) -> Self {
+        Self {
+            config,
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Claims `key` for a request, or returns what became of the earlier
+    /// request with the same key.
+    ///
+    /// # Arguments
+    /// * `key` - Key sent by the client
+    /// * `request` - Description of the request, such as the operation and
+    ///   its arguments serialized; retries must describe it the same way
+    ///
+    /// # Errors
+    /// Fails with [`ShadowError::InvalidConfiguration`] if the key was used
+    /// for a different request.
+    pub fn begin(&self, key: &IdempotencyKey, request: &str) -> Result<Claim<T>, ShadowError> {
+        let now = Instant::now();
+        let mut records = self.records.lock().unwrap();
+        Self::expire(&mut records, self.config.ttl, now);
+        
+        if let Some(record) = records.get(key) {
+            if record.request != request {
+                return Err(ShadowError::InvalidConfiguration {
+                    message: format!("idempotency key '{}' was already used for a different request", key),
+                });
+            }
+            return Ok(match &record.state {
+                State::Running => Claim::InProgress,
+                State::Done(result) => Claim::Replay(result.clone()),
+            });
+        }
+        
+        if records.len() >= self.config.max_entries {
+            Self::drop_oldest(&mut records);
+        }
+        records.insert(key.clone(), Record {
+            request: request.to_string(),
+            state: State::Running,
+            updated: now,
+        });
+        Ok(Claim::Run)
+    }
+    
+    /// Records the result of a request claimed with [`IdempotencyCache::begin`].
+    ///
+    /// A retryable error releases the key instead, so that the next attempt
+    /// runs the request again.
+    pub fn complete(&self, key: &IdempotencyKey, result: Result<T, WireError>) {
+        let mut records = self.records.lock().unwrap();
+        if matches!(&result, Err(error) if error.retryable) {
+            records.remove(key);
+            return;
+        }
+        if let Some(record) = records.get_mut(key) {
+            record.state = State::Done(result);
+            record.updated = Instant::now();
+        }
+    }
+    
+    /// Releases a claimed key without recording a result, for a request that
+    /// was abandoned before it changed anything.
+    pub fn release(&self, key: &IdempotencyKey) {
+        self.records.lock().unwrap().remove(key);
+    }
+    
+    /// Runs `request` under `key`, or replays the result of the earlier
+    /// request with the same key.
+    ///
+    /// # Errors
+    /// Besides the error of the request itself, fails like
+    /// [`IdempotencyCache::begin`], and with a retryable
+    /// [`ShadowError::Cancelled`] while the earlier request is still running.
+    pub async fn run<F, Fut>(&self, key: &IdempotencyKey, request: &str, execute: F) -> Result<T, WireError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<T, ShadowError>>,
+    {
+        match self.begin(key, request).map_err(|e| WireError::from(&e))? {
+            Claim::Replay(result) => result,
+            Claim::InProgress => {
+                let mut busy = WireError::from(ShadowError::Cancelled {
+                    operation: format!("request with idempotency key '{}' is still running", key),
+                });
+                busy.retryable = true;
+                Err(busy)
+            }
+            Claim::Run => {
+                // A request dropped mid-way leaves no result, so its key is released
+                let claim = RunningClaim { cache: self, key: Some(key) };
+                let result = execute().await.map_err(|e| WireError::from(&e));
+                claim.finish(result.clone());
+                result
+            }
+        }
+    }
+    
+    /// Returns the number of keys held.
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+    
+    /// Returns true if no keys are held.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Drops finished requests older than the TTL. Running ones are kept
+    /// whatever their age.
+    fn expire(records: &mut HashMap<IdempotencyKey, Record<T>>, ttl: Duration, now: Instant) {
+        records.retain(|_, record| {
+            matches!(record.state, State::Running) || now.duration_since(record.updated) < ttl
+        });
+    }
+    
+    fn drop_oldest(records: &mut HashMap<IdempotencyKey, Record<T>>) {
+        let oldest = records.iter()
+            .filter(|(_, record)| matches!(record.state, State::Done(_)))
+            .min_by_key(|(_, record)| record.updated)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            records.remove(&key);
+        }
+    }
+}
+
+/// A claimed key, released if its request is dropped before it finishes.
+struct RunningClaim<'a, T: Clone> {
+    cache: &'a IdempotencyCache<T>,
+    key: Option<&'a IdempotencyKey>,
+}
+
+impl<T: Clone> RunningClaim<'_, T> {
+    fn finish(mut self, result: Result<T, WireError>) {
+        if let Some(key) = self.key.take() {
+            self.cache.complete(key, result);
+        }
+    }
+}
+
+impl<T: Clone> Drop for RunningClaim<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.cache.release(key);
+        }
+    }
+}
+
+impl<T: Clone> Default for IdempotencyCache<T> {
+    fn default() -> Self {
+        Self::new(IdempotencyConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::types::ShadowPath;
+    
+    fn key(k: &str) -> IdempotencyKey {
+        IdempotencyKey::new(k).unwrap()
+    }
+    
+    #[test]
+    fn test_key_validation() {
+        assert!(IdempotencyKey::new("").is_err());
+        assert!(IdempotencyKey::new("has space").is_err());
+        assert!(IdempotencyKey::new("x".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1)).is_err());
+        assert_eq!(serde_json::to_string(&key("abc-123")).unwrap(), "\"abc-123\"");
+        assert!(serde_json::from_str::<IdempotencyKey>("\"\"").is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_retry_replays_result() {
+        let cache = IdempotencyCache::default();
+        let mut runs = 0;
+        for _ in 0..2 {
+            let result = cache.run(&key("mount-1"), "mount /src /mnt", || {
+                runs += 1;
+                async { Ok::<_, ShadowError>(42u32) }
+            }).await;
+            assert_eq!(result, Ok(42));
+        }
+        assert_eq!(runs, 1);
+        
+        // A request dropped before it finishes does not hold on to its key
+        let second = key("mount-2");
+        let pending = cache.run(&second, "mount /src /mnt", std::future::pending);
+        tokio::select! {
+            biased;
+            _ = pending => unreachable!(),
+            _ = std::future::ready(()) => {}
+        }
+        assert_eq!(cache.begin(&second, "mount /src /mnt").unwrap(), Claim::Run);
+        
+        let reused = cache.begin(&key("mount-1"), "mount /src /other");
+        assert!(matches!(reused, Err(ShadowError::InvalidConfiguration { .. })));
+    }
+    
+    #[test]
+    fn test_errors_replay_unless_retryable() {
+        let cache: IdempotencyCache<()> = IdempotencyCache::default();
+        
+        assert_eq!(cache.begin(&key("a"), "commit").unwrap(), Claim::Run);
+        assert_eq!(cache.begin(&key("a"), "commit").unwrap(), Claim::InProgress);
+        let failed = WireError::from(ShadowError::NotFound { path: ShadowPath::from("/src") });
+        cache.complete(&key("a"), Err(failed));
+        match cache.begin(&key("a"), "commit").unwrap() {
+            Claim::Replay(Err(error)) => assert_eq!(error.code, ErrorCode::NotFound),
+            other => panic!("expected a replayed error, got {:?}", other),
+        }
+        
+        assert_eq!(cache.begin(&key("b"), "commit").unwrap(), Claim::Run);
+        cache.complete(&key("b"), Err(WireError::from(ShadowError::OverrideStoreFull { current_size: 2, max_size: 1 })));
+        assert_eq!(cache.begin(&key("b"), "commit").unwrap(), Claim::Run);
+    }
+    
+    #[test]
+    fn test_expiry_and_capacity() {
+        let cache = IdempotencyCache::new(IdempotencyConfig { ttl: Duration::ZERO, max_entries: 2 });
+        cache.begin(&key("a"), "apply").unwrap();
+        cache.complete(&key("a"), Ok(1u8));
+        assert_eq!(cache.begin(&key("a"), "apply").unwrap(), Claim::Run);
+        
+        let cache = IdempotencyCache::new(IdempotencyConfig { ttl: Duration::from_secs(60), max_entries: 2 });
+        for k in ["a", "b", "c"] {
+            cache.begin(&key(k), "apply").unwrap();
+            cache.complete(&key(k), Ok(1u8));
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.begin(&key("a"), "apply").unwrap(), Claim::Run);
+    }
+}
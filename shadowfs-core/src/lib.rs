@@ -17,6 +17,7 @@
 //! 
 //! ```rust,ignore
 //! use shadowfs_core::traits::FileSystemProvider;
+//! use shadowfs_core::types::MountOptions;
 //! use std::path::Path;
 //! 
 //! async fn example() -> Result<(), Box<dyn std::error::Error>> {
@@ -26,7 +27,8 @@
 //!     // Mount a shadow filesystem
 //!     provider.mount(
 //!         Path::new("/source/directory"),
-//!         Path::new("/mount/point")
+//!         Path::new("/mount/point"),
+//!         &MountOptions::default(),
 //!     ).await?;
 //!     
 //!     // All operations on /mount/point now go through ShadowFS
@@ -41,8 +43,66 @@
 //! - [`traits`]: Core traits that platform implementations must provide
 //! - [`types`]: Common types used across the system
 //! - [`error`]: Error types and handling
+//! - [`clock`]: The monotonic clock recency, ages and timeouts are measured by
+//! - [`mount_paths`]: Conversions between shadow paths, source paths and paths below a mount point
 //! - [`override_store`]: In-memory storage for file overrides
 //! - [`stats`]: Performance statistics collection
+//! - [`metrics`]: Prometheus metrics of mounts over HTTP and through the `metrics` crate facade
+//! - [`passthrough`]: Store-backed file operations that providers inherit, with advisory locks
+//! - [`handles`]: Open files of a mount by handle, with their flags, positions and duplicates
+//! - [`determinism`]: Deterministic mounts with frozen timestamps and stable inodes, for reproducible builds
+//! - [`hidden`]: Hiding dotfiles, Windows hidden and system files and chosen globs from a mount
+//! - [`self_test`]: Round trips of a probe file through a fresh mount, rolled back if they fail
+//! - [`mount_manager`]: Tracking of concurrent mounts over a shared override store
+//! - [`virtual_fs`]: A provider serving mounts in process, for tests and sandboxes without FUSE, ProjFS or FSKit
+//! - [`compat`]: A `std::fs`-shaped interface to a mount, for code written against std-like filesystem APIs
+//! - [`async_file`]: Buffered Tokio file handles to a mount, for async code without an OS mount
+//! - [`registry`]: Mount records kept across restarts, for finding mounts left by crashed processes
+//! - [`progress`]: Progress reporting and cancellation for long-running operations
+//! - [`idempotency`]: Idempotency keys that make retried daemon requests safe
+//! - [`tokens`]: Scoped, expiring API tokens for automation clients
+//! - [`profile`]: Mount profiles with ignore rules and seed settings, such as the dotfiles preset
+//! - [`rule_book`]: Template rules kept in a file, for changing the rules of a running mount
+//! - [`rebalance`]: Rebalancing of a shared memory budget between mounts by priority and activity
+//! - [`watch`]: Change notifications for the shadow layer with glob-filtered subscriptions
+//! - [`source_watch`]: Source tree watching, conflict detection and cache invalidation
+//! - [`access`]: Per-path allow and deny rules with an audit log of denied operations
+//! - [`trace`]: Debug tracing of the operations on paths matching chosen globs
+//! - [`telemetry`]: Spans following each operation from its mount and provider callback into the store
+//! - [`source_cache`]: Open handles and memory maps of frequently read source files
+//! - [`merged_view`]: Directory listings merging a source directory with its overrides and tombstones, a page at a time
+//! - [`listing_cache`]: Merged directory listings invalidated by override changes and source events
+//! - [`quota`]: Per-mount limits on the number of files, the size of a file and the total bytes written
+//! - [`atime`]: Per-mount access-time policies emulating noatime, relatime and strictatime
+//! - [`path_limits`]: Per-mount limits on entries per directory, path depth and name length
+//! - [`namespace`]: Independent namespaces with their own quotas and stats inside one store
+//! - [`fast_lane`]: Sampled bookkeeping for hot small reads, trading stats accuracy for speed
+//! - [`task`]: Named threads and Tokio tasks, visible in tokio-console
+//! - [`supervision`]: Per-subsystem choice between failing fast and degrading on internal failures
+//! 
+//! ## Feature Flags
+//! 
+//! Everything but `metrics` is enabled by default. Embedders that only need
+//! the override store can turn the rest off:
+//! 
+//! ```toml
+//! shadowfs-core = { version = "0.1", default-features = false, features = ["store-core"] }
+//! ```
+//! 
+//! - `store-core`: [`override_store`], [`watch`], [`source_watch`], [`access`], [`trace`], [`telemetry`], [`source_cache`], [`merged_view`], [`listing_cache`], [`quota`], [`atime`], [`path_limits`], [`namespace`], [`fast_lane`] and [`supervision`]
+//! - `compression`: zstd compression of large overrides
+//! - `dedup`: BLAKE3 content hashes and deduplication; without it hashes are zero
+//! - `persistence`: snapshots, the WAL, the spill tier, export, commit and diff, [`progress`], [`idempotency`] and [`tokens`]
+//! - `stats`: [`stats`]
+//! - `metrics`: [`metrics`]; adds the `metrics` crate and Tokio networking
+//! - `patterns`: regex rules, rule sets, templates and content transforms
+//! - `platform`: [`traits`], [`passthrough`], [`handles`], [`determinism`], [`hidden`], [`self_test`], [`mount_manager`], [`virtual_fs`], [`compat`], [`async_file`], [`registry`], [`profile`], [`rule_book`], [`rebalance`], [`platform`] and the mount types
+//! - `serde`: serde implementations for the store's types
+//! 
+//! The minimal `store-core` build depends on `bytes`, `indexmap`, `lru`,
+//! `shadowfs-types`, `tokio` and `tracing` only. `shadowfs-types` holds
+//! [`types::ShadowPath`], [`types::FileMetadata`] and [`error::ShadowError`]
+//! and builds without std.
 //! 
 //! ## Platform Support
 //! 
@@ -59,9 +119,79 @@
 //! - [Platform Guide](https://github.com/aslitaser/shadowfs/blob/main/docs/platform-guide.md)
 //! - [Contributing](https://github.com/aslitaser/shadowfs/blob/main/docs/contributing.md)
 
+#[cfg(feature = "platform")]
 pub mod traits;
 pub mod types;
 pub mod error;
+pub mod clock;
+pub mod mount_paths;
+#[cfg(feature = "store-core")]
 pub mod override_store;
+#[cfg(feature = "stats")]
 pub mod stats;
-pub mod platform;
\ No newline at end of file
+#[cfg(feature = "platform")]
+pub mod platform;
+#[cfg(feature = "platform")]
+pub mod passthrough;
+#[cfg(feature = "platform")]
+pub mod handles;
+#[cfg(feature = "platform")]
+pub mod determinism;
+#[cfg(feature = "platform")]
+pub mod hidden;
+#[cfg(feature = "platform")]
+pub mod self_test;
+#[cfg(feature = "platform")]
+pub mod mount_manager;
+#[cfg(feature = "platform")]
+pub mod virtual_fs;
+#[cfg(feature = "platform")]
+pub mod compat;
+#[cfg(feature = "platform")]
+pub mod async_file;
+#[cfg(feature = "platform")]
+pub mod registry;
+#[cfg(feature = "persistence")]
+pub mod progress;
+#[cfg(feature = "persistence")]
+pub mod idempotency;
+#[cfg(feature = "persistence")]
+pub mod tokens;
+#[cfg(feature = "platform")]
+pub mod profile;
+#[cfg(feature = "platform")]
+pub mod rule_book;
+#[cfg(feature = "platform")]
+pub mod rebalance;
+#[cfg(feature = "store-core")]
+pub mod watch;
+#[cfg(feature = "store-core")]
+pub mod source_watch;
+pub mod task;
+#[cfg(feature = "store-core")]
+pub mod supervision;
+#[cfg(feature = "store-core")]
+pub mod access;
+#[cfg(feature = "store-core")]
+pub mod trace;
+#[cfg(feature = "store-core")]
+pub mod telemetry;
+#[cfg(feature = "store-core")]
+pub mod source_cache;
+#[cfg(feature = "store-core")]
+pub mod merged_view;
+#[cfg(feature = "store-core")]
+pub mod listing_cache;
+#[cfg(feature = "store-core")]
+pub mod quota;
+#[cfg(feature = "store-core")]
+pub mod atime;
+#[cfg(feature = "store-core")]
+pub mod path_limits;
+#[cfg(feature = "store-core")]
+pub mod namespace;
+#[cfg(feature = "store-core")]
+pub mod fast_lane;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
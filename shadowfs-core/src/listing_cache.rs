@@ -0,0 +1,317 @@
+//! Merged directory listings kept between enumerations.
+//!
+//! Listing a directory of a mount reads the source directory, stats every
+//! source child and looks every child up in the store. A [`ListingCache`]
+//! attached with [`OverrideStore::set_listing_cache`] keeps the merged
+//! listing, so enumerating a big directory that did not change again costs
+//! a lookup instead.
+//!
+//! Every directory has a generation, bumped whenever one of its overrides is
+//! written, deleted or removed and whenever a
+//! [`SourceChangeHandler`](crate::source_watch::SourceChangeHandler) applies
+//! a change to one of its source children. A listing is served only while
+//! the generation it was computed at is current, so one computed while the
+//! directory changed is never served.
+//!
+//! Source changes that no watcher reports are not seen. Attach a cache to
+//! stores whose sources are watched or do not change while mounted, or set
+//! [`ListingCacheConfig::max_age`] to bound how stale a listing can get.
+//! Listed access times are the ones seen when the listing was computed.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use shadowfs_core::listing_cache::{ListingCache, ListingCacheConfig};
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::passthrough::MountContext;
+//! use shadowfs_core::types::ShadowPath;
+//! use std::sync::Arc;
+//!
+//! let source = tempfile::tempdir().unwrap();
+//! std::fs::write(source.path().join("a.txt"), "a").unwrap();
+//! let store = Arc::new(OverrideStore::with_defaults());
+//! store.set_listing_cache(Arc::new(ListingCache::new(ListingCacheConfig::default())));
+//! let mount = MountContext::new(Arc::clone(&store), source.path(), false);
+//!
+//! let root = ShadowPath::from("/");
+//! assert_eq!(mount.read_directory(&root).unwrap().len(), 1);
+//! store.insert_file(ShadowPath::from("/b.txt"), Bytes::from("b"), None).unwrap();
+//! assert_eq!(mount.read_directory(&root).unwrap().len(), 2);
+//! assert_eq!(mount.read_directory(&root).unwrap().len(), 2);
+//!
+//! let stats = store.listing_cache().unwrap().stats();
+//! assert_eq!((stats.misses, stats.hits), (2, 1));
+//! ```
+
+use crate::clock;
+use crate::override_store::OverrideStore;
+use crate::types::{DirectoryEntry, ShadowPath};
+use lru::LruCache;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Directory listings kept by default.
+pub const DEFAULT_MAX_DIRECTORIES: usize = 1024;
+
+/// Generation counters directories share; two directories sharing one only
+/// invalidate each other's listings more often than needed.
+const GENERATION_SLOTS: usize = 4096;
+
+/// Limits of a [`ListingCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ListingCacheConfig {
+    /// Most directory listings kept, the least recently listed dropped first
+    pub max_directories: usize,
+    
+    /// Age after which a listing is computed again even if nothing reported
+    /// a change; `None` keeps listings until they are invalidated
+    pub max_age: Option<Duration>,
+}
+
+impl Default for ListingCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_directories: DEFAULT_MAX_DIRECTORIES,
+            max_age: None,
+        }
+    }
+}
+
+/// Counters of a [`ListingCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ListingCacheStats {
+    /// Listings currently kept
+    pub listings: usize,
+    
+    /// Enumerations served by a kept listing
+    pub hits: u64,
+    
+    /// Enumerations that had to list their directory
+    pub misses: u64,
+    
+    /// Generation bumps, one per directory a change was reported for
+    pub invalidations: u64,
+}
+
+/// Generation a listing was computed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Generation {
+    epoch: u64,
+    directory: u64,
+}
+
+/// A kept listing.
+struct CachedListing {
+    generation: Generation,
+    listed_at: u64,
+    entries: Arc<[DirectoryEntry]>,
+}
+
+/// Merged directory listings of the mounts of a store, by source and directory.
+pub struct ListingCache {
+    config: ListingCacheConfig,
+    listings: Mutex<LruCache<(PathBuf, ShadowPath), CachedListing>>,
+    generations: Box<[AtomicU64]>,
+    
+    /// Bumped to invalidate every listing at once
+    epoch: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl ListingCache {
+    /// Creates an empty cache within `config`.
+    pub fn new(config: ListingCacheConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.max_directories).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            config,
+            listings: Mutex::new(LruCache::new(capacity)),
+            generations: (0..GENERATION_SLOTS).map(|_| AtomicU64::new(0)).collect(),
+            epoch: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+    
+    /// Returns the limits of the cache.
+    pub fn config(&self) -> &ListingCacheConfig {
+        &self.config
+    }
+    
+    /// Returns the listing of `directory` in `source` kept from an earlier
+    /// enumeration, or lists it with `list` and keeps the result.
+    ///
+    /// A listing is only kept if no change to `directory` was reported
+    /// while `list` ran.
+    ///
+    /// # Errors
+    /// Returns what `list` returns, keeping nothing.
+    pub fn get_or_list<E>(
+        &self,
+        source: &Path,
+        directory: &ShadowPath,
+        list: impl FnOnce() -> Result<Vec<DirectoryEntry>, E>,
+    ) -> Result<Arc<[DirectoryEntry]>, E> {
+        let key = (source.to_path_buf(), directory.clone());
+        let generation = self.generation(directory);
+        {
+            let mut listings = self.listings.lock().unwrap();
+            match listings.get(&key) {
+                Some(cached) if cached.generation == generation && !self.expired(cached) => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(Arc::clone(&cached.entries));
+                }
+                Some(_) => {
+                    listings.pop(&key);
+                }
+                None => {}
+            }
+        }
+        
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let entries: Arc<[DirectoryEntry]> = list()?.into();
+        if self.generation(directory) == generation {
+            let cached = CachedListing { generation, listed_at: clock::now(), entries: Arc::clone(&entries) };
+            self.listings.lock().unwrap().put(key, cached);
+        }
+        Ok(entries)
+    }
+    
+    /// Marks every kept listing of `directory` stale.
+    pub fn invalidate(&self, directory: &ShadowPath) {
+        self.generations[slot(directory)].fetch_add(1, Ordering::Release);
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// Marks every kept listing stale, for changes too broad to tell which
+    /// directories they touched.
+    pub fn invalidate_all(&self) {
+        self.epoch.fetch_add(1, Ordering::Release);
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+        self.listings.lock().unwrap().clear();
+    }
+    
+    /// Returns the counters of the cache.
+    pub fn stats(&self) -> ListingCacheStats {
+        ListingCacheStats {
+            listings: self.listings.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+        }
+    }
+    
+    fn generation(&self, directory: &ShadowPath) -> Generation {
+        Generation {
+            epoch: self.epoch.load(Ordering::Acquire),
+            directory: self.generations[slot(directory)].load(Ordering::Acquire),
+        }
+    }
+    
+    fn expired(&self, cached: &CachedListing) -> bool {
+        self.config.max_age.is_some_and(|max_age| clock::elapsed(cached.listed_at) >= max_age)
+    }
+}
+
+impl std::fmt::Debug for ListingCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ListingCache")
+            .field("config", &self.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Returns the generation counter `directory` uses.
+fn slot(directory: &ShadowPath) -> usize {
+    let mut hasher = DefaultHasher::new();
+    directory.hash(&mut hasher);
+    (hasher.finish() % GENERATION_SLOTS as u64) as usize
+}
+
+impl OverrideStore {
+    /// Keeps the merged listings of the store's mounts in `cache`.
+    pub fn set_listing_cache(&self, cache: Arc<ListingCache>) {
+        *self.listing_cache.write().unwrap() = Some(cache);
+    }
+    
+    /// Returns the attached listing cache, if any.
+    pub fn listing_cache(&self) -> Option<Arc<ListingCache>> {
+        self.listing_cache.read().unwrap().clone()
+    }
+    
+    /// Marks the listings showing `path` stale: its parent's and, should it
+    /// be a directory, its own. With `recursive`, every listing is marked,
+    /// since everything below `path` may have changed.
+    pub fn invalidate_listing(&self, path: &ShadowPath, recursive: bool) {
+        let Some(cache) = self.listing_cache() else {
+            return;
+        };
+        if recursive {
+            cache.invalidate_all();
+            return;
+        }
+        cache.invalidate(path);
+        if let Some(parent) = path.parent() {
+            cache.invalidate(&parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FileMetadata;
+    use std::convert::Infallible;
+    
+    fn listing(names: &[&str]) -> Vec<DirectoryEntry> {
+        names.iter().map(|name| DirectoryEntry::new(name.to_string(), FileMetadata::default())).collect()
+    }
+    
+    #[test]
+    fn test_listings_follow_generations() {
+        let cache = ListingCache::new(ListingCacheConfig::default());
+        let source = Path::new("/source");
+        let src = ShadowPath::from("/src");
+        let list = |names: &'static [&'static str]| move || Ok::<_, Infallible>(listing(names));
+        
+        assert_eq!(cache.get_or_list(source, &src, list(&["a"])).unwrap().len(), 1);
+        assert_eq!(cache.get_or_list(source, &src, list(&["a", "b"])).unwrap().len(), 1);
+        assert_eq!(cache.get_or_list(Path::new("/other"), &src, list(&["a", "b"])).unwrap().len(), 2);
+        
+        cache.invalidate(&src);
+        assert_eq!(cache.get_or_list(source, &src, list(&["a", "b"])).unwrap().len(), 2);
+        
+        // A change reported while listing keeps the listing from being kept
+        cache.invalidate(&src);
+        let racing = cache.get_or_list(source, &src, || {
+            cache.invalidate_all();
+            Ok::<_, Infallible>(listing(&["c"]))
+        });
+        assert_eq!(racing.unwrap().len(), 1);
+        assert_eq!(cache.get_or_list(source, &src, list(&[])).unwrap().len(), 0);
+        
+        // Failed listings are not kept
+        assert!(cache.get_or_list(source, &ShadowPath::from("/gone"), || Err("gone")).is_err());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.listings), (1, 6, 1));
+    }
+    
+    #[test]
+    fn test_listings_expire() {
+        let cache = ListingCache::new(ListingCacheConfig { max_age: Some(Duration::ZERO), ..ListingCacheConfig::default() });
+        let root = ShadowPath::from("/");
+        for _ in 0..2 {
+            cache.get_or_list(Path::new("/source"), &root, || Ok::<_, Infallible>(listing(&["a"]))).unwrap();
+        }
+        assert_eq!(cache.stats().misses, 2);
+    }
+}
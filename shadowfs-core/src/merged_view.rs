@@ -0,0 +1,235 @@
+//! Directory listings merging a source directory with its overrides.
+//!
+//! [`OverrideStore::list_directory`] only knows the overrides. What a mount
+//! shows of a directory is its source entries, less the ones a tombstone
+//! deletes, plus the overrides created in it, and every provider needs that
+//! same merge. A [`MergedDirectoryView`] computes it once: all of it with
+//! [`entries`](MergedDirectoryView::entries), or a page at a time with
+//! [`page`](MergedDirectoryView::page), resuming after the last name seen so
+//! a listing can be continued across calls.
+//!
+//! Entries are sorted by name. An override reports its own metadata; a
+//! source entry reports its source metadata, with the length of its
+//! delta override if it has one. Listing does not count as accessing the
+//! entries, so rules are not applied to them.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use shadowfs_core::merged_view::MergedDirectoryView;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::types::ShadowPath;
+//!
+//! let source = tempfile::tempdir().unwrap();
+//! std::fs::write(source.path().join("a.txt"), "a").unwrap();
+//! std::fs::write(source.path().join("b.txt"), "b").unwrap();
+//! let store = OverrideStore::with_defaults();
+//! store.insert_file(ShadowPath::from("/c.txt"), Bytes::from("c"), None).unwrap();
+//! store.mark_deleted(ShadowPath::from("/a.txt")).unwrap();
+//!
+//! let view = MergedDirectoryView::new(&store, source.path(), ShadowPath::from("/"));
+//! let names: Vec<String> = view.entries().unwrap().into_iter().map(|entry| entry.name).collect();
+//! assert_eq!(names, ["b.txt", "c.txt"]);
+//!
+//! let page = view.page(Some("b.txt"), 10).unwrap();
+//! assert_eq!(page.entries.len(), 1);
+//! assert_eq!(page.next, None);
+//! ```
+
+use crate::error::ShadowError;
+use crate::override_store::{source_metadata, source_path, OverrideEntry, OverrideStore};
+use crate::types::{DirectoryEntry, FileMetadata, ShadowPath};
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+/// One child of a merged directory.
+#[derive(Debug)]
+pub enum MergedChild {
+    /// The child is an override, not a tombstone
+    Override(Arc<OverrideEntry>),
+    
+    /// The child exists only in the source, with its source metadata
+    Source(std::fs::Metadata),
+}
+
+/// A part of a merged listing.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryPage {
+    /// Entries of the page, sorted by name
+    pub entries: Vec<DirectoryEntry>,
+    
+    /// Name to pass as `after` to get the next page; `None` on the last page
+    pub next: Option<String>,
+}
+
+/// The listing of one directory of a mount, merging the source directory
+/// with the overrides and tombstones of `store`.
+pub struct MergedDirectoryView<'a> {
+    store: &'a OverrideStore,
+    source_root: &'a Path,
+    directory: ShadowPath,
+}
+
+impl<'a> MergedDirectoryView<'a> {
+    /// Creates the view of `directory` of a mount of `source_root`.
+    pub fn new(store: &'a OverrideStore, source_root: &'a Path, directory: ShadowPath) -> Self {
+        Self { store, source_root, directory }
+    }
+    
+    /// Returns the directory the view lists.
+    pub fn directory(&self) -> &ShadowPath {
+        &self.directory
+    }
+    
+    /// Returns the names of the source entries and the override children of
+    /// the directory, tombstones included, sorted.
+    ///
+    /// A source directory that does not exist has no entries.
+    pub fn names(&self) -> Result<BTreeSet<String>, ShadowError> {
+        let mut names: BTreeSet<String> = self.store.get_directory_children(&self.directory).into_iter().collect();
+        match std::fs::read_dir(source_path(self.source_root, &self.directory)) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry.map_err(|e| ShadowError::from_io_error_with_operation(e, &self.directory, "list"))?;
+                    names.insert(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(ShadowError::from_io_error_with_operation(e, &self.directory, "list")),
+        }
+        Ok(names)
+    }
+    
+    /// Resolves the child `name` of the directory.
+    ///
+    /// # Returns
+    /// `None` if a tombstone deletes the child or it exists nowhere
+    pub fn child(&self, name: &str) -> Result<Option<MergedChild>, ShadowError> {
+        let path = self.directory.join(name);
+        if let Some(entry) = self.store.get(&path) {
+            return Ok((!entry.is_deleted()).then_some(MergedChild::Override(entry)));
+        }
+        match std::fs::symlink_metadata(source_path(self.source_root, &path)) {
+            Ok(metadata) => Ok(Some(MergedChild::Source(metadata))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(ShadowError::from_io_error_with_operation(e, &path, "list")),
+        }
+    }
+    
+    /// Returns the listed entry of the child `name`, or `None` if the
+    /// listing leaves it out.
+    pub fn entry(&self, name: &str) -> Result<Option<DirectoryEntry>, ShadowError> {
+        let metadata = match self.child(name)? {
+            Some(MergedChild::Override(entry)) => entry.metadata(),
+            Some(MergedChild::Source(metadata)) => self.source_entry_metadata(name, &metadata),
+            None => return Ok(None),
+        };
+        Ok(Some(DirectoryEntry::new(name.to_string(), metadata)))
+    }
+    
+    /// Lists the whole directory, sorted by name.
+    pub fn entries(&self) -> Result<Vec<DirectoryEntry>, ShadowError> {
+        let mut entries = Vec::new();
+        for name in self.names()? {
+            entries.extend(self.entry(&name)?);
+        }
+        Ok(entries)
+    }
+    
+    /// Lists up to `limit` entries whose names sort after `after`, or from
+    /// the start of the directory if `after` is `None`.
+    ///
+    /// Only the entries of the page are stat'ed. Entries created or deleted
+    /// between pages are seen if they sort after the last name returned.
+    pub fn page(&self, after: Option<&str>, limit: usize) -> Result<DirectoryPage, ShadowError> {
+        self.page_of(&self.names()?, after, limit)
+    }
+    
+    /// Lists a page like [`page`](Self::page), taking the names of the
+    /// directory from `names`, as returned by [`names`](Self::names), so a
+    /// caller paging through a directory can read it once.
+    ///
+    /// Names that no longer resolve, such as tombstones, are skipped
+    /// without counting against `limit`.
+    pub fn page_of(&self, names: &BTreeSet<String>, after: Option<&str>, limit: usize) -> Result<DirectoryPage, ShadowError> {
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        let mut remaining = names.range::<str, _>((start, Bound::Unbounded)).peekable();
+        let mut page = DirectoryPage::default();
+        let mut last = None;
+        while page.entries.len() < limit.max(1) {
+            let Some(name) = remaining.next() else {
+                break;
+            };
+            page.entries.extend(self.entry(name)?);
+            last = Some(name);
+        }
+        if remaining.peek().is_some() {
+            page.next = last.cloned();
+        }
+        Ok(page)
+    }
+    
+    /// Converts the source metadata of the child `name`, sized by its delta
+    /// override if it has one.
+    fn source_entry_metadata(&self, name: &str, metadata: &std::fs::Metadata) -> FileMetadata {
+        let len = self.store.delta_info(&self.directory.join(name)).map_or(metadata.len(), |delta| delta.len);
+        source_metadata(metadata, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use tempfile::TempDir;
+    
+    fn names(entries: &[DirectoryEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+    
+    #[test]
+    fn test_overrides_and_tombstones_merge_with_the_source() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        for name in ["lib.rs", "main.rs", "old.rs"] {
+            std::fs::write(source.path().join("src").join(name), "fn main() {}").unwrap();
+        }
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/src/main.rs"), Bytes::from("changed"), None).unwrap();
+        store.insert_file(ShadowPath::from("/src/new.rs"), Bytes::from("new"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/src/old.rs")).unwrap();
+        
+        let view = MergedDirectoryView::new(&store, source.path(), ShadowPath::from("/src"));
+        let entries = view.entries().unwrap();
+        assert_eq!(names(&entries), ["lib.rs", "main.rs", "new.rs"]);
+        assert_eq!(entries[1].metadata.size, 7);
+        assert!(view.child("old.rs").unwrap().is_none());
+        assert!(matches!(view.child("lib.rs").unwrap(), Some(MergedChild::Source(_))));
+        
+        // A directory only the store knows lists its overrides
+        store.insert_file(ShadowPath::from("/docs/readme.md"), Bytes::from("docs"), None).unwrap();
+        let docs = MergedDirectoryView::new(&store, source.path(), ShadowPath::from("/docs"));
+        assert_eq!(names(&docs.entries().unwrap()), ["readme.md"]);
+    }
+    
+    #[test]
+    fn test_pages_resume_after_the_last_name() {
+        let source = TempDir::new().unwrap();
+        for i in 0..5 {
+            std::fs::write(source.path().join(format!("file{}", i)), "x").unwrap();
+        }
+        let store = OverrideStore::with_defaults();
+        store.mark_deleted(ShadowPath::from("/file1")).unwrap();
+        let view = MergedDirectoryView::new(&store, source.path(), ShadowPath::from("/"));
+        
+        let first = view.page(None, 2).unwrap();
+        assert_eq!(names(&first.entries), ["file0", "file2"]);
+        assert_eq!(first.next.as_deref(), Some("file2"));
+        
+        let second = view.page(first.next.as_deref(), 2).unwrap();
+        assert_eq!(names(&second.entries), ["file3", "file4"]);
+        assert_eq!(second.next, None);
+        assert!(view.page(Some("file4"), 2).unwrap().entries.is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+//! Prometheus metrics for the statistics of mounts.
+//!
+//! A [`StatsRegistry`] holds the override store of every mount it is told
+//! about, with the operation statistics of its provider if it has any, and
+//! reads them only when asked, so registering a mount costs nothing on the
+//! paths that serve it. [`StatsRegistry::render`] writes the statistics in
+//! the Prometheus text format, [`StatsRegistry::serve`] answers scrapes of
+//! `/metrics` with it over HTTP, and [`StatsRegistry::record`] hands the
+//! same values to the recorder installed for the [`metrics`](::metrics)
+//! crate, for applications that already export through a recorder of their
+//! own. Every series is labelled with the mount it belongs to.
+//!
+//! ```rust
+//! use shadowfs_core::metrics::StatsRegistry;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use std::sync::Arc;
+//!
+//! let registry = StatsRegistry::new();
+//! registry.register("/mnt/work", Arc::new(OverrideStore::with_defaults()), None);
+//! assert!(registry.render().contains("shadowfs_store_entries{mount=\"/mnt/work\",kind=\"file\"} 0"));
+//! ```
+
+use crate::override_store::OverrideStore;
+use crate::stats::{FileSystemStats, OperationType};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// Content type of the output of [`StatsRegistry::render`].
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Most bytes of a request [`StatsRegistry::serve`] reads before answering.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Latency percentiles handed to a `metrics` recorder.
+const RECORDED_QUANTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+
+/// How a metric behaves over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// One series of a metric.
+struct Sample {
+    /// Appended to the metric name, such as `_bucket` for histograms
+    suffix: &'static str,
+    labels: Vec<(&'static str, String)>,
+    value: f64,
+}
+
+/// A metric and its series across all mounts.
+struct Family {
+    name: &'static str,
+    kind: MetricKind,
+    help: &'static str,
+    samples: Vec<Sample>,
+}
+
+/// Metrics in the order they are first added.
+#[derive(Default)]
+struct Families(Vec<Family>);
+
+impl Families {
+    fn add(&mut self, name: &'static str, kind: MetricKind, help: &'static str, sample: Sample) {
+        match self.0.iter_mut().find(|family| family.name == name) {
+            Some(family) => family.samples.push(sample),
+            None => self.0.push(Family { name, kind, help, samples: vec![sample] }),
+        }
+    }
+    
+    fn counter(&mut self, name: &'static str, help: &'static str, labels: Vec<(&'static str, String)>, value: u64) {
+        self.add(name, MetricKind::Counter, help, Sample { suffix: "", labels, value: value as f64 });
+    }
+    
+    fn gauge(&mut self, name: &'static str, help: &'static str, labels: Vec<(&'static str, String)>, value: f64) {
+        self.add(name, MetricKind::Gauge, help, Sample { suffix: "", labels, value });
+    }
+}
+
+/// What the registry reads for one mount.
+struct RegisteredMount {
+    store: Arc<OverrideStore>,
+    operations: Option<Arc<FileSystemStats>>,
+}
+
+/// The mounts whose statistics are exported, by name.
+#[derive(Default)]
+pub struct StatsRegistry {
+    mounts: RwLock<BTreeMap<String, RegisteredMount>>,
+}
+
+impl StatsRegistry {
+    /// Creates a registry without mounts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Exports the statistics of `store` and, if given, the operations of
+    /// its provider under `mount`, replacing any mount of that name.
+    pub fn register(&self, mount: impl Into<String>, store: Arc<OverrideStore>, operations: Option<Arc<FileSystemStats>>) {
+        self.mounts.write().unwrap().insert(mount.into(), RegisteredMount { store, operations });
+    }
+    
+    /// Stops exporting `mount`, returning whether it was registered.
+    pub fn unregister(&self, mount: &str) -> bool {
+        self.mounts.write().unwrap().remove(mount).is_some()
+    }
+    
+    /// Returns the names of the registered mounts.
+    pub fn mounts(&self) -> Vec<String> {
+        self.mounts.read().unwrap().keys().cloned().collect()
+    }
+    
+    /// Writes the statistics of every mount in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for family in self.families().0 {
+            let kind = match family.kind {
+                MetricKind::Counter => "counter",
+                MetricKind::Gauge => "gauge",
+                MetricKind::Histogram => "histogram",
+            };
+            let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", family.name, kind);
+            for sample in family.samples {
+                let labels: Vec<String> = sample.labels.iter()
+                    .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                    .collect();
+                let _ = writeln!(out, "{}{}{{{}}} {}", family.name, sample.suffix, labels.join(","), sample.value);
+            }
+        }
+        out
+    }
+    
+    /// Hands the statistics of every mount to the recorder installed for
+    /// the `metrics` crate, under the names [`render`](Self::render) uses.
+    ///
+    /// Operation latencies are already aggregated, so they are recorded as
+    /// gauges of their 50th, 90th, 99th and 99.9th percentiles, labelled by
+    /// `quantile`, rather than as histograms. Call this as often as the
+    /// recorder is scraped.
+    pub fn record(&self) {
+        for family in self.families().0 {
+            if family.kind == MetricKind::Histogram {
+                continue;
+            }
+            for sample in family.samples {
+                let labels: Vec<::metrics::Label> = sample.labels.into_iter()
+                    .map(|(key, value)| ::metrics::Label::new(key, value))
+                    .collect();
+                match family.kind {
+                    MetricKind::Counter => ::metrics::counter!(family.name, labels).absolute(sample.value as u64),
+                    _ => ::metrics::gauge!(family.name, labels).set(sample.value),
+                }
+            }
+        }
+        
+        for (mount, registered) in self.mounts.read().unwrap().iter() {
+            let Some(operations) = &registered.operations else { continue };
+            for op_type in OperationType::ALL {
+                for quantile in RECORDED_QUANTILES {
+                    let labels = vec![
+                        ::metrics::Label::new("mount", mount.clone()),
+                        ::metrics::Label::new("operation", op_type.name()),
+                        ::metrics::Label::new("quantile", quantile.to_string()),
+                    ];
+                    let latency = operations.latency(op_type).percentile(quantile);
+                    ::metrics::gauge!("shadowfs_operation_duration_seconds", labels).set(latency.as_secs_f64());
+                }
+            }
+        }
+    }
+    
+    /// Answers `GET /metrics` on `addr` with [`render`](Self::render)
+    /// until the future is dropped or the address cannot be bound.
+    pub async fn serve(self: Arc<Self>, addr: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve_listener(listener).await
+    }
+    
+    /// Answers `GET /metrics` on connections accepted by `listener`, such
+    /// as one bound to port 0 to let the system pick the port.
+    pub async fn serve_listener(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let registry = Arc::clone(&self);
+            crate::task::spawn("metrics-scrape", async move {
+                // A client that goes away mid-scrape only loses its answer
+                let _ = registry.answer(stream).await;
+            });
+        }
+    }
+    
+    /// Reads one request from `stream` and answers it.
+    async fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+        
+        let request = String::from_utf8_lossy(&request);
+        let mut words = request.lines().next().unwrap_or_default().split_whitespace();
+        let (status, body) = match (words.next(), words.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render()),
+            (Some("GET"), _) => ("404 Not Found", "Metrics are served at /metrics\n".to_string()),
+            _ => ("405 Method Not Allowed", "Only GET is supported\n".to_string()),
+        };
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            CONTENT_TYPE,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await
+    }
+    
+    /// Reads the statistics of every mount.
+    fn families(&self) -> Families {
+        let mut families = Families::default();
+        for (mount, registered) in self.mounts.read().unwrap().iter() {
+            collect_store(&mut families, mount, &registered.store);
+            if let Some(operations) = &registered.operations {
+                collect_operations(&mut families, mount, operations);
+            }
+        }
+        families
+    }
+}
+
+/// Adds the statistics of the override store of `mount`.
+fn collect_store(families: &mut Families, mount: &str, store: &OverrideStore) {
+    let labels = || vec![("mount", mount.to_string())];
+    let with = |key: &'static str, value: &str| vec![("mount", mount.to_string()), (key, value.to_string())];
+    let snapshot = store.get_stats_snapshot();
+    let (used, limit, _) = store.memory_stats();
+    
+    let entries = "Overrides held by the store, by kind";
+    families.gauge("shadowfs_store_entries", entries, with("kind", "file"), snapshot.file_entries as f64);
+    families.gauge("shadowfs_store_entries", entries, with("kind", "directory"), snapshot.directory_entries as f64);
+    families.gauge("shadowfs_store_entries", entries, with("kind", "deleted"), snapshot.deleted_entries as f64);
+    families.gauge("shadowfs_store_memory_bytes", "Memory used by override content and metadata", labels(), used as f64);
+    families.gauge("shadowfs_store_memory_limit_bytes", "Memory the overrides may use", labels(), limit as f64);
+    families.gauge(
+        "shadowfs_store_compression_saved_bytes",
+        "Memory saved by compressing overrides",
+        labels(),
+        snapshot.compressed_bytes_saved as f64,
+    );
+    families.gauge(
+        "shadowfs_store_dedup_saved_bytes",
+        "Memory saved by deduplicating identical overrides",
+        labels(),
+        snapshot.dedup_bytes_saved as f64,
+    );
+    
+    let lookups = snapshot.cache_hits + snapshot.cache_misses;
+    families.counter("shadowfs_cache_hits_total", "Override lookups served from the hot cache", labels(), snapshot.cache_hits);
+    families.counter("shadowfs_cache_misses_total", "Override lookups that missed the hot cache", labels(), snapshot.cache_misses);
+    families.gauge(
+        "shadowfs_cache_hit_ratio",
+        "Fraction of override lookups served from the hot cache",
+        labels(),
+        if lookups == 0 { 0.0 } else { snapshot.cache_hits as f64 / lookups as f64 },
+    );
+    families.counter("shadowfs_evictions_total", "Overrides evicted from the store", labels(), snapshot.eviction_count);
+    
+    if let Some(cache) = store.source_cache() {
+        let stats = cache.stats();
+        families.counter("shadowfs_source_cache_hits_total", "Source reads served by a file already open", labels(), stats.hits);
+        families.counter("shadowfs_source_cache_misses_total", "Source reads that had to open their file", labels(), stats.misses);
+        families.gauge("shadowfs_source_cache_open_files", "Source files held open", labels(), stats.open_files as f64);
+    }
+}
+
+/// Adds the operations served by the provider of `mount`.
+fn collect_operations(families: &mut Families, mount: &str, operations: &FileSystemStats) {
+    let labels = || vec![("mount", mount.to_string())];
+    families.counter("shadowfs_read_bytes_total", "Bytes returned by reads", labels(), operations.bytes_read.load(Ordering::Relaxed));
+    families.counter(
+        "shadowfs_written_bytes_total",
+        "Bytes accepted by writes",
+        labels(),
+        operations.bytes_written.load(Ordering::Relaxed),
+    );
+    
+    for op_type in OperationType::ALL {
+        let operation = || vec![("mount", mount.to_string()), ("operation", op_type.name().to_string())];
+        families.counter("shadowfs_operations_total", "Operations served, by type", operation(), operations.get_operation_count(op_type));
+        
+        let histogram = operations.latency(op_type);
+        let help = "Latency of operations, by type";
+        let mut cumulative = 0;
+        let buckets = histogram.buckets();
+        for (index, (bound, count)) in buckets.iter().enumerate() {
+            cumulative += count;
+            let le = if index + 1 == buckets.len() { "+Inf".to_string() } else { bound.as_secs_f64().to_string() };
+            let mut labels = operation();
+            labels.push(("le", le));
+            let sample = Sample { suffix: "_bucket", labels, value: cumulative as f64 };
+            families.add("shadowfs_operation_duration_seconds", MetricKind::Histogram, help, sample);
+        }
+        let sum = Sample { suffix: "_sum", labels: operation(), value: histogram.sum().as_secs_f64() };
+        families.add("shadowfs_operation_duration_seconds", MetricKind::Histogram, help, sum);
+        let count = Sample { suffix: "_count", labels: operation(), value: cumulative as f64 };
+        families.add("shadowfs_operation_duration_seconds", MetricKind::Histogram, help, count);
+    }
+}
+
+/// Escapes a label value for the text format.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::types::ShadowPath;
+    use std::time::Duration;
+    
+    #[test]
+    fn test_render_labels_every_mount() {
+        let registry = StatsRegistry::new();
+        let store = Arc::new(OverrideStore::with_defaults());
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("a"), None).unwrap();
+        store.get(&ShadowPath::from("/a.txt"));
+        let operations = Arc::new(FileSystemStats::new());
+        operations.increment_operation(OperationType::Read);
+        operations.record_latency(OperationType::Read, Duration::from_micros(100));
+        registry.register("/mnt/a", Arc::clone(&store), Some(operations));
+        registry.register("/mnt/\"b\"", Arc::new(OverrideStore::with_defaults()), None);
+        
+        let text = registry.render();
+        assert_eq!(text.matches("# TYPE shadowfs_store_entries gauge").count(), 1);
+        assert!(text.contains("shadowfs_store_entries{mount=\"/mnt/a\",kind=\"file\"} 1\n"));
+        assert!(text.contains("shadowfs_store_entries{mount=\"/mnt/\\\"b\\\"\",kind=\"file\"} 0\n"));
+        assert!(text.contains("shadowfs_operations_total{mount=\"/mnt/a\",operation=\"read\"} 1\n"));
+        assert!(text.contains("shadowfs_operation_duration_seconds_bucket{mount=\"/mnt/a\",operation=\"read\",le=\"0.000128\"} 1\n"));
+        assert!(text.contains("shadowfs_operation_duration_seconds_bucket{mount=\"/mnt/a\",operation=\"read\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("shadowfs_operation_duration_seconds_sum{mount=\"/mnt/a\",operation=\"read\"} 0.0001\n"));
+        assert!(!text.contains("operation_duration_seconds_count{mount=\"/mnt/\\\"b\\\"\""));
+        
+        assert!(registry.unregister("/mnt/a"));
+        assert_eq!(registry.mounts(), vec!["/mnt/\"b\"".to_string()]);
+    }
+    
+    #[tokio::test]
+    async fn test_serve_answers_scrapes() {
+        let registry = Arc::new(StatsRegistry::new());
+        registry.register("/mnt/a", Arc::new(OverrideStore::with_defaults()), None);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(Arc::clone(&registry).serve_listener(listener));
+        
+        let scrape = |request: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let response = scrape("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("shadowfs_evictions_total{mount=\"/mnt/a\"} 0\n"));
+        assert!(scrape("GET / HTTP/1.1\r\n\r\n").await.starts_with("HTTP/1.1 404"));
+        assert!(scrape("POST /metrics HTTP/1.1\r\n\r\n").await.starts_with("HTTP/1.1 405"));
+        server.abort();
+    }
+}
@@ -0,0 +1,895 @@
+//! Management of multiple concurrent mounts backed by a shared override store.
+//!
+//! The [`MountManager`] owns a single [`OverrideStore`] and keeps track of which
+//! [`FileSystemProvider`] is serving each mount point. Platform crates only need
+//! to supply a provider factory; the bookkeeping lives here so it is shared by
+//! the CLI, the FFI layer and embedders.
+
+use crate::clock;
+use crate::error::{mount_limit_reached, Result, ShadowError};
+use crate::override_store::OverrideStore;
+use crate::self_test;
+use crate::stats::MountResources;
+use crate::supervision::{FailurePolicy, Supervisor};
+use crate::task;
+use crate::telemetry::mount_span;
+use crate::traits::FileSystemProvider;
+use crate::types::{MountOptions, Platform, ShadowPath};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{broadcast, Notify, RwLock};
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+/// Longest [`MountManager::wait_for_expiry`] sleeps between checks, so it
+/// notices mounts added while it waits.
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Expiry events buffered for each subscriber.
+const EXPIRY_EVENT_CAPACITY: usize = 16;
+
+/// Factory used to create a provider for a new mount.
+///
+/// The factory receives the manager's shared override store so every provider
+/// reads and writes the same overrides.
+pub type ProviderFactory =
+    Arc<dyn Fn(Arc<OverrideStore>) -> Arc<dyn FileSystemProvider> + Send + Sync>;
+
+/// Information about an active mount.
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    /// Unique identifier for this mount
+    pub id: Uuid,
+    
+    /// Source directory being shadowed
+    pub source: PathBuf,
+    
+    /// Mount point where the shadow filesystem is exposed
+    pub mount_point: PathBuf,
+    
+    /// Platform of the provider serving this mount
+    pub platform: Platform,
+    
+    /// Options the mount was created with
+    pub options: MountOptions,
+    
+    /// Time when the mount was created
+    pub mounted_at: SystemTime,
+}
+
+/// Limits on how many mounts run at once and how much override memory they
+/// may reserve together, so a busy machine rejects new mounts instead of
+/// running out of memory.
+///
+/// Each mount reserves the override memory it is configured for,
+/// [`OverrideConfig::max_memory_bytes`](crate::types::OverrideConfig::max_memory_bytes).
+/// The budget bounds the sum of the reservations, so the stores cannot
+/// outgrow it together however full they get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MountLimits {
+    /// Most mounts active at once
+    pub max_mounts: Option<usize>,
+    
+    /// Most override memory, in bytes, reserved by all mounts together
+    pub memory_budget: Option<u64>,
+    
+    /// How long a mount over the limits waits for other mounts to go away;
+    /// without it, the mount fails at once
+    pub queue_timeout: Option<Duration>,
+}
+
+impl MountLimits {
+    /// Limits that admit every mount.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+    
+    /// Checks whether a mount reserving `reserved` bytes may join the
+    /// active mounts, which reserve `active` bytes each.
+    ///
+    /// # Errors
+    /// [`ShadowError::MountLimitReached`] naming the limit in the way
+    pub fn admit(&self, mount_point: &Path, active: &[u64], reserved: u64) -> Result<()> {
+        let rejected = |reason: String| mount_limit_reached(ShadowPath::new(mount_point.to_path_buf()), reason);
+        if let Some(max_mounts) = self.max_mounts {
+            if active.len() >= max_mounts {
+                return Err(rejected(format!("{} of {} allowed mounts are active", active.len(), max_mounts)));
+            }
+        }
+        if let Some(budget) = self.memory_budget {
+            let in_use: u64 = active.iter().sum();
+            if in_use.saturating_add(reserved) > budget {
+                return Err(rejected(format!(
+                    "it reserves {} bytes of override memory, but only {} of the {} byte budget are free",
+                    reserved,
+                    budget.saturating_sub(in_use),
+                    budget
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a mount expired, following its [`MountExpiry`](crate::types::MountExpiry).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExpiryReason {
+    /// The mount outlived its TTL
+    Ttl,
+    /// Nothing used the mount for its idle timeout
+    Idle,
+}
+
+impl fmt::Display for ExpiryReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpiryReason::Ttl => f.write_str("its TTL elapsed"),
+            ExpiryReason::Idle => f.write_str("it was idle"),
+        }
+    }
+}
+
+/// Notification sent to [`MountManager::subscribe_expiry`] subscribers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpiryEvent {
+    /// The mount expires after `remaining`; for [`ExpiryReason::Idle`],
+    /// unless it is used before then
+    Warning {
+        mount_point: PathBuf,
+        reason: ExpiryReason,
+        remaining: Duration,
+    },
+    /// The mount has expired and should be unmounted
+    Expired {
+        mount_point: PathBuf,
+        reason: ExpiryReason,
+    },
+}
+
+/// A point at which a mount expires.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ExpiryDeadline {
+    mount_point: PathBuf,
+    reason: ExpiryReason,
+    /// [`clock`] reading at which the mount expires
+    at: u64,
+}
+
+/// An active mount together with the provider serving it.
+struct ActiveMount {
+    info: MountInfo,
+    provider: Arc<dyn FileSystemProvider>,
+    /// Span the mount was created in, which its unmount joins
+    span: Span,
+    /// [`clock`] reading when the mount was created
+    mounted: u64,
+}
+
+/// Manages multiple concurrent mounts that share one override store.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use shadowfs_core::mount_manager::MountManager;
+/// use shadowfs_core::override_store::OverrideStore;
+/// use shadowfs_core::types::MountOptions;
+/// use std::sync::Arc;
+///
+/// let manager = MountManager::new(
+///     Arc::new(OverrideStore::with_defaults()),
+///     Arc::new(|store| Arc::new(PlatformProvider::new(store))),
+/// );
+///
+/// manager.mount("/src/project", "/mnt/shadow", MountOptions::default()).await?;
+/// for mount in manager.list_mounts().await {
+///     println!("{} -> {}", mount.source.display(), mount.mount_point.display());
+/// }
+/// manager.unmount("/mnt/shadow").await?;
+/// ```
+pub struct MountManager {
+    /// Override store shared by all mounts
+    store: Arc<OverrideStore>,
+    
+    /// Factory creating a provider for each new mount
+    factory: ProviderFactory,
+    
+    /// Applies the failure policies of the mounts
+    supervisor: Arc<Supervisor>,
+    
+    /// Active mounts keyed by mount point
+    mounts: RwLock<HashMap<PathBuf, ActiveMount>>,
+    
+    /// Warnings and expiries of mounts with a [`MountExpiry`](crate::types::MountExpiry)
+    expiry_events: broadcast::Sender<ExpiryEvent>,
+    
+    /// Limits new mounts are admitted under
+    limits: std::sync::RwLock<MountLimits>,
+    
+    /// Wakes mounts queued by the limits when a mount goes away
+    slot_freed: Notify,
+}
+
+impl MountManager {
+    /// Creates a new mount manager.
+    ///
+    /// # Arguments
+    /// * `store` - Override store shared by all mounts
+    /// * `factory` - Factory creating a provider for each mount
+    pub fn new(store: Arc<OverrideStore>, factory: ProviderFactory) -> Self {
+        let supervisor = Arc::new(Supervisor::default());
+        store.set_supervisor(Arc::clone(&supervisor));
+        Self {
+            store,
+            factory,
+            supervisor,
+            mounts: RwLock::new(HashMap::new()),
+            expiry_events: broadcast::channel(EXPIRY_EVENT_CAPACITY).0,
+            limits: std::sync::RwLock::new(MountLimits::unlimited()),
+            slot_freed: Notify::new(),
+        }
+    }
+    
+    /// Sets the limits new mounts are admitted under; active mounts are kept.
+    pub fn set_limits(&self, limits: MountLimits) {
+        *self.limits.write().unwrap() = limits;
+        self.slot_freed.notify_waiters();
+    }
+    
+    /// Returns the limits new mounts are admitted under.
+    pub fn limits(&self) -> MountLimits {
+        *self.limits.read().unwrap()
+    }
+    
+    /// Returns the override store shared by all mounts.
+    pub fn store(&self) -> Arc<OverrideStore> {
+        Arc::clone(&self.store)
+    }
+    
+    /// Returns the supervisor applying the failure policies of the mounts.
+    ///
+    /// Subsystems report failures to it; owners of the manager wait on
+    /// [`Supervisor::wait_for_fail_fast`] and unmount when it returns.
+    pub fn supervisor(&self) -> Arc<Supervisor> {
+        Arc::clone(&self.supervisor)
+    }
+    
+    /// Mounts `source` at `mount_point`.
+    ///
+    /// # Arguments
+    /// * `source` - Source directory to shadow
+    /// * `mount_point` - Where the shadow filesystem will be exposed
+    /// * `options` - Mount options for the new mount
+    ///
+    /// A read-only mount makes the shared store read-only until it is unmounted.
+    /// Other mounts probe whether their source accepts writes, unless it is
+    /// immutable, so the store's health check reports commits as unavailable
+    /// early.
+    /// A mount over the [`MountLimits`] waits for their queue timeout, if
+    /// any, for other mounts to go away.
+    ///
+    /// # Returns
+    /// Information about the new mount, `AlreadyExists` if the mount point is
+    /// in use, or `MountLimitReached` if the limits do not admit it
+    pub async fn mount(
+        &self,
+        source: impl AsRef<Path>,
+        mount_point: impl AsRef<Path>,
+        options: MountOptions,
+    ) -> Result<MountInfo> {
+        let source = source.as_ref().to_path_buf();
+        let mount_point = mount_point.as_ref().to_path_buf();
+        let reserved = options.override_config.max_memory_bytes as u64;
+        let queue_deadline = self.limits().queue_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        
+        // Hold the write lock across the provider call so two callers cannot
+        // race to mount the same point.
+        let mut mounts = loop {
+            let freed = self.slot_freed.notified();
+            tokio::pin!(freed);
+            // Register before checking so a mount going away in between is not missed
+            freed.as_mut().enable();
+            
+            let mounts = self.mounts.write().await;
+            if mounts.contains_key(&mount_point) {
+                return Err(ShadowError::AlreadyExists {
+                    path: ShadowPath::new(mount_point),
+                });
+            }
+            let active: Vec<u64> = mounts.values()
+                .map(|m| m.info.options.override_config.max_memory_bytes as u64)
+                .collect();
+            let rejection = match self.limits().admit(&mount_point, &active, reserved) {
+                Ok(()) => break mounts,
+                Err(e) => e,
+            };
+            drop(mounts);
+            match queue_deadline {
+                Some(deadline) if tokio::time::timeout_at(deadline, freed).await.is_ok() => {}
+                _ => return Err(rejection),
+            }
+        };
+        
+        if options.read_only {
+            self.store.set_read_only(true);
+        } else if !options.immutable_source {
+            // A source refusing writes only matters once the overrides are
+            // committed, so the mount goes ahead and the health check says so
+            let _ = self.store.check_source_writable(&source);
+        }
+        if !options.case_sensitive {
+            self.store.set_case_sensitive(false);
+        }
+        if !options.limits.is_unlimited() {
+            self.store.set_quota(options.limits);
+        }
+        if !options.path_limits.is_unlimited() {
+            self.store.set_path_limits(options.path_limits);
+        }
+        let atime = mounts.values().map(|m| m.info.options.atime).fold(options.atime, Ord::max);
+        self.store.set_atime_policy(atime);
+        self.supervisor.set_policy(self.supervisor.policy().strictest(options.failure_policy));
+        
+        // Providers keep the span current while mounting as the parent of
+        // their callbacks' spans
+        let id = Uuid::new_v4();
+        let span = mount_span(id, &source, &mount_point);
+        let provider = (self.factory)(Arc::clone(&self.store));
+        if let Err(e) = provider.mount(&source, &mount_point, &options).instrument(span.clone()).await {
+            self.sync_shared_state(&mounts);
+            return Err(e);
+        }
+        if options.self_test {
+            if let Err(report) = self.self_test(&mount_point, options.read_only).await {
+                // Leave nothing half-working behind; the report says what failed
+                if let Err(e) = provider.unmount(&mount_point).instrument(span.clone()).await {
+                    tracing::warn!("Failed to unmount {} after its self-test failed: {}", mount_point.display(), e);
+                }
+                self.sync_shared_state(&mounts);
+                return Err(ShadowError::PlatformError {
+                    platform: provider.platform().into(),
+                    message: report,
+                    code: None,
+                });
+            }
+        }
+        
+        let info = MountInfo {
+            id,
+            source,
+            mount_point: mount_point.clone(),
+            platform: provider.platform(),
+            options,
+            mounted_at: SystemTime::now(),
+        };
+        
+        mounts.insert(mount_point, ActiveMount {
+            info: info.clone(),
+            provider,
+            span,
+            mounted: clock::now(),
+        });
+        
+        Ok(info)
+    }
+    
+    /// Runs the [`self_test`] through the new mount at `mount_point`.
+    ///
+    /// # Returns
+    /// The report of a failed test
+    async fn self_test(&self, mount_point: &Path, read_only: bool) -> std::result::Result<(), String> {
+        let store = Arc::clone(&self.store);
+        let probe_point = mount_point.to_path_buf();
+        let report = task::spawn_blocking("self-test", move || self_test::run(&probe_point, &store, read_only))
+            .await
+            .map_err(|e| format!("self-test of {} did not finish: {}", mount_point.display(), e))?;
+        if !report.is_ok() {
+            return Err(report.to_string());
+        }
+        tracing::debug!("{}", report);
+        Ok(())
+    }
+    
+    /// Unmounts the filesystem at `mount_point`.
+    ///
+    /// # Returns
+    /// Information about the removed mount, or `NotMounted` if nothing is mounted there
+    pub async fn unmount(&self, mount_point: impl AsRef<Path>) -> Result<MountInfo> {
+        let mount_point = mount_point.as_ref();
+        let mut mounts = self.mounts.write().await;
+        
+        let active = mounts.get(mount_point).ok_or_else(|| ShadowError::NotMounted {
+            mount_point: ShadowPath::new(mount_point.to_path_buf()),
+        })?;
+        
+        // Only forget the mount once the provider has actually released it
+        active.provider.unmount(mount_point).instrument(active.span.clone()).await?;
+        
+        let active = mounts.remove(mount_point).expect("mount present under write lock");
+        self.sync_shared_state(&mounts);
+        self.slot_freed.notify_waiters();
+        Ok(active.info)
+    }
+    
+    /// Unmounts every active mount.
+    ///
+    /// # Returns
+    /// Mount points that failed to unmount, paired with the error
+    pub async fn unmount_all(&self) -> Vec<(PathBuf, ShadowError)> {
+        let mut mounts = self.mounts.write().await;
+        let mut failures = Vec::new();
+        
+        let mount_points: Vec<PathBuf> = mounts.keys().cloned().collect();
+        for mount_point in mount_points {
+            let provider = Arc::clone(&mounts[&mount_point].provider);
+            let span = mounts[&mount_point].span.clone();
+            match provider.unmount(&mount_point).instrument(span).await {
+                Ok(()) => {
+                    mounts.remove(&mount_point);
+                }
+                Err(e) => failures.push((mount_point, e)),
+            }
+        }
+        
+        self.sync_shared_state(&mounts);
+        self.slot_freed.notify_waiters();
+        failures
+    }
+    
+    /// Recomputes the settings the remaining mounts share.
+    ///
+    /// All mounts see the same overrides, so writes through a read-write mount
+    /// would show up in a read-only one; the read-only mount wins. Likewise a
+    /// subsystem fails fast while any remaining mount asks for it, paths
+    /// resolve case-insensitively while any remaining mount is, and access
+    /// times are kept as often as the strictest remaining mount keeps them.
+    fn sync_shared_state(&self, mounts: &HashMap<PathBuf, ActiveMount>) {
+        self.store.set_read_only(mounts.values().any(|m| m.info.options.read_only));
+        self.store.set_case_sensitive(mounts.values().all(|m| m.info.options.case_sensitive));
+        if let Some(atime) = mounts.values().map(|m| m.info.options.atime).max() {
+            self.store.set_atime_policy(atime);
+        }
+        let policy = mounts.values()
+            .map(|m| m.info.options.failure_policy)
+            .fold(FailurePolicy::default(), FailurePolicy::strictest);
+        self.supervisor.set_policy(policy);
+    }
+    
+    /// Lists all active mounts.
+    pub async fn list_mounts(&self) -> Vec<MountInfo> {
+        let mounts = self.mounts.read().await;
+        let mut infos: Vec<MountInfo> = mounts.values().map(|m| m.info.clone()).collect();
+        infos.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+        infos
+    }
+    
+    /// Gets information about the mount at `mount_point`, if any.
+    pub async fn get_mount(&self, mount_point: impl AsRef<Path>) -> Option<MountInfo> {
+        let mounts = self.mounts.read().await;
+        mounts.get(mount_point.as_ref()).map(|m| m.info.clone())
+    }
+    
+    /// Gets the provider serving `mount_point`, if any.
+    pub async fn get_provider(
+        &self,
+        mount_point: impl AsRef<Path>,
+    ) -> Option<Arc<dyn FileSystemProvider>> {
+        let mounts = self.mounts.read().await;
+        mounts.get(mount_point.as_ref()).map(|m| Arc::clone(&m.provider))
+    }
+    
+    /// Returns the number of active mounts.
+    pub async fn mount_count(&self) -> usize {
+        self.mounts.read().await.len()
+    }
+    
+    /// Subscribes to warnings about mounts approaching their expiry, and to
+    /// the expiries themselves, as found by [`MountManager::wait_for_expiry`].
+    pub fn subscribe_expiry(&self) -> broadcast::Receiver<ExpiryEvent> {
+        self.expiry_events.subscribe()
+    }
+    
+    /// Waits until a mount reaches its [`MountExpiry`](crate::types::MountExpiry).
+    ///
+    /// Subscribers are warned once a mount is within the warning period of
+    /// its TTL or idle timeout. Both run on the monotonic [`clock`], so a
+    /// wall clock stepped by NTP or a resume neither expires a mount early
+    /// nor keeps it late. Activity is read from the statistics of the
+    /// shared store, so using any mount keeps all of them from idling out.
+    /// The mount is not unmounted here; owners of the manager unmount it
+    /// when this returns, like after [`Supervisor::wait_for_fail_fast`].
+    /// Without any expiring mounts, this waits for one to be mounted.
+    ///
+    /// # Returns
+    /// The mount point of the expired mount and why it expired
+    pub async fn wait_for_expiry(&self) -> (PathBuf, ExpiryReason) {
+        let mut warned: HashSet<ExpiryDeadline> = HashSet::new();
+        loop {
+            let deadlines = self.expiry_deadlines().await;
+            let now = clock::now();
+            let mut next_check = EXPIRY_CHECK_INTERVAL;
+            
+            // An idle deadline moves with activity, so warnings are per deadline
+            warned.retain(|deadline| deadlines.iter().any(|(current, _)| current == deadline));
+            for (deadline, warning) in deadlines {
+                let remaining = Duration::from_millis(deadline.at.saturating_sub(now));
+                if remaining.is_zero() {
+                    let _ = self.expiry_events.send(ExpiryEvent::Expired {
+                        mount_point: deadline.mount_point.clone(),
+                        reason: deadline.reason,
+                    });
+                    return (deadline.mount_point, deadline.reason);
+                }
+                if remaining > warning {
+                    next_check = next_check.min(remaining - warning);
+                    continue;
+                }
+                next_check = next_check.min(remaining);
+                if warned.insert(deadline.clone()) {
+                    let _ = self.expiry_events.send(ExpiryEvent::Warning {
+                        mount_point: deadline.mount_point,
+                        reason: deadline.reason,
+                        remaining,
+                    });
+                }
+            }
+            tokio::time::sleep(next_check).await;
+        }
+    }
+    
+    /// Returns the current expiry deadlines of the mounts, each with its
+    /// warning period.
+    async fn expiry_deadlines(&self) -> Vec<(ExpiryDeadline, Duration)> {
+        let last_activity = self.store.stats.last_activity_reading();
+        let mounts = self.mounts.read().await;
+        let mut deadlines = Vec::new();
+        for mount in mounts.values() {
+            let expiry = &mount.info.options.expiry;
+            let deadline = |reason, at| (
+                ExpiryDeadline { mount_point: mount.info.mount_point.clone(), reason, at },
+                expiry.warning,
+            );
+            if let Some(ttl) = expiry.ttl {
+                deadlines.push(deadline(ExpiryReason::Ttl, mount.mounted + ttl.as_millis() as u64));
+            }
+            if let Some(idle_timeout) = expiry.idle_timeout {
+                let idle_since = last_activity.max(mount.mounted);
+                deadlines.push(deadline(ExpiryReason::Idle, idle_since + idle_timeout.as_millis() as u64));
+            }
+        }
+        deadlines
+    }
+    
+    /// Samples the resources used by this process and the shared store.
+    ///
+    /// All mounts of a manager are served by the current process, so the
+    /// report covers them together.
+    pub fn resources(&self) -> MountResources {
+        MountResources::collect(&self.store)
+    }
+}
+
+impl std::fmt::Debug for MountManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MountManager")
+            .field("store_entries", &self.store.entry_count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atime::AtimePolicy;
+    use crate::supervision::{FailureMode, Subsystem};
+    use crate::types::{MountExpiry, OverrideConfig};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    
+    /// Provider that records mount calls instead of touching the OS.
+    #[derive(Default)]
+    struct RecordingProvider {
+        mounted: Mutex<Vec<PathBuf>>,
+        fail_unmount: bool,
+    }
+    
+    #[async_trait]
+    impl FileSystemProvider for RecordingProvider {
+        fn platform(&self) -> Platform {
+            Platform::current()
+        }
+        
+        async fn mount(&self, _source: &Path, mount_point: &Path, _options: &MountOptions) -> Result<()> {
+            self.mounted.lock().unwrap().push(mount_point.to_path_buf());
+            Ok(())
+        }
+        
+        async fn unmount(&self, mount_point: &Path) -> Result<()> {
+            if self.fail_unmount {
+                return Err(ShadowError::Unsupported { feature: "unmount".to_string() });
+            }
+            self.mounted.lock().unwrap().retain(|p| p != mount_point);
+            Ok(())
+        }
+    }
+    
+    fn manager(fail_unmount: bool) -> MountManager {
+        MountManager::new(
+            Arc::new(OverrideStore::with_defaults()),
+            Arc::new(move |_store| {
+                Arc::new(RecordingProvider { fail_unmount, ..Default::default() }) as Arc<dyn FileSystemProvider>
+            }),
+        )
+    }
+    
+    #[tokio::test]
+    async fn test_mount_and_unmount() {
+        let manager = manager(false);
+        
+        let info = manager.mount("/src", "/mnt/a", MountOptions::default()).await.unwrap();
+        assert_eq!(info.source, PathBuf::from("/src"));
+        assert_eq!(info.mount_point, PathBuf::from("/mnt/a"));
+        assert_eq!(manager.mount_count().await, 1);
+        assert!(manager.get_mount("/mnt/a").await.is_some());
+        assert!(manager.get_provider("/mnt/a").await.is_some());
+        
+        let removed = manager.unmount("/mnt/a").await.unwrap();
+        assert_eq!(removed.id, info.id);
+        assert_eq!(manager.mount_count().await, 0);
+        assert!(manager.get_mount("/mnt/a").await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_multiple_mounts_share_store() {
+        let manager = manager(false);
+        manager.mount("/src", "/mnt/b", MountOptions::default()).await.unwrap();
+        manager.mount("/src", "/mnt/a", MountOptions::default()).await.unwrap();
+        
+        let mounts = manager.list_mounts().await;
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].mount_point, PathBuf::from("/mnt/a"));
+        assert_eq!(mounts[1].mount_point, PathBuf::from("/mnt/b"));
+        
+        let store = manager.store();
+        store.insert_file(ShadowPath::from("/file.txt"), bytes::Bytes::from("data"), None).unwrap();
+        assert_eq!(manager.store().entry_count(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_duplicate_mount_point_rejected() {
+        let manager = manager(false);
+        manager.mount("/src", "/mnt/a", MountOptions::default()).await.unwrap();
+        
+        let result = manager.mount("/other", "/mnt/a", MountOptions::default()).await;
+        assert!(matches!(result, Err(ShadowError::AlreadyExists { .. })));
+        assert_eq!(manager.mount_count().await, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_unmount_unknown_mount_point() {
+        let manager = manager(false);
+        let result = manager.unmount("/mnt/missing").await;
+        assert!(matches!(result, Err(ShadowError::NotMounted { .. })));
+    }
+    
+    #[tokio::test]
+    async fn test_failed_unmount_keeps_mount() {
+        let manager = manager(true);
+        manager.mount("/src", "/mnt/a", MountOptions::default()).await.unwrap();
+        
+        assert!(manager.unmount("/mnt/a").await.is_err());
+        assert_eq!(manager.mount_count().await, 1);
+        
+        let failures = manager.unmount_all().await;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(manager.mount_count().await, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_failed_self_test_unmounts() {
+        let manager = manager(false);
+        let result = manager.mount("/src", "/nonexistent/mnt", MountOptions::default().self_test(true)).await;
+        assert!(matches!(result, Err(ShadowError::PlatformError { message, .. }) if message.contains("failed at list")));
+        assert_eq!(manager.mount_count().await, 0);
+        assert!(!manager.store().is_read_only());
+        
+        let mount_point = tempfile::tempdir().unwrap();
+        let options = MountOptions::default().read_only().self_test(true);
+        manager.mount("/src", mount_point.path(), options).await.unwrap();
+        assert_eq!(manager.mount_count().await, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_read_only_mount_rejects_writes() {
+        let manager = manager(false);
+        let store = manager.store();
+        store.insert_file(ShadowPath::from("/kept.txt"), bytes::Bytes::from("data"), None).unwrap();
+        
+        manager.mount("/src", "/mnt/rw", MountOptions::default()).await.unwrap();
+        manager.mount("/src", "/mnt/ro", MountOptions::default().read_only()).await.unwrap();
+        assert!(store.is_read_only());
+        
+        let result = store.insert_file(ShadowPath::from("/new.txt"), bytes::Bytes::from("x"), None);
+        assert!(matches!(result, Err(ShadowError::ReadOnlyFilesystem { .. })));
+        let result = store.mark_deleted(ShadowPath::from("/kept.txt"));
+        assert!(matches!(result, Err(ShadowError::ReadOnlyFilesystem { operation, .. }) if operation == "delete"));
+        assert!(store.create_directory_hierarchy(&ShadowPath::from("/a/b")).is_err());
+        assert!(store.delete_directory_recursive(&ShadowPath::from("/")).is_err());
+        assert!(store.get(&ShadowPath::from("/kept.txt")).is_some());
+        assert_eq!(store.entry_count(), 1);
+        
+        manager.unmount("/mnt/ro").await.unwrap();
+        assert!(!store.is_read_only());
+        store.insert_file(ShadowPath::from("/new.txt"), bytes::Bytes::from("x"), None).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_case_insensitive_mount() {
+        let manager = manager(false);
+        let store = manager.store();
+        store.insert_file(ShadowPath::from("/ReadMe.md"), bytes::Bytes::from("data"), None).unwrap();
+        
+        manager.mount("/src", "/mnt/cs", MountOptions::default().case_sensitive(true)).await.unwrap();
+        assert!(store.get(&ShadowPath::from("/readme.md")).is_none());
+        manager.mount("/src", "/mnt/ci", MountOptions::default().case_sensitive(false)).await.unwrap();
+        assert!(!store.is_case_sensitive());
+        assert!(store.get(&ShadowPath::from("/readme.md")).is_some());
+        
+        manager.unmount("/mnt/ci").await.unwrap();
+        assert!(store.is_case_sensitive());
+    }
+    
+    #[tokio::test]
+    async fn test_atime_policy_follows_mounts() {
+        let manager = manager(false);
+        let store = manager.store();
+        
+        manager.mount("/src", "/mnt/quiet", MountOptions::default().atime(AtimePolicy::NoAtime)).await.unwrap();
+        assert_eq!(store.atime_policy(), AtimePolicy::NoAtime);
+        manager.mount("/src", "/mnt/strict", MountOptions::default().atime(AtimePolicy::Strict)).await.unwrap();
+        assert_eq!(store.atime_policy(), AtimePolicy::Strict);
+        
+        manager.unmount("/mnt/strict").await.unwrap();
+        assert_eq!(store.atime_policy(), AtimePolicy::NoAtime);
+    }
+    
+    #[tokio::test]
+    async fn test_failure_policy_follows_mounts() {
+        let manager = manager(false);
+        let supervisor = manager.supervisor();
+        let ci = MountOptions::default()
+            .failure_policy(FailurePolicy::degrade().with(Subsystem::Watcher, FailureMode::FailFast));
+        
+        manager.mount("/src", "/mnt/a", MountOptions::default()).await.unwrap();
+        manager.mount("/src", "/mnt/b", ci).await.unwrap();
+        assert_eq!(supervisor.policy().mode(Subsystem::Watcher), FailureMode::FailFast);
+        
+        manager.unmount("/mnt/b").await.unwrap();
+        assert_eq!(supervisor.policy(), FailurePolicy::degrade());
+    }
+    
+    #[tokio::test]
+    async fn test_ttl_expiry_warns_first() {
+        let manager = manager(false);
+        let mut events = manager.subscribe_expiry();
+        let expiry = MountExpiry {
+            ttl: Some(Duration::from_millis(300)),
+            warning: Duration::from_millis(200),
+            ..MountExpiry::default()
+        };
+        manager.mount("/src", "/mnt/plain", MountOptions::default()).await.unwrap();
+        manager.mount("/src", "/mnt/ci", MountOptions::default().expiry(expiry)).await.unwrap();
+        
+        let expired = tokio::time::timeout(Duration::from_secs(5), manager.wait_for_expiry()).await.unwrap();
+        assert_eq!(expired, (PathBuf::from("/mnt/ci"), ExpiryReason::Ttl));
+        
+        match events.recv().await.unwrap() {
+            ExpiryEvent::Warning { mount_point, reason: ExpiryReason::Ttl, remaining } => {
+                assert_eq!(mount_point, PathBuf::from("/mnt/ci"));
+                assert!(remaining <= Duration::from_millis(200));
+            }
+            other => panic!("expected a warning, got {:?}", other),
+        }
+        assert!(matches!(events.recv().await.unwrap(), ExpiryEvent::Expired { reason: ExpiryReason::Ttl, .. }));
+    }
+    
+    #[tokio::test]
+    async fn test_activity_defers_idle_expiry() {
+        let manager = manager(false);
+        let expiry = MountExpiry {
+            idle_timeout: Some(Duration::from_millis(300)),
+            ..MountExpiry::default()
+        };
+        manager.mount("/src", "/mnt/a", MountOptions::default().expiry(expiry)).await.unwrap();
+        let started = std::time::Instant::now();
+        
+        let store = manager.store();
+        let keep_busy = async {
+            for _ in 0..5 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                store.get(&ShadowPath::from("/file.txt"));
+            }
+            std::future::pending::<()>().await
+        };
+        let expired = tokio::select! {
+            expired = manager.wait_for_expiry() => expired,
+            _ = keep_busy => unreachable!(),
+        };
+        assert_eq!(expired.1, ExpiryReason::Idle);
+        assert!(started.elapsed() >= Duration::from_millis(800));
+    }
+    
+    #[tokio::test]
+    async fn test_wall_clock_jumps_do_not_expire_mounts() {
+        let manager = manager(false);
+        let expiry = MountExpiry {
+            ttl: Some(Duration::from_secs(3600)),
+            idle_timeout: Some(Duration::from_secs(600)),
+            ..MountExpiry::default()
+        };
+        manager.mount("/src", "/mnt/a", MountOptions::default().expiry(expiry)).await.unwrap();
+        
+        // Resuming from suspend steps the wall clock past both deadlines
+        crate::clock::testing::jump_wall_clock(86_400_000);
+        let waited = tokio::time::timeout(Duration::from_millis(200), manager.wait_for_expiry()).await;
+        assert!(waited.is_err());
+        
+        crate::clock::testing::advance(Duration::from_secs(601));
+        let expired = tokio::time::timeout(Duration::from_secs(5), manager.wait_for_expiry()).await.unwrap();
+        assert_eq!(expired, (PathBuf::from("/mnt/a"), ExpiryReason::Idle));
+    }
+    
+    #[tokio::test]
+    async fn test_mount_limits_reject() {
+        let manager = manager(false);
+        let with_memory = |bytes| MountOptions::default().override_config(OverrideConfig::default().with_max_memory(bytes));
+        manager.set_limits(MountLimits {
+            max_mounts: Some(2),
+            memory_budget: Some(100),
+            ..MountLimits::unlimited()
+        });
+        
+        manager.mount("/src", "/mnt/a", with_memory(60)).await.unwrap();
+        let over_budget = manager.mount("/src", "/mnt/b", with_memory(50)).await;
+        assert!(matches!(
+            over_budget,
+            Err(ShadowError::MountLimitReached { reason, .. }) if reason.contains("only 40 of the 100 byte budget")
+        ));
+        
+        manager.mount("/src", "/mnt/b", with_memory(40)).await.unwrap();
+        let too_many = manager.mount("/src", "/mnt/c", with_memory(0)).await;
+        assert!(matches!(
+            too_many,
+            Err(ShadowError::MountLimitReached { reason, .. }) if reason == "2 of 2 allowed mounts are active"
+        ));
+        assert_eq!(manager.mount_count().await, 2);
+    }
+    
+    #[tokio::test]
+    async fn test_mount_limits_queue_until_unmount() {
+        let manager = Arc::new(manager(false));
+        manager.set_limits(MountLimits {
+            max_mounts: Some(1),
+            queue_timeout: Some(Duration::from_secs(5)),
+            ..MountLimits::unlimited()
+        });
+        manager.mount("/src", "/mnt/a", MountOptions::default()).await.unwrap();
+        
+        let queued = {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move { manager.mount("/src", "/mnt/b", MountOptions::default()).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!queued.is_finished());
+        
+        manager.unmount("/mnt/a").await.unwrap();
+        queued.await.unwrap().unwrap();
+        assert!(manager.get_mount("/mnt/b").await.is_some());
+        
+        manager.set_limits(MountLimits { queue_timeout: Some(Duration::from_millis(50)), ..manager.limits() });
+        let timed_out = manager.mount("/src", "/mnt/c", MountOptions::default()).await;
+        assert!(matches!(timed_out, Err(ShadowError::MountLimitReached { .. })));
+    }
+}
@@ -0,0 +1,217 @@
+//! Conversions between the paths of a mount.
+//!
+//! A path inside a mount has three spellings: the shadow path the store
+//! knows it by, rooted at `/`; its location in the source directory; and its
+//! location below the mount point. [`MountPaths`] converts between them, and
+//! between host paths and shadow paths checks that the host path lies inside
+//! the root it is converted against, so a path escaping through `..` or
+//! naming a sibling directory is refused instead of mapped somewhere wrong.
+//!
+//! Conversions are lexical: `.` and `..` are resolved without touching the
+//! disk, and symlinks are not followed. On Windows and macOS, whose
+//! filesystems match names regardless of case by default, containment
+//! ignores ASCII case.
+//!
+//! ```rust
+//! use shadowfs_core::mount_paths::MountPaths;
+//! use shadowfs_core::types::ShadowPath;
+//! use std::path::Path;
+//!
+//! let paths = MountPaths::new("/work/repo", "/mnt/repo");
+//! let path = ShadowPath::from("/src/main.rs");
+//! assert_eq!(paths.source_path_for(&path), Path::new("/work/repo/src/main.rs"));
+//! assert_eq!(paths.shadow_path_for(Path::new("/work/repo/src/main.rs")).unwrap(), path);
+//! assert_eq!(paths.shadow_path_for_mount_path(Path::new("/mnt/repo/src/main.rs")).unwrap(), path);
+//! assert!(paths.shadow_path_for(Path::new("/work/repo/../other/main.rs")).is_err());
+//! assert!(!paths.contains_source_path(Path::new("/work/repository")));
+//! ```
+
+use crate::error::{invalid_path, ShadowError};
+use crate::types::ShadowPath;
+use std::path::{Component, Path, PathBuf};
+
+/// The source directory and mount point of a mount, converting paths between
+/// them and the shadow paths of the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountPaths {
+    source: PathBuf,
+    mount_point: PathBuf,
+}
+
+impl MountPaths {
+    /// Creates the paths of a mount of `source` at `mount_point`.
+    pub fn new(source: impl Into<PathBuf>, mount_point: impl Into<PathBuf>) -> Self {
+        Self {
+            source: normalize(&source.into()),
+            mount_point: normalize(&mount_point.into()),
+        }
+    }
+    
+    /// Returns the source directory.
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+    
+    /// Returns the mount point.
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+    
+    /// Returns where `path` lives in the source directory.
+    pub fn source_path_for(&self, path: &ShadowPath) -> PathBuf {
+        source_path(&self.source, path)
+    }
+    
+    /// Returns where `path` shows below the mount point.
+    pub fn mount_path_for(&self, path: &ShadowPath) -> PathBuf {
+        source_path(&self.mount_point, path)
+    }
+    
+    /// Returns the shadow path of `source_path`, a path in the source directory.
+    ///
+    /// # Errors
+    /// Returns [`ShadowError::InvalidPath`] if `source_path` lies outside
+    /// the source directory.
+    pub fn shadow_path_for(&self, source_path: &Path) -> Result<ShadowPath, ShadowError> {
+        shadow_path(&self.source, source_path)
+            .ok_or_else(|| outside(source_path, "source directory", &self.source))
+    }
+    
+    /// Returns the shadow path of `mount_path`, a path below the mount point.
+    ///
+    /// # Errors
+    /// Returns [`ShadowError::InvalidPath`] if `mount_path` lies outside
+    /// the mount point.
+    pub fn shadow_path_for_mount_path(&self, mount_path: &Path) -> Result<ShadowPath, ShadowError> {
+        shadow_path(&self.mount_point, mount_path)
+            .ok_or_else(|| outside(mount_path, "mount point", &self.mount_point))
+    }
+    
+    /// Returns true if `path` is the source directory or lies below it.
+    pub fn contains_source_path(&self, path: &Path) -> bool {
+        shadow_path(&self.source, path).is_some()
+    }
+    
+    /// Returns true if `path` is the mount point or lies below it.
+    pub fn contains_mount_path(&self, path: &Path) -> bool {
+        shadow_path(&self.mount_point, path).is_some()
+    }
+}
+
+/// Returns where `path` lives below `root`.
+///
+/// Only the normal components of `path` are joined, so neither a root, a
+/// Windows prefix nor a `..` in it can lead out of `root`.
+pub fn source_path(root: &Path, path: &ShadowPath) -> PathBuf {
+    let mut host = root.to_path_buf();
+    host.extend(path.as_path().components().filter_map(|component| match component {
+        Component::Normal(name) => Some(name),
+        _ => None,
+    }));
+    host
+}
+
+/// Returns the shadow path of `host`, a path below `root`, or `None` if it
+/// lies outside `root`.
+pub fn shadow_path(root: &Path, host: &Path) -> Option<ShadowPath> {
+    let root = normalize(root);
+    let host = normalize(host);
+    let mut root_components = root.components();
+    let mut host_components = host.components();
+    for expected in root_components.by_ref() {
+        if !same_component(expected, host_components.next()?) {
+            return None;
+        }
+    }
+    Some(relative_shadow_path(host_components.as_path()))
+}
+
+/// Returns the shadow path of a path relative to the root of a mount, as
+/// source watchers and provider callbacks report them.
+pub fn relative_shadow_path(relative: &Path) -> ShadowPath {
+    ShadowPath::new(Path::new("/").join(relative))
+}
+
+/// Returns `path` relative to the root of a mount, the inverse of
+/// [`relative_shadow_path`]; the root itself is the empty path.
+pub fn relative_path(path: &ShadowPath) -> &Path {
+    path.as_path().strip_prefix("/").unwrap_or(path.as_path())
+}
+
+/// Resolves `.` and `..` in `path` without touching the disk.
+///
+/// A `..` above the root of an absolute path stays at the root; one above
+/// the start of a relative path is kept, so the path never seems to lie
+/// below a root it climbs out of.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Compares two path components the way the host filesystem matches names.
+fn same_component(a: Component<'_>, b: Component<'_>) -> bool {
+    if cfg!(any(windows, target_os = "macos")) {
+        a.as_os_str().to_string_lossy().eq_ignore_ascii_case(&b.as_os_str().to_string_lossy())
+    } else {
+        a == b
+    }
+}
+
+fn outside(path: &Path, what: &str, root: &Path) -> ShadowError {
+    invalid_path(path.display().to_string(), format!("not inside the {} {}", what, root.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_conversions_round_trip() {
+        let paths = MountPaths::new("/work/./repo/", "/mnt/repo");
+        assert_eq!(paths.source(), Path::new("/work/repo"));
+        
+        let root = ShadowPath::from("/");
+        assert_eq!(paths.source_path_for(&root), Path::new("/work/repo"));
+        assert_eq!(paths.shadow_path_for(Path::new("/work/repo")).unwrap(), root);
+        
+        let nested = ShadowPath::from("/a/b/c.txt");
+        assert_eq!(paths.mount_path_for(&nested), Path::new("/mnt/repo/a/b/c.txt"));
+        assert_eq!(paths.shadow_path_for(&paths.source_path_for(&nested)).unwrap(), nested);
+        assert_eq!(paths.shadow_path_for_mount_path(Path::new("/mnt/repo/a/./b/../b/c.txt")).unwrap(), nested);
+        
+        assert_eq!(relative_shadow_path(Path::new("a/b")), ShadowPath::from("/a/b"));
+        assert_eq!(relative_path(&ShadowPath::from("/a/b")), Path::new("a/b"));
+        assert_eq!(relative_path(&root), Path::new(""));
+    }
+    
+    #[test]
+    fn test_paths_outside_the_root_are_refused() {
+        let paths = MountPaths::new("/work/repo", "/mnt/repo");
+        for outside in ["/work", "/work/repository/a", "/work/repo/../other", "/mnt/repo/a", "work/repo/a"] {
+            assert!(!paths.contains_source_path(Path::new(outside)), "{}", outside);
+            assert!(matches!(
+                paths.shadow_path_for(Path::new(outside)),
+                Err(ShadowError::InvalidPath { .. })
+            ));
+        }
+        assert!(paths.contains_mount_path(Path::new("/mnt/repo/a")));
+        assert!(!paths.contains_mount_path(Path::new("/mnt/../mnt/other")));
+        
+        // A relative root only contains what lies below it
+        assert!(shadow_path(Path::new("repo"), Path::new("repo/../../repo/a")).is_none());
+        assert_eq!(shadow_path(Path::new("repo"), Path::new("./repo/a")), Some(ShadowPath::from("/a")));
+    }
+}
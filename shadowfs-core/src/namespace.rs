@@ -0,0 +1,431 @@
+//! Independent namespaces inside one override store.
+//!
+//! An application embedding the store, such as a test runner giving every
+//! test its own shadow tree, would otherwise need a store per tenant, each
+//! with its own caches, memory tracker and background work. A [`Namespace`]
+//! is a view of the store rooted at `/.namespaces/<name>`: its paths are
+//! relative to that root, so two namespaces never see each other's
+//! overrides, and it keeps its own [`Quota`] and [`NamespaceStats`] while
+//! sharing memory, eviction and persistence with the rest of the store.
+//!
+//! Writes made to the store directly under a namespace's root count against
+//! that namespace too, and so does eviction.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::types::ShadowPath;
+//! use std::sync::Arc;
+//!
+//! let store = Arc::new(OverrideStore::with_defaults());
+//! let first = store.namespace("test-1").unwrap();
+//! let second = store.namespace("test-2").unwrap();
+//!
+//! first.insert_file(ShadowPath::from("/config.toml"), Bytes::from("debug = true"), None).unwrap();
+//! assert!(first.exists(&ShadowPath::from("/config.toml")));
+//! assert!(!second.exists(&ShadowPath::from("/config.toml")));
+//! assert_eq!(first.stats().usage.files, 1);
+//!
+//! assert_eq!(store.drop_namespace("test-1"), 1);
+//! ```
+
+use crate::error::ShadowError;
+use crate::override_store::{OverrideEntry, OverrideStore};
+use crate::quota::{Quota, QuotaUsage, UsageCounter};
+use crate::types::{DirectoryEntry, FileMetadata, ShadowPath};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Directory of the store holding the root of every namespace.
+pub const NAMESPACE_ROOT: &str = "/.namespaces";
+
+/// What a namespace has held and served since it was opened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamespaceStats {
+    /// Name of the namespace
+    pub name: String,
+    
+    /// What its overrides count against its quota
+    pub usage: QuotaUsage,
+    
+    /// Lookups through the namespace
+    pub reads: u64,
+    
+    /// Lookups that found an override
+    pub read_hits: u64,
+    
+    /// Files and directories written and paths deleted
+    pub writes: u64,
+    
+    /// Overrides removed
+    pub removals: u64,
+}
+
+/// Quota and counters of one namespace.
+#[derive(Debug, Default)]
+struct NamespaceState {
+    quota: RwLock<Quota>,
+    usage: UsageCounter,
+    reads: AtomicU64,
+    read_hits: AtomicU64,
+    writes: AtomicU64,
+    removals: AtomicU64,
+}
+
+/// Namespaces opened on a store, by name.
+#[derive(Debug, Default)]
+pub(crate) struct NamespaceTable {
+    namespaces: RwLock<HashMap<String, Arc<NamespaceState>>>,
+}
+
+impl NamespaceTable {
+    /// Returns the name and state of the namespace holding `path`, if
+    /// `path` lies below the root of an open namespace.
+    fn owner(&self, path: &ShadowPath) -> Option<(String, Arc<NamespaceState>)> {
+        let rest = path.as_path().strip_prefix(NAMESPACE_ROOT).ok()?;
+        let mut components = rest.components();
+        let Some(Component::Normal(name)) = components.next() else {
+            return None;
+        };
+        // The root directory itself belongs to no namespace
+        components.next()?;
+        let name = name.to_str()?;
+        let state = self.namespaces.read().unwrap().get(name).cloned()?;
+        Some((name.to_string(), state))
+    }
+    
+    /// Accounts for `old` being replaced by `new` at `path` in the
+    /// namespace holding it, if any.
+    pub(crate) fn replace(&self, path: &ShadowPath, old: Option<&OverrideEntry>, new: Option<&OverrideEntry>) {
+        if let Some((_, state)) = self.owner(path) {
+            state.usage.replace(old, new);
+        }
+    }
+}
+
+/// Returns the root in the store of the namespace called `name`.
+fn namespace_root(name: &str) -> Result<ShadowPath, ShadowError> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(component)), None) if component == name => {
+            Ok(ShadowPath::from(NAMESPACE_ROOT).join(name))
+        }
+        _ => Err(ShadowError::InvalidPath {
+            path: name.to_string(),
+            reason: "a namespace name must be a single path component".to_string(),
+        }),
+    }
+}
+
+/// Adds the namespace a quota belongs to to the reason it was exceeded.
+fn in_namespace(name: &str, error: ShadowError) -> ShadowError {
+    match error {
+        ShadowError::QuotaExceeded { path, reason } => ShadowError::QuotaExceeded {
+            path,
+            reason: format!("{} in namespace {}", reason, name),
+        },
+        other => other,
+    }
+}
+
+/// A view of an [`OverrideStore`] holding the overrides of one tenant.
+///
+/// Paths given to a namespace are relative to its root, and errors name the
+/// path in the store. Clones share the namespace.
+#[derive(Clone)]
+pub struct Namespace {
+    store: Arc<OverrideStore>,
+    name: String,
+    root: ShadowPath,
+    state: Arc<NamespaceState>,
+}
+
+impl Namespace {
+    /// Returns the name of the namespace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    /// Returns the directory of the store holding the namespace.
+    pub fn root(&self) -> &ShadowPath {
+        &self.root
+    }
+    
+    /// Returns the path in the store of `path` in the namespace.
+    pub fn store_path(&self, path: &ShadowPath) -> ShadowPath {
+        match path.as_path().strip_prefix("/") {
+            Ok(relative) => self.root.join(relative),
+            Err(_) => self.root.join(path.as_path()),
+        }
+    }
+    
+    /// Writes a file override at `path`.
+    pub fn insert_file(
+        &self,
+        path: ShadowPath,
+        content: Bytes,
+        original_metadata: Option<FileMetadata>,
+    ) -> Result<(), ShadowError> {
+        self.store.insert_file(self.store_path(&path), content, original_metadata)?;
+        self.state.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Writes a directory override at `path`.
+    pub fn insert_directory(&self, path: ShadowPath, original_metadata: Option<FileMetadata>) -> Result<(), ShadowError> {
+        self.store.insert_directory(self.store_path(&path), original_metadata)?;
+        self.state.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Marks `path` as deleted.
+    pub fn mark_deleted(&self, path: ShadowPath) -> Result<(), ShadowError> {
+        self.store.mark_deleted(self.store_path(&path))?;
+        self.state.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Looks up the override at `path`.
+    pub fn get(&self, path: &ShadowPath) -> Option<Arc<OverrideEntry>> {
+        let entry = self.store.get(&self.store_path(path));
+        self.state.reads.fetch_add(1, Ordering::Relaxed);
+        if entry.is_some() {
+            self.state.read_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        entry
+    }
+    
+    /// Returns true if the namespace holds an override at `path`, a
+    /// tombstone included.
+    pub fn exists(&self, path: &ShadowPath) -> bool {
+        self.store.exists(&self.store_path(path))
+    }
+    
+    /// Returns true if `path` is marked as deleted.
+    pub fn is_deleted(&self, path: &ShadowPath) -> bool {
+        self.store.is_deleted(&self.store_path(path))
+    }
+    
+    /// Removes the override at `path`.
+    pub fn remove(&self, path: &ShadowPath) -> Option<Arc<OverrideEntry>> {
+        let removed = self.store.remove(&self.store_path(path));
+        if removed.is_some() {
+            self.state.removals.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+    
+    /// Lists the overrides in the directory at `path`; `/` lists the root
+    /// of the namespace.
+    pub fn list_directory(&self, path: &ShadowPath) -> Result<Vec<DirectoryEntry>, ShadowError> {
+        self.store.list_directory(&self.store_path(path))
+    }
+    
+    /// Removes every override of the namespace, keeping it open, and
+    /// returns how many were removed.
+    pub fn clear(&self) -> usize {
+        let removed = self.store.get_children_recursive(&self.root)
+            .into_iter()
+            .filter(|path| self.store.remove(path).is_some())
+            .count();
+        self.state.removals.fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+    
+    /// Limits what may be written to the namespace from now on, on top of
+    /// the quota of the store.
+    pub fn set_quota(&self, quota: Quota) {
+        *self.state.quota.write().unwrap() = quota;
+    }
+    
+    /// Returns the limits writes to the namespace are checked against.
+    pub fn quota(&self) -> Quota {
+        *self.state.quota.read().unwrap()
+    }
+    
+    /// Returns what the overrides of the namespace count against its quota.
+    pub fn quota_usage(&self) -> QuotaUsage {
+        self.state.usage.get()
+    }
+    
+    /// Returns what the namespace holds and has served.
+    pub fn stats(&self) -> NamespaceStats {
+        NamespaceStats {
+            name: self.name.clone(),
+            usage: self.quota_usage(),
+            reads: self.state.reads.load(Ordering::Relaxed),
+            read_hits: self.state.read_hits.load(Ordering::Relaxed),
+            writes: self.state.writes.load(Ordering::Relaxed),
+            removals: self.state.removals.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl OverrideStore {
+    /// Opens the namespace called `name`, creating it and its root
+    /// directory on first use.
+    ///
+    /// Overrides already under the root, such as ones loaded from a
+    /// snapshot, count against the namespace from the start.
+    pub fn namespace(self: &Arc<Self>, name: &str) -> Result<Namespace, ShadowError> {
+        let root = namespace_root(name)?;
+        let existing = self.namespaces.namespaces.read().unwrap().get(name).cloned();
+        let state = match existing {
+            Some(state) => state,
+            None => {
+                if !self.exists(&root) {
+                    self.create_directory_hierarchy(&root)?;
+                }
+                let mut namespaces = self.namespaces.namespaces.write().unwrap();
+                let state = namespaces.entry(name.to_string()).or_insert_with(|| {
+                    let state = NamespaceState::default();
+                    for path in self.get_children_recursive(&root) {
+                        let entry = self.entries.get(&path).or_else(|| self.peek_spilled(&path).map(Arc::new));
+                        state.usage.replace(None, entry.as_deref());
+                    }
+                    Arc::new(state)
+                });
+                Arc::clone(state)
+            }
+        };
+        Ok(Namespace {
+            store: Arc::clone(self),
+            name: name.to_string(),
+            root,
+            state,
+        })
+    }
+    
+    /// Returns the names of the open namespaces, sorted.
+    pub fn namespaces(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.namespaces.namespaces.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+    
+    /// Removes the namespace called `name` with all its overrides and
+    /// returns how many overrides were removed.
+    ///
+    /// Handles to the namespace still work but start from an empty tree
+    /// without a root directory.
+    pub fn drop_namespace(&self, name: &str) -> usize {
+        let Ok(root) = namespace_root(name) else {
+            return 0;
+        };
+        let removed = self.get_children_recursive(&root)
+            .into_iter()
+            .filter(|path| self.remove(path).is_some())
+            .count();
+        self.remove(&root);
+        self.namespaces.namespaces.write().unwrap().remove(name);
+        removed
+    }
+    
+    /// Fails with [`ShadowError::QuotaExceeded`] if a change would take a
+    /// namespace past its own quota.
+    pub(crate) fn check_namespace_quotas(&self, changes: &HashMap<&ShadowPath, QuotaUsage>) -> Result<(), ShadowError> {
+        if self.namespaces.namespaces.read().unwrap().is_empty() {
+            return Ok(());
+        }
+        
+        let mut states = HashMap::new();
+        let mut by_namespace: HashMap<String, Vec<(&ShadowPath, QuotaUsage)>> = HashMap::new();
+        for (path, usage) in changes {
+            if let Some((name, state)) = self.namespaces.owner(path) {
+                states.entry(name.clone()).or_insert(state);
+                by_namespace.entry(name).or_default().push((*path, *usage));
+            }
+        }
+        for (name, changes) in by_namespace {
+            let state = &states[&name];
+            let quota = *state.quota.read().unwrap();
+            if quota.is_unlimited() {
+                continue;
+            }
+            self.check_usage(quota, state.usage.get(), changes)
+                .map_err(|error| in_namespace(&name, error))?;
+        }
+        Ok(())
+    }
+    
+    /// Fails with [`ShadowError::QuotaExceeded`] if a file at `path` of
+    /// `size` bytes would exceed the file size limit of its namespace.
+    pub(crate) fn check_namespace_file_size(&self, path: &ShadowPath, size: u64) -> Result<(), ShadowError> {
+        match self.namespaces.owner(path) {
+            Some((name, state)) => crate::quota::check_size(&state.quota.read().unwrap(), path, size)
+                .map_err(|error| in_namespace(&name, error)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_namespaces_are_isolated() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        let first = store.namespace("first").unwrap();
+        let second = store.namespace("second").unwrap();
+        assert_eq!(store.namespaces(), ["first", "second"]);
+        
+        first.insert_directory(ShadowPath::from("/src"), None).unwrap();
+        first.insert_file(ShadowPath::from("/src/a.rs"), Bytes::from("first"), None).unwrap();
+        second.insert_file(ShadowPath::from("/src/a.rs"), Bytes::from("second!"), None).unwrap();
+        assert_eq!(first.store_path(&ShadowPath::from("/src/a.rs")), ShadowPath::from("/.namespaces/first/src/a.rs"));
+        
+        let names: Vec<String> = first.list_directory(&ShadowPath::from("/")).unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["src"]);
+        assert_eq!(first.get(&ShadowPath::from("/src/a.rs")).unwrap().override_metadata.size, 5);
+        assert!(first.get(&ShadowPath::from("/missing")).is_none());
+        
+        let stats = first.stats();
+        assert_eq!(stats.usage, QuotaUsage { files: 2, bytes: 5 });
+        assert_eq!((stats.reads, stats.read_hits, stats.writes), (2, 1, 2));
+        assert_eq!(second.quota_usage(), QuotaUsage { files: 1, bytes: 7 });
+        
+        // Removal through the store itself is accounted to the namespace
+        store.remove(&ShadowPath::from("/.namespaces/second/src/a.rs"));
+        assert_eq!(second.quota_usage(), QuotaUsage::default());
+        
+        assert_eq!(first.clear(), 2);
+        assert_eq!(first.quota_usage(), QuotaUsage::default());
+        assert_eq!(store.drop_namespace("second"), 0);
+        assert_eq!(store.namespaces(), ["first"]);
+        assert!(store.namespace("a/b").is_err() && store.namespace("..").is_err());
+    }
+    
+    #[test]
+    fn test_namespace_quotas() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        let small = store.namespace("small").unwrap();
+        let large = store.namespace("large").unwrap();
+        small.set_quota(Quota { max_files: Some(1), max_file_size: Some(4), ..Quota::default() });
+        
+        small.insert_file(ShadowPath::from("/a"), Bytes::from("a"), None).unwrap();
+        let err = small.insert_file(ShadowPath::from("/b"), Bytes::from("b"), None).unwrap_err();
+        assert!(matches!(&err, ShadowError::QuotaExceeded { reason, .. } if reason == "2 files would exceed the limit of 1 in namespace small"));
+        let err = small.insert_file(ShadowPath::from("/a"), Bytes::from("too long"), None).unwrap_err();
+        assert!(matches!(&err, ShadowError::QuotaExceeded { reason, .. } if reason.contains("limit of 4 bytes")));
+        
+        // Other namespaces and the rest of the store are not limited
+        large.insert_file(ShadowPath::from("/a"), Bytes::from("a"), None).unwrap();
+        large.insert_file(ShadowPath::from("/b"), Bytes::from("too long"), None).unwrap();
+        store.insert_file(ShadowPath::from("/b"), Bytes::from("b"), None).unwrap();
+        
+        // Reopening shares the quota, and opening finds what the root
+        // already holds
+        let reopened = store.namespace("small").unwrap();
+        assert_eq!(reopened.quota(), small.quota());
+        assert_eq!(reopened.quota_usage().files, 1);
+        assert_eq!(store.drop_namespace("large"), 2);
+        store.insert_file(ShadowPath::from("/.namespaces/restored/x"), Bytes::from("xy"), None).unwrap();
+        let restored = store.namespace("restored").unwrap();
+        assert_eq!(restored.quota_usage(), QuotaUsage { files: 1, bytes: 2 });
+    }
+}
@@ -1,15 +1,17 @@
 //! Public API and builder for the override store.
 
-use crate::types::ShadowPath;
+use crate::types::{current_time, ShadowPath};
 use crate::error::ShadowError;
+use crate::supervision::ComponentState;
 use super::{
-    OverrideStore, OverrideStoreConfig, EvictionPolicy, PrefetchStrategy,
-    OverrideSnapshot
+    OverrideStore, OverrideStoreConfig, EvictionPolicy, PrefetchStrategy
 };
+#[cfg(feature = "persistence")]
+use super::OverrideSnapshot;
+#[cfg(feature = "persistence")]
 use bytes::Bytes;
 use std::path::PathBuf;
 use std::time::SystemTime;
-use serde::{Serialize, Deserialize};
 
 /// Builder for creating configured OverrideStore instances.
 /// 
@@ -117,7 +119,9 @@ impl OverrideStoreBuilder {
     /// 
     /// - `EvictionPolicy::Lru` - Least Recently Used (default)
     /// - `EvictionPolicy::Lfu` - Least Frequently Used
+    /// - `EvictionPolicy::Fifo` - Oldest entries first
     /// - `EvictionPolicy::SizeWeighted` - Largest entries first
+    /// - `EvictionPolicy::TwoQueue` - Entries used once first, then LRU (scan-resistant)
     /// 
     /// # Examples
     /// 
@@ -244,6 +248,44 @@ impl OverrideStoreBuilder {
         self
     }
     
+    /// Enables the disk-backed overflow tier in `dir`.
+    /// 
+    /// Under memory pressure, cold file overrides are written to encrypted,
+    /// compressed files below `dir` instead of being evicted, and are loaded
+    /// back transparently when accessed. The files are removed when the
+    /// store is dropped.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `dir` - Directory for spill files; created if missing
+    /// 
+    /// # Examples
+    /// 
+    /// ```rust
+    /// use shadowfs_core::override_store::OverrideStoreBuilder;
+    /// 
+    /// let store = OverrideStoreBuilder::new()
+    ///     .with_spill_dir(std::env::temp_dir().join("shadowfs-spill"))
+    ///     .build()
+    ///     .expect("Failed to create store");
+    /// ```
+    pub fn with_spill_dir(mut self, dir: PathBuf) -> Self {
+        self.config.spill_dir = Some(dir);
+        self
+    }
+    
+    /// Sets the memory pressure ratio above which cold entries are spilled.
+    /// 
+    /// Only used together with [`with_spill_dir`](Self::with_spill_dir).
+    /// 
+    /// # Arguments
+    /// 
+    /// * `threshold` - Threshold as a value between 0.0 and 1.0
+    pub fn with_spill_threshold(mut self, threshold: f64) -> Self {
+        self.config.spill_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+    
     /// Builds the configured OverrideStore.
     /// 
     /// # Returns
@@ -302,7 +344,8 @@ impl Default for OverrideStoreBuilder {
 }
 
 /// Health status of the override store.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HealthStatus {
     /// Store is operating normally
     Healthy,
@@ -345,7 +388,8 @@ impl HealthStatus {
 }
 
 /// Export formats for override store data.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg(feature = "persistence")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum ExportFormat {
     /// Binary format with efficient compression
     Binary,
@@ -353,6 +397,10 @@ pub enum ExportFormat {
     Json,
     /// MessagePack format for compact binary
     MessagePack,
+    /// Tar archive of the overridden files with a manifest of metadata and tombstones
+    Tar,
+    /// Zip archive of the overridden files with a manifest of metadata and tombstones
+    Zip,
 }
 
 /// Migration utilities for override store data.
@@ -371,7 +419,7 @@ impl Migration {
         Self {
             from_version,
             to_version,
-            timestamp: SystemTime::now(),
+            timestamp: current_time(),
         }
     }
 }
@@ -406,6 +454,7 @@ impl OverrideStore {
     /// let store = OverrideStore::from_snapshot(PathBuf::from("backup.snapshot"))
     ///     .expect("Failed to load from snapshot");
     /// ```
+    #[cfg(feature = "persistence")]
     pub fn from_snapshot(path: PathBuf) -> Result<Self, ShadowError> {
         // Check if snapshot file exists
         if !path.exists() {
@@ -414,24 +463,7 @@ impl OverrideStore {
             });
         }
         
-        // Load snapshot data
-        let snapshot_data = std::fs::read(&path)
-            .map_err(|e| ShadowError::IoError { source: e })?;
-        
-        // Deserialize snapshot
-        let snapshot: OverrideSnapshot = bincode::deserialize(&snapshot_data)
-            .map_err(|_| ShadowError::InvalidConfiguration {
-                message: "Corrupted snapshot file".to_string(),
-            })?;
-        
-        // Create store with configuration from snapshot
-        let store = OverrideStore::new(snapshot.config.clone());
-        
-        // Apply snapshot entries
-        // TODO: Implement apply_snapshot properly
-        // For now, just return the store with default config
-        
-        Ok(store)
+        Self::load_snapshot(&path)
     }
     
     /// Gets the current memory usage as a percentage of the limit.
@@ -499,6 +531,8 @@ impl OverrideStore {
     /// - Memory usage levels
     /// - Cache hit rates
     /// - Eviction rates
+    /// - Restarts of supervised background components
+    /// - Whether the source accepted writes when last probed
     /// - Internal consistency
     /// 
     /// # Returns
@@ -545,6 +579,22 @@ impl OverrideStore {
             warnings.push(format!("High eviction count: {}", stats.eviction_count));
         }
         
+        // Check supervised background components
+        if let Some(supervisor) = self.supervisor() {
+            for status in supervisor.components() {
+                if status.state == ComponentState::Failed {
+                    warnings.push(format!("{} gave up after {} restarts", status.component, status.restarts));
+                } else if status.restarts > 0 {
+                    warnings.push(format!("{} restarted {} times", status.component, status.restarts));
+                }
+            }
+        }
+        
+        // Check that commits can write to the source
+        if let Some(denial) = self.source_write_denial() {
+            warnings.push(format!("Commit unavailable: {}", denial));
+        }
+        
         // Check for internal consistency
         let entry_count = self.entry_count();
         if entry_count == 0 && self.memory_tracker.current_usage() > 1024 * 1024 {
@@ -638,30 +688,35 @@ impl OverrideStore {
     ///     .expect("Export failed");
     /// 
     /// // Save to file
-    /// std::fs::write("backup.json", exported).expect("Write failed");
+    /// let dir = tempfile::tempdir().expect("Temp dir failed");
+    /// std::fs::write(dir.path().join("backup.json"), exported).expect("Write failed");
     /// ```
+    #[cfg(feature = "persistence")]
     pub fn export_to_format(&self, format: ExportFormat) -> Result<Bytes, ShadowError> {
-        let snapshot = self.create_snapshot();
-        
         let serialized = match format {
             ExportFormat::Binary => {
-                bincode::serialize(&snapshot)
+                bincode::serialize(&self.export_snapshot())
                     .map_err(|_| ShadowError::InvalidConfiguration {
                         message: "Failed to serialize to binary".to_string(),
                     })?
             }
             ExportFormat::Json => {
-                serde_json::to_vec_pretty(&snapshot)
+                serde_json::to_vec_pretty(&self.export_snapshot())
                     .map_err(|_| ShadowError::InvalidConfiguration {
                         message: "Failed to serialize to JSON".to_string(),
                     })?
             }
             ExportFormat::MessagePack => {
-                rmp_serde::to_vec(&snapshot)
+                rmp_serde::to_vec(&self.export_snapshot())
                     .map_err(|_| ShadowError::InvalidConfiguration {
                         message: "Failed to serialize to MessagePack".to_string(),
                     })?
             }
+            ExportFormat::Tar | ExportFormat::Zip => {
+                let mut archive = std::io::Cursor::new(Vec::new());
+                self.export(&mut archive, format)?;
+                archive.into_inner()
+            }
         };
         
         Ok(Bytes::from(serialized))
@@ -693,6 +748,7 @@ impl OverrideStore {
     /// store.import_from_format(Bytes::from(data), ExportFormat::Json)
     ///     .expect("Import failed");
     /// ```
+    #[cfg(feature = "persistence")]
     pub fn import_from_format(&mut self, data: Bytes, format: ExportFormat) -> Result<(), ShadowError> {
         let snapshot: OverrideSnapshot = match format {
             ExportFormat::Binary => {
@@ -713,6 +769,9 @@ impl OverrideStore {
                         message: "Failed to deserialize from MessagePack".to_string(),
                     })?
             }
+            ExportFormat::Tar | ExportFormat::Zip => {
+                return self.import(data.as_ref(), format);
+            }
         };
         
         // Apply the snapshot to current store
@@ -721,16 +780,77 @@ impl OverrideStore {
         Ok(())
     }
     
+    /// Writes the store in the specified format to `writer`.
+    /// 
+    /// [`ExportFormat::Tar`] and [`ExportFormat::Zip`] produce an archive of
+    /// the overridden files that ordinary tools can extract, with a
+    /// `manifest.json` holding their metadata and the tombstones. Zip
+    /// archives are assembled in memory before they are written.
+    /// 
+    /// # Examples
+    /// 
+    /// ```rust,no_run
+    /// use shadowfs_core::override_store::{OverrideStore, ExportFormat};
+    /// 
+    /// let store = OverrideStore::with_defaults();
+    /// let file = std::fs::File::create("workspace.tar").expect("Create failed");
+    /// store.export(file, ExportFormat::Tar).expect("Export failed");
+    /// ```
+    #[cfg(feature = "persistence")]
+    pub fn export<W: std::io::Write>(&self, mut writer: W, format: ExportFormat) -> Result<(), ShadowError> {
+        match format {
+            ExportFormat::Tar => self.write_tar(writer),
+            ExportFormat::Zip => {
+                let mut archive = std::io::Cursor::new(Vec::new());
+                self.write_zip(&mut archive)?;
+                writer.write_all(archive.get_ref()).map_err(|e| ShadowError::IoError { source: e })
+            }
+            ExportFormat::Binary | ExportFormat::Json | ExportFormat::MessagePack => {
+                let data = self.export_to_format(format)?;
+                writer.write_all(&data).map_err(|e| ShadowError::IoError { source: e })
+            }
+        }
+    }
+    
+    /// Imports data in the specified format from `reader`.
+    /// 
+    /// Like [`OverrideStore::import_from_format`], imported overrides replace
+    /// existing ones at the same paths and other entries are kept. Archives
+    /// are imported as one [`OverrideStore::transaction`], so a malformed
+    /// archive leaves the store unchanged.
+    /// 
+    /// # Examples
+    /// 
+    /// ```rust,no_run
+    /// use shadowfs_core::override_store::{OverrideStore, ExportFormat};
+    /// 
+    /// let mut store = OverrideStore::with_defaults();
+    /// let file = std::fs::File::open("workspace.zip").expect("Open failed");
+    /// store.import(file, ExportFormat::Zip).expect("Import failed");
+    /// ```
+    #[cfg(feature = "persistence")]
+    pub fn import<R: std::io::Read>(&mut self, mut reader: R, format: ExportFormat) -> Result<(), ShadowError> {
+        match format {
+            ExportFormat::Tar => self.read_tar(reader),
+            ExportFormat::Zip => self.read_zip(super::archive::seekable(reader)?),
+            ExportFormat::Binary | ExportFormat::Json | ExportFormat::MessagePack => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data).map_err(|e| ShadowError::IoError { source: e })?;
+                self.import_from_format(Bytes::from(data), format)
+            }
+        }
+    }
+    
     /// Creates a snapshot of the current store state.
-    fn create_snapshot(&self) -> OverrideSnapshot {
+    #[cfg(feature = "persistence")]
+    fn export_snapshot(&self) -> OverrideSnapshot {
         // Use the existing from_store method
         OverrideSnapshot::from_store(self)
     }
     
     /// Applies a snapshot to the current store.
-    fn apply_snapshot(&mut self, _snapshot: OverrideSnapshot) -> Result<(), ShadowError> {
-        // In a real implementation, this would apply the snapshot entries
-        // TODO: Implement snapshot application
-        Ok(())
+    #[cfg(feature = "persistence")]
+    fn apply_snapshot(&mut self, snapshot: OverrideSnapshot) -> Result<(), ShadowError> {
+        snapshot.apply_to_store(self)
     }
 }
\ No newline at end of file
@@ -0,0 +1,388 @@
+//! Append logs: file overrides growing at their end, kept as chunks.
+//!
+//! Writing into a file override stores the whole file again: it is copied,
+//! hashed and possibly compressed, so a log written a line at a time costs
+//! its full size per line. A write starting at the end of a file override
+//! goes to the override's append log instead, a list of fixed-size chunks
+//! behind a tail chunk that writes fill, and costs the bytes written.
+//!
+//! Once a log holds as many bytes as the content before it, and at least
+//! [`APPEND_COMPACTION_MIN`], it is compacted into the content, so the cost
+//! of compacting stays proportional to the bytes appended; until then the
+//! content hash of the entry covers the compacted part only.
+//! [`OverrideStore::compact_append`] compacts a log earlier. Any other
+//! write to the file, a truncation, a hard link or a rename stores the full
+//! content without a log, while reads, including of older versions of the
+//! entry held elsewhere, see the content and log together.
+//!
+//! Appends are logged to the WAL as [`PersistenceOp::Append`] records;
+//! snapshots and the spill tier hold logs compacted.
+
+use crate::error::ShadowError;
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+#[cfg(feature = "persistence")]
+use crate::override_store::PersistenceOp;
+use crate::quota::QuotaUsage;
+#[cfg(feature = "persistence")]
+use crate::supervision::Subsystem;
+use crate::types::{current_time, ShadowPath};
+use bytes::Bytes;
+use std::sync::{Arc, Mutex};
+
+/// Bytes per chunk of an append log.
+pub const APPEND_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes a log holds at least before it is compacted into its entry.
+pub const APPEND_COMPACTION_MIN: u64 = 1024 * 1024;
+
+/// Summary of the append log of a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AppendInfo {
+    /// Bytes of the file compacted into its entry
+    pub compacted_bytes: u64,
+    
+    /// Bytes appended since the last compaction
+    pub appended_bytes: u64,
+    
+    /// Chunks holding the appended bytes, the tail included
+    pub chunk_count: usize,
+}
+
+/// Chunks of an append log: full ones, then the tail being filled.
+#[derive(Debug, Default)]
+struct Chunks {
+    full: Vec<Bytes>,
+    tail: Vec<u8>,
+}
+
+impl Chunks {
+    fn len(&self) -> u64 {
+        (self.full.len() * APPEND_CHUNK_SIZE + self.tail.len()) as u64
+    }
+    
+    fn push(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.tail.capacity() == 0 {
+                self.tail.reserve_exact(APPEND_CHUNK_SIZE);
+            }
+            let taken = data.len().min(APPEND_CHUNK_SIZE - self.tail.len());
+            self.tail.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+            if self.tail.len() == APPEND_CHUNK_SIZE {
+                self.full.push(Bytes::from(std::mem::take(&mut self.tail)));
+            }
+        }
+    }
+    
+    /// Copies the bytes from `start` to `end` into `out`.
+    fn copy_range(&self, start: u64, end: u64, out: &mut Vec<u8>) {
+        let mut at = start;
+        while at < end {
+            let index = (at / APPEND_CHUNK_SIZE as u64) as usize;
+            let within = (at % APPEND_CHUNK_SIZE as u64) as usize;
+            let chunk = self.full.get(index).map_or(&self.tail[..], |chunk| &chunk[..]);
+            let taken = (chunk.len() - within).min((end - at) as usize);
+            out.extend_from_slice(&chunk[within..within + taken]);
+            at += taken as u64;
+        }
+    }
+}
+
+/// The bytes appended to an entry since it was last compacted: the first
+/// `len` bytes of a log later versions of the entry may have grown further.
+#[derive(Clone)]
+pub(crate) struct AppendedTail {
+    chunks: Arc<Mutex<Chunks>>,
+    len: u64,
+}
+
+impl AppendedTail {
+    fn start(data: &[u8]) -> Self {
+        let mut chunks = Chunks::default();
+        chunks.push(data);
+        Self { len: chunks.len(), chunks: Arc::new(Mutex::new(chunks)) }
+    }
+    
+    /// Returns the tail with `data` appended, or `None` if another version
+    /// of the entry already appended to the log.
+    fn extended(&self, data: &[u8]) -> Option<Self> {
+        let mut chunks = self.chunks.lock().unwrap();
+        if chunks.len() != self.len {
+            return None;
+        }
+        chunks.push(data);
+        Some(Self { chunks: Arc::clone(&self.chunks), len: chunks.len() })
+    }
+    
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+    
+    fn chunk_count(&self) -> usize {
+        let len = self.len as usize;
+        len / APPEND_CHUNK_SIZE + usize::from(len % APPEND_CHUNK_SIZE != 0)
+    }
+    
+    /// Copies the bytes from `start` to `end` of the tail into `out`.
+    fn copy_range(&self, start: u64, end: u64, out: &mut Vec<u8>) {
+        self.chunks.lock().unwrap().copy_range(start, end.min(self.len), out);
+    }
+}
+
+impl OverrideEntry {
+    /// Returns `compacted`, the compacted content of the entry, followed by
+    /// its appended tail, if any.
+    pub(crate) fn with_appended(&self, compacted: Bytes) -> Bytes {
+        let Some(tail) = &self.appended else {
+            return compacted;
+        };
+        let mut data = Vec::with_capacity(compacted.len() + tail.len as usize);
+        data.extend_from_slice(&compacted);
+        tail.copy_range(0, tail.len, &mut data);
+        data.into()
+    }
+    
+    /// Reads up to `size` bytes at `offset` of the file content, without
+    /// joining an appended tail to the rest.
+    ///
+    /// A range of uncompressed content is a slice of the stored buffer,
+    /// shared with the entry rather than copied out of it.
+    ///
+    /// # Returns
+    /// `None` for directories and tombstones
+    pub fn read_file_range(&self, offset: u64, size: usize) -> Result<Option<Bytes>, ShadowError> {
+        let compacted = match &self.content {
+            OverrideContent::File { .. } => self.compacted_data()?,
+            _ => return Ok(None),
+        };
+        let compacted_len = compacted.len() as u64;
+        let total = compacted_len + self.appended.as_ref().map_or(0, AppendedTail::len);
+        let start = offset.min(total);
+        let end = offset.saturating_add(size as u64).min(total);
+        if end <= compacted_len {
+            return Ok(Some(compacted.slice(start as usize..end as usize)));
+        }
+        
+        let mut data = Vec::with_capacity((end - start) as usize);
+        if start < compacted_len {
+            data.extend_from_slice(&compacted[start as usize..]);
+        }
+        if let Some(tail) = &self.appended {
+            tail.copy_range(start.max(compacted_len) - compacted_len, end - compacted_len, &mut data);
+        }
+        Ok(Some(data.into()))
+    }
+}
+
+impl OverrideStore {
+    /// Appends `data` to the file override `entry` of `path` through its
+    /// append log, publishing the change and compacting the log when due.
+    ///
+    /// # Returns
+    /// Whether `data` was appended; false when the entry shares its content
+    /// with hard links, or another version of it took the log, so the
+    /// caller stores the full content instead
+    pub(crate) fn append_to_entry(&self, path: &ShadowPath, entry: &OverrideEntry, data: &[u8]) -> Result<bool, ShadowError> {
+        if data.is_empty() || !entry.is_file() || self.links.count(path) > 1 {
+            return Ok(false);
+        }
+        let len = entry.override_metadata.size + data.len() as u64;
+        self.check_file_size(path, len)?;
+        self.check_quota([(path, QuotaUsage::of_content(&entry.content, len))])?;
+        
+        {
+            // Held until the entry is visible, as for inserts
+            #[cfg(feature = "persistence")]
+            let wal = self.wal.read().unwrap();
+            let tail = match &entry.appended {
+                Some(tail) => match tail.extended(data) {
+                    Some(tail) => tail,
+                    None => return Ok(false),
+                },
+                None => AppendedTail::start(data),
+            };
+            #[cfg(feature = "persistence")]
+            if let Some(wal) = wal.as_ref() {
+                if let Err(e) = wal.append(&PersistenceOp::append(path.clone(), Bytes::copy_from_slice(data))) {
+                    let message = || format!("Failed to log append to {}: {}", path, e);
+                    if !self.degrade_on_failure(Subsystem::Persistence, message) {
+                        return Err(e);
+                    }
+                }
+            }
+            
+            let mut appended = entry.clone();
+            appended.override_metadata.size = len;
+            appended.override_metadata.modified = current_time();
+            appended.appended = Some(tail);
+            self.reserve_memory(data.len(), Some(path))?;
+            self.store_reserved_entry(Arc::new(appended), false, Some(&mut 0))?;
+        }
+        
+        let info = self.append_info(path);
+        if info.is_some_and(|info| info.appended_bytes >= info.compacted_bytes.max(APPEND_COMPACTION_MIN)) {
+            self.compact_append(path)?;
+        }
+        Ok(true)
+    }
+    
+    /// Appends `data` to the file override of `path` without logging it,
+    /// storing the full content if it cannot go to the append log.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn apply_append(&self, path: &ShadowPath, data: &[u8]) -> Result<(), ShadowError> {
+        let entry = self.get(path).ok_or_else(|| crate::error::not_found(path.clone()))?;
+        if self.append_to_entry(path, &entry, data)? {
+            return Ok(());
+        }
+        let mut content = entry.get_file_data()?.ok_or_else(|| crate::error::is_a_directory(path.clone()))?.to_vec();
+        content.extend_from_slice(data);
+        self.insert_file(path.clone(), content.into(), entry.original_metadata.clone())
+    }
+    
+    /// Describes the append log of `path`, if its override has one.
+    pub fn append_info(&self, path: &ShadowPath) -> Option<AppendInfo> {
+        let entry = self.entries.get(path)?;
+        let tail = entry.appended.as_ref()?;
+        Some(AppendInfo {
+            compacted_bytes: entry.override_metadata.size - tail.len,
+            appended_bytes: tail.len,
+            chunk_count: tail.chunk_count(),
+        })
+    }
+    
+    /// Compacts the append log of `path` into its file override, which is
+    /// stored again with its full content compressed and hashed.
+    ///
+    /// # Returns
+    /// Whether `path` had an append log to compact
+    pub fn compact_append(&self, path: &ShadowPath) -> Result<bool, ShadowError> {
+        let Some(entry) = self.entries.get(path).filter(|entry| entry.appended.is_some()) else {
+            return Ok(false);
+        };
+        let compacted = self.compacted_entry(&entry)?;
+        self.insert_entry(path.clone(), compacted.content, compacted.original_metadata, compacted.override_metadata)?;
+        Ok(true)
+    }
+    
+    /// Compacts every append log.
+    ///
+    /// # Returns
+    /// Number of logs compacted
+    pub fn compact_all_appends(&self) -> Result<usize, ShadowError> {
+        let mut compacted = 0;
+        for (path, entry) in self.entries.iter() {
+            if entry.appended.is_some() && self.compact_append(&path)? {
+                compacted += 1;
+            }
+        }
+        Ok(compacted)
+    }
+    
+    /// Returns `entry` with its append log compacted into its content,
+    /// leaving the store as it is.
+    pub(crate) fn compacted_entry(&self, entry: &OverrideEntry) -> Result<OverrideEntry, ShadowError> {
+        let mut compacted = entry.clone();
+        if entry.appended.is_some() {
+            compacted.content = self.file_content(entry.get_file_data()?.unwrap_or_default());
+            compacted.appended = None;
+        }
+        Ok(compacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    #[test]
+    fn test_appends_go_to_the_log() {
+        let store = OverrideStore::with_defaults();
+        let log = path("/app.log");
+        store.insert_file(log.clone(), Bytes::from("start\n"), None).unwrap();
+        let first = store.get(&log).unwrap();
+        
+        let line = vec![b'x'; 1000];
+        for i in 0..100u64 {
+            store.write_range(log.clone(), "/unused", 6 + i * 1000, &line, None).unwrap();
+        }
+        let info = store.append_info(&log).unwrap();
+        assert_eq!((info.compacted_bytes, info.appended_bytes, info.chunk_count), (6, 100_000, 2));
+        
+        let entry = store.get(&log).unwrap();
+        assert_eq!(entry.override_metadata.size, 100_006);
+        let data = entry.get_file_data().unwrap().unwrap();
+        assert_eq!((&data[..6], data.len()), (&b"start\n"[..], 100_006));
+        assert_eq!(store.read_range(&log, 3, 5).unwrap(), Bytes::from("rt\nxx"));
+        assert_eq!(store.read_range(&log, 65_530, 20).unwrap().len(), 20);
+        assert_eq!(store.read_range(&log, 100_000, 100).unwrap().len(), 6);
+        
+        // Versions held before keep their length
+        assert_eq!(first.get_file_data().unwrap().unwrap(), Bytes::from("start\n"));
+        
+        // Writing inside the file stores it whole again
+        store.write_range(log.clone(), "/unused", 0, b"S", None).unwrap();
+        assert!(store.append_info(&log).is_none());
+        assert_eq!(store.read_range(&log, 0, 7).unwrap(), Bytes::from("Start\nx"));
+        assert_eq!(store.get(&log).unwrap().override_metadata.size, 100_006);
+    }
+    
+    #[test]
+    fn test_logs_are_compacted_when_due() {
+        let store = OverrideStore::with_defaults();
+        let log = path("/app.log");
+        store.insert_file(log.clone(), Bytes::new(), None).unwrap();
+        let chunk = vec![b'a'; 256 * 1024];
+        for i in 0..3u64 {
+            store.write_range(log.clone(), "/unused", i * chunk.len() as u64, &chunk, None).unwrap();
+        }
+        assert_eq!(store.append_info(&log).unwrap().appended_bytes, 768 * 1024);
+        
+        store.write_range(log.clone(), "/unused", 768 * 1024, &chunk, None).unwrap();
+        assert!(store.append_info(&log).is_none());
+        let entry = store.get(&log).unwrap();
+        assert_eq!(entry.get_file_data().unwrap().unwrap().len(), 1024 * 1024);
+        assert!(matches!(entry.content, OverrideContent::File { is_compressed: true, .. }));
+        
+        store.write_range(log.clone(), "/unused", 1024 * 1024, b"b", None).unwrap();
+        assert!(store.compact_append(&log).unwrap());
+        assert!(!store.compact_append(&log).unwrap());
+        assert_eq!(store.read_range(&log, 1024 * 1024 - 1, 10).unwrap(), Bytes::from("ab"));
+    }
+    
+    #[test]
+    fn test_logs_survive_renames_and_snapshots() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/app.log"), Bytes::from("one\n"), None).unwrap();
+        store.write_range(path("/app.log"), "/unused", 4, b"two\n", None).unwrap();
+        assert!(store.append_info(&path("/app.log")).is_some());
+        
+        store.rename(&path("/app.log"), path("/app.log.1"), crate::override_store::RenameOptions::default()).unwrap();
+        let rotated = store.get(&path("/app.log.1")).unwrap();
+        assert!(rotated.appended.is_none());
+        assert_eq!(rotated.get_file_data().unwrap().unwrap(), Bytes::from("one\ntwo\n"));
+        
+        #[cfg(feature = "persistence")]
+        {
+            let dir = tempfile::TempDir::new().unwrap();
+            let wal_path = dir.path().join("store.wal");
+            store.enable_wal(&wal_path).unwrap();
+            store.insert_file(path("/b.log"), Bytes::from("b"), None).unwrap();
+            store.write_range(path("/b.log"), "/unused", 1, b"cd", None).unwrap();
+            
+            let replayed = OverrideStore::with_defaults();
+            crate::override_store::WriteAheadLog::replay(&wal_path, &replayed).unwrap();
+            assert_eq!(replayed.read_range(&path("/b.log"), 0, 10).unwrap(), Bytes::from("bcd"));
+            assert!(replayed.append_info(&path("/b.log")).is_some());
+            
+            let snapshot_path = dir.path().join("store.snapshot");
+            store.save_snapshot(&snapshot_path).unwrap();
+            let loaded = OverrideStore::load_snapshot(&snapshot_path).unwrap();
+            assert_eq!(loaded.get(&path("/b.log")).unwrap().get_file_data().unwrap().unwrap(), Bytes::from("bcd"));
+        }
+    }
+}
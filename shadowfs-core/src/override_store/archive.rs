@@ -0,0 +1,448 @@
+//! Tar and zip archives of the override set.
+//!
+//! [`ExportFormat::Tar`] and [`ExportFormat::Zip`] write the overrides as an
+//! archive that ordinary tools can list and extract, for sharing a
+//! reproducible test workspace. File overrides are stored under `overrides/`
+//! at their path in the shadow, and directory overrides as directories.
+//! `manifest.json` at the root of the archive lists every override with its
+//! metadata, including tombstones, which have no file of their own.
+//!
+//! Importing reads the manifest back, so metadata and tombstones survive the
+//! round trip. Files and directories under `overrides/` that the manifest
+//! does not list, such as ones added to the archive by hand, are imported as
+//! new overrides. Hard links are exported as separate files and written
+//! ranges as the files they make up.
+//!
+//! [`ExportFormat::Tar`]: crate::override_store::ExportFormat::Tar
+//! [`ExportFormat::Zip`]: crate::override_store::ExportFormat::Zip
+
+use crate::error::ShadowError;
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::types::{FileMetadata, ShadowPath};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::Component;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version of the archive manifest.
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+/// Name of the manifest at the root of an archive.
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Directory of the archive holding the overridden files.
+const OVERRIDES_DIR: &str = "overrides";
+
+/// Manifest describing every override in an archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveManifest {
+    version: u32,
+    entries: Vec<ManifestEntry>,
+}
+
+/// An override listed in the manifest.
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    path: ShadowPath,
+    kind: EntryKind,
+    metadata: FileMetadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original_metadata: Option<FileMetadata>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EntryKind {
+    File,
+    Directory,
+    Deleted,
+}
+
+/// An override as read from or written to an archive.
+struct ArchivedOverride {
+    kind: EntryKind,
+    metadata: FileMetadata,
+    data: Bytes,
+}
+
+/// Overrides read from an archive, before they are applied.
+#[derive(Default)]
+struct ArchiveContents {
+    manifest: Option<ArchiveManifest>,
+    files: HashMap<ShadowPath, Bytes>,
+    directories: Vec<ShadowPath>,
+}
+
+impl OverrideStore {
+    /// Writes the overrides as a tar archive.
+    pub(crate) fn write_tar<W: Write>(&self, writer: W) -> Result<(), ShadowError> {
+        let (manifest, overrides) = self.archive_entries()?;
+        let mut builder = tar::Builder::new(writer);
+        
+        let manifest = encode_manifest(&manifest)?;
+        let mut header = tar_header(tar::EntryType::Regular, 0o644, SystemTime::now(), manifest.len());
+        builder.append_data(&mut header, MANIFEST_NAME, manifest.as_slice()).map_err(io_error)?;
+        
+        for (name, entry) in &overrides {
+            let mode = entry.metadata.permissions.to_unix_mode();
+            let (entry_type, name) = match entry.kind {
+                EntryKind::Directory => (tar::EntryType::Directory, format!("{}/", name)),
+                _ => (tar::EntryType::Regular, name.clone()),
+            };
+            let mut header = tar_header(entry_type, mode, entry.metadata.modified, entry.data.len());
+            builder.append_data(&mut header, name, entry.data.as_ref()).map_err(io_error)?;
+        }
+        builder.into_inner().and_then(|mut writer| writer.flush()).map_err(io_error)
+    }
+    
+    /// Writes the overrides as a zip archive, deflating file content.
+    pub(crate) fn write_zip<W: Write + Seek>(&self, writer: W) -> Result<(), ShadowError> {
+        let (manifest, overrides) = self.archive_entries()?;
+        let mut zip = zip::ZipWriter::new(writer);
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        
+        zip.start_file(MANIFEST_NAME, options.last_modified_time(zip_time(SystemTime::now())))
+            .map_err(zip_error)?;
+        zip.write_all(&encode_manifest(&manifest)?).map_err(io_error)?;
+        
+        for (name, entry) in &overrides {
+            let options = options
+                .unix_permissions(entry.metadata.permissions.to_unix_mode())
+                .last_modified_time(zip_time(entry.metadata.modified));
+            if entry.kind == EntryKind::Directory {
+                zip.add_directory(name.as_str(), options).map_err(zip_error)?;
+            } else {
+                zip.start_file(name.as_str(), options).map_err(zip_error)?;
+                zip.write_all(&entry.data).map_err(io_error)?;
+            }
+        }
+        let mut writer = zip.finish().map_err(zip_error)?;
+        writer.flush().map_err(io_error)
+    }
+    
+    /// Imports the overrides of a tar archive written by [`OverrideStore::write_tar`].
+    pub(crate) fn read_tar<R: Read>(&self, reader: R) -> Result<(), ShadowError> {
+        let mut contents = ArchiveContents::default();
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries().map_err(io_error)? {
+            let mut entry = entry.map_err(io_error)?;
+            let name = entry.path().map_err(io_error)?.to_string_lossy().into_owned();
+            let is_directory = entry.header().entry_type().is_dir();
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut data).map_err(io_error)?;
+            contents.add(&name, is_directory, data)?;
+        }
+        self.import_archive(contents)
+    }
+    
+    /// Imports the overrides of a zip archive written by [`OverrideStore::write_zip`].
+    pub(crate) fn read_zip<R: Read + Seek>(&self, reader: R) -> Result<(), ShadowError> {
+        let mut contents = ArchiveContents::default();
+        let mut archive = zip::ZipArchive::new(reader).map_err(zip_error)?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).map_err(zip_error)?;
+            let name = file.name().to_string();
+            let is_directory = file.is_dir();
+            let mut data = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut data).map_err(io_error)?;
+            contents.add(&name, is_directory, data)?;
+        }
+        self.import_archive(contents)
+    }
+    
+    /// Collects the manifest and the archive member of each override, sorted
+    /// by path so that directories precede their contents.
+    fn archive_entries(&self) -> Result<(ArchiveManifest, BTreeMap<String, ArchivedOverride>), ShadowError> {
+        let mut manifest = ArchiveManifest { version: ARCHIVE_FORMAT_VERSION, entries: Vec::new() };
+        let mut overrides = BTreeMap::new();
+        for entry in self.sorted_entries()? {
+            let kind = match entry.content {
+                OverrideContent::File { .. } => EntryKind::File,
+                OverrideContent::Directory { .. } => EntryKind::Directory,
+                OverrideContent::Deleted => EntryKind::Deleted,
+            };
+            manifest.entries.push(ManifestEntry {
+                path: entry.path.clone(),
+                kind,
+                metadata: entry.override_metadata.clone(),
+                original_metadata: entry.original_metadata.clone(),
+            });
+            
+            // Tombstones and the root directory live in the manifest only
+            let Some(name) = member_name(&entry.path) else {
+                continue;
+            };
+            if kind == EntryKind::Deleted {
+                continue;
+            }
+            let data = entry.get_file_data()?.unwrap_or_default();
+            overrides.insert(name, ArchivedOverride { kind, metadata: entry.override_metadata.clone(), data });
+        }
+        Ok((manifest, overrides))
+    }
+    
+    /// Applies the overrides read from an archive as one transaction.
+    fn import_archive(&self, mut contents: ArchiveContents) -> Result<(), ShadowError> {
+        let manifest = contents.manifest.take().ok_or_else(|| ShadowError::InvalidConfiguration {
+            message: format!("Archive has no {}", MANIFEST_NAME),
+        })?;
+        if manifest.version != ARCHIVE_FORMAT_VERSION {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!(
+                    "Unsupported archive format version {} (expected {})",
+                    manifest.version, ARCHIVE_FORMAT_VERSION
+                ),
+            });
+        }
+        
+        let mut staged = Vec::new();
+        for listed in manifest.entries {
+            let (content, metadata) = match listed.kind {
+                EntryKind::File => {
+                    let data = contents.files.remove(&listed.path).ok_or_else(|| ShadowError::InvalidConfiguration {
+                        message: format!("Archive lists {} but does not contain it", listed.path),
+                    })?;
+                    let (content, _) = self.file_override(data, listed.original_metadata.as_ref());
+                    (content, listed.metadata)
+                }
+                EntryKind::Directory => (OverrideStore::directory_override(None).0, listed.metadata),
+                EntryKind::Deleted => (OverrideContent::Deleted, listed.metadata),
+            };
+            staged.push(OverrideEntry::new(listed.path, content, listed.original_metadata, metadata));
+        }
+        
+        // Members the manifest does not list are imported as new overrides
+        let mut extra_files: Vec<(ShadowPath, Bytes)> = contents.files.into_iter().collect();
+        extra_files.sort_by(|a, b| a.0.as_path().cmp(b.0.as_path()));
+        self.transaction(|tx| {
+            for entry in staged {
+                tx.stage_entry(Arc::new(entry));
+            }
+            for directory in contents.directories {
+                tx.create_directory_hierarchy(&directory);
+            }
+            for (path, data) in extra_files {
+                if let Some(parent) = path.parent() {
+                    tx.create_directory_hierarchy(&parent);
+                }
+                tx.insert_file(path, data, None);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl ArchiveContents {
+    /// Records one archive member.
+    fn add(&mut self, name: &str, is_directory: bool, data: Vec<u8>) -> Result<(), ShadowError> {
+        if name == MANIFEST_NAME {
+            let manifest = serde_json::from_slice(&data).map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Invalid archive manifest: {}", e),
+            })?;
+            self.manifest = Some(manifest);
+            return Ok(());
+        }
+        let Some(path) = shadow_path(name)? else {
+            return Ok(());
+        };
+        if is_directory {
+            self.directories.push(path);
+        } else {
+            self.files.insert(path, Bytes::from(data));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the archive member name of `path`, or `None` for the root.
+fn member_name(path: &ShadowPath) -> Option<String> {
+    let names: Vec<String> = path.as_path()
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    (!names.is_empty()).then(|| format!("{}/{}", OVERRIDES_DIR, names.join("/")))
+}
+
+/// Returns the shadow path of an archive member under `overrides/`.
+///
+/// Members outside `overrides/` are ignored; members that would escape it
+/// are refused, so an archive cannot place overrides above its root.
+fn shadow_path(name: &str) -> Result<Option<ShadowPath>, ShadowError> {
+    let Some(relative) = name.strip_prefix(OVERRIDES_DIR).and_then(|rest| rest.strip_prefix('/')) else {
+        return Ok(None);
+    };
+    let mut parts = Vec::new();
+    for part in relative.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                return Err(ShadowError::InvalidConfiguration {
+                    message: format!("Archive member {} escapes the archive", name),
+                });
+            }
+            part => parts.push(part),
+        }
+    }
+    Ok((!parts.is_empty()).then(|| ShadowPath::from(format!("/{}", parts.join("/")).as_str())))
+}
+
+fn encode_manifest(manifest: &ArchiveManifest) -> Result<Vec<u8>, ShadowError> {
+    serde_json::to_vec_pretty(manifest).map_err(|e| ShadowError::InvalidConfiguration {
+        message: format!("Failed to serialize archive manifest: {}", e),
+    })
+}
+
+pub(super) fn tar_header(entry_type: tar::EntryType, mode: u32, modified: SystemTime, size: usize) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode);
+    header.set_mtime(modified.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs());
+    header.set_size(size as u64);
+    header
+}
+
+/// Converts `time` to the DOS timestamp zip stores, which covers 1980 to 2107.
+pub(super) fn zip_time(time: SystemTime) -> zip::DateTime {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    
+    // Civil date from days since the epoch, after Howard Hinnant's days_from_civil
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    
+    u16::try_from(year)
+        .ok()
+        .and_then(|year| {
+            zip::DateTime::from_date_and_time(
+                year,
+                month as u8,
+                day as u8,
+                (secs_of_day / 3_600) as u8,
+                (secs_of_day % 3_600 / 60) as u8,
+                (secs_of_day % 60) as u8,
+            )
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+pub(super) fn io_error(source: std::io::Error) -> ShadowError {
+    ShadowError::IoError { source }
+}
+
+pub(super) fn zip_error(error: zip::result::ZipError) -> ShadowError {
+    match error {
+        zip::result::ZipError::Io(source) => ShadowError::IoError { source },
+        other => ShadowError::InvalidConfiguration {
+            message: format!("Invalid zip archive: {}", other),
+        },
+    }
+}
+
+/// Wraps a reader that may not seek, for zip's central directory.
+pub(crate) fn seekable<R: Read>(mut reader: R) -> Result<Cursor<Vec<u8>>, ShadowError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).map_err(io_error)?;
+    Ok(Cursor::new(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::ExportFormat;
+    use crate::types::FilePermissions;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    fn workspace() -> OverrideStore {
+        let store = OverrideStore::with_defaults();
+        store.create_directory_hierarchy(&path("/src/bin")).unwrap();
+        store.insert_file(path("/src/bin/run.sh"), Bytes::from("#!/bin/sh\nexit 0\n"), None).unwrap();
+        store.insert_file(path("/README"), Bytes::from("fixture"), None).unwrap();
+        store.mark_deleted(path("/src/legacy.rs")).unwrap();
+        
+        let mut script = (*store.get(&path("/src/bin/run.sh")).unwrap()).clone();
+        script.override_metadata.permissions = FilePermissions::from_unix_mode(0o755);
+        store.restore_entry(script).unwrap();
+        store
+    }
+    
+    fn round_trip(format: ExportFormat) {
+        let store = workspace();
+        let mut archive = Vec::new();
+        store.export(&mut archive, format).unwrap();
+        
+        let mut imported = OverrideStore::with_defaults();
+        imported.import(archive.as_slice(), format).unwrap();
+        
+        let script = imported.get(&path("/src/bin/run.sh")).unwrap();
+        assert_eq!(script.get_file_data().unwrap().unwrap(), "#!/bin/sh\nexit 0\n");
+        assert_eq!(script.override_metadata.permissions.to_unix_mode(), 0o755);
+        assert!(imported.is_deleted(&path("/src/legacy.rs")));
+        assert!(matches!(imported.get(&path("/src/bin")).unwrap().content, OverrideContent::Directory { .. }));
+        assert_eq!(imported.entry_count(), store.entry_count());
+    }
+    
+    #[test]
+    fn test_tar_round_trip() {
+        round_trip(ExportFormat::Tar);
+        
+        let mut archive = Vec::new();
+        workspace().export(&mut archive, ExportFormat::Tar).unwrap();
+        let mut names: Vec<String> = tar::Archive::new(archive.as_slice())
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, [
+            "manifest.json",
+            "overrides/README",
+            "overrides/src/",
+            "overrides/src/bin/",
+            "overrides/src/bin/run.sh",
+        ]);
+    }
+    
+    #[test]
+    fn test_zip_round_trip() {
+        round_trip(ExportFormat::Zip);
+    }
+    
+    #[test]
+    fn test_import_unlisted_members() {
+        let manifest = encode_manifest(&ArchiveManifest { version: ARCHIVE_FORMAT_VERSION, entries: Vec::new() }).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar_header(tar::EntryType::Regular, 0o644, SystemTime::now(), manifest.len());
+        builder.append_data(&mut header, MANIFEST_NAME, manifest.as_slice()).unwrap();
+        let mut header = tar_header(tar::EntryType::Regular, 0o644, SystemTime::now(), 5);
+        builder.append_data(&mut header, "overrides/notes/todo.txt", b"added".as_slice()).unwrap();
+        let archive = builder.into_inner().unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        store.read_tar(archive.as_slice()).unwrap();
+        assert_eq!(store.get(&path("/notes/todo.txt")).unwrap().get_file_data().unwrap().unwrap(), "added");
+        assert!(store.get(&path("/notes")).is_some());
+        
+        // Members cannot escape the overrides directory
+        assert_eq!(shadow_path("overrides/./a//b").unwrap(), Some(path("/a/b")));
+        assert_eq!(shadow_path("notes.txt").unwrap(), None);
+        assert!(shadow_path("overrides/../../etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Inserting many overrides at once.
+//!
+//! [`OverrideStore::insert_batch`] checks every override of a batch before
+//! doing any work for it, builds the ones that pass on the rayon pool, where
+//! large files are compressed in parallel, reserves the memory of the whole
+//! batch in one go and then stores the entries taking each shard's lock
+//! once. Each override succeeds or fails on its own: the result of every
+//! one is returned, in the order they were given.
+//!
+//! File content already compressed is stored as given; only its size is
+//! read from it. Without the `compression` feature, overrides are built one
+//! after the other and compressed content cannot be inserted.
+
+use crate::error::ShadowError;
+use crate::override_store::{calculate_entry_size, compression, OverrideContent, OverrideEntry, OverrideStore};
+use crate::quota::QuotaUsage;
+use crate::types::ShadowPath;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+impl OverrideStore {
+    /// Inserts many overrides, returning the result of each in order.
+    ///
+    /// Overrides are checked like single inserts: against the read-only
+    /// flag, access rules, path limits and quotas. One that fails a check is
+    /// left out and the rest are stored. Should the memory of the whole
+    /// batch not be available at once, each override reserves its own, and
+    /// the ones that do not fit fail.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use shadowfs_core::override_store::{OverrideContent, OverrideStore};
+    /// # use shadowfs_core::types::ShadowPath;
+    /// # use bytes::Bytes;
+    /// let store = OverrideStore::with_defaults();
+    /// let results = store.insert_batch(vec![
+    ///     (ShadowPath::from("/logs"), OverrideContent::Directory { entries: Vec::new() }),
+    ///     (ShadowPath::from("/logs/app.log"), OverrideContent::File {
+    ///         data: Bytes::from("started"),
+    ///         content_hash: [0; 32],
+    ///         is_compressed: false,
+    ///     }),
+    /// ]);
+    /// assert!(results.iter().all(Result::is_ok));
+    /// ```
+    pub fn insert_batch(&self, entries: Vec<(ShadowPath, OverrideContent)>) -> Vec<Result<(), ShadowError>> {
+        let mut results: Vec<Result<(), ShadowError>> = entries.iter().map(|_| Ok(())).collect();
+        
+        // Cheap checks first, so nothing refused is compressed
+        let mut checked = Vec::with_capacity(entries.len());
+        for (index, (path, content)) in entries.into_iter().enumerate() {
+            let path = self.stored_spelling(path);
+            match self.check_insert(&path, &content) {
+                Ok(()) => checked.push((index, path, content)),
+                Err(error) => results[index] = Err(error),
+            }
+        }
+        
+        let mut built = Vec::with_capacity(checked.len());
+        for (index, entry) in build_all(checked, |(index, path, content)| (index, self.batch_entry(path, content))) {
+            match entry {
+                Ok(entry) => built.push((index, entry)),
+                Err(error) => results[index] = Err(error),
+            }
+        }
+        
+        let groups: Vec<(usize, Vec<OverrideEntry>)> = self.admit_within_quota(built, &mut results)
+            .into_iter()
+            .map(|(index, entry)| (index, self.linked_entries(entry)))
+            .collect();
+        
+        let needed = self.batch_memory(&groups);
+        let mut reserved = match self.relieve_pressure(needed).and_then(|()| self.reserve_memory(needed, None)) {
+            Ok(()) => needed,
+            Err(_) => 0,
+        };
+        
+        {
+            // Hold the WAL guard until the entries are visible, as single inserts do
+            #[cfg(feature = "persistence")]
+            let wal = self.wal.read().unwrap();
+            
+            let mut pairs = Vec::new();
+            let mut stored = Vec::new();
+            for (index, entries) in groups {
+                for entry in entries {
+                    let entry = Arc::new(entry);
+                    #[cfg(feature = "persistence")]
+                    if let Err(error) = self.log_entry(wal.as_deref(), &entry) {
+                        results[index] = Err(error);
+                        break;
+                    }
+                    let spilled = self.take_spilled(&entry.path);
+                    self.drop_delta(&entry.path);
+                    pairs.push((entry.path.clone(), Arc::clone(&entry)));
+                    stored.push((index, entry, spilled));
+                }
+            }
+            
+            let residents = self.entries.insert_many(pairs);
+            for ((index, entry, spilled), resident) in stored.into_iter().zip(residents) {
+                let finished = self.finish_store(entry, resident, spilled, Some(&mut reserved));
+                if let (Err(error), Ok(())) = (finished, &results[index]) {
+                    results[index] = Err(error);
+                }
+            }
+        }
+        self.memory_tracker.release(reserved);
+        
+        results
+    }
+    
+    /// Builds the entry of one override of a batch, compressing and
+    /// deduplicating file content the way [`OverrideStore::insert_file`] does.
+    fn batch_entry(&self, path: ShadowPath, content: OverrideContent) -> Result<OverrideEntry, ShadowError> {
+        let (content, metadata) = match content {
+            OverrideContent::File { data, is_compressed: false, .. } => self.file_override(data, None),
+            OverrideContent::File { data, is_compressed: true, .. } => {
+                let size = compression::decompressed_size(&data)
+                    .map_err(|source| ShadowError::IoError { source })?;
+                let (content_hash, data) = self.content_dedup.store_content(data);
+                let content = OverrideContent::File {
+                    data,
+                    content_hash,
+                    is_compressed: true,
+                };
+                (content, Self::file_metadata(size, None))
+            }
+            OverrideContent::Directory { .. } => Self::directory_override(None),
+            OverrideContent::Deleted => Self::tombstone_override(),
+        };
+        Ok(OverrideEntry::new(path, content, None, metadata))
+    }
+    
+    /// Returns the built overrides that fit the quotas, failing the rest.
+    ///
+    /// When the whole batch fits, it is checked once; otherwise overrides
+    /// are admitted in order while they still fit.
+    fn admit_within_quota(
+        &self,
+        built: Vec<(usize, OverrideEntry)>,
+        results: &mut [Result<(), ShadowError>],
+    ) -> Vec<(usize, OverrideEntry)> {
+        // A write reaches every hard link of the path, and each counts
+        let usages: Vec<Vec<(ShadowPath, QuotaUsage)>> = built.iter()
+            .map(|(_, entry)| {
+                let usage = QuotaUsage::of_entry(entry);
+                let written = match entry.content {
+                    OverrideContent::File { .. } => self.links.members(&entry.path),
+                    _ => vec![entry.path.clone()],
+                };
+                written.into_iter().map(|path| (path, usage)).collect()
+            })
+            .collect();
+        let all = usages.iter().flatten().map(|(path, usage)| (path, *usage));
+        if self.check_quota(all).is_ok() {
+            return built;
+        }
+        
+        let mut admitted = Vec::new();
+        let mut admitted_usage: Vec<&(ShadowPath, QuotaUsage)> = Vec::new();
+        for ((index, entry), usage) in built.into_iter().zip(&usages) {
+            let changes = admitted_usage.iter().copied().chain(usage).map(|(path, usage)| (path, *usage));
+            match self.check_quota(changes) {
+                Ok(()) => {
+                    admitted_usage.extend(usage);
+                    admitted.push((index, entry));
+                }
+                Err(error) => results[index] = Err(error),
+            }
+        }
+        admitted
+    }
+    
+    /// Returns the memory the entries add to the store: the size of each
+    /// one stored at a path that holds no resident entry by then.
+    fn batch_memory(&self, groups: &[(usize, Vec<OverrideEntry>)]) -> usize {
+        let mut stored: HashSet<&ShadowPath> = HashSet::new();
+        groups.iter()
+            .flat_map(|(_, entries)| entries)
+            .filter(|entry| stored.insert(&entry.path) && !self.entries.contains_key(&entry.path))
+            .map(calculate_entry_size)
+            .sum()
+    }
+}
+
+/// Builds the overrides of a batch on the rayon pool.
+#[cfg(feature = "compression")]
+fn build_all<T: Send, U: Send>(items: Vec<T>, build: impl Fn(T) -> U + Sync + Send) -> Vec<U> {
+    use rayon::prelude::*;
+    items.into_par_iter().map(build).collect()
+}
+
+/// Builds the overrides of a batch one after the other; without the
+/// `compression` feature there is nothing worth doing in parallel.
+#[cfg(not(feature = "compression"))]
+fn build_all<T, U>(items: Vec<T>, build: impl Fn(T) -> U) -> Vec<U> {
+    items.into_iter().map(build).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    fn file(data: Bytes, is_compressed: bool) -> OverrideContent {
+        OverrideContent::File { data, content_hash: [0; 32], is_compressed }
+    }
+    
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_batch_results_are_per_entry() {
+        use crate::quota::Quota;
+        
+        let store = OverrideStore::with_defaults();
+        store.set_quota(Quota { max_file_size: Some(4 << 20), ..Quota::default() });
+        let large = Bytes::from(vec![b'x'; 2 << 20]);
+        let packed = compression::compress(&[b'y'; 2 << 20]).unwrap();
+        
+        let results = store.insert_batch(vec![
+            (ShadowPath::from("/dir"), OverrideContent::Directory { entries: Vec::new() }),
+            (ShadowPath::from("/dir/a.txt"), file(Bytes::from("first"), false)),
+            (ShadowPath::from("/dir/large.bin"), file(large.clone(), false)),
+            (ShadowPath::from("/dir/huge.bin"), file(Bytes::from(vec![0; 5 << 20]), false)),
+            (ShadowPath::from("/dir/packed.bin"), file(packed.clone(), true)),
+            (ShadowPath::from("/dir/a.txt"), file(Bytes::from("again"), false)),
+            (ShadowPath::from("/gone"), OverrideContent::Deleted),
+        ]);
+        
+        let failed: Vec<usize> = results.iter().enumerate().filter(|(_, result)| result.is_err()).map(|(index, _)| index).collect();
+        assert_eq!(failed, [3]);
+        assert!(matches!(results[3], Err(ShadowError::QuotaExceeded { .. })));
+        assert!(!store.exists(&ShadowPath::from("/dir/huge.bin")));
+        
+        let read = |path: &str| store.get(&ShadowPath::from(path)).unwrap().get_file_data().unwrap().unwrap();
+        assert_eq!(read("/dir/a.txt"), "again");
+        assert_eq!(read("/dir/large.bin"), large);
+        let stored = store.get(&ShadowPath::from("/dir/packed.bin")).unwrap();
+        assert!(matches!(&stored.content, OverrideContent::File { data, is_compressed: true, .. } if *data == packed));
+        assert_eq!(stored.override_metadata.size, 2 << 20);
+        assert!(store.is_deleted(&ShadowPath::from("/gone")));
+        
+        // The path inserted twice is accounted once
+        let held: usize = store.entries.iter().map(|(_, entry)| calculate_entry_size(&entry)).sum();
+        assert_eq!(store.memory_stats().0, held);
+    }
+    
+    #[test]
+    fn test_batch_writes_reach_hard_links() {
+        let store = OverrideStore::with_defaults();
+        let (a, b) = (ShadowPath::from("/a"), ShadowPath::from("/b"));
+        store.insert_file(a.clone(), Bytes::from("old"), None).unwrap();
+        store.link(&a, b.clone()).unwrap();
+        
+        let results = store.insert_batch(vec![(a, file(Bytes::from("new"), false))]);
+        assert!(results[0].is_ok());
+        assert_eq!(store.get(&b).unwrap().get_file_data().unwrap().unwrap(), "new");
+    }
+}
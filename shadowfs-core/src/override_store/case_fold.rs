@@ -0,0 +1,242 @@
+//! Case-insensitive lookups.
+//!
+//! A store serving a mount with Windows or macOS semantics resolves paths
+//! whatever their casing while every override keeps the casing it was
+//! created with. A folded-key index maps the case-folded form of each held
+//! path, and of each directory above one, to its stored spelling, so a
+//! lookup through another casing costs one more map probe instead of a scan
+//! over the keys.
+//!
+//! Exact spellings always win: a store switched to case-insensitive mode
+//! while holding paths that differ only in case keeps each of them
+//! reachable under its own spelling, and other casings resolve to one of
+//! them.
+//!
+//! The index is only kept while the store is case-insensitive, so
+//! case-sensitive stores pay nothing for it.
+
+use crate::override_store::OverrideStore;
+use crate::types::ShadowPath;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+/// Stored spelling of a folded path.
+#[derive(Debug)]
+struct Spelling {
+    path: ShadowPath,
+    /// Held paths at or below this one
+    holds: usize,
+}
+
+/// Folded-key index of a store, kept while the store is case-insensitive.
+#[derive(Debug, Default)]
+pub(crate) struct CaseIndex {
+    insensitive: AtomicBool,
+    spellings: RwLock<HashMap<ShadowPath, Spelling>>,
+}
+
+impl CaseIndex {
+    pub(crate) fn is_insensitive(&self) -> bool {
+        self.insensitive.load(Ordering::Acquire)
+    }
+    
+    /// Records a newly held path and the directories above it.
+    pub(crate) fn record(&self, path: &ShadowPath) {
+        if !self.is_insensitive() {
+            return;
+        }
+        Self::record_locked(&mut self.spellings.write().unwrap(), path);
+    }
+    
+    fn record_locked(spellings: &mut HashMap<ShadowPath, Spelling>, path: &ShadowPath) {
+        let mut current = Some(path.clone());
+        while let Some(path) = current {
+            current = path.parent();
+            spellings.entry(path.to_case_folded())
+                .or_insert_with(|| Spelling { path, holds: 0 })
+                .holds += 1;
+        }
+    }
+    
+    /// Drops a path that is no longer held.
+    pub(crate) fn forget(&self, path: &ShadowPath) {
+        if !self.is_insensitive() {
+            return;
+        }
+        let mut spellings = self.spellings.write().unwrap();
+        let mut current = Some(path.clone());
+        while let Some(path) = current {
+            let folded = path.to_case_folded();
+            if let Some(spelling) = spellings.get_mut(&folded) {
+                spelling.holds -= 1;
+                if spelling.holds == 0 {
+                    spellings.remove(&folded);
+                }
+            }
+            current = path.parent();
+        }
+    }
+    
+    /// Stored spelling of the path `path` folds to, if any.
+    fn spelling(&self, path: &ShadowPath) -> Option<ShadowPath> {
+        self.spellings.read().unwrap().get(&path.to_case_folded()).map(|spelling| spelling.path.clone())
+    }
+    
+    /// Switches the mode, indexing `held` when turning case-insensitive.
+    fn set_insensitive(&self, insensitive: bool, held: impl FnOnce() -> Vec<ShadowPath>) {
+        let mut spellings = self.spellings.write().unwrap();
+        if self.insensitive.swap(insensitive, Ordering::AcqRel) == insensitive {
+            return;
+        }
+        spellings.clear();
+        if insensitive {
+            for path in held() {
+                Self::record_locked(&mut spellings, &path);
+            }
+        }
+    }
+}
+
+impl OverrideStore {
+    /// Makes lookups case-sensitive, or case-insensitive with the casing of
+    /// stored paths preserved.
+    ///
+    /// While case-insensitive, `get`, `exists`, `is_deleted`, `remove` and
+    /// the directory listing find an override through any casing of its
+    /// path, and writes through another casing replace the override in
+    /// place rather than adding a second one. New paths take the casing of
+    /// the held directories above them. Stores are case-sensitive until
+    /// switched; [`MountManager`](crate::mount_manager::MountManager) sets
+    /// the mode from [`MountOptions::case_sensitive`](crate::types::mount::MountOptions).
+    pub fn set_case_sensitive(&self, case_sensitive: bool) {
+        self.case_index.set_insensitive(!case_sensitive, || self.all_paths());
+    }
+    
+    /// Returns true unless the store was made case-insensitive.
+    pub fn is_case_sensitive(&self) -> bool {
+        !self.case_index.is_insensitive()
+    }
+    
+    /// Returns the stored spelling of `path` when a case-insensitive store
+    /// holds it under another casing.
+    pub(crate) fn held_spelling(&self, path: &ShadowPath) -> Option<ShadowPath> {
+        if !self.case_index.is_insensitive() || self.entries.contains_key(path) || self.is_spilled(path) {
+            return None;
+        }
+        self.case_index.spelling(path).filter(|stored| stored != path)
+    }
+    
+    /// Returns the spelling a write to `path` is stored under: the held
+    /// spelling of the path itself, or else `path` under the held spelling
+    /// of its nearest indexed ancestor.
+    pub(crate) fn stored_spelling(&self, path: ShadowPath) -> ShadowPath {
+        if !self.case_index.is_insensitive() || self.entries.contains_key(&path) || self.is_spilled(&path) {
+            return path;
+        }
+        
+        let mut names = Vec::new();
+        let mut current = Some(path.clone());
+        while let Some(ancestor) = current {
+            if let Some(stored) = self.case_index.spelling(&ancestor) {
+                return stored.join(names.iter().rev().collect::<PathBuf>());
+            }
+            names.extend(ancestor.file_name());
+            current = ancestor.parent();
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    fn insensitive_store() -> OverrideStore {
+        let store = OverrideStore::with_defaults();
+        store.set_case_sensitive(false);
+        store
+    }
+    
+    #[test]
+    fn test_lookup_preserves_casing() {
+        let store = insensitive_store();
+        store.insert_directory(ShadowPath::from("/Docs"), None).unwrap();
+        store.insert_file(ShadowPath::from("/Docs/ReadMe.md"), Bytes::from("v1"), None).unwrap();
+        
+        let entry = store.get(&ShadowPath::from("/docs/README.MD")).unwrap();
+        assert_eq!(entry.path, ShadowPath::from("/Docs/ReadMe.md"));
+        assert!(store.exists(&ShadowPath::from("/DOCS")));
+        
+        // Writes through another casing replace the override in place
+        store.insert_file(ShadowPath::from("/docs/readme.md"), Bytes::from("v2"), None).unwrap();
+        store.insert_file(ShadowPath::from("/DOCS/New.txt"), Bytes::from("new"), None).unwrap();
+        assert_eq!(store.entry_count(), 3);
+        assert!(store.entries.contains_key(&ShadowPath::from("/Docs/New.txt")));
+        let mut children = store.get_directory_children(&ShadowPath::from("/docs"));
+        children.sort();
+        assert_eq!(children, vec!["New.txt", "ReadMe.md"]);
+        
+        store.mark_deleted(ShadowPath::from("/docs/new.TXT")).unwrap();
+        assert!(store.is_deleted(&ShadowPath::from("/Docs/New.txt")));
+        assert!(store.remove(&ShadowPath::from("/DOCS/NEW.TXT")).is_some());
+        assert!(!store.exists(&ShadowPath::from("/Docs/New.txt")));
+        
+        // Directories above held paths resolve new paths but are not held themselves
+        store.insert_file(ShadowPath::from("/Tmp/a.txt"), Bytes::from("a"), None).unwrap();
+        assert!(store.get(&ShadowPath::from("/tmp/A.TXT")).is_some());
+        assert!(!store.exists(&ShadowPath::from("/tmp")));
+        assert!(store.case_index.spelling(&ShadowPath::from("/docs/new.txt")).is_none());
+    }
+    
+    #[test]
+    fn test_every_write_path_keeps_the_stored_casing() {
+        let store = insensitive_store();
+        store.insert_file(ShadowPath::from("/Docs/ReadMe.md"), Bytes::from("v1"), None).unwrap();
+        
+        store.transaction(|tx| {
+            tx.insert_file(ShadowPath::from("/docs/readme.md"), Bytes::from("v2"), None);
+            tx.insert_file(ShadowPath::from("/DOCS/Guide.md"), Bytes::from("guide"), None);
+            Ok(())
+        }).unwrap();
+        assert_eq!(store.entry_count(), 2);
+        assert!(store.entries.contains_key(&ShadowPath::from("/Docs/Guide.md")));
+        let readme = store.get(&ShadowPath::from("/Docs/ReadMe.md")).unwrap();
+        assert_eq!(readme.get_file_data().unwrap().unwrap(), Bytes::from("v2"));
+        
+        // Merged overrides land on the held spelling, and differ from it
+        let other = OverrideStore::with_defaults();
+        other.insert_file(ShadowPath::from("/docs/README.md"), Bytes::from("v3"), None).unwrap();
+        assert_eq!(store.merge_conflicts(&other).unwrap().len(), 1);
+        store.merge(&other, crate::override_store::MergeStrategy::TheirsWins).unwrap();
+        assert_eq!(store.entry_count(), 2);
+        let readme = store.get(&ShadowPath::from("/Docs/ReadMe.md")).unwrap();
+        assert_eq!(readme.get_file_data().unwrap().unwrap(), Bytes::from("v3"));
+        
+        store.link(&ShadowPath::from("/docs/GUIDE.md"), ShadowPath::from("/docs/manual.md")).unwrap();
+        assert!(store.entries.contains_key(&ShadowPath::from("/Docs/manual.md")));
+        assert_eq!(store.link_count(&ShadowPath::from("/Docs/Guide.md")), 2);
+        assert_eq!(store.entry_count(), 3);
+    }
+    
+    #[test]
+    fn test_switching_modes() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("lower"), None).unwrap();
+        store.insert_file(ShadowPath::from("/A.txt"), Bytes::from("upper"), None).unwrap();
+        store.insert_file(ShadowPath::from("/Notes.txt"), Bytes::from("notes"), None).unwrap();
+        assert!(store.get(&ShadowPath::from("/notes.txt")).is_none());
+        
+        store.set_case_sensitive(false);
+        assert!(!store.is_case_sensitive());
+        assert!(store.get(&ShadowPath::from("/notes.txt")).is_some());
+        // Paths differing only in case stay reachable under their own spelling
+        let upper = store.get(&ShadowPath::from("/A.txt")).unwrap();
+        assert_eq!(upper.get_file_data().unwrap().unwrap(), Bytes::from("upper"));
+        
+        store.set_case_sensitive(true);
+        assert!(store.get(&ShadowPath::from("/notes.txt")).is_none());
+    }
+}
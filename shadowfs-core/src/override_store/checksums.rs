@@ -0,0 +1,347 @@
+//! Checksum manifests of the merged view of a mount.
+//!
+//! [`OverrideStore::checksum_manifest`] lists every file and symlink a
+//! mount shows, with its size and BLAKE3 hash, walking the merged view the
+//! way [`OverrideStore::export_merged`] does. A consumer of an artifact
+//! built in the sandbox checks it against the manifest with
+//! [`ChecksumManifest::verify_directory`] after extracting it, or with
+//! `b3sum --check` on the output of [`ChecksumManifest::to_b3sum`].
+//!
+//! A manifest can be signed with a [`ManifestKey`]. The signature is a keyed
+//! BLAKE3 hash of the entries, so checking it takes the same key: it shows
+//! that the manifest was produced by someone holding the key and has not
+//! been edited since, not who produced it.
+
+use crate::error::{unauthorized, ShadowError};
+use crate::override_store::merged_export::{ArchiveSink, ExactReader};
+use crate::override_store::OverrideStore;
+use crate::progress::{NoProgress, Progress};
+use crate::types::{FileMetadata, ShadowPath};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Version of the manifest written by [`OverrideStore::checksum_manifest`].
+pub const CHECKSUM_MANIFEST_VERSION: u32 = 1;
+
+/// Context string the signing key is derived under.
+const KEY_CONTEXT: &str = "shadowfs 2024 checksum manifest signing key";
+
+/// Sizes and hashes of the files of a merged view.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumManifest {
+    /// Format version, [`CHECKSUM_MANIFEST_VERSION`] when written
+    pub version: u32,
+    
+    /// One entry per file and symlink, sorted by path
+    pub entries: Vec<ChecksumEntry>,
+    
+    /// Keyed BLAKE3 hash of the entries, hex encoded, if signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// A file or symlink listed in a checksum manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumEntry {
+    /// Path in the merged view
+    pub path: ShadowPath,
+    
+    /// Size of the file in bytes, or of the target path of a symlink
+    pub size: u64,
+    
+    /// BLAKE3 hash of the file, or of the target path of a symlink, hex encoded
+    pub blake3: String,
+    
+    /// Target of the symlink, if the entry is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<PathBuf>,
+}
+
+/// A difference between a manifest and the directory checked against it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumMismatch {
+    /// Listed in the manifest but missing from the directory
+    Missing(ShadowPath),
+    /// In the directory but not listed in the manifest
+    Unexpected(ShadowPath),
+    /// Present in both with a different size, hash or kind
+    Changed(ShadowPath),
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(path) => write!(f, "missing: {}", path),
+            Self::Unexpected(path) => write!(f, "unexpected: {}", path),
+            Self::Changed(path) => write!(f, "changed: {}", path),
+        }
+    }
+}
+
+/// Key signing checksum manifests.
+#[derive(Clone)]
+pub struct ManifestKey([u8; 32]);
+
+impl ManifestKey {
+    /// Derives a key from `secret`, such as the content of a key file.
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self(blake3::derive_key(KEY_CONTEXT, secret))
+    }
+}
+
+impl fmt::Debug for ManifestKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ManifestKey(..)")
+    }
+}
+
+impl ChecksumManifest {
+    /// Signs the manifest with `key`, replacing any earlier signature.
+    pub fn sign(&mut self, key: &ManifestKey) {
+        self.signature = Some(self.keyed_hash(key).to_hex().to_string());
+    }
+    
+    /// Checks that the manifest is signed with `key` and unchanged since.
+    ///
+    /// # Errors
+    /// [`ShadowError::Unauthorized`] if the manifest is unsigned or the
+    /// signature does not match.
+    pub fn verify_signature(&self, key: &ManifestKey) -> Result<(), ShadowError> {
+        let signature = self.signature.as_deref()
+            .ok_or_else(|| unauthorized("verify checksum manifest", "the manifest is not signed"))?;
+        let signature = blake3::Hash::from_hex(signature)
+            .map_err(|_| unauthorized("verify checksum manifest", "the signature is malformed"))?;
+        // Hash equality is constant time
+        if signature != self.keyed_hash(key) {
+            return Err(unauthorized("verify checksum manifest", "the signature does not match"));
+        }
+        Ok(())
+    }
+    
+    /// Formats the files of the manifest the way `b3sum` prints them, one
+    /// `<hash>  <path>` line each with paths relative to the root, for
+    /// checking with `b3sum --check`. Symlinks are left out.
+    pub fn to_b3sum(&self) -> String {
+        self.entries
+            .iter()
+            .filter(|entry| entry.symlink_target.is_none())
+            .map(|entry| format!("{}  {}\n", entry.blake3, relative_name(&entry.path)))
+            .collect()
+    }
+    
+    /// Checks the files and symlinks below `root` against the manifest,
+    /// such as an archive of the merged view after extracting it.
+    /// Directories are not compared.
+    ///
+    /// # Returns
+    /// Every difference found, sorted by path; empty if the directory matches
+    pub fn verify_directory(&self, root: &Path) -> Result<Vec<ChecksumMismatch>, ShadowError> {
+        let mut found = HashMap::new();
+        let mut pending = vec![ShadowPath::from("/")];
+        while let Some(directory) = pending.pop() {
+            let host = root.join(relative_name(&directory));
+            for child in std::fs::read_dir(&host)? {
+                let child = child?;
+                let path = directory.join(child.file_name());
+                let file_type = child.file_type()?;
+                if file_type.is_dir() {
+                    pending.push(path);
+                } else if file_type.is_symlink() {
+                    let target = std::fs::read_link(child.path())?;
+                    found.insert(path.clone(), symlink_entry(path, target));
+                } else if file_type.is_file() {
+                    let mut hasher = blake3::Hasher::new();
+                    let size = std::io::copy(&mut std::fs::File::open(child.path())?, &mut hasher)?;
+                    found.insert(path.clone(), ChecksumEntry {
+                        path,
+                        size,
+                        blake3: hasher.finalize().to_hex().to_string(),
+                        symlink_target: None,
+                    });
+                }
+            }
+        }
+        
+        let mut mismatches = Vec::new();
+        for entry in &self.entries {
+            match found.remove(&entry.path) {
+                None => mismatches.push(ChecksumMismatch::Missing(entry.path.clone())),
+                Some(actual) if actual != *entry => mismatches.push(ChecksumMismatch::Changed(entry.path.clone())),
+                Some(_) => {}
+            }
+        }
+        mismatches.extend(found.into_keys().map(ChecksumMismatch::Unexpected));
+        mismatches.sort_by(|a, b| mismatch_path(a).as_path().cmp(mismatch_path(b).as_path()));
+        Ok(mismatches)
+    }
+    
+    /// Hashes the version and entries of the manifest with `key`.
+    fn keyed_hash(&self, key: &ManifestKey) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new_keyed(&key.0);
+        hasher.update(&self.version.to_le_bytes());
+        for entry in &self.entries {
+            let path = entry.path.as_path().to_string_lossy();
+            let target = entry.symlink_target.as_ref().map(|target| target.to_string_lossy());
+            let fields = [path.as_ref(), entry.blake3.as_str(), target.as_deref().unwrap_or("")];
+            for field in fields {
+                hasher.update(&(field.len() as u64).to_le_bytes());
+                hasher.update(field.as_bytes());
+            }
+            hasher.update(&entry.size.to_le_bytes());
+            hasher.update(&[u8::from(entry.symlink_target.is_some())]);
+        }
+        hasher.finalize()
+    }
+}
+
+/// Collects the entries of a checksum manifest from the merged view.
+#[derive(Default)]
+struct ChecksumSink {
+    entries: Vec<ChecksumEntry>,
+}
+
+impl ArchiveSink for ChecksumSink {
+    fn directory(&mut self, _name: &str, _metadata: &FileMetadata) -> Result<(), ShadowError> {
+        Ok(())
+    }
+    
+    fn file(&mut self, name: &str, _metadata: &FileMetadata, len: u64, content: &mut dyn Read) -> Result<(), ShadowError> {
+        let mut hasher = blake3::Hasher::new();
+        std::io::copy(&mut ExactReader { inner: content, remaining: len }, &mut hasher)?;
+        self.entries.push(ChecksumEntry {
+            path: member_path(name),
+            size: len,
+            blake3: hasher.finalize().to_hex().to_string(),
+            symlink_target: None,
+        });
+        Ok(())
+    }
+    
+    fn symlink(&mut self, name: &str, _metadata: &FileMetadata, target: &Path) -> Result<(), ShadowError> {
+        self.entries.push(symlink_entry(member_path(name), target.to_path_buf()));
+        Ok(())
+    }
+}
+
+impl OverrideStore {
+    /// Lists the files of the merged view of the source tree at `source_root`
+    /// with their sizes and hashes.
+    ///
+    /// See [`OverrideStore::checksum_manifest_with_progress`].
+    pub fn checksum_manifest(&self, source_root: &Path) -> Result<ChecksumManifest, ShadowError> {
+        self.checksum_manifest_with_progress(source_root, &NoProgress)
+    }
+    
+    /// Lists the files of the merged view of the source tree at `source_root`
+    /// with their sizes and hashes, reporting one item per hashed file.
+    ///
+    /// The manifest is unsigned; see [`ChecksumManifest::sign`].
+    pub fn checksum_manifest_with_progress(
+        &self,
+        source_root: &Path,
+        progress: &dyn Progress,
+    ) -> Result<ChecksumManifest, ShadowError> {
+        let mut sink = ChecksumSink::default();
+        self.write_merged_view(&mut sink, source_root, progress)?;
+        progress.finish();
+        Ok(ChecksumManifest {
+            version: CHECKSUM_MANIFEST_VERSION,
+            entries: sink.entries,
+            signature: None,
+        })
+    }
+}
+
+fn symlink_entry(path: ShadowPath, target: PathBuf) -> ChecksumEntry {
+    let bytes = target.to_string_lossy().into_owned().into_bytes();
+    ChecksumEntry {
+        path,
+        size: bytes.len() as u64,
+        blake3: blake3::hash(&bytes).to_hex().to_string(),
+        symlink_target: Some(target),
+    }
+}
+
+/// Returns the path of the archive member `name`.
+fn member_path(name: &str) -> ShadowPath {
+    ShadowPath::from(format!("/{}", name))
+}
+
+/// Returns `path` relative to the root, as `b3sum` and archive members name it.
+fn relative_name(path: &ShadowPath) -> String {
+    path.as_path().to_string_lossy().trim_start_matches('/').to_string()
+}
+
+fn mismatch_path(mismatch: &ChecksumMismatch) -> &ShadowPath {
+    match mismatch {
+        ChecksumMismatch::Missing(path) | ChecksumMismatch::Unexpected(path) | ChecksumMismatch::Changed(path) => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use tempfile::TempDir;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    #[test]
+    fn test_checksum_manifest_of_merged_view() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(source.path().join("old.txt"), "old").unwrap();
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/src/lib.rs"), Bytes::from("pub fn f() {}"), None).unwrap();
+        store.mark_deleted(path("/old.txt")).unwrap();
+        
+        let manifest = store.checksum_manifest(source.path()).unwrap();
+        let paths: Vec<_> = manifest.entries.iter().map(|entry| entry.path.clone()).collect();
+        assert_eq!(paths, [path("/src/lib.rs"), path("/src/main.rs")]);
+        assert_eq!(manifest.entries[1].blake3, blake3::hash(b"fn main() {}").to_hex().to_string());
+        assert_eq!(
+            manifest.to_b3sum().lines().nth(1).unwrap(),
+            format!("{}  src/main.rs", blake3::hash(b"fn main() {}").to_hex()),
+        );
+        
+        // An extracted copy of the merged view matches; edits to it do not
+        let artifact = TempDir::new().unwrap();
+        std::fs::create_dir(artifact.path().join("src")).unwrap();
+        std::fs::write(artifact.path().join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(artifact.path().join("src/lib.rs"), "pub fn f() {}").unwrap();
+        assert!(manifest.verify_directory(artifact.path()).unwrap().is_empty());
+        
+        std::fs::write(artifact.path().join("src/lib.rs"), "pub fn g() {}").unwrap();
+        std::fs::remove_file(artifact.path().join("src/main.rs")).unwrap();
+        std::fs::write(artifact.path().join("extra"), "").unwrap();
+        assert_eq!(manifest.verify_directory(artifact.path()).unwrap(), [
+            ChecksumMismatch::Unexpected(path("/extra")),
+            ChecksumMismatch::Changed(path("/src/lib.rs")),
+            ChecksumMismatch::Missing(path("/src/main.rs")),
+        ]);
+    }
+    
+    #[test]
+    fn test_signed_manifest() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("a.txt"), "a").unwrap();
+        let mut manifest = OverrideStore::with_defaults().checksum_manifest(source.path()).unwrap();
+        let key = ManifestKey::from_secret(b"build secret");
+        assert!(manifest.verify_signature(&key).is_err());
+        
+        manifest.sign(&key);
+        let json = serde_json::to_string(&manifest).unwrap();
+        let mut loaded: ChecksumManifest = serde_json::from_str(&json).unwrap();
+        loaded.verify_signature(&key).unwrap();
+        assert!(loaded.verify_signature(&ManifestKey::from_secret(b"other")).is_err());
+        
+        loaded.entries[0].size += 1;
+        assert!(loaded.verify_signature(&key).is_err());
+    }
+}
@@ -0,0 +1,916 @@
+//! Committing overrides back to the source tree.
+//!
+//! A commit writes every file override, creates every directory override and
+//! removes every tombstoned path under the source root. Each file is written
+//! to a temporary sibling and renamed into place, so a target is either left
+//! untouched or fully replaced. Progress is recorded in a [`CommitJournal`]:
+//! rerunning an interrupted commit skips the paths the journal marks as done
+//! and only re-checks the single path that was in flight.
+//!
+//! When [`CommitOptions::backup_dir`] is set, every source file a commit
+//! replaces or removes is first stashed in a [`CommitBackup`], and
+//! [`rollback_commit`] restores the source tree to its state before the
+//! most recent commit.
+
+use crate::error::ShadowError;
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::progress::{NoProgress, Progress};
+use crate::types::ShadowPath;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current version of the commit journal format.
+pub const COMMIT_JOURNAL_VERSION: u32 = 1;
+
+/// File name of the journal when none is given explicitly.
+const DEFAULT_JOURNAL_NAME: &str = ".shadowfs-commit.journal";
+
+/// Directory holding commit backups when none is given explicitly.
+const DEFAULT_BACKUP_DIR: &str = ".shadowfs-backup";
+
+/// File listing the changes recorded in a backup set.
+const BACKUP_MANIFEST: &str = "manifest.jsonl";
+
+/// Subdirectory of a backup set holding the stashed source files.
+const BACKUP_FILES: &str = "files";
+
+/// Suffix of the temporary files targets are written through.
+const TEMP_SUFFIX: &str = ".shadowfs-tmp";
+
+/// One line of the commit journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum JournalRecord {
+    /// First line, identifying the tree being committed to
+    Header { version: u32, source_root: PathBuf },
+    
+    /// A path is about to be applied
+    Begin { path: ShadowPath },
+    
+    /// A path has been applied; `fingerprint` identifies the applied content
+    Done { path: ShadowPath, fingerprint: String },
+    
+    /// Replaced source files are stashed in the backup set at `dir`
+    Backup { dir: PathBuf },
+}
+
+/// Append-only record of which overrides a commit has already applied.
+///
+/// Every record is flushed to disk before the commit moves on, so after a
+/// crash the journal lists every completed path plus at most one path that
+/// was in flight.
+#[derive(Debug)]
+pub struct CommitJournal {
+    path: PathBuf,
+    file: File,
+    done: HashMap<ShadowPath, String>,
+    pending: HashSet<ShadowPath>,
+    backup_set: Option<PathBuf>,
+}
+
+impl CommitJournal {
+    /// Returns the journal path used for commits to `source_root`.
+    pub fn default_path(source_root: &Path) -> PathBuf {
+        source_root.join(DEFAULT_JOURNAL_NAME)
+    }
+    
+    /// Opens the journal at `path`, loading the progress of an earlier run if one exists.
+    ///
+    /// # Returns
+    /// `InvalidConfiguration` if the journal belongs to a different source root or format version
+    pub fn open(path: &Path, source_root: &Path) -> Result<Self, ShadowError> {
+        let mut done = HashMap::new();
+        let mut pending = HashSet::new();
+        let mut backup_set = None;
+        let mut has_header = false;
+        
+        let (records, valid_len) = read_records::<JournalRecord>(path, "commit journal")?;
+        for record in records {
+            match record {
+                JournalRecord::Header { version, source_root: recorded } => {
+                    if version != COMMIT_JOURNAL_VERSION {
+                        return Err(ShadowError::InvalidConfiguration {
+                            message: format!("Unsupported commit journal version {}", version),
+                        });
+                    }
+                    if recorded != source_root {
+                        return Err(ShadowError::InvalidConfiguration {
+                            message: format!(
+                                "Commit journal {} belongs to {}, not {}",
+                                path.display(),
+                                recorded.display(),
+                                source_root.display()
+                            ),
+                        });
+                    }
+                    has_header = true;
+                }
+                JournalRecord::Begin { path } => {
+                    pending.insert(path);
+                }
+                JournalRecord::Done { path, fingerprint } => {
+                    pending.remove(&path);
+                    done.insert(path, fingerprint);
+                }
+                JournalRecord::Backup { dir } => {
+                    backup_set = Some(dir);
+                }
+            }
+        }
+        
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut journal = Self {
+            path: path.to_path_buf(),
+            file,
+            done,
+            pending,
+            backup_set,
+        };
+        
+        // Drop a torn tail so new records start on a fresh line
+        journal.file.set_len(if has_header { valid_len } else { 0 })?;
+        if !has_header {
+            journal.append(&JournalRecord::Header {
+                version: COMMIT_JOURNAL_VERSION,
+                source_root: source_root.to_path_buf(),
+            })?;
+        }
+        
+        Ok(journal)
+    }
+    
+    /// Returns the path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Returns the number of paths recorded as applied.
+    pub fn done_count(&self) -> usize {
+        self.done.len()
+    }
+    
+    /// Checks whether `path` was applied with content matching `fingerprint`.
+    pub fn is_done(&self, path: &ShadowPath, fingerprint: &str) -> bool {
+        self.done.get(path).is_some_and(|recorded| recorded == fingerprint)
+    }
+    
+    /// Checks whether `path` was being applied when an earlier run stopped.
+    pub fn is_pending(&self, path: &ShadowPath) -> bool {
+        self.pending.contains(path)
+    }
+    
+    /// Returns the backup set this commit stashes replaced files in, if any.
+    pub fn backup_set(&self) -> Option<&Path> {
+        self.backup_set.as_deref()
+    }
+    
+    /// Records that this commit stashes replaced files in the backup set at `dir`.
+    pub fn record_backup_set(&mut self, dir: &Path) -> Result<(), ShadowError> {
+        self.append(&JournalRecord::Backup { dir: dir.to_path_buf() })?;
+        self.backup_set = Some(dir.to_path_buf());
+        Ok(())
+    }
+    
+    /// Records that `path` is about to be applied.
+    pub fn begin(&mut self, path: &ShadowPath) -> Result<(), ShadowError> {
+        self.append(&JournalRecord::Begin { path: path.clone() })?;
+        self.pending.insert(path.clone());
+        Ok(())
+    }
+    
+    /// Records that `path` has been applied.
+    pub fn complete(&mut self, path: &ShadowPath, fingerprint: &str) -> Result<(), ShadowError> {
+        self.append(&JournalRecord::Done {
+            path: path.clone(),
+            fingerprint: fingerprint.to_string(),
+        })?;
+        self.pending.remove(path);
+        self.done.insert(path.clone(), fingerprint.to_string());
+        Ok(())
+    }
+    
+    /// Removes the journal once the commit has finished.
+    pub fn finish(self) -> Result<(), ShadowError> {
+        drop(self.file);
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+    
+    fn append(&mut self, record: &JournalRecord) -> Result<(), ShadowError> {
+        append_record(&mut self.file, record, "commit journal")
+    }
+}
+
+/// Outcome of a commit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitSummary {
+    /// Files written to the source tree
+    pub files_written: usize,
+    
+    /// Directories created in the source tree
+    pub directories_created: usize,
+    
+    /// Paths removed from the source tree
+    pub paths_removed: usize,
+    
+    /// Paths skipped because an earlier, interrupted run already applied them
+    pub resumed: usize,
+    
+    /// Backup set holding the replaced source files, if backups were enabled
+    pub backup_set: Option<PathBuf>,
+}
+
+/// Options controlling where a commit keeps its journal and backups.
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions {
+    /// Location of the commit journal; defaults to [`CommitJournal::default_path`]
+    pub journal_path: Option<PathBuf>,
+    
+    /// Directory to stash replaced and removed source files in, enabling
+    /// [`rollback_commit`]; `None` disables backups
+    pub backup_dir: Option<PathBuf>,
+}
+
+impl CommitOptions {
+    /// Returns options that back up replaced files to [`CommitBackup::default_dir`].
+    pub fn with_default_backup(source_root: &Path) -> Self {
+        Self {
+            journal_path: None,
+            backup_dir: Some(CommitBackup::default_dir(source_root)),
+        }
+    }
+    
+    fn journal_path(&self, source_root: &Path) -> PathBuf {
+        self.journal_path.clone().unwrap_or_else(|| CommitJournal::default_path(source_root))
+    }
+}
+
+/// How a commit changed one source path, as recorded in a backup manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+enum BackupRecord {
+    /// The path did not exist before the commit
+    Created { path: ShadowPath },
+    
+    /// The previous file or directory was stashed under the backup's `files` directory
+    Stashed { path: ShadowPath },
+}
+
+/// Copies of the source files replaced or removed by one commit.
+///
+/// A backup set is a directory holding an append-only manifest of the
+/// changed paths and a `files` tree with the previous contents. Entries are
+/// recorded before the source is touched, so a backup is complete even if
+/// the commit is interrupted.
+#[derive(Debug)]
+pub struct CommitBackup {
+    dir: PathBuf,
+    manifest: File,
+    recorded: HashSet<ShadowPath>,
+}
+
+impl CommitBackup {
+    /// Returns the directory holding backup sets for commits to `source_root`.
+    pub fn default_dir(source_root: &Path) -> PathBuf {
+        source_root.join(DEFAULT_BACKUP_DIR)
+    }
+    
+    /// Creates a new backup set inside `backup_dir`.
+    pub fn create(backup_dir: &Path) -> Result<Self, ShadowError> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let dir = backup_dir.join(format!("commit-{:013}", millis));
+        std::fs::create_dir_all(dir.join(BACKUP_FILES))?;
+        Self::open(&dir)
+    }
+    
+    /// Opens an existing backup set, e.g. to continue an interrupted commit.
+    pub fn open(dir: &Path) -> Result<Self, ShadowError> {
+        let manifest_path = dir.join(BACKUP_MANIFEST);
+        let (records, valid_len) = read_records::<BackupRecord>(&manifest_path, "backup manifest")?;
+        let recorded = records.into_iter().map(BackupRecord::into_path).collect();
+        
+        let manifest = OpenOptions::new().create(true).append(true).open(&manifest_path)?;
+        manifest.set_len(valid_len)?;
+        
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            manifest,
+            recorded,
+        })
+    }
+    
+    /// Finds the most recent backup set in `backup_dir`.
+    pub fn latest(backup_dir: &Path) -> Result<Option<PathBuf>, ShadowError> {
+        let entries = match std::fs::read_dir(backup_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        
+        let mut sets: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.join(BACKUP_MANIFEST).is_file())
+            .collect();
+        sets.sort();
+        Ok(sets.pop())
+    }
+    
+    /// Returns the directory of this backup set.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+    
+    /// Stashes whatever is at `target` before the commit replaces or removes it.
+    ///
+    /// Paths already recorded are left alone, so the stashed copy is always
+    /// the source content from before the commit started.
+    fn stash(&mut self, path: &ShadowPath, target: &Path, entry: &OverrideEntry) -> Result<(), ShadowError> {
+        if self.recorded.contains(path) {
+            return Ok(());
+        }
+        
+        let record = match std::fs::symlink_metadata(target) {
+            // Nothing to restore for a tombstone over a missing path
+            Err(_) if entry.is_deleted() => return Ok(()),
+            Err(_) => BackupRecord::Created { path: path.clone() },
+            // Creating an existing directory changes nothing
+            Ok(metadata) if metadata.is_dir() && entry.is_directory() => return Ok(()),
+            Ok(_) => {
+                let stash = target_path(&self.dir.join(BACKUP_FILES), path)?;
+                remove_path(&stash)?;
+                copy_recursive(target, &stash)?;
+                BackupRecord::Stashed { path: path.clone() }
+            }
+        };
+        
+        append_record(&mut self.manifest, &record, "backup manifest")?;
+        self.recorded.insert(path.clone());
+        Ok(())
+    }
+}
+
+impl BackupRecord {
+    fn into_path(self) -> ShadowPath {
+        match self {
+            Self::Created { path } | Self::Stashed { path } => path,
+        }
+    }
+}
+
+/// Outcome of a rollback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollbackSummary {
+    /// Source paths restored from the backup
+    pub restored: usize,
+    
+    /// Paths created by the commit that were removed again
+    pub removed: usize,
+    
+    /// Backup set that was rolled back and deleted
+    pub backup_set: PathBuf,
+}
+
+/// Undoes the most recent backed-up commit to `source_root`.
+///
+/// Restores every stashed file, removes files the commit created and then
+/// deletes the backup set. A journal left by an interrupted commit using the
+/// same backup set is removed too, so the commit is not resumed afterwards.
+///
+/// # Returns
+/// `NotFound` if no backup set exists
+pub fn rollback_commit(source_root: &Path, options: &CommitOptions) -> Result<RollbackSummary, ShadowError> {
+    let backup_dir = options.backup_dir.clone().unwrap_or_else(|| CommitBackup::default_dir(source_root));
+    let set = CommitBackup::latest(&backup_dir)?.ok_or_else(|| ShadowError::NotFound {
+        path: ShadowPath::new(backup_dir.clone()),
+    })?;
+    let (records, _) = read_records::<BackupRecord>(&set.join(BACKUP_MANIFEST), "backup manifest")?;
+    
+    let mut summary = RollbackSummary {
+        restored: 0,
+        removed: 0,
+        backup_set: set.clone(),
+    };
+    
+    // Undo in reverse so children are handled before their parents
+    for record in records.iter().rev() {
+        match record {
+            BackupRecord::Created { path } => {
+                let target = target_path(source_root, path)?;
+                let result = match std::fs::symlink_metadata(&target) {
+                    // Directories may have held source files that are still there
+                    Ok(metadata) if metadata.is_dir() => std::fs::remove_dir(&target).or(Ok(())),
+                    _ => remove_path(&target),
+                };
+                result.map_err(|e| ShadowError::from_io_error_with_operation(e, path, "rollback"))?;
+                summary.removed += 1;
+            }
+            BackupRecord::Stashed { path } => {
+                let target = target_path(source_root, path)?;
+                let stash = target_path(&set.join(BACKUP_FILES), path)?;
+                restore_path(&stash, &target)
+                    .map_err(|e| ShadowError::from_io_error_with_operation(e, path, "rollback"))?;
+                summary.restored += 1;
+            }
+        }
+    }
+    
+    let journal_path = options.journal_path(source_root);
+    if journal_path.exists() {
+        let journal = CommitJournal::open(&journal_path, source_root)?;
+        if journal.backup_set() == Some(set.as_path()) {
+            journal.finish()?;
+        }
+    }
+    
+    std::fs::remove_dir_all(&set)?;
+    Ok(summary)
+}
+
+impl OverrideStore {
+    /// Writes all overrides to the source tree, resuming an interrupted commit if one exists.
+    ///
+    /// Uses the journal at [`CommitJournal::default_path`] and takes no
+    /// backups; see [`OverrideStore::commit`].
+    pub fn commit_to_source(&self, source_root: &Path) -> Result<CommitSummary, ShadowError> {
+        self.commit(source_root, &CommitOptions::default(), &NoProgress)
+    }
+    
+    /// Writes all overrides to the source tree, recording progress in the journal at `journal_path`.
+    ///
+    /// See [`OverrideStore::commit`].
+    pub fn commit_with_journal(
+        &self,
+        source_root: &Path,
+        journal_path: &Path,
+        progress: &dyn Progress,
+    ) -> Result<CommitSummary, ShadowError> {
+        let options = CommitOptions {
+            journal_path: Some(journal_path.to_path_buf()),
+            backup_dir: None,
+        };
+        self.commit(source_root, &options, progress)
+    }
+    
+    /// Writes all overrides to the source tree, recording progress in a journal.
+    ///
+    /// Directories are created first, then files are written, then tombstoned
+    /// paths are removed deepest-first. Cancellation and crashes leave the
+    /// journal in place, and calling this again with the same journal skips
+    /// every path that was already applied. The journal is removed once the
+    /// commit completes; the backup set, if any, is kept for [`rollback_commit`].
+    /// Delta overrides are compacted into file overrides before anything is written.
+    ///
+    /// The source is probed for writes first. If it refuses them, or starts
+    /// refusing them part-way, the commit fails with
+    /// [`ShadowError::SourceNotWritable`] and the store reports commits as
+    /// unavailable in its health check.
+    ///
+    /// # Arguments
+    /// * `source_root` - Root of the source tree the overrides shadow
+    /// * `options` - Journal and backup locations
+    /// * `progress` - Receives one item per override
+    pub fn commit(
+        &self,
+        source_root: &Path,
+        options: &CommitOptions,
+        progress: &dyn Progress,
+    ) -> Result<CommitSummary, ShadowError> {
+        self.check_source_writable(source_root)?;
+        self.apply_commit(source_root, options, progress)
+            .map_err(|e| self.explain_source_write_error(source_root, e))
+    }
+    
+    /// Applies the overrides for [`OverrideStore::commit`].
+    fn apply_commit(
+        &self,
+        source_root: &Path,
+        options: &CommitOptions,
+        progress: &dyn Progress,
+    ) -> Result<CommitSummary, ShadowError> {
+        let mut journal = CommitJournal::open(&options.journal_path(source_root), source_root)?;
+        let mut backup = match (&options.backup_dir, journal.backup_set()) {
+            (None, _) => None,
+            (Some(_), Some(set)) => Some(CommitBackup::open(set)?),
+            (Some(dir), None) => {
+                let backup = CommitBackup::create(dir)?;
+                journal.record_backup_set(backup.path())?;
+                Some(backup)
+            }
+        };
+        self.compact_all_deltas()?;
+        let entries = commit_order(self.all_entries()?);
+        
+        let bytes: u64 = entries.iter().map(|entry| entry.uncompressed_size()).sum();
+        progress.set_total(Some(entries.len() as u64), Some(bytes));
+        
+        let mut summary = CommitSummary {
+            backup_set: backup.as_ref().map(|b| b.path().to_path_buf()),
+            ..Default::default()
+        };
+        for entry in &entries {
+            progress.check_cancelled("commit")?;
+            progress.set_current_path(&entry.path);
+            
+            let target = target_path(source_root, &entry.path)?;
+            let data = entry.get_file_data()?;
+            let fingerprint = fingerprint(entry, data.as_deref());
+            
+            let already_applied = journal.is_done(&entry.path, &fingerprint)
+                || (journal.is_pending(&entry.path) && is_applied(&target, entry, data.as_deref()));
+            if already_applied {
+                if !journal.is_done(&entry.path, &fingerprint) {
+                    journal.complete(&entry.path, &fingerprint)?;
+                }
+                summary.resumed += 1;
+                progress.advance(1, entry.uncompressed_size());
+                continue;
+            }
+            
+            journal.begin(&entry.path)?;
+            if let Some(backup) = backup.as_mut() {
+                backup.stash(&entry.path, &target, entry)?;
+            }
+            match (&entry.content, data) {
+                (OverrideContent::File { .. }, Some(data)) => {
+                    write_atomically(&target, &data)
+                        .map_err(|e| ShadowError::from_io_error_with_operation(e, &entry.path, "commit"))?;
+                    summary.files_written += 1;
+                }
+                (OverrideContent::Directory { .. }, _) => {
+                    std::fs::create_dir_all(&target)
+                        .map_err(|e| ShadowError::from_io_error_with_operation(e, &entry.path, "commit"))?;
+                    summary.directories_created += 1;
+                }
+                (OverrideContent::Deleted, _) => {
+                    remove_path(&target)
+                        .map_err(|e| ShadowError::from_io_error_with_operation(e, &entry.path, "commit"))?;
+                    summary.paths_removed += 1;
+                }
+                (OverrideContent::File { .. }, None) => unreachable!("file entries always have data"),
+            }
+            journal.complete(&entry.path, &fingerprint)?;
+            progress.advance(1, entry.uncompressed_size());
+        }
+        
+        journal.finish()?;
+        progress.finish();
+        Ok(summary)
+    }
+}
+
+/// Orders entries so parents exist before children and children are removed before parents.
+fn commit_order(mut entries: Vec<Arc<OverrideEntry>>) -> Vec<Arc<OverrideEntry>> {
+    let rank = |entry: &OverrideEntry| match entry.content {
+        OverrideContent::Directory { .. } => 0,
+        OverrideContent::File { .. } => 1,
+        OverrideContent::Deleted => 2,
+    };
+    
+    entries.sort_by(|a, b| {
+        rank(a).cmp(&rank(b)).then_with(|| {
+            if a.is_deleted() {
+                b.path.as_path().cmp(a.path.as_path())
+            } else {
+                a.path.as_path().cmp(b.path.as_path())
+            }
+        })
+    });
+    entries
+}
+
+/// Maps a shadow path onto the source tree, rejecting paths that would escape it.
+pub(super) fn target_path(source_root: &Path, path: &ShadowPath) -> Result<PathBuf, ShadowError> {
+    let mut target = source_root.to_path_buf();
+    for component in path.as_path().components() {
+        match component {
+            Component::Normal(part) => target.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(ShadowError::InvalidPath {
+                    path: path.to_string(),
+                    reason: "path escapes the source root".to_string(),
+                });
+            }
+        }
+    }
+    Ok(target)
+}
+
+/// Identifies the content an entry commits, so a changed override is applied again.
+fn fingerprint(entry: &OverrideEntry, data: Option<&[u8]>) -> String {
+    match (&entry.content, data) {
+        (OverrideContent::File { .. }, Some(data)) => format!("file:{}", blake3::hash(data).to_hex()),
+        (OverrideContent::Directory { .. }, _) => "dir".to_string(),
+        _ => "deleted".to_string(),
+    }
+}
+
+/// Checks whether the source tree already reflects `entry`.
+fn is_applied(target: &Path, entry: &OverrideEntry, data: Option<&[u8]>) -> bool {
+    match (&entry.content, data) {
+        (OverrideContent::File { .. }, Some(data)) => {
+            std::fs::read(target).is_ok_and(|current| current == data)
+        }
+        (OverrideContent::Directory { .. }, _) => target.is_dir(),
+        _ => std::fs::symlink_metadata(target).is_err(),
+    }
+}
+
+/// Writes `data` to a temporary sibling of `target` and renames it into place.
+fn write_atomically(target: &Path, data: &[u8]) -> std::io::Result<()> {
+    let parent = target.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)?;
+    
+    let name = target.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let temp = parent.join(format!(".{}{}", name, TEMP_SUFFIX));
+    
+    let mut file = File::create(&temp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    
+    std::fs::rename(&temp, target)
+}
+
+/// Copies a file or directory tree from `from` to `to`, creating parents as needed.
+fn copy_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    
+    if std::fs::symlink_metadata(from)?.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for child in std::fs::read_dir(from)? {
+            let child = child?;
+            copy_recursive(&child.path(), &to.join(child.file_name()))?;
+        }
+        Ok(())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Moves a stashed file or directory back to `target`, replacing whatever is there.
+fn restore_path(stash: &Path, target: &Path) -> std::io::Result<()> {
+    remove_path(target)?;
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    
+    // The backup normally lives inside the source tree, so a rename suffices
+    std::fs::rename(stash, target).or_else(|_| copy_recursive(stash, target))
+}
+
+/// Reads newline-terminated JSON records from `path`.
+///
+/// A missing file yields no records. A final line without a newline is a
+/// torn append left by a crash and is ignored.
+///
+/// # Returns
+/// The records and the length of the file prefix they were read from
+fn read_records<T: DeserializeOwned>(path: &Path, what: &str) -> Result<(Vec<T>, u64), ShadowError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e.into()),
+    };
+    
+    let mut records = Vec::new();
+    let mut valid_len = 0u64;
+    for line in contents.split_inclusive('\n') {
+        if !line.ends_with('\n') {
+            break;
+        }
+        let record = serde_json::from_str(line.trim_end())
+            .map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Corrupted {} {}: {}", what, path.display(), e),
+            })?;
+        records.push(record);
+        valid_len += line.len() as u64;
+    }
+    
+    Ok((records, valid_len))
+}
+
+/// Appends one JSON record to `file` and flushes it to disk.
+fn append_record<T: Serialize>(file: &mut File, record: &T, what: &str) -> Result<(), ShadowError> {
+    let mut line = serde_json::to_vec(record).map_err(|e| ShadowError::InvalidConfiguration {
+        message: format!("Failed to encode {} record: {}", what, e),
+    })?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Removes a file or directory tree, treating an already missing path as removed.
+fn remove_path(target: &Path) -> std::io::Result<()> {
+    let result = match std::fs::symlink_metadata(target) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(target),
+        Ok(_) => std::fs::remove_file(target),
+        Err(e) => Err(e),
+    };
+    
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ProgressTracker;
+    use bytes::Bytes;
+    use tempfile::TempDir;
+    
+    fn store_with_changes() -> OverrideStore {
+        let store = OverrideStore::with_defaults();
+        store.insert_directory(ShadowPath::from("/new_dir"), None).unwrap();
+        store.insert_file(ShadowPath::from("/new_dir/a.txt"), Bytes::from("a"), None).unwrap();
+        store.insert_file(ShadowPath::from("/b.txt"), Bytes::from("b"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/old.txt")).unwrap();
+        store
+    }
+    
+    #[test]
+    fn test_commit_applies_overrides() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("old.txt"), "old").unwrap();
+        std::fs::write(source.path().join("b.txt"), "original").unwrap();
+        
+        let summary = store_with_changes().commit_to_source(source.path()).unwrap();
+        assert_eq!(summary.files_written, 2);
+        assert_eq!(summary.directories_created, 1);
+        assert_eq!(summary.paths_removed, 1);
+        assert_eq!(summary.resumed, 0);
+        
+        assert_eq!(std::fs::read_to_string(source.path().join("new_dir/a.txt")).unwrap(), "a");
+        assert_eq!(std::fs::read_to_string(source.path().join("b.txt")).unwrap(), "b");
+        assert!(!source.path().join("old.txt").exists());
+        assert!(!CommitJournal::default_path(source.path()).exists());
+    }
+    
+    #[test]
+    fn test_cancelled_commit_resumes() {
+        let source = TempDir::new().unwrap();
+        let journal_path = source.path().join("commit.journal");
+        let store = store_with_changes();
+        
+        // Cancel after the first override has been applied
+        struct CancelAfterFirst(ProgressTracker);
+        impl Progress for CancelAfterFirst {
+            fn set_total(&self, items: Option<u64>, bytes: Option<u64>) {
+                self.0.set_total(items, bytes);
+            }
+            fn advance(&self, items: u64, bytes: u64) {
+                self.0.advance(items, bytes);
+                self.0.cancel_handle().unwrap().cancel();
+            }
+            fn set_current_path(&self, path: &ShadowPath) {
+                self.0.set_current_path(path);
+            }
+            fn finish(&self) {
+                self.0.finish();
+            }
+            fn cancel_handle(&self) -> Option<&crate::progress::CancelHandle> {
+                self.0.cancel_handle()
+            }
+        }
+        
+        let progress = CancelAfterFirst(ProgressTracker::new("commit"));
+        let result = store.commit_with_journal(source.path(), &journal_path, &progress);
+        assert!(matches!(result, Err(ShadowError::Cancelled { .. })));
+        assert!(journal_path.exists());
+        assert!(source.path().join("new_dir").is_dir());
+        
+        let summary = store
+            .commit_with_journal(source.path(), &journal_path, &NoProgress)
+            .unwrap();
+        assert_eq!(summary.resumed, 1);
+        assert_eq!(summary.files_written, 2);
+        assert!(!journal_path.exists());
+    }
+    
+    #[test]
+    fn test_pending_path_already_applied_is_not_rewritten() {
+        let source = TempDir::new().unwrap();
+        let journal_path = source.path().join("commit.journal");
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/b.txt"), Bytes::from("b"), None).unwrap();
+        
+        // Simulate a crash right after the rename but before the journal update
+        let mut journal = CommitJournal::open(&journal_path, source.path()).unwrap();
+        journal.begin(&ShadowPath::from("/b.txt")).unwrap();
+        drop(journal);
+        std::fs::write(source.path().join("b.txt"), "b").unwrap();
+        
+        let summary = store
+            .commit_with_journal(source.path(), &journal_path, &NoProgress)
+            .unwrap();
+        assert_eq!(summary.resumed, 1);
+        assert_eq!(summary.files_written, 0);
+    }
+    
+    #[test]
+    fn test_journal_for_other_source_rejected() {
+        let source = TempDir::new().unwrap();
+        let journal_path = source.path().join("commit.journal");
+        CommitJournal::open(&journal_path, Path::new("/elsewhere")).unwrap();
+        
+        let result = CommitJournal::open(&journal_path, source.path());
+        assert!(matches!(result, Err(ShadowError::InvalidConfiguration { .. })));
+    }
+    
+    #[test]
+    fn test_journal_ignores_torn_tail() {
+        let source = TempDir::new().unwrap();
+        let journal_path = source.path().join("commit.journal");
+        
+        let mut journal = CommitJournal::open(&journal_path, source.path()).unwrap();
+        journal.complete(&ShadowPath::from("/a"), "dir").unwrap();
+        drop(journal);
+        
+        let mut file = OpenOptions::new().append(true).open(&journal_path).unwrap();
+        file.write_all(b"{\"record\":\"do").unwrap();
+        
+        let journal = CommitJournal::open(&journal_path, source.path()).unwrap();
+        assert!(journal.is_done(&ShadowPath::from("/a"), "dir"));
+        assert_eq!(journal.done_count(), 1);
+    }
+    
+    #[test]
+    fn test_rollback_restores_source() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("old.txt"), "old").unwrap();
+        std::fs::write(source.path().join("b.txt"), "original").unwrap();
+        let options = CommitOptions::with_default_backup(source.path());
+        
+        let summary = store_with_changes().commit(source.path(), &options, &NoProgress).unwrap();
+        let backup_set = summary.backup_set.unwrap();
+        assert!(backup_set.starts_with(CommitBackup::default_dir(source.path())));
+        assert_eq!(std::fs::read_to_string(source.path().join("b.txt")).unwrap(), "b");
+        
+        let rollback = rollback_commit(source.path(), &options).unwrap();
+        assert_eq!(rollback.backup_set, backup_set);
+        assert_eq!(rollback.restored, 2);
+        assert_eq!(rollback.removed, 2);
+        
+        assert_eq!(std::fs::read_to_string(source.path().join("b.txt")).unwrap(), "original");
+        assert_eq!(std::fs::read_to_string(source.path().join("old.txt")).unwrap(), "old");
+        assert!(!source.path().join("new_dir").exists());
+        assert!(!backup_set.exists());
+        
+        assert!(matches!(
+            rollback_commit(source.path(), &options),
+            Err(ShadowError::NotFound { .. })
+        ));
+    }
+    
+    #[test]
+    fn test_resumed_commit_keeps_original_backup() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("b.txt"), "original").unwrap();
+        let options = CommitOptions::with_default_backup(source.path());
+        
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/b.txt"), Bytes::from("b"), None).unwrap();
+        
+        // Simulate a crash after the file was stashed and replaced
+        let journal_path = CommitJournal::default_path(source.path());
+        let mut journal = CommitJournal::open(&journal_path, source.path()).unwrap();
+        let mut backup = CommitBackup::create(&CommitBackup::default_dir(source.path())).unwrap();
+        journal.record_backup_set(backup.path()).unwrap();
+        journal.begin(&ShadowPath::from("/b.txt")).unwrap();
+        let entry = store.get(&ShadowPath::from("/b.txt")).unwrap();
+        backup.stash(&ShadowPath::from("/b.txt"), &source.path().join("b.txt"), &entry).unwrap();
+        drop((journal, backup));
+        std::fs::write(source.path().join("b.txt"), "b").unwrap();
+        
+        let summary = store.commit(source.path(), &options, &NoProgress).unwrap();
+        assert_eq!(summary.resumed, 1);
+        
+        rollback_commit(source.path(), &options).unwrap();
+        assert_eq!(std::fs::read_to_string(source.path().join("b.txt")).unwrap(), "original");
+    }
+    
+    #[test]
+    fn test_target_path_rejects_escape() {
+        let root = Path::new("/src");
+        assert_eq!(target_path(root, &ShadowPath::from("/a/b")).unwrap(), PathBuf::from("/src/a/b"));
+        
+        // Deserialized paths skip normalization, so `..` can still reach the store
+        let escaping: ShadowPath = serde_json::from_str(r#"{"inner":"/a/../../etc"}"#).unwrap();
+        assert!(target_path(root, &escaping).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Reconciling overrides with a source tree that moved underneath them.
+//!
+//! When a source watcher sees a change to a path that has an override, the
+//! [`SourceChangeHandler`](crate::source_watch::SourceChangeHandler) records
+//! a [`SourceConflict`] in the store. [`OverrideStore::list_conflicts`]
+//! lists them and [`OverrideStore::resolve_conflict`] settles one by keeping
+//! the override, dropping it in favour of the source, or replacing it with
+//! merged content. For text files, [`merge_text`] merges the two sides
+//! against the content both started from, and
+//! [`OverrideStore::merge_with_source`] does so with the override and the
+//! current source file.
+
+use crate::error::{self, ShadowError};
+use crate::override_store::rename::source_path;
+use crate::override_store::OverrideStore;
+use crate::source_watch::SourceConflict;
+use crate::types::{current_time, ShadowPath};
+use crate::watch::ChangeKind;
+use bytes::Bytes;
+use std::path::Path;
+
+/// Marker opening the override's side of a conflicting hunk.
+pub const OVERRIDE_MARKER: &str = "<<<<<<< override";
+
+/// Marker separating the two sides of a conflicting hunk.
+pub const SEPARATOR_MARKER: &str = "=======";
+
+/// Marker closing the source's side of a conflicting hunk.
+pub const SOURCE_MARKER: &str = ">>>>>>> source";
+
+/// How a [`SourceConflict`] is settled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the override as it is
+    KeepOverride,
+    /// Drop the override so the source shows through
+    TakeSource,
+    /// Replace the override with the given content
+    Merge(Bytes),
+}
+
+/// Outcome of a three-way text merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextMerge {
+    /// Merged text, with conflict markers around hunks both sides changed
+    pub content: String,
+    
+    /// Hunks both sides changed differently
+    pub conflicts: usize,
+}
+
+impl TextMerge {
+    /// Returns true if the sides merged without conflicting hunks.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+impl OverrideStore {
+    /// Records that the source changed underneath the override of `path`,
+    /// keeping the time the first change was detected.
+    pub(crate) fn record_source_conflict(&self, path: &ShadowPath, kind: &ChangeKind) -> SourceConflict {
+        self.source_conflicts.lock().unwrap()
+            .entry(path.to_string())
+            .and_modify(|conflict| conflict.kind = kind.clone())
+            .or_insert_with(|| SourceConflict {
+                path: path.clone(),
+                kind: kind.clone(),
+                detected_at: current_time(),
+            })
+            .clone()
+    }
+    
+    /// Forgets the conflict recorded for `path`, returning it.
+    pub(crate) fn clear_source_conflict(&self, path: &ShadowPath) -> Option<SourceConflict> {
+        self.source_conflicts.lock().unwrap().remove(&path.to_string())
+    }
+    
+    /// Returns and forgets every recorded conflict.
+    pub(crate) fn take_source_conflicts(&self) -> Vec<SourceConflict> {
+        std::mem::take(&mut *self.source_conflicts.lock().unwrap()).into_values().collect()
+    }
+    
+    /// Lists the overrides whose source path changed since they were made,
+    /// sorted by path.
+    ///
+    /// Conflicts of overrides removed since are dropped.
+    pub fn list_conflicts(&self) -> Vec<SourceConflict> {
+        let mut conflicts = self.source_conflicts.lock().unwrap();
+        conflicts.retain(|_, conflict| self.exists(&conflict.path));
+        conflicts.values().cloned().collect()
+    }
+    
+    /// Returns the conflict recorded for the override of `path`, if any.
+    pub fn source_conflict(&self, path: &ShadowPath) -> Option<SourceConflict> {
+        let held = self.held_spelling(path);
+        let path = held.as_ref().unwrap_or(path);
+        self.source_conflicts.lock().unwrap().get(&path.to_string()).cloned()
+    }
+    
+    /// Settles the conflict recorded for `path`.
+    ///
+    /// # Errors
+    /// [`ShadowError::NotFound`] if no conflict is recorded for the path, or
+    /// the error of writing or removing the override.
+    pub fn resolve_conflict(&self, path: &ShadowPath, resolution: Resolution) -> Result<(), ShadowError> {
+        let held = self.held_spelling(path).unwrap_or_else(|| path.clone());
+        if self.source_conflict(&held).is_none() {
+            return Err(error::not_found(held));
+        }
+        match resolution {
+            Resolution::KeepOverride => {}
+            Resolution::TakeSource => {
+                self.check_writable(&held, "resolve conflict")?;
+                self.remove(&held);
+            }
+            Resolution::Merge(content) => {
+                let original = self.get(&held).and_then(|entry| entry.original_metadata.clone());
+                self.insert_file(held.clone(), content, original)?;
+            }
+        }
+        self.clear_source_conflict(&held);
+        Ok(())
+    }
+    
+    /// Merges the text of the override of `path` with the source file under
+    /// `source_root`, against `base`, the content both started from.
+    ///
+    /// A source file that no longer exists merges as empty. Pass the
+    /// content of a clean result to [`resolve_conflict`](Self::resolve_conflict)
+    /// as [`Resolution::Merge`].
+    ///
+    /// # Errors
+    /// [`ShadowError::NotFound`] if `path` has no file override,
+    /// [`ShadowError::Conflict`] if either side is not UTF-8 text, or the
+    /// error of reading the source file.
+    pub fn merge_with_source(&self, path: &ShadowPath, base: &str, source_root: &Path) -> Result<TextMerge, ShadowError> {
+        let entry = self.get(path).ok_or_else(|| error::not_found(path.clone()))?;
+        let ours = entry.get_file_data()?.ok_or_else(|| error::not_found(path.clone()))?;
+        let theirs = match std::fs::read(source_path(source_root, path)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(ShadowError::from(e)),
+        };
+        let text = |data: &[u8], side: &str| {
+            std::str::from_utf8(data)
+                .map(str::to_owned)
+                .map_err(|_| error::conflict(path.clone(), format!("the {} is not text and cannot be merged", side)))
+        };
+        Ok(merge_text(base, &text(&ours, "override")?, &text(&theirs, "source file")?))
+    }
+}
+
+/// Merges line by line the changes `ours` and `theirs` each made to `base`.
+///
+/// Hunks only one side changed take that side; hunks both sides changed
+/// the same way are taken once. Where they changed the same lines
+/// differently, both versions are kept between [`OVERRIDE_MARKER`],
+/// [`SEPARATOR_MARKER`] and [`SOURCE_MARKER`], `ours` first.
+pub fn merge_text(base: &str, ours: &str, theirs: &str) -> TextMerge {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let in_ours = matching_lines(&base, &ours);
+    let in_theirs = matching_lines(&base, &theirs);
+    
+    let mut merge = TextMerge { content: String::new(), conflicts: 0 };
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        // The next base line both sides kept ends the current hunk
+        let stable = (b..base.len()).find(|&i| in_ours[i].is_some() && in_theirs[i].is_some());
+        let (b_end, o_end, t_end) = match stable {
+            Some(i) => (i, in_ours[i].unwrap(), in_theirs[i].unwrap()),
+            None => (base.len(), ours.len(), theirs.len()),
+        };
+        merge_hunk(&mut merge, &base[b..b_end], &ours[o..o_end], &theirs[t..t_end]);
+        let Some(i) = stable else {
+            return merge;
+        };
+        merge.content.push_str(base[i]);
+        (b, o, t) = (i + 1, o_end + 1, t_end + 1);
+    }
+}
+
+/// Appends the merge of one hunk the sides may have changed.
+fn merge_hunk(merge: &mut TextMerge, base: &[&str], ours: &[&str], theirs: &[&str]) {
+    if ours == base || ours == theirs {
+        merge.content.extend(theirs.iter().copied());
+    } else if theirs == base {
+        merge.content.extend(ours.iter().copied());
+    } else {
+        merge.conflicts += 1;
+        for (marker, side) in [(OVERRIDE_MARKER, ours), (SEPARATOR_MARKER, theirs)] {
+            merge.content.push_str(marker);
+            merge.content.push('\n');
+            merge.content.extend(side.iter().copied());
+            if !merge.content.ends_with('\n') {
+                merge.content.push('\n');
+            }
+        }
+        merge.content.push_str(SOURCE_MARKER);
+        merge.content.push('\n');
+    }
+}
+
+/// Pairs the lines of `base` with the lines of `other` in a longest common
+/// subsequence, returning for each base line its index in `other`.
+fn matching_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    
+    // Lines shared at both ends need no table
+    let prefix = base.iter().zip(other).take_while(|(a, b)| a == b).count();
+    let suffix = base[prefix..].iter().rev()
+        .zip(other[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    for (i, matched) in matches.iter_mut().enumerate().take(prefix) {
+        *matched = Some(i);
+    }
+    for i in 0..suffix {
+        matches[base.len() - 1 - i] = Some(other.len() - 1 - i);
+    }
+    
+    let a = &base[prefix..base.len() - suffix];
+    let b = &other[prefix..other.len() - suffix];
+    let width = b.len() + 1;
+    let mut lengths = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * width + j] = if a[i] == b[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            matches[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_merge_text() {
+        let base = "one\ntwo\nthree\nfour\n";
+        let merge = merge_text(base, "one\n2\nthree\nfour\n", "one\ntwo\nthree\nfour\nfive\n");
+        assert!(merge.is_clean());
+        assert_eq!(merge.content, "one\n2\nthree\nfour\nfive\n");
+        
+        let merge = merge_text(base, "one\nzwei\nthree\nfour\n", "one\ndeux\nthree\nfour\n");
+        assert_eq!(merge.conflicts, 1);
+        assert_eq!(merge.content, "one\n<<<<<<< override\nzwei\n=======\ndeux\n>>>>>>> source\nthree\nfour\n");
+        
+        let same = merge_text(base, "one\nthree\n", "one\nthree\n");
+        assert!(same.is_clean());
+        assert_eq!(same.content, "one\nthree\n");
+    }
+    
+    #[test]
+    fn test_resolve_conflicts() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("a.txt"), "a\nb\nc\nd\n").unwrap();
+        let store = OverrideStore::with_defaults();
+        let (a, b) = (ShadowPath::from("/a.txt"), ShadowPath::from("/b.txt"));
+        store.insert_file(a.clone(), Bytes::from("A\nb\nc\n"), None).unwrap();
+        store.insert_file(b.clone(), Bytes::from("b"), None).unwrap();
+        store.record_source_conflict(&a, &ChangeKind::Modified);
+        store.record_source_conflict(&b, &ChangeKind::Modified);
+        assert_eq!(store.list_conflicts().len(), 2);
+        
+        let merge = store.merge_with_source(&a, "a\nb\nc\n", source.path()).unwrap();
+        assert_eq!(merge.content, "A\nb\nc\nd\n");
+        store.resolve_conflict(&a, Resolution::Merge(Bytes::from(merge.content))).unwrap();
+        assert_eq!(store.get(&a).unwrap().get_file_data().unwrap().unwrap(), "A\nb\nc\nd\n");
+        
+        store.resolve_conflict(&b, Resolution::TakeSource).unwrap();
+        assert!(!store.exists(&b));
+        assert!(store.list_conflicts().is_empty());
+        assert!(matches!(store.resolve_conflict(&a, Resolution::KeepOverride), Err(ShadowError::NotFound { .. })));
+    }
+}
@@ -0,0 +1,780 @@
+//! Delta overrides: written ranges layered over a source file.
+//!
+//! Storing a write with [`OverrideStore::insert_file`] copies the whole file
+//! into the store, so a 1 KiB change to a 500 MiB file costs 500 MiB.
+//! [`OverrideStore::write_range`] keeps only the written extents instead, and
+//! [`OverrideStore::read_range`] fills the gaps between them from the source
+//! file. Once the extents make up the share of the file set with
+//! [`OverrideStore::set_delta_compaction_ratio`], or on
+//! [`OverrideStore::compact_delta`], the delta is materialized into an
+//! ordinary file override.
+//!
+//! A path has either a delta or an entry: storing, deleting or removing an
+//! entry drops the delta of its path. Writes to deltas are logged to the WAL
+//! as [`PersistenceOp::WriteRange`] records and truncations as
+//! [`PersistenceOp::Truncate`] ones, while snapshots hold deltas compacted
+//! into file entries. Commits compact all deltas first.
+//!
+use crate::access::AccessOperation;
+use crate::error::{is_a_directory, not_found, quota_exceeded, ShadowError};
+use crate::override_store::optimization::hash_content;
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::types::{current_time, FileMetadata, FilePermissions, FileType, ShadowPath};
+use crate::watch::{ChangeEvent, ChangeKind, ChangeSource};
+#[cfg(feature = "persistence")]
+use crate::override_store::PersistenceOp;
+#[cfg(feature = "persistence")]
+use crate::supervision::Subsystem;
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+/// Share of a file its extents may cover before a delta is compacted.
+pub const DEFAULT_DELTA_COMPACTION_RATIO: f64 = 0.5;
+
+/// Summary of the delta override of a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeltaInfo {
+    /// Source file the extents are layered over
+    pub source: PathBuf,
+    
+    /// Length of the file as seen through the delta
+    pub len: u64,
+    
+    /// Number of separate written ranges
+    pub extent_count: usize,
+    
+    /// Bytes held by the extents
+    pub stored_bytes: u64,
+    
+    /// When the delta was last written
+    pub modified: SystemTime,
+}
+
+/// Extents written over one source file.
+#[derive(Debug, Clone)]
+struct DeltaOverride {
+    source: PathBuf,
+    len: u64,
+    /// Bytes of the source file the delta shows; the rest reads as zeros
+    source_len: u64,
+    /// Non-overlapping extents keyed by their offset
+    extents: BTreeMap<u64, Bytes>,
+    original_metadata: Option<FileMetadata>,
+    modified: SystemTime,
+}
+
+impl DeltaOverride {
+    fn new(source: PathBuf, original_metadata: Option<FileMetadata>) -> Self {
+        let len = std::fs::metadata(&source).map(|m| m.len()).unwrap_or(0);
+        Self {
+            source,
+            len,
+            source_len: len,
+            extents: BTreeMap::new(),
+            original_metadata,
+            modified: current_time(),
+        }
+    }
+    
+    fn stored_bytes(&self) -> u64 {
+        self.extents.values().map(|data| data.len() as u64).sum()
+    }
+    
+    fn info(&self) -> DeltaInfo {
+        DeltaInfo {
+            source: self.source.clone(),
+            len: self.len,
+            extent_count: self.extents.len(),
+            stored_bytes: self.stored_bytes(),
+            modified: self.modified,
+        }
+    }
+    
+    /// Writes `data` at `offset`, merging it with the extents it overlaps or touches.
+    fn write(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+        let touched: Vec<u64> = self.extents.range(..=end)
+            .rev()
+            .take_while(|(start, extent)| **start + extent.len() as u64 >= offset)
+            .map(|(start, _)| *start)
+            .collect();
+        
+        let mut start = offset;
+        let mut stop = end;
+        let mut old = Vec::with_capacity(touched.len());
+        for key in touched {
+            let extent = self.extents.remove(&key).unwrap();
+            start = start.min(key);
+            stop = stop.max(key + extent.len() as u64);
+            old.push((key, extent));
+        }
+        
+        let mut merged = vec![0u8; (stop - start) as usize];
+        for (key, extent) in old {
+            let at = (key - start) as usize;
+            merged[at..at + extent.len()].copy_from_slice(&extent);
+        }
+        let at = (offset - start) as usize;
+        merged[at..at + data.len()].copy_from_slice(data);
+        
+        self.extents.insert(start, Bytes::from(merged));
+        self.len = self.len.max(end);
+        self.modified = current_time();
+    }
+    
+    /// Sets the length of the file to `len`, dropping the extents past it
+    /// and copying the part of the extent `len` cuts through, so the memory
+    /// of the rest is released.
+    ///
+    /// # Returns
+    /// Number of bytes the extents no longer hold
+    fn truncate(&mut self, len: u64) -> u64 {
+        let before = self.stored_bytes();
+        self.extents.split_off(&len);
+        if let Some(mut last) = self.extents.last_entry() {
+            let keep = len - *last.key();
+            if (last.get().len() as u64) > keep {
+                let cut = Bytes::copy_from_slice(&last.get()[..keep as usize]);
+                *last.get_mut() = cut;
+            }
+        }
+        self.len = len;
+        self.source_len = self.source_len.min(len);
+        self.modified = current_time();
+        before - self.stored_bytes()
+    }
+    
+    /// Reads up to `size` bytes at `offset`, taking the extents over the source file.
+    ///
+    /// Parts of the file past the end of the source, or cut off it by a
+    /// truncation, read as zeros.
+    fn read(&self, offset: u64, size: usize) -> Result<Bytes, ShadowError> {
+        let end = self.len.min(offset.saturating_add(size as u64));
+        if offset >= end {
+            return Ok(Bytes::new());
+        }
+        let mut buffer = vec![0u8; (end - offset) as usize];
+        
+        let covered = self.extents.range(..end)
+            .next_back()
+            .is_some_and(|(start, extent)| *start <= offset && *start + extent.len() as u64 >= end);
+        let visible = self.source_len.saturating_sub(offset).min(buffer.len() as u64) as usize;
+        if !covered && visible > 0 {
+            read_source(&self.source, offset, &mut buffer[..visible])?;
+        }
+        
+        let first = self.extents.range(..=offset).next_back().map(|(start, _)| *start).unwrap_or(offset);
+        for (start, extent) in self.extents.range(first..end) {
+            let extent_end = start + extent.len() as u64;
+            if extent_end <= offset {
+                continue;
+            }
+            let from = offset.max(*start);
+            let to = end.min(extent_end);
+            buffer[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&extent[(from - start) as usize..(to - start) as usize]);
+        }
+        Ok(Bytes::from(buffer))
+    }
+    
+    /// Returns the full content of the file as seen through the delta.
+    fn materialize(&self) -> Result<Bytes, ShadowError> {
+        self.read(0, self.len as usize)
+    }
+}
+
+/// Returns the first `len` bytes of `data`, padded with zeros past its end.
+///
+/// A cut keeping half of `data` or more shares its buffer; a shorter one
+/// copies the kept bytes so the buffer can be freed.
+fn truncated(data: Bytes, len: u64) -> Bytes {
+    let len = len as usize;
+    if len > data.len() {
+        let mut extended = Vec::with_capacity(len);
+        extended.extend_from_slice(&data);
+        extended.resize(len, 0);
+        return extended.into();
+    }
+    if len >= data.len() / 2 {
+        data.slice(..len)
+    } else {
+        Bytes::copy_from_slice(&data[..len])
+    }
+}
+
+/// Fills `buffer` from `source` at `offset`, leaving bytes past its end untouched.
+fn read_source(source: &Path, offset: u64, buffer: &mut [u8]) -> Result<(), ShadowError> {
+    let mut file = match std::fs::File::open(source) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(ShadowError::IoError { source: e }),
+    };
+    file.seek(SeekFrom::Start(offset)).map_err(|e| ShadowError::IoError { source: e })?;
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ShadowError::IoError { source: e }),
+        }
+    }
+    Ok(())
+}
+
+/// Delta overrides of a store, keyed by path.
+#[derive(Debug)]
+pub(crate) struct DeltaTable {
+    deltas: RwLock<HashMap<ShadowPath, DeltaOverride>>,
+    compaction_ratio: RwLock<f64>,
+}
+
+impl Default for DeltaTable {
+    fn default() -> Self {
+        Self {
+            deltas: RwLock::new(HashMap::new()),
+            compaction_ratio: RwLock::new(DEFAULT_DELTA_COMPACTION_RATIO),
+        }
+    }
+}
+
+impl DeltaTable {
+    /// Drops the delta of `path`, returning the bytes its extents held.
+    pub(crate) fn remove(&self, path: &ShadowPath) -> Option<u64> {
+        self.deltas.write().unwrap().remove(path).map(|delta| delta.stored_bytes())
+    }
+    
+    fn contains(&self, path: &ShadowPath) -> bool {
+        self.deltas.read().unwrap().contains_key(path)
+    }
+}
+
+impl OverrideStore {
+    /// Writes `data` at `offset` of the file at `path`, storing only the written range.
+    ///
+    /// A path without an override gets a delta over `source`, the file it
+    /// shadows; `original_metadata` is kept for when the delta is compacted.
+    /// A file override is updated in place instead, so callers need not know
+    /// which one a path has; a write at its end goes to its append log. The
+    /// delta is compacted once its extents reach the configured share of
+    /// the file.
+    ///
+    /// # Errors
+    /// Fails for deleted paths and directories, on read-only stores, when the
+    /// access policy denies the write, when the file would grow past the
+    /// file size quota or the largest size a file can have, and when the
+    /// write cannot be logged.
+    pub fn write_range(
+        &self,
+        path: ShadowPath,
+        source: impl Into<PathBuf>,
+        offset: u64,
+        data: &[u8],
+        original_metadata: Option<FileMetadata>,
+    ) -> Result<(), ShadowError> {
+        self.check_writable(&path, "write")?;
+        self.check_access(&path, AccessOperation::Write)?;
+        let end = offset.checked_add(data.len() as u64)
+            .filter(|end| usize::try_from(*end).is_ok())
+            .ok_or_else(|| quota_exceeded(
+                path.clone(),
+                format!("a write of {} bytes at offset {} ends past the largest file size", data.len(), offset),
+            ))?;
+        
+        if let Some(entry) = self.get(&path) {
+            if offset == entry.override_metadata.size && self.append_to_entry(&path, &entry, data)? {
+                return Ok(());
+            }
+            let mut content = match &entry.content {
+                OverrideContent::File { .. } => entry.get_file_data()?.unwrap_or_default().to_vec(),
+                OverrideContent::Directory { .. } => return Err(is_a_directory(path)),
+                OverrideContent::Deleted => return Err(not_found(path)),
+            };
+            let (start, end) = (offset as usize, end as usize);
+            if content.len() < end {
+                self.check_file_size(&path, end as u64)?;
+                content.resize(end, 0);
+            }
+            content[start..end].copy_from_slice(data);
+            let original_metadata = entry.original_metadata.clone();
+            return self.insert_file(path, content.into(), original_metadata);
+        }
+        
+        self.check_file_size(&path, end)?;
+        let source = source.into();
+        {
+            #[cfg(feature = "persistence")]
+            let wal = self.wal.read().unwrap();
+            #[cfg(feature = "persistence")]
+            if let Some(wal) = wal.as_ref() {
+                let op = PersistenceOp::write_range(path.clone(), source.clone(), offset, Bytes::copy_from_slice(data));
+                if let Err(e) = wal.append(&op) {
+                    let message = || format!("Failed to log write of {}: {}", path, e);
+                    if !self.degrade_on_failure(Subsystem::Persistence, message) {
+                        return Err(e);
+                    }
+                }
+            }
+            self.apply_range(&path, source, offset, data, original_metadata)?;
+        }
+        self.compact_delta_if_due(&path)
+    }
+    
+    /// Sets the length of the file at `path` to `len`, cutting it or
+    /// extending it with zeros.
+    ///
+    /// A file override is stored again with its content cut, compressed and
+    /// hashed anew; uncompressed content cut to half its size or more keeps
+    /// sharing its buffer, deduplicated or not. A delta drops the extents
+    /// past `len` and copies only the extent `len` cuts through, and hides
+    /// the source past `len`; a path without an override gets such a delta
+    /// over `source`, as with [`OverrideStore::write_range`]. Either way a
+    /// [`ChangeKind::Modified`] event is published.
+    ///
+    /// # Errors
+    /// Fails like [`OverrideStore::write_range`], and when `len` is past
+    /// the file size quota.
+    pub fn truncate(
+        &self,
+        path: ShadowPath,
+        source: impl Into<PathBuf>,
+        len: u64,
+        original_metadata: Option<FileMetadata>,
+    ) -> Result<(), ShadowError> {
+        self.check_writable(&path, "truncate")?;
+        self.check_access(&path, AccessOperation::Write)?;
+        
+        if let Some(entry) = self.get(&path) {
+            let data = match &entry.content {
+                OverrideContent::File { .. } => entry.get_file_data()?.unwrap_or_default(),
+                OverrideContent::Directory { .. } => return Err(is_a_directory(path)),
+                OverrideContent::Deleted => return Err(not_found(path)),
+            };
+            let metadata = FileMetadata {
+                size: len,
+                modified: current_time(),
+                ..entry.override_metadata.clone()
+            };
+            let content = self.file_content(truncated(data, len));
+            // Storing the entry publishes the change
+            return self.insert_entry(path, content, entry.original_metadata.clone(), metadata);
+        }
+        
+        self.check_file_size(&path, len)?;
+        let source = source.into();
+        {
+            #[cfg(feature = "persistence")]
+            let wal = self.wal.read().unwrap();
+            #[cfg(feature = "persistence")]
+            if let Some(wal) = wal.as_ref() {
+                if let Err(e) = wal.append(&PersistenceOp::truncate(path.clone(), source.clone(), len)) {
+                    let message = || format!("Failed to log truncation of {}: {}", path, e);
+                    if !self.degrade_on_failure(Subsystem::Persistence, message) {
+                        return Err(e);
+                    }
+                }
+            }
+            self.apply_truncate(&path, source, len, original_metadata);
+        }
+        if let Some(service) = self.watch_service() {
+            service.publish(ChangeEvent::new(path.clone(), ChangeKind::Modified, false, ChangeSource::OverrideStore));
+        }
+        self.compact_delta_if_due(&path)
+    }
+    
+    /// Compacts the delta of `path` once its extents cover the configured
+    /// share of the file.
+    fn compact_delta_if_due(&self, path: &ShadowPath) -> Result<(), ShadowError> {
+        let ratio = self.delta_compaction_ratio();
+        let due = self.delta_info(path)
+            .is_some_and(|info| info.stored_bytes as f64 >= info.len as f64 * ratio);
+        if due {
+            self.compact_delta(path)?;
+        }
+        Ok(())
+    }
+    
+    /// Applies a range write to the delta of `path` without logging it.
+    pub(crate) fn apply_range(
+        &self,
+        path: &ShadowPath,
+        source: PathBuf,
+        offset: u64,
+        data: &[u8],
+        original_metadata: Option<FileMetadata>,
+    ) -> Result<(), ShadowError> {
+        // Merged extents grow by at most the written bytes
+        self.reserve_memory(data.len(), Some(path))?;
+        let mut deltas = self.deltas.deltas.write().unwrap();
+        let delta = deltas.entry(path.clone())
+            .or_insert_with(|| DeltaOverride::new(source, original_metadata));
+        let before = delta.stored_bytes();
+        delta.write(offset, data);
+        let grown = delta.stored_bytes() - before;
+        drop(deltas);
+        self.memory_tracker.release(data.len() - grown as usize);
+        self.invalidate_listing(path, false);
+        Ok(())
+    }
+    
+    /// Truncates the delta of `path` to `len` without logging it.
+    pub(crate) fn apply_truncate(
+        &self,
+        path: &ShadowPath,
+        source: PathBuf,
+        len: u64,
+        original_metadata: Option<FileMetadata>,
+    ) {
+        let mut deltas = self.deltas.deltas.write().unwrap();
+        let released = deltas.entry(path.clone())
+            .or_insert_with(|| DeltaOverride::new(source, original_metadata))
+            .truncate(len);
+        drop(deltas);
+        self.memory_tracker.release(released as usize);
+        self.invalidate_listing(path, false);
+    }
+    
+    /// Reads up to `size` bytes at `offset` of the file at `path`.
+    ///
+    /// Works for file overrides and deltas alike; reads past the end of the
+    /// file return fewer bytes.
+    ///
+    /// # Errors
+    /// Fails if `path` has neither a file override nor a delta, or if the
+    /// source file under a delta cannot be read.
+    pub fn read_range(&self, path: &ShadowPath, offset: u64, size: usize) -> Result<Bytes, ShadowError> {
+        if let Some(delta) = self.deltas.deltas.read().unwrap().get(path) {
+            return delta.read(offset, size);
+        }
+        
+        let entry = self.get(path).ok_or_else(|| not_found(path.clone()))?;
+        match &entry.content {
+            OverrideContent::File { .. } => Ok(entry.read_file_range(offset, size)?.unwrap_or_default()),
+            OverrideContent::Directory { .. } => Err(is_a_directory(path.clone())),
+            OverrideContent::Deleted => Err(not_found(path.clone())),
+        }
+    }
+    
+    /// Sets the share of a file (0.0 to 1.0) its written ranges may cover
+    /// before its delta is compacted into a full copy.
+    ///
+    /// Like the spill settings, the ratio is local to the running process.
+    pub fn set_delta_compaction_ratio(&self, ratio: f64) {
+        *self.deltas.compaction_ratio.write().unwrap() = ratio;
+    }
+    
+    /// Returns the share of a file at which its delta is compacted.
+    pub fn delta_compaction_ratio(&self) -> f64 {
+        *self.deltas.compaction_ratio.read().unwrap()
+    }
+    
+    /// Returns true if `path` has a delta override.
+    pub fn has_delta(&self, path: &ShadowPath) -> bool {
+        self.deltas.contains(path)
+    }
+    
+    /// Describes the delta override of `path`, if it has one.
+    pub fn delta_info(&self, path: &ShadowPath) -> Option<DeltaInfo> {
+        self.deltas.deltas.read().unwrap().get(path).map(DeltaOverride::info)
+    }
+    
+    /// Returns the paths that have delta overrides.
+    pub fn delta_paths(&self) -> Vec<ShadowPath> {
+        self.deltas.deltas.read().unwrap().keys().cloned().collect()
+    }
+    
+    /// Replaces the delta of `path` with a file override holding its full content.
+    ///
+    /// # Returns
+    /// Whether `path` had a delta to compact
+    pub fn compact_delta(&self, path: &ShadowPath) -> Result<bool, ShadowError> {
+        let Some(delta) = self.deltas.deltas.read().unwrap().get(path).cloned() else {
+            return Ok(false);
+        };
+        let content = delta.materialize()?;
+        // Storing the entry drops the delta and releases its memory
+        self.insert_file(path.clone(), content, delta.original_metadata)?;
+        Ok(true)
+    }
+    
+    /// Compacts every delta override.
+    ///
+    /// # Returns
+    /// Number of deltas compacted
+    pub fn compact_all_deltas(&self) -> Result<usize, ShadowError> {
+        let mut compacted = 0;
+        for path in self.delta_paths() {
+            if self.compact_delta(&path)? {
+                compacted += 1;
+            }
+        }
+        Ok(compacted)
+    }
+    
+    /// Drops the delta of `path`, releasing its memory.
+    ///
+    /// # Returns
+    /// Whether `path` had a delta
+    pub(crate) fn drop_delta(&self, path: &ShadowPath) -> bool {
+        match self.deltas.remove(path) {
+            Some(bytes) => {
+                self.memory_tracker.release(bytes as usize);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Materializes every delta into an uncompressed file entry, for
+    /// snapshots and merges.
+    ///
+    /// Deltas whose source file can no longer be read are left out.
+    pub(crate) fn delta_entries(&self) -> Vec<OverrideEntry> {
+        let deltas: Vec<(ShadowPath, DeltaOverride)> = self.deltas.deltas.read().unwrap()
+            .iter()
+            .map(|(path, delta)| (path.clone(), delta.clone()))
+            .collect();
+        deltas.into_iter()
+            .filter_map(|(path, delta)| {
+                let data = delta.materialize().ok()?;
+                let metadata = FileMetadata {
+                    size: data.len() as u64,
+                    created: delta.modified,
+                    modified: delta.modified,
+                    accessed: delta.modified,
+                    permissions: delta.original_metadata.as_ref()
+                        .map(|m| m.permissions)
+                        .unwrap_or_else(FilePermissions::default_file),
+                    file_type: FileType::File,
+                    platform_specific: delta.original_metadata.as_ref()
+                        .map(|m| m.platform_specific.clone())
+                        .unwrap_or_default(),
+                };
+                let content = OverrideContent::File {
+                    content_hash: hash_content(&data),
+                    data,
+                    is_compressed: false,
+                };
+                Some(OverrideEntry::new(path, content, delta.original_metadata, metadata))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    fn store_without_compaction() -> OverrideStore {
+        let store = OverrideStore::with_defaults();
+        store.set_delta_compaction_ratio(2.0);
+        store
+    }
+    
+    #[test]
+    fn test_write_range_stores_only_extents() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("big.bin");
+        std::fs::write(&source, vec![b'a'; 64 * 1024]).unwrap();
+        
+        let store = store_without_compaction();
+        let path = ShadowPath::from("/big.bin");
+        store.write_range(path.clone(), &source, 100, b"xyz", None).unwrap();
+        store.write_range(path.clone(), &source, 103, b"!", None).unwrap();
+        store.write_range(path.clone(), &source, 1000, b"far", None).unwrap();
+        
+        let info = store.delta_info(&path).unwrap();
+        assert_eq!(info.len, 64 * 1024);
+        assert_eq!(info.extent_count, 2);
+        assert_eq!(info.stored_bytes, 7);
+        assert!(store.get(&path).is_none());
+        
+        assert_eq!(store.read_range(&path, 98, 8).unwrap(), Bytes::from("aaxyz!aa"));
+        assert_eq!(store.read_range(&path, 1001, 4).unwrap(), Bytes::from("araa"));
+        assert_eq!(store.read_range(&path, 64 * 1024 - 1, 10).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_write_range_past_end_extends_file() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("small.txt");
+        std::fs::write(&source, "abc").unwrap();
+        
+        let store = store_without_compaction();
+        let path = ShadowPath::from("/small.txt");
+        store.write_range(path.clone(), &source, 5, b"z", None).unwrap();
+        
+        assert_eq!(store.delta_info(&path).unwrap().len, 6);
+        assert_eq!(store.read_range(&path, 0, 100).unwrap(), Bytes::from_static(b"abc\0\0z"));
+    }
+    
+    #[test]
+    fn test_write_range_refuses_sizes_out_of_bounds() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("missing.txt");
+        let store = store_without_compaction();
+        store.set_quota(crate::quota::Quota { max_file_size: Some(1024), ..Default::default() });
+        let path = ShadowPath::from("/a.txt");
+        store.insert_file(path.clone(), Bytes::from("abc"), None).unwrap();
+        
+        // Neither an offset the end overflows nor one far past the limit
+        // grows the file before the size is checked
+        for offset in [u64::MAX - 1, 1 << 40] {
+            let err = store.write_range(path.clone(), &source, offset, b"xyz", None).unwrap_err();
+            assert!(matches!(err, ShadowError::QuotaExceeded { .. }));
+            let err = store.write_range(ShadowPath::from("/b.txt"), &source, offset, b"xyz", None).unwrap_err();
+            assert!(matches!(err, ShadowError::QuotaExceeded { .. }));
+        }
+        assert_eq!(store.read_range(&path, 0, 100).unwrap(), Bytes::from("abc"));
+    }
+    
+    #[test]
+    fn test_compaction_replaces_delta_with_entry() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("file.txt");
+        std::fs::write(&source, "hello world").unwrap();
+        
+        let store = store_without_compaction();
+        let path = ShadowPath::from("/file.txt");
+        store.write_range(path.clone(), &source, 0, b"J", None).unwrap();
+        assert!(store.compact_delta(&path).unwrap());
+        
+        assert!(!store.has_delta(&path));
+        let data = store.get(&path).unwrap().get_file_data().unwrap().unwrap();
+        assert_eq!(data, Bytes::from("Jello world"));
+        
+        // Later writes go to the entry
+        store.write_range(path.clone(), &source, 6, b"W", None).unwrap();
+        assert_eq!(store.read_range(&path, 0, 100).unwrap(), Bytes::from("Jello World"));
+        assert!(!store.compact_delta(&path).unwrap());
+    }
+    
+    #[test]
+    fn test_large_delta_is_compacted_automatically() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("file.txt");
+        std::fs::write(&source, "0123456789").unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        let path = ShadowPath::from("/file.txt");
+        store.write_range(path.clone(), &source, 0, b"ab", None).unwrap();
+        assert!(store.has_delta(&path));
+        store.write_range(path.clone(), &source, 4, b"cdef", None).unwrap();
+        
+        assert!(!store.has_delta(&path));
+        assert_eq!(store.read_range(&path, 0, 100).unwrap(), Bytes::from("ab23cdef89"));
+    }
+    
+    #[test]
+    #[cfg(feature = "persistence")]
+    fn test_deltas_survive_wal_replay_and_snapshots() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("file.txt");
+        std::fs::write(&source, "original").unwrap();
+        let wal_path = dir.path().join("store.wal");
+        let path = ShadowPath::from("/file.txt");
+        
+        let store = store_without_compaction();
+        store.enable_wal(&wal_path).unwrap();
+        store.write_range(path.clone(), &source, 0, b"O", None).unwrap();
+        store.truncate(ShadowPath::from("/short.txt"), &source, 4, None).unwrap();
+        
+        let replayed = store_without_compaction();
+        crate::override_store::WriteAheadLog::replay(&wal_path, &replayed).unwrap();
+        assert_eq!(replayed.read_range(&path, 0, 100).unwrap(), Bytes::from("Original"));
+        assert_eq!(replayed.read_range(&ShadowPath::from("/short.txt"), 0, 100).unwrap(), Bytes::from("orig"));
+        
+        let snapshot_path = dir.path().join("store.snapshot");
+        store.save_snapshot(&snapshot_path).unwrap();
+        let loaded = OverrideStore::load_snapshot(&snapshot_path).unwrap();
+        let data = loaded.get(&path).unwrap().get_file_data().unwrap().unwrap();
+        assert_eq!(data, Bytes::from("Original"));
+    }
+    
+    #[test]
+    fn test_truncate_cuts_and_extends_deltas() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("big.bin");
+        std::fs::write(&source, vec![b'a'; 100]).unwrap();
+        let store = store_without_compaction();
+        let service = std::sync::Arc::new(crate::watch::WatchService::default());
+        store.set_watch_service(std::sync::Arc::clone(&service));
+        let mut events = service.subscribe(crate::watch::WatchFilter::default());
+        let path = ShadowPath::from("/big.bin");
+        
+        store.write_range(path.clone(), &source, 10, b"xyz", None).unwrap();
+        store.write_range(path.clone(), &source, 50, b"far", None).unwrap();
+        store.truncate(path.clone(), &source, 51, None).unwrap();
+        let info = store.delta_info(&path).unwrap();
+        assert_eq!((info.len, info.extent_count, info.stored_bytes), (51, 2, 4));
+        assert_eq!(events.try_recv().unwrap().kind, ChangeKind::Modified);
+        
+        // Growing again shows zeros, not the source the cut hid
+        store.truncate(path.clone(), &source, 60, None).unwrap();
+        assert_eq!(store.read_range(&path, 49, 5).unwrap(), Bytes::from(&b"af\0\0\0"[..]));
+        assert_eq!(store.read_range(&path, 9, 6).unwrap(), Bytes::from("axyzaa"));
+        assert_eq!(store.read_range(&path, 0, 100).unwrap().len(), 60);
+        
+        store.truncate(path.clone(), &source, 12, None).unwrap();
+        assert_eq!(store.read_range(&path, 0, 100).unwrap(), Bytes::from("aaaaaaaaaaxy"));
+        assert_eq!(store.delta_info(&path).unwrap().stored_bytes, 2);
+        
+        // Cutting a directory or a deleted file is refused
+        store.mark_deleted(ShadowPath::from("/gone")).unwrap();
+        assert!(store.truncate(ShadowPath::from("/gone"), dir.path().join("gone"), 0, None).is_err());
+    }
+    
+    #[test]
+    fn test_truncate_compressed_and_shared_overrides() {
+        let store = OverrideStore::with_defaults();
+        let content = Bytes::from("0123456789".repeat(110_000));
+        let (a, b) = (ShadowPath::from("/a.txt"), ShadowPath::from("/b.txt"));
+        store.insert_file(a.clone(), content.clone(), None).unwrap();
+        store.insert_file(b.clone(), content.clone(), None).unwrap();
+        assert!(matches!(store.get(&a).unwrap().content, OverrideContent::File { is_compressed: true, .. }));
+        
+        let hash = |path: &ShadowPath| match store.get(path).unwrap().content {
+            OverrideContent::File { content_hash, .. } => content_hash,
+            _ => unreachable!(),
+        };
+        let shared = hash(&b);
+        store.truncate(a.clone(), "/unused", 12, None).unwrap();
+        let entry = store.get(&a).unwrap();
+        assert_eq!(entry.get_file_data().unwrap().unwrap(), Bytes::from("012345678901"));
+        assert_eq!(entry.override_metadata.size, 12);
+        assert_ne!(hash(&a), shared);
+        assert_eq!(hash(&b), shared);
+        assert_eq!(store.get(&b).unwrap().get_file_data().unwrap().unwrap(), content);
+        
+        store.truncate(a.clone(), "/unused", 14, None).unwrap();
+        assert_eq!(store.read_range(&a, 10, 10).unwrap(), Bytes::from(&b"01\0\0"[..]));
+        assert_eq!(store.get(&a).unwrap().override_metadata.size, 14);
+    }
+    
+    #[test]
+    fn test_insert_and_remove_drop_delta() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("file.txt");
+        std::fs::write(&source, "content").unwrap();
+        
+        let store = store_without_compaction();
+        let path = ShadowPath::from("/file.txt");
+        let baseline = store.memory_stats().0;
+        store.write_range(path.clone(), &source, 0, b"C", None).unwrap();
+        assert!(store.memory_stats().0 > baseline);
+        store.remove(&path);
+        assert!(!store.has_delta(&path));
+        assert_eq!(store.memory_stats().0, baseline);
+        
+        store.write_range(path.clone(), &source, 0, b"C", None).unwrap();
+        store.mark_deleted(path.clone()).unwrap();
+        assert!(!store.has_delta(&path));
+        assert!(store.read_range(&path, 0, 10).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Comparison of the override store against the source tree.
+
+use crate::error::ShadowError;
+use crate::override_store::commit::target_path;
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::progress::{NoProgress, Progress};
+use crate::types::ShadowPath;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How an override changes the source tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    /// The path does not exist in the source tree
+    Added,
+    /// The path exists in the source tree with different content or type
+    Modified,
+    /// The path exists in the source tree but is deleted by a tombstone
+    Deleted,
+}
+
+impl DiffKind {
+    /// Returns the one-letter status code used in text output.
+    pub fn code(&self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Modified => 'M',
+            Self::Deleted => 'D',
+        }
+    }
+}
+
+/// A single difference between the override store and the source tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffEntry {
+    /// Path relative to the mount root
+    pub path: ShadowPath,
+    
+    /// Kind of change
+    pub kind: DiffKind,
+    
+    /// Whether the override is a directory
+    pub is_directory: bool,
+    
+    /// Size of the path in the source tree, if it exists there
+    pub source_size: Option<u64>,
+    
+    /// Size of the override, unless the path is deleted
+    pub override_size: Option<u64>,
+}
+
+impl OverrideStore {
+    /// Lists the paths whose overrides differ from the source tree.
+    ///
+    /// See [`OverrideStore::diff_against_source_with_progress`].
+    pub fn diff_against_source(&self, source_root: &Path) -> Result<Vec<DiffEntry>, ShadowError> {
+        self.diff_against_source_with_progress(source_root, &NoProgress)
+    }
+    
+    /// Lists the paths whose overrides differ from the source tree, reporting one item per override.
+    ///
+    /// File overrides whose content matches the source file, directory
+    /// overrides for existing directories and tombstones for paths missing
+    /// from the source are left out. Delta overrides are always listed as
+    /// modified.
+    ///
+    /// # Arguments
+    /// * `source_root` - Root of the source tree the overrides shadow
+    /// * `progress` - Receives one item per override
+    ///
+    /// # Returns
+    /// Differences sorted by path
+    pub fn diff_against_source_with_progress(
+        &self,
+        source_root: &Path,
+        progress: &dyn Progress,
+    ) -> Result<Vec<DiffEntry>, ShadowError> {
+        let entries = self.all_entries()?;
+        progress.set_total(Some(entries.len() as u64), None);
+        
+        let mut diff = Vec::new();
+        for entry in &entries {
+            progress.check_cancelled("diff")?;
+            progress.set_current_path(&entry.path);
+            
+            if let Some(change) = diff_entry(source_root, entry)? {
+                diff.push(change);
+            }
+            progress.advance(1, entry.uncompressed_size());
+        }
+        
+        // A delta only exists over a source file, whose content it changes
+        for path in self.delta_paths() {
+            let Some(info) = self.delta_info(&path) else { continue };
+            let target = target_path(source_root, &path)?;
+            diff.push(DiffEntry {
+                source_size: std::fs::metadata(&target).ok().filter(|m| m.is_file()).map(|m| m.len()),
+                override_size: Some(info.len),
+                path,
+                kind: DiffKind::Modified,
+                is_directory: false,
+            });
+        }
+        
+        diff.sort_by(|a, b| a.path.as_path().cmp(b.path.as_path()));
+        progress.finish();
+        Ok(diff)
+    }
+}
+
+/// Compares one override with the corresponding source path.
+fn diff_entry(source_root: &Path, entry: &OverrideEntry) -> Result<Option<DiffEntry>, ShadowError> {
+    let target = target_path(source_root, &entry.path)?;
+    let source = std::fs::symlink_metadata(&target).ok();
+    let source_size = source.as_ref().filter(|m| m.is_file()).map(|m| m.len());
+    
+    let kind = match (&entry.content, &source) {
+        (OverrideContent::Deleted, None) => return Ok(None),
+        (OverrideContent::Deleted, Some(_)) => DiffKind::Deleted,
+        (_, None) => DiffKind::Added,
+        (OverrideContent::Directory { .. }, Some(metadata)) if metadata.is_dir() => return Ok(None),
+        (OverrideContent::Directory { .. }, Some(_)) => DiffKind::Modified,
+        (OverrideContent::File { .. }, Some(metadata)) => {
+            if metadata.is_file() && metadata.len() == entry.uncompressed_size() {
+                let data = entry.get_file_data()?.unwrap_or_default();
+                let current = std::fs::read(&target)
+                    .map_err(|e| ShadowError::from_io_error_with_operation(e, &entry.path, "diff"))?;
+                if current == data {
+                    return Ok(None);
+                }
+            }
+            DiffKind::Modified
+        }
+    };
+    
+    Ok(Some(DiffEntry {
+        path: entry.path.clone(),
+        kind,
+        is_directory: entry.is_directory(),
+        source_size,
+        override_size: (!entry.is_deleted()).then(|| entry.uncompressed_size()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_diff_against_source() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("dir")).unwrap();
+        std::fs::write(source.path().join("same.txt"), "same").unwrap();
+        std::fs::write(source.path().join("changed.txt"), "old").unwrap();
+        std::fs::write(source.path().join("gone.txt"), "gone").unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        store.insert_directory(ShadowPath::from("/dir"), None).unwrap();
+        store.insert_file(ShadowPath::from("/same.txt"), Bytes::from("same"), None).unwrap();
+        store.insert_file(ShadowPath::from("/changed.txt"), Bytes::from("new!"), None).unwrap();
+        store.insert_file(ShadowPath::from("/added.txt"), Bytes::from("added"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/gone.txt")).unwrap();
+        store.mark_deleted(ShadowPath::from("/never.txt")).unwrap();
+        
+        let diff = store.diff_against_source(source.path()).unwrap();
+        let summary: Vec<(String, DiffKind)> = diff.iter()
+            .map(|d| (d.path.to_string(), d.kind))
+            .collect();
+        assert_eq!(summary, vec![
+            ("/added.txt".to_string(), DiffKind::Added),
+            ("/changed.txt".to_string(), DiffKind::Modified),
+            ("/gone.txt".to_string(), DiffKind::Deleted),
+        ]);
+        
+        assert_eq!(diff[0].source_size, None);
+        assert_eq!(diff[0].override_size, Some(5));
+        assert_eq!(diff[1].source_size, Some(3));
+        assert_eq!(diff[2].override_size, None);
+    }
+    
+    #[test]
+    fn test_diff_type_change_is_modified() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("entry"), "file").unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        store.insert_directory(ShadowPath::from("/entry"), None).unwrap();
+        
+        let diff = store.diff_against_source(source.path()).unwrap();
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].kind, DiffKind::Modified);
+        assert!(diff[0].is_directory);
+    }
+}
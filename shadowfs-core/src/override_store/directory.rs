@@ -1,20 +1,21 @@
 //! Directory cache for managing parent-child relationships.
 
 use crate::types::ShadowPath;
-use dashmap::{DashMap, DashSet};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
 
 /// Cache for directory structure and parent-child relationships.
 #[derive(Debug)]
 pub struct DirectoryCache {
     /// Map of directory paths to their immediate children
-    children: DashMap<ShadowPath, DashSet<String>>,
+    children: RwLock<HashMap<ShadowPath, HashSet<String>>>,
 }
 
 impl DirectoryCache {
     /// Creates a new DirectoryCache.
     pub fn new() -> Self {
         Self {
-            children: DashMap::new(),
+            children: RwLock::new(HashMap::new()),
         }
     }
     
@@ -25,8 +26,10 @@ impl DirectoryCache {
     /// * `child_name` - Name of the child entry (not full path)
     pub fn add_child(&self, parent: &ShadowPath, child_name: &str) {
         self.children
+            .write()
+            .unwrap()
             .entry(parent.clone())
-            .or_insert_with(DashSet::new)
+            .or_default()
             .insert(child_name.to_string());
     }
     
@@ -39,13 +42,13 @@ impl DirectoryCache {
     /// # Returns
     /// true if the child was removed, false if it didn't exist
     pub fn remove_child(&self, parent: &ShadowPath, child_name: &str) -> bool {
-        if let Some(children) = self.children.get(parent) {
-            let removed = children.remove(child_name).is_some();
+        let mut all_children = self.children.write().unwrap();
+        if let Some(children) = all_children.get_mut(parent) {
+            let removed = children.remove(child_name);
             
             // Clean up empty parent entry
             if children.is_empty() {
-                drop(children);
-                self.children.remove(parent);
+                all_children.remove(parent);
             }
             
             removed
@@ -63,8 +66,10 @@ impl DirectoryCache {
     /// Vector of child entry names
     pub fn get_children(&self, parent: &ShadowPath) -> Vec<String> {
         self.children
+            .read()
+            .unwrap()
             .get(parent)
-            .map(|children| children.iter().map(|entry| entry.key().clone()).collect())
+            .map(|children| children.iter().cloned().collect())
             .unwrap_or_default()
     }
     
@@ -77,11 +82,22 @@ impl DirectoryCache {
     /// true if the directory has children
     pub fn has_children(&self, parent: &ShadowPath) -> bool {
         self.children
+            .read()
+            .unwrap()
             .get(parent)
             .map(|children| !children.is_empty())
             .unwrap_or(false)
     }
     
+    /// Gets the number of children of a directory.
+    pub fn child_count(&self, parent: &ShadowPath) -> usize {
+        self.children
+            .read()
+            .unwrap()
+            .get(parent)
+            .map_or(0, |children| children.len())
+    }
+    
     /// Checks if a specific child exists in a directory.
     ///
     /// # Arguments
@@ -92,6 +108,8 @@ impl DirectoryCache {
     /// true if the child exists
     pub fn has_child(&self, parent: &ShadowPath, child_name: &str) -> bool {
         self.children
+            .read()
+            .unwrap()
             .get(parent)
             .map(|children| children.contains(child_name))
             .unwrap_or(false)
@@ -106,8 +124,10 @@ impl DirectoryCache {
     /// Vector of removed child names
     pub fn clear_children(&self, parent: &ShadowPath) -> Vec<String> {
         self.children
+            .write()
+            .unwrap()
             .remove(parent)
-            .map(|(_, children)| children.into_iter().collect())
+            .map(|children| children.into_iter().collect())
             .unwrap_or_default()
     }
     
@@ -116,19 +136,21 @@ impl DirectoryCache {
     /// # Returns
     /// Vector of all parent directory paths
     pub fn get_all_parents(&self) -> Vec<ShadowPath> {
-        self.children.iter().map(|entry| entry.key().clone()).collect()
+        self.children.read().unwrap().keys().cloned().collect()
     }
     
     /// Gets the total number of directories being tracked.
     pub fn directory_count(&self) -> usize {
-        self.children.len()
+        self.children.read().unwrap().len()
     }
     
     /// Gets the total number of child entries across all directories.
     pub fn total_child_count(&self) -> usize {
         self.children
-            .iter()
-            .map(|entry| entry.value().len())
+            .read()
+            .unwrap()
+            .values()
+            .map(|children| children.len())
             .sum()
     }
 }
@@ -261,6 +283,7 @@ mod tests {
         
         let children = cache.get_children(&parent);
         assert_eq!(children.len(), 3);
+        assert_eq!(cache.child_count(&parent), 3);
         assert!(children.contains(&"file1.txt".to_string()));
         assert!(children.contains(&"file2.txt".to_string()));
         assert!(children.contains(&"subdir".to_string()));
@@ -1,12 +1,15 @@
 //! Override entry types and content structures.
 
+use super::append::AppendedTail;
+use crate::clock;
 use crate::types::{FileMetadata, ShadowPath};
 use bytes::Bytes;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::SystemTime;
 
 /// Content stored in an override entry.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OverrideContent {
     /// File content with hash for integrity checking
     File {
@@ -24,7 +27,7 @@ pub enum OverrideContent {
 }
 
 /// An entry in the override store representing a file or directory override.
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OverrideEntry {
     /// Path of the overridden file/directory
     pub path: ShadowPath,
@@ -41,12 +44,24 @@ pub struct OverrideEntry {
     /// When this override was created
     pub created_at: SystemTime,
     
-    /// Last access time as Unix timestamp (for LRU tracking)
-    #[serde(with = "atomic_u64_serde")]
+    /// [`clock`] reading when this override was created, which orders FIFO
+    /// eviction; entries loaded from a snapshot read when they were loaded
+    #[cfg_attr(feature = "serde", serde(skip, default = "clock::now"))]
+    pub(crate) created: u64,
+    
+    /// Last access time in seconds since the Unix epoch, as reported to
+    /// readers; eviction tracks recency on the monotonic clock instead
+    #[cfg_attr(feature = "serde", serde(with = "atomic_u64_serde"))]
     pub last_accessed: AtomicU64,
+    
+    /// Bytes appended to the file since `content` was last compacted; the
+    /// store compacts it before serializing the entry
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) appended: Option<AppendedTail>,
 }
 
 /// Custom serialization for AtomicU64
+#[cfg(feature = "serde")]
 mod atomic_u64_serde {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use std::sync::atomic::{AtomicU64, Ordering};
@@ -67,6 +82,21 @@ mod atomic_u64_serde {
     }
 }
 
+// Snapshot checksums hash this output, so it leaves out the process-local
+// `created` reading and stays what earlier versions wrote
+impl std::fmt::Debug for OverrideEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverrideEntry")
+            .field("path", &self.path)
+            .field("content", &self.content)
+            .field("original_metadata", &self.original_metadata)
+            .field("override_metadata", &self.override_metadata)
+            .field("created_at", &self.created_at)
+            .field("last_accessed", &self.last_accessed)
+            .finish()
+    }
+}
+
 impl Clone for OverrideEntry {
     fn clone(&self) -> Self {
         Self {
@@ -75,28 +105,62 @@ impl Clone for OverrideEntry {
             original_metadata: self.original_metadata.clone(),
             override_metadata: self.override_metadata.clone(),
             created_at: self.created_at,
+            created: self.created,
             last_accessed: AtomicU64::new(self.last_accessed.load(Ordering::Relaxed)),
+            appended: self.appended.clone(),
         }
     }
 }
 
 impl OverrideEntry {
+    /// Creates an entry last accessed and created now.
+    pub(crate) fn new(
+        path: ShadowPath,
+        content: OverrideContent,
+        original_metadata: Option<FileMetadata>,
+        override_metadata: FileMetadata,
+    ) -> Self {
+        Self {
+            path,
+            content,
+            original_metadata,
+            override_metadata,
+            created_at: clock::wall_now(),
+            created: clock::now(),
+            last_accessed: AtomicU64::new(
+                clock::wall_now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            ),
+            appended: None,
+        }
+    }
+    
     /// Gets the file data, decompressing if necessary
     pub fn get_file_data(&self) -> Result<Option<Bytes>, crate::error::ShadowError> {
+        match &self.content {
+            OverrideContent::File { .. } => Ok(Some(self.with_appended(self.compacted_data()?))),
+            _ => Ok(None),
+        }
+    }
+    
+    /// Gets the file data held by `content`, decompressing if necessary,
+    /// without the bytes appended since it was compacted
+    pub(crate) fn compacted_data(&self) -> Result<Bytes, crate::error::ShadowError> {
         match &self.content {
             OverrideContent::File { data, is_compressed, .. } => {
                 if *is_compressed {
                     use crate::override_store::compression;
                     compression::decompress(data)
-                        .map(Some)
                         .map_err(|e| crate::error::ShadowError::IoError { 
                             source: e 
                         })
                 } else {
-                    Ok(Some(data.clone()))
+                    Ok(data.clone())
                 }
             }
-            _ => Ok(None),
+            _ => Ok(Bytes::new()),
         }
     }
 
@@ -123,7 +187,7 @@ impl OverrideEntry {
                     // For compressed data, return the override_metadata size
                     self.override_metadata.size
                 } else {
-                    data.len() as u64
+                    data.len() as u64 + self.appended.as_ref().map_or(0, AppendedTail::len)
                 }
             }
             _ => self.override_metadata.size,
@@ -0,0 +1,561 @@
+//! Background eviction driven by store and system memory pressure.
+//!
+//! Without an evictor, entries are only evicted inside `insert_file` once the
+//! store is already near its limit, so the write that crosses the threshold
+//! pays for the eviction. A [`BackgroundEvictor`] polls the store's memory
+//! usage and the operating system's memory pressure instead, and evicts ahead
+//! of time:
+//!
+//! - Above [`EvictorConfig::high_watermark`] it evicts down to
+//!   [`EvictorConfig::low_watermark`], then waits for usage to climb past the
+//!   high watermark again, so it does not evict a few entries on every tick.
+//! - When the system reports memory pressure it evicts down to the low
+//!   watermark, or to half of it when the pressure is critical, whatever the
+//!   store's own usage.
+//!
+//! System pressure is the kernel's memory pressure level on macOS (the one
+//! dispatch memory pressure sources report), the cgroup memory limit on Linux
+//! (or available system memory outside a limited cgroup), and the job object
+//! memory limit on Windows (or the system memory load outside a limited job).
+//! With a spill directory configured, evicted entries are spilled as usual.
+//! When the store has a [`Supervisor`](crate::supervision::Supervisor)
+//! attached, the evictor runs as its [`Component::Evictor`] and is restarted
+//! if it crashes.
+
+use crate::error::ShadowError;
+use crate::override_store::OverrideStore;
+use crate::supervision::{Component, ComponentHandle};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Share of a memory limit in use above which the system is under pressure.
+const WARNING_USAGE: f64 = 0.90;
+
+/// Share of a memory limit in use above which pressure is critical.
+const CRITICAL_USAGE: f64 = 0.95;
+
+/// Memory pressure reported by the operating system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum SystemMemoryPressure {
+    /// Memory is not scarce
+    #[default]
+    Normal,
+    
+    /// Memory is getting scarce; caches should shrink
+    Warning,
+    
+    /// Memory is nearly exhausted; caches should shrink as much as they can
+    Critical,
+}
+
+impl SystemMemoryPressure {
+    /// Samples the current memory pressure of this process's environment.
+    ///
+    /// Returns [`SystemMemoryPressure::Normal`] where it cannot be determined.
+    pub fn current() -> Self {
+        sample_system_pressure()
+    }
+    
+    /// Classifies `used` bytes out of a `limit`.
+    pub fn from_usage(used: u64, limit: u64) -> Self {
+        if limit == 0 {
+            return Self::Normal;
+        }
+        let ratio = used as f64 / limit as f64;
+        if ratio >= CRITICAL_USAGE {
+            Self::Critical
+        } else if ratio >= WARNING_USAGE {
+            Self::Warning
+        } else {
+            Self::Normal
+        }
+    }
+    
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Normal => 0,
+            Self::Warning => 1,
+            Self::Critical => 2,
+        }
+    }
+    
+    fn from_u8(value: u8) -> Self {
+        match value {
+            2 => Self::Critical,
+            1 => Self::Warning,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// Settings of a [`BackgroundEvictor`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvictorConfig {
+    /// How often memory usage and system pressure are checked
+    pub interval: Duration,
+    
+    /// Share of the store's memory limit (0.0 to 1.0) above which eviction starts
+    pub high_watermark: f64,
+    
+    /// Share of the store's memory limit that eviction brings usage down to
+    pub low_watermark: f64,
+    
+    /// Whether system memory pressure triggers eviction
+    pub follow_system_pressure: bool,
+}
+
+impl Default for EvictorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            high_watermark: 0.8,
+            low_watermark: 0.6,
+            follow_system_pressure: true,
+        }
+    }
+}
+
+impl EvictorConfig {
+    /// Returns the share of the memory limit to evict down to, if any.
+    fn target(&self, store_ratio: f64, system: SystemMemoryPressure) -> Option<f64> {
+        let system_target = match system {
+            _ if !self.follow_system_pressure => None,
+            SystemMemoryPressure::Normal => None,
+            SystemMemoryPressure::Warning => Some(self.low_watermark),
+            SystemMemoryPressure::Critical => Some(self.low_watermark / 2.0),
+        };
+        let store_target = (store_ratio > self.high_watermark).then_some(self.low_watermark);
+        
+        let target = match (store_target, system_target) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (target, None) | (None, target) => target,
+        };
+        target.filter(|target| store_ratio > *target)
+    }
+}
+
+/// What one eviction round did.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EvictionRound {
+    /// Share of the memory limit in use before the round
+    pub usage_before: f64,
+    
+    /// Share of the memory limit in use after the round
+    pub usage_after: f64,
+    
+    /// System memory pressure the round reacted to
+    pub system_pressure: SystemMemoryPressure,
+    
+    /// Bytes evicted or spilled
+    pub bytes_freed: usize,
+}
+
+/// Counters of a running [`BackgroundEvictor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EvictorStats {
+    /// Rounds that evicted something
+    pub eviction_rounds: u64,
+    
+    /// Bytes evicted or spilled in total
+    pub bytes_freed: u64,
+    
+    /// System memory pressure at the last check
+    pub system_pressure: SystemMemoryPressure,
+}
+
+#[derive(Debug, Default)]
+struct SharedStats {
+    eviction_rounds: AtomicU64,
+    bytes_freed: AtomicU64,
+    system_pressure: AtomicU8,
+}
+
+/// Tokio task evicting from a store ahead of memory pressure.
+///
+/// The task stops when the evictor is dropped.
+#[derive(Debug)]
+pub struct BackgroundEvictor {
+    task: ComponentHandle,
+    stats: Arc<SharedStats>,
+}
+
+impl BackgroundEvictor {
+    /// Starts evicting from `store` on the current Tokio runtime.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn(store: Arc<OverrideStore>, config: EvictorConfig) -> Self {
+        let stats = Arc::new(SharedStats::default());
+        let task = match store.supervisor() {
+            Some(supervisor) => {
+                let stats = Arc::clone(&stats);
+                supervisor.supervise(Component::Evictor, move || {
+                    let run = run(Arc::clone(&store), config.clone(), Arc::clone(&stats));
+                    async move {
+                        run.await;
+                        Ok(())
+                    }
+                })
+            }
+            None => ComponentHandle::unsupervised(crate::task::spawn("evictor", run(store, config, Arc::clone(&stats)))),
+        };
+        Self { task, stats }
+    }
+    
+    /// Returns what the evictor has done so far.
+    pub fn stats(&self) -> EvictorStats {
+        EvictorStats {
+            eviction_rounds: self.stats.eviction_rounds.load(Ordering::Relaxed),
+            bytes_freed: self.stats.bytes_freed.load(Ordering::Relaxed),
+            system_pressure: SystemMemoryPressure::from_u8(self.stats.system_pressure.load(Ordering::Relaxed)),
+        }
+    }
+    
+    /// Stops the evictor; dropping it does the same.
+    pub fn stop(self) {
+        self.task.stop();
+    }
+}
+
+async fn run(store: Arc<OverrideStore>, config: EvictorConfig, stats: Arc<SharedStats>) {
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let system = if config.follow_system_pressure {
+            SystemMemoryPressure::current()
+        } else {
+            SystemMemoryPressure::Normal
+        };
+        stats.system_pressure.store(system.to_u8(), Ordering::Relaxed);
+        
+        // A failed spill is retried on the next tick; the synchronous path
+        // reports the error to the writer that needs the memory
+        if let Ok(round) = store.evict_for_pressure(&config, system) {
+            if round.bytes_freed > 0 {
+                stats.eviction_rounds.fetch_add(1, Ordering::Relaxed);
+                stats.bytes_freed.fetch_add(round.bytes_freed as u64, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl OverrideStore {
+    /// Runs one eviction round as a [`BackgroundEvictor`] would under `system` pressure.
+    #[tracing::instrument(level = "debug", skip_all, fields(system = ?system))]
+    pub fn evict_for_pressure(
+        &self,
+        config: &EvictorConfig,
+        system: SystemMemoryPressure,
+    ) -> Result<EvictionRound, ShadowError> {
+        let usage_before = self.memory_usage_ratio();
+        let bytes_freed = match config.target(usage_before, system) {
+            Some(target) => self.evict_to_ratio(target)?,
+            None => 0,
+        };
+        Ok(EvictionRound {
+            usage_before,
+            usage_after: self.memory_usage_ratio(),
+            system_pressure: system,
+            bytes_freed,
+        })
+    }
+    
+    /// Evicts least recently used entries until at most `target` of the
+    /// memory limit (0.0 to 1.0) is in use.
+    ///
+    /// # Returns
+    /// Number of bytes evicted or spilled
+    pub fn evict_to_ratio(&self, target: f64) -> Result<usize, ShadowError> {
+        let config = self.get_config();
+        let target_bytes = (config.max_memory as f64 * target) as usize;
+        let mut freed = 0;
+        loop {
+            let usage = self.memory_tracker.current_usage();
+            if usage <= target_bytes {
+                break;
+            }
+            let round = self.evict_entries(config.eviction_policy, usage - target_bytes)?;
+            if round == 0 {
+                break;
+            }
+            freed += round;
+        }
+        Ok(freed)
+    }
+    
+    /// Changes the memory limit of the store at runtime, evicting least
+    /// recently used entries until the overrides fit a lowered limit.
+    ///
+    /// # Returns
+    /// Number of bytes evicted or spilled
+    pub fn set_memory_limit(&self, bytes: usize) -> Result<usize, ShadowError> {
+        self.config.write().unwrap().max_memory = bytes;
+        self.memory_tracker.set_max_allowed(bytes);
+        self.evict_to_ratio(1.0)
+    }
+    
+    fn memory_usage_ratio(&self) -> f64 {
+        let limit = self.get_config().max_memory;
+        if limit == 0 {
+            return 0.0;
+        }
+        self.memory_tracker.current_usage() as f64 / limit as f64
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn sample_system_pressure() -> SystemMemoryPressure {
+    cgroup_memory()
+        .or_else(system_memory)
+        .map(|(used, limit)| SystemMemoryPressure::from_usage(used, limit))
+        .unwrap_or_default()
+}
+
+/// Returns the memory used by and the limit of this process's cgroup, if it has a limit.
+#[cfg(target_os = "linux")]
+fn cgroup_memory() -> Option<(u64, u64)> {
+    use std::path::Path;
+    
+    let read = |path: &Path| -> Option<u64> {
+        std::fs::read_to_string(path).ok()?.trim().parse().ok()
+    };
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let (version, group) = parse_memory_cgroup(&cgroups)?;
+    let group = group.trim_start_matches('/');
+    
+    let (used, limit) = if version == 2 {
+        let dir = Path::new("/sys/fs/cgroup").join(group);
+        // memory.max holds "max" without a limit, which does not parse
+        (read(&dir.join("memory.current"))?, read(&dir.join("memory.max"))?)
+    } else {
+        let dir = Path::new("/sys/fs/cgroup/memory").join(group);
+        (read(&dir.join("memory.usage_in_bytes"))?, read(&dir.join("memory.limit_in_bytes"))?)
+    };
+    
+    // cgroup v1 reports no limit as a huge page-aligned value
+    let total = system_memory().map(|(_, total)| total).unwrap_or(u64::MAX);
+    (limit < total).then_some((used, limit))
+}
+
+/// Finds the memory cgroup in the contents of `/proc/self/cgroup`.
+///
+/// Returns the cgroup version and the group's path.
+#[cfg(target_os = "linux")]
+fn parse_memory_cgroup(contents: &str) -> Option<(u8, &str)> {
+    let mut unified = None;
+    for line in contents.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        if controllers.split(',').any(|controller| controller == "memory") {
+            return Some((1, path));
+        }
+        if controllers.is_empty() {
+            unified = Some((2, path));
+        }
+    }
+    unified
+}
+
+/// Returns the system memory in use, excluding reclaimable caches, and the total.
+#[cfg(target_os = "linux")]
+fn system_memory() -> Option<(u64, u64)> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| -> Option<u64> {
+        let line = meminfo.lines().find(|line| line.starts_with(name))?;
+        let kib: u64 = line[name.len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+        Some(kib * 1024)
+    };
+    let total = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+    Some((total.saturating_sub(available), total))
+}
+
+#[cfg(target_os = "macos")]
+fn sample_system_pressure() -> SystemMemoryPressure {
+    const NAME: &[u8] = b"kern.memorystatus_vm_pressure_level\0";
+    let mut level: libc::c_int = 0;
+    let mut size = std::mem::size_of::<libc::c_int>();
+    // SAFETY: NAME is NUL-terminated and level/size describe a valid c_int buffer
+    let result = unsafe {
+        libc::sysctlbyname(
+            NAME.as_ptr().cast(),
+            (&mut level as *mut libc::c_int).cast(),
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if result != 0 {
+        return SystemMemoryPressure::Normal;
+    }
+    // Levels as reported by DISPATCH_SOURCE_TYPE_MEMORYPRESSURE
+    match level {
+        4 => SystemMemoryPressure::Critical,
+        2 => SystemMemoryPressure::Warning,
+        _ => SystemMemoryPressure::Normal,
+    }
+}
+
+#[cfg(windows)]
+fn sample_system_pressure() -> SystemMemoryPressure {
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::JobObjects::{
+        JobObjectExtendedLimitInformation, QueryInformationJobObject,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_JOB_MEMORY,
+        JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+    };
+    use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows::Win32::System::SystemInformation::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+    use windows::Win32::System::Threading::GetCurrentProcess;
+    
+    let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+    // SAFETY: a null job handle queries the job of this process, and info
+    // is a buffer of the size passed
+    let queried = unsafe {
+        QueryInformationJobObject(
+            HANDLE::default(),
+            JobObjectExtendedLimitInformation,
+            (&mut info as *mut JOBOBJECT_EXTENDED_LIMIT_INFORMATION).cast(),
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            None,
+        )
+    };
+    if queried.is_ok() {
+        let flags = info.BasicLimitInformation.LimitFlags.0;
+        let limit = [
+            (JOB_OBJECT_LIMIT_JOB_MEMORY, info.JobMemoryLimit),
+            (JOB_OBJECT_LIMIT_PROCESS_MEMORY, info.ProcessMemoryLimit),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| flags & flag.0 != 0)
+        .map(|(_, limit)| limit as u64)
+        .min();
+        
+        let mut counters = PROCESS_MEMORY_COUNTERS::default();
+        // SAFETY: counters is a buffer of the size passed
+        let sampled = unsafe {
+            GetProcessMemoryInfo(
+                GetCurrentProcess(),
+                &mut counters,
+                std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+            )
+        };
+        // Job limits apply to committed memory; this process's commit is the
+        // best estimate of the job's that needs no extra rights
+        if let (Some(limit), true) = (limit, sampled.is_ok()) {
+            return SystemMemoryPressure::from_usage(counters.PagefileUsage as u64, limit);
+        }
+    }
+    
+    let mut status = MEMORYSTATUSEX {
+        dwLength: std::mem::size_of::<MEMORYSTATUSEX>() as u32,
+        ..Default::default()
+    };
+    // SAFETY: status is a MEMORYSTATUSEX with dwLength set as required
+    match unsafe { GlobalMemoryStatusEx(&mut status) } {
+        Ok(()) => SystemMemoryPressure::from_usage(status.dwMemoryLoad as u64, 100),
+        Err(_) => SystemMemoryPressure::Normal,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn sample_system_pressure() -> SystemMemoryPressure {
+    SystemMemoryPressure::Normal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::OverrideStoreConfig;
+    use crate::types::ShadowPath;
+    use bytes::Bytes;
+    
+    fn filled_store() -> OverrideStore {
+        let store = OverrideStore::new(OverrideStoreConfig {
+            max_memory: 64 * 1024,
+            eviction_threshold: 1.0,
+            enable_compression: false,
+            ..OverrideStoreConfig::default()
+        });
+        let mut i = 0u8;
+        while store.memory_stats().2 < 0.85 {
+            let content = Bytes::from(vec![i; 1024]);
+            store.insert_file(ShadowPath::from(format!("/file{}", i).as_str()), content, None).unwrap();
+            i += 1;
+        }
+        store
+    }
+    
+    #[test]
+    fn test_evicts_to_low_watermark_with_hysteresis() {
+        let store = filled_store();
+        let config = EvictorConfig::default();
+        
+        let round = store.evict_for_pressure(&config, SystemMemoryPressure::Normal).unwrap();
+        assert!(round.usage_before > config.high_watermark);
+        assert!(round.usage_after <= config.low_watermark);
+        assert!(round.bytes_freed > 0);
+        
+        // Between the watermarks nothing happens until usage passes the high one
+        let round = store.evict_for_pressure(&config, SystemMemoryPressure::Normal).unwrap();
+        assert_eq!(round.bytes_freed, 0);
+    }
+    
+    #[test]
+    fn test_system_pressure_evicts_below_high_watermark() {
+        let store = filled_store();
+        let config = EvictorConfig {
+            high_watermark: 0.99,
+            ..EvictorConfig::default()
+        };
+        assert_eq!(store.evict_for_pressure(&config, SystemMemoryPressure::Normal).unwrap().bytes_freed, 0);
+        
+        let round = store.evict_for_pressure(&config, SystemMemoryPressure::Critical).unwrap();
+        assert!(round.usage_after <= config.low_watermark / 2.0);
+        
+        let ignoring = EvictorConfig {
+            follow_system_pressure: false,
+            ..config
+        };
+        let store = filled_store();
+        assert_eq!(store.evict_for_pressure(&ignoring, SystemMemoryPressure::Critical).unwrap().bytes_freed, 0);
+    }
+    
+    #[test]
+    fn test_pressure_from_usage() {
+        assert_eq!(SystemMemoryPressure::from_usage(50, 100), SystemMemoryPressure::Normal);
+        assert_eq!(SystemMemoryPressure::from_usage(92, 100), SystemMemoryPressure::Warning);
+        assert_eq!(SystemMemoryPressure::from_usage(99, 100), SystemMemoryPressure::Critical);
+        assert_eq!(SystemMemoryPressure::from_usage(1, 0), SystemMemoryPressure::Normal);
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_memory_cgroup() {
+        assert_eq!(parse_memory_cgroup("0::/user.slice/app.scope\n"), Some((2, "/user.slice/app.scope")));
+        let v1 = "12:cpu,cpuacct:/a\n4:memory:/docker/abc\n0::/\n";
+        assert_eq!(parse_memory_cgroup(v1), Some((1, "/docker/abc")));
+        assert_eq!(parse_memory_cgroup(""), None);
+    }
+    
+    #[tokio::test]
+    async fn test_background_evictor_runs() {
+        let store = Arc::new(filled_store());
+        let evictor = BackgroundEvictor::spawn(Arc::clone(&store), EvictorConfig {
+            interval: Duration::from_millis(10),
+            follow_system_pressure: false,
+            ..EvictorConfig::default()
+        });
+        
+        for _ in 0..100 {
+            if evictor.stats().eviction_rounds > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(evictor.stats().bytes_freed > 0);
+        assert!(store.memory_stats().2 <= 0.6);
+        evictor.stop();
+    }
+}
@@ -0,0 +1,148 @@
+//! Collection of overrides that no longer change what the mount shows.
+//!
+//! A long session leaves tombstones behind for every file it created and
+//! deleted again: they hide nothing in the source, yet they hold memory,
+//! get persisted and are walked by every listing. Deleting a directory
+//! tree built only in the shadow layer leaves a whole chain of them.
+//! [`OverrideStore::collect_garbage`] drops tombstones with nothing under
+//! them in the source, and empty directory overrides that only exist in
+//! the shadow layer and can no longer be reached because an ancestor is
+//! deleted or gone. Neither changes what the mount shows.
+
+use super::rename::source_path;
+use super::size::calculate_entry_size;
+use super::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::types::ShadowPath;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Outcome of [`OverrideStore::collect_garbage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GarbageCollection {
+    /// Tombstones removed
+    pub tombstones: usize,
+    
+    /// Directory overrides removed
+    pub directories: usize,
+    
+    /// Memory the removed overrides held
+    pub freed_bytes: usize,
+}
+
+impl GarbageCollection {
+    /// Returns the number of overrides removed.
+    pub fn removed(&self) -> usize {
+        self.tombstones + self.directories
+    }
+}
+
+impl OverrideStore {
+    /// Removes the overrides that make no difference over the source tree
+    /// at `source_root`: tombstones of paths the source does not have, and
+    /// empty directory overrides the source does not have under an ancestor
+    /// that is deleted or missing.
+    ///
+    /// Paths are visited deepest first, so a chain of such directories and
+    /// tombstones goes in one pass. A source path that cannot be looked up
+    /// for any reason other than not existing keeps its override.
+    pub fn collect_garbage(&self, source_root: &Path) -> GarbageCollection {
+        let mut paths = self.all_paths();
+        paths.sort_by_key(|path| std::cmp::Reverse(path.as_path().components().count()));
+        
+        let mut collection = GarbageCollection::default();
+        for path in paths {
+            let Some(entry) = self.peek_entry(&path) else { continue };
+            let collectable = match entry.content {
+                OverrideContent::Deleted => source_missing(source_root, &path),
+                OverrideContent::Directory { .. } => {
+                    !self.directory_cache.has_children(&path)
+                        && source_missing(source_root, &path)
+                        && self.is_unreachable(&path, source_root)
+                }
+                OverrideContent::File { .. } => false,
+            };
+            if !collectable {
+                continue;
+            }
+            
+            let Some(removed) = self.remove(&path) else { continue };
+            collection.freed_bytes += calculate_entry_size(&removed);
+            match removed.content {
+                OverrideContent::Directory { .. } => collection.directories += 1,
+                _ => collection.tombstones += 1,
+            }
+        }
+        collection
+    }
+    
+    /// Looks `path` up without counting as an access or bringing a spilled
+    /// override back into memory.
+    fn peek_entry(&self, path: &ShadowPath) -> Option<Arc<OverrideEntry>> {
+        self.entries.get(path).or_else(|| self.peek_spilled(path).map(Arc::new))
+    }
+    
+    /// Returns true if some ancestor of `path` resolves to nothing or to a
+    /// file, so the mount cannot reach `path`.
+    fn is_unreachable(&self, path: &ShadowPath, source_root: &Path) -> bool {
+        let mut current = path.parent();
+        while let Some(ancestor) = current {
+            match self.peek_entry(&ancestor).map(|entry| entry.content.clone()) {
+                Some(OverrideContent::Directory { .. }) => {}
+                Some(_) => return true,
+                None if !source_path(source_root, &ancestor).is_dir() => return true,
+                None => {}
+            }
+            current = ancestor.parent();
+        }
+        false
+    }
+}
+
+/// Returns true if the source tree has nothing at `path`.
+fn source_missing(source_root: &Path, path: &ShadowPath) -> bool {
+    matches!(
+        std::fs::symlink_metadata(source_path(source_root, path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_collects_only_what_hides_nothing() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "source").unwrap();
+        let store = OverrideStore::with_defaults();
+        
+        // A tombstone over a source file stays, one over nothing goes
+        store.mark_deleted(ShadowPath::from("/src/lib.rs")).unwrap();
+        store.insert_file(ShadowPath::from("/src/scratch.rs"), Bytes::from("x"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/src/scratch.rs")).unwrap();
+        
+        // A deleted tree built only in the shadow layer goes entirely
+        for directory in ["/build", "/build/out", "/build/out/obj"] {
+            store.insert_directory(ShadowPath::from(directory), None).unwrap();
+        }
+        store.mark_deleted(ShadowPath::from("/build/out/obj/main.o")).unwrap();
+        store.mark_deleted(ShadowPath::from("/build")).unwrap();
+        
+        // An empty directory the mount can still reach stays
+        store.insert_directory(ShadowPath::from("/empty"), None).unwrap();
+        
+        let collection = store.collect_garbage(source.path());
+        assert_eq!(collection.tombstones, 3);
+        assert_eq!(collection.directories, 2);
+        assert!(collection.freed_bytes > 0);
+        let mut remaining = store.all_paths();
+        remaining.sort_by_key(|path| path.to_string());
+        assert_eq!(remaining, vec![ShadowPath::from("/empty"), ShadowPath::from("/src/lib.rs")]);
+        assert!(store.is_deleted(&ShadowPath::from("/src/lib.rs")));
+        
+        assert_eq!(store.collect_garbage(source.path()), GarbageCollection::default());
+    }
+}
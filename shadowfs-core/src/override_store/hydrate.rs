@@ -0,0 +1,251 @@
+//! Hydration: copying source subtrees into the store ahead of a workload.
+//!
+//! Providers serve files without an override from the source tree on every
+//! read. Hydrating a subtree copies those files into the store instead, so
+//! they keep being served once the source is slow or out of reach, such as
+//! a network share before going offline. Hydrated files are ordinary file
+//! overrides with the source's content, which diffs leave out as unchanged.
+//!
+//! Paths that already have an override, a tombstone or written ranges are
+//! left alone, and tombstoned directories are not descended into. With the
+//! `patterns` feature, files matching the store's read transforms are
+//! stored as transformed, as they would have been served.
+
+use super::rename::{source_metadata, source_path};
+use crate::error::ShadowError;
+use crate::override_store::OverrideStore;
+use crate::progress::{NoProgress, Progress};
+use crate::types::ShadowPath;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// What hydrating a subtree would copy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HydrationEstimate {
+    /// Source files that would be copied
+    pub files: u64,
+    
+    /// Source directories that would be created in the store
+    pub directories: u64,
+    
+    /// Total size of the files that would be copied, in bytes
+    pub bytes: u64,
+    
+    /// Paths skipped because they already have an override or written ranges
+    pub already_present: u64,
+    
+    /// Memory the store has left before it starts evicting, in bytes
+    pub memory_available: u64,
+}
+
+impl HydrationEstimate {
+    /// Returns true if the files fit into the store without evicting.
+    pub fn fits_in_memory(&self) -> bool {
+        self.bytes <= self.memory_available
+    }
+}
+
+/// Outcome of hydrating a subtree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HydrationSummary {
+    /// Files copied into the store
+    pub files: u64,
+    
+    /// Directories created in the store
+    pub directories: u64,
+    
+    /// Bytes of file content copied
+    pub bytes: u64,
+    
+    /// Paths skipped because they already had an override or written ranges
+    pub already_present: u64,
+}
+
+/// A source path found while walking the subtree to hydrate.
+enum Found<'a> {
+    File { path: &'a ShadowPath, host: &'a Path, metadata: &'a std::fs::Metadata },
+    Directory { path: &'a ShadowPath, metadata: &'a std::fs::Metadata },
+    Present,
+}
+
+impl OverrideStore {
+    /// Works out what [`OverrideStore::hydrate`] would copy from the subtree
+    /// at `path` of the source tree at `source_root`, without reading files.
+    pub fn estimate_hydration(&self, source_root: &Path, path: &ShadowPath) -> Result<HydrationEstimate, ShadowError> {
+        let mut estimate = HydrationEstimate {
+            memory_available: self.memory_tracker.available_space() as u64,
+            ..HydrationEstimate::default()
+        };
+        self.walk_for_hydration(source_root, path, &NoProgress, &mut |found| {
+            match found {
+                Found::File { metadata, .. } => {
+                    estimate.files += 1;
+                    estimate.bytes += metadata.len();
+                }
+                Found::Directory { .. } => estimate.directories += 1,
+                Found::Present => estimate.already_present += 1,
+            }
+            Ok(())
+        })?;
+        Ok(estimate)
+    }
+    
+    /// Copies the files of the subtree at `path` of the source tree at
+    /// `source_root` into the store.
+    ///
+    /// See [`OverrideStore::hydrate_with_progress`].
+    pub fn hydrate(&self, source_root: &Path, path: &ShadowPath) -> Result<HydrationSummary, ShadowError> {
+        self.hydrate_with_progress(source_root, path, &NoProgress)
+    }
+    
+    /// Copies the files of the subtree at `path` of the source tree at
+    /// `source_root` into the store, reporting one item per file.
+    ///
+    /// The subtree is estimated first, so the progress totals are known.
+    /// Cancelling stops between files; the files copied so far stay hydrated.
+    ///
+    /// # Arguments
+    /// * `source_root` - Root of the source tree the store shadows
+    /// * `path` - File or directory to hydrate, `/` for the whole tree
+    /// * `progress` - Receives one item per copied file
+    pub fn hydrate_with_progress(
+        &self,
+        source_root: &Path,
+        path: &ShadowPath,
+        progress: &dyn Progress,
+    ) -> Result<HydrationSummary, ShadowError> {
+        let estimate = self.estimate_hydration(source_root, path)?;
+        progress.set_total(Some(estimate.files), Some(estimate.bytes));
+        
+        let mut summary = HydrationSummary::default();
+        self.walk_for_hydration(source_root, path, progress, &mut |found| {
+            match found {
+                Found::File { path, host, metadata } => {
+                    progress.set_current_path(path);
+                    let content = self.hydrated_content(source_root, path, host)?;
+                    let len = content.len() as u64;
+                    self.insert_file(path.clone(), content, Some(source_metadata(metadata, metadata.len())))?;
+                    summary.files += 1;
+                    summary.bytes += len;
+                    progress.advance(1, len);
+                }
+                Found::Directory { path, metadata } => {
+                    self.insert_directory(path.clone(), Some(source_metadata(metadata, 0)))?;
+                    summary.directories += 1;
+                }
+                Found::Present => summary.already_present += 1,
+            }
+            Ok(())
+        })?;
+        progress.finish();
+        Ok(summary)
+    }
+    
+    /// Returns the content to store for the source file `host` seen at `path`.
+    fn hydrated_content(&self, source_root: &Path, path: &ShadowPath, host: &Path) -> Result<Bytes, ShadowError> {
+        #[cfg(feature = "patterns")]
+        if let Some(content) = self.transform_source(path, source_root)? {
+            return Ok(content);
+        }
+        #[cfg(not(feature = "patterns"))]
+        let _ = source_root;
+        std::fs::read(host)
+            .map(Bytes::from)
+            .map_err(|e| ShadowError::from_io_error_with_operation(e, path, "hydrate"))
+    }
+    
+    /// Walks the source subtree at `path` iteratively, reporting every path
+    /// hydration would copy or skip.
+    fn walk_for_hydration(
+        &self,
+        source_root: &Path,
+        path: &ShadowPath,
+        progress: &dyn Progress,
+        visit: &mut dyn FnMut(Found<'_>) -> Result<(), ShadowError>,
+    ) -> Result<(), ShadowError> {
+        let mut pending = vec![path.clone()];
+        while let Some(path) = pending.pop() {
+            progress.check_cancelled("hydrate")?;
+            let host = source_path(source_root, &path);
+            let metadata = std::fs::metadata(&host)
+                .map_err(|e| ShadowError::from_io_error_with_operation(e, &path, "hydrate"))?;
+            
+            let existing = self.get(&path);
+            if existing.as_ref().is_some_and(|entry| entry.is_deleted()) {
+                continue;
+            }
+            if metadata.is_dir() {
+                if existing.is_none() && path.as_path() != Path::new("/") {
+                    visit(Found::Directory { path: &path, metadata: &metadata })?;
+                }
+                for child in std::fs::read_dir(&host)? {
+                    pending.push(path.join(child?.file_name()));
+                }
+            } else if metadata.is_file() {
+                if existing.is_some() || self.has_delta(&path) {
+                    visit(Found::Present)?;
+                } else {
+                    visit(Found::File { path: &path, host: &host, metadata: &metadata })?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ProgressTracker;
+    use tempfile::TempDir;
+    
+    fn source_tree() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.path().join("src/nested/lib.rs"), "pub fn f() {}").unwrap();
+        std::fs::write(dir.path().join("src/edited.rs"), "old").unwrap();
+        std::fs::write(dir.path().join("src/deleted.rs"), "gone").unwrap();
+        std::fs::write(dir.path().join("README.md"), "readme").unwrap();
+        dir
+    }
+    
+    #[test]
+    fn test_hydrate_subtree() {
+        let source = source_tree();
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/src/edited.rs"), Bytes::from("new"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/src/deleted.rs")).unwrap();
+        
+        let subtree = ShadowPath::from("/src");
+        let estimate = store.estimate_hydration(source.path(), &subtree).unwrap();
+        assert_eq!((estimate.files, estimate.directories, estimate.already_present), (2, 2, 1));
+        assert_eq!(estimate.bytes, 25);
+        assert!(estimate.fits_in_memory());
+        
+        let tracker = ProgressTracker::new("hydrate");
+        let summary = store.hydrate_with_progress(source.path(), &subtree, &tracker).unwrap();
+        assert_eq!((summary.files, summary.directories, summary.bytes), (2, 2, 25));
+        let progress = tracker.snapshot();
+        assert_eq!((progress.items_done, progress.items_total, progress.finished), (2, Some(2), true));
+        
+        let lib = store.get(&ShadowPath::from("/src/nested/lib.rs")).unwrap();
+        assert_eq!(lib.get_file_data().unwrap().unwrap(), Bytes::from("pub fn f() {}"));
+        assert_eq!(store.get(&ShadowPath::from("/src/edited.rs")).unwrap().get_file_data().unwrap().unwrap(), Bytes::from("new"));
+        assert!(store.get(&ShadowPath::from("/src/deleted.rs")).unwrap().is_deleted());
+        assert!(store.get(&ShadowPath::from("/README.md")).is_none());
+        assert!(store.diff_against_source(source.path()).unwrap().iter().all(|entry| entry.path.as_path() != Path::new("/src/main.rs")));
+        
+        // A second pass finds everything present
+        let again = store.estimate_hydration(source.path(), &subtree).unwrap();
+        assert_eq!((again.files, again.directories, again.already_present), (0, 0, 3));
+    }
+    
+    #[test]
+    fn test_hydrate_missing_path_fails() {
+        let source = source_tree();
+        let store = OverrideStore::with_defaults();
+        assert!(store.hydrate(source.path(), &ShadowPath::from("/missing")).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+//! Everything the store knows about one path, for debugging.
+//!
+//! [`OverrideStore::inspect_path`] gathers what would otherwise take a
+//! debugger to find out about a misbehaving path: the state of its
+//! override, how the content is held, whether it is resident, spilled or
+//! hot in the read cache, its delta and hard links, the source file
+//! underneath, and the changes to it still in the write-ahead log. Looking
+//! a path up this way does not count as an access, so inspecting a path
+//! never changes what gets evicted.
+
+use super::rename::{source_metadata, source_path};
+use super::{DeltaInfo, OverrideContent, OverrideEntry, OverrideStore};
+use crate::source_watch::SourceConflict;
+use crate::types::{FileMetadata, ShadowPath};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Logged changes kept in a [`PathReport`].
+pub const RECENT_OPERATION_LIMIT: usize = 20;
+
+/// What the store holds for a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PathState {
+    /// A file override
+    File,
+    /// A directory override
+    Directory,
+    /// A tombstone hiding the source path
+    Deleted,
+    /// Written ranges layered over the source file
+    Delta,
+    /// Nothing; the path is served from the source, if it exists there
+    Source,
+}
+
+/// How the content of a file override is held.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContentInfo {
+    /// Length of the file
+    pub size: u64,
+    
+    /// Bytes the content takes in the store
+    pub stored_bytes: u64,
+    
+    /// Whether the content is zstd-compressed
+    pub compressed: bool,
+    
+    /// BLAKE3 hash the content is deduplicated under, hex encoded; all
+    /// zeroes without the `dedup` feature
+    pub content_hash: String,
+}
+
+/// A change to a path recorded in the write-ahead log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoggedOperation {
+    /// When the change was made
+    pub timestamp: SystemTime,
+    
+    /// What the change did
+    pub description: String,
+}
+
+/// Everything the store knows about one path.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PathReport {
+    /// Path inspected, in the casing the store holds it under
+    pub path: ShadowPath,
+    
+    /// What the store holds for the path
+    pub state: PathState,
+    
+    /// Content of a file override
+    pub content: Option<ContentInfo>,
+    
+    /// Metadata of the override
+    pub override_metadata: Option<FileMetadata>,
+    
+    /// Metadata of the source path when the override was made
+    pub original_metadata: Option<FileMetadata>,
+    
+    /// When the override was made
+    pub created_at: Option<SystemTime>,
+    
+    /// When the override was last read or written
+    pub last_accessed: Option<SystemTime>,
+    
+    /// Whether the override is on disk in the spill tier rather than in memory
+    pub spilled: bool,
+    
+    /// Whether the override is in the read cache of hot entries
+    pub hot_cached: bool,
+    
+    /// Whether the source file is held open by the source file cache
+    pub source_cached: bool,
+    
+    /// Written ranges of a delta override
+    pub delta: Option<DeltaInfo>,
+    
+    /// Paths sharing the override as hard links, including this one
+    pub hard_links: Vec<ShadowPath>,
+    
+    /// Metadata of the source path now, if it exists
+    pub source: Option<FileMetadata>,
+    
+    /// Unresolved source change to the path while it had an override, if
+    /// the mount watches its source and saw one
+    pub conflict: Option<SourceConflict>,
+    
+    /// Latest changes to the path in the write-ahead log, oldest first
+    pub recent_operations: Vec<LoggedOperation>,
+}
+
+impl ContentInfo {
+    fn of(entry: &OverrideEntry) -> Option<Self> {
+        match &entry.content {
+            OverrideContent::File { data, content_hash, is_compressed } => Some(Self {
+                size: entry.uncompressed_size(),
+                stored_bytes: data.len() as u64,
+                compressed: *is_compressed,
+                content_hash: content_hash.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl OverrideStore {
+    /// Reports everything the store knows about `path`, with the metadata
+    /// of the source path under `source_root` if given.
+    ///
+    /// Unlike [`get`](Self::get), this neither counts as an access nor
+    /// brings a spilled override back into memory.
+    pub fn inspect_path(&self, path: &ShadowPath, source_root: Option<&Path>) -> PathReport {
+        let path = self.held_spelling(path).unwrap_or_else(|| path.clone());
+        let spilled = self.is_spilled(&path);
+        let entry = self.entries.get(&path).or_else(|| self.peek_spilled(&path).map(Arc::new));
+        let delta = self.delta_info(&path);
+        let state = match &entry {
+            Some(entry) => match entry.content {
+                OverrideContent::File { .. } => PathState::File,
+                OverrideContent::Directory { .. } => PathState::Directory,
+                OverrideContent::Deleted => PathState::Deleted,
+            },
+            None if delta.is_some() => PathState::Delta,
+            None => PathState::Source,
+        };
+        
+        let source_file = source_root.map(|root| source_path(root, &path));
+        let source = source_file.as_ref()
+            .and_then(|file| std::fs::symlink_metadata(file).ok())
+            .map(|metadata| source_metadata(&metadata, metadata.len()));
+        let source_cached = source_file.as_ref()
+            .zip(self.source_cache())
+            .is_some_and(|(file, cache)| cache.contains(file));
+        
+        PathReport {
+            content: entry.as_deref().and_then(ContentInfo::of),
+            override_metadata: entry.as_ref().map(|entry| entry.override_metadata.clone()),
+            original_metadata: entry.as_ref().and_then(|entry| entry.original_metadata.clone()),
+            created_at: entry.as_ref().map(|entry| entry.created_at),
+            last_accessed: entry.as_ref().map(|entry| {
+                let seconds = entry.last_accessed.load(std::sync::atomic::Ordering::Relaxed);
+                SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+            }),
+            spilled,
+            hot_cached: self.hot_cache.contains(&path),
+            source_cached,
+            delta,
+            hard_links: if self.link_id(&path).is_some() { self.hard_links(&path) } else { Vec::new() },
+            source,
+            conflict: self.source_conflict(&path),
+            recent_operations: self.recent_operations(&path),
+            state,
+            path,
+        }
+    }
+    
+    #[cfg(feature = "persistence")]
+    fn recent_operations(&self, path: &ShadowPath) -> Vec<LoggedOperation> {
+        self.wal_path()
+            .and_then(|log| super::WriteAheadLog::history(log, path, RECENT_OPERATION_LIMIT).ok())
+            .unwrap_or_default()
+    }
+    
+    #[cfg(not(feature = "persistence"))]
+    fn recent_operations(&self, _path: &ShadowPath) -> Vec<LoggedOperation> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_inspect_overrides_and_source_paths() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("a.txt"), "source").unwrap();
+        let store = OverrideStore::with_defaults();
+        #[cfg(feature = "persistence")]
+        store.enable_wal(source.path().join("store.wal")).unwrap();
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("one"), None).unwrap();
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("two!"), None).unwrap();
+        
+        let report = store.inspect_path(&ShadowPath::from("/a.txt"), Some(source.path()));
+        assert_eq!(report.state, PathState::File);
+        let content = report.content.unwrap();
+        assert_eq!(content.size, 4);
+        assert_eq!(content.content_hash.len(), 64);
+        assert_eq!(report.source.unwrap().size, 6);
+        #[cfg(feature = "persistence")]
+        {
+            assert_eq!(report.recent_operations.len(), 2);
+            assert!(report.recent_operations[1].description.contains("4 bytes"));
+        }
+        #[cfg(not(feature = "persistence"))]
+        assert!(report.recent_operations.is_empty());
+        
+        let report = store.inspect_path(&ShadowPath::from("/b.txt"), Some(source.path()));
+        assert_eq!(report.state, PathState::Source);
+        assert!(report.source.is_none() && report.content.is_none());
+        
+        store.mark_deleted(ShadowPath::from("/a.txt")).unwrap();
+        let report = store.inspect_path(&ShadowPath::from("/a.txt"), None);
+        assert_eq!(report.state, PathState::Deleted);
+        #[cfg(feature = "persistence")]
+        assert_eq!(report.recent_operations.last().unwrap().description, "deleted");
+    }
+}
@@ -0,0 +1,258 @@
+//! Hard links between file overrides.
+//!
+//! Paths linked with [`OverrideStore::link`] share one identity, like an
+//! inode: they hold the same content, which the dedup layer stores once,
+//! and a write through any of them is applied to all of them. Replacing a
+//! linked path with a directory or a tombstone, or removing it, unlinks it
+//! from the others.
+//!
+//! Link identities live in memory only. Snapshots and the WAL record every
+//! path as an entry of its own, so after a reload linked paths hold equal but
+//! independent content.
+
+use crate::access::AccessOperation;
+use crate::error::ShadowError;
+use crate::override_store::{OverrideEntry, OverrideStore};
+use crate::quota::QuotaUsage;
+use crate::types::{FileMetadata, ShadowPath};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+/// Identity shared by hard-linked paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkId(pub u64);
+
+#[derive(Debug, Default)]
+struct LinkGroups {
+    by_path: HashMap<ShadowPath, LinkId>,
+    members: HashMap<LinkId, HashSet<ShadowPath>>,
+    next_id: u64,
+}
+
+/// Link groups of a store, keyed by path and by identity.
+#[derive(Debug, Default)]
+pub(crate) struct LinkTable {
+    groups: RwLock<LinkGroups>,
+}
+
+impl LinkTable {
+    /// Adds `new_path` to the group of `existing`, creating the group if needed.
+    pub(crate) fn link(&self, existing: &ShadowPath, new_path: &ShadowPath) -> LinkId {
+        let mut groups = self.groups.write().unwrap();
+        Self::unlink_locked(&mut groups, new_path);
+        
+        let id = match groups.by_path.get(existing) {
+            Some(&id) => id,
+            None => {
+                groups.next_id += 1;
+                let id = LinkId(groups.next_id);
+                groups.by_path.insert(existing.clone(), id);
+                groups.members.entry(id).or_default().insert(existing.clone());
+                id
+            }
+        };
+        groups.by_path.insert(new_path.clone(), id);
+        groups.members.entry(id).or_default().insert(new_path.clone());
+        id
+    }
+    
+    /// Removes `path` from its group; a group left with one path is dissolved.
+    pub(crate) fn unlink(&self, path: &ShadowPath) {
+        let mut groups = self.groups.write().unwrap();
+        Self::unlink_locked(&mut groups, path);
+    }
+    
+    fn unlink_locked(groups: &mut LinkGroups, path: &ShadowPath) {
+        let Some(id) = groups.by_path.remove(path) else {
+            return;
+        };
+        let members = groups.members.get_mut(&id).unwrap();
+        members.remove(path);
+        if members.len() <= 1 {
+            for last in members.iter() {
+                groups.by_path.remove(last);
+            }
+            groups.members.remove(&id);
+        }
+    }
+    
+    /// Takes `paths` out of their groups without dissolving any group, so
+    /// they can be given back under new names with [`LinkTable::attach`].
+    pub(crate) fn detach(&self, paths: &[ShadowPath]) -> Vec<(ShadowPath, LinkId)> {
+        let mut groups = self.groups.write().unwrap();
+        let mut detached = Vec::new();
+        for path in paths {
+            if let Some(id) = groups.by_path.remove(path) {
+                groups.members.get_mut(&id).unwrap().remove(path);
+                detached.push((path.clone(), id));
+            }
+        }
+        detached
+    }
+    
+    /// Adds each path to the group of its identity, then dissolves the
+    /// groups left with one path.
+    pub(crate) fn attach(&self, links: Vec<(ShadowPath, LinkId)>) {
+        let mut groups = self.groups.write().unwrap();
+        for (path, id) in links {
+            groups.by_path.insert(path.clone(), id);
+            groups.members.entry(id).or_default().insert(path);
+        }
+        
+        let lone: Vec<LinkId> = groups.members.iter()
+            .filter(|(_, members)| members.len() <= 1)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in lone {
+            for last in groups.members.remove(&id).unwrap() {
+                groups.by_path.remove(&last);
+            }
+        }
+    }
+    
+    /// Returns the identity of `path`, if it is linked.
+    pub(crate) fn id(&self, path: &ShadowPath) -> Option<LinkId> {
+        self.groups.read().unwrap().by_path.get(path).copied()
+    }
+    
+    /// Returns every path sharing the identity of `path`, including `path`, sorted.
+    pub(crate) fn members(&self, path: &ShadowPath) -> Vec<ShadowPath> {
+        let groups = self.groups.read().unwrap();
+        match groups.by_path.get(path) {
+            Some(id) => {
+                let mut members: Vec<ShadowPath> = groups.members[id].iter().cloned().collect();
+                members.sort_by(|a, b| a.as_path().cmp(b.as_path()));
+                members
+            }
+            None => vec![path.clone()],
+        }
+    }
+    
+    /// Returns the number of paths sharing the identity of `path`.
+    pub(crate) fn count(&self, path: &ShadowPath) -> u64 {
+        let groups = self.groups.read().unwrap();
+        groups.by_path.get(path).map_or(1, |id| groups.members[id].len() as u64)
+    }
+}
+
+impl OverrideStore {
+    /// Creates a hard link: `new_path` becomes another name for the file
+    /// override at `existing`.
+    ///
+    /// Both paths then share one content, and writing either of them
+    /// updates both. `existing` must be a file override; `new_path` must
+    /// not have an override other than a tombstone.
+    ///
+    /// # Arguments
+    /// * `existing` - Path of the file override to link to
+    /// * `new_path` - New name for the same file
+    pub fn link(&self, existing: &ShadowPath, new_path: ShadowPath) -> Result<LinkId, ShadowError> {
+        let existing = &self.stored_spelling(existing.clone());
+        let new_path = self.stored_spelling(new_path);
+        self.check_writable(&new_path, "link")?;
+        self.check_access(&new_path, AccessOperation::Write)?;
+        
+        // Both paths share the content, so an append log is compacted into it first
+        self.compact_append(existing)?;
+        let source = self.get(existing)
+            .filter(|entry| !entry.is_deleted())
+            .ok_or_else(|| crate::error::not_found(existing.clone()))?;
+        if source.is_directory() {
+            return Err(crate::error::is_a_directory(existing.clone()));
+        }
+        if self.get(&new_path).is_some_and(|entry| !entry.is_deleted()) {
+            return Err(crate::error::already_exists(new_path));
+        }
+        // Every name counts against the quota, as a write reaches each of them
+        self.check_insert(&new_path, &source.content)?;
+        self.check_quota([(&new_path, QuotaUsage::of_entry(&source))])?;
+        
+        // Cloning the content shares its buffer with the existing path
+        let entry = OverrideEntry::new(
+            new_path.clone(),
+            source.content.clone(),
+            None,
+            source.override_metadata.clone(),
+        );
+        self.store_entry(entry, true)?;
+        Ok(self.links.link(existing, &new_path))
+    }
+    
+    /// Returns the identity shared by `path` and its hard links, if it has any.
+    pub fn link_id(&self, path: &ShadowPath) -> Option<LinkId> {
+        self.links.id(path)
+    }
+    
+    /// Returns the number of names of the file at `path`, 1 if it is not linked.
+    pub fn link_count(&self, path: &ShadowPath) -> u64 {
+        self.links.count(path)
+    }
+    
+    /// Returns `path` and every path hard-linked to it, sorted.
+    pub fn hard_links(&self, path: &ShadowPath) -> Vec<ShadowPath> {
+        self.links.members(path)
+    }
+    
+    /// Returns the override metadata of `path` with its link count and the
+    /// named streams of its overrides filled in.
+    ///
+    /// Both are only recorded where the platform metadata tracks them; use
+    /// [`OverrideStore::link_count`] and [`OverrideStore::streams`] otherwise.
+    pub fn metadata(&self, path: &ShadowPath) -> Option<FileMetadata> {
+        let entry = self.get(path)?;
+        let mut metadata = entry.override_metadata.clone();
+        metadata.set_nlink(self.link_count(path));
+        metadata.set_streams(self.streams(path));
+        Some(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_link_shares_content_and_writes() {
+        let store = OverrideStore::with_defaults();
+        let a = ShadowPath::from("/a.txt");
+        let b = ShadowPath::from("/dir/b.txt");
+        store.insert_file(a.clone(), Bytes::from_static(b"one"), None).unwrap();
+        
+        let id = store.link(&a, b.clone()).unwrap();
+        assert_eq!(store.link_id(&b), Some(id));
+        assert_eq!(store.link_count(&a), 2);
+        assert_eq!(store.hard_links(&b), vec![a.clone(), b.clone()]);
+        assert_eq!(store.get(&b).unwrap().get_file_data().unwrap().unwrap(), Bytes::from_static(b"one"));
+        
+        // A write through either name is visible through the other
+        store.insert_file(b.clone(), Bytes::from_static(b"two"), None).unwrap();
+        assert_eq!(store.get(&a).unwrap().get_file_data().unwrap().unwrap(), Bytes::from_static(b"two"));
+        
+        // Deleting one name leaves the other in place, unlinked
+        store.mark_deleted(a.clone()).unwrap();
+        assert_eq!(store.link_count(&b), 1);
+        assert_eq!(store.link_id(&b), None);
+        assert_eq!(store.get(&b).unwrap().get_file_data().unwrap().unwrap(), Bytes::from_static(b"two"));
+    }
+    
+    #[test]
+    fn test_link_requires_file_and_free_target() {
+        let store = OverrideStore::with_defaults();
+        let file = ShadowPath::from("/file");
+        let dir = ShadowPath::from("/dir");
+        store.insert_file(file.clone(), Bytes::from_static(b"x"), None).unwrap();
+        store.insert_directory(dir.clone(), None).unwrap();
+        
+        assert!(matches!(store.link(&dir, ShadowPath::from("/d2")), Err(ShadowError::IsADirectory { .. })));
+        assert!(matches!(store.link(&ShadowPath::from("/missing"), ShadowPath::from("/m2")), Err(ShadowError::NotFound { .. })));
+        assert!(matches!(store.link(&file, dir.clone()), Err(ShadowError::AlreadyExists { .. })));
+        
+        // A tombstone may be replaced by a link
+        let gone = ShadowPath::from("/gone");
+        store.mark_deleted(gone.clone()).unwrap();
+        store.link(&file, gone.clone()).unwrap();
+        assert_eq!(store.link_count(&gone), 2);
+    }
+}
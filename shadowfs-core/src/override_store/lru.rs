@@ -3,11 +3,16 @@
 use crate::types::ShadowPath;
 use super::entry::OverrideEntry;
 use super::size::calculate_entry_size;
-use dashmap::DashMap;
-use indexmap::IndexMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use super::optimization::ShardedMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Mutex;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use std::time::Instant;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use web_time::Instant;
 
 /// Statistics about path access patterns.
 #[derive(Debug, Clone)]
@@ -39,7 +44,8 @@ impl AccessStats {
 }
 
 /// Eviction policy for when memory limits are reached.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EvictionPolicy {
     /// Evict least recently used entries
     Lru,
@@ -52,50 +58,127 @@ pub enum EvictionPolicy {
     
     /// Evict largest entries first
     SizeWeighted,
+    
+    /// Scan-resistant 2Q: entries used only once go first, oldest first,
+    /// then frequently used entries in LRU order
+    ///
+    /// Recently evicted paths are remembered, and one that comes back is
+    /// treated as frequently used straight away.
+    TwoQueue,
+}
+
+impl EvictionPolicy {
+    /// Every policy, in declaration order.
+    pub const ALL: [EvictionPolicy; 5] = [
+        EvictionPolicy::Lru,
+        EvictionPolicy::Lfu,
+        EvictionPolicy::Fifo,
+        EvictionPolicy::SizeWeighted,
+        EvictionPolicy::TwoQueue,
+    ];
+    
+    /// Returns the name used in configuration and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvictionPolicy::Lru => "lru",
+            EvictionPolicy::Lfu => "lfu",
+            EvictionPolicy::Fifo => "fifo",
+            EvictionPolicy::SizeWeighted => "size-weighted",
+            EvictionPolicy::TwoQueue => "2q",
+        }
+    }
+    
+    /// Parses a policy name as returned by [`EvictionPolicy::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|policy| policy.name() == name)
+    }
+}
+
+/// Number of evicted paths remembered by [`EvictionPolicy::TwoQueue`].
+const GHOST_CAPACITY: usize = 1024;
+
+/// Independently locked parts of an [`LruTracker`]; accesses to paths in
+/// different shards never wait for each other.
+const SHARDS: usize = 64;
+
+/// What the tracker knows about a path.
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    /// Tick of the last access; a larger tick is a more recent access
+    tick: u64,
+    
+    /// When the path was last accessed
+    last_accessed: Instant,
+    
+    /// Accesses recorded for the path
+    count: u64,
+}
+
+/// The paths of one shard.
+#[derive(Debug, Clone, Default)]
+struct Shard {
+    accesses: HashMap<ShadowPath, Access>,
+    
+    /// Recently evicted paths, by the tick of their eviction, for the 2Q policy
+    ghosts: HashMap<ShadowPath, u64>,
 }
 
 /// Tracks access patterns for LRU eviction.
+///
+/// Paths are spread over independently locked shards, and every access is
+/// stamped from one atomic tick, so recording accesses scales with the
+/// threads doing it while the tracker still knows the exact LRU order.
+/// Putting paths in that order costs a sort, paid only when selecting
+/// victims or listing the least recently used paths.
 pub struct LruTracker {
-    /// Ordered map of paths to last access time
-    access_order: Mutex<IndexMap<ShadowPath, Instant>>,
+    shards: Box<[Mutex<Shard>]>,
     
-    /// Access count for each path
-    access_count: DashMap<ShadowPath, AtomicU64>,
+    /// Source of access and eviction ticks
+    tick: AtomicU64,
     
-    /// Generation counter for versioning
-    generation: AtomicU64,
+    /// Remembered evicted paths across all shards
+    ghost_count: AtomicUsize,
 }
 
 impl LruTracker {
     /// Creates a new LRU tracker.
     pub fn new() -> Self {
         Self {
-            access_order: Mutex::new(IndexMap::new()),
-            access_count: DashMap::new(),
-            generation: AtomicU64::new(0),
+            shards: (0..SHARDS).map(|_| Mutex::new(Shard::default())).collect(),
+            tick: AtomicU64::new(0),
+            ghost_count: AtomicUsize::new(0),
         }
     }
     
     /// Records an access to a path.
     pub fn record_access(&self, path: &ShadowPath) {
+        self.record_accesses(path, 1);
+    }
+    
+    /// Records `accesses` accesses to a path at once, as one sampled access
+    /// stands for several.
+    pub fn record_accesses(&self, path: &ShadowPath, accesses: u64) {
         let now = Instant::now();
-        
-        // Update access count
-        self.access_count
-            .entry(path.clone())
-            .or_insert_with(|| AtomicU64::new(0))
-            .fetch_add(1, Ordering::Relaxed);
-        
-        // Update access order
-        let mut order = self.access_order.lock().unwrap();
-        
-        // Remove and re-insert to move to end (most recent)
-        // shift_remove preserves the order of remaining elements
-        order.shift_remove(path);
-        order.insert(path.clone(), now);
-        
-        // Increment generation
-        self.generation.fetch_add(1, Ordering::Relaxed);
+        let mut shard = self.shard(path).lock().unwrap();
+        let tick = self.next_tick();
+        
+        // A path evicted not long ago is known to be reused, so it counts
+        // as frequently used
+        let returning = shard.ghosts.remove(path).is_some();
+        if returning {
+            self.ghost_count.fetch_sub(1, Ordering::Relaxed);
+        }
+        match shard.accesses.get_mut(path) {
+            Some(access) => {
+                access.count = if returning { access.count.max(1) + accesses } else { access.count + accesses };
+                access.tick = tick;
+                access.last_accessed = now;
+            }
+            None => {
+                let count = if returning { 1 + accesses } else { accesses };
+                shard.accesses.insert(path.clone(), Access { tick, last_accessed: now, count });
+            }
+        }
     }
     
     /// Gets the least recently used paths.
@@ -106,65 +189,100 @@ impl LruTracker {
     /// # Returns
     /// Vector of paths ordered from least to most recently used
     pub fn get_least_recently_used(&self, count: usize) -> Vec<ShadowPath> {
-        let order = self.access_order.lock().unwrap();
-        
-        order.keys()
-            .take(count)
-            .cloned()
-            .collect()
+        let mut order = self.accesses();
+        if count < order.len() {
+            order.select_nth_unstable_by_key(count, |(_, access)| access.tick);
+            order.truncate(count);
+        }
+        order.sort_unstable_by_key(|(_, access)| access.tick);
+        order.into_iter().map(|(path, _)| path).collect()
     }
     
     /// Removes tracking data for a path.
     pub fn remove_entry(&self, path: &ShadowPath) {
-        self.access_count.remove(path);
-        
-        let mut order = self.access_order.lock().unwrap();
-        order.shift_remove(path);
+        self.shard(path).lock().unwrap().accesses.remove(path);
+    }
+    
+    /// Returns a copy of the access order and counts as they are now, for
+    /// selecting victims without racing concurrent accesses.
+    pub(crate) fn snapshot(&self) -> Self {
+        Self {
+            shards: self.shards.iter().map(|shard| Mutex::new(shard.lock().unwrap().clone())).collect(),
+            tick: AtomicU64::new(self.tick.load(Ordering::Relaxed)),
+            ghost_count: AtomicUsize::new(self.ghost_count.load(Ordering::Relaxed)),
+        }
+    }
+    
+    /// Remembers that `path` was evicted, so 2Q can promote it if it returns.
+    pub fn record_eviction(&self, path: &ShadowPath) {
+        let tick = self.next_tick();
+        if self.shard(path).lock().unwrap().ghosts.insert(path.clone(), tick).is_none()
+            && self.ghost_count.fetch_add(1, Ordering::Relaxed) >= GHOST_CAPACITY
+        {
+            self.forget_oldest_ghosts();
+        }
+    }
+    
+    /// Forgets the evicted paths remembered longest, down to the capacity.
+    fn forget_oldest_ghosts(&self) {
+        let mut ghosts: Vec<(ShadowPath, u64)> = self.shards.iter()
+            .flat_map(|shard| {
+                let shard = shard.lock().unwrap();
+                shard.ghosts.iter().map(|(path, &tick)| (path.clone(), tick)).collect::<Vec<_>>()
+            })
+            .collect();
+        let excess = ghosts.len().saturating_sub(GHOST_CAPACITY);
+        ghosts.sort_unstable_by_key(|(_, tick)| *tick);
+        for (path, tick) in ghosts.into_iter().take(excess) {
+            let mut shard = self.shard(&path).lock().unwrap();
+            // Evicted again since; then it is no longer among the oldest
+            if shard.ghosts.get(&path) == Some(&tick) {
+                shard.ghosts.remove(&path);
+                self.ghost_count.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+    
+    /// Returns the next access or eviction tick.
+    fn next_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed) + 1
+    }
+    
+    /// Returns the shard `path` is tracked in.
+    fn shard(&self, path: &ShadowPath) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        &self.shards[(hasher.finish() % SHARDS as u64) as usize]
+    }
+    
+    /// Returns what is known about every tracked path, in no order.
+    fn accesses(&self) -> Vec<(ShadowPath, Access)> {
+        let mut accesses = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            accesses.extend(shard.accesses.iter().map(|(path, access)| (path.clone(), *access)));
+        }
+        accesses
+    }
+    
+    /// Returns what is known about every tracked path, least recently used first.
+    fn accesses_in_lru_order(&self) -> Vec<(ShadowPath, Access)> {
+        let mut accesses = self.accesses();
+        accesses.sort_unstable_by_key(|(_, access)| access.tick);
+        accesses
     }
     
     /// Gets access statistics for a path.
     pub fn get_access_stats(&self, path: &ShadowPath) -> Option<AccessStats> {
-        let order = self.access_order.lock().unwrap();
-        
-        if let Some(&last_accessed) = order.get(path) {
-            let access_count = self.access_count
-                .get(path)
-                .map(|entry| entry.load(Ordering::Relaxed))
-                .unwrap_or(0);
-            
-            let mut stats = AccessStats {
-                last_accessed,
-                access_count,
-                age_seconds: 0,
-            };
-            stats.update_age();
-            
-            Some(stats)
-        } else {
-            None
-        }
+        let access = *self.shard(path).lock().unwrap().accesses.get(path)?;
+        Some(access_stats(access))
     }
     
-    /// Gets all tracked paths with their access stats.
+    /// Gets all tracked paths with their access stats, least recently used first.
     pub fn get_all_stats(&self) -> Vec<(ShadowPath, AccessStats)> {
-        let order = self.access_order.lock().unwrap();
-        
-        order.iter()
-            .map(|(path, &last_accessed)| {
-                let access_count = self.access_count
-                    .get(path)
-                    .map(|entry| entry.load(Ordering::Relaxed))
-                    .unwrap_or(0);
-                
-                let mut stats = AccessStats {
-                    last_accessed,
-                    access_count,
-                    age_seconds: 0,
-                };
-                stats.update_age();
-                
-                (path.clone(), stats)
-            })
+        self.accesses_in_lru_order()
+            .into_iter()
+            .map(|(path, access)| (path, access_stats(access)))
             .collect()
     }
     
@@ -180,26 +298,21 @@ impl LruTracker {
     pub fn select_victims(
         &self,
         policy: EvictionPolicy,
-        entries: &DashMap<ShadowPath, std::sync::Arc<OverrideEntry>>,
+        entries: &ShardedMap<ShadowPath, std::sync::Arc<OverrideEntry>>,
         target_bytes: usize,
     ) -> Vec<ShadowPath> {
-        // Get candidates based on policy
+        // Get candidates based on policy; every ordering below is stable and
+        // starts from LRU order, so ties go to the least recently used entry
         let candidates: Vec<ShadowPath> = match policy {
             EvictionPolicy::Lru => {
-                // IndexMap preserves order - first entries are least recently used
-                let order = self.access_order.lock().unwrap();
-                order.keys().cloned().collect()
+                self.accesses_in_lru_order().into_iter().map(|(path, _)| path).collect()
             }
             
             EvictionPolicy::Lfu => {
                 // Sort by access count (ascending)
-                let mut freq_list: Vec<_> = self.access_count
-                    .iter()
-                    .map(|entry| {
-                        let path = entry.key().clone();
-                        let count = entry.value().load(Ordering::Relaxed);
-                        (path, count)
-                    })
+                let mut freq_list: Vec<_> = self.accesses_in_lru_order()
+                    .into_iter()
+                    .map(|(path, access)| (path, access.count))
                     .collect();
                 
                 freq_list.sort_by_key(|(_, count)| *count);
@@ -207,13 +320,11 @@ impl LruTracker {
             }
             
             EvictionPolicy::Fifo => {
-                // Sort by creation time (oldest first)
-                let mut time_list: Vec<_> = entries.iter()
-                    .map(|entry| {
-                        let path = entry.key().clone();
-                        let created = entry.value().created_at;
-                        (path, created)
-                    })
+                // Sort by creation on the monotonic clock (oldest first), so
+                // a wall clock stepped back cannot make old entries look new
+                let mut time_list: Vec<_> = self.in_lru_order(entries)
+                    .into_iter()
+                    .map(|(path, entry)| (path, entry.created))
                     .collect();
                 
                 time_list.sort_by_key(|(_, time)| *time);
@@ -222,10 +333,10 @@ impl LruTracker {
             
             EvictionPolicy::SizeWeighted => {
                 // Sort by size (largest first)
-                let mut size_list: Vec<_> = entries.iter()
-                    .map(|entry| {
-                        let path = entry.key().clone();
-                        let size = calculate_entry_size(entry.value());
+                let mut size_list: Vec<_> = self.in_lru_order(entries)
+                    .into_iter()
+                    .map(|(path, entry)| {
+                        let size = calculate_entry_size(&entry);
                         (path, size)
                     })
                     .collect();
@@ -233,6 +344,16 @@ impl LruTracker {
                 size_list.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
                 size_list.into_iter().map(|(path, _)| path).collect()
             }
+            
+            EvictionPolicy::TwoQueue => {
+                // Entries used once form the probation queue; since their only
+                // access was their insertion, LRU order is also insertion order
+                let (probation, frequent): (Vec<_>, Vec<_>) = self.accesses_in_lru_order()
+                    .into_iter()
+                    .partition(|(_, access)| access.count <= 1);
+                
+                probation.into_iter().chain(frequent).map(|(path, _)| path).collect()
+            }
         };
         
         // Select victims until we reach target bytes
@@ -245,13 +366,40 @@ impl LruTracker {
             }
             
             if let Some(entry) = entries.get(&path) {
-                freed_bytes += calculate_entry_size(entry.value());
+                freed_bytes += calculate_entry_size(&entry);
                 victims.push(path);
             }
         }
         
         victims
     }
+    
+    /// Returns a snapshot of `entries`, least recently used first.
+    ///
+    /// Entries the tracker has not seen sort before all others.
+    fn in_lru_order(
+        &self,
+        entries: &ShardedMap<ShadowPath, std::sync::Arc<OverrideEntry>>,
+    ) -> Vec<(ShadowPath, std::sync::Arc<OverrideEntry>)> {
+        let ticks: HashMap<ShadowPath, u64> = self.accesses()
+            .into_iter()
+            .map(|(path, access)| (path, access.tick))
+            .collect();
+        let mut snapshot: Vec<_> = entries.iter().collect();
+        snapshot.sort_by_key(|(path, _)| ticks.get(path).copied().unwrap_or(0));
+        snapshot
+    }
+}
+
+/// Returns the statistics of a path as `access` describes it.
+fn access_stats(access: Access) -> AccessStats {
+    let mut stats = AccessStats {
+        last_accessed: access.last_accessed,
+        access_count: access.count,
+        age_seconds: 0,
+    };
+    stats.update_age();
+    stats
 }
 
 impl Default for LruTracker {
@@ -340,7 +488,7 @@ mod tests {
     #[test]
     fn test_eviction_policy_lru() {
         let tracker = LruTracker::new();
-        let entries = DashMap::new();
+        let entries = ShardedMap::new();
         
         // Create test entries
         let paths: Vec<_> = (0..5)
@@ -367,7 +515,9 @@ mod tests {
                     platform_specific: PlatformMetadata::Linux { inode: i as u64, nlink: 1 },
                 },
                 created_at: SystemTime::now(),
+                created: 0,
                 last_accessed: AtomicU64::new(0),
+                appended: None,
             };
             
             entries.insert(path.clone(), std::sync::Arc::new(entry));
@@ -389,7 +539,7 @@ mod tests {
     #[test]
     fn test_eviction_policy_lfu() {
         let tracker = LruTracker::new();
-        let entries = DashMap::new();
+        let entries = ShardedMap::new();
         
         let path1 = ShadowPath::new("/file1".into());
         let path2 = ShadowPath::new("/file2".into());
@@ -415,7 +565,9 @@ mod tests {
                     platform_specific: PlatformMetadata::Linux { inode: i as u64, nlink: 1 },
                 },
                 created_at: SystemTime::now(),
+                created: 0,
                 last_accessed: AtomicU64::new(0),
+                appended: None,
             };
             entries.insert((*path).clone(), std::sync::Arc::new(entry));
         }
@@ -444,7 +596,7 @@ mod tests {
     #[test]
     fn test_eviction_policy_size_weighted() {
         let tracker = LruTracker::new();
-        let entries = DashMap::new();
+        let entries = ShardedMap::new();
         
         // Create entries with different sizes
         let sizes = [1000, 500, 2000, 300];
@@ -469,7 +621,9 @@ mod tests {
                         platform_specific: PlatformMetadata::Linux { inode: i as u64, nlink: 1 },
                     },
                     created_at: SystemTime::now(),
+                    created: 0,
                     last_accessed: AtomicU64::new(0),
+                    appended: None,
                 };
                 
                 entries.insert(path.clone(), std::sync::Arc::new(entry));
@@ -490,6 +644,109 @@ mod tests {
         }
     }
     
+    fn file_entry(path: &ShadowPath, size: usize, created: u64) -> std::sync::Arc<OverrideEntry> {
+        let mut entry = OverrideEntry::new(
+            path.clone(),
+            OverrideContent::File {
+                data: Bytes::from(vec![0u8; size]),
+                content_hash: [0u8; 32],
+                is_compressed: false,
+            },
+            None,
+            FileMetadata::default(),
+        );
+        entry.created = created;
+        std::sync::Arc::new(entry)
+    }
+    
+    #[test]
+    fn test_eviction_policy_fifo() {
+        let tracker = LruTracker::new();
+        let entries = ShardedMap::new();
+        let start = crate::clock::now();
+        
+        let paths: Vec<_> = (0..3)
+            .map(|i| ShadowPath::new(format!("/file{}", i).into()))
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            entries.insert(path.clone(), file_entry(path, 100, start + i as u64 * 1000));
+            tracker.record_access(path);
+        }
+        
+        // Using the oldest entry does not save it
+        tracker.record_access(&paths[0]);
+        tracker.record_access(&paths[0]);
+        
+        let victims = tracker.select_victims(EvictionPolicy::Fifo, &entries, usize::MAX);
+        assert_eq!(victims, paths);
+    }
+    
+    #[test]
+    fn test_fifo_ignores_wall_clock_jumps() {
+        let tracker = LruTracker::new();
+        let entries = ShardedMap::new();
+        let paths = vec![ShadowPath::from("/old"), ShadowPath::from("/new")];
+        
+        entries.insert(paths[0].clone(), file_entry(&paths[0], 100, crate::clock::now()));
+        // NTP steps the wall clock back an hour between the two writes
+        crate::clock::testing::jump_wall_clock(-3_600_000);
+        crate::clock::testing::advance(std::time::Duration::from_millis(1));
+        entries.insert(paths[1].clone(), file_entry(&paths[1], 100, crate::clock::now()));
+        tracker.record_access(&paths[1]);
+        tracker.record_access(&paths[0]);
+        
+        assert!(entries.get(&paths[1]).unwrap().created_at < entries.get(&paths[0]).unwrap().created_at);
+        let victims = tracker.select_victims(EvictionPolicy::Fifo, &entries, usize::MAX);
+        assert_eq!(victims, paths);
+    }
+    
+    #[test]
+    fn test_eviction_policy_two_queue() {
+        let tracker = LruTracker::new();
+        let entries = ShardedMap::new();
+        
+        let paths: Vec<_> = (0..4)
+            .map(|i| ShadowPath::new(format!("/file{}", i).into()))
+            .collect();
+        for path in &paths {
+            entries.insert(path.clone(), file_entry(path, 100, crate::clock::now()));
+            tracker.record_access(path);
+        }
+        
+        // file0 and file2 are used again; a scan over file1 and file3 must not evict them
+        tracker.record_access(&paths[2]);
+        tracker.record_access(&paths[0]);
+        
+        let victims = tracker.select_victims(EvictionPolicy::TwoQueue, &entries, usize::MAX);
+        assert_eq!(victims, vec![paths[1].clone(), paths[3].clone(), paths[2].clone(), paths[0].clone()]);
+        
+        // An evicted path that comes back skips the probation queue
+        tracker.remove_entry(&paths[1]);
+        tracker.record_eviction(&paths[1]);
+        tracker.record_access(&paths[1]);
+        
+        let victims = tracker.select_victims(EvictionPolicy::TwoQueue, &entries, usize::MAX);
+        assert_eq!(victims, vec![paths[3].clone(), paths[2].clone(), paths[0].clone(), paths[1].clone()]);
+    }
+    
+    #[test]
+    fn test_ghosts_are_bounded() {
+        let tracker = LruTracker::new();
+        let paths: Vec<_> = (0..GHOST_CAPACITY + 10)
+            .map(|i| ShadowPath::new(format!("/file{}", i).into()))
+            .collect();
+        for path in &paths {
+            tracker.record_eviction(path);
+        }
+        assert_eq!(tracker.ghost_count.load(Ordering::Relaxed), GHOST_CAPACITY);
+        
+        // The oldest evictions are forgotten, the newest still promote
+        tracker.record_access(&paths[0]);
+        tracker.record_access(&paths[GHOST_CAPACITY + 9]);
+        assert_eq!(tracker.get_access_stats(&paths[0]).unwrap().access_count, 1);
+        assert_eq!(tracker.get_access_stats(&paths[GHOST_CAPACITY + 9]).unwrap().access_count, 2);
+    }
+    
     #[test]
     fn test_access_stats_age() {
         let mut stats = AccessStats::new();
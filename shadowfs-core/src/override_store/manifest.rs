@@ -0,0 +1,367 @@
+//! Structured manifests of the override set.
+//!
+//! [`OverrideStore::export_manifest`] describes every override — its path,
+//! kind, size, content hash, timestamps and metadata, tombstones included —
+//! without its content. The manifest serializes to stable JSON sorted by
+//! path, so two shadow sessions can be diffed or audited in CI with ordinary
+//! tools.
+//!
+//! [`OverrideStore::apply_manifest`] recreates the overrides a manifest
+//! lists, taking file content from a directory laid out like the shadow, such
+//! as an extracted archive. With [`ManifestContent::Lazy`] the files are not
+//! read up front: each becomes a delta override over its file in the
+//! directory, read when it is accessed.
+
+use crate::error::ShadowError;
+use crate::override_store::optimization::hash_content;
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::types::{FileMetadata, ShadowPath};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Version of the manifest written by [`OverrideStore::export_manifest`].
+pub const MANIFEST_FORMAT_VERSION: u32 = 1;
+
+/// Every override of a store, without content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverrideManifest {
+    /// Format version, [`MANIFEST_FORMAT_VERSION`] when written
+    pub version: u32,
+    
+    /// One entry per override, sorted by path
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// An override listed in a manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path of the override in the shadow
+    pub path: ShadowPath,
+    
+    /// Whether the override is a file, a directory or a tombstone
+    pub kind: ManifestEntryKind,
+    
+    /// Size of the content in bytes, uncompressed
+    pub size: u64,
+    
+    /// BLAKE3 hash of the content, hex encoded; only files have one, and
+    /// only with the `dedup` feature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    
+    /// When the override was made
+    pub overridden_at: SystemTime,
+    
+    /// Metadata of the override
+    pub metadata: FileMetadata,
+    
+    /// Metadata of the source entry it shadows, if there was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_metadata: Option<FileMetadata>,
+}
+
+/// Kind of a listed override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestEntryKind {
+    /// File with content
+    File,
+    /// Directory
+    Directory,
+    /// Tombstone hiding a source entry
+    Deleted,
+}
+
+/// Where [`OverrideStore::apply_manifest`] takes file content from.
+///
+/// Both variants name a directory holding each listed file at its path in
+/// the shadow, so `/src/main.rs` is read from `<dir>/src/main.rs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestContent {
+    /// Read every file into the store now, checking it against the manifest
+    Eager(PathBuf),
+    /// Read each file when it is accessed, through a delta override over it
+    Lazy(PathBuf),
+}
+
+/// Outcome of [`OverrideStore::apply_manifest`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestSummary {
+    /// File overrides recreated, lazy ones included
+    pub files: usize,
+    
+    /// File overrides left to be read when accessed
+    pub lazy_files: usize,
+    
+    /// Directory overrides recreated
+    pub directories: usize,
+    
+    /// Tombstones recreated
+    pub tombstones: usize,
+}
+
+impl OverrideStore {
+    /// Describes every override of the store, sorted by path.
+    ///
+    /// Delta overrides are listed as the files they make up.
+    pub fn export_manifest(&self) -> Result<OverrideManifest, ShadowError> {
+        let mut entries = Vec::new();
+        for entry in self.sorted_entries()? {
+            let (kind, content_hash) = match &entry.content {
+                OverrideContent::File { .. } => {
+                    let data = entry.get_file_data()?.unwrap_or_default();
+                    (ManifestEntryKind::File, content_hash_hex(&data))
+                }
+                OverrideContent::Directory { .. } => (ManifestEntryKind::Directory, None),
+                OverrideContent::Deleted => (ManifestEntryKind::Deleted, None),
+            };
+            entries.push(ManifestEntry {
+                path: entry.path.clone(),
+                kind,
+                size: entry.override_metadata.size,
+                content_hash,
+                overridden_at: entry.created_at,
+                metadata: entry.override_metadata.clone(),
+                original_metadata: entry.original_metadata.clone(),
+            });
+        }
+        Ok(OverrideManifest { version: MANIFEST_FORMAT_VERSION, entries })
+    }
+    
+    /// Recreates the overrides listed in `manifest` on top of the store.
+    ///
+    /// Directories, tombstones and eagerly read files are applied in one
+    /// transaction. Lazy files are layered over their content afterwards,
+    /// replacing any override their paths had.
+    ///
+    /// # Errors
+    /// Fails for an unsupported manifest version, and with
+    /// [`ShadowError::Conflict`] if a file in `content` is missing or does not
+    /// match the size or hash listed for it, before anything is applied.
+    pub fn apply_manifest(
+        &self,
+        manifest: &OverrideManifest,
+        content: &ManifestContent,
+    ) -> Result<ManifestSummary, ShadowError> {
+        if manifest.version != MANIFEST_FORMAT_VERSION {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!(
+                    "Unsupported manifest format version {} (expected {})",
+                    manifest.version, MANIFEST_FORMAT_VERSION
+                ),
+            });
+        }
+        
+        let mut summary = ManifestSummary::default();
+        let mut staged = Vec::new();
+        let mut lazy = Vec::new();
+        for listed in &manifest.entries {
+            let entry_content = match listed.kind {
+                ManifestEntryKind::File => {
+                    summary.files += 1;
+                    match content {
+                        ManifestContent::Eager(dir) => {
+                            let data = read_listed(dir, listed)?;
+                            self.file_override(data, listed.original_metadata.as_ref()).0
+                        }
+                        ManifestContent::Lazy(dir) => {
+                            lazy.push((listed, check_listed_size(dir, listed)?));
+                            continue;
+                        }
+                    }
+                }
+                ManifestEntryKind::Directory => {
+                    summary.directories += 1;
+                    OverrideStore::directory_override(None).0
+                }
+                ManifestEntryKind::Deleted => {
+                    summary.tombstones += 1;
+                    OverrideContent::Deleted
+                }
+            };
+            let mut entry = OverrideEntry::new(
+                listed.path.clone(),
+                entry_content,
+                listed.original_metadata.clone(),
+                listed.metadata.clone(),
+            );
+            entry.created_at = listed.overridden_at;
+            staged.push(entry);
+        }
+        
+        self.transaction(|tx| {
+            for entry in staged {
+                tx.stage_entry(Arc::new(entry));
+            }
+            for (listed, _) in &lazy {
+                if tx.get(&listed.path).is_some() {
+                    tx.remove(listed.path.clone());
+                }
+            }
+            Ok(())
+        })?;
+        
+        // An empty write leaves a delta reading all of its file from the source
+        for (listed, source) in lazy {
+            self.drop_delta(&listed.path);
+            self.write_range(listed.path.clone(), source, 0, &[], listed.original_metadata.clone())?;
+            summary.lazy_files += 1;
+        }
+        Ok(summary)
+    }
+    
+    /// Returns every override, deltas materialized, sorted by path so that
+    /// directories precede their contents.
+    pub(crate) fn sorted_entries(&self) -> Result<Vec<Arc<OverrideEntry>>, ShadowError> {
+        let mut entries: HashMap<ShadowPath, Arc<OverrideEntry>> = self.all_entries()?
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        for entry in self.delta_entries() {
+            entries.insert(entry.path.clone(), Arc::new(entry));
+        }
+        let mut entries: Vec<Arc<OverrideEntry>> = entries.into_values().collect();
+        entries.sort_by(|a, b| a.path.as_path().cmp(b.path.as_path()));
+        Ok(entries)
+    }
+}
+
+/// Returns the hex BLAKE3 hash of `data`, or `None` without the `dedup` feature.
+fn content_hash_hex(data: &[u8]) -> Option<String> {
+    if !cfg!(feature = "dedup") {
+        return None;
+    }
+    Some(hash_content(data).iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Returns where the content of `path` lies under `dir`.
+fn content_path(dir: &Path, path: &ShadowPath) -> PathBuf {
+    let mut file = dir.to_path_buf();
+    for component in path.as_path().components() {
+        if let Component::Normal(name) = component {
+            file.push(name);
+        }
+    }
+    file
+}
+
+/// Reads the content of a listed file, checking its size and hash.
+fn read_listed(dir: &Path, listed: &ManifestEntry) -> Result<Bytes, ShadowError> {
+    let file = content_path(dir, &listed.path);
+    let data = std::fs::read(&file).map_err(|e| mismatch(listed, format!("cannot read {}: {}", file.display(), e)))?;
+    if data.len() as u64 != listed.size {
+        return Err(mismatch(listed, format!("{} has {} bytes, the manifest lists {}", file.display(), data.len(), listed.size)));
+    }
+    if let (Some(expected), Some(actual)) = (&listed.content_hash, content_hash_hex(&data)) {
+        if *expected != actual {
+            return Err(mismatch(listed, format!("{} does not match the hash in the manifest", file.display())));
+        }
+    }
+    Ok(Bytes::from(data))
+}
+
+/// Checks that a file to be read lazily exists with the listed size.
+fn check_listed_size(dir: &Path, listed: &ManifestEntry) -> Result<PathBuf, ShadowError> {
+    let file = content_path(dir, &listed.path);
+    let size = std::fs::metadata(&file)
+        .map_err(|e| mismatch(listed, format!("cannot read {}: {}", file.display(), e)))?
+        .len();
+    if size != listed.size {
+        return Err(mismatch(listed, format!("{} has {} bytes, the manifest lists {}", file.display(), size, listed.size)));
+    }
+    Ok(file)
+}
+
+fn mismatch(listed: &ManifestEntry, reason: String) -> ShadowError {
+    ShadowError::Conflict { path: listed.path.clone(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    fn session() -> OverrideStore {
+        let store = OverrideStore::with_defaults();
+        store.create_directory_hierarchy(&path("/src")).unwrap();
+        store.insert_file(path("/src/main.rs"), Bytes::from("fn main() {}\n"), None).unwrap();
+        store.mark_deleted(path("/src/old.rs")).unwrap();
+        store
+    }
+    
+    /// Writes the file overrides of `store` under `dir`, laid out like the shadow.
+    fn write_content(store: &OverrideStore, dir: &Path) {
+        for entry in store.sorted_entries().unwrap() {
+            if let Some(data) = entry.get_file_data().unwrap() {
+                let file = content_path(dir, &entry.path);
+                std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+                std::fs::write(file, data).unwrap();
+            }
+        }
+    }
+    
+    #[test]
+    fn test_export_manifest() {
+        let manifest = session().export_manifest().unwrap();
+        let listed: Vec<(&str, ManifestEntryKind)> = manifest.entries.iter()
+            .map(|entry| (entry.path.as_path().to_str().unwrap(), entry.kind))
+            .collect();
+        assert_eq!(listed, vec![
+            ("/", ManifestEntryKind::Directory),
+            ("/src", ManifestEntryKind::Directory),
+            ("/src/main.rs", ManifestEntryKind::File),
+            ("/src/old.rs", ManifestEntryKind::Deleted),
+        ]);
+        
+        let main = &manifest.entries[2];
+        assert_eq!(main.size, 13);
+        assert_eq!(main.content_hash.as_deref().map(str::len), cfg!(feature = "dedup").then_some(64));
+        
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert_eq!(serde_json::from_str::<OverrideManifest>(&json).unwrap(), manifest);
+    }
+    
+    #[test]
+    fn test_apply_manifest_eagerly() {
+        let original = session();
+        let dir = tempfile::tempdir().unwrap();
+        write_content(&original, dir.path());
+        
+        let store = OverrideStore::with_defaults();
+        let manifest = original.export_manifest().unwrap();
+        let summary = store.apply_manifest(&manifest, &ManifestContent::Eager(dir.path().to_path_buf())).unwrap();
+        assert_eq!(summary, ManifestSummary { files: 1, lazy_files: 0, directories: 2, tombstones: 1 });
+        assert_eq!(store.export_manifest().unwrap(), manifest);
+        
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() { panic!() }\n").unwrap();
+        let changed = OverrideStore::with_defaults()
+            .apply_manifest(&manifest, &ManifestContent::Eager(dir.path().to_path_buf()));
+        assert!(matches!(changed, Err(ShadowError::Conflict { .. })));
+    }
+    
+    #[test]
+    fn test_apply_manifest_lazily() {
+        let original = session();
+        let dir = tempfile::tempdir().unwrap();
+        write_content(&original, dir.path());
+        
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/src/main.rs"), Bytes::from("stale"), None).unwrap();
+        let manifest = original.export_manifest().unwrap();
+        let summary = store.apply_manifest(&manifest, &ManifestContent::Lazy(dir.path().to_path_buf())).unwrap();
+        assert_eq!(summary.lazy_files, 1);
+        
+        let main = path("/src/main.rs");
+        assert!(store.has_delta(&main));
+        assert_eq!(store.delta_info(&main).unwrap().stored_bytes, 0);
+        assert_eq!(store.read_range(&main, 0, 64).unwrap(), "fn main() {}\n");
+        assert!(store.is_deleted(&path("/src/old.rs")));
+    }
+}
@@ -10,7 +10,7 @@ pub struct MemoryTracker {
     current_usage: AtomicUsize,
     
     /// Maximum allowed memory in bytes
-    max_allowed: usize,
+    max_allowed: AtomicUsize,
     
     /// Total number of allocations made
     allocation_count: AtomicU64,
@@ -21,7 +21,7 @@ impl MemoryTracker {
     pub fn new(max_allowed: usize) -> Self {
         Self {
             current_usage: AtomicUsize::new(0),
-            max_allowed,
+            max_allowed: AtomicUsize::new(max_allowed),
             allocation_count: AtomicU64::new(0),
         }
     }
@@ -42,11 +42,11 @@ impl MemoryTracker {
             let new_usage = current.saturating_add(size);
             
             // Check if allocation would exceed limit
-            if new_usage > self.max_allowed {
-                let _available = self.max_allowed.saturating_sub(current);
+            let max_allowed = self.max_allowed();
+            if new_usage > max_allowed {
                 return Err(ShadowError::OverrideStoreFull {
                     current_size: current,
-                    max_size: self.max_allowed,
+                    max_size: max_allowed,
                 });
             }
             
@@ -78,10 +78,21 @@ impl MemoryTracker {
         self.current_usage.load(Ordering::Relaxed)
     }
     
+    /// Returns the maximum allowed memory in bytes.
+    pub fn max_allowed(&self) -> usize {
+        self.max_allowed.load(Ordering::Relaxed)
+    }
+    
+    /// Changes the maximum allowed memory. Memory already allocated is kept,
+    /// so usage may stay above a lowered limit until it is released.
+    pub fn set_max_allowed(&self, max_allowed: usize) {
+        self.max_allowed.store(max_allowed, Ordering::Relaxed);
+    }
+    
     /// Returns the available space in bytes.
     pub fn available_space(&self) -> usize {
         let current = self.current_usage.load(Ordering::Relaxed);
-        self.max_allowed.saturating_sub(current)
+        self.max_allowed().saturating_sub(current)
     }
     
     /// Returns true if memory usage is above 90%.
@@ -92,7 +103,7 @@ impl MemoryTracker {
     /// Returns the memory pressure ratio (0.0 to 1.0).
     pub fn get_pressure_ratio(&self) -> f64 {
         let current = self.current_usage.load(Ordering::Relaxed) as f64;
-        let max = self.max_allowed as f64;
+        let max = self.max_allowed() as f64;
         if max > 0.0 {
             current / max
         } else {
@@ -100,9 +111,11 @@ impl MemoryTracker {
         }
     }
     
-    /// Internal method to release memory (called by MemoryGuard).
-    fn release(&self, size: usize) {
-        self.current_usage.fetch_sub(size, Ordering::AcqRel);
+    /// Releases memory, either from a dropped MemoryGuard or from an allocation whose guard was forgotten.
+    pub(crate) fn release(&self, size: usize) {
+        let _ = self.current_usage.fetch_update(Ordering::AcqRel, Ordering::Relaxed, |current| {
+            Some(current.saturating_sub(size))
+        });
     }
 }
 
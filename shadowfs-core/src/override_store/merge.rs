@@ -0,0 +1,328 @@
+//! Merging the overrides of one store into another.
+//!
+//! [`OverrideStore::merge`] layers a second set of overrides on top of the
+//! ones a store already holds, for example per-test changes on top of a
+//! shared fixture set loaded from a snapshot. Paths only the other store has
+//! are added and paths both stores hold with the same content are left
+//! alone; where the two differ, the [`MergeStrategy`] decides. Conflicts are
+//! per path: a file merged below a path that this store has deleted is
+//! added, and stays hidden behind the tombstone.
+
+use crate::error::{conflict, ShadowError};
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::types::ShadowPath;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Paths named in the error of a failed merge; the rest are counted.
+const REPORTED_CONFLICTS: usize = 5;
+
+/// Which side wins when both stores hold a path with different overrides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MergeStrategy {
+    /// The merged store's override replaces this store's
+    TheirsWins,
+    /// This store's override is kept
+    OursWins,
+    /// Nothing is merged and the merge fails with [`ShadowError::Conflict`]
+    #[default]
+    FailOnConflict,
+}
+
+/// One side of a [`MergeConflict`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeSide {
+    /// Whether the override is a directory
+    pub is_directory: bool,
+    
+    /// Whether the override is a tombstone
+    pub is_deleted: bool,
+    
+    /// Size of the file content, for file overrides
+    pub size: Option<u64>,
+    
+    /// When the override was last modified
+    pub modified: SystemTime,
+}
+
+impl MergeSide {
+    fn of(entry: &OverrideEntry) -> Self {
+        Self {
+            is_directory: matches!(entry.content, OverrideContent::Directory { .. }),
+            is_deleted: matches!(entry.content, OverrideContent::Deleted),
+            size: matches!(entry.content, OverrideContent::File { .. }).then_some(entry.override_metadata.size),
+            modified: entry.override_metadata.modified,
+        }
+    }
+}
+
+/// A path both stores hold with different overrides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    /// Path of the override
+    pub path: ShadowPath,
+    
+    /// This store's override
+    pub ours: MergeSide,
+    
+    /// The merged store's override
+    pub theirs: MergeSide,
+}
+
+/// Outcome of [`OverrideStore::merge`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Overrides only the merged store had
+    pub added: usize,
+    
+    /// Conflicting overrides replaced by the merged store's
+    pub replaced: usize,
+    
+    /// Conflicting overrides kept over the merged store's
+    pub kept: usize,
+    
+    /// Overrides identical in both stores
+    pub unchanged: usize,
+    
+    /// Every conflicting path, sorted, however it was resolved
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// What merging another store would change.
+struct MergePlan {
+    added: Vec<Arc<OverrideEntry>>,
+    differing: Vec<Arc<OverrideEntry>>,
+    conflicts: Vec<MergeConflict>,
+    unchanged: usize,
+}
+
+impl OverrideStore {
+    /// Merges the overrides of `other` into this store.
+    ///
+    /// Every change is applied as one [`OverrideStore::transaction`], so the
+    /// store ends up with all of `other`'s overrides that the strategy lets
+    /// through, or, on error, exactly as it was. `other` is not modified;
+    /// its written ranges are merged as full file overrides.
+    ///
+    /// # Arguments
+    /// * `other` - Store whose overrides are merged in
+    /// * `strategy` - Which side wins for paths both stores hold differently
+    ///
+    /// # Errors
+    /// With [`MergeStrategy::FailOnConflict`], fails with
+    /// [`ShadowError::Conflict`] naming the first conflicting paths if there
+    /// are any; [`OverrideStore::merge_conflicts`] lists all of them.
+    /// Otherwise fails like [`OverrideStore::transaction`].
+    pub fn merge(&self, other: &OverrideStore, strategy: MergeStrategy) -> Result<MergeSummary, ShadowError> {
+        let plan = self.plan_merge(other)?;
+        if strategy == MergeStrategy::FailOnConflict && !plan.conflicts.is_empty() {
+            return Err(conflict_error(&plan.conflicts));
+        }
+        
+        let mut summary = MergeSummary {
+            added: plan.added.len(),
+            unchanged: plan.unchanged,
+            conflicts: plan.conflicts,
+            ..MergeSummary::default()
+        };
+        let mut staged = plan.added;
+        if strategy == MergeStrategy::TheirsWins {
+            summary.replaced = plan.differing.len();
+            staged.extend(plan.differing);
+        } else {
+            summary.kept = plan.differing.len();
+        }
+        
+        self.transaction(|tx| {
+            for entry in staged {
+                tx.stage_entry(entry);
+            }
+            Ok(())
+        })?;
+        Ok(summary)
+    }
+    
+    /// Lists the paths that [`OverrideStore::merge`] would report as
+    /// conflicts, without merging anything.
+    pub fn merge_conflicts(&self, other: &OverrideStore) -> Result<Vec<MergeConflict>, ShadowError> {
+        Ok(self.plan_merge(other)?.conflicts)
+    }
+    
+    fn plan_merge(&self, other: &OverrideStore) -> Result<MergePlan, ShadowError> {
+        let mut theirs: HashMap<ShadowPath, Arc<OverrideEntry>> = other.all_entries()?
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        for entry in other.delta_entries() {
+            theirs.insert(entry.path.clone(), Arc::new(entry));
+        }
+        
+        let mut ours: HashMap<ShadowPath, Arc<OverrideEntry>> = self.all_entries()?
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        // Our written ranges are compared as the files they make up, left
+        // in place until the transaction replaces them
+        for entry in self.delta_entries() {
+            if theirs.contains_key(&entry.path) {
+                ours.insert(entry.path.clone(), Arc::new(entry));
+            }
+        }
+        
+        let mut theirs: Vec<Arc<OverrideEntry>> = theirs.into_values().collect();
+        // Parents first, so that added directories precede their children
+        theirs.sort_by(|a, b| a.path.as_path().cmp(b.path.as_path()));
+        
+        let mut plan = MergePlan {
+            added: Vec::new(),
+            differing: Vec::new(),
+            conflicts: Vec::new(),
+            unchanged: 0,
+        };
+        for entry in theirs {
+            let held = self.held_spelling(&entry.path);
+            match ours.get(held.as_ref().unwrap_or(&entry.path)) {
+                None => plan.added.push(entry),
+                Some(current) if same_override(current, &entry)? => plan.unchanged += 1,
+                Some(current) => {
+                    plan.conflicts.push(MergeConflict {
+                        path: entry.path.clone(),
+                        ours: MergeSide::of(current),
+                        theirs: MergeSide::of(&entry),
+                    });
+                    plan.differing.push(entry);
+                }
+            }
+        }
+        Ok(plan)
+    }
+}
+
+/// Returns true if two overrides show the same thing at their path.
+fn same_override(ours: &Arc<OverrideEntry>, theirs: &Arc<OverrideEntry>) -> Result<bool, ShadowError> {
+    if Arc::ptr_eq(ours, theirs) {
+        return Ok(true);
+    }
+    Ok(match (&ours.content, &theirs.content) {
+        (OverrideContent::File { .. }, OverrideContent::File { .. }) => ours.get_file_data()? == theirs.get_file_data()?,
+        (OverrideContent::Directory { .. }, OverrideContent::Directory { .. }) => true,
+        (OverrideContent::Deleted, OverrideContent::Deleted) => true,
+        _ => false,
+    })
+}
+
+fn conflict_error(conflicts: &[MergeConflict]) -> ShadowError {
+    let mut named: Vec<String> = conflicts.iter()
+        .take(REPORTED_CONFLICTS)
+        .map(|conflict| conflict.path.to_string())
+        .collect();
+    if conflicts.len() > REPORTED_CONFLICTS {
+        named.push(format!("{} more", conflicts.len() - REPORTED_CONFLICTS));
+    }
+    conflict(
+        conflicts[0].path.clone(),
+        format!("{} paths differ between the merged stores: {}", conflicts.len(), named.join(", ")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    fn fixture() -> OverrideStore {
+        let store = OverrideStore::with_defaults();
+        store.insert_directory(path("/etc"), None).unwrap();
+        store.insert_file(path("/etc/hosts"), Bytes::from("127.0.0.1 fixture"), None).unwrap();
+        store.insert_file(path("/etc/motd"), Bytes::from("welcome"), None).unwrap();
+        store
+    }
+    
+    fn per_test() -> OverrideStore {
+        let store = OverrideStore::with_defaults();
+        store.insert_directory(path("/etc"), None).unwrap();
+        store.insert_file(path("/etc/hosts"), Bytes::from("127.0.0.1 test"), None).unwrap();
+        store.insert_file(path("/etc/motd"), Bytes::from("welcome"), None).unwrap();
+        store.mark_deleted(path("/etc/legacy.conf")).unwrap();
+        store
+    }
+    
+    fn content(store: &OverrideStore, p: &str) -> Bytes {
+        store.get(&path(p)).unwrap().get_file_data().unwrap().unwrap()
+    }
+    
+    #[test]
+    fn test_merge_theirs_wins() {
+        let store = fixture();
+        let summary = store.merge(&per_test(), MergeStrategy::TheirsWins).unwrap();
+        
+        assert_eq!((summary.added, summary.replaced, summary.kept, summary.unchanged), (1, 1, 0, 2));
+        assert_eq!(content(&store, "/etc/hosts"), "127.0.0.1 test");
+        assert!(store.is_deleted(&path("/etc/legacy.conf")));
+        
+        let hosts = &summary.conflicts[0];
+        assert_eq!(hosts.path, path("/etc/hosts"));
+        assert_eq!((hosts.ours.size, hosts.theirs.size), (Some(17), Some(14)));
+    }
+    
+    #[test]
+    fn test_merge_ours_wins() {
+        let store = fixture();
+        let summary = store.merge(&per_test(), MergeStrategy::OursWins).unwrap();
+        
+        assert_eq!((summary.added, summary.replaced, summary.kept), (1, 0, 1));
+        assert_eq!(content(&store, "/etc/hosts"), "127.0.0.1 fixture");
+        assert!(store.is_deleted(&path("/etc/legacy.conf")));
+    }
+    
+    #[test]
+    fn test_merge_fail_on_conflict() {
+        let store = fixture();
+        let other = per_test();
+        other.mark_deleted(path("/etc/motd")).unwrap();
+        
+        let conflicts = store.merge_conflicts(&other).unwrap();
+        assert_eq!(conflicts.len(), 2);
+        assert!(conflicts[1].theirs.is_deleted && !conflicts[1].ours.is_deleted);
+        
+        match store.merge(&other, MergeStrategy::FailOnConflict) {
+            Err(ShadowError::Conflict { path: first, reason }) => {
+                assert_eq!(first, path("/etc/hosts"));
+                assert!(reason.contains("2 paths") && reason.contains("/etc/motd"), "{}", reason);
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert!(!store.exists(&path("/etc/legacy.conf")));
+        
+        // Without conflicts the default strategy merges
+        let summary = store.merge(&fixture(), MergeStrategy::default()).unwrap();
+        assert_eq!(summary.unchanged, 3);
+    }
+    
+    #[test]
+    fn test_planning_leaves_written_ranges_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("issue");
+        std::fs::write(&source, "Linux").unwrap();
+        let store = fixture();
+        store.write_range(path("/etc/issue"), &source, 0, b"B", None).unwrap();
+        let other = fixture();
+        other.insert_file(path("/etc/issue"), Bytes::from("Minux"), None).unwrap();
+        
+        assert_eq!(store.merge_conflicts(&other).unwrap()[0].ours.size, Some(5));
+        assert!(store.merge(&other, MergeStrategy::FailOnConflict).is_err());
+        assert!(store.merge(&other, MergeStrategy::OursWins).is_ok());
+        assert!(store.has_delta(&path("/etc/issue")));
+        
+        store.merge(&other, MergeStrategy::TheirsWins).unwrap();
+        assert!(!store.has_delta(&path("/etc/issue")));
+        assert_eq!(content(&store, "/etc/issue"), "Minux");
+    }
+}
@@ -0,0 +1,539 @@
+//! Archives of the merged view of a mount.
+//!
+//! [`OverrideStore::export_merged`] writes what a mount shows, the source
+//! tree with the overrides on top and tombstoned paths left out, as a tar,
+//! gzipped tar or zip archive. The source is read directly rather than
+//! through the mount. Directories are listed in parallel one level at a
+//! time, and file content is streamed into the archive: source files are
+//! copied from disk and files with written ranges are read through their
+//! delta, so only overrides, which are in memory already, are buffered.
+//!
+//! Members are named by their path in the mount, without a leading slash.
+//! Source symlinks are archived as symlinks; sockets, FIFOs and devices are
+//! left out. With the `patterns` feature, files matching the store's read
+//! transforms are archived as transformed.
+
+use crate::error::ShadowError;
+use crate::override_store::archive::{io_error, tar_header, zip_error, zip_time};
+use crate::override_store::rename::{source_metadata, source_path};
+use crate::override_store::{OverrideEntry, OverrideStore};
+use crate::progress::{NoProgress, Progress};
+use crate::types::{FileMetadata, ShadowPath};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{Read, Seek, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Most threads listing directories at once.
+const MAX_WALK_THREADS: usize = 8;
+
+/// Size of the chunks files with written ranges are read in.
+const DELTA_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Archive formats of [`OverrideStore::export_merged`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergedArchiveFormat {
+    /// Uncompressed tar archive
+    Tar,
+    /// Tar archive compressed with gzip
+    TarGz,
+    /// Zip archive with deflated files
+    Zip,
+}
+
+impl MergedArchiveFormat {
+    /// Returns the usual file name extension of the format, such as `tar.gz`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+impl fmt::Display for MergedArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Outcome of exporting the merged view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergedExportSummary {
+    /// Files archived
+    pub files: u64,
+    
+    /// Directories archived
+    pub directories: u64,
+    
+    /// Symlinks archived
+    pub symlinks: u64,
+    
+    /// Bytes of file content archived, before compression
+    pub bytes: u64,
+    
+    /// Files and directories archived from overrides rather than the source
+    pub overridden: u64,
+}
+
+/// Where the content of a path in the merged view comes from.
+enum Origin {
+    Override(Arc<OverrideEntry>),
+    Source(std::fs::Metadata),
+    Delta(std::fs::Metadata, u64),
+}
+
+/// A path of the merged view, found by [`OverrideStore::walk_merged`].
+struct MergedNode {
+    path: ShadowPath,
+    origin: Origin,
+}
+
+impl MergedNode {
+    fn is_directory(&self) -> bool {
+        match &self.origin {
+            Origin::Override(entry) => entry.is_directory(),
+            Origin::Source(metadata) => metadata.is_dir(),
+            Origin::Delta(..) => false,
+        }
+    }
+    
+    /// Returns the size of the file, or `None` for directories and symlinks.
+    fn file_size(&self) -> Option<u64> {
+        match &self.origin {
+            Origin::Override(entry) => (!entry.is_directory()).then(|| entry.uncompressed_size()),
+            Origin::Source(metadata) => metadata.is_file().then_some(metadata.len()),
+            Origin::Delta(_, len) => Some(*len),
+        }
+    }
+}
+
+/// Receives the members of the merged view, such as an archive format.
+pub(super) trait ArchiveSink {
+    fn directory(&mut self, name: &str, metadata: &FileMetadata) -> Result<(), ShadowError>;
+    
+    fn file(&mut self, name: &str, metadata: &FileMetadata, len: u64, content: &mut dyn Read) -> Result<(), ShadowError>;
+    
+    fn symlink(&mut self, name: &str, metadata: &FileMetadata, target: &Path) -> Result<(), ShadowError>;
+}
+
+impl<W: Write> ArchiveSink for tar::Builder<W> {
+    fn directory(&mut self, name: &str, metadata: &FileMetadata) -> Result<(), ShadowError> {
+        let mode = metadata.permissions.to_unix_mode();
+        let mut header = tar_header(tar::EntryType::Directory, mode, metadata.modified, 0);
+        self.append_data(&mut header, format!("{}/", name), std::io::empty()).map_err(io_error)
+    }
+    
+    fn file(&mut self, name: &str, metadata: &FileMetadata, len: u64, content: &mut dyn Read) -> Result<(), ShadowError> {
+        let mode = metadata.permissions.to_unix_mode();
+        let mut header = tar_header(tar::EntryType::Regular, mode, metadata.modified, len as usize);
+        self.append_data(&mut header, name, ExactReader { inner: content, remaining: len }).map_err(io_error)
+    }
+    
+    fn symlink(&mut self, name: &str, metadata: &FileMetadata, target: &Path) -> Result<(), ShadowError> {
+        let mut header = tar_header(tar::EntryType::Symlink, 0o777, metadata.modified, 0);
+        self.append_link(&mut header, name, target).map_err(io_error)
+    }
+}
+
+impl<W: Write + Seek> ArchiveSink for zip::ZipWriter<W> {
+    fn directory(&mut self, name: &str, metadata: &FileMetadata) -> Result<(), ShadowError> {
+        self.add_directory(name, zip_options(metadata)).map_err(zip_error)
+    }
+    
+    fn file(&mut self, name: &str, metadata: &FileMetadata, len: u64, content: &mut dyn Read) -> Result<(), ShadowError> {
+        let options = zip_options(metadata).large_file(len >= u64::from(u32::MAX));
+        self.start_file(name, options).map_err(zip_error)?;
+        std::io::copy(&mut ExactReader { inner: content, remaining: len }, self).map_err(io_error)?;
+        Ok(())
+    }
+    
+    fn symlink(&mut self, name: &str, metadata: &FileMetadata, target: &Path) -> Result<(), ShadowError> {
+        let target = target.to_string_lossy().into_owned();
+        self.add_symlink(name, target, zip_options(metadata)).map_err(zip_error)
+    }
+}
+
+fn zip_options(metadata: &FileMetadata) -> zip::write::FileOptions {
+    zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(metadata.permissions.to_unix_mode())
+        .last_modified_time(zip_time(metadata.modified))
+}
+
+/// Reads exactly `remaining` bytes, failing if the content turns out shorter
+/// than announced, such as a source file truncated while it is archived.
+pub(super) struct ExactReader<'a> {
+    pub(super) inner: &'a mut dyn Read,
+    pub(super) remaining: u64,
+}
+
+impl Read for ExactReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let max = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "file shrank while it was being archived",
+            ));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Reads a file with written ranges through its delta.
+struct DeltaReader<'a> {
+    store: &'a OverrideStore,
+    path: &'a ShadowPath,
+    offset: u64,
+}
+
+impl Read for DeltaReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let chunk = self.store
+            .read_range(self.path, self.offset, buf.len().min(DELTA_CHUNK_SIZE))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+        buf[..chunk.len()].copy_from_slice(&chunk);
+        self.offset += chunk.len() as u64;
+        Ok(chunk.len())
+    }
+}
+
+impl OverrideStore {
+    /// Archives the merged view of the source tree at `source_root`.
+    ///
+    /// See [`OverrideStore::export_merged_with_progress`].
+    pub fn export_merged<W: Write + Seek>(
+        &self,
+        source_root: &Path,
+        writer: W,
+        format: MergedArchiveFormat,
+    ) -> Result<MergedExportSummary, ShadowError> {
+        self.export_merged_with_progress(source_root, writer, format, &NoProgress)
+    }
+    
+    /// Archives the merged view of the source tree at `source_root`,
+    /// reporting one item per file.
+    ///
+    /// The whole tree is listed before the first member is written, so the
+    /// progress totals are known. Tar archives only write forward; zip
+    /// archives seek back to finish each member's header.
+    ///
+    /// # Arguments
+    /// * `source_root` - Root of the source tree the store shadows
+    /// * `writer` - Destination of the archive, such as a buffered file
+    /// * `format` - Archive format to write
+    /// * `progress` - Receives one item per archived file
+    pub fn export_merged_with_progress<W: Write + Seek>(
+        &self,
+        source_root: &Path,
+        writer: W,
+        format: MergedArchiveFormat,
+        progress: &dyn Progress,
+    ) -> Result<MergedExportSummary, ShadowError> {
+        let summary = match format {
+            MergedArchiveFormat::Tar => {
+                let mut builder = tar::Builder::new(writer);
+                let summary = self.write_merged_view(&mut builder, source_root, progress)?;
+                builder.into_inner().and_then(|mut writer| writer.flush()).map_err(io_error)?;
+                summary
+            }
+            MergedArchiveFormat::TarGz => {
+                let encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                let mut builder = tar::Builder::new(encoder);
+                let summary = self.write_merged_view(&mut builder, source_root, progress)?;
+                builder.into_inner()
+                    .and_then(|encoder| encoder.finish())
+                    .and_then(|mut writer| writer.flush())
+                    .map_err(io_error)?;
+                summary
+            }
+            MergedArchiveFormat::Zip => {
+                let mut zip = zip::ZipWriter::new(writer);
+                let summary = self.write_merged_view(&mut zip, source_root, progress)?;
+                zip.finish().map_err(zip_error)?.flush().map_err(io_error)?;
+                summary
+            }
+        };
+        progress.finish();
+        Ok(summary)
+    }
+    
+    /// Lists the merged view and writes it to `sink`, setting the progress
+    /// totals once the whole tree is listed.
+    pub(super) fn write_merged_view(
+        &self,
+        sink: &mut dyn ArchiveSink,
+        source_root: &Path,
+        progress: &dyn Progress,
+    ) -> Result<MergedExportSummary, ShadowError> {
+        let nodes = self.walk_merged(source_root, progress)?;
+        let sizes = nodes.iter().filter_map(MergedNode::file_size);
+        let (files, bytes) = sizes.fold((0, 0), |(files, bytes), size| (files + 1, bytes + size));
+        progress.set_total(Some(files), Some(bytes));
+        self.write_merged(sink, source_root, &nodes, progress)
+    }
+    
+    /// Writes `nodes` to `sink` in order, streaming file content.
+    fn write_merged(
+        &self,
+        sink: &mut dyn ArchiveSink,
+        source_root: &Path,
+        nodes: &[MergedNode],
+        progress: &dyn Progress,
+    ) -> Result<MergedExportSummary, ShadowError> {
+        let mut summary = MergedExportSummary::default();
+        for node in nodes {
+            progress.check_cancelled("export")?;
+            let path = &node.path;
+            let name = member_name(path);
+            let archived = |e: ShadowError| match e {
+                ShadowError::IoError { source } => ShadowError::from_io_error_with_operation(source, path, "export"),
+                other => other,
+            };
+            
+            let len = match &node.origin {
+                Origin::Override(entry) if entry.is_directory() => {
+                    sink.directory(&name, &entry.override_metadata).map_err(archived)?;
+                    summary.directories += 1;
+                    summary.overridden += 1;
+                    continue;
+                }
+                Origin::Override(entry) => {
+                    let data = entry.get_file_data()?.unwrap_or_default();
+                    let len = data.len() as u64;
+                    sink.file(&name, &entry.override_metadata, len, &mut data.as_ref()).map_err(archived)?;
+                    summary.overridden += 1;
+                    len
+                }
+                Origin::Source(metadata) if metadata.is_dir() => {
+                    sink.directory(&name, &source_metadata(metadata, 0)).map_err(archived)?;
+                    summary.directories += 1;
+                    continue;
+                }
+                Origin::Source(metadata) if metadata.file_type().is_symlink() => {
+                    let target = std::fs::read_link(source_path(source_root, path))
+                        .map_err(|e| ShadowError::from_io_error_with_operation(e, path, "export"))?;
+                    sink.symlink(&name, &source_metadata(metadata, 0), &target).map_err(archived)?;
+                    summary.symlinks += 1;
+                    continue;
+                }
+                Origin::Source(metadata) => {
+                    progress.set_current_path(path);
+                    self.write_source_file(sink, source_root, path, &name, metadata).map_err(archived)?
+                }
+                Origin::Delta(metadata, len) => {
+                    progress.set_current_path(path);
+                    let mut reader = DeltaReader { store: self, path, offset: 0 };
+                    sink.file(&name, &source_metadata(metadata, *len), *len, &mut reader).map_err(archived)?;
+                    *len
+                }
+            };
+            summary.files += 1;
+            summary.bytes += len;
+            progress.advance(1, len);
+        }
+        Ok(summary)
+    }
+    
+    /// Streams the source file at `path` into `sink`, returning its size.
+    fn write_source_file(
+        &self,
+        sink: &mut dyn ArchiveSink,
+        source_root: &Path,
+        path: &ShadowPath,
+        name: &str,
+        metadata: &std::fs::Metadata,
+    ) -> Result<u64, ShadowError> {
+        #[cfg(feature = "patterns")]
+        if let Some(content) = self.transform_source(path, source_root)? {
+            let len = content.len() as u64;
+            sink.file(name, &source_metadata(metadata, len), len, &mut content.as_ref())?;
+            return Ok(len);
+        }
+        
+        let mut file = std::fs::File::open(source_path(source_root, path))?;
+        let len = file.metadata()?.len();
+        sink.file(name, &source_metadata(metadata, len), len, &mut file)?;
+        Ok(len)
+    }
+    
+    /// Lists the merged view below the root, listing the directories of
+    /// each level on up to [`MAX_WALK_THREADS`] threads, sorted by path so
+    /// that directories precede their contents.
+    fn walk_merged(&self, source_root: &Path, progress: &dyn Progress) -> Result<Vec<MergedNode>, ShadowError> {
+        let threads = std::thread::available_parallelism().map_or(1, NonZeroUsize::get).min(MAX_WALK_THREADS);
+        let mut nodes = Vec::new();
+        let mut level = vec![ShadowPath::from("/")];
+        while !level.is_empty() {
+            progress.check_cancelled("export")?;
+            let chunk_size = (level.len() + threads - 1) / threads;
+            let listed: Vec<Result<Vec<MergedNode>, ShadowError>> = std::thread::scope(|scope| {
+                let workers: Vec<_> = level
+                    .chunks(chunk_size)
+                    .map(|directories| {
+                        scope.spawn(move || {
+                            let mut found = Vec::new();
+                            for directory in directories {
+                                found.extend(self.merged_children(source_root, directory)?);
+                            }
+                            Ok(found)
+                        })
+                    })
+                    .collect();
+                workers.into_iter()
+                    .map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                    .collect()
+            });
+            
+            level = Vec::new();
+            for found in listed {
+                for node in found? {
+                    if node.is_directory() {
+                        level.push(node.path.clone());
+                    }
+                    nodes.push(node);
+                }
+            }
+        }
+        nodes.sort_by(|a, b| a.path.as_path().cmp(b.path.as_path()));
+        Ok(nodes)
+    }
+    
+    /// Lists the children of `directory` in the merged view, dropping
+    /// tombstones and source paths that are neither files, directories nor
+    /// symlinks.
+    fn merged_children(&self, source_root: &Path, directory: &ShadowPath) -> Result<Vec<MergedNode>, ShadowError> {
+        let mut names: BTreeSet<PathBuf> = self.get_directory_children(directory).into_iter().map(PathBuf::from).collect();
+        let host = source_path(source_root, directory);
+        match std::fs::read_dir(&host) {
+            Ok(entries) => {
+                for entry in entries {
+                    names.insert(PathBuf::from(entry?.file_name()));
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(ShadowError::from_io_error_with_operation(e, directory, "export")),
+        }
+        
+        let mut children = Vec::new();
+        for name in names {
+            let path = directory.join(&name);
+            if let Some(entry) = self.get(&path) {
+                if !entry.is_deleted() {
+                    children.push(MergedNode { path, origin: Origin::Override(entry) });
+                }
+                continue;
+            }
+            let metadata = match std::fs::symlink_metadata(source_path(source_root, &path)) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(ShadowError::from_io_error_with_operation(e, &path, "export")),
+            };
+            let file_type = metadata.file_type();
+            let origin = match self.delta_info(&path) {
+                Some(delta) => Origin::Delta(metadata, delta.len),
+                None if file_type.is_file() || file_type.is_dir() || file_type.is_symlink() => Origin::Source(metadata),
+                None => continue,
+            };
+            children.push(MergedNode { path, origin });
+        }
+        Ok(children)
+    }
+}
+
+/// Returns the archive member name of `path`, relative to the root.
+fn member_name(path: &ShadowPath) -> String {
+    path.as_path()
+        .components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    /// A source tree and a store overriding, adding and deleting paths in it.
+    fn merged_workspace() -> (TempDir, OverrideStore) {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir_all(source.path().join("src/old")).unwrap();
+        std::fs::write(source.path().join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(source.path().join("src/old/legacy.rs"), "legacy").unwrap();
+        std::fs::write(source.path().join("Cargo.toml"), "[package]").unwrap();
+        std::fs::write(source.path().join("data.bin"), vec![b'a'; 100]).unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/Cargo.toml"), Bytes::from("[workspace]"), None).unwrap();
+        store.create_directory_hierarchy(&path("/docs")).unwrap();
+        store.insert_file(path("/docs/notes.md"), Bytes::from("notes"), None).unwrap();
+        store.mark_deleted(path("/src/old")).unwrap();
+        store.write_range(path("/data.bin"), source.path().join("data.bin"), 10, b"ZZ", None).unwrap();
+        (source, store)
+    }
+    
+    #[test]
+    fn test_export_merged_tar_gz() {
+        let (source, store) = merged_workspace();
+        let mut archive = Cursor::new(Vec::new());
+        let summary = store.export_merged(source.path(), &mut archive, MergedArchiveFormat::TarGz).unwrap();
+        assert_eq!((summary.files, summary.directories, summary.overridden), (4, 2, 3));
+        
+        let decoder = flate2::read::GzDecoder::new(archive.get_ref().as_slice());
+        let mut members = BTreeMap::new();
+        for entry in tar::Archive::new(decoder).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            members.insert(name, content);
+        }
+        assert_eq!(members.keys().collect::<Vec<_>>(), [
+            "Cargo.toml",
+            "data.bin",
+            "docs/",
+            "docs/notes.md",
+            "src/",
+            "src/main.rs",
+        ]);
+        assert_eq!(members["Cargo.toml"], "[workspace]");
+        assert_eq!(&members["data.bin"][8..14], "aaZZaa");
+        assert_eq!(members["src/main.rs"], "fn main() {}");
+    }
+    
+    #[test]
+    fn test_export_merged_zip() {
+        let (source, store) = merged_workspace();
+        let mut archive = Cursor::new(Vec::new());
+        let summary = store.export_merged(source.path(), &mut archive, MergedArchiveFormat::Zip).unwrap();
+        assert_eq!(summary.bytes, 11 + 100 + 5 + 12);
+        
+        let mut zip = zip::ZipArchive::new(archive).unwrap();
+        let mut notes = String::new();
+        zip.by_name("docs/notes.md").unwrap().read_to_string(&mut notes).unwrap();
+        assert_eq!(notes, "notes");
+        assert!(zip.by_name("src/old/legacy.rs").is_err());
+    }
+}
@@ -22,11 +22,34 @@
 //! # Key Features
 //! 
 //! - **Memory Management**: Automatic eviction with configurable policies
+//! - **Eviction Dry Runs**: What every eviction policy would evict to free some memory, without evicting it
+//! - **Background Eviction**: Proactive eviction following store and system memory pressure
 //! - **Performance**: BLAKE3 content deduplication and LRU caching
 //! - **Compression**: Transparent zstd compression for large files
 //! - **Patterns**: Advanced pattern matching with transformations
+//! - **Read Transforms**: Source files rewritten as they are read, such as secrets redacted by a sed expression
 //! - **Persistence**: Snapshot and WAL support for durability
+//! - **Spill to Disk**: Optional encrypted overflow tier for cold entries under memory pressure
+//! - **Named Snapshots**: Cheap, structurally shared checkpoints to branch and jump between
+//! - **Commit**: Resumable, journaled write-back of overrides to the source tree
+//! - **Source Write Checks**: Commits to a source that turned read-only fail with the cause and its fix
+//! - **Hard Links**: Several paths sharing one file override and its content
+//! - **Rename**: Moving files and directory trees, with overwrite, no-replace and exchange semantics
+//! - **Transactions**: Groups of changes applied all together or not at all
+//! - **Batch Inserts**: Many overrides compressed in parallel and stored with one memory reservation
+//! - **Merge**: Layering the overrides of one store on top of another's
+//! - **Archives**: Export to and import from tar and zip archives with a metadata manifest
+//! - **Merged Archives**: Tar, gzipped tar and zip archives of the source tree with the overrides applied
+//! - **Checksums**: BLAKE3 manifests of the merged view, optionally signed, for verifying exported artifacts
+//! - **Manifests**: JSON listings of every override for diffing sessions, reapplied eagerly or lazily
+//! - **Hydration**: Copying source subtrees into the store ahead of a workload, with size estimates
+//! - **Case-Insensitive Lookups**: Overrides found through any casing of their path, with the stored casing preserved
+//! - **Named Streams**: NTFS alternate data streams kept as overrides of their own and listed with their file
+//! - **Delta Overrides**: Only the written ranges of large source files are stored
+//! - **Append Logs**: Appends to file overrides cost the bytes appended, not the file size
 //! - **Statistics**: Comprehensive monitoring and health checks
+//! - **Source Conflicts**: Overrides whose source changed underneath them, kept, dropped or merged as text
+//! - **Path Inspection**: One report of everything known about a path, for debugging
 //! 
 //! # Thread Safety
 //! 
@@ -39,21 +62,73 @@ mod memory;
 mod lru;
 mod size;
 mod directory;
+#[cfg(feature = "persistence")]
 mod persistence;
+#[cfg(feature = "persistence")]
+mod commit;
+#[cfg(feature = "persistence")]
+mod diff;
+#[cfg(feature = "persistence")]
+mod seed;
+#[cfg(feature = "persistence")]
+mod hydrate;
+mod snapshots;
+mod links;
+mod rename;
+mod case_fold;
+mod streams;
+mod source_access;
+mod inspect;
+mod conflicts;
+mod simulate;
+mod gc;
+mod transaction;
+mod batch;
+mod merge;
+mod delta;
+mod append;
+mod evictor;
+mod spill;
 mod optimization;
 mod stats;
+mod rules;
+#[cfg(feature = "patterns")]
 mod patterns;
+#[cfg(feature = "patterns")]
+mod read_transform;
+#[cfg(feature = "persistence")]
+mod archive;
+#[cfg(feature = "persistence")]
+mod manifest;
+#[cfg(feature = "persistence")]
+mod merged_export;
+#[cfg(feature = "persistence")]
+mod checksums;
 mod api;
 
 // Public API exports
 pub use api::{
-    OverrideStoreBuilder, HealthStatus, ExportFormat, Migration
+    OverrideStoreBuilder, HealthStatus, Migration
 };
+#[cfg(feature = "persistence")]
+pub use api::ExportFormat;
+#[cfg(feature = "persistence")]
+pub use archive::ARCHIVE_FORMAT_VERSION;
+#[cfg(feature = "persistence")]
+pub use manifest::{
+    ManifestContent, ManifestEntry, ManifestEntryKind, ManifestSummary, OverrideManifest, MANIFEST_FORMAT_VERSION
+};
+
+pub use source_access::{probe_source_writable, SourceWriteDenial};
+pub use inspect::{ContentInfo, LoggedOperation, PathReport, PathState, RECENT_OPERATION_LIMIT};
+pub use simulate::{EvictionSimulation, PolicySimulation, SimulatedVictim};
+pub use gc::GarbageCollection;
+pub use conflicts::{merge_text, Resolution, TextMerge, OVERRIDE_MARKER, SEPARATOR_MARKER, SOURCE_MARKER};
 
 // Core types (public)
 // OverrideStore and OverrideStoreConfig are defined below
 pub use entry::{OverrideEntry, OverrideContent};
-pub use lru::EvictionPolicy;
+pub use lru::{AccessStats, EvictionPolicy, LruTracker};
 pub use optimization::PrefetchStrategy;
 pub use stats::{
     OverrideStoreStats, StatsSnapshot, MemoryBreakdown, StatsReport,
@@ -61,32 +136,74 @@ pub use stats::{
 };
 
 // Pattern matching (public)
+pub use rules::OverrideRule;
+#[cfg(feature = "patterns")]
 pub use patterns::{
-    OverrideRule, RuleSet, RulePriority, TransformChain, TransformFn, transforms,
+    RuleSet, RuleId, RuleInfo, RulePriority, TransformChain, TransformError, TransformFn, transforms,
     OverrideCondition, OverrideTemplate, CowContent, ContentLoader, OverrideRuleEntry,
     OverrideContentType
 };
+#[cfg(feature = "patterns")]
+pub use read_transform::{
+    ChainTransformer, ContentTransformer, ReadTransforms, TransformCacheStats, TransformerId, TransformerInfo,
+    DEFAULT_TRANSFORM_CACHE_ENTRIES
+};
 
 // Advanced features (public but less common)
-pub use persistence::{OverrideSnapshot, PersistenceConfig};
+#[cfg(feature = "persistence")]
+pub use persistence::{
+    OverrideSnapshot, PersistenceConfig, PersistenceOp, WriteAheadLog, SNAPSHOT_FORMAT_VERSION
+};
+#[cfg(feature = "persistence")]
+pub use commit::{
+    rollback_commit, CommitBackup, CommitJournal, CommitOptions, CommitSummary, RollbackSummary,
+    COMMIT_JOURNAL_VERSION
+};
+#[cfg(feature = "persistence")]
+pub use diff::{DiffEntry, DiffKind};
+#[cfg(feature = "persistence")]
+pub use seed::SeedSummary;
+#[cfg(feature = "persistence")]
+pub use hydrate::{HydrationEstimate, HydrationSummary};
+#[cfg(feature = "persistence")]
+pub use merged_export::{MergedArchiveFormat, MergedExportSummary};
+#[cfg(feature = "persistence")]
+pub use checksums::{ChecksumEntry, ChecksumManifest, ChecksumMismatch, ManifestKey, CHECKSUM_MANIFEST_VERSION};
+pub use snapshots::{SnapshotInfo, SnapshotRestoreSummary};
+pub use links::LinkId;
+pub use rename::{RenameCollision, RenameOptions};
+pub(crate) use rename::{source_metadata, source_path};
+pub use transaction::Transaction;
+pub use merge::{MergeConflict, MergeSide, MergeStrategy, MergeSummary};
+pub use delta::{DeltaInfo, DEFAULT_DELTA_COMPACTION_RATIO};
+pub use append::{AppendInfo, APPEND_CHUNK_SIZE, APPEND_COMPACTION_MIN};
+pub use evictor::{BackgroundEvictor, EvictionRound, EvictorConfig, EvictorStats, SystemMemoryPressure};
+pub use spill::{SpillStats, DEFAULT_SPILL_THRESHOLD};
 pub use optimization::{ContentDeduplication, compression};
 
 // Internal utilities (kept private)
 use memory::MemoryTracker;
-use lru::LruTracker;
 use size::calculate_entry_size;
 use directory::{DirectoryCache, PathTraversal};
 use optimization::{ReadThroughCache, DirectoryPrefetcher, ShardedMap};
 
-use crate::types::{FileMetadata, ShadowPath, DirectoryEntry};
+use crate::types::{current_time, FileMetadata, ShadowPath, DirectoryEntry};
 use crate::error::ShadowError;
+use crate::access::AccessOperation;
+use crate::quota::QuotaUsage;
+#[cfg(feature = "persistence")]
+use crate::supervision::Subsystem;
 use bytes::Bytes;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
-use std::time::SystemTime;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::instrument;
 
 /// Configuration for the override store.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OverrideStoreConfig {
     /// Maximum memory usage in bytes
     pub max_memory: usize,
@@ -108,6 +225,22 @@ pub struct OverrideStoreConfig {
     
     /// Whether to enable compression for large files
     pub enable_compression: bool,
+    
+    /// Directory for the disk-backed overflow tier; cold entries are dropped when unset
+    ///
+    /// Spill settings are local to the running process and are not persisted
+    /// with snapshots.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub spill_dir: Option<PathBuf>,
+    
+    /// Memory pressure ratio (0.0 to 1.0) above which cold entries are spilled to disk
+    #[cfg_attr(feature = "serde", serde(skip, default = "default_spill_threshold"))]
+    pub spill_threshold: f64,
+}
+
+#[cfg(feature = "serde")]
+fn default_spill_threshold() -> f64 {
+    DEFAULT_SPILL_THRESHOLD
 }
 
 impl Default for OverrideStoreConfig {
@@ -120,6 +253,8 @@ impl Default for OverrideStoreConfig {
             cache_size: 1000,
             prefetch_strategy: PrefetchStrategy::Children,
             enable_compression: true,
+            spill_dir: None,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
         }
     }
 }
@@ -152,6 +287,81 @@ pub struct OverrideStore {
     
     /// Runtime configuration that can be updated
     config: RwLock<OverrideStoreConfig>,
+    
+    /// Write-ahead log receiving every mutation, when WAL mode is enabled
+    #[cfg(feature = "persistence")]
+    pub(crate) wal: RwLock<Option<Arc<WriteAheadLog>>>,
+    
+    /// Named snapshots sharing entries with the store
+    pub(crate) snapshots: RwLock<BTreeMap<String, snapshots::NamedSnapshot>>,
+    
+    /// Watch service receiving an event for every stored override, when attached
+    pub(crate) watch: RwLock<Option<Arc<crate::watch::WatchService>>>,
+    
+    /// Disk-backed overflow tier, opened on first spill
+    pub(crate) spill: RwLock<Option<Arc<spill::SpillStore>>>,
+    
+    /// Whether writes through the store are rejected
+    read_only: AtomicBool,
+    
+    /// Access rules enforced on writes and deletes, when attached
+    pub(crate) access: RwLock<Option<Arc<crate::access::AccessPolicy>>>,
+    
+    /// Paths whose operations providers trace, when attached
+    pub(crate) trace: RwLock<Option<Arc<crate::trace::PathTraceFilter>>>,
+    
+    /// Open source files serving reads that fall through to the source, when attached
+    pub(crate) source_cache: RwLock<Option<Arc<crate::source_cache::SourceFileCache>>>,
+    
+    /// Merged directory listings of the mounts, when attached
+    pub(crate) listing_cache: RwLock<Option<Arc<crate::listing_cache::ListingCache>>>,
+    
+    /// Rules overriding source files on first access, when attached
+    #[cfg(feature = "patterns")]
+    pub(crate) rules: RwLock<Option<Arc<RuleSet>>>,
+    
+    /// Transformers rewriting source files as they are read, when attached
+    #[cfg(feature = "patterns")]
+    pub(crate) read_transforms: RwLock<Option<Arc<ReadTransforms>>>,
+    
+    /// Hard-link groups of file overrides
+    pub(crate) links: links::LinkTable,
+    
+    /// Case mode and the folded-key index of case-insensitive stores
+    pub(crate) case_index: case_fold::CaseIndex,
+    
+    /// Named streams held for each file, when streams are on
+    pub(crate) stream_index: streams::StreamIndex,
+    
+    /// Written ranges of source files that have no full override
+    pub(crate) deltas: delta::DeltaTable,
+    
+    /// Supervisor deciding how persistence failures are handled, when attached
+    pub(crate) supervisor: RwLock<Option<Arc<crate::supervision::Supervisor>>>,
+    
+    /// Why the source refused writes when last probed
+    pub(crate) source_write_denial: RwLock<Option<SourceWriteDenial>>,
+    
+    /// Overrides whose source path changed underneath them, keyed by path
+    pub(crate) source_conflicts: Mutex<BTreeMap<String, crate::source_watch::SourceConflict>>,
+    
+    /// Limits writes are checked against
+    pub(crate) quota: RwLock<crate::quota::Quota>,
+    
+    /// What the held overrides count against the quota
+    pub(crate) quota_usage: crate::quota::UsageCounter,
+    
+    /// Limits new overrides are checked against
+    pub(crate) path_limits: RwLock<crate::path_limits::PathLimits>,
+    
+    /// Which accesses store a new access time, as an [`crate::atime::AtimePolicy`]
+    pub(crate) atime_policy: AtomicU8,
+    
+    /// Quotas and usage of the namespaces opened on the store
+    pub(crate) namespaces: crate::namespace::NamespaceTable,
+    
+    /// Sampling of the bookkeeping of hot small lookups
+    pub(crate) fast_lane: crate::fast_lane::FastLaneState,
 }
 
 impl OverrideStore {
@@ -179,6 +389,33 @@ impl OverrideStore {
             prefetcher,
             stats,
             config: RwLock::new(config),
+            #[cfg(feature = "persistence")]
+            wal: RwLock::new(None),
+            snapshots: RwLock::new(BTreeMap::new()),
+            watch: RwLock::new(None),
+            spill: RwLock::new(None),
+            read_only: AtomicBool::new(false),
+            access: RwLock::new(None),
+            trace: RwLock::new(None),
+            source_cache: RwLock::new(None),
+            listing_cache: RwLock::new(None),
+            #[cfg(feature = "patterns")]
+            rules: RwLock::new(None),
+            #[cfg(feature = "patterns")]
+            read_transforms: RwLock::new(None),
+            links: links::LinkTable::default(),
+            case_index: case_fold::CaseIndex::default(),
+            stream_index: streams::StreamIndex::default(),
+            deltas: delta::DeltaTable::default(),
+            supervisor: RwLock::new(None),
+            source_write_denial: RwLock::new(None),
+            source_conflicts: Mutex::new(BTreeMap::new()),
+            quota: RwLock::new(crate::quota::Quota::default()),
+            quota_usage: crate::quota::UsageCounter::default(),
+            path_limits: RwLock::new(crate::path_limits::PathLimits::default()),
+            atime_policy: AtomicU8::new(crate::atime::AtimePolicy::default() as u8),
+            namespaces: crate::namespace::NamespaceTable::default(),
+            fast_lane: crate::fast_lane::FastLaneState::default(),
         }
     }
     
@@ -187,6 +424,29 @@ impl OverrideStore {
         Self::new(OverrideStoreConfig::default())
     }
     
+    /// Makes the store read-only, or writable again.
+    ///
+    /// While read-only, every write path (`insert_file`, `insert_directory`,
+    /// `mark_deleted`, batch inserts, recursive deletes and snapshot restores)
+    /// fails with [`ShadowError::ReadOnlyFilesystem`], whatever the platform
+    /// layer does. Loading persisted state and evicting entries are unaffected.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Release);
+    }
+    
+    /// Returns true if writes through the store are rejected.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Acquire)
+    }
+    
+    /// Fails with [`ShadowError::ReadOnlyFilesystem`] if the store is read-only.
+    pub(crate) fn check_writable(&self, path: &ShadowPath, operation: &str) -> Result<(), ShadowError> {
+        if self.is_read_only() {
+            return Err(crate::error::read_only_filesystem(path.clone(), operation));
+        }
+        Ok(())
+    }
+    
     /// Inserts a file override.
     ///
     /// # Arguments
@@ -202,11 +462,47 @@ impl OverrideStore {
         content: Bytes,
         original_metadata: Option<FileMetadata>,
     ) -> Result<(), ShadowError> {
+        self.check_writable(&path, "write")?;
+        self.check_access(&path, AccessOperation::Write)?;
+        
+        let (override_content, override_metadata) = self.file_override(content, original_metadata.as_ref());
+        self.insert_entry(path, override_content, original_metadata, override_metadata)
+    }
+    
+    /// Builds the content and metadata of a file override.
+    pub(crate) fn file_override(
+        &self,
+        content: Bytes,
+        original_metadata: Option<&FileMetadata>,
+    ) -> (OverrideContent, FileMetadata) {
+        let original_size = content.len() as u64;
+        let override_content = self.file_content(content);
+        (override_content, Self::file_metadata(original_size, original_metadata))
+    }
+    
+    /// Builds the metadata of a file override of `size` uncompressed bytes.
+    pub(crate) fn file_metadata(size: u64, original_metadata: Option<&FileMetadata>) -> FileMetadata {
+        FileMetadata {
+            size, // Store original uncompressed size
+            created: current_time(),
+            modified: current_time(),
+            accessed: current_time(),
+            permissions: original_metadata
+                .map(|m| m.permissions.clone())
+                .unwrap_or_else(|| crate::types::FilePermissions::default_file()),
+            file_type: crate::types::FileType::File,
+            platform_specific: original_metadata
+                .map(|m| m.platform_specific.clone())
+                .unwrap_or_else(|| crate::types::PlatformMetadata::default()),
+        }
+    }
+    
+    /// Compresses and deduplicates file content the way it is stored.
+    pub(crate) fn file_content(&self, content: Bytes) -> OverrideContent {
         let config = self.config.read().unwrap();
         let enable_compression = config.enable_compression;
         drop(config);
         
-        let original_size = content.len() as u64;
         let mut data = content;
         let mut is_compressed = false;
         
@@ -224,29 +520,13 @@ impl OverrideStore {
         }
         
         // Use BLAKE3 for content deduplication
-        let (content_hash, dedup_data) = self.content_dedup.store_content(data.clone());
+        let (content_hash, data) = self.content_dedup.store_content(data);
         
-        let override_content = OverrideContent::File {
-            data: (*dedup_data).clone(),
+        OverrideContent::File {
+            data,
             content_hash,
             is_compressed,
-        };
-        
-        let override_metadata = FileMetadata {
-            size: original_size, // Store original uncompressed size
-            created: SystemTime::now(),
-            modified: SystemTime::now(),
-            accessed: SystemTime::now(),
-            permissions: original_metadata.as_ref()
-                .map(|m| m.permissions.clone())
-                .unwrap_or_else(|| crate::types::FilePermissions::default_file()),
-            file_type: crate::types::FileType::File,
-            platform_specific: original_metadata.as_ref()
-                .map(|m| m.platform_specific.clone())
-                .unwrap_or_else(|| crate::types::PlatformMetadata::default()),
-        };
-        
-        self.insert_entry(path, override_content, original_metadata, override_metadata)
+        }
     }
     
     /// Inserts a directory override.
@@ -262,25 +542,31 @@ impl OverrideStore {
         path: ShadowPath,
         original_metadata: Option<FileMetadata>,
     ) -> Result<(), ShadowError> {
+        let (override_content, override_metadata) = Self::directory_override(original_metadata.as_ref());
+        self.insert_entry(path, override_content, original_metadata, override_metadata)
+    }
+    
+    /// Builds the content and metadata of a directory override.
+    pub(crate) fn directory_override(original_metadata: Option<&FileMetadata>) -> (OverrideContent, FileMetadata) {
         let override_content = OverrideContent::Directory {
             entries: Vec::new(),
         };
         
         let override_metadata = FileMetadata {
             size: 0,
-            created: SystemTime::now(),
-            modified: SystemTime::now(),
-            accessed: SystemTime::now(),
-            permissions: original_metadata.as_ref()
+            created: current_time(),
+            modified: current_time(),
+            accessed: current_time(),
+            permissions: original_metadata
                 .map(|m| m.permissions.clone())
                 .unwrap_or_else(|| crate::types::FilePermissions::default_directory()),
             file_type: crate::types::FileType::Directory,
-            platform_specific: original_metadata.as_ref()
+            platform_specific: original_metadata
                 .map(|m| m.platform_specific.clone())
                 .unwrap_or_else(|| crate::types::PlatformMetadata::default()),
         };
         
-        self.insert_entry(path, override_content, original_metadata, override_metadata)
+        (override_content, override_metadata)
     }
     
     /// Marks a file or directory as deleted.
@@ -291,22 +577,29 @@ impl OverrideStore {
     /// # Returns
     /// Ok(()) on success, or an error if memory limits would be exceeded
     pub fn mark_deleted(&self, path: ShadowPath) -> Result<(), ShadowError> {
-        let override_content = OverrideContent::Deleted;
-        
+        let (override_content, override_metadata) = Self::tombstone_override();
+        self.insert_entry(path.clone(), override_content, None, override_metadata)?;
+        self.drop_streams(&path);
+        Ok(())
+    }
+    
+    /// Builds the content and metadata of a tombstone.
+    pub(crate) fn tombstone_override() -> (OverrideContent, FileMetadata) {
         let override_metadata = FileMetadata {
             size: 0,
-            created: SystemTime::now(),
-            modified: SystemTime::now(),
-            accessed: SystemTime::now(),
+            created: current_time(),
+            modified: current_time(),
+            accessed: current_time(),
             permissions: crate::types::FilePermissions::default_file(),
             file_type: crate::types::FileType::File,
             platform_specific: crate::types::PlatformMetadata::default(),
         };
         
-        self.insert_entry(path, override_content, None, override_metadata)
+        (OverrideContent::Deleted, override_metadata)
     }
     
     /// Internal method to insert an entry with memory management.
+    #[instrument(name = "insert", level = "trace", skip_all, fields(path = %path))]
     pub(crate) fn insert_entry(
         &self,
         path: ShadowPath,
@@ -314,38 +607,162 @@ impl OverrideStore {
         original_metadata: Option<FileMetadata>,
         override_metadata: FileMetadata,
     ) -> Result<(), ShadowError> {
-        let entry = OverrideEntry {
-            path: path.clone(),
-            content,
-            original_metadata,
-            override_metadata,
-            created_at: SystemTime::now(),
-            last_accessed: AtomicU64::new(
-                SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs()
-            ),
+        let path = self.stored_spelling(path);
+        self.check_insert(&path, &content)?;
+        // A write reaches every hard link of the path, and each counts
+        let usage = QuotaUsage::of_content(&content, override_metadata.size);
+        let written = match content {
+            OverrideContent::File { .. } => self.links.members(&path),
+            _ => vec![path.clone()],
         };
+        self.check_quota(written.iter().map(|path| (path, usage)))?;
+        self.apply_entry(OverrideEntry::new(path, content, original_metadata, override_metadata), None)
+    }
+    
+    /// Fails if `content` may not be stored at `path`: the store is
+    /// read-only, an access rule refuses the write or delete, or a new
+    /// override would go past the path limits.
+    pub(crate) fn check_insert(&self, path: &ShadowPath, content: &OverrideContent) -> Result<(), ShadowError> {
+        let (operation, access) = match content {
+            OverrideContent::File { .. } => ("write", AccessOperation::Write),
+            OverrideContent::Directory { .. } => ("create directory", AccessOperation::Write),
+            OverrideContent::Deleted => ("delete", AccessOperation::Delete),
+        };
+        self.check_writable(path, operation)?;
+        self.check_access(path, access)?;
+        self.check_path_limits(path, content)
+    }
+    
+    /// Stores a checked entry and updates the hard links of its path.
+    ///
+    /// `reserved` is memory already set aside for the entries, as
+    /// [`OverrideStore::store_reserved_entry`] takes it.
+    pub(crate) fn apply_entry(&self, entry: OverrideEntry, mut reserved: Option<&mut usize>) -> Result<(), ShadowError> {
+        for entry in self.linked_entries(entry) {
+            self.store_reserved_entry(Arc::new(entry), true, reserved.as_deref_mut())?;
+        }
+        Ok(())
+    }
+    
+    /// Returns the entries storing `entry` writes: one for each other hard
+    /// link of its path, then `entry` itself. Storing anything but a file
+    /// unlinks the path, so it is stored alone.
+    pub(crate) fn linked_entries(&self, entry: OverrideEntry) -> Vec<OverrideEntry> {
+        let path = entry.path.clone();
         
-        let entry_size = calculate_entry_size(&entry);
-        
-        // Check if we need to evict before inserting
-        let config = self.config.read().unwrap();
-        let eviction_threshold = config.eviction_threshold;
-        let eviction_policy = config.eviction_policy;
-        drop(config);
+        // A write reaches every hard link of the path; anything else unlinks it
+        let links: Vec<ShadowPath> = match entry.content {
+            OverrideContent::File { .. } => self.links.members(&path)
+                .into_iter()
+                .filter(|link| *link != path)
+                .collect(),
+            _ => {
+                self.links.unlink(&path);
+                Vec::new()
+            }
+        };
+        let mut entries: Vec<OverrideEntry> = links.into_iter()
+            .map(|link| {
+                let link_original = self.entries.get(&link).and_then(|entry| entry.original_metadata.clone());
+                OverrideEntry::new(link, entry.content.clone(), link_original, entry.override_metadata.clone())
+            })
+            .collect();
+        entries.push(entry);
+        entries
+    }
+    
+    /// Inserts a previously persisted entry, keeping its timestamps.
+    ///
+    /// Restored entries are not written to the WAL since they already are durable.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn restore_entry(&self, entry: OverrideEntry) -> Result<(), ShadowError> {
+        self.store_entry(entry, false)
+    }
+    
+    /// Stores a fully built entry, handling eviction, stats and directory tracking.
+    fn store_entry(&self, entry: OverrideEntry, log_to_wal: bool) -> Result<(), ShadowError> {
+        self.store_shared_entry(Arc::new(entry), log_to_wal)
+    }
+    
+    /// Stores an entry that may also be referenced elsewhere, such as by a named snapshot.
+    pub(crate) fn store_shared_entry(&self, entry_arc: Arc<OverrideEntry>, log_to_wal: bool) -> Result<(), ShadowError> {
+        self.store_reserved_entry(entry_arc, log_to_wal, None)
+    }
+    
+    /// Stores an entry, drawing the memory of a new path from `reserved`
+    /// while it lasts. A reservation was made with pressure already
+    /// relieved, so no eviction runs before the insert.
+    #[cfg_attr(not(feature = "persistence"), allow(unused_variables))]
+    pub(crate) fn store_reserved_entry(
+        &self,
+        entry_arc: Arc<OverrideEntry>,
+        log_to_wal: bool,
+        reserved: Option<&mut usize>,
+    ) -> Result<(), ShadowError> {
+        let path = entry_arc.path.clone();
+        if reserved.is_none() {
+            self.relieve_pressure(calculate_entry_size(&entry_arc))?;
+        }
         
-        if self.memory_tracker.get_pressure_ratio() > eviction_threshold {
-            // Calculate how much memory we need to free
-            let target_bytes = (entry_size * 2).max(self.memory_tracker.current_usage() / 4);
-            self.evict_entries(eviction_policy, target_bytes)?;
+        // Hold the WAL guard until the entry is visible so a checkpoint cannot
+        // snapshot the store between the log append and the insert
+        #[cfg(feature = "persistence")]
+        let wal = self.wal.read().unwrap();
+        #[cfg(feature = "persistence")]
+        if log_to_wal {
+            self.log_entry(wal.as_deref(), &entry_arc)?;
         }
         
-        let entry_arc = Arc::new(entry);
+        // A spilled previous version is replaced like a resident one, and
+        // an entry supersedes the written ranges of a delta
+        let spilled = self.take_spilled(&path);
+        self.drop_delta(&path);
+        
+        let resident = self.entries.insert(path, entry_arc.clone());
+        self.finish_store(entry_arc, resident, spilled, reserved)
+    }
+    
+    /// Logs the write of `entry` to `wal`, keeping it without durability
+    /// instead if the store degrades on persistence failures.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn log_entry(&self, wal: Option<&WriteAheadLog>, entry: &OverrideEntry) -> Result<(), ShadowError> {
+        let Some(wal) = wal else {
+            return Ok(());
+        };
+        let appended = wal.append(&PersistenceOp::insert(
+            entry.path.clone(),
+            entry.content.clone(),
+            entry.override_metadata.clone(),
+        ));
+        if let Err(e) = appended {
+            // A degraded store keeps the write in memory without durability
+            let message = || format!("Failed to log write of {}: {}", entry.path, e);
+            if !self.degrade_on_failure(Subsystem::Persistence, message) {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Accounts an entry just made visible in place of `resident`, or of
+    /// the `spilled` version of its path: memory, quotas, stats, caches,
+    /// change events and directory tracking.
+    pub(crate) fn finish_store(
+        &self,
+        entry_arc: Arc<OverrideEntry>,
+        resident: Option<Arc<OverrideEntry>>,
+        spilled: Option<Arc<OverrideEntry>>,
+        reserved: Option<&mut usize>,
+    ) -> Result<(), ShadowError> {
+        let path = entry_arc.path.clone();
+        let entry_size = calculate_entry_size(&entry_arc);
         
-        // If replacing an existing entry, we don't need additional memory allocation
-        let old_entry = self.entries.insert(path.clone(), entry_arc.clone());
+        // If replacing a resident entry, we don't need additional memory allocation
+        self.invalidate_listing(&path, false);
+        let needs_allocation = resident.is_none();
+        let old_entry = resident.or(spilled);
+        self.quota_usage.replace(old_entry.as_deref(), Some(&entry_arc));
+        self.namespaces.replace(&path, old_entry.as_deref(), Some(&entry_arc));
         
         // Calculate stats for the new entry
         let compression_saved = match &entry_arc.content {
@@ -358,11 +775,15 @@ impl OverrideStore {
         
         let dedup_saved = 0; // Would need actual dedup tracking
         
-        // If this is a new entry (not a replacement), allocate memory and update stats
+        if needs_allocation {
+            match reserved {
+                Some(budget) if *budget >= entry_size => *budget -= entry_size,
+                _ => self.reserve_memory(entry_size, Some(&path))?,
+            }
+        }
+        
+        // If this is a new entry (not a replacement), update stats
         if old_entry.is_none() {
-            let _guard = self.memory_tracker.try_allocate(entry_size)?;
-            std::mem::forget(_guard); // Keep the allocation
-            
             // Update stats for new entry
             self.stats.update_on_insert(&entry_arc, entry_size, compression_saved, dedup_saved);
         } else {
@@ -381,12 +802,22 @@ impl OverrideStore {
             }
         }
         
+        // Drop the replaced entry from the hot cache so reads see the new content
+        if old_entry.is_some() {
+            self.hot_cache.remove(&path);
+        }
+        
+        self.publish_entry_change(&entry_arc, old_entry.is_some());
+        
         // Update LRU tracker
         self.lru_tracker.record_access(&path);
         
-        // Update directory cache if this is a new entry
+        // Update directory cache and case index if this is a new entry
         if old_entry.is_none() {
-            if let Some(parent) = path.parent() {
+            self.case_index.record(&path);
+            // Named streams belong to their file, not to its directory
+            let stream = self.stream_index.record(&path);
+            if let Some(parent) = path.parent().filter(|_| !stream) {
                 let filename = PathTraversal::get_filename(&path);
                 if !filename.is_empty() {
                     self.directory_cache.add_child(&parent, &filename);
@@ -397,6 +828,27 @@ impl OverrideStore {
         Ok(())
     }
     
+    /// Evicts entries ahead of an insert of `incoming` bytes when the store
+    /// is above its eviction threshold.
+    pub(crate) fn relieve_pressure(&self, incoming: usize) -> Result<(), ShadowError> {
+        let config = self.config.read().unwrap();
+        // With a spill tier, cold entries move to disk earlier instead of being dropped
+        let eviction_threshold = if config.spill_dir.is_some() {
+            config.spill_threshold
+        } else {
+            config.eviction_threshold
+        };
+        let eviction_policy = config.eviction_policy;
+        drop(config);
+        
+        if self.memory_tracker.get_pressure_ratio() > eviction_threshold {
+            // Calculate how much memory we need to free
+            let target_bytes = (incoming * 2).max(self.memory_tracker.current_usage() / 4);
+            self.evict_entries(eviction_policy, target_bytes)?;
+        }
+        Ok(())
+    }
+    
     /// Gets an override entry if it exists.
     ///
     /// # Arguments
@@ -404,32 +856,79 @@ impl OverrideStore {
     ///
     /// # Returns
     /// Arc to the override entry if found
+    #[instrument(name = "get", level = "trace", skip_all, fields(path = %path))]
     pub fn get(&self, path: &ShadowPath) -> Option<Arc<OverrideEntry>> {
+        let held = self.held_spelling(path);
+        let path = held.as_ref().unwrap_or(path);
+        
         // Check hot cache first
         if let Some(entry) = self.hot_cache.get(path) {
-            // Cache hit!
-            self.stats.update_cache_access(true);
+            self.record_hot_hit(path, &entry);
+            return Some(entry);
+        }
+        
+        // Check main store, then the spill tier
+        let resident = self.entries.get(path);
+        self.record_store_lookup(path, resident)
+    }
+    
+    /// Looks up many paths at once, as compilers and linters stat them in
+    /// bursts.
+    ///
+    /// Counts like calling [`OverrideStore::get`] for each path, but takes
+    /// the hot cache lock once and each shard of the main store at most once.
+    ///
+    /// # Returns
+    /// The entry of each path, in the order of `paths`
+    #[instrument(name = "get_many", level = "trace", skip_all, fields(paths = paths.len()))]
+    pub fn get_many(&self, paths: &[ShadowPath]) -> Vec<Option<Arc<OverrideEntry>>> {
+        let held: Vec<ShadowPath> = paths
+            .iter()
+            .map(|path| self.held_spelling(path).unwrap_or_else(|| path.clone()))
+            .collect();
+        
+        let mut found = self.hot_cache.get_many(&held);
+        let mut missed = Vec::new();
+        for (index, (path, entry)) in held.iter().zip(&found).enumerate() {
+            match entry {
+                Some(entry) => self.record_hot_hit(path, entry),
+                None => missed.push(index),
+            }
+        }
+        
+        let resident = self.entries.get_many(missed.iter().map(|&index| &held[index]));
+        for (index, resident) in missed.into_iter().zip(resident) {
+            found[index] = self.record_store_lookup(&held[index], resident);
+        }
+        found
+    }
+    
+    /// Does the bookkeeping of a lookup answered by the hot cache.
+    fn record_hot_hit(&self, path: &ShadowPath, entry: &OverrideEntry) {
+        // Cache hit! Hot small entries may sample the bookkeeping
+        let accesses = self.fast_lane_weight(entry);
+        if accesses > 0 {
+            self.stats.update_cache_accesses(true, accesses);
             
             // Update hot path tracking
             let bytes = entry.override_metadata.size;
-            self.stats.update_hot_path_access(path, bytes);
+            self.stats.update_hot_path_accesses(path, bytes, accesses);
             
             // Update LRU tracker on access
-            self.lru_tracker.record_access(path);
-            
-            // Update last accessed time
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            entry.last_accessed.store(now, Ordering::Relaxed);
-            
-            return Some(entry);
+            self.lru_tracker.record_accesses(path, accesses);
+        } else {
+            self.stats.record_activity();
         }
         
-        // Check main store
-        if let Some(entry) = self.entries.get(path) {
-            let entry_arc = entry.clone();
+        // Update last accessed time as the atime policy asks
+        self.record_access(entry);
+    }
+    
+    /// Finishes a lookup the hot cache missed, given what the main store
+    /// holds for `path`: falls back to the spill tier, caches what was found
+    /// and does the bookkeeping.
+    fn record_store_lookup(&self, path: &ShadowPath, resident: Option<Arc<OverrideEntry>>) -> Option<Arc<OverrideEntry>> {
+        if let Some(entry_arc) = resident.or_else(|| self.load_spilled(path).ok().flatten()) {
             
             // Cache miss, but found in main store
             self.stats.update_cache_access(false);
@@ -444,12 +943,8 @@ impl OverrideStore {
             // Update LRU tracker on access
             self.lru_tracker.record_access(path);
             
-            // Update last accessed time
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            entry_arc.last_accessed.store(now, Ordering::Relaxed);
+            // Update last accessed time as the atime policy asks
+            self.record_access(&entry_arc);
             
             Some(entry_arc)
         } else {
@@ -467,7 +962,9 @@ impl OverrideStore {
     /// # Returns
     /// true if the path exists (including deleted entries)
     pub fn exists(&self, path: &ShadowPath) -> bool {
-        self.entries.contains_key(path)
+        let held = self.held_spelling(path);
+        let path = held.as_ref().unwrap_or(path);
+        self.entries.contains_key(path) || self.is_spilled(path)
     }
     
     /// Checks if a path is marked as deleted.
@@ -478,6 +975,8 @@ impl OverrideStore {
     /// # Returns
     /// true if the path is marked as deleted
     pub fn is_deleted(&self, path: &ShadowPath) -> bool {
+        let held = self.held_spelling(path);
+        let path = held.as_ref().unwrap_or(path);
         if let Some(entry) = self.entries.get(path) {
             matches!(entry.content, OverrideContent::Deleted)
         } else {
@@ -492,8 +991,41 @@ impl OverrideStore {
     ///
     /// # Returns
     /// The removed entry if it existed
+    #[cfg_attr(not(feature = "persistence"), allow(unused_variables))]
+    #[instrument(name = "remove", level = "trace", skip_all, fields(path = %path))]
     pub fn remove(&self, path: &ShadowPath) -> Option<Arc<OverrideEntry>> {
-        if let Some((_, entry)) = self.entries.remove(path) {
+        let held = self.held_spelling(path);
+        let path = held.as_ref().unwrap_or(path);
+        #[cfg(feature = "persistence")]
+        let wal = self.wal.read().unwrap();
+        let spilled = self.take_spilled(path);
+        let resident = self.entries.remove(path).map(|(_, entry)| entry);
+        let was_resident = resident.is_some();
+        let removed = resident.or(spilled);
+        let had_delta = self.drop_delta(path);
+        if removed.is_some() || had_delta {
+            self.invalidate_listing(path, false);
+        }
+        #[cfg(feature = "persistence")]
+        if removed.is_some() || had_delta {
+            if let Some(wal) = wal.as_ref() {
+                // remove() has no error channel, so a failed append only loses
+                // durability of this removal; the in-memory state is still correct
+                // and a fail-fast policy tears the mount down through the supervisor
+                if let Err(e) = wal.append(&PersistenceOp::remove(path.clone())) {
+                    self.degrade_on_failure(Subsystem::Persistence, || {
+                        format!("Failed to log removal of {}: {}", path, e)
+                    });
+                }
+            }
+        }
+        
+        if let Some(entry) = removed {
+            self.quota_usage.replace(Some(&entry), None);
+            self.namespaces.replace(path, Some(&entry), None);
+            self.links.unlink(path);
+            self.case_index.forget(path);
+            
             // Calculate removal stats
             let entry_size = calculate_entry_size(&entry);
             let compression_saved = match &entry.content {
@@ -501,6 +1033,11 @@ impl OverrideStore {
                 _ => 0,
             };
             
+            // Spilled entries gave their memory back when they were spilled
+            if was_resident {
+                self.memory_tracker.release(entry_size);
+            }
+            
             // Update stats for removal
             self.stats.update_on_remove(&entry, entry_size, compression_saved, 0);
             
@@ -511,7 +1048,8 @@ impl OverrideStore {
             self.lru_tracker.remove_entry(path);
             
             // Remove from directory cache
-            if let Some(parent) = path.parent() {
+            let stream = self.stream_index.forget(path);
+            if let Some(parent) = path.parent().filter(|_| !stream) {
                 let filename = PathTraversal::get_filename(path);
                 if !filename.is_empty() {
                     self.directory_cache.remove_child(&parent, &filename);
@@ -525,7 +1063,6 @@ impl OverrideStore {
                 // For now, we leave it to avoid breaking other references
             }
             
-            // Memory will be freed when the Arc is dropped
             Some(entry)
         } else {
             None
@@ -534,16 +1071,22 @@ impl OverrideStore {
     
     /// Evicts entries based on the configured policy.
     ///
+    /// With a spill tier, the coldest entries by LRU are spilled instead,
+    /// whatever the policy.
+    ///
     /// # Arguments
     /// * `policy` - Eviction policy to use
     /// * `target_bytes` - Target number of bytes to free
     ///
     /// # Returns
     /// Number of bytes actually freed
-    fn evict_entries(&self, _policy: EvictionPolicy, target_bytes: usize) -> Result<usize, ShadowError> {
-        // For now, use a simple LRU eviction without complex victim selection
-        let lru_paths = self.lru_tracker.get_least_recently_used(10); // Get up to 10 candidates
-        let victims = lru_paths;
+    #[instrument(name = "evict", level = "debug", skip(self), err)]
+    fn evict_entries(&self, policy: EvictionPolicy, target_bytes: usize) -> Result<usize, ShadowError> {
+        if let Some(tier) = self.spill_tier()? {
+            return self.spill_cold_entries(&tier, target_bytes, None);
+        }
+        
+        let victims = self.lru_tracker.select_victims(policy, &self.entries, target_bytes);
         let mut freed_bytes = 0;
         
         let mut evicted_count = 0;
@@ -552,6 +1095,9 @@ impl OverrideStore {
                 let entry_size = calculate_entry_size(&entry);
                 freed_bytes += entry_size;
                 evicted_count += 1;
+                if policy == EvictionPolicy::TwoQueue {
+                    self.lru_tracker.record_eviction(&path);
+                }
                 if freed_bytes >= target_bytes {
                     break;
                 }
@@ -580,40 +1126,6 @@ impl OverrideStore {
         }
     }
     
-    /// Inserts multiple entries in a batch operation.
-    ///
-    /// # Arguments
-    /// * `entries` - Vector of (path, content) pairs to insert
-    ///
-    /// # Returns
-    /// Ok(()) if all entries were inserted successfully, or the first error encountered
-    pub fn insert_batch(&self, entries: Vec<(ShadowPath, OverrideContent)>) -> Result<(), ShadowError> {
-        for (path, content) in entries {
-            match content {
-                OverrideContent::File { data, is_compressed, .. } => {
-                    // For batch insert, use the original data and let insert_file handle optimization
-                    let original_data = if is_compressed {
-                        // If data was compressed, decompress it first
-                        compression::decompress(&data)
-                            .map_err(|e| ShadowError::IoError { 
-                                source: e 
-                            })?
-                    } else {
-                        data
-                    };
-                    self.insert_file(path, original_data, None)?;
-                }
-                OverrideContent::Directory { .. } => {
-                    self.insert_directory(path, None)?;
-                }
-                OverrideContent::Deleted => {
-                    self.mark_deleted(path)?;
-                }
-            }
-        }
-        Ok(())
-    }
-    
     /// Removes multiple entries in a batch operation.
     ///
     /// # Arguments
@@ -650,7 +1162,7 @@ impl OverrideStore {
     
     /// Gets the number of entries in the store.
     pub fn entry_count(&self) -> usize {
-        self.entries.len()
+        self.entries.len() + self.spill.read().unwrap().as_ref().map_or(0, |tier| tier.len())
     }
     
     // === Directory Operations ===
@@ -782,12 +1294,12 @@ impl OverrideStore {
     /// # Returns
     /// Vector of paths that were deleted
     pub fn delete_directory_recursive(&self, path: &ShadowPath) -> Result<Vec<ShadowPath>, ShadowError> {
+        self.check_writable(path, "delete")?;
+        self.check_access(path, AccessOperation::Delete)?;
         let mut deleted_paths = Vec::new();
         
         // Find all affected children (direct and indirect)
-        let all_paths: Vec<ShadowPath> = self.entries.iter()
-            .map(|entry| entry.key().clone())
-            .collect();
+        let all_paths = self.all_paths();
         
         let affected_children = PathTraversal::find_affected_children(path, &all_paths);
         
@@ -835,9 +1347,7 @@ impl OverrideStore {
     /// # Returns
     /// Vector of all child paths (direct and indirect)
     pub fn get_children_recursive(&self, path: &ShadowPath) -> Vec<ShadowPath> {
-        let all_paths: Vec<ShadowPath> = self.entries.iter()
-            .map(|entry| entry.key().clone())
-            .collect();
+        let all_paths = self.all_paths();
         
         PathTraversal::find_affected_children(path, &all_paths)
     }
@@ -869,7 +1379,8 @@ impl OverrideStore {
     /// # Returns
     /// Vector of child names
     pub fn get_directory_children(&self, parent: &ShadowPath) -> Vec<String> {
-        self.directory_cache.get_children(parent)
+        let held = self.held_spelling(parent);
+        self.directory_cache.get_children(held.as_ref().unwrap_or(parent))
     }
     
     /// Gets optimization statistics.
@@ -937,7 +1448,9 @@ impl OverrideStore {
     /// # Returns
     /// Detailed statistics report with performance metrics
     pub fn get_stats_report(&self) -> StatsReport {
-        self.stats.generate_report()
+        let mut report = self.stats.generate_report();
+        report.source_cache = self.source_cache().map(|cache| cache.stats());
+        report
     }
     
     /// Gets current statistics snapshot.
@@ -948,6 +1461,17 @@ impl OverrideStore {
         self.stats.get_snapshot()
     }
     
+    /// Returns when the store was last used by a lookup, an insert or a removal.
+    pub fn last_activity(&self) -> SystemTime {
+        self.stats.last_activity()
+    }
+    
+    /// Returns how long the store has gone unused, unaffected by changes of
+    /// the wall clock.
+    pub fn idle_for(&self) -> Duration {
+        self.stats.idle_for()
+    }
+    
     /// Gets memory usage breakdown.
     ///
     /// # Returns
@@ -990,4 +1514,149 @@ impl OverrideStore {
     pub fn reset_stats(&self) {
         self.stats.reset();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn store_with_policy(policy: EvictionPolicy) -> OverrideStore {
+        OverrideStore::new(OverrideStoreConfig {
+            eviction_policy: policy,
+            enable_compression: false,
+            ..OverrideStoreConfig::default()
+        })
+    }
+    
+    fn insert(store: &OverrideStore, path: &str, size: usize) -> ShadowPath {
+        let path = ShadowPath::new(path.into());
+        store.insert_file(path.clone(), Bytes::from(vec![0u8; size]), None).unwrap();
+        path
+    }
+    
+    fn evict_one(store: &OverrideStore, policy: EvictionPolicy) -> Vec<ShadowPath> {
+        let before: Vec<_> = store.entries.iter().map(|(path, _)| path).collect();
+        store.evict_entries(policy, 1).unwrap();
+        before.into_iter().filter(|path| !store.exists(path)).collect()
+    }
+    
+    #[test]
+    fn test_get_many_counts_like_get() {
+        let store = store_with_policy(EvictionPolicy::Lru);
+        let hot = insert(&store, "/hot", 10);
+        let cold = insert(&store, "/cold", 20);
+        let deleted = ShadowPath::from("/deleted");
+        store.mark_deleted(deleted.clone()).unwrap();
+        store.get(&hot);
+        let before = store.get_stats_snapshot();
+        let hot_accesses = || store.lru_tracker.get_access_stats(&hot).unwrap().access_count;
+        let hot_before = hot_accesses();
+        
+        let missing = ShadowPath::from("/missing");
+        let found = store.get_many(&[hot.clone(), missing, cold.clone(), deleted, hot.clone()]);
+        let sizes: Vec<_> = found.iter().map(|entry| entry.as_ref().map(|entry| entry.override_metadata.size)).collect();
+        assert_eq!(sizes, [Some(10), None, Some(20), Some(0), Some(10)]);
+        assert!(found[3].as_ref().unwrap().is_deleted());
+        
+        let after = store.get_stats_snapshot();
+        assert_eq!(after.cache_hits - before.cache_hits, 2);
+        assert_eq!(after.cache_misses - before.cache_misses, 3);
+        assert!(store.hot_cache.contains(&cold));
+        assert_eq!(hot_accesses() - hot_before, 2);
+    }
+    
+    #[test]
+    fn test_evict_lru() {
+        let store = store_with_policy(EvictionPolicy::Lru);
+        let a = insert(&store, "/a", 100);
+        let b = insert(&store, "/b", 100);
+        let _c = insert(&store, "/c", 100);
+        
+        store.get(&a);
+        
+        assert_eq!(evict_one(&store, EvictionPolicy::Lru), vec![b]);
+    }
+    
+    #[test]
+    fn test_evict_lfu() {
+        let store = store_with_policy(EvictionPolicy::Lfu);
+        let a = insert(&store, "/a", 100);
+        let b = insert(&store, "/b", 100);
+        let c = insert(&store, "/c", 100);
+        
+        store.get(&a);
+        store.get(&a);
+        store.get(&b);
+        store.get(&c);
+        store.get(&c);
+        
+        assert_eq!(evict_one(&store, EvictionPolicy::Lfu), vec![b]);
+    }
+    
+    #[test]
+    fn test_evict_fifo() {
+        let store = store_with_policy(EvictionPolicy::Fifo);
+        let a = insert(&store, "/a", 100);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let b = insert(&store, "/b", 100);
+        
+        store.get(&a);
+        store.get(&a);
+        
+        assert_eq!(evict_one(&store, EvictionPolicy::Fifo), vec![a]);
+        assert!(store.exists(&b));
+    }
+    
+    #[test]
+    fn test_evict_size_weighted() {
+        let store = store_with_policy(EvictionPolicy::SizeWeighted);
+        let _a = insert(&store, "/a", 100);
+        let big = insert(&store, "/big", 10_000);
+        let _c = insert(&store, "/c", 100);
+        
+        assert_eq!(evict_one(&store, EvictionPolicy::SizeWeighted), vec![big]);
+    }
+    
+    #[test]
+    fn test_evict_two_queue() {
+        let store = store_with_policy(EvictionPolicy::TwoQueue);
+        let hot = insert(&store, "/hot", 100);
+        store.get(&hot);
+        
+        // A scan of one-off reads is evicted before the reused entry
+        let scanned: Vec<_> = (0..3)
+            .map(|i| insert(&store, &format!("/scan{}", i), 100))
+            .collect();
+        for path in &scanned {
+            assert_eq!(evict_one(&store, EvictionPolicy::TwoQueue), vec![path.clone()]);
+        }
+        assert!(store.exists(&hot));
+        
+        // An evicted path written again goes straight to the frequent queue
+        let returning = insert(&store, "/scan0", 100);
+        let fresh = insert(&store, "/fresh", 100);
+        assert_eq!(evict_one(&store, EvictionPolicy::TwoQueue), vec![fresh]);
+        assert_eq!(evict_one(&store, EvictionPolicy::TwoQueue), vec![hot]);
+        assert!(store.exists(&returning));
+    }
+    
+    #[test]
+    #[cfg(feature = "dedup")]
+    fn test_duplicate_content_shares_one_buffer() {
+        let store = store_with_policy(EvictionPolicy::Lru);
+        let first = insert(&store, "/first", 4096);
+        // The same content again, in a buffer of its own
+        let second = insert(&store, "/second", 4096);
+        
+        let (dedup_entries, dedup_bytes, _, _) = store.optimization_stats();
+        assert_eq!((dedup_entries, dedup_bytes), (1, 4096));
+        
+        let first = store.get(&first).unwrap().get_file_data().unwrap().unwrap();
+        let second = store.get(&second).unwrap();
+        assert_eq!(second.get_file_data().unwrap().unwrap().as_ptr(), first.as_ptr());
+        
+        // Reads are slices of the shared buffer
+        let range = second.read_file_range(1024, 100).unwrap().unwrap();
+        assert_eq!(range.len(), 100);
+        assert_eq!(range.as_ptr(), first[1024..].as_ptr());
+    }
+}
@@ -2,20 +2,25 @@
 
 use crate::types::ShadowPath;
 use bytes::Bytes;
+#[cfg(feature = "dedup")]
 use dashmap::DashMap;
 use lru::LruCache;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
 use std::num::NonZeroUsize;
 
 /// Content hash type for deduplication
 pub type ContentHash = [u8; 32];
 
 /// Content deduplication system for eliminating duplicate data
+#[cfg(feature = "dedup")]
 pub struct ContentDeduplication {
-    /// Map from content hash to reference-counted data
-    content_hashes: DashMap<ContentHash, Arc<Bytes>>,
+    /// Map from content hash to the data every entry with that content
+    /// shares; cloning `Bytes` only bumps its reference count
+    content_hashes: DashMap<ContentHash, Bytes>,
 }
 
+#[cfg(feature = "dedup")]
 impl ContentDeduplication {
     /// Creates a new content deduplication system
     pub fn new() -> Self {
@@ -25,22 +30,17 @@ impl ContentDeduplication {
     }
 
     /// Stores content and returns deduplicated reference
-    pub fn store_content(&self, data: Bytes) -> (ContentHash, Arc<Bytes>) {
+    ///
+    /// Content already stored is returned in place of `data`, which is
+    /// dropped, so every caller shares one buffer.
+    pub fn store_content(&self, data: Bytes) -> (ContentHash, Bytes) {
         let hash = hash_content(&data);
-        
-        // Check if we already have this content
-        if let Some(existing) = self.content_hashes.get(&hash) {
-            return (hash, existing.clone());
-        }
-        
-        // Store new content
-        let arc_data = Arc::new(data);
-        self.content_hashes.insert(hash, arc_data.clone());
-        (hash, arc_data)
+        let stored = self.content_hashes.entry(hash).or_insert(data);
+        (hash, stored.clone())
     }
 
     /// Gets content by hash if it exists
-    pub fn get_content(&self, hash: &ContentHash) -> Option<Arc<Bytes>> {
+    pub fn get_content(&self, hash: &ContentHash) -> Option<Bytes> {
         self.content_hashes.get(hash).map(|entry| entry.clone())
     }
 
@@ -60,6 +60,40 @@ impl ContentDeduplication {
     }
 }
 
+/// Content deduplication stand-in for builds without the `dedup` feature
+///
+/// Content is stored as given and every hash is zero.
+#[cfg(not(feature = "dedup"))]
+pub struct ContentDeduplication;
+
+#[cfg(not(feature = "dedup"))]
+impl ContentDeduplication {
+    /// Creates a new content deduplication system
+    pub fn new() -> Self {
+        Self
+    }
+    
+    /// Wraps content without looking for duplicates
+    pub fn store_content(&self, data: Bytes) -> (ContentHash, Bytes) {
+        (hash_content(&data), data)
+    }
+    
+    /// Gets content by hash; nothing is ever shared
+    pub fn get_content(&self, _hash: &ContentHash) -> Option<Bytes> {
+        None
+    }
+    
+    /// Removes content by hash; nothing is ever shared
+    pub fn remove_content(&self, _hash: &ContentHash) -> bool {
+        false
+    }
+    
+    /// Gets statistics about deduplicated content
+    pub fn stats(&self) -> (usize, usize) {
+        (0, 0)
+    }
+}
+
 /// Read-through cache for hot entries
 pub struct ReadThroughCache<T> {
     /// LRU cache for hot entries
@@ -81,6 +115,17 @@ impl<T> ReadThroughCache<T> {
         cache.get(path).cloned()
     }
 
+    /// Gets the cached entries of many paths under one lock, in order
+    pub fn get_many<'p>(&self, paths: impl IntoIterator<Item = &'p ShadowPath>) -> Vec<Option<Arc<T>>> {
+        let mut cache = self.hot_entries.lock().unwrap();
+        paths.into_iter().map(|path| cache.get(path).cloned()).collect()
+    }
+    
+    /// Checks whether `path` is cached, without counting it as used
+    pub fn contains(&self, path: &ShadowPath) -> bool {
+        self.hot_entries.lock().unwrap().contains(path)
+    }
+    
     /// Puts an entry into the cache
     pub fn put(&self, path: ShadowPath, entry: Arc<T>) {
         let mut cache = self.hot_entries.lock().unwrap();
@@ -107,7 +152,8 @@ impl<T> ReadThroughCache<T> {
 }
 
 /// Strategy for prefetching directory contents
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrefetchStrategy {
     /// No prefetching
     None,
@@ -171,23 +217,19 @@ impl DirectoryPrefetcher {
 
 /// Sharded storage for better concurrency
 pub struct ShardedMap<K, V> {
-    /// Array of sharded maps
-    shards: [DashMap<K, V>; 16],
+    /// Array of independently locked shards
+    shards: [RwLock<HashMap<K, V>>; 16],
 }
 
 impl<K, V> ShardedMap<K, V>
 where
     K: std::hash::Hash + Eq + Clone,
+    V: Clone,
 {
     /// Creates a new sharded map
     pub fn new() -> Self {
         Self {
-            shards: [
-                DashMap::new(), DashMap::new(), DashMap::new(), DashMap::new(),
-                DashMap::new(), DashMap::new(), DashMap::new(), DashMap::new(),
-                DashMap::new(), DashMap::new(), DashMap::new(), DashMap::new(),
-                DashMap::new(), DashMap::new(), DashMap::new(), DashMap::new(),
-            ],
+            shards: std::array::from_fn(|_| RwLock::new(HashMap::new())),
         }
     }
 
@@ -204,46 +246,118 @@ where
     /// Inserts a key-value pair
     pub fn insert(&self, key: K, value: V) -> Option<V> {
         let shard_idx = self.shard_index(&key);
-        self.shards[shard_idx].insert(key, value)
+        self.shards[shard_idx].write().unwrap().insert(key, value)
     }
 
-    /// Gets a value by key
-    pub fn get(&self, key: &K) -> Option<dashmap::mapref::one::Ref<'_, K, V>> {
+    /// Gets a clone of the value for a key
+    pub fn get(&self, key: &K) -> Option<V> {
         let shard_idx = self.shard_index(key);
-        self.shards[shard_idx].get(key)
+        self.shards[shard_idx].read().unwrap().get(key).cloned()
+    }
+
+    /// Gets clones of the values of many keys, in order, taking each
+    /// shard's lock at most once
+    pub fn get_many<'k>(&self, keys: impl IntoIterator<Item = &'k K>) -> Vec<Option<V>>
+    where
+        K: 'k,
+    {
+        let keys: Vec<&K> = keys.into_iter().collect();
+        let mut by_shard: [Vec<usize>; 16] = Default::default();
+        for (index, key) in keys.iter().enumerate() {
+            by_shard[self.shard_index(key)].push(index);
+        }
+        
+        let mut values = vec![None; keys.len()];
+        for (shard, indices) in self.shards.iter().zip(&by_shard) {
+            if indices.is_empty() {
+                continue;
+            }
+            let shard = shard.read().unwrap();
+            for &index in indices {
+                values[index] = shard.get(keys[index]).cloned();
+            }
+        }
+        values
+    }
+    
+    /// Inserts many key-value pairs, taking each shard's lock at most once,
+    /// and returns the values they replaced, in order
+    ///
+    /// A key given twice is replaced by its later pair, as with repeated
+    /// calls to [`ShardedMap::insert`].
+    pub fn insert_many(&self, pairs: Vec<(K, V)>) -> Vec<Option<V>> {
+        let mut by_shard: [Vec<usize>; 16] = Default::default();
+        for (index, (key, _)) in pairs.iter().enumerate() {
+            by_shard[self.shard_index(key)].push(index);
+        }
+        
+        let mut pairs: Vec<Option<(K, V)>> = pairs.into_iter().map(Some).collect();
+        let mut replaced = vec![None; pairs.len()];
+        for (shard, indices) in self.shards.iter().zip(&by_shard) {
+            if indices.is_empty() {
+                continue;
+            }
+            let mut shard = shard.write().unwrap();
+            for &index in indices {
+                let (key, value) = pairs[index].take().expect("each pair is inserted once");
+                replaced[index] = shard.insert(key, value);
+            }
+        }
+        replaced
     }
-
+    
     /// Removes a key-value pair
     pub fn remove(&self, key: &K) -> Option<(K, V)> {
         let shard_idx = self.shard_index(key);
-        self.shards[shard_idx].remove(key)
+        self.shards[shard_idx].write().unwrap().remove_entry(key)
     }
 
+    /// Removes a key-value pair if `predicate` holds for the current value
+    pub fn remove_if(&self, key: &K, predicate: impl FnOnce(&K, &V) -> bool) -> Option<(K, V)> {
+        let shard_idx = self.shard_index(key);
+        let mut shard = self.shards[shard_idx].write().unwrap();
+        let (current_key, current) = shard.get_key_value(key)?;
+        if predicate(current_key, current) {
+            shard.remove_entry(key)
+        } else {
+            None
+        }
+    }
+    
     /// Checks if a key exists
     pub fn contains_key(&self, key: &K) -> bool {
         let shard_idx = self.shard_index(key);
-        self.shards[shard_idx].contains_key(key)
+        self.shards[shard_idx].read().unwrap().contains_key(key)
     }
 
     /// Gets the total number of entries across all shards
     pub fn len(&self) -> usize {
-        self.shards.iter().map(|shard| shard.len()).sum()
+        self.shards.iter().map(|shard| shard.read().unwrap().len()).sum()
     }
 
     /// Checks if the map is empty
     pub fn is_empty(&self) -> bool {
-        self.shards.iter().all(|shard| shard.is_empty())
-    }
-
-    /// Iterates over all key-value pairs
-    pub fn iter(&self) -> impl Iterator<Item = dashmap::mapref::multiple::RefMulti<'_, K, V>> {
-        self.shards.iter().flat_map(|shard| shard.iter())
+        self.shards.iter().all(|shard| shard.read().unwrap().is_empty())
+    }
+    
+    /// Iterates over a snapshot of all key-value pairs
+    ///
+    /// Each shard is copied under its own read lock, so the snapshot is
+    /// consistent per shard but not across shards.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        let mut pairs = Vec::with_capacity(self.len());
+        for shard in &self.shards {
+            let shard = shard.read().unwrap();
+            pairs.extend(shard.iter().map(|(key, value)| (key.clone(), value.clone())));
+        }
+        pairs.into_iter()
     }
 }
 
 impl<K, V> Default for ShardedMap<K, V>
 where
     K: std::hash::Hash + Eq + Clone,
+    V: Clone,
 {
     fn default() -> Self {
         Self::new()
@@ -251,11 +365,19 @@ where
 }
 
 /// Hashes content using BLAKE3
+#[cfg(feature = "dedup")]
 pub fn hash_content(data: &[u8]) -> ContentHash {
     blake3::hash(data).into()
 }
 
+/// Returns the zero hash; content hashes need the `dedup` feature
+#[cfg(not(feature = "dedup"))]
+pub fn hash_content(_data: &[u8]) -> ContentHash {
+    [0u8; 32]
+}
+
 /// Compression utilities for large entries
+#[cfg(feature = "compression")]
 pub mod compression {
     use bytes::Bytes;
     use std::io::{Read, Write};
@@ -264,6 +386,7 @@ pub mod compression {
     pub const COMPRESSION_THRESHOLD: usize = 1024 * 1024;
 
     /// Compresses data using zstd
+    #[tracing::instrument(level = "trace", skip_all, fields(bytes = data.len()))]
     pub fn compress(data: &[u8]) -> Result<Bytes, std::io::Error> {
         let mut encoder = zstd::Encoder::new(Vec::new(), 3)?;
         encoder.write_all(data)?;
@@ -272,6 +395,7 @@ pub mod compression {
     }
 
     /// Decompresses data using zstd
+    #[tracing::instrument(level = "trace", skip_all, fields(bytes = compressed_data.len()))]
     pub fn decompress(compressed_data: &[u8]) -> Result<Bytes, std::io::Error> {
         let mut decoder = zstd::Decoder::new(compressed_data)?;
         let mut decompressed = Vec::new();
@@ -279,30 +403,76 @@ pub mod compression {
         Ok(Bytes::from(decompressed))
     }
 
+    /// Returns the size of zstd compressed data once decompressed, without
+    /// keeping the decompressed data
+    pub fn decompressed_size(compressed_data: &[u8]) -> Result<u64, std::io::Error> {
+        let mut decoder = zstd::Decoder::new(compressed_data)?;
+        std::io::copy(&mut decoder, &mut std::io::sink())
+    }
+    
     /// Checks if data should be compressed
     pub fn should_compress(data: &[u8]) -> bool {
         data.len() >= COMPRESSION_THRESHOLD
     }
 }
 
+/// Compression stand-in for builds without the `compression` feature
+///
+/// Nothing is compressed, and compressed data cannot be read back.
+#[cfg(not(feature = "compression"))]
+pub mod compression {
+    use bytes::Bytes;
+    
+    /// Minimum size for compression (1MB)
+    pub const COMPRESSION_THRESHOLD: usize = 1024 * 1024;
+    
+    /// Fails; compression needs the `compression` feature
+    pub fn compress(_data: &[u8]) -> Result<Bytes, std::io::Error> {
+        Err(unsupported())
+    }
+    
+    /// Fails; decompression needs the `compression` feature
+    pub fn decompress(_compressed_data: &[u8]) -> Result<Bytes, std::io::Error> {
+        Err(unsupported())
+    }
+    
+    /// Fails; decompression needs the `compression` feature
+    pub fn decompressed_size(_compressed_data: &[u8]) -> Result<u64, std::io::Error> {
+        Err(unsupported())
+    }
+    
+    /// Always false, so callers keep data uncompressed
+    pub fn should_compress(_data: &[u8]) -> bool {
+        false
+    }
+    
+    fn unsupported() -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "shadowfs-core was built without the `compression` feature",
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[cfg(feature = "dedup")]
     fn test_content_deduplication() {
         let dedup = ContentDeduplication::new();
         let data1 = Bytes::from("hello world");
         let data2 = Bytes::from("hello world");
         let data3 = Bytes::from("different");
 
-        let (hash1, arc1) = dedup.store_content(data1);
-        let (hash2, arc2) = dedup.store_content(data2);
-        let (hash3, _arc3) = dedup.store_content(data3);
+        let (hash1, stored1) = dedup.store_content(data1);
+        let (hash2, stored2) = dedup.store_content(data2);
+        let (hash3, _stored3) = dedup.store_content(data3);
 
-        // Same content should have same hash and same Arc
+        // Same content should have same hash and share one buffer
         assert_eq!(hash1, hash2);
-        assert!(Arc::ptr_eq(&arc1, &arc2));
+        assert_eq!(stored1.as_ptr(), stored2.as_ptr());
         assert_ne!(hash1, hash3);
     }
 
@@ -337,16 +507,24 @@ mod tests {
         map.insert("key1".to_string(), 1);
         map.insert("key2".to_string(), 2);
         
-        assert_eq!(map.get(&"key1".to_string()).unwrap().value(), &1);
-        assert_eq!(map.get(&"key2".to_string()).unwrap().value(), &2);
+        assert_eq!(map.get(&"key1".to_string()), Some(1));
+        assert_eq!(map.get(&"key2".to_string()), Some(2));
         assert_eq!(map.len(), 2);
         
         map.remove(&"key1".to_string());
         assert!(map.get(&"key1".to_string()).is_none());
         assert_eq!(map.len(), 1);
+        
+        let keys: Vec<String> = ["key2", "key1", "key2"].iter().map(|key| key.to_string()).collect();
+        assert_eq!(map.get_many(&keys), [Some(2), None, Some(2)]);
+        
+        let pairs = vec![("key2".to_string(), 3), ("key3".to_string(), 4), ("key3".to_string(), 5)];
+        assert_eq!(map.insert_many(pairs), [Some(2), None, Some(4)]);
+        assert_eq!(map.get(&"key3".to_string()), Some(5));
     }
 
     #[test]
+    #[cfg(feature = "compression")]
     fn test_compression() {
         use compression::*;
         
@@ -364,6 +542,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "dedup")]
     fn test_hash_content() {
         let data1 = b"hello world";
         let data2 = b"hello world";
@@ -1,51 +1,21 @@
 //! Advanced pattern matching and rule-based overrides for shadowfs.
-
-use crate::types::{ShadowPath, FileMetadata};
+//!
+//! Rule sets, templates and content transforms need the `patterns` feature;
+//! plain [`OverrideRule`] matching is always available.
+
+use super::rules::OverrideRule;
+use super::rename::{source_metadata, source_path};
+use super::{OverrideEntry, OverrideStore};
+use crate::error::ShadowError;
+use crate::types::{ShadowPath, FileMetadata, current_time};
 use bytes::Bytes;
-use regex::Regex;
 use std::collections::{HashMap, BTreeMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{SystemTime, Duration};
 use std::fmt;
 
-/// Pattern matching rules for file overrides
-#[derive(Debug, Clone)]
-pub enum OverrideRule {
-    /// Exact path match
-    Exact(ShadowPath),
-    /// Path prefix match
-    Prefix(ShadowPath),
-    /// Path suffix match
-    Suffix(String),
-    /// Regular expression match on path
-    Regex(Regex),
-    /// Glob pattern match
-    Glob(String),
-}
-
-impl OverrideRule {
-    /// Tests if a path matches this rule
-    pub fn matches(&self, path: &ShadowPath) -> bool {
-        let path_str = path.to_string();
-        
-        match self {
-            OverrideRule::Exact(pattern) => path == pattern,
-            OverrideRule::Prefix(prefix) => path_str.starts_with(&prefix.to_string()),
-            OverrideRule::Suffix(suffix) => path_str.ends_with(suffix),
-            OverrideRule::Regex(regex) => regex.is_match(&path_str),
-            OverrideRule::Glob(pattern) => {
-                // Simple glob matching (*, ?, [])
-                glob_match(pattern, &path_str)
-            }
-        }
-    }
-    
-    /// Creates a regex rule from a pattern string
-    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
-        Ok(OverrideRule::Regex(Regex::new(pattern)?))
-    }
-}
-
 /// Rule priority for ordering multiple matching rules
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RulePriority(pub u32);
@@ -222,6 +192,92 @@ pub mod transforms {
             Ok(Bytes::from(result))
         })
     }
+    
+    /// Substitutes text like a sed `s` command, such as
+    /// `s/password=.*/password=***/g`.
+    ///
+    /// Any character may delimit the expression. The pattern is a regular
+    /// expression matched against raw bytes, so content need not be UTF-8.
+    /// In the replacement, `&` stands for the whole match and `\1` to `\9`
+    /// for capture groups. Flags are `g`, replacing every match instead of
+    /// the first, and `i`, ignoring case.
+    pub fn sed(expression: &str) -> Result<TransformFn, TransformError> {
+        let invalid = |reason: &str| TransformError::TransformFailed(format!("invalid sed expression '{}': {}", expression, reason));
+        let mut chars = expression.chars();
+        if chars.next() != Some('s') {
+            return Err(invalid("expected s/pattern/replacement/"));
+        }
+        let delimiter = chars.next().filter(|c| !c.is_alphanumeric() && *c != '\\')
+            .ok_or_else(|| invalid("missing delimiter"))?;
+        
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for c in chars {
+            if escaped {
+                // An escaped delimiter stands for itself; other escapes are kept
+                if c != delimiter {
+                    parts.last_mut().unwrap().push('\\');
+                }
+                parts.last_mut().unwrap().push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == delimiter {
+                parts.push(String::new());
+            } else {
+                parts.last_mut().unwrap().push(c);
+            }
+        }
+        if parts.len() != 3 || escaped {
+            return Err(invalid("expected s/pattern/replacement/flags"));
+        }
+        let flags = parts.pop().unwrap();
+        let replacement = sed_replacement(&parts.pop().unwrap());
+        let pattern = parts.pop().unwrap();
+        
+        let mut global = false;
+        let mut builder = regex::bytes::RegexBuilder::new(&pattern);
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => {
+                    builder.case_insensitive(true);
+                }
+                _ => return Err(invalid(&format!("unknown flag '{}'", flag))),
+            }
+        }
+        let regex = builder.build().map_err(|e| invalid(&e.to_string()))?;
+        Ok(Box::new(move |data: &[u8]| {
+            let limit = if global { 0 } else { 1 };
+            Ok(Bytes::from(regex.replacen(data, limit, replacement.as_bytes()).into_owned()))
+        }))
+    }
+    
+    /// Converts a sed replacement to the syntax of the regex crate.
+    fn sed_replacement(replacement: &str) -> String {
+        let mut converted = String::with_capacity(replacement.len());
+        let mut chars = replacement.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '&' => converted.push_str("${0}"),
+                '$' => converted.push_str("$$"),
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => {
+                        converted.push_str("${");
+                        converted.push(digit);
+                        converted.push('}');
+                    }
+                    Some('n') => converted.push('\n'),
+                    Some('t') => converted.push('\t'),
+                    Some('$') => converted.push_str("$$"),
+                    Some(other) => converted.push(other),
+                    None => converted.push('\\'),
+                },
+                _ => converted.push(c),
+            }
+        }
+        converted
+    }
 }
 
 /// Conditions for when an override should be active
@@ -260,7 +316,7 @@ impl OverrideCondition {
         match self {
             OverrideCondition::Always => true,
             OverrideCondition::TimeRange { start, end } => {
-                let now = SystemTime::now();
+                let now = current_time();
                 now >= *start && now <= *end
             }
             OverrideCondition::UserMatch(users) => {
@@ -375,7 +431,7 @@ impl OverrideTemplate {
                    path.extension().unwrap_or_default());
         
         // Add timestamp
-        if let Ok(timestamp) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        if let Ok(timestamp) = current_time().duration_since(SystemTime::UNIX_EPOCH) {
             vars.insert("timestamp".to_string(), timestamp.as_secs().to_string());
         }
         
@@ -576,10 +632,43 @@ impl fmt::Display for ResolveError {
 
 impl std::error::Error for ResolveError {}
 
+/// Identifier of a rule within its [`RuleSet`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuleId(pub u64);
+
+impl fmt::Display for RuleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Description of a rule in a [`RuleSet`], for listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleInfo {
+    /// Identifier returned by [`RuleSet::add_rule`]
+    pub id: RuleId,
+    /// Priority of the rule
+    pub priority: RulePriority,
+    /// The path rule, such as `glob:*.env`
+    pub rule: String,
+    /// Paths the rule was applied to
+    pub hits: u64,
+}
+
+/// A rule with its identifier and hit counter
+#[derive(Clone)]
+struct StoredRule {
+    id: RuleId,
+    entry: OverrideRuleEntry,
+    hits: Arc<AtomicU64>,
+}
+
 /// Set of override rules with priority-based matching
 pub struct RuleSet {
     /// Rules stored in priority order (highest first)
-    rules: RwLock<BTreeMap<RulePriority, Vec<OverrideRuleEntry>>>,
+    rules: RwLock<BTreeMap<RulePriority, Vec<StoredRule>>>,
+    /// Identifier of the next rule added
+    next_id: AtomicU64,
 }
 
 impl RuleSet {
@@ -587,25 +676,39 @@ impl RuleSet {
     pub fn new() -> Self {
         Self {
             rules: RwLock::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
         }
     }
     
-    /// Adds a rule to the set
-    pub fn add_rule(&self, rule: OverrideRuleEntry) {
+    /// Adds a rule to the set, returning its identifier
+    pub fn add_rule(&self, rule: OverrideRuleEntry) -> RuleId {
+        let id = RuleId(self.next_id.fetch_add(1, Ordering::Relaxed));
         let mut rules = self.rules.write().unwrap();
         rules.entry(rule.priority)
             .or_insert_with(Vec::new)
-            .push(rule);
+            .push(StoredRule { id, entry: rule, hits: Arc::new(AtomicU64::new(0)) });
+        id
     }
     
     /// Finds the first matching rule for a path
     pub fn find_match(&self, path: &ShadowPath, metadata: Option<&FileMetadata>) -> Option<OverrideRuleEntry> {
+        self.first_match(path, metadata).map(|rule| rule.entry)
+    }
+    
+    /// Finds the first matching rule for a path and counts a hit against it
+    pub fn record_match(&self, path: &ShadowPath, metadata: Option<&FileMetadata>) -> Option<(RuleId, OverrideRuleEntry)> {
+        let rule = self.first_match(path, metadata)?;
+        rule.hits.fetch_add(1, Ordering::Relaxed);
+        Some((rule.id, rule.entry))
+    }
+    
+    fn first_match(&self, path: &ShadowPath, metadata: Option<&FileMetadata>) -> Option<StoredRule> {
         let rules = self.rules.read().unwrap();
         
         // Iterate through priorities in descending order
         for (_, rule_list) in rules.iter().rev() {
             for rule in rule_list {
-                if rule.rule.matches(path) && rule.condition.is_active(metadata) {
+                if rule.entry.rule.matches(path) && rule.entry.condition.is_active(metadata) {
                     return Some(rule.clone());
                 }
             }
@@ -622,8 +725,8 @@ impl RuleSet {
         // Iterate through priorities in descending order
         for (_, rule_list) in rules.iter().rev() {
             for rule in rule_list {
-                if rule.rule.matches(path) && rule.condition.is_active(metadata) {
-                    matches.push(rule.clone());
+                if rule.entry.rule.matches(path) && rule.entry.condition.is_active(metadata) {
+                    matches.push(rule.entry.clone());
                 }
             }
         }
@@ -635,6 +738,43 @@ impl RuleSet {
     pub fn remove_priority(&self, priority: RulePriority) -> Option<Vec<OverrideRuleEntry>> {
         let mut rules = self.rules.write().unwrap();
         rules.remove(&priority)
+            .map(|removed| removed.into_iter().map(|rule| rule.entry).collect())
+    }
+    
+    /// Removes the rule with the given identifier
+    pub fn remove_rule(&self, id: RuleId) -> Option<OverrideRuleEntry> {
+        let mut rules = self.rules.write().unwrap();
+        let (priority, index) = rules.iter()
+            .find_map(|(priority, list)| list.iter().position(|rule| rule.id == id).map(|index| (*priority, index)))?;
+        let list = rules.get_mut(&priority)?;
+        let removed = list.remove(index);
+        if list.is_empty() {
+            rules.remove(&priority);
+        }
+        Some(removed.entry)
+    }
+    
+    /// Returns the number of paths the rule with the given identifier was applied to
+    pub fn hits(&self, id: RuleId) -> Option<u64> {
+        let rules = self.rules.read().unwrap();
+        rules.values()
+            .flatten()
+            .find(|rule| rule.id == id)
+            .map(|rule| rule.hits.load(Ordering::Relaxed))
+    }
+    
+    /// Lists the rules, highest priority first
+    pub fn list(&self) -> Vec<RuleInfo> {
+        let rules = self.rules.read().unwrap();
+        rules.iter()
+            .rev()
+            .flat_map(|(priority, list)| list.iter().map(move |rule| RuleInfo {
+                id: rule.id,
+                priority: *priority,
+                rule: rule.entry.rule.to_string(),
+                hits: rule.hits.load(Ordering::Relaxed),
+            }))
+            .collect()
     }
     
     /// Gets the number of rules in the set
@@ -656,41 +796,71 @@ impl Default for RuleSet {
     }
 }
 
-/// Simple glob pattern matching
-fn glob_match(pattern: &str, text: &str) -> bool {
-    glob_match_recursive(pattern, text, 0, 0)
+/// Loads copy-on-write content from a source tree.
+struct SourceTreeLoader<'a> {
+    root: &'a Path,
+}
+
+impl ContentLoader for SourceTreeLoader<'_> {
+    fn load_content(&self, path: &ShadowPath) -> Result<Bytes, CowError> {
+        std::fs::read(source_path(self.root, path))
+            .map(Bytes::from)
+            .map_err(CowError::IoError)
+    }
 }
 
-fn glob_match_recursive(pattern: &str, text: &str, p_idx: usize, t_idx: usize) -> bool {
-    let pattern_chars: Vec<char> = pattern.chars().collect();
-    let text_chars: Vec<char> = text.chars().collect();
+impl OverrideStore {
+    /// Applies `rules` to source files read through the store's mounts.
+    pub fn set_rule_set(&self, rules: Arc<RuleSet>) {
+        *self.rules.write().unwrap() = Some(rules);
+    }
     
-    if p_idx == pattern_chars.len() {
-        return t_idx == text_chars.len();
+    /// Stops applying rules, returning the previous rule set.
+    pub fn clear_rule_set(&self) -> Option<Arc<RuleSet>> {
+        self.rules.write().unwrap().take()
     }
     
-    if t_idx == text_chars.len() {
-        return pattern_chars[p_idx..].iter().all(|&c| c == '*');
+    /// Returns the applied rule set, if any.
+    pub fn rule_set(&self) -> Option<Arc<RuleSet>> {
+        self.rules.read().unwrap().clone()
     }
     
-    match pattern_chars[p_idx] {
-        '*' => {
-            // Try matching zero or more characters
-            glob_match_recursive(pattern, text, p_idx + 1, t_idx) ||
-            glob_match_recursive(pattern, text, p_idx, t_idx + 1)
-        }
-        '?' => {
-            // Match exactly one character
-            glob_match_recursive(pattern, text, p_idx + 1, t_idx + 1)
+    /// Overrides `path` with the content of the first matching rule, the
+    /// first time it is accessed.
+    ///
+    /// Providers call this for files they would otherwise serve from the
+    /// source tree at `source_root`. The rule's content is stored as an
+    /// ordinary file override, so the rule is not consulted for the path
+    /// again and counts one hit.
+    ///
+    /// # Returns
+    /// The new override, or `None` if the path already has one, is not a
+    /// file in the source tree, or no rule matches it
+    pub fn apply_rules(&self, path: &ShadowPath, source_root: &Path) -> Result<Option<Arc<OverrideEntry>>, ShadowError> {
+        let Some(rules) = self.rule_set() else {
+            return Ok(None);
+        };
+        if self.get(path).is_some() || self.has_delta(path) {
+            return Ok(None);
         }
-        c => {
-            // Match literal character
-            if text_chars[t_idx] == c {
-                glob_match_recursive(pattern, text, p_idx + 1, t_idx + 1)
-            } else {
-                false
-            }
+        let source = source_path(source_root, path);
+        let Ok(metadata) = std::fs::metadata(&source) else {
+            return Ok(None);
+        };
+        if !metadata.is_file() {
+            return Ok(None);
         }
+        let original_metadata = source_metadata(&metadata, metadata.len());
+        let Some((id, rule)) = rules.record_match(path, Some(&original_metadata)) else {
+            return Ok(None);
+        };
+        
+        let content = rule.content.resolve(path, &SourceTreeLoader { root: source_root })
+            .map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Rule {} ({}) failed for {}: {}", id, rule.rule, path, e),
+            })?;
+        self.insert_file(path.clone(), content, Some(original_metadata))?;
+        Ok(self.get(path))
     }
 }
 
@@ -698,23 +868,6 @@ fn glob_match_recursive(pattern: &str, text: &str, p_idx: usize, t_idx: usize) -
 mod tests {
     use super::*;
     
-    #[test]
-    fn test_override_rule_matching() {
-        let path = ShadowPath::new("/home/user/test.txt".into());
-        
-        let exact_rule = OverrideRule::Exact(path.clone());
-        assert!(exact_rule.matches(&path));
-        
-        let prefix_rule = OverrideRule::Prefix(ShadowPath::new("/home".into()));
-        assert!(prefix_rule.matches(&path));
-        
-        let suffix_rule = OverrideRule::Suffix(".txt".to_string());
-        assert!(suffix_rule.matches(&path));
-        
-        let glob_rule = OverrideRule::Glob("*.txt".to_string());
-        assert!(glob_rule.matches(&path));
-    }
-    
     #[test]
     fn test_transform_chain() {
         let chain = TransformChain::new()
@@ -726,6 +879,21 @@ mod tests {
         assert_eq!(result, Bytes::from("PREFIX: HELLO WORLD"));
     }
     
+    #[test]
+    fn test_sed_transform() {
+        let apply = |expression: &str, input: &str| transforms::sed(expression).unwrap()(input.as_bytes()).unwrap();
+        assert_eq!(apply("s/password=.*/password=***/", "user=a\npassword=hunter2\n"), Bytes::from("user=a\npassword=***\n"));
+        assert_eq!(apply("s/o/0/", "foo boo"), Bytes::from("f0o boo"));
+        assert_eq!(apply("s/o/0/g", "foo boo"), Bytes::from("f00 b00"));
+        assert_eq!(apply("s|/usr/(\\w+)|/opt/\\1 [&]|i", "/USR/lib"), Bytes::from("/opt/lib [/USR/lib]"));
+        assert_eq!(apply("s/a\\/b/$HOME \\&/", "a/b"), Bytes::from("$HOME &"));
+        
+        assert!(transforms::sed("y/a/b/").is_err());
+        assert!(transforms::sed("s/a/b").is_err());
+        assert!(transforms::sed("s/a/b/x").is_err());
+        assert!(transforms::sed("s/(/b/").is_err());
+    }
+    
     #[test]
     fn test_template_expansion() {
         let template = OverrideTemplate::new("Hello ${name}!".to_string())
@@ -736,15 +904,6 @@ mod tests {
         assert!(result.starts_with(b"Hello World!"));
     }
     
-    #[test]
-    fn test_glob_matching() {
-        assert!(glob_match("*.txt", "file.txt"));
-        assert!(glob_match("test.*", "test.rs"));
-        assert!(glob_match("a?c", "abc"));
-        assert!(!glob_match("*.txt", "file.rs"));
-        assert!(glob_match("**", "anything/goes/here"));
-    }
-    
     #[test]
     fn test_rule_set_priority() {
         let rule_set = RuleSet::new();
@@ -770,4 +929,55 @@ mod tests {
         let match_result = rule_set.find_match(&path, None).unwrap();
         assert_eq!(match_result.priority, RulePriority::HIGH);
     }
-}
\ No newline at end of file
+    
+    fn env_rule(template: &str) -> OverrideRuleEntry {
+        OverrideRuleEntry {
+            rule: OverrideRule::Glob("*.env".to_string()),
+            priority: RulePriority::MEDIUM,
+            condition: OverrideCondition::Always,
+            content: OverrideContentType::Template(OverrideTemplate::new(template.to_string())),
+        }
+    }
+    
+    #[test]
+    fn test_rule_ids_and_removal() {
+        let rule_set = RuleSet::new();
+        let first = rule_set.add_rule(env_rule("a"));
+        let second = rule_set.add_rule(env_rule("b"));
+        assert_ne!(first, second);
+        
+        let path = ShadowPath::from("/app/.env");
+        assert_eq!(rule_set.record_match(&path, None).unwrap().0, first);
+        assert_eq!(rule_set.hits(first), Some(1));
+        assert_eq!(rule_set.list()[0].rule, "glob:*.env");
+        
+        assert!(rule_set.remove_rule(first).is_some());
+        assert!(rule_set.remove_rule(first).is_none());
+        assert_eq!(rule_set.record_match(&path, None).unwrap().0, second);
+        assert_eq!(rule_set.rule_count(), 1);
+    }
+    
+    #[test]
+    fn test_rules_apply_on_first_access() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("prod.env"), "SECRET=hunter2\n").unwrap();
+        std::fs::write(source.path().join("main.rs"), "fn main() {}\n").unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        let rule_set = Arc::new(RuleSet::new());
+        let id = rule_set.add_rule(env_rule("# masked ${filename}\n"));
+        store.set_rule_set(Arc::clone(&rule_set));
+        
+        let env = ShadowPath::from("/prod.env");
+        let entry = store.apply_rules(&env, source.path()).unwrap().unwrap();
+        assert_eq!(entry.get_file_data().unwrap().unwrap(), "# masked prod.env\n");
+        assert!(entry.original_metadata.is_some());
+        
+        // Applied once; the override now answers for the path
+        assert!(store.apply_rules(&env, source.path()).unwrap().is_none());
+        assert!(store.apply_rules(&ShadowPath::from("/main.rs"), source.path()).unwrap().is_none());
+        assert!(store.apply_rules(&ShadowPath::from("/missing.env"), source.path()).unwrap().is_none());
+        assert_eq!(rule_set.hits(id), Some(1));
+        assert_eq!(std::fs::read_to_string(source.path().join("prod.env")).unwrap(), "SECRET=hunter2\n");
+    }
+}
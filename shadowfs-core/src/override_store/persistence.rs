@@ -2,16 +2,36 @@
 
 use crate::types::{FileMetadata, ShadowPath};
 use crate::error::ShadowError;
-use crate::override_store::{OverrideStore, OverrideStoreConfig, OverrideEntry, OverrideContent};
+use crate::override_store::{LoggedOperation, OverrideStore, OverrideStoreConfig, OverrideEntry, OverrideContent};
+use crate::progress::{NoProgress, Progress};
 use async_trait::async_trait;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+/// Magic bytes at the start of every snapshot file.
+const SNAPSHOT_MAGIC: [u8; 8] = *b"SHDWSNAP";
+
+/// Current version of the on-disk snapshot format.
+///
+/// Bump this whenever the serialized layout of [`OverrideSnapshot`] changes.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Header flag set when the snapshot payload is zstd-compressed.
+const SNAPSHOT_FLAG_COMPRESSED: u8 = 0x01;
+
+/// Size of the snapshot header: magic, version and flags.
+const SNAPSHOT_HEADER_LEN: usize = SNAPSHOT_MAGIC.len() + 4 + 1;
+
+/// Compression level used by [`OverrideStore::save_snapshot`].
+const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
 /// Operations that can be persisted to the write-ahead log.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PersistenceOp {
@@ -35,6 +55,27 @@ pub enum PersistenceOp {
     Snapshot {
         timestamp: u64,
     },
+    /// Write a range of a delta override layered over `source`
+    WriteRange {
+        path: ShadowPath,
+        source: PathBuf,
+        offset: u64,
+        data: Bytes,
+        timestamp: u64,
+    },
+    /// Set the length of a delta override layered over `source`
+    Truncate {
+        path: ShadowPath,
+        source: PathBuf,
+        len: u64,
+        timestamp: u64,
+    },
+    /// Append to the end of a file override
+    Append {
+        path: ShadowPath,
+        data: Bytes,
+        timestamp: u64,
+    },
 }
 
 impl PersistenceOp {
@@ -70,6 +111,36 @@ impl PersistenceOp {
         }
     }
     
+    /// Creates a new WriteRange operation with current timestamp.
+    pub fn write_range(path: ShadowPath, source: PathBuf, offset: u64, data: Bytes) -> Self {
+        Self::WriteRange {
+            path,
+            source,
+            offset,
+            data,
+            timestamp: current_timestamp(),
+        }
+    }
+    
+    /// Creates a new Truncate operation with current timestamp.
+    pub fn truncate(path: ShadowPath, source: PathBuf, len: u64) -> Self {
+        Self::Truncate {
+            path,
+            source,
+            len,
+            timestamp: current_timestamp(),
+        }
+    }
+    
+    /// Creates a new Append operation with current timestamp.
+    pub fn append(path: ShadowPath, data: Bytes) -> Self {
+        Self::Append {
+            path,
+            data,
+            timestamp: current_timestamp(),
+        }
+    }
+    
     /// Returns the timestamp of this operation.
     pub fn timestamp(&self) -> u64 {
         match self {
@@ -77,6 +148,9 @@ impl PersistenceOp {
             Self::Remove { timestamp, .. } => *timestamp,
             Self::Clear { timestamp } => *timestamp,
             Self::Snapshot { timestamp } => *timestamp,
+            Self::WriteRange { timestamp, .. } => *timestamp,
+            Self::Truncate { timestamp, .. } => *timestamp,
+            Self::Append { timestamp, .. } => *timestamp,
         }
     }
 }
@@ -102,18 +176,20 @@ impl OverrideSnapshot {
         let config = store.get_config();
         let timestamp = current_timestamp();
         
-        // Extract all entries
-        let entries: HashMap<ShadowPath, OverrideEntry> = store.entries
-            .iter()
+        // Extract all entries, including spilled ones; a spill file that can
+        // no longer be read leaves only the entries still resident in memory
+        let all_entries = store.all_entries()
+            .unwrap_or_else(|_| store.entries.iter().map(|(_, entry)| entry).collect());
+        let mut entries: HashMap<ShadowPath, OverrideEntry> = all_entries
+            .into_iter()
             .map(|entry| {
-                let path = entry.key().clone();
-                let override_entry = (**entry.value()).clone();
-                (path, override_entry)
+                let entry = store.compacted_entry(&entry).unwrap_or_else(|_| (*entry).clone());
+                (entry.path.clone(), entry)
             })
             .collect();
         
         // Extract directory cache state
-        let directory_children: HashMap<ShadowPath, Vec<String>> = store.get_all_parent_directories()
+        let mut directory_children: HashMap<ShadowPath, Vec<String>> = store.get_all_parent_directories()
             .into_iter()
             .map(|parent| {
                 let children = store.get_directory_children(&parent);
@@ -121,6 +197,17 @@ impl OverrideSnapshot {
             })
             .collect();
         
+        // Delta overrides are stored compacted, as full file entries
+        for entry in store.delta_entries() {
+            if let (Some(parent), Some(name)) = (entry.path.parent(), entry.path.file_name()) {
+                let children = directory_children.entry(parent).or_default();
+                if !children.contains(&name) {
+                    children.push(name);
+                }
+            }
+            entries.insert(entry.path.clone(), entry);
+        }
+        
         let mut snapshot = Self {
             config,
             entries,
@@ -177,22 +264,47 @@ impl OverrideSnapshot {
     /// Restores an override store from this snapshot.
     pub fn restore_to_store(&self) -> Result<OverrideStore, ShadowError> {
         if !self.verify_integrity() {
-            return Err(ShadowError::PlatformError {
-                platform: crate::error::Platform::Linux, // Use current platform
+            return Err(ShadowError::InvalidConfiguration {
                 message: "Snapshot integrity check failed".to_string(),
-                code: None,
             });
         }
         
         let store = OverrideStore::new(self.config.clone());
-        
-        // Restore entries
-        for (path, entry) in &self.entries {
-            let entry_arc = Arc::new(entry.clone());
-            store.entries.insert(path.clone(), entry_arc);
-            
-            // Update LRU tracker
-            store.lru_tracker.record_access(path);
+        self.apply_to_store(&store)?;
+        Ok(store)
+    }
+    
+    /// Applies the entries and directory structure of this snapshot to `store`.
+    ///
+    /// Existing entries with the same path are replaced; other entries are kept.
+    pub fn apply_to_store(&self, store: &OverrideStore) -> Result<(), ShadowError> {
+        self.apply_to_store_with_progress(store, &NoProgress)
+    }
+    
+    /// Applies this snapshot to `store`, reporting one item per restored entry.
+    ///
+    /// Cancellation stops between entries. Restoring an entry replaces any
+    /// existing one, so applying the snapshot again completes a cancelled run.
+    pub fn apply_to_store_with_progress(
+        &self,
+        store: &OverrideStore,
+        progress: &dyn Progress,
+    ) -> Result<(), ShadowError> {
+        let bytes: u64 = self.entries.values().map(|entry| content_len(&entry.content)).sum();
+        progress.set_total(Some(self.entries.len() as u64), Some(bytes));
+        self.apply_entries(store, progress)?;
+        progress.finish();
+        Ok(())
+    }
+    
+    /// Restores entries and directory structure without touching progress totals.
+    fn apply_entries(&self, store: &OverrideStore, progress: &dyn Progress) -> Result<(), ShadowError> {
+        // Restore entries, including tombstones
+        for entry in self.entries.values() {
+            progress.check_cancelled("snapshot restore")?;
+            progress.set_current_path(&entry.path);
+            store.restore_entry(entry.clone())?;
+            progress.advance(1, content_len(&entry.content));
         }
         
         // Restore directory cache
@@ -202,7 +314,84 @@ impl OverrideSnapshot {
             }
         }
         
-        Ok(store)
+        Ok(())
+    }
+    
+    /// Encodes this snapshot into the versioned on-disk format.
+    ///
+    /// # Arguments
+    /// * `compression_level` - zstd level to compress the payload with, or `None` to store it raw
+    pub fn encode(&self, compression_level: Option<i32>) -> Result<Vec<u8>, ShadowError> {
+        let payload = bincode::serialize(self)
+            .map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Failed to serialize snapshot: {}", e),
+            })?;
+        
+        let (flags, payload) = match compression_level {
+            Some(level) => {
+                let compressed = zstd::encode_all(payload.as_slice(), level)
+                    .map_err(|e| ShadowError::IoError { source: e })?;
+                (SNAPSHOT_FLAG_COMPRESSED, compressed)
+            }
+            None => (0, payload),
+        };
+        
+        let mut data = Vec::with_capacity(SNAPSHOT_HEADER_LEN + payload.len());
+        data.extend_from_slice(&SNAPSHOT_MAGIC);
+        data.extend_from_slice(&SNAPSHOT_FORMAT_VERSION.to_le_bytes());
+        data.push(flags);
+        data.extend_from_slice(&payload);
+        Ok(data)
+    }
+    
+    /// Decodes a snapshot written by [`OverrideSnapshot::encode`].
+    ///
+    /// # Returns
+    /// The snapshot, or `InvalidConfiguration` if the data is not a snapshot,
+    /// was written by an unsupported format version, or fails its integrity check
+    pub fn decode(data: &[u8]) -> Result<Self, ShadowError> {
+        if data.len() < SNAPSHOT_HEADER_LEN || data[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            return Err(ShadowError::InvalidConfiguration {
+                message: "Not a ShadowFS snapshot file".to_string(),
+            });
+        }
+        
+        let version_offset = SNAPSHOT_MAGIC.len();
+        let version = u32::from_le_bytes([
+            data[version_offset],
+            data[version_offset + 1],
+            data[version_offset + 2],
+            data[version_offset + 3],
+        ]);
+        if version != SNAPSHOT_FORMAT_VERSION {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!(
+                    "Unsupported snapshot format version {} (expected {})",
+                    version, SNAPSHOT_FORMAT_VERSION
+                ),
+            });
+        }
+        
+        let flags = data[SNAPSHOT_HEADER_LEN - 1];
+        let payload = &data[SNAPSHOT_HEADER_LEN..];
+        let payload = if flags & SNAPSHOT_FLAG_COMPRESSED != 0 {
+            zstd::decode_all(payload).map_err(|e| ShadowError::IoError { source: e })?
+        } else {
+            payload.to_vec()
+        };
+        
+        let snapshot: Self = bincode::deserialize(&payload)
+            .map_err(|_| ShadowError::InvalidConfiguration {
+                message: "Corrupted snapshot file".to_string(),
+            })?;
+        
+        if !snapshot.verify_integrity() {
+            return Err(ShadowError::InvalidConfiguration {
+                message: "Snapshot integrity check failed".to_string(),
+            });
+        }
+        
+        Ok(snapshot)
     }
 }
 
@@ -277,32 +466,9 @@ impl FileBasedPersistence {
         Self::new(PersistenceConfig::default())
     }
     
-    /// Compresses data using zstd if compression is enabled.
-    fn compress_data(&self, data: &[u8]) -> Result<Vec<u8>, ShadowError> {
-        if self.config.enable_compression {
-            zstd::encode_all(data, self.config.compression_level)
-                .map_err(|e| ShadowError::PlatformError {
-                    platform: crate::error::Platform::Linux,
-                    message: format!("Compression failed: {}", e),
-                    code: None,
-                })
-        } else {
-            Ok(data.to_vec())
-        }
-    }
-    
-    /// Decompresses data using zstd if compression is enabled.
-    fn decompress_data(&self, data: &[u8]) -> Result<Vec<u8>, ShadowError> {
-        if self.config.enable_compression {
-            zstd::decode_all(data)
-                .map_err(|e| ShadowError::PlatformError {
-                    platform: crate::error::Platform::Linux,
-                    message: format!("Decompression failed: {}", e),
-                    code: None,
-                })
-        } else {
-            Ok(data.to_vec())
-        }
+    /// Returns the zstd level snapshots are written with, if compression is enabled.
+    fn compression_level(&self) -> Option<i32> {
+        self.config.enable_compression.then_some(self.config.compression_level)
     }
     
     /// Serializes data using bincode.
@@ -331,18 +497,15 @@ impl OverridePersistence for FileBasedPersistence {
     async fn save_snapshot(&self, store: &OverrideStore) -> Result<(), ShadowError> {
         let snapshot = OverrideSnapshot::from_store(store);
         
-        // Serialize snapshot
-        let serialized = self.serialize(&snapshot)?;
-        
-        // Compress if enabled
-        let compressed = self.compress_data(&serialized)?;
+        // Serialize into the versioned format, compressing if enabled
+        let encoded = snapshot.encode(self.compression_level())?;
         
         // Write to file atomically
         let temp_path = self.config.snapshot_path.with_extension("tmp");
         let mut file = File::create(&temp_path).await
             .map_err(|e| ShadowError::IoError { source: e })?;
         
-        file.write_all(&compressed).await
+        file.write_all(&encoded).await
             .map_err(|e| ShadowError::IoError { source: e })?;
         
         file.sync_all().await
@@ -359,33 +522,20 @@ impl OverridePersistence for FileBasedPersistence {
         let mut file = File::open(&self.config.snapshot_path).await
             .map_err(|e| ShadowError::IoError { source: e })?;
         
-        let mut compressed = Vec::new();
-        file.read_to_end(&mut compressed).await
+        let mut encoded = Vec::new();
+        file.read_to_end(&mut encoded).await
             .map_err(|e| ShadowError::IoError { source: e })?;
         
-        // Decompress if enabled
-        let serialized = self.decompress_data(&compressed)?;
-        
-        // Deserialize snapshot
-        let snapshot: OverrideSnapshot = self.deserialize(&serialized)?;
+        // Decode and verify snapshot
+        let snapshot = OverrideSnapshot::decode(&encoded)?;
         
         // Restore store from snapshot
         snapshot.restore_to_store()
     }
     
     async fn append_operation(&self, op: PersistenceOp) -> Result<(), ShadowError> {
-        // Serialize operation
-        let serialized = self.serialize(&op)?;
-        
-        // Create operation entry with length prefix
-        let op_len = serialized.len() as u32;
-        let mut entry = Vec::with_capacity(4 + serialized.len() + 8);
-        entry.extend_from_slice(&op_len.to_le_bytes());
-        entry.extend_from_slice(&serialized);
-        
-        // Add checksum
-        let checksum = crc32fast::hash(&serialized);
-        entry.extend_from_slice(&checksum.to_le_bytes());
+        // Frame the operation with a length prefix and checksum
+        let entry = encode_wal_record(&self.serialize(&op)?);
         
         // Append to WAL
         let mut file = OpenOptions::new()
@@ -414,74 +564,13 @@ impl OverridePersistence for FileBasedPersistence {
         file.read_to_end(&mut buffer).await
             .map_err(|e| ShadowError::IoError { source: e })?;
         
-        let mut offset = 0;
-        
-        while offset + 8 < buffer.len() {
-            // Read length prefix
-            let op_len = u32::from_le_bytes([
-                buffer[offset],
-                buffer[offset + 1],
-                buffer[offset + 2],
-                buffer[offset + 3],
-            ]) as usize;
-            offset += 4;
-            
-            if offset + op_len + 4 > buffer.len() {
-                // Incomplete entry, stop replay
-                break;
-            }
-            
-            // Read operation data
-            let op_data = &buffer[offset..offset + op_len];
-            offset += op_len;
-            
-            // Read and verify checksum
-            let stored_checksum = u32::from_le_bytes([
-                buffer[offset],
-                buffer[offset + 1],
-                buffer[offset + 2],
-                buffer[offset + 3],
-            ]);
-            offset += 4;
-            
-            let calculated_checksum = crc32fast::hash(op_data);
-            if stored_checksum != calculated_checksum {
-                return Err(ShadowError::PlatformError {
-                    platform: crate::error::Platform::Linux,
-                    message: "WAL corruption detected: checksum mismatch".to_string(),
-                    code: None,
-                });
-            }
-            
-            // Deserialize and apply operation
-            let op: PersistenceOp = self.deserialize(op_data)?;
-            
+        for op in decode_wal_records(&buffer)? {
             // Skip operations before the timestamp
             if op.timestamp() < from_timestamp {
                 continue;
             }
             
-            // Apply operation to store
-            match op {
-                PersistenceOp::Insert { path, content, metadata, .. } => {
-                    let _ = store.insert_entry(path, content, None, metadata);
-                }
-                PersistenceOp::Remove { path, .. } => {
-                    store.remove(&path);
-                }
-                PersistenceOp::Clear { .. } => {
-                    // Clear all entries
-                    let all_paths: Vec<_> = store.entries.iter()
-                        .map(|entry| entry.key().clone())
-                        .collect();
-                    for path in all_paths {
-                        store.remove(&path);
-                    }
-                }
-                PersistenceOp::Snapshot { .. } => {
-                    // Snapshot markers are informational only
-                }
-            }
+            apply_operation(store, op)?;
         }
         
         Ok(())
@@ -513,6 +602,400 @@ impl OverridePersistence for FileBasedPersistence {
     }
 }
 
+/// Append-only write-ahead log used by an [`OverrideStore`] in WAL mode.
+///
+/// Every mutation of the store is appended and synced before it becomes
+/// visible, so a session that crashes can be replayed on the next mount with
+/// [`OverrideStore::recover`].
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    /// Location of the log file
+    path: PathBuf,
+    
+    /// Open log file, serialized so records never interleave
+    file: Mutex<std::fs::File>,
+}
+
+impl WriteAheadLog {
+    /// Opens (or creates) a write-ahead log at `path`, appending to existing records.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ShadowError> {
+        let path = path.as_ref().to_path_buf();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| ShadowError::IoError { source: e })?;
+        
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+    
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Appends an operation and syncs it to disk.
+    pub fn append(&self, op: &PersistenceOp) -> Result<(), ShadowError> {
+        let serialized = bincode::serialize(op)
+            .map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Failed to serialize WAL operation: {}", e),
+            })?;
+        let record = encode_wal_record(&serialized);
+        
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&record)
+            .and_then(|_| file.sync_data())
+            .map_err(|e| ShadowError::IoError { source: e })
+    }
+    
+    /// Discards all records, typically right after a snapshot was taken.
+    pub fn truncate(&self) -> Result<(), ShadowError> {
+        let file = self.file.lock().unwrap();
+        file.set_len(0)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| ShadowError::IoError { source: e })
+    }
+    
+    /// Returns the current size of the log in bytes.
+    pub fn size(&self) -> Result<u64, ShadowError> {
+        let file = self.file.lock().unwrap();
+        file.metadata()
+            .map(|m| m.len())
+            .map_err(|e| ShadowError::IoError { source: e })
+    }
+    
+    /// Replays every record of the log at `path` into `store`.
+    ///
+    /// A torn record at the end of the log (from a crash mid-append) is ignored.
+    ///
+    /// # Returns
+    /// Number of operations applied; a missing log counts as empty
+    pub fn replay(path: impl AsRef<Path>, store: &OverrideStore) -> Result<usize, ShadowError> {
+        Self::replay_with_progress(path, store, &NoProgress)
+    }
+    
+    /// Replays the log at `path` into `store`, reporting one item per operation.
+    pub fn replay_with_progress(
+        path: impl AsRef<Path>,
+        store: &OverrideStore,
+        progress: &dyn Progress,
+    ) -> Result<usize, ShadowError> {
+        let ops = Self::read_operations(path)?;
+        progress.set_total(Some(ops.len() as u64), Some(ops_len(&ops)));
+        let count = apply_operations(store, ops, progress)?;
+        progress.finish();
+        Ok(count)
+    }
+    
+    /// Returns the last `limit` operations of the log at `path` that changed
+    /// `target`, oldest first.
+    pub fn history(path: impl AsRef<Path>, target: &ShadowPath, limit: usize) -> Result<Vec<LoggedOperation>, ShadowError> {
+        let mut history: Vec<LoggedOperation> = Self::read_operations(path)?
+            .iter()
+            .filter_map(|op| {
+                let description = match op {
+                    PersistenceOp::Insert { path, content, metadata, .. } if path == target => match content {
+                        OverrideContent::File { .. } => format!("wrote {} bytes", metadata.size),
+                        OverrideContent::Directory { .. } => "created directory".to_string(),
+                        OverrideContent::Deleted => "deleted".to_string(),
+                    },
+                    PersistenceOp::Remove { path, .. } if path == target => "override removed".to_string(),
+                    PersistenceOp::WriteRange { path, offset, data, .. } if path == target => {
+                        format!("wrote {} bytes at offset {}", data.len(), offset)
+                    }
+                    PersistenceOp::Truncate { path, len, .. } if path == target => format!("truncated to {} bytes", len),
+                    PersistenceOp::Append { path, data, .. } if path == target => format!("appended {} bytes", data.len()),
+                    PersistenceOp::Clear { .. } => "store cleared".to_string(),
+                    _ => return None,
+                };
+                let timestamp = UNIX_EPOCH + std::time::Duration::from_secs(op.timestamp());
+                Some(LoggedOperation { timestamp, description })
+            })
+            .collect();
+        history.drain(..history.len().saturating_sub(limit));
+        Ok(history)
+    }
+    
+    /// Reads all complete operations from the log at `path`.
+    fn read_operations(path: impl AsRef<Path>) -> Result<Vec<PersistenceOp>, ShadowError> {
+        match std::fs::read(path.as_ref()) {
+            Ok(buffer) => decode_wal_records(&buffer),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(ShadowError::IoError { source: e }),
+        }
+    }
+}
+
+/// Snapshot and WAL methods for OverrideStore.
+impl OverrideStore {
+    /// Saves all entries, tombstones and directory structure to `path`.
+    ///
+    /// The snapshot is written to a temporary file and renamed into place, so an
+    /// existing snapshot is never left half-written.
+    ///
+    /// # Arguments
+    /// * `path` - Destination snapshot file
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), ShadowError> {
+        let path = path.as_ref();
+        let compression_level = self.get_config().enable_compression.then_some(DEFAULT_COMPRESSION_LEVEL);
+        let encoded = OverrideSnapshot::from_store(self).encode(compression_level)?;
+        
+        let temp_path = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temp_path)
+            .map_err(|e| ShadowError::IoError { source: e })?;
+        file.write_all(&encoded)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| ShadowError::IoError { source: e })?;
+        
+        std::fs::rename(&temp_path, path)
+            .map_err(|e| ShadowError::IoError { source: e })
+    }
+    
+    /// Loads a store from a snapshot written by [`OverrideStore::save_snapshot`].
+    ///
+    /// # Arguments
+    /// * `path` - Snapshot file to load
+    ///
+    /// # Returns
+    /// A new store using the configuration and entries recorded in the snapshot
+    pub fn load_snapshot(path: impl AsRef<Path>) -> Result<Self, ShadowError> {
+        let data = std::fs::read(path.as_ref())
+            .map_err(|e| ShadowError::from_io_error(e, Some(&ShadowPath::new(path.as_ref().to_path_buf()))))?;
+        
+        OverrideSnapshot::decode(&data)?.restore_to_store()
+    }
+    
+    /// Enables WAL mode, logging every subsequent mutation to `path`.
+    ///
+    /// Replaces any previously attached log.
+    pub fn enable_wal(&self, path: impl AsRef<Path>) -> Result<(), ShadowError> {
+        let wal = WriteAheadLog::open(path)?;
+        *self.wal.write().unwrap() = Some(Arc::new(wal));
+        Ok(())
+    }
+    
+    /// Disables WAL mode, returning the detached log if one was attached.
+    pub fn disable_wal(&self) -> Option<Arc<WriteAheadLog>> {
+        self.wal.write().unwrap().take()
+    }
+    
+    /// Returns the path of the attached write-ahead log, if WAL mode is enabled.
+    pub fn wal_path(&self) -> Option<PathBuf> {
+        self.wal.read().unwrap().as_ref().map(|wal| wal.path().to_path_buf())
+    }
+    
+    /// Restores state left behind by a previous session and enables WAL mode.
+    ///
+    /// Loads the snapshot at `snapshot_path` if it exists, replays the log at
+    /// `wal_path` on top of it, then keeps logging to `wal_path`. The store's own
+    /// configuration is kept; only entries are taken from the snapshot.
+    ///
+    /// # Returns
+    /// Number of WAL operations replayed
+    pub fn recover(
+        &self,
+        snapshot_path: impl AsRef<Path>,
+        wal_path: impl AsRef<Path>,
+    ) -> Result<usize, ShadowError> {
+        self.recover_with_progress(snapshot_path, wal_path, &NoProgress)
+    }
+    
+    /// Like [`OverrideStore::recover`], reporting restored entries and replayed
+    /// operations as a single run of items.
+    ///
+    /// If cancelled, the snapshot and log on disk are left untouched and WAL
+    /// mode stays disabled, so recovery can simply be run again on a fresh store.
+    pub fn recover_with_progress(
+        &self,
+        snapshot_path: impl AsRef<Path>,
+        wal_path: impl AsRef<Path>,
+        progress: &dyn Progress,
+    ) -> Result<usize, ShadowError> {
+        // Replayed operations are already in the log, so don't log them twice
+        self.disable_wal();
+        
+        let snapshot = if snapshot_path.as_ref().exists() {
+            let data = std::fs::read(snapshot_path.as_ref())
+                .map_err(|e| ShadowError::IoError { source: e })?;
+            Some(OverrideSnapshot::decode(&data)?)
+        } else {
+            None
+        };
+        let ops = WriteAheadLog::read_operations(wal_path.as_ref())?;
+        
+        let (snapshot_items, snapshot_bytes) = snapshot.as_ref()
+            .map(|s| (s.entries.len() as u64, s.entries.values().map(|e| content_len(&e.content)).sum()))
+            .unwrap_or((0, 0));
+        progress.set_total(
+            Some(snapshot_items + ops.len() as u64),
+            Some(snapshot_bytes + ops_len(&ops)),
+        );
+        
+        if let Some(snapshot) = snapshot {
+            snapshot.apply_entries(self, progress)?;
+        }
+        let replayed = apply_operations(self, ops, progress)?;
+        
+        self.enable_wal(wal_path)?;
+        progress.finish();
+        Ok(replayed)
+    }
+    
+    /// Writes a snapshot to `snapshot_path` and truncates the attached WAL.
+    ///
+    /// Mutations are blocked while the checkpoint runs so that no operation is
+    /// dropped from both the snapshot and the log.
+    pub fn checkpoint(&self, snapshot_path: impl AsRef<Path>) -> Result<(), ShadowError> {
+        // Held for writing to block mutations, though only read
+        #[allow(clippy::readonly_write_lock)]
+        let wal = self.wal.write().unwrap();
+        self.save_snapshot(snapshot_path)?;
+        if let Some(wal) = wal.as_ref() {
+            wal.truncate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Frames a serialized operation as `len | payload | crc32`.
+fn encode_wal_record(serialized: &[u8]) -> Vec<u8> {
+    let op_len = serialized.len() as u32;
+    let mut entry = Vec::with_capacity(4 + serialized.len() + 4);
+    entry.extend_from_slice(&op_len.to_le_bytes());
+    entry.extend_from_slice(serialized);
+    entry.extend_from_slice(&crc32fast::hash(serialized).to_le_bytes());
+    entry
+}
+
+/// Decodes all complete records of a WAL buffer.
+///
+/// Stops at an incomplete trailing record and fails on a checksum mismatch.
+fn decode_wal_records(buffer: &[u8]) -> Result<Vec<PersistenceOp>, ShadowError> {
+    let mut ops = Vec::new();
+    let mut offset = 0;
+    
+    while offset + 4 <= buffer.len() {
+        // Read length prefix
+        let op_len = u32::from_le_bytes([
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ]) as usize;
+        offset += 4;
+        
+        if offset + op_len + 4 > buffer.len() {
+            // Incomplete entry, stop replay
+            break;
+        }
+        
+        // Read operation data
+        let op_data = &buffer[offset..offset + op_len];
+        offset += op_len;
+        
+        // Read and verify checksum
+        let stored_checksum = u32::from_le_bytes([
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ]);
+        offset += 4;
+        
+        if stored_checksum != crc32fast::hash(op_data) {
+            return Err(ShadowError::PlatformError {
+                platform: crate::error::Platform::Linux,
+                message: "WAL corruption detected: checksum mismatch".to_string(),
+                code: None,
+            });
+        }
+        
+        let op = bincode::deserialize(op_data)
+            .map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Failed to deserialize WAL operation: {}", e),
+            })?;
+        ops.push(op);
+    }
+    
+    Ok(ops)
+}
+
+/// Applies logged operations to `store` in order, returning how many were applied.
+fn apply_operations(
+    store: &OverrideStore,
+    ops: Vec<PersistenceOp>,
+    progress: &dyn Progress,
+) -> Result<usize, ShadowError> {
+    let count = ops.len();
+    for op in ops {
+        progress.check_cancelled("WAL replay")?;
+        let bytes = op_len(&op);
+        if let PersistenceOp::Insert { path, .. } | PersistenceOp::Remove { path, .. } = &op {
+            progress.set_current_path(path);
+        }
+        apply_operation(store, op)?;
+        progress.advance(1, bytes);
+    }
+    Ok(count)
+}
+
+/// Returns the number of content bytes carried by an override.
+fn content_len(content: &OverrideContent) -> u64 {
+    match content {
+        OverrideContent::File { data, .. } => data.len() as u64,
+        _ => 0,
+    }
+}
+
+/// Returns the number of content bytes carried by a logged operation.
+fn op_len(op: &PersistenceOp) -> u64 {
+    match op {
+        PersistenceOp::Insert { content, .. } => content_len(content),
+        PersistenceOp::WriteRange { data, .. } | PersistenceOp::Append { data, .. } => data.len() as u64,
+        _ => 0,
+    }
+}
+
+/// Returns the number of content bytes carried by a batch of logged operations.
+fn ops_len(ops: &[PersistenceOp]) -> u64 {
+    ops.iter().map(op_len).sum()
+}
+
+/// Applies a single logged operation to `store`.
+fn apply_operation(store: &OverrideStore, op: PersistenceOp) -> Result<(), ShadowError> {
+    match op {
+        PersistenceOp::Insert { path, content, metadata, .. } => {
+            store.insert_entry(path, content, None, metadata)?;
+        }
+        PersistenceOp::Remove { path, .. } => {
+            store.remove(&path);
+        }
+        PersistenceOp::Clear { .. } => {
+            // Clear all entries and deltas
+            for path in store.all_paths().into_iter().chain(store.delta_paths()) {
+                store.remove(&path);
+            }
+        }
+        PersistenceOp::Snapshot { .. } => {
+            // Snapshot markers are informational only
+        }
+        PersistenceOp::WriteRange { path, source, offset, data, .. } => {
+            store.apply_range(&path, source, offset, &data, None)?;
+        }
+        PersistenceOp::Truncate { path, source, len, .. } => {
+            store.apply_truncate(&path, source, len, None);
+        }
+        PersistenceOp::Append { path, data, .. } => {
+            store.apply_append(&path, &data)?;
+        }
+    }
+    
+    Ok(())
+}
+
 /// Helper function to get current Unix timestamp.
 fn current_timestamp() -> u64 {
     SystemTime::now()
@@ -747,4 +1230,157 @@ mod tests {
         // WAL should be much smaller after compaction (only snapshot marker)
         assert!(wal_info_after.unwrap() < wal_info_before.unwrap());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_store_snapshot_roundtrip_with_tombstones() {
+        let temp_dir = tempdir().unwrap();
+        let snapshot_path = temp_dir.path().join("store.snapshot");
+        
+        let store = OverrideStore::with_defaults();
+        store.create_directory_hierarchy(&ShadowPath::from("/project/src")).unwrap();
+        store.insert_file(ShadowPath::from("/project/src/main.rs"), Bytes::from("fn main() {}"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/project/old.txt")).unwrap();
+        
+        store.save_snapshot(&snapshot_path).unwrap();
+        let loaded = OverrideStore::load_snapshot(&snapshot_path).unwrap();
+        
+        assert_eq!(loaded.entry_count(), store.entry_count());
+        assert!(loaded.is_deleted(&ShadowPath::from("/project/old.txt")));
+        let names: Vec<String> = loaded.list_directory(&ShadowPath::from("/project/src")).unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, vec!["main.rs".to_string()]);
+    }
+    
+    #[test]
+    fn test_snapshot_header_is_versioned() {
+        let store = OverrideStore::with_defaults();
+        let mut encoded = OverrideSnapshot::from_store(&store).encode(None).unwrap();
+        assert_eq!(&encoded[..8], &SNAPSHOT_MAGIC);
+        assert!(OverrideSnapshot::decode(&encoded).is_ok());
+        
+        // Future format versions are rejected rather than misread
+        encoded[8..12].copy_from_slice(&(SNAPSHOT_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            OverrideSnapshot::decode(&encoded),
+            Err(ShadowError::InvalidConfiguration { .. })
+        ));
+        
+        assert!(OverrideSnapshot::decode(b"not a snapshot").is_err());
+    }
+    
+    #[test]
+    fn test_wal_mode_replays_after_crash() {
+        let temp_dir = tempdir().unwrap();
+        let snapshot_path = temp_dir.path().join("store.snapshot");
+        let wal_path = temp_dir.path().join("store.wal");
+        
+        {
+            let store = OverrideStore::with_defaults();
+            store.enable_wal(&wal_path).unwrap();
+            store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("a"), None).unwrap();
+            store.insert_file(ShadowPath::from("/b.txt"), Bytes::from("b"), None).unwrap();
+            store.remove(&ShadowPath::from("/a.txt"));
+            store.mark_deleted(ShadowPath::from("/c.txt")).unwrap();
+            // Dropped without a snapshot, as if the session crashed
+        }
+        
+        let recovered = OverrideStore::with_defaults();
+        let replayed = recovered.recover(&snapshot_path, &wal_path).unwrap();
+        assert_eq!(replayed, 4);
+        assert!(!recovered.exists(&ShadowPath::from("/a.txt")));
+        assert!(recovered.exists(&ShadowPath::from("/b.txt")));
+        assert!(recovered.is_deleted(&ShadowPath::from("/c.txt")));
+        assert_eq!(recovered.wal_path(), Some(wal_path.clone()));
+    }
+    
+    #[test]
+    fn test_checkpoint_truncates_wal() {
+        let temp_dir = tempdir().unwrap();
+        let snapshot_path = temp_dir.path().join("store.snapshot");
+        let wal_path = temp_dir.path().join("store.wal");
+        
+        let store = OverrideStore::with_defaults();
+        store.enable_wal(&wal_path).unwrap();
+        store.insert_file(ShadowPath::from("/before.txt"), Bytes::from("1"), None).unwrap();
+        store.checkpoint(&snapshot_path).unwrap();
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+        
+        store.insert_file(ShadowPath::from("/after.txt"), Bytes::from("2"), None).unwrap();
+        drop(store);
+        
+        let recovered = OverrideStore::with_defaults();
+        assert_eq!(recovered.recover(&snapshot_path, &wal_path).unwrap(), 1);
+        assert!(recovered.exists(&ShadowPath::from("/before.txt")));
+        assert!(recovered.exists(&ShadowPath::from("/after.txt")));
+    }
+    
+    #[test]
+    fn test_wal_replay_ignores_torn_tail() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("store.wal");
+        
+        let wal = WriteAheadLog::open(&wal_path).unwrap();
+        wal.append(&PersistenceOp::remove(ShadowPath::from("/x"))).unwrap();
+        drop(wal);
+        
+        // Simulate a crash halfway through writing the next record
+        let mut file = std::fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        assert_eq!(WriteAheadLog::replay(&wal_path, &store).unwrap(), 1);
+    }
+    
+    #[test]
+    fn test_recover_reports_progress() {
+        use crate::progress::ProgressTracker;
+        
+        let temp_dir = tempdir().unwrap();
+        let snapshot_path = temp_dir.path().join("store.snapshot");
+        let wal_path = temp_dir.path().join("store.wal");
+        
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/one.txt"), Bytes::from("12345"), None).unwrap();
+        store.save_snapshot(&snapshot_path).unwrap();
+        store.enable_wal(&wal_path).unwrap();
+        store.insert_file(ShadowPath::from("/two.txt"), Bytes::from("123"), None).unwrap();
+        drop(store);
+        
+        let tracker = ProgressTracker::new("recover");
+        let recovered = OverrideStore::with_defaults();
+        recovered.recover_with_progress(&snapshot_path, &wal_path, &tracker).unwrap();
+        
+        let update = tracker.snapshot();
+        assert_eq!(update.items_total, Some(2));
+        assert_eq!(update.items_done, 2);
+        assert_eq!(update.bytes_done, 8);
+        assert!(update.finished);
+    }
+    
+    #[test]
+    fn test_cancelled_recover_can_be_rerun() {
+        use crate::progress::ProgressTracker;
+        
+        let temp_dir = tempdir().unwrap();
+        let snapshot_path = temp_dir.path().join("store.snapshot");
+        let wal_path = temp_dir.path().join("store.wal");
+        
+        let store = OverrideStore::with_defaults();
+        store.enable_wal(&wal_path).unwrap();
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("a"), None).unwrap();
+        drop(store);
+        
+        let tracker = ProgressTracker::new("recover");
+        tracker.cancel_handle().unwrap().cancel();
+        let recovered = OverrideStore::with_defaults();
+        let result = recovered.recover_with_progress(&snapshot_path, &wal_path, &tracker);
+        assert!(matches!(result, Err(ShadowError::Cancelled { .. })));
+        assert!(recovered.wal_path().is_none());
+        
+        let recovered = OverrideStore::with_defaults();
+        assert_eq!(recovered.recover(&snapshot_path, &wal_path).unwrap(), 1);
+        assert!(recovered.exists(&ShadowPath::from("/a.txt")));
+    }
+}
@@ -0,0 +1,414 @@
+//! Transformers rewriting source files as they are read through a mount.
+//!
+//! Unlike rules, which store their content as an override on first access,
+//! read transforms leave the store alone: a source file matching a
+//! registered transformer is read, passed through the matching transformers
+//! in the order they were registered, and served as the result, every time
+//! it is read. The source keeps its content, so editing it shows through the
+//! mount after the next read.
+//!
+//! Transformed output is cached per path and keyed by a hash of the source
+//! content. A source whose size and modification time are unchanged is
+//! served from the cache without being read; one that was touched but not
+//! changed is read and hashed, but not transformed again.
+//!
+//! A transformer is any [`ContentTransformer`]: a [`TransformChain`] through
+//! [`ChainTransformer`], such as the sed-like [`transforms::sed`], or an
+//! embedder's own type. A WebAssembly filter plugs in as a transformer that
+//! calls into its module; shadowfs does not bundle a WebAssembly runtime.
+//!
+//! Files with an override or written ranges are served from the store
+//! untransformed. Writing to a transformed file through the FUSE provider
+//! stores the transformed content as its override before the write.
+
+use super::patterns::{TransformChain, TransformError};
+use super::rename::source_path;
+use super::rules::OverrideRule;
+use super::OverrideStore;
+use crate::error::ShadowError;
+use crate::types::ShadowPath;
+use bytes::Bytes;
+use lru::LruCache;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+/// Transformed files a [`ReadTransforms`] keeps by default.
+pub const DEFAULT_TRANSFORM_CACHE_ENTRIES: usize = 1024;
+
+/// Rewrites the content of source files as they are read.
+pub trait ContentTransformer: Send + Sync {
+    /// Name shown when listing transformers and in errors.
+    fn name(&self) -> &str;
+    
+    /// Returns the content to serve for `path` in place of `content`.
+    fn transform(&self, path: &ShadowPath, content: &[u8]) -> Result<Bytes, TransformError>;
+}
+
+/// A [`TransformChain`] used as a [`ContentTransformer`].
+#[derive(Debug, Clone)]
+pub struct ChainTransformer {
+    name: String,
+    chain: TransformChain,
+}
+
+impl ChainTransformer {
+    /// Creates a transformer applying `chain`.
+    pub fn new(name: impl Into<String>, chain: TransformChain) -> Self {
+        Self { name: name.into(), chain }
+    }
+}
+
+impl ContentTransformer for ChainTransformer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn transform(&self, _path: &ShadowPath, content: &[u8]) -> Result<Bytes, TransformError> {
+        self.chain.apply(content)
+    }
+}
+
+/// Identifier of a transformer within its [`ReadTransforms`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TransformerId(pub u64);
+
+impl fmt::Display for TransformerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Description of a registered transformer, for listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransformerInfo {
+    pub id: TransformerId,
+    
+    /// Paths the transformer applies to, such as `glob:*.conf`
+    pub rule: String,
+    
+    pub name: String,
+}
+
+/// Counters of a [`ReadTransforms`] cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TransformCacheStats {
+    /// Reads served from the cache
+    pub hits: u64,
+    
+    /// Reads that ran the transformers
+    pub misses: u64,
+    
+    /// Transformed files currently cached
+    pub entries: usize,
+}
+
+struct Registered {
+    id: TransformerId,
+    rule: OverrideRule,
+    transformer: Arc<dyn ContentTransformer>,
+}
+
+/// Transformed content of one path and the source it came from.
+struct CachedOutput {
+    modified: Option<SystemTime>,
+    source_len: u64,
+    source_hash: u64,
+    output: Bytes,
+}
+
+/// Transformers applied to source files read through a store's mounts.
+///
+/// # Examples
+///
+/// ```rust
+/// use shadowfs_core::override_store::{
+///     transforms, ChainTransformer, OverrideRule, OverrideStore, ReadTransforms, TransformChain,
+/// };
+/// use std::sync::Arc;
+///
+/// let redact = transforms::sed("s/password=.*/password=***/").unwrap();
+/// let transformer = ChainTransformer::new("redact passwords", TransformChain::new().add_transform(redact));
+///
+/// let read_transforms = Arc::new(ReadTransforms::new());
+/// read_transforms.register(OverrideRule::Glob("*.conf".to_string()), Arc::new(transformer));
+///
+/// let store = OverrideStore::with_defaults();
+/// store.set_read_transforms(read_transforms);
+/// ```
+pub struct ReadTransforms {
+    /// Transformers in the order they were registered
+    transformers: RwLock<Vec<Registered>>,
+    
+    /// Identifier of the next transformer registered
+    next_id: AtomicU64,
+    
+    cache: Mutex<LruCache<ShadowPath, CachedOutput>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ReadTransforms {
+    /// Creates an empty registry caching [`DEFAULT_TRANSFORM_CACHE_ENTRIES`] files.
+    pub fn new() -> Self {
+        Self::with_cache_capacity(DEFAULT_TRANSFORM_CACHE_ENTRIES)
+    }
+    
+    /// Creates an empty registry caching up to `entries` transformed files.
+    pub fn with_cache_capacity(entries: usize) -> Self {
+        Self {
+            transformers: RwLock::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(entries).unwrap_or(NonZeroUsize::MIN))),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+    
+    /// Applies `transformer` to the source files matching `rule`, after the
+    /// transformers registered before it.
+    pub fn register(&self, rule: OverrideRule, transformer: Arc<dyn ContentTransformer>) -> TransformerId {
+        let id = TransformerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.transformers.write().unwrap().push(Registered { id, rule, transformer });
+        self.cache.lock().unwrap().clear();
+        id
+    }
+    
+    /// Removes the transformer with `id`.
+    ///
+    /// # Returns
+    /// false if no such transformer exists
+    pub fn unregister(&self, id: TransformerId) -> bool {
+        let mut transformers = self.transformers.write().unwrap();
+        let before = transformers.len();
+        transformers.retain(|registered| registered.id != id);
+        let removed = transformers.len() != before;
+        if removed {
+            self.cache.lock().unwrap().clear();
+        }
+        removed
+    }
+    
+    /// Lists the transformers in the order they apply.
+    pub fn list(&self) -> Vec<TransformerInfo> {
+        self.transformers.read().unwrap().iter()
+            .map(|registered| TransformerInfo {
+                id: registered.id,
+                rule: registered.rule.to_string(),
+                name: registered.transformer.name().to_string(),
+            })
+            .collect()
+    }
+    
+    /// Returns true if any transformer applies to `path`.
+    pub fn matches(&self, path: &ShadowPath) -> bool {
+        self.transformers.read().unwrap().iter().any(|registered| registered.rule.matches(path))
+    }
+    
+    /// Returns the counters of the cache.
+    pub fn cache_stats(&self) -> TransformCacheStats {
+        TransformCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.cache.lock().unwrap().len(),
+        }
+    }
+    
+    /// Forgets all transformed output.
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+    
+    /// Returns the transformed content of the source file `source` seen at
+    /// `path`.
+    ///
+    /// # Returns
+    /// `None` if no transformer applies to the path or `source` is not a file
+    pub fn apply(&self, path: &ShadowPath, source: &Path) -> Result<Option<Bytes>, ShadowError> {
+        let chain: Vec<Arc<dyn ContentTransformer>> = self.transformers.read().unwrap().iter()
+            .filter(|registered| registered.rule.matches(path))
+            .map(|registered| Arc::clone(&registered.transformer))
+            .collect();
+        if chain.is_empty() {
+            return Ok(None);
+        }
+        let metadata = match std::fs::metadata(source) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return Ok(None),
+        };
+        let modified = metadata.modified().ok();
+        
+        if let Some(cached) = self.cache.lock().unwrap().get(path) {
+            if cached.modified.is_some() && cached.modified == modified && cached.source_len == metadata.len() {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(Some(cached.output.clone()));
+            }
+        }
+        
+        let content = std::fs::read(source).map_err(|e| ShadowError::IoError { source: e })?;
+        let source_hash = content_hash(&content);
+        if let Some(cached) = self.cache.lock().unwrap().get_mut(path) {
+            if cached.source_hash == source_hash && cached.source_len == content.len() as u64 {
+                cached.modified = modified;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(Some(cached.output.clone()));
+            }
+        }
+        
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let source_len = content.len() as u64;
+        let mut output = Bytes::from(content);
+        for transformer in &chain {
+            output = transformer.transform(path, &output).map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Transformer {} failed for {}: {}", transformer.name(), path, e),
+            })?;
+        }
+        self.cache.lock().unwrap().put(path.clone(), CachedOutput {
+            modified,
+            source_len,
+            source_hash,
+            output: output.clone(),
+        });
+        Ok(Some(output))
+    }
+}
+
+impl Default for ReadTransforms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ReadTransforms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadTransforms")
+            .field("transformers", &self.transformers.read().unwrap().len())
+            .field("cache", &self.cache_stats())
+            .finish()
+    }
+}
+
+/// Hashes source content for the cache; paired with the path and length,
+/// a 64-bit hash is enough to tell versions of one file apart.
+fn content_hash(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl OverrideStore {
+    /// Applies `transforms` to source files read through the store's mounts.
+    pub fn set_read_transforms(&self, transforms: Arc<ReadTransforms>) {
+        *self.read_transforms.write().unwrap() = Some(transforms);
+    }
+    
+    /// Stops transforming source files.
+    pub fn clear_read_transforms(&self) {
+        *self.read_transforms.write().unwrap() = None;
+    }
+    
+    /// Returns the attached read transforms, if any.
+    pub fn read_transforms(&self) -> Option<Arc<ReadTransforms>> {
+        self.read_transforms.read().unwrap().clone()
+    }
+    
+    /// Returns the content to serve for the source file of `path`, if a
+    /// read transform applies to it.
+    ///
+    /// Providers call this for files they would otherwise serve from the
+    /// source tree at `source_root`.
+    ///
+    /// # Returns
+    /// `None` if no transforms are attached or none applies, the path has an
+    /// override or written ranges, or it is not a file in the source tree
+    pub fn transform_source(&self, path: &ShadowPath, source_root: &Path) -> Result<Option<Bytes>, ShadowError> {
+        let Some(transforms) = self.read_transforms() else {
+            return Ok(None);
+        };
+        if !transforms.matches(path) || self.get(path).is_some() || self.has_delta(path) {
+            return Ok(None);
+        }
+        transforms.apply(path, &source_path(source_root, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::transforms;
+    use std::sync::atomic::AtomicUsize;
+    
+    /// Transformer counting how often it runs.
+    struct Counting(AtomicUsize);
+    
+    impl ContentTransformer for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+        
+        fn transform(&self, _path: &ShadowPath, content: &[u8]) -> Result<Bytes, TransformError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(Bytes::from(content.to_ascii_uppercase()))
+        }
+    }
+    
+    #[test]
+    fn test_transforms_cached_by_source_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.conf");
+        std::fs::write(&file, "mode=debug\n").unwrap();
+        let path = ShadowPath::from("/app.conf");
+        
+        let counting = Arc::new(Counting(AtomicUsize::new(0)));
+        let read_transforms = ReadTransforms::new();
+        read_transforms.register(OverrideRule::Glob("*.conf".to_string()), counting.clone());
+        let sed = transforms::sed("s/debug/release/").unwrap();
+        read_transforms.register(OverrideRule::Glob("*.conf".to_string()), Arc::new(ChainTransformer::new("sed", TransformChain::new().add_transform(sed))));
+        
+        assert_eq!(read_transforms.apply(&path, &file).unwrap().unwrap(), Bytes::from("MODE=DEBUG\n"));
+        assert_eq!(read_transforms.apply(&path, &file).unwrap().unwrap(), Bytes::from("MODE=DEBUG\n"));
+        assert_eq!(counting.0.load(Ordering::Relaxed), 1);
+        assert!(read_transforms.apply(&ShadowPath::from("/app.toml"), &file).unwrap().is_none());
+        
+        // Rewritten unchanged, the source is hashed but not transformed again
+        std::fs::write(&file, "mode=debug\n").unwrap();
+        read_transforms.apply(&path, &file).unwrap();
+        assert_eq!(counting.0.load(Ordering::Relaxed), 1);
+        
+        std::fs::write(&file, "mode=verbose\n").unwrap();
+        assert_eq!(read_transforms.apply(&path, &file).unwrap().unwrap(), Bytes::from("MODE=VERBOSE\n"));
+        assert_eq!(counting.0.load(Ordering::Relaxed), 2);
+        assert_eq!(read_transforms.cache_stats(), TransformCacheStats { hits: 2, misses: 2, entries: 1 });
+    }
+    
+    #[test]
+    fn test_store_serves_overrides_untransformed() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("secrets.env"), "TOKEN=abc123\n").unwrap();
+        std::fs::write(dir.path().join("local.env"), "TOKEN=local\n").unwrap();
+        
+        let read_transforms = Arc::new(ReadTransforms::new());
+        let redact = transforms::sed("s/=.*/=***/").unwrap();
+        let id = read_transforms.register(
+            OverrideRule::Glob("*.env".to_string()),
+            Arc::new(ChainTransformer::new("redact", TransformChain::new().add_transform(redact))),
+        );
+        let store = OverrideStore::with_defaults();
+        store.set_read_transforms(Arc::clone(&read_transforms));
+        
+        let secrets = ShadowPath::from("/secrets.env");
+        let local = ShadowPath::from("/local.env");
+        assert_eq!(store.transform_source(&secrets, dir.path()).unwrap().unwrap(), Bytes::from("TOKEN=***\n"));
+        store.insert_file(local.clone(), Bytes::from("TOKEN=override\n"), None).unwrap();
+        assert!(store.transform_source(&local, dir.path()).unwrap().is_none());
+        
+        assert_eq!(read_transforms.list()[0].rule, "glob:*.env");
+        assert!(read_transforms.unregister(id));
+        assert!(store.transform_source(&secrets, dir.path()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,565 @@
+//! Renaming files and directory trees.
+//!
+//! [`OverrideStore::rename`] moves a path and everything visible below it,
+//! whether the entries are overrides or only exist in the source tree, so
+//! platform layers forward their rename calls instead of walking trees
+//! themselves. Overrides move as they are, keeping their timestamps,
+//! permissions and hard links. Paths that only exist in the source are
+//! copied into the store under their new name with the source metadata,
+//! and the old name is hidden behind a tombstone.
+
+use crate::access::AccessOperation;
+use crate::error::{
+    already_exists, directory_not_empty, invalid_path, is_a_directory, not_a_directory, not_found, ShadowError,
+};
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore, PathTraversal};
+use crate::quota::QuotaUsage;
+use crate::types::{current_time, FileMetadata, FilePermissions, FileType, PlatformMetadata, ShadowPath};
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+pub(crate) use crate::mount_paths::source_path;
+
+/// What a rename does when the new path already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RenameCollision {
+    /// Replace it, as `rename(2)` does: a file may replace a file and a
+    /// directory may replace an empty directory
+    #[default]
+    Overwrite,
+    /// Fail with [`ShadowError::AlreadyExists`], like `RENAME_NOREPLACE`
+    NoReplace,
+    /// Swap the two paths, like `RENAME_EXCHANGE`; both must exist
+    Exchange,
+}
+
+/// Options for [`OverrideStore::rename`].
+#[derive(Debug, Clone, Default)]
+pub struct RenameOptions {
+    /// What happens when the new path already exists
+    pub collision: RenameCollision,
+    
+    /// Source directory the store shadows. Paths that only exist there are
+    /// copied into the store when they move; `None` moves overrides only.
+    pub source_root: Option<PathBuf>,
+}
+
+impl RenameOptions {
+    /// Returns options for a store shadowing `source_root`.
+    pub fn with_source(source_root: impl Into<PathBuf>) -> Self {
+        Self {
+            collision: RenameCollision::default(),
+            source_root: Some(source_root.into()),
+        }
+    }
+}
+
+/// A visible path about to move, with what it holds.
+struct MovedEntry {
+    path: ShadowPath,
+    content: OverrideContent,
+    original_metadata: Option<FileMetadata>,
+    override_metadata: FileMetadata,
+}
+
+impl MovedEntry {
+    fn is_directory(&self) -> bool {
+        matches!(self.content, OverrideContent::Directory { .. })
+    }
+}
+
+impl OverrideStore {
+    /// Renames `old` to `new`, moving everything below it if it is a directory.
+    ///
+    /// With a source root in `options`, files and directories that only
+    /// exist in the source move too: they are copied into the store, which
+    /// for a large source tree means reading all of it. Source symbolic
+    /// links become file overrides holding the link target. Whatever the
+    /// source still has at `old` is hidden by a tombstone, and so is
+    /// whatever it has below a moved directory at `new`, so the new tree
+    /// shows exactly what the old one did.
+    ///
+    /// Renaming a path to itself, or to another name of the same hard-linked
+    /// file, does nothing.
+    ///
+    /// # Arguments
+    /// * `old` - Path to move
+    /// * `new` - Path to move it to
+    /// * `options` - Collision behaviour and the source tree
+    ///
+    /// # Errors
+    /// Fails with [`ShadowError::NotFound`] if `old` is not visible, or for
+    /// an exchange if `new` is not; with [`ShadowError::InvalidPath`] if one
+    /// path is below the other; and with the errors of the collision mode
+    /// when `new` exists. Read-only stores, access rules and path limits on
+    /// any of the moved paths, and a quota the copied source tree would
+    /// exceed, reject the rename before anything changes.
+    #[tracing::instrument(level = "trace", skip_all, fields(from = %old, to = %new))]
+    pub fn rename(&self, old: &ShadowPath, new: ShadowPath, options: RenameOptions) -> Result<(), ShadowError> {
+        self.check_writable(old, "rename")?;
+        self.check_writable(&new, "rename")?;
+        let source = options.source_root.as_deref();
+        let exchange = options.collision == RenameCollision::Exchange;
+        
+        // A case-insensitive store moves what it holds under the stored
+        // spelling, and a rename changing only the case of a path recases it
+        let held = self.held_spelling(old);
+        let old = held.as_ref().unwrap_or(old);
+        let (old_key, new_key) = match self.is_case_sensitive() {
+            true => (old.clone(), new.clone()),
+            false => (old.to_case_folded(), new.to_case_folded()),
+        };
+        let recase = old_key == new_key && *old != new;
+        
+        if PathTraversal::is_parent_of(&old_key, &new_key) || (exchange && PathTraversal::is_parent_of(&new_key, &old_key)) {
+            return Err(invalid_path(new.to_string(), format!("cannot move {} below itself", old)));
+        }
+        
+        // Written ranges move as the files they make up
+        for path in self.delta_paths() {
+            if in_tree(old, &path) || in_tree(&new, &path) {
+                self.compact_delta(&path)?;
+            }
+        }
+        
+        let moved = self.visible_tree(old, source)?.ok_or_else(|| not_found(old.clone()))?;
+        let same_file = self.links.id(old).is_some_and(|id| self.links.id(&new) == Some(id));
+        if *old == new || (same_file && !exchange) || (recase && exchange) {
+            return Ok(());
+        }
+        
+        let replaced = match recase {
+            true => None,
+            false => self.visible_tree(&new, source)?,
+        };
+        match (&replaced, options.collision) {
+            (Some(_), RenameCollision::NoReplace) => return Err(already_exists(new)),
+            (None, RenameCollision::Exchange) => return Err(not_found(new)),
+            (Some(target), RenameCollision::Overwrite) => match (moved[0].is_directory(), target[0].is_directory()) {
+                (false, true) => return Err(is_a_directory(new)),
+                (true, false) => return Err(not_a_directory(new)),
+                (true, true) if target.len() > 1 => return Err(directory_not_empty(new)),
+                _ => self.check_access(&new, AccessOperation::Delete)?,
+            },
+            _ => {}
+        }
+        let returned = if exchange { replaced.unwrap_or_default() } else { Vec::new() };
+        
+        // Check every path before changing any of them. The moved paths are
+        // given up first, so whatever lands on one of them counts instead
+        let mut usage = Vec::new();
+        for (entries, from, to) in [(&moved, old, &new), (&returned, &new, old)] {
+            for entry in entries {
+                self.check_access(&entry.path, AccessOperation::Delete)?;
+                self.check_access(&rebase(&entry.path, from, to), AccessOperation::Write)?;
+                usage.push((entry.path.clone(), QuotaUsage::default()));
+            }
+        }
+        for (entries, from, to) in [(&moved, old, &new), (&returned, &new, old)] {
+            for entry in entries {
+                let path = rebase(&entry.path, from, to);
+                // Recasing keeps every name where it was
+                if !recase {
+                    self.check_insert(&path, &entry.content)?;
+                }
+                usage.push((path, QuotaUsage::of_content(&entry.content, entry.override_metadata.size)));
+            }
+        }
+        self.check_quota(usage.iter().map(|(path, usage)| (path, *usage)))?;
+        
+        // Hard links are carried over to the new names once the trees have moved
+        let paths: Vec<ShadowPath> = moved.iter().chain(&returned).map(|entry| entry.path.clone()).collect();
+        let links: Vec<_> = self.links.detach(&paths)
+            .into_iter()
+            .map(|(path, id)| match in_tree(old, &path) {
+                true => (rebase(&path, old, &new), id),
+                false => (rebase(&path, &new, old), id),
+            })
+            .collect();
+        
+        let result = if recase {
+            // Both spellings name the same file, so the old one needs no tombstone
+            for path in self.all_paths() {
+                if in_tree(old, &path) {
+                    self.remove(&path);
+                }
+            }
+            self.place_tree(moved, old, &new, source)
+        } else {
+            let replaced_at = self.held_spelling(&new).unwrap_or_else(|| new.clone());
+            self.clear_tree(old, source)
+                .and_then(|()| self.clear_tree(&replaced_at, source))
+                .and_then(|()| self.place_tree(moved, old, &new, source))
+                .and_then(|()| self.place_tree(returned, &new, old, source))
+        };
+        self.links.attach(links.into_iter().filter(|(path, _)| self.entries.contains_key(path)).collect());
+        result
+    }
+    
+    /// Collects `root` and everything visible below it, parents first, or
+    /// `None` if `root` is not visible.
+    fn visible_tree(&self, root: &ShadowPath, source: Option<&Path>) -> Result<Option<Vec<MovedEntry>>, ShadowError> {
+        let Some(top) = self.visible_entry(root, source)? else {
+            return Ok(None);
+        };
+        
+        let mut tree = Vec::new();
+        let mut pending = vec![top];
+        while let Some(entry) = pending.pop() {
+            if entry.is_directory() {
+                let mut names: HashSet<String> = self.get_directory_children(&entry.path).into_iter().collect();
+                if let Some(children) = source.and_then(|root| std::fs::read_dir(source_path(root, &entry.path)).ok()) {
+                    names.extend(children.flatten().map(|child| child.file_name().to_string_lossy().into_owned()));
+                }
+                for name in names {
+                    if let Some(child) = self.visible_entry(&entry.path.join(&name), source)? {
+                        pending.push(child);
+                    }
+                }
+            }
+            tree.push(entry);
+        }
+        Ok(Some(tree))
+    }
+    
+    /// Returns what `path` holds, from its override or else from the source.
+    fn visible_entry(&self, path: &ShadowPath, source: Option<&Path>) -> Result<Option<MovedEntry>, ShadowError> {
+        if let Some(entry) = self.get(path) {
+            if entry.is_deleted() {
+                return Ok(None);
+            }
+            return Ok(Some(MovedEntry {
+                path: path.clone(),
+                content: self.compacted_entry(&entry)?.content,
+                original_metadata: entry.original_metadata.clone(),
+                override_metadata: entry.override_metadata.clone(),
+            }));
+        }
+        
+        let Some(root) = source else {
+            return Ok(None);
+        };
+        let host_path = source_path(root, path);
+        let Ok(metadata) = std::fs::symlink_metadata(&host_path) else {
+            return Ok(None);
+        };
+        let io_error = |e| ShadowError::from_io_error_with_operation(e, path, "rename");
+        
+        let (content, size) = if metadata.is_dir() {
+            (OverrideContent::Directory { entries: Vec::new() }, 0)
+        } else {
+            let data = if metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(&host_path).map_err(io_error)?;
+                Bytes::from(target.to_string_lossy().into_owned())
+            } else {
+                Bytes::from(std::fs::read(&host_path).map_err(io_error)?)
+            };
+            let size = data.len() as u64;
+            (self.file_content(data), size)
+        };
+        let metadata = source_metadata(&metadata, size);
+        
+        Ok(Some(MovedEntry {
+            path: path.clone(),
+            content,
+            original_metadata: Some(metadata.clone()),
+            override_metadata: metadata,
+        }))
+    }
+    
+    /// Removes every override at and below `path`, leaving a tombstone at
+    /// `path` if the source has it.
+    ///
+    /// Without a source tree, a path counts as being in the source if its
+    /// override recorded original metadata or is already a tombstone.
+    fn clear_tree(&self, path: &ShadowPath, source: Option<&Path>) -> Result<(), ShadowError> {
+        let shadowed = match source {
+            Some(root) => std::fs::symlink_metadata(source_path(root, path)).is_ok(),
+            None => self.get(path).is_some_and(|entry| entry.is_deleted() || entry.original_metadata.is_some()),
+        };
+        
+        for child in self.all_paths() {
+            if PathTraversal::is_parent_of(path, &child) {
+                self.remove(&child);
+            }
+        }
+        if shadowed {
+            self.store_entry(tombstone(path.clone()), true)
+        } else {
+            self.remove(path);
+            Ok(())
+        }
+    }
+    
+    /// Stores `entries` under `to` in place of `from`, then hides whatever
+    /// else the source has below the directories among them.
+    fn place_tree(
+        &self,
+        entries: Vec<MovedEntry>,
+        from: &ShadowPath,
+        to: &ShadowPath,
+        source: Option<&Path>,
+    ) -> Result<(), ShadowError> {
+        let placed: HashSet<ShadowPath> = entries.iter().map(|entry| rebase(&entry.path, from, to)).collect();
+        let mut directories = Vec::new();
+        
+        for entry in entries {
+            let path = rebase(&entry.path, from, to);
+            if entry.is_directory() {
+                directories.push(path.clone());
+            }
+            let entry = OverrideEntry::new(path, entry.content, entry.original_metadata, entry.override_metadata);
+            self.store_entry(entry, true)?;
+        }
+        
+        let Some(root) = source else {
+            return Ok(());
+        };
+        for directory in directories {
+            let Ok(children) = std::fs::read_dir(source_path(root, &directory)) else {
+                continue;
+            };
+            for child in children.flatten() {
+                let path = directory.join(child.file_name());
+                if !placed.contains(&path) {
+                    self.store_entry(tombstone(path), true)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if `path` is `root` or below it.
+fn in_tree(root: &ShadowPath, path: &ShadowPath) -> bool {
+    path == root || PathTraversal::is_parent_of(root, path)
+}
+
+/// Maps `path`, which is `from` or below it, to the same place under `to`.
+fn rebase(path: &ShadowPath, from: &ShadowPath, to: &ShadowPath) -> ShadowPath {
+    match path.as_path().strip_prefix(from.as_path()) {
+        Ok(rest) if !rest.as_os_str().is_empty() => to.join(rest),
+        _ => to.clone(),
+    }
+}
+
+/// Builds a tombstone for `path`, like [`OverrideStore::mark_deleted`].
+fn tombstone(path: ShadowPath) -> OverrideEntry {
+    let now = current_time();
+    let metadata = FileMetadata::new(
+        0,
+        now,
+        now,
+        now,
+        FilePermissions::default_file(),
+        FileType::File,
+        PlatformMetadata::default(),
+    );
+    OverrideEntry::new(path, OverrideContent::Deleted, None, metadata)
+}
+
+/// Converts the metadata of a source path holding `size` bytes.
+pub(crate) fn source_metadata(metadata: &std::fs::Metadata, size: u64) -> FileMetadata {
+    let file_type = if metadata.is_dir() {
+        FileType::Directory
+    } else if metadata.file_type().is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::File
+    };
+    
+    #[cfg(unix)]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt;
+        FilePermissions::from_unix_mode(metadata.permissions().mode())
+    };
+    #[cfg(not(unix))]
+    let permissions = {
+        let mut permissions = match file_type {
+            FileType::Directory => FilePermissions::default_directory(),
+            _ => FilePermissions::default_file(),
+        };
+        if metadata.permissions().readonly() {
+            permissions.readonly = true;
+            permissions.owner_write = false;
+            permissions.group_write = false;
+            permissions.other_write = false;
+        }
+        permissions
+    };
+    
+    // Attributes such as hidden and system decide what some mounts show
+    #[cfg(windows)]
+    let platform_specific = {
+        use std::os::windows::fs::MetadataExt;
+        PlatformMetadata::Windows { attributes: metadata.file_attributes(), reparse_tag: None, streams: Vec::new() }
+    };
+    #[cfg(not(windows))]
+    let platform_specific = PlatformMetadata::default();
+    
+    let now = current_time();
+    FileMetadata::new(
+        size,
+        metadata.created().unwrap_or(now),
+        metadata.modified().unwrap_or(now),
+        metadata.accessed().unwrap_or(now),
+        permissions,
+        file_type,
+        platform_specific,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    fn content(store: &OverrideStore, path: &str) -> Option<Bytes> {
+        store.get(&ShadowPath::from(path)).and_then(|entry| entry.get_file_data().unwrap())
+    }
+    
+    #[test]
+    fn test_rename_override_keeps_metadata() {
+        let store = OverrideStore::with_defaults();
+        let old = ShadowPath::from("/notes.txt");
+        store.insert_file(old.clone(), Bytes::from_static(b"draft"), None).unwrap();
+        let metadata = store.get(&old).unwrap().override_metadata.clone();
+        
+        store.rename(&old, ShadowPath::from("/final.txt"), RenameOptions::default()).unwrap();
+        
+        // Without original metadata the old name was never in the source
+        assert!(store.get(&old).is_none());
+        let moved = store.get(&ShadowPath::from("/final.txt")).unwrap();
+        assert_eq!(moved.override_metadata, metadata);
+        assert_eq!(content(&store, "/final.txt"), Some(Bytes::from_static(b"draft")));
+    }
+    
+    #[test]
+    fn test_rename_source_directory() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir_all(source.path().join("a/sub")).unwrap();
+        std::fs::write(source.path().join("a/edited.txt"), "source").unwrap();
+        std::fs::write(source.path().join("a/gone.txt"), "gone").unwrap();
+        std::fs::write(source.path().join("a/sub/kept.txt"), "kept").unwrap();
+        std::fs::create_dir(source.path().join("b")).unwrap();
+        std::fs::write(source.path().join("b/hidden.txt"), "hidden").unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/a/edited.txt"), Bytes::from_static(b"override"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/a/gone.txt")).unwrap();
+        store.mark_deleted(ShadowPath::from("/b")).unwrap();
+        
+        let options = RenameOptions::with_source(source.path());
+        store.rename(&ShadowPath::from("/a"), ShadowPath::from("/b"), options).unwrap();
+        
+        assert!(store.is_deleted(&ShadowPath::from("/a")));
+        assert!(store.get(&ShadowPath::from("/a/edited.txt")).is_none());
+        assert!(store.get(&ShadowPath::from("/b")).unwrap().is_directory());
+        assert_eq!(content(&store, "/b/edited.txt"), Some(Bytes::from_static(b"override")));
+        assert_eq!(content(&store, "/b/sub/kept.txt"), Some(Bytes::from_static(b"kept")));
+        assert!(store.get(&ShadowPath::from("/b/gone.txt")).is_none());
+        
+        // What the source had below the deleted target stays hidden
+        assert!(store.is_deleted(&ShadowPath::from("/b/hidden.txt")));
+        
+        let kept = store.get(&ShadowPath::from("/b/sub/kept.txt")).unwrap();
+        let source_modified = std::fs::metadata(source.path().join("a/sub/kept.txt")).unwrap().modified().unwrap();
+        assert_eq!(kept.override_metadata.modified, source_modified);
+        assert_eq!(kept.override_metadata.size, 4);
+    }
+    
+    #[test]
+    fn test_rename_collisions() {
+        let store = OverrideStore::with_defaults();
+        let file = ShadowPath::from("/file");
+        let other = ShadowPath::from("/other");
+        let dir = ShadowPath::from("/dir");
+        let full = ShadowPath::from("/full");
+        store.insert_file(file.clone(), Bytes::from_static(b"one"), None).unwrap();
+        store.insert_file(other.clone(), Bytes::from_static(b"two"), None).unwrap();
+        store.insert_directory(dir.clone(), None).unwrap();
+        store.insert_directory(full.clone(), None).unwrap();
+        store.insert_file(ShadowPath::from("/full/x"), Bytes::from_static(b"x"), None).unwrap();
+        
+        let no_replace = RenameOptions { collision: RenameCollision::NoReplace, ..Default::default() };
+        assert!(matches!(store.rename(&file, other.clone(), no_replace), Err(ShadowError::AlreadyExists { .. })));
+        assert!(matches!(store.rename(&file, dir.clone(), RenameOptions::default()), Err(ShadowError::IsADirectory { .. })));
+        assert!(matches!(store.rename(&dir, file.clone(), RenameOptions::default()), Err(ShadowError::NotADirectory { .. })));
+        assert!(matches!(store.rename(&dir, full.clone(), RenameOptions::default()), Err(ShadowError::DirectoryNotEmpty { .. })));
+        assert!(matches!(store.rename(&dir, dir.join("inner"), RenameOptions::default()), Err(ShadowError::InvalidPath { .. })));
+        assert!(matches!(store.rename(&ShadowPath::from("/missing"), file.clone(), RenameOptions::default()), Err(ShadowError::NotFound { .. })));
+        assert_eq!(content(&store, "/other"), Some(Bytes::from_static(b"two")));
+        
+        store.rename(&file, other.clone(), RenameOptions::default()).unwrap();
+        assert_eq!(content(&store, "/other"), Some(Bytes::from_static(b"one")));
+        assert!(store.get(&file).is_none());
+        
+        store.rename(&full, dir.clone(), RenameOptions::default()).unwrap();
+        assert_eq!(content(&store, "/dir/x"), Some(Bytes::from_static(b"x")));
+    }
+    
+    #[test]
+    fn test_rename_exchange() {
+        let store = OverrideStore::with_defaults();
+        let file = ShadowPath::from("/file");
+        let dir = ShadowPath::from("/dir");
+        store.insert_file(file.clone(), Bytes::from_static(b"content"), None).unwrap();
+        store.insert_directory(dir.clone(), None).unwrap();
+        store.insert_file(ShadowPath::from("/dir/child"), Bytes::from_static(b"child"), None).unwrap();
+        
+        let exchange = || RenameOptions { collision: RenameCollision::Exchange, ..Default::default() };
+        store.rename(&file, dir.clone(), exchange()).unwrap();
+        
+        assert_eq!(content(&store, "/dir"), Some(Bytes::from_static(b"content")));
+        assert!(store.get(&file).unwrap().is_directory());
+        assert_eq!(content(&store, "/file/child"), Some(Bytes::from_static(b"child")));
+        assert!(store.get(&ShadowPath::from("/dir/child")).is_none());
+        
+        let missing = store.rename(&file, ShadowPath::from("/missing"), exchange());
+        assert!(matches!(missing, Err(ShadowError::NotFound { .. })));
+    }
+    
+    #[test]
+    fn test_rename_keeps_hard_links() {
+        let store = OverrideStore::with_defaults();
+        let a = ShadowPath::from("/a");
+        let b = ShadowPath::from("/b");
+        let c = ShadowPath::from("/c");
+        store.insert_file(a.clone(), Bytes::from_static(b"shared"), None).unwrap();
+        let id = store.link(&a, b.clone()).unwrap();
+        
+        // Renaming onto another name of the same file changes nothing
+        store.rename(&a, b.clone(), RenameOptions::default()).unwrap();
+        assert_eq!(store.hard_links(&a), vec![a.clone(), b.clone()]);
+        
+        store.rename(&a, c.clone(), RenameOptions::default()).unwrap();
+        assert_eq!(store.link_id(&c), Some(id));
+        assert_eq!(store.hard_links(&b), vec![b.clone(), c.clone()]);
+        
+        store.insert_file(c, Bytes::from_static(b"updated"), None).unwrap();
+        assert_eq!(content(&store, "/b"), Some(Bytes::from_static(b"updated")));
+    }
+    
+    #[test]
+    fn test_rename_case_insensitive() {
+        let store = OverrideStore::with_defaults();
+        store.set_case_sensitive(false);
+        store.insert_directory(ShadowPath::from("/src"), None).unwrap();
+        store.insert_file(ShadowPath::from("/src/Main.rs"), Bytes::from_static(b"fn main() {}"), None).unwrap();
+        
+        // Changing only the case recases the held tree
+        store.rename(&ShadowPath::from("/SRC"), ShadowPath::from("/Src"), RenameOptions::default()).unwrap();
+        assert!(store.entries.contains_key(&ShadowPath::from("/Src/Main.rs")));
+        assert!(!store.entries.contains_key(&ShadowPath::from("/src")));
+        assert_eq!(content(&store, "/src/main.rs"), Some(Bytes::from_static(b"fn main() {}")));
+        
+        store.insert_file(ShadowPath::from("/other.rs"), Bytes::from_static(b"other"), None).unwrap();
+        let no_replace = RenameOptions { collision: RenameCollision::NoReplace, ..Default::default() };
+        let result = store.rename(&ShadowPath::from("/other.rs"), ShadowPath::from("/SRC/MAIN.RS"), no_replace);
+        assert!(matches!(result, Err(ShadowError::AlreadyExists { .. })));
+        assert!(store.rename(&ShadowPath::from("/Src"), ShadowPath::from("/src/lib"), RenameOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Path matching rules shared by overrides, access policies and watches.
+//!
+//! Exact, prefix, suffix and glob rules are always available; regex rules
+//! need the `patterns` feature.
+
+use crate::types::ShadowPath;
+#[cfg(feature = "patterns")]
+use regex::Regex;
+
+/// Pattern matching rules for file overrides
+#[derive(Debug, Clone)]
+pub enum OverrideRule {
+    /// Exact path match
+    Exact(ShadowPath),
+    /// Path prefix match
+    Prefix(ShadowPath),
+    /// Path suffix match
+    Suffix(String),
+    /// Regular expression match on path
+    #[cfg(feature = "patterns")]
+    Regex(Regex),
+    /// Glob pattern match
+    Glob(String),
+}
+
+impl OverrideRule {
+    /// Tests if a path matches this rule
+    pub fn matches(&self, path: &ShadowPath) -> bool {
+        let path_str = path.to_string();
+        
+        match self {
+            OverrideRule::Exact(pattern) => path == pattern,
+            OverrideRule::Prefix(prefix) => path_str.starts_with(&prefix.to_string()),
+            OverrideRule::Suffix(suffix) => path_str.ends_with(suffix),
+            #[cfg(feature = "patterns")]
+            OverrideRule::Regex(regex) => regex.is_match(&path_str),
+            OverrideRule::Glob(pattern) => {
+                // Simple glob matching (*, ?, [])
+                glob_match(pattern, &path_str)
+            }
+        }
+    }
+    
+    /// Creates a regex rule from a pattern string
+    #[cfg(feature = "patterns")]
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(OverrideRule::Regex(Regex::new(pattern)?))
+    }
+}
+
+impl std::fmt::Display for OverrideRule {
+    /// Formats the rule as its kind and pattern, such as `glob:*.env`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverrideRule::Exact(path) => write!(f, "exact:{}", path),
+            OverrideRule::Prefix(prefix) => write!(f, "prefix:{}", prefix),
+            OverrideRule::Suffix(suffix) => write!(f, "suffix:{}", suffix),
+            #[cfg(feature = "patterns")]
+            OverrideRule::Regex(regex) => write!(f, "regex:{}", regex.as_str()),
+            OverrideRule::Glob(pattern) => write!(f, "glob:{}", pattern),
+        }
+    }
+}
+
+/// Simple glob pattern matching
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_match_recursive(pattern, text, 0, 0)
+}
+
+fn glob_match_recursive(pattern: &str, text: &str, p_idx: usize, t_idx: usize) -> bool {
+    let pattern_chars: Vec<char> = pattern.chars().collect();
+    let text_chars: Vec<char> = text.chars().collect();
+    
+    if p_idx == pattern_chars.len() {
+        return t_idx == text_chars.len();
+    }
+    
+    if t_idx == text_chars.len() {
+        return pattern_chars[p_idx..].iter().all(|&c| c == '*');
+    }
+    
+    match pattern_chars[p_idx] {
+        '*' => {
+            // Try matching zero or more characters
+            glob_match_recursive(pattern, text, p_idx + 1, t_idx) ||
+            glob_match_recursive(pattern, text, p_idx, t_idx + 1)
+        }
+        '?' => {
+            // Match exactly one character
+            glob_match_recursive(pattern, text, p_idx + 1, t_idx + 1)
+        }
+        c => {
+            // Match literal character
+            if text_chars[t_idx] == c {
+                glob_match_recursive(pattern, text, p_idx + 1, t_idx + 1)
+            } else {
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_override_rule_matching() {
+        let path = ShadowPath::new("/home/user/test.txt".into());
+        
+        let exact_rule = OverrideRule::Exact(path.clone());
+        assert!(exact_rule.matches(&path));
+        
+        let prefix_rule = OverrideRule::Prefix(ShadowPath::new("/home".into()));
+        assert!(prefix_rule.matches(&path));
+        
+        let suffix_rule = OverrideRule::Suffix(".txt".to_string());
+        assert!(suffix_rule.matches(&path));
+        
+        let glob_rule = OverrideRule::Glob("*.txt".to_string());
+        assert!(glob_rule.matches(&path));
+    }
+    
+    #[test]
+    fn test_glob_matching() {
+        assert!(glob_match("*.txt", "file.txt"));
+        assert!(glob_match("test.*", "test.rs"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("*.txt", "file.rs"));
+        assert!(glob_match("**", "anything/goes/here"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Dry runs of eviction.
+//!
+//! [`OverrideStore::simulate_eviction`] works out which resident overrides
+//! each [`EvictionPolicy`] would evict to free a number of bytes, without
+//! evicting anything or counting as an access. Every policy is run against
+//! the same copy of the entries and their access history, so the results
+//! are comparable even while the store keeps serving.
+
+use super::lru::EvictionPolicy;
+use super::optimization::ShardedMap;
+use super::size::calculate_entry_size;
+use super::OverrideStore;
+use crate::types::ShadowPath;
+
+/// An override a policy would evict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimulatedVictim {
+    /// Path of the override
+    pub path: ShadowPath,
+    
+    /// Memory evicting it would free
+    pub bytes: usize,
+}
+
+/// What one policy would evict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolicySimulation {
+    /// Policy simulated
+    pub policy: EvictionPolicy,
+    
+    /// Overrides evicted, in eviction order
+    pub victims: Vec<SimulatedVictim>,
+    
+    /// Memory the victims hold
+    pub freed_bytes: usize,
+}
+
+/// Outcome of [`OverrideStore::simulate_eviction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EvictionSimulation {
+    /// Memory asked to be freed
+    pub target_bytes: usize,
+    
+    /// Memory the resident overrides hold
+    pub resident_bytes: usize,
+    
+    /// Policy the store evicts with
+    pub configured_policy: EvictionPolicy,
+    
+    /// Whether the store spills cold overrides to disk instead of evicting
+    /// them, in which case the LRU result is what would be spilled
+    pub spills: bool,
+    
+    /// One result per policy, in [`EvictionPolicy::ALL`] order
+    pub policies: Vec<PolicySimulation>,
+}
+
+impl EvictionSimulation {
+    /// Returns the result of `policy`.
+    pub fn policy(&self, policy: EvictionPolicy) -> Option<&PolicySimulation> {
+        self.policies.iter().find(|simulation| simulation.policy == policy)
+    }
+}
+
+impl PolicySimulation {
+    /// Returns true if the victims free at least `target_bytes`.
+    pub fn reaches(&self, target_bytes: usize) -> bool {
+        self.freed_bytes >= target_bytes
+    }
+}
+
+impl OverrideStore {
+    /// Works out which overrides each eviction policy would evict to free
+    /// `target_bytes`, without changing the store.
+    ///
+    /// A policy that runs out of resident overrides before reaching the
+    /// target lists them all.
+    pub fn simulate_eviction(&self, target_bytes: usize) -> EvictionSimulation {
+        let entries = ShardedMap::new();
+        for (path, entry) in self.entries.iter() {
+            entries.insert(path, entry);
+        }
+        let tracker = self.lru_tracker.snapshot();
+        let config = self.config.read().unwrap();
+        
+        let policies = EvictionPolicy::ALL.into_iter()
+            .map(|policy| {
+                let victims: Vec<SimulatedVictim> = tracker.select_victims(policy, &entries, target_bytes)
+                    .into_iter()
+                    .filter_map(|path| {
+                        let bytes = calculate_entry_size(&*entries.get(&path)?);
+                        Some(SimulatedVictim { path, bytes })
+                    })
+                    .collect();
+                PolicySimulation {
+                    policy,
+                    freed_bytes: victims.iter().map(|victim| victim.bytes).sum(),
+                    victims,
+                }
+            })
+            .collect();
+        
+        EvictionSimulation {
+            target_bytes,
+            resident_bytes: entries.iter().map(|(_, entry)| calculate_entry_size(&entry)).sum(),
+            configured_policy: config.eviction_policy,
+            spills: config.spill_dir.is_some(),
+            policies,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::OverrideStoreBuilder;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_simulation_leaves_store_unchanged() {
+        let store = OverrideStoreBuilder::new().with_compression(false).build().unwrap();
+        store.insert_file(ShadowPath::from("/small.txt"), Bytes::from(vec![b'a'; 10]), None).unwrap();
+        store.insert_file(ShadowPath::from("/large.txt"), Bytes::from(vec![b'b'; 10_000]), None).unwrap();
+        store.get(&ShadowPath::from("/small.txt"));
+        let before = store.lru_tracker.get_least_recently_used(2);
+        
+        let simulation = store.simulate_eviction(1);
+        assert_eq!(simulation.policies.len(), EvictionPolicy::ALL.len());
+        let lru = simulation.policy(EvictionPolicy::Lru).unwrap();
+        assert_eq!(lru.victims[0].path, ShadowPath::from("/large.txt"));
+        assert!(lru.reaches(1));
+        let largest = simulation.policy(EvictionPolicy::SizeWeighted).unwrap();
+        assert_eq!(largest.victims.len(), 1);
+        assert!(largest.freed_bytes >= 10_000);
+        
+        let everything = store.simulate_eviction(usize::MAX);
+        assert!(everything.policies.iter().all(|policy| policy.victims.len() == 2 && !policy.reaches(usize::MAX)));
+        assert_eq!(everything.policy(EvictionPolicy::Fifo).unwrap().freed_bytes, everything.resident_bytes);
+        
+        assert!(store.exists(&ShadowPath::from("/large.txt")));
+        assert_eq!(store.lru_tracker.get_least_recently_used(2), before);
+    }
+}
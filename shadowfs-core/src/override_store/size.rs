@@ -21,7 +21,8 @@ pub fn calculate_entry_size(entry: &OverrideEntry) -> usize {
     // Add content size
     size += match &entry.content {
         OverrideContent::File { data, content_hash, .. } => {
-            calculate_bytes_size(data) + std::mem::size_of_val(content_hash)
+            let appended = entry.appended.as_ref().map_or(0, |tail| tail.len() as usize);
+            calculate_bytes_size(data) + std::mem::size_of_val(content_hash) + appended
         }
         OverrideContent::Directory { entries } => {
             // Vector overhead
@@ -85,7 +86,9 @@ mod tests {
                 platform_specific: PlatformMetadata::Linux { inode: 0, nlink: 1 },
             },
             created_at: SystemTime::now(),
+            created: 0,
             last_accessed: AtomicU64::new(0),
+            appended: None,
         };
         
         let size = calculate_entry_size(&entry);
@@ -118,7 +121,9 @@ mod tests {
                 platform_specific: PlatformMetadata::Linux { inode: 0, nlink: 3 },
             },
             created_at: SystemTime::now(),
+            created: 0,
             last_accessed: AtomicU64::new(0),
+            appended: None,
         };
         
         let size = calculate_entry_size(&entry);
@@ -0,0 +1,219 @@
+//! Named in-memory snapshots of the override store.
+//!
+//! A named snapshot records the set of entries at a point in time. Entries are
+//! immutable once stored and shared through `Arc`, so taking a snapshot only
+//! copies pointers and restoring one only touches paths that differ from the
+//! current state. Restoring a snapshot and then making changes branches off
+//! it; other snapshots are unaffected.
+
+use crate::error::ShadowError;
+use crate::override_store::{OverrideEntry, OverrideStore};
+use crate::types::{current_time, ShadowPath};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Entries captured by a named snapshot.
+#[derive(Debug, Clone)]
+pub(crate) struct NamedSnapshot {
+    /// When the snapshot was taken
+    created_at: SystemTime,
+    
+    /// Entries shared with the store at the time of the snapshot
+    entries: Arc<HashMap<ShadowPath, Arc<OverrideEntry>>>,
+}
+
+/// Summary of a named snapshot returned by [`OverrideStore::list_snapshots`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    /// Name the snapshot was created with
+    pub name: String,
+    
+    /// When the snapshot was taken
+    pub created_at: SystemTime,
+    
+    /// Number of overrides in the snapshot, including tombstones
+    pub entry_count: usize,
+}
+
+/// Outcome of restoring a named snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotRestoreSummary {
+    /// Entries inserted or replaced to match the snapshot
+    pub replaced: usize,
+    
+    /// Entries removed because the snapshot does not contain them
+    pub removed: usize,
+    
+    /// Entries already identical to the snapshot
+    pub unchanged: usize,
+}
+
+impl OverrideStore {
+    /// Records the current overrides under `name`.
+    ///
+    /// The snapshot shares entries with the store, so it costs one pointer per
+    /// override. Memory held only by snapshots is not counted against the
+    /// store's memory limit.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the new snapshot
+    ///
+    /// # Returns
+    /// An error if a snapshot with the same name already exists
+    pub fn create_snapshot(&self, name: &str) -> Result<SnapshotInfo, ShadowError> {
+        let mut snapshots = self.snapshots.write().unwrap();
+        if snapshots.contains_key(name) {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!("snapshot '{}' already exists", name),
+            });
+        }
+        
+        let entries: HashMap<ShadowPath, Arc<OverrideEntry>> = self.all_entries()?
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        let snapshot = NamedSnapshot {
+            created_at: current_time(),
+            entries: Arc::new(entries),
+        };
+        let info = snapshot_info(name, &snapshot);
+        snapshots.insert(name.to_string(), snapshot);
+        Ok(info)
+    }
+    
+    /// Replaces the current overrides with those recorded in snapshot `name`.
+    ///
+    /// Only paths that differ from the snapshot are touched, and each change
+    /// goes through the WAL when one is enabled. The snapshot itself is kept,
+    /// so it can be restored again later.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the snapshot to restore
+    pub fn restore_snapshot(&self, name: &str) -> Result<SnapshotRestoreSummary, ShadowError> {
+        self.check_writable(&ShadowPath::from("/"), "restore snapshot")?;
+        let snapshot = self.snapshots.read().unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| unknown_snapshot(name))?;
+        
+        let mut summary = SnapshotRestoreSummary::default();
+        for path in self.all_paths() {
+            if !snapshot.entries.contains_key(&path) && self.remove(&path).is_some() {
+                summary.removed += 1;
+            }
+        }
+        
+        for (path, entry) in snapshot.entries.iter() {
+            let unchanged = self.entries.get(path).is_some_and(|current| Arc::ptr_eq(&current, entry));
+            if unchanged {
+                summary.unchanged += 1;
+            } else {
+                // The snapshot's content may differ from the paths linked to this one
+                self.links.unlink(path);
+                self.store_shared_entry(Arc::clone(entry), true)?;
+                summary.replaced += 1;
+            }
+        }
+        
+        Ok(summary)
+    }
+    
+    /// Lists named snapshots sorted by name.
+    pub fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.snapshots.read().unwrap()
+            .iter()
+            .map(|(name, snapshot)| snapshot_info(name, snapshot))
+            .collect()
+    }
+    
+    /// Deletes snapshot `name`, releasing entries that only it referenced.
+    pub fn delete_snapshot(&self, name: &str) -> Result<(), ShadowError> {
+        self.snapshots.write().unwrap()
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| unknown_snapshot(name))
+    }
+}
+
+fn snapshot_info(name: &str, snapshot: &NamedSnapshot) -> SnapshotInfo {
+    SnapshotInfo {
+        name: name.to_string(),
+        created_at: snapshot.created_at,
+        entry_count: snapshot.entries.len(),
+    }
+}
+
+fn unknown_snapshot(name: &str) -> ShadowError {
+    ShadowError::InvalidConfiguration {
+        message: format!("no snapshot named '{}'", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    fn read(store: &OverrideStore, path: &str) -> Option<Bytes> {
+        store.get(&ShadowPath::from(path)).and_then(|e| e.get_file_data().unwrap())
+    }
+    
+    #[test]
+    fn test_snapshot_branching() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/config.toml"), Bytes::from("base"), None).unwrap();
+        store.create_snapshot("base").unwrap();
+        
+        store.insert_file(ShadowPath::from("/config.toml"), Bytes::from("experiment a"), None).unwrap();
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("a"), None).unwrap();
+        store.create_snapshot("a").unwrap();
+        
+        let summary = store.restore_snapshot("base").unwrap();
+        assert_eq!(summary, SnapshotRestoreSummary { replaced: 1, removed: 1, unchanged: 0 });
+        assert_eq!(read(&store, "/config.toml"), Some(Bytes::from("base")));
+        assert!(!store.exists(&ShadowPath::from("/a.txt")));
+        
+        store.mark_deleted(ShadowPath::from("/config.toml")).unwrap();
+        store.create_snapshot("b").unwrap();
+        
+        store.restore_snapshot("a").unwrap();
+        assert_eq!(read(&store, "/config.toml"), Some(Bytes::from("experiment a")));
+        assert_eq!(read(&store, "/a.txt"), Some(Bytes::from("a")));
+        
+        store.restore_snapshot("b").unwrap();
+        assert!(store.is_deleted(&ShadowPath::from("/config.toml")));
+    }
+    
+    #[test]
+    fn test_snapshot_shares_entries() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/big.bin"), Bytes::from(vec![7u8; 4096]), None).unwrap();
+        store.create_snapshot("one").unwrap();
+        
+        let current = store.entries.get(&ShadowPath::from("/big.bin")).unwrap();
+        let snapshots = store.snapshots.read().unwrap();
+        assert!(Arc::ptr_eq(&current, &snapshots["one"].entries[&ShadowPath::from("/big.bin")]));
+        drop(snapshots);
+        
+        let summary = store.restore_snapshot("one").unwrap();
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.replaced, 0);
+    }
+    
+    #[test]
+    fn test_snapshot_names() {
+        let store = OverrideStore::with_defaults();
+        store.create_snapshot("b").unwrap();
+        store.create_snapshot("a").unwrap();
+        assert!(store.create_snapshot("a").is_err());
+        
+        let names: Vec<String> = store.list_snapshots().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        
+        store.delete_snapshot("a").unwrap();
+        assert!(store.delete_snapshot("a").is_err());
+        assert!(store.restore_snapshot("a").is_err());
+        assert_eq!(store.list_snapshots().len(), 1);
+    }
+}
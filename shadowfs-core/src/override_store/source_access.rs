@@ -0,0 +1,178 @@
+//! Detection of a source tree that stopped accepting writes.
+//!
+//! Commits write into the source tree. When the source is remounted
+//! read-only or its permissions change during a session, a commit would
+//! otherwise stop part-way with a bare permission error on whichever path
+//! it reached first. The store probes the source before committing and
+//! again whenever a commit write is refused, and when the source refuses
+//! writes it fails with [`ShadowError::SourceNotWritable`] naming the cause
+//! and the fix. The denial is remembered, so
+//! [`health_check`](OverrideStore::health_check) reports commits as
+//! unavailable until a later probe succeeds.
+
+use crate::error::{self, ShadowError};
+use crate::override_store::OverrideStore;
+use crate::types::ShadowPath;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the file created to probe whether the source accepts writes.
+const PROBE_PREFIX: &str = ".shadowfs-write-probe-";
+
+/// OS error code for a write to a read-only filesystem.
+#[cfg(unix)]
+const READ_ONLY_FILESYSTEM: i32 = 30;
+#[cfg(windows)]
+const READ_ONLY_FILESYSTEM: i32 = 19;
+#[cfg(not(any(unix, windows)))]
+const READ_ONLY_FILESYSTEM: i32 = -1;
+
+/// Why a source tree refuses writes, and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceWriteDenial {
+    /// Root of the source tree
+    pub source_root: PathBuf,
+    
+    /// What the source refused the probe with
+    pub reason: String,
+    
+    /// What to do so commits work again
+    pub remediation: String,
+}
+
+impl SourceWriteDenial {
+    /// Classifies the error a write under `source_root` failed with.
+    fn from_io_error(source_root: &Path, error: &std::io::Error) -> Self {
+        let root = source_root.display();
+        let (reason, remediation) = if error.raw_os_error() == Some(READ_ONLY_FILESYSTEM) {
+            (
+                "it is on a read-only filesystem".to_string(),
+                format!("remount the filesystem holding {} read-write, or commit to a writable copy", root),
+            )
+        } else {
+            match error.kind() {
+                ErrorKind::PermissionDenied => (
+                    "the current user may not write to it".to_string(),
+                    format!("grant write access, for example with `chmod -R u+w {}`, or commit as its owner", root),
+                ),
+                ErrorKind::NotFound => (
+                    "the directory no longer exists".to_string(),
+                    format!("restore {} or mount the source from its new location", root),
+                ),
+                _ => (error.to_string(), format!("check that {} is a writable directory", root)),
+            }
+        };
+        Self {
+            source_root: source_root.to_path_buf(),
+            reason,
+            remediation,
+        }
+    }
+    
+    /// Returns the error commits fail with while the source refuses writes.
+    pub fn to_error(&self) -> ShadowError {
+        error::source_not_writable(ShadowPath::new(self.source_root.clone()), format!("{}; {}", self.reason, self.remediation))
+    }
+}
+
+impl fmt::Display for SourceWriteDenial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not writable: {}; {}", self.source_root.display(), self.reason, self.remediation)
+    }
+}
+
+/// Checks that files can be created under `source_root` by creating and
+/// removing a probe file there.
+pub fn probe_source_writable(source_root: &Path) -> Result<(), SourceWriteDenial> {
+    let probe = source_root.join(format!("{}{}", PROBE_PREFIX, std::process::id()));
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(file) => drop(file),
+        // Left behind by an earlier probe of this process
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(SourceWriteDenial::from_io_error(source_root, &e)),
+    }
+    std::fs::remove_file(&probe).map_err(|e| SourceWriteDenial::from_io_error(source_root, &e))
+}
+
+impl OverrideStore {
+    /// Probes whether commits to `source_root` can write, remembering the
+    /// outcome for [`health_check`](OverrideStore::health_check).
+    ///
+    /// # Errors
+    /// [`ShadowError::SourceNotWritable`] with the cause and its fix if the
+    /// source refuses writes.
+    pub fn check_source_writable(&self, source_root: &Path) -> Result<(), ShadowError> {
+        let denial = probe_source_writable(source_root).err();
+        let error = denial.as_ref().map(SourceWriteDenial::to_error);
+        *self.source_write_denial.write().unwrap() = denial;
+        error.map_or(Ok(()), Err)
+    }
+    
+    /// Returns why the source refused writes when last probed, if it did.
+    pub fn source_write_denial(&self) -> Option<SourceWriteDenial> {
+        self.source_write_denial.read().unwrap().clone()
+    }
+    
+    /// Returns false if the source refused writes when last probed.
+    pub fn can_commit(&self) -> bool {
+        self.source_write_denial.read().unwrap().is_none()
+    }
+    
+    /// Replaces a permission or I/O error from writing under `source_root`
+    /// with [`ShadowError::SourceNotWritable`] if the whole source now
+    /// refuses writes, rather than the one path.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn explain_source_write_error(&self, source_root: &Path, error: ShadowError) -> ShadowError {
+        if !matches!(error, ShadowError::PermissionDenied { .. } | ShadowError::IoError { .. }) {
+            return error;
+        }
+        match self.check_source_writable(source_root) {
+            Err(denied) => denied,
+            Ok(()) => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_probe_writable_source() {
+        let source = tempfile::tempdir().unwrap();
+        let store = OverrideStore::with_defaults();
+        store.check_source_writable(source.path()).unwrap();
+        assert!(store.can_commit());
+        assert_eq!(std::fs::read_dir(source.path()).unwrap().count(), 0);
+    }
+    
+    #[test]
+    fn test_missing_source_is_reported() {
+        let source = tempfile::tempdir().unwrap();
+        let missing = source.path().join("gone");
+        let store = OverrideStore::with_defaults();
+        
+        let err = store.check_source_writable(&missing).unwrap_err();
+        assert!(matches!(&err, ShadowError::SourceNotWritable { reason, .. } if reason.contains("no longer exists")));
+        assert!(!store.can_commit());
+        let health = store.health_check();
+        assert!(health.issues().iter().any(|message| message.starts_with("Commit unavailable")));
+        
+        std::fs::create_dir(&missing).unwrap();
+        store.check_source_writable(&missing).unwrap();
+        assert!(store.source_write_denial().is_none());
+    }
+    
+    #[test]
+    fn test_classify_denials() {
+        let root = Path::new("/src/repo");
+        let read_only = std::io::Error::from_raw_os_error(READ_ONLY_FILESYSTEM);
+        assert_eq!(SourceWriteDenial::from_io_error(root, &read_only).reason, "it is on a read-only filesystem");
+        
+        let denied = SourceWriteDenial::from_io_error(root, &std::io::Error::from(ErrorKind::PermissionDenied));
+        assert!(denied.remediation.contains("chmod -R u+w /src/repo"));
+        assert!(matches!(denied.to_error(), ShadowError::SourceNotWritable { path, .. } if path == ShadowPath::from("/src/repo")));
+    }
+}
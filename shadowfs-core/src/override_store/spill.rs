@@ -0,0 +1,501 @@
+//! Disk-backed overflow tier for cold overrides.
+//!
+//! When [`OverrideStoreConfig::spill_dir`](super::OverrideStoreConfig::spill_dir)
+//! is set, memory pressure above the spill threshold moves the least recently
+//! used file overrides to disk instead of dropping them. Each spilled entry is
+//! serialized, compressed with zstd and sealed with ChaCha20-Poly1305 under a
+//! key generated for the store, so spill files cannot be read by anyone else
+//! and are useless once the store is gone. Spilled entries remain part of the
+//! store: lookups load them back transparently, and whole-store operations
+//! such as commit, diff and snapshots see them too.
+//!
+//! Directories and tombstones are never spilled. They are small and are
+//! needed to answer existence checks without touching the disk.
+//!
+//! The tier needs the `persistence` feature. Without it, setting a spill
+//! directory makes the first spill fail with an unsupported error instead.
+
+use super::size::calculate_entry_size;
+use super::{OverrideEntry, OverrideStore};
+use crate::error::ShadowError;
+use crate::types::ShadowPath;
+#[cfg(feature = "persistence")]
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+#[cfg(feature = "persistence")]
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+#[cfg(feature = "persistence")]
+use dashmap::DashMap;
+use std::path::Path;
+#[cfg(feature = "persistence")]
+use std::path::PathBuf;
+#[cfg(feature = "persistence")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Default memory pressure ratio above which cold entries are spilled.
+pub const DEFAULT_SPILL_THRESHOLD: f64 = 0.75;
+
+/// zstd level used for spill files; spilling is on the write path, so keep it cheap.
+#[cfg(feature = "persistence")]
+const SPILL_COMPRESSION_LEVEL: i32 = 3;
+
+/// Number of LRU candidates considered per spill round.
+const SPILL_CANDIDATES: usize = 64;
+
+/// Location of one spilled entry.
+#[cfg(feature = "persistence")]
+#[derive(Debug, Clone, Copy)]
+struct SpillRecord {
+    /// File id, also used as the encryption nonce
+    id: u64,
+    
+    /// Size of the spill file in bytes
+    size: u64,
+}
+
+/// Encrypted, compressed files holding spilled entries.
+#[cfg(feature = "persistence")]
+pub(crate) struct SpillStore {
+    /// Directory private to this store, removed on drop
+    dir: PathBuf,
+    
+    /// Cipher keyed for this store only
+    cipher: ChaCha20Poly1305,
+    
+    /// Spilled entries by path
+    records: DashMap<ShadowPath, SpillRecord>,
+    
+    /// Next file id; ids are never reused so nonces are unique per key
+    next_id: AtomicU64,
+    
+    /// Serializes loads so an entry is brought back into memory only once
+    load_lock: Mutex<()>,
+}
+
+#[cfg(feature = "persistence")]
+impl std::fmt::Debug for SpillStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpillStore")
+            .field("dir", &self.dir)
+            .field("entries", &self.records.len())
+            .finish()
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl SpillStore {
+    /// Creates a spill directory for one store below `parent`.
+    pub(crate) fn open(parent: &Path) -> Result<Self, ShadowError> {
+        std::fs::create_dir_all(parent).map_err(|e| ShadowError::IoError { source: e })?;
+        let dir = parent.join(format!("spill-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).map_err(|e| ShadowError::IoError { source: e })?;
+        
+        Ok(Self {
+            dir,
+            cipher: ChaCha20Poly1305::new(&ChaCha20Poly1305::generate_key(&mut OsRng)),
+            records: DashMap::new(),
+            next_id: AtomicU64::new(0),
+            load_lock: Mutex::new(()),
+        })
+    }
+    
+    fn file_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.spill", id))
+    }
+    
+    fn nonce(id: u64) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&id.to_le_bytes());
+        Nonce::from(nonce)
+    }
+    
+    /// Writes `entry` to disk, replacing any earlier spill of the same path.
+    pub(crate) fn write(&self, entry: &OverrideEntry) -> Result<(), ShadowError> {
+        let encoded = bincode::serialize(entry)
+            .map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Failed to serialize spilled entry {}: {}", entry.path, e),
+            })?;
+        let compressed = zstd::encode_all(encoded.as_slice(), SPILL_COMPRESSION_LEVEL)
+            .map_err(|e| ShadowError::IoError { source: e })?;
+        
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let sealed = self.cipher.encrypt(&Self::nonce(id), compressed.as_slice())
+            .map_err(|_| ShadowError::InvalidConfiguration {
+                message: format!("Failed to encrypt spilled entry {}", entry.path),
+            })?;
+        std::fs::write(self.file_path(id), &sealed).map_err(|e| ShadowError::IoError { source: e })?;
+        
+        let record = SpillRecord { id, size: sealed.len() as u64 };
+        if let Some(previous) = self.records.insert(entry.path.clone(), record) {
+            let _ = std::fs::remove_file(self.file_path(previous.id));
+        }
+        Ok(())
+    }
+    
+    /// Reads the spilled entry for `path` without removing it.
+    pub(crate) fn read(&self, path: &ShadowPath) -> Result<Option<OverrideEntry>, ShadowError> {
+        let Some(record) = self.records.get(path).map(|r| *r) else {
+            return Ok(None);
+        };
+        
+        let sealed = std::fs::read(self.file_path(record.id)).map_err(|e| ShadowError::IoError { source: e })?;
+        let compressed = self.cipher.decrypt(&Self::nonce(record.id), sealed.as_slice())
+            .map_err(|_| ShadowError::InvalidConfiguration {
+                message: format!("Spill file for {} failed authentication", path),
+            })?;
+        let encoded = zstd::decode_all(compressed.as_slice()).map_err(|e| ShadowError::IoError { source: e })?;
+        let entry = bincode::deserialize(&encoded)
+            .map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("Failed to decode spilled entry {}: {}", path, e),
+            })?;
+        Ok(Some(entry))
+    }
+    
+    /// Forgets the spilled entry for `path` and deletes its file.
+    pub(crate) fn discard(&self, path: &ShadowPath) -> bool {
+        match self.records.remove(path) {
+            Some((_, record)) => {
+                let _ = std::fs::remove_file(self.file_path(record.id));
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Checks whether `path` is spilled.
+    pub(crate) fn contains(&self, path: &ShadowPath) -> bool {
+        self.records.contains_key(path)
+    }
+    
+    /// Returns the paths of all spilled entries.
+    pub(crate) fn paths(&self) -> Vec<ShadowPath> {
+        self.records.iter().map(|r| r.key().clone()).collect()
+    }
+    
+    /// Number of spilled entries.
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+    
+    /// Total size of the spill files in bytes.
+    pub(crate) fn bytes_on_disk(&self) -> u64 {
+        self.records.iter().map(|r| r.size).sum()
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Spill tier stand-in for builds without the `persistence` feature; it can never be opened.
+#[cfg(not(feature = "persistence"))]
+#[derive(Debug)]
+pub(crate) struct SpillStore {
+    never: std::convert::Infallible,
+    
+    load_lock: Mutex<()>,
+}
+
+#[cfg(not(feature = "persistence"))]
+impl SpillStore {
+    /// Fails; spilling to disk needs the `persistence` feature.
+    pub(crate) fn open(_parent: &Path) -> Result<Self, ShadowError> {
+        Err(ShadowError::IoError {
+            source: std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "spilling to disk needs the `persistence` feature of shadowfs-core",
+            ),
+        })
+    }
+    
+    pub(crate) fn write(&self, _entry: &OverrideEntry) -> Result<(), ShadowError> {
+        match self.never {}
+    }
+    
+    pub(crate) fn read(&self, _path: &ShadowPath) -> Result<Option<OverrideEntry>, ShadowError> {
+        match self.never {}
+    }
+    
+    pub(crate) fn discard(&self, _path: &ShadowPath) -> bool {
+        match self.never {}
+    }
+    
+    pub(crate) fn contains(&self, _path: &ShadowPath) -> bool {
+        match self.never {}
+    }
+    
+    pub(crate) fn paths(&self) -> Vec<ShadowPath> {
+        match self.never {}
+    }
+    
+    pub(crate) fn len(&self) -> usize {
+        match self.never {}
+    }
+    
+    pub(crate) fn bytes_on_disk(&self) -> u64 {
+        match self.never {}
+    }
+}
+
+/// Size of the disk-backed overflow tier.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpillStats {
+    /// Number of entries held on disk
+    pub entries: usize,
+    
+    /// Bytes used by spill files
+    pub bytes_on_disk: u64,
+}
+
+impl OverrideStore {
+    /// Returns the size of the spill tier, or `None` when nothing was spilled yet.
+    pub fn spill_stats(&self) -> Option<SpillStats> {
+        self.spill.read().unwrap().as_ref().map(|tier| SpillStats {
+            entries: tier.len(),
+            bytes_on_disk: tier.bytes_on_disk(),
+        })
+    }
+    
+    /// Returns the spill tier, opening it on first use if a spill directory is configured.
+    ///
+    /// The directory is read once; changing `spill_dir` later does not move
+    /// an already open tier.
+    pub(crate) fn spill_tier(&self) -> Result<Option<Arc<SpillStore>>, ShadowError> {
+        if let Some(tier) = self.spill.read().unwrap().as_ref() {
+            return Ok(Some(Arc::clone(tier)));
+        }
+        let Some(dir) = self.config.read().unwrap().spill_dir.clone() else {
+            return Ok(None);
+        };
+        
+        let mut spill = self.spill.write().unwrap();
+        if spill.is_none() {
+            *spill = Some(Arc::new(SpillStore::open(&dir)?));
+        }
+        Ok(spill.clone())
+    }
+    
+    /// Moves cold file overrides to disk until `target_bytes` of memory are freed.
+    ///
+    /// # Arguments
+    /// * `tier` - Spill tier receiving the entries
+    /// * `target_bytes` - Memory to free
+    /// * `keep` - Path that must stay resident, such as the entry being loaded
+    ///
+    /// # Returns
+    /// Number of bytes of memory freed
+    #[tracing::instrument(name = "spill", level = "debug", skip(self, tier, keep), err)]
+    pub(crate) fn spill_cold_entries(
+        &self,
+        tier: &SpillStore,
+        target_bytes: usize,
+        keep: Option<&ShadowPath>,
+    ) -> Result<usize, ShadowError> {
+        let mut freed = 0;
+        for path in self.lru_tracker.get_least_recently_used(SPILL_CANDIDATES) {
+            if freed >= target_bytes {
+                break;
+            }
+            if keep == Some(&path) {
+                continue;
+            }
+            let Some(entry) = self.entries.get(&path) else {
+                continue;
+            };
+            if !entry.is_file() {
+                continue;
+            }
+            
+            tier.write(&self.compacted_entry(&entry)?)?;
+            // A concurrent writer may have replaced the entry; keep the newer one in memory
+            if self.entries.remove_if(&path, |_, current| Arc::ptr_eq(current, &entry)).is_none() {
+                tier.discard(&path);
+                continue;
+            }
+            self.hot_cache.remove(&path);
+            self.lru_tracker.remove_entry(&path);
+            
+            let size = calculate_entry_size(&entry);
+            self.memory_tracker.release(size);
+            freed += size;
+        }
+        Ok(freed)
+    }
+    
+    /// Accounts `size` bytes for a resident entry, spilling cold entries if the store is full.
+    pub(crate) fn reserve_memory(&self, size: usize, keep: Option<&ShadowPath>) -> Result<(), ShadowError> {
+        match self.memory_tracker.try_allocate(size) {
+            Ok(guard) => std::mem::forget(guard),
+            Err(full) => {
+                let Some(tier) = self.spill_tier()? else {
+                    return Err(full);
+                };
+                self.spill_cold_entries(&tier, size, keep)?;
+                std::mem::forget(self.memory_tracker.try_allocate(size)?);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Brings the spilled entry for `path` back into memory.
+    ///
+    /// An entry too large to fit even after spilling others is returned but
+    /// stays on disk, so it is read from there on every access.
+    pub(crate) fn load_spilled(&self, path: &ShadowPath) -> Result<Option<Arc<OverrideEntry>>, ShadowError> {
+        let Some(tier) = self.spill.read().unwrap().clone() else {
+            return Ok(None);
+        };
+        let _loading = tier.load_lock.lock().unwrap();
+        if let Some(resident) = self.entries.get(path) {
+            return Ok(Some(resident));
+        }
+        let Some(entry) = tier.read(path)? else {
+            return Ok(None);
+        };
+        
+        let entry = Arc::new(entry);
+        if self.reserve_memory(calculate_entry_size(&entry), Some(path)).is_ok() {
+            self.entries.insert(path.clone(), Arc::clone(&entry));
+            tier.discard(path);
+            self.lru_tracker.record_access(path);
+        }
+        Ok(Some(entry))
+    }
+    
+    /// Removes the spilled entry for `path` from disk, returning it.
+    pub(crate) fn take_spilled(&self, path: &ShadowPath) -> Option<Arc<OverrideEntry>> {
+        let tier = self.spill.read().unwrap().clone()?;
+        let entry = tier.read(path).ok().flatten().map(Arc::new);
+        tier.discard(path);
+        entry
+    }
+    
+    /// Reads the spilled entry for `path` without bringing it back into memory.
+    pub(crate) fn peek_spilled(&self, path: &ShadowPath) -> Option<OverrideEntry> {
+        self.spill.read().unwrap().as_ref()?.read(path).ok().flatten()
+    }
+    
+    /// Checks whether `path` is held by the spill tier.
+    pub(crate) fn is_spilled(&self, path: &ShadowPath) -> bool {
+        self.spill.read().unwrap().as_ref().is_some_and(|tier| tier.contains(path))
+    }
+    
+    /// Returns the paths of all overrides, resident or spilled, including
+    /// tombstones.
+    pub fn all_paths(&self) -> Vec<ShadowPath> {
+        let mut paths: Vec<ShadowPath> = self.entries.iter().map(|(path, _)| path).collect();
+        if let Some(tier) = self.spill.read().unwrap().as_ref() {
+            paths.extend(tier.paths().into_iter().filter(|path| !self.entries.contains_key(path)));
+        }
+        paths
+    }
+    
+    /// Returns all overrides, reading spilled ones from disk without making them resident.
+    pub(crate) fn all_entries(&self) -> Result<Vec<Arc<OverrideEntry>>, ShadowError> {
+        let mut entries: Vec<Arc<OverrideEntry>> = self.entries.iter().map(|(_, entry)| entry).collect();
+        let Some(tier) = self.spill.read().unwrap().clone() else {
+            return Ok(entries);
+        };
+        for path in tier.paths() {
+            if self.entries.contains_key(&path) {
+                continue;
+            }
+            if let Some(entry) = tier.read(&path)? {
+                entries.push(Arc::new(entry));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(all(test, feature = "persistence"))]
+mod tests {
+    use super::*;
+    use crate::override_store::OverrideStoreConfig;
+    use bytes::Bytes;
+    
+    fn spilling_store(dir: &Path, max_memory: usize) -> OverrideStore {
+        OverrideStore::new(OverrideStoreConfig {
+            max_memory,
+            enable_compression: false,
+            spill_dir: Some(dir.to_path_buf()),
+            spill_threshold: 0.5,
+            ..OverrideStoreConfig::default()
+        })
+    }
+    
+    fn content(i: usize) -> Bytes {
+        Bytes::from(vec![i as u8; 4096])
+    }
+    
+    #[test]
+    fn test_cold_entries_spill_and_load_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = spilling_store(dir.path(), 64 * 1024);
+        
+        for i in 0..40 {
+            store.insert_file(ShadowPath::from(format!("/file{}.bin", i)), content(i), None).unwrap();
+        }
+        
+        let stats = store.spill_stats().unwrap();
+        assert!(stats.entries > 0);
+        assert!(stats.bytes_on_disk > 0);
+        assert_eq!(store.entry_count(), 40);
+        assert!(store.memory_stats().0 <= 64 * 1024);
+        
+        // Every entry is still readable, spilled or not
+        for i in 0..40 {
+            let path = ShadowPath::from(format!("/file{}.bin", i));
+            assert!(store.exists(&path));
+            let entry = store.get(&path).unwrap();
+            assert_eq!(entry.get_file_data().unwrap().unwrap(), content(i));
+        }
+        assert_eq!(store.all_entries().unwrap().len(), 40);
+    }
+    
+    #[test]
+    fn test_spilled_entries_are_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = spilling_store(dir.path(), 16 * 1024);
+        let secret = Bytes::from("top secret token ".repeat(256));
+        store.insert_file(ShadowPath::from("/secret.txt"), secret.clone(), None).unwrap();
+        for i in 0..10 {
+            store.insert_file(ShadowPath::from(format!("/filler{}", i)), content(i), None).unwrap();
+        }
+        assert!(store.is_spilled(&ShadowPath::from("/secret.txt")));
+        
+        let spill_dir = std::fs::read_dir(dir.path()).unwrap().next().unwrap().unwrap().path();
+        for file in std::fs::read_dir(&spill_dir).unwrap() {
+            let raw = std::fs::read(file.unwrap().path()).unwrap();
+            assert!(!raw.windows(10).any(|w| w == b"top secret"));
+        }
+        
+        drop(store);
+        assert!(!spill_dir.exists());
+    }
+    
+    #[test]
+    fn test_replacing_and_removing_spilled_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = spilling_store(dir.path(), 16 * 1024);
+        let cold = ShadowPath::from("/cold.bin");
+        store.insert_file(cold.clone(), content(1), None).unwrap();
+        for i in 0..10 {
+            store.insert_file(ShadowPath::from(format!("/filler{}", i)), content(i), None).unwrap();
+        }
+        assert!(store.is_spilled(&cold));
+        
+        store.insert_file(cold.clone(), Bytes::from("new"), None).unwrap();
+        assert!(!store.is_spilled(&cold));
+        assert_eq!(store.get(&cold).unwrap().get_file_data().unwrap().unwrap(), Bytes::from("new"));
+        
+        let evicted: Vec<ShadowPath> = store.all_paths().into_iter().filter(|p| store.is_spilled(p)).collect();
+        let removed = store.remove(&evicted[0]).unwrap();
+        assert_eq!(removed.path, evicted[0]);
+        assert!(!store.exists(&evicted[0]));
+        assert!(!store.is_spilled(&evicted[0]));
+    }
+}
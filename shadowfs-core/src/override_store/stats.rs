@@ -1,7 +1,9 @@
 //! Statistics and monitoring for the override store.
 
-use crate::types::ShadowPath;
+use crate::clock;
+use crate::types::{current_time, ShadowPath};
 use crate::override_store::{OverrideEntry, OverrideContent};
+use crate::source_cache::SourceCacheStats;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{SystemTime, Duration};
@@ -94,6 +96,9 @@ pub struct OverrideStoreStats {
     cache_hits: AtomicU64,
     cache_misses: AtomicU64,
     
+    // Clock reading of the last lookup or change
+    last_activity: AtomicU64,
+    
     // Callback registry for real-time monitoring
     callbacks: Arc<RwLock<Vec<Box<dyn Fn(&StatsSnapshot) + Send + Sync>>>>,
     
@@ -139,32 +144,37 @@ pub struct HotPathStats {
     pub avg_interval: Duration,
     /// Total bytes accessed
     pub bytes_accessed: u64,
+    /// Clock reading of the last access, which intervals are measured from
+    last_seen: u64,
 }
 
 impl HotPathStats {
     fn new() -> Self {
         Self {
             access_count: 0,
-            last_accessed: SystemTime::now(),
+            last_accessed: clock::wall_now(),
             avg_interval: Duration::from_secs(0),
             bytes_accessed: 0,
+            last_seen: clock::now(),
         }
     }
 
-    fn update_access(&mut self, bytes: u64) {
-        let now = SystemTime::now();
+    /// Records `accesses` accesses of `bytes` each, which took place since
+    /// the last one recorded.
+    fn update_access(&mut self, bytes: u64, accesses: u64) {
+        let now = clock::now();
         
         if self.access_count > 0 {
-            if let Ok(interval) = now.duration_since(self.last_accessed) {
-                // Update running average of access intervals
-                let total_duration = self.avg_interval.as_nanos() as u64 * self.access_count + interval.as_nanos() as u64;
-                self.avg_interval = Duration::from_nanos(total_duration / (self.access_count + 1));
-            }
+            // Update running average of access intervals
+            let interval = Duration::from_millis(now.saturating_sub(self.last_seen));
+            let total_duration = self.avg_interval.as_nanos() as u64 * self.access_count + interval.as_nanos() as u64;
+            self.avg_interval = Duration::from_nanos(total_duration / (self.access_count + accesses));
         }
         
-        self.access_count += 1;
-        self.last_accessed = now;
-        self.bytes_accessed += bytes;
+        self.access_count += accesses;
+        self.last_accessed = clock::to_wall(now);
+        self.last_seen = now;
+        self.bytes_accessed += bytes * accesses;
     }
 }
 
@@ -217,6 +227,13 @@ pub struct StatsReport {
     pub hot_paths: Vec<(ShadowPath, HotPathStats)>,
     /// Efficiency ratios
     pub efficiency: EfficiencyMetrics,
+    /// Source file cache counters, if the store has one
+    pub source_cache: Option<SourceCacheStats>,
+    /// Latency percentiles of the filesystem operations served over the
+    /// store, keyed by operation name; empty unless added with
+    /// [`StatsReport::with_operation_latencies`]
+    #[cfg(feature = "stats")]
+    pub operation_latencies: std::collections::BTreeMap<String, crate::stats::LatencySummary>,
 }
 
 /// Performance-related metrics
@@ -260,14 +277,39 @@ impl OverrideStoreStats {
             eviction_count: AtomicU64::new(0),
             cache_hits: AtomicU64::new(0),
             cache_misses: AtomicU64::new(0),
+            last_activity: AtomicU64::new(clock::now()),
             callbacks: Arc::new(RwLock::new(Vec::new())),
             alert_config: Arc::new(RwLock::new(AlertConfig::default())),
             hot_paths: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Returns when the store was last used, by a lookup, an insert or a removal.
+    ///
+    /// Eviction, spilling and snapshots do not count, so this tells when the
+    /// filesystem was last used.
+    pub fn last_activity(&self) -> SystemTime {
+        clock::to_wall(self.last_activity_reading())
+    }
+    
+    /// Returns how long the store has gone without a lookup, an insert or a
+    /// removal, on the monotonic [`clock`].
+    pub fn idle_for(&self) -> Duration {
+        clock::elapsed(self.last_activity_reading())
+    }
+    
+    /// Returns the [`clock`] reading of the last lookup, insert or removal.
+    pub(crate) fn last_activity_reading(&self) -> u64 {
+        self.last_activity.load(Ordering::Relaxed)
+    }
+    
+    pub(crate) fn record_activity(&self) {
+        self.last_activity.store(clock::now(), Ordering::Relaxed);
+    }
+    
     /// Updates statistics when inserting an entry
     pub fn update_on_insert(&self, entry: &OverrideEntry, memory_size: usize, compression_saved: usize, dedup_saved: usize) {
+        self.record_activity();
         let entry_type = EntryType::from(&entry.content);
         
         // Update entry counts
@@ -287,7 +329,7 @@ impl OverrideStoreStats {
         if let EntryType::File = entry_type {
             let mut hot_paths = self.hot_paths.lock().unwrap();
             let stats = hot_paths.entry(entry.path.clone()).or_insert_with(HotPathStats::new);
-            stats.update_access(entry.override_metadata.size);
+            stats.update_access(entry.override_metadata.size, 1);
         }
 
         // Trigger callbacks
@@ -296,6 +338,7 @@ impl OverrideStoreStats {
 
     /// Updates statistics when removing an entry
     pub fn update_on_remove(&self, entry: &OverrideEntry, memory_size: usize, compression_saved: usize, dedup_saved: usize) {
+        self.record_activity();
         let entry_type = EntryType::from(&entry.content);
         
         // Update entry counts
@@ -328,10 +371,17 @@ impl OverrideStoreStats {
 
     /// Updates cache hit/miss statistics
     pub fn update_cache_access(&self, hit: bool) {
+        self.update_cache_accesses(hit, 1);
+    }
+    
+    /// Counts `accesses` cache hits or misses at once, as one sampled
+    /// lookup stands for several.
+    pub fn update_cache_accesses(&self, hit: bool, accesses: u64) {
+        self.record_activity();
         if hit {
-            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            self.cache_hits.fetch_add(accesses, Ordering::Relaxed);
         } else {
-            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+            self.cache_misses.fetch_add(accesses, Ordering::Relaxed);
         }
         
         // Recalculate hit rate
@@ -350,9 +400,14 @@ impl OverrideStoreStats {
 
     /// Updates hot path statistics on access
     pub fn update_hot_path_access(&self, path: &ShadowPath, bytes: u64) {
+        self.update_hot_path_accesses(path, bytes, 1);
+    }
+    
+    /// Updates hot path statistics for `accesses` accesses at once
+    pub fn update_hot_path_accesses(&self, path: &ShadowPath, bytes: u64, accesses: u64) {
         let mut hot_paths = self.hot_paths.lock().unwrap();
         let stats = hot_paths.entry(path.clone()).or_insert_with(HotPathStats::new);
-        stats.update_access(bytes);
+        stats.update_access(bytes, accesses);
     }
 
     /// Generates a comprehensive statistics report
@@ -370,13 +425,16 @@ impl OverrideStoreStats {
             performance_metrics,
             hot_paths,
             efficiency,
+            source_cache: None,
+            #[cfg(feature = "stats")]
+            operation_latencies: Default::default(),
         }
     }
 
     /// Gets current statistics snapshot
     pub fn get_snapshot(&self) -> StatsSnapshot {
         StatsSnapshot {
-            timestamp: SystemTime::now(),
+            timestamp: current_time(),
             total_entries: self.total_entries.load(Ordering::Relaxed),
             file_entries: self.file_entries.load(Ordering::Relaxed),
             directory_entries: self.directory_entries.load(Ordering::Relaxed),
@@ -588,7 +646,9 @@ mod tests {
             original_metadata: None,
             override_metadata: FileMetadata::default(),
             created_at: SystemTime::now(),
+            created: 0,
             last_accessed: AtomicU64::new(0),
+            appended: None,
         }
     }
 
@@ -652,6 +712,24 @@ mod tests {
         assert_eq!(hot_paths[0].1.bytes_accessed, 450);
     }
 
+    #[test]
+    fn test_clock_jumps_do_not_skew_intervals_or_idleness() {
+        let stats = OverrideStoreStats::new();
+        let path = ShadowPath::new("/hot/file.txt".into());
+        
+        stats.update_hot_path_access(&path, 100);
+        // Resuming from suspend steps the wall clock a day ahead
+        crate::clock::testing::jump_wall_clock(86_400_000);
+        crate::clock::testing::advance(Duration::from_secs(2));
+        stats.update_hot_path_access(&path, 100);
+        
+        let hot = &stats.get_hot_paths(1)[0].1;
+        assert!(hot.avg_interval >= Duration::from_secs(1) && hot.avg_interval < Duration::from_secs(60));
+        assert!(stats.idle_for() >= Duration::from_secs(2) && stats.idle_for() < Duration::from_secs(60));
+        // Reports follow the wall clock
+        assert!(stats.last_activity() > current_time() + Duration::from_secs(23 * 3600));
+    }
+    
     #[test]
     fn test_memory_breakdown() {
         let stats = OverrideStoreStats::new();
@@ -0,0 +1,227 @@
+//! NTFS named streams of file overrides.
+//!
+//! Files on NTFS hold named streams besides their content, such as the
+//! `Zone.Identifier` a browser attaches to downloads. A store serving a
+//! Windows mount keeps the override of a stream under the stream path of
+//! its file, `/setup.exe:Zone.Identifier`, as a file override of its own,
+//! so streams are read, written, spilled and persisted like any file. A
+//! tombstone at a stream path deletes the source stream.
+//!
+//! Stream paths are not children of the directory holding their file:
+//! listings leave them out, and [`OverrideStore::streams`] lists the
+//! streams of a file instead. Deleting a file deletes its stream overrides
+//! with it.
+//!
+//! Colons are ordinary filename characters outside Windows, so a store
+//! only treats paths as streams once [`OverrideStore::set_named_streams`]
+//! turned them on, as the ProjFS provider does.
+
+use crate::error::{invalid_path, not_found, unsupported, ShadowError};
+use crate::override_store::OverrideStore;
+use crate::types::{NamedStream, ShadowPath};
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+/// Named streams held by a store, by the path of their file.
+#[derive(Debug, Default)]
+pub(crate) struct StreamIndex {
+    enabled: AtomicBool,
+    streams: RwLock<HashMap<ShadowPath, BTreeSet<String>>>,
+}
+
+impl StreamIndex {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+    
+    /// Records a newly held path, returning true if it is a named stream.
+    pub(crate) fn record(&self, path: &ShadowPath) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        let Some(name) = path.stream_name() else {
+            return false;
+        };
+        self.streams.write().unwrap().entry(path.without_stream()).or_default().insert(name);
+        true
+    }
+    
+    /// Drops a path that is no longer held, returning true if it is a
+    /// named stream.
+    pub(crate) fn forget(&self, path: &ShadowPath) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        let Some(name) = path.stream_name() else {
+            return false;
+        };
+        let file = path.without_stream();
+        let mut streams = self.streams.write().unwrap();
+        if let Some(names) = streams.get_mut(&file) {
+            names.remove(&name);
+            if names.is_empty() {
+                streams.remove(&file);
+            }
+        }
+        true
+    }
+    
+    /// Names of the streams held for `file`.
+    fn names(&self, file: &ShadowPath) -> Vec<String> {
+        self.streams.read().unwrap().get(file).map(|names| names.iter().cloned().collect()).unwrap_or_default()
+    }
+}
+
+impl OverrideStore {
+    /// Turns named streams on or off.
+    ///
+    /// While on, paths such as `/notes.txt:meta` are the named streams of
+    /// their file rather than entries of their directory. Stores start with
+    /// streams off; held paths are reindexed when the mode changes.
+    pub fn set_named_streams(&self, enabled: bool) {
+        if self.stream_index.enabled.swap(enabled, Ordering::AcqRel) == enabled {
+            return;
+        }
+        self.stream_index.streams.write().unwrap().clear();
+        for path in self.all_paths() {
+            let (Some(name), Some(parent)) = (path.file_name(), path.parent()) else {
+                continue;
+            };
+            if !path.is_stream() {
+                continue;
+            }
+            if enabled {
+                self.directory_cache.remove_child(&parent, &name);
+                self.stream_index.record(&path);
+            } else {
+                self.directory_cache.add_child(&parent, &name);
+            }
+        }
+    }
+    
+    /// Returns true if the store treats stream paths as named streams.
+    pub fn has_named_streams(&self) -> bool {
+        self.stream_index.is_enabled()
+    }
+    
+    /// Writes the named stream `stream` of the file at `file`.
+    ///
+    /// # Errors
+    /// Fails with [`ShadowError::Unsupported`] unless named streams are on,
+    /// with [`ShadowError::InvalidPath`] for an empty stream name or one
+    /// holding a separator, with [`ShadowError::NotFound`] if the file is
+    /// deleted, and otherwise as [`insert_file`](OverrideStore::insert_file).
+    pub fn insert_stream(&self, file: &ShadowPath, stream: &str, content: Bytes) -> Result<(), ShadowError> {
+        if !self.has_named_streams() {
+            return Err(unsupported("named streams"));
+        }
+        if stream.is_empty() || stream.contains([':', '/', '\\']) {
+            return Err(invalid_path(file.to_string(), format!("invalid stream name {:?}", stream)));
+        }
+        if self.is_deleted(file) {
+            return Err(not_found(file.clone()));
+        }
+        self.insert_file(file.with_stream(stream), content, None)
+    }
+    
+    /// Returns the named streams the overrides give the file at `file`,
+    /// sorted by name. Deleted streams are left out.
+    pub fn streams(&self, file: &ShadowPath) -> Vec<NamedStream> {
+        self.merge_streams(file, Vec::new())
+    }
+    
+    /// Applies the stream overrides of `file` to the streams its source
+    /// has, returning the streams the mount shows, sorted by name.
+    ///
+    /// Overrides replace the source stream of the same name, tombstones
+    /// delete it, and a deleted file has no streams at all.
+    pub fn merge_streams(&self, file: &ShadowPath, source: Vec<NamedStream>) -> Vec<NamedStream> {
+        if !self.has_named_streams() || self.is_deleted(file) {
+            return Vec::new();
+        }
+        let mut streams: BTreeMap<String, NamedStream> = source.into_iter()
+            .map(|stream| (stream.name.clone(), stream))
+            .collect();
+        for name in self.stream_index.names(file) {
+            match self.get(&file.with_stream(&name)) {
+                Some(entry) if entry.is_deleted() => {
+                    streams.remove(&name);
+                }
+                Some(entry) => {
+                    let size = entry.override_metadata.size;
+                    streams.insert(name.clone(), NamedStream { name, size });
+                }
+                None => {}
+            }
+        }
+        streams.into_values().collect()
+    }
+    
+    /// Removes the stream overrides of `file`, once the file is deleted.
+    pub(crate) fn drop_streams(&self, file: &ShadowPath) {
+        if !self.has_named_streams() {
+            return;
+        }
+        for name in self.stream_index.names(file) {
+            self.remove(&file.with_stream(&name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn stream(name: &str, size: u64) -> NamedStream {
+        NamedStream { name: name.to_string(), size }
+    }
+    
+    #[test]
+    fn test_streams_belong_to_their_file() {
+        let store = OverrideStore::with_defaults();
+        let file = ShadowPath::from("/setup.exe");
+        store.insert_file(file.clone(), Bytes::from_static(b"MZ"), None).unwrap();
+        assert!(matches!(
+            store.insert_stream(&file, "Zone.Identifier", Bytes::new()),
+            Err(ShadowError::Unsupported { .. })
+        ));
+        
+        store.set_named_streams(true);
+        store.insert_stream(&file, "Zone.Identifier", Bytes::from_static(b"[ZoneTransfer]")).unwrap();
+        store.insert_stream(&file, "meta", Bytes::from_static(b"x")).unwrap();
+        assert!(store.insert_stream(&file, "a:b", Bytes::new()).is_err());
+        assert_eq!(store.streams(&file), [stream("Zone.Identifier", 14), stream("meta", 1)]);
+        
+        // Listings show the file, not its streams
+        assert_eq!(store.get_directory_children(&ShadowPath::from("/")), ["setup.exe"]);
+        
+        // Source streams are replaced or deleted by their overrides
+        store.mark_deleted(file.with_stream("meta")).unwrap();
+        let merged = store.merge_streams(&file, vec![stream("meta", 3), stream("Zone.Identifier", 2), stream("src", 5)]);
+        assert_eq!(merged, [stream("Zone.Identifier", 14), stream("src", 5)]);
+        
+        // Deleting the file deletes its streams
+        store.mark_deleted(file.clone()).unwrap();
+        assert!(store.get(&file.with_stream("Zone.Identifier")).is_none());
+        assert!(store.merge_streams(&file, vec![stream("src", 5)]).is_empty());
+    }
+    
+    #[test]
+    fn test_turning_streams_on_reindexes_held_paths() {
+        let store = OverrideStore::with_defaults();
+        let root = ShadowPath::from("/");
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from_static(b"a"), None).unwrap();
+        store.insert_file(ShadowPath::from("/a.txt:meta"), Bytes::from_static(b"m"), None).unwrap();
+        assert_eq!(store.get_directory_children(&root).len(), 2);
+        assert!(store.streams(&ShadowPath::from("/a.txt")).is_empty());
+        
+        store.set_named_streams(true);
+        assert_eq!(store.get_directory_children(&root), ["a.txt"]);
+        assert_eq!(store.streams(&ShadowPath::from("/a.txt")), [stream("meta", 1)]);
+        
+        store.set_named_streams(false);
+        assert_eq!(store.get_directory_children(&root).len(), 2);
+    }
+}
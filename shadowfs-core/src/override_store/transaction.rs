@@ -0,0 +1,442 @@
+//! Atomic groups of changes to the store.
+//!
+//! [`OverrideStore::transaction`] hands a [`Transaction`] to a closure that
+//! stages writes, directories, tombstones and removals. Nothing reaches the
+//! store until the closure returns `Ok`; the staged changes are then checked
+//! against the read-only flag and access rules, their memory is reserved in
+//! one go, and they are applied in the order they were staged. If applying
+//! one of them still fails, the ones already applied are rolled back, so the
+//! store ends up with all of the changes or none of them.
+//!
+//! Other threads can see the changes as they are applied, and watchers get
+//! an event for each one, including the restores of a rollback. With a WAL,
+//! every applied change and every restore is logged like any other write.
+
+use crate::access::AccessOperation;
+use crate::error::ShadowError;
+use crate::override_store::{calculate_entry_size, LinkId, OverrideEntry, OverrideStore};
+use crate::quota::QuotaUsage;
+use crate::types::{FileMetadata, ShadowPath};
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A change staged in a [`Transaction`].
+enum Staged {
+    /// Store this entry at its path
+    Put(Arc<OverrideEntry>),
+    /// Remove the override at the path
+    Remove,
+}
+
+/// Changes staged for [`OverrideStore::transaction`].
+pub struct Transaction<'a> {
+    store: &'a OverrideStore,
+    staged: Vec<(ShadowPath, Staged)>,
+}
+
+/// What a touched path held before the transaction, for rollback.
+struct Prior {
+    entries: Vec<(ShadowPath, Option<Arc<OverrideEntry>>)>,
+    links: Vec<(ShadowPath, LinkId)>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Stages a file override, like [`OverrideStore::insert_file`].
+    pub fn insert_file(&mut self, path: ShadowPath, content: Bytes, original_metadata: Option<FileMetadata>) {
+        let (content, metadata) = self.store.file_override(content, original_metadata.as_ref());
+        self.put(OverrideEntry::new(path, content, original_metadata, metadata));
+    }
+    
+    /// Stages a directory override, like [`OverrideStore::insert_directory`].
+    pub fn insert_directory(&mut self, path: ShadowPath, original_metadata: Option<FileMetadata>) {
+        let (content, metadata) = OverrideStore::directory_override(original_metadata.as_ref());
+        self.put(OverrideEntry::new(path, content, original_metadata, metadata));
+    }
+    
+    /// Stages a tombstone, like [`OverrideStore::mark_deleted`].
+    pub fn mark_deleted(&mut self, path: ShadowPath) {
+        let (content, metadata) = OverrideStore::tombstone_override();
+        self.put(OverrideEntry::new(path, content, None, metadata));
+    }
+    
+    /// Stages the removal of the override at `path`, like [`OverrideStore::remove`].
+    pub fn remove(&mut self, path: ShadowPath) {
+        self.staged.push((path, Staged::Remove));
+    }
+    
+    /// Stages directory overrides for `path` and each of its parents that
+    /// neither the store nor the transaction has yet, like
+    /// [`OverrideStore::create_directory_hierarchy`].
+    pub fn create_directory_hierarchy(&mut self, path: &ShadowPath) {
+        let mut chain = crate::override_store::PathTraversal::get_parent_chain(path);
+        chain.reverse();
+        chain.push(path.clone());
+        for directory in chain {
+            if self.get(&directory).is_none() {
+                self.insert_directory(directory, None);
+            }
+        }
+    }
+    
+    /// Returns the entry at `path` as it will be once the staged changes are
+    /// applied, falling back to the store for paths the transaction has not
+    /// touched.
+    pub fn get(&self, path: &ShadowPath) -> Option<Arc<OverrideEntry>> {
+        match self.staged.iter().rev().find(|(staged, _)| staged == path) {
+            Some((_, Staged::Put(entry))) => Some(Arc::clone(entry)),
+            Some((_, Staged::Remove)) => None,
+            None => self.store.get(path),
+        }
+    }
+    
+    /// Returns the number of staged changes.
+    pub fn len(&self) -> usize {
+        self.staged.len()
+    }
+    
+    /// Returns true if nothing has been staged.
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+    
+    /// Stages an entry built elsewhere, such as one taken from another store.
+    pub(crate) fn stage_entry(&mut self, entry: Arc<OverrideEntry>) {
+        self.staged.push((entry.path.clone(), Staged::Put(entry)));
+    }
+    
+    fn put(&mut self, entry: OverrideEntry) {
+        self.stage_entry(Arc::new(entry));
+    }
+}
+
+impl OverrideStore {
+    /// Applies a group of changes atomically.
+    ///
+    /// `build` stages the changes on a [`Transaction`]. If it returns an
+    /// error nothing is applied and the error is returned. Otherwise every
+    /// staged change is checked, the memory for all of them is reserved up
+    /// front, evicting first when the store is under pressure, and they are
+    /// applied in order. A failure while applying rolls back the changes
+    /// already made.
+    ///
+    /// # Arguments
+    /// * `build` - Closure staging the changes, whose result is returned on commit
+    ///
+    /// # Errors
+    /// Fails with the error of `build`; with [`ShadowError::ReadOnlyFilesystem`]
+    /// or [`ShadowError::AccessDenied`] if any staged change is refused; with
+    /// [`ShadowError::OverrideStoreFull`] if the changes do not fit; or with
+    /// the error that made the store roll back. In every case the store is
+    /// left as it was.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use shadowfs_core::override_store::OverrideStore;
+    /// # use shadowfs_core::types::ShadowPath;
+    /// # use bytes::Bytes;
+    /// let store = OverrideStore::with_defaults();
+    /// store.transaction(|tx| {
+    ///     tx.create_directory_hierarchy(&ShadowPath::from("/app/config"));
+    ///     tx.insert_file(ShadowPath::from("/app/config/app.toml"), Bytes::from("debug = true"), None);
+    ///     tx.mark_deleted(ShadowPath::from("/app/config/legacy.ini"));
+    ///     Ok(())
+    /// })?;
+    /// assert!(store.is_deleted(&ShadowPath::from("/app/config/legacy.ini")));
+    /// # Ok::<(), shadowfs_core::error::ShadowError>(())
+    /// ```
+    pub fn transaction<T, F>(&self, build: F) -> Result<T, ShadowError>
+    where
+        F: FnOnce(&mut Transaction<'_>) -> Result<T, ShadowError>,
+    {
+        let mut transaction = Transaction { store: self, staged: Vec::new() };
+        let value = build(&mut transaction)?;
+        self.commit_transaction(transaction.staged)?;
+        Ok(value)
+    }
+    
+    fn commit_transaction(&self, staged: Vec<(ShadowPath, Staged)>) -> Result<(), ShadowError> {
+        if staged.is_empty() {
+            return Ok(());
+        }
+        
+        let staged: Vec<(ShadowPath, Staged)> = staged.into_iter()
+            .map(|(path, change)| self.stored_change(path, change))
+            .collect();
+        for (path, change) in &staged {
+            match change {
+                Staged::Put(entry) => self.check_insert(path, &entry.content)?,
+                Staged::Remove => {
+                    self.check_writable(path, "remove")?;
+                    self.check_access(path, AccessOperation::Delete)?;
+                }
+            }
+        }
+        self.check_quota(staged.iter().map(|(path, change)| match change {
+            Staged::Put(entry) => (path, QuotaUsage::of_entry(entry)),
+            Staged::Remove => (path, QuotaUsage::default()),
+        }))?;
+        
+        // Written ranges become full entries, so a rollback can restore them
+        for (path, _) in &staged {
+            if self.has_delta(path) {
+                self.compact_delta(path)?;
+            }
+        }
+        
+        let needed = self.transaction_memory(&staged);
+        self.relieve_pressure(needed)?;
+        self.reserve_memory(needed, None)?;
+        let mut reserved = needed;
+        
+        let prior = self.capture_prior(&staged);
+        let mut applied = Ok(());
+        for (path, change) in staged {
+            applied = match change {
+                Staged::Put(entry) => {
+                    let entry = Arc::try_unwrap(entry).unwrap_or_else(|shared| (*shared).clone());
+                    self.apply_entry(entry, Some(&mut reserved))
+                }
+                Staged::Remove => {
+                    self.remove(&path);
+                    Ok(())
+                }
+            };
+            if applied.is_err() {
+                break;
+            }
+        }
+        self.memory_tracker.release(reserved);
+        
+        if let Err(error) = applied {
+            self.roll_back(prior);
+            return Err(error);
+        }
+        Ok(())
+    }
+    
+    /// Moves a change staged through another casing of a held path to the
+    /// spelling the store holds it under, as a direct write would be.
+    fn stored_change(&self, path: ShadowPath, change: Staged) -> (ShadowPath, Staged) {
+        let stored = self.stored_spelling(path.clone());
+        if stored == path {
+            return (path, change);
+        }
+        let change = match change {
+            Staged::Put(entry) => {
+                let mut entry = Arc::try_unwrap(entry).unwrap_or_else(|shared| (*shared).clone());
+                entry.path = stored.clone();
+                Staged::Put(Arc::new(entry))
+            }
+            Staged::Remove => Staged::Remove,
+        };
+        (stored, change)
+    }
+    
+    /// Returns the memory the staged changes add to the store: the size of
+    /// each entry stored at a path that holds no resident entry by then.
+    fn transaction_memory(&self, staged: &[(ShadowPath, Staged)]) -> usize {
+        let mut resident: HashSet<&ShadowPath> = HashSet::new();
+        let mut removed: HashSet<&ShadowPath> = HashSet::new();
+        let mut needed = 0;
+        for (path, change) in staged {
+            match change {
+                Staged::Put(entry) => {
+                    let present = resident.contains(path)
+                        || (!removed.contains(path) && self.entries.contains_key(path));
+                    if !present {
+                        needed += calculate_entry_size(entry);
+                        resident.insert(path);
+                    }
+                }
+                Staged::Remove => {
+                    resident.remove(path);
+                    removed.insert(path);
+                }
+            }
+        }
+        needed
+    }
+    
+    /// Records what every path the staged changes can reach holds now,
+    /// including the hard links of staged paths.
+    fn capture_prior(&self, staged: &[(ShadowPath, Staged)]) -> Prior {
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        let mut links = Vec::new();
+        for (path, _) in staged {
+            for touched in self.links.members(path) {
+                if !seen.insert(touched.clone()) {
+                    continue;
+                }
+                if let Some(id) = self.links.id(&touched) {
+                    links.push((touched.clone(), id));
+                }
+                let current = self.entries.get(&touched).or_else(|| self.load_spilled(&touched).ok().flatten());
+                entries.push((touched, current));
+            }
+        }
+        Prior { entries, links }
+    }
+    
+    /// Puts back what the touched paths held before the transaction.
+    fn roll_back(&self, prior: Prior) {
+        for (path, entry) in prior.entries.into_iter().rev() {
+            match entry {
+                // The memory of a replaced entry was never released, and a
+                // removed one gave its memory back, so the restore fits
+                Some(entry) => {
+                    let _ = self.store_shared_entry(entry, true);
+                }
+                None => {
+                    self.remove(&path);
+                }
+            }
+        }
+        
+        let paths: Vec<ShadowPath> = prior.links.iter().map(|(path, _)| path.clone()).collect();
+        self.links.detach(&paths);
+        self.links.attach(prior.links);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::{OverrideContent, OverrideStoreConfig};
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    fn file_data(store: &OverrideStore, p: &str) -> Option<Bytes> {
+        store.get(&path(p)).and_then(|entry| entry.get_file_data().unwrap())
+    }
+    
+    #[test]
+    fn test_transaction_applies_all() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/app/old.txt"), Bytes::from("old"), None).unwrap();
+        
+        let staged = store.transaction(|tx| {
+            tx.create_directory_hierarchy(&path("/app/conf"));
+            tx.insert_file(path("/app/conf/a.toml"), Bytes::from("a"), None);
+            tx.insert_file(path("/app/conf/b.toml"), Bytes::from("b"), None);
+            tx.mark_deleted(path("/app/old.txt"));
+            assert_eq!(tx.get(&path("/app/conf/a.toml")).unwrap().get_file_data().unwrap().unwrap(), "a");
+            Ok(tx.len())
+        }).unwrap();
+        
+        // The root, /app and /app/conf, two files and a tombstone
+        assert_eq!(staged, 6);
+        assert!(store.exists(&path("/app/conf")));
+        assert_eq!(file_data(&store, "/app/conf/b.toml").unwrap(), "b");
+        assert!(store.is_deleted(&path("/app/old.txt")));
+    }
+    
+    #[test]
+    fn test_transaction_checks_the_access_policy() {
+        use crate::access::{AccessPolicy, AccessRule};
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/etc/hosts"), Bytes::from("local"), None).unwrap();
+        store.set_access_policy(Arc::new(
+            AccessPolicy::allow_all().with_rule(AccessRule::deny_prefix("/etc", &[AccessOperation::Delete])),
+        ));
+        
+        for tombstone in [false, true] {
+            let err = store.transaction(|tx| {
+                tx.insert_file(path("/a.txt"), Bytes::from("a"), None);
+                if tombstone {
+                    tx.mark_deleted(path("/etc/hosts"));
+                } else {
+                    tx.remove(path("/etc/hosts"));
+                }
+                Ok(())
+            }).unwrap_err();
+            assert!(matches!(err, ShadowError::AccessDenied { .. }));
+        }
+        assert_eq!(file_data(&store, "/etc/hosts").unwrap(), "local");
+        assert!(store.get(&path("/a.txt")).is_none());
+    }
+    
+    #[test]
+    fn test_transaction_error_applies_nothing() {
+        let store = OverrideStore::with_defaults();
+        let result: Result<(), _> = store.transaction(|tx| {
+            tx.insert_file(path("/a.txt"), Bytes::from("a"), None);
+            Err(crate::error::not_found(path("/missing")))
+        });
+        
+        assert!(matches!(result, Err(ShadowError::NotFound { .. })));
+        assert!(!store.exists(&path("/a.txt")));
+    }
+    
+    #[test]
+    fn test_transaction_reserves_memory_up_front() {
+        let store = OverrideStore::new(OverrideStoreConfig {
+            max_memory: 4096,
+            eviction_threshold: 1.0,
+            enable_compression: false,
+            ..OverrideStoreConfig::default()
+        });
+        store.insert_file(path("/kept.txt"), Bytes::from("kept"), None).unwrap();
+        let (used, _, _) = store.memory_stats();
+        
+        let result = store.transaction(|tx| {
+            for i in 0..4 {
+                tx.insert_file(path(&format!("/big{}.bin", i)), Bytes::from(vec![i as u8; 1500]), None);
+            }
+            Ok(())
+        });
+        
+        assert!(matches!(result, Err(ShadowError::OverrideStoreFull { .. })));
+        assert!(!store.exists(&path("/big0.bin")));
+        assert_eq!(file_data(&store, "/kept.txt").unwrap(), "kept");
+        assert_eq!(store.memory_stats().0, used);
+    }
+    
+    #[test]
+    fn test_transaction_checks_before_applying() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/a.txt"), Bytes::from("a"), None).unwrap();
+        store.set_read_only(true);
+        
+        let result = store.transaction(|tx| {
+            tx.insert_file(path("/b.txt"), Bytes::from("b"), None);
+            tx.remove(path("/a.txt"));
+            Ok(())
+        });
+        
+        assert!(matches!(result, Err(ShadowError::ReadOnlyFilesystem { .. })));
+        assert!(!store.exists(&path("/b.txt")));
+        assert_eq!(file_data(&store, "/a.txt").unwrap(), "a");
+    }
+    
+    #[test]
+    fn test_roll_back_restores_entries_and_links() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(path("/a.txt"), Bytes::from("a"), None).unwrap();
+        store.link(&path("/a.txt"), path("/b.txt")).unwrap();
+        
+        let staged = vec![
+            (path("/a.txt"), Staged::Remove),
+            (path("/b.txt"), Staged::Put(Arc::new(OverrideEntry::new(
+                path("/b.txt"),
+                OverrideContent::Deleted,
+                None,
+                OverrideStore::tombstone_override().1,
+            )))),
+            (path("/c.txt"), Staged::Remove),
+        ];
+        let prior = store.capture_prior(&staged);
+        store.commit_transaction(staged).unwrap();
+        assert!(!store.exists(&path("/a.txt")));
+        assert_eq!(store.link_count(&path("/b.txt")), 1);
+        
+        store.roll_back(prior);
+        assert_eq!(file_data(&store, "/a.txt").unwrap(), "a");
+        assert_eq!(file_data(&store, "/b.txt").unwrap(), "a");
+        assert_eq!(store.link_count(&path("/a.txt")), 2);
+        assert!(!store.exists(&path("/c.txt")));
+    }
+}
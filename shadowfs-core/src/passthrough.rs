@@ -0,0 +1,872 @@
+//! File operations on a mount, served from its override store with
+//! everything else passed through to the source tree.
+//!
+//! The file operations of [`FileSystemProvider`] default to the ones here,
+//! so a platform crate only overrides what its backend handles differently.
+//! They work on a [`MountContext`]: the shared store, the source directory
+//! and the advisory locks of one mount. Paths resolve the way the FUSE
+//! provider resolves them: overrides first, then the store's rules, read
+//! transforms and written ranges, then the source. Writes to source files
+//! keep only the written ranges. The operations block on the source tree,
+//! like platform callbacks do.
+//!
+//! [`FileSystemProvider`]: crate::traits::FileSystemProvider
+
+use crate::access::AccessOperation;
+use crate::determinism::Determinism;
+use crate::error::{self, Result, ShadowError};
+use crate::handles::HandleTable;
+use crate::hidden::HiddenFiles;
+use crate::merged_view::{DirectoryPage, MergedDirectoryView};
+use crate::override_store::{source_metadata, source_path, OverrideEntry, OverrideStore, RenameOptions};
+use crate::task;
+use crate::types::{DirectoryEntry, FileMetadata, FileType, ShadowPath};
+use bytes::Bytes;
+use futures_core::Stream;
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use tokio::task::JoinHandle;
+
+/// Kinds of advisory lock on a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// Held by any number of owners at once, excluding exclusive locks
+    Shared,
+    /// Held by a single owner, excluding every other lock
+    Exclusive,
+}
+
+/// Lock held on one path.
+#[derive(Debug)]
+enum HeldLock {
+    Shared(BTreeSet<u64>),
+    Exclusive(u64),
+}
+
+/// Advisory whole-file locks held on the paths of a mount.
+///
+/// Owners are identified by a number of the caller's choosing, such as the
+/// lock owner FUSE passes or a process id. Locks are never waited for: a
+/// conflicting request fails at once.
+#[derive(Debug, Default)]
+pub struct LockTable {
+    held: Mutex<HashMap<ShadowPath, HeldLock>>,
+}
+
+impl LockTable {
+    /// Creates a table holding no locks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Takes a lock of `kind` on `path` for `owner`, replacing the lock the
+    /// owner already holds there.
+    ///
+    /// # Errors
+    /// [`ShadowError::Conflict`] if another owner holds a lock the new one
+    /// excludes.
+    pub fn try_lock(&self, path: &ShadowPath, owner: u64, kind: LockKind) -> Result<()> {
+        let mut held = self.held.lock().unwrap();
+        let blocked = match held.get(path) {
+            None => false,
+            Some(HeldLock::Exclusive(holder)) => *holder != owner,
+            Some(HeldLock::Shared(holders)) => {
+                kind == LockKind::Exclusive && holders.iter().any(|holder| *holder != owner)
+            }
+        };
+        if blocked {
+            return Err(error::conflict(path.clone(), "locked by another owner"));
+        }
+        
+        match kind {
+            LockKind::Exclusive => {
+                held.insert(path.clone(), HeldLock::Exclusive(owner));
+            }
+            LockKind::Shared => match held.get_mut(path) {
+                Some(HeldLock::Shared(holders)) => {
+                    holders.insert(owner);
+                }
+                _ => {
+                    held.insert(path.clone(), HeldLock::Shared(BTreeSet::from([owner])));
+                }
+            },
+        }
+        Ok(())
+    }
+    
+    /// Releases the lock `owner` holds on `path`.
+    ///
+    /// # Returns
+    /// false if the owner held no lock there
+    pub fn unlock(&self, path: &ShadowPath, owner: u64) -> bool {
+        let mut held = self.held.lock().unwrap();
+        let released = match held.get_mut(path) {
+            Some(HeldLock::Exclusive(holder)) if *holder == owner => {
+                held.remove(path);
+                return true;
+            }
+            Some(HeldLock::Shared(holders)) => holders.remove(&owner) && holders.is_empty(),
+            _ => return false,
+        };
+        if released {
+            held.remove(path);
+        }
+        true
+    }
+    
+    /// Checks whether any owner holds a lock on `path`.
+    pub fn is_locked(&self, path: &ShadowPath) -> bool {
+        self.held.lock().unwrap().contains_key(path)
+    }
+}
+
+/// What a path of a mount resolves to.
+enum Resolved {
+    Override(Arc<OverrideEntry>),
+    Transformed(std::fs::Metadata, Bytes),
+    Delta(std::fs::Metadata, u64),
+    Source(std::fs::Metadata),
+}
+
+/// Most threads stating the source for one [`MountContext::metadata_many`].
+const MAX_STAT_THREADS: usize = 8;
+
+/// Fewest source paths [`MountContext::metadata_many`] spreads over threads.
+const PARALLEL_STAT_MIN: usize = 64;
+
+/// How far the store resolves a path before the source is consulted.
+enum Stored {
+    /// The store decides: the override from the store or a rule, or none
+    /// for a tombstone or deleted ancestor
+    Resolved(Option<Arc<OverrideEntry>>),
+    /// The source decides
+    Source,
+}
+
+/// The store, source directory, locks and open files of one mount.
+#[derive(Clone)]
+pub struct MountContext {
+    /// Store holding the overrides of the mount
+    pub store: Arc<OverrideStore>,
+    
+    /// Source directory the mount shadows
+    pub source: PathBuf,
+    
+    /// Whether the mount refuses changes
+    pub read_only: bool,
+    
+    /// Advisory locks held on paths of the mount
+    pub locks: Arc<LockTable>,
+    
+    /// Files open on the mount, by handle
+    pub handles: Arc<HandleTable>,
+    
+    /// Metadata reported in place of what differs between machines, for a
+    /// deterministic mount
+    pub determinism: Option<Determinism>,
+    
+    /// Entries left out of listings, and possibly lookups
+    pub hidden_files: HiddenFiles,
+}
+
+impl MountContext {
+    /// Creates the context of a mount of `source` holding no locks.
+    pub fn new(store: Arc<OverrideStore>, source: impl Into<PathBuf>, read_only: bool) -> Self {
+        Self {
+            store,
+            source: source.into(),
+            read_only,
+            locks: Arc::new(LockTable::new()),
+            handles: Arc::new(HandleTable::new()),
+            determinism: None,
+            hidden_files: HiddenFiles::default(),
+        }
+    }
+    
+    /// Reports metadata as `determinism` asks instead of as stored.
+    pub fn with_determinism(mut self, determinism: Determinism) -> Self {
+        self.determinism = Some(determinism);
+        self
+    }
+    
+    /// Hides the entries `hidden` matches.
+    pub fn with_hidden_files(mut self, hidden: HiddenFiles) -> Self {
+        self.hidden_files = hidden;
+        self
+    }
+    
+    /// Reads up to `size` bytes at `offset` of the file at `path`; reads
+    /// past the end of the file return fewer bytes.
+    pub fn read(&self, path: &ShadowPath, offset: u64, size: usize) -> Result<Bytes> {
+        self.store.check_access(path, AccessOperation::Read)?;
+        match self.resolve(path)? {
+            Some(Resolved::Override(entry)) => {
+                entry.read_file_range(offset, size)?.ok_or_else(|| error::is_a_directory(path.clone()))
+            }
+            Some(Resolved::Transformed(_, content)) => Ok(slice(&content, offset, size)),
+            Some(Resolved::Delta(..)) => self.store.read_range(path, offset, size),
+            Some(Resolved::Source(metadata)) if metadata.is_dir() => Err(error::is_a_directory(path.clone())),
+            Some(Resolved::Source(_)) => self.store.read_source(&source_path(&self.source, path), offset, size)
+                .map_err(|e| ShadowError::from_io_error_with_operation(e, path, "read")),
+            None => Err(error::not_found(path.clone())),
+        }
+    }
+    
+    /// Writes `data` at `offset` of the file at `path`.
+    ///
+    /// Source files keep only the written ranges until the store compacts
+    /// them; a transformed file first stores its transformed content.
+    ///
+    /// # Returns
+    /// Number of bytes written
+    pub fn write(&self, path: &ShadowPath, offset: u64, data: &[u8]) -> Result<usize> {
+        self.check_writable(path, "write")?;
+        let original_metadata = self.prepare_in_place_change(path)?;
+        self.store.write_range(path.clone(), source_path(&self.source, path), offset, data, original_metadata)?;
+        Ok(data.len())
+    }
+    
+    /// Sets the length of the file at `path` to `len`, cutting it or
+    /// extending it with zeros.
+    ///
+    /// Like writes, truncating a source file keeps only its new length
+    /// until the store compacts it; see [`OverrideStore::truncate`].
+    pub fn truncate(&self, path: &ShadowPath, len: u64) -> Result<()> {
+        self.check_writable(path, "truncate")?;
+        let original_metadata = self.prepare_in_place_change(path)?;
+        self.store.truncate(path.clone(), source_path(&self.source, path), len, original_metadata)
+    }
+    
+    /// Checks that the file at `path` can be changed in place, storing the
+    /// content of a transformed file first.
+    ///
+    /// # Returns
+    /// The metadata of the source file the change is made over, if any
+    fn prepare_in_place_change(&self, path: &ShadowPath) -> Result<Option<FileMetadata>> {
+        let original_metadata = match self.resolve(path)? {
+            Some(Resolved::Override(entry)) if entry.is_directory() => return Err(error::is_a_directory(path.clone())),
+            Some(Resolved::Override(entry)) => entry.original_metadata.clone(),
+            Some(Resolved::Source(metadata)) if metadata.is_dir() => return Err(error::is_a_directory(path.clone())),
+            Some(Resolved::Source(metadata)) => Some(source_metadata(&metadata, metadata.len())),
+            Some(Resolved::Delta(metadata, len)) => Some(source_metadata(&metadata, len)),
+            Some(Resolved::Transformed(metadata, content)) => {
+                let original = source_metadata(&metadata, metadata.len());
+                self.store.insert_file(path.clone(), content, Some(original.clone()))?;
+                Some(original)
+            }
+            None => return Err(error::not_found(path.clone())),
+        };
+        Ok(original_metadata)
+    }
+    
+    /// Replaces the content of the file at `path` with `content`, creating
+    /// the file if its parent is a directory, like `std::fs::write`.
+    pub fn write_file(&self, path: &ShadowPath, content: Bytes) -> Result<()> {
+        self.check_writable(path, "write")?;
+        let original_metadata = match self.resolve(path)? {
+            Some(Resolved::Override(entry)) if entry.is_directory() => return Err(error::is_a_directory(path.clone())),
+            Some(Resolved::Override(entry)) => entry.original_metadata.clone(),
+            Some(Resolved::Source(metadata)) if metadata.is_dir() => return Err(error::is_a_directory(path.clone())),
+            Some(Resolved::Source(metadata) | Resolved::Transformed(metadata, _)) => {
+                Some(source_metadata(&metadata, metadata.len()))
+            }
+            Some(Resolved::Delta(metadata, len)) => Some(source_metadata(&metadata, len)),
+            None => {
+                self.check_creatable(path)?;
+                None
+            }
+        };
+        self.store.insert_file(path.clone(), content, original_metadata)
+    }
+    
+    /// Creates the file `path` holding `content`.
+    ///
+    /// # Errors
+    /// [`ShadowError::AlreadyExists`] if the path exists, and
+    /// [`ShadowError::NotFound`] or [`ShadowError::NotADirectory`] if its
+    /// parent is not a directory of the mount.
+    pub fn create_file(&self, path: &ShadowPath, content: Bytes) -> Result<()> {
+        self.check_creatable(path)?;
+        self.store.insert_file(path.clone(), content, None)
+    }
+    
+    /// Creates the directory `path`, failing like [`MountContext::create_file`].
+    pub fn create_directory(&self, path: &ShadowPath) -> Result<()> {
+        self.check_creatable(path)?;
+        self.store.insert_directory(path.clone(), None)
+    }
+    
+    /// Deletes the file or empty directory at `path`, leaving a tombstone.
+    pub fn delete(&self, path: &ShadowPath) -> Result<()> {
+        self.check_writable(path, "delete")?;
+        self.store.check_access(path, AccessOperation::Delete)?;
+        if self.metadata(path)?.file_type == FileType::Directory && !self.read_directory(path)?.is_empty() {
+            return Err(error::directory_not_empty(path.clone()));
+        }
+        self.store.mark_deleted(path.clone())
+    }
+    
+    /// Moves `from` to `to`, replacing a file there.
+    pub fn rename(&self, from: &ShadowPath, to: &ShadowPath) -> Result<()> {
+        self.check_writable(from, "rename")?;
+        self.store.rename(from, to.clone(), RenameOptions::with_source(&self.source))
+    }
+    
+    /// Returns the metadata of `path`.
+    pub fn metadata(&self, path: &ShadowPath) -> Result<FileMetadata> {
+        let resolved = self.resolve(path)?;
+        self.resolved_metadata(path, resolved)
+    }
+    
+    /// Returns the metadata of each of `paths`, in order, as
+    /// [`MountContext::metadata`] would.
+    ///
+    /// Overrides are looked up in one pass over the store's shards, then the
+    /// paths they leave to the source are stat'ed in one round, spread over
+    /// up to [`MAX_STAT_THREADS`] threads when there are many.
+    pub fn metadata_many(&self, paths: &[ShadowPath]) -> Vec<Result<FileMetadata>> {
+        let mut results = Vec::with_capacity(paths.len());
+        let mut pending = Vec::new();
+        for (path, entry) in paths.iter().zip(self.store.get_many(paths)) {
+            results.push(match self.resolve_stored(path, entry) {
+                Ok(Stored::Resolved(entry)) => Some(self.resolved_metadata(path, entry.map(Resolved::Override))),
+                Ok(Stored::Source) => {
+                    pending.push(source_path(&self.source, path));
+                    None
+                }
+                Err(e) => Some(Err(e)),
+            });
+        }
+        
+        let mut stats = stat_all(pending).into_iter();
+        results
+            .into_iter()
+            .zip(paths)
+            .map(|(result, path)| {
+                result.unwrap_or_else(|| {
+                    let stat = stats.next().expect("one stat per path left to the source");
+                    self.resolve_source(path, stat).and_then(|resolved| self.resolved_metadata(path, resolved))
+                })
+            })
+            .collect()
+    }
+    
+    /// Returns the metadata of `path` as resolved.
+    fn resolved_metadata(&self, path: &ShadowPath, resolved: Option<Resolved>) -> Result<FileMetadata> {
+        let metadata = match resolved {
+            Some(Resolved::Override(entry)) => entry.metadata(),
+            Some(Resolved::Transformed(metadata, content)) => source_metadata(&metadata, content.len() as u64),
+            Some(Resolved::Delta(metadata, len)) => source_metadata(&metadata, len),
+            Some(Resolved::Source(metadata)) => source_metadata(&metadata, metadata.len()),
+            None => return Err(error::not_found(path.clone())),
+        };
+        Ok(self.reported(path, metadata))
+    }
+    
+    /// Returns `metadata` of `path` as the mount reports it.
+    fn reported(&self, path: &ShadowPath, mut metadata: FileMetadata) -> FileMetadata {
+        if let Some(determinism) = &self.determinism {
+            determinism.apply(path, &mut metadata);
+        }
+        metadata
+    }
+    
+    /// Lists the directory at `path`, merging source entries with overrides
+    /// and dropping tombstones, sorted by name.
+    ///
+    /// Listing does not count as accessing the entries, so rules are not
+    /// applied to them and transformed files report their source size. With
+    /// a [`ListingCache`](crate::listing_cache::ListingCache) attached to the
+    /// store, listings of directories that did not change are not merged again.
+    pub fn read_directory(&self, path: &ShadowPath) -> Result<Vec<DirectoryEntry>> {
+        self.store.check_access(path, AccessOperation::Read)?;
+        if self.metadata(path)?.file_type != FileType::Directory {
+            return Err(error::not_a_directory(path.clone()));
+        }
+        
+        let listing = match self.store.listing_cache() {
+            Some(cache) => cache.get_or_list(&self.source, path, || self.merged_listing(path))?,
+            None => self.merged_listing(path)?.into(),
+        };
+        let entries = listing.iter()
+            .map(|entry| DirectoryEntry::new(entry.name.clone(), self.reported(&path.join(&entry.name), entry.metadata.clone())))
+            .collect();
+        Ok(self.hidden_files.filter(path, entries))
+    }
+    
+    /// Lists up to `limit` entries of the directory at `path` whose names
+    /// sort after `cursor`, or from its start if `cursor` is `None`.
+    ///
+    /// Pass the `next` of the page as the cursor of the next call. Only the
+    /// names of the directory and the entries of the page are held, so huge
+    /// directories list in bounded memory; the listing cache is not used.
+    /// Hidden entries and tombstones are skipped without counting against
+    /// `limit`.
+    pub fn list_directory_paged(&self, path: &ShadowPath, cursor: Option<&str>, limit: usize) -> Result<DirectoryPage> {
+        let names = self.directory_names(path)?;
+        self.directory_page(path, &names, cursor, limit)
+    }
+    
+    /// Returns the names in the directory at `path`, tombstones included,
+    /// for [`directory_page`](Self::directory_page) to page through.
+    pub fn directory_names(&self, path: &ShadowPath) -> Result<BTreeSet<String>> {
+        self.store.check_access(path, AccessOperation::Read)?;
+        if self.metadata(path)?.file_type != FileType::Directory {
+            return Err(error::not_a_directory(path.clone()));
+        }
+        MergedDirectoryView::new(&self.store, &self.source, path.clone()).names()
+    }
+    
+    /// Lists a page of the directory at `path` like
+    /// [`list_directory_paged`](Self::list_directory_paged), from the
+    /// `names` [`directory_names`](Self::directory_names) returned, so a
+    /// directory paged through is read once.
+    pub fn directory_page(&self, path: &ShadowPath, names: &BTreeSet<String>, cursor: Option<&str>, limit: usize) -> Result<DirectoryPage> {
+        let view = MergedDirectoryView::new(&self.store, &self.source, path.clone());
+        let limit = limit.max(1);
+        let mut page = DirectoryPage { entries: Vec::new(), next: cursor.map(str::to_string) };
+        loop {
+            let part = view.page_of(names, page.next.as_deref(), limit - page.entries.len())?;
+            let entries = part.entries.into_iter()
+                .map(|entry| DirectoryEntry::new(entry.name.clone(), self.reported(&path.join(&entry.name), entry.metadata)))
+                .collect();
+            page.entries.extend(self.hidden_files.filter(path, entries));
+            page.next = part.next;
+            if page.next.is_none() || page.entries.len() >= limit {
+                return Ok(page);
+            }
+        }
+    }
+    
+    /// Streams the entries of the directory at `path`, sorted by name,
+    /// listing `page_size` of them at a time on the blocking pool.
+    ///
+    /// The stream must be polled within a Tokio runtime.
+    pub fn directory_stream(&self, path: &ShadowPath, page_size: usize) -> DirectoryStream {
+        DirectoryStream {
+            context: self.clone(),
+            path: path.clone(),
+            page_size,
+            names: None,
+            buffered: Vec::new().into_iter(),
+            cursor: None,
+            done: false,
+            pending: None,
+        }
+    }
+    
+    /// Merges the source entries of the directory at `path` with its
+    /// overrides, as stored.
+    fn merged_listing(&self, path: &ShadowPath) -> Result<Vec<DirectoryEntry>> {
+        MergedDirectoryView::new(&self.store, &self.source, path.clone()).entries()
+    }
+    
+    /// Takes an advisory lock on the file at `path` for `owner`.
+    ///
+    /// See [`LockTable::try_lock`].
+    pub fn lock(&self, path: &ShadowPath, owner: u64, kind: LockKind) -> Result<()> {
+        if self.resolve(path)?.is_none() {
+            return Err(error::not_found(path.clone()));
+        }
+        self.locks.try_lock(path, owner, kind)
+    }
+    
+    /// Releases the lock `owner` holds on `path`, returning false if it held none.
+    pub fn unlock(&self, path: &ShadowPath, owner: u64) -> bool {
+        self.locks.unlock(path, owner)
+    }
+    
+    /// Resolves `path`, applying the store's rules on first access.
+    fn resolve(&self, path: &ShadowPath) -> Result<Option<Resolved>> {
+        match self.resolve_stored(path, self.store.get(path))? {
+            Stored::Resolved(entry) => Ok(entry.map(Resolved::Override)),
+            Stored::Source => self.resolve_source(path, std::fs::symlink_metadata(source_path(&self.source, path))),
+        }
+    }
+    
+    /// Resolves `path` as far as the store can, given its override `entry`.
+    fn resolve_stored(&self, path: &ShadowPath, entry: Option<Arc<OverrideEntry>>) -> Result<Stored> {
+        if self.hidden_files.hides_lookup(path, None) {
+            return Ok(Stored::Resolved(None));
+        }
+        if let Some(entry) = entry {
+            let visible = !entry.is_deleted() && !self.hidden_files.hides_lookup(path, Some(&entry.override_metadata));
+            return Ok(Stored::Resolved(visible.then_some(entry)));
+        }
+        let mut ancestor = path.parent();
+        while let Some(parent) = ancestor {
+            if self.store.is_deleted(&parent) {
+                return Ok(Stored::Resolved(None));
+            }
+            ancestor = parent.parent();
+        }
+        if let Some(entry) = self.store.apply_rules(path, &self.source)? {
+            let visible = !self.hidden_files.hides_lookup(path, Some(&entry.override_metadata));
+            return Ok(Stored::Resolved(visible.then_some(entry)));
+        }
+        Ok(Stored::Source)
+    }
+    
+    /// Resolves `path`, left to the source, from the result of stating it.
+    fn resolve_source(&self, path: &ShadowPath, stat: std::io::Result<std::fs::Metadata>) -> Result<Option<Resolved>> {
+        let metadata = match stat {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ShadowError::from_io_error_with_operation(e, path, "stat")),
+        };
+        if self.hidden_files.hides_lookup(path, Some(&source_metadata(&metadata, metadata.len()))) {
+            return Ok(None);
+        }
+        if metadata.is_file() {
+            if let Some(content) = self.store.transform_source(path, &self.source)? {
+                return Ok(Some(Resolved::Transformed(metadata, content)));
+            }
+        }
+        Ok(Some(match self.store.delta_info(path) {
+            Some(delta) => Resolved::Delta(metadata, delta.len),
+            None => Resolved::Source(metadata),
+        }))
+    }
+    
+    pub(crate) fn check_writable(&self, path: &ShadowPath, operation: &str) -> Result<()> {
+        if self.read_only {
+            return Err(error::read_only_filesystem(path.clone(), operation));
+        }
+        Ok(())
+    }
+    
+    /// Checks that `path` does not exist and its parent is a directory.
+    fn check_creatable(&self, path: &ShadowPath) -> Result<()> {
+        self.check_writable(path, "create")?;
+        if self.resolve(path)?.is_some() {
+            return Err(error::already_exists(path.clone()));
+        }
+        if let Some(parent) = path.parent() {
+            if self.metadata(&parent)?.file_type != FileType::Directory {
+                return Err(error::not_a_directory(parent));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A page of a listing together with the names it was paged from.
+type FetchedPage = Result<(Arc<BTreeSet<String>>, DirectoryPage)>;
+
+/// Entries of a directory of a mount, listed a page at a time; see
+/// [`MountContext::directory_stream`].
+///
+/// The names of the directory are read when the first page is listed, and
+/// later pages are taken from them. A failed page ends the stream after its
+/// error.
+pub struct DirectoryStream {
+    context: MountContext,
+    path: ShadowPath,
+    page_size: usize,
+    names: Option<Arc<BTreeSet<String>>>,
+    buffered: std::vec::IntoIter<DirectoryEntry>,
+    cursor: Option<String>,
+    done: bool,
+    pending: Option<JoinHandle<FetchedPage>>,
+}
+
+impl DirectoryStream {
+    /// Lists the next page on the blocking pool.
+    fn fetch(&self) -> JoinHandle<FetchedPage> {
+        let context = self.context.clone();
+        let path = self.path.clone();
+        let names = self.names.clone();
+        let cursor = self.cursor.clone();
+        let page_size = self.page_size;
+        task::spawn_blocking("directory-stream", move || {
+            let names = match names {
+                Some(names) => names,
+                None => Arc::new(context.directory_names(&path)?),
+            };
+            let page = context.directory_page(&path, &names, cursor.as_deref(), page_size)?;
+            Ok((names, page))
+        })
+    }
+}
+
+impl Stream for DirectoryStream {
+    type Item = Result<DirectoryEntry>;
+    
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(entry) = self.buffered.next() {
+                return Poll::Ready(Some(Ok(entry)));
+            }
+            if self.done {
+                return Poll::Ready(None);
+            }
+            if self.pending.is_none() {
+                self.pending = Some(self.fetch());
+            }
+            let fetched = ready!(Pin::new(self.pending.as_mut().unwrap()).poll(cx));
+            self.pending = None;
+            match fetched {
+                Ok(Ok((names, page))) => {
+                    self.names = Some(names);
+                    self.done = page.next.is_none();
+                    self.cursor = page.next;
+                    self.buffered = page.entries.into_iter();
+                }
+                Ok(Err(e)) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(error::cancelled(format!("listing of {}: {}", self.path, e)))));
+                }
+            }
+        }
+    }
+}
+
+/// Stats `paths` in order without following symlinks, on several threads
+/// when there are enough of them.
+fn stat_all(paths: Vec<PathBuf>) -> Vec<std::io::Result<std::fs::Metadata>> {
+    let threads = std::thread::available_parallelism().map_or(1, NonZeroUsize::get).min(MAX_STAT_THREADS);
+    if threads == 1 || paths.len() < PARALLEL_STAT_MIN {
+        return paths.iter().map(std::fs::symlink_metadata).collect();
+    }
+    let chunk_size = (paths.len() + threads - 1) / threads;
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(std::fs::symlink_metadata).collect::<Vec<_>>()))
+            .collect();
+        workers.into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    })
+}
+
+/// Returns up to `size` bytes of `data` at `offset`.
+fn slice(data: &Bytes, offset: u64, size: usize) -> Bytes {
+    let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+    let end = start.saturating_add(size).min(data.len());
+    data.slice(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    #[test]
+    fn test_lock_table() {
+        let locks = LockTable::new();
+        let file = path("/a.txt");
+        locks.try_lock(&file, 1, LockKind::Shared).unwrap();
+        locks.try_lock(&file, 2, LockKind::Shared).unwrap();
+        assert!(locks.try_lock(&file, 1, LockKind::Exclusive).is_err());
+        
+        assert!(locks.unlock(&file, 2));
+        locks.try_lock(&file, 1, LockKind::Exclusive).unwrap();
+        assert!(locks.try_lock(&file, 2, LockKind::Shared).is_err());
+        assert!(locks.unlock(&file, 1));
+        assert!(!locks.is_locked(&file));
+        assert!(!locks.unlock(&file, 1));
+    }
+    
+    #[test]
+    fn test_operations_pass_through_to_source() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "pub fn old() {}").unwrap();
+        std::fs::write(source.path().join("README"), "readme").unwrap();
+        let context = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false);
+        
+        assert_eq!(context.read(&path("/src/lib.rs"), 4, 2).unwrap(), Bytes::from("fn"));
+        assert_eq!(context.write(&path("/src/lib.rs"), 7, b"FN").unwrap(), 2);
+        assert_eq!(context.read(&path("/src/lib.rs"), 0, 100).unwrap(), Bytes::from("pub fn FNd() {}"));
+        assert_eq!(std::fs::read_to_string(source.path().join("src/lib.rs")).unwrap(), "pub fn old() {}");
+        
+        context.create_directory(&path("/docs")).unwrap();
+        context.create_file(&path("/docs/notes.md"), Bytes::from("notes")).unwrap();
+        assert!(context.create_file(&path("/README"), Bytes::new()).is_err());
+        assert!(context.create_file(&path("/missing/file"), Bytes::new()).is_err());
+        assert!(context.delete(&path("/docs")).is_err());
+        
+        context.rename(&path("/README"), &path("/docs/README")).unwrap();
+        context.delete(&path("/src/lib.rs")).unwrap();
+        let names = |dir: &str| -> Vec<String> {
+            context.read_directory(&path(dir)).unwrap().into_iter().map(|entry| entry.name).collect()
+        };
+        assert_eq!(names("/"), ["docs", "src"]);
+        assert_eq!(names("/docs"), ["README", "notes.md"]);
+        assert!(names("/src").is_empty());
+        assert_eq!(context.metadata(&path("/docs/README")).unwrap().size, 6);
+        
+        context.delete(&path("/src")).unwrap();
+        assert!(context.read(&path("/src/lib.rs"), 0, 1).is_err());
+        context.lock(&path("/docs/notes.md"), 7, LockKind::Exclusive).unwrap();
+        assert!(context.lock(&path("/src"), 7, LockKind::Shared).is_err());
+        
+        let read_only = MountContext::new(Arc::clone(&context.store), source.path(), true);
+        assert!(read_only.write(&path("/docs/notes.md"), 0, b"x").is_err());
+    }
+    
+    #[test]
+    fn test_hidden_entries_leave_listings() {
+        use crate::hidden::HiddenLookups;
+        
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join(".git")).unwrap();
+        std::fs::write(source.path().join(".git/HEAD"), "ref").unwrap();
+        std::fs::write(source.path().join("main.rs"), "fn main() {}").unwrap();
+        let store = Arc::new(OverrideStore::with_defaults());
+        let hidden = HiddenFiles::dotfiles().with_glob("*.tmp");
+        let context = MountContext::new(Arc::clone(&store), source.path(), false).with_hidden_files(hidden.clone());
+        context.create_file(&path("/build.tmp"), Bytes::from("tmp")).unwrap();
+        
+        let names: Vec<String> = context.read_directory(&path("/")).unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["main.rs"]);
+        assert_eq!(context.read(&path("/.git/HEAD"), 0, 3).unwrap(), Bytes::from("ref"));
+        assert!(context.metadata(&path("/build.tmp")).is_ok());
+        
+        let not_found = MountContext::new(store, source.path(), false)
+            .with_hidden_files(hidden.with_lookups(HiddenLookups::NotFound));
+        assert!(matches!(not_found.read(&path("/.git/HEAD"), 0, 3), Err(ShadowError::NotFound { .. })));
+        assert!(not_found.metadata(&path("/build.tmp")).is_err());
+        assert!(not_found.metadata_many(&[path("/.git"), path("/main.rs")])[0].is_err());
+        assert!(not_found.metadata(&path("/main.rs")).is_ok());
+    }
+    
+    #[tokio::test]
+    async fn test_paged_listings_match_the_full_listing() {
+        let source = TempDir::new().unwrap();
+        for i in 0..10 {
+            std::fs::write(source.path().join(format!("{:02}.rs", i)), "x").unwrap();
+        }
+        std::fs::write(source.path().join(".hidden"), "x").unwrap();
+        let context = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false)
+            .with_hidden_files(HiddenFiles::dotfiles());
+        context.delete(&path("/03.rs")).unwrap();
+        context.create_file(&path("/10.rs"), Bytes::from("new")).unwrap();
+        let full: Vec<String> = context.read_directory(&path("/")).unwrap().into_iter().map(|entry| entry.name).collect();
+        
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = context.list_directory_paged(&path("/"), cursor.as_deref(), 3).unwrap();
+            assert!(page.entries.len() == 3 || page.next.is_none());
+            paged.extend(page.entries.into_iter().map(|entry| entry.name));
+            cursor = page.next;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(paged, full);
+        assert!(context.list_directory_paged(&path("/01.rs"), None, 3).is_err());
+        
+        let mut stream = context.directory_stream(&path("/"), 4);
+        let mut streamed = Vec::new();
+        while let Some(entry) = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            streamed.push(entry.unwrap().name);
+        }
+        assert_eq!(streamed, full);
+        
+        let mut missing = context.directory_stream(&path("/missing"), 4);
+        assert!(std::future::poll_fn(|cx| Pin::new(&mut missing).poll_next(cx)).await.unwrap().is_err());
+        assert!(std::future::poll_fn(|cx| Pin::new(&mut missing).poll_next(cx)).await.is_none());
+    }
+    
+    #[test]
+    fn test_metadata_many_matches_metadata() {
+        let source = TempDir::new().unwrap();
+        let mut paths = vec![path("/"), path("/override.rs"), path("/gone.rs"), path("/missing.rs")];
+        for i in 0..PARALLEL_STAT_MIN {
+            std::fs::write(source.path().join(format!("{}.rs", i)), "x".repeat(i)).unwrap();
+            paths.push(path(&format!("/{}.rs", i)));
+        }
+        std::fs::write(source.path().join("gone.rs"), "gone").unwrap();
+        let context = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false);
+        context.create_file(&path("/override.rs"), Bytes::from("override")).unwrap();
+        context.delete(&path("/gone.rs")).unwrap();
+        context.write(&path("/3.rs"), 10, b"delta").unwrap();
+        
+        let batched = context.metadata_many(&paths);
+        assert_eq!(batched.len(), paths.len());
+        for (path, metadata) in paths.iter().zip(batched) {
+            match (metadata, context.metadata(path)) {
+                (Ok(batched), Ok(single)) => assert_eq!(batched, single, "{}", path),
+                (Err(_), Err(_)) => {}
+                (batched, single) => panic!("{}: {:?} != {:?}", path, batched, single),
+            }
+        }
+        assert_eq!(context.metadata(&path("/3.rs")).unwrap().size, 15);
+        assert!(context.metadata(&path("/gone.rs")).is_err());
+    }
+    
+    #[test]
+    fn test_cached_listings_see_changes() {
+        use crate::listing_cache::{ListingCache, ListingCacheConfig};
+        use crate::source_watch::{SourceChange, SourceChangeHandler};
+        use crate::watch::ChangeKind;
+        
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "lib").unwrap();
+        let store = Arc::new(OverrideStore::with_defaults());
+        store.set_listing_cache(Arc::new(ListingCache::new(ListingCacheConfig::default())));
+        let context = MountContext::new(Arc::clone(&store), source.path(), false);
+        let list = || -> Vec<(String, u64)> {
+            context.read_directory(&path("/src")).unwrap().into_iter().map(|entry| (entry.name, entry.metadata.size)).collect()
+        };
+        
+        assert_eq!(list(), [("lib.rs".to_string(), 3)]);
+        assert_eq!(list(), [("lib.rs".to_string(), 3)]);
+        
+        // Overrides, range writes and deletions show up at once
+        context.create_file(&path("/src/main.rs"), Bytes::from("main")).unwrap();
+        context.write(&path("/src/lib.rs"), 3, b"rary").unwrap();
+        assert_eq!(list(), [("lib.rs".to_string(), 7), ("main.rs".to_string(), 4)]);
+        context.delete(&path("/src/main.rs")).unwrap();
+        assert_eq!(list(), [("lib.rs".to_string(), 7)]);
+        
+        // Source changes show up once the watcher reports them
+        std::fs::write(source.path().join("src/build.rs"), "build").unwrap();
+        assert_eq!(list().len(), 1);
+        SourceChangeHandler::new(Arc::clone(&store)).handle(SourceChange {
+            path: path("/src/build.rs"),
+            kind: ChangeKind::Created,
+            is_directory: false,
+            recursive: false,
+        });
+        assert_eq!(list().len(), 2);
+        
+        let stats = store.listing_cache().unwrap().stats();
+        assert_eq!((stats.hits, stats.misses), (2, 4));
+    }
+    
+    #[test]
+    fn test_write_file_replaces_or_creates() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "pub fn old() {}").unwrap();
+        let context = MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false);
+        
+        context.write(&path("/src/lib.rs"), 0, b"PUB").unwrap();
+        context.write_file(&path("/src/lib.rs"), Bytes::from("new")).unwrap();
+        assert_eq!(context.read(&path("/src/lib.rs"), 0, 100).unwrap(), Bytes::from("new"));
+        assert_eq!(context.store.get(&path("/src/lib.rs")).unwrap().original_metadata.as_ref().unwrap().size, 15);
+        
+        context.write_file(&path("/src/main.rs"), Bytes::from("fn main() {}")).unwrap();
+        assert_eq!(context.metadata(&path("/src/main.rs")).unwrap().size, 12);
+        assert!(context.write_file(&path("/src"), Bytes::new()).is_err());
+        assert!(context.write_file(&path("/missing/file"), Bytes::new()).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Guards on the shape of the tree a mount holds.
+//!
+//! A workload creating hundreds of thousands of files in one directory, or
+//! nesting directories thousands deep, slows every listing through the
+//! [`DirectoryCache`](crate::override_store::directory::DirectoryCache) long
+//! before memory runs out, and a name longer than the backend accepts fails
+//! with whatever opaque error the platform gives. [`PathLimits`] caps the
+//! entries one directory may hold, the depth of a path and the length of a
+//! name, and a write past any of them fails up front with
+//! [`ShadowError::LimitExceeded`] naming the limit and the value that broke
+//! it.
+//!
+//! Only new overrides are checked: replacing an existing one and deleting
+//! are always allowed, and loading persisted state is never refused.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use shadowfs_core::error::{LimitKind, ShadowError};
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::path_limits::PathLimits;
+//! use shadowfs_core::types::ShadowPath;
+//!
+//! let store = OverrideStore::with_defaults();
+//! store.set_path_limits(PathLimits { max_path_depth: Some(2), ..PathLimits::default() });
+//!
+//! store.insert_file(ShadowPath::from("/a/b"), Bytes::from("b"), None).unwrap();
+//! let err = store.insert_file(ShadowPath::from("/a/b/c"), Bytes::from("c"), None).unwrap_err();
+//! assert!(matches!(err, ShadowError::LimitExceeded { limit: LimitKind::PathDepth, actual: 3, max: 2, .. }));
+//! ```
+
+use crate::error::{limit_exceeded, LimitKind, ShadowError};
+use crate::override_store::{OverrideContent, OverrideStore};
+use crate::types::{Platform, ShadowPath};
+use std::path::Component;
+
+/// Longest name, in [`NameUnits`], that FUSE, ProjFS and FSKit accept.
+pub const PLATFORM_NAME_MAX: u64 = 255;
+
+/// What the length of a name is counted in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum NameUnits {
+    /// UTF-8 bytes, as Linux and macOS count them
+    #[default]
+    Bytes,
+    
+    /// UTF-16 code units, as Windows counts them
+    Utf16,
+}
+
+impl NameUnits {
+    /// Returns the length of `name` in these units.
+    pub fn length(self, name: &str) -> u64 {
+        match self {
+            Self::Bytes => name.len() as u64,
+            Self::Utf16 => name.encode_utf16().count() as u64,
+        }
+    }
+}
+
+/// Limits on the tree a mount holds; `None` leaves a dimension unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PathLimits {
+    /// Overrides one directory may hold, tombstones included
+    pub max_directory_entries: Option<u64>,
+    
+    /// Components a path may have below the root
+    pub max_path_depth: Option<u64>,
+    
+    /// Longest name, in `name_units`
+    pub max_name_length: Option<u64>,
+    
+    /// What name lengths are counted in
+    pub name_units: NameUnits,
+}
+
+impl PathLimits {
+    /// Returns the limits the backend of `platform` imposes on names, with
+    /// directories and depth left unlimited.
+    pub fn for_platform(platform: Platform) -> Self {
+        Self {
+            max_name_length: Some(PLATFORM_NAME_MAX),
+            name_units: match platform {
+                Platform::Windows => NameUnits::Utf16,
+                Platform::MacOS | Platform::Linux => NameUnits::Bytes,
+            },
+            ..Self::default()
+        }
+    }
+    
+    /// Returns true if no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_directory_entries.is_none() && self.max_path_depth.is_none() && self.max_name_length.is_none()
+    }
+}
+
+/// Returns the number of components of `path` below the root.
+fn depth(path: &ShadowPath) -> u64 {
+    path.as_path()
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count() as u64
+}
+
+impl OverrideStore {
+    /// Limits the shape of what may be written through the store from now
+    /// on.
+    ///
+    /// Overrides already held are kept even if they exceed the new limits.
+    pub fn set_path_limits(&self, limits: PathLimits) {
+        *self.path_limits.write().unwrap() = limits;
+    }
+    
+    /// Returns the limits new overrides are checked against.
+    pub fn path_limits(&self) -> PathLimits {
+        *self.path_limits.read().unwrap()
+    }
+    
+    /// Fails with [`ShadowError::LimitExceeded`] if storing `content` as a
+    /// new override at `path` would go past a limit.
+    pub(crate) fn check_path_limits(&self, path: &ShadowPath, content: &OverrideContent) -> Result<(), ShadowError> {
+        let limits = self.path_limits();
+        if limits.is_unlimited() || matches!(content, OverrideContent::Deleted) {
+            return Ok(());
+        }
+        if self.entries.contains_key(path) || self.peek_spilled(path).is_some() {
+            return Ok(());
+        }
+        
+        if let Some(max) = limits.max_name_length {
+            let length = path.file_name().map_or(0, |name| limits.name_units.length(&name));
+            if length > max {
+                return Err(limit_exceeded(path.clone(), LimitKind::NameLength, length, max));
+            }
+        }
+        if let Some(max) = limits.max_path_depth {
+            let depth = depth(path);
+            if depth > max {
+                return Err(limit_exceeded(path.clone(), LimitKind::PathDepth, depth, max));
+            }
+        }
+        if let (Some(max), Some(parent)) = (limits.max_directory_entries, path.parent()) {
+            let entries = self.directory_cache.child_count(&parent) as u64 + 1;
+            if entries > max {
+                return Err(limit_exceeded(parent, LimitKind::DirectoryEntries, entries, max));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_limits_are_enforced() {
+        let store = OverrideStore::with_defaults();
+        store.set_path_limits(PathLimits {
+            max_directory_entries: Some(2),
+            max_path_depth: Some(3),
+            max_name_length: Some(8),
+            name_units: NameUnits::Bytes,
+        });
+        
+        store.insert_directory(ShadowPath::from("/dir"), None).unwrap();
+        store.insert_file(ShadowPath::from("/dir/a.txt"), Bytes::from("a"), None).unwrap();
+        store.insert_file(ShadowPath::from("/dir/b.txt"), Bytes::from("b"), None).unwrap();
+        let err = store.insert_file(ShadowPath::from("/dir/c.txt"), Bytes::from("c"), None).unwrap_err();
+        assert!(matches!(&err, ShadowError::LimitExceeded { path, limit: LimitKind::DirectoryEntries, actual: 3, max: 2 } if path == &ShadowPath::from("/dir")));
+        
+        // Replacing or deleting an override is not a new entry
+        store.insert_file(ShadowPath::from("/dir/a.txt"), Bytes::from("aa"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/dir/b.txt")).unwrap();
+        
+        store.insert_file(ShadowPath::from("/x/y/z"), Bytes::from("z"), None).unwrap();
+        let err = store.insert_file(ShadowPath::from("/x/y/z/w"), Bytes::from("w"), None).unwrap_err();
+        assert!(matches!(err, ShadowError::LimitExceeded { limit: LimitKind::PathDepth, actual: 4, max: 3, .. }));
+        let err = store.insert_file(ShadowPath::from("/long-name.txt"), Bytes::from("l"), None).unwrap_err();
+        assert_eq!(err.to_string(), "Limit exceeded at /long-name.txt: a name of length 13 would exceed the limit of 8");
+    }
+    
+    #[test]
+    fn test_platform_name_limits() {
+        let name = "é".repeat(200);
+        let windows = PathLimits::for_platform(Platform::Windows);
+        let linux = PathLimits::for_platform(Platform::Linux);
+        assert_eq!(windows.name_units.length(&name), 200);
+        assert_eq!(linux.name_units.length(&name), 400);
+        assert_eq!(linux.max_name_length, Some(255));
+        assert!(!linux.is_unlimited() && PathLimits::default().is_unlimited());
+    }
+}
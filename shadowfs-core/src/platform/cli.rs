@@ -109,7 +109,7 @@ pub async fn run_cli() {
         let linux_detector = LinuxDetector::new();
         if let Ok(fuse) = linux_detector.detect_fuse() {
             if fuse.installed {
-                let version = fuse.version.map(|v| v.to_string()).unwrap_or_else(|| "Unknown".to_string());
+                let version = if fuse.version_string.is_empty() { "Unknown".to_string() } else { fuse.version_string.clone() };
                 content.push(("FUSE", format!("Available (v{})", version), true));
             } else {
                 content.push(("FUSE", "Not Available".to_string(), false));
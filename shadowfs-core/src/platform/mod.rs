@@ -5,6 +5,7 @@ mod linux_detector;
 pub mod install;
 mod capability_test;
 mod compatibility;
+mod preflight;
 pub mod runtime;
 pub mod cli;
 
@@ -12,6 +13,7 @@ pub use detector::*;
 pub use install::*;
 pub use capability_test::*;
 pub use compatibility::*;
+pub use preflight::*;
 // Re-export specific items to avoid name conflicts
 pub use runtime::{
     FeatureType, FeatureStatus, PerformanceMetrics, FeatureChange,
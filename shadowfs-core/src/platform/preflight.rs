@@ -0,0 +1,637 @@
+//! Resource preflight checks run before mounting.
+//!
+//! The capability tests describe what a platform can do in general; the
+//! preflight checks look at the limits that matter for one mount with the
+//! features it uses, such as whether the inotify watch limit covers the
+//! source tree when source watching is enabled. Every check is a
+//! [`CapabilityTest`], so it can also be added to a [`TestSuite`], and comes
+//! with a [`Remediation`] naming the setting to change.
+
+use super::capability_test::{CapabilityTest, Remediation, TestResult};
+use crate::error::ShadowError;
+use crate::types::mount::Platform;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Open-file limit below which mounts may run out of descriptors.
+pub const DEFAULT_MIN_OPEN_FILES: u64 = 4096;
+
+/// Smallest watch limit suggested when the current one is too low.
+const MIN_SUGGESTED_WATCHES: usize = 524_288;
+
+/// Linux procfs file holding the per-user inotify watch limit.
+const MAX_USER_WATCHES: &str = "/proc/sys/fs/inotify/max_user_watches";
+
+/// Entitlement an FSKit module must be signed with.
+const FSKIT_ENTITLEMENT: &str = "com.apple.developer.fskit.fsmodule";
+
+/// Checks that the inotify watch limit covers every directory of the source tree.
+pub struct InotifyWatchLimitTest {
+    source: PathBuf,
+    directories: OnceLock<usize>,
+}
+
+impl InotifyWatchLimitTest {
+    pub fn new(source: PathBuf) -> Self {
+        Self {
+            source,
+            directories: OnceLock::new(),
+        }
+    }
+    
+    /// Counts the directories of the source tree, stopping once `limit` is exceeded.
+    fn directories(&self, limit: usize) -> usize {
+        *self.directories.get_or_init(|| count_directories(&self.source, limit.saturating_add(1)))
+    }
+}
+
+/// Counts `root` and the directories below it, without following symbolic
+/// links, up to `max`.
+pub fn count_directories(root: &Path, max: usize) -> usize {
+    let mut count = 0;
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        count += 1;
+        if count >= max {
+            break;
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                pending.push(entry.path());
+            }
+        }
+    }
+    count
+}
+
+/// Judges a watch limit of `limit` for a tree of `directories` directories.
+///
+/// Other programs share the per-user limit, so using more than half of it is
+/// reported as a warning.
+pub fn watch_limit_result(directories: usize, limit: usize) -> TestResult {
+    if directories > limit {
+        TestResult::Failed {
+            reason: format!(
+                "The source tree has more than {} directories but fs.inotify.max_user_watches is {}; \
+                 changes below the unwatched directories are only found by periodic rescans",
+                limit, limit
+            ),
+            fixable: true,
+        }
+    } else if directories > limit / 2 {
+        TestResult::Warning {
+            message: format!(
+                "Watching {} directories uses more than half of fs.inotify.max_user_watches ({})",
+                directories, limit
+            ),
+        }
+    } else {
+        TestResult::Passed {
+            details: format!("{} directories, watch limit {}", directories, limit),
+        }
+    }
+}
+
+/// Suggests a watch limit leaving room for other programs.
+pub fn suggested_watch_limit(directories: usize) -> usize {
+    directories.saturating_mul(2).next_power_of_two().max(MIN_SUGGESTED_WATCHES)
+}
+
+fn read_watch_limit() -> Option<usize> {
+    std::fs::read_to_string(MAX_USER_WATCHES).ok()?.trim().parse().ok()
+}
+
+impl CapabilityTest for InotifyWatchLimitTest {
+    fn name(&self) -> &'static str {
+        "Inotify Watch Limit"
+    }
+    
+    fn description(&self) -> &'static str {
+        "Check that fs.inotify.max_user_watches covers every directory of the source tree"
+    }
+    
+    fn run(&self) -> TestResult {
+        match read_watch_limit() {
+            Some(limit) => watch_limit_result(self.directories(limit), limit),
+            None => TestResult::Skipped {
+                reason: format!("Cannot read {}", MAX_USER_WATCHES),
+            },
+        }
+    }
+    
+    fn is_critical(&self) -> bool {
+        false // The watcher falls back to periodic rescans
+    }
+    
+    fn remediation(&self) -> Option<Remediation> {
+        let limit = read_watch_limit().unwrap_or(0);
+        let suggested = suggested_watch_limit(self.directories(limit));
+        Some(Remediation {
+            instructions: vec![
+                format!("Raise the inotify watch limit to at least {}", suggested),
+                format!(
+                    "Make it permanent with 'fs.inotify.max_user_watches={}' in /etc/sysctl.d/90-shadowfs.conf",
+                    suggested
+                ),
+            ],
+            documentation_links: vec![
+                "https://man7.org/linux/man-pages/man7/inotify.7.html".to_string(),
+            ],
+            difficulty: 1,
+            requires_admin: true,
+            fix_command: Some(format!("sudo sysctl -w fs.inotify.max_user_watches={}", suggested)),
+        })
+    }
+    
+    fn platform(&self) -> Option<Platform> {
+        Some(Platform::Linux)
+    }
+}
+
+/// Checks the soft limit on open file descriptors.
+pub struct OpenFileLimitTest {
+    required: u64,
+}
+
+impl OpenFileLimitTest {
+    pub fn new(required: u64) -> Self {
+        Self { required }
+    }
+}
+
+/// Returns the soft and hard limits on open file descriptors.
+#[cfg(unix)]
+pub fn open_file_limits() -> Option<(u64, u64)> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    Some((limit.rlim_cur as _, limit.rlim_max as _))
+}
+
+/// Returns the soft and hard limits on open file descriptors.
+#[cfg(not(unix))]
+pub fn open_file_limits() -> Option<(u64, u64)> {
+    None
+}
+
+/// Raises the soft open-file limit to `required`, or as far as the hard
+/// limit allows, returning the new soft limit.
+#[cfg(unix)]
+pub fn raise_open_file_limit(required: u64) -> std::io::Result<u64> {
+    let (soft, hard) = open_file_limits().ok_or_else(std::io::Error::last_os_error)?;
+    if soft >= required {
+        return Ok(soft);
+    }
+    let target = required.min(hard);
+    let limit = libc::rlimit {
+        rlim_cur: target as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(target)
+}
+
+impl CapabilityTest for OpenFileLimitTest {
+    fn name(&self) -> &'static str {
+        "Open File Limit"
+    }
+    
+    fn description(&self) -> &'static str {
+        "Check that enough file descriptors can be opened for the mount"
+    }
+    
+    fn run(&self) -> TestResult {
+        match open_file_limits() {
+            Some((soft, _)) if soft >= self.required => TestResult::Passed {
+                details: format!("Open file limit is {}", soft),
+            },
+            Some((soft, hard)) => TestResult::Failed {
+                reason: format!("Open file limit is {}, below the recommended {}", soft, self.required),
+                // The soft limit can be raised without privileges up to the hard limit
+                fixable: hard >= self.required,
+            },
+            None => TestResult::Skipped {
+                reason: "Open file limits are not available on this platform".to_string(),
+            },
+        }
+    }
+    
+    fn is_critical(&self) -> bool {
+        false
+    }
+    
+    fn remediation(&self) -> Option<Remediation> {
+        let hard = open_file_limits().map_or(0, |(_, hard)| hard);
+        let mut instructions = vec![format!(
+            "Raise the open file limit of the shell starting shadowfs to {}",
+            self.required
+        )];
+        if hard < self.required {
+            instructions.push(format!(
+                "The hard limit is {}; raise it with a 'nofile' entry in /etc/security/limits.conf \
+                 or LimitNOFILE= in the systemd unit",
+                hard
+            ));
+        }
+        Some(Remediation {
+            instructions,
+            documentation_links: vec![
+                "https://man7.org/linux/man-pages/man2/getrlimit.2.html".to_string(),
+            ],
+            difficulty: if hard >= self.required { 1 } else { 2 },
+            requires_admin: hard < self.required,
+            fix_command: Some(format!("ulimit -n {}", self.required)),
+        })
+    }
+}
+
+/// Checks that the Windows Projected File System feature is enabled.
+pub struct ProjFsEnabledTest;
+
+impl CapabilityTest for ProjFsEnabledTest {
+    fn name(&self) -> &'static str {
+        "ProjFS Enabled"
+    }
+    
+    fn description(&self) -> &'static str {
+        "Check that the Windows Projected File System optional feature is enabled"
+    }
+    
+    fn run(&self) -> TestResult {
+        use super::windows_detector::WindowsDetector;
+        
+        match WindowsDetector::new().detect_projfs() {
+            Ok(info) if info.enabled && info.available => TestResult::Passed {
+                details: "Client-ProjFS is enabled".to_string(),
+            },
+            Ok(info) if info.available => TestResult::Failed {
+                reason: "ProjectedFSLib.dll is present but the Client-ProjFS feature is disabled".to_string(),
+                fixable: true,
+            },
+            Ok(_) => TestResult::Failed {
+                reason: "The Client-ProjFS feature is not installed; it needs Windows 10 1809 or later".to_string(),
+                fixable: true,
+            },
+            Err(e) => TestResult::Skipped {
+                reason: format!("{:?}", e),
+            },
+        }
+    }
+    
+    fn is_critical(&self) -> bool {
+        true
+    }
+    
+    fn remediation(&self) -> Option<Remediation> {
+        Some(Remediation {
+            instructions: vec![
+                "Enable 'Windows Projected File System' in Turn Windows features on or off".to_string(),
+                "Restart if Windows asks for it".to_string(),
+            ],
+            documentation_links: vec![
+                "https://learn.microsoft.com/windows/win32/projfs/enabling-windows-projected-file-system".to_string(),
+            ],
+            difficulty: 2,
+            requires_admin: true,
+            fix_command: Some(
+                "powershell -Command \"Enable-WindowsOptionalFeature -Online -FeatureName Client-ProjFS -NoRestart\"".to_string(),
+            ),
+        })
+    }
+    
+    fn platform(&self) -> Option<Platform> {
+        Some(Platform::Windows)
+    }
+}
+
+/// Checks that FSKit is available and the running binary carries the FSKit entitlement.
+pub struct FsKitEntitlementTest;
+
+/// Returns true if the entitlements printed by `codesign --xml` grant FSKit module access.
+pub fn has_fskit_entitlement(entitlements: &str) -> bool {
+    let Some((_, rest)) = entitlements.split_once(FSKIT_ENTITLEMENT) else {
+        return false;
+    };
+    let value = rest.trim_start().trim_start_matches("</key>").trim_start();
+    value.starts_with("<true/>")
+}
+
+impl CapabilityTest for FsKitEntitlementTest {
+    fn name(&self) -> &'static str {
+        "FSKit Entitlement"
+    }
+    
+    fn description(&self) -> &'static str {
+        "Check that FSKit is available and this binary is signed with the FSKit entitlement"
+    }
+    
+    fn run(&self) -> TestResult {
+        use super::macos_detector::MacOSDetector;
+        
+        match MacOSDetector::new().detect_fskit() {
+            Ok(info) if !info.available => {
+                return TestResult::Failed {
+                    reason: "FSKit is not available; it needs macOS 15.4 or later".to_string(),
+                    fixable: false,
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return TestResult::Skipped {
+                    reason: format!("{:?}", e),
+                }
+            }
+        }
+        
+        let Ok(exe) = std::env::current_exe() else {
+            return TestResult::Skipped {
+                reason: "Cannot locate the running binary".to_string(),
+            };
+        };
+        let output = std::process::Command::new("codesign")
+            .args(["-d", "--entitlements", "-", "--xml"])
+            .arg(&exe)
+            .output();
+        match output {
+            Ok(output) if has_fskit_entitlement(&String::from_utf8_lossy(&output.stdout)) => TestResult::Passed {
+                details: format!("{} is signed with {}", exe.display(), FSKIT_ENTITLEMENT),
+            },
+            Ok(_) => TestResult::Failed {
+                reason: format!("{} is not signed with the {} entitlement", exe.display(), FSKIT_ENTITLEMENT),
+                fixable: true,
+            },
+            Err(e) => TestResult::Skipped {
+                reason: format!("Cannot run codesign: {}", e),
+            },
+        }
+    }
+    
+    fn is_critical(&self) -> bool {
+        true
+    }
+    
+    fn remediation(&self) -> Option<Remediation> {
+        Some(Remediation {
+            instructions: vec![
+                format!("Sign the binary with an entitlements file granting {}", FSKIT_ENTITLEMENT),
+                "Enable the extension in System Settings > General > Login Items & Extensions > File System Extensions".to_string(),
+            ],
+            documentation_links: vec![
+                "https://developer.apple.com/documentation/fskit".to_string(),
+            ],
+            difficulty: 3,
+            requires_admin: false,
+            fix_command: Some("codesign --force --sign - --entitlements shadowfs.entitlements $(which shadowfs)".to_string()),
+        })
+    }
+    
+    fn platform(&self) -> Option<Platform> {
+        Some(Platform::MacOS)
+    }
+}
+
+/// Features of a mount that decide which preflight checks apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightOptions {
+    /// Whether the source tree will be watched for changes
+    pub watch_source: bool,
+    
+    /// Open-file limit to require
+    pub min_open_files: u64,
+}
+
+impl Default for PreflightOptions {
+    fn default() -> Self {
+        Self {
+            watch_source: false,
+            min_open_files: DEFAULT_MIN_OPEN_FILES,
+        }
+    }
+}
+
+/// A preflight check that did not pass.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightIssue {
+    /// Name of the check
+    pub check: String,
+    
+    /// Failure or warning reported by the check
+    pub result: TestResult,
+    
+    /// Whether the mount cannot work until the issue is fixed
+    pub critical: bool,
+    
+    /// How to fix the issue
+    pub remediation: Option<Remediation>,
+}
+
+impl PreflightIssue {
+    /// Describes the issue and its fix in one line.
+    pub fn message(&self) -> String {
+        let problem = match &self.result {
+            TestResult::Failed { reason, .. } => reason.as_str(),
+            TestResult::Warning { message } => message.as_str(),
+            TestResult::Passed { details } => details.as_str(),
+            TestResult::Skipped { reason } => reason.as_str(),
+        };
+        let mut message = format!("{}: {}", self.check, problem);
+        if let Some(remediation) = &self.remediation {
+            if let Some(command) = &remediation.fix_command {
+                message.push_str(&format!(" (fix: {})", command));
+            } else if let Some(instruction) = remediation.instructions.first() {
+                message.push_str(&format!(" (fix: {})", instruction));
+            }
+        }
+        message
+    }
+}
+
+/// Outcome of the preflight checks of a mount.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreflightReport {
+    /// Checks that passed or were skipped
+    pub passed: Vec<String>,
+    
+    /// Checks that failed or warned, in the order they ran
+    pub issues: Vec<PreflightIssue>,
+}
+
+impl PreflightReport {
+    /// Returns true unless a critical check failed.
+    pub fn can_mount(&self) -> bool {
+        !self.issues.iter().any(|issue| issue.critical && issue.result.is_failure())
+    }
+    
+    /// Returns the issues that do not prevent mounting.
+    pub fn warnings(&self) -> impl Iterator<Item = &PreflightIssue> {
+        self.issues.iter().filter(|issue| !(issue.critical && issue.result.is_failure()))
+    }
+    
+    /// Fails with the critical issues and their fixes if the mount cannot work.
+    pub fn into_result(self) -> Result<Self, ShadowError> {
+        if self.can_mount() {
+            return Ok(self);
+        }
+        let blocking: Vec<String> = self.issues.iter()
+            .filter(|issue| issue.critical && issue.result.is_failure())
+            .map(PreflightIssue::message)
+            .collect();
+        Err(ShadowError::InvalidConfiguration {
+            message: format!("Preflight checks failed: {}", blocking.join("; ")),
+        })
+    }
+}
+
+/// Resource checks to run before a mount.
+pub struct Preflight {
+    tests: Vec<Box<dyn CapabilityTest>>,
+}
+
+impl Preflight {
+    /// Creates an empty set of checks.
+    pub fn new() -> Self {
+        Self { tests: Vec::new() }
+    }
+    
+    /// Selects the checks relevant to mounting `source` with `options` on this platform.
+    pub fn for_mount(source: &Path, options: &PreflightOptions) -> Self {
+        let mut preflight = Self::new()
+            .with_test(Box::new(OpenFileLimitTest::new(options.min_open_files)));
+        match Platform::current() {
+            Platform::Linux if options.watch_source => {
+                preflight = preflight.with_test(Box::new(InotifyWatchLimitTest::new(source.to_path_buf())));
+            }
+            Platform::Linux => {}
+            Platform::Windows => preflight = preflight.with_test(Box::new(ProjFsEnabledTest)),
+            Platform::MacOS => preflight = preflight.with_test(Box::new(FsKitEntitlementTest)),
+        }
+        preflight
+    }
+    
+    /// Adds a check.
+    pub fn with_test(mut self, test: Box<dyn CapabilityTest>) -> Self {
+        self.tests.push(test);
+        self
+    }
+    
+    /// Names of the checks, in the order they run.
+    pub fn checks(&self) -> Vec<&'static str> {
+        self.tests.iter().map(|test| test.name()).collect()
+    }
+    
+    /// Runs the checks applying to this platform, without printing anything.
+    pub fn run(&self) -> PreflightReport {
+        let platform = Platform::current();
+        let mut report = PreflightReport::default();
+        for test in &self.tests {
+            if test.platform().is_some_and(|p| p != platform) {
+                continue;
+            }
+            let result = test.run();
+            match result {
+                TestResult::Passed { .. } | TestResult::Skipped { .. } => report.passed.push(test.name().to_string()),
+                TestResult::Failed { .. } | TestResult::Warning { .. } => report.issues.push(PreflightIssue {
+                    check: test.name().to_string(),
+                    critical: test.is_critical(),
+                    remediation: test.remediation(),
+                    result,
+                }),
+            }
+        }
+        report
+    }
+}
+
+impl Default for Preflight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    struct FailingTest;
+    
+    impl CapabilityTest for FailingTest {
+        fn name(&self) -> &'static str {
+            "Failing"
+        }
+        
+        fn description(&self) -> &'static str {
+            "Always fails"
+        }
+        
+        fn run(&self) -> TestResult {
+            TestResult::Failed { reason: "broken".to_string(), fixable: true }
+        }
+        
+        fn is_critical(&self) -> bool {
+            true
+        }
+        
+        fn remediation(&self) -> Option<Remediation> {
+            Some(Remediation {
+                instructions: vec!["Repair it".to_string()],
+                documentation_links: Vec::new(),
+                difficulty: 1,
+                requires_admin: false,
+                fix_command: Some("repair --now".to_string()),
+            })
+        }
+    }
+    
+    #[test]
+    fn test_watch_limit_result() {
+        assert!(matches!(watch_limit_result(10, 8192), TestResult::Passed { .. }));
+        assert!(matches!(watch_limit_result(5000, 8192), TestResult::Warning { .. }));
+        assert!(matches!(watch_limit_result(9000, 8192), TestResult::Failed { fixable: true, .. }));
+        assert_eq!(suggested_watch_limit(10), MIN_SUGGESTED_WATCHES);
+        assert_eq!(suggested_watch_limit(600_000), 2_097_152);
+    }
+    
+    #[test]
+    fn test_count_directories_stops_at_max() {
+        let dir = std::env::temp_dir().join(format!("shadowfs_preflight_{}", std::process::id()));
+        for sub in ["a/b", "c", "d/e/f"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        std::fs::write(dir.join("a/file"), b"x").unwrap();
+        
+        assert_eq!(count_directories(&dir, usize::MAX), 7);
+        assert_eq!(count_directories(&dir, 3), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn test_fskit_entitlement_parsing() {
+        let signed = "<dict><key>com.apple.developer.fskit.fsmodule</key><true/></dict>";
+        let unsigned = "<dict><key>com.apple.security.app-sandbox</key><true/></dict>";
+        let disabled = "<dict><key>com.apple.developer.fskit.fsmodule</key><false/></dict>";
+        assert!(has_fskit_entitlement(signed));
+        assert!(!has_fskit_entitlement(unsigned));
+        assert!(!has_fskit_entitlement(disabled));
+    }
+    
+    #[test]
+    fn test_critical_failure_blocks_mount() {
+        let report = Preflight::new().with_test(Box::new(FailingTest)).run();
+        assert!(!report.can_mount());
+        assert_eq!(report.warnings().count(), 0);
+        assert_eq!(report.issues[0].message(), "Failing: broken (fix: repair --now)");
+        
+        let err = report.into_result().unwrap_err();
+        assert!(err.to_string().contains("repair --now"));
+        
+        let checks = Preflight::for_mount(Path::new("/"), &PreflightOptions::default()).checks();
+        assert_eq!(checks[0], "Open File Limit");
+    }
+}
@@ -196,7 +196,7 @@ impl RuntimeDetector {
             perf_trackers: Arc::clone(&self.perf_trackers),
         };
         
-        thread::spawn(move || {
+        crate::task::spawn_thread("feature-refresh", move || {
             loop {
                 thread::sleep(interval);
                 
@@ -221,6 +221,7 @@ impl RuntimeDetector {
                 }
             }
         })
+        .expect("failed to spawn feature refresh thread")
     }
     
     /// Track performance for a feature operation
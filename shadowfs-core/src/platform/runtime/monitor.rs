@@ -49,7 +49,7 @@ impl FeatureMonitor {
         let running_flag = Arc::clone(&self.running);
         let platform = Platform::current();
         
-        let handle = thread::spawn(move || {
+        let spawned = crate::task::spawn_thread("feature-monitor", move || {
             #[cfg(target_os = "linux")]
             {
                 if platform == Platform::Linux {
@@ -80,7 +80,13 @@ impl FeatureMonitor {
             }
         });
         
-        Ok(handle)
+        match spawned {
+            Ok(handle) => Ok(handle),
+            Err(e) => {
+                *running = false;
+                Err(e.into())
+            }
+        }
     }
     
     /// Stop monitoring
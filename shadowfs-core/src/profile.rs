@@ -0,0 +1,170 @@
+//! Mount profiles bundling ignore rules and seed settings for common workflows.
+//!
+//! A profile describes how a mount is prepared and how its results are read
+//! back: which paths of a seed directory become overrides, and which paths
+//! are noise (caches, history files, lock files) that should not show up in
+//! diffs or be committed. [`MountProfile::dotfiles`] is the curated preset for
+//! trying a dotfiles repository on top of a home directory.
+
+use crate::error::ShadowError;
+use crate::override_store::{DiffEntry, OverrideRule, OverrideStore, SeedSummary};
+use crate::progress::Progress;
+use crate::types::ShadowPath;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Preset of ignore rules and seed filters for a kind of mount.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MountProfile {
+    /// Short name used to select the profile
+    pub name: String,
+    
+    /// One-line description shown to users
+    pub description: String,
+    
+    /// Glob patterns of mount paths whose changes are ignored
+    pub ignore: Vec<String>,
+    
+    /// Glob patterns of seed directory paths that are not copied
+    pub seed_ignore: Vec<String>,
+}
+
+impl MountProfile {
+    /// Creates an empty profile with the given name.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            ignore: Vec::new(),
+            seed_ignore: Vec::new(),
+        }
+    }
+    
+    /// Profile for trying a dotfiles repository on top of `$HOME`.
+    ///
+    /// Ignores caches, trash, shell and editor history and package stores that
+    /// programs write to as a side effect of running, and skips version
+    /// control metadata and repository docs when seeding.
+    pub fn dotfiles() -> Self {
+        let ignore = [
+            "/.cache",
+            "/.local/share/Trash",
+            "/.local/state",
+            "/.npm",
+            "/.cargo/registry",
+            "/.cargo/git",
+            "/.rustup/toolchains",
+            "/.gradle/caches",
+            "/.m2/repository",
+            "/.thumbnails",
+            "*/node_modules",
+            "*/__pycache__",
+            "*_history",
+            "*.swp",
+            "*.lock",
+            "*/.DS_Store",
+        ];
+        let seed_ignore = [
+            "/.git",
+            "/.github",
+            "/.gitignore",
+            "/.gitmodules",
+            "/README*",
+            "/LICENSE*",
+        ];
+        
+        Self {
+            name: "dotfiles".to_string(),
+            description: "Try a dotfiles repository on top of your home directory".to_string(),
+            ignore: ignore.iter().map(|s| s.to_string()).collect(),
+            seed_ignore: seed_ignore.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+    
+    /// Looks up a built-in profile by name.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dotfiles" => Some(Self::dotfiles()),
+            _ => None,
+        }
+    }
+    
+    /// Checks whether changes to `path` are ignored by this profile.
+    ///
+    /// A pattern matching a directory also covers everything below it.
+    pub fn is_ignored(&self, path: &ShadowPath) -> bool {
+        matches_any(&self.ignore, path)
+    }
+    
+    /// Removes ignored paths from a diff.
+    pub fn filter_diff(&self, diff: Vec<DiffEntry>) -> Vec<DiffEntry> {
+        diff.into_iter().filter(|entry| !self.is_ignored(&entry.path)).collect()
+    }
+    
+    /// Seeds `store` with the contents of `dir`, skipping paths matched by `seed_ignore`.
+    pub fn seed(
+        &self,
+        store: &OverrideStore,
+        dir: &Path,
+        progress: &dyn Progress,
+    ) -> Result<SeedSummary, ShadowError> {
+        let skip = |path: &ShadowPath| matches_any(&self.seed_ignore, path);
+        store.seed_from_directory_with_progress(dir, &skip, progress)
+    }
+}
+
+/// Checks whether any pattern matches `path` or one of its ancestors.
+fn matches_any(patterns: &[String], path: &ShadowPath) -> bool {
+    let rules: Vec<OverrideRule> = patterns.iter().map(|p| OverrideRule::Glob(p.clone())).collect();
+    let mut current = Some(path.clone());
+    
+    while let Some(candidate) = current {
+        if candidate.as_path() == Path::new("/") {
+            break;
+        }
+        if rules.iter().any(|rule| rule.matches(&candidate)) {
+            return true;
+        }
+        current = candidate.parent();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_dotfiles_ignores_caches() {
+        let profile = MountProfile::dotfiles();
+        
+        assert!(profile.is_ignored(&ShadowPath::from("/.cache/fontconfig/x.cache")));
+        assert!(profile.is_ignored(&ShadowPath::from("/.bash_history")));
+        assert!(profile.is_ignored(&ShadowPath::from("/src/app/node_modules/left-pad/index.js")));
+        assert!(!profile.is_ignored(&ShadowPath::from("/.bashrc")));
+        assert!(!profile.is_ignored(&ShadowPath::from("/.config/nvim/init.lua")));
+    }
+    
+    #[test]
+    fn test_seed_skips_repository_metadata() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".zshrc"), "export EDITOR=vim").unwrap();
+        std::fs::write(dir.path().join("README.md"), "my dotfiles").unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        
+        let store = OverrideStore::with_defaults();
+        let summary = MountProfile::dotfiles()
+            .seed(&store, dir.path(), &crate::progress::NoProgress)
+            .unwrap();
+        
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.skipped, 2);
+        assert!(store.exists(&ShadowPath::from("/.zshrc")));
+    }
+    
+    #[test]
+    fn test_builtin_lookup() {
+        assert_eq!(MountProfile::builtin("dotfiles"), Some(MountProfile::dotfiles()));
+        assert!(MountProfile::builtin("unknown").is_none());
+    }
+}
@@ -0,0 +1,440 @@
+//! Progress reporting for long-running operations.
+//!
+//! Operations that walk large trees (snapshot restore, WAL replay, commits,
+//! imports, diffs) accept a `&dyn Progress` and report what they have done so
+//! far. The CLI renders reports as progress bars through [`ConsoleProgress`],
+//! while the daemon uses a [`ProgressTracker`] and streams its updates to
+//! clients.
+//!
+//! Cancellation is cooperative: operations call [`Progress::check_cancelled`]
+//! between steps and stop with [`ShadowError::Cancelled`] at a point where the
+//! store is consistent. The CLI maps Ctrl-C to a [`CancelHandle`] with
+//! [`CancelHandle::cancel_on_ctrl_c`], and the daemon cancels operations by id
+//! through an [`OperationRegistry`].
+
+use crate::error::{Result, ShadowError};
+use crate::types::ShadowPath;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "platform")]
+use std::time::Duration;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Point-in-time view of an operation's progress.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgressUpdate {
+    /// Name of the operation being tracked
+    pub operation: String,
+    
+    /// Number of items processed so far
+    pub items_done: u64,
+    
+    /// Total number of items, if known
+    pub items_total: Option<u64>,
+    
+    /// Number of bytes processed so far
+    pub bytes_done: u64,
+    
+    /// Total number of bytes, if known
+    pub bytes_total: Option<u64>,
+    
+    /// Path currently being processed
+    pub current_path: Option<ShadowPath>,
+    
+    /// Whether the operation has finished
+    pub finished: bool,
+}
+
+impl ProgressUpdate {
+    /// Returns the completed fraction (0.0 to 1.0) based on items, if the total is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.items_total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.items_done as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
+
+/// Shared flag used to ask a running operation to stop.
+///
+/// Cloning the handle shares the flag, so one clone can be given to the
+/// operation and another kept by whoever may cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    /// Creates a new, non-cancelled handle.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Requests cancellation.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+    
+    /// Checks whether cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+    
+    /// Cancels this handle when the process receives Ctrl-C.
+    ///
+    /// Must be called from within a Tokio runtime. The returned task can be
+    /// aborted once the operation has finished.
+    pub fn cancel_on_ctrl_c(&self) -> tokio::task::JoinHandle<()> {
+        let handle = self.clone();
+        crate::task::spawn("ctrl-c", async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                handle.cancel();
+            }
+        })
+    }
+}
+
+/// Sink for progress reports from long-running operations.
+///
+/// All methods take `&self` so a single reporter can be shared by the
+/// threads or tasks doing the work.
+pub trait Progress: Send + Sync {
+    /// Sets the expected totals; `None` means unknown.
+    fn set_total(&self, items: Option<u64>, bytes: Option<u64>);
+    
+    /// Records that `items` more items and `bytes` more bytes were processed.
+    fn advance(&self, items: u64, bytes: u64);
+    
+    /// Records the path currently being processed.
+    fn set_current_path(&self, path: &ShadowPath);
+    
+    /// Marks the operation as finished.
+    fn finish(&self);
+    
+    /// Returns the handle used to cancel the operation, if it can be cancelled.
+    fn cancel_handle(&self) -> Option<&CancelHandle> {
+        None
+    }
+    
+    /// Checks whether the operation has been asked to stop.
+    fn is_cancelled(&self) -> bool {
+        self.cancel_handle().is_some_and(CancelHandle::is_cancelled)
+    }
+    
+    /// Returns `Cancelled` if the operation has been asked to stop.
+    ///
+    /// Operations call this between steps, never in the middle of one.
+    fn check_cancelled(&self, operation: &str) -> Result<()> {
+        if self.is_cancelled() {
+            Err(ShadowError::Cancelled { operation: operation.to_string() })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Progress reporter that discards all reports.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn set_total(&self, _items: Option<u64>, _bytes: Option<u64>) {}
+    
+    fn advance(&self, _items: u64, _bytes: u64) {}
+    
+    fn set_current_path(&self, _path: &ShadowPath) {}
+    
+    fn finish(&self) {}
+}
+
+/// Progress reporter that keeps the latest state and publishes it to subscribers.
+///
+/// Subscribers receive a [`watch::Receiver`], so slow consumers such as RPC
+/// streams only ever see the most recent update instead of a growing backlog.
+#[derive(Debug)]
+pub struct ProgressTracker {
+    state: watch::Sender<ProgressUpdate>,
+    cancel: CancelHandle,
+}
+
+impl ProgressTracker {
+    /// Creates a tracker for the named operation.
+    pub fn new(operation: impl Into<String>) -> Self {
+        Self::with_cancel_handle(operation, CancelHandle::new())
+    }
+    
+    /// Creates a tracker sharing an existing cancel handle.
+    pub fn with_cancel_handle(operation: impl Into<String>, cancel: CancelHandle) -> Self {
+        let (state, _) = watch::channel(ProgressUpdate {
+            operation: operation.into(),
+            ..Default::default()
+        });
+        Self { state, cancel }
+    }
+    
+    /// Returns a receiver that observes every subsequent update.
+    pub fn subscribe(&self) -> watch::Receiver<ProgressUpdate> {
+        self.state.subscribe()
+    }
+    
+    /// Returns the current progress.
+    pub fn snapshot(&self) -> ProgressUpdate {
+        self.state.borrow().clone()
+    }
+}
+
+impl Progress for ProgressTracker {
+    fn set_total(&self, items: Option<u64>, bytes: Option<u64>) {
+        self.state.send_modify(|update| {
+            update.items_total = items;
+            update.bytes_total = bytes;
+        });
+    }
+    
+    fn advance(&self, items: u64, bytes: u64) {
+        self.state.send_modify(|update| {
+            update.items_done += items;
+            update.bytes_done += bytes;
+        });
+    }
+    
+    fn set_current_path(&self, path: &ShadowPath) {
+        self.state.send_modify(|update| update.current_path = Some(path.clone()));
+    }
+    
+    fn finish(&self) {
+        self.state.send_modify(|update| {
+            update.current_path = None;
+            update.finished = true;
+        });
+    }
+    
+    fn cancel_handle(&self) -> Option<&CancelHandle> {
+        Some(&self.cancel)
+    }
+}
+
+/// Registry of running operations, used by the daemon to report and cancel them by id.
+#[derive(Debug, Default)]
+pub struct OperationRegistry {
+    operations: RwLock<HashMap<Uuid, Arc<ProgressTracker>>>,
+}
+
+impl OperationRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Registers a new operation and returns its id and tracker.
+    pub fn start(&self, operation: impl Into<String>) -> (Uuid, Arc<ProgressTracker>) {
+        let id = Uuid::new_v4();
+        let tracker = Arc::new(ProgressTracker::new(operation));
+        self.operations.write().unwrap().insert(id, Arc::clone(&tracker));
+        (id, tracker)
+    }
+    
+    /// Requests cancellation of an operation.
+    ///
+    /// # Returns
+    /// false if no operation with this id is registered
+    pub fn cancel(&self, id: Uuid) -> bool {
+        match self.operations.read().unwrap().get(&id) {
+            Some(tracker) => {
+                tracker.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Gets the tracker of an operation.
+    pub fn get(&self, id: Uuid) -> Option<Arc<ProgressTracker>> {
+        self.operations.read().unwrap().get(&id).cloned()
+    }
+    
+    /// Lists all registered operations with their current progress.
+    pub fn list(&self) -> Vec<(Uuid, ProgressUpdate)> {
+        self.operations.read().unwrap()
+            .iter()
+            .map(|(id, tracker)| (*id, tracker.snapshot()))
+            .collect()
+    }
+    
+    /// Removes an operation once it has completed or been cancelled.
+    pub fn remove(&self, id: Uuid) -> Option<Arc<ProgressTracker>> {
+        self.operations.write().unwrap().remove(&id)
+    }
+}
+
+/// Progress reporter that draws a terminal progress bar.
+#[cfg(feature = "platform")]
+#[derive(Debug)]
+pub struct ConsoleProgress {
+    bar: indicatif::ProgressBar,
+    cancel: CancelHandle,
+}
+
+#[cfg(feature = "platform")]
+impl ConsoleProgress {
+    /// Creates a progress bar labelled with the operation name.
+    pub fn new(operation: impl Into<String>) -> Self {
+        Self::with_cancel_handle(operation, CancelHandle::new())
+    }
+    
+    /// Creates a progress bar sharing an existing cancel handle.
+    pub fn with_cancel_handle(operation: impl Into<String>, cancel: CancelHandle) -> Self {
+        let bar = indicatif::ProgressBar::new_spinner();
+        bar.set_prefix(operation.into());
+        bar.enable_steady_tick(Duration::from_millis(120));
+        bar.set_style(Self::spinner_style());
+        Self { bar, cancel }
+    }
+    
+    /// Creates a progress bar that draws nothing, for non-interactive output.
+    pub fn hidden() -> Self {
+        Self {
+            bar: indicatif::ProgressBar::hidden(),
+            cancel: CancelHandle::new(),
+        }
+    }
+    
+    fn spinner_style() -> indicatif::ProgressStyle {
+        indicatif::ProgressStyle::with_template("{spinner} {prefix}: {pos} items {wide_msg}")
+            .unwrap_or_else(|_| indicatif::ProgressStyle::default_spinner())
+    }
+    
+    fn bar_style() -> indicatif::ProgressStyle {
+        indicatif::ProgressStyle::with_template("{prefix}: [{bar:40}] {pos}/{len} ({eta}) {wide_msg}")
+            .unwrap_or_else(|_| indicatif::ProgressStyle::default_bar())
+            .progress_chars("=> ")
+    }
+}
+
+#[cfg(feature = "platform")]
+impl Progress for ConsoleProgress {
+    fn set_total(&self, items: Option<u64>, _bytes: Option<u64>) {
+        match items {
+            Some(total) => {
+                self.bar.set_length(total);
+                self.bar.set_style(Self::bar_style());
+            }
+            None => {
+                self.bar.unset_length();
+                self.bar.set_style(Self::spinner_style());
+            }
+        }
+    }
+    
+    fn advance(&self, items: u64, _bytes: u64) {
+        self.bar.inc(items);
+    }
+    
+    fn set_current_path(&self, path: &ShadowPath) {
+        self.bar.set_message(path.to_string());
+    }
+    
+    fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+    
+    fn cancel_handle(&self) -> Option<&CancelHandle> {
+        Some(&self.cancel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_cancel_handle_shared_between_clones() {
+        let handle = CancelHandle::new();
+        let clone = handle.clone();
+        assert!(!clone.is_cancelled());
+        
+        handle.cancel();
+        assert!(clone.is_cancelled());
+    }
+    
+    #[test]
+    fn test_tracker_accumulates_updates() {
+        let tracker = ProgressTracker::new("restore");
+        tracker.set_total(Some(4), Some(100));
+        tracker.advance(1, 10);
+        tracker.advance(2, 30);
+        tracker.set_current_path(&ShadowPath::from("/a/b"));
+        
+        let update = tracker.snapshot();
+        assert_eq!(update.operation, "restore");
+        assert_eq!(update.items_done, 3);
+        assert_eq!(update.bytes_done, 40);
+        assert_eq!(update.fraction(), Some(0.75));
+        assert_eq!(update.current_path, Some(ShadowPath::from("/a/b")));
+        
+        tracker.finish();
+        let update = tracker.snapshot();
+        assert!(update.finished);
+        assert!(update.current_path.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_tracker_subscribers_see_latest_state() {
+        let tracker = ProgressTracker::new("replay");
+        let mut receiver = tracker.subscribe();
+        
+        tracker.advance(5, 0);
+        receiver.changed().await.unwrap();
+        assert_eq!(receiver.borrow_and_update().items_done, 5);
+    }
+    
+    #[test]
+    fn test_is_cancelled_uses_handle() {
+        let cancel = CancelHandle::new();
+        let tracker = ProgressTracker::with_cancel_handle("gc", cancel.clone());
+        assert!(!tracker.is_cancelled());
+        
+        cancel.cancel();
+        assert!(tracker.is_cancelled());
+        assert!(!NoProgress.is_cancelled());
+    }
+    
+    #[test]
+    fn test_check_cancelled() {
+        let tracker = ProgressTracker::new("diff");
+        assert!(tracker.check_cancelled("diff").is_ok());
+        
+        tracker.cancel_handle().unwrap().cancel();
+        assert!(matches!(
+            tracker.check_cancelled("diff"),
+            Err(ShadowError::Cancelled { operation }) if operation == "diff"
+        ));
+    }
+    
+    #[test]
+    fn test_operation_registry_cancel_by_id() {
+        let registry = OperationRegistry::new();
+        let (id, tracker) = registry.start("commit");
+        assert_eq!(registry.list().len(), 1);
+        
+        assert!(registry.cancel(id));
+        assert!(tracker.is_cancelled());
+        assert!(!registry.cancel(Uuid::new_v4()));
+        
+        registry.remove(id);
+        assert!(registry.get(id).is_none());
+    }
+    
+    #[test]
+    #[cfg(feature = "platform")]
+    fn test_console_progress_hidden() {
+        let progress = ConsoleProgress::hidden();
+        progress.set_total(Some(2), None);
+        progress.advance(2, 0);
+        progress.finish();
+    }
+}
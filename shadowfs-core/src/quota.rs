@@ -0,0 +1,284 @@
+//! Per-mount limits on what may be written.
+//!
+//! The memory limit of the override store bounds the bytes held in memory,
+//! but compression, spilling and eviction let a sandboxed process keep
+//! creating files long after that, and millions of tiny files cost the host
+//! far more than their bytes. A [`Quota`] caps the files and directories a
+//! mount holds, the size of any one file and the total bytes of file
+//! content. Every write through the store is checked against it before
+//! anything changes, and one that does not fit fails with
+//! [`ShadowError::QuotaExceeded`] naming the limit.
+//!
+//! Tombstones are not counted, so deleting is always allowed, and loading
+//! persisted state is never refused.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use shadowfs_core::error::ShadowError;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::quota::Quota;
+//! use shadowfs_core::types::ShadowPath;
+//!
+//! let store = OverrideStore::with_defaults();
+//! store.set_quota(Quota { max_files: Some(1), ..Quota::default() });
+//!
+//! store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("a"), None).unwrap();
+//! let err = store.insert_file(ShadowPath::from("/b.txt"), Bytes::from("b"), None).unwrap_err();
+//! assert!(matches!(err, ShadowError::QuotaExceeded { .. }));
+//! ```
+
+use crate::error::{quota_exceeded, ShadowError};
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideStore};
+use crate::types::ShadowPath;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Limits on what a mount may hold; `None` leaves a dimension unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Quota {
+    /// Files and directories the mount may hold
+    pub max_files: Option<u64>,
+    
+    /// Largest size of one file, in bytes
+    pub max_file_size: Option<u64>,
+    
+    /// Total size of all file content, in bytes
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Returns true if no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What the overrides of a store count against its [`Quota`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuotaUsage {
+    /// File and directory overrides
+    pub files: u64,
+    
+    /// Total size of the file overrides, in bytes
+    pub bytes: u64,
+}
+
+impl QuotaUsage {
+    /// Returns what `content` of `size` bytes counts against a quota.
+    pub(crate) fn of_content(content: &OverrideContent, size: u64) -> Self {
+        match content {
+            OverrideContent::File { .. } => Self { files: 1, bytes: size },
+            OverrideContent::Directory { .. } => Self { files: 1, bytes: 0 },
+            OverrideContent::Deleted => Self::default(),
+        }
+    }
+    
+    /// Returns what `entry` counts against a quota.
+    pub(crate) fn of_entry(entry: &OverrideEntry) -> Self {
+        Self::of_content(&entry.content, entry.uncompressed_size())
+    }
+}
+
+/// Running [`QuotaUsage`] of a store, kept up to date on every insert and
+/// removal whether or not a quota is set.
+#[derive(Debug, Default)]
+pub(crate) struct UsageCounter {
+    files: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl UsageCounter {
+    /// Accounts for `old` being replaced by `new`; either may be absent.
+    pub(crate) fn replace(&self, old: Option<&OverrideEntry>, new: Option<&OverrideEntry>) {
+        let old = old.map(QuotaUsage::of_entry).unwrap_or_default();
+        let new = new.map(QuotaUsage::of_entry).unwrap_or_default();
+        // Adding before subtracting keeps the counters from wrapping below zero
+        self.files.fetch_add(new.files, Ordering::Relaxed);
+        self.files.fetch_sub(old.files, Ordering::Relaxed);
+        self.bytes.fetch_add(new.bytes, Ordering::Relaxed);
+        self.bytes.fetch_sub(old.bytes, Ordering::Relaxed);
+    }
+    
+    pub(crate) fn get(&self) -> QuotaUsage {
+        QuotaUsage {
+            files: self.files.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Fails with [`ShadowError::QuotaExceeded`] if a file of `size` bytes at
+/// `path` would exceed the file size limit of `quota`.
+pub(crate) fn check_size(quota: &Quota, path: &ShadowPath, size: u64) -> Result<(), ShadowError> {
+    match quota.max_file_size {
+        Some(limit) if size > limit => Err(quota_exceeded(
+            path.clone(),
+            format!("a file of {} bytes would exceed the limit of {} bytes", size, limit),
+        )),
+        _ => Ok(()),
+    }
+}
+
+impl OverrideStore {
+    /// Limits what may be written through the store from now on.
+    ///
+    /// Overrides already held are kept even if they exceed the new limits.
+    pub fn set_quota(&self, quota: Quota) {
+        *self.quota.write().unwrap() = quota;
+    }
+    
+    /// Returns the limits writes are checked against.
+    pub fn quota(&self) -> Quota {
+        *self.quota.read().unwrap()
+    }
+    
+    /// Returns what the overrides held count against the quota.
+    pub fn quota_usage(&self) -> QuotaUsage {
+        self.quota_usage.get()
+    }
+    
+    /// Fails with [`ShadowError::QuotaExceeded`] if a file written at
+    /// `path` would grow to `size` bytes, over the file size limit of the
+    /// store or of its namespace.
+    pub(crate) fn check_file_size(&self, path: &ShadowPath, size: u64) -> Result<(), ShadowError> {
+        check_size(&self.quota(), path, size)?;
+        self.check_namespace_file_size(path, size)
+    }
+    
+    /// Fails with [`ShadowError::QuotaExceeded`] if storing each change,
+    /// what the new override at a path counts against the quota, would
+    /// exceed the quota of the store or of a namespace. A path changed more
+    /// than once counts with its last change.
+    pub(crate) fn check_quota<'a>(
+        &self,
+        changes: impl IntoIterator<Item = (&'a ShadowPath, QuotaUsage)>,
+    ) -> Result<(), ShadowError> {
+        let changes: HashMap<&ShadowPath, QuotaUsage> = changes.into_iter().collect();
+        self.check_namespace_quotas(&changes)?;
+        
+        let quota = self.quota();
+        if quota.is_unlimited() {
+            return Ok(());
+        }
+        self.check_usage(quota, self.quota_usage(), changes)
+    }
+    
+    /// Fails with [`ShadowError::QuotaExceeded`] if `changes` would take
+    /// overrides counting `usage` past `quota`.
+    pub(crate) fn check_usage<'a>(
+        &self,
+        quota: Quota,
+        mut usage: QuotaUsage,
+        changes: impl IntoIterator<Item = (&'a ShadowPath, QuotaUsage)>,
+    ) -> Result<(), ShadowError> {
+        let mut first = None;
+        for (path, new) in changes {
+            check_size(&quota, path, new.bytes)?;
+            let old = self.entries.get(path)
+                .map(|entry| QuotaUsage::of_entry(&entry))
+                .or_else(|| self.peek_spilled(path).map(|entry| QuotaUsage::of_entry(&entry)))
+                .unwrap_or_default();
+            usage.files = (usage.files + new.files).saturating_sub(old.files);
+            usage.bytes = (usage.bytes + new.bytes).saturating_sub(old.bytes);
+            if new.files > old.files || new.bytes > old.bytes {
+                first = first.or(Some(path));
+            }
+        }
+        let Some(path) = first else {
+            return Ok(());
+        };
+        
+        if let Some(limit) = quota.max_files.filter(|limit| usage.files > *limit) {
+            return Err(quota_exceeded(
+                path.clone(),
+                format!("{} files would exceed the limit of {}", usage.files, limit),
+            ));
+        }
+        if let Some(limit) = quota.max_total_bytes.filter(|limit| usage.bytes > *limit) {
+            return Err(quota_exceeded(
+                path.clone(),
+                format!("{} bytes in total would exceed the limit of {} bytes", usage.bytes, limit),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_limits_are_enforced() {
+        let store = OverrideStore::with_defaults();
+        store.set_quota(Quota {
+            max_files: Some(3),
+            max_file_size: Some(10),
+            max_total_bytes: Some(15),
+        });
+        
+        store.insert_directory(ShadowPath::from("/dir"), None).unwrap();
+        store.insert_file(ShadowPath::from("/dir/a.txt"), Bytes::from("0123456789"), None).unwrap();
+        let err = store.insert_file(ShadowPath::from("/dir/b.txt"), Bytes::from("0123456789a"), None).unwrap_err();
+        assert!(matches!(&err, ShadowError::QuotaExceeded { reason, .. } if reason.contains("limit of 10 bytes")));
+        let err = store.insert_file(ShadowPath::from("/dir/b.txt"), Bytes::from("012345"), None).unwrap_err();
+        assert!(matches!(&err, ShadowError::QuotaExceeded { reason, .. } if reason.contains("in total")));
+        
+        // Shrinking a file makes room, and replacing one is not a new file
+        store.insert_file(ShadowPath::from("/dir/a.txt"), Bytes::from("01"), None).unwrap();
+        store.insert_file(ShadowPath::from("/dir/b.txt"), Bytes::from("012345"), None).unwrap();
+        assert_eq!(store.quota_usage(), QuotaUsage { files: 3, bytes: 8 });
+        let err = store.insert_directory(ShadowPath::from("/other"), None).unwrap_err();
+        assert!(matches!(&err, ShadowError::QuotaExceeded { reason, .. } if reason == "4 files would exceed the limit of 3"));
+        
+        // Deleting is always allowed and frees its share
+        store.mark_deleted(ShadowPath::from("/dir/b.txt")).unwrap();
+        assert_eq!(store.quota_usage(), QuotaUsage { files: 2, bytes: 2 });
+        store.remove(&ShadowPath::from("/dir/a.txt"));
+        assert_eq!(store.quota_usage(), QuotaUsage { files: 1, bytes: 0 });
+    }
+    
+    #[test]
+    fn test_links_and_renames_count_what_they_add() {
+        use crate::override_store::RenameOptions;
+        let store = OverrideStore::with_defaults();
+        store.set_quota(Quota { max_files: Some(1), max_total_bytes: Some(10), ..Quota::default() });
+        store.insert_file(ShadowPath::from("/a"), Bytes::from("01234567"), None).unwrap();
+        
+        // Every name of a linked file counts with its full size
+        let err = store.link(&ShadowPath::from("/a"), ShadowPath::from("/b")).unwrap_err();
+        assert!(matches!(err, ShadowError::QuotaExceeded { .. }));
+        assert!(store.get(&ShadowPath::from("/b")).is_none());
+        
+        // Moving an override adds nothing, copying a source tree does
+        store.rename(&ShadowPath::from("/a"), ShadowPath::from("/c"), RenameOptions::default()).unwrap();
+        assert_eq!(store.quota_usage(), QuotaUsage { files: 1, bytes: 8 });
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("tree")).unwrap();
+        std::fs::write(source.path().join("tree/x"), "x").unwrap();
+        let options = RenameOptions::with_source(source.path());
+        let err = store.rename(&ShadowPath::from("/tree"), ShadowPath::from("/moved"), options).unwrap_err();
+        assert!(matches!(err, ShadowError::QuotaExceeded { .. }));
+        assert!(store.get(&ShadowPath::from("/tree")).is_none());
+        assert_eq!(store.quota_usage(), QuotaUsage { files: 1, bytes: 8 });
+    }
+    
+    #[test]
+    fn test_transactions_count_every_change() {
+        let store = OverrideStore::with_defaults();
+        store.set_quota(Quota { max_files: Some(2), ..Quota::default() });
+        
+        let result = store.transaction(|tx| {
+            for name in ["/a", "/b", "/c"] {
+                tx.insert_file(ShadowPath::from(name), Bytes::from("x"), None);
+            }
+            Ok(())
+        });
+        assert!(matches!(result, Err(ShadowError::QuotaExceeded { .. })));
+        assert_eq!(store.quota_usage(), QuotaUsage::default());
+    }
+}
@@ -0,0 +1,327 @@
+//! Rebalancing of a shared memory budget between the stores of several mounts.
+//!
+//! [`MountLimits::memory_budget`](crate::mount_manager::MountLimits) caps the
+//! override memory all mounts reserve together, but each mount keeps the
+//! limit it was mounted with, so a busy mount evicts while an idle one sits
+//! on memory it does not use. A [`MemoryRebalancer`] moves the budget to
+//! where it is used instead:
+//!
+//! - Every store keeps a floor, so an idle mount still serves its hottest
+//!   overrides.
+//! - Half of the budget above the floors is divided by [`MemoryPriority`]
+//!   alone, so idle mounts keep a share in proportion to their priority.
+//! - The other half is divided by priority times the lookups since the
+//!   previous round, feeding the busy mounts.
+//!
+//! Stores whose limit goes down evict their least recently used entries (or
+//! spill them, with a spill directory configured) before the stores whose
+//! limit goes up are raised, so the limits never add up to more than the
+//! budget. Rounds that would move less than [`REBALANCE_TOLERANCE`] of the
+//! budget change nothing, so steady load does not evict a few entries on
+//! every round.
+//!
+//! Mounts served by separate processes can rebalance without a
+//! [`MemoryRebalancer`] by exchanging [`MemoryClaim`]s and each applying its
+//! own share of [`divide_budget`].
+
+use crate::error::ShadowError;
+use crate::override_store::OverrideStore;
+use crate::types::MemoryPriority;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Fraction of the budget a round has to move for any limit to change.
+pub const REBALANCE_TOLERANCE: f64 = 0.01;
+
+/// What a mount asks of a shared memory budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryClaim {
+    /// Weight of the mount
+    pub priority: MemoryPriority,
+    
+    /// Lookups since the previous round
+    pub activity: u64,
+    
+    /// Memory the mount keeps however idle it is, in bytes
+    pub floor: u64,
+}
+
+/// Divides `budget` bytes between `claims`, as described in the module
+/// documentation.
+///
+/// When the floors alone exceed the budget, each claim gets its floor scaled
+/// down to fit.
+///
+/// # Returns
+/// The share of each claim in bytes, in the order of `claims`, adding up to
+/// the budget
+pub fn divide_budget(budget: u64, claims: &[MemoryClaim]) -> Vec<u64> {
+    let floors: u64 = claims.iter().map(|claim| claim.floor).sum();
+    if floors >= budget {
+        return claims.iter().map(|claim| proportion(budget, claim.floor, floors)).collect();
+    }
+    
+    let weights: Vec<u64> = claims.iter().map(|claim| u64::from(claim.priority.0.max(1))).collect();
+    let busy: Vec<u64> = claims.iter().zip(&weights)
+        .map(|(claim, weight)| claim.activity.saturating_mul(*weight))
+        .collect();
+    let total_weight: u64 = weights.iter().sum();
+    let total_busy = busy.iter().fold(0u64, |total, busy| total.saturating_add(*busy));
+    
+    let spare = budget - floors;
+    let by_activity = if total_busy == 0 { 0 } else { spare / 2 };
+    let by_priority = spare - by_activity;
+    let mut shares: Vec<u64> = claims.iter().enumerate()
+        .map(|(i, claim)| {
+            claim.floor
+                + proportion(by_priority, weights[i], total_weight)
+                + proportion(by_activity, busy[i], total_busy)
+        })
+        .collect();
+    
+    // Rounding leaves a few bytes over; they go to the largest share
+    let leftover = budget - shares.iter().sum::<u64>();
+    if let Some(largest) = shares.iter_mut().max() {
+        *largest += leftover;
+    }
+    shares
+}
+
+/// Returns `amount * part / whole` without overflowing.
+fn proportion(amount: u64, part: u64, whole: u64) -> u64 {
+    if whole == 0 {
+        return 0;
+    }
+    (u128::from(amount) * u128::from(part) / u128::from(whole)) as u64
+}
+
+/// A change to the memory limit of one store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebalanceEvent {
+    /// Name the store was registered under
+    pub name: String,
+    
+    /// Memory limit before the round, in bytes
+    pub previous: u64,
+    
+    /// Memory limit after the round, in bytes
+    pub limit: u64,
+    
+    /// Bytes evicted or spilled to fit the new limit
+    pub evicted: u64,
+    
+    /// Lookups since the previous round
+    pub activity: u64,
+}
+
+impl fmt::Display for RebalanceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.limit < self.previous { "shrunk" } else { "grew" };
+        write!(
+            f, "memory limit of {} {} from {} to {} bytes after {} lookups",
+            self.name, direction, self.previous, self.limit, self.activity,
+        )?;
+        if self.evicted > 0 {
+            write!(f, ", evicting {} bytes", self.evicted)?;
+        }
+        Ok(())
+    }
+}
+
+/// A store taking part in rebalancing.
+struct Member {
+    store: Arc<OverrideStore>,
+    priority: MemoryPriority,
+    floor: u64,
+    
+    /// Lookups of the store when the previous round ran
+    lookups: u64,
+}
+
+/// Divides a memory budget between stores served by the same process.
+///
+/// # Examples
+///
+/// ```rust
+/// use shadowfs_core::override_store::OverrideStore;
+/// use shadowfs_core::rebalance::MemoryRebalancer;
+/// use shadowfs_core::types::MemoryPriority;
+/// use std::sync::Arc;
+///
+/// let rebalancer = MemoryRebalancer::new(256 * 1024 * 1024);
+/// rebalancer.register("build", Arc::new(OverrideStore::with_defaults()), MemoryPriority::HIGH, 16 * 1024 * 1024);
+/// rebalancer.register("docs", Arc::new(OverrideStore::with_defaults()), MemoryPriority::LOW, 16 * 1024 * 1024);
+///
+/// for event in rebalancer.rebalance().unwrap() {
+///     println!("{}", event);
+/// }
+/// ```
+pub struct MemoryRebalancer {
+    /// Memory all registered stores share, in bytes
+    budget: u64,
+    
+    /// Registered stores by name
+    members: Mutex<BTreeMap<String, Member>>,
+}
+
+impl MemoryRebalancer {
+    /// Creates a rebalancer dividing `budget` bytes.
+    pub fn new(budget: u64) -> Self {
+        Self { budget, members: Mutex::new(BTreeMap::new()) }
+    }
+    
+    /// Returns the memory all registered stores share, in bytes.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+    
+    /// Registers `store` under `name`, replacing any store registered under
+    /// it before. Its limit is left alone until the next round.
+    ///
+    /// # Arguments
+    /// * `name` - Name reported in events, such as the mount point
+    /// * `store` - Store whose memory limit to manage
+    /// * `priority` - Weight of the store
+    /// * `floor` - Memory the store keeps however idle it is, in bytes
+    pub fn register(&self, name: impl Into<String>, store: Arc<OverrideStore>, priority: MemoryPriority, floor: u64) {
+        let lookups = lookups(&store);
+        self.members.lock().unwrap().insert(name.into(), Member { store, priority, floor, lookups });
+    }
+    
+    /// Stops managing the store registered under `name`; its memory goes to
+    /// the other stores in the next round.
+    ///
+    /// # Returns
+    /// false if no store is registered under `name`
+    pub fn unregister(&self, name: &str) -> bool {
+        self.members.lock().unwrap().remove(name).is_some()
+    }
+    
+    /// Returns the number of registered stores.
+    pub fn len(&self) -> usize {
+        self.members.lock().unwrap().len()
+    }
+    
+    /// Returns true if no stores are registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Divides the budget by the priorities and the lookups since the
+    /// previous round, shrinking stores before growing others.
+    ///
+    /// # Returns
+    /// A change for each store whose limit moved, shrunk stores first
+    pub fn rebalance(&self) -> Result<Vec<RebalanceEvent>, ShadowError> {
+        let mut members = self.members.lock().unwrap();
+        let mut claims = Vec::with_capacity(members.len());
+        for member in members.values_mut() {
+            let current = lookups(&member.store);
+            claims.push(MemoryClaim {
+                priority: member.priority,
+                activity: current.saturating_sub(member.lookups),
+                floor: member.floor,
+            });
+            member.lookups = current;
+        }
+        let shares = divide_budget(self.budget, &claims);
+        
+        let mut changes: Vec<(&String, &Member, u64, u64, u64)> = members.iter()
+            .zip(claims.iter().zip(shares))
+            .map(|((name, member), (claim, share))| {
+                (name, member, member.store.get_config().max_memory as u64, share, claim.activity)
+            })
+            .filter(|(_, _, previous, share, _)| previous != share)
+            .collect();
+        let moved: u64 = changes.iter().map(|(_, _, previous, share, _)| previous.abs_diff(*share)).sum();
+        if (moved as f64) < self.budget as f64 * REBALANCE_TOLERANCE * 2.0 {
+            return Ok(Vec::new());
+        }
+        
+        changes.sort_by_key(|(_, _, previous, share, _)| share > previous);
+        let mut events = Vec::with_capacity(changes.len());
+        for (name, member, previous, limit, activity) in changes {
+            let evicted = member.store.set_memory_limit(limit as usize)? as u64;
+            events.push(RebalanceEvent { name: name.clone(), previous, limit, evicted, activity });
+        }
+        Ok(events)
+    }
+}
+
+impl fmt::Debug for MemoryRebalancer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryRebalancer")
+            .field("budget", &self.budget)
+            .field("stores", &self.len())
+            .finish()
+    }
+}
+
+/// Returns the lookups `store` has served, the activity rebalancing follows.
+pub fn lookups(store: &OverrideStore) -> u64 {
+    let stats = store.get_stats_snapshot();
+    stats.cache_hits + stats.cache_misses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::OverrideStoreConfig;
+    use crate::types::{FileMetadata, ShadowPath};
+    use bytes::Bytes;
+    
+    fn claim(priority: MemoryPriority, activity: u64, floor: u64) -> MemoryClaim {
+        MemoryClaim { priority, activity, floor }
+    }
+    
+    #[test]
+    fn test_divide_budget() {
+        // Without activity the spare memory follows the priorities
+        let shares = divide_budget(1000, &[claim(MemoryPriority::HIGH, 0, 100), claim(MemoryPriority::NORMAL, 0, 100)]);
+        assert_eq!(shares, vec![634, 366]);
+        
+        // Half of it follows the activity
+        let shares = divide_budget(1000, &[claim(MemoryPriority::NORMAL, 90, 100), claim(MemoryPriority::NORMAL, 10, 100)]);
+        assert_eq!(shares, vec![660, 340]);
+        assert_eq!(shares.iter().sum::<u64>(), 1000);
+        
+        // Floors over the budget are scaled down
+        let shares = divide_budget(100, &[claim(MemoryPriority::NORMAL, 5, 150), claim(MemoryPriority::NORMAL, 0, 50)]);
+        assert_eq!(shares, vec![75, 25]);
+        assert!(divide_budget(100, &[]).is_empty());
+    }
+    
+    #[test]
+    fn test_idle_store_feeds_busy_one() {
+        let store = || Arc::new(OverrideStore::new(OverrideStoreConfig { max_memory: 64 * 1024, ..Default::default() }));
+        let (busy, idle) = (store(), store());
+        let metadata = FileMetadata::default();
+        for i in 0..40 {
+            idle.insert_file(ShadowPath::from(format!("/idle/{}", i).as_str()), Bytes::from(vec![0u8; 1024]), Some(metadata.clone())).unwrap();
+        }
+        
+        let rebalancer = MemoryRebalancer::new(128 * 1024);
+        rebalancer.register("busy", Arc::clone(&busy), MemoryPriority::NORMAL, 16 * 1024);
+        rebalancer.register("idle", Arc::clone(&idle), MemoryPriority::NORMAL, 16 * 1024);
+        for _ in 0..100 {
+            busy.get(&ShadowPath::from("/busy/file"));
+        }
+        
+        let events = rebalancer.rebalance().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].name, "idle");
+        assert!(events[0].limit < 64 * 1024 && events[0].evicted > 0);
+        assert!(idle.memory_stats().0 <= events[0].limit as usize);
+        assert_eq!(events[1].name, "busy");
+        assert_eq!(events[1].activity, 100);
+        assert_eq!(events[0].limit + events[1].limit, 128 * 1024);
+        
+        // Nothing moves while the load stays the same
+        for _ in 0..100 {
+            busy.get(&ShadowPath::from("/busy/file"));
+        }
+        assert!(rebalancer.rebalance().unwrap().is_empty());
+    }
+}
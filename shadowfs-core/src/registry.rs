@@ -0,0 +1,249 @@
+//! Mount records kept across restarts, for finding mounts left behind by
+//! crashed processes.
+//!
+//! A [`FileMountRegistry`] keeps one JSON [`MountRecord`] per mount in a
+//! directory under the user's configuration directory, which survives
+//! reboots unlike the runtime state of running mounts. A process serving a
+//! mount registers it once mounted and unregisters it once unmounted, so a
+//! record whose process is gone belongs to a mount that was never cleanly
+//! unmounted: [`FileMountRegistry::stale`] lists those, with the snapshot of
+//! their overrides where the process kept one, and
+//! [`MountRegistry::cleanup_stale`] drops them.
+//!
+//! ```rust
+//! use shadowfs_core::registry::FileMountRegistry;
+//! use shadowfs_core::types::{MountOptions, MountRecord};
+//!
+//! let dir = tempfile::tempdir()?;
+//! let registry = FileMountRegistry::new(dir.path());
+//! let record = MountRecord::new("/src".into(), "/mnt".into(), MountOptions::default(), std::process::id());
+//! registry.register_record(&record)?;
+//! assert_eq!(registry.records().len(), 1);
+//! assert!(registry.stale().is_empty());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::ShadowError;
+use crate::types::{MountRecord, MountRegistry};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Returns the directory holding the persistent configuration of shadowfs.
+///
+/// `SHADOWFS_CONFIG_DIR` overrides the platform default: `%APPDATA%\shadowfs`
+/// on Windows, `~/Library/Application Support/shadowfs` on macOS and
+/// `$XDG_CONFIG_HOME/shadowfs` or `~/.config/shadowfs` elsewhere.
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("SHADOWFS_CONFIG_DIR") {
+        return PathBuf::from(dir);
+    }
+    #[cfg(windows)]
+    if let Some(dir) = std::env::var_os("APPDATA") {
+        return PathBuf::from(dir).join("shadowfs");
+    }
+    #[cfg(target_os = "macos")]
+    if let Some(home) = std::env::var_os("HOME") {
+        return PathBuf::from(home).join("Library/Application Support/shadowfs");
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+            return PathBuf::from(dir).join("shadowfs");
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            return PathBuf::from(home).join(".config/shadowfs");
+        }
+    }
+    std::env::temp_dir().join("shadowfs-config")
+}
+
+/// Checks whether a process with `pid` is still running.
+pub fn is_process_alive(pid: u32) -> bool {
+    // 0 and anything past i32::MAX would address process groups on Unix
+    if pid == 0 || pid > i32::MAX as u32 {
+        return false;
+    }
+    
+    #[cfg(unix)]
+    {
+        // SAFETY: signal 0 performs only the existence and permission check
+        let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+        result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    
+    #[cfg(windows)]
+    {
+        use windows::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+        use windows::Win32::System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+        
+        // SAFETY: the handle is checked before use and closed exactly once
+        unsafe {
+            let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) else {
+                return false;
+            };
+            let mut code = 0u32;
+            let running = GetExitCodeProcess(process, &mut code).is_ok() && code == STILL_ACTIVE.0 as u32;
+            let _ = CloseHandle(process);
+            running
+        }
+    }
+    
+    #[cfg(not(any(unix, windows)))]
+    {
+        true
+    }
+}
+
+/// [`MountRegistry`] keeping one JSON file per mount record in a directory.
+///
+/// Records are written to a sibling first and renamed into place, so
+/// processes registering mounts at the same time never see a torn record.
+#[derive(Debug, Clone)]
+pub struct FileMountRegistry {
+    dir: PathBuf,
+}
+
+impl FileMountRegistry {
+    /// Creates a registry keeping its records in `dir`, which is created
+    /// on the first registration.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+    
+    /// Creates a registry in the `mounts` directory under [`config_dir`].
+    pub fn in_config_dir() -> Self {
+        Self::new(config_dir().join("mounts"))
+    }
+    
+    /// Returns the directory holding the records.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    
+    fn record_file(&self, id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+    
+    /// Writes `record`, replacing a record with the same id.
+    pub fn register_record(&self, record: &MountRecord) -> Result<(), ShadowError> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| ShadowError::IoError { source: e })?;
+        let data = serde_json::to_vec_pretty(record).map_err(|e| ShadowError::InvalidConfiguration {
+            message: format!("cannot serialize mount record: {}", e),
+        })?;
+        let path = self.record_file(record.id);
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, data).map_err(|e| ShadowError::IoError { source: e })?;
+        std::fs::rename(&temp, &path).map_err(|e| ShadowError::IoError { source: e })
+    }
+    
+    /// Removes the record with `id`; removing one that is not there is not
+    /// an error.
+    pub fn unregister_record(&self, id: Uuid) -> Result<(), ShadowError> {
+        match std::fs::remove_file(self.record_file(id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(ShadowError::IoError { source: e }),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Reads the record with `id`, if it is registered.
+    pub fn record(&self, id: Uuid) -> Option<MountRecord> {
+        let data = std::fs::read(self.record_file(id)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+    
+    /// Reads every record, sorted by mount point. Files that are not
+    /// records are skipped.
+    pub fn records(&self) -> Vec<MountRecord> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut records: Vec<MountRecord> = entries
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| std::fs::read(entry.path()).ok())
+            .filter_map(|data| serde_json::from_slice(&data).ok())
+            .collect();
+        records.sort_by(|a, b| a.target.cmp(&b.target));
+        records
+    }
+    
+    /// Returns the records of mounts whose process is gone without
+    /// unregistering them.
+    pub fn stale(&self) -> Vec<MountRecord> {
+        self.records()
+            .into_iter()
+            .filter(|record| !record.is_process_alive())
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl MountRegistry for FileMountRegistry {
+    async fn register(&mut self, record: MountRecord) -> Result<(), ShadowError> {
+        self.register_record(&record)
+    }
+    
+    async fn unregister(&mut self, id: Uuid) -> Result<(), ShadowError> {
+        self.unregister_record(id)
+    }
+    
+    async fn get(&self, id: Uuid) -> Option<MountRecord> {
+        self.record(id)
+    }
+    
+    async fn list(&self) -> Vec<MountRecord> {
+        self.records()
+    }
+    
+    async fn cleanup_stale(&mut self) -> Result<Vec<Uuid>, ShadowError> {
+        let mut removed = Vec::new();
+        for record in self.stale() {
+            self.unregister_record(record.id)?;
+            removed.push(record.id);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MountOptions;
+    
+    fn record(target: &str, process_id: u32) -> MountRecord {
+        MountRecord::new("/src".to_string(), target.to_string(), MountOptions::default(), process_id)
+    }
+    
+    #[tokio::test]
+    async fn test_records_survive_and_stale_ones_are_cleaned_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = FileMountRegistry::new(dir.path().join("mounts"));
+        assert!(registry.list().await.is_empty());
+        
+        let live = record("/mnt/b", std::process::id()).with_snapshot_path(dir.path().join("b.snapshot"));
+        let crashed = record("/mnt/a", u32::MAX);
+        registry.register(live.clone()).await.unwrap();
+        registry.register(crashed.clone()).await.unwrap();
+        
+        // A registry opened later, as after a restart, finds both
+        let mut reopened = FileMountRegistry::new(dir.path().join("mounts"));
+        let targets: Vec<String> = reopened.list().await.into_iter().map(|record| record.target).collect();
+        assert_eq!(targets, ["/mnt/a", "/mnt/b"]);
+        assert_eq!(reopened.get(live.id).await.unwrap().snapshot_path, live.snapshot_path);
+        
+        assert_eq!(reopened.stale().len(), 1);
+        assert_eq!(reopened.cleanup_stale().await.unwrap(), [crashed.id]);
+        assert!(reopened.get(crashed.id).await.is_none());
+        
+        reopened.unregister(live.id).await.unwrap();
+        reopened.unregister(live.id).await.unwrap();
+        assert!(reopened.list().await.is_empty());
+    }
+    
+    #[test]
+    fn test_process_liveness() {
+        assert!(is_process_alive(std::process::id()));
+        assert!(!is_process_alive(0));
+        assert!(!is_process_alive(u32::MAX));
+    }
+}
@@ -0,0 +1,215 @@
+//! Template rules kept in a file, for changing the rules of a running mount.
+//!
+//! The [`RuleSet`] a mount applies lives in its serving process. `shadowfs
+//! rules` edits a [`RuleBook`] file next to the mount record instead, and the
+//! serving process picks the changes up with a [`RuleBookSync`], which adds
+//! and removes rules in place so the rules that stay keep their hit counts.
+//!
+//! Each rule replaces the source files matching a glob, such as `*.env`,
+//! with a template expanded for the path; see [`OverrideTemplate`] for the
+//! variables it may use.
+
+use crate::error::ShadowError;
+use crate::override_store::{
+    OverrideCondition, OverrideContentType, OverrideRule, OverrideRuleEntry, OverrideTemplate, RuleId, RulePriority,
+    RuleSet,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Version of the rule book file written by [`RuleBook::save`].
+const RULE_BOOK_VERSION: u32 = 1;
+
+/// A rule replacing the source files that match a glob with a template.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateRule {
+    /// Identifier within the rule book
+    pub id: u64,
+    
+    /// Glob matched against the path in the mount, such as `*.env`
+    pub glob: String,
+    
+    /// Content replacing matching files
+    pub template: String,
+    
+    /// Rules with higher priorities are tried first
+    pub priority: u32,
+}
+
+impl TemplateRule {
+    /// Builds the rule set entry of the rule.
+    pub fn to_entry(&self) -> OverrideRuleEntry {
+        OverrideRuleEntry {
+            rule: OverrideRule::Glob(self.glob.clone()),
+            priority: RulePriority(self.priority),
+            condition: OverrideCondition::Always,
+            content: OverrideContentType::Template(OverrideTemplate::new(self.template.clone())),
+        }
+    }
+}
+
+/// Template rules of a mount, as kept in its rules file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleBook {
+    version: u32,
+    
+    /// Identifier given to the next rule added
+    next_id: u64,
+    
+    /// Rules in the order they were added
+    pub rules: Vec<TemplateRule>,
+}
+
+impl Default for RuleBook {
+    fn default() -> Self {
+        Self { version: RULE_BOOK_VERSION, next_id: 1, rules: Vec::new() }
+    }
+}
+
+impl RuleBook {
+    /// Creates an empty rule book.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Adds a rule, returning its identifier.
+    pub fn add(&mut self, glob: impl Into<String>, template: impl Into<String>, priority: u32) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.rules.push(TemplateRule { id, glob: glob.into(), template: template.into(), priority });
+        id
+    }
+    
+    /// Removes the rule with `id`.
+    ///
+    /// # Returns
+    /// false if no such rule exists
+    pub fn remove(&mut self, id: u64) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|rule| rule.id != id);
+        self.rules.len() != before
+    }
+    
+    /// Loads the rule book saved at `path`; a missing file gives an empty book.
+    pub fn load(path: &Path) -> Result<Self, ShadowError> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(ShadowError::IoError { source: e }),
+        };
+        let book: RuleBook = serde_json::from_slice(&data).map_err(|e| ShadowError::InvalidConfiguration {
+            message: format!("invalid rules file {}: {}", path.display(), e),
+        })?;
+        if book.version != RULE_BOOK_VERSION {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!("unsupported rules file version {}", book.version),
+            });
+        }
+        Ok(book)
+    }
+    
+    /// Writes the rule book to `path`, replacing the previous file at once so
+    /// the serving process never reads half of it.
+    pub fn save(&self, path: &Path) -> Result<(), ShadowError> {
+        let data = serde_json::to_vec_pretty(self).map_err(|e| ShadowError::InvalidConfiguration {
+            message: format!("cannot serialize rules: {}", e),
+        })?;
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, data).map_err(|e| ShadowError::IoError { source: e })?;
+        std::fs::rename(&temp, path).map_err(|e| ShadowError::IoError { source: e })
+    }
+}
+
+/// Keeps a [`RuleSet`] in step with a rule book.
+pub struct RuleBookSync {
+    rule_set: Arc<RuleSet>,
+    
+    /// Rules installed in the rule set, by rule book identifier
+    installed: BTreeMap<u64, (TemplateRule, RuleId)>,
+}
+
+impl RuleBookSync {
+    /// Starts syncing into `rule_set`, which should hold no other rules.
+    pub fn new(rule_set: Arc<RuleSet>) -> Self {
+        Self { rule_set, installed: BTreeMap::new() }
+    }
+    
+    /// Adds the rules new to `book` and removes those no longer in it; a
+    /// rule that was changed is replaced and starts counting hits anew.
+    ///
+    /// # Returns
+    /// Numbers of rules added and removed
+    pub fn apply(&mut self, book: &RuleBook) -> (usize, usize) {
+        let wanted: BTreeMap<u64, &TemplateRule> = book.rules.iter().map(|rule| (rule.id, rule)).collect();
+        let stale: Vec<u64> = self.installed.iter()
+            .filter(|(id, (rule, _))| wanted.get(id) != Some(&rule))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &stale {
+            let (_, rule_id) = self.installed.remove(id).unwrap();
+            self.rule_set.remove_rule(rule_id);
+        }
+        
+        let mut added = 0;
+        for (id, rule) in wanted {
+            if !self.installed.contains_key(&id) {
+                let rule_id = self.rule_set.add_rule(rule.to_entry());
+                self.installed.insert(id, (rule.clone(), rule_id));
+                added += 1;
+            }
+        }
+        (added, stale.len())
+    }
+    
+    /// Returns the hits of each installed rule, by rule book identifier.
+    pub fn hits(&self) -> BTreeMap<u64, u64> {
+        self.installed.iter()
+            .map(|(id, (_, rule_id))| (*id, self.rule_set.hits(*rule_id).unwrap_or(0)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ShadowPath;
+    
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rules.json");
+        assert_eq!(RuleBook::load(&path).unwrap(), RuleBook::new());
+        
+        let mut book = RuleBook::new();
+        let env = book.add("*.env", "# masked\n", 500);
+        let key = book.add("*.pem", "", 500);
+        assert!(book.remove(key));
+        assert!(!book.remove(key));
+        book.save(&path).unwrap();
+        
+        let mut loaded = RuleBook::load(&path).unwrap();
+        assert_eq!(loaded.rules.iter().map(|rule| rule.id).collect::<Vec<_>>(), vec![env]);
+        assert_eq!(loaded.add("*.key", "", 0), key + 1);
+    }
+    
+    #[test]
+    fn test_sync_keeps_hits() {
+        let rule_set = Arc::new(RuleSet::new());
+        let mut sync = RuleBookSync::new(Arc::clone(&rule_set));
+        let mut book = RuleBook::new();
+        let env = book.add("*.env", "masked", 500);
+        assert_eq!(sync.apply(&book), (1, 0));
+        
+        rule_set.record_match(&ShadowPath::from("/app/.env"), None).unwrap();
+        let pem = book.add("*.pem", "", 500);
+        assert_eq!(sync.apply(&book), (1, 0));
+        assert_eq!(sync.hits(), BTreeMap::from([(env, 1), (pem, 0)]));
+        
+        book.remove(env);
+        assert_eq!(sync.apply(&book), (0, 1));
+        assert_eq!(rule_set.rule_count(), 1);
+        assert!(rule_set.find_match(&ShadowPath::from("/app/.env"), None).is_none());
+    }
+}
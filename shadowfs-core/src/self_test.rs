@@ -0,0 +1,240 @@
+//! Round trips of a probe file through a fresh mount.
+//!
+//! A mount can come up half-working: the kernel accepts it, but the
+//! provider fails its first write, or writes never reach the store. With
+//! [`MountOptions::self_test`](crate::types::MountOptions::self_test) set,
+//! [`MountManager::mount`](crate::mount_manager::MountManager::mount) calls
+//! [`run`] right after the provider mounts. The test lists the mount root,
+//! then creates, reads, rewrites and deletes a probe file through the mount
+//! point, the way any other program would, and checks the store saw the
+//! writes. If a step fails, the manager unmounts again and fails the mount
+//! with the [`SelfTestReport`].
+//!
+//! Read-only mounts only have their root listed. The probe is created in
+//! the root of the mount, not as a dotfile, so mounts hiding dotfiles can
+//! still be tested, and is dropped from the store afterwards, passed or
+//! not. Mounts served in process, such as
+//! [`VirtualFs`](crate::virtual_fs::VirtualFs), have no mount point to go
+//! through and should not ask for the test.
+
+use crate::clock;
+use crate::override_store::OverrideStore;
+use crate::types::ShadowPath;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long the store gets to see a write made through the mount; some
+/// providers capture writes only once the file is closed.
+pub const STORE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Content the probe file is created with.
+const CREATED: &[u8] = b"shadowfs self-test\n";
+
+/// Content the probe file is rewritten with.
+const REWRITTEN: &[u8] = b"shadowfs self-test, rewritten\n";
+
+/// One step of a self-test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestStep {
+    /// List the root of the mount
+    List,
+    /// Create the probe file
+    Create,
+    /// Read the probe file back
+    Read,
+    /// Rewrite the probe file
+    Write,
+    /// Find the rewritten content in the store
+    Verify,
+    /// Delete the probe file and check it is gone from the mount and the store
+    Delete,
+}
+
+impl fmt::Display for SelfTestStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SelfTestStep::List => "list",
+            SelfTestStep::Create => "create",
+            SelfTestStep::Read => "read",
+            SelfTestStep::Write => "write",
+            SelfTestStep::Verify => "verify",
+            SelfTestStep::Delete => "delete",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What a self-test did and, if it failed, where.
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    /// Mount point the test went through
+    pub mount_point: PathBuf,
+    
+    /// Path of the probe file in the mount
+    pub probe: ShadowPath,
+    
+    /// Steps that passed, in order
+    pub passed: Vec<SelfTestStep>,
+    
+    /// The step that failed and why, if one did
+    pub failure: Option<(SelfTestStep, String)>,
+    
+    /// How long the test took
+    pub elapsed: Duration,
+}
+
+impl SelfTestReport {
+    /// Returns true if every step passed.
+    pub fn is_ok(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            None => write!(f, "self-test of {} passed", self.mount_point.display())?,
+            Some((step, reason)) => write!(
+                f,
+                "self-test of {} failed at {} of {}: {}",
+                self.mount_point.display(),
+                step,
+                self.probe,
+                reason,
+            )?,
+        }
+        let passed: Vec<String> = self.passed.iter().map(ToString::to_string).collect();
+        write!(f, " (passed: {}; {:?})", if passed.is_empty() { "none".to_string() } else { passed.join(", ") }, self.elapsed)
+    }
+}
+
+/// Runs the self-test through the mount at `mount_point` of `store`.
+///
+/// Blocks on the mount, so async callers run it on the blocking pool.
+pub fn run(mount_point: &Path, store: &OverrideStore, read_only: bool) -> SelfTestReport {
+    probe(mount_point, store, read_only, STORE_TIMEOUT)
+}
+
+/// Runs the self-test, giving the store `store_timeout` to see each write.
+fn probe(mount_point: &Path, store: &OverrideStore, read_only: bool, store_timeout: Duration) -> SelfTestReport {
+    let started = Instant::now();
+    let name = format!("shadowfs-self-test-{}-{}", std::process::id(), clock::now());
+    let mut report = SelfTestReport {
+        mount_point: mount_point.to_path_buf(),
+        probe: ShadowPath::from("/").join(&name),
+        passed: Vec::new(),
+        failure: None,
+        elapsed: Duration::ZERO,
+    };
+    
+    let host = mount_point.join(&name);
+    let steps: &[SelfTestStep] = if read_only {
+        &[SelfTestStep::List]
+    } else {
+        &[SelfTestStep::List, SelfTestStep::Create, SelfTestStep::Read, SelfTestStep::Write, SelfTestStep::Verify, SelfTestStep::Delete]
+    };
+    for &step in steps {
+        match run_step(step, mount_point, &host, &report.probe, store, store_timeout) {
+            Ok(()) => report.passed.push(step),
+            Err(reason) => {
+                report.failure = Some((step, reason));
+                break;
+            }
+        }
+    }
+    
+    if !read_only {
+        if report.failure.is_some() {
+            let _ = std::fs::remove_file(&host);
+        }
+        store.remove(&report.probe);
+    }
+    report.elapsed = started.elapsed();
+    report
+}
+
+fn run_step(
+    step: SelfTestStep,
+    mount_point: &Path,
+    host: &Path,
+    probe: &ShadowPath,
+    store: &OverrideStore,
+    store_timeout: Duration,
+) -> Result<(), String> {
+    match step {
+        SelfTestStep::List => std::fs::read_dir(mount_point).map(drop).map_err(|e| e.to_string()),
+        SelfTestStep::Create => std::fs::write(host, CREATED).map_err(|e| e.to_string()),
+        SelfTestStep::Read => expect_content(host, CREATED),
+        SelfTestStep::Write => {
+            std::fs::write(host, REWRITTEN).map_err(|e| e.to_string())?;
+            expect_content(host, REWRITTEN)
+        }
+        SelfTestStep::Verify => wait_for_store(store_timeout, || {
+            match store.get(probe).map(|entry| entry.get_file_data()) {
+                Some(Ok(Some(data))) if data == REWRITTEN => Ok(()),
+                Some(Ok(Some(data))) => Err(format!("store holds {} bytes, not the {} written", data.len(), REWRITTEN.len())),
+                Some(Ok(None)) => Err("store holds no file content".to_string()),
+                Some(Err(e)) => Err(format!("store cannot read the content: {}", e)),
+                None => Err("store has no override".to_string()),
+            }
+        }),
+        SelfTestStep::Delete => {
+            std::fs::remove_file(host).map_err(|e| e.to_string())?;
+            if host.symlink_metadata().is_ok() {
+                return Err("probe is still in the mount".to_string());
+            }
+            wait_for_store(store_timeout, || match store.get(probe) {
+                Some(entry) if !entry.is_deleted() => Err("store still holds the probe".to_string()),
+                _ => Ok(()),
+            })
+        }
+    }
+}
+
+/// Reads `host` through the mount, expecting `expected`.
+fn expect_content(host: &Path, expected: &[u8]) -> Result<(), String> {
+    let data = std::fs::read(host).map_err(|e| e.to_string())?;
+    if data != expected {
+        return Err(format!("read {} bytes, not the {} written", data.len(), expected.len()));
+    }
+    Ok(())
+}
+
+/// Retries `check` until it passes or `timeout` passes.
+fn wait_for_store(timeout: Duration, mut check: impl FnMut() -> Result<(), String>) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let result = check();
+        if result.is_ok() || Instant::now() >= deadline {
+            return result;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_writes_that_miss_the_store_fail() {
+        // A plain directory stands in for a mount whose writes never reach the store
+        let mount = TempDir::new().unwrap();
+        let store = OverrideStore::with_defaults();
+        
+        let report = probe(mount.path(), &store, false, Duration::from_millis(50));
+        assert_eq!(report.passed, [SelfTestStep::List, SelfTestStep::Create, SelfTestStep::Read, SelfTestStep::Write]);
+        assert!(matches!(&report.failure, Some((SelfTestStep::Verify, reason)) if reason.contains("no override")));
+        assert!(report.to_string().contains("failed at verify"));
+        
+        // The probe is cleaned up
+        assert_eq!(std::fs::read_dir(mount.path()).unwrap().count(), 0);
+        assert_eq!(store.entry_count(), 0);
+        
+        let read_only = run(mount.path(), &store, true);
+        assert!(read_only.is_ok());
+        assert_eq!(read_only.passed, [SelfTestStep::List]);
+    }
+}
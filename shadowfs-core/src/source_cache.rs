@@ -0,0 +1,503 @@
+//! Open handles and memory maps of frequently read source files.
+//!
+//! Reads that fall through to the source tree would otherwise open, seek,
+//! read and close the file on every call. A [`SourceFileCache`] attached to
+//! the store with [`OverrideStore::set_source_cache`] keeps the most
+//! recently read files open and serves reads with positional reads on the
+//! open handle. Files at least [`SourceCacheConfig::map_threshold`] bytes
+//! long are memory-mapped instead, within [`SourceCacheConfig::max_mapped_bytes`].
+//!
+//! Every read compares the file's length and modification time with the
+//! ones seen when it was opened and reopens a file that changed, so a cache
+//! never serves stale content for longer than one read racing the change.
+//! Source watchers can also drop files as soon as they change with
+//! [`SourceFileCache::invalidate`].
+//!
+//! Mapping is off by default: a mapped file truncated by another process
+//! faults the reader, so only sources that do not change, such as the ones
+//! mounted with `MountOptions::immutable_source`, should be mapped; see
+//! [`SourceCacheConfig::for_immutable_source`].
+//!
+//! ```rust
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::source_cache::{SourceCacheConfig, SourceFileCache};
+//! use std::sync::Arc;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let file = dir.path().join("data.bin");
+//! std::fs::write(&file, b"0123456789").unwrap();
+//!
+//! let store = OverrideStore::with_defaults();
+//! store.set_source_cache(Arc::new(SourceFileCache::new(SourceCacheConfig::default())));
+//! assert_eq!(&store.read_source(&file, 2, 3).unwrap()[..], b"234");
+//! assert_eq!(&store.read_source(&file, 8, 10).unwrap()[..], b"89");
+//!
+//! let stats = store.get_stats_report().source_cache.unwrap();
+//! assert_eq!((stats.misses, stats.hits), (1, 1));
+//! ```
+
+use crate::override_store::OverrideStore;
+use bytes::Bytes;
+use lru::LruCache;
+use std::fs::File;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Source files kept open by default.
+pub const DEFAULT_MAX_OPEN_FILES: usize = 256;
+
+/// Smallest file [`SourceCacheConfig::for_immutable_source`] maps.
+pub const DEFAULT_MAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Bytes of source files mapped at once by default.
+pub const DEFAULT_MAX_MAPPED_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Limits of a [`SourceFileCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceCacheConfig {
+    /// Most source files kept open, mapped or not
+    pub max_open_files: usize,
+    
+    /// Files at least this long are memory-mapped; `None` maps none
+    pub map_threshold: Option<u64>,
+    
+    /// Most bytes mapped at once; larger files are read through their handle
+    pub max_mapped_bytes: u64,
+}
+
+impl Default for SourceCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            map_threshold: None,
+            max_mapped_bytes: DEFAULT_MAX_MAPPED_BYTES,
+        }
+    }
+}
+
+impl SourceCacheConfig {
+    /// Limits for a source that does not change while mounted, mapping
+    /// files of [`DEFAULT_MAP_THRESHOLD`] bytes or more.
+    pub fn for_immutable_source() -> Self {
+        Self {
+            map_threshold: Some(DEFAULT_MAP_THRESHOLD),
+            ..Self::default()
+        }
+    }
+}
+
+/// Counters of a [`SourceFileCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceCacheStats {
+    /// Files currently open
+    pub open_files: usize,
+    
+    /// Files among them that are memory-mapped
+    pub mapped_files: usize,
+    
+    /// Bytes currently mapped
+    pub mapped_bytes: u64,
+    
+    /// Reads served by a file that was already open
+    pub hits: u64,
+    
+    /// Reads that had to open their file
+    pub misses: u64,
+    
+    /// Files dropped because they changed
+    pub invalidations: u64,
+    
+    /// Files closed to stay within the limits
+    pub evictions: u64,
+}
+
+/// Read-only memory map of a whole file.
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// SAFETY: the mapping is read-only and only ever read through shared references
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+#[cfg(unix)]
+unsafe impl Sync for Mapping {}
+
+#[cfg(unix)]
+impl Mapping {
+    /// Maps the first `len` bytes of `file`, which must be at least one.
+    fn new(file: &File, len: usize) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        // SAFETY: maps an open file read-only; the result is checked before use
+        let ptr = unsafe {
+            libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+    
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: `ptr` points to `len` mapped bytes until the mapping is dropped
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly the region mapped in `new`
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// An open source file and what it looked like when opened.
+struct CachedFile {
+    file: File,
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    mapping: Option<Mapping>,
+}
+
+impl CachedFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        Ok(Self {
+            file,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            mapping: None,
+        })
+    }
+    
+    /// Returns true if `metadata` of the path still describes the open file.
+    fn is_current(&self, metadata: &std::fs::Metadata) -> bool {
+        self.modified.is_some() && self.modified == metadata.modified().ok() && self.len == metadata.len()
+    }
+    
+    fn mapped_len(&self) -> u64 {
+        #[cfg(unix)]
+        if self.mapping.is_some() {
+            return self.len;
+        }
+        0
+    }
+    
+    fn read(&self, offset: u64, size: usize) -> io::Result<Bytes> {
+        #[cfg(unix)]
+        if let Some(mapping) = &self.mapping {
+            let data = mapping.bytes();
+            let start = offset.min(data.len() as u64) as usize;
+            let end = start.saturating_add(size).min(data.len());
+            return Ok(Bytes::copy_from_slice(&data[start..end]));
+        }
+        read_file_at(&self.file, offset, size)
+    }
+}
+
+/// Reads up to `size` bytes at `offset` of `file`, fewer at its end.
+fn read_file_at(file: &File, offset: u64, size: usize) -> io::Result<Bytes> {
+    let mut data = vec![0u8; size];
+    let mut filled = 0;
+    while filled < size {
+        match read_at(file, &mut data[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    data.truncate(filled);
+    Ok(Bytes::from(data))
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buffer, offset)
+}
+
+// Without positional reads the handle is seeked; these targets are single-threaded
+#[cfg(not(any(unix, windows)))]
+fn read_at(mut file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::io::{Read, Seek, SeekFrom};
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buffer)
+}
+
+/// Open handles and memory maps of recently read source files.
+pub struct SourceFileCache {
+    config: SourceCacheConfig,
+    files: Mutex<LruCache<PathBuf, Arc<CachedFile>>>,
+    mapped_bytes: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl std::fmt::Debug for SourceFileCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SourceFileCache")
+            .field("config", &self.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl SourceFileCache {
+    /// Creates an empty cache within `config`'s limits.
+    pub fn new(config: SourceCacheConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.max_open_files).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            config,
+            files: Mutex::new(LruCache::new(capacity)),
+            mapped_bytes: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+    
+    /// Returns the limits of the cache.
+    pub fn config(&self) -> &SourceCacheConfig {
+        &self.config
+    }
+    
+    /// Reads up to `size` bytes at `offset` of the source file `path`;
+    /// reads past the end of the file return fewer bytes.
+    pub fn read(&self, path: &Path, offset: u64, size: usize) -> io::Result<Bytes> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.invalidate(path, false);
+                return Err(e);
+            }
+        };
+        let file = match self.lookup(path, &metadata) {
+            Some(file) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                file
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                self.insert(path, CachedFile::open(path)?)
+            }
+        };
+        file.read(offset, size)
+    }
+    
+    /// Returns the open file at `path` if it has not changed since opened,
+    /// dropping it if it has.
+    fn lookup(&self, path: &Path, metadata: &std::fs::Metadata) -> Option<Arc<CachedFile>> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.get(path)?;
+        if file.is_current(metadata) {
+            return Some(Arc::clone(file));
+        }
+        if let Some(stale) = files.pop(path) {
+            self.release(&stale);
+            self.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+        None
+    }
+    
+    /// Caches a newly opened file, mapping it if it is large enough and
+    /// closing the least recently read files to make room.
+    fn insert(&self, path: &Path, file: CachedFile) -> Arc<CachedFile> {
+        let mut files = self.files.lock().unwrap();
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut file = file;
+        #[cfg(unix)]
+        if self.should_map(file.len) {
+            self.make_room_for_mapping(&mut files, file.len);
+            file.mapping = Mapping::new(&file.file, file.len as usize).ok();
+            self.mapped_bytes.fetch_add(file.mapped_len(), Ordering::Relaxed);
+        }
+        let file = Arc::new(file);
+        // Replaces the file when another reader opened it first
+        if let Some((evicted_path, evicted)) = files.push(path.to_path_buf(), Arc::clone(&file)) {
+            self.release(&evicted);
+            if evicted_path != path {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        file
+    }
+    
+    #[cfg(unix)]
+    fn should_map(&self, len: u64) -> bool {
+        self.config.map_threshold.is_some_and(|threshold| len >= threshold.max(1) && len <= self.config.max_mapped_bytes)
+    }
+    
+    /// Closes the least recently read mapped files until `len` more bytes
+    /// fit within the mapping limit.
+    #[cfg(unix)]
+    fn make_room_for_mapping(&self, files: &mut LruCache<PathBuf, Arc<CachedFile>>, len: u64) {
+        let mut mapped = self.mapped_bytes.load(Ordering::Relaxed);
+        if mapped + len <= self.config.max_mapped_bytes {
+            return;
+        }
+        let mut victims = Vec::new();
+        for (path, file) in files.iter().rev() {
+            if mapped + len <= self.config.max_mapped_bytes {
+                break;
+            }
+            if file.mapped_len() > 0 {
+                mapped -= file.mapped_len();
+                victims.push(path.clone());
+            }
+        }
+        for path in victims {
+            if let Some(file) = files.pop(&path) {
+                self.release(&file);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    
+    /// Stops counting the mapping of a file leaving the cache.
+    fn release(&self, file: &CachedFile) {
+        self.mapped_bytes.fetch_sub(file.mapped_len(), Ordering::Relaxed);
+    }
+    
+    /// Returns true if the file at `path` is open in the cache.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains(path)
+    }
+    
+    /// Drops the cached file at `path`, and with `recursive` every file
+    /// underneath it, so the next read reopens them.
+    pub fn invalidate(&self, path: &Path, recursive: bool) {
+        let mut files = self.files.lock().unwrap();
+        let paths: Vec<PathBuf> = if recursive {
+            files.iter().map(|(cached, _)| cached).filter(|cached| cached.starts_with(path)).cloned().collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        for path in paths {
+            if let Some(file) = files.pop(&path) {
+                self.release(&file);
+                self.invalidations.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    
+    /// Closes every cached file.
+    pub fn clear(&self) {
+        let mut files = self.files.lock().unwrap();
+        while let Some((_, file)) = files.pop_lru() {
+            self.release(&file);
+        }
+    }
+    
+    /// Returns the counters of the cache.
+    pub fn stats(&self) -> SourceCacheStats {
+        let files = self.files.lock().unwrap();
+        SourceCacheStats {
+            open_files: files.len(),
+            mapped_files: files.iter().filter(|(_, file)| file.mapped_len() > 0).count(),
+            mapped_bytes: self.mapped_bytes.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl OverrideStore {
+    /// Serves [`read_source`](Self::read_source) through `cache`.
+    pub fn set_source_cache(&self, cache: Arc<SourceFileCache>) {
+        *self.source_cache.write().unwrap() = Some(cache);
+    }
+    
+    /// Returns the attached source file cache, if any.
+    pub fn source_cache(&self) -> Option<Arc<SourceFileCache>> {
+        self.source_cache.read().unwrap().clone()
+    }
+    
+    /// Reads up to `size` bytes at `offset` of the source file `file`,
+    /// through the source file cache when one is attached.
+    pub fn read_source(&self, file: &Path, offset: u64, size: usize) -> io::Result<Bytes> {
+        match self.source_cache() {
+            Some(cache) => cache.read(file, offset, size),
+            None => read_file_at(&File::open(file)?, offset, size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_changed_files_are_reopened() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "first").unwrap();
+        let cache = SourceFileCache::new(SourceCacheConfig::default());
+        
+        assert_eq!(&cache.read(&path, 0, 100).unwrap()[..], b"first");
+        assert_eq!(&cache.read(&path, 1, 2).unwrap()[..], b"ir");
+        std::fs::write(&path, "second version").unwrap();
+        assert_eq!(&cache.read(&path, 0, 100).unwrap()[..], b"second version");
+        
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (1, 2, 1));
+        assert_eq!(stats.open_files, 1);
+        
+        std::fs::remove_file(&path).unwrap();
+        assert!(cache.read(&path, 0, 1).is_err());
+        assert_eq!(cache.stats().open_files, 0);
+    }
+    
+    #[test]
+    fn test_limits_and_invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let paths: Vec<PathBuf> = ["a", "b", "sub/c", "sub/d"].iter().map(|name| dir.path().join(name)).collect();
+        for path in &paths {
+            std::fs::write(path, vec![b'x'; 64]).unwrap();
+        }
+        let cache = SourceFileCache::new(SourceCacheConfig {
+            max_open_files: 3,
+            map_threshold: Some(32),
+            max_mapped_bytes: 128,
+        });
+        
+        for path in &paths {
+            assert_eq!(cache.read(path, 60, 10).unwrap().len(), 4);
+        }
+        let stats = cache.stats();
+        assert!(stats.open_files <= 3);
+        assert!(stats.mapped_bytes <= 128);
+        assert!(stats.evictions >= 1);
+        #[cfg(unix)]
+        assert_eq!(stats.mapped_files, 2);
+        
+        cache.invalidate(&dir.path().join("sub"), true);
+        assert!(cache.stats().open_files <= 1);
+        cache.clear();
+        assert_eq!(cache.stats().open_files, 0);
+        assert_eq!(cache.stats().mapped_bytes, 0);
+    }
+}
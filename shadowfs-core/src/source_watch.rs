@@ -0,0 +1,384 @@
+//! Watching the source tree for changes made outside the shadow layer.
+//!
+//! Each platform crate implements [`SourceWatcher`] with its native change
+//! notification API and reports changes, relative to the source root, to a
+//! [`SourceChangeSink`]. The [`SourceChangeHandler`] is the sink shared by
+//! all platforms. For every change it
+//!
+//! - marks the directory listings showing the path stale in the store's
+//!   [`ListingCache`](crate::listing_cache::ListingCache), if attached,
+//! - runs the registered invalidation callbacks so metadata caches drop
+//!   stale source information,
+//! - detects a [`SourceConflict`] when the changed path also has an
+//!   override, since the override was made against older source content,
+//!   and deals with it as its [`ConflictPolicy`] says; marked conflicts are
+//!   kept in the store until [`OverrideStore::resolve_conflict`] settles them,
+//! - publishes a [`ChangeEvent`] to the store's watch service when the
+//!   change is visible through the mount, that is when no override hides it.
+
+use crate::error::ShadowError;
+use crate::override_store::OverrideStore;
+use crate::types::{current_time, ShadowPath};
+use crate::watch::{ChangeEvent, ChangeKind, ChangeSource};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// A change to the source tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceChange {
+    /// Path relative to the source root, in the same form as override paths
+    pub path: ShadowPath,
+    
+    /// What happened
+    pub kind: ChangeKind,
+    
+    /// Whether the path is a directory
+    pub is_directory: bool,
+    
+    /// Whether anything below the path may have changed, for example after
+    /// the watcher dropped events; consumers must rescan the whole subtree
+    pub recursive: bool,
+}
+
+impl SourceChange {
+    /// Creates a change to a single path.
+    pub fn new(path: ShadowPath, kind: ChangeKind, is_directory: bool) -> Self {
+        Self {
+            path,
+            kind,
+            is_directory,
+            recursive: false,
+        }
+    }
+    
+    /// Creates a change covering the directory `path` and everything below it.
+    pub fn rescan(path: ShadowPath) -> Self {
+        Self {
+            path,
+            kind: ChangeKind::Modified,
+            is_directory: true,
+            recursive: true,
+        }
+    }
+    
+    /// Creates a change from a path relative to the source root.
+    pub fn from_relative(relative: &Path, kind: ChangeKind, is_directory: bool) -> Self {
+        Self::new(source_path(relative), kind, is_directory)
+    }
+}
+
+/// Converts a path relative to the source root into an override path.
+pub fn source_path(relative: &Path) -> ShadowPath {
+    crate::mount_paths::relative_shadow_path(relative)
+}
+
+/// Receives batches of source changes from a [`SourceWatcher`].
+pub trait SourceChangeSink: Send + Sync {
+    /// Handles a batch of changes, in the order they were observed.
+    fn on_source_changes(&self, changes: Vec<SourceChange>);
+}
+
+/// Watches the source directory of a mount.
+pub trait SourceWatcher: Send {
+    /// Starts watching, delivering changes to `sink` until [`stop`](Self::stop) is called.
+    fn start(&mut self, sink: Arc<dyn SourceChangeSink>) -> Result<(), ShadowError>;
+    
+    /// Stops watching; no changes are delivered once this returns.
+    fn stop(&mut self);
+    
+    /// Returns true while the watcher is running.
+    fn is_running(&self) -> bool;
+}
+
+/// A source change to a path that also has an override.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceConflict {
+    /// Path with the override
+    pub path: ShadowPath,
+    
+    /// Most recent source change seen for the path
+    pub kind: ChangeKind,
+    
+    /// When the conflict was first detected
+    pub detected_at: SystemTime,
+}
+
+/// What a [`SourceChangeHandler`] does when the source changes underneath
+/// an override.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ConflictPolicy {
+    /// Keep the override and record nothing
+    Ignore,
+    /// Drop the override so the changed source shows through the mount
+    InvalidateOverride,
+    /// Keep the override and record the conflict for later resolution
+    #[default]
+    MarkConflicted,
+}
+
+/// Callback invalidating cached source information for a path.
+///
+/// The second argument is true when everything below the path is stale too.
+pub type InvalidateFn = Box<dyn Fn(&ShadowPath, bool) + Send + Sync>;
+
+/// Callback told of each conflict detected, with the policy applied to it.
+pub type ConflictFn = Box<dyn Fn(&SourceConflict, ConflictPolicy) + Send + Sync>;
+
+/// Applies source changes to an override store.
+pub struct SourceChangeHandler {
+    store: Arc<OverrideStore>,
+    invalidators: RwLock<Vec<InvalidateFn>>,
+    listeners: RwLock<Vec<ConflictFn>>,
+    policy: RwLock<ConflictPolicy>,
+}
+
+impl std::fmt::Debug for SourceChangeHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SourceChangeHandler")
+            .field("invalidators", &self.invalidators.read().unwrap().len())
+            .field("listeners", &self.listeners.read().unwrap().len())
+            .field("policy", &*self.policy.read().unwrap())
+            .finish()
+    }
+}
+
+impl SourceChangeHandler {
+    /// Creates a handler for the source tree underneath `store` that marks
+    /// overrides as conflicted.
+    pub fn new(store: Arc<OverrideStore>) -> Self {
+        Self::with_policy(store, ConflictPolicy::default())
+    }
+    
+    /// Creates a handler for the source tree underneath `store` that deals
+    /// with conflicts as `policy` says.
+    pub fn with_policy(store: Arc<OverrideStore>, policy: ConflictPolicy) -> Self {
+        Self {
+            store,
+            invalidators: RwLock::new(Vec::new()),
+            listeners: RwLock::new(Vec::new()),
+            policy: RwLock::new(policy),
+        }
+    }
+    
+    /// Changes how conflicts detected from now on are dealt with.
+    pub fn set_policy(&self, policy: ConflictPolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+    
+    /// Returns how conflicts are dealt with.
+    pub fn policy(&self) -> ConflictPolicy {
+        *self.policy.read().unwrap()
+    }
+    
+    /// Registers a callback run for every changed path before anything else.
+    pub fn on_invalidate(&self, invalidate: impl Fn(&ShadowPath, bool) + Send + Sync + 'static) {
+        self.invalidators.write().unwrap().push(Box::new(invalidate));
+    }
+    
+    /// Registers a callback run for every conflict detected, unless the
+    /// policy is [`ConflictPolicy::Ignore`].
+    pub fn on_conflict(&self, listener: impl Fn(&SourceConflict, ConflictPolicy) + Send + Sync + 'static) {
+        self.listeners.write().unwrap().push(Box::new(listener));
+    }
+    
+    /// Returns the conflicts recorded in the store, sorted by path.
+    ///
+    /// See [`OverrideStore::list_conflicts`].
+    pub fn conflicts(&self) -> Vec<SourceConflict> {
+        self.store.list_conflicts()
+    }
+    
+    /// Returns the conflict recorded for `path`, if any.
+    pub fn conflict(&self, path: &ShadowPath) -> Option<SourceConflict> {
+        self.store.source_conflict(path)
+    }
+    
+    /// Returns and forgets the conflicts recorded in the store.
+    pub fn take_conflicts(&self) -> Vec<SourceConflict> {
+        self.store.take_source_conflicts()
+    }
+    
+    /// Applies one source change.
+    pub fn handle(&self, change: SourceChange) {
+        self.store.invalidate_listing(&change.path, change.recursive);
+        if let ChangeKind::Renamed { from } = &change.kind {
+            self.store.invalidate_listing(from, change.recursive);
+        }
+        for invalidate in self.invalidators.read().unwrap().iter() {
+            invalidate(&change.path, change.recursive);
+            if let ChangeKind::Renamed { from } = &change.kind {
+                invalidate(from, change.recursive);
+            }
+        }
+        
+        let mut touched = vec![change.path.clone()];
+        if let ChangeKind::Renamed { from } = &change.kind {
+            touched.push(from.clone());
+        }
+        if change.recursive {
+            let below: Vec<ShadowPath> = touched.iter()
+                .flat_map(|path| self.store.get_children_recursive(path))
+                .collect();
+            touched.extend(below);
+        }
+        
+        let mut hidden = false;
+        for path in &touched {
+            hidden |= self.record_conflict(path, &change.kind) && *path == change.path;
+        }
+        
+        if !hidden {
+            if let Some(service) = self.store.watch_service() {
+                service.publish(ChangeEvent::new(
+                    change.path,
+                    change.kind,
+                    change.is_directory,
+                    ChangeSource::SourceTree,
+                ));
+            }
+        }
+    }
+    
+    /// Applies the policy if `path` has a file override or tombstone.
+    ///
+    /// Directory overrides merge with the source listing, so source changes
+    /// to them never conflict.
+    ///
+    /// # Returns
+    /// Whether an override still hides the source path
+    fn record_conflict(&self, path: &ShadowPath, kind: &ChangeKind) -> bool {
+        let Some(entry) = self.store.get(path) else {
+            return false;
+        };
+        if entry.is_directory() {
+            return false;
+        }
+        // The source side of a deletion the shadow layer already made is not a conflict
+        if *kind == ChangeKind::Deleted && entry.is_deleted() {
+            return true;
+        }
+        
+        let policy = self.policy();
+        let conflict = match policy {
+            ConflictPolicy::Ignore => return true,
+            ConflictPolicy::InvalidateOverride => {
+                self.store.remove(path);
+                self.store.clear_source_conflict(path);
+                SourceConflict {
+                    path: path.clone(),
+                    kind: kind.clone(),
+                    detected_at: current_time(),
+                }
+            }
+            ConflictPolicy::MarkConflicted => self.store.record_source_conflict(path, kind),
+        };
+        for listener in self.listeners.read().unwrap().iter() {
+            listener(&conflict, policy);
+        }
+        policy == ConflictPolicy::MarkConflicted
+    }
+}
+
+impl SourceChangeSink for SourceChangeHandler {
+    fn on_source_changes(&self, changes: Vec<SourceChange>) {
+        for change in changes {
+            self.handle(change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watch::{WatchFilter, WatchService};
+    use std::sync::Mutex;
+    use bytes::Bytes;
+    
+    #[test]
+    fn test_conflicts_and_invalidation() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        store.insert_file(ShadowPath::from("/src/main.rs"), Bytes::from("shadow"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/old.txt")).unwrap();
+        
+        let handler = SourceChangeHandler::new(Arc::clone(&store));
+        let invalidated = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&invalidated);
+        handler.on_invalidate(move |path, _| sink.lock().unwrap().push(path.to_string()));
+        
+        handler.on_source_changes(vec![
+            SourceChange::from_relative(Path::new("src/main.rs"), ChangeKind::Modified, false),
+            SourceChange::from_relative(Path::new("src/lib.rs"), ChangeKind::Created, false),
+            SourceChange::from_relative(Path::new("old.txt"), ChangeKind::Deleted, false),
+            SourceChange::from_relative(Path::new("src/main.rs"), ChangeKind::Deleted, false),
+        ]);
+        
+        assert_eq!(invalidated.lock().unwrap().len(), 4);
+        let conflicts = handler.take_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, ShadowPath::from("/src/main.rs"));
+        assert_eq!(conflicts[0].kind, ChangeKind::Deleted);
+        assert!(handler.conflicts().is_empty());
+    }
+    
+    #[test]
+    fn test_rescan_checks_whole_subtree() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        store.create_directory_hierarchy(&ShadowPath::from("/a/b")).unwrap();
+        store.insert_file(ShadowPath::from("/a/b/c.txt"), Bytes::from("x"), None).unwrap();
+        
+        let handler = SourceChangeHandler::new(Arc::clone(&store));
+        handler.handle(SourceChange::rescan(source_path(Path::new("a"))));
+        
+        let paths: Vec<String> = handler.conflicts().into_iter().map(|c| c.path.to_string()).collect();
+        assert_eq!(paths, vec!["/a/b/c.txt"]);
+    }
+    
+    #[test]
+    fn test_only_visible_changes_are_published() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        let service = Arc::new(WatchService::default());
+        store.insert_file(ShadowPath::from("/hidden.txt"), Bytes::from("shadow"), None).unwrap();
+        store.set_watch_service(Arc::clone(&service));
+        let mut events = service.subscribe(WatchFilter::all());
+        
+        let handler = SourceChangeHandler::new(Arc::clone(&store));
+        handler.handle(SourceChange::from_relative(Path::new("hidden.txt"), ChangeKind::Modified, false));
+        handler.handle(SourceChange::from_relative(Path::new("visible.txt"), ChangeKind::Created, false));
+        
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.path, ShadowPath::from("/visible.txt"));
+        assert_eq!(event.source, ChangeSource::SourceTree);
+        assert!(events.try_recv().is_none());
+    }
+    
+    #[test]
+    fn test_conflict_policies() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        let service = Arc::new(WatchService::default());
+        store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("shadow"), None).unwrap();
+        store.set_watch_service(Arc::clone(&service));
+        let mut events = service.subscribe(WatchFilter::all());
+        
+        let handler = SourceChangeHandler::with_policy(Arc::clone(&store), ConflictPolicy::Ignore);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        handler.on_conflict(move |conflict, policy| sink.lock().unwrap().push((conflict.path.to_string(), policy)));
+        let change = SourceChange::from_relative(Path::new("a.txt"), ChangeKind::Modified, false);
+        
+        handler.handle(change.clone());
+        assert!(handler.conflicts().is_empty() && seen.lock().unwrap().is_empty());
+        assert!(events.try_recv().is_none());
+        
+        handler.set_policy(ConflictPolicy::InvalidateOverride);
+        handler.handle(change);
+        assert!(handler.conflicts().is_empty());
+        assert!(!store.exists(&ShadowPath::from("/a.txt")));
+        assert_eq!(*seen.lock().unwrap(), vec![("/a.txt".to_string(), ConflictPolicy::InvalidateOverride)]);
+        let published: Vec<ChangeSource> = std::iter::from_fn(|| events.try_recv()).map(|event| event.source).collect();
+        assert!(published.contains(&ChangeSource::SourceTree));
+    }
+}
@@ -1,10 +1,13 @@
 //! Performance tracking and statistics for ShadowFS operations.
 
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::collections::HashMap;
-use std::sync::RwLock;
-use std::time::Duration;
-use crate::types::ShadowPath;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use serde::{Deserialize, Serialize};
+use crate::override_store::{OverrideStore, StatsReport};
+use crate::supervision::ComponentStatus;
+use crate::types::{current_time, ShadowPath};
 
 /// Types of operations that can be tracked for statistics.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -21,6 +24,19 @@ pub enum OperationType {
 }
 
 impl OperationType {
+    /// Every operation type, in the order they are reported.
+    pub const ALL: [OperationType; 9] = [
+        OperationType::Open,
+        OperationType::Read,
+        OperationType::Write,
+        OperationType::Close,
+        OperationType::Stat,
+        OperationType::ReadDir,
+        OperationType::Create,
+        OperationType::Delete,
+        OperationType::Rename,
+    ];
+    
     /// Returns a human-readable name for the operation type.
     pub fn name(&self) -> &'static str {
         match self {
@@ -131,26 +147,21 @@ pub struct FileSystemStats {
     
     /// Number of currently active file handles
     pub active_handles: AtomicU64,
+    
+    /// Latencies of operations by type
+    latencies: HashMap<OperationType, LatencyHistogram>,
 }
 
 impl FileSystemStats {
     /// Creates a new FileSystemStats instance with all counters at zero.
     pub fn new() -> Self {
         let mut operation_counts = HashMap::new();
+        let mut latencies = HashMap::new();
         
         // Initialize all operation types with zero counts
-        for op_type in [
-            OperationType::Open,
-            OperationType::Read,
-            OperationType::Write,
-            OperationType::Close,
-            OperationType::Stat,
-            OperationType::ReadDir,
-            OperationType::Create,
-            OperationType::Delete,
-            OperationType::Rename,
-        ] {
+        for op_type in OperationType::ALL {
             operation_counts.insert(op_type, AtomicU64::new(0));
+            latencies.insert(op_type, LatencyHistogram::new());
         }
         
         Self {
@@ -162,9 +173,40 @@ impl FileSystemStats {
             cache_misses: AtomicU64::new(0),
             override_memory_usage: AtomicUsize::new(0),
             active_handles: AtomicU64::new(0),
+            latencies,
+        }
+    }
+    
+    /// Starts timing an operation; dropping the timer counts the operation
+    /// and records its latency.
+    pub fn start_operation(self: &Arc<Self>, op_type: OperationType) -> OperationTimer {
+        OperationTimer {
+            stats: Arc::clone(self),
+            operation: op_type,
+            started: Instant::now(),
+        }
+    }
+    
+    /// Records how long an operation took.
+    pub fn record_latency(&self, op_type: OperationType, duration: Duration) {
+        if let Some(histogram) = self.latencies.get(&op_type) {
+            histogram.record(duration);
         }
     }
     
+    /// Returns the latency histogram of an operation type.
+    pub fn latency(&self, op_type: OperationType) -> &LatencyHistogram {
+        &self.latencies[&op_type]
+    }
+    
+    /// Returns the latency summary of every operation type, keyed by
+    /// [`OperationType::name`].
+    pub fn latency_summaries(&self) -> BTreeMap<String, LatencySummary> {
+        OperationType::ALL.iter()
+            .map(|op_type| (op_type.name().to_string(), self.latency(*op_type).summary()))
+            .collect()
+    }
+    
     /// Increments the count for a specific operation type.
     pub fn increment_operation(&self, op_type: OperationType) {
         let counts = self.operation_counts.read().unwrap();
@@ -271,6 +313,9 @@ impl FileSystemStats {
         for counter in counts.values() {
             counter.store(0, Ordering::Relaxed);
         }
+        for histogram in self.latencies.values() {
+            histogram.reset();
+        }
     }
 }
 
@@ -280,6 +325,213 @@ impl Default for FileSystemStats {
     }
 }
 
+/// Number of power-of-two buckets of a [`LatencyHistogram`], covering up
+/// to about six days.
+const LATENCY_BUCKETS: usize = 40;
+
+/// Bits of a latency kept below its leading bit, splitting every
+/// power-of-two bucket into 16 linear sub-buckets.
+const SUB_BUCKET_BITS: u32 = 4;
+
+/// Number of counters of a [`LatencyHistogram`]: one per microsecond below
+/// 32 and 16 per power of two above.
+const LATENCY_SLOTS: usize = (LATENCY_BUCKETS - SUB_BUCKET_BITS as usize) << SUB_BUCKET_BITS;
+
+/// Longest latency told apart from longer ones, in microseconds.
+const MAX_LATENCY_MICROS: u64 = (1 << (LATENCY_BUCKETS - 1)) - 1;
+
+/// Latencies counted in HDR-style log-linear buckets of microseconds.
+///
+/// Every power-of-two range is split into 16 linear sub-buckets, so
+/// percentiles are accurate to within 1/16 of the latency, which is enough
+/// to see a tail grow a few percent. Recording is two atomic increments, of
+/// the sub-bucket and of the running sum. Percentiles are reported as the
+/// highest latency of the sub-bucket they fall in.
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    slots: [AtomicU64; LATENCY_SLOTS],
+    sum_micros: AtomicU64,
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        Self {
+            slots: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+    
+    /// Records one latency.
+    pub fn record(&self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.slots[slot(micros.min(MAX_LATENCY_MICROS))].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+    
+    /// Returns the number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.slots.iter().map(|slot| slot.load(Ordering::Relaxed)).sum()
+    }
+    
+    /// Returns the total of the latencies recorded.
+    pub fn sum(&self) -> Duration {
+        Duration::from_micros(self.sum_micros.load(Ordering::Relaxed))
+    }
+    
+    /// Returns the upper bound of every power-of-two bucket with the number
+    /// of latencies in it, shortest first. The last bucket also holds
+    /// everything longer.
+    pub fn buckets(&self) -> Vec<(Duration, u64)> {
+        let mut counts = [0; LATENCY_BUCKETS];
+        for (index, slot) in self.slots.iter().enumerate() {
+            let (lowest, _) = slot_range(index);
+            counts[(u64::BITS - lowest.leading_zeros()) as usize] += slot.load(Ordering::Relaxed);
+        }
+        counts.iter()
+            .enumerate()
+            .map(|(bucket, count)| (Duration::from_micros(1 << bucket), *count))
+            .collect()
+    }
+    
+    /// Returns the latency below which `quantile` (0.0 to 1.0) of the
+    /// recorded latencies fall, or zero if none were recorded.
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        let counts: Vec<u64> = self.slots.iter().map(|slot| slot.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return Duration::ZERO;
+        }
+        
+        let rank = ((total as f64 * quantile.clamp(0.0, 1.0)).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_micros(slot_range(index).1);
+            }
+        }
+        Duration::from_micros(MAX_LATENCY_MICROS)
+    }
+    
+    /// Returns the longest latency recorded, to within a sub-bucket, or zero
+    /// if none were recorded.
+    pub fn max(&self) -> Duration {
+        self.slots.iter()
+            .rposition(|slot| slot.load(Ordering::Relaxed) > 0)
+            .map_or(Duration::ZERO, |index| Duration::from_micros(slot_range(index).1))
+    }
+    
+    /// Returns the percentiles and non-empty buckets of the histogram.
+    pub fn summary(&self) -> LatencySummary {
+        let micros = |quantile| self.percentile(quantile).as_micros() as u64;
+        LatencySummary {
+            count: self.count(),
+            p50_micros: micros(0.5),
+            p90_micros: micros(0.9),
+            p99_micros: micros(0.99),
+            p999_micros: micros(0.999),
+            max_micros: self.max().as_micros() as u64,
+            buckets: self.buckets()
+                .into_iter()
+                .filter(|(_, count)| *count > 0)
+                .map(|(below, count)| LatencyBucket { below_micros: below.as_micros() as u64, count })
+                .collect(),
+        }
+    }
+    
+    /// Clears every bucket.
+    pub fn reset(&self) {
+        for slot in &self.slots {
+            slot.store(0, Ordering::Relaxed);
+        }
+        self.sum_micros.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Returns the counter of a [`LatencyHistogram`] a latency of `micros`
+/// falls in: the latency itself below 32, and otherwise its top five bits
+/// offset by 16 for every bit dropped below them.
+fn slot(micros: u64) -> usize {
+    let bits = u64::BITS - micros.leading_zeros();
+    if bits <= SUB_BUCKET_BITS + 1 {
+        return micros as usize;
+    }
+    let shift = bits - SUB_BUCKET_BITS - 1;
+    ((shift as usize) << SUB_BUCKET_BITS) + (micros >> shift) as usize
+}
+
+/// Returns the lowest and highest latency, in microseconds, counted by the
+/// counter at `index`.
+fn slot_range(index: usize) -> (u64, u64) {
+    let sub_buckets = 1 << SUB_BUCKET_BITS;
+    if index < 2 * sub_buckets {
+        return (index as u64, index as u64);
+    }
+    let shift = (index >> SUB_BUCKET_BITS) - 1;
+    let lowest = ((index - (shift << SUB_BUCKET_BITS)) as u64) << shift;
+    (lowest, lowest + (1 << shift) - 1)
+}
+
+/// Latency percentiles and distribution of one operation type.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    /// Operations timed
+    pub count: u64,
+    
+    /// Median latency in microseconds
+    pub p50_micros: u64,
+    
+    /// 90th percentile latency in microseconds
+    pub p90_micros: u64,
+    
+    /// 99th percentile latency in microseconds
+    pub p99_micros: u64,
+    
+    /// 99.9th percentile latency in microseconds
+    pub p999_micros: u64,
+    
+    /// Longest latency in microseconds
+    pub max_micros: u64,
+    
+    /// Power-of-two buckets holding any latencies, shortest first
+    pub buckets: Vec<LatencyBucket>,
+}
+
+/// Number of latencies in one power-of-two bucket of a [`LatencySummary`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    /// Latencies in the bucket are shorter than this many microseconds,
+    /// and at least half as long
+    pub below_micros: u64,
+    
+    /// Latencies in the bucket
+    pub count: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Times one operation, started with [`FileSystemStats::start_operation`].
+///
+/// The operation is counted and its latency recorded when the timer is
+/// dropped, so every early return of a handler is measured.
+pub struct OperationTimer {
+    stats: Arc<FileSystemStats>,
+    operation: OperationType,
+    started: Instant,
+}
+
+impl Drop for OperationTimer {
+    fn drop(&mut self) {
+        self.stats.increment_operation(self.operation);
+        self.stats.record_latency(self.operation, self.started.elapsed());
+    }
+}
+
 /// Trait for collecting filesystem operation statistics.
 pub trait StatsCollector: Send + Sync {
     /// Records metrics for a completed operation.
@@ -316,6 +568,7 @@ impl StatsCollector for DefaultStatsCollector {
     fn record_operation(&self, metrics: OperationMetrics) {
         // Increment operation count
         self.stats.increment_operation(metrics.operation);
+        self.stats.record_latency(metrics.operation, metrics.duration);
         
         // Update byte counters if applicable
         if let Some(bytes) = metrics.bytes_transferred {
@@ -351,9 +604,403 @@ impl StatsCollector for DefaultStatsCollector {
     }
 }
 
+/// Approximate kernel memory for the inode and dentry of one cached override.
+pub const KERNEL_ENTRY_OVERHEAD: u64 = 1024;
+
+/// Operating system resources held by one process.
+///
+/// Each value is `None` when the platform does not expose it or the process
+/// cannot be inspected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessResources {
+    /// Number of threads
+    pub threads: Option<u64>,
+    
+    /// Open file descriptors or handles
+    pub open_handles: Option<u64>,
+    
+    /// Resident set size in bytes
+    pub rss_bytes: Option<u64>,
+}
+
+impl ProcessResources {
+    /// Samples the resources of the process with `pid`.
+    #[cfg(target_os = "linux")]
+    pub fn sample(pid: u32) -> Self {
+        let proc_dir = std::path::PathBuf::from(format!("/proc/{}", pid));
+        let status = std::fs::read_to_string(proc_dir.join("status")).unwrap_or_default();
+        let field = |name: &str| {
+            status.lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|value| value.split_whitespace().next())
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        
+        Self {
+            threads: field("Threads:"),
+            open_handles: std::fs::read_dir(proc_dir.join("fd")).ok().map(|fds| fds.count() as u64),
+            // VmRSS is reported in KiB
+            rss_bytes: field("VmRSS:").map(|kib| kib * 1024),
+        }
+    }
+    
+    /// Samples the resources of the process with `pid`.
+    #[cfg(target_os = "macos")]
+    pub fn sample(pid: u32) -> Self {
+        let pid = pid as libc::c_int;
+        let mut info: libc::proc_taskinfo = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+        // SAFETY: the buffer is a proc_taskinfo of the size passed in
+        let read = unsafe {
+            libc::proc_pidinfo(pid, libc::PROC_PIDTASKINFO, 0, &mut info as *mut _ as *mut libc::c_void, size)
+        };
+        let task = (read == size).then_some(info);
+        
+        // With a null buffer proc_pidinfo returns the size the fd list needs
+        let fd_bytes = unsafe { libc::proc_pidinfo(pid, libc::PROC_PIDLISTFDS, 0, std::ptr::null_mut(), 0) };
+        let open_handles = (fd_bytes > 0)
+            .then(|| fd_bytes as u64 / std::mem::size_of::<libc::proc_fdinfo>() as u64);
+        
+        Self {
+            threads: task.map(|task| task.pti_threadnum as u64),
+            open_handles,
+            rss_bytes: task.map(|task| task.pti_resident_size),
+        }
+    }
+    
+    /// Samples the resources of the process with `pid`.
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn sample(_pid: u32) -> Self {
+        Self::default()
+    }
+}
+
+/// Resource footprint of a process serving mounts over one override store.
+///
+/// The process values cover everything the serving process holds; the store
+/// values are the part of its memory attributable to overrides. The kernel
+/// cache estimate is an upper bound on the page cache and inode memory the
+/// kernel may keep for the overrides, which is not charged to the process.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MountResources {
+    /// Process serving the mounts
+    pub process_id: u32,
+    
+    /// Threads, handles and resident memory of the process
+    pub process: ProcessResources,
+    
+    /// Number of overrides in the store, including spilled ones
+    pub store_entries: u64,
+    
+    /// Memory used by override content and metadata
+    pub store_memory_bytes: u64,
+    
+    /// Bytes of override content spilled to disk
+    pub spilled_bytes: u64,
+    
+    /// Upper bound on kernel cache memory held for the overrides
+    pub kernel_cache_estimate_bytes: u64,
+    
+    /// Background components supervised for the store, with their restarts
+    #[serde(default)]
+    pub components: Vec<ComponentStatus>,
+    
+    /// When the values were sampled
+    pub sampled_at: SystemTime,
+}
+
+impl MountResources {
+    /// Samples the current process and the memory of `store`.
+    pub fn collect(store: &OverrideStore) -> Self {
+        let process_id = std::process::id();
+        let snapshot = store.get_stats_snapshot();
+        let store_entries = store.entry_count() as u64;
+        let raw_bytes = (snapshot.total_memory_bytes + snapshot.compressed_bytes_saved) as u64;
+        
+        Self {
+            process_id,
+            process: ProcessResources::sample(process_id),
+            store_entries,
+            store_memory_bytes: store.memory_stats().0 as u64,
+            spilled_bytes: store.spill_stats().map_or(0, |spill| spill.bytes_on_disk),
+            kernel_cache_estimate_bytes: kernel_cache_estimate(store_entries, raw_bytes),
+            components: store.supervisor().map_or_else(Vec::new, |supervisor| supervisor.components()),
+            sampled_at: current_time(),
+        }
+    }
+    
+    /// Re-samples the process values, keeping the store values.
+    pub fn refresh_process(&mut self) {
+        self.process = ProcessResources::sample(self.process_id);
+    }
+}
+
+/// Count and latency percentiles of one operation type in a [`StatsRecord`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationRecord {
+    /// Operations served
+    pub count: u64,
+    
+    /// Median latency in microseconds
+    pub p50_micros: u64,
+    
+    /// 90th percentile latency in microseconds
+    pub p90_micros: u64,
+    
+    /// 99th percentile latency in microseconds
+    pub p99_micros: u64,
+    
+    /// 99.9th percentile latency in microseconds
+    #[serde(default)]
+    pub p999_micros: u64,
+}
+
+/// Statistics of a mount at one point in time, saved to compare two runs.
+///
+/// Operation counts and latencies come from the provider's
+/// [`FileSystemStats`], cache and memory figures from the override store.
+/// A serving process keeps its latest record in its state directory, and
+/// [`StatsRecord::diff`] compares two records.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsRecord {
+    /// Operations served, keyed by [`OperationType::name`]
+    pub operations: BTreeMap<String, OperationRecord>,
+    
+    /// Bytes returned by reads
+    pub bytes_read: u64,
+    
+    /// Bytes accepted by writes
+    pub bytes_written: u64,
+    
+    /// Override lookups served from the store's hot cache
+    pub cache_hits: u64,
+    
+    /// Override lookups that missed the hot cache
+    pub cache_misses: u64,
+    
+    /// Fraction of override lookups served from the hot cache, 0.0 to 1.0
+    pub cache_hit_rate: f64,
+    
+    /// Entries evicted from the store
+    pub evictions: u64,
+    
+    /// Number of overrides in the store
+    pub store_entries: u64,
+    
+    /// Memory used by override content and metadata
+    pub store_memory_bytes: u64,
+    
+    /// When the values were sampled
+    pub recorded_at: SystemTime,
+}
+
+impl StatsRecord {
+    /// Samples the operations in `operations` and the state of `store`.
+    pub fn collect(operations: &FileSystemStats, store: &OverrideStore) -> Self {
+        let snapshot = store.get_stats_snapshot();
+        let lookups = snapshot.cache_hits + snapshot.cache_misses;
+        let record = |op_type: OperationType| {
+            let latency = operations.latency(op_type);
+            let micros = |quantile| latency.percentile(quantile).as_micros() as u64;
+            OperationRecord {
+                count: operations.get_operation_count(op_type),
+                p50_micros: micros(0.5),
+                p90_micros: micros(0.9),
+                p99_micros: micros(0.99),
+                p999_micros: micros(0.999),
+            }
+        };
+        
+        Self {
+            operations: OperationType::ALL.iter().map(|op_type| (op_type.name().to_string(), record(*op_type))).collect(),
+            bytes_read: operations.bytes_read.load(Ordering::Relaxed),
+            bytes_written: operations.bytes_written.load(Ordering::Relaxed),
+            cache_hits: snapshot.cache_hits,
+            cache_misses: snapshot.cache_misses,
+            cache_hit_rate: if lookups == 0 { 0.0 } else { snapshot.cache_hits as f64 / lookups as f64 },
+            evictions: snapshot.eviction_count,
+            store_entries: store.entry_count() as u64,
+            store_memory_bytes: store.memory_stats().0 as u64,
+            recorded_at: current_time(),
+        }
+    }
+    
+    /// Compares this record with a later one, metric by metric.
+    ///
+    /// Operations not served in either record are left out; every other
+    /// metric is listed even when unchanged, so the output of two runs
+    /// always lines up.
+    pub fn diff(&self, after: &StatsRecord) -> Vec<StatsChange> {
+        let mut changes = Vec::new();
+        let mut change = |metric: String, before: f64, after: f64| {
+            changes.push(StatsChange { metric, before, after });
+        };
+        
+        let names: std::collections::BTreeSet<&String> = self.operations.keys().chain(after.operations.keys()).collect();
+        for name in names {
+            let before = self.operations.get(name).copied().unwrap_or_default();
+            let later = after.operations.get(name).copied().unwrap_or_default();
+            if before.count == 0 && later.count == 0 {
+                continue;
+            }
+            change(format!("{}.count", name), before.count as f64, later.count as f64);
+            change(format!("{}.p50_us", name), before.p50_micros as f64, later.p50_micros as f64);
+            change(format!("{}.p90_us", name), before.p90_micros as f64, later.p90_micros as f64);
+            change(format!("{}.p99_us", name), before.p99_micros as f64, later.p99_micros as f64);
+            change(format!("{}.p999_us", name), before.p999_micros as f64, later.p999_micros as f64);
+        }
+        
+        change("bytes_read".into(), self.bytes_read as f64, after.bytes_read as f64);
+        change("bytes_written".into(), self.bytes_written as f64, after.bytes_written as f64);
+        change("cache_hits".into(), self.cache_hits as f64, after.cache_hits as f64);
+        change("cache_misses".into(), self.cache_misses as f64, after.cache_misses as f64);
+        change("cache_hit_rate".into(), self.cache_hit_rate, after.cache_hit_rate);
+        change("evictions".into(), self.evictions as f64, after.evictions as f64);
+        change("store_entries".into(), self.store_entries as f64, after.store_entries as f64);
+        change("store_memory_bytes".into(), self.store_memory_bytes as f64, after.store_memory_bytes as f64);
+        changes
+    }
+}
+
+impl StatsReport {
+    /// Adds the latency summaries of the operations in `operations`, such
+    /// as those a provider serving over the store records.
+    pub fn with_operation_latencies(mut self, operations: &FileSystemStats) -> Self {
+        self.operation_latencies = operations.latency_summaries();
+        self
+    }
+}
+
+/// One metric compared between two [`StatsRecord`]s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsChange {
+    /// Name of the metric, such as `read.p99_us` or `cache_hit_rate`
+    pub metric: String,
+    
+    /// Value in the earlier record
+    pub before: f64,
+    
+    /// Value in the later record
+    pub after: f64,
+}
+
+impl StatsChange {
+    /// Returns how much the metric grew, negative if it shrank.
+    pub fn delta(&self) -> f64 {
+        self.after - self.before
+    }
+    
+    /// Returns the change relative to the earlier value, such as 0.25 for
+    /// 25% more, or `None` if the earlier value was zero.
+    pub fn relative(&self) -> Option<f64> {
+        (self.before != 0.0).then(|| self.delta() / self.before)
+    }
+}
+
+/// Hot paths reported in each [`LiveStats`].
+pub const LIVE_HOT_PATHS: usize = 10;
+
+/// State of a serving mount sampled for the live `shadowfs stats` dashboard.
+///
+/// Counters are cumulative since the mount started; rates such as
+/// [`LiveStats::eviction_rate`] come from comparing two samples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveStats {
+    /// Memory used by override content and metadata
+    pub memory_bytes: u64,
+    
+    /// Memory the store may use before evicting
+    pub memory_limit_bytes: u64,
+    
+    /// Fraction of the memory limit in use, 0.0 to 1.0
+    pub memory_pressure: f64,
+    
+    /// Override lookups served from the store's hot cache
+    pub cache_hits: u64,
+    
+    /// Override lookups that missed the hot cache
+    pub cache_misses: u64,
+    
+    /// Fraction of override lookups served from the hot cache, 0.0 to 1.0
+    pub cache_hit_rate: f64,
+    
+    /// Entries evicted from the store
+    pub evictions: u64,
+    
+    /// Most accessed overrides, busiest first
+    pub hot_paths: Vec<HotPathRecord>,
+    
+    /// Latencies keyed by [`OperationType::name`]
+    pub latencies: BTreeMap<String, LatencySummary>,
+    
+    /// When the values were sampled
+    pub sampled_at: SystemTime,
+}
+
+/// Accesses to one override in [`LiveStats::hot_paths`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HotPathRecord {
+    /// Path of the override
+    pub path: ShadowPath,
+    
+    /// Lookups of the override
+    pub accesses: u64,
+    
+    /// Bytes of content served by those lookups
+    pub bytes: u64,
+}
+
+impl LiveStats {
+    /// Samples the operations in `operations` and the state of `store`.
+    pub fn collect(operations: &FileSystemStats, store: &OverrideStore) -> Self {
+        let snapshot = store.get_stats_snapshot();
+        let lookups = snapshot.cache_hits + snapshot.cache_misses;
+        let (memory_bytes, memory_limit_bytes, memory_pressure) = store.memory_stats();
+        let hot_paths = store.get_hot_paths(LIVE_HOT_PATHS)
+            .into_iter()
+            .map(|(path, stats)| HotPathRecord { path, accesses: stats.access_count, bytes: stats.bytes_accessed })
+            .collect();
+        
+        Self {
+            memory_bytes: memory_bytes as u64,
+            memory_limit_bytes: memory_limit_bytes as u64,
+            memory_pressure,
+            cache_hits: snapshot.cache_hits,
+            cache_misses: snapshot.cache_misses,
+            cache_hit_rate: if lookups == 0 { 0.0 } else { snapshot.cache_hits as f64 / lookups as f64 },
+            evictions: snapshot.eviction_count,
+            hot_paths,
+            latencies: operations.latency_summaries(),
+            sampled_at: current_time(),
+        }
+    }
+    
+    /// Returns the evictions per second since `earlier`, or zero if it was
+    /// not sampled before this one.
+    pub fn eviction_rate(&self, earlier: &LiveStats) -> f64 {
+        match self.sampled_at.duration_since(earlier.sampled_at) {
+            Ok(elapsed) if !elapsed.is_zero() => {
+                self.evictions.saturating_sub(earlier.evictions) as f64 / elapsed.as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Estimates the kernel memory cached for `entries` overrides holding
+/// `content_bytes` of uncompressed content.
+///
+/// Reads of override content are served with page caching enabled, so the
+/// kernel may hold every content page, plus an inode and dentry per entry.
+pub fn kernel_cache_estimate(entries: u64, content_bytes: u64) -> u64 {
+    content_bytes.saturating_add(entries.saturating_mul(KERNEL_ENTRY_OVERHEAD))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::supervision::{Component, Supervisor};
+    use std::sync::Arc;
     
     #[test]
     fn test_filesystem_stats_new() {
@@ -686,4 +1333,131 @@ mod tests {
         
         assert_eq!(stats.active_handles.load(Ordering::Relaxed), 2);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_mount_resources_collect() {
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/a.txt"), bytes::Bytes::from_static(b"hello"), None).unwrap();
+        
+        let resources = MountResources::collect(&store);
+        assert_eq!(resources.process_id, std::process::id());
+        assert_eq!(resources.store_entries, 1);
+        assert!(resources.store_memory_bytes > 0);
+        assert!(resources.kernel_cache_estimate_bytes >= KERNEL_ENTRY_OVERHEAD + 5);
+        assert!(resources.components.is_empty());
+        
+        let supervisor = Arc::new(Supervisor::default());
+        supervisor.component_crashed(Component::StatsTicker, "report not written", Duration::ZERO);
+        store.set_supervisor(supervisor);
+        let resources = MountResources::collect(&store);
+        assert_eq!(resources.components[0].component, Component::StatsTicker);
+        assert_eq!(resources.components[0].restarts, 1);
+        
+        #[cfg(target_os = "linux")]
+        {
+            assert!(resources.process.threads.unwrap() >= 1);
+            assert!(resources.process.open_handles.unwrap() >= 3);
+            assert!(resources.process.rss_bytes.unwrap() > 0);
+        }
+    }
+    
+    #[test]
+    fn test_latency_histogram_percentiles() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.percentile(0.5), Duration::ZERO);
+        
+        for _ in 0..90 {
+            histogram.record(Duration::from_micros(100));
+        }
+        for _ in 0..10 {
+            histogram.record(Duration::from_millis(10));
+        }
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.sum(), Duration::from_micros(109_000));
+        assert_eq!(histogram.buckets()[7], (Duration::from_micros(128), 90));
+        // Percentiles are the highest latency of their sub-bucket, within 1/16
+        assert_eq!(histogram.percentile(0.5), Duration::from_micros(103));
+        assert_eq!(histogram.percentile(0.9), Duration::from_micros(103));
+        assert_eq!(histogram.percentile(0.99), Duration::from_micros(10239));
+        assert_eq!(histogram.max(), Duration::from_micros(10239));
+        
+        let summary = histogram.summary();
+        assert_eq!((summary.count, summary.p999_micros), (100, 10239));
+        assert_eq!(summary.buckets, vec![
+            LatencyBucket { below_micros: 128, count: 90 },
+            LatencyBucket { below_micros: 16384, count: 10 },
+        ]);
+    }
+    
+    #[test]
+    fn test_latency_slots_cover_every_latency() {
+        for micros in [0, 1, 31, 32, 33, 100, 4095, 4096, 1_000_000, MAX_LATENCY_MICROS] {
+            let (lowest, highest) = slot_range(slot(micros));
+            assert!(lowest <= micros && micros <= highest, "{} in {}..={}", micros, lowest, highest);
+            assert!(highest - lowest <= micros / 16, "{} in {}..={}", micros, lowest, highest);
+        }
+        assert_eq!(slot(MAX_LATENCY_MICROS), LATENCY_SLOTS - 1);
+        
+        // Latencies too long to tell apart share the last slot
+        let histogram = LatencyHistogram::new();
+        histogram.record(Duration::from_secs(30 * 24 * 60 * 60));
+        assert_eq!(histogram.buckets().last().unwrap().1, 1);
+        assert_eq!(histogram.percentile(1.0), Duration::from_micros(MAX_LATENCY_MICROS));
+    }
+    
+    #[test]
+    fn test_stats_record_diff() {
+        let stats = Arc::new(FileSystemStats::new());
+        let store = OverrideStore::with_defaults();
+        drop(stats.start_operation(OperationType::Read));
+        let before = StatsRecord::collect(&stats, &store);
+        assert_eq!(before.operations["read"].count, 1);
+        
+        drop(stats.start_operation(OperationType::Read));
+        stats.record_latency(OperationType::Read, Duration::from_millis(5));
+        stats.increment_operation(OperationType::Read);
+        stats.add_bytes_read(4096);
+        let after = StatsRecord::collect(&stats, &store);
+        
+        let changes = before.diff(&after);
+        let change = |metric: &str| changes.iter().find(|change| change.metric == metric).unwrap().clone();
+        assert_eq!(change("read.count").delta(), 2.0);
+        assert_eq!(change("read.count").relative(), Some(2.0));
+        assert!(change("read.p99_us").after >= 4096.0);
+        assert!(change("read.p999_us").after >= 4096.0);
+        assert_eq!(change("bytes_read").relative(), None);
+        // Operations served in neither record are left out
+        assert!(!changes.iter().any(|change| change.metric.starts_with("write.")));
+        
+        let report = store.get_stats_report().with_operation_latencies(&stats);
+        assert_eq!(report.operation_latencies["read"].count, 3);
+        assert_eq!(report.operation_latencies["write"], LatencySummary::default());
+    }
+    
+    #[test]
+    fn test_live_stats() {
+        let stats = Arc::new(FileSystemStats::new());
+        let store = OverrideStore::with_defaults();
+        let path = ShadowPath::from("/hot.txt");
+        store.insert_file(path.clone(), bytes::Bytes::from("data"), None).unwrap();
+        store.get(&path);
+        store.get(&path);
+        drop(stats.start_operation(OperationType::Read));
+        
+        let earlier = LiveStats::collect(&stats, &store);
+        let hottest = &earlier.hot_paths[0];
+        assert_eq!(hottest.path, path);
+        assert!(hottest.accesses >= 2);
+        assert_eq!(hottest.bytes, hottest.accesses * 4);
+        assert!(earlier.memory_bytes > 0 && earlier.memory_pressure > 0.0);
+        assert_eq!(earlier.latencies["read"].count, 1);
+        
+        let later = LiveStats {
+            evictions: earlier.evictions + 6,
+            sampled_at: earlier.sampled_at + Duration::from_secs(2),
+            ..earlier.clone()
+        };
+        assert_eq!(later.eviction_rate(&earlier), 3.0);
+        assert_eq!(earlier.eviction_rate(&later), 0.0);
+    }
+}
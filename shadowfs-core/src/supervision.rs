@@ -0,0 +1,797 @@
+//! Failure policies and restarts for internal subsystems.
+//!
+//! Background subsystems such as the source watcher, the write-ahead log and
+//! statistics reporting can fail while a mount keeps serving requests. A
+//! [`FailurePolicy`] decides per subsystem whether such a failure tears the
+//! mount down ([`FailureMode::FailFast`], for CI where a silent loss of
+//! durability or change tracking must not go unnoticed) or is recorded as a
+//! warning while the mount continues without the subsystem
+//! ([`FailureMode::Degrade`], for interactive use).
+//!
+//! Subsystems report failures to a [`Supervisor`], which applies the policy.
+//! Whoever owns the mount waits on [`Supervisor::wait_for_fail_fast`] and
+//! unmounts when it returns.
+//!
+//! The supervisor also owns the background [`Component`]s of a mount, such
+//! as the source watcher and the eviction worker. A component that crashes,
+//! by returning an error or panicking, is restarted with exponential backoff
+//! per its [`RestartPolicy`]; only once it keeps crashing is the failure
+//! reported under its subsystem and the policy applied. Restart counts are
+//! available from [`Supervisor::components`].
+//!
+//! ```rust
+//! use shadowfs_core::supervision::{FailureMode, FailurePolicy, Subsystem, Supervisor};
+//!
+//! let policy = FailurePolicy::degrade().with(Subsystem::Persistence, FailureMode::FailFast);
+//! let supervisor = Supervisor::new(policy);
+//!
+//! assert_eq!(supervisor.report(Subsystem::Stats, "report not written"), FailureMode::Degrade);
+//! assert!(supervisor.fail_fast_failure().is_none());
+//!
+//! assert_eq!(supervisor.report(Subsystem::Persistence, "disk full"), FailureMode::FailFast);
+//! assert!(supervisor.fail_fast_failure().is_some());
+//! ```
+
+use crate::override_store::OverrideStore;
+use crate::types::current_time;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Most degraded failures kept by a supervisor; older ones are dropped.
+const MAX_RECORDED_FAILURES: usize = 100;
+
+/// Internal subsystem whose failures a [`FailurePolicy`] governs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Subsystem {
+    /// Source tree watching and cache invalidation
+    Watcher,
+    
+    /// Write-ahead log and snapshots of the override store
+    Persistence,
+    
+    /// Statistics and resource reporting
+    Stats,
+    
+    /// Background eviction
+    Eviction,
+}
+
+impl Subsystem {
+    /// All subsystems, in a stable order.
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Watcher,
+        Subsystem::Persistence,
+        Subsystem::Stats,
+        Subsystem::Eviction,
+    ];
+    
+    /// Returns the name used in configuration and messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Watcher => "watcher",
+            Subsystem::Persistence => "persistence",
+            Subsystem::Stats => "stats",
+            Subsystem::Eviction => "eviction",
+        }
+    }
+    
+    /// Parses a subsystem name as returned by [`Subsystem::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|subsystem| subsystem.name() == name)
+    }
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What happens when a subsystem fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum FailureMode {
+    /// Tear the mount down
+    FailFast,
+    
+    /// Record a warning and continue without the subsystem
+    #[default]
+    Degrade,
+}
+
+/// Failure mode of each subsystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FailurePolicy {
+    /// Mode for source watcher failures
+    pub watcher: FailureMode,
+    
+    /// Mode for write-ahead log and snapshot failures
+    pub persistence: FailureMode,
+    
+    /// Mode for statistics reporting failures
+    pub stats: FailureMode,
+    
+    /// Mode for background eviction failures
+    pub eviction: FailureMode,
+}
+
+impl FailurePolicy {
+    /// Degrades on every failure, the default for interactive use.
+    pub fn degrade() -> Self {
+        Self::default()
+    }
+    
+    /// Tears the mount down on any failure, for CI.
+    pub fn fail_fast() -> Self {
+        Self {
+            watcher: FailureMode::FailFast,
+            persistence: FailureMode::FailFast,
+            stats: FailureMode::FailFast,
+            eviction: FailureMode::FailFast,
+        }
+    }
+    
+    /// Returns the policy with `subsystem` set to `mode`.
+    pub fn with(mut self, subsystem: Subsystem, mode: FailureMode) -> Self {
+        match subsystem {
+            Subsystem::Watcher => self.watcher = mode,
+            Subsystem::Persistence => self.persistence = mode,
+            Subsystem::Stats => self.stats = mode,
+            Subsystem::Eviction => self.eviction = mode,
+        }
+        self
+    }
+    
+    /// Returns the mode for `subsystem`.
+    pub fn mode(&self, subsystem: Subsystem) -> FailureMode {
+        match subsystem {
+            Subsystem::Watcher => self.watcher,
+            Subsystem::Persistence => self.persistence,
+            Subsystem::Stats => self.stats,
+            Subsystem::Eviction => self.eviction,
+        }
+    }
+    
+    /// Combines two policies, failing fast wherever either does.
+    ///
+    /// Used when several mounts share one store and supervisor.
+    pub fn strictest(self, other: Self) -> Self {
+        Subsystem::ALL.into_iter().fold(self, |policy, subsystem| {
+            if other.mode(subsystem) == FailureMode::FailFast {
+                policy.with(subsystem, FailureMode::FailFast)
+            } else {
+                policy
+            }
+        })
+    }
+}
+
+/// Background component of a mount, restarted by a [`Supervisor`] when it crashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Component {
+    /// Source tree watcher
+    Watcher,
+    
+    /// Background evictor
+    Evictor,
+    
+    /// Compaction of delta overrides
+    Compactor,
+    
+    /// Periodic statistics and resource reports
+    StatsTicker,
+    
+    /// Checkpoints of the write-ahead log
+    PersistenceWriter,
+}
+
+impl Component {
+    /// All components, in a stable order.
+    pub const ALL: [Component; 5] = [
+        Component::Watcher,
+        Component::Evictor,
+        Component::Compactor,
+        Component::StatsTicker,
+        Component::PersistenceWriter,
+    ];
+    
+    /// Returns the name used in messages and task names.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Component::Watcher => "watcher",
+            Component::Evictor => "evictor",
+            Component::Compactor => "compactor",
+            Component::StatsTicker => "stats-ticker",
+            Component::PersistenceWriter => "persistence-writer",
+        }
+    }
+    
+    /// Returns the subsystem whose failure mode applies once the component
+    /// is given up on.
+    pub fn subsystem(&self) -> Subsystem {
+        match self {
+            Component::Watcher => Subsystem::Watcher,
+            Component::Evictor => Subsystem::Eviction,
+            Component::Compactor | Component::PersistenceWriter => Subsystem::Persistence,
+            Component::StatsTicker => Subsystem::Stats,
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a [`Supervisor`] restarts crashed components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Delay before restarting a component after its first crash
+    pub initial_backoff: Duration,
+    
+    /// Longest delay between restarts; the delay doubles with every crash in a row
+    pub max_backoff: Duration,
+    
+    /// Crashes in a row after which the component is given up on
+    pub max_restarts: u32,
+    
+    /// Run time after which a crash no longer counts as one in a row
+    pub reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            max_restarts: 5,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Returns the delay before the restart following `crashes` crashes in a row.
+    pub fn backoff(&self, crashes: u32) -> Duration {
+        let doublings = crashes.saturating_sub(1).min(31);
+        self.initial_backoff.saturating_mul(1 << doublings).min(self.max_backoff)
+    }
+}
+
+/// Lifecycle state of a supervised component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ComponentState {
+    /// Running normally
+    Running,
+    
+    /// Crashed and waiting for its restart
+    Restarting,
+    
+    /// Finished or stopped by its owner
+    Stopped,
+    
+    /// Crashed too often in a row and given up on
+    Failed,
+}
+
+/// Restart history of a supervised component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComponentStatus {
+    /// Component the status belongs to
+    pub component: Component,
+    
+    /// Current lifecycle state
+    pub state: ComponentState,
+    
+    /// Restarts since the component was first started
+    pub restarts: u64,
+    
+    /// Last crash, if the component ever crashed
+    pub last_failure: Option<String>,
+}
+
+/// Supervisor-side record of a component.
+#[derive(Debug)]
+struct ComponentRecord {
+    status: ComponentStatus,
+    crashes_in_a_row: u32,
+}
+
+/// A failure reported to a [`Supervisor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubsystemFailure {
+    /// Subsystem that failed
+    pub subsystem: Subsystem,
+    
+    /// Description of the failure
+    pub message: String,
+    
+    /// Mode applied to the failure
+    pub mode: FailureMode,
+    
+    /// When the failure was reported
+    pub at: SystemTime,
+}
+
+impl fmt::Display for SubsystemFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {}", self.subsystem, self.message)
+    }
+}
+
+/// Applies a [`FailurePolicy`] to failures reported by subsystems.
+#[derive(Debug)]
+pub struct Supervisor {
+    policy: RwLock<FailurePolicy>,
+    degraded: Mutex<Vec<SubsystemFailure>>,
+    fatal: Mutex<Option<SubsystemFailure>>,
+    fatal_notify: Notify,
+    restart_policy: RwLock<RestartPolicy>,
+    components: Mutex<BTreeMap<Component, ComponentRecord>>,
+}
+
+impl Supervisor {
+    /// Creates a supervisor applying `policy`.
+    pub fn new(policy: FailurePolicy) -> Self {
+        Self {
+            policy: RwLock::new(policy),
+            degraded: Mutex::new(Vec::new()),
+            fatal: Mutex::new(None),
+            fatal_notify: Notify::new(),
+            restart_policy: RwLock::new(RestartPolicy::default()),
+            components: Mutex::new(BTreeMap::new()),
+        }
+    }
+    
+    /// Returns the policy in effect.
+    pub fn policy(&self) -> FailurePolicy {
+        *self.policy.read().unwrap()
+    }
+    
+    /// Replaces the policy; failures already reported keep their mode.
+    pub fn set_policy(&self, policy: FailurePolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+    
+    /// Reports a failure of `subsystem` and returns the mode applied to it.
+    ///
+    /// With [`FailureMode::FailFast`] the first such failure is kept and
+    /// wakes [`Supervisor::wait_for_fail_fast`]; the caller should stop
+    /// the operation that failed. With [`FailureMode::Degrade`] the failure
+    /// is recorded and the caller should carry on without the subsystem.
+    pub fn report(&self, subsystem: Subsystem, message: impl Into<String>) -> FailureMode {
+        let mode = self.policy().mode(subsystem);
+        let failure = SubsystemFailure {
+            subsystem,
+            message: message.into(),
+            mode,
+            at: current_time(),
+        };
+        
+        match mode {
+            FailureMode::FailFast => {
+                let mut fatal = self.fatal.lock().unwrap();
+                if fatal.is_none() {
+                    *fatal = Some(failure);
+                    self.fatal_notify.notify_waiters();
+                }
+            }
+            FailureMode::Degrade => {
+                let mut degraded = self.degraded.lock().unwrap();
+                if degraded.len() == MAX_RECORDED_FAILURES {
+                    degraded.remove(0);
+                }
+                degraded.push(failure);
+            }
+        }
+        mode
+    }
+    
+    /// Returns the failures that degraded the mount, oldest first.
+    pub fn degraded(&self) -> Vec<SubsystemFailure> {
+        self.degraded.lock().unwrap().clone()
+    }
+    
+    /// Returns true if no failure was reported.
+    pub fn is_healthy(&self) -> bool {
+        self.fail_fast_failure().is_none() && self.degraded.lock().unwrap().is_empty()
+    }
+    
+    /// Returns the failure that requires tearing the mount down, if any.
+    pub fn fail_fast_failure(&self) -> Option<SubsystemFailure> {
+        self.fatal.lock().unwrap().clone()
+    }
+    
+    /// Waits until a subsystem fails under [`FailureMode::FailFast`].
+    pub async fn wait_for_fail_fast(&self) -> SubsystemFailure {
+        loop {
+            let notified = self.fatal_notify.notified();
+            tokio::pin!(notified);
+            // Register before checking so a failure in between is not missed
+            notified.as_mut().enable();
+            if let Some(failure) = self.fail_fast_failure() {
+                return failure;
+            }
+            notified.await;
+        }
+    }
+    
+    /// Returns the restart policy for components.
+    pub fn restart_policy(&self) -> RestartPolicy {
+        *self.restart_policy.read().unwrap()
+    }
+    
+    /// Replaces the restart policy for components.
+    pub fn set_restart_policy(&self, policy: RestartPolicy) {
+        *self.restart_policy.write().unwrap() = policy;
+    }
+    
+    /// Runs `component` on the current Tokio runtime, restarting it whenever
+    /// it crashes.
+    ///
+    /// `start` is called to start the component and again for every restart.
+    /// The component crashes when its future returns an error or panics, and
+    /// stops for good when it returns `Ok(())` or the returned handle is
+    /// dropped. Must be called from within a Tokio runtime.
+    pub fn supervise<F, Fut>(self: &Arc<Self>, component: Component, start: F) -> ComponentHandle
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.component_started(component);
+        let task = crate::task::spawn(
+            &format!("supervise-{}", component.name()),
+            Arc::clone(self).run_component(component, start),
+        );
+        ComponentHandle {
+            task,
+            supervised: Some((Arc::clone(self), component)),
+        }
+    }
+    
+    async fn run_component<F, Fut>(self: Arc<Self>, component: Component, mut start: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        loop {
+            let started = Instant::now();
+            let mut run = AbortOnDrop(crate::task::spawn(component.name(), start()));
+            let message = match (&mut run.0).await {
+                Ok(Ok(())) => {
+                    self.component_stopped(component);
+                    return;
+                }
+                Ok(Err(message)) => message,
+                Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e.into_panic())),
+                Err(_) => return,
+            };
+            
+            match self.component_crashed(component, message, started.elapsed()) {
+                Some(backoff) => tokio::time::sleep(backoff).await,
+                None => return,
+            }
+            self.component_started(component);
+        }
+    }
+    
+    /// Records that `component` is running.
+    ///
+    /// [`Supervisor::supervise`] calls this itself; components that manage
+    /// their own thread call it when they start and after every restart.
+    pub fn component_started(&self, component: Component) {
+        self.update_component(component, |record| record.status.state = ComponentState::Running);
+    }
+    
+    /// Records that `component` stopped without crashing.
+    pub fn component_stopped(&self, component: Component) {
+        self.update_component(component, |record| {
+            if record.status.state != ComponentState::Failed {
+                record.status.state = ComponentState::Stopped;
+            }
+        });
+    }
+    
+    /// Records a crash of `component` after it ran for `ran_for`.
+    ///
+    /// Returns how long to wait before restarting it, or `None` once it has
+    /// crashed more than [`RestartPolicy::max_restarts`] times in a row; the
+    /// failure is then reported under [`Component::subsystem`].
+    pub fn component_crashed(
+        &self,
+        component: Component,
+        message: impl Into<String>,
+        ran_for: Duration,
+    ) -> Option<Duration> {
+        let policy = self.restart_policy();
+        let message = message.into();
+        let mut restarts = 0;
+        let crashes = self.update_component(component, |record| {
+            if ran_for >= policy.reset_after {
+                record.crashes_in_a_row = 0;
+            }
+            record.crashes_in_a_row += 1;
+            record.status.last_failure = Some(message.clone());
+            if record.crashes_in_a_row > policy.max_restarts {
+                record.status.state = ComponentState::Failed;
+            } else {
+                record.status.state = ComponentState::Restarting;
+                record.status.restarts += 1;
+            }
+            restarts = record.status.restarts;
+            record.crashes_in_a_row
+        });
+        
+        if crashes > policy.max_restarts {
+            self.report(
+                component.subsystem(),
+                format!("{} gave up after {} restarts: {}", component, restarts, message),
+            );
+            None
+        } else {
+            Some(policy.backoff(crashes))
+        }
+    }
+    
+    /// Returns the status of every component started so far.
+    pub fn components(&self) -> Vec<ComponentStatus> {
+        self.components.lock().unwrap().values().map(|record| record.status.clone()).collect()
+    }
+    
+    /// Returns the status of `component`, if it was ever started.
+    pub fn component(&self, component: Component) -> Option<ComponentStatus> {
+        self.components.lock().unwrap().get(&component).map(|record| record.status.clone())
+    }
+    
+    fn update_component<T>(&self, component: Component, f: impl FnOnce(&mut ComponentRecord) -> T) -> T {
+        let mut components = self.components.lock().unwrap();
+        let record = components.entry(component).or_insert_with(|| ComponentRecord {
+            status: ComponentStatus {
+                component,
+                state: ComponentState::Running,
+                restarts: 0,
+                last_failure: None,
+            },
+            crashes_in_a_row: 0,
+        });
+        f(record)
+    }
+}
+
+/// Handle to a component run by [`Supervisor::supervise`].
+///
+/// The component stops when the handle is dropped.
+#[derive(Debug)]
+pub struct ComponentHandle {
+    task: JoinHandle<()>,
+    supervised: Option<(Arc<Supervisor>, Component)>,
+}
+
+impl ComponentHandle {
+    /// Wraps a task that runs without a supervisor.
+    pub(crate) fn unsupervised(task: JoinHandle<()>) -> Self {
+        Self { task, supervised: None }
+    }
+    
+    /// Returns true once the component stopped or was given up on.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+    
+    /// Stops the component; dropping the handle does the same.
+    pub fn stop(self) {}
+}
+
+impl Drop for ComponentHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+        if let Some((supervisor, component)) = &self.supervised {
+            supervisor.component_stopped(*component);
+        }
+    }
+}
+
+/// Aborts a task when dropped, so stopping a supervisor task stops the
+/// component it runs.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Extracts the message of a panic payload.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map_or_else(|| "unknown panic".to_string(), |message| message.to_string()),
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new(FailurePolicy::default())
+    }
+}
+
+impl OverrideStore {
+    /// Reports persistence failures of this store to `supervisor`.
+    ///
+    /// Without a supervisor, a failed write-ahead log append fails the write.
+    /// With one, the policy decides: [`FailureMode::Degrade`] keeps the
+    /// write in memory without durability, [`FailureMode::FailFast`] fails it.
+    pub fn set_supervisor(&self, supervisor: Arc<Supervisor>) {
+        *self.supervisor.write().unwrap() = Some(supervisor);
+    }
+    
+    /// Stops reporting failures, returning the previous supervisor.
+    pub fn clear_supervisor(&self) -> Option<Arc<Supervisor>> {
+        self.supervisor.write().unwrap().take()
+    }
+    
+    /// Returns the attached supervisor, if any.
+    pub fn supervisor(&self) -> Option<Arc<Supervisor>> {
+        self.supervisor.read().unwrap().clone()
+    }
+    
+    /// Reports a failure of `subsystem`, returning true if the caller may
+    /// continue in degraded mode.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn degrade_on_failure(&self, subsystem: Subsystem, message: impl FnOnce() -> String) -> bool {
+        match self.supervisor.read().unwrap().as_ref() {
+            Some(supervisor) => supervisor.report(subsystem, message()) == FailureMode::Degrade,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_policy_merge_and_names() {
+        let ci = FailurePolicy::degrade().with(Subsystem::Watcher, FailureMode::FailFast);
+        let other = FailurePolicy::degrade().with(Subsystem::Stats, FailureMode::FailFast);
+        let merged = ci.strictest(other);
+        assert_eq!(merged.mode(Subsystem::Watcher), FailureMode::FailFast);
+        assert_eq!(merged.mode(Subsystem::Persistence), FailureMode::Degrade);
+        assert_eq!(merged.mode(Subsystem::Stats), FailureMode::FailFast);
+        
+        for subsystem in Subsystem::ALL {
+            assert_eq!(Subsystem::from_name(subsystem.name()), Some(subsystem));
+        }
+    }
+    
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_policy_json_form() {
+        let ci = FailurePolicy::degrade().with(Subsystem::Watcher, FailureMode::FailFast);
+        let json = serde_json::to_string(&ci).unwrap();
+        assert_eq!(json, r#"{"watcher":"fail-fast","persistence":"degrade","stats":"degrade","eviction":"degrade"}"#);
+        assert_eq!(serde_json::from_str::<FailurePolicy>("{}").unwrap(), FailurePolicy::degrade());
+    }
+    
+    #[tokio::test]
+    async fn test_fail_fast_wakes_waiter() {
+        let supervisor = Arc::new(Supervisor::new(FailurePolicy::fail_fast()));
+        let waiter = {
+            let supervisor = Arc::clone(&supervisor);
+            tokio::spawn(async move { supervisor.wait_for_fail_fast().await })
+        };
+        tokio::task::yield_now().await;
+        
+        supervisor.report(Subsystem::Watcher, "inotify queue lost");
+        supervisor.report(Subsystem::Stats, "second failure");
+        let failure = waiter.await.unwrap();
+        assert_eq!(failure.subsystem, Subsystem::Watcher);
+        assert_eq!(failure.to_string(), "watcher failed: inotify queue lost");
+        assert!(!supervisor.is_healthy());
+    }
+    
+    #[tokio::test]
+    async fn test_crashed_component_is_restarted() {
+        let supervisor = Arc::new(Supervisor::default());
+        supervisor.set_restart_policy(RestartPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RestartPolicy::default()
+        });
+        
+        let attempts = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let handle = supervisor.supervise(Component::Compactor, {
+            let attempts = Arc::clone(&attempts);
+            move || {
+                let attempt = attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    match attempt {
+                        0 => Err("disk full".to_string()),
+                        1 => panic!("corrupt delta"),
+                        _ => Ok(()),
+                    }
+                }
+            }
+        });
+        while !handle.is_finished() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        
+        let status = supervisor.component(Component::Compactor).unwrap();
+        assert_eq!(status.state, ComponentState::Stopped);
+        assert_eq!(status.restarts, 2);
+        assert_eq!(status.last_failure.as_deref(), Some("panicked: corrupt delta"));
+        assert!(supervisor.is_healthy());
+    }
+    
+    #[test]
+    fn test_component_given_up_after_max_restarts() {
+        let supervisor = Supervisor::new(FailurePolicy::degrade().with(Subsystem::Eviction, FailureMode::FailFast));
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(40), policy.max_backoff);
+        
+        for crash in 1..=policy.max_restarts {
+            let backoff = supervisor.component_crashed(Component::Evictor, "spill failed", Duration::ZERO);
+            assert_eq!(backoff, Some(policy.backoff(crash)));
+        }
+        // A crash after a long run starts a new streak
+        assert!(supervisor.component_crashed(Component::Evictor, "spill failed", policy.reset_after).is_some());
+        for _ in 1..policy.max_restarts {
+            supervisor.component_crashed(Component::Evictor, "spill failed", Duration::ZERO);
+        }
+        assert!(supervisor.fail_fast_failure().is_none());
+        
+        assert_eq!(supervisor.component_crashed(Component::Evictor, "spill failed", Duration::ZERO), None);
+        let status = supervisor.component(Component::Evictor).unwrap();
+        assert_eq!(status.state, ComponentState::Failed);
+        assert_eq!(status.restarts, 2 * policy.max_restarts as u64);
+        let failure = supervisor.fail_fast_failure().unwrap();
+        assert_eq!(failure.subsystem, Subsystem::Eviction);
+        assert_eq!(failure.message, "evictor gave up after 10 restarts: spill failed");
+    }
+    
+    #[cfg(all(target_os = "linux", feature = "persistence"))]
+    #[test]
+    fn test_wal_failure_degrades_store() {
+        use crate::types::ShadowPath;
+        use bytes::Bytes;
+        
+        // Every write to /dev/full fails with ENOSPC
+        let store = OverrideStore::with_defaults();
+        store.enable_wal("/dev/full").unwrap();
+        
+        let path = ShadowPath::from("/file.txt");
+        assert!(store.insert_file(path.clone(), Bytes::from_static(b"x"), None).is_err());
+        
+        let supervisor = Arc::new(Supervisor::default());
+        store.set_supervisor(Arc::clone(&supervisor));
+        store.insert_file(path.clone(), Bytes::from_static(b"x"), None).unwrap();
+        assert!(store.get(&path).is_some());
+        assert_eq!(supervisor.degraded()[0].subsystem, Subsystem::Persistence);
+        
+        supervisor.set_policy(FailurePolicy::fail_fast());
+        assert!(store.insert_file(ShadowPath::from("/other.txt"), Bytes::from_static(b"y"), None).is_err());
+        assert!(supervisor.fail_fast_failure().is_some());
+    }
+}
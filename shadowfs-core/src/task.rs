@@ -0,0 +1,155 @@
+//! Named threads and tasks.
+//!
+//! Every thread and Tokio task shadowfs starts carries a name beginning with
+//! `shadowfs-` followed by its subsystem, such as `shadowfs-source-watch` or
+//! `shadowfs-async-bridge-worker-2`, so a hung mount can be diagnosed from
+//! a thread dump or a debugger.
+//!
+//! Task names are only recorded by Tokio when the crate is built with the
+//! `tokio-console` feature and `RUSTFLAGS="--cfg tokio_unstable"`; they then
+//! show up in [tokio-console](https://github.com/tokio-rs/console). Without
+//! them the task helpers behave like their `tokio::task` counterparts.
+
+use std::future::Future;
+use std::io;
+use std::thread;
+use tokio::runtime::{Handle, Runtime};
+use tokio::task::JoinHandle;
+
+/// Prefix shared by the names of all shadowfs threads and tasks.
+pub const NAME_PREFIX: &str = "shadowfs-";
+
+/// Returns the thread or task name for `subsystem`.
+pub fn name(subsystem: &str) -> String {
+    format!("{}{}", NAME_PREFIX, subsystem)
+}
+
+/// Starts a thread named after `subsystem`.
+///
+/// # Errors
+/// Fails if the operating system cannot create the thread.
+pub fn spawn_thread<F, T>(subsystem: &str, f: F) -> io::Result<thread::JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::Builder::new().name(name(subsystem)).spawn(f)
+}
+
+/// Spawns a task on the current Tokio runtime, named after `subsystem`.
+///
+/// Must be called from within a Tokio runtime.
+#[track_caller]
+pub fn spawn<F>(subsystem: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    {
+        tokio::task::Builder::new()
+            .name(&name(subsystem))
+            .spawn(future)
+            .expect("failed to spawn task")
+    }
+    
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = subsystem;
+        tokio::spawn(future)
+    }
+}
+
+/// Spawns a task on the runtime behind `handle`, named after `subsystem`.
+#[track_caller]
+pub fn spawn_on<F>(subsystem: &str, future: F, handle: &Handle) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    {
+        tokio::task::Builder::new()
+            .name(&name(subsystem))
+            .spawn_on(future, handle)
+            .expect("failed to spawn task")
+    }
+    
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = subsystem;
+        handle.spawn(future)
+    }
+}
+
+/// Runs blocking code on the Tokio blocking pool, named after `subsystem`.
+///
+/// Must be called from within a Tokio runtime.
+#[track_caller]
+pub fn spawn_blocking<F, T>(subsystem: &str, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    {
+        tokio::task::Builder::new()
+            .name(&name(subsystem))
+            .spawn_blocking(f)
+            .expect("failed to spawn blocking task")
+    }
+    
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = subsystem;
+        tokio::task::spawn_blocking(f)
+    }
+}
+
+/// Builds a multi-threaded Tokio runtime whose worker threads are named
+/// after `subsystem`. WASM has no threads, so there the runtime runs on the
+/// current thread.
+///
+/// # Errors
+/// Fails if the runtime's threads or I/O driver cannot be created.
+pub fn runtime(subsystem: &str) -> io::Result<Runtime> {
+    #[cfg(not(target_family = "wasm"))]
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    #[cfg(target_family = "wasm")]
+    let mut builder = tokio::runtime::Builder::new_current_thread();
+    
+    builder
+        .enable_all()
+        .thread_name(name(subsystem))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_spawned_thread_is_named() {
+        let handle = spawn_thread("test-worker", || {
+            thread::current().name().map(str::to_string)
+        }).unwrap();
+        assert_eq!(handle.join().unwrap().as_deref(), Some("shadowfs-test-worker"));
+    }
+    
+    #[tokio::test]
+    async fn test_spawn_runs_task() {
+        let value = spawn("test-task", async { 21 * 2 }).await.unwrap();
+        assert_eq!(value, 42);
+        let value = spawn_blocking("test-blocking", || 7).await.unwrap();
+        assert_eq!(value, 7);
+    }
+    
+    #[test]
+    fn test_runtime_threads_are_named() {
+        let runtime = runtime("test-runtime").unwrap();
+        let name = runtime.block_on(async {
+            spawn("test-task", async { thread::current().name().map(str::to_string) }).await.unwrap()
+        });
+        assert_eq!(name.as_deref(), Some("shadowfs-test-runtime"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Spans following an operation from the provider callback into the store.
+//!
+//! [`MountManager::mount`](crate::mount_manager::MountManager::mount)
+//! mounts inside a [`mount_span`] carrying the mount's id, source and mount
+//! point. Providers keep [`Span::current`] from their `mount` and enter an
+//! [`operation_span`] under it for each callback, naming the operation and
+//! the path. Store operations, eviction and compression record trace-level
+//! spans of their own, which nest under whichever callback they serve, so a
+//! subscriber exporting spans, such as the CLI built with its `otlp`
+//! feature, shows a slow read end to end in Jaeger.
+//!
+//! Spans cost an atomic load while no subscriber is interested in them.
+//!
+//! ```rust
+//! use shadowfs_core::telemetry::{mount_span, operation_span};
+//! use std::path::Path;
+//!
+//! let mount = mount_span("0b5e", Path::new("/src"), Path::new("/mnt"));
+//! let _entered = operation_span(&mount, "read", "/src/main.rs").entered();
+//! ```
+
+use std::fmt::Display;
+use std::path::Path;
+use tracing::Span;
+
+/// Returns the span a mount is created and served in.
+pub fn mount_span(id: impl Display, source: &Path, mount_point: &Path) -> Span {
+    tracing::info_span!(
+        "mount",
+        mount_id = %id,
+        source = %source.display(),
+        mount_point = %mount_point.display(),
+    )
+}
+
+/// Returns the span of a provider callback for `operation` on `path` under
+/// the span of its mount.
+///
+/// Every callback span is named `operation`, since span names are fixed at
+/// compile time; `otel.name` gives it the name of the operation once
+/// exported.
+pub fn operation_span(mount: &Span, operation: &'static str, path: impl Display) -> Span {
+    tracing::debug_span!(
+        parent: mount,
+        "operation",
+        otel.name = operation,
+        operation,
+        path = %path,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::override_store::OverrideStore;
+    use crate::types::ShadowPath;
+    use bytes::Bytes;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+    
+    /// Name, fields and names of the ancestors of a span.
+    type RecordedSpan = (String, String, Vec<String>);
+    
+    /// Records each span opened.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<RecordedSpan>>>);
+    
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorder {
+        fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+            struct Fields(String);
+            impl Visit for Fields {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    self.0.push_str(&format!("{}={:?} ", field.name(), value));
+                }
+            }
+            let mut fields = Fields(String::new());
+            attributes.record(&mut fields);
+            let span = context.span(id).unwrap();
+            let ancestors = span.scope().skip(1).map(|span| span.name().to_string()).collect();
+            self.0.lock().unwrap().push((span.name().to_string(), fields.0, ancestors));
+        }
+    }
+    
+    #[test]
+    fn test_store_spans_nest_under_the_callback_and_mount() {
+        let recorder = Recorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let store = OverrideStore::with_defaults();
+            let mount = mount_span("0b5e", Path::new("/src"), Path::new("/mnt"));
+            let _entered = operation_span(&mount, "write", "/a.txt").entered();
+            store.insert_file(ShadowPath::from("/a.txt"), Bytes::from("a"), None).unwrap();
+        });
+        
+        let spans = recorder.0.lock().unwrap();
+        let (_, fields, _) = spans.iter().find(|(name, ..)| name == "mount").unwrap();
+        assert!(fields.contains("mount_id=0b5e") && fields.contains("mount_point=/mnt"));
+        let (_, fields, ancestors) = spans.iter().find(|(name, ..)| name == "operation").unwrap();
+        assert!(fields.contains("otel.name=\"write\"") && fields.contains("path=/a.txt"));
+        assert_eq!(ancestors, &["mount"]);
+        let (_, fields, ancestors) = spans.iter().find(|(name, ..)| name == "insert").unwrap();
+        assert!(fields.contains("path=/a.txt"));
+        assert_eq!(ancestors, &["operation", "mount"]);
+    }
+}
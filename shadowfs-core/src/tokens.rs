@@ -0,0 +1,296 @@
+//! Scoped API tokens for automation clients.
+//!
+//! A CI system that only needs to read diffs and statistics should not be
+//! able to commit overrides or unmount. The daemon issues it a token carrying
+//! just the [`TokenScope`]s it needs, optionally expiring, and checks every
+//! request against it with [`TokenRegistry::authorize`].
+//!
+//! The secret is shown once, when the token is issued. The registry keeps
+//! only its BLAKE3 hash, so the tokens file does not give the secrets away.
+
+use crate::error::{unauthorized, ShadowError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// Prefix of every token secret, so leaked tokens are easy to recognise.
+pub const TOKEN_PREFIX: &str = "sfs_";
+
+/// Version of the tokens file written by [`TokenRegistry::save`].
+const TOKENS_FILE_VERSION: u32 = 1;
+
+/// What a token allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Reading status, statistics and diffs
+    Read,
+    /// Mounting and unmounting
+    MountControl,
+    /// Committing overrides to the source and rolling commits back
+    Commit,
+}
+
+impl TokenScope {
+    /// Every scope.
+    pub const ALL: [TokenScope; 3] = [TokenScope::Read, TokenScope::MountControl, TokenScope::Commit];
+    
+    /// Returns the name of the scope as used in the CLI and in the tokens file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::MountControl => "mount_control",
+            TokenScope::Commit => "commit",
+        }
+    }
+    
+    /// Parses a scope name as returned by [`TokenScope::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scope| scope.name() == name)
+    }
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Public description of an issued token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    /// Identifier used to list and revoke the token
+    pub id: Uuid,
+    
+    /// Name given at issuance, such as the CI system using it
+    pub name: String,
+    
+    /// What the token allows
+    pub scopes: BTreeSet<TokenScope>,
+    
+    /// When the token was issued
+    pub created: SystemTime,
+    
+    /// When the token stops working, if ever
+    pub expires: Option<SystemTime>,
+}
+
+impl TokenInfo {
+    /// Returns true if the token has expired at `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+}
+
+/// A newly issued token with its secret.
+#[derive(Debug, Clone)]
+pub struct IssuedToken {
+    /// Secret the client presents; it cannot be recovered later
+    pub secret: String,
+    
+    /// Description of the token
+    pub info: TokenInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredToken {
+    /// BLAKE3 hash of the secret, hex encoded
+    secret_hash: String,
+    #[serde(flatten)]
+    info: TokenInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokensFile {
+    version: u32,
+    tokens: Vec<StoredToken>,
+}
+
+/// Tokens issued by a daemon, keyed by the hash of their secret.
+#[derive(Debug, Default)]
+pub struct TokenRegistry {
+    tokens: RwLock<HashMap<String, TokenInfo>>,
+}
+
+impl TokenRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Issues a token.
+    ///
+    /// # Arguments
+    /// * `name` - Name to list the token under
+    /// * `scopes` - What the token allows
+    /// * `ttl` - How long the token works, or `None` until it is revoked
+    pub fn issue(
+        &self,
+        name: impl Into<String>,
+        scopes: impl IntoIterator<Item = TokenScope>,
+        ttl: Option<Duration>,
+    ) -> IssuedToken {
+        let created = SystemTime::now();
+        let secret = format!("{}{}{}", TOKEN_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let info = TokenInfo {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            scopes: scopes.into_iter().collect(),
+            created,
+            expires: ttl.map(|ttl| created + ttl),
+        };
+        self.tokens.write().unwrap().insert(secret_hash(&secret), info.clone());
+        IssuedToken { secret, info }
+    }
+    
+    /// Checks that `secret` is a live token allowing `scope`.
+    ///
+    /// # Errors
+    /// Fails with [`ShadowError::Unauthorized`] if the token is unknown,
+    /// revoked or expired, or lacks the scope.
+    pub fn authorize(&self, secret: &str, scope: TokenScope) -> Result<TokenInfo, ShadowError> {
+        let tokens = self.tokens.read().unwrap();
+        let info = tokens.get(&secret_hash(secret))
+            .ok_or_else(|| unauthorized(scope.name(), "unknown or revoked token"))?;
+        if info.is_expired(SystemTime::now()) {
+            return Err(unauthorized(scope.name(), format!("token '{}' has expired", info.name)));
+        }
+        if !info.scopes.contains(&scope) {
+            return Err(unauthorized(scope.name(), format!("token '{}' lacks the {} scope", info.name, scope)));
+        }
+        Ok(info.clone())
+    }
+    
+    /// Revokes the token with `id`.
+    ///
+    /// # Returns
+    /// false if no such token exists
+    pub fn revoke(&self, id: Uuid) -> bool {
+        let mut tokens = self.tokens.write().unwrap();
+        let before = tokens.len();
+        tokens.retain(|_, info| info.id != id);
+        tokens.len() != before
+    }
+    
+    /// Lists the tokens, expired ones included, oldest first.
+    pub fn list(&self) -> Vec<TokenInfo> {
+        let mut tokens: Vec<TokenInfo> = self.tokens.read().unwrap().values().cloned().collect();
+        tokens.sort_by_key(|info| info.created);
+        tokens
+    }
+    
+    /// Forgets expired tokens.
+    ///
+    /// # Returns
+    /// Number of tokens removed
+    pub fn purge_expired(&self) -> usize {
+        let now = SystemTime::now();
+        let mut tokens = self.tokens.write().unwrap();
+        let before = tokens.len();
+        tokens.retain(|_, info| !info.is_expired(now));
+        before - tokens.len()
+    }
+    
+    /// Loads the registry saved at `path`; a missing file gives an empty registry.
+    pub fn load(path: &Path) -> Result<Self, ShadowError> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(ShadowError::IoError { source: e }),
+        };
+        let file: TokensFile = serde_json::from_slice(&data).map_err(|e| ShadowError::InvalidConfiguration {
+            message: format!("invalid tokens file {}: {}", path.display(), e),
+        })?;
+        if file.version != TOKENS_FILE_VERSION {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!("unsupported tokens file version {}", file.version),
+            });
+        }
+        let tokens = file.tokens.into_iter()
+            .map(|stored| (stored.secret_hash, stored.info))
+            .collect();
+        Ok(Self { tokens: RwLock::new(tokens) })
+    }
+    
+    /// Writes the registry to `path`, readable by the current user only.
+    pub fn save(&self, path: &Path) -> Result<(), ShadowError> {
+        let tokens = self.tokens.read().unwrap()
+            .iter()
+            .map(|(hash, info)| StoredToken { secret_hash: hash.clone(), info: info.clone() })
+            .collect();
+        let file = TokensFile { version: TOKENS_FILE_VERSION, tokens };
+        let data = serde_json::to_vec_pretty(&file).map_err(|e| ShadowError::InvalidConfiguration {
+            message: format!("cannot serialize tokens: {}", e),
+        })?;
+        
+        // Written to a sibling first so a crash never leaves a torn file
+        let temp = path.with_extension("tmp");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut out = options.open(&temp).map_err(|e| ShadowError::IoError { source: e })?;
+        std::io::Write::write_all(&mut out, &data).map_err(|e| ShadowError::IoError { source: e })?;
+        out.sync_all().map_err(|e| ShadowError::IoError { source: e })?;
+        std::fs::rename(&temp, path).map_err(|e| ShadowError::IoError { source: e })
+    }
+}
+
+fn secret_hash(secret: &str) -> String {
+    blake3::hash(secret.as_bytes()).to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_scopes_are_enforced() {
+        let registry = TokenRegistry::new();
+        let ci = registry.issue("ci", [TokenScope::Read], None);
+        assert!(ci.secret.starts_with(TOKEN_PREFIX));
+        
+        assert_eq!(registry.authorize(&ci.secret, TokenScope::Read).unwrap().name, "ci");
+        let denied = registry.authorize(&ci.secret, TokenScope::Commit);
+        assert!(matches!(denied, Err(ShadowError::Unauthorized { operation, .. }) if operation == "commit"));
+        assert!(registry.authorize("sfs_guess", TokenScope::Read).is_err());
+        
+        assert!(registry.revoke(ci.info.id));
+        assert!(registry.authorize(&ci.secret, TokenScope::Read).is_err());
+        assert!(!registry.revoke(ci.info.id));
+    }
+    
+    #[test]
+    fn test_expiry() {
+        let registry = TokenRegistry::new();
+        let expired = registry.issue("old", [TokenScope::Read], Some(Duration::ZERO));
+        let live = registry.issue("new", [TokenScope::Read], Some(Duration::from_secs(3600)));
+        
+        assert!(matches!(
+            registry.authorize(&expired.secret, TokenScope::Read),
+            Err(ShadowError::Unauthorized { reason, .. }) if reason.contains("expired")
+        ));
+        assert_eq!(registry.purge_expired(), 1);
+        assert_eq!(registry.list(), vec![live.info]);
+    }
+    
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.json");
+        assert!(TokenRegistry::load(&path).unwrap().list().is_empty());
+        
+        let registry = TokenRegistry::new();
+        let token = registry.issue("deploy", [TokenScope::MountControl, TokenScope::Commit], None);
+        registry.save(&path).unwrap();
+        
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains(&token.secret));
+        let loaded = TokenRegistry::load(&path).unwrap();
+        assert_eq!(loaded.authorize(&token.secret, TokenScope::Commit).unwrap(), token.info);
+    }
+}
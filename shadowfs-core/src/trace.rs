@@ -0,0 +1,112 @@
+//! Operation tracing limited to chosen paths.
+//!
+//! Debug logging of every operation on a busy mount buries the one file
+//! being investigated. A [`PathTraceFilter`] holds globs such as
+//! `/src/*.lock`; providers ask [`OverrideStore::is_traced`] before each
+//! operation and log the ones on matching paths under [`TRACE_TARGET`], so
+//! the log subscriber can enable that target at debug level while the rest
+//! of the mount stays at its usual level. The globs can be replaced while
+//! the mount is serving, and with none set the check is a single atomic
+//! load.
+//!
+//! ```rust
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::trace::PathTraceFilter;
+//! use shadowfs_core::types::ShadowPath;
+//! use std::sync::Arc;
+//!
+//! let store = OverrideStore::with_defaults();
+//! let filter = Arc::new(PathTraceFilter::new());
+//! store.set_trace_filter(Arc::clone(&filter));
+//!
+//! filter.set_globs(vec!["*/Cargo.lock".to_string()]);
+//! assert!(store.is_traced(&ShadowPath::from("/app/Cargo.lock")));
+//! assert!(!store.is_traced(&ShadowPath::from("/app/Cargo.toml")));
+//! ```
+
+use crate::override_store::{OverrideRule, OverrideStore};
+use crate::types::ShadowPath;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Log target of traced operations.
+pub const TRACE_TARGET: &str = "shadowfs::trace";
+
+/// Globs selecting the paths whose operations are traced.
+#[derive(Debug, Default)]
+pub struct PathTraceFilter {
+    /// Whether any glob is set
+    active: AtomicBool,
+    
+    /// Each glob with the rule matching it
+    globs: RwLock<Vec<(String, OverrideRule)>>,
+}
+
+impl PathTraceFilter {
+    /// Creates a filter tracing no paths.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Replaces the globs; an empty list stops tracing.
+    pub fn set_globs(&self, globs: Vec<String>) {
+        let mut current = self.globs.write().unwrap();
+        *current = globs.into_iter().map(|glob| (glob.clone(), OverrideRule::Glob(glob))).collect();
+        self.active.store(!current.is_empty(), Ordering::Release);
+    }
+    
+    /// Returns the current globs.
+    pub fn globs(&self) -> Vec<String> {
+        self.globs.read().unwrap().iter().map(|(glob, _)| glob.clone()).collect()
+    }
+    
+    /// Returns true if any glob is set.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+    
+    /// Returns true if operations on `path` are traced.
+    pub fn matches(&self, path: &ShadowPath) -> bool {
+        self.is_active() && self.globs.read().unwrap().iter().any(|(_, rule)| rule.matches(path))
+    }
+}
+
+impl OverrideStore {
+    /// Traces the operations providers serve on the paths `filter` selects.
+    pub fn set_trace_filter(&self, filter: Arc<PathTraceFilter>) {
+        *self.trace.write().unwrap() = Some(filter);
+    }
+    
+    /// Returns the attached trace filter, if any.
+    pub fn trace_filter(&self) -> Option<Arc<PathTraceFilter>> {
+        self.trace.read().unwrap().clone()
+    }
+    
+    /// Returns true if providers should trace operations on `path`.
+    pub fn is_traced(&self, path: &ShadowPath) -> bool {
+        self.trace.read().unwrap().as_ref().is_some_and(|filter| filter.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_globs_can_be_replaced() {
+        let filter = PathTraceFilter::new();
+        assert!(!filter.is_active());
+        assert!(!filter.matches(&ShadowPath::from("/a.txt")));
+        
+        filter.set_globs(vec!["/logs/*".to_string(), "*.db".to_string()]);
+        assert!(filter.is_active());
+        assert!(filter.matches(&ShadowPath::from("/logs/today.txt")));
+        assert!(filter.matches(&ShadowPath::from("/var/app.db")));
+        assert!(!filter.matches(&ShadowPath::from("/a.txt")));
+        assert_eq!(filter.globs(), vec!["/logs/*", "*.db"]);
+        
+        filter.set_globs(Vec::new());
+        assert!(!filter.is_active());
+        assert!(!filter.matches(&ShadowPath::from("/logs/today.txt")));
+    }
+}
@@ -4,6 +4,10 @@
 //! must implement to provide ShadowFS functionality.
 
 use async_trait::async_trait;
+use std::path::Path;
+use crate::async_file::ShadowFile;
+use crate::merged_view::DirectoryPage;
+use crate::passthrough::{DirectoryStream, LockKind, MountContext};
 use crate::types::{
     ShadowPath, FileHandle, FileMetadata, DirectoryEntry, 
     OperationResult, OpenFlags, Bytes, MountOptions, MountHandle
@@ -125,6 +129,150 @@ pub trait FileSystem: Send + Sync {
     async fn read_directory(&self, path: &ShadowPath) -> OperationResult<Vec<DirectoryEntry>>;
 }
 
+/// Mount-level interface implemented by each platform backend.
+///
+/// Unlike [`FileSystem`], a provider is shared behind an `Arc` and addressed by
+/// mount point, which lets a [`MountManager`](crate::mount_manager::MountManager)
+/// drive several concurrent mounts that all sit on top of one `OverrideStore`.
+#[async_trait]
+pub trait FileSystemProvider: Send + Sync {
+    /// Returns the platform this provider targets.
+    fn platform(&self) -> Platform;
+    
+    /// Mounts `source` at `mount_point`.
+    ///
+    /// # Arguments
+    /// * `source` - The source directory to shadow
+    /// * `mount_point` - Where the shadow filesystem will be exposed
+    /// * `options` - Mount options controlling behavior
+    async fn mount(
+        &self,
+        source: &Path,
+        mount_point: &Path,
+        options: &MountOptions,
+    ) -> crate::error::Result<()>;
+    
+    /// Unmounts the filesystem previously mounted at `mount_point`.
+    async fn unmount(&self, mount_point: &Path) -> crate::error::Result<()>;
+    
+    /// Returns the store and source of the mount at `mount_point`.
+    ///
+    /// The file operations below default to serving the mount from this
+    /// context, so a provider that returns one only overrides the operations
+    /// its backend handles differently. Without a context they fail with
+    /// [`ShadowError::NotMounted`](crate::error::ShadowError::NotMounted).
+    fn context(&self, _mount_point: &Path) -> Option<MountContext> {
+        None
+    }
+    
+    /// Reads up to `size` bytes at `offset` of the file at `path`.
+    async fn read(&self, mount_point: &Path, path: &ShadowPath, offset: u64, size: usize) -> crate::error::Result<bytes::Bytes> {
+        mount_context(self, mount_point)?.read(path, offset, size)
+    }
+    
+    /// Writes `data` at `offset` of the file at `path`, returning the number of bytes written.
+    async fn write(&self, mount_point: &Path, path: &ShadowPath, offset: u64, data: &[u8]) -> crate::error::Result<usize> {
+        mount_context(self, mount_point)?.write(path, offset, data)
+    }
+    
+    /// Sets the length of the file at `path` to `len`, cutting it or extending it with zeros.
+    async fn truncate(&self, mount_point: &Path, path: &ShadowPath, len: u64) -> crate::error::Result<()> {
+        mount_context(self, mount_point)?.truncate(path, len)
+    }
+    
+    /// Opens the file at `path` as an async handle with `flags`; see
+    /// [`MountContext::open`].
+    async fn open(&self, mount_point: &Path, path: &ShadowPath, flags: OpenFlags) -> crate::error::Result<ShadowFile> {
+        mount_context(self, mount_point)?.open(path, flags)
+    }
+    
+    /// Creates the file `path` holding `content`.
+    async fn create_file(&self, mount_point: &Path, path: &ShadowPath, content: bytes::Bytes) -> crate::error::Result<()> {
+        mount_context(self, mount_point)?.create_file(path, content)
+    }
+    
+    /// Creates the directory `path`.
+    async fn create_directory(&self, mount_point: &Path, path: &ShadowPath) -> crate::error::Result<()> {
+        mount_context(self, mount_point)?.create_directory(path)
+    }
+    
+    /// Deletes the file or empty directory at `path`.
+    async fn delete(&self, mount_point: &Path, path: &ShadowPath) -> crate::error::Result<()> {
+        mount_context(self, mount_point)?.delete(path)
+    }
+    
+    /// Moves `from` to `to`.
+    async fn rename(&self, mount_point: &Path, from: &ShadowPath, to: &ShadowPath) -> crate::error::Result<()> {
+        mount_context(self, mount_point)?.rename(from, to)
+    }
+    
+    /// Returns the metadata of `path`.
+    async fn metadata(&self, mount_point: &Path, path: &ShadowPath) -> crate::error::Result<FileMetadata> {
+        mount_context(self, mount_point)?.metadata(path)
+    }
+    
+    /// Returns the metadata of each of `paths`, in order, looked up in one
+    /// batch; see [`MountContext::metadata_many`].
+    async fn metadata_many(&self, mount_point: &Path, paths: &[ShadowPath]) -> crate::error::Result<Vec<crate::error::Result<FileMetadata>>> {
+        Ok(mount_context(self, mount_point)?.metadata_many(paths))
+    }
+    
+    /// Lists the directory at `path`, sorted by name.
+    async fn read_directory(&self, mount_point: &Path, path: &ShadowPath) -> crate::error::Result<Vec<DirectoryEntry>> {
+        mount_context(self, mount_point)?.read_directory(path)
+    }
+    
+    /// Lists up to `limit` entries of the directory at `path` sorting after
+    /// `cursor`; see [`MountContext::list_directory_paged`].
+    async fn list_directory_paged(&self, mount_point: &Path, path: &ShadowPath, cursor: Option<&str>, limit: usize) -> crate::error::Result<DirectoryPage> {
+        mount_context(self, mount_point)?.list_directory_paged(path, cursor, limit)
+    }
+    
+    /// Streams the entries of the directory at `path`, `page_size` at a
+    /// time; see [`MountContext::directory_stream`].
+    fn directory_stream(&self, mount_point: &Path, path: &ShadowPath, page_size: usize) -> crate::error::Result<DirectoryStream> {
+        Ok(mount_context(self, mount_point)?.directory_stream(path, page_size))
+    }
+    
+    /// Returns the value of the extended attribute `name` of `path`, if set.
+    ///
+    /// The store keeps no extended attributes, so the default is unsupported.
+    async fn get_xattr(&self, _mount_point: &Path, _path: &ShadowPath, _name: &str) -> crate::error::Result<Option<bytes::Bytes>> {
+        Err(crate::error::unsupported("extended attributes"))
+    }
+    
+    /// Sets the extended attribute `name` of `path`.
+    async fn set_xattr(&self, _mount_point: &Path, _path: &ShadowPath, _name: &str, _value: &[u8]) -> crate::error::Result<()> {
+        Err(crate::error::unsupported("extended attributes"))
+    }
+    
+    /// Lists the names of the extended attributes of `path`.
+    async fn list_xattrs(&self, _mount_point: &Path, _path: &ShadowPath) -> crate::error::Result<Vec<String>> {
+        Err(crate::error::unsupported("extended attributes"))
+    }
+    
+    /// Removes the extended attribute `name` of `path`.
+    async fn remove_xattr(&self, _mount_point: &Path, _path: &ShadowPath, _name: &str) -> crate::error::Result<()> {
+        Err(crate::error::unsupported("extended attributes"))
+    }
+    
+    /// Takes an advisory lock on the file at `path` for `owner`, failing
+    /// with a conflict instead of waiting.
+    async fn lock(&self, mount_point: &Path, path: &ShadowPath, owner: u64, kind: LockKind) -> crate::error::Result<()> {
+        mount_context(self, mount_point)?.lock(path, owner, kind)
+    }
+    
+    /// Releases the lock `owner` holds on `path`, returning false if it held none.
+    async fn unlock(&self, mount_point: &Path, path: &ShadowPath, owner: u64) -> crate::error::Result<bool> {
+        Ok(mount_context(self, mount_point)?.unlock(path, owner))
+    }
+}
+
+/// Returns the context of the mount at `mount_point`, or a not-mounted error.
+fn mount_context<P: FileSystemProvider + ?Sized>(provider: &P, mount_point: &Path) -> crate::error::Result<MountContext> {
+    provider.context(mount_point).ok_or_else(|| crate::error::not_mounted(ShadowPath::new(mount_point.to_path_buf())))
+}
+
 /// Trait for detecting platform capabilities and creating platform-specific implementations.
 pub trait PlatformDetector: Send + Sync {
     /// Detects the current platform's capabilities.
@@ -305,6 +453,63 @@ impl Default for PlatformCapabilities {
 mod tests {
     use super::*;
     
+    use crate::override_store::OverrideStore;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    
+    /// Provider that only knows its mounts, inheriting every file operation.
+    struct ContextProvider {
+        mount_point: PathBuf,
+        context: MountContext,
+    }
+    
+    #[async_trait]
+    impl FileSystemProvider for ContextProvider {
+        fn platform(&self) -> Platform {
+            Platform::current()
+        }
+        
+        async fn mount(&self, _source: &Path, _mount_point: &Path, _options: &MountOptions) -> crate::error::Result<()> {
+            Ok(())
+        }
+        
+        async fn unmount(&self, _mount_point: &Path) -> crate::error::Result<()> {
+            Ok(())
+        }
+        
+        fn context(&self, mount_point: &Path) -> Option<MountContext> {
+            (mount_point == self.mount_point).then(|| self.context.clone())
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_provider_defaults_delegate_to_context() {
+        let source = tempfile::TempDir::new().unwrap();
+        std::fs::write(source.path().join("a.txt"), "hello").unwrap();
+        let provider = ContextProvider {
+            mount_point: PathBuf::from("/mnt/shadow"),
+            context: MountContext::new(Arc::new(OverrideStore::with_defaults()), source.path(), false),
+        };
+        let mount = Path::new("/mnt/shadow");
+        let file = ShadowPath::from("/a.txt");
+        
+        provider.write(mount, &file, 0, b"J").await.unwrap();
+        assert_eq!(provider.read(mount, &file, 0, 10).await.unwrap(), bytes::Bytes::from("Jello"));
+        provider.create_file(mount, &ShadowPath::from("/b.txt"), bytes::Bytes::from("b")).await.unwrap();
+        let names: Vec<_> = provider.read_directory(mount, &ShadowPath::from("/")).await.unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["a.txt", "b.txt"]);
+        provider.lock(mount, &file, 1, LockKind::Exclusive).await.unwrap();
+        assert!(provider.unlock(mount, &file, 1).await.unwrap());
+        assert!(provider.get_xattr(mount, &file, "user.test").await.is_err());
+        let mut handle = provider.open(mount, &file, OpenFlags::READ).await.unwrap();
+        let mut contents = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut handle, &mut contents).await.unwrap();
+        assert_eq!(contents, "Jello");
+        
+        let error = provider.metadata(Path::new("/elsewhere"), &file).await.unwrap_err();
+        assert!(matches!(error, crate::error::ShadowError::NotMounted { .. }));
+    }
+    
     #[test]
     fn test_platform_capabilities_current() {
         let caps = PlatformCapabilities::current();
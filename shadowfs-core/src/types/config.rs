@@ -1,14 +1,44 @@
 //! Configuration types for ShadowFS.
+//!
+//! [`ShadowConfig`] is what `config.toml` in the configuration directory
+//! holds: global settings and named [`ConfigProfile`]s of mount settings.
+//! Settings loaded from the file can be overridden by environment
+//! variables, and several files layered with [`ShadowConfig::merge`].
+//!
+//! ```toml
+//! log_level = "debug"
+//!
+//! [profiles.dev]
+//! source = "/home/me/project"
+//! mount_point = "/tmp/project"
+//! memory_limit = "512M"
+//! eviction_policy = "2q"
+//!
+//! [[profiles.dev.rules]]
+//! glob = "*.env"
+//! template = "# ${filename} is not available here"
+//! ```
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use crate::error::ShadowError;
+use crate::override_store::EvictionPolicy;
 use super::mount::MountOptions;
 
+/// Environment variable naming the configuration file to use instead of
+/// `config.toml` in the configuration directory.
+pub const CONFIG_ENV: &str = "SHADOWFS_CONFIG";
+
+/// Prefix of the environment variables overriding profile settings, as in
+/// `SHADOWFS_PROFILE_DEV_MEMORY_LIMIT`.
+const PROFILE_ENV_PREFIX: &str = "SHADOWFS_PROFILE_";
+
 /// Log level for the ShadowFS daemon.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     /// Only log errors
     Error,
@@ -56,8 +86,122 @@ impl std::str::FromStr for LogLevel {
     }
 }
 
+/// Parses a size such as `4096`, `512K`, `64M` or `2G`, in binary units.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let split = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (count, unit) = value.split_at(split);
+    let count: u64 = count.parse().map_err(|_| format!("invalid size '{}'", value))?;
+    let unit_bytes: u64 = match unit.to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("invalid size '{}', expected a number of bytes optionally followed by K, M, G or T", value)),
+    };
+    count.checked_mul(unit_bytes).ok_or_else(|| format!("size '{}' is too large", value))
+}
+
+/// Parses a boolean given in an environment variable.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn invalid_env(name: &str, value: &str, expected: &str) -> ShadowError {
+    ShadowError::InvalidConfiguration {
+        message: format!("{}={} is not valid, expected {}", name, value, expected),
+    }
+}
+
+/// A template rule a profile adds to the rules of its mounts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigRule {
+    /// Glob of the source files the rule replaces
+    pub glob: String,
+    
+    /// Content served instead, with `${path}` and `${filename}` expanded
+    pub template: String,
+    
+    /// Rules with a higher priority win over lower ones
+    #[serde(default)]
+    pub priority: u32,
+}
+
+/// Named mount settings kept in the configuration file.
+///
+/// Every setting is optional: a mount takes what its command line leaves
+/// out from the profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigProfile {
+    /// Source directory to shadow
+    pub source: Option<PathBuf>,
+    
+    /// Mount point for the virtual filesystem
+    #[serde(alias = "mountpoint")]
+    pub mount_point: Option<PathBuf>,
+    
+    /// Most memory the overrides may take, such as `512M`
+    pub memory_limit: Option<String>,
+    
+    /// Eviction policy by name, such as `lru` or `2q`
+    pub eviction_policy: Option<String>,
+    
+    /// Whether every write is rejected
+    pub read_only: Option<bool>,
+    
+    /// Rules replacing matching source files
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ConfigRule>,
+}
+
+impl ConfigProfile {
+    /// Returns the memory limit in bytes, if the profile sets one.
+    pub fn memory_limit_bytes(&self) -> Result<Option<u64>, ShadowError> {
+        self.memory_limit.as_deref()
+            .map(|size| parse_size(size).map_err(|message| ShadowError::InvalidConfiguration { message }))
+            .transpose()
+    }
+    
+    /// Returns the eviction policy, if the profile sets one.
+    pub fn eviction_policy(&self) -> Result<Option<EvictionPolicy>, ShadowError> {
+        self.eviction_policy.as_deref()
+            .map(|name| EvictionPolicy::from_name(name).ok_or_else(|| {
+                let names: Vec<_> = EvictionPolicy::ALL.iter().map(EvictionPolicy::name).collect();
+                ShadowError::InvalidConfiguration {
+                    message: format!("unknown eviction policy '{}', expected one of: {}", name, names.join(", ")),
+                }
+            }))
+            .transpose()
+    }
+    
+    /// Checks that the memory limit and eviction policy can be parsed.
+    pub fn validate(&self) -> Result<(), ShadowError> {
+        self.memory_limit_bytes()?;
+        self.eviction_policy()?;
+        Ok(())
+    }
+    
+    /// Takes every setting `other` gives, keeping the others.
+    pub fn merge(&mut self, other: ConfigProfile) {
+        self.source = other.source.or(self.source.take());
+        self.mount_point = other.mount_point.or(self.mount_point.take());
+        self.memory_limit = other.memory_limit.or(self.memory_limit.take());
+        self.eviction_policy = other.eviction_policy.or(self.eviction_policy.take());
+        self.read_only = other.read_only.or(self.read_only);
+        if !other.rules.is_empty() {
+            self.rules = other.rules;
+        }
+    }
+}
+
 /// Global configuration for ShadowFS.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ShadowConfig {
     /// Logging level
     pub log_level: LogLevel,
@@ -73,6 +217,9 @@ pub struct ShadowConfig {
     
     /// Path to the mount registry database
     pub mount_registry_path: PathBuf,
+    
+    /// Named mount settings
+    pub profiles: BTreeMap<String, ConfigProfile>,
 }
 
 impl Default for ShadowConfig {
@@ -83,6 +230,7 @@ impl Default for ShadowConfig {
             daemon_mode: false,
             pid_file: None,
             mount_registry_path: PathBuf::from("/var/lib/shadowfs/mounts.db"),
+            profiles: BTreeMap::new(),
         }
     }
 }
@@ -101,7 +249,142 @@ impl ShadowConfig {
             daemon_mode: false,
             pid_file: None,
             mount_registry_path: PathBuf::from("./shadowfs-mounts.db"),
+            profiles: BTreeMap::new(),
+        }
+    }
+    
+    /// Returns the configuration file used by default: the file named by
+    /// [`CONFIG_ENV`], or `config.toml` in the configuration directory.
+    pub fn default_path() -> PathBuf {
+        match std::env::var_os(CONFIG_ENV) {
+            Some(path) => PathBuf::from(path),
+            None => crate::registry::config_dir().join("config.toml"),
+        }
+    }
+    
+    /// Loads the configuration file at `path`, or the defaults if there is
+    /// none, and applies the overrides in the environment.
+    pub fn load(path: &Path) -> Result<Self, ShadowError> {
+        let mut config = Self::load_file(path)?;
+        config.apply_env(std::env::vars())?;
+        Ok(config)
+    }
+    
+    /// Loads the configuration file at `path` as it is, without the
+    /// overrides in the environment, or the defaults if there is none.
+    pub fn load_file(path: &Path) -> Result<Self, ShadowError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_toml(&text).map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("{}: {}", path.display(), e),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ShadowError::IoError { source: e }),
+        }
+    }
+    
+    /// Parses a configuration file, checking every profile.
+    pub fn from_toml(text: &str) -> Result<Self, ShadowError> {
+        let config: Self = toml::from_str(text).map_err(|e| ShadowError::InvalidConfiguration {
+            message: e.to_string(),
+        })?;
+        for (name, profile) in &config.profiles {
+            profile.validate().map_err(|e| ShadowError::InvalidConfiguration {
+                message: format!("profile {}: {}", name, e),
+            })?;
+        }
+        Ok(config)
+    }
+    
+    /// Writes the configuration to `path`, creating its directory.
+    pub fn save(&self, path: &Path) -> Result<(), ShadowError> {
+        let text = toml::to_string_pretty(self).map_err(|e| ShadowError::InvalidConfiguration {
+            message: format!("cannot serialize configuration: {}", e),
+        })?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| ShadowError::IoError { source: e })?;
+        }
+        let temp = path.with_extension("toml.tmp");
+        std::fs::write(&temp, text).map_err(|e| ShadowError::IoError { source: e })?;
+        std::fs::rename(&temp, path).map_err(|e| ShadowError::IoError { source: e })
+    }
+    
+    /// Layers `other` on top of this configuration.
+    ///
+    /// Global settings left at their defaults in `other` keep their value
+    /// here, and profiles of the same name are merged setting by setting.
+    pub fn merge(&mut self, other: ShadowConfig) {
+        let defaults = Self::default();
+        if other.log_level != defaults.log_level {
+            self.log_level = other.log_level;
+        }
+        self.log_file = other.log_file.or(self.log_file.take());
+        self.daemon_mode |= other.daemon_mode;
+        self.pid_file = other.pid_file.or(self.pid_file.take());
+        if other.mount_registry_path != defaults.mount_registry_path {
+            self.mount_registry_path = other.mount_registry_path;
+        }
+        for (name, profile) in other.profiles {
+            self.profiles.entry(name).or_default().merge(profile);
+        }
+    }
+    
+    /// Applies the `SHADOWFS_*` overrides among `vars`.
+    ///
+    /// `SHADOWFS_LOG_LEVEL`, `SHADOWFS_LOG_FILE`, `SHADOWFS_DAEMON` and
+    /// `SHADOWFS_PID_FILE` set the global settings, and
+    /// `SHADOWFS_PROFILE_<NAME>_<SETTING>` a setting of a profile, with the
+    /// name upper-cased and dashes turned into underscores: `SOURCE`,
+    /// `MOUNT_POINT`, `MEMORY_LIMIT`, `EVICTION_POLICY` or `READ_ONLY`.
+    pub fn apply_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), ShadowError> {
+        for (name, value) in vars {
+            match name.as_str() {
+                "SHADOWFS_LOG_LEVEL" => {
+                    self.log_level = value.parse().map_err(|_| invalid_env(&name, &value, "a log level"))?;
+                }
+                "SHADOWFS_LOG_FILE" => self.log_file = Some(PathBuf::from(value)),
+                "SHADOWFS_DAEMON" => {
+                    self.daemon_mode = parse_bool(&value).ok_or_else(|| invalid_env(&name, &value, "true or false"))?;
+                }
+                "SHADOWFS_PID_FILE" => self.pid_file = Some(PathBuf::from(value)),
+                _ => {
+                    if let Some(setting) = name.strip_prefix(PROFILE_ENV_PREFIX) {
+                        self.apply_profile_env(&name, setting, value)?;
+                    }
+                }
+            }
         }
+        Ok(())
+    }
+    
+    /// Applies `SHADOWFS_PROFILE_<NAME>_<SETTING>`, with `setting` the part
+    /// after the prefix.
+    fn apply_profile_env(&mut self, name: &str, setting: &str, value: String) -> Result<(), ShadowError> {
+        const SETTINGS: [&str; 5] = ["_MOUNT_POINT", "_MEMORY_LIMIT", "_EVICTION_POLICY", "_READ_ONLY", "_SOURCE"];
+        let Some((profile, key)) = SETTINGS.iter().find_map(|key| Some((setting.strip_suffix(key)?, *key))) else {
+            return Ok(());
+        };
+        let env_name = |profile_name: &str| profile_name.to_ascii_uppercase().replace('-', "_");
+        let profile_name = self.profiles.keys()
+            .find(|existing| env_name(existing) == profile)
+            .cloned()
+            .unwrap_or_else(|| profile.to_ascii_lowercase());
+        let entry = self.profiles.entry(profile_name).or_default();
+        match key {
+            "_SOURCE" => entry.source = Some(PathBuf::from(value)),
+            "_MOUNT_POINT" => entry.mount_point = Some(PathBuf::from(value)),
+            "_MEMORY_LIMIT" => {
+                parse_size(&value).map_err(|_| invalid_env(name, &value, "a size such as 512M"))?;
+                entry.memory_limit = Some(value);
+            }
+            "_EVICTION_POLICY" => {
+                if EvictionPolicy::from_name(&value).is_none() {
+                    return Err(invalid_env(name, &value, "lru, lfu, fifo, size-weighted or 2q"));
+                }
+                entry.eviction_policy = Some(value);
+            }
+            _ => entry.read_only = Some(parse_bool(&value).ok_or_else(|| invalid_env(name, &value, "true or false"))?),
+        }
+        Ok(())
     }
     
     /// Validates the configuration.
@@ -157,6 +440,10 @@ pub struct MountRecord {
     
     /// Process ID that created this mount
     pub process_id: u32,
+    
+    /// Snapshot the overrides of the mount are checkpointed into, if any
+    #[serde(default)]
+    pub snapshot_path: Option<PathBuf>,
 }
 
 impl MountRecord {
@@ -174,6 +461,7 @@ impl MountRecord {
             options,
             created_at: SystemTime::now(),
             process_id,
+            snapshot_path: None,
         }
     }
     
@@ -193,14 +481,19 @@ impl MountRecord {
             options,
             created_at,
             process_id,
+            snapshot_path: None,
         }
     }
     
+    /// Sets the snapshot the overrides of the mount are checkpointed into.
+    pub fn with_snapshot_path(mut self, path: PathBuf) -> Self {
+        self.snapshot_path = Some(path);
+        self
+    }
+    
     /// Checks if the process that created this mount is still alive.
     pub fn is_process_alive(&self) -> bool {
-        // Platform-specific implementation would go here
-        // For now, return true as a placeholder
-        true
+        crate::registry::is_process_alive(self.process_id)
     }
 }
 
@@ -292,6 +585,57 @@ mod tests {
         assert!(config.validate().is_ok());
     }
     
+    #[test]
+    fn test_profiles_load_merge_and_env_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(ShadowConfig::load(&path).unwrap().profiles.len(), 0);
+        
+        let text = r#"
+            log_level = "debug"
+            
+            [profiles.dev-box]
+            source = "/src"
+            mountpoint = "/mnt"
+            memory_limit = "512M"
+            
+            [[profiles.dev-box.rules]]
+            glob = "*.env"
+            template = "hidden"
+        "#;
+        let mut config = ShadowConfig::from_toml(text).unwrap();
+        assert_eq!(config.log_level, LogLevel::Debug);
+        let dev = &config.profiles["dev-box"];
+        assert_eq!(dev.mount_point, Some(PathBuf::from("/mnt")));
+        assert_eq!(dev.memory_limit_bytes().unwrap(), Some(512 << 20));
+        assert_eq!(dev.rules[0].priority, 0);
+        
+        config.save(&path).unwrap();
+        assert_eq!(ShadowConfig::from_toml(&std::fs::read_to_string(&path).unwrap()).unwrap().profiles, config.profiles);
+        
+        let mut overlay = ShadowConfig::default();
+        overlay.profiles.insert("dev-box".to_string(), ConfigProfile { read_only: Some(true), ..ConfigProfile::default() });
+        config.merge(overlay);
+        let dev = &config.profiles["dev-box"];
+        assert_eq!((dev.source.as_deref(), dev.read_only), (Some(Path::new("/src")), Some(true)));
+        assert_eq!(config.log_level, LogLevel::Debug);
+        
+        let vars = [
+            ("SHADOWFS_LOG_LEVEL", "warn"),
+            ("SHADOWFS_PROFILE_DEV_BOX_EVICTION_POLICY", "2q"),
+            ("SHADOWFS_PROFILE_CI_MOUNT_POINT", "/ci"),
+            ("UNRELATED", "x"),
+        ];
+        config.apply_env(vars.map(|(name, value)| (name.to_string(), value.to_string()))).unwrap();
+        assert_eq!(config.log_level, LogLevel::Warn);
+        assert_eq!(config.profiles["dev-box"].eviction_policy().unwrap(), Some(EvictionPolicy::TwoQueue));
+        assert_eq!(config.profiles["ci"].mount_point, Some(PathBuf::from("/ci")));
+        
+        let err = config.apply_env([("SHADOWFS_PROFILE_CI_READ_ONLY".to_string(), "maybe".to_string())]).unwrap_err();
+        assert!(err.to_string().contains("SHADOWFS_PROFILE_CI_READ_ONLY=maybe"));
+        assert!(ShadowConfig::from_toml("[profiles.bad]\neviction_policy = \"mru\"").is_err());
+    }
+    
     #[test]
     fn test_mount_record_new() {
         let options = MountOptions::default();
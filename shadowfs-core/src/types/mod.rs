@@ -4,14 +4,18 @@ pub mod metadata;
 pub mod operations;
 pub mod directory;
 pub mod error;
+#[cfg(feature = "platform")]
 pub mod mount;
+#[cfg(feature = "platform")]
 pub mod config;
 
 // Re-export all types from submodules
 pub use path::ShadowPath;
-pub use metadata::{FileType, FilePermissions, PlatformMetadata, FileMetadata, WindowsMetadata, MacOSMetadata, LinuxMetadata};
+pub use metadata::{FileType, FilePermissions, NamedStream, PlatformMetadata, FileMetadata, WindowsMetadata, MacOSMetadata, LinuxMetadata, Platform, Timestamp, current_time};
 pub use operations::{FileHandle, OpenFlags, Bytes, FileOperation};
 pub use directory::DirectoryEntry;
 pub use error::{ShadowError, OperationResult};
-pub use mount::{MountOptions, MountOptionsBuilder, CacheConfig, OverrideConfig, MountHandle, Platform};
-pub use config::{LogLevel, ShadowConfig, MountRecord, MountRegistry};
\ No newline at end of file
+#[cfg(feature = "platform")]
+pub use mount::{MountOptions, MountOptionsBuilder, MountExpiry, MemoryPriority, CacheConfig, OverrideConfig, MountHandle, IMMUTABLE_SOURCE_TTL};
+#[cfg(feature = "platform")]
+pub use config::{parse_size, ConfigProfile, ConfigRule, LogLevel, ShadowConfig, MountRecord, MountRegistry, CONFIG_ENV};
\ No newline at end of file
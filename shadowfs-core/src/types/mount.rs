@@ -1,46 +1,21 @@
 //! Mount-related types and configuration.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 use tokio::sync::oneshot;
+use crate::atime::AtimePolicy;
+use crate::determinism::Determinism;
+use crate::error::ShadowError;
+use crate::hidden::HiddenFiles;
+use crate::mount_paths::{self, MountPaths};
+use crate::path_limits::PathLimits;
+use crate::quota::Quota;
+use crate::supervision::FailurePolicy;
 use crate::types::{FilePermissions, ShadowPath};
 
-/// Represents the platform where the filesystem is mounted.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
-pub enum Platform {
-    Windows,
-    MacOS,
-    Linux,
-}
-
-impl Platform {
-    /// Returns the current platform based on the target OS.
-    pub fn current() -> Self {
-        #[cfg(target_os = "windows")]
-        return Platform::Windows;
-        
-        #[cfg(target_os = "macos")]
-        return Platform::MacOS;
-        
-        #[cfg(target_os = "linux")]
-        return Platform::Linux;
-        
-        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
-        compile_error!("Unsupported platform");
-    }
-}
-
-impl std::fmt::Display for Platform {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Platform::Windows => write!(f, "Windows"),
-            Platform::MacOS => write!(f, "macOS"),
-            Platform::Linux => write!(f, "Linux"),
-        }
-    }
-}
+pub use super::metadata::Platform;
 
 /// Handle representing a mounted filesystem with platform-specific details.
 pub struct MountHandle {
@@ -118,6 +93,31 @@ impl MountHandle {
     pub fn uptime(&self) -> Result<std::time::Duration, std::time::SystemTimeError> {
         self.mount_time.elapsed()
     }
+    
+    /// Returns the source directory and mount point of this mount, for
+    /// converting paths between them.
+    pub fn paths(&self) -> MountPaths {
+        MountPaths::new(self.source.as_path(), self.target.as_path())
+    }
+    
+    /// Returns where `path` lives in the source directory.
+    pub fn source_path_for(&self, path: &ShadowPath) -> PathBuf {
+        mount_paths::source_path(self.source.as_path(), path)
+    }
+    
+    /// Returns the shadow path of `source_path`, a path in the source directory.
+    ///
+    /// # Errors
+    /// Returns [`ShadowError::InvalidPath`] if `source_path` lies outside
+    /// the source directory.
+    pub fn shadow_path_for(&self, source_path: &Path) -> Result<ShadowPath, ShadowError> {
+        self.paths().shadow_path_for(source_path)
+    }
+    
+    /// Returns true if `path` is the source directory or lies below it.
+    pub fn contains(&self, path: &Path) -> bool {
+        mount_paths::shadow_path(self.source.as_path(), path).is_some()
+    }
 }
 
 impl std::fmt::Debug for MountHandle {
@@ -147,6 +147,10 @@ impl std::hash::Hash for MountHandle {
     }
 }
 
+/// How long providers cache source metadata and entries of a mount with an
+/// immutable source, unless the cache configuration asks for longer.
+pub const IMMUTABLE_SOURCE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
 /// Configuration options for mounting a shadow filesystem.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MountOptions {
@@ -174,6 +178,57 @@ pub struct MountOptions {
     
     /// Override store configuration
     pub override_config: OverrideConfig,
+    
+    /// Whether internal failures tear the mount down or degrade it, per subsystem
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    
+    /// When the mount unmounts itself
+    #[serde(default)]
+    pub expiry: MountExpiry,
+    
+    /// Weight of the mount when memory is rebalanced between mounts
+    #[serde(default)]
+    pub memory_priority: MemoryPriority,
+    
+    /// Promise that nothing changes the source tree while it is mounted,
+    /// such as a CI checkout. Providers then skip the source watcher and
+    /// cache source metadata and content for [`IMMUTABLE_SOURCE_TTL`];
+    /// changes made to the source anyway may stay invisible until remount
+    #[serde(default)]
+    pub immutable_source: bool,
+    
+    /// Most files, largest file and most bytes of file content the mount
+    /// may hold; writes past them fail with `QuotaExceeded`
+    #[serde(default)]
+    pub limits: Quota,
+    
+    /// Report frozen timestamps, stable inode numbers, fixed owners and
+    /// git-style permissions, so builds inside the mount are reproducible
+    /// (None = report metadata as it is)
+    #[serde(default)]
+    pub determinism: Option<Determinism>,
+    
+    /// Which reads of an override update its access time, as with the
+    /// `noatime`, `relatime` and `strictatime` mount options
+    #[serde(default)]
+    pub atime: AtimePolicy,
+    
+    /// Most entries per directory, deepest path and longest name new files
+    /// may have; writes past them fail with `LimitExceeded`. Defaults to
+    /// the name length the platform's backend accepts
+    #[serde(default)]
+    pub path_limits: PathLimits,
+    
+    /// Dotfiles, Windows hidden and system files and globs left out of
+    /// listings, and whether they can still be reached by path
+    #[serde(default)]
+    pub hidden_files: HiddenFiles,
+    
+    /// Round-trip a probe file through the mount right after mounting and
+    /// unmount again if it fails; see [`crate::self_test`]
+    #[serde(default)]
+    pub self_test: bool,
 }
 
 impl Default for MountOptions {
@@ -193,6 +248,16 @@ impl Default for MountOptions {
             default_permissions: FilePermissions::default_directory(),
             cache_config: CacheConfig::default(),
             override_config: OverrideConfig::default(),
+            failure_policy: FailurePolicy::default(),
+            expiry: MountExpiry::default(),
+            memory_priority: MemoryPriority::default(),
+            immutable_source: false,
+            limits: Quota::default(),
+            determinism: None,
+            atime: AtimePolicy::default(),
+            path_limits: PathLimits::for_platform(Platform::current()),
+            hidden_files: HiddenFiles::default(),
+            self_test: false,
         }
     }
 }
@@ -255,6 +320,79 @@ impl MountOptions {
         self.override_config = config;
         self
     }
+    
+    /// Sets the failure policy.
+    pub fn failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+    
+    /// Sets when the mount unmounts itself.
+    pub fn expiry(mut self, expiry: MountExpiry) -> Self {
+        self.expiry = expiry;
+        self
+    }
+    
+    /// Sets the weight of the mount when memory is rebalanced.
+    pub fn memory_priority(mut self, priority: MemoryPriority) -> Self {
+        self.memory_priority = priority;
+        self
+    }
+    
+    /// Declares that the source tree will not change while mounted.
+    pub fn immutable_source(mut self) -> Self {
+        self.immutable_source = true;
+        self
+    }
+    
+    /// Sets the file count and size limits of the mount.
+    pub fn limits(mut self, limits: Quota) -> Self {
+        self.limits = limits;
+        self
+    }
+    
+    /// Makes the mount report metadata as `determinism` asks.
+    pub fn deterministic(mut self, determinism: Determinism) -> Self {
+        self.determinism = Some(determinism);
+        self
+    }
+    
+    /// Sets which reads update access times.
+    pub fn atime(mut self, policy: AtimePolicy) -> Self {
+        self.atime = policy;
+        self
+    }
+    
+    /// Sets the directory size, depth and name length limits of the mount.
+    pub fn path_limits(mut self, limits: PathLimits) -> Self {
+        self.path_limits = limits;
+        self
+    }
+    
+    /// Sets which entries the mount hides.
+    pub fn hidden_files(mut self, hidden: HiddenFiles) -> Self {
+        self.hidden_files = hidden;
+        self
+    }
+    
+    /// Sets whether the mount is checked with a probe file once mounted.
+    pub fn self_test(mut self, enabled: bool) -> Self {
+        self.self_test = enabled;
+        self
+    }
+    
+    /// Returns true if the source tree needs watching for outside changes.
+    pub fn watches_source(&self) -> bool {
+        !self.immutable_source
+    }
+    
+    /// Returns how long providers may cache source metadata and entries,
+    /// or `None` to keep their usual short timeouts because the source
+    /// may change.
+    pub fn source_cache_ttl(&self) -> Option<Duration> {
+        self.immutable_source
+            .then(|| Duration::from_secs(self.cache_config.ttl_seconds).max(IMMUTABLE_SOURCE_TTL))
+    }
 }
 
 /// Builder for MountOptions with a fluent interface.
@@ -338,12 +476,132 @@ impl MountOptionsBuilder {
         self
     }
     
+    /// Sets the failure policy.
+    pub fn failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.options.failure_policy = policy;
+        self
+    }
+    
+    /// Sets when the mount unmounts itself.
+    pub fn expiry(mut self, expiry: MountExpiry) -> Self {
+        self.options.expiry = expiry;
+        self
+    }
+    
+    /// Sets the weight of the mount when memory is rebalanced.
+    pub fn memory_priority(mut self, priority: MemoryPriority) -> Self {
+        self.options.memory_priority = priority;
+        self
+    }
+    
+    /// Sets whether the source tree is promised not to change while mounted.
+    pub fn immutable_source(mut self, immutable: bool) -> Self {
+        self.options.immutable_source = immutable;
+        self
+    }
+    
+    /// Sets the file count and size limits of the mount.
+    pub fn limits(mut self, limits: Quota) -> Self {
+        self.options.limits = limits;
+        self
+    }
+    
+    /// Sets the metadata a deterministic mount reports, or None to report
+    /// metadata as it is.
+    pub fn determinism(mut self, determinism: Option<Determinism>) -> Self {
+        self.options.determinism = determinism;
+        self
+    }
+    
+    /// Sets which reads update access times.
+    pub fn atime(mut self, policy: AtimePolicy) -> Self {
+        self.options.atime = policy;
+        self
+    }
+    
+    /// Sets the directory size, depth and name length limits of the mount.
+    pub fn path_limits(mut self, limits: PathLimits) -> Self {
+        self.options.path_limits = limits;
+        self
+    }
+    
+    /// Sets which entries the mount hides.
+    pub fn hidden_files(mut self, hidden: HiddenFiles) -> Self {
+        self.options.hidden_files = hidden;
+        self
+    }
+    
+    /// Sets whether the mount is checked with a probe file once mounted.
+    pub fn self_test(mut self, enabled: bool) -> Self {
+        self.options.self_test = enabled;
+        self
+    }
+    
     /// Builds the final MountOptions.
     pub fn build(self) -> MountOptions {
         self.options
     }
 }
 
+/// Automatic unmounting of forgotten mounts, such as sandboxes left behind
+/// on a shared build machine.
+///
+/// Both limits are off by default. Activity is any lookup or change in the
+/// override store, as counted by its statistics; mounts sharing a store are
+/// busy or idle together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MountExpiry {
+    /// Unmount this long after mounting, however busy the mount is
+    pub ttl: Option<Duration>,
+    
+    /// Unmount after this long without any filesystem operations
+    pub idle_timeout: Option<Duration>,
+    
+    /// How long before expiring to warn
+    pub warning: Duration,
+}
+
+impl Default for MountExpiry {
+    fn default() -> Self {
+        Self {
+            ttl: None,
+            idle_timeout: None,
+            warning: Duration::from_secs(60),
+        }
+    }
+}
+
+impl MountExpiry {
+    /// Returns true if either limit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.ttl.is_some() || self.idle_timeout.is_some()
+    }
+}
+
+/// Weight of a mount when a shared memory budget is rebalanced between
+/// mounts; a mount gets a share of the budget in proportion to its priority
+/// and recent activity, as divided by the `rebalance` module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
+pub struct MemoryPriority(pub u32);
+
+impl MemoryPriority {
+    /// Half the weight of a normal mount
+    pub const LOW: Self = Self(50);
+    
+    /// Weight of mounts without a configured priority
+    pub const NORMAL: Self = Self(100);
+    
+    /// Twice the weight of a normal mount
+    pub const HIGH: Self = Self(200);
+}
+
+impl Default for MemoryPriority {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
 /// Configuration for the filesystem cache.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct CacheConfig {
@@ -508,6 +766,49 @@ mod tests {
         assert_eq!(options.max_path_length, Some(1024));
     }
 
+    #[test]
+    fn test_immutable_source() {
+        let options = MountOptions::default();
+        assert!(options.watches_source());
+        assert!(options.source_cache_ttl().is_none());
+        
+        let options = MountOptions::builder().immutable_source(true).build();
+        assert!(!options.watches_source());
+        assert_eq!(options.source_cache_ttl(), Some(IMMUTABLE_SOURCE_TTL));
+        
+        let longer = MountOptions::new()
+            .immutable_source()
+            .cache_config(CacheConfig { ttl_seconds: 7 * 24 * 60 * 60, ..CacheConfig::default() });
+        assert_eq!(longer.source_cache_ttl(), Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        
+        // Options saved before the field existed deserialize as mutable
+        let mut value = serde_json::to_value(MountOptions::default()).unwrap();
+        value.as_object_mut().unwrap().remove("immutable_source");
+        assert!(!serde_json::from_value::<MountOptions>(value).unwrap().immutable_source);
+    }
+    
+    #[test]
+    fn test_limits_default_to_unlimited() {
+        let limits = Quota { max_files: Some(1_000), ..Quota::default() };
+        assert_eq!(MountOptions::builder().limits(limits).build().limits, limits);
+        
+        // Options saved before the field existed deserialize without limits
+        let mut value = serde_json::to_value(MountOptions::default()).unwrap();
+        value.as_object_mut().unwrap().remove("limits");
+        assert!(serde_json::from_value::<MountOptions>(value).unwrap().limits.is_unlimited());
+    }
+    
+    #[test]
+    fn test_path_limits_default_to_the_platform() {
+        let options = MountOptions::default();
+        assert_eq!(options.path_limits, PathLimits::for_platform(Platform::current()));
+        
+        // Options saved before the field existed deserialize without limits
+        let mut value = serde_json::to_value(options).unwrap();
+        value.as_object_mut().unwrap().remove("path_limits");
+        assert!(serde_json::from_value::<MountOptions>(value).unwrap().path_limits.is_unlimited());
+    }
+    
     #[test]
     fn test_cache_config_presets() {
         let disabled = CacheConfig::disabled();
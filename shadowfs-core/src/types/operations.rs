@@ -80,6 +80,27 @@ impl OpenFlags {
         Self(bits & Self::all().0)
     }
 
+    /// Converts the flags of `open(2)`, as FUSE and FSKit pass them.
+    #[cfg(unix)]
+    pub fn from_posix(flags: i32) -> Self {
+        let mut open_flags = match flags & libc::O_ACCMODE {
+            libc::O_WRONLY => Self::WRITE,
+            libc::O_RDWR => Self::READ | Self::WRITE,
+            _ => Self::READ,
+        };
+        for (flag, open_flag) in [
+            (libc::O_APPEND, Self::APPEND),
+            (libc::O_CREAT, Self::CREATE),
+            (libc::O_TRUNC, Self::TRUNCATE),
+            (libc::O_EXCL, Self::EXCLUSIVE),
+        ] {
+            if flags & flag != 0 {
+                open_flags |= open_flag;
+            }
+        }
+        open_flags
+    }
+    
     /// Returns true if no flags are set.
     pub const fn is_empty(&self) -> bool {
         self.0 == 0
@@ -410,4 +431,15 @@ mod tests {
         assert!(truncated.contains(OpenFlags::WRITE));
         assert_eq!(truncated.bits(), 0b000011);
     }
-}
\ No newline at end of file
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_open_flags_from_posix() {
+        assert_eq!(OpenFlags::from_posix(libc::O_RDONLY), OpenFlags::READ);
+        assert_eq!(OpenFlags::from_posix(libc::O_WRONLY | libc::O_APPEND), OpenFlags::WRITE | OpenFlags::APPEND);
+        assert_eq!(
+            OpenFlags::from_posix(libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC),
+            OpenFlags::READ | OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! The shadow layer in process, without an OS mount.
+//!
+//! [`VirtualFs`] is a [`FileSystemProvider`] that mounts nothing: a mount
+//! only records the source directory and options, and every file operation
+//! is served from the override store and the source the way the platform
+//! providers serve them. It gives the overlay semantics where FUSE, ProjFS
+//! and FSKit are unavailable, such as unit tests, CI containers and
+//! sandboxes, and mount points need not exist.
+//!
+//! ```rust
+//! use shadowfs_core::mount_manager::MountManager;
+//! use shadowfs_core::override_store::OverrideStore;
+//! use shadowfs_core::types::{MountOptions, ShadowPath};
+//! use shadowfs_core::virtual_fs::VirtualFs;
+//! use std::path::Path;
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> shadowfs_core::error::Result<()> {
+//! let manager = MountManager::new(Arc::new(OverrideStore::with_defaults()), VirtualFs::factory());
+//! manager.mount("/path/to/source", "/virtual/mount", MountOptions::default()).await?;
+//!
+//! let provider = manager.get_provider("/virtual/mount").await.unwrap();
+//! let mount = Path::new("/virtual/mount");
+//! provider.write(mount, &ShadowPath::from("/README.md"), 0, b"# Draft").await?;
+//! let entries = provider.read_directory(mount, &ShadowPath::from("/")).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{self, Result, ShadowError};
+use crate::mount_manager::ProviderFactory;
+use crate::override_store::OverrideStore;
+use crate::passthrough::MountContext;
+use crate::traits::FileSystemProvider;
+use crate::types::{MountOptions, Platform, ShadowPath};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Provider serving its mounts in process, from the store and the source.
+pub struct VirtualFs {
+    store: Arc<OverrideStore>,
+    contexts: Mutex<HashMap<PathBuf, MountContext>>,
+}
+
+impl VirtualFs {
+    /// Creates a provider with no mounts over `store`.
+    pub fn new(store: Arc<OverrideStore>) -> Self {
+        Self { store, contexts: Mutex::new(HashMap::new()) }
+    }
+    
+    /// Returns a factory creating a virtual provider for each mount of a
+    /// [`MountManager`](crate::mount_manager::MountManager).
+    pub fn factory() -> ProviderFactory {
+        Arc::new(|store| Arc::new(VirtualFs::new(store)) as Arc<dyn FileSystemProvider>)
+    }
+    
+    /// Returns the store the mounts are served from.
+    pub fn store(&self) -> &Arc<OverrideStore> {
+        &self.store
+    }
+    
+    /// Checks whether `mount_point` is mounted by this provider.
+    pub fn is_mounted(&self, mount_point: &Path) -> bool {
+        self.contexts.lock().unwrap().contains_key(mount_point)
+    }
+}
+
+#[async_trait]
+impl FileSystemProvider for VirtualFs {
+    fn platform(&self) -> Platform {
+        Platform::current()
+    }
+    
+    async fn mount(&self, source: &Path, mount_point: &Path, options: &MountOptions) -> Result<()> {
+        if !source.is_dir() {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!("{} is not a directory", source.display()),
+            });
+        }
+        let mut contexts = self.contexts.lock().unwrap();
+        if contexts.contains_key(mount_point) {
+            return Err(ShadowError::AlreadyExists {
+                path: ShadowPath::new(mount_point.to_path_buf()),
+            });
+        }
+        
+        let mut context = MountContext::new(Arc::clone(&self.store), source, options.read_only);
+        context.determinism = options.determinism;
+        context.hidden_files = options.hidden_files.clone();
+        contexts.insert(mount_point.to_path_buf(), context);
+        debug!("Mounted {} at {} in process", source.display(), mount_point.display());
+        Ok(())
+    }
+    
+    async fn unmount(&self, mount_point: &Path) -> Result<()> {
+        self.contexts.lock().unwrap().remove(mount_point)
+            .ok_or_else(|| error::not_mounted(ShadowPath::new(mount_point.to_path_buf())))?;
+        debug!("Unmounted {}", mount_point.display());
+        Ok(())
+    }
+    
+    fn context(&self, mount_point: &Path) -> Option<MountContext> {
+        self.contexts.lock().unwrap().get(mount_point).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mount_manager::MountManager;
+    use bytes::Bytes;
+    use tempfile::TempDir;
+    
+    fn path(p: &str) -> ShadowPath {
+        ShadowPath::from(p)
+    }
+    
+    #[tokio::test]
+    async fn test_overlay_without_os_mount() {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "pub fn old() {}").unwrap();
+        let manager = MountManager::new(Arc::new(OverrideStore::with_defaults()), VirtualFs::factory());
+        let mount = Path::new("/nowhere/mount");
+        manager.mount(source.path(), mount, MountOptions::default()).await.unwrap();
+        let provider = manager.get_provider(mount).await.unwrap();
+        
+        provider.write(mount, &path("/src/lib.rs"), 7, b"new").await.unwrap();
+        provider.create_file(mount, &path("/src/main.rs"), Bytes::from("fn main() {}")).await.unwrap();
+        provider.rename(mount, &path("/src/main.rs"), &path("/main.rs")).await.unwrap();
+        assert_eq!(provider.read(mount, &path("/src/lib.rs"), 0, 100).await.unwrap(), "pub fn new() {}");
+        let names: Vec<_> = provider.read_directory(mount, &path("/")).await.unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["main.rs", "src"]);
+        provider.delete(mount, &path("/main.rs")).await.unwrap();
+        assert!(provider.metadata(mount, &path("/main.rs")).await.is_err());
+        
+        // The source is never touched
+        assert_eq!(std::fs::read_to_string(source.path().join("src/lib.rs")).unwrap(), "pub fn old() {}");
+        assert!(!source.path().join("main.rs").exists());
+        
+        assert!(provider.mount(source.path(), mount, &MountOptions::default()).await.is_err());
+        manager.unmount(mount).await.unwrap();
+        assert!(provider.read(mount, &path("/src/lib.rs"), 0, 1).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_mount_honours_options() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("a.txt"), "a").unwrap();
+        let fs = VirtualFs::new(Arc::new(OverrideStore::with_defaults()));
+        let mount = Path::new("/mnt/ro");
+        
+        assert!(fs.mount(&source.path().join("a.txt"), mount, &MountOptions::default()).await.is_err());
+        fs.mount(source.path(), mount, &MountOptions::new().read_only()).await.unwrap();
+        assert!(fs.is_mounted(mount));
+        assert!(fs.write(mount, &path("/a.txt"), 0, b"b").await.is_err());
+        assert_eq!(fs.read(mount, &path("/a.txt"), 0, 1).await.unwrap(), "a");
+        
+        fs.unmount(mount).await.unwrap();
+        assert!(matches!(fs.unmount(mount).await, Err(ShadowError::NotMounted { .. })));
+    }
+}
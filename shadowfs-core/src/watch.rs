@@ -0,0 +1,349 @@
+//! Change notifications for the shadow layer.
+//!
+//! A [`WatchService`] fans change events out to subscribers over bounded
+//! async channels. Each subscriber chooses which paths it cares about with a
+//! [`WatchFilter`] of glob patterns. The override store publishes an event
+//! for every override it stores once a service is attached with
+//! [`OverrideStore::set_watch_service`], and platform providers forward their
+//! native notifications (ProjFS notifications, FSEvents, inotify) as the same
+//! [`ChangeEvent`] type, so consumers do not need to know where a change came
+//! from.
+//!
+//! Publishing never blocks: if a subscriber's channel is full the event is
+//! dropped for that subscriber and counted in [`WatchService::dropped_events`].
+
+use crate::override_store::{OverrideContent, OverrideEntry, OverrideRule, OverrideStore};
+use crate::types::{current_time, Platform, ShadowPath};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+
+/// Default number of events buffered per subscriber.
+pub const DEFAULT_WATCH_CAPACITY: usize = 1024;
+
+/// What happened to a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum ChangeKind {
+    /// The path was created
+    Created,
+    /// The content or metadata of the path changed
+    Modified,
+    /// The path was deleted
+    Deleted,
+    /// The path was renamed; the event's path is the new name
+    Renamed {
+        /// Previous path
+        from: ShadowPath,
+    },
+}
+
+/// Where a change was observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ChangeSource {
+    /// The override store changed
+    OverrideStore,
+    /// A platform provider reported a native notification
+    Native(Platform),
+    /// The source tree changed outside the shadow layer
+    SourceTree,
+}
+
+/// A change to a path in the shadow layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChangeEvent {
+    /// Path relative to the mount root
+    pub path: ShadowPath,
+    
+    /// What happened
+    pub kind: ChangeKind,
+    
+    /// Whether the path is a directory
+    pub is_directory: bool,
+    
+    /// Where the change was observed
+    pub source: ChangeSource,
+    
+    /// When the change was observed
+    pub timestamp: SystemTime,
+}
+
+impl ChangeEvent {
+    /// Creates an event observed now.
+    pub fn new(path: ShadowPath, kind: ChangeKind, is_directory: bool, source: ChangeSource) -> Self {
+        Self {
+            path,
+            kind,
+            is_directory,
+            source,
+            timestamp: current_time(),
+        }
+    }
+}
+
+/// Paths a subscriber wants to hear about.
+#[derive(Debug, Clone, Default)]
+pub struct WatchFilter {
+    rules: Vec<OverrideRule>,
+}
+
+impl WatchFilter {
+    /// Matches every path.
+    pub fn all() -> Self {
+        Self::default()
+    }
+    
+    /// Matches paths matching the glob `pattern`.
+    pub fn glob(pattern: impl Into<String>) -> Self {
+        Self::all().or_glob(pattern)
+    }
+    
+    /// Also matches paths matching the glob `pattern`.
+    pub fn or_glob(mut self, pattern: impl Into<String>) -> Self {
+        self.rules.push(OverrideRule::Glob(pattern.into()));
+        self
+    }
+    
+    /// Checks whether `event` passes the filter; renames match on either name.
+    pub fn matches(&self, event: &ChangeEvent) -> bool {
+        if self.rules.is_empty() {
+            return true;
+        }
+        let matches_path = |path: &ShadowPath| self.rules.iter().any(|rule| rule.matches(path));
+        
+        matches_path(&event.path)
+            || matches!(&event.kind, ChangeKind::Renamed { from } if matches_path(from))
+    }
+}
+
+/// Receiving end of a subscription; dropping it unsubscribes.
+#[derive(Debug)]
+pub struct WatchSubscription {
+    id: u64,
+    receiver: mpsc::Receiver<ChangeEvent>,
+}
+
+impl WatchSubscription {
+    /// Returns the subscription id, usable with [`WatchService::unsubscribe`].
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    
+    /// Waits for the next event; returns `None` once the service is gone or the subscription was removed.
+    pub async fn recv(&mut self) -> Option<ChangeEvent> {
+        self.receiver.recv().await
+    }
+    
+    /// Returns the next buffered event without waiting.
+    pub fn try_recv(&mut self) -> Option<ChangeEvent> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    id: u64,
+    filter: WatchFilter,
+    sender: mpsc::Sender<ChangeEvent>,
+}
+
+/// Fans change events out to filtered subscribers.
+#[derive(Debug)]
+pub struct WatchService {
+    subscribers: RwLock<Vec<Subscriber>>,
+    next_id: AtomicU64,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+impl Default for WatchService {
+    fn default() -> Self {
+        Self::new(DEFAULT_WATCH_CAPACITY)
+    }
+}
+
+impl WatchService {
+    /// Creates a service buffering up to `capacity` events per subscriber.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            subscribers: RwLock::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            capacity: capacity.max(1),
+            dropped: AtomicU64::new(0),
+        }
+    }
+    
+    /// Subscribes to events passing `filter`.
+    pub fn subscribe(&self, filter: WatchFilter) -> WatchSubscription {
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.subscribers.write().unwrap().push(Subscriber { id, filter, sender });
+        WatchSubscription { id, receiver }
+    }
+    
+    /// Removes subscription `id`; its receiver yields `None` after draining buffered events.
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        let mut subscribers = self.subscribers.write().unwrap();
+        let before = subscribers.len();
+        subscribers.retain(|subscriber| subscriber.id != id);
+        subscribers.len() != before
+    }
+    
+    /// Delivers `event` to every subscriber whose filter matches.
+    ///
+    /// # Returns
+    /// Number of subscribers that received the event
+    pub fn publish(&self, event: ChangeEvent) -> usize {
+        let mut delivered = 0;
+        let mut closed = false;
+        
+        for subscriber in self.subscribers.read().unwrap().iter() {
+            if !subscriber.filter.matches(&event) {
+                continue;
+            }
+            match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => delivered += 1,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => closed = true,
+            }
+        }
+        
+        if closed {
+            self.subscribers.write().unwrap().retain(|subscriber| !subscriber.sender.is_closed());
+        }
+        delivered
+    }
+    
+    /// Number of active subscriptions.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.read().unwrap().len()
+    }
+    
+    /// Number of events dropped because a subscriber's buffer was full.
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl OverrideStore {
+    /// Publishes a [`ChangeEvent`] to `service` for every override stored from now on.
+    ///
+    /// Inserting a path without an override reports [`ChangeKind::Created`],
+    /// replacing one reports [`ChangeKind::Modified`] and tombstones report
+    /// [`ChangeKind::Deleted`]. Removing an override, which reveals the
+    /// source again, and eviction are not reported.
+    pub fn set_watch_service(&self, service: Arc<WatchService>) {
+        *self.watch.write().unwrap() = Some(service);
+    }
+    
+    /// Stops publishing change events, returning the previous service.
+    pub fn clear_watch_service(&self) -> Option<Arc<WatchService>> {
+        self.watch.write().unwrap().take()
+    }
+    
+    /// Returns the attached watch service, if any.
+    pub fn watch_service(&self) -> Option<Arc<WatchService>> {
+        self.watch.read().unwrap().clone()
+    }
+    
+    /// Publishes the change made by storing `entry`.
+    pub(crate) fn publish_entry_change(&self, entry: &OverrideEntry, replaced: bool) {
+        let Some(service) = self.watch.read().unwrap().clone() else {
+            return;
+        };
+        
+        let kind = match (&entry.content, replaced) {
+            (OverrideContent::Deleted, _) => ChangeKind::Deleted,
+            (_, true) => ChangeKind::Modified,
+            (_, false) => ChangeKind::Created,
+        };
+        service.publish(ChangeEvent::new(
+            entry.path.clone(),
+            kind,
+            entry.is_directory(),
+            ChangeSource::OverrideStore,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    
+    fn event(path: &str, kind: ChangeKind) -> ChangeEvent {
+        ChangeEvent::new(ShadowPath::from(path), kind, false, ChangeSource::Native(Platform::Linux))
+    }
+    
+    #[test]
+    fn test_glob_filters() {
+        let service = WatchService::default();
+        let mut rust = service.subscribe(WatchFilter::glob("*.rs"));
+        let mut everything = service.subscribe(WatchFilter::all());
+        
+        assert_eq!(service.publish(event("/src/main.rs", ChangeKind::Modified)), 2);
+        assert_eq!(service.publish(event("/README.md", ChangeKind::Created)), 1);
+        assert_eq!(service.publish(event("/lib.rs", ChangeKind::Renamed {
+            from: ShadowPath::from("/notes.txt"),
+        })), 2);
+        assert_eq!(service.publish(event("/notes.md", ChangeKind::Renamed {
+            from: ShadowPath::from("/old.rs"),
+        })), 2);
+        
+        assert_eq!(rust.try_recv().unwrap().path, ShadowPath::from("/src/main.rs"));
+        assert_eq!(rust.try_recv().unwrap().path, ShadowPath::from("/lib.rs"));
+        assert_eq!(rust.try_recv().unwrap().path, ShadowPath::from("/notes.md"));
+        assert!(rust.try_recv().is_none());
+        assert_eq!(everything.try_recv().unwrap().kind, ChangeKind::Modified);
+    }
+    
+    #[test]
+    fn test_full_and_closed_subscribers() {
+        let service = WatchService::new(1);
+        let mut slow = service.subscribe(WatchFilter::all());
+        let dropped = service.subscribe(WatchFilter::all());
+        drop(dropped);
+        
+        assert_eq!(service.publish(event("/a", ChangeKind::Created)), 1);
+        assert_eq!(service.subscriber_count(), 1);
+        
+        assert_eq!(service.publish(event("/b", ChangeKind::Created)), 0);
+        assert_eq!(service.dropped_events(), 1);
+        assert_eq!(slow.try_recv().unwrap().path, ShadowPath::from("/a"));
+        
+        assert!(service.unsubscribe(slow.id()));
+        assert!(!service.unsubscribe(slow.id()));
+    }
+    
+    #[tokio::test]
+    async fn test_store_publishes_changes() {
+        let store = OverrideStore::with_defaults();
+        let service = Arc::new(WatchService::default());
+        store.set_watch_service(Arc::clone(&service));
+        let mut subscription = service.subscribe(WatchFilter::glob("/docs/*"));
+        
+        store.insert_directory(ShadowPath::from("/docs"), None).unwrap();
+        store.insert_file(ShadowPath::from("/docs/a.txt"), Bytes::from("one"), None).unwrap();
+        store.insert_file(ShadowPath::from("/docs/a.txt"), Bytes::from("two"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/docs/a.txt")).unwrap();
+        store.insert_file(ShadowPath::from("/other.txt"), Bytes::from("x"), None).unwrap();
+        
+        let mut kinds = Vec::new();
+        for _ in 0..3 {
+            kinds.push(subscription.recv().await.unwrap().kind);
+        }
+        assert_eq!(kinds, vec![ChangeKind::Created, ChangeKind::Modified, ChangeKind::Deleted]);
+        assert!(subscription.try_recv().is_none());
+        
+        store.clear_watch_service();
+        store.insert_file(ShadowPath::from("/docs/b.txt"), Bytes::new(), None).unwrap();
+        assert!(subscription.try_recv().is_none());
+    }
+}
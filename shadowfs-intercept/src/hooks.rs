@@ -0,0 +1,953 @@
+//! The libc functions the preloaded library replaces.
+//!
+//! Each hook resolves its path, hands calls on paths under the root to the
+//! [`Interceptor`] and passes everything else to the function it replaces,
+//! looked up with `dlsym(RTLD_NEXT)`. Calls made while a hook runs, by the
+//! interceptor itself or while it loads, are passed through as well.
+//!
+//! Every hook has the contract of the libc function it replaces.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::interceptor::{Interceptor, ROOT_ENV};
+use shadowfs_core::override_store::RenameCollision;
+use shadowfs_core::determinism::Determinism;
+use shadowfs_core::types::{FileMetadata, FileType, ShadowPath};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::ffi::{CStr, OsStr};
+use std::io;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use libc::{mode_t, DIR, FILE};
+
+/// Interceptor of the process, `None` if it is not sandboxed.
+static INTERCEPTOR: OnceLock<Option<Interceptor>> = OnceLock::new();
+
+/// Addresses of the directory streams the hooks opened.
+static STREAMS: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+thread_local! {
+    /// Set while a hook runs on this thread.
+    static IN_SHIM: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns the function named `$name` that the hook replaces, as `$type`.
+macro_rules! real {
+    ($name:literal: $type:ty) => {{
+        static ADDRESS: AtomicUsize = AtomicUsize::new(0);
+        let mut address = ADDRESS.load(Ordering::Relaxed);
+        if address == 0 {
+            address = libc::dlsym(libc::RTLD_NEXT, concat!($name, "\0").as_ptr().cast()) as usize;
+            if address == 0 {
+                libc::abort();
+            }
+            ADDRESS.store(address, Ordering::Relaxed);
+        }
+        std::mem::transmute::<usize, $type>(address)
+    }};
+}
+
+type OpenFn = unsafe extern "C" fn(*const c_char, c_int, ...) -> c_int;
+type OpenAtFn = unsafe extern "C" fn(c_int, *const c_char, c_int, ...) -> c_int;
+type FopenFn = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut FILE;
+type StreamFn = unsafe extern "C" fn(*mut FILE) -> c_int;
+type FdFn = unsafe extern "C" fn(c_int) -> c_int;
+type StatFn = unsafe extern "C" fn(*const c_char, *mut libc::stat) -> c_int;
+type VersionedStatFn = unsafe extern "C" fn(c_int, *const c_char, *mut libc::stat) -> c_int;
+type PathFn = unsafe extern "C" fn(*const c_char) -> c_int;
+type FstatFn = unsafe extern "C" fn(c_int, *mut libc::stat) -> c_int;
+type VersionedFstatFn = unsafe extern "C" fn(c_int, c_int, *mut libc::stat) -> c_int;
+type FstatAtFn = unsafe extern "C" fn(c_int, *const c_char, *mut libc::stat, c_int) -> c_int;
+type VersionedFstatAtFn = unsafe extern "C" fn(c_int, c_int, *const c_char, *mut libc::stat, c_int) -> c_int;
+type StatxFn = unsafe extern "C" fn(c_int, *const c_char, c_int, libc::c_uint, *mut libc::statx) -> c_int;
+type AccessFn = unsafe extern "C" fn(*const c_char, c_int) -> c_int;
+type AccessAtFn = unsafe extern "C" fn(c_int, *const c_char, c_int, c_int) -> c_int;
+type MkdirFn = unsafe extern "C" fn(*const c_char, mode_t) -> c_int;
+type MkdirAtFn = unsafe extern "C" fn(c_int, *const c_char, mode_t) -> c_int;
+type UnlinkAtFn = unsafe extern "C" fn(c_int, *const c_char, c_int) -> c_int;
+type RenameFn = unsafe extern "C" fn(*const c_char, *const c_char) -> c_int;
+type RenameAtFn = unsafe extern "C" fn(c_int, *const c_char, c_int, *const c_char) -> c_int;
+type RenameAt2Fn = unsafe extern "C" fn(c_int, *const c_char, c_int, *const c_char, c_uint) -> c_int;
+type GetXattrFn = unsafe extern "C" fn(*const c_char, *const c_char, *mut c_void, usize) -> isize;
+type ListXattrFn = unsafe extern "C" fn(*const c_char, *mut c_char, usize) -> isize;
+type OpendirFn = unsafe extern "C" fn(*const c_char) -> *mut DIR;
+type FdopendirFn = unsafe extern "C" fn(c_int) -> *mut DIR;
+type ReaddirFn = unsafe extern "C" fn(*mut DIR) -> *mut libc::dirent64;
+type DirFn = unsafe extern "C" fn(*mut DIR) -> c_int;
+type RewinddirFn = unsafe extern "C" fn(*mut DIR);
+type ExitFn = unsafe extern "C" fn(c_int) -> !;
+type ForkFn = unsafe extern "C" fn() -> libc::pid_t;
+type SpawnFn = unsafe extern "C" fn(
+    *mut libc::pid_t,
+    *const c_char,
+    *const libc::posix_spawn_file_actions_t,
+    *const libc::posix_spawnattr_t,
+    *const *mut c_char,
+    *const *mut c_char,
+) -> c_int;
+type ExecFn = unsafe extern "C" fn(*const c_char, *const *const c_char) -> c_int;
+type ExecEnvFn = unsafe extern "C" fn(*const c_char, *const *const c_char, *const *const c_char) -> c_int;
+type SystemFn = unsafe extern "C" fn(*const c_char) -> c_int;
+type PopenFn = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut FILE;
+
+/// Returns the interceptor, creating it on first use.
+///
+/// A process asked to be sandboxed that cannot be is aborted rather than
+/// left to write to the source tree.
+fn interceptor() -> Option<&'static Interceptor> {
+    INTERCEPTOR
+        .get_or_init(|| match Interceptor::from_env()? {
+            Ok(interceptor) => {
+                // SAFETY: shutdown is a plain extern "C" function
+                unsafe { libc::atexit(shutdown) };
+                Some(interceptor)
+            }
+            Err(e) => {
+                eprintln!("shadowfs-intercept: cannot sandbox {:?}: {}", std::env::var_os(ROOT_ENV), e);
+                std::process::abort();
+            }
+        })
+        .as_ref()
+}
+
+/// Runs `f` on the interceptor, or returns `None` to pass the call through
+/// because the process is not sandboxed or a hook is already running.
+fn with_interceptor<T>(f: impl FnOnce(&Interceptor) -> Option<T>) -> Option<T> {
+    IN_SHIM
+        .try_with(|active| {
+            if active.replace(true) {
+                return None;
+            }
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| interceptor().and_then(f)));
+            active.set(false);
+            result.unwrap_or_else(|_| std::process::abort())
+        })
+        .ok()
+        .flatten()
+}
+
+/// Publishes the changes of the process when it exits through `exit`.
+extern "C" fn shutdown() {
+    // SAFETY: flushing every stream is what exit does next anyway
+    unsafe { libc::fflush(std::ptr::null_mut()) };
+    publish();
+}
+
+/// Publishes the changes of the process for the processes it starts.
+fn publish() {
+    if let Some(Err(e)) = with_interceptor(|interceptor| Some(interceptor.publish())) {
+        eprintln!("shadowfs-intercept: cannot save the sandbox: {}", e);
+    }
+}
+
+/// Resolves `path` relative to the directory open as `dirfd`.
+unsafe fn resolve<'a>(dirfd: c_int, path: *const c_char) -> Option<Cow<'a, Path>> {
+    if path.is_null() {
+        return None;
+    }
+    let path = Path::new(OsStr::from_bytes(CStr::from_ptr(path).to_bytes()));
+    if dirfd == libc::AT_FDCWD || path.is_absolute() {
+        return Some(Cow::Borrowed(path));
+    }
+    let directory = std::fs::read_link(format!("/proc/self/fd/{}", dirfd)).ok()?;
+    Some(Cow::Owned(directory.join(path)))
+}
+
+/// Runs `f` on the store path of `path` if it is under the root.
+unsafe fn redirect<T>(
+    dirfd: c_int,
+    path: *const c_char,
+    f: impl FnOnce(&Interceptor, ShadowPath) -> Option<T>,
+) -> Option<T> {
+    let path = resolve(dirfd, path)?;
+    with_interceptor(|interceptor| {
+        let path = interceptor.shadow_path(&path)?;
+        if let Err(e) = interceptor.sync() {
+            eprintln!("shadowfs-intercept: cannot load the sandbox: {}", e);
+        }
+        f(interceptor, path)
+    })
+}
+
+/// Returns what a libc function returns for `result`, setting `errno` on
+/// failure.
+fn status(result: io::Result<c_int>) -> c_int {
+    result.unwrap_or_else(|e| {
+        // SAFETY: errno is thread-local
+        unsafe { *libc::__errno_location() = e.raw_os_error().unwrap_or(libc::EIO) };
+        -1
+    })
+}
+
+unsafe fn open_hook(dirfd: c_int, path: *const c_char, flags: c_int) -> Option<c_int> {
+    redirect(dirfd, path, |interceptor, path| interceptor.open(&path, flags)).map(status)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn open(path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    open_hook(libc::AT_FDCWD, path, flags).unwrap_or_else(|| real!("open": OpenFn)(path, flags, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn open64(path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    open_hook(libc::AT_FDCWD, path, flags).unwrap_or_else(|| real!("open64": OpenFn)(path, flags, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openat(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    open_hook(dirfd, path, flags).unwrap_or_else(|| real!("openat": OpenAtFn)(dirfd, path, flags, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openat64(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    open_hook(dirfd, path, flags).unwrap_or_else(|| real!("openat64": OpenAtFn)(dirfd, path, flags, mode))
+}
+
+// The variants `_FORTIFY_SOURCE` calls when the mode is not needed
+
+#[no_mangle]
+pub unsafe extern "C" fn __open_2(path: *const c_char, flags: c_int) -> c_int {
+    open(path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __open64_2(path: *const c_char, flags: c_int) -> c_int {
+    open64(path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __openat_2(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    openat(dirfd, path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __openat64_2(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    openat64(dirfd, path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn creat(path: *const c_char, mode: mode_t) -> c_int {
+    open(path, libc::O_CREAT | libc::O_WRONLY | libc::O_TRUNC, mode)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn creat64(path: *const c_char, mode: mode_t) -> c_int {
+    open64(path, libc::O_CREAT | libc::O_WRONLY | libc::O_TRUNC, mode)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn close(fd: c_int) -> c_int {
+    let stored = with_interceptor(|interceptor| interceptor.close(fd));
+    let closed = real!("close": FdFn)(fd);
+    match stored {
+        Some(Err(e)) => status(Err(e)),
+        _ => closed,
+    }
+}
+
+/// Returns the `open(2)` flags of an `fopen` mode.
+fn stream_flags(mode: &[u8]) -> c_int {
+    let mut flags = match mode.first() {
+        Some(b'w') => libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+        Some(b'a') => libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND,
+        _ => libc::O_RDONLY,
+    };
+    for flag in mode.iter().skip(1) {
+        match flag {
+            b'+' => flags = flags & !libc::O_ACCMODE | libc::O_RDWR,
+            b'x' => flags |= libc::O_EXCL,
+            b'e' => flags |= libc::O_CLOEXEC,
+            _ => {}
+        }
+    }
+    flags
+}
+
+unsafe fn fopen_hook(path: *const c_char, mode: *const c_char) -> Option<*mut FILE> {
+    let fd = open_hook(libc::AT_FDCWD, path, stream_flags(CStr::from_ptr(mode).to_bytes()))?;
+    if fd < 0 {
+        return Some(std::ptr::null_mut());
+    }
+    let stream = libc::fdopen(fd, mode);
+    if stream.is_null() {
+        let error = *libc::__errno_location();
+        close(fd);
+        *libc::__errno_location() = error;
+    }
+    Some(stream)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fopen(path: *const c_char, mode: *const c_char) -> *mut FILE {
+    fopen_hook(path, mode).unwrap_or_else(|| real!("fopen": FopenFn)(path, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fopen64(path: *const c_char, mode: *const c_char) -> *mut FILE {
+    fopen_hook(path, mode).unwrap_or_else(|| real!("fopen64": FopenFn)(path, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fclose(stream: *mut FILE) -> c_int {
+    // fclose closes the descriptor without going through close
+    let fd = libc::fileno(stream);
+    let stored = with_interceptor(|interceptor| {
+        interceptor.is_writing(fd).then(|| {
+            libc::fflush(stream);
+            interceptor.close(fd)
+        })?
+    });
+    let closed = real!("fclose": StreamFn)(stream);
+    match stored {
+        Some(Err(e)) => status(Err(e)),
+        _ => closed,
+    }
+}
+
+/// What `stat` reports for a redirected path besides its size and times.
+struct Identity {
+    device: u64,
+    inode: u64,
+    mode: u32,
+    nlink: u32,
+}
+
+impl Identity {
+    /// Returns the identity of the entry at `path` with `metadata`: the
+    /// device of the root and an inode number derived from the path, so
+    /// a file keeps its identity through every descriptor and stat call.
+    fn of(interceptor: &Interceptor, path: &ShadowPath, metadata: &FileMetadata) -> Self {
+        let (kind, nlink) = match metadata.file_type {
+            FileType::Directory => (libc::S_IFDIR, 2),
+            FileType::Symlink => (libc::S_IFLNK, 1),
+            FileType::File => (libc::S_IFREG, 1),
+        };
+        Self {
+            device: interceptor.device(),
+            inode: Determinism::inode(path),
+            mode: kind | metadata.permissions.to_unix_mode(),
+            nlink,
+        }
+    }
+    
+    /// Returns the identity of the file open as `fd` if the interceptor
+    /// serves it from memory.
+    fn of_descriptor(interceptor: &Interceptor, fd: c_int) -> Option<Self> {
+        let path = interceptor.opened_path(fd)?;
+        let metadata = interceptor.metadata(&path)?.ok()?;
+        Some(Self::of(interceptor, &path, &metadata))
+    }
+    
+    unsafe fn stamp(&self, stat: &mut libc::stat) {
+        stat.st_dev = self.device;
+        stat.st_ino = self.inode;
+        stat.st_mode = self.mode;
+        stat.st_nlink = u64::from(self.nlink);
+        stat.st_uid = libc::geteuid();
+        stat.st_gid = libc::getegid();
+    }
+    
+    unsafe fn stamp_statx(&self, statx: &mut libc::statx) {
+        statx.stx_dev_major = libc::major(self.device);
+        statx.stx_dev_minor = libc::minor(self.device);
+        statx.stx_ino = self.inode;
+        statx.stx_mode = self.mode as u16;
+        statx.stx_nlink = self.nlink;
+        statx.stx_uid = libc::geteuid();
+        statx.stx_gid = libc::getegid();
+    }
+}
+
+/// Returns `time` as seconds and nanoseconds since the Unix epoch.
+fn since_epoch(time: SystemTime) -> (i64, u32) {
+    let since = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    (since.as_secs() as i64, since.subsec_nanos())
+}
+
+/// Fills `buf` with what `stat` reports for `metadata` of `path`.
+unsafe fn fill_stat(interceptor: &Interceptor, path: &ShadowPath, metadata: &FileMetadata, buf: *mut libc::stat) {
+    let mut stat: libc::stat = std::mem::zeroed();
+    Identity::of(interceptor, path, metadata).stamp(&mut stat);
+    stat.st_size = metadata.size as i64;
+    stat.st_blksize = 4096;
+    stat.st_blocks = ((metadata.size + 511) / 512) as i64;
+    for (time, (seconds, nanoseconds)) in [
+        (metadata.accessed, (&mut stat.st_atime, &mut stat.st_atime_nsec)),
+        (metadata.modified, (&mut stat.st_mtime, &mut stat.st_mtime_nsec)),
+        (metadata.modified, (&mut stat.st_ctime, &mut stat.st_ctime_nsec)),
+    ] {
+        let since = since_epoch(time);
+        (*seconds, *nanoseconds) = (since.0, i64::from(since.1));
+    }
+    *buf = stat;
+}
+
+/// Fills `buf` with what `statx` reports for `metadata` of `path`.
+unsafe fn fill_statx(interceptor: &Interceptor, path: &ShadowPath, metadata: &FileMetadata, buf: *mut libc::statx) {
+    let mut statx: libc::statx = std::mem::zeroed();
+    Identity::of(interceptor, path, metadata).stamp_statx(&mut statx);
+    statx.stx_mask = libc::STATX_BASIC_STATS | libc::STATX_BTIME;
+    statx.stx_size = metadata.size;
+    statx.stx_blksize = 4096;
+    statx.stx_blocks = (metadata.size + 511) / 512;
+    for (time, timestamp) in [
+        (metadata.accessed, &mut statx.stx_atime),
+        (metadata.created, &mut statx.stx_btime),
+        (metadata.modified, &mut statx.stx_mtime),
+        (metadata.modified, &mut statx.stx_ctime),
+    ] {
+        (timestamp.tv_sec, timestamp.tv_nsec) = since_epoch(time);
+    }
+    *buf = statx;
+}
+
+/// Returns true if the `*at` call on `path` with `flags` is about the
+/// descriptor `dirfd` itself.
+unsafe fn is_empty_path(path: *const c_char, flags: c_int) -> bool {
+    flags & libc::AT_EMPTY_PATH != 0 && !path.is_null() && *path == 0
+}
+
+unsafe fn stat_hook(dirfd: c_int, path: *const c_char, buf: *mut libc::stat) -> Option<c_int> {
+    redirect(dirfd, path, |interceptor, path| {
+        let metadata = interceptor.metadata(&path)?;
+        Some(status(metadata.map(|metadata| {
+            fill_stat(interceptor, &path, &metadata, buf);
+            0
+        })))
+    })
+}
+
+/// Gives what `fstat` returned for `fd` the identity `stat` reports for
+/// the path it was opened at, keeping the size and times of the memory
+/// file, which follow what is written to it.
+unsafe fn fstat_hook(fd: c_int, buf: *mut libc::stat, result: c_int) -> c_int {
+    if result == 0 {
+        if let Some(identity) = with_interceptor(|interceptor| Identity::of_descriptor(interceptor, fd)) {
+            identity.stamp(&mut *buf);
+        }
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn stat(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("stat": StatFn)(path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn stat64(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("stat64": StatFn)(path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lstat(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("lstat": StatFn)(path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lstat64(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("lstat64": StatFn)(path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fstat(fd: c_int, buf: *mut libc::stat) -> c_int {
+    fstat_hook(fd, buf, real!("fstat": FstatFn)(fd, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fstat64(fd: c_int, buf: *mut libc::stat) -> c_int {
+    fstat_hook(fd, buf, real!("fstat64": FstatFn)(fd, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fstatat(dirfd: c_int, path: *const c_char, buf: *mut libc::stat, flags: c_int) -> c_int {
+    if is_empty_path(path, flags) {
+        return fstat(dirfd, buf);
+    }
+    stat_hook(dirfd, path, buf).unwrap_or_else(|| real!("fstatat": FstatAtFn)(dirfd, path, buf, flags))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fstatat64(dirfd: c_int, path: *const c_char, buf: *mut libc::stat, flags: c_int) -> c_int {
+    if is_empty_path(path, flags) {
+        return fstat64(dirfd, buf);
+    }
+    stat_hook(dirfd, path, buf).unwrap_or_else(|| real!("fstatat64": FstatAtFn)(dirfd, path, buf, flags))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn statx(
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mask: libc::c_uint,
+    buf: *mut libc::statx,
+) -> c_int {
+    if is_empty_path(path, flags) {
+        let result = real!("statx": StatxFn)(dirfd, path, flags, mask, buf);
+        if result == 0 {
+            if let Some(identity) = with_interceptor(|interceptor| Identity::of_descriptor(interceptor, dirfd)) {
+                identity.stamp_statx(&mut *buf);
+            }
+        }
+        return result;
+    }
+    redirect(dirfd, path, |interceptor, path| {
+        let metadata = interceptor.metadata(&path)?;
+        Some(status(metadata.map(|metadata| {
+            fill_statx(interceptor, &path, &metadata, buf);
+            0
+        })))
+    })
+    .unwrap_or_else(|| real!("statx": StatxFn)(dirfd, path, flags, mask, buf))
+}
+
+// glibc before 2.33 implements the stat functions inline with these
+
+#[no_mangle]
+pub unsafe extern "C" fn __xstat(version: c_int, path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("__xstat": VersionedStatFn)(version, path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __xstat64(version: c_int, path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("__xstat64": VersionedStatFn)(version, path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __lxstat(version: c_int, path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("__lxstat": VersionedStatFn)(version, path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __lxstat64(version: c_int, path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_hook(libc::AT_FDCWD, path, buf).unwrap_or_else(|| real!("__lxstat64": VersionedStatFn)(version, path, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __fxstat(version: c_int, fd: c_int, buf: *mut libc::stat) -> c_int {
+    fstat_hook(fd, buf, real!("__fxstat": VersionedFstatFn)(version, fd, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __fxstat64(version: c_int, fd: c_int, buf: *mut libc::stat) -> c_int {
+    fstat_hook(fd, buf, real!("__fxstat64": VersionedFstatFn)(version, fd, buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __fxstatat(
+    version: c_int,
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    stat_hook(dirfd, path, buf)
+        .unwrap_or_else(|| real!("__fxstatat": VersionedFstatAtFn)(version, dirfd, path, buf, flags))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __fxstatat64(
+    version: c_int,
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    stat_hook(dirfd, path, buf)
+        .unwrap_or_else(|| real!("__fxstatat64": VersionedFstatAtFn)(version, dirfd, path, buf, flags))
+}
+
+unsafe fn access_hook(dirfd: c_int, path: *const c_char) -> Option<c_int> {
+    redirect(dirfd, path, |interceptor, path| {
+        Some(status(interceptor.metadata(&path)?.map(|_| 0)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn access(path: *const c_char, mode: c_int) -> c_int {
+    access_hook(libc::AT_FDCWD, path).unwrap_or_else(|| real!("access": AccessFn)(path, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn faccessat(dirfd: c_int, path: *const c_char, mode: c_int, flags: c_int) -> c_int {
+    access_hook(dirfd, path).unwrap_or_else(|| real!("faccessat": AccessAtFn)(dirfd, path, mode, flags))
+}
+
+unsafe fn unlink_hook(dirfd: c_int, path: *const c_char, flags: c_int) -> Option<c_int> {
+    redirect(dirfd, path, |interceptor, path| {
+        Some(status(match flags & libc::AT_REMOVEDIR {
+            0 => interceptor.unlink(&path),
+            _ => interceptor.remove_directory(&path),
+        }.map(|()| 0)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn unlink(path: *const c_char) -> c_int {
+    unlink_hook(libc::AT_FDCWD, path, 0).unwrap_or_else(|| real!("unlink": PathFn)(path))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rmdir(path: *const c_char) -> c_int {
+    unlink_hook(libc::AT_FDCWD, path, libc::AT_REMOVEDIR).unwrap_or_else(|| real!("rmdir": PathFn)(path))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn unlinkat(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    unlink_hook(dirfd, path, flags).unwrap_or_else(|| real!("unlinkat": UnlinkAtFn)(dirfd, path, flags))
+}
+
+unsafe fn mkdir_hook(dirfd: c_int, path: *const c_char) -> Option<c_int> {
+    redirect(dirfd, path, |interceptor, path| {
+        Some(status(interceptor.create_directory(&path).map(|()| 0)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mkdir(path: *const c_char, mode: mode_t) -> c_int {
+    mkdir_hook(libc::AT_FDCWD, path).unwrap_or_else(|| real!("mkdir": MkdirFn)(path, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mkdirat(dirfd: c_int, path: *const c_char, mode: mode_t) -> c_int {
+    mkdir_hook(dirfd, path).unwrap_or_else(|| real!("mkdirat": MkdirAtFn)(dirfd, path, mode))
+}
+
+/// Renames within the root in the store; a rename across its boundary
+/// fails as one across filesystems does, which callers fall back from by
+/// copying.
+unsafe fn rename_hook(
+    from_dirfd: c_int,
+    from: *const c_char,
+    to_dirfd: c_int,
+    to: *const c_char,
+    flags: c_uint,
+) -> Option<c_int> {
+    let from = resolve(from_dirfd, from)?;
+    let to = resolve(to_dirfd, to)?;
+    with_interceptor(|interceptor| {
+        if let Err(e) = interceptor.sync() {
+            eprintln!("shadowfs-intercept: cannot load the sandbox: {}", e);
+        }
+        let collision = match flags {
+            0 => Ok(RenameCollision::Overwrite),
+            libc::RENAME_NOREPLACE => Ok(RenameCollision::NoReplace),
+            libc::RENAME_EXCHANGE => Ok(RenameCollision::Exchange),
+            _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        };
+        let result = match (interceptor.shadow_path(&from), interceptor.shadow_path(&to)) {
+            (Some(from), Some(to)) => collision.and_then(|collision| interceptor.rename(&from, &to, collision)),
+            (None, None) => return None,
+            _ => Err(io::Error::from_raw_os_error(libc::EXDEV)),
+        };
+        Some(status(result.map(|()| 0)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rename(from: *const c_char, to: *const c_char) -> c_int {
+    rename_hook(libc::AT_FDCWD, from, libc::AT_FDCWD, to, 0).unwrap_or_else(|| real!("rename": RenameFn)(from, to))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn renameat(from_dirfd: c_int, from: *const c_char, to_dirfd: c_int, to: *const c_char) -> c_int {
+    rename_hook(from_dirfd, from, to_dirfd, to, 0)
+        .unwrap_or_else(|| real!("renameat": RenameAtFn)(from_dirfd, from, to_dirfd, to))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn renameat2(
+    from_dirfd: c_int,
+    from: *const c_char,
+    to_dirfd: c_int,
+    to: *const c_char,
+    flags: c_uint,
+) -> c_int {
+    rename_hook(from_dirfd, from, to_dirfd, to, flags)
+        .unwrap_or_else(|| real!("renameat2": RenameAt2Fn)(from_dirfd, from, to_dirfd, to, flags))
+}
+
+/// Changes into directories under the root through a descriptor, which
+/// may be in the skeleton when only the store holds the directory.
+#[no_mangle]
+pub unsafe extern "C" fn chdir(path: *const c_char) -> c_int {
+    if redirect(libc::AT_FDCWD, path, |_, _| Some(())).is_none() {
+        return real!("chdir": PathFn)(path);
+    }
+    let fd = open(path, libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC, 0);
+    if fd < 0 {
+        return -1;
+    }
+    let changed = libc::fchdir(fd);
+    let error = *libc::__errno_location();
+    close(fd);
+    *libc::__errno_location() = error;
+    changed
+}
+
+// Redirected paths have no extended attributes
+
+unsafe fn xattr_hook(path: *const c_char, missing: io::Result<c_int>) -> Option<isize> {
+    redirect(libc::AT_FDCWD, path, |interceptor, path| {
+        let metadata = interceptor.metadata(&path)?;
+        Some(status(metadata.and(missing)) as isize)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getxattr(path: *const c_char, name: *const c_char, value: *mut c_void, size: usize) -> isize {
+    xattr_hook(path, Err(io::Error::from_raw_os_error(libc::ENODATA)))
+        .unwrap_or_else(|| real!("getxattr": GetXattrFn)(path, name, value, size))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lgetxattr(path: *const c_char, name: *const c_char, value: *mut c_void, size: usize) -> isize {
+    xattr_hook(path, Err(io::Error::from_raw_os_error(libc::ENODATA)))
+        .unwrap_or_else(|| real!("lgetxattr": GetXattrFn)(path, name, value, size))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn listxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize {
+    xattr_hook(path, Ok(0)).unwrap_or_else(|| real!("listxattr": ListXattrFn)(path, list, size))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn llistxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize {
+    xattr_hook(path, Ok(0)).unwrap_or_else(|| real!("llistxattr": ListXattrFn)(path, list, size))
+}
+
+/// Directory stream listing a merged directory.
+struct DirStream {
+    /// Descriptor of the directory, closed with the stream
+    fd: c_int,
+    entries: Vec<libc::dirent64>,
+    position: usize,
+}
+
+/// Returns the entry `readdir` reports for `name`.
+fn dirent(name: &str, inode: u64, file_type: FileType, offset: usize) -> libc::dirent64 {
+    // SAFETY: dirent64 is plain data, for which all zeroes is valid
+    let mut entry: libc::dirent64 = unsafe { std::mem::zeroed() };
+    entry.d_ino = inode;
+    entry.d_off = offset as i64;
+    entry.d_reclen = std::mem::size_of::<libc::dirent64>() as u16;
+    entry.d_type = match file_type {
+        FileType::Directory => libc::DT_DIR,
+        FileType::Symlink => libc::DT_LNK,
+        FileType::File => libc::DT_REG,
+    };
+    let length = name.len().min(entry.d_name.len() - 1);
+    for (slot, byte) in entry.d_name.iter_mut().zip(&name.as_bytes()[..length]) {
+        *slot = *byte as c_char;
+    }
+    entry
+}
+
+/// Returns the stream `dir` if a hook opened it.
+unsafe fn stream<'a>(dir: *mut DIR) -> Option<&'a mut DirStream> {
+    let streams = STREAMS.lock().unwrap_or_else(|e| e.into_inner());
+    streams.contains(&(dir as usize)).then(|| &mut *dir.cast::<DirStream>())
+}
+
+/// Lists the directory open as `fd` if the store holds something in it.
+fn listing(fd: c_int) -> Option<io::Result<Vec<libc::dirent64>>> {
+    with_interceptor(|interceptor| {
+        let opened = std::fs::read_link(format!("/proc/self/fd/{}", fd)).ok()?;
+        let path = interceptor.shadow_path(&opened)?;
+        if let Err(e) = interceptor.sync() {
+            eprintln!("shadowfs-intercept: cannot load the sandbox: {}", e);
+        }
+        let entries = match interceptor.read_directory(&path)? {
+            Ok(entries) => entries,
+            Err(e) => return Some(Err(e)),
+        };
+        let parent = path.parent().unwrap_or_else(|| path.clone());
+        let mut listing = vec![
+            dirent(".", Determinism::inode(&path), FileType::Directory, 1),
+            dirent("..", Determinism::inode(&parent), FileType::Directory, 2),
+        ];
+        for entry in entries {
+            let inode = Determinism::inode(&path.join(&entry.name));
+            listing.push(dirent(&entry.name, inode, entry.metadata.file_type, listing.len() + 1));
+        }
+        Some(Ok(listing))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fdopendir(fd: c_int) -> *mut DIR {
+    match listing(fd) {
+        Some(Ok(entries)) => {
+            let dir = Box::into_raw(Box::new(DirStream { fd, entries, position: 0 })).cast::<DIR>();
+            STREAMS.lock().unwrap_or_else(|e| e.into_inner()).insert(dir as usize);
+            dir
+        }
+        Some(Err(e)) => {
+            status(Err(e));
+            std::ptr::null_mut()
+        }
+        None => real!("fdopendir": FdopendirFn)(fd),
+    }
+}
+
+/// Opens directories under the root as descriptors, which may be in the
+/// skeleton, and lists them with [`fdopendir`].
+#[no_mangle]
+pub unsafe extern "C" fn opendir(path: *const c_char) -> *mut DIR {
+    if redirect(libc::AT_FDCWD, path, |_, _| Some(())).is_none() {
+        return real!("opendir": OpendirFn)(path);
+    }
+    let fd = open(path, libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC, 0);
+    if fd < 0 {
+        return std::ptr::null_mut();
+    }
+    let dir = fdopendir(fd);
+    if dir.is_null() {
+        let error = *libc::__errno_location();
+        close(fd);
+        *libc::__errno_location() = error;
+    }
+    dir
+}
+
+unsafe fn readdir_hook(dir: *mut DIR) -> Option<*mut libc::dirent64> {
+    let stream = stream(dir)?;
+    let entry = stream.entries.get_mut(stream.position);
+    stream.position += usize::from(entry.is_some());
+    Some(entry.map_or(std::ptr::null_mut(), |entry| entry as *mut _))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn readdir(dir: *mut DIR) -> *mut libc::dirent64 {
+    readdir_hook(dir).unwrap_or_else(|| real!("readdir": ReaddirFn)(dir))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn readdir64(dir: *mut DIR) -> *mut libc::dirent64 {
+    readdir_hook(dir).unwrap_or_else(|| real!("readdir64": ReaddirFn)(dir))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rewinddir(dir: *mut DIR) {
+    match stream(dir) {
+        Some(stream) => stream.position = 0,
+        None => real!("rewinddir": RewinddirFn)(dir),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dirfd(dir: *mut DIR) -> c_int {
+    match stream(dir) {
+        Some(stream) => stream.fd,
+        None => real!("dirfd": DirFn)(dir),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn closedir(dir: *mut DIR) -> c_int {
+    if STREAMS.lock().unwrap_or_else(|e| e.into_inner()).remove(&(dir as usize)) {
+        drop(Box::from_raw(dir.cast::<DirStream>()));
+        return 0;
+    }
+    real!("closedir": DirFn)(dir)
+}
+
+// A process publishes its changes before it starts another and when it
+// exits, so processes only miss what ones running alongside them did since
+// they were started.
+
+#[no_mangle]
+pub unsafe extern "C" fn fork() -> libc::pid_t {
+    publish();
+    real!("fork": ForkFn)()
+}
+
+/// Forks instead, since the child of vfork would return into this frame.
+#[no_mangle]
+pub unsafe extern "C" fn vfork() -> libc::pid_t {
+    fork()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn posix_spawn(
+    pid: *mut libc::pid_t,
+    path: *const c_char,
+    file_actions: *const libc::posix_spawn_file_actions_t,
+    attributes: *const libc::posix_spawnattr_t,
+    argv: *const *mut c_char,
+    envp: *const *mut c_char,
+) -> c_int {
+    publish();
+    real!("posix_spawn": SpawnFn)(pid, path, file_actions, attributes, argv, envp)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn posix_spawnp(
+    pid: *mut libc::pid_t,
+    file: *const c_char,
+    file_actions: *const libc::posix_spawn_file_actions_t,
+    attributes: *const libc::posix_spawnattr_t,
+    argv: *const *mut c_char,
+    envp: *const *mut c_char,
+) -> c_int {
+    publish();
+    real!("posix_spawnp": SpawnFn)(pid, file, file_actions, attributes, argv, envp)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execve(path: *const c_char, argv: *const *const c_char, envp: *const *const c_char) -> c_int {
+    publish();
+    real!("execve": ExecEnvFn)(path, argv, envp)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execvpe(file: *const c_char, argv: *const *const c_char, envp: *const *const c_char) -> c_int {
+    publish();
+    real!("execvpe": ExecEnvFn)(file, argv, envp)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execv(path: *const c_char, argv: *const *const c_char) -> c_int {
+    publish();
+    real!("execv": ExecFn)(path, argv)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execvp(file: *const c_char, argv: *const *const c_char) -> c_int {
+    publish();
+    real!("execvp": ExecFn)(file, argv)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn system(command: *const c_char) -> c_int {
+    publish();
+    real!("system": SystemFn)(command)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn popen(command: *const c_char, mode: *const c_char) -> *mut FILE {
+    publish();
+    real!("popen": PopenFn)(command, mode)
+}
+
+// Shells exit with _exit, which skips the atexit handlers; what is still
+// buffered in streams is lost, as it is without the shim
+
+#[no_mangle]
+pub unsafe extern "C" fn _exit(status: c_int) -> ! {
+    publish();
+    real!("_exit": ExitFn)(status)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn _Exit(status: c_int) -> ! {
+    publish();
+    real!("_Exit": ExitFn)(status)
+}
@@ -0,0 +1,673 @@
+//! What the hooks do with a call, independent of how it was intercepted.
+
+use bytes::Bytes;
+use shadowfs_core::error::{LimitKind, ShadowError};
+use shadowfs_core::mount_paths;
+use shadowfs_core::override_store::{AlertConfig, MergeStrategy, OverrideStore, RenameCollision, RenameOptions};
+use shadowfs_core::passthrough::MountContext;
+use shadowfs_core::types::{DirectoryEntry, FileMetadata, FileType, ShadowPath};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Environment variable naming the source tree whose paths are redirected;
+/// the process is not intercepted without it.
+pub const ROOT_ENV: &str = "SHADOWFS_INTERCEPT_ROOT";
+
+/// Environment variable naming a snapshot the processes of a sandbox share
+/// their changes through.
+pub const SNAPSHOT_ENV: &str = "SHADOWFS_INTERCEPT_SNAPSHOT";
+
+/// A memory file standing in for a file the process opened.
+#[derive(Debug)]
+struct MemoryFile {
+    /// Path the file was opened at
+    path: ShadowPath,
+    
+    /// Whether what is written to it goes to the store
+    writable: bool,
+    
+    /// Metadata of the file the override replaces, if there was one
+    original: Option<FileMetadata>,
+    
+    /// Descriptor of the memory file of the interceptor's own, which
+    /// outlives whatever the process does with its descriptors
+    file: File,
+}
+
+/// Device and inode number identifying a memory file through every
+/// descriptor duplicated from the one `open` returned.
+type FileId = (u64, u64);
+
+
+/// Inode number and modification time of a snapshot, which is replaced
+/// rather than rewritten whenever it is saved.
+type SnapshotVersion = (u64, SystemTime);
+
+/// Redirects file operations under a source tree to an override store.
+///
+/// Files read from the store and files opened for writing are served from
+/// anonymous memory files, so reads, writes, seeks, `fstat` and `mmap` on
+/// the descriptor need no interception; the content written goes to the
+/// store whenever a descriptor of the file is closed, including ones
+/// duplicated with `dup2` or inherited by a child process. Everything else
+/// under the root that the store has nothing to say about is passed
+/// through to the source. Directories that only exist in the store are
+/// opened as empty directories in a skeleton of the tree in the temporary
+/// directory, which paths are mapped back from, so descriptors of them work
+/// for `openat`, `fdopendir` and as the working directory. The skeleton is
+/// named after the root, so the processes of a sandbox share it, and is
+/// left behind as it only ever holds empty directories.
+///
+/// Processes share their changes through a snapshot. Each one merges in
+/// what the others saved whenever the snapshot changes, and saves what it
+/// changed itself with [`Interceptor::publish`], which the hooks call
+/// before the process starts another one and when it exits.
+pub struct Interceptor {
+    /// Canonical path of the source tree
+    root: PathBuf,
+    
+    /// Device the source tree is on, reported for every redirected path
+    device: u64,
+    
+    /// Directory the directories only the store has are created in
+    skeleton: PathBuf,
+    
+    /// Store and source directory the operations are served from
+    context: MountContext,
+    
+    /// Snapshot the store is shared through
+    snapshot: Option<PathBuf>,
+    
+    /// Version of the snapshot last merged into the store or saved
+    seen: Mutex<Option<SnapshotVersion>>,
+    
+    /// Memory files the process may still have open
+    files: Mutex<HashMap<FileId, MemoryFile>>,
+    
+    /// Paths changed since the store was last published
+    changed: Mutex<Vec<ShadowPath>>,
+}
+
+impl Interceptor {
+    /// Creates an interceptor redirecting paths under `root` to `store`.
+    pub fn new(root: impl AsRef<Path>, store: Arc<OverrideStore>) -> io::Result<Self> {
+        let root = std::fs::canonicalize(root)?;
+        // The process being sandboxed owns stderr
+        store.update_alert_config(AlertConfig { alerts_enabled: false, ..AlertConfig::default() });
+        Ok(Self {
+            context: MountContext::new(store, root.clone(), false),
+            device: std::fs::metadata(&root)?.dev(),
+            skeleton: std::env::temp_dir().join(format!("shadowfs-intercept-{:016x}", {
+                let mut hasher = DefaultHasher::new();
+                root.hash(&mut hasher);
+                hasher.finish()
+            })),
+            root,
+            snapshot: None,
+            seen: Mutex::new(None),
+            files: Mutex::new(HashMap::new()),
+            changed: Mutex::new(Vec::new()),
+        })
+    }
+    
+    /// Creates the interceptor configured by [`ROOT_ENV`] and
+    /// [`SNAPSHOT_ENV`], or returns `None` if the root is not set.
+    pub fn from_env() -> Option<io::Result<Self>> {
+        let root = std::env::var_os(ROOT_ENV)?;
+        Some(Self::new(root, Arc::new(OverrideStore::with_defaults())).and_then(|mut interceptor| {
+            interceptor.snapshot = std::env::var_os(SNAPSHOT_ENV).map(PathBuf::from);
+            interceptor.sync()?;
+            Ok(interceptor)
+        }))
+    }
+    
+    /// Returns the store the operations are served from.
+    pub fn store(&self) -> &Arc<OverrideStore> {
+        &self.context.store
+    }
+    
+    /// Returns the device the source tree is on.
+    pub fn device(&self) -> u64 {
+        self.device
+    }
+    
+    /// Maps `path`, absolute or relative to the working directory, to its
+    /// path in the store, or returns `None` if it is outside the root and
+    /// the skeleton.
+    ///
+    /// `.` and `..` are resolved lexically; symlinks are not followed, so a
+    /// path reaching the root through one is passed through.
+    pub fn shadow_path(&self, path: &Path) -> Option<ShadowPath> {
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            std::env::current_dir().ok()?.join(path)
+        };
+        mount_paths::shadow_path(&self.root, &absolute)
+            .or_else(|| mount_paths::shadow_path(&self.skeleton, &absolute))
+    }
+    
+    /// Returns true if the store decides what `path` is: it has an override
+    /// or tombstone there or on an ancestor, or written ranges of it.
+    fn is_shadowed(&self, path: &ShadowPath) -> bool {
+        let store = &self.context.store;
+        if store.get(path).is_some() || store.delta_info(path).is_some() {
+            return true;
+        }
+        let mut ancestor = path.parent();
+        while let Some(parent) = ancestor {
+            if store.is_deleted(&parent) {
+                return true;
+            }
+            ancestor = parent.parent();
+        }
+        false
+    }
+    
+    /// Opens `path` with the `open(2)` `flags`, or returns `None` to open
+    /// the source file: the store holds nothing for a file only read, or
+    /// the path is a directory.
+    pub fn open(&self, path: &ShadowPath, flags: i32) -> Option<io::Result<RawFd>> {
+        let writing = flags & libc::O_ACCMODE != libc::O_RDONLY || flags & (libc::O_CREAT | libc::O_TRUNC) != 0;
+        if !writing && !self.is_shadowed(path) {
+            return None;
+        }
+        if let Err(e) = self.store_written(Some(path)) {
+            return Some(Err(e));
+        }
+        let existing = match self.context.metadata(path) {
+            Ok(metadata) if metadata.file_type == FileType::Directory => {
+                if writing {
+                    return Some(Err(io::Error::from_raw_os_error(libc::EISDIR)));
+                }
+                return self.open_skeleton(path, flags);
+            }
+            Ok(_) if flags & libc::O_CREAT != 0 && flags & libc::O_EXCL != 0 => {
+                return Some(Err(io::Error::from_raw_os_error(libc::EEXIST)));
+            }
+            Ok(_) if flags & libc::O_DIRECTORY != 0 => {
+                return Some(Err(io::Error::from_raw_os_error(libc::ENOTDIR)));
+            }
+            Ok(metadata) => Some(metadata),
+            Err(ShadowError::NotFound { .. }) if flags & libc::O_CREAT != 0 => None,
+            Err(e) => return Some(Err(to_io_error(e))),
+        };
+        Some(self.open_memory_file(path, flags, existing, writing))
+    }
+    
+    fn open_memory_file(
+        &self,
+        path: &ShadowPath,
+        flags: i32,
+        existing: Option<FileMetadata>,
+        writing: bool,
+    ) -> io::Result<RawFd> {
+        let content = match &existing {
+            Some(metadata) if flags & libc::O_TRUNC == 0 => {
+                let size = usize::try_from(metadata.size).unwrap_or(usize::MAX);
+                self.context.read(path, 0, size).map_err(to_io_error)?
+            }
+            _ => Bytes::new(),
+        };
+        let original = match self.context.store.get(path) {
+            Some(entry) => entry.original_metadata.clone(),
+            None => existing,
+        };
+        if writing {
+            // The file is an override from now on, before anything is written
+            self.context.store.insert_file(path.clone(), content.clone(), original.clone()).map_err(to_io_error)?;
+            self.changed.lock().unwrap().push(path.clone());
+        }
+        
+        let fd = memory_file(path, &content, flags & libc::O_CLOEXEC != 0, !writing)?;
+        if flags & libc::O_APPEND != 0 {
+            // SAFETY: fd is a descriptor this function just opened
+            unsafe { libc::fcntl(fd, libc::F_SETFL, libc::O_APPEND) };
+        }
+        // SAFETY: as above
+        let file = File::from(unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?);
+        let metadata = file.metadata()?;
+        self.files.lock().unwrap().insert(
+            (metadata.dev(), metadata.ino()),
+            MemoryFile { path: path.clone(), writable: writing, original, file },
+        );
+        Ok(fd)
+    }
+    
+    /// Opens the directory at `path` in the skeleton if the source does not
+    /// have it, or returns `None` to open the source directory.
+    fn open_skeleton(&self, path: &ShadowPath, flags: i32) -> Option<io::Result<RawFd>> {
+        let relative = mount_paths::relative_path(path);
+        if self.root.join(relative).is_dir() {
+            return None;
+        }
+        let directory = self.skeleton.join(relative);
+        let opened = std::fs::create_dir_all(&directory).and_then(|()| {
+            let directory = CString::new(directory.into_os_string().into_vec())?;
+            // SAFETY: directory is a valid C string
+            match unsafe { libc::open(directory.as_ptr(), flags | libc::O_DIRECTORY) } {
+                fd if fd < 0 => Err(io::Error::last_os_error()),
+                fd => Ok(fd),
+            }
+        });
+        Some(opened)
+    }
+    
+    /// Returns the memory file behind `fd` if the interceptor opened it.
+    fn memory_file(&self, fd: RawFd) -> Option<FileId> {
+        let files = self.files.lock().unwrap();
+        if files.is_empty() {
+            return None;
+        }
+        // SAFETY: the descriptor stays open; ManuallyDrop keeps File from closing it
+        let metadata = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) }).metadata().ok()?;
+        let id = (metadata.dev(), metadata.ino());
+        files.contains_key(&id).then_some(id)
+    }
+    
+    /// Returns the path of the file `fd` was opened at if the interceptor
+    /// serves it from memory, so `fstat` can report the same file `stat`
+    /// does.
+    pub fn opened_path(&self, fd: RawFd) -> Option<ShadowPath> {
+        if let Some(id) = self.memory_file(fd) {
+            return self.files.lock().unwrap().get(&id).map(|file| file.path.clone());
+        }
+        let opened = std::fs::read_link(format!("/proc/self/fd/{}", fd)).ok()?;
+        mount_paths::shadow_path(&self.skeleton, &opened)
+    }
+    
+    /// Returns true if `fd` was opened for writing through the interceptor.
+    pub fn is_writing(&self, fd: RawFd) -> bool {
+        let Some(id) = self.memory_file(fd) else { return false };
+        self.files.lock().unwrap().get(&id).is_some_and(|file| file.writable)
+    }
+    
+    /// Stores what was written to `fd` if it was opened for writing through
+    /// the interceptor, and returns `None` if the interceptor did not open
+    /// it. The caller still closes the descriptor.
+    pub fn close(&self, fd: RawFd) -> Option<io::Result<()>> {
+        let id = self.memory_file(fd)?;
+        let mut files = self.files.lock().unwrap();
+        let file = files.get(&id)?;
+        let stored = if file.writable { self.write_back(file) } else { Ok(()) };
+        if !is_open_elsewhere(id, &[fd, file.file.as_raw_fd()]) {
+            files.remove(&id);
+        }
+        Some(stored)
+    }
+    
+    /// Stores what was written to the memory files open for writing at
+    /// `path`, or at any path, and forgets the ones the process closed
+    /// without `close`, as `dup2` does. Closed files go first, so what
+    /// was written to files still open wins.
+    fn store_written(&self, path: Option<&ShadowPath>) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let matching: Vec<FileId> = files.iter()
+            .filter(|(_, file)| path.map_or(true, |path| file.path == *path))
+            .map(|(&id, _)| id)
+            .collect();
+        let (open, closed): (Vec<FileId>, Vec<FileId>) = matching.into_iter()
+            .partition(|&id| is_open_elsewhere(id, &[files[&id].file.as_raw_fd()]));
+        for id in closed {
+            let file = files.remove(&id).expect("listed above");
+            if file.writable {
+                self.write_back(&file)?;
+            }
+        }
+        for id in open {
+            if files[&id].writable {
+                self.write_back(&files[&id])?;
+            }
+        }
+        Ok(())
+    }
+    
+    fn write_back(&self, file: &MemoryFile) -> io::Result<()> {
+        let size = file.file.metadata()?.len();
+        let mut content = vec![0; usize::try_from(size).unwrap_or(usize::MAX)];
+        file.file.read_exact_at(&mut content, 0)?;
+        self.context.store
+            .insert_file(file.path.clone(), Bytes::from(content), file.original.clone())
+            .map_err(to_io_error)?;
+        self.changed.lock().unwrap().push(file.path.clone());
+        Ok(())
+    }
+    
+    /// Returns the metadata of `path` if the store decides what it is.
+    pub fn metadata(&self, path: &ShadowPath) -> Option<io::Result<FileMetadata>> {
+        self.is_shadowed(path).then(|| self.context.metadata(path).map_err(to_io_error))
+    }
+    
+    /// Deletes the file at `path`, leaving a tombstone in the store.
+    pub fn unlink(&self, path: &ShadowPath) -> io::Result<()> {
+        match self.context.metadata(path).map_err(to_io_error)?.file_type {
+            FileType::Directory => Err(io::Error::from_raw_os_error(libc::EISDIR)),
+            _ => self.change(path, || self.context.delete(path)),
+        }
+    }
+    
+    /// Deletes the empty directory at `path`, leaving a tombstone.
+    pub fn remove_directory(&self, path: &ShadowPath) -> io::Result<()> {
+        match self.context.metadata(path).map_err(to_io_error)?.file_type {
+            FileType::Directory => self.change(path, || self.context.delete(path)),
+            _ => Err(io::Error::from_raw_os_error(libc::ENOTDIR)),
+        }
+    }
+    
+    /// Creates the directory `path` in the store.
+    pub fn create_directory(&self, path: &ShadowPath) -> io::Result<()> {
+        self.change(path, || self.context.create_directory(path))
+    }
+    
+    /// Moves `from` to `to` in the store, doing what `collision` asks if
+    /// `to` exists.
+    pub fn rename(&self, from: &ShadowPath, to: &ShadowPath, collision: RenameCollision) -> io::Result<()> {
+        let options = RenameOptions { collision, ..RenameOptions::with_source(&self.root) };
+        self.change(from, || self.context.store.rename(from, to.clone(), options))?;
+        self.changed.lock().unwrap().push(to.clone());
+        Ok(())
+    }
+    
+    /// Runs `operation`, recording that it changed `path` and what is below.
+    fn change(&self, path: &ShadowPath, operation: impl FnOnce() -> Result<(), ShadowError>) -> io::Result<()> {
+        operation().map_err(to_io_error)?;
+        self.changed.lock().unwrap().push(path.clone());
+        Ok(())
+    }
+    
+    /// Lists the directory at `path` merged with the store, or returns
+    /// `None` to list the source directory because the store holds nothing
+    /// at or below it.
+    pub fn read_directory(&self, path: &ShadowPath) -> Option<io::Result<Vec<DirectoryEntry>>> {
+        if !self.is_shadowed(path) && self.context.store.get_directory_children(path).is_empty() {
+            return None;
+        }
+        Some(self.context.read_directory(path).map_err(to_io_error))
+    }
+    
+    /// Merges in what other processes saved to the snapshot since this
+    /// one last did, except for the paths this process changed since.
+    pub fn sync(&self) -> io::Result<()> {
+        let Some(path) = &self.snapshot else { return Ok(()) };
+        let mut seen = self.seen.lock().unwrap();
+        let version = snapshot_version(path)?;
+        if version.is_some() && version != *seen {
+            let changed = self.changed.lock().unwrap().clone();
+            self.merge_saved(path, &changed)?;
+            *seen = version;
+        }
+        Ok(())
+    }
+    
+    /// Stores what was written to every file still open for writing and
+    /// saves what this process changed to the snapshot, so the processes
+    /// started from now on see it.
+    pub fn publish(&self) -> io::Result<()> {
+        self.store_written(None)?;
+        let Some(path) = &self.snapshot else { return Ok(()) };
+        let mut seen = self.seen.lock().unwrap();
+        let changed = self.changed.lock().unwrap().clone();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        
+        // Processes publishing at once save one after the other
+        let lock = File::options().create(true).truncate(false).write(true).open(path.with_extension("lock"))?;
+        // SAFETY: lock is open until the end of the function, which releases it
+        if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let version = snapshot_version(path)?;
+        if version.is_some() && version != *seen {
+            self.merge_saved(path, &changed)?;
+        }
+        self.context.store.save_snapshot(path).map_err(to_io_error)?;
+        *seen = snapshot_version(path)?;
+        self.changed.lock().unwrap().drain(..changed.len());
+        Ok(())
+    }
+    
+    /// Merges the snapshot at `path` into the store, except for the
+    /// `changed` paths and what is below them.
+    fn merge_saved(&self, path: &Path, changed: &[ShadowPath]) -> io::Result<()> {
+        let saved = OverrideStore::load_snapshot(path).map_err(to_io_error)?;
+        for stale in saved.all_paths() {
+            if changed.iter().any(|changed| stale.as_path().starts_with(changed.as_path())) {
+                saved.remove(&stale);
+            }
+        }
+        self.context.store.merge(&saved, MergeStrategy::TheirsWins).map_err(to_io_error)?;
+        Ok(())
+    }
+}
+
+/// Returns the version of the snapshot at `path`, or `None` if nothing
+/// saved it yet.
+fn snapshot_version(path: &Path) -> io::Result<Option<SnapshotVersion>> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(Some((metadata.ino(), metadata.modified()?))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true if a descriptor of the process other than `except` refers
+/// to the file `id`.
+fn is_open_elsewhere(id: FileId, except: &[RawFd]) -> bool {
+    let Ok(descriptors) = std::fs::read_dir("/proc/self/fd") else { return true };
+    descriptors
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let fd = entry.file_name().to_str().and_then(|name| name.parse().ok());
+            fd.is_some_and(|fd: RawFd| !except.contains(&fd))
+        })
+        .filter_map(|entry| std::fs::metadata(entry.path()).ok())
+        .any(|metadata| (metadata.dev(), metadata.ino()) == id)
+}
+
+/// Creates an anonymous memory file holding `content`, positioned at its
+/// start, and sealed against changes if `sealed`.
+fn memory_file(path: &ShadowPath, content: &[u8], cloexec: bool, sealed: bool) -> io::Result<RawFd> {
+    let name = CString::new(format!("shadowfs:{}", path)).unwrap_or_default();
+    let mut flags = libc::MFD_ALLOW_SEALING;
+    if cloexec {
+        flags |= libc::MFD_CLOEXEC;
+    }
+    // SAFETY: name is a valid C string
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    
+    // SAFETY: fd was just opened and is owned by the File until into_raw_fd
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(content)?;
+    let fd = file.into_raw_fd();
+    // SAFETY: fd is open; lseek and fcntl have no memory preconditions
+    unsafe {
+        libc::lseek(fd, 0, libc::SEEK_SET);
+        if sealed {
+            libc::fcntl(fd, libc::F_ADD_SEALS, libc::F_SEAL_WRITE | libc::F_SEAL_GROW | libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL);
+        }
+    }
+    Ok(fd)
+}
+
+/// Returns the `errno` reporting `error`.
+pub fn errno(error: &ShadowError) -> i32 {
+    match error {
+        ShadowError::AccessDenied { .. } | ShadowError::PermissionDenied { .. } => libc::EACCES,
+        ShadowError::ReadOnlyFilesystem { .. } => libc::EROFS,
+        ShadowError::NotFound { .. } => libc::ENOENT,
+        ShadowError::AlreadyExists { .. } => libc::EEXIST,
+        ShadowError::NotADirectory { .. } => libc::ENOTDIR,
+        ShadowError::IsADirectory { .. } => libc::EISDIR,
+        ShadowError::DirectoryNotEmpty { .. } => libc::ENOTEMPTY,
+        ShadowError::InvalidPath { .. } => libc::EINVAL,
+        ShadowError::QuotaExceeded { .. } => libc::EDQUOT,
+        ShadowError::LimitExceeded { limit: LimitKind::NameLength | LimitKind::PathDepth, .. } => libc::ENAMETOOLONG,
+        ShadowError::LimitExceeded { limit: LimitKind::DirectoryEntries, .. } => libc::ENOSPC,
+        ShadowError::IoError { source } => source.raw_os_error().unwrap_or(libc::EIO),
+        _ => libc::EIO,
+    }
+}
+
+fn to_io_error(error: ShadowError) -> io::Error {
+    io::Error::from_raw_os_error(errno(&error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    
+    fn write_fd(fd: RawFd, data: &[u8]) {
+        // SAFETY: the test owns fd; ManuallyDrop leaves closing it to the test
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+        file.write_all(data).unwrap();
+    }
+    
+    fn read_fd(fd: RawFd) -> String {
+        // SAFETY: as in write_fd
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        content
+    }
+    
+    #[test]
+    fn test_writes_go_to_the_store() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "source").unwrap();
+        let interceptor = Interceptor::new(source.path(), Arc::new(OverrideStore::with_defaults())).unwrap();
+        
+        let lib = interceptor.shadow_path(&source.path().join("src/../src/lib.rs")).unwrap();
+        assert_eq!(lib, ShadowPath::from("/src/lib.rs"));
+        assert!(interceptor.shadow_path(Path::new("/etc/hosts")).is_none());
+        
+        // Reading an untouched file goes to the source
+        assert!(interceptor.open(&lib, libc::O_RDONLY).is_none());
+        
+        // A shell redirecting output duplicates the descriptor and closes it
+        let fd = interceptor.open(&lib, libc::O_WRONLY | libc::O_APPEND).unwrap().unwrap();
+        // SAFETY: fd is open, and each descriptor is closed once it is no longer used
+        let stdout = unsafe { libc::dup(fd) };
+        write_fd(fd, b" changed");
+        interceptor.close(fd).unwrap().unwrap();
+        unsafe { libc::close(fd) };
+        write_fd(stdout, b" twice");
+        interceptor.close(stdout).unwrap().unwrap();
+        unsafe { libc::close(stdout) };
+        assert!(interceptor.files.lock().unwrap().is_empty());
+        
+        let fd = interceptor.open(&lib, libc::O_RDONLY).unwrap().unwrap();
+        assert_eq!(read_fd(fd), "source changed twice");
+        interceptor.close(fd).unwrap().unwrap();
+        // SAFETY: as above
+        unsafe { libc::close(fd) };
+        assert_eq!(std::fs::read_to_string(source.path().join("src/lib.rs")).unwrap(), "source");
+        
+        // dup2 closes the descriptor it replaces without close
+        let fd = interceptor.open(&lib, libc::O_WRONLY | libc::O_TRUNC).unwrap().unwrap();
+        write_fd(fd, b"replaced");
+        let null = File::open("/dev/null").unwrap();
+        // SAFETY: as above
+        unsafe { libc::dup2(null.as_raw_fd(), fd) };
+        let reopened = interceptor.open(&lib, libc::O_RDONLY).unwrap().unwrap();
+        assert_eq!(read_fd(reopened), "replaced");
+        unsafe { libc::close(reopened) };
+        unsafe { libc::close(fd) };
+        
+        let created = ShadowPath::from("/src/new.rs");
+        let fd = interceptor.open(&created, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL).unwrap().unwrap();
+        assert_eq!(interceptor.metadata(&created).unwrap().unwrap().size, 0);
+        assert_eq!(interceptor.open(&created, libc::O_CREAT | libc::O_EXCL).unwrap().unwrap_err().raw_os_error(), Some(libc::EEXIST));
+        write_fd(fd, b"fn new() {}");
+        interceptor.publish().unwrap();
+        // SAFETY: as above
+        unsafe { libc::close(fd) };
+        assert_eq!(interceptor.metadata(&created).unwrap().unwrap().size, 11);
+    }
+    
+    #[test]
+    fn test_namespace_changes_stay_in_the_store() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("a.txt"), "a").unwrap();
+        std::fs::write(source.path().join("b.txt"), "b").unwrap();
+        let interceptor = Interceptor::new(source.path(), Arc::new(OverrideStore::with_defaults())).unwrap();
+        let root = ShadowPath::from("/");
+        assert!(interceptor.read_directory(&root).is_none());
+        
+        interceptor.unlink(&ShadowPath::from("/a.txt")).unwrap();
+        interceptor.create_directory(&ShadowPath::from("/out")).unwrap();
+        interceptor
+            .rename(&ShadowPath::from("/b.txt"), &ShadowPath::from("/out/b.txt"), RenameCollision::Overwrite)
+            .unwrap();
+        assert_eq!(
+            interceptor.remove_directory(&ShadowPath::from("/out")).unwrap_err().raw_os_error(),
+            Some(libc::ENOTEMPTY)
+        );
+        
+        let names: Vec<String> = interceptor.read_directory(&root).unwrap().unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["out"]);
+        assert_eq!(
+            interceptor.metadata(&ShadowPath::from("/a.txt")).unwrap().unwrap_err().raw_os_error(),
+            Some(libc::ENOENT)
+        );
+        assert!(source.path().join("a.txt").exists() && !source.path().join("out").exists());
+        
+        // A directory only the store has opens in the skeleton
+        assert!(interceptor.open(&root, libc::O_RDONLY | libc::O_DIRECTORY).is_none());
+        let out = ShadowPath::from("/out");
+        let fd = interceptor.open(&out, libc::O_RDONLY | libc::O_DIRECTORY).unwrap().unwrap();
+        assert_eq!(interceptor.opened_path(fd), Some(out.clone()));
+        let opened = std::fs::read_link(format!("/proc/self/fd/{}", fd)).unwrap();
+        assert_eq!(interceptor.shadow_path(&opened.join("b.txt")), Some(out.join("b.txt")));
+        // SAFETY: fd is open and no longer used
+        unsafe { libc::close(fd) };
+        // Other processes of the sandbox map the skeleton back too
+        let other = Interceptor::new(source.path(), Arc::new(OverrideStore::with_defaults())).unwrap();
+        assert_eq!(other.shadow_path(&opened), Some(out));
+    }
+    
+    /// A parent started before its child sees what the child saved, and
+    /// keeps it when it saves what it changed itself.
+    #[test]
+    fn test_processes_share_the_snapshot() {
+        let source = tempfile::tempdir().unwrap();
+        let snapshot = source.path().join("shadow.snapshot");
+        let process = || {
+            let mut interceptor = Interceptor::new(source.path(), Arc::new(OverrideStore::with_defaults())).unwrap();
+            interceptor.snapshot = Some(snapshot.clone());
+            interceptor.sync().unwrap();
+            interceptor
+        };
+        let (parent, child) = (process(), process());
+        parent.create_directory(&ShadowPath::from("/parent")).unwrap();
+        child.create_directory(&ShadowPath::from("/child")).unwrap();
+        child.publish().unwrap();
+        assert!(parent.metadata(&ShadowPath::from("/child")).is_none());
+        parent.sync().unwrap();
+        assert!(parent.metadata(&ShadowPath::from("/child")).unwrap().is_ok());
+        parent.publish().unwrap();
+        
+        let next = process();
+        assert!(next.metadata(&ShadowPath::from("/child")).unwrap().is_ok());
+        assert!(next.metadata(&ShadowPath::from("/parent")).unwrap().is_ok());
+    }
+}
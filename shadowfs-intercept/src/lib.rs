@@ -0,0 +1,33 @@
+//! Driver-free sandbox for ShadowFS
+//!
+//! Where neither FUSE nor ProjFS can be used, as on locked-down CI runners,
+//! this crate builds a shared library that is preloaded into a process
+//! with `LD_PRELOAD`. It replaces the libc file functions and redirects
+//! every call on a path under `SHADOWFS_INTERCEPT_ROOT` to an override
+//! store in the process, so the process sees its own writes while the
+//! source tree stays untouched:
+//!
+//! ```sh
+//! SHADOWFS_INTERCEPT_ROOT=$PWD \
+//! SHADOWFS_INTERCEPT_SNAPSHOT=/tmp/sandbox.snap \
+//! LD_PRELOAD=target/release/libshadowfs_intercept.so \
+//! make test
+//! ```
+//!
+//! The sandbox is degraded next to a mount. Each process has its own store;
+//! processes only see what other processes wrote if
+//! `SHADOWFS_INTERCEPT_SNAPSHOT` names a snapshot they share, which each
+//! saves to before starting another process and when it exits. Only calls
+//! through libc are seen: statically linked programs, raw system calls and
+//! `getdents` bypass the store, paths reaching the root through a symlink
+//! are not recognized, and `..` is resolved lexically. The hooks are only
+//! built for 64-bit glibc on Linux; DLL injection on Windows is not
+//! supported yet.
+
+#[cfg(target_os = "linux")]
+pub mod interceptor;
+#[cfg(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64", not(test)))]
+mod hooks;
+
+#[cfg(target_os = "linux")]
+pub use interceptor::{Interceptor, ROOT_ENV, SNAPSHOT_ENV};
@@ -0,0 +1,69 @@
+//! Runs shells with the built library preloaded, the way the sandbox is
+//! used, so the hooks are exercised through libc rather than called.
+
+#![cfg(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64"))]
+
+use shadowfs_intercept::{ROOT_ENV, SNAPSHOT_ENV};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The cdylib Cargo built next to this test's executable.
+fn library() -> PathBuf {
+    let deps = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let library = deps.join("libshadowfs_intercept.so");
+    assert!(library.exists(), "{} was not built", library.display());
+    library
+}
+
+/// Runs `script` with `sh` in `root` with the library preloaded, returning
+/// what it printed.
+fn run_preloaded(root: &Path, snapshot: &Path, script: &str) -> String {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .current_dir(root)
+        .env("LD_PRELOAD", library())
+        .env(ROOT_ENV, root)
+        .env(SNAPSHOT_ENV, snapshot)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn listing(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_shell_changes_stay_in_the_sandbox() {
+    let source = tempfile::tempdir().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let snapshot = state.path().join("sandbox.snap");
+    std::fs::write(source.path().join("kept.txt"), "kept").unwrap();
+    std::fs::write(source.path().join("gone.txt"), "source").unwrap();
+    
+    // Every command but echo is a child of the shell, so each one picks up
+    // what the processes before it saved to the snapshot
+    let output = run_preloaded(
+        source.path(),
+        &snapshot,
+        "echo x > new.txt; cat new.txt; rm gone.txt; ls; \
+         mkdir dir; mv new.txt dir/moved.txt; cat dir/moved.txt; ls dir; \
+         test -e gone.txt || echo deleted",
+    );
+    assert_eq!(output, "x\nkept.txt\nnew.txt\nx\nmoved.txt\ndeleted\n");
+    
+    // A later process of the same sandbox starts from the snapshot
+    let output = run_preloaded(source.path(), &snapshot, "cat dir/moved.txt kept.txt; ls");
+    assert_eq!(output, "x\nkeptdir\nkept.txt\n");
+    
+    assert_eq!(listing(source.path()), ["gone.txt", "kept.txt"]);
+    assert_eq!(std::fs::read_to_string(source.path().join("gone.txt")).unwrap(), "source");
+    assert_eq!(std::fs::read_to_string(source.path().join("kept.txt")).unwrap(), "kept");
+}
@@ -0,0 +1,1441 @@
+//! FUSE provider backed by the shared core `OverrideStore`.
+//!
+//! Reads fall through to the source directory unless the path has an
+//! override; writes copy the file into the store, and renames are carried
+//! out by the store too, so compression, dedup, eviction and stats all
+//! behave exactly as on the other platforms. Source files matching the
+//! store's rule set are replaced by the rule's content on first access, and
+//! those matching its read transforms are served transformed; writing to a
+//! transformed file stores the transformed content as its override first.
+//!
+//! Inode numbers are handed out per path by an inode table that follows
+//! renames. New files and directories exist only in the store until
+//! committed, and deletes leave tombstones, so the source directory is
+//! never written. Kernel mount options such as `allow_other` are set per
+//! provider with [`FuseMountOptions`].
+
+use crate::source_watch::{LinuxSourceWatcher, LinuxWatchConfig, WatchHealth};
+use async_trait::async_trait;
+use bytes::Bytes;
+use fuser::consts::FOPEN_KEEP_CACHE;
+use fuser::{
+    BackgroundSession, FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite, Request, FUSE_ROOT_ID,
+};
+use shadowfs_core::access::AccessOperation;
+use shadowfs_core::atime::AtimePolicy;
+use shadowfs_core::determinism::Determinism;
+use shadowfs_core::error::{LimitKind, Platform as ErrorPlatform, Result, ShadowError};
+use shadowfs_core::handles::HandleTable;
+use shadowfs_core::hidden::HiddenFiles;
+use shadowfs_core::merged_view::{MergedChild, MergedDirectoryView};
+use shadowfs_core::mount_paths;
+use shadowfs_core::override_store::{OverrideEntry, OverrideStore, RenameCollision, RenameOptions};
+use shadowfs_core::passthrough::MountContext;
+use shadowfs_core::source_watch::{SourceChangeHandler, SourceWatcher};
+use shadowfs_core::stats::{FileSystemStats, OperationType};
+use shadowfs_core::supervision::{FailureMode, Subsystem};
+use shadowfs_core::telemetry::operation_span;
+use shadowfs_core::trace::TRACE_TARGET;
+use shadowfs_core::traits::FileSystemProvider;
+use shadowfs_core::types::{
+    FileHandle, FileMetadata, FilePermissions, FileType as ShadowFileType, MountOptions, OpenFlags,
+    Platform, PlatformMetadata, ShadowPath,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::ops::Bound;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::span::EnteredSpan;
+use tracing::{debug, warn, Span};
+
+/// How long the kernel may cache attributes and entries of a source that
+/// may change.
+const TTL: Duration = Duration::from_secs(1);
+
+/// Block size reported for override entries.
+const BLOCK_SIZE: u32 = 4096;
+
+/// Longest file name reported by `statfs` when the source cannot be queried.
+const MAX_NAME_LENGTH: u32 = 255;
+
+/// Kernel mount options of the FUSE mounts made by a [`FuseProvider`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuseMountOptions {
+    /// Let users other than the one mounting access the mount; needs
+    /// `user_allow_other` in `/etc/fuse.conf` unless mounting as root
+    pub allow_other: bool,
+    
+    /// Have the kernel unmount when the serving process exits, even if it
+    /// is killed. fusermount3 only honours this together with `allow_other`
+    pub auto_unmount: bool,
+    
+    /// Largest read request the kernel sends, in bytes (None = kernel default)
+    pub max_read: Option<u32>,
+}
+
+impl FuseMountOptions {
+    /// Returns the `fuser` options for these settings.
+    fn mount_options(&self) -> Vec<MountOption> {
+        let mut options = Vec::new();
+        if self.allow_other {
+            options.push(MountOption::AllowOther);
+        }
+        if self.auto_unmount {
+            options.push(MountOption::AutoUnmount);
+        }
+        if let Some(max_read) = self.max_read {
+            options.push(MountOption::CUSTOM(format!("max_read={}", max_read)));
+        }
+        options
+    }
+}
+
+/// Linux filesystem provider that serves mounts through FUSE.
+///
+/// Every mount created by a provider shares the provider's `OverrideStore`.
+pub struct FuseProvider {
+    /// Override store shared by all mounts of this provider
+    store: Arc<OverrideStore>,
+    
+    /// Running FUSE sessions keyed by mount point; dropping one unmounts it
+    sessions: Mutex<HashMap<PathBuf, BackgroundSession>>,
+    
+    /// Running source watchers keyed by source directory
+    source_watchers: Mutex<HashMap<PathBuf, LinuxSourceWatcher>>,
+    
+    /// Contexts of the running mounts, serving the provider's file operations
+    contexts: Mutex<HashMap<PathBuf, MountContext>>,
+    
+    /// Mount points whose source was declared immutable
+    immutable_mounts: Mutex<HashSet<PathBuf>>,
+    
+    /// Kernel mount options applied to every mount
+    mount_options: FuseMountOptions,
+    
+    /// Counts and latencies of the FUSE operations served by all mounts
+    stats: Arc<FileSystemStats>,
+}
+
+impl FuseProvider {
+    /// Creates a provider serving overrides from `store`.
+    pub fn new(store: Arc<OverrideStore>) -> Self {
+        Self::with_stats(store, Arc::new(FileSystemStats::new()))
+    }
+    
+    /// Creates a provider serving overrides from `store` that records the
+    /// operations it serves in `stats`.
+    pub fn with_stats(store: Arc<OverrideStore>, stats: Arc<FileSystemStats>) -> Self {
+        Self {
+            store,
+            sessions: Mutex::new(HashMap::new()),
+            source_watchers: Mutex::new(HashMap::new()),
+            contexts: Mutex::new(HashMap::new()),
+            immutable_mounts: Mutex::new(HashSet::new()),
+            mount_options: FuseMountOptions::default(),
+            stats,
+        }
+    }
+    
+    /// Sets the kernel mount options of the mounts this provider makes.
+    pub fn with_mount_options(mut self, options: FuseMountOptions) -> Self {
+        self.mount_options = options;
+        self
+    }
+    
+    /// Returns the override store used by this provider.
+    pub fn store(&self) -> Arc<OverrideStore> {
+        Arc::clone(&self.store)
+    }
+    
+    /// Returns the operation statistics of this provider's mounts.
+    pub fn stats(&self) -> Arc<FileSystemStats> {
+        Arc::clone(&self.stats)
+    }
+    
+    /// Checks whether `mount_point` is currently mounted by this provider.
+    pub fn is_mounted(&self, mount_point: &Path) -> bool {
+        self.sessions.lock().unwrap().contains_key(mount_point)
+    }
+    
+    /// Starts watching `source` with inotify or fanotify.
+    ///
+    /// Source changes are checked against the override store for conflicts
+    /// and published to its watch service, and close the changed files in
+    /// the store's source file cache. Beyond that, reads always go to the
+    /// source, so only the kernel's short attribute TTL delays changes.
+    ///
+    /// Watcher failures go to the store's supervisor, if one is attached.
+    /// When its policy degrades the watcher, a watcher that fails to start is
+    /// logged and the mount goes on without source change notifications.
+    ///
+    /// A source mounted with [`MountOptions::immutable_source`] is not
+    /// watched; the handler is returned without a watcher feeding it.
+    pub fn start_source_watching(&self, source: &Path, config: LinuxWatchConfig) -> Result<Arc<SourceChangeHandler>> {
+        self.stop_source_watching(source);
+        let handler = Arc::new(SourceChangeHandler::new(Arc::clone(&self.store)));
+        handler.on_invalidate({
+            let (store, source) = (Arc::clone(&self.store), source.to_path_buf());
+            move |path, recursive| {
+                if let Some(cache) = store.source_cache() {
+                    cache.invalidate(&mount_paths::source_path(&source, path), recursive);
+                }
+            }
+        });
+        if self.is_immutable_source(source) {
+            debug!("Not watching immutable source {}", source.display());
+            return Ok(handler);
+        }
+        let mut watcher = LinuxSourceWatcher::new(source, config);
+        let supervisor = self.store.supervisor();
+        if let Some(supervisor) = &supervisor {
+            watcher = watcher.with_supervisor(Arc::clone(supervisor));
+        }
+        if let Err(e) = watcher.start(Arc::clone(&handler) as Arc<_>) {
+            let mode = supervisor.map(|supervisor| {
+                supervisor.report(Subsystem::Watcher, format!("watching {} failed: {}", source.display(), e))
+            });
+            if mode == Some(FailureMode::Degrade) {
+                warn!("Not watching {} for changes: {}", source.display(), e);
+                return Ok(handler);
+            }
+            return Err(e);
+        }
+        self.source_watchers.lock().unwrap().insert(source.to_path_buf(), watcher);
+        Ok(handler)
+    }
+    
+    /// Stops watching `source`.
+    pub fn stop_source_watching(&self, source: &Path) {
+        let watcher = self.source_watchers.lock().unwrap().remove(source);
+        if let Some(mut watcher) = watcher {
+            watcher.stop();
+        }
+    }
+    
+    /// Checks whether a running mount declared `source` immutable.
+    fn is_immutable_source(&self, source: &Path) -> bool {
+        let contexts = self.contexts.lock().unwrap();
+        self.immutable_mounts.lock().unwrap().iter()
+            .any(|mount_point| contexts.get(mount_point).is_some_and(|context| context.source == source))
+    }
+    
+    /// Returns the health of the watcher of `source`, if one is running.
+    pub fn source_watch_health(&self, source: &Path) -> Option<WatchHealth> {
+        self.source_watchers.lock().unwrap().get(source).map(LinuxSourceWatcher::health)
+    }
+}
+
+#[async_trait]
+impl FileSystemProvider for FuseProvider {
+    fn platform(&self) -> Platform {
+        Platform::Linux
+    }
+    
+    async fn mount(&self, source: &Path, mount_point: &Path, options: &MountOptions) -> Result<()> {
+        if self.is_mounted(mount_point) {
+            return Err(ShadowError::AlreadyExists {
+                path: ShadowPath::new(mount_point.to_path_buf()),
+            });
+        }
+        
+        let mut filesystem = ShadowFilesystem::new(source.to_path_buf(), Arc::clone(&self.store), options.read_only)
+            .with_stats(Arc::clone(&self.stats));
+        if let Some(ttl) = options.source_cache_ttl() {
+            filesystem = filesystem.with_immutable_source(ttl);
+        }
+        if let Some(determinism) = options.determinism {
+            filesystem = filesystem.with_determinism(determinism);
+        }
+        if !options.hidden_files.is_empty() {
+            filesystem = filesystem.with_hidden_files(options.hidden_files.clone());
+        }
+        let handles = Arc::new(HandleTable::new());
+        filesystem = filesystem.with_handles(Arc::clone(&handles));
+        
+        let mut mount_options = vec![
+            MountOption::FSName("shadowfs".to_string()),
+            MountOption::DefaultPermissions,
+        ];
+        if options.read_only {
+            mount_options.push(MountOption::RO);
+        }
+        if options.atime == AtimePolicy::NoAtime {
+            // Spares the kernel setattr calls the store would ignore
+            mount_options.push(MountOption::NoAtime);
+        }
+        mount_options.extend(self.mount_options.mount_options());
+        
+        let session = fuser::spawn_mount2(filesystem, mount_point, &mount_options)
+            .map_err(|e| ShadowError::PlatformError {
+                platform: ErrorPlatform::Linux,
+                message: format!("Failed to mount {}: {}", mount_point.display(), e),
+                code: e.raw_os_error(),
+            })?;
+        
+        debug!("Mounted {} at {}", source.display(), mount_point.display());
+        self.sessions.lock().unwrap().insert(mount_point.to_path_buf(), session);
+        let mut context = MountContext::new(Arc::clone(&self.store), source, options.read_only);
+        context.determinism = options.determinism;
+        context.hidden_files = options.hidden_files.clone();
+        context.handles = handles;
+        self.contexts.lock().unwrap().insert(mount_point.to_path_buf(), context);
+        if options.immutable_source {
+            self.immutable_mounts.lock().unwrap().insert(mount_point.to_path_buf());
+            self.stop_source_watching(source);
+        }
+        Ok(())
+    }
+    
+    async fn unmount(&self, mount_point: &Path) -> Result<()> {
+        let session = self.sessions.lock().unwrap().remove(mount_point)
+            .ok_or_else(|| ShadowError::NotMounted {
+                mount_point: ShadowPath::new(mount_point.to_path_buf()),
+            })?;
+        self.contexts.lock().unwrap().remove(mount_point);
+        self.immutable_mounts.lock().unwrap().remove(mount_point);
+        
+        // Dropping the session unmounts and joins the FUSE thread, which blocks
+        shadowfs_core::task::spawn_blocking("fuse-unmount", move || drop(session))
+            .await
+            .map_err(|e| ShadowError::PlatformError {
+                platform: ErrorPlatform::Linux,
+                message: format!("Failed to unmount {}: {}", mount_point.display(), e),
+                code: None,
+            })?;
+        
+        debug!("Unmounted {}", mount_point.display());
+        Ok(())
+    }
+    
+    fn context(&self, mount_point: &Path) -> Option<MountContext> {
+        self.contexts.lock().unwrap().get(mount_point).cloned()
+    }
+}
+
+/// Bidirectional mapping between FUSE inode numbers and shadow paths.
+#[derive(Debug)]
+struct InodeTable {
+    by_ino: HashMap<u64, ShadowPath>,
+    by_path: HashMap<ShadowPath, u64>,
+    next_ino: u64,
+    /// Whether inode numbers are derived from the path instead of handed
+    /// out in lookup order
+    stable: bool,
+}
+
+impl InodeTable {
+    fn new() -> Self {
+        let root = ShadowPath::from("/");
+        let mut table = Self {
+            by_ino: HashMap::new(),
+            by_path: HashMap::new(),
+            next_ino: FUSE_ROOT_ID + 1,
+            stable: false,
+        };
+        table.by_ino.insert(FUSE_ROOT_ID, root.clone());
+        table.by_path.insert(root, FUSE_ROOT_ID);
+        table
+    }
+    
+    /// Creates a table numbering each path by [`Determinism::inode`], so
+    /// the numbers do not depend on the order paths are looked up in. Two
+    /// paths with the same hash take the next free number in lookup order,
+    /// and renamed paths keep their number like in any other table.
+    fn stable() -> Self {
+        Self { stable: true, ..Self::new() }
+    }
+    
+    /// Returns the inode for `path`, allocating one if needed.
+    fn get_or_insert(&mut self, path: &ShadowPath) -> u64 {
+        if let Some(ino) = self.by_path.get(path) {
+            return *ino;
+        }
+        let ino = if self.stable {
+            let mut ino = Determinism::inode(path);
+            while self.by_ino.contains_key(&ino) {
+                ino = ino.checked_add(1).unwrap_or(FUSE_ROOT_ID + 1);
+            }
+            ino
+        } else {
+            self.next_ino += 1;
+            self.next_ino - 1
+        };
+        self.by_ino.insert(ino, path.clone());
+        self.by_path.insert(path.clone(), ino);
+        ino
+    }
+    
+    fn path(&self, ino: u64) -> Option<&ShadowPath> {
+        self.by_ino.get(&ino)
+    }
+    
+    /// Moves the inodes of `from` and every path below it to `to`. With
+    /// `exchange` the inodes at and below `to` move to `from`; otherwise
+    /// they are dropped, since the rename replaced them.
+    fn rename(&mut self, from: &ShadowPath, to: &ShadowPath, exchange: bool) {
+        let under = |path: &ShadowPath, root: &ShadowPath| path.as_path().starts_with(root.as_path());
+        let moved: Vec<(ShadowPath, u64)> = self.by_path.iter()
+            .filter(|(path, _)| under(path, from) || under(path, to))
+            .map(|(path, ino)| (path.clone(), *ino))
+            .collect();
+        
+        for (path, _) in &moved {
+            self.by_path.remove(path);
+        }
+        for (path, ino) in moved {
+            let renamed = if under(&path, from) {
+                rebase(&path, from, to)
+            } else if exchange {
+                rebase(&path, to, from)
+            } else {
+                self.by_ino.remove(&ino);
+                continue;
+            };
+            self.by_ino.insert(ino, renamed.clone());
+            self.by_path.insert(renamed, ino);
+        }
+    }
+}
+
+/// Replaces what `attr` holds that differs between machines with what
+/// `determinism` asks for. Sizes, and block counts derived from them, stay.
+fn deterministic_attr(attr: FileAttr, determinism: &Determinism) -> FileAttr {
+    let file_type = match attr.kind {
+        FileType::Directory => ShadowFileType::Directory,
+        FileType::Symlink => ShadowFileType::Symlink,
+        _ => ShadowFileType::File,
+    };
+    let permissions = Determinism::permissions(file_type, FilePermissions::from_unix_mode(attr.perm.into()));
+    let epoch = determinism.epoch();
+    FileAttr {
+        blocks: attr.size.div_ceil(512),
+        atime: epoch,
+        mtime: epoch,
+        ctime: epoch,
+        crtime: epoch,
+        perm: permissions.to_unix_mode() as u16,
+        nlink: if file_type == ShadowFileType::Directory { 2 } else { attr.nlink },
+        uid: determinism.uid,
+        gid: determinism.gid,
+        blksize: BLOCK_SIZE,
+        ..attr
+    }
+}
+
+/// Maps `path`, which is `from` or below it, to the same place under `to`.
+fn rebase(path: &ShadowPath, from: &ShadowPath, to: &ShadowPath) -> ShadowPath {
+    match path.as_path().strip_prefix(from.as_path()) {
+        Ok(rest) if !rest.as_os_str().is_empty() => to.join(rest),
+        _ => to.clone(),
+    }
+}
+
+/// A path resolved against the override store and the source directory.
+enum Node {
+    /// The path has a live override
+    Override(Arc<OverrideEntry>),
+    
+    /// The path only exists in the source directory
+    Source(std::fs::Metadata),
+    
+    /// The source file with written ranges layered over it, and its length
+    Delta(std::fs::Metadata, u64),
+    
+    /// The source file as rewritten by the store's read transforms
+    Transformed(std::fs::Metadata, Bytes),
+}
+
+impl Node {
+    /// Names where the content of the node comes from, for traces.
+    fn origin(&self) -> &'static str {
+        match self {
+            Node::Override(entry) if entry.is_directory() => "override directory",
+            Node::Override(_) => "override",
+            Node::Source(_) => "source",
+            Node::Delta(..) => "source with written ranges",
+            Node::Transformed(..) => "transformed source",
+        }
+    }
+}
+
+/// Listing of an open directory, kept between its readdir calls.
+struct DirectoryCursor {
+    /// Names in the directory when it was opened, tombstones included
+    names: BTreeSet<String>,
+    /// Offset the next readdir is expected at, and the name found there
+    resume: Option<(i64, String)>,
+}
+
+/// `fuser::Filesystem` implementation overlaying an `OverrideStore` on a source directory.
+struct ShadowFilesystem {
+    source: PathBuf,
+    store: Arc<OverrideStore>,
+    read_only: bool,
+    inodes: InodeTable,
+    uid: u32,
+    gid: u32,
+    stats: Arc<FileSystemStats>,
+    /// How long the kernel may cache attributes and entries
+    ttl: Duration,
+    /// Whether the kernel may keep the page cache of source files across opens
+    keep_source_cache: bool,
+    /// Metadata reported in place of what differs between machines
+    determinism: Option<Determinism>,
+    /// Entries left out of listings, and possibly lookups
+    hidden: HiddenFiles,
+    /// Files opened through the mount, shared with its context
+    handles: Arc<HandleTable>,
+    /// Listings of the open directories, by handle
+    directories: HashMap<u64, DirectoryCursor>,
+    /// Handle the next opened directory gets
+    next_directory_handle: u64,
+    /// Span of the mount, which each callback's span is opened under
+    span: Span,
+}
+
+impl ShadowFilesystem {
+    fn new(source: PathBuf, store: Arc<OverrideStore>, read_only: bool) -> Self {
+        // SAFETY: getuid/getgid cannot fail and have no preconditions
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Self {
+            source,
+            store,
+            read_only,
+            inodes: InodeTable::new(),
+            uid,
+            gid,
+            stats: Arc::new(FileSystemStats::new()),
+            ttl: TTL,
+            keep_source_cache: false,
+            determinism: None,
+            hidden: HiddenFiles::default(),
+            handles: Arc::new(HandleTable::new()),
+            directories: HashMap::new(),
+            next_directory_handle: 1,
+            span: Span::current(),
+        }
+    }
+    
+    /// Records served operations in `stats` instead of a private counter.
+    fn with_stats(mut self, stats: Arc<FileSystemStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+    
+    /// Lets the kernel cache attributes and entries for `ttl` and keep the
+    /// page cache of unmodified source files, for a source that will not
+    /// change. Changes made through the mount still invalidate what they
+    /// touch, since the kernel sees them.
+    fn with_immutable_source(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self.keep_source_cache = true;
+        self
+    }
+    
+    /// Numbers inodes by path and reports attributes as `determinism` asks.
+    fn with_determinism(mut self, determinism: Determinism) -> Self {
+        self.inodes = InodeTable::stable();
+        self.determinism = Some(determinism);
+        self
+    }
+    
+    /// Leaves the entries `hidden` matches out of listings, and out of
+    /// lookups if it says so.
+    fn with_hidden_files(mut self, hidden: HiddenFiles) -> Self {
+        self.hidden = hidden;
+        self
+    }
+    
+    /// Registers the files opened through the mount in `handles`.
+    fn with_handles(mut self, handles: Arc<HandleTable>) -> Self {
+        self.handles = handles;
+        self
+    }
+    
+    /// Enters the span of the callback serving `operation` on `path`.
+    fn enter(&self, operation: &'static str, path: &ShadowPath) -> EnteredSpan {
+        operation_span(&self.span, operation, path).entered()
+    }
+    
+    /// Logs `operation` on `path` if the store traces that path.
+    fn trace(&self, path: &ShadowPath, operation: std::fmt::Arguments<'_>) {
+        if self.store.is_traced(path) {
+            debug!(target: TRACE_TARGET, "{} {}", operation, path);
+        }
+    }
+    
+    /// Maps a shadow path to its location in the source directory.
+    fn source_path(&self, path: &ShadowPath) -> PathBuf {
+        mount_paths::source_path(&self.source, path)
+    }
+    
+    /// Resolves `path`, honouring tombstones in the store.
+    ///
+    /// A source file matching one of the store's rules is overridden with
+    /// the rule's content the first time it is resolved; one matching a
+    /// read transform resolves to its transformed content.
+    fn resolve(&self, path: &ShadowPath) -> Option<Node> {
+        if let Some(entry) = self.store.get(path) {
+            return if entry.is_deleted() { None } else { Some(Node::Override(entry)) };
+        }
+        match self.store.apply_rules(path, &self.source) {
+            Ok(Some(entry)) => return Some(Node::Override(entry)),
+            Ok(None) => {}
+            Err(e) => warn!("Serving {} from the source: {}", path, e),
+        }
+        let metadata = std::fs::symlink_metadata(self.source_path(path)).ok()?;
+        match self.store.transform_source(path, &self.source) {
+            Ok(Some(content)) => return Some(Node::Transformed(metadata, content)),
+            Ok(None) => {}
+            Err(e) => warn!("Serving {} untransformed: {}", path, e),
+        }
+        Some(match self.store.delta_info(path) {
+            Some(delta) => Node::Delta(metadata, delta.len),
+            None => Node::Source(metadata),
+        })
+    }
+    
+    fn attr(&self, ino: u64, node: &Node) -> FileAttr {
+        let attr = match node {
+            Node::Override(entry) => {
+                let metadata = &entry.override_metadata;
+                let size = entry.uncompressed_size();
+                FileAttr {
+                    ino,
+                    size,
+                    blocks: size.div_ceil(512),
+                    atime: entry.accessed(),
+                    mtime: metadata.modified,
+                    ctime: metadata.modified,
+                    crtime: metadata.created,
+                    kind: file_type(metadata.file_type),
+                    perm: metadata.permissions.to_unix_mode() as u16,
+                    nlink: if entry.is_directory() { 2 } else { self.store.link_count(&entry.path) as u32 },
+                    uid: self.uid,
+                    gid: self.gid,
+                    rdev: 0,
+                    blksize: BLOCK_SIZE,
+                    flags: 0,
+                }
+            }
+            Node::Source(metadata) => Self::source_attr(ino, metadata, metadata.len()),
+            Node::Delta(metadata, len) => Self::source_attr(ino, metadata, *len),
+            Node::Transformed(metadata, content) => Self::source_attr(ino, metadata, content.len() as u64),
+        };
+        match &self.determinism {
+            Some(determinism) => deterministic_attr(attr, determinism),
+            None => attr,
+        }
+    }
+    
+    /// Returns the attributes of a source file seen as `size` bytes long.
+    fn source_attr(ino: u64, metadata: &std::fs::Metadata, size: u64) -> FileAttr {
+        FileAttr {
+            ino,
+            size,
+            blocks: metadata.blocks(),
+            atime: metadata.accessed().unwrap_or(SystemTime::UNIX_EPOCH),
+            mtime: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            ctime: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            crtime: metadata.created().unwrap_or(SystemTime::UNIX_EPOCH),
+            kind: std_file_type(&metadata.file_type()),
+            perm: (metadata.permissions().mode() & 0o7777) as u16,
+            nlink: metadata.nlink() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            rdev: metadata.rdev() as u32,
+            blksize: metadata.blksize() as u32,
+            flags: 0,
+        }
+    }
+    
+    /// Resolves the path `name` would have in the directory `parent` for
+    /// creating it, failing with the errno to report.
+    fn creatable_path(&self, parent: u64, name: &OsStr) -> std::result::Result<ShadowPath, i32> {
+        if self.read_only {
+            return Err(libc::EROFS);
+        }
+        let parent_path = self.inodes.path(parent).ok_or(libc::ENOENT)?;
+        match self.resolve(parent_path) {
+            Some(node) if is_directory(&node) => {}
+            Some(_) => return Err(libc::ENOTDIR),
+            None => return Err(libc::ENOENT),
+        }
+        let path = parent_path.join(name);
+        if self.resolve(&path).is_some() {
+            return Err(libc::EEXIST);
+        }
+        Ok(path)
+    }
+    
+    /// Leaves a tombstone for `name` in the directory `parent`, which must
+    /// be a directory exactly when `directory` is set, and an empty one.
+    fn remove(&self, parent: u64, name: &OsStr, directory: bool) -> std::result::Result<(), i32> {
+        if self.read_only {
+            return Err(libc::EROFS);
+        }
+        let path = self.inodes.path(parent).ok_or(libc::ENOENT)?.join(name);
+        let _span = self.enter(if directory { "rmdir" } else { "unlink" }, &path);
+        let node = self.resolve(&path).ok_or(libc::ENOENT)?;
+        match (directory, is_directory(&node)) {
+            (true, false) => return Err(libc::ENOTDIR),
+            (false, true) => return Err(libc::EISDIR),
+            (true, true) if !self.merged_children(&path).is_empty() => return Err(libc::ENOTEMPTY),
+            _ => {}
+        }
+        self.trace(&path, format_args!("remove {}", node.origin()));
+        self.store.check_access(&path, AccessOperation::Delete)
+            .and_then(|()| self.store.mark_deleted(path.clone()))
+            .map_err(|e| {
+                debug!("Failed to remove {}: {}", path, e);
+                errno(&e)
+            })
+    }
+    
+    /// Lists `path` by merging source entries with overrides, dropping tombstones.
+    fn merged_children(&self, path: &ShadowPath) -> BTreeMap<String, FileType> {
+        let view = MergedDirectoryView::new(&self.store, &self.source, path.clone());
+        let names = view.names().unwrap_or_else(|e| {
+            debug!("Failed to list {}: {}", path, e);
+            Default::default()
+        });
+        
+        names.into_iter()
+            .filter_map(|name| child_type(&view, &name).map(|kind| (name, kind)))
+            .collect()
+    }
+}
+
+/// Lists the children of the directory `view` shows among its `names`,
+/// from `start` on, as readdir shows them: without tombstones and hidden
+/// entries. Each comes with its position among the names after `start`.
+fn listed_children<'a>(
+    view: &'a MergedDirectoryView<'a>,
+    hidden: &'a HiddenFiles,
+    names: &'a BTreeSet<String>,
+    start: Bound<&str>,
+) -> impl Iterator<Item = (usize, &'a String, FileType)> + 'a {
+    names.range::<str, _>((start, Bound::Unbounded))
+        .enumerate()
+        .filter(|(_, name)| !hidden.hides(&view.directory().join(name.as_str()), None))
+        .filter_map(|(position, name)| child_type(view, name).map(|kind| (position, name, kind)))
+}
+
+/// Returns the type of the child `name` of the directory `view` shows, or
+/// `None` if it was deleted or cannot be read.
+fn child_type(view: &MergedDirectoryView<'_>, name: &str) -> Option<FileType> {
+    match view.child(name) {
+        Ok(Some(MergedChild::Override(entry))) => Some(file_type(entry.override_metadata.file_type)),
+        Ok(Some(MergedChild::Source(metadata))) => Some(std_file_type(&metadata.file_type())),
+        _ => None,
+    }
+}
+
+impl Filesystem for ShadowFilesystem {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let _timer = self.stats.start_operation(OperationType::Stat);
+        let Some(parent_path) = self.inodes.path(parent).cloned() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let path = parent_path.join(name);
+        let _span = self.enter("lookup", &path);
+        if self.hidden.hides_lookup(&path, None) {
+            self.trace(&path, format_args!("lookup of hidden entry"));
+            reply.error(libc::ENOENT);
+            return;
+        }
+        match self.resolve(&path) {
+            Some(node) => {
+                self.trace(&path, format_args!("lookup found {}", node.origin()));
+                let ino = self.inodes.get_or_insert(&path);
+                reply.entry(&self.ttl, &self.attr(ino, &node), 0);
+            }
+            None => {
+                self.trace(&path, format_args!("lookup found nothing"));
+                reply.error(libc::ENOENT);
+            }
+        }
+    }
+    
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        let _timer = self.stats.start_operation(OperationType::Stat);
+        let Some(path) = self.inodes.path(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let _span = self.enter("getattr", path);
+        match self.resolve(path) {
+            Some(node) => {
+                self.trace(path, format_args!("getattr of {}", node.origin()));
+                reply.attr(&self.ttl, &self.attr(ino, &node));
+            }
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        let _timer = self.stats.start_operation(OperationType::Open);
+        let Some(path) = self.inodes.path(ino).cloned() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let _span = self.enter("open", &path);
+        let Some(node) = self.resolve(&path) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let access_mode = flags & libc::O_ACCMODE;
+        self.trace(&path, format_args!("open {} with flags {:#o}", node.origin(), flags));
+        if self.read_only && access_mode != libc::O_RDONLY {
+            reply.error(libc::EROFS);
+            return;
+        }
+        
+        let mut operations = Vec::new();
+        if access_mode != libc::O_WRONLY {
+            operations.push(AccessOperation::Read);
+        }
+        if access_mode != libc::O_RDONLY {
+            operations.push(AccessOperation::Write);
+        }
+        for operation in operations {
+            if let Err(e) = self.store.check_access(&path, operation) {
+                reply.error(errno(&e));
+                return;
+            }
+        }
+        // Unmodified files of an immutable source read the same every time
+        let keep_cache = self.keep_source_cache && access_mode == libc::O_RDONLY && matches!(node, Node::Source(_));
+        let fh = self.handles.open(path, OpenFlags::from_posix(flags));
+        reply.opened(fh.id(), if keep_cache { FOPEN_KEEP_CACHE } else { 0 });
+    }
+    
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let _timer = self.stats.start_operation(OperationType::Read);
+        let Some(path) = self.inodes.path(ino).cloned() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let _span = self.enter("read", &path);
+        let offset = offset.max(0) as u64;
+        let node = self.resolve(&path);
+        if let Some(node) = &node {
+            self.trace(&path, format_args!("read {} bytes at {} from {}", size, offset, node.origin()));
+        }
+        
+        match node {
+            Some(Node::Override(entry)) => match entry.read_file_range(offset, size as usize) {
+                Ok(Some(data)) => {
+                    self.stats.add_bytes_read(data.len() as u64);
+                    reply.data(&data);
+                }
+                Ok(None) => reply.error(libc::EISDIR),
+                Err(e) => {
+                    warn!("Failed to read override {}: {}", path, e);
+                    reply.error(libc::EIO);
+                }
+            },
+            Some(Node::Delta(..)) => match self.store.read_range(&path, offset, size as usize) {
+                Ok(data) => {
+                    self.stats.add_bytes_read(data.len() as u64);
+                    reply.data(&data);
+                }
+                Err(e) => {
+                    warn!("Failed to read {}: {}", path, e);
+                    reply.error(errno(&e));
+                }
+            },
+            Some(Node::Transformed(_, content)) => {
+                let start = (offset as usize).min(content.len());
+                let end = (start + size as usize).min(content.len());
+                self.stats.add_bytes_read((end - start) as u64);
+                reply.data(&content[start..end]);
+            }
+            Some(Node::Source(_)) => match self.store.read_source(&self.source_path(&path), offset, size as usize) {
+                Ok(data) => {
+                    self.stats.add_bytes_read(data.len() as u64);
+                    reply.data(&data);
+                }
+                Err(e) => reply.error(e.raw_os_error().unwrap_or(libc::EIO)),
+            },
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let _timer = self.stats.start_operation(OperationType::Write);
+        if self.read_only {
+            reply.error(libc::EROFS);
+            return;
+        }
+        let Some(path) = self.inodes.path(ino).cloned() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let _span = self.enter("write", &path);
+        let Some(node) = self.resolve(&path) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        
+        // Writes to source files keep only the written ranges; the store
+        // compacts them into a full copy once they cover enough of the file
+        let original_metadata = match &node {
+            Node::Source(metadata) | Node::Delta(metadata, _) => Some(metadata_from_std(metadata)),
+            Node::Transformed(metadata, content) => {
+                // The writer saw the transformed content, so it becomes the override
+                let original = metadata_from_std(metadata);
+                if let Err(e) = self.store.insert_file(path.clone(), content.clone(), Some(original.clone())) {
+                    warn!("Failed to store override for {}: {}", path, e);
+                    reply.error(errno(&e));
+                    return;
+                }
+                Some(original)
+            }
+            Node::Override(entry) => entry.original_metadata.clone(),
+        };
+        // Appends go to the end of the file as the store has it
+        let offset = match self.handles.get(FileHandle::new(fh)) {
+            Some(file) => file.write_offset(offset.max(0) as u64, self.attr(ino, &node).size),
+            None => offset.max(0) as u64,
+        };
+        self.trace(&path, format_args!("write {} bytes at {} over {}", data.len(), offset, node.origin()));
+        
+        match self.store.write_range(path.clone(), self.source_path(&path), offset, data, original_metadata) {
+            Ok(()) => {
+                self.stats.add_bytes_written(data.len() as u64);
+                reply.written(data.len() as u32);
+            }
+            Err(e) => {
+                warn!("Failed to store override for {}: {}", path, e);
+                reply.error(errno(&e));
+            }
+        }
+    }
+    
+    fn mkdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, _mode: u32, _umask: u32, reply: ReplyEntry) {
+        let _timer = self.stats.start_operation(OperationType::Create);
+        let _span = self.inodes.path(parent).map(|parent| self.enter("mkdir", &parent.join(name)));
+        let path = match self.creatable_path(parent, name) {
+            Ok(path) => path,
+            Err(code) => {
+                reply.error(code);
+                return;
+            }
+        };
+        self.trace(&path, format_args!("mkdir"));
+        if let Err(e) = self.store.insert_directory(path.clone(), None) {
+            debug!("Failed to create directory {}: {}", path, e);
+            reply.error(errno(&e));
+            return;
+        }
+        match self.resolve(&path) {
+            Some(node) => {
+                let ino = self.inodes.get_or_insert(&path);
+                reply.entry(&self.ttl, &self.attr(ino, &node), 0);
+            }
+            None => reply.error(libc::EIO),
+        }
+    }
+    
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let _timer = self.stats.start_operation(OperationType::Create);
+        let _span = self.inodes.path(parent).map(|parent| self.enter("create", &parent.join(name)));
+        let path = match self.creatable_path(parent, name) {
+            Ok(path) => path,
+            Err(code) => {
+                reply.error(code);
+                return;
+            }
+        };
+        self.trace(&path, format_args!("create"));
+        if let Err(e) = self.store.insert_file(path.clone(), Bytes::new(), None) {
+            debug!("Failed to create {}: {}", path, e);
+            reply.error(errno(&e));
+            return;
+        }
+        match self.resolve(&path) {
+            Some(node) => {
+                let ino = self.inodes.get_or_insert(&path);
+                let fh = self.handles.open(path, OpenFlags::from_posix(flags));
+                reply.created(&self.ttl, &self.attr(ino, &node), 0, fh.id(), 0);
+            }
+            None => reply.error(libc::EIO),
+        }
+    }
+    
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let _timer = self.stats.start_operation(OperationType::Delete);
+        match self.remove(parent, name, false) {
+            Ok(()) => reply.ok(),
+            Err(code) => reply.error(code),
+        }
+    }
+    
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let _timer = self.stats.start_operation(OperationType::Delete);
+        match self.remove(parent, name, true) {
+            Ok(()) => reply.ok(),
+            Err(code) => reply.error(code),
+        }
+    }
+    
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let _timer = self.stats.start_operation(OperationType::Rename);
+        if self.read_only {
+            reply.error(libc::EROFS);
+            return;
+        }
+        let (Some(parent_path), Some(new_parent_path)) = (self.inodes.path(parent), self.inodes.path(newparent)) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let from = parent_path.join(name);
+        let to = new_parent_path.join(newname);
+        let _span = self.enter("rename", &from);
+        
+        let collision = if flags & libc::RENAME_EXCHANGE != 0 {
+            RenameCollision::Exchange
+        } else if flags & libc::RENAME_NOREPLACE != 0 {
+            RenameCollision::NoReplace
+        } else {
+            RenameCollision::Overwrite
+        };
+        let options = RenameOptions {
+            collision,
+            ..RenameOptions::with_source(&self.source)
+        };
+        if self.store.is_traced(&from) || self.store.is_traced(&to) {
+            debug!(target: TRACE_TARGET, "rename {} to {} ({:?})", from, to, collision);
+        }
+        
+        match self.store.rename(&from, to.clone(), options) {
+            Ok(()) => {
+                self.inodes.rename(&from, &to, collision == RenameCollision::Exchange);
+                reply.ok();
+            }
+            Err(e) => {
+                debug!("Failed to rename {} to {}: {}", from, to, e);
+                reply.error(errno(&e));
+            }
+        }
+    }
+    
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.handles.close(FileHandle::new(fh));
+        reply.ok();
+    }
+    
+    /// Reads the names of the directory once, for its readdir calls to
+    /// page through.
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        let Some(path) = self.inodes.path(ino).cloned() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let _span = self.enter("opendir", &path);
+        if let Err(e) = self.store.check_access(&path, AccessOperation::Read) {
+            reply.error(errno(&e));
+            return;
+        }
+        match MergedDirectoryView::new(&self.store, &self.source, path.clone()).names() {
+            Ok(names) => {
+                self.trace(&path, format_args!("opendir of {} names", names.len()));
+                let fh = self.next_directory_handle;
+                self.next_directory_handle += 1;
+                self.directories.insert(fh, DirectoryCursor { names, resume: None });
+                reply.opened(fh, 0);
+            }
+            Err(e) => {
+                debug!("Failed to list {}: {}", path, e);
+                reply.error(errno(&e));
+            }
+        }
+    }
+    
+    fn releasedir(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _flags: i32, reply: ReplyEmpty) {
+        self.directories.remove(&fh);
+        reply.ok();
+    }
+    
+    /// Fills the reply from the names read by opendir, stat'ing only the
+    /// entries that fit. Offsets 1 and 2 follow "." and ".."; offset `n`
+    /// above them follows the `n - 2`th name, tombstones and hidden entries
+    /// included, so a readdir continuing where the last one stopped resumes
+    /// from the name recorded there instead of counting.
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let _timer = self.stats.start_operation(OperationType::ReadDir);
+        let Some(path) = self.inodes.path(ino).cloned() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let _span = self.enter("readdir", &path);
+        let Some(mut cursor) = self.directories.remove(&fh) else {
+            reply.error(libc::EBADF);
+            return;
+        };
+        self.trace(&path, format_args!("readdir at {} of {} names", offset, cursor.names.len()));
+        let parent_ino = path.parent()
+            .map(|parent| self.inodes.get_or_insert(&parent))
+            .unwrap_or(FUSE_ROOT_ID);
+        
+        // The offset passed back with an entry is the offset of the next one
+        let mut offset = offset.max(0);
+        if offset == 0 && !reply.add(ino, 1, FileType::Directory, ".") {
+            offset = 1;
+        }
+        if offset == 1 && !reply.add(parent_ino, 2, FileType::Directory, "..") {
+            offset = 2;
+        }
+        if offset >= 2 {
+            let start = match cursor.resume.take() {
+                Some((at, name)) if at == offset => Some(name),
+                _ => cursor.names.iter().nth((offset - 2) as usize).cloned(),
+            };
+            if let Some(start) = start {
+                let view = MergedDirectoryView::new(&self.store, &self.source, path.clone());
+                for (position, name, kind) in listed_children(&view, &self.hidden, &cursor.names, Bound::Included(start.as_str())) {
+                    let at = offset + position as i64;
+                    let child_ino = self.inodes.get_or_insert(&path.join(name));
+                    if reply.add(child_ino, at + 1, kind, name) {
+                        cursor.resume = Some((at, name.clone()));
+                        break;
+                    }
+                }
+            }
+        }
+        self.directories.insert(fh, cursor);
+        reply.ok();
+    }
+    
+    /// Reports the capacity of the filesystem holding the source, since
+    /// overrides are eventually committed there.
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        match source_statvfs(&self.source) {
+            Ok(stat) => reply.statfs(
+                stat.f_blocks as u64,
+                stat.f_bfree as u64,
+                stat.f_bavail as u64,
+                stat.f_files as u64,
+                stat.f_ffree as u64,
+                stat.f_bsize as u32,
+                stat.f_namemax as u32,
+                stat.f_frsize as u32,
+            ),
+            Err(e) => {
+                debug!("Failed to query the filesystem of {}: {}", self.source.display(), e);
+                reply.statfs(0, 0, 0, 0, 0, BLOCK_SIZE, MAX_NAME_LENGTH, BLOCK_SIZE);
+            }
+        }
+    }
+}
+
+/// Queries the filesystem holding `source`.
+fn source_statvfs(source: &Path) -> std::io::Result<libc::statvfs> {
+    let path = std::ffi::CString::new(source.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is valid for writes
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded, so it filled `stat`
+    Ok(unsafe { stat.assume_init() })
+}
+
+/// Returns true if `node` is a directory.
+fn is_directory(node: &Node) -> bool {
+    match node {
+        Node::Override(entry) => entry.is_directory(),
+        Node::Source(metadata) | Node::Delta(metadata, _) | Node::Transformed(metadata, _) => metadata.is_dir(),
+    }
+}
+
+/// Maps a store error to the errno reported to the kernel.
+fn errno(error: &ShadowError) -> i32 {
+    match error {
+        ShadowError::AccessDenied { .. } => libc::EACCES,
+        ShadowError::ReadOnlyFilesystem { .. } => libc::EROFS,
+        ShadowError::NotFound { .. } => libc::ENOENT,
+        ShadowError::AlreadyExists { .. } => libc::EEXIST,
+        ShadowError::NotADirectory { .. } => libc::ENOTDIR,
+        ShadowError::IsADirectory { .. } => libc::EISDIR,
+        ShadowError::DirectoryNotEmpty { .. } => libc::ENOTEMPTY,
+        ShadowError::InvalidPath { .. } => libc::EINVAL,
+        ShadowError::QuotaExceeded { .. } => libc::EDQUOT,
+        ShadowError::LimitExceeded { limit: LimitKind::NameLength | LimitKind::PathDepth, .. } => libc::ENAMETOOLONG,
+        _ => libc::ENOSPC,
+    }
+}
+
+fn file_type(file_type: ShadowFileType) -> FileType {
+    match file_type {
+        ShadowFileType::File => FileType::RegularFile,
+        ShadowFileType::Directory => FileType::Directory,
+        ShadowFileType::Symlink => FileType::Symlink,
+    }
+}
+
+fn std_file_type(file_type: &std::fs::FileType) -> FileType {
+    if file_type.is_dir() {
+        FileType::Directory
+    } else if file_type.is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::RegularFile
+    }
+}
+
+fn metadata_from_std(metadata: &std::fs::Metadata) -> FileMetadata {
+    let file_type = if metadata.is_dir() {
+        ShadowFileType::Directory
+    } else if metadata.file_type().is_symlink() {
+        ShadowFileType::Symlink
+    } else {
+        ShadowFileType::File
+    };
+    FileMetadata::new(
+        metadata.len(),
+        metadata.created().unwrap_or(SystemTime::UNIX_EPOCH),
+        metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        metadata.accessed().unwrap_or(SystemTime::UNIX_EPOCH),
+        FilePermissions::from_unix_mode(metadata.permissions().mode()),
+        file_type,
+        PlatformMetadata::default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shadowfs_core::hidden::HiddenLookups;
+    use shadowfs_core::override_store::{transforms, ChainTransformer, OverrideRule, ReadTransforms, TransformChain};
+    
+    #[test]
+    fn test_inode_table_root_and_allocation() {
+        let mut table = InodeTable::new();
+        assert_eq!(table.path(FUSE_ROOT_ID), Some(&ShadowPath::from("/")));
+        
+        let path = ShadowPath::from("/a/b.txt");
+        let ino = table.get_or_insert(&path);
+        assert_ne!(ino, FUSE_ROOT_ID);
+        assert_eq!(table.get_or_insert(&path), ino);
+        assert_eq!(table.path(ino), Some(&path));
+    }
+    
+    #[test]
+    fn test_inode_table_rename() {
+        let mut table = InodeTable::new();
+        let dir = table.get_or_insert(&ShadowPath::from("/a"));
+        let child = table.get_or_insert(&ShadowPath::from("/a/b.txt"));
+        let sibling = table.get_or_insert(&ShadowPath::from("/ab"));
+        let replaced = table.get_or_insert(&ShadowPath::from("/c"));
+        
+        table.rename(&ShadowPath::from("/a"), &ShadowPath::from("/c"), false);
+        assert_eq!(table.path(dir), Some(&ShadowPath::from("/c")));
+        assert_eq!(table.path(child), Some(&ShadowPath::from("/c/b.txt")));
+        assert_eq!(table.path(sibling), Some(&ShadowPath::from("/ab")));
+        assert_eq!(table.path(replaced), None);
+        
+        table.rename(&ShadowPath::from("/c"), &ShadowPath::from("/ab"), true);
+        assert_eq!(table.path(dir), Some(&ShadowPath::from("/ab")));
+        assert_eq!(table.path(sibling), Some(&ShadowPath::from("/c")));
+    }
+    
+    #[test]
+    fn test_deterministic_attributes() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("build.sh"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(source.path().join("build.sh"), std::fs::Permissions::from_mode(0o700)).unwrap();
+        let determinism = Determinism::new(1_700_000_000);
+        let mut fs = ShadowFilesystem::new(source.path().to_path_buf(), Arc::new(OverrideStore::with_defaults()), false)
+            .with_determinism(determinism);
+        
+        // Inode numbers follow the path, whatever was looked up before
+        let path = ShadowPath::from("/build.sh");
+        fs.inodes.get_or_insert(&ShadowPath::from("/other"));
+        let ino = fs.inodes.get_or_insert(&path);
+        assert_eq!(ino, Determinism::inode(&path));
+        
+        let attr = fs.attr(ino, &fs.resolve(&path).unwrap());
+        assert_eq!((attr.mtime, attr.atime, attr.ctime), (determinism.epoch(), determinism.epoch(), determinism.epoch()));
+        assert_eq!((attr.uid, attr.gid, attr.perm), (0, 0, 0o755));
+        assert_eq!(attr.size, 10);
+    }
+    
+    #[test]
+    fn test_source_path_mapping() {
+        let fs = ShadowFilesystem::new(PathBuf::from("/src"), Arc::new(OverrideStore::with_defaults()), false);
+        assert_eq!(fs.source_path(&ShadowPath::from("/a/b")), PathBuf::from("/src/a/b"));
+        assert_eq!(fs.source_path(&ShadowPath::from("/")), PathBuf::from("/src"));
+    }
+    
+    #[test]
+    fn test_tombstones_hide_paths() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        store.insert_file(ShadowPath::from("/new.txt"), Bytes::from("hi"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/gone.txt")).unwrap();
+        
+        let fs = ShadowFilesystem::new(PathBuf::from("/nonexistent-source"), store, false);
+        assert!(matches!(fs.resolve(&ShadowPath::from("/new.txt")), Some(Node::Override(_))));
+        assert!(fs.resolve(&ShadowPath::from("/gone.txt")).is_none());
+        
+        let children = fs.merged_children(&ShadowPath::from("/"));
+        assert!(children.contains_key("new.txt"));
+        assert!(!children.contains_key("gone.txt"));
+    }
+    
+    #[test]
+    fn test_hidden_files_leave_readdir() {
+        let store = Arc::new(OverrideStore::with_defaults());
+        store.insert_file(ShadowPath::from("/.env"), Bytes::from("KEY=1"), None).unwrap();
+        store.insert_file(ShadowPath::from("/main.rs"), Bytes::from("fn main() {}"), None).unwrap();
+        
+        let fs = ShadowFilesystem::new(PathBuf::from("/nonexistent-source"), store, false)
+            .with_hidden_files(HiddenFiles::dotfiles().with_lookups(HiddenLookups::NotFound));
+        let root = ShadowPath::from("/");
+        let view = MergedDirectoryView::new(&fs.store, &fs.source, root.clone());
+        let names = view.names().unwrap();
+        let listed: Vec<_> = listed_children(&view, &fs.hidden, &names, Bound::Unbounded).map(|(_, name, _)| name.as_str()).collect();
+        assert_eq!(listed, ["main.rs"]);
+        assert!(fs.hidden.hides_lookup(&ShadowPath::from("/.env"), None));
+        
+        // Hidden entries still keep their directory from being removed
+        assert_eq!(fs.merged_children(&root).len(), 2);
+    }
+    
+    #[test]
+    fn test_create_and_remove_leave_source_untouched() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("docs")).unwrap();
+        std::fs::write(source.path().join("docs/a.txt"), "a").unwrap();
+        let store = Arc::new(OverrideStore::with_defaults());
+        let mut fs = ShadowFilesystem::new(source.path().to_path_buf(), Arc::clone(&store), false);
+        let docs = fs.inodes.get_or_insert(&ShadowPath::from("/docs"));
+        
+        assert_eq!(fs.creatable_path(docs, OsStr::new("a.txt")), Err(libc::EEXIST));
+        let path = fs.creatable_path(docs, OsStr::new("b")).unwrap();
+        store.insert_directory(path, None).unwrap();
+        let a = fs.inodes.get_or_insert(&ShadowPath::from("/docs/a.txt"));
+        assert_eq!(fs.creatable_path(a, OsStr::new("c")), Err(libc::ENOTDIR));
+        
+        assert_eq!(fs.remove(FUSE_ROOT_ID, OsStr::new("docs"), true), Err(libc::ENOTEMPTY));
+        assert_eq!(fs.remove(docs, OsStr::new("a.txt"), true), Err(libc::ENOTDIR));
+        assert_eq!(fs.remove(docs, OsStr::new("b"), false), Err(libc::EISDIR));
+        fs.remove(docs, OsStr::new("a.txt"), false).unwrap();
+        fs.remove(docs, OsStr::new("b"), true).unwrap();
+        fs.remove(FUSE_ROOT_ID, OsStr::new("docs"), true).unwrap();
+        assert!(fs.resolve(&ShadowPath::from("/docs")).is_none());
+        assert!(source.path().join("docs/a.txt").exists());
+        
+        fs.read_only = true;
+        assert_eq!(fs.creatable_path(FUSE_ROOT_ID, OsStr::new("new")), Err(libc::EROFS));
+    }
+    
+    #[test]
+    fn test_fuse_mount_options() {
+        assert!(FuseMountOptions::default().mount_options().is_empty());
+        let options = FuseMountOptions { allow_other: true, auto_unmount: true, max_read: Some(131072) };
+        assert_eq!(options.mount_options(), vec![
+            MountOption::AllowOther,
+            MountOption::AutoUnmount,
+            MountOption::CUSTOM("max_read=131072".to_string()),
+        ]);
+    }
+    
+    #[test]
+    fn test_written_ranges_resolve_as_delta() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("big.bin"), vec![0u8; 4096]).unwrap();
+        let store = Arc::new(OverrideStore::with_defaults());
+        let fs = ShadowFilesystem::new(source.path().to_path_buf(), Arc::clone(&store), false);
+        
+        let path = ShadowPath::from("/big.bin");
+        store.write_range(path.clone(), fs.source_path(&path), 4096, b"tail", None).unwrap();
+        match fs.resolve(&path) {
+            Some(node @ Node::Delta(..)) => assert_eq!(fs.attr(2, &node).size, 4100),
+            _ => panic!("expected a delta"),
+        }
+    }
+    
+    #[test]
+    fn test_read_transforms_resize_source_files() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("app.env"), "TOKEN=abc123\n").unwrap();
+        let read_transforms = Arc::new(ReadTransforms::new());
+        let redact = transforms::sed("s/=.*/=/").unwrap();
+        read_transforms.register(
+            OverrideRule::Glob("*.env".to_string()),
+            Arc::new(ChainTransformer::new("redact", TransformChain::new().add_transform(redact))),
+        );
+        let store = Arc::new(OverrideStore::with_defaults());
+        store.set_read_transforms(read_transforms);
+        let fs = ShadowFilesystem::new(source.path().to_path_buf(), store, false);
+        
+        match fs.resolve(&ShadowPath::from("/app.env")) {
+            Some(node @ Node::Transformed(..)) => assert_eq!(fs.attr(2, &node).size, 7),
+            _ => panic!("expected transformed content"),
+        }
+    }
+}
@@ -1 +1,5 @@
-pub mod fuse;
\ No newline at end of file
+pub mod fuse;
+pub mod source_watch;
+
+pub use fuse::{FuseMountOptions, FuseProvider};
+pub use source_watch::{LinuxSourceWatcher, LinuxWatchConfig, WatchBackend, WatchHealth};
@@ -0,0 +1,1206 @@
+//! Source tree watching with inotify, or fanotify for mount-wide monitoring.
+//!
+//! inotify watches single directories, so the inotify backend adds a watch
+//! for every directory of the source tree and follows directories as they
+//! are created, moved and removed. Each watch counts against the per-user
+//! `fs.inotify.max_user_watches` limit. When the limit is reached the watcher
+//! keeps the watches it already has, reports itself as degraded through
+//! [`LinuxSourceWatcher::health`] and asks consumers to rescan the whole tree
+//! every [`LinuxWatchConfig::rescan_interval`] instead. A queue overflow
+//! likewise becomes a rescan of the whole tree, after which the watches are
+//! rebuilt.
+//!
+//! The fanotify backend replaces the per-directory watches with a single
+//! mark on the filesystem holding the source root, so it is not bound by the
+//! watch limit. It needs `CAP_SYS_ADMIN` and Linux 5.9 or later; without
+//! them the watcher falls back to inotify. Directories of other filesystems
+//! mounted below the source root are not covered by the mark.
+//!
+//! With a supervisor attached, a watcher whose event source fails is
+//! reopened on the same backend with the supervisor's backoff, and its
+//! restarts are counted under [`Component::Watcher`].
+
+use shadowfs_core::error::{platform_error, Platform as ErrorPlatform, ShadowError};
+use shadowfs_core::source_watch::{source_path, SourceChange, SourceChangeSink, SourceWatcher};
+use shadowfs_core::supervision::{Component, Subsystem, Supervisor};
+use shadowfs_core::types::ShadowPath;
+use shadowfs_core::watch::ChangeKind;
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr, OsString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Default interval of full rescans while the watcher is degraded.
+pub const DEFAULT_RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Size of the buffer events are read into.
+const EVENT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Size of `struct inotify_event` without the name.
+const INOTIFY_EVENT_HEADER: usize = 16;
+
+/// Size of `struct fanotify_event_metadata`.
+const FANOTIFY_METADATA_SIZE: usize = 24;
+
+/// Events requested for every watched directory.
+const INOTIFY_MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR
+    | libc::IN_DONT_FOLLOW
+    | libc::IN_EXCL_UNLINK;
+
+/// Events requested for the fanotify filesystem mark.
+const FANOTIFY_MASK: u64 = libc::FAN_CREATE
+    | libc::FAN_DELETE
+    | libc::FAN_MODIFY
+    | libc::FAN_ATTRIB
+    | libc::FAN_MOVED_FROM
+    | libc::FAN_MOVED_TO
+    | libc::FAN_ONDIR;
+
+/// Native API used to watch the source tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WatchBackend {
+    /// One inotify watch per directory
+    #[default]
+    Inotify,
+    
+    /// One fanotify mark on the whole filesystem; needs `CAP_SYS_ADMIN`
+    Fanotify,
+}
+
+/// Configuration of a [`LinuxSourceWatcher`].
+#[derive(Debug, Clone)]
+pub struct LinuxWatchConfig {
+    /// Backend to try first
+    pub backend: WatchBackend,
+    
+    /// Interval of full rescans while the watcher is degraded
+    pub rescan_interval: Duration,
+}
+
+impl Default for LinuxWatchConfig {
+    fn default() -> Self {
+        Self {
+            backend: WatchBackend::default(),
+            rescan_interval: DEFAULT_RESCAN_INTERVAL,
+        }
+    }
+}
+
+/// Whether the watcher sees every change of the source tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchHealth {
+    /// Every directory of the source tree is watched
+    Healthy,
+    
+    /// Part of the tree is not watched and changes there are only picked up
+    /// by the periodic rescans
+    Degraded {
+        /// Why the watcher is degraded
+        reason: String,
+    },
+}
+
+impl WatchHealth {
+    /// Returns true if every directory of the source tree is watched.
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, WatchHealth::Healthy)
+    }
+}
+
+/// One event as read from an inotify file descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEvent {
+    /// Watch descriptor of the directory the event happened in
+    pub wd: i32,
+    
+    /// `IN_*` event flags
+    pub mask: u32,
+    
+    /// Cookie pairing the two halves of a rename
+    pub cookie: u32,
+    
+    /// Name of the entry within the directory, if the event is about one
+    pub name: Option<OsString>,
+}
+
+/// Parses the `inotify_event` records in `buffer`.
+///
+/// A truncated trailing record is ignored.
+pub fn parse_event_buffer(buffer: &[u8]) -> Vec<RawEvent> {
+    let mut events = Vec::new();
+    let mut offset = 0;
+    
+    while offset + INOTIFY_EVENT_HEADER <= buffer.len() {
+        let field = |at: usize| u32::from_ne_bytes(buffer[offset + at..offset + at + 4].try_into().unwrap());
+        let name_len = field(12) as usize;
+        let end = offset + INOTIFY_EVENT_HEADER + name_len;
+        if end > buffer.len() {
+            break;
+        }
+        
+        // The name is padded with NULs to an aligned length
+        let name = &buffer[offset + INOTIFY_EVENT_HEADER..end];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+        events.push(RawEvent {
+            wd: field(0) as i32,
+            mask: field(4),
+            cookie: field(8),
+            name: (!name.is_empty()).then(|| OsString::from_vec(name.to_vec())),
+        });
+        offset = end;
+    }
+    events
+}
+
+/// Mapping between inotify watch descriptors and directories relative to
+/// the source root.
+#[derive(Debug, Default)]
+pub struct WatchTable {
+    by_wd: HashMap<i32, PathBuf>,
+    by_path: HashMap<PathBuf, i32>,
+}
+
+impl WatchTable {
+    /// Records that `wd` watches the directory `relative`.
+    pub fn insert(&mut self, wd: i32, relative: PathBuf) {
+        if let Some(old) = self.by_wd.insert(wd, relative.clone()) {
+            self.by_path.remove(&old);
+        }
+        if let Some(old) = self.by_path.insert(relative, wd) {
+            if old != wd {
+                self.by_wd.remove(&old);
+            }
+        }
+    }
+    
+    /// Returns the directory watched by `wd`.
+    pub fn path(&self, wd: i32) -> Option<&Path> {
+        self.by_wd.get(&wd).map(PathBuf::as_path)
+    }
+    
+    /// Forgets the watch `wd`, returning the directory it watched.
+    pub fn remove(&mut self, wd: i32) -> Option<PathBuf> {
+        let path = self.by_wd.remove(&wd)?;
+        self.by_path.remove(&path);
+        Some(path)
+    }
+    
+    /// Forgets the watches of `relative` and every directory below it,
+    /// returning their descriptors.
+    pub fn remove_subtree(&mut self, relative: &Path) -> Vec<i32> {
+        let wds: Vec<i32> = self.by_path.iter()
+            .filter(|(path, _)| path.starts_with(relative))
+            .map(|(_, &wd)| wd)
+            .collect();
+        for &wd in &wds {
+            self.remove(wd);
+        }
+        wds
+    }
+    
+    /// Moves the watches of `from` and every directory below it to `to`.
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        let moved: Vec<(PathBuf, i32)> = self.by_path.iter()
+            .filter(|(path, _)| path.starts_with(from))
+            .map(|(path, &wd)| (path.clone(), wd))
+            .collect();
+        for (path, wd) in moved {
+            self.by_path.remove(&path);
+            let rest = path.strip_prefix(from).unwrap();
+            let renamed = if rest.as_os_str().is_empty() { to.to_path_buf() } else { to.join(rest) };
+            self.by_wd.insert(wd, renamed.clone());
+            self.by_path.insert(renamed, wd);
+        }
+    }
+    
+    /// Number of watched directories.
+    pub fn len(&self) -> usize {
+        self.by_wd.len()
+    }
+    
+    /// Returns true if no directory is watched.
+    pub fn is_empty(&self) -> bool {
+        self.by_wd.is_empty()
+    }
+    
+    /// Forgets every watch.
+    pub fn clear(&mut self) {
+        self.by_wd.clear();
+        self.by_path.clear();
+    }
+}
+
+/// Result of translating a batch of inotify events.
+#[derive(Debug, Default)]
+pub struct Translation {
+    /// Changes to report, in order
+    pub changes: Vec<SourceChange>,
+    
+    /// Directories, relative to the source root, whose subtrees need watches
+    pub watch: Vec<PathBuf>,
+    
+    /// Watches to remove because their directories left the source tree
+    pub unwatch: Vec<i32>,
+    
+    /// Whether events were lost and every watch has to be rebuilt
+    pub resync: bool,
+}
+
+impl Translation {
+    fn push(&mut self, change: SourceChange) {
+        // Writes arrive as a burst of IN_MODIFY events for the same file
+        if self.changes.last() != Some(&change) {
+            self.changes.push(change);
+        }
+    }
+    
+    /// Reports an entry that was moved out of the source tree.
+    fn moved_out(&mut self, table: &mut WatchTable, relative: &Path, is_directory: bool) {
+        if is_directory {
+            self.unwatch.extend(table.remove_subtree(relative));
+        }
+        self.push(SourceChange::from_relative(relative, ChangeKind::Deleted, is_directory));
+    }
+    
+    /// Reports a new entry whose contents, if it is a directory, were not
+    /// watched yet.
+    fn created(&mut self, relative: PathBuf, is_directory: bool) {
+        let mut change = SourceChange::from_relative(&relative, ChangeKind::Created, is_directory);
+        if is_directory {
+            change.recursive = true;
+            self.watch.push(relative);
+        }
+        self.push(change);
+    }
+}
+
+/// Converts a batch of inotify events into source changes.
+///
+/// `table` is updated for directories renamed within the tree and for
+/// removed watches; the returned [`Translation`] lists the watches the
+/// caller has to add or remove.
+pub fn translate_events(table: &mut WatchTable, events: &[RawEvent]) -> Translation {
+    let mut translation = Translation::default();
+    // The old name of a rename waiting for its new name
+    let mut pending_from: Option<(u32, PathBuf, bool)> = None;
+    
+    for event in events {
+        // A rename is reported as adjacent events; anything else in between
+        // means the old name left the source tree
+        if event.mask & libc::IN_MOVED_TO == 0 {
+            if let Some((_, from, is_directory)) = pending_from.take() {
+                translation.moved_out(table, &from, is_directory);
+            }
+        }
+        
+        if event.mask & libc::IN_Q_OVERFLOW != 0 {
+            translation.push(SourceChange::rescan(ShadowPath::from("/")));
+            translation.resync = true;
+            continue;
+        }
+        if event.mask & libc::IN_IGNORED != 0 {
+            table.remove(event.wd);
+            continue;
+        }
+        // Events still queued for a watch that was already removed
+        let Some(directory) = table.path(event.wd).map(Path::to_path_buf) else {
+            continue;
+        };
+        let is_directory = event.mask & libc::IN_ISDIR != 0;
+        
+        if event.mask & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_UNMOUNT) != 0 {
+            // Other directories are reported by their parent; the root has none
+            if directory.as_os_str().is_empty() || event.mask & libc::IN_UNMOUNT != 0 {
+                translation.push(SourceChange::rescan(source_path(&directory)));
+            }
+            continue;
+        }
+        let Some(name) = &event.name else {
+            continue;
+        };
+        let relative = directory.join(name);
+        
+        if event.mask & libc::IN_MOVED_FROM != 0 {
+            pending_from = Some((event.cookie, relative, is_directory));
+            continue;
+        }
+        if event.mask & libc::IN_MOVED_TO != 0 {
+            match pending_from.take() {
+                Some((cookie, from, _)) if cookie == event.cookie => {
+                    if is_directory {
+                        table.rename(&from, &relative);
+                    }
+                    translation.push(SourceChange::from_relative(
+                        &relative,
+                        ChangeKind::Renamed { from: source_path(&from) },
+                        is_directory,
+                    ));
+                }
+                unmatched => {
+                    if let Some((_, from, from_is_directory)) = unmatched {
+                        translation.moved_out(table, &from, from_is_directory);
+                    }
+                    // Moved in from outside the source tree
+                    translation.created(relative, is_directory);
+                }
+            }
+            continue;
+        }
+        
+        if event.mask & libc::IN_CREATE != 0 {
+            translation.created(relative, is_directory);
+        } else if event.mask & libc::IN_DELETE != 0 {
+            translation.push(SourceChange::from_relative(&relative, ChangeKind::Deleted, is_directory));
+        } else if event.mask & (libc::IN_MODIFY | libc::IN_ATTRIB) != 0 {
+            translation.push(SourceChange::from_relative(&relative, ChangeKind::Modified, is_directory));
+        }
+    }
+    
+    if let Some((_, from, is_directory)) = pending_from {
+        translation.moved_out(table, &from, is_directory);
+    }
+    translation
+}
+
+/// One event as read from a fanotify file descriptor reporting directory
+/// file handles and entry names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FanotifyEvent {
+    /// `FAN_*` event flags
+    pub mask: u64,
+    
+    /// `struct file_handle` of the parent directory, empty for events
+    /// without one such as queue overflows
+    pub handle: Vec<u8>,
+    
+    /// Name of the entry within the parent directory
+    pub name: OsString,
+}
+
+/// Parses the `fanotify_event_metadata` records in `buffer`.
+///
+/// Only `FAN_EVENT_INFO_TYPE_DFID_NAME` information records are read; a
+/// truncated trailing record is ignored.
+pub fn parse_fanotify_buffer(buffer: &[u8]) -> Vec<FanotifyEvent> {
+    let mut events = Vec::new();
+    let mut offset = 0;
+    
+    while offset + FANOTIFY_METADATA_SIZE <= buffer.len() {
+        let event_len = u32::from_ne_bytes(buffer[offset..offset + 4].try_into().unwrap()) as usize;
+        let metadata_len = u16::from_ne_bytes(buffer[offset + 6..offset + 8].try_into().unwrap()) as usize;
+        let mask = u64::from_ne_bytes(buffer[offset + 8..offset + 16].try_into().unwrap());
+        if event_len < metadata_len || metadata_len < FANOTIFY_METADATA_SIZE || offset + event_len > buffer.len() {
+            break;
+        }
+        
+        let mut event = FanotifyEvent {
+            mask,
+            handle: Vec::new(),
+            name: OsString::new(),
+        };
+        let mut info = offset + metadata_len;
+        let end = offset + event_len;
+        // Header (4 bytes), fsid (8 bytes), then the file handle and the name
+        while info + 4 <= end {
+            let info_type = buffer[info];
+            let info_len = u16::from_ne_bytes(buffer[info + 2..info + 4].try_into().unwrap()) as usize;
+            if info_len < 4 || info + info_len > end {
+                break;
+            }
+            let record = &buffer[info..info + info_len];
+            if info_type == libc::FAN_EVENT_INFO_TYPE_DFID_NAME && record.len() >= 20 {
+                let handle_bytes = u32::from_ne_bytes(record[12..16].try_into().unwrap()) as usize;
+                let handle_end = 20 + handle_bytes;
+                if handle_end <= record.len() {
+                    let name = &record[handle_end..];
+                    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                    event.handle = record[12..handle_end].to_vec();
+                    event.name = OsStr::from_bytes(name).to_os_string();
+                }
+            }
+            info += info_len;
+        }
+        events.push(event);
+        offset = end;
+    }
+    events
+}
+
+/// Converts a fanotify event for `relative` into a source change.
+///
+/// fanotify merges queued events for the same entry into one mask and
+/// reports the two halves of a rename without a cookie, so whether the
+/// entry `exists` now decides between a creation, a modification and a
+/// deletion.
+pub fn translate_fanotify(mask: u64, relative: &Path, exists: bool) -> Option<SourceChange> {
+    if mask & FANOTIFY_MASK & !libc::FAN_ONDIR == 0 {
+        return None;
+    }
+    let is_directory = mask & libc::FAN_ONDIR != 0;
+    let kind = if !exists {
+        ChangeKind::Deleted
+    } else if mask & (libc::FAN_CREATE | libc::FAN_MOVED_TO) != 0 {
+        ChangeKind::Created
+    } else {
+        ChangeKind::Modified
+    };
+    
+    let mut change = SourceChange::from_relative(relative, kind, is_directory);
+    // Nothing was reported for the contents of a directory moved in
+    change.recursive = exists && is_directory && mask & libc::FAN_MOVED_TO != 0;
+    Some(change)
+}
+
+/// Marks the watcher degraded, logging the first reason.
+fn degrade(health: &Mutex<WatchHealth>, reason: String, rescan_interval: Duration) {
+    let mut health = health.lock().unwrap();
+    if health.is_healthy() {
+        warn!("Source watching degraded: {}; rescanning the source tree every {:?}", reason, rescan_interval);
+        *health = WatchHealth::Degraded { reason };
+    }
+}
+
+/// Error for a failed system call.
+fn os_error(message: impl std::fmt::Display) -> ShadowError {
+    let error = io::Error::last_os_error();
+    platform_error(ErrorPlatform::Linux, format!("{}: {}", message, error), error.raw_os_error())
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Reads from a non-blocking descriptor, returning `None` once it is drained.
+fn read_available(fd: RawFd, buffer: &mut [u8]) -> io::Result<Option<usize>> {
+    loop {
+        let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if read >= 0 {
+            return Ok(Some(read as usize));
+        }
+        let error = io::Error::last_os_error();
+        match error.kind() {
+            io::ErrorKind::Interrupted => continue,
+            io::ErrorKind::WouldBlock => return Ok(None),
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Per-directory inotify watches of the source tree.
+struct InotifySource {
+    fd: OwnedFd,
+    root: PathBuf,
+    table: WatchTable,
+    health: Arc<Mutex<WatchHealth>>,
+    rescan_interval: Duration,
+}
+
+impl InotifySource {
+    fn open(root: PathBuf, health: Arc<Mutex<WatchHealth>>, rescan_interval: Duration) -> Result<Self, ShadowError> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(os_error("inotify_init1 failed"));
+        }
+        let mut source = Self {
+            // SAFETY: fd was just returned by inotify_init1
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            root,
+            table: WatchTable::default(),
+            health,
+            rescan_interval,
+        };
+        
+        let wd = source.add_watch(Path::new(""))
+            .map_err(|e| platform_error(
+                ErrorPlatform::Linux,
+                format!("Failed to watch {}: {}", source.root.display(), e),
+                e.raw_os_error(),
+            ))?;
+        source.table.insert(wd, PathBuf::new());
+        source.watch_children(Path::new(""));
+        debug!("Watching {} with {} inotify watches", source.root.display(), source.table.len());
+        Ok(source)
+    }
+    
+    fn add_watch(&self, relative: &Path) -> io::Result<i32> {
+        let path = cstring(&self.root.join(relative))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), INOTIFY_MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+    
+    /// Watches `relative` and every directory below it.
+    fn watch_tree(&mut self, relative: &Path) {
+        match self.add_watch(relative) {
+            Ok(wd) => self.table.insert(wd, relative.to_path_buf()),
+            Err(e) => {
+                self.watch_failed(relative, e);
+                return;
+            }
+        }
+        self.watch_children(relative);
+    }
+    
+    fn watch_children(&mut self, relative: &Path) {
+        let entries = match std::fs::read_dir(self.root.join(relative)) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Cannot list {}: {}", self.root.join(relative).display(), e);
+                return;
+            }
+        };
+        for entry in entries.flatten() {
+            if !self.health.lock().unwrap().is_healthy() {
+                return;
+            }
+            // Symbolic links to directories are not followed
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                self.watch_tree(&relative.join(entry.file_name()));
+            }
+        }
+    }
+    
+    fn watch_failed(&self, relative: &Path, error: io::Error) {
+        match error.raw_os_error() {
+            Some(libc::ENOSPC) => degrade(
+                &self.health,
+                format!(
+                    "inotify watch limit reached after {} directories; raise fs.inotify.max_user_watches",
+                    self.table.len()
+                ),
+                self.rescan_interval,
+            ),
+            Some(libc::ENOMEM) => degrade(
+                &self.health,
+                "out of memory for inotify watches".to_string(),
+                self.rescan_interval,
+            ),
+            // Removed or made inaccessible since it was listed
+            _ => debug!("Cannot watch {}: {}", self.root.join(relative).display(), error),
+        }
+    }
+    
+    fn read_changes(&mut self, buffer: &mut [u8]) -> io::Result<Vec<SourceChange>> {
+        let mut changes = Vec::new();
+        while let Some(read) = read_available(self.fd.as_raw_fd(), buffer)? {
+            let events = parse_event_buffer(&buffer[..read]);
+            let translation = translate_events(&mut self.table, &events);
+            
+            for wd in translation.unwatch {
+                unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+            }
+            if translation.resync {
+                warn!("inotify queue overflowed; rebuilding watches of {}", self.root.display());
+                self.table.clear();
+                self.watch_tree(Path::new(""));
+            } else {
+                for relative in translation.watch {
+                    self.watch_tree(&relative);
+                }
+            }
+            changes.extend(translation.changes);
+        }
+        Ok(changes)
+    }
+}
+
+/// Filesystem-wide fanotify mark covering the source tree.
+struct FanotifySource {
+    fd: OwnedFd,
+    /// Source root, used to open directory handles
+    mount_fd: OwnedFd,
+    root: PathBuf,
+    /// Directories of recently seen handles
+    directories: HashMap<Vec<u8>, PathBuf>,
+}
+
+impl FanotifySource {
+    fn open(root: PathBuf) -> Result<Self, ShadowError> {
+        let fd = unsafe {
+            libc::fanotify_init(
+                libc::FAN_CLASS_NOTIF | libc::FAN_CLOEXEC | libc::FAN_NONBLOCK | libc::FAN_REPORT_DFID_NAME,
+                (libc::O_RDONLY | libc::O_LARGEFILE) as u32,
+            )
+        };
+        if fd < 0 {
+            return Err(os_error("fanotify_init failed"));
+        }
+        // SAFETY: fd was just returned by fanotify_init
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        
+        let path = cstring(&root).map_err(|e| ShadowError::IoError { source: e })?;
+        let marked = unsafe {
+            libc::fanotify_mark(
+                fd.as_raw_fd(),
+                libc::FAN_MARK_ADD | libc::FAN_MARK_FILESYSTEM,
+                FANOTIFY_MASK,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+            )
+        };
+        if marked < 0 {
+            return Err(os_error(format!("fanotify_mark failed for {}", root.display())));
+        }
+        
+        let mount_fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+        if mount_fd < 0 {
+            return Err(os_error(format!("Failed to open {}", root.display())));
+        }
+        debug!("Watching {} with fanotify", root.display());
+        Ok(Self {
+            fd,
+            // SAFETY: mount_fd was just returned by open
+            mount_fd: unsafe { OwnedFd::from_raw_fd(mount_fd) },
+            root,
+            directories: HashMap::new(),
+        })
+    }
+    
+    /// Resolves a directory file handle to its current path.
+    fn directory(&mut self, handle: &[u8]) -> Option<PathBuf> {
+        if let Some(path) = self.directories.get(handle) {
+            return Some(path.clone());
+        }
+        
+        // open_by_handle_at needs the handle aligned like struct file_handle
+        let mut aligned = vec![0u64; handle.len().div_ceil(8)];
+        // SAFETY: aligned holds at least handle.len() bytes
+        unsafe { std::ptr::copy_nonoverlapping(handle.as_ptr(), aligned.as_mut_ptr().cast::<u8>(), handle.len()) };
+        let fd = unsafe {
+            libc::open_by_handle_at(
+                self.mount_fd.as_raw_fd(),
+                aligned.as_mut_ptr().cast::<libc::file_handle>(),
+                libc::O_PATH | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            // The directory is gone; its removal is reported by its parent
+            debug!("Cannot resolve fanotify directory handle: {}", io::Error::last_os_error());
+            return None;
+        }
+        // SAFETY: fd was just returned by open_by_handle_at
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let path = std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).ok()?;
+        self.directories.insert(handle.to_vec(), path.clone());
+        Some(path)
+    }
+    
+    fn read_changes(&mut self, buffer: &mut [u8]) -> io::Result<Vec<SourceChange>> {
+        let mut changes = Vec::new();
+        while let Some(read) = read_available(self.fd.as_raw_fd(), buffer)? {
+            for event in parse_fanotify_buffer(&buffer[..read]) {
+                if event.mask & libc::FAN_Q_OVERFLOW != 0 {
+                    warn!("fanotify queue overflowed; rescanning {}", self.root.display());
+                    changes.push(SourceChange::rescan(ShadowPath::from("/")));
+                    continue;
+                }
+                if event.handle.is_empty() {
+                    continue;
+                }
+                let Some(directory) = self.directory(&event.handle) else {
+                    continue;
+                };
+                // The mark covers the whole filesystem
+                let Ok(relative) = directory.join(&event.name).strip_prefix(&self.root).map(Path::to_path_buf) else {
+                    continue;
+                };
+                if event.mask & libc::FAN_ONDIR != 0
+                    && event.mask & (libc::FAN_DELETE | libc::FAN_MOVED_FROM | libc::FAN_MOVED_TO) != 0
+                {
+                    // Cached paths below a moved or removed directory are stale
+                    self.directories.clear();
+                }
+                let exists = self.root.join(&relative).symlink_metadata().is_ok();
+                if let Some(change) = translate_fanotify(event.mask, &relative, exists) {
+                    if changes.last() != Some(&change) {
+                        changes.push(change);
+                    }
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Kernel notification interface used by the watcher thread.
+enum EventSource {
+    Inotify(InotifySource),
+    Fanotify(FanotifySource),
+}
+
+impl EventSource {
+    fn fd(&self) -> RawFd {
+        match self {
+            EventSource::Inotify(source) => source.fd.as_raw_fd(),
+            EventSource::Fanotify(source) => source.fd.as_raw_fd(),
+        }
+    }
+    
+    fn read_changes(&mut self, buffer: &mut [u8]) -> io::Result<Vec<SourceChange>> {
+        match self {
+            EventSource::Inotify(source) => source.read_changes(buffer),
+            EventSource::Fanotify(source) => source.read_changes(buffer),
+        }
+    }
+}
+
+/// Opens the event source of `backend`, without falling back to another one.
+fn open_backend(
+    root: PathBuf,
+    backend: WatchBackend,
+    health: &Arc<Mutex<WatchHealth>>,
+    rescan_interval: Duration,
+) -> Result<EventSource, ShadowError> {
+    match backend {
+        WatchBackend::Inotify => {
+            InotifySource::open(root, Arc::clone(health), rescan_interval).map(EventSource::Inotify)
+        }
+        WatchBackend::Fanotify => FanotifySource::open(root).map(EventSource::Fanotify),
+    }
+}
+
+/// Waits up to `timeout` for the stop descriptor, returning true if it became readable.
+fn wait_for_stop(stop: &OwnedFd, timeout: Duration) -> bool {
+    let mut fds = [libc::pollfd { fd: stop.as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+    let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+    unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout) > 0 && fds[0].revents != 0 }
+}
+
+/// Runs the watcher thread as the supervisor's [`Component::Watcher`].
+///
+/// A crashed watcher, one whose poll or read failed or that panicked, is
+/// reopened on the same backend after the supervisor's backoff, until the
+/// supervisor gives up on it. Without a supervisor a crash ends watching.
+fn run_supervised(
+    mut source: EventSource,
+    reopen: impl Fn() -> Result<EventSource, ShadowError>,
+    stop: Arc<OwnedFd>,
+    sink: Arc<dyn SourceChangeSink>,
+    health: Arc<Mutex<WatchHealth>>,
+    rescan_interval: Duration,
+    supervisor: Option<Arc<Supervisor>>,
+) {
+    let Some(supervisor) = supervisor else {
+        let _ = run(source, &stop, &sink, &health, rescan_interval, None);
+        return;
+    };
+    
+    supervisor.component_started(Component::Watcher);
+    loop {
+        let started = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            run(source, &stop, &sink, &health, rescan_interval, Some(&supervisor))
+        }));
+        let mut message = match result {
+            Ok(Ok(())) => {
+                supervisor.component_stopped(Component::Watcher);
+                return;
+            }
+            Ok(Err(message)) => message,
+            Err(_) => "watcher thread panicked".to_string(),
+        };
+        
+        // Failed reopens count as crashes that ran for no time
+        let mut ran_for = started.elapsed();
+        source = loop {
+            let Some(backoff) = supervisor.component_crashed(Component::Watcher, message.clone(), ran_for) else {
+                return;
+            };
+            if wait_for_stop(&stop, backoff) {
+                supervisor.component_stopped(Component::Watcher);
+                return;
+            }
+            *health.lock().unwrap() = WatchHealth::Healthy;
+            match reopen() {
+                Ok(source) => break source,
+                Err(e) => {
+                    message = format!("reopening the watcher failed: {}", e);
+                    ran_for = Duration::ZERO;
+                }
+            }
+        };
+        debug!("Restarted source watcher after: {}", message);
+        supervisor.component_started(Component::Watcher);
+    }
+}
+
+/// Delivers changes until the stop descriptor becomes readable.
+///
+/// # Errors
+/// Fails when polling or reading the event source fails.
+fn run(
+    mut source: EventSource,
+    stop: &OwnedFd,
+    sink: &Arc<dyn SourceChangeSink>,
+    health: &Mutex<WatchHealth>,
+    rescan_interval: Duration,
+    supervisor: Option<&Arc<Supervisor>>,
+) -> Result<(), String> {
+    let mut buffer = vec![0u8; EVENT_BUFFER_SIZE];
+    let mut next_rescan: Option<Instant> = None;
+    let report = |message: String| {
+        if let Some(supervisor) = supervisor {
+            supervisor.report(Subsystem::Watcher, message);
+        }
+    };
+    
+    loop {
+        // Degraded watchers rescan right away, then every rescan_interval
+        let degraded = match &*health.lock().unwrap() {
+            WatchHealth::Healthy => None,
+            WatchHealth::Degraded { reason } => Some(reason.clone()),
+        };
+        let timeout = if let Some(reason) = degraded {
+            if next_rescan.is_none() {
+                report(reason);
+            }
+            let now = Instant::now();
+            let due = next_rescan.is_none_or(|at| now >= at);
+            if due {
+                sink.on_source_changes(vec![SourceChange::rescan(ShadowPath::from("/"))]);
+            }
+            let at = *next_rescan.insert(if due { now + rescan_interval } else { next_rescan.unwrap() });
+            at.saturating_duration_since(now).as_millis().min(i32::MAX as u128) as i32
+        } else {
+            -1
+        };
+        
+        let mut fds = [
+            libc::pollfd { fd: stop.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: source.fd(), events: libc::POLLIN, revents: 0 },
+        ];
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            warn!("Source watcher poll failed: {}", error);
+            return Err(format!("poll failed: {}", error));
+        }
+        if fds[0].revents != 0 {
+            return Ok(());
+        }
+        if fds[1].revents == 0 {
+            continue;
+        }
+        
+        match source.read_changes(&mut buffer) {
+            Ok(changes) if !changes.is_empty() => sink.on_source_changes(changes),
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to read source change events: {}", e);
+                return Err(format!("reading change events failed: {}", e));
+            }
+        }
+    }
+}
+
+/// [`SourceWatcher`] backed by inotify or fanotify.
+pub struct LinuxSourceWatcher {
+    root: PathBuf,
+    config: LinuxWatchConfig,
+    backend: WatchBackend,
+    health: Arc<Mutex<WatchHealth>>,
+    supervisor: Option<Arc<Supervisor>>,
+    stop: Option<Arc<OwnedFd>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LinuxSourceWatcher {
+    /// Creates a watcher for the source tree at `root`.
+    pub fn new(root: impl Into<PathBuf>, config: LinuxWatchConfig) -> Self {
+        Self {
+            root: root.into(),
+            backend: config.backend,
+            config,
+            health: Arc::new(Mutex::new(WatchHealth::Healthy)),
+            supervisor: None,
+            stop: None,
+            thread: None,
+        }
+    }
+    
+    /// Reports degraded or stopped watching to `supervisor`, whose policy
+    /// decides whether the mount goes on with periodic rescans.
+    pub fn with_supervisor(mut self, supervisor: Arc<Supervisor>) -> Self {
+        self.supervisor = Some(supervisor);
+        self
+    }
+    
+    /// Returns the backend in use, which differs from the configured one
+    /// after a fallback to inotify.
+    pub fn backend(&self) -> WatchBackend {
+        self.backend
+    }
+    
+    /// Returns whether every directory of the source tree is watched.
+    pub fn health(&self) -> WatchHealth {
+        self.health.lock().unwrap().clone()
+    }
+    
+    fn open_source(&mut self, root: PathBuf) -> Result<EventSource, ShadowError> {
+        if self.config.backend == WatchBackend::Fanotify {
+            match FanotifySource::open(root.clone()) {
+                Ok(source) => {
+                    self.backend = WatchBackend::Fanotify;
+                    return Ok(EventSource::Fanotify(source));
+                }
+                Err(e) => warn!("{}; falling back to inotify", e),
+            }
+        }
+        self.backend = WatchBackend::Inotify;
+        InotifySource::open(root, Arc::clone(&self.health), self.config.rescan_interval)
+            .map(EventSource::Inotify)
+    }
+}
+
+impl SourceWatcher for LinuxSourceWatcher {
+    fn start(&mut self, sink: Arc<dyn SourceChangeSink>) -> Result<(), ShadowError> {
+        if self.is_running() {
+            return Ok(());
+        }
+        
+        // fanotify reports resolved paths, so the root must be canonical
+        let root = std::fs::canonicalize(&self.root).map_err(|e| ShadowError::IoError { source: e })?;
+        *self.health.lock().unwrap() = WatchHealth::Healthy;
+        let source = self.open_source(root.clone())?;
+        
+        let stop = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if stop < 0 {
+            return Err(os_error("eventfd failed"));
+        }
+        // SAFETY: stop was just returned by eventfd
+        let stop = Arc::new(unsafe { OwnedFd::from_raw_fd(stop) });
+        
+        let thread_stop = Arc::clone(&stop);
+        let health = Arc::clone(&self.health);
+        let rescan_interval = self.config.rescan_interval;
+        let supervisor = self.supervisor.clone();
+        let reopen = {
+            let (backend, health) = (self.backend, Arc::clone(&self.health));
+            move || open_backend(root.clone(), backend, &health, rescan_interval)
+        };
+        let thread = std::thread::Builder::new()
+            .name("shadowfs-source-watch".to_string())
+            .spawn(move || run_supervised(source, reopen, thread_stop, sink, health, rescan_interval, supervisor))
+            .map_err(|e| ShadowError::IoError { source: e })?;
+        
+        self.stop = Some(stop);
+        self.thread = Some(thread);
+        Ok(())
+    }
+    
+    fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let value: u64 = 1;
+            unsafe { libc::write(stop.as_raw_fd(), (&value as *const u64).cast(), 8) };
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("Source watcher thread panicked");
+            }
+        }
+    }
+    
+    fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+}
+
+impl Drop for LinuxSourceWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn event(wd: i32, mask: u32, cookie: u32, name: &str) -> RawEvent {
+        RawEvent {
+            wd,
+            mask,
+            cookie,
+            name: (!name.is_empty()).then(|| OsString::from(name)),
+        }
+    }
+    
+    fn encode(events: &[RawEvent]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for event in events {
+            let name = event.name.as_ref().map(|n| n.as_bytes().to_vec()).unwrap_or_default();
+            let padded = if name.is_empty() { 0 } else { (name.len() + 1).div_ceil(16) * 16 };
+            buffer.extend_from_slice(&event.wd.to_ne_bytes());
+            buffer.extend_from_slice(&event.mask.to_ne_bytes());
+            buffer.extend_from_slice(&event.cookie.to_ne_bytes());
+            buffer.extend_from_slice(&(padded as u32).to_ne_bytes());
+            buffer.extend_from_slice(&name);
+            buffer.resize(buffer.len() + padded - name.len(), 0);
+        }
+        buffer
+    }
+    
+    #[test]
+    fn test_parse_event_buffer() {
+        let events = vec![
+            event(1, libc::IN_CREATE, 0, "a.txt"),
+            event(1, libc::IN_DELETE_SELF, 0, ""),
+            event(2, libc::IN_MOVED_FROM, 7, "a-much-longer-file-name.txt"),
+        ];
+        let buffer = encode(&events);
+        assert_eq!(parse_event_buffer(&buffer), events);
+        
+        // A truncated record is dropped
+        assert_eq!(parse_event_buffer(&buffer[..buffer.len() - 4]), events[..2].to_vec());
+    }
+    
+    #[test]
+    fn test_translate_renames_and_directories() {
+        let mut table = WatchTable::default();
+        table.insert(1, PathBuf::new());
+        table.insert(2, PathBuf::from("src"));
+        table.insert(3, PathBuf::from("src/nested"));
+        
+        let translation = translate_events(&mut table, &[
+            event(2, libc::IN_MODIFY, 0, "main.rs"),
+            event(2, libc::IN_MODIFY, 0, "main.rs"),
+            event(1, libc::IN_MOVED_FROM | libc::IN_ISDIR, 9, "src"),
+            event(1, libc::IN_MOVED_TO | libc::IN_ISDIR, 9, "lib"),
+            event(1, libc::IN_CREATE | libc::IN_ISDIR, 0, "new"),
+            event(1, libc::IN_MOVED_FROM, 4, "gone.txt"),
+            event(1, libc::IN_MOVED_TO, 5, "arrived.txt"),
+        ]);
+        
+        assert_eq!(translation.changes[0], SourceChange::new(ShadowPath::from("/src/main.rs"), ChangeKind::Modified, false));
+        assert_eq!(translation.changes[1], SourceChange::new(
+            ShadowPath::from("/lib"),
+            ChangeKind::Renamed { from: ShadowPath::from("/src") },
+            true,
+        ));
+        assert_eq!(translation.changes[2].kind, ChangeKind::Created);
+        assert!(translation.changes[2].recursive);
+        assert_eq!(translation.changes[3], SourceChange::new(ShadowPath::from("/gone.txt"), ChangeKind::Deleted, false));
+        assert_eq!(translation.changes[4], SourceChange::new(ShadowPath::from("/arrived.txt"), ChangeKind::Created, false));
+        assert_eq!(translation.changes.len(), 5);
+        assert_eq!(translation.watch, vec![PathBuf::from("new")]);
+        
+        // The renamed directory keeps its watches under the new name
+        assert_eq!(table.path(2), Some(Path::new("lib")));
+        assert_eq!(table.path(3), Some(Path::new("lib/nested")));
+    }
+    
+    #[test]
+    fn test_translate_moved_out_and_overflow() {
+        let mut table = WatchTable::default();
+        table.insert(1, PathBuf::new());
+        table.insert(2, PathBuf::from("build"));
+        table.insert(3, PathBuf::from("build/out"));
+        
+        let translation = translate_events(&mut table, &[
+            event(1, libc::IN_MOVED_FROM | libc::IN_ISDIR, 3, "build"),
+            event(3, libc::IN_CREATE, 0, "late.o"),
+            event(-1, libc::IN_Q_OVERFLOW, 0, ""),
+            event(1, libc::IN_IGNORED, 0, ""),
+        ]);
+        
+        assert_eq!(translation.changes, vec![
+            SourceChange::new(ShadowPath::from("/build"), ChangeKind::Deleted, true),
+            SourceChange::rescan(ShadowPath::from("/")),
+        ]);
+        let mut unwatch = translation.unwatch.clone();
+        unwatch.sort();
+        assert_eq!(unwatch, vec![2, 3]);
+        assert!(translation.resync);
+        assert!(table.is_empty());
+    }
+    
+    #[test]
+    fn test_parse_fanotify_buffer() {
+        let handle = [8u32.to_ne_bytes(), 1i32.to_ne_bytes()].concat();
+        let mut record = vec![libc::FAN_EVENT_INFO_TYPE_DFID_NAME, 0, 0, 0];
+        record.extend_from_slice(&[0u8; 8]);
+        record.extend_from_slice(&handle);
+        record.extend_from_slice(&[0xAB; 8]);
+        record.extend_from_slice(b"file.txt\0");
+        record.resize(record.len().div_ceil(4) * 4, 0);
+        let record_len = record.len() as u16;
+        record[2..4].copy_from_slice(&record_len.to_ne_bytes());
+        
+        let mut buffer = Vec::new();
+        let event_len = (FANOTIFY_METADATA_SIZE + record.len()) as u32;
+        buffer.extend_from_slice(&event_len.to_ne_bytes());
+        buffer.extend_from_slice(&[3, 0]);
+        buffer.extend_from_slice(&(FANOTIFY_METADATA_SIZE as u16).to_ne_bytes());
+        buffer.extend_from_slice(&(libc::FAN_CREATE | libc::FAN_ONDIR).to_ne_bytes());
+        buffer.extend_from_slice(&(-1i32).to_ne_bytes());
+        buffer.extend_from_slice(&0i32.to_ne_bytes());
+        buffer.extend_from_slice(&record);
+        
+        let events = parse_fanotify_buffer(&buffer);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, OsString::from("file.txt"));
+        assert_eq!(events[0].handle.len(), 16);
+        
+        let change = translate_fanotify(events[0].mask, Path::new("dir/file.txt"), true).unwrap();
+        assert_eq!(change, SourceChange::new(ShadowPath::from("/dir/file.txt"), ChangeKind::Created, true));
+        
+        // Created, written and renamed away before the events were read
+        let merged = libc::FAN_CREATE | libc::FAN_MODIFY | libc::FAN_MOVED_FROM;
+        let change = translate_fanotify(merged, Path::new("dir/file.txt"), false).unwrap();
+        assert_eq!(change.kind, ChangeKind::Deleted);
+    }
+    
+    struct Collect(Mutex<Vec<SourceChange>>);
+    
+    impl SourceChangeSink for Collect {
+        fn on_source_changes(&self, changes: Vec<SourceChange>) {
+            self.0.lock().unwrap().extend(changes);
+        }
+    }
+    
+    #[test]
+    fn test_inotify_watcher_follows_new_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = Arc::new(Collect(Mutex::new(Vec::new())));
+        let mut watcher = LinuxSourceWatcher::new(dir.path(), LinuxWatchConfig::default());
+        watcher.start(Arc::clone(&sink) as Arc<_>).unwrap();
+        assert!(watcher.is_running());
+        assert_eq!(watcher.backend(), WatchBackend::Inotify);
+        
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        std::fs::write(dir.path().join("sub/file.txt"), b"data").unwrap();
+        
+        let expected = SourceChange::new(ShadowPath::from("/sub/file.txt"), ChangeKind::Created, false);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !sink.0.lock().unwrap().contains(&expected) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        watcher.stop();
+        
+        let changes = sink.0.lock().unwrap();
+        assert_eq!(changes[0].path, ShadowPath::from("/sub"));
+        assert!(changes[0].recursive);
+        assert!(changes.contains(&expected));
+        assert!(watcher.health().is_healthy());
+        assert!(!watcher.is_running());
+    }
+}
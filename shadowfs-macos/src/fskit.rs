@@ -9,6 +9,10 @@ pub mod macos_xattr;
 pub mod xattr_cache;
 pub mod finder_integration;
 pub mod mount;
+pub mod module;
+pub mod host;
+pub mod firmlinks;
+pub mod fsevents;
 
 pub use provider::FSKitProvider;
 pub use operations::FSOperationsImpl;
@@ -19,4 +23,8 @@ pub use xattr_ops::XattrOperations;
 pub use macos_xattr::{MacOSXattrHandler, MacOSXattrType, QuarantineData, FinderInfo, finder_flags};
 pub use xattr_cache::{XattrCache, CacheConfig, CacheStats};
 pub use finder_integration::{FinderIntegration, FinderLabel, FinderTag};
-pub use mount::{FSKitMount, FileSystem, MountOptions, MountError, MountInfo, FileSystemStatistics, BrowseVisibility};
\ No newline at end of file
+pub use mount::{FSKitMount, FileSystem, MountOptions, MountError, MountInfo, FileSystemStatistics, BrowseVisibility};
+pub use module::{ShadowFSModule, ShadowFSVolume, ShadowUnaryFileSystem, ShadowVolume, ModuleOptions, VolumePhase, VolumeAction, extension_main};
+pub use host::FSKitHostProvider;
+pub use firmlinks::{Firmlink, FirmlinkMap, SourceResolver};
+pub use fsevents::FsEventsWatcher;
\ No newline at end of file
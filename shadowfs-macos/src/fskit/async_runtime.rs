@@ -142,7 +142,7 @@ impl AsyncRuntime {
         let metrics = Arc::clone(&self.metrics);
         let mut shutdown_rx = self.shutdown_rx.lock().unwrap();
         
-        self.runtime.spawn(async move {
+        shadowfs_core::task::spawn_on("fskit-dispatch", async move {
             loop {
                 tokio::select! {
                     _ = shutdown_rx.recv() => break,
@@ -152,7 +152,7 @@ impl AsyncRuntime {
                                 let start = Instant::now();
                                 metrics.record_queue_depth(queue.lock().unwrap().len());
                                 
-                                tokio::task::spawn_blocking(move || {
+                                shadowfs_core::task::spawn_blocking("fskit-op", move || {
                                     (op.execute)();
                                     metrics.record_operation_latency(
                                         op.operation_type,
@@ -164,7 +164,7 @@ impl AsyncRuntime {
                     }
                 }
             }
-        });
+        }, &self.runtime);
     }
     
     /// Queue an operation with priority
@@ -603,19 +603,19 @@ impl ThreadManager {
     pub fn new() -> Result<Self> {
         let read_pool = rayon::ThreadPoolBuilder::new()
             .num_threads(4)
-            .thread_name(|i| format!("fskit-read-{}", i))
+            .thread_name(|i| format!("shadowfs-fskit-read-{}", i))
             .build()
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         
         let write_pool = rayon::ThreadPoolBuilder::new()
             .num_threads(2)
-            .thread_name(|i| format!("fskit-write-{}", i))
+            .thread_name(|i| format!("shadowfs-fskit-write-{}", i))
             .build()
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         
         let metadata_pool = rayon::ThreadPoolBuilder::new()
             .num_threads(2)
-            .thread_name(|i| format!("fskit-meta-{}", i))
+            .thread_name(|i| format!("shadowfs-fskit-meta-{}", i))
             .build()
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         
@@ -4,7 +4,7 @@ use objc2::{
     ClassType, DeclaredClass, ProtocolType,
 };
 use objc2_foundation::{
-    NSArray, NSData, NSDate, NSDictionary, NSError, NSNumber, NSObject, NSString, NSURL,
+    NSArray, NSData, NSDate, NSDictionary, NSError, NSNumber, NSObject, NSString, NSURL, NSUUID,
 };
 use std::ffi::c_void;
 
@@ -275,6 +275,13 @@ declare_class!(
     impl DeclaredClass for FSVolume {}
 
     unsafe impl FSVolume {
+        #[method_id(initWithVolumeID:volumeName:)]
+        pub fn init_with_volume_id(
+            this: Allocated<Self>,
+            volume_id: &NSUUID,
+            volume_name: &NSString,
+        ) -> Id<Self>;
+        
         #[method_id(volumeName)]
         pub fn volume_name(&self) -> Id<NSString>;
 
@@ -430,6 +437,72 @@ declare_class!(
     }
 );
 
+declare_class!(
+    pub struct FSUnaryFileSystem;
+    
+    unsafe impl ClassType for FSUnaryFileSystem {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "FSUnaryFileSystem";
+    }
+    
+    impl DeclaredClass for FSUnaryFileSystem {}
+);
+
+declare_class!(
+    pub struct FSGenericURLResource;
+    
+    unsafe impl ClassType for FSGenericURLResource {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "FSGenericURLResource";
+    }
+    
+    impl DeclaredClass for FSGenericURLResource {}
+    
+    unsafe impl FSGenericURLResource {
+        #[method_id(URL)]
+        pub fn url(&self) -> Id<NSURL>;
+    }
+);
+
+declare_class!(
+    pub struct FSProbeResult;
+    
+    unsafe impl ClassType for FSProbeResult {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "FSProbeResult";
+    }
+    
+    impl DeclaredClass for FSProbeResult {}
+    
+    unsafe impl FSProbeResult {
+        #[method_id(notRecognizedProbeResult)]
+        pub fn not_recognized() -> Id<Self>;
+        
+        #[method_id(usableProbeResultWithName:containerID:)]
+        pub fn usable(name: &NSString, container_id: &NSUUID) -> Id<Self>;
+    }
+);
+
+declare_class!(
+    pub struct FSTaskOptions;
+    
+    unsafe impl ClassType for FSTaskOptions {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "FSTaskOptions";
+    }
+    
+    impl DeclaredClass for FSTaskOptions {}
+    
+    unsafe impl FSTaskOptions {
+        #[method_id(taskOptions)]
+        pub fn task_options(&self) -> Id<NSArray<NSString>>;
+    }
+);
+
 use objc2_foundation::NSArray;
 use std::error::Error;
 use std::fmt;
@@ -725,6 +798,21 @@ pub fn create_error(code: i64, description: &str) -> Id<NSError> {
     }
 }
 
+/// Creates an error in `NSPOSIXErrorDomain`, the domain FSKit expects the
+/// errors of file system operations in.
+pub fn posix_error(code: i32, description: &str) -> Id<NSError> {
+    unsafe {
+        let domain = NSString::from_str("NSPOSIXErrorDomain");
+        let desc = NSString::from_str(description);
+        let user_info = NSDictionary::from_keys_and_objects(
+            &[&*NSString::from_str("NSLocalizedDescriptionKey")],
+            vec![desc.as_ref()],
+        );
+        
+        NSError::errorWithDomain_code_userInfo(&domain, code as isize, Some(&user_info))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnicodeNormalization {
     NFC,
@@ -751,7 +751,7 @@ impl FSFileOps {
             data: Some(file_data),
         };
         
-        override_store.items.insert(path.to_path_buf(), override_item);
+        override_store.insert_item(path.to_path_buf(), override_item);
         
         Ok(())
     }
@@ -0,0 +1,349 @@
+//! APFS firmlink awareness for source paths.
+//!
+//! Since Catalina the boot volume is an APFS volume group: a read-only system
+//! volume mounted at `/` and a writable data volume mounted at
+//! `/System/Volumes/Data`. Firmlinks listed in `/usr/share/firmlinks` make
+//! directories of the data volume appear at their usual place on the system
+//! volume, so `/Users/me` and `/System/Volumes/Data/Users/me` are the same
+//! directory. Plain path arithmetic treats them as different trees, which
+//! lists entries twice when a source covers both sides and misses paths that
+//! are reported through the other side. The [`SourceResolver`] maps every
+//! source path to a single canonical form, the one on the system volume.
+
+use shadowfs_core::mount_paths;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tracing::debug;
+
+/// Mount point of the data volume of the boot volume group.
+pub const DATA_VOLUME_ROOT: &str = "/System/Volumes/Data";
+
+/// File listing the firmlinks of the boot volume group.
+pub const FIRMLINKS_FILE: &str = "/usr/share/firmlinks";
+
+/// A firmlink from the system volume into the data volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Firmlink {
+    /// Absolute path of the firmlink on the system volume
+    pub path: PathBuf,
+    
+    /// Target directory, relative to the data volume root
+    pub target: PathBuf,
+}
+
+impl Firmlink {
+    /// Returns the absolute path of the target through the data volume mount.
+    pub fn data_path(&self) -> PathBuf {
+        Path::new(DATA_VOLUME_ROOT).join(&self.target)
+    }
+}
+
+/// The firmlinks of a volume group.
+#[derive(Debug, Clone, Default)]
+pub struct FirmlinkMap {
+    /// Firmlinks, deepest target first so nested targets match before their parents
+    links: Vec<Firmlink>,
+}
+
+impl FirmlinkMap {
+    /// Parses the content of a firmlinks file.
+    ///
+    /// Each line holds the absolute firmlink path and its target relative to
+    /// the data volume, separated by a tab. Blank lines, comments and lines
+    /// that do not follow this format are ignored.
+    pub fn parse(contents: &str) -> Self {
+        let mut links: Vec<Firmlink> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (path, target) = line.split_once('\t')?;
+                let path = Path::new(path.trim());
+                let target = Path::new(target.trim());
+                if !path.is_absolute() || target.as_os_str().is_empty() || target.is_absolute() {
+                    return None;
+                }
+                Some(Firmlink {
+                    path: path.to_path_buf(),
+                    target: target.to_path_buf(),
+                })
+            })
+            .collect();
+        
+        links.sort_by_key(|link| std::cmp::Reverse(link.target.components().count()));
+        Self { links }
+    }
+    
+    /// Reads the firmlinks file at `path`.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+    
+    /// Loads the firmlinks of the running system.
+    ///
+    /// Returns an empty map on systems without a volume group, where every
+    /// path is already canonical.
+    pub fn load() -> Self {
+        match Self::from_file(Path::new(FIRMLINKS_FILE)) {
+            Ok(map) => map,
+            Err(e) => {
+                debug!("No firmlinks loaded from {}: {}", FIRMLINKS_FILE, e);
+                Self::default()
+            }
+        }
+    }
+    
+    /// Returns the parsed firmlinks.
+    pub fn links(&self) -> &[Firmlink] {
+        &self.links
+    }
+    
+    /// Returns true if no firmlinks are known.
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+    
+    /// Maps a path through the data volume to its firmlinked location.
+    ///
+    /// `/System/Volumes/Data/Users/me` becomes `/Users/me`. Paths that do not
+    /// go through a firmlink target, including data volume entries that have
+    /// no firmlink, are returned unchanged apart from dropping `.` components.
+    pub fn canonicalize(&self, path: &Path) -> PathBuf {
+        let path = clean(path);
+        for link in &self.links {
+            if let Some(rest) = strip_prefix_ignore_case(&path, &link.data_path()) {
+                return join_rest(&link.path, &rest);
+            }
+        }
+        path
+    }
+    
+    /// Maps a firmlinked path to its location through the data volume mount.
+    ///
+    /// This is the inverse of [`canonicalize`](Self::canonicalize) and
+    /// returns `None` for paths that are not below a firmlink.
+    pub fn data_volume_path(&self, path: &Path) -> Option<PathBuf> {
+        let path = clean(path);
+        self.links.iter().find_map(|link| {
+            strip_prefix_ignore_case(&path, &link.path).map(|rest| join_rest(&link.data_path(), &rest))
+        })
+    }
+    
+    /// Returns true if `path` is itself a firmlink or a firmlink target.
+    pub fn is_firmlink(&self, path: &Path) -> bool {
+        let path = clean(path);
+        self.links.iter().any(|link| {
+            [link.path.clone(), link.data_path()]
+                .iter()
+                .any(|side| strip_prefix_ignore_case(&path, side).is_some_and(|rest| rest.as_os_str().is_empty()))
+        })
+    }
+}
+
+/// Resolves paths of the source tree to canonical absolute paths.
+#[derive(Debug, Clone)]
+pub struct SourceResolver {
+    /// Canonical source root
+    root: PathBuf,
+    
+    /// Firmlinks used for canonicalization
+    firmlinks: FirmlinkMap,
+}
+
+impl SourceResolver {
+    /// Creates a resolver for `root` using the firmlinks of the running system.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self::with_firmlinks(root, FirmlinkMap::load())
+    }
+    
+    /// Creates a resolver for `root` using `firmlinks`.
+    ///
+    /// Symbolic links in the root, such as `/tmp` pointing to `/private/tmp`,
+    /// are resolved when the root exists. The result is then mapped through
+    /// the firmlinks, so a root given through the data volume and the same
+    /// root given through its firmlink resolve identically.
+    pub fn with_firmlinks(root: impl AsRef<Path>, firmlinks: FirmlinkMap) -> Self {
+        let root = root.as_ref();
+        let resolved = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let canonical = firmlinks.canonicalize(&resolved);
+        if canonical != resolved {
+            debug!("Source root {} is firmlinked to {}", resolved.display(), canonical.display());
+        }
+        
+        Self {
+            root: canonical,
+            firmlinks,
+        }
+    }
+    
+    /// Returns the canonical source root.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    
+    /// Returns the firmlinks used by this resolver.
+    pub fn firmlinks(&self) -> &FirmlinkMap {
+        &self.firmlinks
+    }
+    
+    /// Returns the source path of `relative`, a path relative to the mount root.
+    pub fn resolve(&self, relative: &Path) -> PathBuf {
+        let path = mount_paths::relative_shadow_path(relative);
+        self.firmlinks.canonicalize(&mount_paths::source_path(&self.root, &path))
+    }
+    
+    /// Returns the path of the absolute source path `path` relative to the root.
+    ///
+    /// Paths reported through either side of a firmlink map to the same
+    /// relative path. Returns `None` for paths outside the source tree.
+    pub fn relative_path(&self, path: &Path) -> Option<PathBuf> {
+        let canonical = self.firmlinks.canonicalize(path);
+        strip_prefix_ignore_case(&canonical, &self.root)
+    }
+    
+    /// Returns true if `path` is a second view of a directory already in the source tree.
+    ///
+    /// When the root contains both a firmlink and the data volume mount, as
+    /// `/` does, every firmlinked directory is reachable twice. Entries seen
+    /// through the data volume are duplicates and should be skipped when
+    /// enumerating; data volume entries without a firmlink are kept.
+    pub fn is_duplicate(&self, path: &Path) -> bool {
+        let path = clean(path);
+        let canonical = self.firmlinks.canonicalize(&path);
+        canonical != path && strip_prefix_ignore_case(&canonical, &self.root).is_some()
+    }
+}
+
+/// Drops `.` components and redundant separators.
+fn clean(path: &Path) -> PathBuf {
+    path.components().filter(|c| !matches!(c, Component::CurDir)).collect()
+}
+
+/// Strips `prefix` from `path` component-wise, ignoring ASCII case.
+///
+/// The system and data volumes of a standard install are case-insensitive,
+/// so `/users/me` goes through the same firmlink as `/Users/me`.
+fn strip_prefix_ignore_case(path: &Path, prefix: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    for expected in prefix.components() {
+        let actual = components.next()?;
+        let same = match (actual, expected) {
+            (Component::Normal(a), Component::Normal(b)) => a.eq_ignore_ascii_case(b),
+            (a, b) => a == b,
+        };
+        if !same {
+            return None;
+        }
+    }
+    Some(components.as_path().to_path_buf())
+}
+
+fn join_rest(base: &Path, rest: &Path) -> PathBuf {
+    if rest.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// `/usr/share/firmlinks` of macOS 14 Sonoma.
+    const SONOMA_FIRMLINKS: &str = "\
+/AppleInternal\tAppleInternal
+/Applications\tApplications
+/Library\tLibrary
+/System/Library/Caches\tSystem/Library/Caches
+/System/Library/Assets\tSystem/Library/Assets
+/System/Library/PreinstalledAssets\tSystem/Library/PreinstalledAssets
+/System/Library/AssetsV2\tSystem/Library/AssetsV2
+/System/Library/PreinstalledAssetsV2\tSystem/Library/PreinstalledAssetsV2
+/System/Library/CoreServices/CoreTypes.bundle/Contents/Library\tSystem/Library/CoreServices/CoreTypes.bundle/Contents/Library
+/System/Library/Speech\tSystem/Library/Speech
+/Users\tUsers
+/Volumes\tVolumes
+/cores\tcores
+/opt\topt
+/private\tprivate
+/usr/local\tusr/local
+/usr/libexec/cups\tusr/libexec/cups
+/usr/share/snmp\tusr/share/snmp
+";
+
+    fn sonoma() -> FirmlinkMap {
+        FirmlinkMap::parse(SONOMA_FIRMLINKS)
+    }
+    
+    #[test]
+    fn test_parse_sonoma_layout() {
+        let map = sonoma();
+        assert_eq!(map.links().len(), 18);
+        
+        let ignored = FirmlinkMap::parse("# comment\n\n/Users Users\n/abs\t/abs\nrelative\trelative\n/Users\tUsers\n");
+        assert_eq!(ignored.links(), &[Firmlink {
+            path: PathBuf::from("/Users"),
+            target: PathBuf::from("Users"),
+        }]);
+    }
+    
+    #[test]
+    fn test_canonicalize_data_volume_paths() {
+        let map = sonoma();
+        let cases = [
+            ("/System/Volumes/Data/Users/me/src", "/Users/me/src"),
+            ("/System/Volumes/Data/Users", "/Users"),
+            ("/System/Volumes/Data/./Applications/Xcode.app", "/Applications/Xcode.app"),
+            ("/System/Volumes/Data/usr/local/bin", "/usr/local/bin"),
+            ("/System/Volumes/Data/System/Library/Caches/com.apple.x", "/System/Library/Caches/com.apple.x"),
+            ("/system/volumes/data/users/me", "/Users/me"),
+            // Not firmlinked: stays on the data volume
+            ("/System/Volumes/Data/.fseventsd", "/System/Volumes/Data/.fseventsd"),
+            ("/System/Volumes/Data/usr/bin", "/System/Volumes/Data/usr/bin"),
+            // Already canonical
+            ("/Users/me", "/Users/me"),
+            ("/System/Library/Frameworks", "/System/Library/Frameworks"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(map.canonicalize(Path::new(input)), PathBuf::from(expected), "{}", input);
+        }
+        
+        assert_eq!(
+            map.data_volume_path(Path::new("/Users/me")),
+            Some(PathBuf::from("/System/Volumes/Data/Users/me"))
+        );
+        assert_eq!(map.data_volume_path(Path::new("/System/Library/Frameworks")), None);
+        assert!(map.is_firmlink(Path::new("/usr/local")));
+        assert!(map.is_firmlink(Path::new("/System/Volumes/Data/usr/local")));
+        assert!(!map.is_firmlink(Path::new("/usr/local/bin")));
+    }
+    
+    #[test]
+    fn test_resolver_maps_both_sides_of_a_firmlink() {
+        let through_data = SourceResolver::with_firmlinks("/System/Volumes/Data/Users/me/project", sonoma());
+        let through_link = SourceResolver::with_firmlinks("/Users/me/project", sonoma());
+        assert_eq!(through_data.root(), through_link.root());
+        assert_eq!(through_data.resolve(Path::new("/src/main.rs")), PathBuf::from("/Users/me/project/src/main.rs"));
+        
+        // Changes reported through the data volume are not missed
+        assert_eq!(
+            through_link.relative_path(Path::new("/System/Volumes/Data/Users/me/project/src/lib.rs")),
+            Some(PathBuf::from("src/lib.rs"))
+        );
+        assert_eq!(through_link.relative_path(Path::new("/Users/other")), None);
+    }
+    
+    #[test]
+    fn test_resolver_skips_duplicate_entries() {
+        let whole_disk = SourceResolver::with_firmlinks("/", sonoma());
+        assert!(whole_disk.is_duplicate(Path::new("/System/Volumes/Data/Users")));
+        assert!(whole_disk.is_duplicate(Path::new("/System/Volumes/Data/System/Library/Caches")));
+        assert!(!whole_disk.is_duplicate(Path::new("/System/Volumes/Data/.fseventsd")));
+        assert!(!whole_disk.is_duplicate(Path::new("/Users")));
+        
+        // A root on the data volume itself sees each directory once
+        let data_volume = SourceResolver::with_firmlinks(DATA_VOLUME_ROOT, sonoma());
+        assert!(!data_volume.is_duplicate(Path::new("/System/Volumes/Data/Users")));
+    }
+}
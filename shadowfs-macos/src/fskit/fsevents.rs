@@ -0,0 +1,447 @@
+//! Source tree watching with FSEvents.
+//!
+//! The watcher registers one recursive FSEvents stream for the source root
+//! with file-level events. FSEvents coalesces changes within the configured
+//! latency, so a single event may carry several flags (created, modified and
+//! removed); the current state of the path decides what is reported. Event
+//! paths are mapped back to override paths through the [`SourceResolver`],
+//! which also folds paths reported through `/System/Volumes/Data` onto their
+//! firmlinked location.
+
+use super::firmlinks::SourceResolver;
+use core_foundation::array::CFArray;
+use core_foundation::base::TCFType;
+use core_foundation::runloop::{kCFRunLoopDefaultMode, CFRunLoop};
+use core_foundation::string::CFString;
+use shadowfs_core::error::{Platform as ErrorPlatform, ShadowError};
+use shadowfs_core::source_watch::{source_path, SourceChange, SourceChangeSink, SourceWatcher};
+use shadowfs_core::types::ShadowPath;
+use shadowfs_core::watch::ChangeKind;
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Default time FSEvents waits to coalesce changes before delivering them.
+pub const DEFAULT_LATENCY: Duration = Duration::from_millis(100);
+
+// FSEventStreamCreateFlags
+const CREATE_FLAG_WATCH_ROOT: u32 = 0x0000_0004;
+const CREATE_FLAG_FILE_EVENTS: u32 = 0x0000_0010;
+
+// FSEventStreamEventFlags
+const FLAG_MUST_SCAN_SUB_DIRS: u32 = 0x0000_0001;
+const FLAG_USER_DROPPED: u32 = 0x0000_0002;
+const FLAG_KERNEL_DROPPED: u32 = 0x0000_0004;
+const FLAG_HISTORY_DONE: u32 = 0x0000_0010;
+const FLAG_ROOT_CHANGED: u32 = 0x0000_0020;
+const FLAG_MOUNT: u32 = 0x0000_0040;
+const FLAG_UNMOUNT: u32 = 0x0000_0080;
+const FLAG_ITEM_CREATED: u32 = 0x0000_0100;
+const FLAG_ITEM_REMOVED: u32 = 0x0000_0200;
+const FLAG_ITEM_RENAMED: u32 = 0x0000_0800;
+const FLAG_ITEM_IS_DIR: u32 = 0x0002_0000;
+
+/// Flags after which the state below the path is unknown.
+const RESCAN_FLAGS: u32 = FLAG_MUST_SCAN_SUB_DIRS
+    | FLAG_USER_DROPPED
+    | FLAG_KERNEL_DROPPED
+    | FLAG_ROOT_CHANGED
+    | FLAG_MOUNT
+    | FLAG_UNMOUNT;
+
+/// `kFSEventStreamEventIdSinceNow`
+const EVENT_ID_SINCE_NOW: u64 = u64::MAX;
+
+type FSEventStreamRef = *mut c_void;
+
+type FSEventStreamCallback = extern "C" fn(
+    stream: FSEventStreamRef,
+    info: *mut c_void,
+    num_events: usize,
+    event_paths: *mut c_void,
+    event_flags: *const u32,
+    event_ids: *const u64,
+);
+
+#[repr(C)]
+struct FSEventStreamContext {
+    version: isize,
+    info: *mut c_void,
+    retain: *const c_void,
+    release: *const c_void,
+    copy_description: *const c_void,
+}
+
+#[link(name = "CoreServices", kind = "framework")]
+extern "C" {
+    fn FSEventStreamCreate(
+        allocator: *const c_void,
+        callback: FSEventStreamCallback,
+        context: *const FSEventStreamContext,
+        paths_to_watch: *const c_void,
+        since_when: u64,
+        latency: f64,
+        flags: u32,
+    ) -> FSEventStreamRef;
+    fn FSEventStreamScheduleWithRunLoop(stream: FSEventStreamRef, run_loop: *const c_void, mode: *const c_void);
+    fn FSEventStreamStart(stream: FSEventStreamRef) -> u8;
+    fn FSEventStreamStop(stream: FSEventStreamRef);
+    fn FSEventStreamInvalidate(stream: FSEventStreamRef);
+    fn FSEventStreamRelease(stream: FSEventStreamRef);
+}
+
+/// One event as delivered by FSEvents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEvent {
+    /// Absolute path of the changed item
+    pub path: PathBuf,
+    
+    /// `FSEventStreamEventFlags` of the event
+    pub flags: u32,
+    
+    /// Event id; the two halves of a rename have consecutive ids
+    pub id: u64,
+}
+
+/// Converts a batch of FSEvents into source changes.
+///
+/// # Arguments
+/// * `resolver` - Resolver of the watched source root
+/// * `events` - Events in delivery order
+/// * `exists` - Checks whether a path currently exists
+pub fn translate_events(
+    resolver: &SourceResolver,
+    events: &[RawEvent],
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<SourceChange> {
+    let mut changes = Vec::new();
+    // The old name of a rename waiting for its new name
+    let mut pending_rename: Option<(ShadowPath, bool, u64)> = None;
+    
+    for event in events {
+        if event.flags & FLAG_HISTORY_DONE != 0 {
+            continue;
+        }
+        let Some(relative) = resolver.relative_path(&event.path) else {
+            continue;
+        };
+        let path = source_path(&relative);
+        let is_directory = event.flags & FLAG_ITEM_IS_DIR != 0;
+        
+        if event.flags & FLAG_ITEM_RENAMED != 0 {
+            if exists(&event.path) {
+                let change = match pending_rename.take() {
+                    Some((from, _, id)) if id + 1 == event.id => ChangeKind::Renamed { from },
+                    Some((from, from_is_directory, _)) => {
+                        changes.push(SourceChange::new(from, ChangeKind::Deleted, from_is_directory));
+                        ChangeKind::Created
+                    }
+                    None => ChangeKind::Created,
+                };
+                changes.push(SourceChange::new(path, change, is_directory));
+            } else if let Some((from, from_is_directory, _)) = pending_rename.replace((path, is_directory, event.id)) {
+                // Moved out of the source tree
+                changes.push(SourceChange::new(from, ChangeKind::Deleted, from_is_directory));
+            }
+            continue;
+        }
+        
+        if let Some((from, from_is_directory, _)) = pending_rename.take() {
+            changes.push(SourceChange::new(from, ChangeKind::Deleted, from_is_directory));
+        }
+        
+        if event.flags & RESCAN_FLAGS != 0 {
+            changes.push(SourceChange::rescan(path));
+            continue;
+        }
+        
+        let kind = match (exists(&event.path), event.flags) {
+            (false, flags) if flags & FLAG_ITEM_REMOVED != 0 => ChangeKind::Deleted,
+            // Created and removed within the latency window
+            (false, _) => continue,
+            (true, flags) if flags & FLAG_ITEM_CREATED != 0 && flags & FLAG_ITEM_REMOVED == 0 => ChangeKind::Created,
+            // Replaced, as by an atomic save, or changed in place
+            (true, _) => ChangeKind::Modified,
+        };
+        changes.push(SourceChange::new(path, kind, is_directory));
+    }
+    
+    if let Some((from, from_is_directory, _)) = pending_rename {
+        changes.push(SourceChange::new(from, ChangeKind::Deleted, from_is_directory));
+    }
+    changes
+}
+
+/// State shared with the FSEvents callback.
+struct CallbackState {
+    resolver: SourceResolver,
+    sink: Arc<dyn SourceChangeSink>,
+}
+
+extern "C" fn stream_callback(
+    _stream: FSEventStreamRef,
+    info: *mut c_void,
+    num_events: usize,
+    event_paths: *mut c_void,
+    event_flags: *const u32,
+    event_ids: *const u64,
+) {
+    // Panics must not unwind into CoreServices
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // SAFETY: info is the CallbackState owned by the watcher thread, which
+        // outlives the stream; the arrays hold num_events elements
+        let state = unsafe { &*(info as *const CallbackState) };
+        let paths = unsafe { std::slice::from_raw_parts(event_paths as *const *const c_char, num_events) };
+        let flags = unsafe { std::slice::from_raw_parts(event_flags, num_events) };
+        let ids = unsafe { std::slice::from_raw_parts(event_ids, num_events) };
+        
+        let events: Vec<RawEvent> = (0..num_events)
+            .map(|i| RawEvent {
+                path: PathBuf::from(unsafe { CStr::from_ptr(paths[i]) }.to_string_lossy().into_owned()),
+                flags: flags[i],
+                id: ids[i],
+            })
+            .collect();
+        
+        let changes = translate_events(&state.resolver, &events, Path::exists);
+        if !changes.is_empty() {
+            state.sink.on_source_changes(changes);
+        }
+    }));
+}
+
+/// Run loop of the watcher thread; `CFRunLoopStop` may be called from any thread.
+struct RunLoopHandle(CFRunLoop);
+
+unsafe impl Send for RunLoopHandle {}
+
+/// [`SourceWatcher`] backed by an FSEvents stream.
+pub struct FsEventsWatcher {
+    resolver: SourceResolver,
+    latency: Duration,
+    run_loop: Option<RunLoopHandle>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FsEventsWatcher {
+    /// Creates a watcher for the source tree of `resolver`.
+    pub fn new(resolver: SourceResolver) -> Self {
+        Self {
+            resolver,
+            latency: DEFAULT_LATENCY,
+            run_loop: None,
+            thread: None,
+        }
+    }
+    
+    /// Sets how long FSEvents coalesces changes before delivering them.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+}
+
+impl SourceWatcher for FsEventsWatcher {
+    fn start(&mut self, sink: Arc<dyn SourceChangeSink>) -> Result<(), ShadowError> {
+        if self.is_running() {
+            return Ok(());
+        }
+        
+        let root = self.resolver.root().to_path_buf();
+        let state = CallbackState {
+            resolver: self.resolver.clone(),
+            sink,
+        };
+        let latency = self.latency.as_secs_f64();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        
+        let thread = std::thread::Builder::new()
+            .name("shadowfs-fsevents".to_string())
+            .spawn(move || {
+                let state = Box::new(state);
+                let context = FSEventStreamContext {
+                    version: 0,
+                    info: &*state as *const CallbackState as *mut c_void,
+                    retain: std::ptr::null(),
+                    release: std::ptr::null(),
+                    copy_description: std::ptr::null(),
+                };
+                let paths = CFArray::from_CFTypes(&[CFString::new(&root.to_string_lossy())]);
+                
+                unsafe {
+                    let stream = FSEventStreamCreate(
+                        std::ptr::null(),
+                        stream_callback,
+                        &context,
+                        paths.as_concrete_TypeRef() as *const c_void,
+                        EVENT_ID_SINCE_NOW,
+                        latency,
+                        CREATE_FLAG_FILE_EVENTS | CREATE_FLAG_WATCH_ROOT,
+                    );
+                    if stream.is_null() {
+                        let _ = ready_tx.send(Err(format!("FSEventStreamCreate failed for {}", root.display())));
+                        return;
+                    }
+                    
+                    let run_loop = CFRunLoop::get_current();
+                    FSEventStreamScheduleWithRunLoop(
+                        stream,
+                        run_loop.as_concrete_TypeRef() as *const c_void,
+                        kCFRunLoopDefaultMode as *const c_void,
+                    );
+                    if FSEventStreamStart(stream) == 0 {
+                        FSEventStreamInvalidate(stream);
+                        FSEventStreamRelease(stream);
+                        let _ = ready_tx.send(Err(format!("FSEventStreamStart failed for {}", root.display())));
+                        return;
+                    }
+                    
+                    debug!("Watching {} with FSEvents", root.display());
+                    let _ = ready_tx.send(Ok(RunLoopHandle(run_loop)));
+                    CFRunLoop::run_current();
+                    
+                    FSEventStreamStop(stream);
+                    FSEventStreamInvalidate(stream);
+                    FSEventStreamRelease(stream);
+                }
+                drop(state);
+            })
+            .map_err(|e| ShadowError::IoError { source: e })?;
+        
+        match ready_rx.recv() {
+            Ok(Ok(run_loop)) => {
+                self.run_loop = Some(run_loop);
+                self.thread = Some(thread);
+                Ok(())
+            }
+            Ok(Err(message)) => {
+                let _ = thread.join();
+                Err(ShadowError::PlatformError {
+                    platform: ErrorPlatform::MacOS,
+                    message,
+                    code: None,
+                })
+            }
+            Err(_) => {
+                let _ = thread.join();
+                Err(ShadowError::PlatformError {
+                    platform: ErrorPlatform::MacOS,
+                    message: "FSEvents watcher thread exited during startup".to_string(),
+                    code: None,
+                })
+            }
+        }
+    }
+    
+    fn stop(&mut self) {
+        if let Some(run_loop) = self.run_loop.take() {
+            run_loop.0.stop();
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("FSEvents watcher thread panicked");
+            }
+        }
+    }
+    
+    fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+}
+
+impl Drop for FsEventsWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::firmlinks::FirmlinkMap;
+    use std::collections::HashSet;
+    
+    const FLAG_ITEM_MODIFIED: u32 = 0x0000_1000;
+    const FLAG_ITEM_IS_FILE: u32 = 0x0001_0000;
+    
+    fn resolver() -> SourceResolver {
+        SourceResolver::with_firmlinks(
+            "/Users/me/project",
+            FirmlinkMap::parse("/Users\tUsers\n"),
+        )
+    }
+    
+    fn event(path: &str, flags: u32, id: u64) -> RawEvent {
+        RawEvent { path: PathBuf::from(path), flags, id }
+    }
+    
+    fn translate(events: &[RawEvent], existing: &[&str]) -> Vec<SourceChange> {
+        let existing: HashSet<PathBuf> = existing.iter().map(PathBuf::from).collect();
+        translate_events(&resolver(), events, |path| existing.contains(path))
+    }
+    
+    #[test]
+    fn test_coalesced_flags_use_current_state() {
+        let file = FLAG_ITEM_IS_FILE;
+        let changes = translate(
+            &[
+                event("/Users/me/project/new.txt", file | FLAG_ITEM_CREATED | FLAG_ITEM_MODIFIED, 1),
+                event("/Users/me/project/saved.txt", file | FLAG_ITEM_CREATED | FLAG_ITEM_REMOVED, 2),
+                event("/Users/me/project/gone.txt", file | FLAG_ITEM_CREATED | FLAG_ITEM_REMOVED, 3),
+                event("/Users/me/project/tmp.txt", file | FLAG_ITEM_CREATED, 4),
+                event("/Users/other/elsewhere.txt", file | FLAG_ITEM_MODIFIED, 5),
+            ],
+            &["/Users/me/project/new.txt", "/Users/me/project/saved.txt"],
+        );
+        
+        let kinds: Vec<(String, ChangeKind)> = changes.into_iter().map(|c| (c.path.to_string(), c.kind)).collect();
+        assert_eq!(kinds, vec![
+            ("/new.txt".to_string(), ChangeKind::Created),
+            ("/saved.txt".to_string(), ChangeKind::Modified),
+            ("/gone.txt".to_string(), ChangeKind::Deleted),
+        ]);
+    }
+    
+    #[test]
+    fn test_rename_pairs_and_firmlinked_paths() {
+        let changes = translate(
+            &[
+                event("/System/Volumes/Data/Users/me/project/a.txt", FLAG_ITEM_RENAMED, 10),
+                event("/Users/me/project/b.txt", FLAG_ITEM_RENAMED, 11),
+                event("/Users/me/project/moved-out.txt", FLAG_ITEM_RENAMED, 20),
+                event("/Users/me/project/dir", FLAG_ITEM_RENAMED | FLAG_ITEM_IS_DIR, 30),
+            ],
+            &["/Users/me/project/b.txt", "/Users/me/project/dir"],
+        );
+        
+        assert_eq!(changes[0].path, ShadowPath::from("/b.txt"));
+        assert_eq!(changes[0].kind, ChangeKind::Renamed { from: ShadowPath::from("/a.txt") });
+        assert_eq!(changes[1].path, ShadowPath::from("/moved-out.txt"));
+        assert_eq!(changes[1].kind, ChangeKind::Deleted);
+        assert_eq!(changes[2].path, ShadowPath::from("/dir"));
+        assert_eq!(changes[2].kind, ChangeKind::Created);
+        assert!(changes[2].is_directory);
+        assert_eq!(changes.len(), 3);
+    }
+    
+    #[test]
+    fn test_dropped_events_request_rescan() {
+        let changes = translate(
+            &[
+                event("/Users/me/project/src", FLAG_MUST_SCAN_SUB_DIRS, 1),
+                event("/Users/me/project", FLAG_KERNEL_DROPPED, 2),
+                event("/Users/me/project", FLAG_HISTORY_DONE, 3),
+            ],
+            &[],
+        );
+        
+        assert_eq!(changes, vec![
+            SourceChange::rescan(ShadowPath::from("/src")),
+            SourceChange::rescan(ShadowPath::from("/")),
+        ]);
+    }
+}
@@ -0,0 +1,249 @@
+//! The host side of FSKit mounts: the provider `shadowfs mount` uses on
+//! macOS.
+//!
+//! An FSKit volume is served by the module in its extension process, not by
+//! the process that mounts it. [`FSKitHostProvider`] mounts with `mount -F`,
+//! which has FSKit load the module (see [`module`](super::module)), and
+//! hands the overrides of its store over through a snapshot: written before
+//! mounting, named in the mount options, and read back once the volume is
+//! unmounted, by which time the module has saved the overrides made through
+//! it. While the volume is mounted its files are reached through the mount
+//! point; the provider has no [`MountContext`](shadowfs_core::passthrough::MountContext)
+//! to serve them from, so its file operations fail with
+//! [`ShadowError::NotMounted`].
+//!
+//! Mounting needs macOS 15 or later with the module extension enabled.
+//! Without them, and when `mount` or `umount` fail, the error comes back as
+//! a [`ShadowError`].
+
+use super::module::{ModuleOptions, FS_SHORT_NAME, MODULE_BUNDLE_ID};
+use super::mount::MountError;
+use async_trait::async_trait;
+use shadowfs_core::error::{not_mounted, unsupported, Platform as ErrorPlatform, Result, ShadowError};
+use shadowfs_core::override_store::{OverrideSnapshot, OverrideStore};
+use shadowfs_core::registry::config_dir;
+use shadowfs_core::traits::FileSystemProvider;
+use shadowfs_core::types::{MountOptions, Platform, ShadowPath};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use tokio::process::Command;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Oldest major release of macOS with FSKit.
+const MINIMUM_MACOS_MAJOR: u32 = 15;
+
+/// A volume mounted by the provider.
+struct HostMount {
+    /// Snapshot the module loads the overrides from and saves them to
+    snapshot: PathBuf,
+}
+
+/// Mounts the overrides of a store as FSKit volumes; see the
+/// [module documentation](self).
+pub struct FSKitHostProvider {
+    /// Override store handed to every volume mounted
+    store: Arc<OverrideStore>,
+    
+    /// Mounted volumes by mount point
+    mounts: Mutex<HashMap<PathBuf, HostMount>>,
+}
+
+impl FSKitHostProvider {
+    /// Creates a provider mounting the overrides of `store`.
+    pub fn new(store: Arc<OverrideStore>) -> Self {
+        Self {
+            store,
+            mounts: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Returns true if the provider has a volume mounted at `mount_point`.
+    pub fn is_mounted(&self, mount_point: &Path) -> bool {
+        self.mounts.lock().unwrap().contains_key(mount_point)
+    }
+    
+    /// Checks that this Mac can mount with FSKit: it runs macOS 15 or
+    /// later, and the ShadowFS module is installed and enabled.
+    pub async fn check_available() -> Result<()> {
+        match macos_major_version() {
+            Some(major) if major >= MINIMUM_MACOS_MAJOR => {}
+            _ => return Err(unsupported("FSKit mounts, which need macOS 15 or later")),
+        }
+        
+        let output = Command::new("/usr/bin/pluginkit")
+            .args(["-m", "-i", MODULE_BUNDLE_ID])
+            .output()
+            .await
+            .map_err(|e| ShadowError::from_io_error(e, None))?;
+        // pluginkit lists an installed extension, marked `+` once enabled
+        let listing = String::from_utf8_lossy(&output.stdout);
+        if !listing.lines().any(|line| line.trim_start().starts_with('+')) {
+            return Err(MountError::ExtensionNotActivated.into());
+        }
+        Ok(())
+    }
+    
+    /// Replaces the overrides of the store with the ones the module saved
+    /// to `snapshot`, then removes it.
+    fn take_back_overrides(&self, snapshot: &Path) -> Result<()> {
+        let data = std::fs::read(snapshot)
+            .map_err(|e| ShadowError::from_io_error(e, Some(&ShadowPath::new(snapshot.to_path_buf()))))?;
+        let saved = OverrideSnapshot::decode(&data)?;
+        
+        // Overrides reverted in the volume are missing from its snapshot
+        let reverted: Vec<ShadowPath> = OverrideSnapshot::from_store(&self.store).entries
+            .into_keys()
+            .filter(|path| !saved.entries.contains_key(path))
+            .collect();
+        self.store.remove_batch(&reverted);
+        saved.apply_to_store(&self.store)?;
+        
+        if let Err(e) = std::fs::remove_file(snapshot) {
+            warn!("Failed to remove FSKit snapshot {}: {}", snapshot.display(), e);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FileSystemProvider for FSKitHostProvider {
+    fn platform(&self) -> Platform {
+        Platform::MacOS
+    }
+    
+    async fn mount(&self, source: &Path, mount_point: &Path, options: &MountOptions) -> Result<()> {
+        if self.is_mounted(mount_point) {
+            return Err(ShadowError::AlreadyExists {
+                path: ShadowPath::new(mount_point.to_path_buf()),
+            });
+        }
+        Self::check_available().await?;
+        
+        let snapshot_dir = config_dir().join("fskit");
+        std::fs::create_dir_all(&snapshot_dir).map_err(|e| ShadowError::from_io_error(e, None))?;
+        let snapshot = snapshot_dir.join(format!("{}.snapshot", Uuid::new_v4()));
+        let module_options = ModuleOptions {
+            volume_name: mount_point.file_name().map(|name| name.to_string_lossy().into_owned()),
+            read_only: options.read_only,
+            snapshot: Some(snapshot.clone()),
+        };
+        let mount_option = module_options.to_mount_option()?;
+        self.store.save_snapshot(&snapshot)?;
+        
+        let output = Command::new("/sbin/mount")
+            .args(["-F", "-t", FS_SHORT_NAME, "-o", &mount_option])
+            .arg(source)
+            .arg(mount_point)
+            .output()
+            .await;
+        if let Err(e) = check_output("mount", output) {
+            let _ = std::fs::remove_file(&snapshot);
+            return Err(e);
+        }
+        
+        self.mounts.lock().unwrap().insert(mount_point.to_path_buf(), HostMount { snapshot });
+        info!("Mounted {} at {} with FSKit", source.display(), mount_point.display());
+        Ok(())
+    }
+    
+    async fn unmount(&self, mount_point: &Path) -> Result<()> {
+        let mount = self.mounts.lock().unwrap().remove(mount_point)
+            .ok_or_else(|| not_mounted(ShadowPath::new(mount_point.to_path_buf())))?;
+        
+        let output = Command::new("/sbin/umount").arg(mount_point).output().await;
+        if let Err(e) = check_output("umount", output) {
+            self.mounts.lock().unwrap().insert(mount_point.to_path_buf(), mount);
+            return Err(e);
+        }
+        
+        self.take_back_overrides(&mount.snapshot)?;
+        info!("Unmounted FSKit volume at {}", mount_point.display());
+        Ok(())
+    }
+}
+
+/// Returns the major version of the running macOS.
+fn macos_major_version() -> Option<u32> {
+    let name = CString::new("kern.osproductversion").ok()?;
+    let mut buffer = [0u8; 32];
+    let mut len = buffer.len();
+    let status = unsafe {
+        libc::sysctlbyname(name.as_ptr(), buffer.as_mut_ptr().cast(), &mut len, std::ptr::null_mut(), 0)
+    };
+    if status != 0 {
+        return None;
+    }
+    let version = std::str::from_utf8(&buffer[..len]).ok()?;
+    parse_major_version(version.trim_end_matches('\0'))
+}
+
+/// Parses the major version of a `major.minor.patch` version.
+fn parse_major_version(version: &str) -> Option<u32> {
+    version.split('.').next()?.parse().ok()
+}
+
+/// Turns the outcome of running `command` into an error if it failed.
+fn check_output(command: &str, output: std::io::Result<Output>) -> Result<()> {
+    let output = output.map_err(|e| ShadowError::from_io_error(e, None))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if stderr.contains("Operation not permitted") {
+        return Err(MountError::PermissionDenied.into());
+    }
+    Err(ShadowError::PlatformError {
+        platform: ErrorPlatform::MacOS,
+        message: format!("{} failed: {}", command, stderr),
+        code: output.status.code(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    
+    fn output(code: i32, stderr: &str) -> std::io::Result<Output> {
+        Ok(Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        })
+    }
+    
+    #[test]
+    fn test_failed_commands_become_shadow_errors() {
+        assert!(check_output("mount", output(0, "")).is_ok());
+        
+        let error = check_output("mount", output(1, "mount: File system named shadowfs not found")).unwrap_err();
+        match error {
+            ShadowError::PlatformError { platform, message, code } => {
+                assert_eq!(platform, ErrorPlatform::MacOS);
+                assert!(message.starts_with("mount failed: "));
+                assert_eq!(code, Some(1));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        
+        let error = check_output("mount", output(1, "mount: Operation not permitted")).unwrap_err();
+        assert!(matches!(error, ShadowError::Unauthorized { .. }));
+        
+        assert_eq!(parse_major_version("15.4.1"), Some(15));
+        assert_eq!(parse_major_version("26"), Some(26));
+        assert_eq!(parse_major_version(""), None);
+    }
+    
+    #[tokio::test]
+    async fn test_unmounting_an_unknown_mount_point_fails() {
+        let provider = FSKitHostProvider::new(Arc::new(OverrideStore::with_defaults()));
+        let error = provider.unmount(Path::new("/Volumes/missing")).await.unwrap_err();
+        assert!(matches!(error, ShadowError::NotMounted { .. }));
+        assert!(provider.context(Path::new("/Volumes/missing")).is_none());
+    }
+}
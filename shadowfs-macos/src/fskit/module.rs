@@ -0,0 +1,773 @@
+//! The FSKit module: the side of a mount that runs in the file system
+//! extension.
+//!
+//! On macOS 15 and later a file system is an FSKit module, an app extension
+//! of the [`FSMODULE_EXTENSION_POINT`] extension point. `mount -F -t
+//! shadowfs <source> <mount point>` has FSKit start the extension and hand
+//! the source directory, as a resource, to its principal class: a
+//! [`ShadowFSModule`], the `FSUnaryFileSystem` of ShadowFS. Loading the
+//! resource returns a [`ShadowFSVolume`], whose operations
+//! [`FSOperationsImpl`] serves. A module serves one volume at a time.
+//!
+//! The `-o` options of `mount` reach the module as [`ModuleOptions`]: the
+//! volume name, whether the volume is read-only, and the path of a snapshot
+//! of the override store. The module loads the overrides of the snapshot
+//! into the volume, and writes the overrides of the volume back to it when
+//! the volume is unmounted, so the process that mounted it can pick them
+//! up; see [`FSKitHostProvider`](super::host::FSKitHostProvider).
+//!
+//! FSKit takes a volume through the phases of [`VolumePhase`] in order:
+//! loaded, mounted, activated, and unmounted. [`ShadowVolume`] keeps the
+//! phase and refuses calls made out of order.
+//!
+//! The module is built into an app extension bundle, `ShadowFS.appex`,
+//! whose executable calls [`extension_main`]. Its `Info.plist` names the
+//! extension point under `EXAppExtensionAttributes`, `ShadowFSModule` as the
+//! principal class and `shadowfs` as the `FSShortName`, and the bundle is
+//! signed with the `com.apple.developer.fskit.fsmodule` entitlement. The
+//! extension must also be enabled under System Settings > General > Login
+//! Items & Extensions > File System Extensions.
+
+use super::bindings::{
+    nsstring_to_path, posix_error, FSDirectory, FSFile, FSGenericURLResource, FSItem, FSItemType as FSKitItemType,
+    FSOperations, FSProbeResult, FSTaskOptions, FSUnaryFileSystem, FSVolume,
+};
+use super::mount::MountError;
+use super::operations::{FSItemType, FSOperationsImpl};
+use super::provider::{FSKitConfig, FSKitProvider, QueuePriority};
+use block2::Block;
+use objc2::rc::{Allocated, Id};
+use objc2::runtime::{AnyObject, NSObjectProtocol};
+use objc2::{declare_class, msg_send_id, mutability, ClassType, DeclaredClass};
+use objc2_foundation::{NSArray, NSData, NSDictionary, NSError, NSNumber, NSObject, NSString, NSUUID};
+use shadowfs_core::error::ShadowError;
+use shadowfs_core::override_store::OverrideStore;
+use std::ffi::{c_char, c_int, CString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Extension point of FSKit modules.
+pub const FSMODULE_EXTENSION_POINT: &str = "com.apple.fskit.fsmodule";
+
+/// Bundle identifier of the ShadowFS module.
+pub const MODULE_BUNDLE_ID: &str = "com.shadowfs.fskit.module";
+
+/// File system type `mount -F -t` loads the module for.
+pub const FS_SHORT_NAME: &str = "shadowfs";
+
+/// Name of volumes mounted without one.
+const DEFAULT_VOLUME_NAME: &str = "ShadowFS";
+
+/// Mount options the module understands.
+///
+/// They travel as the comma-separated `-o` options of `mount`, so the
+/// values cannot hold commas. Options the module does not know, such as
+/// the ones `mount` adds itself, are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleOptions {
+    /// Name of the volume, as Finder shows it
+    pub volume_name: Option<String>,
+    
+    /// Whether the volume refuses changes
+    pub read_only: bool,
+    
+    /// Snapshot of the override store the volume starts from and is saved to
+    pub snapshot: Option<PathBuf>,
+}
+
+impl ModuleOptions {
+    /// Parses the options FSKit passes to the module: `-o` arguments and
+    /// their comma-separated lists.
+    pub fn parse<'a>(args: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut options = Self::default();
+        for option in args.into_iter().filter(|arg| *arg != "-o").flat_map(|arg| arg.split(',')) {
+            match option.split_once('=') {
+                Some(("volname", name)) => options.volume_name = Some(name.to_string()),
+                Some(("snapshot", path)) => options.snapshot = Some(PathBuf::from(path)),
+                None if option == "rdonly" || option == "ro" => options.read_only = true,
+                None if option == "rw" => options.read_only = false,
+                _ => {}
+            }
+        }
+        options
+    }
+    
+    /// Formats the options as the argument of `mount -o`.
+    ///
+    /// # Errors
+    /// Returns an error if a value holds a comma.
+    pub fn to_mount_option(&self) -> Result<String, ShadowError> {
+        let mut options = vec![if self.read_only { "rdonly" } else { "rw" }.to_string()];
+        if let Some(name) = &self.volume_name {
+            options.push(format!("volname={}", name));
+        }
+        if let Some(snapshot) = &self.snapshot {
+            options.push(format!("snapshot={}", snapshot.display()));
+        }
+        if let Some(option) = options.iter().find(|option| option.contains(',')) {
+            return Err(ShadowError::InvalidConfiguration {
+                message: format!("FSKit mount option '{}' cannot hold a comma", option),
+            });
+        }
+        Ok(options.join(","))
+    }
+}
+
+/// Where a volume is in the sequence of calls FSKit makes to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumePhase {
+    /// Returned from `loadResource`, not mounted yet
+    Loaded,
+    
+    /// Mounted, with no root item handed out yet
+    Mounted,
+    
+    /// Activated: FSKit has the root item and sends operations
+    Active,
+    
+    /// Unmounted; the volume is done
+    Unmounted,
+}
+
+impl VolumePhase {
+    /// Returns the phase `action` takes a volume in this phase to.
+    ///
+    /// # Errors
+    /// Returns an error if `action` is not expected in this phase.
+    pub fn after(self, action: VolumeAction) -> Result<VolumePhase, ShadowError> {
+        match (self, action) {
+            (VolumePhase::Loaded, VolumeAction::Mount) => Ok(VolumePhase::Mounted),
+            (VolumePhase::Mounted, VolumeAction::Activate) => Ok(VolumePhase::Active),
+            (VolumePhase::Active, VolumeAction::Deactivate) => Ok(VolumePhase::Mounted),
+            // FSKit may unmount without deactivating first
+            (VolumePhase::Mounted | VolumePhase::Active, VolumeAction::Unmount) => Ok(VolumePhase::Unmounted),
+            (phase, action) => Err(MountError::SystemError(format!("cannot {:?} a volume that is {:?}", action, phase)).into()),
+        }
+    }
+}
+
+/// A call FSKit makes to move a volume between phases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeAction {
+    Mount,
+    Activate,
+    Deactivate,
+    Unmount,
+}
+
+/// The volume of one source directory, as the module serves it.
+pub struct ShadowVolume {
+    source: PathBuf,
+    options: ModuleOptions,
+    volume_id: Uuid,
+    /// Kept alive for `operations`, which only holds it weakly
+    _provider: Arc<FSKitProvider>,
+    operations: Arc<FSOperationsImpl>,
+    phase: Mutex<VolumePhase>,
+}
+
+impl ShadowVolume {
+    /// Creates the volume of `source`, loading the overrides of the
+    /// snapshot `options` names, if any.
+    pub fn load(source: &Path, options: ModuleOptions) -> Result<Self, ShadowError> {
+        if !source.is_dir() {
+            return Err(MountError::ValidationFailed(format!("Source path is not a directory: {}", source.display())).into());
+        }
+        
+        let volume_id = Uuid::new_v4();
+        let mut provider = FSKitProvider::new(FSKitConfig {
+            volume_name: options.volume_name.clone().unwrap_or_else(|| DEFAULT_VOLUME_NAME.to_string()),
+            volume_uuid: volume_id,
+            case_sensitive: false,
+            supports_extended_attrs: true,
+            dispatch_queue_priority: QueuePriority::Default,
+            max_readahead_size: 1024 * 1024,
+        });
+        // FSKit hands the module paths relative to the volume root
+        provider.set_paths(source.to_path_buf(), PathBuf::from("/"));
+        let provider = Arc::new(provider);
+        let operations = Arc::new(FSOperationsImpl::new(Arc::downgrade(&provider)));
+        
+        if let Some(snapshot) = &options.snapshot {
+            let store = OverrideStore::load_snapshot(snapshot)?;
+            let loaded = operations.import_overrides(&store).map_err(MountError::VolumeCreationFailed)?;
+            info!("Loaded {} overrides from {}", loaded, snapshot.display());
+        }
+        
+        Ok(Self {
+            source: source.to_path_buf(),
+            options,
+            volume_id,
+            _provider: provider,
+            operations,
+            phase: Mutex::new(VolumePhase::Loaded),
+        })
+    }
+    
+    /// Returns the source directory of the volume.
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+    
+    /// Returns the name of the volume.
+    pub fn volume_name(&self) -> &str {
+        self.options.volume_name.as_deref().unwrap_or(DEFAULT_VOLUME_NAME)
+    }
+    
+    /// Returns whether the volume refuses changes.
+    pub fn is_read_only(&self) -> bool {
+        self.options.read_only
+    }
+    
+    /// Returns the phase the volume is in.
+    pub fn phase(&self) -> VolumePhase {
+        *self.phase.lock().unwrap()
+    }
+    
+    /// Returns the operations serving the volume.
+    pub fn operations(&self) -> &Arc<FSOperationsImpl> {
+        &self.operations
+    }
+    
+    /// Marks the volume mounted.
+    pub fn mount(&self) -> Result<(), ShadowError> {
+        self.advance(VolumeAction::Mount)
+    }
+    
+    /// Marks the volume active, serving operations.
+    pub fn activate(&self) -> Result<(), ShadowError> {
+        self.advance(VolumeAction::Activate)
+    }
+    
+    /// Marks the volume mounted but no longer serving operations.
+    pub fn deactivate(&self) -> Result<(), ShadowError> {
+        self.advance(VolumeAction::Deactivate)
+    }
+    
+    /// Saves the overrides of the volume and marks it unmounted.
+    pub fn unmount(&self) -> Result<(), ShadowError> {
+        let mut phase = self.phase.lock().unwrap();
+        let next = phase.after(VolumeAction::Unmount)?;
+        self.save_overrides()?;
+        *phase = next;
+        Ok(())
+    }
+    
+    /// Writes the overrides made through the volume to its snapshot, if it
+    /// was given one.
+    pub fn save_overrides(&self) -> Result<(), ShadowError> {
+        let Some(snapshot) = &self.options.snapshot else {
+            return Ok(());
+        };
+        let store = OverrideStore::with_defaults();
+        let saved = self.operations.export_overrides(&store).map_err(MountError::SystemError)?;
+        store.save_snapshot(snapshot)?;
+        info!("Saved {} overrides to {}", saved, snapshot.display());
+        Ok(())
+    }
+    
+    fn advance(&self, action: VolumeAction) -> Result<(), ShadowError> {
+        let mut phase = self.phase.lock().unwrap();
+        *phase = phase.after(action)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ShadowVolume {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShadowVolume")
+            .field("source", &self.source)
+            .field("options", &self.options)
+            .field("volume_id", &self.volume_id)
+            .field("phase", &self.phase())
+            .finish()
+    }
+}
+
+/// The `FSUnaryFileSystem` side of the module, without the Objective-C
+/// glue: which resources it takes, and the one volume it serves.
+#[derive(Debug, Default)]
+pub struct ShadowUnaryFileSystem {
+    volume: Mutex<Option<Arc<ShadowVolume>>>,
+}
+
+impl ShadowUnaryFileSystem {
+    /// Returns true if `resource` is a source directory the module can mount.
+    pub fn probe(&self, resource: &Path) -> bool {
+        resource.is_dir()
+    }
+    
+    /// Loads the volume of the source directory `resource`.
+    ///
+    /// # Errors
+    /// Returns an error if the module already serves a volume, or if the
+    /// volume cannot be created.
+    pub fn load(&self, resource: &Path, options: ModuleOptions) -> Result<Arc<ShadowVolume>, ShadowError> {
+        let mut volume = self.volume.lock().unwrap();
+        if let Some(loaded) = volume.as_ref() {
+            return Err(MountError::AlreadyMounted(loaded.source().to_path_buf()).into());
+        }
+        let loaded = Arc::new(ShadowVolume::load(resource, options)?);
+        info!("Loaded volume {} of {}", loaded.volume_name(), resource.display());
+        *volume = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+    
+    /// Drops the volume, saving its overrides if FSKit did not unmount it.
+    pub fn unload(&self) -> Result<(), ShadowError> {
+        let volume = self.volume.lock().unwrap().take().ok_or(MountError::NotMounted)?;
+        if volume.phase() != VolumePhase::Unmounted {
+            warn!("Unloading volume {} that was not unmounted", volume.volume_name());
+            volume.save_overrides()?;
+        }
+        Ok(())
+    }
+    
+    /// Returns the volume the module serves, if any.
+    pub fn volume(&self) -> Option<Arc<ShadowVolume>> {
+        self.volume.lock().unwrap().clone()
+    }
+}
+
+declare_class!(
+    /// The principal class of the module, FSKit's entry into it.
+    pub struct ShadowFSModule;
+    
+    unsafe impl ClassType for ShadowFSModule {
+        #[inherits(NSObject)]
+        type Super = FSUnaryFileSystem;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "ShadowFSModule";
+    }
+    
+    impl DeclaredClass for ShadowFSModule {
+        type Ivars = ShadowUnaryFileSystem;
+    }
+    
+    unsafe impl ShadowFSModule {
+        #[method_id(init)]
+        fn init(this: Allocated<Self>) -> Option<Id<Self>> {
+            let this = this.set_ivars(ShadowUnaryFileSystem::default());
+            unsafe { msg_send_id![super(this), init] }
+        }
+        
+        #[method(probeResource:replyHandler:)]
+        fn probe_resource(
+            &self,
+            resource: &FSGenericURLResource,
+            reply: &Block<dyn Fn(*mut FSProbeResult, *mut NSError)>,
+        ) {
+            let result = match resource_path(resource) {
+                Some(path) if self.ivars().probe(&path) => {
+                    let name = NSString::from_str(&path.file_name().unwrap_or_default().to_string_lossy());
+                    FSProbeResult::usable(&name, &NSUUID::new())
+                }
+                _ => FSProbeResult::not_recognized(),
+            };
+            reply.call((Id::as_ptr(&result).cast_mut(), ptr::null_mut()));
+        }
+        
+        #[method(loadResource:options:replyHandler:)]
+        fn load_resource(
+            &self,
+            resource: &FSGenericURLResource,
+            options: &FSTaskOptions,
+            reply: &Block<dyn Fn(*mut FSVolume, *mut NSError)>,
+        ) {
+            let args: Vec<String> = options.task_options().iter().map(|option| option.to_string()).collect();
+            let loaded = resource_path(resource)
+                .ok_or_else(|| MountError::ValidationFailed("FSKit resource has no path".into()).into())
+                .and_then(|source| self.ivars().load(&source, ModuleOptions::parse(args.iter().map(String::as_str))));
+            match loaded {
+                Ok(volume) => {
+                    let volume = ShadowFSVolume::new(volume);
+                    reply.call((Id::as_ptr(&volume).cast_mut().cast(), ptr::null_mut()));
+                }
+                Err(e) => reply.call((ptr::null_mut(), Id::as_ptr(&shadow_error(&e)).cast_mut())),
+            }
+        }
+        
+        #[method(unloadResource:options:replyHandler:)]
+        fn unload_resource(
+            &self,
+            _resource: &FSGenericURLResource,
+            _options: &FSTaskOptions,
+            reply: &Block<dyn Fn(*mut NSError)>,
+        ) {
+            reply_status(reply, self.ivars().unload());
+        }
+    }
+);
+
+declare_class!(
+    /// The volume FSKit talks to, forwarding its operations to the
+    /// [`FSOperationsImpl`] of a [`ShadowVolume`].
+    pub struct ShadowFSVolume;
+    
+    unsafe impl ClassType for ShadowFSVolume {
+        #[inherits(NSObject)]
+        type Super = FSVolume;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "ShadowFSVolume";
+    }
+    
+    impl DeclaredClass for ShadowFSVolume {
+        type Ivars = Arc<ShadowVolume>;
+    }
+    
+    unsafe impl NSObjectProtocol for ShadowFSVolume {}
+    
+    unsafe impl ShadowFSVolume {
+        #[method(mountWithOptions:replyHandler:)]
+        fn mount_with_options(&self, _options: &FSTaskOptions, reply: &Block<dyn Fn(*mut NSError)>) {
+            reply_status(reply, self.ivars().mount());
+        }
+        
+        #[method(activateWithOptions:replyHandler:)]
+        fn activate_with_options(&self, _options: &FSTaskOptions, reply: &Block<dyn Fn(*mut FSItem, *mut NSError)>) {
+            let root = self.ivars().activate().and_then(|()| {
+                self.ivars().operations().root_item().map_err(|e| MountError::MountFailed(e).into())
+            });
+            match root {
+                Ok(root) => reply.call((root.cast(), ptr::null_mut())),
+                Err(e) => reply.call((ptr::null_mut(), Id::as_ptr(&shadow_error(&e)).cast_mut())),
+            }
+        }
+        
+        #[method(deactivateWithOptions:replyHandler:)]
+        fn deactivate_with_options(&self, _options: &FSTaskOptions, reply: &Block<dyn Fn(*mut NSError)>) {
+            reply_status(reply, self.ivars().deactivate());
+        }
+        
+        #[method(unmountWithReplyHandler:)]
+        fn unmount_with_reply_handler(&self, reply: &Block<dyn Fn(*mut NSError)>) {
+            reply_status(reply, self.ivars().unmount());
+        }
+    }
+    
+    unsafe impl FSOperations for ShadowFSVolume {
+        #[method(lookupItemNamed:inDirectory:replyHandler:)]
+        unsafe fn lookup_item_named(
+            &self,
+            name: &NSString,
+            directory: &FSDirectory,
+            reply: &Block<dyn Fn(*mut FSItem, *mut NSError)>,
+        ) {
+            reply_item(reply, self.ivars().operations().lookup_item_named(directory.as_ref(), &name.to_string()));
+        }
+        
+        #[method(enumerateDirectory:startingAtOffset:replyHandler:)]
+        unsafe fn enumerate_directory(
+            &self,
+            directory: &FSDirectory,
+            offset: i64,
+            reply: &Block<dyn Fn(*mut NSArray<FSItem>, *mut NSError)>,
+        ) {
+            // The whole listing goes out with the first call
+            if offset > 0 {
+                let empty = NSArray::<FSItem>::new();
+                return reply.call((Id::as_ptr(&empty).cast_mut(), ptr::null_mut()));
+            }
+            match self.ivars().operations().read_directory(directory.as_ref()) {
+                Ok(entries) => reply.call((entries.cast(), ptr::null_mut())),
+                Err(e) => reply.call((ptr::null_mut(), Id::as_ptr(&operation_error(&e)).cast_mut())),
+            }
+        }
+        
+        #[method(readContentsOfFile:atOffset:length:replyHandler:)]
+        unsafe fn read_contents_of_file(
+            &self,
+            file: &FSFile,
+            offset: i64,
+            length: i64,
+            reply: &Block<dyn Fn(*mut NSData, *mut NSError)>,
+        ) {
+            let operations = self.ivars().operations();
+            let read = operations.open_file(file.as_ref(), libc::O_RDONLY as u32).and_then(|handle| {
+                let data = operations.read_file(handle, offset.max(0) as u64, length.max(0) as usize);
+                operations.close_file(handle)?;
+                data
+            });
+            match read {
+                Ok(data) => {
+                    let data = NSData::with_bytes(&data);
+                    reply.call((Id::as_ptr(&data).cast_mut(), ptr::null_mut()));
+                }
+                Err(e) => reply.call((ptr::null_mut(), Id::as_ptr(&operation_error(&e)).cast_mut())),
+            }
+        }
+        
+        #[method(writeContentsToFile:atOffset:data:replyHandler:)]
+        unsafe fn write_contents_to_file(
+            &self,
+            file: &FSFile,
+            offset: i64,
+            data: &NSData,
+            reply: &Block<dyn Fn(i64, *mut NSError)>,
+        ) {
+            if self.ivars().is_read_only() {
+                return reply.call((0, Id::as_ptr(&read_only_error()).cast_mut()));
+            }
+            let operations = self.ivars().operations();
+            let written = operations.open_file(file.as_ref(), libc::O_WRONLY as u32).and_then(|handle| {
+                let written = operations.write_file(handle, offset.max(0) as u64, data.bytes());
+                operations.close_file(handle)?;
+                written
+            });
+            match written {
+                Ok(written) => reply.call((written as i64, ptr::null_mut())),
+                Err(e) => reply.call((0, Id::as_ptr(&operation_error(&e)).cast_mut())),
+            }
+        }
+        
+        #[method(createItemNamed:type:inDirectory:attributes:replyHandler:)]
+        unsafe fn create_item_named(
+            &self,
+            name: &NSString,
+            item_type: FSKitItemType,
+            directory: &FSDirectory,
+            _attributes: &NSDictionary,
+            reply: &Block<dyn Fn(*mut FSItem, *mut NSError)>,
+        ) {
+            if self.ivars().is_read_only() {
+                return reply.call((ptr::null_mut(), Id::as_ptr(&read_only_error()).cast_mut()));
+            }
+            let item_type = match item_type {
+                FSKitItemType::Directory => FSItemType::Directory,
+                FSKitItemType::SymbolicLink => FSItemType::SymbolicLink,
+                _ => FSItemType::File,
+            };
+            reply_item(reply, self.ivars().operations().create_item_named(directory.as_ref(), &name.to_string(), item_type, None));
+        }
+        
+        #[method(deleteItem:replyHandler:)]
+        unsafe fn delete_item(&self, item: &FSItem, reply: &Block<dyn Fn(*mut NSError)>) {
+            if self.ivars().is_read_only() {
+                return reply.call((Id::as_ptr(&read_only_error()).cast_mut(),));
+            }
+            reply_operation(reply, self.ivars().operations().remove_item(item.as_ref()));
+        }
+        
+        #[method(renameItem:toName:replyHandler:)]
+        unsafe fn rename_item(
+            &self,
+            item: &FSItem,
+            new_name: &NSString,
+            reply: &Block<dyn Fn(*mut FSItem, *mut NSError)>,
+        ) {
+            if self.ivars().is_read_only() {
+                return reply.call((ptr::null_mut(), Id::as_ptr(&read_only_error()).cast_mut()));
+            }
+            match self.ivars().operations().rename_item(item.as_ref(), &new_name.to_string(), None) {
+                Ok(()) => reply.call((item as *const FSItem as *mut FSItem, ptr::null_mut())),
+                Err(e) => reply.call((ptr::null_mut(), Id::as_ptr(&operation_error(&e)).cast_mut())),
+            }
+        }
+        
+        #[method(getAttributesOfItem:replyHandler:)]
+        unsafe fn get_attributes_of_item(
+            &self,
+            item: &FSItem,
+            reply: &Block<dyn Fn(*mut NSDictionary, *mut NSError)>,
+        ) {
+            match self.ivars().operations().get_attributes(item.as_ref()) {
+                Ok(attributes) => {
+                    let keys = ["size", "mode", "uid", "gid", "atime", "mtime", "ctime"].map(NSString::from_str);
+                    let values = vec![
+                        NSNumber::new_u64(attributes.size),
+                        NSNumber::new_u32(attributes.mode),
+                        NSNumber::new_u32(attributes.uid),
+                        NSNumber::new_u32(attributes.gid),
+                        NSNumber::new_i64(attributes.atime),
+                        NSNumber::new_i64(attributes.mtime),
+                        NSNumber::new_i64(attributes.ctime),
+                    ];
+                    let keys: Vec<&NSString> = keys.iter().map(|key| &**key).collect();
+                    let attributes = NSDictionary::from_keys_and_objects(&keys, values);
+                    reply.call((Id::as_ptr(&attributes).cast_mut().cast(), ptr::null_mut()));
+                }
+                Err(e) => reply.call((ptr::null_mut(), Id::as_ptr(&operation_error(&e)).cast_mut())),
+            }
+        }
+        
+        #[method(setAttributes:onItem:replyHandler:)]
+        unsafe fn set_attributes_on_item(
+            &self,
+            _attributes: &NSDictionary,
+            _item: &FSItem,
+            reply: &Block<dyn Fn(*mut NSError)>,
+        ) {
+            let error = posix_error(libc::ENOTSUP, "Setting attributes is not supported");
+            reply.call((Id::as_ptr(&error).cast_mut(),));
+        }
+    }
+);
+
+impl ShadowFSVolume {
+    /// Wraps `volume` for FSKit.
+    fn new(volume: Arc<ShadowVolume>) -> Id<Self> {
+        let volume_id = NSUUID::from_bytes(volume.volume_id.into_bytes());
+        let name = NSString::from_str(volume.volume_name());
+        let this = Self::alloc().set_ivars(volume);
+        unsafe { msg_send_id![super(this), initWithVolumeID: &*volume_id, volumeName: &*name] }
+    }
+}
+
+/// Runs the module: registers its classes with the Objective-C runtime, so
+/// FSKit finds the principal class, and hands the process over to the
+/// extension runtime.
+///
+/// The executable of the module bundle calls this from `main` and nothing
+/// else.
+pub fn extension_main() -> ! {
+    extern "C" {
+        fn NSExtensionMain(argc: c_int, argv: *const *const c_char) -> c_int;
+    }
+    
+    let _ = (ShadowFSModule::class(), ShadowFSVolume::class());
+    let args: Vec<CString> = std::env::args()
+        .map(|arg| CString::new(arg).unwrap_or_default())
+        .collect();
+    let argv: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).chain(std::iter::once(ptr::null())).collect();
+    let status = unsafe { NSExtensionMain(args.len() as c_int, argv.as_ptr()) };
+    std::process::exit(status)
+}
+
+/// Returns the local path of `resource`.
+fn resource_path(resource: &FSGenericURLResource) -> Option<PathBuf> {
+    unsafe { resource.url().path() }.map(|path| nsstring_to_path(&path))
+}
+
+/// Replies to FSKit with the outcome of a lifecycle call.
+fn reply_status(reply: &Block<dyn Fn(*mut NSError)>, result: Result<(), ShadowError>) {
+    match result {
+        Ok(()) => reply.call((ptr::null_mut(),)),
+        Err(e) => {
+            warn!("FSKit call failed: {}", e);
+            reply.call((Id::as_ptr(&shadow_error(&e)).cast_mut(),));
+        }
+    }
+}
+
+/// Replies to FSKit with the item an operation returned.
+fn reply_item(reply: &Block<dyn Fn(*mut FSItem, *mut NSError)>, result: Result<*mut AnyObject, String>) {
+    match result {
+        Ok(item) => reply.call((item.cast(), ptr::null_mut())),
+        Err(e) => reply.call((ptr::null_mut(), Id::as_ptr(&operation_error(&e)).cast_mut())),
+    }
+}
+
+/// Replies to FSKit with the outcome of an operation.
+fn reply_operation(reply: &Block<dyn Fn(*mut NSError)>, result: Result<(), String>) {
+    match result {
+        Ok(()) => reply.call((ptr::null_mut(),)),
+        Err(e) => reply.call((Id::as_ptr(&operation_error(&e)).cast_mut(),)),
+    }
+}
+
+/// Converts a lifecycle error to the POSIX error FSKit reports.
+fn shadow_error(error: &ShadowError) -> Id<NSError> {
+    let code = match error {
+        ShadowError::NotFound { .. } => libc::ENOENT,
+        ShadowError::AlreadyExists { .. } => libc::EBUSY,
+        ShadowError::NotMounted { .. } => libc::EINVAL,
+        ShadowError::InvalidConfiguration { .. } => libc::EINVAL,
+        ShadowError::Unauthorized { .. } | ShadowError::AccessDenied { .. } => libc::EPERM,
+        _ => libc::EIO,
+    };
+    posix_error(code, &error.to_string())
+}
+
+/// Converts the message of a failed operation to the POSIX error FSKit
+/// reports.
+///
+/// [`FSOperationsImpl`] reports failures as messages, so the error is told
+/// by what they say.
+fn operation_error(message: &str) -> Id<NSError> {
+    let lower = message.to_lowercase();
+    let code = if lower.contains("not found") || lower.contains("been deleted") {
+        libc::ENOENT
+    } else if lower.contains("already exists") {
+        libc::EEXIST
+    } else if lower.contains("not empty") {
+        libc::ENOTEMPTY
+    } else {
+        libc::EIO
+    };
+    posix_error(code, message)
+}
+
+fn read_only_error() -> Id<NSError> {
+    posix_error(libc::EROFS, "The volume is read-only")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use shadowfs_core::types::ShadowPath;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_options_round_trip_through_mount() {
+        let options = ModuleOptions {
+            volume_name: Some("Work".into()),
+            read_only: true,
+            snapshot: Some(PathBuf::from("/tmp/work.snapshot")),
+        };
+        let option = options.to_mount_option().unwrap();
+        assert_eq!(option, "rdonly,volname=Work,snapshot=/tmp/work.snapshot");
+        
+        // `mount` adds options of its own, and FSKit may pass `-o` apart
+        assert_eq!(ModuleOptions::parse(["-o", &option, "nobrowse"]), options);
+        assert_eq!(ModuleOptions::parse(["rw"]), ModuleOptions::default());
+        
+        let comma = ModuleOptions { volume_name: Some("a,b".into()), ..ModuleOptions::default() };
+        assert!(comma.to_mount_option().is_err());
+    }
+    
+    #[test]
+    fn test_volume_phases_follow_fskit_order() {
+        let source = TempDir::new().unwrap();
+        let module = ShadowUnaryFileSystem::default();
+        assert!(module.probe(source.path()));
+        assert!(!module.probe(&source.path().join("missing")));
+        
+        let volume = module.load(source.path(), ModuleOptions::default()).unwrap();
+        assert!(matches!(module.load(source.path(), ModuleOptions::default()), Err(ShadowError::AlreadyExists { .. })));
+        assert!(volume.activate().is_err());
+        
+        volume.mount().unwrap();
+        volume.activate().unwrap();
+        assert_eq!(volume.phase(), VolumePhase::Active);
+        volume.unmount().unwrap();
+        assert!(volume.mount().is_err());
+        
+        module.unload().unwrap();
+        assert!(module.volume().is_none());
+        assert!(matches!(module.unload(), Err(ShadowError::NotMounted { .. })));
+    }
+    
+    #[test]
+    fn test_overrides_come_back_through_the_snapshot() {
+        let source = TempDir::new().unwrap();
+        let snapshot = source.path().join("overrides.snapshot");
+        let store = OverrideStore::with_defaults();
+        store.insert_file(ShadowPath::from("/notes.txt"), Bytes::from("draft"), None).unwrap();
+        store.mark_deleted(ShadowPath::from("/old.txt")).unwrap();
+        store.save_snapshot(&snapshot).unwrap();
+        
+        let options = ModuleOptions { snapshot: Some(snapshot.clone()), ..ModuleOptions::default() };
+        let volume = ShadowVolume::load(source.path(), options).unwrap();
+        volume.mount().unwrap();
+        volume.unmount().unwrap();
+        
+        let saved = OverrideStore::load_snapshot(&snapshot).unwrap();
+        let notes = saved.get(&ShadowPath::from("/notes.txt")).unwrap();
+        assert_eq!(notes.get_file_data().unwrap().unwrap(), Bytes::from("draft"));
+        assert!(saved.is_deleted(&ShadowPath::from("/old.txt")));
+    }
+}
@@ -25,8 +25,11 @@ use crate::fskit::provider::{FSKitProvider, FSKitConfig, QueuePriority};
 use crate::fskit::operations::FSOperationsImpl;
 use crate::fskit::finder_integration::{FinderIntegration, FinderLabel};
 
+use shadowfs_core::error::{Platform, ShadowError};
+use shadowfs_core::hidden::HiddenFiles;
 use shadowfs_core::override_store::OverrideStore;
 use shadowfs_core::stats::{FileSystemStats, OperationType};
+use shadowfs_core::types::ShadowPath;
 
 /// Error types for mount operations
 #[derive(Debug, Error)]
@@ -59,6 +62,35 @@ pub enum MountError {
     SystemError(String),
 }
 
+impl From<MountError> for ShadowError {
+    fn from(error: MountError) -> Self {
+        match error {
+            MountError::AlreadyMounted(mount_point) => ShadowError::AlreadyExists {
+                path: ShadowPath::new(mount_point),
+            },
+            MountError::NotMounted => ShadowError::NotMounted {
+                mount_point: ShadowPath::from("/"),
+            },
+            MountError::ValidationFailed(message) => ShadowError::InvalidConfiguration { message },
+            MountError::PermissionDenied => ShadowError::Unauthorized {
+                operation: "mount with FSKit".to_string(),
+                reason: "the ShadowFS file system extension is not allowed to mount".to_string(),
+            },
+            MountError::ExtensionNotActivated => ShadowError::PlatformError {
+                platform: Platform::MacOS,
+                message: "the ShadowFS file system extension is not enabled; turn it on under System Settings > \
+                          General > Login Items & Extensions > File System Extensions".to_string(),
+                code: None,
+            },
+            error => ShadowError::PlatformError {
+                platform: Platform::MacOS,
+                message: error.to_string(),
+                code: None,
+            },
+        }
+    }
+}
+
 /// Mount options for the filesystem
 #[derive(Debug, Clone)]
 pub struct MountOptions {
@@ -97,6 +129,9 @@ pub struct MountOptions {
     
     /// Cache size in bytes
     pub cache_size: usize,
+    
+    /// Entries left out of listings, and possibly lookups
+    pub hidden_files: HiddenFiles,
 }
 
 impl Default for MountOptions {
@@ -116,6 +151,7 @@ impl Default for MountOptions {
             debug_logging: false,
             enable_profiling: false,
             cache_size: 64 * 1024 * 1024, // 64MB
+            hidden_files: HiddenFiles::default(),
         }
     }
 }
@@ -394,7 +430,7 @@ impl FileSystem for FSKitMount {
     #[instrument(skip(self, options))]
     fn mount(&self, source: &Path, target: &Path, options: MountOptions) -> Result<(), MountError> {
         // Use tokio runtime for async operations
-        let runtime = tokio::runtime::Runtime::new()
+        let runtime = shadowfs_core::task::runtime("fskit-mount")
             .map_err(|e| MountError::SystemError(e.to_string()))?;
         
         runtime.block_on(async {
@@ -418,6 +454,7 @@ impl FileSystem for FSKitMount {
             unsafe {
                 volume.set_delegate(self.operations.as_objc());
             }
+            self.operations.set_hidden_files(options.hidden_files.clone());
             
             // Configure capabilities
             if options.read_only {
@@ -467,7 +504,7 @@ impl FileSystem for FSKitMount {
     
     #[instrument(skip(self))]
     fn unmount(&self) -> Result<(), MountError> {
-        let runtime = tokio::runtime::Runtime::new()
+        let runtime = shadowfs_core::task::runtime("fskit-mount")
             .map_err(|e| MountError::SystemError(e.to_string()))?;
         
         runtime.block_on(async {
@@ -529,7 +566,7 @@ impl FileSystem for FSKitMount {
     }
     
     fn mount_info(&self) -> Option<MountInfo> {
-        let runtime = tokio::runtime::Runtime::new().ok()?;
+        let runtime = shadowfs_core::task::runtime("fskit-mount").ok()?;
         
         runtime.block_on(async {
             let state = self.state.read().ok()?;
@@ -767,6 +804,19 @@ mod tests {
         assert_eq!(options.max_concurrent_ops, 100);
     }
     
+    #[test]
+    fn test_mount_errors_become_shadow_errors() {
+        let error = ShadowError::from(MountError::AlreadyMounted(PathBuf::from("/Volumes/work")));
+        assert!(matches!(error, ShadowError::AlreadyExists { .. }));
+        
+        let error = ShadowError::from(MountError::ExtensionNotActivated);
+        assert!(matches!(error, ShadowError::PlatformError { platform: Platform::MacOS, .. }));
+        assert!(error.to_string().contains("File System Extensions"));
+        
+        let error = ShadowError::from(MountError::ValidationFailed("no source".into()));
+        assert!(matches!(error, ShadowError::InvalidConfiguration { .. }));
+    }
+    
     #[test]
     fn test_debug_logger() {
         let logger = DebugLogger::new();
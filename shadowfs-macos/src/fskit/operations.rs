@@ -7,6 +7,15 @@ use std::sync::{Arc, RwLock};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::ffi::{CStr, OsStr, OsString};
+use shadowfs_core::hidden::HiddenFiles;
+use shadowfs_core::mount_paths;
+use shadowfs_core::override_store::{OverrideContent, OverrideSnapshot, OverrideStore as CoreOverrideStore};
+use shadowfs_core::telemetry::operation_span;
+use shadowfs_core::handles::HandleTable;
+use shadowfs_core::types::{FileHandle, OpenFlags, ShadowPath};
+use tracing::span::EnteredSpan;
+use tracing::Span;
+use bytes::Bytes;
 
 #[cfg(unix)]
 use libc;
@@ -18,19 +27,55 @@ pub struct FSOperationsImpl {
     override_store: Arc<RwLock<OverrideStore>>,
     xattr_handler: Arc<RwLock<ExtendedAttributesHandler>>,
     case_sensitive: bool,
+    /// Entries left out of listings, and possibly lookups, set when mounting
+    hidden_files: RwLock<HiddenFiles>,
+    /// Span of the mount, which each operation's span is opened under
+    span: Span,
 }
 
 #[derive(Debug, Default)]
 struct OperationsState {
-    open_files: HashMap<u64, FileHandle>,
+    handles: HandleTable,
     active_operations: HashMap<u64, OperationType>,
-    next_handle_id: u64,
+    next_operation_id: u64,
 }
 
 #[derive(Debug, Default)]
 pub(super) struct OverrideStore {
     pub(super) items: HashMap<PathBuf, OverrideItem>,
     pub(super) deleted_paths: HashSet<PathBuf>,
+    /// Stored casing of each item, keyed by its case-folded path
+    folded: HashMap<ShadowPath, PathBuf>,
+}
+
+impl OverrideStore {
+    /// Stores `item` at `path`, indexing it for case-insensitive lookups
+    pub(super) fn insert_item(&mut self, path: PathBuf, item: OverrideItem) {
+        self.folded.entry(case_folded(&path)).or_insert_with(|| path.clone());
+        self.items.insert(path, item);
+    }
+    
+    pub(super) fn remove_item(&mut self, path: &Path) -> Option<OverrideItem> {
+        let item = self.items.remove(path)?;
+        let folded = case_folded(path);
+        if self.folded.get(&folded).is_some_and(|stored| stored == path) {
+            self.folded.remove(&folded);
+        }
+        Some(item)
+    }
+    
+    /// Returns the path of the item `path` names case-insensitively, or
+    /// `path` itself if there is none
+    pub(super) fn stored_casing(&self, path: &Path) -> PathBuf {
+        if self.items.contains_key(path) {
+            return path.to_path_buf();
+        }
+        self.folded.get(&case_folded(path)).cloned().unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+fn case_folded(path: &Path) -> ShadowPath {
+    ShadowPath::new(path.to_path_buf()).to_case_folded()
 }
 
 #[derive(Debug, Clone)]
@@ -60,14 +105,6 @@ struct DirectoryEntry {
 
 use std::collections::HashSet;
 
-#[derive(Debug, Clone)]
-struct FileHandle {
-    id: u64,
-    path: PathBuf,
-    flags: u32,
-    ref_count: usize,
-}
-
 #[derive(Debug, Clone)]
 enum OperationType {
     Read { offset: u64, length: usize },
@@ -85,6 +122,8 @@ impl FSOperationsImpl {
             override_store: Arc::new(RwLock::new(OverrideStore::default())),
             xattr_handler: Arc::new(RwLock::new(ExtendedAttributesHandler::new(ConflictResolution::UseOverride))),
             case_sensitive: false, // Default to case-insensitive for macOS
+            hidden_files: RwLock::new(HiddenFiles::default()),
+            span: Span::current(),
         }
     }
     
@@ -99,13 +138,115 @@ impl FSOperationsImpl {
             override_store: Arc::new(RwLock::new(OverrideStore::default())),
             xattr_handler: Arc::new(RwLock::new(ExtendedAttributesHandler::new(ConflictResolution::UseOverride))),
             case_sensitive,
+            hidden_files: RwLock::new(HiddenFiles::default()),
+            span: Span::current(),
+        }
+    }
+    
+    /// Sets which entries listings and lookups leave out.
+    pub fn set_hidden_files(&self, hidden: HiddenFiles) {
+        *self.hidden_files.write().unwrap() = hidden;
+    }
+    
+    /// Returns the item of the volume root, with the attributes of the
+    /// source root.
+    pub fn root_item(&self) -> Result<*mut AnyObject, String> {
+        let root = Path::new("/");
+        let metadata = std::fs::metadata(self.get_source_path(root)?)
+            .map_err(|e| format!("Failed to get metadata: {}", e))?;
+        let attributes = FileAttributes {
+            size: 0,
+            mode: self.get_file_mode(&metadata),
+            uid: self.get_uid(&metadata),
+            gid: self.get_gid(&metadata),
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        };
+        self.create_fs_item_with_attrs(root, FSItemType::Directory, attributes)
+    }
+    
+    /// Loads the overrides of `store` into the volume: files with their
+    /// content, directories, and deleted paths.
+    ///
+    /// # Returns
+    /// Number of overrides loaded
+    pub fn import_overrides(&self, store: &CoreOverrideStore) -> Result<usize, String> {
+        let snapshot = OverrideSnapshot::from_store(store);
+        let mut override_store = self.override_store.write()
+            .map_err(|e| format!("Failed to acquire override store lock: {}", e))?;
+        
+        for (path, entry) in &snapshot.entries {
+            let item_path = path.as_path().to_path_buf();
+            let item_type = match entry.content {
+                OverrideContent::Deleted => {
+                    override_store.deleted_paths.insert(item_path);
+                    continue;
+                }
+                OverrideContent::Directory { .. } => FSItemType::Directory,
+                OverrideContent::File { .. } => FSItemType::File,
+            };
+            let data = entry.get_file_data().map_err(|e| e.to_string())?;
+            let metadata = &entry.override_metadata;
+            let attributes = FileAttributes {
+                size: metadata.size,
+                mode: metadata.permissions.to_unix_mode(),
+                uid: self.get_current_uid(),
+                gid: self.get_current_gid(),
+                atime: metadata.accessed.since_unix_epoch().as_secs() as i64,
+                mtime: metadata.modified.since_unix_epoch().as_secs() as i64,
+                ctime: metadata.created.since_unix_epoch().as_secs() as i64,
+            };
+            override_store.insert_item(item_path.clone(), OverrideItem {
+                path: item_path,
+                item_type,
+                attributes,
+                data: data.map(|data| data.to_vec()),
+            });
         }
+        
+        Ok(snapshot.entries.len())
+    }
+    
+    /// Writes the overrides of the volume to `store`, keyed by their path
+    /// in the volume.
+    ///
+    /// # Returns
+    /// Number of overrides written
+    pub fn export_overrides(&self, store: &CoreOverrideStore) -> Result<usize, String> {
+        let override_store = self.override_store.read()
+            .map_err(|e| format!("Failed to acquire override store lock: {}", e))?;
+        
+        for path in &override_store.deleted_paths {
+            store.mark_deleted(self.get_shadow_path(path)?).map_err(|e| e.to_string())?;
+        }
+        for (path, item) in &override_store.items {
+            let path = self.get_shadow_path(path)?;
+            let written = match item.item_type {
+                FSItemType::Directory => store.insert_directory(path, None),
+                _ => store.insert_file(path, Bytes::from(item.data.clone().unwrap_or_default()), None),
+            };
+            written.map_err(|e| e.to_string())?;
+        }
+        
+        Ok(override_store.deleted_paths.len() + override_store.items.len())
+    }
+    
+    /// Enters the span of the operation `operation` on `path`.
+    fn enter(&self, operation: &'static str, path: &Path) -> EnteredSpan {
+        operation_span(&self.span, operation, path.display()).entered()
     }
 
     pub fn lookup_item_named(&self, parent: &AnyObject, name: &str) -> Result<*mut AnyObject, String> {
         // Build the full path for the item
         let parent_path = self.get_item_path(parent)?;
         let item_path = parent_path.join(name);
+        let _span = self.enter("lookup", &item_path);
+        
+        // Hidden entries do not exist for mounts that hide them from lookups
+        if self.hidden_files.read().unwrap().hides_lookup(&mount_paths::relative_shadow_path(&item_path), None) {
+            return Err(format!("Item '{}' not found", name));
+        }
         
         // Check override store first
         if let Some(item) = self.check_override_store(&item_path)? {
@@ -156,7 +297,7 @@ impl FSOperationsImpl {
             let source_path = self.get_source_path(item_path)?;
             
             // Check if file exists on source filesystem
-            if !Path::new(&source_path).exists() {
+            if !source_path.exists() {
                 return Err(format!("Item '{}' not found", name));
             }
 
@@ -243,29 +384,28 @@ impl FSOperationsImpl {
         }
     }
 
-    fn get_source_path(&self, virtual_path: &Path) -> Result<String, String> {
-        // Map virtual path to source filesystem path
-        // This would typically involve removing a mount prefix and adding source root
-        Ok(virtual_path.to_str()
-            .ok_or_else(|| "Invalid path".to_string())?
-            .to_string())
+    /// Maps the path of an item, relative to the volume root or below the
+    /// mount point, to its location in the source directory
+    fn get_source_path(&self, virtual_path: &Path) -> Result<PathBuf, String> {
+        let provider = self.provider.upgrade()
+            .ok_or_else(|| "Provider deallocated".to_string())?;
+        let path = self.get_shadow_path(virtual_path)?;
+        Ok(provider.source_resolver().resolve(mount_paths::relative_path(&path)))
+    }
+    
+    /// Maps the path of an item, relative to the volume root or below the
+    /// mount point, to its path in the volume
+    fn get_shadow_path(&self, virtual_path: &Path) -> Result<ShadowPath, String> {
+        let provider = self.provider.upgrade()
+            .ok_or_else(|| "Provider deallocated".to_string())?;
+        Ok(provider.paths()
+            .shadow_path_for_mount_path(virtual_path)
+            .unwrap_or_else(|_| mount_paths::relative_shadow_path(virtual_path)))
     }
 
     fn normalize_path_case(&self, path: &Path, override_store: &OverrideStore) -> Result<PathBuf, String> {
         // For case-insensitive systems, find the canonical casing
-        let path_lower = path.to_str()
-            .ok_or_else(|| "Invalid path encoding".to_string())?
-            .to_lowercase();
-
-        for stored_path in override_store.items.keys() {
-            if let Some(stored_str) = stored_path.to_str() {
-                if stored_str.to_lowercase() == path_lower {
-                    return Ok(stored_path.clone());
-                }
-            }
-        }
-
-        Ok(path.to_path_buf())
+        Ok(override_store.stored_casing(path))
     }
 
     fn get_file_mode(&self, metadata: &std::fs::Metadata) -> u32 {
@@ -315,6 +455,7 @@ impl FSOperationsImpl {
 
     pub fn read_directory(&self, directory: &AnyObject) -> Result<*mut AnyObject, String> {
         let dir_path = self.get_item_path(directory)?;
+        let _span = self.enter("read_directory", &dir_path);
         
         // Create a map to track all directory entries
         let mut entries: HashMap<String, DirectoryEntry> = HashMap::new();
@@ -325,6 +466,12 @@ impl FSOperationsImpl {
         // Then, apply override store modifications
         self.apply_override_entries(&dir_path, &mut entries)?;
         
+        // Leave out the entries the mount hides
+        let hidden = self.hidden_files.read().unwrap();
+        let directory = mount_paths::relative_shadow_path(&dir_path);
+        entries.retain(|name, _| !hidden.hides(&directory.join(name), None));
+        drop(hidden);
+        
         // Finally, create and return FSDirectoryContent
         self.create_directory_content(entries)
     }
@@ -333,7 +480,7 @@ impl FSOperationsImpl {
         let source_path = self.get_source_path(dir_path)?;
         
         // Check if directory exists on source filesystem
-        if !Path::new(&source_path).exists() {
+        if !source_path.exists() {
             return Ok(()); // Directory doesn't exist on source, only use override entries
         }
         
@@ -515,7 +662,7 @@ impl FSOperationsImpl {
         let provider = self.provider.upgrade()
             .ok_or_else(|| "Provider deallocated".to_string())?;
 
-        let mut state = self.state.write()
+        let state = self.state.read()
             .map_err(|e| format!("Failed to acquire state lock: {}", e))?;
 
         unsafe {
@@ -525,17 +672,7 @@ impl FSOperationsImpl {
                 .to_string_lossy()
                 .into_owned();
 
-            let handle_id = state.next_handle_id;
-            state.next_handle_id += 1;
-
-            let handle = FileHandle {
-                id: handle_id,
-                path: PathBuf::from(path_str),
-                flags,
-                ref_count: 1,
-            };
-
-            state.open_files.insert(handle_id, handle);
+            let handle = state.handles.open(ShadowPath::from(path_str.as_str()), OpenFlags::from_posix(flags as i32));
 
             let open_result: *mut AnyObject = msg_send![
                 &**provider,
@@ -544,10 +681,10 @@ impl FSOperationsImpl {
             ];
 
             if open_result.is_null() {
-                state.open_files.remove(&handle_id);
+                state.handles.close(handle);
                 Err("Failed to open file".to_string())
             } else {
-                Ok(handle_id)
+                Ok(handle.id())
             }
         }
     }
@@ -556,15 +693,12 @@ impl FSOperationsImpl {
         let provider = self.provider.upgrade()
             .ok_or_else(|| "Provider deallocated".to_string())?;
 
-        let mut state = self.state.write()
+        let state = self.state.read()
             .map_err(|e| format!("Failed to acquire state lock: {}", e))?;
 
-        if let Some(mut handle) = state.open_files.get_mut(&handle_id) {
-            handle.ref_count = handle.ref_count.saturating_sub(1);
-            
-            if handle.ref_count == 0 {
-                state.open_files.remove(&handle_id);
-                
+        if let Some(file) = state.handles.close(FileHandle::new(handle_id)) {
+            // Handles duplicated from this one keep the file open
+            if file.handle_count() == 0 {
                 unsafe {
                     let _: () = msg_send![
                         &**provider,
@@ -585,12 +719,12 @@ impl FSOperationsImpl {
         let mut state = self.state.write()
             .map_err(|e| format!("Failed to acquire state lock: {}", e))?;
 
-        if !state.open_files.contains_key(&handle_id) {
+        if state.handles.get(FileHandle::new(handle_id)).is_none() {
             return Err(format!("Invalid file handle: {}", handle_id));
         }
 
-        let op_id = state.next_handle_id;
-        state.next_handle_id += 1;
+        let op_id = state.next_operation_id;
+        state.next_operation_id += 1;
         
         state.active_operations.insert(op_id, OperationType::Read { offset, length });
 
@@ -626,12 +760,12 @@ impl FSOperationsImpl {
         let mut state = self.state.write()
             .map_err(|e| format!("Failed to acquire state lock: {}", e))?;
 
-        if !state.open_files.contains_key(&handle_id) {
+        if state.handles.get(FileHandle::new(handle_id)).is_none() {
             return Err(format!("Invalid file handle: {}", handle_id));
         }
 
-        let op_id = state.next_handle_id;
-        state.next_handle_id += 1;
+        let op_id = state.next_operation_id;
+        state.next_operation_id += 1;
         
         state.active_operations.insert(op_id, OperationType::Write { 
             offset, 
@@ -666,6 +800,7 @@ impl FSOperationsImpl {
         // Get the parent directory path
         let parent_path = self.get_item_path(parent)?;
         let new_item_path = parent_path.join(name);
+        let _span = self.enter("create", &new_item_path);
         
         // Check if item already exists in override store or source filesystem
         if self.item_exists(&new_item_path)? {
@@ -719,7 +854,7 @@ impl FSOperationsImpl {
         
         // Check source filesystem
         let source_path = self.get_source_path(path)?;
-        Ok(Path::new(&source_path).exists())
+        Ok(source_path.exists())
     }
     
     fn add_to_override_store(&self, path: &Path, item_type: FSItemType, attributes: FileAttributes) -> Result<(), String> {
@@ -742,7 +877,7 @@ impl FSOperationsImpl {
         };
         
         // Insert into override store
-        override_store.items.insert(path.to_path_buf(), override_item);
+        override_store.insert_item(path.to_path_buf(), override_item);
         
         Ok(())
     }
@@ -782,6 +917,7 @@ impl FSOperationsImpl {
     pub fn remove_item(&self, item: &AnyObject) -> Result<(), String> {
         // Get the item path
         let item_path = self.get_item_path(item)?;
+        let _span = self.enter("remove", &item_path);
         
         // Check if this is a directory and handle recursive deletion
         let is_directory = self.is_directory(&item_path)?;
@@ -885,7 +1021,7 @@ impl FSOperationsImpl {
         override_store.deleted_paths.insert(path.to_path_buf());
         
         // Remove from items if it was an override item
-        override_store.items.remove(path);
+        override_store.remove_item(path);
         
         // Note: We never touch the actual source filesystem files
         // The deletion only exists in our override layer
@@ -896,6 +1032,7 @@ impl FSOperationsImpl {
     pub fn rename_item(&self, item: &AnyObject, new_name: &str, new_parent: Option<&AnyObject>) -> Result<(), String> {
         // Get the current item path
         let old_path = self.get_item_path(item)?;
+        let _span = self.enter("rename", &old_path);
         
         // Determine the new parent directory
         let new_parent_path = if let Some(parent) = new_parent {
@@ -933,10 +1070,10 @@ impl FSOperationsImpl {
             .map_err(|e| format!("Failed to acquire override store lock: {}", e))?;
         
         // Check if item exists in override store
-        if let Some(mut override_item) = override_store.items.remove(old_path) {
+        if let Some(mut override_item) = override_store.remove_item(old_path) {
             // Update the path while preserving all metadata
             override_item.path = new_path.to_path_buf();
-            override_store.items.insert(new_path.to_path_buf(), override_item);
+            override_store.insert_item(new_path.to_path_buf(), override_item);
         } else {
             // Item exists only in source filesystem
             // We need to create an override entry for the renamed item
@@ -974,7 +1111,7 @@ impl FSOperationsImpl {
                     data,
                 };
                 
-                override_store.items.insert(new_path.to_path_buf(), override_item);
+                override_store.insert_item(new_path.to_path_buf(), override_item);
             }
             
             // Mark the old path as deleted (tombstone)
@@ -1069,7 +1206,7 @@ impl FSOperationsImpl {
         let state = self.state.read()
             .map_err(|e| format!("Failed to acquire state lock: {}", e))?;
 
-        Ok(state.open_files.len())
+        Ok(state.handles.len())
     }
 
     pub fn getxattr(&self, path: &Path, name: &OsStr, buffer: Option<&mut [u8]>) -> Result<usize, String> {
@@ -1196,24 +1333,21 @@ mod tests {
     #[test]
     fn test_operations_state_initialization() {
         let state = OperationsState::default();
-        assert_eq!(state.open_files.len(), 0);
+        assert!(state.handles.is_empty());
         assert_eq!(state.active_operations.len(), 0);
-        assert_eq!(state.next_handle_id, 0);
+        assert_eq!(state.next_operation_id, 0);
     }
 
     #[test]
-    fn test_file_handle_creation() {
-        let handle = FileHandle {
-            id: 1,
-            path: PathBuf::from("/test/file.txt"),
-            flags: 0x01,
-            ref_count: 1,
-        };
+    fn test_duplicated_handles_keep_the_file_open() {
+        let state = OperationsState::default();
+        let path = ShadowPath::from("/test/file.txt");
+        let handle = state.handles.open(path.clone(), OpenFlags::from_posix(libc::O_RDONLY));
+        let duplicate = state.handles.dup(handle).unwrap();
         
-        assert_eq!(handle.id, 1);
-        assert_eq!(handle.path.to_str().unwrap(), "/test/file.txt");
-        assert_eq!(handle.flags, 0x01);
-        assert_eq!(handle.ref_count, 1);
+        assert_eq!(state.handles.close(handle).unwrap().handle_count(), 1);
+        assert_eq!(state.handles.get(duplicate).unwrap().path(), &path);
+        assert_eq!(state.handles.close(duplicate).unwrap().handle_count(), 0);
     }
 
     #[test]
@@ -5,8 +5,13 @@ use std::ffi::c_void;
 use dashmap::DashMap;
 use dispatch::Queue as DispatchQueue;
 use uuid::Uuid;
+use shadowfs_core::mount_paths::MountPaths;
 use shadowfs_core::override_store::OverrideStore;
 use shadowfs_core::stats::FileSystemStats;
+use super::firmlinks::{FirmlinkMap, SourceResolver};
+use super::fsevents::FsEventsWatcher;
+use shadowfs_core::error::ShadowError;
+use shadowfs_core::source_watch::{SourceChangeHandler, SourceWatcher};
 
 pub enum QueuePriority {
     High,
@@ -44,12 +49,14 @@ pub struct FileContext {
 pub struct FSKitProvider {
     config: FSKitConfig,
     source_root: PathBuf,
+    source_resolver: SourceResolver,
     mount_point: PathBuf,
     override_store: Arc<OverrideStore>,
     dispatch_queue: DispatchQueue,
     file_handles: DashMap<u64, FileContext>,
     stats: Arc<FileSystemStats>,
     next_handle_id: AtomicU64,
+    source_watcher: Option<FsEventsWatcher>,
 }
 
 #[repr(C)]
@@ -169,17 +176,20 @@ impl FSKitProvider {
         Self {
             config,
             source_root: PathBuf::new(),
+            source_resolver: SourceResolver::with_firmlinks(PathBuf::new(), FirmlinkMap::default()),
             mount_point: PathBuf::new(),
             override_store: Arc::new(OverrideStore::with_defaults()),
             dispatch_queue,
             file_handles: DashMap::new(),
             stats: Arc::new(FileSystemStats::default()),
             next_handle_id: AtomicU64::new(1),
+            source_watcher: None,
         }
     }
     
     pub fn set_paths(&mut self, source: PathBuf, mount: PathBuf) {
-        self.source_root = source;
+        self.source_resolver = SourceResolver::new(&source);
+        self.source_root = self.source_resolver.root().to_path_buf();
         self.mount_point = mount;
     }
     
@@ -203,6 +213,35 @@ impl FSKitProvider {
         &self.override_store
     }
     
+    pub fn source_resolver(&self) -> &SourceResolver {
+        &self.source_resolver
+    }
+    
+    /// Returns the source root and mount point, for converting paths between them.
+    pub fn paths(&self) -> MountPaths {
+        MountPaths::new(&self.source_root, &self.mount_point)
+    }
+    
+    /// Starts watching the source tree with FSEvents.
+    ///
+    /// Source changes are checked against the override store for conflicts
+    /// and published to its watch service; register metadata caches with
+    /// [`SourceChangeHandler::on_invalidate`] on the returned handler.
+    pub fn start_source_watching(&mut self) -> Result<Arc<SourceChangeHandler>, ShadowError> {
+        self.stop_source_watching();
+        let handler = Arc::new(SourceChangeHandler::new(Arc::clone(&self.override_store)));
+        let mut watcher = FsEventsWatcher::new(self.source_resolver.clone());
+        watcher.start(Arc::clone(&handler) as Arc<_>)?;
+        self.source_watcher = Some(watcher);
+        Ok(handler)
+    }
+    
+    pub fn stop_source_watching(&mut self) {
+        if let Some(mut watcher) = self.source_watcher.take() {
+            watcher.stop();
+        }
+    }
+    
     pub fn stats(&self) -> &Arc<FileSystemStats> {
         &self.stats
     }
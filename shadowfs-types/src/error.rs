@@ -0,0 +1,515 @@
+//! Error types for the ShadowFS system.
+
+use crate::path::ShadowPath;
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+use thiserror::Error;
+
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+
+/// Represents the platform where the error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Platform {
+    Windows,
+    MacOS,
+    Linux,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Windows => write!(f, "Windows"),
+            Platform::MacOS => write!(f, "macOS"),
+            Platform::Linux => write!(f, "Linux"),
+        }
+    }
+}
+
+/// Structural limit a path can run into, as [`ShadowError::LimitExceeded`]
+/// reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LimitKind {
+    /// Entries one directory may hold
+    DirectoryEntries,
+    /// Components a path may have below the root
+    PathDepth,
+    /// Length of one name, in the units of the platform
+    NameLength,
+}
+
+impl LimitKind {
+    /// Returns the limit as it is written on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitKind::DirectoryEntries => "directory_entries",
+            LimitKind::PathDepth => "path_depth",
+            LimitKind::NameLength => "name_length",
+        }
+    }
+    
+    /// Returns the limit written as [`LimitKind::as_str`] writes it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "directory_entries" => Some(LimitKind::DirectoryEntries),
+            "path_depth" => Some(LimitKind::PathDepth),
+            "name_length" => Some(LimitKind::NameLength),
+            _ => None,
+        }
+    }
+    
+    /// Describes a value of `actual` going past a limit of `max`.
+    fn describe(&self, actual: u64, max: u64) -> String {
+        match self {
+            LimitKind::DirectoryEntries => format!("{} entries would exceed the limit of {} per directory", actual, max),
+            LimitKind::PathDepth => format!("a depth of {} would exceed the limit of {}", actual, max),
+            LimitKind::NameLength => format!("a name of length {} would exceed the limit of {}", actual, max),
+        }
+    }
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Comprehensive error type for all ShadowFS operations.
+#[derive(Debug, Error)]
+pub enum ShadowError {
+    /// File or directory not found.
+    #[error("Path not found: {path}")]
+    NotFound { 
+        path: ShadowPath 
+    },
+    
+    /// Permission denied for the operation.
+    #[error("Permission denied for operation '{operation}' on path: {path}")]
+    PermissionDenied { 
+        path: ShadowPath, 
+        operation: String 
+    },
+    
+    /// File or directory already exists.
+    #[error("Path already exists: {path}")]
+    AlreadyExists { 
+        path: ShadowPath 
+    },
+    
+    /// Expected a directory but found something else.
+    #[error("Not a directory: {path}")]
+    NotADirectory { 
+        path: ShadowPath 
+    },
+    
+    /// Expected a file but found a directory.
+    #[error("Is a directory: {path}")]
+    IsADirectory { 
+        path: ShadowPath 
+    },
+    
+    /// Invalid path provided.
+    #[error("Invalid path '{path}': {reason}")]
+    InvalidPath { 
+        path: String, 
+        reason: String 
+    },
+    
+    /// I/O error from the underlying system.
+    #[cfg(feature = "std")]
+    #[error("I/O error: {source}")]
+    IoError {
+        #[source]
+        source: std::io::Error,
+    },
+    
+    /// Platform-specific error.
+    #[error("Platform error on {platform}: {message} (code: {code:?})")]
+    PlatformError { 
+        platform: Platform, 
+        message: String, 
+        code: Option<i32> 
+    },
+    
+    /// Override store is full.
+    #[error("Override store is full: current size {current_size} bytes, maximum {max_size} bytes")]
+    OverrideStoreFull { 
+        current_size: usize, 
+        max_size: usize 
+    },
+    
+    /// Mount point is not mounted.
+    #[error("Mount point not mounted: {mount_point}")]
+    NotMounted { 
+        mount_point: ShadowPath 
+    },
+    
+    /// Feature not supported.
+    #[error("Unsupported feature: {feature}")]
+    Unsupported { 
+        feature: String 
+    },
+    
+    /// Invalid configuration.
+    #[error("Invalid configuration: {message}")]
+    InvalidConfiguration { 
+        message: String 
+    },
+    
+    /// Operation was cancelled before it completed.
+    #[error("Operation cancelled: {operation}")]
+    Cancelled { 
+        operation: String 
+    },
+    
+    /// Operation refused by an access policy rule.
+    #[error("Access denied: {operation} on {path}")]
+    AccessDenied { 
+        path: ShadowPath, 
+        operation: String 
+    },
+    
+    /// Write attempted on a read-only mount.
+    #[error("Read-only filesystem: cannot {operation} {path}")]
+    ReadOnlyFilesystem { 
+        path: ShadowPath, 
+        operation: String 
+    },
+    
+    /// Directory still has entries.
+    #[error("Directory not empty: {path}")]
+    DirectoryNotEmpty { 
+        path: ShadowPath 
+    },
+    
+    /// Two versions of a path disagree and neither may win.
+    #[error("Conflict at {path}: {reason}")]
+    Conflict { 
+        path: ShadowPath, 
+        reason: String 
+    },
+    
+    /// Client credentials do not allow the operation.
+    #[error("Not authorized to {operation}: {reason}")]
+    Unauthorized { 
+        operation: String, 
+        reason: String 
+    },
+    
+    /// A new mount would exceed the mount count or memory budget.
+    #[error("Cannot mount {mount_point}: {reason}")]
+    MountLimitReached { 
+        mount_point: ShadowPath, 
+        reason: String 
+    },
+    
+    /// The source tree refuses the writes a commit needs.
+    #[error("Source {path} is not writable: {reason}")]
+    SourceNotWritable { 
+        path: ShadowPath, 
+        reason: String 
+    },
+    
+    /// A write would take the mount past its file count or size limits.
+    #[error("Quota exceeded writing {path}: {reason}")]
+    QuotaExceeded { 
+        path: ShadowPath, 
+        reason: String 
+    },
+    
+    /// A path would go past a directory size, depth or name length limit.
+    #[error("Limit exceeded at {path}: {}", .limit.describe(*.actual, *.max))]
+    LimitExceeded { 
+        path: ShadowPath, 
+        limit: LimitKind, 
+        actual: u64, 
+        max: u64 
+    },
+}
+
+#[cfg(feature = "std")]
+impl ShadowError {
+    /// Creates a ShadowError from an io::Error with context about the path.
+    /// This provides more specific error mapping than the generic From trait.
+    pub fn from_io_error(error: std::io::Error, path: Option<&ShadowPath>) -> Self {
+        use std::io::ErrorKind;
+        
+        match error.kind() {
+            ErrorKind::NotFound => {
+                if let Some(p) = path {
+                    ShadowError::NotFound { path: p.clone() }
+                } else {
+                    ShadowError::IoError { source: error }
+                }
+            }
+            ErrorKind::PermissionDenied => {
+                if let Some(p) = path {
+                    ShadowError::PermissionDenied { 
+                        path: p.clone(), 
+                        operation: "access".to_string() 
+                    }
+                } else {
+                    ShadowError::IoError { source: error }
+                }
+            }
+            ErrorKind::AlreadyExists => {
+                if let Some(p) = path {
+                    ShadowError::AlreadyExists { path: p.clone() }
+                } else {
+                    ShadowError::IoError { source: error }
+                }
+            }
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => {
+                if let Some(p) = path {
+                    ShadowError::InvalidPath { 
+                        path: p.to_string(), 
+                        reason: error.to_string() 
+                    }
+                } else {
+                    ShadowError::InvalidPath {
+                        path: String::new(),
+                        reason: error.to_string()
+                    }
+                }
+            }
+            _ => ShadowError::IoError { source: error }
+        }
+    }
+    
+    /// Creates a ShadowError from an io::Error for a specific operation.
+    pub fn from_io_error_with_operation(
+        error: std::io::Error, 
+        path: &ShadowPath, 
+        operation: &str
+    ) -> Self {
+        use std::io::ErrorKind;
+        
+        match error.kind() {
+            ErrorKind::PermissionDenied => {
+                ShadowError::PermissionDenied { 
+                    path: path.clone(), 
+                    operation: operation.to_string() 
+                }
+            }
+            _ => Self::from_io_error(error, Some(path))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for ShadowError {
+    fn from(error: std::io::Error) -> Self {
+        Self::from_io_error(error, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    
+    #[test]
+    fn test_error_display() {
+        let path = ShadowPath::from("/test/file.txt");
+        
+        // Test NotFound
+        let err = ShadowError::NotFound { path: path.clone() };
+        assert_eq!(err.to_string(), "Path not found: /test/file.txt");
+        
+        // Test PermissionDenied
+        let err = ShadowError::PermissionDenied { 
+            path: path.clone(), 
+            operation: "write".to_string() 
+        };
+        assert_eq!(err.to_string(), "Permission denied for operation 'write' on path: /test/file.txt");
+        
+        // Test AlreadyExists
+        let err = ShadowError::AlreadyExists { path: path.clone() };
+        assert_eq!(err.to_string(), "Path already exists: /test/file.txt");
+        
+        // Test NotADirectory
+        let err = ShadowError::NotADirectory { path: path.clone() };
+        assert_eq!(err.to_string(), "Not a directory: /test/file.txt");
+        
+        // Test IsADirectory
+        let err = ShadowError::IsADirectory { path: path.clone() };
+        assert_eq!(err.to_string(), "Is a directory: /test/file.txt");
+        
+        // Test InvalidPath
+        let err = ShadowError::InvalidPath { 
+            path: "//invalid//path".to_string(), 
+            reason: "contains double slashes".to_string() 
+        };
+        assert_eq!(err.to_string(), "Invalid path '//invalid//path': contains double slashes");
+        
+        // Test PlatformError
+        let err = ShadowError::PlatformError { 
+            platform: Platform::Windows, 
+            message: "Access denied".to_string(), 
+            code: Some(5) 
+        };
+        assert_eq!(err.to_string(), "Platform error on Windows: Access denied (code: Some(5))");
+        
+        // Test OverrideStoreFull
+        let err = ShadowError::OverrideStoreFull { 
+            current_size: 1048576, 
+            max_size: 1048576 
+        };
+        assert_eq!(err.to_string(), "Override store is full: current size 1048576 bytes, maximum 1048576 bytes");
+        
+        // Test NotMounted
+        let err = ShadowError::NotMounted { 
+            mount_point: ShadowPath::from("/mnt/shadow") 
+        };
+        assert_eq!(err.to_string(), "Mount point not mounted: /mnt/shadow");
+        
+        // Test Unsupported
+        let err = ShadowError::Unsupported { 
+            feature: "symbolic links".to_string() 
+        };
+        assert_eq!(err.to_string(), "Unsupported feature: symbolic links");
+        
+        // Test Cancelled
+        let err = ShadowError::Cancelled { 
+            operation: "commit".to_string() 
+        };
+        assert_eq!(err.to_string(), "Operation cancelled: commit");
+        
+        // Test AccessDenied
+        let err = ShadowError::AccessDenied { 
+            path: path.clone(), 
+            operation: "write".to_string() 
+        };
+        assert_eq!(err.to_string(), "Access denied: write on /test/file.txt");
+        
+        // Test ReadOnlyFilesystem
+        let err = ShadowError::ReadOnlyFilesystem { 
+            path: path.clone(), 
+            operation: "delete".to_string() 
+        };
+        assert_eq!(err.to_string(), "Read-only filesystem: cannot delete /test/file.txt");
+        
+        // Test DirectoryNotEmpty
+        let err = ShadowError::DirectoryNotEmpty { path: path.clone() };
+        assert_eq!(err.to_string(), "Directory not empty: /test/file.txt");
+        
+        // Test Conflict
+        let err = ShadowError::Conflict { 
+            path: path.clone(), 
+            reason: "both stores changed it".to_string() 
+        };
+        assert_eq!(err.to_string(), "Conflict at /test/file.txt: both stores changed it");
+        
+        // Test Unauthorized
+        let err = ShadowError::Unauthorized { 
+            operation: "commit".to_string(), 
+            reason: "token expired".to_string() 
+        };
+        assert_eq!(err.to_string(), "Not authorized to commit: token expired");
+        
+        // Test MountLimitReached
+        let err = ShadowError::MountLimitReached { 
+            mount_point: ShadowPath::from("/mnt/ci"), 
+            reason: "4 of 4 mounts active".to_string() 
+        };
+        assert_eq!(err.to_string(), "Cannot mount /mnt/ci: 4 of 4 mounts active");
+        
+        // Test SourceNotWritable
+        let err = ShadowError::SourceNotWritable { 
+            path: ShadowPath::from("/src/repo"), 
+            reason: "it is on a read-only filesystem".to_string() 
+        };
+        assert_eq!(err.to_string(), "Source /src/repo is not writable: it is on a read-only filesystem");
+        
+        // Test QuotaExceeded
+        let err = ShadowError::QuotaExceeded { 
+            path: ShadowPath::from("/tmp/a.txt"), 
+            reason: "1001 files would exceed the limit of 1000".to_string() 
+        };
+        assert_eq!(err.to_string(), "Quota exceeded writing /tmp/a.txt: 1001 files would exceed the limit of 1000");
+        
+        // Test LimitExceeded
+        let err = ShadowError::LimitExceeded { 
+            path: ShadowPath::from("/tmp/cache"), 
+            limit: LimitKind::DirectoryEntries, 
+            actual: 10001, 
+            max: 10000 
+        };
+        assert_eq!(err.to_string(), "Limit exceeded at /tmp/cache: 10001 entries would exceed the limit of 10000 per directory");
+        assert_eq!(LimitKind::from_name(LimitKind::NameLength.as_str()), Some(LimitKind::NameLength));
+    }
+    
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_io_error_conversion() {
+        // Test basic conversion without path
+        let io_err = std::io::Error::new(std::io::ErrorKind::Other, "generic error");
+        let shadow_err: ShadowError = io_err.into();
+        assert!(matches!(shadow_err, ShadowError::IoError { .. }));
+    }
+    
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_io_error_conversion_with_path() {
+        let path = ShadowPath::from("/test/file.txt");
+        
+        // Test NotFound conversion
+        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
+        assert!(matches!(shadow_err, ShadowError::NotFound { path: p } if p == path));
+        
+        // Test PermissionDenied conversion
+        let io_err = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
+        assert!(matches!(
+            shadow_err, 
+            ShadowError::PermissionDenied { path: p, operation } 
+            if p == path && operation == "access"
+        ));
+        
+        // Test AlreadyExists conversion
+        let io_err = std::io::Error::new(std::io::ErrorKind::AlreadyExists, "exists");
+        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
+        assert!(matches!(shadow_err, ShadowError::AlreadyExists { path: p } if p == path));
+        
+        // Test InvalidInput conversion
+        let io_err = std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid");
+        let shadow_err = ShadowError::from_io_error(io_err, Some(&path));
+        assert!(matches!(
+            shadow_err, 
+            ShadowError::InvalidPath { path: p, .. } 
+            if p == path.to_string()
+        ));
+    }
+    
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_io_error_with_operation() {
+        let path = ShadowPath::from("/test/file.txt");
+        
+        // Test PermissionDenied with custom operation
+        let io_err = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let shadow_err = ShadowError::from_io_error_with_operation(io_err, &path, "write");
+        assert!(matches!(
+            shadow_err, 
+            ShadowError::PermissionDenied { path: p, operation } 
+            if p == path && operation == "write"
+        ));
+        
+        // Test other error kinds fallback to from_io_error
+        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+        let shadow_err = ShadowError::from_io_error_with_operation(io_err, &path, "read");
+        assert!(matches!(shadow_err, ShadowError::NotFound { path: p } if p == path));
+    }
+    
+    #[test]
+    fn test_platform_display() {
+        assert_eq!(Platform::Windows.to_string(), "Windows");
+        assert_eq!(Platform::MacOS.to_string(), "macOS");
+        assert_eq!(Platform::Linux.to_string(), "Linux");
+    }
+}
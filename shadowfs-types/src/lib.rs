@@ -0,0 +1,41 @@
+//! # ShadowFS Types
+//! 
+//! The path, metadata and error types shared by every ShadowFS crate, kept
+//! free of the override store and its dependencies so that constrained
+//! environments, such as a WASM plugin runtime, can use them too.
+//! 
+//! The crate is `no_std` and needs only `alloc`. The default `std` feature
+//! adds what a host filesystem brings with it:
+//! 
+//! - [`ShadowPath`] wraps a `PathBuf` and converts to and from host paths;
+//!   without `std` it holds a `/`-separated `String`
+//! - [`Timestamp`] is `std::time::SystemTime`; without `std` it is a plain
+//!   count of seconds and nanoseconds since the Unix epoch with the same
+//!   serde form
+//! - [`ShadowError`] gains the `IoError` variant and conversions from
+//!   `std::io::Error`
+//! 
+//! [`WireError`] is the serializable form of a [`ShadowError`], for errors
+//! that have to cross a process boundary.
+//! 
+//! `shadowfs-core` re-exports all of these, so most code should keep
+//! importing them from there.
+
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod error;
+pub mod metadata;
+pub mod path;
+pub mod wire;
+
+pub use error::{LimitKind, Platform, ShadowError};
+pub use metadata::{FileMetadata, FilePermissions, FileType, NamedStream, PlatformMetadata, Timestamp};
+#[cfg(feature = "std")]
+pub use metadata::current_time;
+pub use path::ShadowPath;
+pub use wire::{ErrorCategory, ErrorCode, WireError};
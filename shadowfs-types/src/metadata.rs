@@ -0,0 +1,375 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(not(feature = "std"))]
+use core::time::Duration;
+
+/// Point in time used for file timestamps.
+#[cfg(feature = "std")]
+pub type Timestamp = std::time::SystemTime;
+
+/// Returns the current wall-clock time.
+///
+/// `SystemTime::now` panics on `wasm32-unknown-unknown`, so there the time
+/// comes from JavaScript's `Date.now()` instead, with millisecond precision.
+#[cfg(feature = "std")]
+pub fn current_time() -> Timestamp {
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    return std::time::UNIX_EPOCH + std::time::Duration::from_millis(js_sys::Date::now() as u64);
+    
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    return std::time::SystemTime::now();
+}
+
+/// Point in time used for file timestamps, counted from the Unix epoch.
+///
+/// Serializes with the same fields as `std::time::SystemTime`, so metadata
+/// written by a std build reads back here and the other way round.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename = "SystemTime"))]
+pub struct Timestamp {
+    secs_since_epoch: u64,
+    nanos_since_epoch: u32,
+}
+
+#[cfg(not(feature = "std"))]
+impl Timestamp {
+    /// The Unix epoch.
+    pub const UNIX_EPOCH: Timestamp = Timestamp { secs_since_epoch: 0, nanos_since_epoch: 0 };
+    
+    /// Creates a timestamp the given time after the Unix epoch.
+    pub fn from_unix(since_epoch: Duration) -> Self {
+        Self {
+            secs_since_epoch: since_epoch.as_secs(),
+            nanos_since_epoch: since_epoch.subsec_nanos(),
+        }
+    }
+    
+    /// Returns the time elapsed since the Unix epoch.
+    pub fn since_unix_epoch(&self) -> Duration {
+        Duration::new(self.secs_since_epoch, self.nanos_since_epoch)
+    }
+}
+
+/// Represents the type of a file system entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileType {
+    /// Regular file
+    File,
+    /// Directory
+    Directory,
+    /// Symbolic link
+    Symlink,
+}
+
+/// Represents file permissions in a platform-agnostic way.
+/// Abstracts Unix permissions (rwx) and Windows ACLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilePermissions {
+    /// Whether the file is read-only
+    pub readonly: bool,
+    /// Owner read permission
+    pub owner_read: bool,
+    /// Owner write permission
+    pub owner_write: bool,
+    /// Owner execute permission
+    pub owner_execute: bool,
+    /// Group read permission
+    pub group_read: bool,
+    /// Group write permission
+    pub group_write: bool,
+    /// Group execute permission
+    pub group_execute: bool,
+    /// Other read permission
+    pub other_read: bool,
+    /// Other write permission
+    pub other_write: bool,
+    /// Other execute permission
+    pub other_execute: bool,
+}
+
+impl FilePermissions {
+    /// Creates a new FilePermissions instance from Unix mode bits.
+    pub fn from_unix_mode(mode: u32) -> Self {
+        Self {
+            readonly: (mode & 0o200) == 0, // No owner write permission
+            owner_read: (mode & 0o400) != 0,
+            owner_write: (mode & 0o200) != 0,
+            owner_execute: (mode & 0o100) != 0,
+            group_read: (mode & 0o040) != 0,
+            group_write: (mode & 0o020) != 0,
+            group_execute: (mode & 0o010) != 0,
+            other_read: (mode & 0o004) != 0,
+            other_write: (mode & 0o002) != 0,
+            other_execute: (mode & 0o001) != 0,
+        }
+    }
+    
+    /// Converts the permissions to Unix mode bits.
+    pub fn to_unix_mode(&self) -> u32 {
+        let mut mode = 0;
+        
+        if self.owner_read { mode |= 0o400; }
+        if self.owner_write { mode |= 0o200; }
+        if self.owner_execute { mode |= 0o100; }
+        if self.group_read { mode |= 0o040; }
+        if self.group_write { mode |= 0o020; }
+        if self.group_execute { mode |= 0o010; }
+        if self.other_read { mode |= 0o004; }
+        if self.other_write { mode |= 0o002; }
+        if self.other_execute { mode |= 0o001; }
+        
+        mode
+    }
+    
+    /// Returns true if the file is executable by anyone.
+    pub fn is_executable(&self) -> bool {
+        self.owner_execute || self.group_execute || self.other_execute
+    }
+    
+    /// Returns default permissions for a file.
+    pub fn default_file() -> Self {
+        Self::from_unix_mode(0o644)
+    }
+    
+    /// Returns default permissions for a directory.
+    pub fn default_directory() -> Self {
+        Self::from_unix_mode(0o755)
+    }
+}
+
+/// An NTFS named stream of a file, such as `Zone.Identifier`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamedStream {
+    /// Name of the stream, without the file name or the `:$DATA` type
+    pub name: String,
+    /// Size of the stream in bytes
+    pub size: u64,
+}
+
+/// Platform-specific metadata.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlatformMetadata {
+    /// Windows-specific metadata
+    Windows {
+        /// File attributes (hidden, system, archive, etc.)
+        attributes: u32,
+        /// Reparse point tag (for symlinks and other special files)
+        reparse_tag: Option<u32>,
+        /// Named streams of the file, sorted by name. Not serialized: the
+        /// streams are overrides and source streams of their own, listed
+        /// when the metadata is read.
+        #[cfg_attr(feature = "serde", serde(skip))]
+        streams: Vec<NamedStream>,
+    },
+    /// macOS-specific metadata
+    MacOS {
+        /// BSD flags
+        flags: u32,
+        /// Extended attributes count
+        xattr_count: usize,
+    },
+    /// Linux-specific metadata
+    Linux {
+        /// Inode number
+        inode: u64,
+        /// Number of hard links
+        nlink: u64,
+    },
+}
+
+impl Default for PlatformMetadata {
+    fn default() -> Self {
+        #[cfg(target_os = "windows")]
+        {
+            Self::Windows {
+                attributes: 0,
+                reparse_tag: None,
+                streams: Vec::new(),
+            }
+        }
+        #[cfg(target_os = "macos")]
+        {
+            Self::MacOS {
+                flags: 0,
+                xattr_count: 0,
+            }
+        }
+        #[cfg(target_os = "linux")]
+        {
+            Self::Linux {
+                inode: 0,
+                nlink: 1,
+            }
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            Self::Linux {
+                inode: 0,
+                nlink: 1,
+            }
+        }
+    }
+}
+
+/// Complete metadata for a file system entry.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileMetadata {
+    /// Size in bytes
+    pub size: u64,
+    /// Creation time
+    pub created: Timestamp,
+    /// Last modification time
+    pub modified: Timestamp,
+    /// Last access time
+    pub accessed: Timestamp,
+    /// File permissions
+    pub permissions: FilePermissions,
+    /// Type of file system entry
+    pub file_type: FileType,
+    /// Platform-specific metadata
+    pub platform_specific: PlatformMetadata,
+}
+
+impl FileMetadata {
+    /// Creates a new FileMetadata instance.
+    pub fn new(
+        size: u64,
+        created: Timestamp,
+        modified: Timestamp,
+        accessed: Timestamp,
+        permissions: FilePermissions,
+        file_type: FileType,
+        platform_specific: PlatformMetadata,
+    ) -> Self {
+        Self {
+            size,
+            created,
+            modified,
+            accessed,
+            permissions,
+            file_type,
+            platform_specific,
+        }
+    }
+    
+    /// Returns the number of hard links, 1 where the platform metadata does
+    /// not track them.
+    pub fn nlink(&self) -> u64 {
+        match self.platform_specific {
+            PlatformMetadata::Linux { nlink, .. } => nlink,
+            _ => 1,
+        }
+    }
+    
+    /// Sets the number of hard links where the platform metadata tracks them.
+    pub fn set_nlink(&mut self, count: u64) {
+        if let PlatformMetadata::Linux { nlink, .. } = &mut self.platform_specific {
+            *nlink = count;
+        }
+    }
+    
+    /// Returns the named streams of the file, none where the platform
+    /// metadata does not track them.
+    pub fn streams(&self) -> &[NamedStream] {
+        match &self.platform_specific {
+            PlatformMetadata::Windows { streams, .. } => streams,
+            _ => &[],
+        }
+    }
+    
+    /// Sets the named streams of the file where the platform metadata
+    /// tracks them.
+    pub fn set_streams(&mut self, named: Vec<NamedStream>) {
+        if let PlatformMetadata::Windows { streams, .. } = &mut self.platform_specific {
+            *streams = named;
+        }
+    }
+}
+
+impl Default for FileMetadata {
+    /// Timestamps are the current time, or the Unix epoch without `std`.
+    fn default() -> Self {
+        #[cfg(feature = "std")]
+        let now = current_time();
+        #[cfg(not(feature = "std"))]
+        let now = Timestamp::UNIX_EPOCH;
+        Self {
+            size: 0,
+            created: now,
+            modified: now,
+            accessed: now,
+            permissions: FilePermissions::default_file(),
+            file_type: FileType::File,
+            platform_specific: PlatformMetadata::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_file_permissions_from_unix_mode() {
+        let perms = FilePermissions::from_unix_mode(0o755);
+        assert!(perms.owner_read);
+        assert!(perms.owner_write);
+        assert!(perms.owner_execute);
+        assert!(perms.group_read);
+        assert!(!perms.group_write);
+        assert!(perms.group_execute);
+        assert!(perms.other_read);
+        assert!(!perms.other_write);
+        assert!(perms.other_execute);
+        assert!(!perms.readonly);
+    }
+    
+    #[test]
+    fn test_file_permissions_to_unix_mode() {
+        let perms = FilePermissions::from_unix_mode(0o644);
+        assert_eq!(perms.to_unix_mode(), 0o644);
+        
+        let perms2 = FilePermissions::from_unix_mode(0o755);
+        assert_eq!(perms2.to_unix_mode(), 0o755);
+    }
+    
+    #[test]
+    fn test_file_permissions_is_executable() {
+        let perms_exec = FilePermissions::from_unix_mode(0o755);
+        assert!(perms_exec.is_executable());
+        
+        let perms_no_exec = FilePermissions::from_unix_mode(0o644);
+        assert!(!perms_no_exec.is_executable());
+    }
+    
+    #[test]
+    fn test_streams_are_windows_metadata() {
+        let stream = NamedStream { name: String::from("Zone.Identifier"), size: 26 };
+        let mut metadata = FileMetadata {
+            platform_specific: PlatformMetadata::Windows { attributes: 0x20, reparse_tag: None, streams: Vec::new() },
+            ..FileMetadata::default()
+        };
+        metadata.set_streams(alloc::vec![stream.clone()]);
+        assert_eq!(metadata.streams(), core::slice::from_ref(&stream));
+        
+        metadata.platform_specific = PlatformMetadata::Linux { inode: 1, nlink: 1 };
+        metadata.set_streams(alloc::vec![stream]);
+        assert!(metadata.streams().is_empty());
+    }
+    
+    #[test]
+    fn test_file_permissions_readonly() {
+        let perms_readonly = FilePermissions::from_unix_mode(0o444);
+        assert!(perms_readonly.readonly);
+        
+        let perms_writeable = FilePermissions::from_unix_mode(0o644);
+        assert!(!perms_writeable.readonly);
+    }
+}
\ No newline at end of file
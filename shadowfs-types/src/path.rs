@@ -0,0 +1,432 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(not(feature = "std"))]
+use alloc::borrow::ToOwned;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+/// A normalized path representation for ShadowFS that provides
+/// platform-agnostic path handling and comparison.
+///
+/// With the `std` feature the path is a host `PathBuf`. Without it the path
+/// is a `String` using `/` as the separator, normalized the same way a Unix
+/// `PathBuf` would be; both forms serialize as a plain string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShadowPath {
+    #[cfg(feature = "std")]
+    inner: PathBuf,
+    #[cfg(not(feature = "std"))]
+    inner: String,
+}
+
+#[cfg(feature = "std")]
+impl ShadowPath {
+    /// Creates a new ShadowPath from a PathBuf, normalizing it.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            inner: Self::normalize_path(path),
+        }
+    }
+    
+    /// Normalizes a path by removing . and .. components.
+    fn normalize_path(path: PathBuf) -> PathBuf {
+        let mut components = Vec::new();
+        
+        for component in path.components() {
+            match component {
+                std::path::Component::CurDir => {
+                    // Skip . components
+                }
+                std::path::Component::ParentDir => {
+                    // Handle .. by popping the last component if possible
+                    if !components.is_empty() {
+                        components.pop();
+                    }
+                }
+                _ => {
+                    components.push(component);
+                }
+            }
+        }
+        
+        components.iter().collect()
+    }
+    
+    /// Converts the ShadowPath to a host-specific PathBuf.
+    pub fn to_host_path(&self) -> PathBuf {
+        self.inner.clone()
+    }
+    
+    /// Returns true if the path is absolute.
+    pub fn is_absolute(&self) -> bool {
+        self.inner.is_absolute()
+    }
+    
+    /// Strips the given prefix from the path.
+    pub fn strip_prefix<P: AsRef<Path>>(&self, base: P) -> Option<ShadowPath> {
+        self.inner
+            .strip_prefix(base)
+            .ok()
+            .map(|p| ShadowPath::new(p.to_path_buf()))
+    }
+    
+    /// Returns the inner PathBuf reference.
+    pub fn as_path(&self) -> &Path {
+        &self.inner
+    }
+    
+    /// Returns the parent path, if any.
+    pub fn parent(&self) -> Option<ShadowPath> {
+        self.inner.parent().map(|p| ShadowPath::new(p.to_path_buf()))
+    }
+    
+    /// Returns the filename component, if any.
+    pub fn file_name(&self) -> Option<String> {
+        self.inner.file_name().and_then(|name| name.to_str()).map(String::from)
+    }
+    
+    /// Returns the file stem (filename without extension), if any.
+    pub fn file_stem(&self) -> Option<String> {
+        self.inner.file_stem().and_then(|name| name.to_str()).map(String::from)
+    }
+    
+    /// Returns the file extension, if any.
+    pub fn extension(&self) -> Option<String> {
+        self.inner.extension().and_then(|ext| ext.to_str()).map(String::from)
+    }
+    
+    /// Joins this path with another path component.
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> ShadowPath {
+        ShadowPath::new(self.inner.join(path))
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl ShadowPath {
+    /// Creates a new ShadowPath from a `/`-separated string, normalizing it.
+    pub fn new(path: String) -> Self {
+        Self {
+            inner: Self::normalize_path(&path),
+        }
+    }
+    
+    /// Normalizes a path by removing empty, . and .. components. As with a
+    /// `PathBuf`, a .. with nothing before it to pop is dropped.
+    fn normalize_path(path: &str) -> String {
+        let mut components = Vec::new();
+        if path.starts_with('/') {
+            components.push("/");
+        }
+        
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop();
+                }
+                _ => components.push(component),
+            }
+        }
+        
+        match components.split_first() {
+            Some((&"/", rest)) => ["/", &rest.join("/")].concat(),
+            _ => components.join("/"),
+        }
+    }
+    
+    /// Returns the path as a `/`-separated string.
+    pub fn as_str(&self) -> &str {
+        &self.inner
+    }
+    
+    /// Returns true if the path is absolute.
+    pub fn is_absolute(&self) -> bool {
+        self.inner.starts_with('/')
+    }
+    
+    /// Strips the given prefix, which must end on a component boundary.
+    pub fn strip_prefix(&self, base: &str) -> Option<ShadowPath> {
+        let base = Self::normalize_path(base);
+        let rest = self.inner.strip_prefix(base.as_str())?;
+        if rest.is_empty() || rest.starts_with('/') || base.ends_with('/') || base.is_empty() {
+            Some(ShadowPath::new(rest.trim_start_matches('/').to_owned()))
+        } else {
+            None
+        }
+    }
+    
+    /// Returns the parent path, if any.
+    pub fn parent(&self) -> Option<ShadowPath> {
+        match self.inner.as_str() {
+            "" | "/" => None,
+            path => match path.rfind('/') {
+                Some(0) => Some(ShadowPath::new("/".to_owned())),
+                Some(index) => Some(ShadowPath::new(path[..index].to_owned())),
+                None => Some(ShadowPath::new(String::new())),
+            },
+        }
+    }
+    
+    /// Returns the filename component, if any.
+    pub fn file_name(&self) -> Option<String> {
+        self.name().map(String::from)
+    }
+    
+    /// Returns the file stem (filename without extension), if any.
+    pub fn file_stem(&self) -> Option<String> {
+        self.name().map(|name| Self::split_extension(name).0.to_owned())
+    }
+    
+    /// Returns the file extension, if any.
+    pub fn extension(&self) -> Option<String> {
+        self.name().and_then(|name| Self::split_extension(name).1).map(String::from)
+    }
+    
+    /// Joins this path with another path component. An absolute component
+    /// replaces the path, as it does for a `PathBuf`.
+    pub fn join(&self, path: &str) -> ShadowPath {
+        if path.starts_with('/') || self.inner.is_empty() {
+            ShadowPath::new(path.to_owned())
+        } else {
+            ShadowPath::new([self.inner.as_str(), "/", path].concat())
+        }
+    }
+    
+    fn name(&self) -> Option<&str> {
+        self.inner.rsplit('/').next().filter(|name| !name.is_empty())
+    }
+    
+    /// Splits a filename at its last dot the way `Path::file_stem` does: a
+    /// leading dot starts a hidden name, not an extension.
+    fn split_extension(name: &str) -> (&str, Option<&str>) {
+        match name.rfind('.') {
+            Some(0) | None => (name, None),
+            Some(index) => (&name[..index], Some(&name[index + 1..])),
+        }
+    }
+}
+
+impl ShadowPath {
+    /// Returns the path with every character lowercased, the form under
+    /// which case-insensitive file systems treat two paths as the same.
+    /// A host path that is not valid Unicode folds to itself.
+    pub fn to_case_folded(&self) -> ShadowPath {
+        #[cfg(feature = "std")]
+        return match self.inner.to_str() {
+            Some(path) => Self { inner: PathBuf::from(path.to_lowercase()) },
+            None => self.clone(),
+        };
+        
+        #[cfg(not(feature = "std"))]
+        return Self { inner: self.inner.to_lowercase() };
+    }
+    
+    /// Returns true if both paths name the same file on a case-insensitive file system.
+    pub fn eq_ignore_case(&self, other: &ShadowPath) -> bool {
+        self == other || self.to_case_folded() == other.to_case_folded()
+    }
+    
+    /// Returns the NTFS named stream the path refers to: `meta` for
+    /// `/notes.txt:meta` or `/notes.txt:meta:$DATA`.
+    ///
+    /// Only the last component can name a stream, and `/notes.txt::$DATA`
+    /// is the unnamed stream, the file itself. Colons are ordinary filename
+    /// characters outside Windows, so only stores serving a Windows mount
+    /// treat such paths as streams.
+    pub fn stream_name(&self) -> Option<String> {
+        Self::split_stream(&self.file_name()?).1.map(String::from)
+    }
+    
+    /// Returns true if the path refers to a named stream of a file.
+    pub fn is_stream(&self) -> bool {
+        self.stream_name().is_some()
+    }
+    
+    /// Returns the path of the file a stream path belongs to, or the path
+    /// itself if it does not refer to a named stream.
+    pub fn without_stream(&self) -> ShadowPath {
+        let (Some(name), Some(parent)) = (self.file_name(), self.parent()) else {
+            return self.clone();
+        };
+        match Self::split_stream(&name) {
+            (file, Some(_)) => parent.join(file),
+            (_, None) => self.clone(),
+        }
+    }
+    
+    /// Returns the path of the named stream `stream` of the file at this path.
+    pub fn with_stream(&self, stream: &str) -> ShadowPath {
+        let file = self.without_stream();
+        let (Some(name), Some(parent)) = (file.file_name(), file.parent()) else {
+            return file;
+        };
+        parent.join([name.as_str(), ":", stream].concat().as_str())
+    }
+    
+    /// Splits a filename into the file and the named stream it refers to.
+    fn split_stream(name: &str) -> (&str, Option<&str>) {
+        let Some((file, stream)) = name.split_once(':') else {
+            return (name, None);
+        };
+        // The stream type is optional, and $DATA is the only one with content
+        let stream = match stream.rsplit_once(':') {
+            Some((stream, kind)) if kind.eq_ignore_ascii_case("$DATA") => stream,
+            _ => stream,
+        };
+        if file.is_empty() || stream.is_empty() {
+            (name, None)
+        } else {
+            (file, Some(stream))
+        }
+    }
+}
+
+impl fmt::Display for ShadowPath {
+    #[cfg(feature = "std")]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use alloc::string::ToString;
+        
+        // Display paths with forward slashes on all platforms
+        let display_str = if cfg!(windows) {
+            self.inner.display().to_string().replace('\\', "/")
+        } else {
+            self.inner.display().to_string()
+        };
+        write!(f, "{}", display_str)
+    }
+    
+    #[cfg(not(feature = "std"))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.inner)
+    }
+}
+
+impl From<&str> for ShadowPath {
+    fn from(s: &str) -> Self {
+        ShadowPath::new(s.into())
+    }
+}
+
+impl From<String> for ShadowPath {
+    fn from(s: String) -> Self {
+        #[cfg(feature = "std")]
+        return ShadowPath::new(PathBuf::from(s));
+        
+        #[cfg(not(feature = "std"))]
+        return ShadowPath::new(s);
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<PathBuf> for ShadowPath {
+    fn from(path: PathBuf) -> Self {
+        ShadowPath::new(path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl AsRef<Path> for ShadowPath {
+    fn as_ref(&self) -> &Path {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_path_normalization() {
+        let path = ShadowPath::from("./foo/../bar/./baz");
+        assert_eq!(path.to_host_path(), PathBuf::from("bar/baz"));
+    }
+    
+    #[test]
+    fn test_absolute_path() {
+        let abs_path = ShadowPath::from("/foo/bar");
+        assert!(abs_path.is_absolute());
+        
+        let rel_path = ShadowPath::from("foo/bar");
+        assert!(!rel_path.is_absolute());
+    }
+    
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_strip_prefix() {
+        let path = ShadowPath::from("/foo/bar/baz");
+        let stripped = path.strip_prefix("/foo").unwrap();
+        assert_eq!(stripped.to_host_path(), PathBuf::from("bar/baz"));
+    }
+    
+    #[test]
+    fn test_display_forward_slashes() {
+        let path = ShadowPath::from("foo/bar/baz");
+        assert_eq!(path.to_string(), "foo/bar/baz");
+    }
+    
+    #[test]
+    fn test_normalization_without_host_paths() {
+        // The std and string forms must agree, so these run under both
+        for (input, expected) in [
+            ("./foo/../bar/./baz", "bar/baz"),
+            ("/a//b/", "/a/b"),
+            ("/a/../..", ""),
+            ("../a", "a"),
+            ("/", "/"),
+        ] {
+            assert_eq!(ShadowPath::from(input).to_string(), expected, "{}", input);
+        }
+    }
+    
+    #[test]
+    fn test_components() {
+        let path = ShadowPath::from("/src/lib.tar.gz");
+        assert_eq!(path.parent().unwrap().to_string(), "/src");
+        assert_eq!(path.file_name().as_deref(), Some("lib.tar.gz"));
+        assert_eq!(path.file_stem().as_deref(), Some("lib.tar"));
+        assert_eq!(path.extension().as_deref(), Some("gz"));
+        assert_eq!(path.join("b").to_string(), "/src/lib.tar.gz/b");
+        assert_eq!(path.join("/etc").to_string(), "/etc");
+        assert_eq!(path.strip_prefix("/src").unwrap().to_string(), "lib.tar.gz");
+        assert!(path.strip_prefix("/sr").is_none());
+        
+        let hidden = ShadowPath::from("/home/.bashrc");
+        assert_eq!(hidden.file_stem().as_deref(), Some(".bashrc"));
+        assert_eq!(hidden.extension(), None);
+        
+        assert_eq!(ShadowPath::from("/a").parent().unwrap().to_string(), "/");
+        assert!(ShadowPath::from("/").parent().is_none());
+        assert!(ShadowPath::from("/").file_name().is_none());
+    }
+    
+    #[test]
+    fn test_case_folding() {
+        let path = ShadowPath::from("/Docs/ReadMe.MD");
+        assert_eq!(path.to_case_folded().to_string(), "/docs/readme.md");
+        assert!(path.eq_ignore_case(&ShadowPath::from("/DOCS/readme.md")));
+        assert!(!path.eq_ignore_case(&ShadowPath::from("/Docs/ReadMe.txt")));
+        assert_eq!(ShadowPath::from("/Straße").to_case_folded(), ShadowPath::from("/straße"));
+    }
+    
+    #[test]
+    fn test_named_streams() {
+        let stream = ShadowPath::from("/docs/notes.txt:meta");
+        assert_eq!(stream.stream_name().as_deref(), Some("meta"));
+        assert_eq!(stream.without_stream().to_string(), "/docs/notes.txt");
+        assert_eq!(ShadowPath::from("/docs/notes.txt:meta:$DATA").stream_name().as_deref(), Some("meta"));
+        assert_eq!(ShadowPath::from("/docs/notes.txt").with_stream("meta"), stream);
+        assert_eq!(stream.with_stream("other").to_string(), "/docs/notes.txt:other");
+        assert_eq!(ShadowPath::from("notes.txt:meta").without_stream().to_string(), "notes.txt");
+        
+        // The unnamed stream is the file itself
+        for path in ["/docs/notes.txt", "/docs/notes.txt::$DATA", "/docs/:meta", "/"] {
+            assert!(!ShadowPath::from(path).is_stream(), "{}", path);
+            assert_eq!(ShadowPath::from(path).without_stream(), ShadowPath::from(path));
+        }
+    }
+}
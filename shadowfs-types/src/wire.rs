@@ -0,0 +1,491 @@
+//! Stable wire form of [`ShadowError`].
+//!
+//! Errors that cross a process boundary, such as replies from the daemon,
+//! the FFI layer or the browser bindings, travel as a [`WireError`] rather
+//! than a display string, so the receiving side can still branch on what
+//! went wrong. An [`ErrorCode`] names one error variant and never changes
+//! meaning. Codes are only ever added; a peer that does not know a code
+//! reads it as [`ErrorCode::Unknown`] and falls back on the category and
+//! message.
+
+use crate::error::{LimitKind, Platform, ShadowError};
+use alloc::string::{String, ToString};
+
+#[cfg(feature = "std")]
+use crate::path::ShadowPath;
+
+/// Identifies the kind of a [`ShadowError`] on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ErrorCode {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    InvalidPath,
+    Io,
+    Platform,
+    OverrideStoreFull,
+    NotMounted,
+    Unsupported,
+    InvalidConfiguration,
+    Cancelled,
+    AccessDenied,
+    ReadOnlyFilesystem,
+    DirectoryNotEmpty,
+    Conflict,
+    Unauthorized,
+    MountLimitReached,
+    SourceNotWritable,
+    QuotaExceeded,
+    LimitExceeded,
+    /// A code this version does not know
+    #[cfg_attr(feature = "serde", serde(other))]
+    Unknown,
+}
+
+impl ErrorCode {
+    /// Returns the code as it is written on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::PermissionDenied => "permission_denied",
+            ErrorCode::AlreadyExists => "already_exists",
+            ErrorCode::NotADirectory => "not_a_directory",
+            ErrorCode::IsADirectory => "is_a_directory",
+            ErrorCode::InvalidPath => "invalid_path",
+            ErrorCode::Io => "io",
+            ErrorCode::Platform => "platform",
+            ErrorCode::OverrideStoreFull => "override_store_full",
+            ErrorCode::NotMounted => "not_mounted",
+            ErrorCode::Unsupported => "unsupported",
+            ErrorCode::InvalidConfiguration => "invalid_configuration",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::AccessDenied => "access_denied",
+            ErrorCode::ReadOnlyFilesystem => "read_only_filesystem",
+            ErrorCode::DirectoryNotEmpty => "directory_not_empty",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::MountLimitReached => "mount_limit_reached",
+            ErrorCode::SourceNotWritable => "source_not_writable",
+            ErrorCode::QuotaExceeded => "quota_exceeded",
+            ErrorCode::LimitExceeded => "limit_exceeded",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+    
+    /// Returns the category the code belongs to.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            ErrorCode::NotFound | ErrorCode::NotMounted => ErrorCategory::NotFound,
+            ErrorCode::AlreadyExists
+            | ErrorCode::NotADirectory
+            | ErrorCode::IsADirectory
+            | ErrorCode::DirectoryNotEmpty
+            | ErrorCode::Conflict => ErrorCategory::Conflict,
+            ErrorCode::PermissionDenied
+            | ErrorCode::AccessDenied
+            | ErrorCode::ReadOnlyFilesystem
+            | ErrorCode::Unauthorized
+            | ErrorCode::SourceNotWritable => ErrorCategory::Permission,
+            ErrorCode::InvalidPath | ErrorCode::InvalidConfiguration => ErrorCategory::InvalidInput,
+            ErrorCode::Unsupported => ErrorCategory::Unsupported,
+            ErrorCode::OverrideStoreFull
+            | ErrorCode::MountLimitReached
+            | ErrorCode::QuotaExceeded
+            | ErrorCode::LimitExceeded => ErrorCategory::Resource,
+            ErrorCode::Io => ErrorCategory::Io,
+            ErrorCode::Platform => ErrorCategory::Platform,
+            ErrorCode::Cancelled => ErrorCategory::Cancelled,
+            ErrorCode::Unknown => ErrorCategory::Unknown,
+        }
+    }
+}
+
+/// Broad class of an error, for callers that only care what kind of
+/// failure it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ErrorCategory {
+    /// The path or mount does not exist
+    NotFound,
+    /// The path exists, or is of the wrong type for the operation
+    Conflict,
+    /// The operation is not allowed
+    Permission,
+    /// A path or configuration was malformed
+    InvalidInput,
+    /// The feature is not available
+    Unsupported,
+    /// The store ran out of room
+    Resource,
+    /// An I/O operation failed
+    Io,
+    /// The platform filesystem API failed
+    Platform,
+    /// The operation was cancelled
+    Cancelled,
+    /// A category this version does not know
+    #[cfg_attr(feature = "serde", serde(other))]
+    Unknown,
+}
+
+impl ErrorCategory {
+    /// Returns the category as it is written on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::Conflict => "conflict",
+            ErrorCategory::Permission => "permission",
+            ErrorCategory::InvalidInput => "invalid_input",
+            ErrorCategory::Unsupported => "unsupported",
+            ErrorCategory::Resource => "resource",
+            ErrorCategory::Io => "io",
+            ErrorCategory::Platform => "platform",
+            ErrorCategory::Cancelled => "cancelled",
+            ErrorCategory::Unknown => "unknown",
+        }
+    }
+}
+
+/// A [`ShadowError`] in a form that can be serialized and sent to another
+/// process.
+///
+/// `message` is the display text of the error. The optional fields carry
+/// the variant's data, so that [`ShadowError::from`] can rebuild the
+/// original error on the other side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WireError {
+    /// Which error this is
+    pub code: ErrorCode,
+    
+    /// Broad class of the error
+    pub category: ErrorCategory,
+    
+    /// Human-readable description
+    pub message: String,
+    
+    /// Path or mount point the error is about
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub path: Option<String>,
+    
+    /// Whether the same request may succeed if retried later
+    pub retryable: bool,
+    
+    /// Operation that was refused or cancelled
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub operation: Option<String>,
+    
+    /// Why a path or configuration is invalid, the unsupported feature, the
+    /// limit a path exceeded, or the message of an I/O or platform error
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub detail: Option<String>,
+    
+    /// Platform a platform error came from
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub platform: Option<Platform>,
+    
+    /// OS error code of an I/O or platform error
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub os_code: Option<i32>,
+    
+    /// Store size when the store was full, or the value past a limit
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub current_size: Option<u64>,
+    
+    /// Store limit when the store was full, or the limit exceeded
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub max_size: Option<u64>,
+}
+
+impl WireError {
+    fn new(code: ErrorCode, message: String, retryable: bool) -> Self {
+        Self {
+            code,
+            category: code.category(),
+            message,
+            path: None,
+            retryable,
+            operation: None,
+            detail: None,
+            platform: None,
+            os_code: None,
+            current_size: None,
+            max_size: None,
+        }
+    }
+}
+
+impl ShadowError {
+    /// Returns the wire code of the error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ShadowError::NotFound { .. } => ErrorCode::NotFound,
+            ShadowError::PermissionDenied { .. } => ErrorCode::PermissionDenied,
+            ShadowError::AlreadyExists { .. } => ErrorCode::AlreadyExists,
+            ShadowError::NotADirectory { .. } => ErrorCode::NotADirectory,
+            ShadowError::IsADirectory { .. } => ErrorCode::IsADirectory,
+            ShadowError::InvalidPath { .. } => ErrorCode::InvalidPath,
+            #[cfg(feature = "std")]
+            ShadowError::IoError { .. } => ErrorCode::Io,
+            ShadowError::PlatformError { .. } => ErrorCode::Platform,
+            ShadowError::OverrideStoreFull { .. } => ErrorCode::OverrideStoreFull,
+            ShadowError::NotMounted { .. } => ErrorCode::NotMounted,
+            ShadowError::Unsupported { .. } => ErrorCode::Unsupported,
+            ShadowError::InvalidConfiguration { .. } => ErrorCode::InvalidConfiguration,
+            ShadowError::Cancelled { .. } => ErrorCode::Cancelled,
+            ShadowError::AccessDenied { .. } => ErrorCode::AccessDenied,
+            ShadowError::ReadOnlyFilesystem { .. } => ErrorCode::ReadOnlyFilesystem,
+            ShadowError::DirectoryNotEmpty { .. } => ErrorCode::DirectoryNotEmpty,
+            ShadowError::Conflict { .. } => ErrorCode::Conflict,
+            ShadowError::Unauthorized { .. } => ErrorCode::Unauthorized,
+            ShadowError::MountLimitReached { .. } => ErrorCode::MountLimitReached,
+            ShadowError::SourceNotWritable { .. } => ErrorCode::SourceNotWritable,
+            ShadowError::QuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+            ShadowError::LimitExceeded { .. } => ErrorCode::LimitExceeded,
+        }
+    }
+    
+    /// Returns the broad class of the error.
+    pub fn category(&self) -> ErrorCategory {
+        self.code().category()
+    }
+    
+    /// Returns true if the failure may be transient: the store was full,
+    /// which eviction can fix, a mount limit was reached, which frees up as
+    /// other mounts go away, or an I/O call was interrupted or timed out.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ShadowError::OverrideStoreFull { .. } | ShadowError::MountLimitReached { .. } => true,
+            #[cfg(feature = "std")]
+            ShadowError::IoError { source } => matches!(
+                source.kind(),
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl From<&ShadowError> for WireError {
+    fn from(error: &ShadowError) -> Self {
+        let mut wire = WireError::new(error.code(), error.to_string(), error.is_retryable());
+        match error {
+            ShadowError::NotFound { path }
+            | ShadowError::AlreadyExists { path }
+            | ShadowError::NotADirectory { path }
+            | ShadowError::IsADirectory { path }
+            | ShadowError::DirectoryNotEmpty { path }
+            | ShadowError::NotMounted { mount_point: path } => {
+                wire.path = Some(path.to_string());
+            }
+            ShadowError::PermissionDenied { path, operation }
+            | ShadowError::AccessDenied { path, operation }
+            | ShadowError::ReadOnlyFilesystem { path, operation } => {
+                wire.path = Some(path.to_string());
+                wire.operation = Some(operation.clone());
+            }
+            ShadowError::InvalidPath { path, reason } => {
+                wire.path = Some(path.clone());
+                wire.detail = Some(reason.clone());
+            }
+            ShadowError::Conflict { path, reason }
+            | ShadowError::MountLimitReached { mount_point: path, reason }
+            | ShadowError::SourceNotWritable { path, reason }
+            | ShadowError::QuotaExceeded { path, reason } => {
+                wire.path = Some(path.to_string());
+                wire.detail = Some(reason.clone());
+            }
+            #[cfg(feature = "std")]
+            ShadowError::IoError { source } => {
+                wire.detail = Some(source.to_string());
+                wire.os_code = source.raw_os_error();
+            }
+            ShadowError::PlatformError { platform, message, code } => {
+                wire.platform = Some(*platform);
+                wire.detail = Some(message.clone());
+                wire.os_code = *code;
+            }
+            ShadowError::OverrideStoreFull { current_size, max_size } => {
+                wire.current_size = Some(*current_size as u64);
+                wire.max_size = Some(*max_size as u64);
+            }
+            ShadowError::LimitExceeded { path, limit, actual, max } => {
+                wire.path = Some(path.to_string());
+                wire.detail = Some(limit.as_str().to_string());
+                wire.current_size = Some(*actual);
+                wire.max_size = Some(*max);
+            }
+            ShadowError::Unsupported { feature: detail }
+            | ShadowError::InvalidConfiguration { message: detail } => {
+                wire.detail = Some(detail.clone());
+            }
+            ShadowError::Cancelled { operation } => {
+                wire.operation = Some(operation.clone());
+            }
+            ShadowError::Unauthorized { operation, reason } => {
+                wire.operation = Some(operation.clone());
+                wire.detail = Some(reason.clone());
+            }
+        }
+        wire
+    }
+}
+
+impl From<ShadowError> for WireError {
+    fn from(error: ShadowError) -> Self {
+        WireError::from(&error)
+    }
+}
+
+/// Rebuilds the error a [`WireError`] was made from.
+///
+/// Codes this version does not know, platform errors without a platform,
+/// and limit errors naming a limit this version does not know, become I/O
+/// errors carrying the message.
+#[cfg(feature = "std")]
+impl From<WireError> for ShadowError {
+    fn from(wire: WireError) -> Self {
+        let WireError { code, message, path, operation, detail, platform, os_code, current_size, max_size, .. } = wire;
+        let shadow_path = || ShadowPath::from(path.clone().unwrap_or_default());
+        let limit = detail.as_deref().and_then(LimitKind::from_name);
+        let operation = operation.unwrap_or_default();
+        let detail = detail.unwrap_or_else(|| message.clone());
+        
+        match (code, platform) {
+            (ErrorCode::NotFound, _) => ShadowError::NotFound { path: shadow_path() },
+            (ErrorCode::PermissionDenied, _) => ShadowError::PermissionDenied { path: shadow_path(), operation },
+            (ErrorCode::AlreadyExists, _) => ShadowError::AlreadyExists { path: shadow_path() },
+            (ErrorCode::NotADirectory, _) => ShadowError::NotADirectory { path: shadow_path() },
+            (ErrorCode::IsADirectory, _) => ShadowError::IsADirectory { path: shadow_path() },
+            (ErrorCode::InvalidPath, _) => ShadowError::InvalidPath {
+                path: path.unwrap_or_default(),
+                reason: detail,
+            },
+            (ErrorCode::Platform, Some(platform)) => ShadowError::PlatformError {
+                platform,
+                message: detail,
+                code: os_code,
+            },
+            (ErrorCode::OverrideStoreFull, _) => ShadowError::OverrideStoreFull {
+                current_size: current_size.unwrap_or_default() as usize,
+                max_size: max_size.unwrap_or_default() as usize,
+            },
+            (ErrorCode::NotMounted, _) => ShadowError::NotMounted { mount_point: shadow_path() },
+            (ErrorCode::Unsupported, _) => ShadowError::Unsupported { feature: detail },
+            (ErrorCode::InvalidConfiguration, _) => ShadowError::InvalidConfiguration { message: detail },
+            (ErrorCode::Cancelled, _) => ShadowError::Cancelled { operation },
+            (ErrorCode::AccessDenied, _) => ShadowError::AccessDenied { path: shadow_path(), operation },
+            (ErrorCode::ReadOnlyFilesystem, _) => ShadowError::ReadOnlyFilesystem { path: shadow_path(), operation },
+            (ErrorCode::DirectoryNotEmpty, _) => ShadowError::DirectoryNotEmpty { path: shadow_path() },
+            (ErrorCode::Conflict, _) => ShadowError::Conflict { path: shadow_path(), reason: detail },
+            (ErrorCode::Unauthorized, _) => ShadowError::Unauthorized { operation, reason: detail },
+            (ErrorCode::MountLimitReached, _) => ShadowError::MountLimitReached { mount_point: shadow_path(), reason: detail },
+            (ErrorCode::SourceNotWritable, _) => ShadowError::SourceNotWritable { path: shadow_path(), reason: detail },
+            (ErrorCode::QuotaExceeded, _) => ShadowError::QuotaExceeded { path: shadow_path(), reason: detail },
+            (ErrorCode::LimitExceeded, _) if limit.is_some() => ShadowError::LimitExceeded {
+                path: shadow_path(),
+                limit: limit.unwrap(),
+                actual: current_size.unwrap_or_default(),
+                max: max_size.unwrap_or_default(),
+            },
+            (ErrorCode::Io | ErrorCode::Platform | ErrorCode::LimitExceeded | ErrorCode::Unknown, _) => {
+                let source = match os_code {
+                    Some(code) if code != 0 => std::io::Error::from_raw_os_error(code),
+                    _ => std::io::Error::new(std::io::ErrorKind::Other, detail),
+                };
+                ShadowError::IoError { source }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::ShadowPath;
+    
+    fn path() -> ShadowPath {
+        ShadowPath::from("/etc/hosts")
+    }
+    
+    #[test]
+    fn test_wire_fields() {
+        let wire = WireError::from(ShadowError::AccessDenied { path: path(), operation: "write".to_string() });
+        assert_eq!(wire.code, ErrorCode::AccessDenied);
+        assert_eq!(wire.category, ErrorCategory::Permission);
+        assert_eq!(wire.message, "Access denied: write on /etc/hosts");
+        assert_eq!(wire.path.as_deref(), Some("/etc/hosts"));
+        assert_eq!(wire.operation.as_deref(), Some("write"));
+        assert!(!wire.retryable);
+        
+        let full = WireError::from(ShadowError::OverrideStoreFull { current_size: 10, max_size: 8 });
+        assert_eq!(full.category, ErrorCategory::Resource);
+        assert!(full.retryable);
+        assert_eq!((full.current_size, full.max_size), (Some(10), Some(8)));
+        
+        assert_eq!(ErrorCode::ReadOnlyFilesystem.as_str(), "read_only_filesystem");
+    }
+    
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_round_trip() {
+        let errors = std::vec![
+            ShadowError::NotFound { path: path() },
+            ShadowError::PermissionDenied { path: path(), operation: "read".to_string() },
+            ShadowError::InvalidPath { path: "a//b".to_string(), reason: "empty component".to_string() },
+            ShadowError::IoError { source: std::io::Error::from_raw_os_error(2) },
+            ShadowError::PlatformError { platform: Platform::MacOS, message: "FSKit".to_string(), code: Some(-1) },
+            ShadowError::OverrideStoreFull { current_size: 1024, max_size: 512 },
+            ShadowError::NotMounted { mount_point: ShadowPath::from("/mnt/shadow") },
+            ShadowError::Unsupported { feature: "xattrs".to_string() },
+            ShadowError::Cancelled { operation: "commit".to_string() },
+            ShadowError::ReadOnlyFilesystem { path: path(), operation: "delete".to_string() },
+            ShadowError::DirectoryNotEmpty { path: path() },
+            ShadowError::Conflict { path: path(), reason: "changed in both stores".to_string() },
+            ShadowError::Unauthorized { operation: "commit".to_string(), reason: "token expired".to_string() },
+            ShadowError::MountLimitReached { mount_point: path(), reason: "memory budget exhausted".to_string() },
+            ShadowError::SourceNotWritable { path: path(), reason: "permission denied".to_string() },
+            ShadowError::QuotaExceeded { path: path(), reason: "1001 files would exceed the limit of 1000".to_string() },
+            ShadowError::LimitExceeded { path: path(), limit: LimitKind::PathDepth, actual: 65, max: 64 },
+        ];
+        for error in errors {
+            let rebuilt = ShadowError::from(WireError::from(&error));
+            assert_eq!(rebuilt.code(), error.code());
+            assert_eq!(rebuilt.to_string(), error.to_string());
+        }
+        
+        let interrupted = ShadowError::IoError { source: std::io::ErrorKind::Interrupted.into() };
+        assert!(WireError::from(interrupted).retryable);
+    }
+    
+    #[cfg(all(feature = "serde", feature = "std"))]
+    #[test]
+    fn test_serde_form() {
+        let wire = WireError::from(ShadowError::NotFound { path: path() });
+        let json = serde_json::to_value(&wire).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "code": "not_found",
+            "category": "not_found",
+            "message": "Path not found: /etc/hosts",
+            "path": "/etc/hosts",
+            "retryable": false,
+        }));
+        
+        // Codes and categories from a newer peer still decode
+        let newer: WireError = serde_json::from_value(serde_json::json!({
+            "code": "lease_expired",
+            "category": "lease",
+            "message": "Lease expired",
+            "retryable": true,
+        }))
+        .unwrap();
+        assert_eq!(newer.code, ErrorCode::Unknown);
+        assert_eq!(newer.category, ErrorCategory::Unknown);
+        assert!(matches!(ShadowError::from(newer), ShadowError::IoError { .. }));
+    }
+}
@@ -0,0 +1,185 @@
+//! IndexedDB persistence for the browser store.
+//!
+//! Each override is one record in the `overrides` object store, keyed by its
+//! path: `{ kind: "file", data: Uint8Array }`, `{ kind: "directory" }` or
+//! `{ kind: "deleted" }` for a tombstone. Metadata is not kept, so entries
+//! read back with fresh timestamps and default permissions.
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+/// Object store holding one record per override.
+const OBJECT_STORE: &str = "overrides";
+
+/// Schema version passed to `indexedDB.open`.
+const SCHEMA_VERSION: u32 = 1;
+
+/// A persisted override.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Record {
+    /// File content, decompressed
+    File(Vec<u8>),
+    /// Directory
+    Directory,
+    /// Tombstone hiding a source path
+    Deleted,
+}
+
+impl Record {
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let object = Object::new();
+        let kind = match self {
+            Record::File(_) => "file",
+            Record::Directory => "directory",
+            Record::Deleted => "deleted",
+        };
+        Reflect::set(&object, &"kind".into(), &kind.into())?;
+        if let Record::File(data) = self {
+            Reflect::set(&object, &"data".into(), &Uint8Array::from(data.as_slice()))?;
+        }
+        Ok(object.into())
+    }
+    
+    fn from_js(value: &JsValue) -> Result<Self, JsValue> {
+        match Reflect::get(value, &"kind".into())?.as_string().as_deref() {
+            Some("file") => {
+                let data = Reflect::get(value, &"data".into())?.dyn_into::<Uint8Array>()?;
+                Ok(Record::File(data.to_vec()))
+            }
+            Some("directory") => Ok(Record::Directory),
+            Some("deleted") => Ok(Record::Deleted),
+            _ => Err(JsValue::from_str("unrecognised override record")),
+        }
+    }
+}
+
+/// An open IndexedDB database holding a store's overrides.
+#[derive(Debug, Clone)]
+pub(crate) struct IdbBackend {
+    db: IdbDatabase,
+}
+
+impl IdbBackend {
+    /// Opens the database `name`, creating it on first use. Works in both
+    /// windows and workers.
+    pub(crate) async fn open(name: &str) -> Result<Self, JsValue> {
+        let factory = Reflect::get(&js_sys::global(), &"indexedDB".into())?
+            .dyn_into::<IdbFactory>()
+            .map_err(|_| JsValue::from_str("IndexedDB is not available"))?;
+        let request = factory.open_with_u32(name, SCHEMA_VERSION)?;
+        
+        let upgrading = request.clone();
+        let on_upgrade = Closure::once_into_js(move || {
+            let Ok(db) = upgrading.result().and_then(|db| db.dyn_into::<IdbDatabase>()) else {
+                return;
+            };
+            if !db.object_store_names().contains(OBJECT_STORE) {
+                let _ = db.create_object_store(OBJECT_STORE);
+            }
+        });
+        request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+        
+        let db = settle(&request).await?.dyn_into::<IdbDatabase>()?;
+        Ok(Self { db })
+    }
+    
+    /// Reads every persisted override, ordered by path so that parents come
+    /// before their children.
+    pub(crate) async fn load(&self) -> Result<Vec<(String, Record)>, JsValue> {
+        let transaction = self.db.transaction_with_str_and_mode(OBJECT_STORE, IdbTransactionMode::Readonly)?;
+        let store = transaction.object_store(OBJECT_STORE)?;
+        
+        // Both requests need their handlers before either can complete
+        let keys = settle(&store.get_all_keys()?);
+        let values = settle(&store.get_all()?);
+        let keys: Array = keys.await?.unchecked_into();
+        let values: Array = values.await?.unchecked_into();
+        
+        keys.iter()
+            .zip(values.iter())
+            .map(|(key, value)| {
+                let path = key.as_string().ok_or_else(|| JsValue::from_str("override key is not a path"))?;
+                Ok((path, Record::from_js(&value)?))
+            })
+            .collect()
+    }
+    
+    /// Writes `changes` in one transaction, deleting the records of paths
+    /// whose record is `None`. With `replace_all`, every other record is
+    /// removed first.
+    pub(crate) async fn write(&self, changes: Vec<(String, Option<Record>)>, replace_all: bool) -> Result<(), JsValue> {
+        let transaction = self.db.transaction_with_str_and_mode(OBJECT_STORE, IdbTransactionMode::Readwrite)?;
+        let completed = complete(&transaction);
+        
+        let queued = (|| {
+            let store = transaction.object_store(OBJECT_STORE)?;
+            if replace_all {
+                store.clear()?;
+            }
+            for (path, record) in changes {
+                let key = JsValue::from_str(&path);
+                match record {
+                    Some(record) => store.put_with_key(&record.to_js()?, &key)?,
+                    None => store.delete(&key)?,
+                };
+            }
+            Ok::<_, JsValue>(())
+        })();
+        
+        if let Err(error) = queued {
+            // Leave the database as it was rather than half-written
+            let _ = transaction.abort();
+            return Err(error);
+        }
+        completed.await.map(|_| ())
+    }
+}
+
+/// Resolves with the result of `request`, or rejects with its error.
+///
+/// The handlers are attached before this returns, so several requests can
+/// be issued before any of them is awaited.
+fn settle(request: &IdbRequest) -> JsFuture {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let succeeded = request.clone();
+        let on_success = Closure::once_into_js(move || {
+            let _ = resolve.call1(&JsValue::UNDEFINED, &succeeded.result().unwrap_or(JsValue::UNDEFINED));
+        });
+        
+        let failed = request.clone();
+        let on_error = Closure::once_into_js(move || {
+            let error = failed.error().ok().flatten().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+        
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise)
+}
+
+/// Resolves once `transaction` commits, or rejects if it fails or aborts.
+/// Like [`settle`], it listens from the moment it is called.
+fn complete(transaction: &IdbTransaction) -> JsFuture {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_complete = Closure::once_into_js(move || {
+            let _ = resolve.call0(&JsValue::UNDEFINED);
+        });
+        
+        // A failed request fires error and then abort; whichever comes first wins
+        let failed = transaction.clone();
+        let on_error = Closure::<dyn FnMut()>::new(move || {
+            let error = failed.error().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        })
+        .into_js_value();
+        
+        transaction.set_oncomplete(Some(on_complete.unchecked_ref()));
+        transaction.set_onerror(Some(on_error.unchecked_ref()));
+        transaction.set_onabort(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise)
+}
@@ -0,0 +1,426 @@
+//! # ShadowFS for the browser
+//!
+//! The override store compiled to `wasm32-unknown-unknown`, for web
+//! playgrounds that demonstrate shadow semantics and let users try out rule
+//! sets before deploying them. There is no source tree and no platform
+//! provider: JavaScript reads and writes overrides directly, and a mount's
+//! view of them is what the store reports.
+//!
+//! ```js
+//! import init, { ShadowStore } from "shadowfs-wasm";
+//!
+//! await init();
+//! const store = await ShadowStore.open("playground");
+//! store.writeFile("/etc/hosts", new TextEncoder().encode("127.0.0.1 demo\n"));
+//! store.remove("/etc/motd");
+//! store.addRule("**/*.env", new TextEncoder().encode("SECRET=redacted\n"), 10);
+//! await store.save();
+//! ```
+//!
+//! Overrides persist to IndexedDB, one record per path, through
+//! [`ShadowStore::save`]. Background components such as the
+//! evictor and the supervisor need timers and threads, so they are not
+//! available here; eviction still runs inline when an insert would exceed
+//! the memory limit.
+//!
+//! Failed calls throw an `Error` carrying the `code`, `category`,
+//! `retryable` and `path` of the store's wire error form, so scripts can
+//! tell a missing path from a full store without matching on messages.
+//!
+//! Build with `wasm-pack build shadowfs-wasm --target web`.
+
+mod idb;
+
+use bytes::Bytes;
+use idb::{IdbBackend, Record};
+use js_sys::{Object, Promise, Reflect};
+use shadowfs_core::error::{ShadowError, WireError};
+use shadowfs_core::override_store::{
+    OverrideContent, OverrideContentType, OverrideCondition, OverrideRule, OverrideRuleEntry, OverrideStore,
+    OverrideStoreConfig, RenameCollision, RenameOptions, RulePriority, RuleSet,
+};
+use shadowfs_core::types::ShadowPath;
+use shadowfs_core::watch::{ChangeKind, WatchFilter, WatchService, WatchSubscription};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+/// Change events buffered between saves before falling back to rewriting
+/// every record.
+const PENDING_CHANGE_CAPACITY: usize = 4096;
+
+/// An override store for JavaScript, optionally backed by IndexedDB.
+#[wasm_bindgen]
+pub struct ShadowStore {
+    store: OverrideStore,
+    rules: RuleSet,
+    backend: Option<IdbBackend>,
+    watch: Arc<WatchService>,
+    changes: WatchSubscription,
+    dirty: HashSet<ShadowPath>,
+    /// Value of `watch.dropped_events()` at the last save
+    dropped_at_save: u64,
+    /// Set when a save fails, so the next one rewrites everything
+    rewrite_all: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen]
+impl ShadowStore {
+    /// Creates an in-memory store. `maxMemory` defaults to the store's usual
+    /// limit; `save()` does nothing.
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_memory: Option<usize>) -> ShadowStore {
+        Self::with_backend(max_memory, None)
+    }
+    
+    /// Opens a store persisted in the IndexedDB database `name`, loading the
+    /// overrides saved there.
+    pub async fn open(name: String, max_memory: Option<usize>) -> Result<ShadowStore, JsValue> {
+        let backend = IdbBackend::open(&name).await?;
+        let records = backend.load().await?;
+        let mut shadow = Self::with_backend(max_memory, Some(backend));
+        
+        for (path, record) in records {
+            let path = ShadowPath::from(path);
+            match record {
+                Record::File(data) => shadow.store.insert_file(path, Bytes::from(data), None),
+                Record::Directory => shadow.store.insert_directory(path, None),
+                Record::Deleted => shadow.store.mark_deleted(path),
+            }
+            .map_err(js_error)?;
+        }
+        
+        // Loading is not a change that needs saving
+        while shadow.changes.try_recv().is_some() {}
+        shadow.dropped_at_save = shadow.watch.dropped_events();
+        Ok(shadow)
+    }
+    
+    fn with_backend(max_memory: Option<usize>, backend: Option<IdbBackend>) -> Self {
+        let defaults = OverrideStoreConfig::default();
+        let store = OverrideStore::new(OverrideStoreConfig {
+            max_memory: max_memory.unwrap_or(defaults.max_memory),
+            ..defaults
+        });
+        let watch = Arc::new(WatchService::new(PENDING_CHANGE_CAPACITY));
+        let changes = watch.subscribe(WatchFilter::all());
+        store.set_watch_service(Arc::clone(&watch));
+        
+        Self {
+            store,
+            rules: RuleSet::new(),
+            backend,
+            watch,
+            changes,
+            dirty: HashSet::new(),
+            dropped_at_save: 0,
+            rewrite_all: Rc::new(Cell::new(false)),
+        }
+    }
+    
+    /// Stores `data` as the content of `path`.
+    #[wasm_bindgen(js_name = writeFile)]
+    pub fn write_file(&self, path: &str, data: &[u8]) -> Result<(), JsValue> {
+        self.store
+            .insert_file(ShadowPath::from(path), Bytes::copy_from_slice(data), None)
+            .map_err(js_error)
+    }
+    
+    /// Returns the content of `path`, or `undefined` if it has no file
+    /// override.
+    #[wasm_bindgen(js_name = readFile)]
+    pub fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>, JsValue> {
+        let Some(entry) = self.store.get(&ShadowPath::from(path)) else {
+            return Ok(None);
+        };
+        let data = entry.get_file_data().map_err(js_error)?;
+        Ok(data.map(|data| data.to_vec()))
+    }
+    
+    /// Creates `path` and any missing parents as directory overrides.
+    pub fn mkdir(&self, path: &str) -> Result<(), JsValue> {
+        self.store
+            .create_directory_hierarchy(&ShadowPath::from(path))
+            .map_err(js_error)
+    }
+    
+    /// Hides `path` behind a tombstone, as deleting it through a mount would.
+    pub fn remove(&self, path: &str) -> Result<(), JsValue> {
+        self.store.mark_deleted(ShadowPath::from(path)).map_err(js_error)
+    }
+    
+    /// Drops the override for `path`, so the source shows through again.
+    pub fn revert(&mut self, path: &str) -> bool {
+        // Removing an override publishes no event, so record it here
+        let path = ShadowPath::from(path);
+        let removed = self.store.remove(&path).is_some();
+        if removed {
+            self.dirty.insert(path);
+        }
+        removed
+    }
+    
+    /// Renames `from` to `to`, moving everything below it. `mode` is
+    /// `"overwrite"` (the default), `"noreplace"` or `"exchange"`.
+    pub fn rename(&mut self, from: &str, to: &str, mode: Option<String>) -> Result<(), JsValue> {
+        let collision = match mode.as_deref() {
+            None | Some("overwrite") => RenameCollision::Overwrite,
+            Some("noreplace") => RenameCollision::NoReplace,
+            Some("exchange") => RenameCollision::Exchange,
+            Some(other) => return Err(JsError::new(&format!("unknown rename mode '{}'", other)).into()),
+        };
+        let (from, to) = (ShadowPath::from(from), ShadowPath::from(to));
+        
+        // Overrides removed from the old tree publish no event either
+        let touched: Vec<ShadowPath> = self.store.all_paths()
+            .into_iter()
+            .filter(|path| path.as_path().starts_with(from.as_path()) || path.as_path().starts_with(to.as_path()))
+            .collect();
+        let options = RenameOptions { collision, ..RenameOptions::default() };
+        self.store.rename(&from, to, options).map_err(js_error)?;
+        self.dirty.extend(touched);
+        Ok(())
+    }
+    
+    /// Returns true if `path` has an override that is not a tombstone.
+    pub fn exists(&self, path: &str) -> bool {
+        let path = ShadowPath::from(path);
+        self.store.exists(&path) && !self.store.is_deleted(&path)
+    }
+    
+    /// Returns true if `path` is hidden by a tombstone.
+    #[wasm_bindgen(js_name = isDeleted)]
+    pub fn is_deleted(&self, path: &str) -> bool {
+        self.store.is_deleted(&ShadowPath::from(path))
+    }
+    
+    /// Describes the override of each of `paths`, looked up in one batch:
+    /// `undefined` for a path without one, or an object with its `type`
+    /// (`"file"`, `"directory"` or `"deleted"`) and `size`.
+    #[wasm_bindgen(js_name = statMany)]
+    pub fn stat_many(&self, paths: Vec<String>) -> Vec<JsValue> {
+        let paths: Vec<ShadowPath> = paths.iter().map(|path| ShadowPath::from(path.as_str())).collect();
+        self.store
+            .get_many(&paths)
+            .into_iter()
+            .map(|entry| {
+                let Some(entry) = entry else {
+                    return JsValue::UNDEFINED;
+                };
+                let kind = match entry.content {
+                    OverrideContent::File { .. } => "file",
+                    OverrideContent::Directory { .. } => "directory",
+                    OverrideContent::Deleted => "deleted",
+                };
+                let stat: JsValue = Object::new().into();
+                let _ = Reflect::set(&stat, &"type".into(), &kind.into());
+                let _ = Reflect::set(&stat, &"size".into(), &(entry.override_metadata.size as f64).into());
+                stat
+            })
+            .collect()
+    }
+    
+    /// Lists the names in the directory override at `path`.
+    #[wasm_bindgen(js_name = readDir)]
+    pub fn read_dir(&self, path: &str) -> Result<Vec<String>, JsValue> {
+        let entries = self.store.list_directory(&ShadowPath::from(path)).map_err(js_error)?;
+        Ok(entries.into_iter().map(|entry| entry.name).collect())
+    }
+    
+    /// Lists every overridden path, tombstones included, in sorted order.
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self.store.all_paths().iter().map(ShadowPath::to_string).collect();
+        paths.sort();
+        paths
+    }
+    
+    /// Number of overrides in the store.
+    #[wasm_bindgen(getter, js_name = entryCount)]
+    pub fn entry_count(&self) -> usize {
+        self.store.entry_count()
+    }
+    
+    /// Adds a rule serving `content` for paths matching the glob `pattern`.
+    /// Rules with a higher `priority` are consulted first.
+    #[wasm_bindgen(js_name = addRule)]
+    pub fn add_rule(&self, pattern: &str, content: &[u8], priority: u32) {
+        self.add_rule_entry(OverrideRule::Glob(pattern.to_string()), content, priority);
+    }
+    
+    /// Adds a rule serving `content` for paths matching the regular
+    /// expression `pattern`.
+    #[wasm_bindgen(js_name = addRegexRule)]
+    pub fn add_regex_rule(&self, pattern: &str, content: &[u8], priority: u32) -> Result<(), JsValue> {
+        let rule = OverrideRule::regex(pattern).map_err(|error| JsError::new(&error.to_string()))?;
+        self.add_rule_entry(rule, content, priority);
+        Ok(())
+    }
+    
+    fn add_rule_entry(&self, rule: OverrideRule, content: &[u8], priority: u32) {
+        self.rules.add_rule(OverrideRuleEntry {
+            rule,
+            priority: RulePriority(priority),
+            condition: OverrideCondition::Always,
+            content: OverrideContentType::Static(Bytes::copy_from_slice(content)),
+        });
+    }
+    
+    /// Returns the content the highest-priority matching rule would serve
+    /// for `path`, or `undefined` if no rule matches.
+    #[wasm_bindgen(js_name = previewRule)]
+    pub fn preview_rule(&self, path: &str) -> Option<Vec<u8>> {
+        let entry = self.rules.find_match(&ShadowPath::from(path), None)?;
+        match entry.content {
+            OverrideContentType::Static(content) => Some(content.to_vec()),
+            _ => None,
+        }
+    }
+    
+    /// Number of rules added.
+    #[wasm_bindgen(getter, js_name = ruleCount)]
+    pub fn rule_count(&self) -> usize {
+        self.rules.rule_count()
+    }
+    
+    /// Removes every rule.
+    #[wasm_bindgen(js_name = clearRules)]
+    pub fn clear_rules(&self) {
+        self.rules.clear();
+    }
+    
+    /// Writes the overrides changed since the last save to IndexedDB. The
+    /// returned promise settles once the transaction commits; a failed save
+    /// is retried in full by the next one.
+    pub fn save(&mut self) -> Promise {
+        let Some(backend) = self.backend.clone() else {
+            return Promise::resolve(&JsValue::UNDEFINED);
+        };
+        
+        while let Some(event) = self.changes.try_recv() {
+            if let ChangeKind::Renamed { from } = event.kind {
+                self.dirty.insert(from);
+            }
+            self.dirty.insert(event.path);
+        }
+        
+        // Dropped events mean the dirty set is incomplete
+        let dropped = self.watch.dropped_events();
+        let replace_all = self.rewrite_all.replace(false) || dropped != self.dropped_at_save;
+        self.dropped_at_save = dropped;
+        
+        let dirty = std::mem::take(&mut self.dirty);
+        let paths = if replace_all { self.store.all_paths() } else { dirty.into_iter().collect() };
+        let changes: Result<Vec<(String, Option<Record>)>, JsValue> = paths
+            .into_iter()
+            .map(|path| Ok((path.to_string(), self.record(&path)?)))
+            .collect();
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(error) => {
+                self.rewrite_all.set(true);
+                return Promise::reject(&error);
+            }
+        };
+        
+        let rewrite_all = Rc::clone(&self.rewrite_all);
+        wasm_bindgen_futures::future_to_promise(async move {
+            if let Err(error) = backend.write(changes, replace_all).await {
+                rewrite_all.set(true);
+                return Err(error);
+            }
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+    
+    /// The record to persist for `path`, or `None` if it has no override.
+    fn record(&self, path: &ShadowPath) -> Result<Option<Record>, JsValue> {
+        let Some(entry) = self.store.get(path) else {
+            return Ok(None);
+        };
+        let record = match &entry.content {
+            OverrideContent::File { .. } => {
+                let data = entry.get_file_data().map_err(js_error)?.unwrap_or_default();
+                Record::File(data.to_vec())
+            }
+            OverrideContent::Directory { .. } => Record::Directory,
+            OverrideContent::Deleted => Record::Deleted,
+        };
+        Ok(Some(record))
+    }
+}
+
+/// Converts a store error into a JS `Error` whose `code`, `category`,
+/// `retryable` and `path` properties follow [`WireError`], so callers can
+/// branch on them instead of parsing the message.
+fn js_error(error: ShadowError) -> JsValue {
+    let wire = WireError::from(&error);
+    let value: JsValue = JsError::new(&wire.message).into();
+    let _ = Reflect::set(&value, &"code".into(), &wire.code.as_str().into());
+    let _ = Reflect::set(&value, &"category".into(), &wire.category.as_str().into());
+    let _ = Reflect::set(&value, &"retryable".into(), &wire.retryable.into());
+    if let Some(path) = &wire.path {
+        let _ = Reflect::set(&value, &"path".into(), &path.as_str().into());
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_file_round_trip() {
+        let store = ShadowStore::new(None);
+        store.mkdir("/etc").unwrap();
+        store.write_file("/etc/hosts", b"127.0.0.1 demo\n").unwrap();
+        
+        assert!(store.exists("/etc/hosts"));
+        assert_eq!(store.read_file("/etc/hosts").unwrap().as_deref(), Some(&b"127.0.0.1 demo\n"[..]));
+        assert_eq!(store.read_dir("/etc").unwrap(), vec!["hosts".to_string()]);
+        assert_eq!(store.paths(), vec!["/".to_string(), "/etc".to_string(), "/etc/hosts".to_string()]);
+    }
+    
+    #[test]
+    fn test_remove_and_revert() {
+        let mut store = ShadowStore::new(None);
+        store.write_file("/motd", b"hello").unwrap();
+        
+        store.remove("/motd").unwrap();
+        assert!(store.is_deleted("/motd"));
+        assert!(!store.exists("/motd"));
+        assert_eq!(store.read_file("/motd").unwrap(), None);
+        
+        assert!(store.revert("/motd"));
+        assert!(store.dirty.contains(&ShadowPath::from("/motd")));
+        assert!(!store.is_deleted("/motd"));
+        assert_eq!(store.entry_count(), 0);
+    }
+    
+    #[test]
+    fn test_rename_marks_old_paths_dirty() {
+        let mut store = ShadowStore::new(None);
+        store.mkdir("/drafts").unwrap();
+        store.write_file("/drafts/post.md", b"# Hello").unwrap();
+        
+        store.rename("/drafts", "/posts", None).unwrap();
+        assert!(!store.exists("/drafts/post.md"));
+        assert_eq!(store.read_file("/posts/post.md").unwrap().as_deref(), Some(&b"# Hello"[..]));
+        assert!(store.dirty.contains(&ShadowPath::from("/drafts/post.md")));
+    }
+    
+    #[test]
+    fn test_rule_preview_prefers_priority() {
+        let store = ShadowStore::new(None);
+        store.add_rule("*.env", b"low", 1);
+        store.add_regex_rule(r"^/app/.*\.env$", b"high", 10).unwrap();
+        
+        assert_eq!(store.preview_rule("/app/prod.env").as_deref(), Some(&b"high"[..]));
+        assert_eq!(store.preview_rule("/other/dev.env").as_deref(), Some(&b"low"[..]));
+        assert_eq!(store.preview_rule("/app/readme.md"), None);
+        
+        store.clear_rules();
+        assert_eq!(store.rule_count(), 0);
+    }
+}
@@ -129,6 +129,9 @@ impl From<WindowsError> for windows::core::Error {
             WindowsError::ProjFSError { hresult, .. } => {
                 windows::core::Error::from(windows::core::HRESULT(hresult))
             }
+            WindowsError::QueueFull(_) => {
+                windows::core::Error::from_win32(170) // ERROR_BUSY
+            }
             _ => {
                 // Default to generic error
                 windows::core::Error::from_win32(5) // ERROR_ACCESS_DENIED
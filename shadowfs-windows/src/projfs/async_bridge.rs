@@ -3,11 +3,12 @@ use std::thread;
 use std::collections::BinaryHeap;
 use std::cmp::{Ordering, Reverse};
 use std::sync::atomic::{AtomicU64, AtomicBool, Ordering as AtomicOrdering};
-use tokio::sync::{mpsc, oneshot, Semaphore, RwLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Notify, Semaphore, RwLock};
 use tokio::runtime::Handle;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, warn, trace};
-use windows::core::Result;
+use windows::core::{Result, HRESULT};
 use windows::Win32::Storage::ProjectedFileSystem::*;
 
 use crate::error::WindowsError;
@@ -17,23 +18,46 @@ const DEFAULT_WORKER_THREADS: usize = 4;
 const DEFAULT_QUEUE_SIZE: usize = 1000;
 const DEFAULT_MAX_CONCURRENT_OPS: usize = 100;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Reads up to this many bytes are scheduled before larger ones
+pub const SMALL_READ_BYTES: u32 = 64 * 1024;
+
+/// Wait before a request refused by a full queue is offered again; doubled
+/// on every further refusal
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Longest wait between two offers of a deferred request
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Offers of a deferred request before it fails with `ERROR_BUSY`
+const MAX_DEFERRALS: u32 = 8;
+
+/// `ERROR_IO_PENDING`: the request completes later
+const ERROR_IO_PENDING: u32 = 997;
+
+/// Order in which queued callbacks are served, most urgent first
+///
+/// Metadata comes before data so Explorer can keep browsing while files
+/// hydrate, and small reads come before large ones so opening a document
+/// does not wait behind a bulk copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TaskPriority {
-    Critical = 0,  // Highest priority - read operations
+    Critical = 0,  // Metadata lookups: placeholder info and file names
     High = 1,      // Directory enumeration
-    Normal = 2,    // Metadata operations
-    Low = 3,       // Notifications
+    Normal = 2,    // Reads of up to SMALL_READ_BYTES
+    Low = 3,       // Larger reads and notifications
 }
 
 impl TaskPriority {
-    fn from_request(request: &CallbackRequest) -> Self {
+    /// Returns the priority `request` is queued at
+    pub fn of(request: &CallbackRequest) -> Self {
         match request {
-            CallbackRequest::GetFileData { .. } => TaskPriority::Critical,
-            CallbackRequest::GetPlaceholderInfo { .. } => TaskPriority::High,
+            CallbackRequest::GetPlaceholderInfo { .. } |
+            CallbackRequest::QueryFileName { .. } => TaskPriority::Critical,
             CallbackRequest::StartDirectoryEnumeration { .. } |
             CallbackRequest::GetDirectoryEnumeration { .. } |
             CallbackRequest::EndDirectoryEnumeration { .. } => TaskPriority::High,
-            CallbackRequest::QueryFileName { .. } => TaskPriority::Normal,
+            CallbackRequest::GetFileData { length, .. } if *length <= SMALL_READ_BYTES => TaskPriority::Normal,
+            CallbackRequest::GetFileData { .. } |
             CallbackRequest::Notification { .. } => TaskPriority::Low,
         }
     }
@@ -84,6 +108,36 @@ pub enum CallbackRequest {
     },
 }
 
+/// How a submitted callback was taken by the bridge
+#[derive(Debug, Clone)]
+pub enum Dispatch {
+    /// The request is queued
+    Queued(CancellationToken),
+    
+    /// The queue was full; the request is offered again after a backoff
+    Deferred(CancellationToken),
+}
+
+impl Dispatch {
+    /// Returns the token cancelling the request
+    pub fn token(&self) -> &CancellationToken {
+        match self {
+            Dispatch::Queued(token) | Dispatch::Deferred(token) => token,
+        }
+    }
+    
+    /// Returns true if the request waits for room in the queue
+    pub fn is_deferred(&self) -> bool {
+        matches!(self, Dispatch::Deferred(_))
+    }
+    
+    /// Returns the HRESULT a ProjFS callback returns for a deferred request,
+    /// `ERROR_IO_PENDING`, so the callback thread is not held while it waits
+    pub fn pending_hresult() -> HRESULT {
+        HRESULT::from(windows::Win32::Foundation::WIN32_ERROR(ERROR_IO_PENDING))
+    }
+}
+
 #[derive(Debug)]
 struct PrioritizedTask {
     request: CallbackRequest,
@@ -91,6 +145,7 @@ struct PrioritizedTask {
     sequence: u64,
     cancellation_token: CancellationToken,
     submitted_at: std::time::Instant,
+    deferrals: u32,
 }
 
 impl PartialEq for PrioritizedTask {
@@ -116,39 +171,83 @@ impl Ord for PrioritizedTask {
     }
 }
 
+/// Outcome of offering a task to a bounded queue
+#[derive(Debug)]
+enum Admission {
+    /// The task was queued
+    Queued,
+    
+    /// The task was queued in place of this less urgent one
+    Evicted(PrioritizedTask),
+    
+    /// The queue is full of tasks at least as urgent; the task is handed back
+    Refused(PrioritizedTask),
+}
+
 struct PriorityQueue {
     heap: Arc<RwLock<BinaryHeap<Reverse<PrioritizedTask>>>>,
+    capacity: usize,
     sequence_counter: AtomicU64,
     active_tasks: Arc<RwLock<Vec<(u64, CancellationToken)>>>,
+    
+    /// Wakes a worker when a task is queued
+    available: Notify,
 }
 
 impl PriorityQueue {
-    fn new() -> Self {
+    fn new(capacity: usize) -> Self {
         Self {
             heap: Arc::new(RwLock::new(BinaryHeap::new())),
+            capacity: capacity.max(1),
             sequence_counter: AtomicU64::new(0),
             active_tasks: Arc::new(RwLock::new(Vec::new())),
+            available: Notify::new(),
         }
     }
 
-    async fn push(&self, request: CallbackRequest) -> CancellationToken {
-        let sequence = self.sequence_counter.fetch_add(1, AtomicOrdering::SeqCst);
-        let priority = TaskPriority::from_request(&request);
-        let cancellation_token = CancellationToken::new();
-        
-        let task = PrioritizedTask {
+    fn task(&self, request: CallbackRequest) -> PrioritizedTask {
+        PrioritizedTask {
+            priority: TaskPriority::of(&request),
             request,
-            priority,
-            sequence,
-            cancellation_token: cancellation_token.clone(),
+            sequence: self.sequence_counter.fetch_add(1, AtomicOrdering::SeqCst),
+            cancellation_token: CancellationToken::new(),
             submitted_at: std::time::Instant::now(),
-        };
+            deferrals: 0,
+        }
+    }
 
+    /// Queues `task` if there is room, making room by evicting the least
+    /// urgent, most recent task if it is less urgent than `task`
+    async fn push(&self, task: PrioritizedTask) -> Admission {
         let mut heap = self.heap.write().await;
-        heap.push(Reverse(task));
+        if heap.len() >= self.capacity {
+            heap.retain(|Reverse(queued)| !queued.cancellation_token.is_cancelled());
+        }
+        
+        let mut admission = Admission::Queued;
+        if heap.len() >= self.capacity {
+            let mut tasks = std::mem::take(&mut *heap).into_vec();
+            let least_urgent = tasks.iter()
+                .enumerate()
+                .max_by(|(_, Reverse(a)), (_, Reverse(b))| a.cmp(b))
+                .map(|(index, _)| index);
+            match least_urgent {
+                Some(index) if tasks[index].0.priority > task.priority => {
+                    admission = Admission::Evicted(tasks.swap_remove(index).0);
+                }
+                _ => {
+                    *heap = BinaryHeap::from(tasks);
+                    return Admission::Refused(task);
+                }
+            }
+            *heap = BinaryHeap::from(tasks);
+        }
         
-        trace!("Added task with priority {:?}, sequence {}", priority, sequence);
-        cancellation_token
+        trace!("Added task with priority {:?}, sequence {}", task.priority, task.sequence);
+        heap.push(Reverse(task));
+        drop(heap);
+        self.available.notify_one();
+        admission
     }
 
     async fn pop(&self) -> Option<PrioritizedTask> {
@@ -165,6 +264,17 @@ impl PriorityQueue {
         None
     }
 
+    /// Waits for the most urgent queued task
+    async fn next(&self) -> PrioritizedTask {
+        loop {
+            let available = self.available.notified();
+            if let Some(task) = self.pop().await {
+                return task;
+            }
+            available.await;
+        }
+    }
+    
     async fn cancel_task(&self, sequence: u64) -> bool {
         let active = self.active_tasks.read().await;
         for (seq, token) in active.iter() {
@@ -185,16 +295,18 @@ impl PriorityQueue {
         self.heap.read().await.len()
     }
 
+    /// Counts the queued tasks by priority
+    async fn depths(&self) -> [usize; 4] {
+        let mut depths = [0; 4];
+        for Reverse(task) in self.heap.read().await.iter() {
+            depths[task.priority as usize] += 1;
+        }
+        depths
+    }
+    
     async fn clear_cancelled(&self) {
         let mut heap = self.heap.write().await;
-        let valid_tasks: Vec<_> = heap
-            .drain()
-            .filter(|Reverse(task)| !task.cancellation_token.is_cancelled())
-            .collect();
-        
-        for task in valid_tasks {
-            heap.push(task);
-        }
+        heap.retain(|Reverse(task)| !task.cancellation_token.is_cancelled());
     }
 }
 
@@ -209,16 +321,20 @@ pub struct AsyncBridge {
     performance_monitor: Arc<PerformanceMonitor>,
 }
 
+/// Counters of an [`AsyncBridge`]
 #[derive(Debug, Default, Clone)]
-struct BridgeMetrics {
-    total_requests: u64,
-    completed_requests: u64,
-    failed_requests: u64,
-    dropped_requests: u64,
-    cancelled_requests: u64,
-    current_queue_size: usize,
-    peak_queue_size: usize,
-    priority_stats: [u64; 4],  // Stats per priority level
+pub struct BridgeMetrics {
+    pub total_requests: u64,
+    pub completed_requests: u64,
+    pub failed_requests: u64,
+    pub dropped_requests: u64,
+    pub cancelled_requests: u64,
+    
+    /// Requests refused or evicted by a full queue and offered again later
+    pub deferred_requests: u64,
+    pub current_queue_size: usize,
+    pub peak_queue_size: usize,
+    pub priority_stats: [u64; 4],  // Stats per priority level
 }
 
 impl AsyncBridge {
@@ -231,13 +347,16 @@ impl AsyncBridge {
         )
     }
 
+    /// Creates a bridge with `worker_threads` workers serving at most
+    /// `max_concurrent_ops` requests at once from a queue of `queue_size`
+    /// requests; requests that find it full are deferred, see [`AsyncBridge::try_send_callback`]
     pub fn with_config(
         runtime_handle: Handle,
         worker_threads: usize,
-        _queue_size: usize,
+        queue_size: usize,
         max_concurrent_ops: usize,
     ) -> Result<Self> {
-        let priority_queue = Arc::new(PriorityQueue::new());
+        let priority_queue = Arc::new(PriorityQueue::new(queue_size));
         let semaphore = Arc::new(Semaphore::new(max_concurrent_ops));
         let metrics = Arc::new(Mutex::new(BridgeMetrics::default()));
         let shutdown_token = CancellationToken::new();
@@ -258,75 +377,74 @@ impl AsyncBridge {
             let running = is_running.clone();
             let perf_monitor = performance_monitor.clone();
             
-            let worker_handle = thread::Builder::new()
-                .name(format!("shadowfs-async-bridge-worker-{}", i))
-                .spawn(move || {
-                    handle.block_on(async {
-                        while running.load(AtomicOrdering::Relaxed) {
-                            tokio::select! {
-                                _ = shutdown.cancelled() => {
-                                    debug!("Worker thread {} shutting down", i);
-                                    break;
+            let worker_handle = shadowfs_core::task::spawn_thread(&format!("async-bridge-worker-{}", i), move || {
+                handle.block_on(async {
+                    while running.load(AtomicOrdering::Relaxed) {
+                        tokio::select! {
+                            _ = shutdown.cancelled() => {
+                                debug!("Worker thread {} shutting down", i);
+                                break;
+                            }
+                            task = queue.next() => {
+                                perf_monitor.record_thread_active();
+                                perf_monitor.record_dequeue(task.priority, task.sequence);
+                                
+                                if task.cancellation_token.is_cancelled() {
+                                    let mut m = metrics.lock().unwrap();
+                                    m.cancelled_requests += 1;
+                                    queue.remove_active(task.sequence).await;
+                                    perf_monitor.record_thread_idle();
+                                    continue;
                                 }
-                                _ = tokio::time::sleep(tokio::time::Duration::from_millis(10)) => {
-                                    if let Some(task) = queue.pop().await {
-                                        perf_monitor.record_thread_active();
-                                        perf_monitor.record_dequeue(task.priority, task.sequence);
-                                        
-                                        if task.cancellation_token.is_cancelled() {
-                                            let mut m = metrics.lock().unwrap();
-                                            m.cancelled_requests += 1;
-                                            queue.remove_active(task.sequence).await;
-                                            perf_monitor.record_thread_idle();
-                                            continue;
-                                        }
 
-                                        let permit = match sem.try_acquire() {
+                                let permit = match sem.try_acquire() {
+                                    Ok(permit) => permit,
+                                    Err(_) => {
+                                        warn!("Backpressure limit reached, waiting for permit");
+                                        perf_monitor.record_backpressure();
+                                        match sem.acquire().await {
                                             Ok(permit) => permit,
-                                            Err(_) => {
-                                                warn!("Backpressure limit reached, waiting for permit");
-                                                perf_monitor.record_backpressure();
-                                                match sem.acquire().await {
-                                                    Ok(permit) => permit,
-                                                    Err(e) => {
-                                                        error!("Failed to acquire semaphore permit: {}", e);
-                                                        Self::handle_error_response(&task.request);
-                                                        queue.remove_active(task.sequence).await;
-                                                        perf_monitor.record_error();
-                                                        perf_monitor.record_thread_idle();
-                                                        continue;
-                                                    }
-                                                }
+                                            Err(e) => {
+                                                error!("Failed to acquire semaphore permit: {}", e);
+                                                queue.remove_active(task.sequence).await;
+                                                Self::respond_with_error(
+                                                    task.request,
+                                                    WindowsError::AsyncProcessing("Backpressure limit exceeded".into()),
+                                                );
+                                                perf_monitor.record_error();
+                                                perf_monitor.record_thread_idle();
+                                                continue;
                                             }
-                                        };
-
-                                        let elapsed = task.submitted_at.elapsed();
-                                        if elapsed > std::time::Duration::from_secs(5) {
-                                            warn!("Task waited {} ms before processing (priority: {:?})", 
-                                                  elapsed.as_millis(), task.priority);
-                                            perf_monitor.record_timeout();
-                                        }
-
-                                        {
-                                            let mut m = metrics.lock().unwrap();
-                                            m.priority_stats[task.priority as usize] += 1;
                                         }
-
-                                        let task_start = std::time::Instant::now();
-                                        Self::process_request(task.request, &metrics, &perf_monitor).await;
-                                        let task_duration = task_start.elapsed();
-                                        
-                                        perf_monitor.record_task_complete(task_duration, true);
-                                        queue.remove_active(task.sequence).await;
-                                        drop(permit);
-                                        perf_monitor.record_thread_idle();
                                     }
+                                };
+
+                                let elapsed = task.submitted_at.elapsed();
+                                if elapsed > std::time::Duration::from_secs(5) {
+                                    warn!("Task waited {} ms before processing (priority: {:?})", 
+                                          elapsed.as_millis(), task.priority);
+                                    perf_monitor.record_timeout();
+                                }
+                                
+                                {
+                                    let mut m = metrics.lock().unwrap();
+                                    m.priority_stats[task.priority as usize] += 1;
                                 }
+                                
+                                let task_start = std::time::Instant::now();
+                                Self::process_request(task.request, &metrics, &perf_monitor).await;
+                                let task_duration = task_start.elapsed();
+                                
+                                perf_monitor.record_task_complete(task_duration, true);
+                                queue.remove_active(task.sequence).await;
+                                drop(permit);
+                                perf_monitor.record_thread_idle();
                             }
                         }
-                    });
-                })
-                .map_err(|e| WindowsError::ThreadCreation(e.to_string()))?;
+                    }
+                });
+            })
+            .map_err(|e| WindowsError::ThreadCreation(e.to_string()))?;
 
             worker_handles.push(worker_handle);
         }
@@ -335,7 +453,7 @@ impl AsyncBridge {
         let cleanup_shutdown = shutdown_token.clone();
         let cleanup_handle = runtime_handle.clone();
         
-        thread::spawn(move || {
+        shadowfs_core::task::spawn_thread("async-bridge-cleanup", move || {
             cleanup_handle.block_on(async {
                 let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
                 loop {
@@ -347,7 +465,8 @@ impl AsyncBridge {
                     }
                 }
             });
-        });
+        })
+        .map_err(|e| WindowsError::ThreadCreation(e.to_string()))?;
 
         Ok(Self {
             priority_queue,
@@ -449,8 +568,9 @@ impl AsyncBridge {
         m.current_queue_size = m.current_queue_size.saturating_sub(1);
     }
 
-    fn handle_error_response(request: &CallbackRequest) {
-        let error = Err(WindowsError::AsyncProcessing("Backpressure limit exceeded".into()).into());
+    /// Answers `request` with `error` without processing it
+    fn respond_with_error(request: CallbackRequest, error: WindowsError) {
+        let error = Err(error.into());
         
         match request {
             CallbackRequest::GetPlaceholderInfo { response, .. } |
@@ -465,57 +585,119 @@ impl AsyncBridge {
         }
     }
 
+    /// Queues `request`, deferring it if the queue is full; see [`AsyncBridge::try_send_callback`]
     pub fn send_callback(&self, request: CallbackRequest) -> Result<CancellationToken> {
+        self.try_send_callback(request).map(|dispatch| dispatch.token().clone())
+    }
+    
+    /// Queues `request` from a callback thread, telling whether it was queued or deferred
+    ///
+    /// A full queue takes a request in place of a less urgent one, which is
+    /// deferred instead, and defers the request otherwise. A deferred request
+    /// is offered again after a backoff, doubled on every refusal up to one
+    /// second, and answered with `ERROR_BUSY` once it has been refused
+    /// `MAX_DEFERRALS` times. Callbacks return [`Dispatch::pending_hresult`]
+    /// for deferred requests instead of holding their ProjFS thread.
+    pub fn try_send_callback(&self, request: CallbackRequest) -> Result<Dispatch> {
+        self.runtime_handle.block_on(self.send_callback_async(request))
+    }
+    
+    pub async fn send_callback_async(&self, request: CallbackRequest) -> Result<Dispatch> {
         if !self.is_running.load(AtomicOrdering::Relaxed) {
             return Err(WindowsError::ServiceNotRunning.into());
         }
 
-        let handle = self.runtime_handle.clone();
-        let queue = self.priority_queue.clone();
-        let metrics = self.metrics.clone();
-        let perf_monitor = self.performance_monitor.clone();
-        
-        let priority = TaskPriority::from_request(&request);
-        let sequence = queue.sequence_counter.fetch_add(1, AtomicOrdering::SeqCst);
+        let task = self.priority_queue.task(request);
+        let token = task.cancellation_token.clone();
+        self.metrics.lock().unwrap().total_requests += 1;
         
-        let token = handle.block_on(async {
-            let current_size = queue.len().await;
-            {
-                let mut m = metrics.lock().unwrap();
-                m.total_requests += 1;
-                m.current_queue_size = current_size;
-                if current_size > m.peak_queue_size {
-                    m.peak_queue_size = current_size;
-                }
+        let priority = task.priority;
+        self.performance_monitor.record_enqueue(priority, task.sequence);
+        let dispatch = match self.priority_queue.push(task).await {
+            Admission::Queued => Dispatch::Queued(token),
+            Admission::Evicted(evicted) => {
+                debug!("Queue full, deferring a {:?} request for a {:?} one", evicted.priority, priority);
+                self.defer(evicted);
+                Dispatch::Queued(token)
             }
-            
-            perf_monitor.record_enqueue(priority, sequence);
-            queue.push(request).await
-        });
-
-        Ok(token)
+            Admission::Refused(task) => {
+                debug!("Queue full, deferring a {:?} request", priority);
+                self.defer(task);
+                Dispatch::Deferred(token)
+            }
+        };
+        
+        let current_size = self.priority_queue.len().await;
+        let mut m = self.metrics.lock().unwrap();
+        m.current_queue_size = current_size;
+        m.peak_queue_size = m.peak_queue_size.max(current_size);
+        Ok(dispatch)
     }
 
-    pub async fn send_callback_async(&self, request: CallbackRequest) -> Result<CancellationToken> {
-        if !self.is_running.load(AtomicOrdering::Relaxed) {
-            return Err(WindowsError::ServiceNotRunning.into());
-        }
+    /// Offers `task` to the queue again after a backoff
+    fn defer(&self, task: PrioritizedTask) {
+        Self::defer_task(
+            self.priority_queue.clone(),
+            self.metrics.clone(),
+            self.performance_monitor.clone(),
+            self.shutdown_token.clone(),
+            task,
+        );
+    }
 
-        let priority = TaskPriority::from_request(&request);
-        let sequence = self.priority_queue.sequence_counter.fetch_add(1, AtomicOrdering::SeqCst);
+    fn defer_task(
+        queue: Arc<PriorityQueue>,
+        metrics: Arc<Mutex<BridgeMetrics>>,
+        perf_monitor: Arc<PerformanceMonitor>,
+        shutdown: CancellationToken,
+        mut task: PrioritizedTask,
+    ) {
+        perf_monitor.record_deferral(task.priority, task.sequence);
+        metrics.lock().unwrap().deferred_requests += 1;
         
-        let current_size = self.priority_queue.len().await;
-        {
-            let mut m = self.metrics.lock().unwrap();
-            m.total_requests += 1;
-            m.current_queue_size = current_size;
-            if current_size > m.peak_queue_size {
-                m.peak_queue_size = current_size;
+        shadowfs_core::task::spawn("async-bridge-deferral", async move {
+            loop {
+                if task.deferrals >= MAX_DEFERRALS {
+                    warn!("Giving up on a {:?} request after {} deferrals", task.priority, task.deferrals);
+                    perf_monitor.record_drop(task.priority, task.sequence);
+                    metrics.lock().unwrap().dropped_requests += 1;
+                    Self::respond_with_error(task.request, WindowsError::QueueFull(queue.capacity));
+                    return;
+                }
+                
+                let backoff = INITIAL_BACKOFF.saturating_mul(1 << task.deferrals).min(MAX_BACKOFF);
+                task.deferrals += 1;
+                tokio::select! {
+                    _ = shutdown.cancelled() => {
+                        Self::respond_with_error(task.request, WindowsError::ServiceNotRunning);
+                        return;
+                    }
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                if task.cancellation_token.is_cancelled() {
+                    metrics.lock().unwrap().cancelled_requests += 1;
+                    return;
+                }
+                
+                perf_monitor.record_enqueue(task.priority, task.sequence);
+                match queue.push(task).await {
+                    Admission::Queued => return,
+                    Admission::Evicted(evicted) => {
+                        Self::defer_task(queue, metrics, perf_monitor, shutdown, evicted);
+                        return;
+                    }
+                    Admission::Refused(refused) => {
+                        perf_monitor.record_deferral(refused.priority, refused.sequence);
+                        task = refused;
+                    }
+                }
             }
-        }
-        
-        self.performance_monitor.record_enqueue(priority, sequence);
-        Ok(self.priority_queue.push(request).await)
+        });
+    }
+    
+    /// Returns the number of queued requests at each [`TaskPriority`], most urgent first
+    pub async fn queue_depths(&self) -> [usize; 4] {
+        self.priority_queue.depths().await
     }
 
     pub fn get_metrics(&self) -> BridgeMetrics {
@@ -621,4 +803,26 @@ mod tests {
         assert_eq!(metrics.completed_requests, 0);
         assert_eq!(metrics.failed_requests, 0);
     }
+    
+    fn read(length: u32) -> CallbackRequest {
+        let (response, _) = oneshot::channel();
+        CallbackRequest::GetFileData { callback_data: PRJ_CALLBACK_DATA::default(), byte_offset: 0, length, response }
+    }
+    
+    #[tokio::test]
+    async fn test_full_queue_makes_room_for_urgent_requests() {
+        let queue = PriorityQueue::new(2);
+        assert!(matches!(queue.push(queue.task(read(1 << 20))).await, Admission::Queued));
+        assert!(matches!(queue.push(queue.task(read(4096))).await, Admission::Queued));
+        
+        // Metadata evicts the large read, and another large read finds no room
+        let (response, _) = oneshot::channel();
+        let placeholder = queue.task(CallbackRequest::GetPlaceholderInfo { callback_data: PRJ_CALLBACK_DATA::default(), response });
+        assert!(matches!(queue.push(placeholder).await, Admission::Evicted(task) if task.priority == TaskPriority::Low));
+        assert!(matches!(queue.push(queue.task(read(1 << 20))).await, Admission::Refused(_)));
+        assert_eq!(queue.depths().await, [1, 0, 1, 0]);
+        
+        assert_eq!(queue.next().await.priority, TaskPriority::Critical);
+        assert_eq!(queue.next().await.priority, TaskPriority::Normal);
+    }
 }
\ No newline at end of file
@@ -1,20 +1,26 @@
+use std::collections::BTreeSet;
+use std::ops::Bound;
 use std::sync::{Arc, Weak};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io::{Read, Seek, SeekFrom};
 use std::fs::File;
+use std::os::windows::fs::MetadataExt;
 use parking_lot::RwLock;
 use windows::core::{GUID, HRESULT, PCWSTR, PWSTR};
 use windows::Win32::Storage::ProjectedFileSystem::{
     PRJ_CALLBACK_DATA,
+    PRJ_CB_DATA_FLAG_ENUM_RESTART_SCAN,
     PRJ_NAMESPACE_VIRTUALIZATION_CONTEXT,
     PRJ_DIR_ENTRY_BUFFER_HANDLE,
+    PRJ_NOTIFICATION,
+    PRJ_NOTIFICATION_PARAMETERS,
     PRJ_PLACEHOLDER_INFO,
     PrjFileNameMatch,
     PrjFillDirEntryBuffer,
     PrjWritePlaceholderInfo,
     PrjWriteFileData,
 };
-use windows::Win32::Foundation::{S_OK, E_OUTOFMEMORY, E_INVALIDARG, ERROR_INSUFFICIENT_BUFFER, WIN32_ERROR};
+use windows::Win32::Foundation::{BOOLEAN, S_OK, E_OUTOFMEMORY, E_INVALIDARG, ERROR_INSUFFICIENT_BUFFER, WIN32_ERROR};
 use windows::Win32::Storage::FileSystem::{
     FILE_ATTRIBUTE_DIRECTORY,
     FILE_ATTRIBUTE_NORMAL,
@@ -22,8 +28,14 @@ use windows::Win32::Storage::FileSystem::{
     FILE_BASIC_INFO,
 };
 use super::provider::{ProjFSProvider, EnumerationSession};
-use shadowfs_core::types::ShadowPath;
+use shadowfs_core::hidden::HiddenLookups;
+use shadowfs_core::merged_view::{MergedChild, MergedDirectoryView};
+use shadowfs_core::mount_paths;
+use shadowfs_core::types::{FileType, ShadowPath};
 use shadowfs_core::override_store::DirectoryEntry;
+use shadowfs_core::telemetry::operation_span;
+use tracing::span::EnteredSpan;
+use tracing::Span;
 
 /// Thread-safe callback context for ProjFS operations
 pub struct CallbackContext {
@@ -32,6 +44,9 @@ pub struct CallbackContext {
     
     /// Shared state for cross-callback communication
     shared_state: Arc<SharedCallbackState>,
+    
+    /// Span of the mount, which each callback's span is opened under
+    span: Span,
 }
 
 /// Shared state accessible across callbacks
@@ -60,6 +75,7 @@ impl CallbackContext {
                 virtualization_root,
                 source_root,
             }),
+            span: Span::current(),
         }
     }
     
@@ -73,6 +89,11 @@ impl CallbackContext {
         &self.shared_state
     }
     
+    /// Enters the span of the callback serving `operation` on `path`
+    pub fn enter(&self, operation: &'static str, path: &str) -> EnteredSpan {
+        operation_span(&self.span, operation, path).entered()
+    }
+    
     /// Generates a new operation ID
     pub fn next_operation_id(&self) -> u64 {
         let mut id = self.shared_state.operation_id.write();
@@ -82,14 +103,21 @@ impl CallbackContext {
 }
 
 impl SharedCallbackState {
-    /// Resolves a relative path to the source file system
+    /// Converts a path relative to the virtualization root, as ProjFS
+    /// passes them to callbacks, into the path the override store uses
+    pub fn shadow_path(&self, relative_path: impl AsRef<Path>) -> ShadowPath {
+        mount_paths::relative_shadow_path(relative_path.as_ref())
+    }
+    
+    /// Resolves a relative path to the source file system; `..` cannot
+    /// lead out of the source root
     pub fn resolve_source_path(&self, relative_path: &str) -> PathBuf {
-        self.source_root.join(relative_path)
+        mount_paths::source_path(&self.source_root, &self.shadow_path(relative_path))
     }
     
     /// Resolves a relative path to the virtualization root
     pub fn resolve_virtual_path(&self, relative_path: &str) -> PathBuf {
-        self.virtualization_root.join(relative_path)
+        mount_paths::source_path(&self.virtualization_root, &self.shadow_path(relative_path))
     }
 }
 
@@ -183,6 +211,7 @@ pub extern "system" fn start_directory_enumeration_callback(
         } else {
             String::new()
         };
+        let _span = context.enter("start_directory_enumeration", &file_path);
         
         // Convert the search expression (wildcard pattern)
         let search_expression = if !callback_data.FilePathName.is_null() {
@@ -199,6 +228,8 @@ pub extern "system" fn start_directory_enumeration_callback(
             directory_path: PathBuf::from(&file_path),
             is_restart: false,
             continuation_token: None,
+            names: None,
+            complete: false,
         };
         
         // Store the enumeration session
@@ -248,19 +279,23 @@ pub extern "system" fn get_directory_enumeration_callback(
             None => return E_OUTOFMEMORY,
         };
         
-        // Get or update the enumeration session
-        let (directory_path, continuation_token) = {
+        // Get or update the enumeration session; a restarted scan starts
+        // over from freshly read names
+        let restart = callback_data.Flags.0 & PRJ_CB_DATA_FLAG_ENUM_RESTART_SCAN.0 != 0;
+        let session = {
             let provider = provider.read();
-            match provider.active_enumerations.get(&*enumeration_id) {
-                Some(session) => {
-                    let mut session = session.clone();
-                    
-                    // Update search expression if provided
-                    if !search_expression.is_null() {
+            match provider.active_enumerations.get_mut(&*enumeration_id) {
+                Some(mut session) => {
+                    if restart {
+                        session.is_restart = true;
+                        session.names = None;
+                        session.continuation_token = None;
+                        session.complete = false;
+                    }
+                    if !search_expression.is_null() && (restart || session.search_expression.is_none()) {
                         session.search_expression = pcwstr_to_string(search_expression);
                     }
-                    
-                    (session.directory_path.clone(), session.continuation_token.clone())
+                    session.clone()
                 }
                 None => {
                     log::error!("Enumeration session not found for ID: {:?}", enumeration_id);
@@ -268,108 +303,114 @@ pub extern "system" fn get_directory_enumeration_callback(
                 }
             }
         };
+        if session.complete {
+            return S_OK;
+        }
         
-        // Resolve the actual directory path
-        let source_path = context.shared_state().resolve_source_path(
-            directory_path.to_str().unwrap_or("")
-        );
-        
-        // Collect entries from override store first
-        let mut entries = Vec::new();
-        
-        // Check override store for this directory
-        {
+        let shadow_dir = context.shared_state().shadow_path(&session.directory_path);
+        let (store, hidden) = {
             let provider = provider.read();
-            let shadow_path = ShadowPath::from(directory_path.clone());
-            if let Ok(dir_entries) = provider.override_store.list_directory(&shadow_path) {
-                for entry in dir_entries {
-                    entries.push(entry.name);
+            (Arc::clone(&provider.override_store), provider.hidden_files.clone())
+        };
+        let view = MergedDirectoryView::new(&store, &context.shared_state().source_root, shadow_dir.clone());
+        
+        // The names are read once per enumeration, and each fill resumes
+        // from the entry the last one had no room for
+        let names = match session.names {
+            Some(names) => names,
+            None => match view.names() {
+                Ok(names) => Arc::new(names),
+                Err(e) => {
+                    log::error!("Failed to read directory {}: {}", shadow_dir, e);
+                    Arc::new(BTreeSet::new())
+                }
+            },
+        };
+        let resume = session.continuation_token.map(|token| String::from_utf8_lossy(&token).into_owned());
+        let start = resume.as_deref().map_or(Bound::Unbounded, Bound::Included);
+        
+        for name in names.range::<str, _>((start, Bound::Unbounded)) {
+            // Check if this entry matches the search pattern
+            if let Some(pattern) = &session.search_expression {
+                let pattern_wide = pattern.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+                let name_wide = name.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+                if !PrjFileNameMatch(
+                    PCWSTR::from_raw(name_wide.as_ptr()),
+                    PCWSTR::from_raw(pattern_wide.as_ptr())
+                ).as_bool() {
+                    continue;
                 }
             }
-        }
-        
-        // Read entries from source directory
-        if source_path.exists() && source_path.is_dir() {
-            match std::fs::read_dir(&source_path) {
-                Ok(read_dir) => {
-                    for entry in read_dir.flatten() {
-                        if let Ok(metadata) = entry.metadata() {
-                            let file_name = entry.file_name();
-                            let file_name_str = file_name.to_string_lossy();
-                            
-                            // Check if this entry matches the search pattern
-                            let matches = if let Some(ref session) = provider.read().active_enumerations.get(&*enumeration_id) {
-                                if let Some(ref pattern) = session.search_expression {
-                                    // Use ProjFS pattern matching
-                                    let pattern_wide = pattern.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
-                                    let name_wide = file_name_str.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
-                                    
-                                    PrjFileNameMatch(
-                                        PCWSTR::from_raw(name_wide.as_ptr()),
-                                        PCWSTR::from_raw(pattern_wide.as_ptr())
-                                    ).as_bool()
-                                } else {
-                                    true
-                                }
-                            } else {
-                                true
-                            };
-                            
-                            if !matches {
-                                continue;
-                            }
-                            
-                            // Create file info
-                            let mut file_info = FILE_BASIC_INFO {
-                                CreationTime: Default::default(),
-                                LastAccessTime: Default::default(),
-                                LastWriteTime: Default::default(),
-                                ChangeTime: Default::default(),
-                                FileAttributes: if metadata.is_dir() {
-                                    FILE_ATTRIBUTE_DIRECTORY.0
-                                } else {
-                                    FILE_ATTRIBUTE_NORMAL.0
-                                },
-                            };
-                            
-                            // Convert file name to wide string
-                            let file_name_wide = file_name_str.encode_utf16()
-                                .chain(std::iter::once(0))
-                                .collect::<Vec<u16>>();
-                            
-                            // Add entry to buffer
-                            let result = PrjFillDirEntryBuffer(
-                                PCWSTR::from_raw(file_name_wide.as_ptr()),
-                                Some(&file_info as *const _ as *const _),
-                                dir_entry_buffer_handle,
-                            );
-                            
-                            // Check if buffer is full
-                            if result == HRESULT::from_win32(ERROR_INSUFFICIENT_BUFFER.0) {
-                                // Save continuation token
-                                let mut provider = provider.write();
-                                if let Some(mut session) = provider.active_enumerations.get_mut(&*enumeration_id) {
-                                    session.continuation_token = Some(file_name.as_encoded_bytes().to_vec());
-                                }
-                                return S_OK;
-                            } else if result.is_err() {
-                                log::error!("Failed to fill directory entry buffer: {:?}", result);
-                                return result.into();
-                            }
-                        }
+            
+            // Tombstoned and hidden entries are left out of the projection
+            let path = shadow_dir.join(name.as_str());
+            let is_directory = match view.child(name) {
+                Ok(Some(MergedChild::Override(entry))) => {
+                    if hidden.hides(&path, Some(&entry.override_metadata)) {
+                        continue;
                     }
+                    entry.is_directory()
                 }
+                Ok(Some(MergedChild::Source(metadata))) => {
+                    if hidden.hides(&path, None) || hidden.hides_attributes(metadata.file_attributes()) {
+                        continue;
+                    }
+                    metadata.is_dir()
+                }
+                Ok(None) => continue,
                 Err(e) => {
-                    log::error!("Failed to read directory {}: {}", source_path.display(), e);
+                    log::debug!("Failed to read {}: {}", path, e);
+                    continue;
+                }
+            };
+            
+            // Create file info
+            let file_info = FILE_BASIC_INFO {
+                CreationTime: Default::default(),
+                LastAccessTime: Default::default(),
+                LastWriteTime: Default::default(),
+                ChangeTime: Default::default(),
+                FileAttributes: if is_directory {
+                    FILE_ATTRIBUTE_DIRECTORY.0
+                } else {
+                    FILE_ATTRIBUTE_NORMAL.0
+                },
+            };
+            
+            // Convert file name to wide string
+            let file_name_wide = name.encode_utf16()
+                .chain(std::iter::once(0))
+                .collect::<Vec<u16>>();
+            
+            // Add entry to buffer
+            let result = PrjFillDirEntryBuffer(
+                PCWSTR::from_raw(file_name_wide.as_ptr()),
+                Some(&file_info as *const _ as *const _),
+                dir_entry_buffer_handle,
+            );
+            
+            // Check if buffer is full
+            if result == HRESULT::from_win32(ERROR_INSUFFICIENT_BUFFER.0) {
+                // The next fill starts with the entry that did not fit
+                let provider = provider.read();
+                if let Some(mut session) = provider.active_enumerations.get_mut(&*enumeration_id) {
+                    session.names = Some(Arc::clone(&names));
+                    session.continuation_token = Some(name.as_bytes().to_vec());
                 }
+                return S_OK;
+            } else if result.is_err() {
+                log::error!("Failed to fill directory entry buffer: {:?}", result);
+                return result.into();
             }
         }
         
-        // Clear continuation token when enumeration is complete
+        // Later fills of a complete enumeration return no entries
         {
-            let mut provider = provider.write();
+            let provider = provider.read();
             if let Some(mut session) = provider.active_enumerations.get_mut(&*enumeration_id) {
+                session.names = None;
                 session.continuation_token = None;
+                session.complete = true;
             }
         }
         
@@ -468,34 +509,71 @@ pub extern "system" fn get_placeholder_info_callback(
         } else {
             String::new()
         };
+        let _span = context.enter("get_placeholder_info", &file_path);
         
         let path_buf = PathBuf::from(&file_path);
         
         // First check override store for metadata
-        let shadow_path = ShadowPath::from(path_buf.clone());
-        let override_entry = {
+        let shadow_path = context.shared_state().shadow_path(&path_buf);
+        let hidden = provider.read().hidden_files.clone();
+        // A named stream is hidden along with its file
+        if hidden.hides_lookup(&shadow_path.without_stream(), None) || is_deleted_stream(&provider, &shadow_path) {
+            return HRESULT::from(WIN32_ERROR(2)); // ERROR_FILE_NOT_FOUND
+        }
+        let source_path = context.shared_state().resolve_source_path(&file_path);
+        let (override_entry, lookup_cache) = {
             let provider = provider.read();
-            provider.override_store.get(&shadow_path)
+            (provider.override_store.get(&shadow_path), Arc::clone(&provider.lookup_cache))
         };
         
-        // If not in override store, get from source file system
-        let (metadata, is_symlink) = if let Some(entry) = override_entry {
-            // Get metadata from override entry
-            let meta = entry.metadata().clone();
-            (meta, false)
-        } else {
-            let source_path = context.shared_state().resolve_source_path(&file_path);
-            
-            match std::fs::symlink_metadata(&source_path) {
-                Ok(meta) => {
-                    let is_symlink = meta.file_type().is_symlink();
-                    (meta, is_symlink)
+        // Overrides are looked up first; the source only when there is none
+        let source = if override_entry.is_none() {
+            match lookup_cache.lookup(&shadow_path, || std::fs::symlink_metadata(&source_path)) {
+                Ok(Some(info)) if hidden.lookups == HiddenLookups::NotFound && hidden.hides_attributes(info.attributes) => {
+                    return HRESULT::from(WIN32_ERROR(2)); // ERROR_FILE_NOT_FOUND
                 }
+                Ok(Some(info)) => Some(info),
+                Ok(None) => return HRESULT::from(WIN32_ERROR(2)), // ERROR_FILE_NOT_FOUND
                 Err(e) => {
                     log::error!("Failed to get metadata for {}: {}", source_path.display(), e);
                     return HRESULT::from(WIN32_ERROR(e.raw_os_error().unwrap_or(5) as u32)); // ERROR_ACCESS_DENIED
                 }
             }
+        } else {
+            None
+        };
+        
+        // Source files matching a rule are overridden on first access
+        let override_entry = override_entry.or_else(|| {
+            let provider = provider.read();
+            provider.override_store.apply_rules(&shadow_path, &context.shared_state().source_root)
+                .unwrap_or_else(|e| {
+                    log::warn!("Serving {} from the source: {}", file_path, e);
+                    None
+                })
+        });
+        
+        // Source files matching a read transform are served transformed
+        let transformed_len = if override_entry.is_none() {
+            let provider = provider.read();
+            provider.override_store.transform_source(&shadow_path, &context.shared_state().source_root)
+                .unwrap_or_else(|e| {
+                    log::warn!("Serving {} untransformed: {}", file_path, e);
+                    None
+                })
+                .map(|content| content.len() as u64)
+        } else {
+            None
+        };
+        
+        let (is_directory, is_symlink, file_size) = match (&override_entry, source) {
+            (Some(entry), _) => {
+                let metadata = entry.metadata();
+                let is_directory = metadata.file_type == FileType::Directory;
+                (is_directory, false, if is_directory { 0 } else { metadata.size })
+            }
+            (None, Some(info)) => (info.is_directory, info.is_symlink, transformed_len.unwrap_or(info.size)),
+            (None, None) => return HRESULT::from(WIN32_ERROR(2)), // ERROR_FILE_NOT_FOUND
         };
         
         // Convert timestamps
@@ -509,7 +587,7 @@ pub extern "system" fn get_placeholder_info_callback(
         let change_time = last_write_time; // Windows doesn't have separate change time
         
         // Determine file attributes
-        let mut attributes = if metadata.is_dir() {
+        let mut attributes = if is_directory {
             FILE_ATTRIBUTE_DIRECTORY
         } else {
             FILE_ATTRIBUTE_NORMAL
@@ -519,13 +597,6 @@ pub extern "system" fn get_placeholder_info_callback(
             attributes |= FILE_ATTRIBUTE_REPARSE_POINT;
         }
         
-        // Get file size (0 for directories)
-        let file_size = if metadata.is_file() {
-            metadata.len() as i64
-        } else {
-            0
-        };
-        
         // Create placeholder info
         let mut placeholder_info = PRJ_PLACEHOLDER_INFO {
             FileBasicInfo: FILE_BASIC_INFO {
@@ -581,7 +652,7 @@ pub extern "system" fn get_placeholder_info_callback(
             "GetPlaceholderInfo[{}]: Path={}, IsDir={}, Size={}",
             operation_id,
             file_path,
-            metadata.is_dir(),
+            is_directory,
             file_size
         );
         
@@ -591,10 +662,77 @@ pub extern "system" fn get_placeholder_info_callback(
             provider.stats.increment_placeholder_creations();
         }
         
+        assign_short_name(&context, &provider, &file_path);
+        
         S_OK
     }
 }
 
+/// Returns true if `shadow_path` is a named stream of a file the overrides
+/// delete, which takes its source streams with it
+fn is_deleted_stream(provider: &Arc<RwLock<ProjFSProvider>>, shadow_path: &ShadowPath) -> bool {
+    shadow_path.is_stream() && provider.read().override_store.is_deleted(&shadow_path.without_stream())
+}
+
+/// Assigns an 8.3 short name to a freshly written placeholder if the policy asks for one
+///
+/// The name is set on a separate thread because opening the placeholder
+/// from inside the callback would re-enter ProjFS for the same path.
+fn assign_short_name(context: &CallbackContext, provider: &Arc<RwLock<ProjFSProvider>>, file_path: &str) {
+    let path_buf = PathBuf::from(file_path);
+    let (Some(name), Some(parent)) = (path_buf.file_name(), path_buf.parent()) else {
+        return;
+    };
+    let name = name.to_string_lossy().into_owned();
+    
+    let short_name = {
+        let provider = provider.read();
+        provider.short_names.short_name(parent, &name, || {
+            list_sibling_names(context, &provider, parent)
+        })
+    };
+    
+    if let Some(short_name) = short_name {
+        let virtual_path = context.shared_state().resolve_virtual_path(file_path);
+        let spawned = shadowfs_core::task::spawn_thread("short-name", move || {
+            if let Err(e) = super::short_names::apply_short_name(&virtual_path, &short_name) {
+                log::warn!(
+                    "Failed to set short name {} for {}: {}",
+                    short_name,
+                    virtual_path.display(),
+                    e
+                );
+            }
+        });
+        if let Err(e) = spawned {
+            log::warn!("Failed to start short name thread: {}", e);
+        }
+    }
+}
+
+/// Lists the names visible in a projected directory, merging overrides and source entries
+fn list_sibling_names(context: &CallbackContext, provider: &ProjFSProvider, directory: &std::path::Path) -> Vec<String> {
+    let shadow_dir = context.shared_state().shadow_path(directory);
+    let mut names: Vec<String> = provider.override_store
+        .list_directory(&shadow_dir)
+        .map(|entries| entries.into_iter().map(|entry| entry.name).collect())
+        .unwrap_or_default();
+    
+    let source_dir = context.shared_state().resolve_source_path(&directory.to_string_lossy());
+    if let Ok(read_dir) = std::fs::read_dir(&source_dir) {
+        for entry in read_dir.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let deleted = provider.override_store
+                .get(&shadow_dir.join(&name))
+                .is_some_and(|entry| entry.is_deleted());
+            if !deleted {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
 /// Get file data callback
 /// This is called when the system needs to read actual file contents
 pub extern "system" fn get_file_data_callback(
@@ -629,44 +767,61 @@ pub extern "system" fn get_file_data_callback(
         } else {
             String::new()
         };
+        let _span = context.enter("get_file_data", &file_path);
         
         let path_buf = PathBuf::from(&file_path);
         
         // Check if we have override data for this file
-        let shadow_path = ShadowPath::from(path_buf.clone());
+        let shadow_path = context.shared_state().shadow_path(&path_buf);
+        if is_deleted_stream(&provider, &shadow_path) {
+            return HRESULT::from(WIN32_ERROR(2)); // ERROR_FILE_NOT_FOUND
+        }
         let override_entry = {
             let provider = provider.read();
-            provider.override_store.get(&shadow_path)
+            provider.override_store.get(&shadow_path).or_else(|| {
+                // Source files matching a rule are overridden on first access
+                provider.override_store.apply_rules(&shadow_path, &context.shared_state().source_root)
+                    .unwrap_or_else(|e| {
+                        log::warn!("Serving {} from the source: {}", file_path, e);
+                        None
+                    })
+            })
         };
         
-        // Get file data from either override store or source
+        // Get the requested range from either the override store or a read transform
         let file_data = if let Some(entry) = override_entry {
-            // Get data from override entry
-            match entry.get_file_data() {
-                Ok(Some(data)) => Some(data),
-                Ok(None) => None,
+            // Uncompressed override content is sliced, not copied
+            match entry.read_file_range(byte_offset, length as usize) {
+                Ok(data) => data,
                 Err(e) => {
                     log::error!("Failed to get override file data {}: {}", file_path, e);
                     return HRESULT::from(WIN32_ERROR(5)); // ERROR_ACCESS_DENIED
                 }
             }
         } else {
-            None
+            // Source files matching a read transform are served transformed
+            let provider = provider.read();
+            provider.override_store.transform_source(&shadow_path, &context.shared_state().source_root)
+                .unwrap_or_else(|e| {
+                    log::warn!("Serving {} untransformed: {}", file_path, e);
+                    None
+                })
+                .map(|content| {
+                    let start = (byte_offset as usize).min(content.len());
+                    content.slice(start..start.saturating_add(length as usize).min(content.len()))
+                })
         };
         
         // If we have override data, use it; otherwise open from source
-        if let Some(data) = file_data {
+        let mut file = if let Some(data) = file_data {
             // Write the data directly from memory
-            let data_slice = &data[byte_offset as usize..];
-            let to_write = std::cmp::min(length as usize, data_slice.len());
-            
-            if to_write > 0 {
+            if !data.is_empty() {
                 let result = PrjWriteFileData(
                     callback_data.NamespaceVirtualizationContext,
                     &callback_data.DataStreamId,
-                    data_slice[..to_write].as_ptr() as *const _,
+                    data.as_ptr() as *const _,
                     byte_offset,
-                    to_write as u32,
+                    data.len() as u32,
                 );
                 
                 if result.is_err() {
@@ -678,16 +833,21 @@ pub extern "system" fn get_file_data_callback(
                     );
                     return result.into();
                 }
-                
-                // Update stats
-                {
-                    let provider = provider.read();
-                    provider.stats.add_bytes_read(to_write as u64);
-                }
             }
+            
+            // Update stats
+            let provider = provider.read();
+            provider.stats.add_bytes_read(data.len() as u64);
+            provider.stats.increment_file_reads();
+            return S_OK;
         } else {
             // Open from source file system
             let source_path = context.shared_state().resolve_source_path(&file_path);
+            let cloud_files = provider.read().cloud_files;
+            if let Err(e) = super::cloud_files::ensure_hydrated(&source_path, cloud_files) {
+                log::error!("Cannot serve {}: {}", source_path.display(), e);
+                return HRESULT::from(WIN32_ERROR(50)); // ERROR_NOT_SUPPORTED
+            }
             match File::open(&source_path) {
                 Ok(f) => f,
                 Err(e) => {
@@ -774,8 +934,67 @@ pub extern "system" fn get_file_data_callback(
         {
             let provider = provider.read();
             provider.stats.increment_file_reads();
+            
+            // Hydrated from source: watch it so a later change invalidates the placeholder
+            provider.watch_source(&path_buf);
+        }
+        
+        S_OK
+    }
+}
+/// Notification callback
+/// This is called after file system operations under the virtualization root
+/// that the provider registered for, and captures the writes they report
+pub extern "system" fn notification_callback(
+    callback_data: *const PRJ_CALLBACK_DATA,
+    is_directory: BOOLEAN,
+    notification: PRJ_NOTIFICATION,
+    destination_file_name: PCWSTR,
+    _operation_parameters: *mut PRJ_NOTIFICATION_PARAMETERS,
+) -> HRESULT {
+    unsafe {
+        // Validate input parameters
+        if callback_data.is_null() {
+            return E_INVALIDARG;
         }
         
+        let callback_data = &*callback_data;
+        
+        // Get the callback context
+        let context = match get_context(callback_data.NamespaceVirtualizationContext) {
+            Some(ctx) => ctx,
+            None => return E_INVALIDARG,
+        };
+        
+        // Get the provider
+        let provider = match context.get_provider() {
+            Some(p) => p,
+            None => return E_OUTOFMEMORY,
+        };
+        
+        let file_path = pcwstr_to_string(callback_data.FilePathName).unwrap_or_default();
+        let _span = context.enter("notification", &file_path);
+        let destination = pcwstr_to_string(destination_file_name).map(PathBuf::from);
+        let is_directory = is_directory.as_bool();
+        
+        // The operation has already happened, so a failed capture is only logged
+        let provider = provider.read();
+        let path = PathBuf::from(&file_path);
+        match provider.capture_notification(notification, &path, destination.as_deref(), is_directory) {
+            Ok(captured) => {
+                let operation_id = context.next_operation_id();
+                log::debug!(
+                    "Notification[{}]: Path={}, Type={}, Captured={}",
+                    operation_id,
+                    file_path,
+                    notification.0,
+                    captured
+                );
+            }
+            Err(e) => log::warn!("Failed to capture write to {}: {}", file_path, e),
+        }
+        provider.forward_notification(notification, &path, destination.as_deref(), is_directory);
+        
         S_OK
     }
-}
\ No newline at end of file
+}
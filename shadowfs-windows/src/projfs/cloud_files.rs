@@ -0,0 +1,222 @@
+//! Interop with cloud files providers (OneDrive and other cfapi sync roots).
+//!
+//! Cloud files providers keep their own placeholders through the cloud filter
+//! minifilter: a dehydrated file has no local data and is fetched from the
+//! network when it is read. Projecting such a tree through ProjFS nests two
+//! placeholder systems, and reads through the projection may trigger
+//! recalls at arbitrary points or fail outright. The provider therefore
+//! detects cloud sources up front and either refuses them with a clear error
+//! or hydrates each file explicitly before serving its data, depending on the
+//! [`CloudFilesPolicy`].
+
+use std::path::Path;
+use crate::error::WindowsError;
+use log::{debug, info};
+
+/// File data is not local and is fetched when read
+const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+/// The file or directory is fetched when opened
+const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x0004_0000;
+
+/// Data has been moved to offline storage (Offline Files or HSM)
+const FILE_ATTRIBUTE_OFFLINE: u32 = 0x0000_1000;
+
+/// How sources inside cloud sync roots are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CloudFilesPolicy {
+    /// Refuse to project a source inside a cloud sync root
+    #[default]
+    Refuse,
+    
+    /// Project it, hydrating each cloud placeholder before serving its data
+    HydrateOnDemand,
+}
+
+/// Local availability of a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFileState {
+    /// Data is present locally
+    Local,
+    
+    /// A cloud placeholder whose data must be downloaded first
+    Dehydrated,
+    
+    /// Data lives in offline storage and is recalled on access
+    Offline,
+}
+
+impl SourceFileState {
+    /// Classifies a file from its Win32 attributes
+    pub fn from_attributes(attributes: u32) -> Self {
+        if attributes & (FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS | FILE_ATTRIBUTE_RECALL_ON_OPEN) != 0 {
+            Self::Dehydrated
+        } else if attributes & FILE_ATTRIBUTE_OFFLINE != 0 {
+            Self::Offline
+        } else {
+            Self::Local
+        }
+    }
+    
+    /// Reads the state of the file at `path`
+    pub fn of(path: &Path) -> Result<Self, WindowsError> {
+        use std::os::windows::fs::MetadataExt;
+        
+        let metadata = std::fs::symlink_metadata(path)?;
+        Ok(Self::from_attributes(metadata.file_attributes()))
+    }
+}
+
+/// Checks whether `path` lies inside a registered cloud files sync root.
+pub fn is_in_sync_root(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::CloudFilters::{
+        CfGetSyncRootInfoByPath, CF_SYNC_ROOT_BASIC_INFO, CF_SYNC_ROOT_INFO_BASIC,
+    };
+    
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut info = CF_SYNC_ROOT_BASIC_INFO::default();
+    
+    unsafe {
+        CfGetSyncRootInfoByPath(
+            PCWSTR(wide_path.as_ptr()),
+            CF_SYNC_ROOT_INFO_BASIC,
+            &mut info as *mut _ as *mut _,
+            std::mem::size_of::<CF_SYNC_ROOT_BASIC_INFO>() as u32,
+            None,
+        )
+        .is_ok()
+    }
+}
+
+/// Checks that `source_root` can be projected under `policy`.
+///
+/// # Returns
+/// * `Ok(())` - The source is local, or cloud sources are hydrated on demand
+/// * `Err(WindowsError::Unsupported)` - The source is a cloud tree and the policy refuses it
+pub fn check_source_root(source_root: &Path, policy: CloudFilesPolicy) -> Result<(), WindowsError> {
+    let in_sync_root = is_in_sync_root(source_root);
+    let state = SourceFileState::of(source_root)?;
+    if !in_sync_root && state == SourceFileState::Local {
+        return Ok(());
+    }
+    
+    match policy {
+        CloudFilesPolicy::Refuse => Err(WindowsError::Unsupported {
+            message: format!(
+                "source directory {} is managed by a cloud files provider such as OneDrive; \
+                 projecting cloud placeholders is not supported unless hydration on demand is enabled, \
+                 or use a local copy of the directory",
+                source_root.display()
+            ),
+        }),
+        CloudFilesPolicy::HydrateOnDemand => {
+            info!(
+                "Source {} is a cloud files tree; files will be hydrated before they are projected",
+                source_root.display()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Checks that `path` is not inside a cloud sync root, where a ProjFS root cannot be nested.
+pub fn check_virtualization_root(path: &Path) -> Result<(), WindowsError> {
+    if is_in_sync_root(path) {
+        return Err(WindowsError::Unsupported {
+            message: format!(
+                "virtualization root {} is inside a cloud files sync root such as OneDrive; \
+                 choose a directory outside of it",
+                path.display()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Makes the data of the source file at `path` local before it is served.
+///
+/// Dehydrated cloud placeholders are hydrated through the cloud filter API
+/// when the policy allows it and refused otherwise. Offline files are left
+/// to the regular recall performed by reading them.
+pub fn ensure_hydrated(path: &Path, policy: CloudFilesPolicy) -> Result<(), WindowsError> {
+    match SourceFileState::of(path)? {
+        SourceFileState::Local | SourceFileState::Offline => Ok(()),
+        SourceFileState::Dehydrated if policy == CloudFilesPolicy::Refuse => Err(WindowsError::Unsupported {
+            message: format!("{} is a dehydrated cloud placeholder", path.display()),
+        }),
+        SourceFileState::Dehydrated => hydrate(path),
+    }
+}
+
+/// Downloads the full content of a cloud placeholder.
+fn hydrate(path: &Path) -> Result<(), WindowsError> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::Storage::CloudFilters::{CfHydratePlaceholder, CF_HYDRATE_FLAG_NONE};
+    use windows::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_READ_ATTRIBUTES, FILE_SHARE_DELETE,
+        FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    
+    debug!("Hydrating cloud placeholder {}", path.display());
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    
+    unsafe {
+        let handle = CreateFileW(
+            PCWSTR(wide_path.as_ptr()),
+            FILE_READ_ATTRIBUTES.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS,
+            None,
+        )?;
+        
+        // A length of -1 hydrates through the end of the file
+        let result = CfHydratePlaceholder(handle, 0, -1, CF_HYDRATE_FLAG_NONE, None);
+        let _ = CloseHandle(handle);
+        result.map_err(|e| WindowsError::ProjFSError {
+            message: format!("Failed to hydrate cloud placeholder {}: {}", path.display(), e.message()),
+            hresult: e.code().0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_state_from_attributes() {
+        const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+        
+        assert_eq!(SourceFileState::from_attributes(FILE_ATTRIBUTE_ARCHIVE), SourceFileState::Local);
+        // Hydrated cloud files keep their reparse point but have local data
+        assert_eq!(
+            SourceFileState::from_attributes(FILE_ATTRIBUTE_ARCHIVE | FILE_ATTRIBUTE_REPARSE_POINT),
+            SourceFileState::Local
+        );
+        assert_eq!(
+            SourceFileState::from_attributes(FILE_ATTRIBUTE_REPARSE_POINT | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS),
+            SourceFileState::Dehydrated
+        );
+        assert_eq!(
+            SourceFileState::from_attributes(FILE_ATTRIBUTE_RECALL_ON_OPEN),
+            SourceFileState::Dehydrated
+        );
+        assert_eq!(SourceFileState::from_attributes(FILE_ATTRIBUTE_OFFLINE), SourceFileState::Offline);
+    }
+    
+    #[test]
+    fn test_local_directory_is_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(SourceFileState::of(dir.path()).unwrap(), SourceFileState::Local);
+        if !is_in_sync_root(dir.path()) {
+            assert!(check_source_root(dir.path(), CloudFilesPolicy::Refuse).is_ok());
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Source lookups remembered between placeholder requests.
+//!
+//! ProjFS asks for placeholder info the first time anything touches a path,
+//! and tools probing for files that do not exist (`.git` walks, `PATH`
+//! searches, Explorer looking for `desktop.ini`) ask again and again. Each
+//! request would otherwise stat the source. A [`LookupCache`] keeps what the
+//! source said about a path: its metadata, or that it is missing.
+//!
+//! Overrides are looked up before the cache, so an override created at a
+//! path the cache knows as missing is projected at once. The provider still
+//! drops the entries of a path when it captures a write to it, when a source
+//! watcher or lease break reports a change, and when it invalidates the
+//! placeholder, since the source side of the path may have changed with it.
+//! Dropping a path also drops the entries below it and the named streams of
+//! its file, and the missing entries of its ancestors, which a created path
+//! must have; writing a stream creates its file. A lookup that raced an
+//! invalidation is not kept.
+//!
+//! Paths are compared case-insensitively, as NTFS compares them. Hand the
+//! provider's cache to
+//! [`PerformanceMonitor::watch_lookup_cache`](super::PerformanceMonitor::watch_lookup_cache)
+//! to have its hits and misses reported with the other callback metrics.
+
+use dashmap::DashMap;
+use std::io;
+use std::os::windows::fs::MetadataExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use shadowfs_core::types::ShadowPath;
+
+/// Source lookups kept by default.
+pub const DEFAULT_MAX_ENTRIES: usize = 16 * 1024;
+
+/// Limits of a [`LookupCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupCacheConfig {
+    /// Most lookups kept; a full cache drops its expired entries, or all of
+    /// them if none expired
+    pub max_entries: usize,
+    
+    /// Age after which the source is asked again even if nothing reported a
+    /// change; `None` keeps lookups until they are invalidated
+    pub max_age: Option<Duration>,
+}
+
+impl Default for LookupCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// What a placeholder needs to know about a source entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceInfo {
+    /// Whether the entry is a directory
+    pub is_directory: bool,
+    
+    /// Whether the entry is a symbolic link or another reparse point
+    pub is_symlink: bool,
+    
+    /// Length in bytes; 0 for directories
+    pub size: u64,
+    
+    /// `FILE_ATTRIBUTE_*` flags of the entry
+    pub attributes: u32,
+}
+
+impl SourceInfo {
+    /// Takes the fields a placeholder needs from `metadata`.
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            is_directory: metadata.is_dir(),
+            is_symlink: metadata.file_type().is_symlink(),
+            size: if metadata.is_file() { metadata.len() } else { 0 },
+            attributes: metadata.file_attributes(),
+        }
+    }
+}
+
+/// Counters of a [`LookupCache`], as [`PerformanceMonitor`](super::PerformanceMonitor) reports them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LookupCacheStats {
+    /// Lookups currently kept, missing paths included
+    pub entries: usize,
+    
+    /// Lookups served with kept metadata
+    pub hits: u64,
+    
+    /// Lookups served by knowing the path is missing
+    pub negative_hits: u64,
+    
+    /// Lookups that had to ask the source
+    pub misses: u64,
+    
+    /// Invalidations, one per path or full flush
+    pub invalidations: u64,
+}
+
+impl LookupCacheStats {
+    /// Returns the share of lookups the cache served, from 0 to 1.
+    pub fn hit_rate(&self) -> f64 {
+        let served = self.hits + self.negative_hits;
+        let total = served + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            served as f64 / total as f64
+        }
+    }
+}
+
+/// A kept lookup; `None` if the source has no such path.
+struct CachedLookup {
+    info: Option<SourceInfo>,
+    cached_at: Instant,
+}
+
+/// Source metadata and missing paths of one mount, by path.
+pub struct LookupCache {
+    config: LookupCacheConfig,
+    negative: bool,
+    entries: DashMap<ShadowPath, CachedLookup>,
+    
+    /// Bumped by every invalidation, so lookups racing one are not kept
+    generation: AtomicU64,
+    hits: AtomicU64,
+    negative_hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl LookupCache {
+    /// Creates an empty cache within `config`, remembering missing paths
+    /// if `negative` is set.
+    pub fn new(config: LookupCacheConfig, negative: bool) -> Self {
+        Self {
+            config,
+            negative,
+            entries: DashMap::new(),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            negative_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+    
+    /// Returns the limits of the cache.
+    pub fn config(&self) -> &LookupCacheConfig {
+        &self.config
+    }
+    
+    /// Returns what the source has at `path`, asking `stat` if no lookup of
+    /// it is kept.
+    ///
+    /// # Returns
+    /// `None` if the source has no such path
+    ///
+    /// # Errors
+    /// Returns the errors of `stat` other than `NotFound`, keeping nothing.
+    pub fn lookup(
+        &self,
+        path: &ShadowPath,
+        stat: impl FnOnce() -> io::Result<std::fs::Metadata>,
+    ) -> io::Result<Option<SourceInfo>> {
+        let key = path.to_case_folded();
+        if let Some(cached) = self.entries.get(&key) {
+            if !self.expired(&cached) {
+                let counter = if cached.info.is_some() { &self.hits } else { &self.negative_hits };
+                counter.fetch_add(1, Ordering::Relaxed);
+                return Ok(cached.info);
+            }
+        }
+        
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let generation = self.generation.load(Ordering::Acquire);
+        let info = match stat() {
+            Ok(metadata) => Some(SourceInfo::from_metadata(&metadata)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        if info.is_some() || self.negative {
+            self.keep(key, info, generation);
+        }
+        Ok(info)
+    }
+    
+    /// Drops the lookups of the file of `path`, of its named streams and
+    /// the paths below it, and the missing lookups of its ancestors.
+    pub fn invalidate(&self, path: &ShadowPath) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+        let key = path.to_case_folded().without_stream();
+        self.entries.retain(|cached_path, cached| {
+            let below = cached_path.as_path().starts_with(key.as_path()) || cached_path.without_stream() == key;
+            let ancestor = cached.info.is_none() && key.as_path().starts_with(cached_path.as_path());
+            !below && !ancestor
+        });
+    }
+    
+    /// Drops every lookup, for changes too broad to tell which paths they
+    /// touched.
+    pub fn invalidate_all(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+        self.entries.clear();
+    }
+    
+    /// Returns the counters of the cache.
+    pub fn stats(&self) -> LookupCacheStats {
+        LookupCacheStats {
+            entries: self.entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            negative_hits: self.negative_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// Keeps a lookup made at `generation`, unless an invalidation came since.
+    fn keep(&self, key: ShadowPath, info: Option<SourceInfo>, generation: u64) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+        if self.entries.len() >= self.config.max_entries {
+            self.entries.retain(|_, cached| !self.expired(cached));
+            if self.entries.len() >= self.config.max_entries {
+                self.entries.clear();
+            }
+        }
+        
+        self.entries.insert(key.clone(), CachedLookup { info, cached_at: Instant::now() });
+        // An invalidation may have run between the stat and the insert
+        if self.generation.load(Ordering::Acquire) != generation {
+            self.entries.remove(&key);
+        }
+    }
+    
+    fn expired(&self, cached: &CachedLookup) -> bool {
+        self.config.max_age.is_some_and(|max_age| cached.cached_at.elapsed() >= max_age)
+    }
+}
+
+impl Default for LookupCache {
+    fn default() -> Self {
+        Self::new(LookupCacheConfig::default(), true)
+    }
+}
+
+impl std::fmt::Debug for LookupCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LookupCache")
+            .field("config", &self.config)
+            .field("negative", &self.negative)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_lookups_are_kept_until_invalidated() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("a.txt"), "abc").unwrap();
+        let cache = LookupCache::default();
+        let stat = |name: &str| {
+            let path = source.path().join(name);
+            move || std::fs::symlink_metadata(path)
+        };
+        
+        let a = ShadowPath::from("/a.txt");
+        assert_eq!(cache.lookup(&a, stat("a.txt")).unwrap().unwrap().size, 3);
+        assert_eq!(cache.lookup(&ShadowPath::from("/A.TXT"), stat("a.txt")).unwrap().unwrap().size, 3);
+        
+        // Missing paths are remembered until something below them appears
+        let missing = ShadowPath::from("/build");
+        assert!(cache.lookup(&missing, stat("build")).unwrap().is_none());
+        std::fs::create_dir(source.path().join("build")).unwrap();
+        assert!(cache.lookup(&missing, stat("build")).unwrap().is_none());
+        cache.invalidate(&ShadowPath::from("/build/out.o"));
+        assert!(cache.lookup(&missing, stat("build")).unwrap().unwrap().is_directory);
+        
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.negative_hits, stats.misses), (1, 1, 3));
+        assert_eq!(stats.entries, 2);
+        
+        // Streams go with their file, and writing one creates the file
+        let stream = ShadowPath::from("/A.txt:meta");
+        assert!(cache.lookup(&stream, stat("a.txt:meta")).unwrap().is_none());
+        assert!(cache.lookup(&ShadowPath::from("/new.txt"), stat("new.txt")).unwrap().is_none());
+        cache.invalidate(&ShadowPath::from("/a.txt"));
+        cache.invalidate(&ShadowPath::from("/new.txt:meta"));
+        assert_eq!(cache.stats().entries, 1);
+        
+        cache.invalidate_all();
+        assert_eq!(cache.stats().entries, 0);
+    }
+    
+    #[test]
+    fn test_negative_lookups_can_be_turned_off() {
+        let cache = LookupCache::new(LookupCacheConfig { max_entries: 1, max_age: None }, false);
+        let missing = || Err(io::Error::from(io::ErrorKind::NotFound));
+        assert!(cache.lookup(&ShadowPath::from("/gone"), missing).unwrap().is_none());
+        assert!(cache.lookup(&ShadowPath::from("/gone"), missing).unwrap().is_none());
+        assert_eq!(cache.stats().misses, 2);
+        
+        // Other errors are not kept either
+        let denied = || Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(cache.lookup(&ShadowPath::from("/locked"), denied).is_err());
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
@@ -4,6 +4,13 @@ pub mod virtualization;
 pub mod async_bridge;
 pub mod futures;
 pub mod performance;
+pub mod short_names;
+pub mod oplocks;
+pub mod cloud_files;
+pub mod source_watcher;
+pub mod write_capture;
+pub mod lookup_cache;
+pub mod streams;
 
 pub use provider::{ProjFSProvider, ProjFSConfig, ProjFSHandle};
 pub use callbacks::{
@@ -13,9 +20,17 @@ pub use callbacks::{
     end_directory_enumeration_callback,
     get_placeholder_info_callback,
     get_file_data_callback,
+    notification_callback,
 };
 pub use virtualization::VirtualizationRoot;
-pub use async_bridge::{AsyncBridge, CallbackRequest, TaskPriority};
+pub use short_names::{ShortNamePolicy, ShortNameTable};
+pub use oplocks::{OplockConfig, PendingUpdates, SourceLeases};
+pub use cloud_files::{CloudFilesPolicy, SourceFileState};
+pub use source_watcher::{SourceWatchBackend, SourceWatchConfig, WindowsSourceWatcher};
+pub use write_capture::{WriteCapture, CAPTURED_NOTIFICATIONS};
+pub use lookup_cache::{LookupCache, LookupCacheConfig, LookupCacheStats, SourceInfo};
+pub use streams::source_streams;
+pub use async_bridge::{AsyncBridge, BridgeMetrics, CallbackRequest, Dispatch, TaskPriority};
 pub use futures::{
     ReadFileFuture,
     EnumerateDirectoryFuture, 
@@ -0,0 +1,490 @@
+//! Opportunistic lock (oplock) coordination for projected files.
+//!
+//! NTFS grants oplocks and leases on placeholders and hydrated files itself,
+//! so applications such as Office or antivirus scanners get them as on any
+//! other volume. The provider has two jobs to keep those applications from
+//! failing or stalling:
+//!
+//! - It must not hold handles that conflict with other openers. Source files
+//!   that were hydrated into the projection are watched through a read-handle
+//!   lease instead of an open handle, and the lease is released as soon as it
+//!   breaks. A break means the source changed, so the stale placeholder is
+//!   invalidated.
+//! - Invalidating a placeholder breaks the oplocks applications hold on it.
+//!   While such a break is in progress ProjFS reports the file as busy; the
+//!   update is then deferred and retried with backoff instead of failing.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::error::WindowsError;
+
+/// The file is in use by another process
+const ERROR_SHARING_VIOLATION: u32 = 32;
+
+/// A byte range of the file is locked by another process
+const ERROR_LOCK_VIOLATION: u32 = 33;
+
+/// ProjFS is busy with the file, typically while an oplock break completes
+const ERROR_FILE_SYSTEM_VIRTUALIZATION_BUSY: u32 = 371;
+
+/// An oplock break is in progress on the file
+const ERROR_OPLOCK_BREAK_IN_PROGRESS: u32 = 742;
+
+/// `FSCTL_REQUEST_OPLOCK` control code
+const FSCTL_REQUEST_OPLOCK: u32 = 0x0009_0240;
+
+/// Oplock levels of a read-handle lease
+const OPLOCK_LEVEL_CACHE_READ: u32 = 0x1;
+const OPLOCK_LEVEL_CACHE_HANDLE: u32 = 0x2;
+
+/// Flag requesting a new oplock
+const REQUEST_OPLOCK_INPUT_FLAG_REQUEST: u32 = 0x1;
+
+/// Completion key used to stop the lease thread
+const SHUTDOWN_KEY: usize = usize::MAX;
+
+/// Settings for oplock handling in the ProjFS provider.
+#[derive(Debug, Clone)]
+pub struct OplockConfig {
+    /// Watch hydrated source files with leases and invalidate placeholders when they change
+    pub source_leases: bool,
+    
+    /// Delay before the first retry of a busy placeholder update
+    pub retry_interval: Duration,
+    
+    /// Upper bound for the retry delay
+    pub max_retry_interval: Duration,
+    
+    /// Retries before a busy placeholder update is given up
+    pub max_retries: u32,
+}
+
+impl Default for OplockConfig {
+    fn default() -> Self {
+        Self {
+            source_leases: true,
+            retry_interval: Duration::from_millis(100),
+            max_retry_interval: Duration::from_secs(5),
+            max_retries: 20,
+        }
+    }
+}
+
+/// Checks whether a Win32 error means the file is temporarily held by an oplock or another opener.
+pub fn is_busy_error(code: u32) -> bool {
+    matches!(
+        code,
+        ERROR_SHARING_VIOLATION
+            | ERROR_LOCK_VIOLATION
+            | ERROR_FILE_SYSTEM_VIRTUALIZATION_BUSY
+            | ERROR_OPLOCK_BREAK_IN_PROGRESS
+    )
+}
+
+/// A placeholder update waiting for an oplock break to finish.
+#[derive(Debug, Clone)]
+struct PendingUpdate {
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+/// Placeholder updates deferred because the file was busy.
+#[derive(Debug)]
+pub struct PendingUpdates {
+    config: OplockConfig,
+    pending: Mutex<HashMap<PathBuf, PendingUpdate>>,
+}
+
+impl PendingUpdates {
+    /// Creates an empty queue using the retry settings of `config`
+    pub fn new(config: OplockConfig) -> Self {
+        Self {
+            config,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Records that updating `path` failed because the file was busy
+    ///
+    /// # Returns
+    /// `false` if the update has been retried too often and was dropped
+    pub fn defer(&self, path: &Path, now: Instant) -> bool {
+        let mut pending = self.pending.lock();
+        let attempts = pending.get(path).map_or(0, |update| update.attempts) + 1;
+        if attempts > self.config.max_retries {
+            pending.remove(path);
+            return false;
+        }
+        
+        let delay = self.config.retry_interval
+            .saturating_mul(1 << (attempts - 1).min(16))
+            .min(self.config.max_retry_interval);
+        pending.insert(path.to_path_buf(), PendingUpdate {
+            attempts,
+            next_attempt: now + delay,
+        });
+        true
+    }
+    
+    /// Takes the paths whose retry time has come
+    ///
+    /// Taken paths stay queued with their attempt count, so a failed retry
+    /// passed back to [`PendingUpdates::defer`] keeps backing off.
+    pub fn take_due(&self, now: Instant) -> Vec<PathBuf> {
+        let mut pending = self.pending.lock();
+        let mut due = Vec::new();
+        for (path, update) in pending.iter_mut() {
+            if update.next_attempt <= now {
+                update.next_attempt = now + self.config.max_retry_interval;
+                due.push(path.clone());
+            }
+        }
+        due
+    }
+    
+    /// Forgets `path` after a successful update
+    pub fn complete(&self, path: &Path) {
+        self.pending.lock().remove(path);
+    }
+    
+    /// Number of deferred updates
+    pub fn len(&self) -> usize {
+        self.pending.lock().len()
+    }
+    
+    /// Checks whether no updates are deferred
+    pub fn is_empty(&self) -> bool {
+        self.pending.lock().is_empty()
+    }
+}
+
+/// Event delivered by [`SourceLeases`] to its handler.
+#[derive(Debug)]
+pub enum LeaseEvent<'a> {
+    /// The lease on a source file broke because another process opened it for writing, renamed or deleted it
+    Broken(&'a Path),
+    
+    /// No lease broke within the retry interval; a chance to retry deferred updates
+    Idle,
+}
+
+/// Input buffer of `FSCTL_REQUEST_OPLOCK`
+#[repr(C)]
+#[derive(Default)]
+struct RequestOplockInputBuffer {
+    structure_version: u16,
+    structure_length: u16,
+    requested_oplock_level: u32,
+    flags: u32,
+}
+
+/// Output buffer of `FSCTL_REQUEST_OPLOCK`
+#[repr(C)]
+#[derive(Default)]
+struct RequestOplockOutputBuffer {
+    structure_version: u16,
+    structure_length: u16,
+    original_oplock_level: u32,
+    new_oplock_level: u32,
+    flags: u32,
+    access_mode: u32,
+    share_mode: u16,
+}
+
+/// A granted lease; the buffers must stay in place until the request completes.
+struct Lease {
+    relative_path: PathBuf,
+    handle: windows::Win32::Foundation::HANDLE,
+    _overlapped: Box<windows::Win32::System::IO::OVERLAPPED>,
+    _input: Box<RequestOplockInputBuffer>,
+    _output: Box<RequestOplockOutputBuffer>,
+}
+
+// The handle and buffers are only touched by the kernel and by whoever owns the lease
+unsafe impl Send for Lease {}
+
+/// Read-handle leases on source files, serviced by a completion-port thread.
+pub struct SourceLeases {
+    port: windows::Win32::Foundation::HANDLE,
+    leases: Arc<Mutex<HashMap<usize, Lease>>>,
+    watched: Arc<Mutex<HashMap<PathBuf, usize>>>,
+    next_key: AtomicUsize,
+    worker: Option<JoinHandle<()>>,
+}
+
+// The completion port handle may be used from any thread
+unsafe impl Send for SourceLeases {}
+unsafe impl Sync for SourceLeases {}
+
+impl SourceLeases {
+    /// Starts the lease thread
+    ///
+    /// # Arguments
+    /// * `idle_interval` - How often `handler` receives [`LeaseEvent::Idle`] when nothing breaks
+    /// * `handler` - Called on the lease thread for every event
+    pub fn start<F>(idle_interval: Duration, handler: F) -> Result<Self, WindowsError>
+    where
+        F: Fn(LeaseEvent<'_>) + Send + 'static,
+    {
+        use windows::Win32::Foundation::INVALID_HANDLE_VALUE;
+        use windows::Win32::System::IO::CreateIoCompletionPort;
+        
+        let port = unsafe { CreateIoCompletionPort(INVALID_HANDLE_VALUE, None, 0, 1)? };
+        let leases: Arc<Mutex<HashMap<usize, Lease>>> = Arc::new(Mutex::new(HashMap::new()));
+        let watched: Arc<Mutex<HashMap<PathBuf, usize>>> = Arc::new(Mutex::new(HashMap::new()));
+        
+        let worker_port = port.0;
+        let worker_leases = Arc::clone(&leases);
+        let worker_watched = Arc::clone(&watched);
+        let timeout = idle_interval.as_millis().min(u32::MAX as u128) as u32;
+        let worker = std::thread::Builder::new()
+            .name("shadowfs-oplocks".to_string())
+            .spawn(move || {
+                let port = windows::Win32::Foundation::HANDLE(worker_port);
+                Self::run(port, timeout, &worker_leases, &worker_watched, handler);
+            })
+            .map_err(|e| WindowsError::ThreadCreation(e.to_string()))?;
+        
+        Ok(Self {
+            port,
+            leases,
+            watched,
+            next_key: AtomicUsize::new(0),
+            worker: Some(worker),
+        })
+    }
+    
+    /// Requests a read-handle lease on `source_path`
+    ///
+    /// Paths that are already watched are left alone. If the lease cannot be
+    /// granted, for example because another process has the file open for
+    /// writing, the file is simply not watched.
+    ///
+    /// # Arguments
+    /// * `relative_path` - Path relative to the virtualization root, passed back on a break
+    /// * `source_path` - Absolute path of the source file
+    pub fn watch(&self, relative_path: &Path, source_path: &Path) -> Result<bool, WindowsError> {
+        use std::os::windows::ffi::OsStrExt;
+        use windows::core::PCWSTR;
+        use windows::Win32::Foundation::{CloseHandle, ERROR_IO_PENDING, GetLastError};
+        use windows::Win32::Storage::FileSystem::{
+            CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OVERLAPPED, FILE_READ_ATTRIBUTES,
+            FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+        };
+        use windows::Win32::System::IO::{CreateIoCompletionPort, DeviceIoControl, OVERLAPPED};
+        
+        if self.watched.lock().contains_key(relative_path) {
+            return Ok(false);
+        }
+        
+        let wide_path: Vec<u16> = source_path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        
+        unsafe {
+            // FILE_READ_ATTRIBUTES with full sharing never conflicts with other openers
+            let handle = CreateFileW(
+                PCWSTR(wide_path.as_ptr()),
+                FILE_READ_ATTRIBUTES.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                OPEN_EXISTING,
+                FILE_FLAG_OVERLAPPED | FILE_FLAG_BACKUP_SEMANTICS,
+                None,
+            )?;
+            
+            if let Err(e) = CreateIoCompletionPort(handle, self.port, key, 0) {
+                let _ = CloseHandle(handle);
+                return Err(e.into());
+            }
+            
+            let mut lease = Lease {
+                relative_path: relative_path.to_path_buf(),
+                handle,
+                _overlapped: Box::new(OVERLAPPED::default()),
+                _input: Box::new(RequestOplockInputBuffer {
+                    structure_version: 1,
+                    structure_length: std::mem::size_of::<RequestOplockInputBuffer>() as u16,
+                    requested_oplock_level: OPLOCK_LEVEL_CACHE_READ | OPLOCK_LEVEL_CACHE_HANDLE,
+                    flags: REQUEST_OPLOCK_INPUT_FLAG_REQUEST,
+                }),
+                _output: Box::new(RequestOplockOutputBuffer::default()),
+            };
+            
+            // Register before issuing the request so a break racing with it finds the lease
+            let mut leases = self.leases.lock();
+            let granted = DeviceIoControl(
+                handle,
+                FSCTL_REQUEST_OPLOCK,
+                Some(&*lease._input as *const _ as *const _),
+                std::mem::size_of::<RequestOplockInputBuffer>() as u32,
+                Some(&mut *lease._output as *mut _ as *mut _),
+                std::mem::size_of::<RequestOplockOutputBuffer>() as u32,
+                None,
+                Some(&mut *lease._overlapped as *mut _),
+            );
+            
+            // A granted lease stays pending until it breaks
+            if granted.as_bool() || GetLastError() != ERROR_IO_PENDING {
+                drop(leases);
+                let _ = CloseHandle(lease.handle);
+                return Ok(false);
+            }
+            
+            leases.insert(key, lease);
+            self.watched.lock().insert(relative_path.to_path_buf(), key);
+        }
+        
+        Ok(true)
+    }
+    
+    /// Number of source files currently watched
+    pub fn len(&self) -> usize {
+        self.watched.lock().len()
+    }
+    
+    /// Checks whether no source files are watched
+    pub fn is_empty(&self) -> bool {
+        self.watched.lock().is_empty()
+    }
+    
+    fn run<F>(
+        port: windows::Win32::Foundation::HANDLE,
+        timeout_ms: u32,
+        leases: &Mutex<HashMap<usize, Lease>>,
+        watched: &Mutex<HashMap<PathBuf, usize>>,
+        handler: F,
+    )
+    where
+        F: Fn(LeaseEvent<'_>),
+    {
+        use windows::Win32::Foundation::CloseHandle;
+        use windows::Win32::System::IO::{GetQueuedCompletionStatus, OVERLAPPED};
+        
+        loop {
+            let mut bytes = 0u32;
+            let mut key = 0usize;
+            let mut overlapped: *mut OVERLAPPED = std::ptr::null_mut();
+            let result = unsafe {
+                GetQueuedCompletionStatus(port, &mut bytes, &mut key, &mut overlapped, timeout_ms)
+            };
+            
+            if overlapped.is_null() {
+                if key == SHUTDOWN_KEY {
+                    break;
+                }
+                // Timed out without a completion
+                handler(LeaseEvent::Idle);
+                continue;
+            }
+            
+            let Some(lease) = leases.lock().remove(&key) else {
+                continue;
+            };
+            watched.lock().remove(&lease.relative_path);
+            
+            // Closing the handle acknowledges the break, so the other opener proceeds at once
+            unsafe {
+                let _ = CloseHandle(lease.handle);
+            }
+            if result.as_bool() {
+                log::debug!("Source lease broke for {}", lease.relative_path.display());
+            }
+            handler(LeaseEvent::Broken(&lease.relative_path));
+        }
+    }
+}
+
+impl Drop for SourceLeases {
+    fn drop(&mut self) {
+        use windows::Win32::Foundation::CloseHandle;
+        use windows::Win32::System::IO::PostQueuedCompletionStatus;
+        
+        unsafe {
+            let _ = PostQueuedCompletionStatus(self.port, 0, SHUTDOWN_KEY, None);
+        }
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        
+        // Closing a handle completes its pending lease request during cleanup,
+        // before the buffers are dropped with the lease
+        for (_, lease) in self.leases.lock().drain() {
+            unsafe {
+                let _ = CloseHandle(lease.handle);
+            }
+        }
+        unsafe {
+            let _ = CloseHandle(self.port);
+        }
+    }
+}
+
+impl std::fmt::Debug for SourceLeases {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SourceLeases")
+            .field("watched", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn config() -> OplockConfig {
+        OplockConfig {
+            source_leases: false,
+            retry_interval: Duration::from_millis(100),
+            max_retry_interval: Duration::from_millis(350),
+            max_retries: 3,
+        }
+    }
+    
+    #[test]
+    fn test_busy_errors() {
+        assert!(is_busy_error(ERROR_SHARING_VIOLATION));
+        assert!(is_busy_error(ERROR_OPLOCK_BREAK_IN_PROGRESS));
+        assert!(is_busy_error(ERROR_FILE_SYSTEM_VIRTUALIZATION_BUSY));
+        assert!(!is_busy_error(2)); // ERROR_FILE_NOT_FOUND
+    }
+    
+    #[test]
+    fn test_deferred_updates_back_off() {
+        let pending = PendingUpdates::new(config());
+        let path = Path::new("docs\\report.docx");
+        let start = Instant::now();
+        
+        assert!(pending.defer(path, start));
+        assert!(pending.take_due(start).is_empty());
+        assert_eq!(pending.take_due(start + Duration::from_millis(100)), vec![path.to_path_buf()]);
+        
+        // Second failure waits twice as long, the third is capped
+        let retry = start + Duration::from_millis(100);
+        assert!(pending.defer(path, retry));
+        assert!(pending.take_due(retry + Duration::from_millis(150)).is_empty());
+        assert_eq!(pending.take_due(retry + Duration::from_millis(200)).len(), 1);
+        assert!(pending.defer(path, retry));
+        assert!(pending.take_due(retry + Duration::from_millis(349)).is_empty());
+        
+        // The fourth failure exceeds max_retries and drops the update
+        assert!(!pending.defer(path, retry));
+        assert!(pending.is_empty());
+    }
+    
+    #[test]
+    fn test_completed_update_is_forgotten() {
+        let pending = PendingUpdates::new(config());
+        let path = Path::new("book.xlsx");
+        pending.defer(path, Instant::now());
+        assert_eq!(pending.len(), 1);
+        
+        pending.complete(path);
+        assert!(pending.is_empty());
+        assert!(pending.take_due(Instant::now() + Duration::from_secs(1)).is_empty());
+    }
+}
@@ -7,6 +7,7 @@ use tracing::{debug, info, warn};
 use dashmap::DashMap;
 
 use super::TaskPriority;
+use super::lookup_cache::{LookupCache, LookupCacheStats};
 
 /// Performance metrics for callback operations
 #[derive(Debug, Clone)]
@@ -31,6 +32,7 @@ pub struct QueueMetrics {
     pub total_enqueued: u64,
     pub total_dequeued: u64,
     pub total_dropped: u64,
+    pub total_deferred: u64,
     pub by_priority: [PriorityMetrics; 4],
 }
 
@@ -131,6 +133,7 @@ pub struct PerformanceMonitor {
     enqueued_total: Arc<AtomicU64>,
     dequeued_total: Arc<AtomicU64>,
     dropped_total: Arc<AtomicU64>,
+    deferred_total: Arc<AtomicU64>,
     
     // Priority-specific metrics
     priority_queues: Arc<[DashMap<u64, Instant>; 4]>,
@@ -150,6 +153,9 @@ pub struct PerformanceMonitor {
     timeout_events: Arc<AtomicU64>,
     error_count: Arc<AtomicU64>,
     
+    // Placeholder lookup cache, once watched
+    lookup_cache: Arc<parking_lot::RwLock<Option<Arc<LookupCache>>>>,
+    
     // Monitoring interval
     sample_interval: Duration,
 }
@@ -166,6 +172,7 @@ impl PerformanceMonitor {
             enqueued_total: Arc::new(AtomicU64::new(0)),
             dequeued_total: Arc::new(AtomicU64::new(0)),
             dropped_total: Arc::new(AtomicU64::new(0)),
+            deferred_total: Arc::new(AtomicU64::new(0)),
             
             priority_queues: Arc::new([
                 DashMap::new(),
@@ -197,6 +204,8 @@ impl PerformanceMonitor {
             timeout_events: Arc::new(AtomicU64::new(0)),
             error_count: Arc::new(AtomicU64::new(0)),
             
+            lookup_cache: Arc::new(parking_lot::RwLock::new(None)),
+            
             sample_interval: Duration::from_millis(100),
         }
     }
@@ -251,7 +260,7 @@ impl PerformanceMonitor {
             self.priority_processed[priority_idx].fetch_add(1, Ordering::Relaxed);
             
             // Record wait time
-            tokio::spawn({
+            shadowfs_core::task::spawn("perf-wait-times", {
                 let wait_times = self.priority_wait_times[priority_idx].clone();
                 async move {
                     let mut times = wait_times.write().await;
@@ -268,11 +277,24 @@ impl PerformanceMonitor {
         }
     }
 
-    /// Record task dropped
+    /// Record task dropped, whether it was queued or waiting to be
     pub fn record_drop(&self, priority: TaskPriority, task_id: u64) {
         self.dropped_total.fetch_add(1, Ordering::Relaxed);
-        self.queue_depth.fetch_sub(1, Ordering::AcqRel);
-        self.priority_queues[priority as usize].remove(&task_id);
+        self.forget_queued(priority, task_id);
+    }
+    
+    /// Record task deferred by a full queue, to be queued again after a backoff
+    pub fn record_deferral(&self, priority: TaskPriority, task_id: u64) {
+        self.deferred_total.fetch_add(1, Ordering::Relaxed);
+        self.backpressure_events.fetch_add(1, Ordering::Relaxed);
+        self.forget_queued(priority, task_id);
+    }
+    
+    /// Takes a task out of the queue depth if it was queued
+    fn forget_queued(&self, priority: TaskPriority, task_id: u64) {
+        if self.priority_queues[priority as usize].remove(&task_id).is_some() {
+            self.queue_depth.fetch_sub(1, Ordering::AcqRel);
+        }
     }
 
     /// Record thread activity
@@ -293,7 +315,7 @@ impl PerformanceMonitor {
         }
 
         let duration_ms = duration.as_millis() as u64;
-        tokio::spawn({
+        shadowfs_core::task::spawn("perf-durations", {
             let durations = self.task_durations.clone();
             async move {
                 let mut d = durations.write().await;
@@ -320,6 +342,16 @@ impl PerformanceMonitor {
         self.error_count.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Reports the hits and misses of `cache` with the other metrics
+    pub fn watch_lookup_cache(&self, cache: Arc<LookupCache>) {
+        *self.lookup_cache.write() = Some(cache);
+    }
+    
+    /// Get lookup cache metrics, if a cache is watched
+    pub fn get_lookup_cache_metrics(&self) -> Option<LookupCacheStats> {
+        self.lookup_cache.read().as_ref().map(|cache| cache.stats())
+    }
+    
     /// Update max queue depth
     fn update_max_depth(&self, current: usize) {
         let mut max = self.max_queue_depth.load(Ordering::Relaxed);
@@ -413,6 +445,7 @@ impl PerformanceMonitor {
             total_enqueued: self.enqueued_total.load(Ordering::Relaxed),
             total_dequeued: self.dequeued_total.load(Ordering::Relaxed),
             total_dropped: self.dropped_total.load(Ordering::Relaxed),
+            total_deferred: self.deferred_total.load(Ordering::Relaxed),
             by_priority,
         }
     }
@@ -482,7 +515,7 @@ impl PerformanceMonitor {
 
     /// Start periodic monitoring
     pub fn start_monitoring(self: Arc<Self>) {
-        tokio::spawn(async move {
+        shadowfs_core::task::spawn("perf-monitor", async move {
             let mut interval = tokio::time::interval(Duration::from_secs(60));
             
             loop {
@@ -543,6 +576,7 @@ impl PerformanceMonitor {
         report.push_str(&format!("  Max Depth: {}\n", queue.max_depth));
         report.push_str(&format!("  Average Depth: {:.2}\n", queue.avg_depth));
         report.push_str(&format!("  Total Enqueued: {}\n", queue.total_enqueued));
+        report.push_str(&format!("  Total Deferred: {}\n", queue.total_deferred));
         report.push_str(&format!("  Total Dropped: {}\n\n", queue.total_dropped));
 
         report.push_str("Priority Queue Breakdown:\n");
@@ -562,6 +596,16 @@ impl PerformanceMonitor {
         report.push_str(&format!("  Tasks Failed: {}\n", threads.tasks_failed));
         report.push_str(&format!("  Avg Task Duration: {:.2}ms\n\n", threads.avg_task_duration_ms));
 
+        if let Some(cache) = self.get_lookup_cache_metrics() {
+            report.push_str("Lookup Cache:\n");
+            report.push_str(&format!("  Entries: {}\n", cache.entries));
+            report.push_str(&format!("  Hits: {}\n", cache.hits));
+            report.push_str(&format!("  Negative Hits: {}\n", cache.negative_hits));
+            report.push_str(&format!("  Misses: {}\n", cache.misses));
+            report.push_str(&format!("  Hit Rate: {:.1}%\n", cache.hit_rate() * 100.0));
+            report.push_str(&format!("  Invalidations: {}\n\n", cache.invalidations));
+        }
+        
         report.push_str("Callback Latencies:\n");
         for metric in callbacks {
             report.push_str(&format!("  {}:\n", metric.operation_type));
@@ -586,6 +630,7 @@ impl Clone for PerformanceMonitor {
             enqueued_total: self.enqueued_total.clone(),
             dequeued_total: self.dequeued_total.clone(),
             dropped_total: self.dropped_total.clone(),
+            deferred_total: self.deferred_total.clone(),
             priority_queues: self.priority_queues.clone(),
             priority_processed: self.priority_processed.clone(),
             priority_wait_times: self.priority_wait_times.clone(),
@@ -598,6 +643,7 @@ impl Clone for PerformanceMonitor {
             backpressure_events: self.backpressure_events.clone(),
             timeout_events: self.timeout_events.clone(),
             error_count: self.error_count.clone(),
+            lookup_cache: self.lookup_cache.clone(),
             sample_interval: self.sample_interval,
         }
     }
@@ -644,6 +690,23 @@ mod tests {
         assert_eq!(thread_metrics.tasks_completed, 1);
     }
 
+    #[tokio::test]
+    async fn test_lookup_cache_metrics() {
+        let monitor = PerformanceMonitor::new(1);
+        assert!(monitor.get_lookup_cache_metrics().is_none());
+        
+        let cache = Arc::new(LookupCache::default());
+        monitor.clone().watch_lookup_cache(Arc::clone(&cache));
+        let missing = || Err(std::io::Error::from(std::io::ErrorKind::NotFound));
+        for _ in 0..3 {
+            cache.lookup(&shadowfs_core::types::ShadowPath::from("/desktop.ini"), missing).unwrap();
+        }
+        
+        let metrics = monitor.get_lookup_cache_metrics().unwrap();
+        assert_eq!((metrics.negative_hits, metrics.misses), (2, 1));
+        assert!(monitor.generate_report().await.contains("Negative Hits: 2"));
+    }
+    
     #[test]
     fn test_latency_histogram() {
         let histogram = LatencyHistogram::new();
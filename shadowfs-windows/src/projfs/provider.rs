@@ -1,11 +1,34 @@
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::fmt;
 use dashmap::DashMap;
+use parking_lot::RwLock;
 use windows::core::GUID;
-use windows::Win32::Storage::ProjectedFileSystem::{PRJ_INSTANCE_HANDLE, PrjStopVirtualizing};
+use windows::core::PCWSTR;
+use windows::Win32::Storage::ProjectedFileSystem::{
+    PRJ_INSTANCE_HANDLE, PRJ_NOTIFICATION, PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_DELETED,
+    PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_MODIFIED, PRJ_NOTIFICATION_FILE_OVERWRITTEN,
+    PRJ_NOTIFICATION_FILE_RENAMED, PRJ_NOTIFICATION_NEW_FILE_CREATED, PRJ_UPDATE_ALLOW_DIRTY_METADATA,
+    PRJ_UPDATE_ALLOW_READ_ONLY, PrjDeleteFile, PrjStopVirtualizing,
+};
+use shadowfs_core::error::ShadowError;
+use shadowfs_core::hidden::HiddenFiles;
+use shadowfs_core::merged_view::MergedDirectoryView;
+use shadowfs_core::mount_paths;
 use shadowfs_core::override_store::OverrideStore;
+use shadowfs_core::source_watch::{SourceChangeHandler, SourceWatcher};
+use shadowfs_core::types::{FileMetadata, Platform, ShadowPath};
+use shadowfs_core::watch::{ChangeEvent, ChangeKind, ChangeSource, WatchService};
+use crate::error::WindowsError;
 use crate::stats::FileSystemStats;
+use super::cloud_files::{self, CloudFilesPolicy};
+use super::lookup_cache::{LookupCache, LookupCacheConfig};
+use super::oplocks::{is_busy_error, LeaseEvent, OplockConfig, PendingUpdates, SourceLeases};
+use super::short_names::{ShortNamePolicy, ShortNameTable};
+use super::source_watcher::{SourceWatchConfig, WindowsSourceWatcher};
+use super::streams;
+use super::write_capture::{WriteCapture, CAPTURED_NOTIFICATIONS};
 
 /// Safe wrapper around PRJ_INSTANCE_HANDLE
 pub struct ProjFSHandle {
@@ -81,28 +104,60 @@ pub struct ProjFSConfig {
     /// Number of threads in the ProjFS thread pool (default: CPU count)
     pub pool_thread_count: u32,
     
-    /// Notification mappings for specific paths
+    /// Notification mappings for specific paths (default: the notifications
+    /// that capture writes, for the whole virtualization root)
     pub notification_mappings: Vec<NotificationMapping>,
     
     /// Enable negative path caching
     pub enable_negative_cache: bool,
     
+    /// Limits of the cache of source lookups made for placeholders
+    pub lookup_cache: LookupCacheConfig,
+    
     /// Optional virtualization instance ID
     pub virtualization_instance_id: Option<GUID>,
+    
+    /// How 8.3 short names are reported for projected files
+    pub short_name_policy: ShortNamePolicy,
+    
+    /// Oplock break and busy-file handling
+    pub oplocks: OplockConfig,
+    
+    /// Handling of sources managed by a cloud files provider such as OneDrive
+    pub cloud_files: CloudFilesPolicy,
+    
+    /// How changes made directly to the source directory are watched
+    pub source_watch: SourceWatchConfig,
 }
 
 impl Default for ProjFSConfig {
     fn default() -> Self {
         Self {
             pool_thread_count: num_cpus::get() as u32,
-            notification_mappings: Vec::new(),
+            notification_mappings: vec![NotificationMapping {
+                notification_root: PathBuf::new(),
+                notifications: CAPTURED_NOTIFICATIONS.0,
+            }],
             enable_negative_cache: true,
+            lookup_cache: LookupCacheConfig::default(),
             virtualization_instance_id: None,
+            short_name_policy: ShortNamePolicy::Disabled,
+            oplocks: OplockConfig::default(),
+            cloud_files: CloudFilesPolicy::Refuse,
+            source_watch: SourceWatchConfig::default(),
         }
     }
 }
 
+impl ProjFSConfig {
+    /// Creates the lookup cache these settings ask for
+    pub fn lookup_cache(&self) -> LookupCache {
+        LookupCache::new(self.lookup_cache.clone(), self.enable_negative_cache)
+    }
+}
+
 /// Represents an active enumeration session
+#[derive(Clone)]
 pub struct EnumerationSession {
     /// Optional search pattern for filtering results
     pub search_expression: Option<String>,
@@ -113,8 +168,14 @@ pub struct EnumerationSession {
     /// Whether this is a restart of the enumeration
     pub is_restart: bool,
     
-    /// Continuation token for resuming enumeration
+    /// Name of the entry the next fill starts with, as UTF-8
     pub continuation_token: Option<Vec<u8>>,
+    
+    /// Names of the directory, read by the first fill of the enumeration
+    pub names: Option<Arc<BTreeSet<String>>>,
+    
+    /// Whether every entry has been filled
+    pub complete: bool,
 }
 
 /// Represents an open file handle
@@ -146,6 +207,36 @@ pub struct ProjFSProvider {
     
     /// File system statistics
     pub stats: Arc<FileSystemStats>,
+    
+    /// Short names generated for projected files
+    pub short_names: ShortNameTable,
+    
+    /// Oplock break and busy-file handling settings
+    pub oplock_config: OplockConfig,
+    
+    /// Placeholder updates deferred while an oplock break completes
+    pub pending_updates: PendingUpdates,
+    
+    /// Lease thread for source files, once oplock handling is started
+    pub source_leases: Option<SourceLeases>,
+    
+    /// Receives native ProjFS notifications as change events, when set
+    pub watch_service: Option<Arc<WatchService>>,
+    
+    /// Handling of sources managed by a cloud files provider
+    pub cloud_files: CloudFilesPolicy,
+    
+    /// Settings for watching the source directory
+    pub source_watch_config: SourceWatchConfig,
+    
+    /// Watcher reporting changes made directly to the source, once started
+    pub source_watcher: Option<WindowsSourceWatcher>,
+    
+    /// Entries left out of enumerations, and possibly placeholder lookups
+    pub hidden_files: HiddenFiles,
+    
+    /// Source metadata and missing paths seen by placeholder requests
+    pub lookup_cache: Arc<LookupCache>,
 }
 
 impl ProjFSProvider {
@@ -157,6 +248,8 @@ impl ProjFSProvider {
         override_store: Arc<OverrideStore>,
         stats: Arc<FileSystemStats>,
     ) -> Self {
+        // NTFS paths such as `notes.txt:meta` name a stream of their file
+        override_store.set_named_streams(true);
         Self {
             instance: ProjFSHandle::new(instance),
             virtualization_root,
@@ -165,8 +258,307 @@ impl ProjFSProvider {
             active_enumerations: DashMap::new(),
             file_handles: DashMap::new(),
             stats,
+            short_names: ShortNameTable::default(),
+            oplock_config: OplockConfig::default(),
+            pending_updates: PendingUpdates::new(OplockConfig::default()),
+            source_leases: None,
+            watch_service: None,
+            cloud_files: CloudFilesPolicy::Refuse,
+            source_watch_config: SourceWatchConfig::default(),
+            source_watcher: None,
+            hidden_files: HiddenFiles::default(),
+            lookup_cache: Arc::new(LookupCache::default()),
+        }
+    }
+    
+    /// Sets how 8.3 short names are reported for projected files
+    pub fn with_short_name_policy(mut self, policy: ShortNamePolicy) -> Self {
+        self.short_names = ShortNameTable::new(policy);
+        self
+    }
+    
+    /// Sets how oplock breaks and busy files are handled
+    pub fn with_oplock_config(mut self, config: OplockConfig) -> Self {
+        self.pending_updates = PendingUpdates::new(config.clone());
+        self.oplock_config = config;
+        self
+    }
+    
+    /// Sets how sources managed by a cloud files provider are handled
+    pub fn with_cloud_files_policy(mut self, policy: CloudFilesPolicy) -> Self {
+        self.cloud_files = policy;
+        self
+    }
+    
+    /// Sets how changes made directly to the source directory are watched
+    pub fn with_source_watch_config(mut self, config: SourceWatchConfig) -> Self {
+        self.source_watch_config = config;
+        self
+    }
+    
+    /// Sets which entries are left out of enumerations and lookups
+    pub fn with_hidden_files(mut self, hidden: HiddenFiles) -> Self {
+        self.hidden_files = hidden;
+        self
+    }
+    
+    /// Sets the cache of source lookups made for placeholders
+    pub fn with_lookup_cache(mut self, cache: LookupCache) -> Self {
+        self.lookup_cache = Arc::new(cache);
+        self
+    }
+    
+    /// Checks that the source root can be projected, refusing cloud file trees unless hydration is enabled
+    pub fn check_source_root(&self) -> Result<(), WindowsError> {
+        cloud_files::check_source_root(&self.source_root, self.cloud_files)
+    }
+    
+    /// Forwards ProjFS notifications to `service` through [`ProjFSProvider::forward_notification`]
+    pub fn with_watch_service(mut self, service: Arc<WatchService>) -> Self {
+        self.watch_service = Some(service);
+        self
+    }
+    
+    /// Publishes a native ProjFS notification as a change event
+    ///
+    /// Notifications that do not change the namespace, such as file opens,
+    /// are ignored.
+    ///
+    /// # Arguments
+    /// * `notification` - Notification type received by the notification callback
+    /// * `relative_path` - Path the notification is about, relative to the virtualization root
+    /// * `destination` - New path for renames
+    /// * `is_directory` - Whether the path is a directory
+    pub fn forward_notification(
+        &self,
+        notification: PRJ_NOTIFICATION,
+        relative_path: &Path,
+        destination: Option<&Path>,
+        is_directory: bool,
+    ) {
+        let Some(service) = &self.watch_service else {
+            return;
+        };
+        let Some((path, kind)) = change_from_notification(notification, relative_path, destination) else {
+            return;
+        };
+        
+        service.publish(ChangeEvent::new(path, kind, is_directory, ChangeSource::Native(Platform::Windows)));
+    }
+    
+    /// Records a write reported by a native ProjFS notification in the override store
+    ///
+    /// See [`WriteCapture::capture`].
+    pub fn capture_notification(
+        &self,
+        notification: PRJ_NOTIFICATION,
+        relative_path: &Path,
+        destination: Option<&Path>,
+        is_directory: bool,
+    ) -> Result<bool, ShadowError> {
+        let captured = WriteCapture::new(&self.override_store, &self.source_root, &self.virtualization_root)
+            .capture(notification, relative_path, destination, is_directory)?;
+        if captured {
+            for path in std::iter::once(relative_path).chain(destination) {
+                self.lookup_cache.invalidate(&mount_paths::relative_shadow_path(path));
+            }
+        }
+        Ok(captured)
+    }
+    
+    /// Starts the thread that handles source lease breaks and retries deferred placeholder updates
+    ///
+    /// When a lease breaks the placeholder of the changed file is invalidated,
+    /// and deferred placeholder updates are retried whenever the thread is
+    /// idle. Source files are only watched if enabled in the oplock config.
+    pub fn start_oplock_handling(provider: &Arc<RwLock<Self>>) -> Result<(), WindowsError> {
+        let config = provider.read().oplock_config.clone();
+        let weak = Arc::downgrade(provider);
+        let leases = SourceLeases::start(config.retry_interval, move |event| {
+            let Some(provider) = weak.upgrade() else {
+                return;
+            };
+            let provider = provider.read();
+            match event {
+                LeaseEvent::Broken(path) => provider.invalidate_placeholder(path),
+                LeaseEvent::Idle => provider.retry_pending_updates(),
+            }
+        })?;
+        
+        provider.write().source_leases = Some(leases);
+        Ok(())
+    }
+    
+    /// Starts watching the source directory for changes made outside the projection
+    ///
+    /// Changes are checked against the override store for conflicts and
+    /// published to its watch service, and the placeholders of changed paths
+    /// are invalidated so the next access projects the new source content.
+    /// A rescan of the whole tree cannot drop the root placeholder, so it
+    /// only empties the lookup cache; stale hydrated files below it are then
+    /// refreshed as their leases break.
+    pub fn start_source_watching(provider: &Arc<RwLock<Self>>) -> Result<Arc<SourceChangeHandler>, WindowsError> {
+        Self::stop_source_watching(provider);
+        let (store, source_root, config) = {
+            let provider = provider.read();
+            (
+                Arc::clone(&provider.override_store),
+                provider.source_root.clone(),
+                provider.source_watch_config.clone(),
+            )
+        };
+        
+        let handler = Arc::new(SourceChangeHandler::new(store));
+        let weak = Arc::downgrade(provider);
+        handler.on_invalidate(move |path, _recursive| {
+            let Some(provider) = weak.upgrade() else {
+                return;
+            };
+            let provider = provider.read();
+            let relative_path = mount_paths::relative_path(path);
+            if relative_path.as_os_str().is_empty() {
+                provider.lookup_cache.invalidate_all();
+            } else {
+                provider.invalidate_placeholder(relative_path);
+            }
+        });
+        
+        let mut watcher = WindowsSourceWatcher::new(source_root, config);
+        watcher.start_watching(Arc::clone(&handler) as Arc<_>)?;
+        provider.write().source_watcher = Some(watcher);
+        Ok(handler)
+    }
+    
+    /// Stops watching the source directory
+    ///
+    /// The watcher is stopped outside the provider lock, since its thread may
+    /// be waiting for the lock to invalidate a placeholder.
+    pub fn stop_source_watching(provider: &Arc<RwLock<Self>>) {
+        let watcher = provider.write().source_watcher.take();
+        if let Some(mut watcher) = watcher {
+            watcher.stop();
         }
     }
+    
+    /// Watches the source file behind a placeholder that was just hydrated from it
+    pub fn watch_source(&self, relative_path: &Path) {
+        if !self.oplock_config.source_leases {
+            return;
+        }
+        if let Some(leases) = &self.source_leases {
+            let source_path = mount_paths::source_path(&self.source_root, &mount_paths::relative_shadow_path(relative_path));
+            if let Err(e) = leases.watch(relative_path, &source_path) {
+                log::debug!("No lease on {}: {}", source_path.display(), e);
+            }
+        }
+    }
+    
+    /// Drops the placeholder of `relative_path` so the next access projects it again
+    ///
+    /// Paths with an override are left alone since the override shadows the
+    /// source. If the file is busy, usually because an application's oplock
+    /// is being broken, the update is deferred and retried later. The source
+    /// lookups of the path are dropped either way.
+    pub fn invalidate_placeholder(&self, relative_path: &Path) {
+        self.lookup_cache.invalidate(&mount_paths::relative_shadow_path(relative_path));
+        if self.override_store.exists(&ShadowPath::from(relative_path.to_path_buf())) {
+            self.pending_updates.complete(relative_path);
+            return;
+        }
+        
+        let wide_path: Vec<u16> = relative_path.to_string_lossy()
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+        let result = unsafe {
+            PrjDeleteFile(
+                self.instance.get(),
+                PCWSTR(wide_path.as_ptr()),
+                PRJ_UPDATE_ALLOW_DIRTY_METADATA | PRJ_UPDATE_ALLOW_READ_ONLY,
+                None,
+            )
+        };
+        
+        match result {
+            Ok(()) => {
+                self.pending_updates.complete(relative_path);
+                if let Some(parent) = relative_path.parent() {
+                    self.short_names.invalidate(parent);
+                }
+            }
+            Err(e) if is_busy_error((e.code().0 & 0xFFFF) as u32) => {
+                if !self.pending_updates.defer(relative_path, std::time::Instant::now()) {
+                    log::warn!(
+                        "Giving up on invalidating {} after {} busy retries",
+                        relative_path.display(),
+                        self.oplock_config.max_retries
+                    );
+                }
+            }
+            Err(e) => {
+                self.pending_updates.complete(relative_path);
+                log::warn!("Failed to invalidate placeholder {}: {}", relative_path.display(), e);
+            }
+        }
+    }
+    
+    /// Returns the metadata the mount shows for `relative_path`, or `None`
+    /// if nothing is there
+    ///
+    /// The named streams of the path, those of its source file merged with
+    /// their overrides, are listed in its platform metadata.
+    pub fn metadata(&self, relative_path: &Path) -> Result<Option<FileMetadata>, ShadowError> {
+        let shadow_path = mount_paths::relative_shadow_path(relative_path);
+        let (Some(parent), Some(name)) = (shadow_path.parent(), shadow_path.file_name()) else {
+            return Ok(None);
+        };
+        let view = MergedDirectoryView::new(&self.override_store, &self.source_root, parent);
+        let Some(mut metadata) = view.entry(&name)?.map(|entry| entry.metadata) else {
+            return Ok(None);
+        };
+        
+        let source_path = mount_paths::source_path(&self.source_root, &shadow_path);
+        let source = match streams::source_streams(&source_path) {
+            Ok(streams) => streams,
+            Err(e) => {
+                log::debug!("No source streams for {}: {}", source_path.display(), e);
+                Vec::new()
+            }
+        };
+        metadata.set_streams(self.override_store.merge_streams(&shadow_path, source));
+        Ok(Some(metadata))
+    }
+    
+    /// Retries deferred placeholder updates whose backoff has elapsed
+    pub fn retry_pending_updates(&self) {
+        for path in self.pending_updates.take_due(std::time::Instant::now()) {
+            self.invalidate_placeholder(&path);
+        }
+    }
+}
+
+/// Maps a ProjFS notification to the path and kind of the change it reports
+fn change_from_notification(
+    notification: PRJ_NOTIFICATION,
+    relative_path: &Path,
+    destination: Option<&Path>,
+) -> Option<(ShadowPath, ChangeKind)> {
+    let path = ShadowPath::from(Path::new("/").join(relative_path));
+    let kind = match notification {
+        PRJ_NOTIFICATION_NEW_FILE_CREATED => ChangeKind::Created,
+        PRJ_NOTIFICATION_FILE_OVERWRITTEN | PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_MODIFIED => ChangeKind::Modified,
+        PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_DELETED => ChangeKind::Deleted,
+        PRJ_NOTIFICATION_FILE_RENAMED => {
+            // Renames into the virtualization root from outside arrive without a source path
+            let destination = ShadowPath::from(Path::new("/").join(destination?));
+            if relative_path.as_os_str().is_empty() {
+                return Some((destination, ChangeKind::Created));
+            }
+            return Some((destination, ChangeKind::Renamed { from: path }));
+        }
+        _ => return None,
+    };
+    Some((path, kind))
 }
 
 impl fmt::Debug for ProjFSProvider {
@@ -177,6 +569,9 @@ impl fmt::Debug for ProjFSProvider {
             .field("source_root", &self.source_root)
             .field("active_enumerations", &self.active_enumerations.len())
             .field("file_handles", &self.file_handles.len())
+            .field("short_name_policy", &self.short_names.policy())
+            .field("pending_updates", &self.pending_updates.len())
+            .field("lookup_cache", &self.lookup_cache)
             .finish()
     }
 }
\ No newline at end of file
@@ -0,0 +1,288 @@
+//! 8.3 short-name generation for projected files.
+//!
+//! ProjFS placeholders carry no short name, so volumes with 8.3 generation
+//! disabled report none and legacy installers that call `GetShortPathNameW`
+//! fail. With [`ShortNamePolicy::Deterministic`] the provider assigns a short
+//! name to every placeholder whose long name is not already 8.3 compliant.
+//! Names are derived from the sorted directory listing, so the same tree
+//! always yields the same short names regardless of the order in which files
+//! are first opened.
+
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Characters allowed in a short name besides ASCII letters and digits.
+const SHORT_NAME_SPECIAL_CHARS: &str = "!#$%&'()-@^_`{}~";
+
+/// Number of `~N` suffixes tried before switching to hashed base names.
+const PLAIN_SUFFIX_ATTEMPTS: u32 = 4;
+
+/// Upper bound on attempts before giving up on a name.
+const MAX_ATTEMPTS: u32 = 1000;
+
+/// How short names are assigned to projected files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShortNamePolicy {
+    /// Report no short names, matching plain ProjFS behaviour
+    #[default]
+    Disabled,
+    
+    /// Generate NTFS-style short names from the directory listing
+    Deterministic,
+}
+
+/// Checks whether `name` is already a valid 8.3 name and needs no short name.
+pub fn is_short_name_compliant(name: &str) -> bool {
+    if name == "." || name == ".." {
+        return true;
+    }
+    
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) => (base, Some(ext)),
+        None => (name, None),
+    };
+    
+    let valid_part = |part: &str, max: usize| {
+        !part.is_empty() && part.len() <= max && part.chars().all(is_short_name_char)
+    };
+    
+    valid_part(base, 8) && ext.is_none_or(|ext| valid_part(ext, 3))
+}
+
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || SHORT_NAME_SPECIAL_CHARS.contains(c)
+}
+
+/// Uppercases and strips a name part down to valid short-name characters.
+fn sanitize(part: &str) -> String {
+    part.chars()
+        .filter(|c| *c != ' ' && *c != '.')
+        .map(|c| {
+            let c = c.to_ascii_uppercase();
+            if is_short_name_char(c) { c } else { '_' }
+        })
+        .collect()
+}
+
+/// Stable FNV-1a hash of a long name, used for hashed short names.
+fn name_hash(name: &str, attempt: u32) -> u16 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for unit in name.to_uppercase().encode_utf16().chain(std::iter::once(attempt as u16)) {
+        hash ^= unit as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    (hash ^ (hash >> 16)) as u16
+}
+
+/// Builds the candidate short name for `attempt` (1-based).
+fn candidate(long_name: &str, attempt: u32) -> String {
+    let (base, ext) = match long_name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (sanitize(base), sanitize(ext)),
+        _ => (sanitize(long_name), String::new()),
+    };
+    let base = if base.is_empty() { "_".to_string() } else { base };
+    let ext: String = ext.chars().take(3).collect();
+    
+    // Like NTFS: NAME~1 .. NAME~4, then two characters plus a hash
+    let stem = if attempt <= PLAIN_SUFFIX_ATTEMPTS {
+        let prefix: String = base.chars().take(6).collect();
+        format!("{}~{}", prefix, attempt)
+    } else {
+        let hashed = attempt - PLAIN_SUFFIX_ATTEMPTS - 1;
+        let prefix: String = base.chars().take(2).collect();
+        format!("{}{:04X}~{}", prefix, name_hash(long_name, hashed / 9), hashed % 9 + 1)
+    };
+    
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+/// Short names assigned within one directory.
+#[derive(Debug, Default)]
+struct DirectoryShortNames {
+    /// Assigned short names keyed by uppercased long name
+    assigned: HashMap<String, String>,
+    
+    /// Uppercased names in use, both short names and compliant long names
+    taken: HashSet<String>,
+}
+
+impl DirectoryShortNames {
+    /// Assigns short names to a full directory listing in sorted order.
+    fn from_listing(mut names: Vec<String>) -> Self {
+        names.sort_by_key(|name| name.to_uppercase());
+        names.dedup_by_key(|name| name.to_uppercase());
+        
+        let mut table = Self::default();
+        for name in names.iter().filter(|name| is_short_name_compliant(name)) {
+            table.taken.insert(name.to_uppercase());
+        }
+        for name in &names {
+            table.assign(name);
+        }
+        table
+    }
+    
+    /// Returns the short name of `name`, assigning a free one if needed.
+    fn assign(&mut self, name: &str) -> Option<String> {
+        if is_short_name_compliant(name) {
+            self.taken.insert(name.to_uppercase());
+            return None;
+        }
+        
+        let key = name.to_uppercase();
+        if let Some(short) = self.assigned.get(&key) {
+            return Some(short.clone());
+        }
+        
+        let short = (1..=MAX_ATTEMPTS)
+            .map(|attempt| candidate(name, attempt))
+            .find(|short| !self.taken.contains(short))?;
+        self.taken.insert(short.clone());
+        self.assigned.insert(key, short.clone());
+        Some(short)
+    }
+}
+
+/// Per-directory cache of generated short names.
+#[derive(Debug, Default)]
+pub struct ShortNameTable {
+    policy: ShortNamePolicy,
+    directories: DashMap<PathBuf, DirectoryShortNames>,
+}
+
+impl ShortNameTable {
+    /// Creates a table applying `policy`.
+    pub fn new(policy: ShortNamePolicy) -> Self {
+        Self {
+            policy,
+            directories: DashMap::new(),
+        }
+    }
+    
+    /// Returns the policy of this table.
+    pub fn policy(&self) -> ShortNamePolicy {
+        self.policy
+    }
+    
+    /// Returns the short name for `name` in `directory`, if it needs one.
+    ///
+    /// The first lookup in a directory calls `list_directory` to get every
+    /// name in it. Names that appear later are assigned the next free short
+    /// name, so names already handed out never change.
+    pub fn short_name(
+        &self,
+        directory: &Path,
+        name: &str,
+        list_directory: impl FnOnce() -> Vec<String>,
+    ) -> Option<String> {
+        if self.policy == ShortNamePolicy::Disabled {
+            return None;
+        }
+        
+        self.directories
+            .entry(directory.to_path_buf())
+            .or_insert_with(|| DirectoryShortNames::from_listing(list_directory()))
+            .assign(name)
+    }
+    
+    /// Forgets the names generated for `directory`.
+    pub fn invalidate(&self, directory: &Path) {
+        self.directories.remove(directory);
+    }
+}
+
+/// Sets the NTFS short name of the placeholder at `path`.
+///
+/// Requires the `SeRestorePrivilege`; without it the call fails and the file
+/// simply keeps reporting no short name.
+pub fn apply_short_name(path: &Path, short_name: &str) -> windows::core::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{CloseHandle, GENERIC_WRITE};
+    use windows::Win32::Storage::FileSystem::{
+        CreateFileW, SetFileShortNameW, DELETE, FILE_FLAG_BACKUP_SEMANTICS, FILE_SHARE_DELETE,
+        FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let wide_name: Vec<u16> = short_name.encode_utf16().chain(std::iter::once(0)).collect();
+    
+    unsafe {
+        let handle = CreateFileW(
+            PCWSTR(wide_path.as_ptr()),
+            GENERIC_WRITE.0 | DELETE.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS,
+            None,
+        )?;
+        let result = SetFileShortNameW(handle, PCWSTR(wide_name.as_ptr())).ok();
+        let _ = CloseHandle(handle);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_compliant_names() {
+        assert!(is_short_name_compliant("README.TXT"));
+        assert!(is_short_name_compliant("setup.exe"));
+        assert!(is_short_name_compliant("MAKEFILE"));
+        assert!(!is_short_name_compliant("Program Files"));
+        assert!(!is_short_name_compliant("longfilename.txt"));
+        assert!(!is_short_name_compliant("archive.tar.gz"));
+        assert!(!is_short_name_compliant("data.json5"));
+    }
+    
+    #[test]
+    fn test_generation_matches_ntfs_style() {
+        let table = ShortNameTable::new(ShortNamePolicy::Deterministic);
+        let listing = || vec!["Program Files".to_string(), "Program Files (x86)".to_string()];
+        
+        let first = table.short_name(Path::new("C:\\"), "Program Files", listing);
+        let second = table.short_name(Path::new("C:\\"), "Program Files (x86)", Vec::new);
+        assert_eq!(first.as_deref(), Some("PROGRA~1"));
+        assert_eq!(second.as_deref(), Some("PROGRA~2"));
+        
+        assert_eq!(table.short_name(Path::new("C:\\"), "setup.exe", Vec::new), None);
+    }
+    
+    #[test]
+    fn test_generation_is_order_independent() {
+        let names: Vec<String> = (0..6).map(|i| format!("installer-part-{}.cab", i)).collect();
+        let mut reversed = names.clone();
+        reversed.reverse();
+        
+        let a = DirectoryShortNames::from_listing(names.clone());
+        let b = DirectoryShortNames::from_listing(reversed);
+        assert_eq!(a.assigned, b.assigned);
+        
+        // Four plain suffixes, then hashed names; all unique
+        let shorts: HashSet<&String> = a.assigned.values().collect();
+        assert_eq!(shorts.len(), names.len());
+        assert!(a.assigned.values().all(|short| is_short_name_compliant(short)));
+        assert_eq!(a.assigned["INSTALLER-PART-0.CAB"], "INSTAL~1.CAB");
+    }
+    
+    #[test]
+    fn test_collision_with_existing_compliant_name() {
+        let listing = vec!["PROGRA~1".to_string(), "Program Files".to_string()];
+        let table = DirectoryShortNames::from_listing(listing);
+        assert_eq!(table.assigned["PROGRAM FILES"], "PROGRA~2");
+    }
+    
+    #[test]
+    fn test_disabled_policy_reports_none() {
+        let table = ShortNameTable::new(ShortNamePolicy::Disabled);
+        assert_eq!(table.short_name(Path::new("C:\\"), "Program Files", Vec::new), None);
+    }
+}
@@ -0,0 +1,1039 @@
+//! Watching the source directory for changes made outside the projection.
+//!
+//! Two backends are available, selected with [`SourceWatchBackend`]:
+//!
+//! - `ReadDirectoryChangesW` watches the source tree through a directory
+//!   handle serviced by a completion-port thread. It needs no privileges, but
+//!   the kernel drops notifications when its buffer overflows.
+//! - The NTFS change journal (USN journal) is read from the volume, so no
+//!   change is lost while the journal retains it, at the cost of requiring
+//!   administrator rights and resolving parent directories by file id.
+//!
+//! Either way the changes are translated into core [`SourceChange`]s and fed
+//! to a [`SourceChangeSink`], normally the shared `SourceChangeHandler`, which
+//! invalidates placeholders and records conflicts with overrides. Whenever
+//! changes may have been lost, because of a buffer overflow or because the
+//! journal wrapped, a rescan of the whole source tree is reported instead.
+
+use shadowfs_core::error::{platform_error, Platform as ErrorPlatform, ShadowError};
+use shadowfs_core::source_watch::{source_path, SourceChange, SourceChangeSink, SourceWatcher};
+use shadowfs_core::types::ShadowPath;
+use shadowfs_core::watch::ChangeKind;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::error::WindowsError;
+
+/// `FILE_NOTIFY_INFORMATION` actions
+const FILE_ACTION_ADDED: u32 = 1;
+const FILE_ACTION_REMOVED: u32 = 2;
+const FILE_ACTION_MODIFIED: u32 = 3;
+const FILE_ACTION_RENAMED_OLD_NAME: u32 = 4;
+const FILE_ACTION_RENAMED_NEW_NAME: u32 = 5;
+
+/// Size of the fixed part of `FILE_NOTIFY_INFORMATION`
+const NOTIFY_HEADER_SIZE: usize = 12;
+
+/// `USN_RECORD_V2` reason flags
+const USN_REASON_DATA_OVERWRITE: u32 = 0x0000_0001;
+const USN_REASON_DATA_EXTEND: u32 = 0x0000_0002;
+const USN_REASON_DATA_TRUNCATION: u32 = 0x0000_0004;
+const USN_REASON_NAMED_DATA_OVERWRITE: u32 = 0x0000_0010;
+const USN_REASON_NAMED_DATA_EXTEND: u32 = 0x0000_0020;
+const USN_REASON_NAMED_DATA_TRUNCATION: u32 = 0x0000_0040;
+const USN_REASON_FILE_CREATE: u32 = 0x0000_0100;
+const USN_REASON_FILE_DELETE: u32 = 0x0000_0200;
+const USN_REASON_EA_CHANGE: u32 = 0x0000_0400;
+const USN_REASON_SECURITY_CHANGE: u32 = 0x0000_0800;
+const USN_REASON_RENAME_OLD_NAME: u32 = 0x0000_1000;
+const USN_REASON_RENAME_NEW_NAME: u32 = 0x0000_2000;
+const USN_REASON_BASIC_INFO_CHANGE: u32 = 0x0000_8000;
+const USN_REASON_CLOSE: u32 = 0x8000_0000;
+
+/// Reasons that modify a file without changing its name
+const USN_REASON_MODIFIED: u32 = USN_REASON_DATA_OVERWRITE
+    | USN_REASON_DATA_EXTEND
+    | USN_REASON_DATA_TRUNCATION
+    | USN_REASON_NAMED_DATA_OVERWRITE
+    | USN_REASON_NAMED_DATA_EXTEND
+    | USN_REASON_NAMED_DATA_TRUNCATION
+    | USN_REASON_EA_CHANGE
+    | USN_REASON_SECURITY_CHANGE
+    | USN_REASON_BASIC_INFO_CHANGE;
+
+/// Size of the fixed part of `USN_RECORD_V2`
+const USN_RECORD_V2_HEADER_SIZE: usize = 60;
+
+/// Change journal control codes
+const FSCTL_QUERY_USN_JOURNAL: u32 = 0x0009_00F4;
+const FSCTL_READ_USN_JOURNAL: u32 = 0x0009_00BB;
+
+/// Win32 errors reported by the watch APIs
+const ERROR_OPERATION_ABORTED: u32 = 995;
+const ERROR_NOTIFY_ENUM_DIR: u32 = 1022;
+const ERROR_JOURNAL_DELETE_IN_PROGRESS: u32 = 1178;
+const ERROR_JOURNAL_NOT_ACTIVE: u32 = 1179;
+const ERROR_JOURNAL_ENTRY_DELETED: u32 = 1181;
+
+/// `FILE_ATTRIBUTE_DIRECTORY`
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+/// Completion key used to stop the watch thread
+const SHUTDOWN_KEY: usize = usize::MAX;
+
+/// Size of the buffer the change journal is read into
+const USN_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Where source changes are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceWatchBackend {
+    /// `ReadDirectoryChangesW` on the source directory
+    #[default]
+    ReadDirectoryChanges,
+    
+    /// The NTFS change journal of the source volume; requires administrator rights
+    UsnJournal,
+}
+
+/// Settings for watching the source directory.
+#[derive(Debug, Clone)]
+pub struct SourceWatchConfig {
+    /// Which API changes are read from
+    pub backend: SourceWatchBackend,
+    
+    /// Size of the `ReadDirectoryChangesW` buffer; changes beyond it cause a rescan
+    pub buffer_size: usize,
+    
+    /// How often the change journal is polled when it has no new records
+    pub poll_interval: Duration,
+}
+
+impl Default for SourceWatchConfig {
+    fn default() -> Self {
+        Self {
+            backend: SourceWatchBackend::ReadDirectoryChanges,
+            // Larger buffers are rejected for network shares
+            buffer_size: 64 * 1024,
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+/// A change as reported by either backend, before rename pairing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawAction {
+    /// The path was created or moved into the tree
+    Added,
+    /// The path was deleted or moved out of the tree
+    Removed,
+    /// Content or metadata changed
+    Modified,
+    /// First half of a rename; the old name
+    RenamedFrom,
+    /// Second half of a rename; the new name
+    RenamedTo,
+}
+
+/// A single raw change relative to the source root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawChange {
+    /// What happened
+    pub action: RawAction,
+    
+    /// Path relative to the source root
+    pub relative_path: PathBuf,
+    
+    /// Whether the path is a directory
+    pub is_directory: bool,
+}
+
+impl RawChange {
+    fn new(action: RawAction, relative_path: PathBuf, is_directory: bool) -> Self {
+        Self {
+            action,
+            relative_path,
+            is_directory,
+        }
+    }
+}
+
+/// Parses the `FILE_NOTIFY_INFORMATION` records filled in by `ReadDirectoryChangesW`.
+///
+/// # Returns
+/// The action code and path, relative to the watched directory, of every record
+pub fn parse_notify_buffer(buffer: &[u8]) -> Vec<(u32, PathBuf)> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    
+    while offset + NOTIFY_HEADER_SIZE <= buffer.len() {
+        let next = read_u32(buffer, offset) as usize;
+        let action = read_u32(buffer, offset + 4);
+        let name_length = read_u32(buffer, offset + 8) as usize;
+        let name_start = offset + NOTIFY_HEADER_SIZE;
+        let Some(name) = buffer.get(name_start..name_start + name_length) else {
+            break;
+        };
+        records.push((action, PathBuf::from(decode_utf16(name))));
+        
+        if next == 0 {
+            break;
+        }
+        offset += next;
+    }
+    records
+}
+
+/// Converts `ReadDirectoryChangesW` records into raw changes.
+///
+/// The notifications carry no file type, so it is read from the source;
+/// paths that no longer exist are reported as files.
+fn raw_changes_from_notify(source_root: &Path, records: Vec<(u32, PathBuf)>) -> Vec<RawChange> {
+    records.into_iter()
+        .filter_map(|(action, relative_path)| {
+            let action = match action {
+                FILE_ACTION_ADDED => RawAction::Added,
+                FILE_ACTION_REMOVED => RawAction::Removed,
+                FILE_ACTION_MODIFIED => RawAction::Modified,
+                FILE_ACTION_RENAMED_OLD_NAME => RawAction::RenamedFrom,
+                FILE_ACTION_RENAMED_NEW_NAME => RawAction::RenamedTo,
+                _ => return None,
+            };
+            let is_directory = action != RawAction::Removed
+                && std::fs::symlink_metadata(source_root.join(&relative_path))
+                    .map(|metadata| metadata.is_dir())
+                    .unwrap_or(false);
+            Some(RawChange::new(action, relative_path, is_directory))
+        })
+        .collect()
+}
+
+/// Pairs renames and drops repeated notifications for the same change.
+///
+/// A rename arrives as an old-name record followed by a new-name record. An
+/// old name without a new one was moved out of the tree and is reported as
+/// deleted; a new name without an old one was moved in and is reported as
+/// created.
+pub fn translate_changes(raw: Vec<RawChange>) -> Vec<SourceChange> {
+    let mut changes: Vec<SourceChange> = Vec::new();
+    let mut rename_from: Option<RawChange> = None;
+    
+    for change in raw {
+        let pending_from = rename_from.take();
+        if let Some(from) = &pending_from {
+            if change.action != RawAction::RenamedTo {
+                changes.push(SourceChange::from_relative(&from.relative_path, ChangeKind::Deleted, from.is_directory));
+            }
+        }
+        
+        let source_change = match change.action {
+            RawAction::RenamedFrom => {
+                rename_from = Some(change);
+                continue;
+            }
+            RawAction::RenamedTo => {
+                let kind = match pending_from {
+                    Some(from) => ChangeKind::Renamed { from: source_path(&from.relative_path) },
+                    None => ChangeKind::Created,
+                };
+                SourceChange::from_relative(&change.relative_path, kind, change.is_directory)
+            }
+            RawAction::Added => SourceChange::from_relative(&change.relative_path, ChangeKind::Created, change.is_directory),
+            RawAction::Removed => SourceChange::from_relative(&change.relative_path, ChangeKind::Deleted, change.is_directory),
+            RawAction::Modified => SourceChange::from_relative(&change.relative_path, ChangeKind::Modified, change.is_directory),
+        };
+        
+        // A single write is usually reported as several modifications
+        if changes.last() != Some(&source_change) {
+            changes.push(source_change);
+        }
+    }
+    
+    if let Some(from) = rename_from {
+        changes.push(SourceChange::from_relative(&from.relative_path, ChangeKind::Deleted, from.is_directory));
+    }
+    changes
+}
+
+/// A record of the change journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsnRecord {
+    /// File reference number of the changed file
+    pub file_reference: u64,
+    
+    /// File reference number of its parent directory
+    pub parent_reference: u64,
+    
+    /// `USN_REASON_*` flags accumulated for the file
+    pub reason: u32,
+    
+    /// Win32 attributes of the file
+    pub attributes: u32,
+    
+    /// File name, without the directory
+    pub name: String,
+}
+
+impl UsnRecord {
+    /// Whether the record is for a directory
+    pub fn is_directory(&self) -> bool {
+        self.attributes & FILE_ATTRIBUTE_DIRECTORY != 0
+    }
+    
+    /// Maps the record's reasons to a raw action
+    ///
+    /// Renames are reported as soon as they happen; every other change is
+    /// taken from the record written when the last handle closes, which
+    /// carries all reasons accumulated while the file was open.
+    pub fn action(&self) -> Option<RawAction> {
+        if self.reason & USN_REASON_CLOSE == 0 {
+            return if self.reason & USN_REASON_RENAME_OLD_NAME != 0 {
+                Some(RawAction::RenamedFrom)
+            } else if self.reason & USN_REASON_RENAME_NEW_NAME != 0 {
+                Some(RawAction::RenamedTo)
+            } else {
+                None
+            };
+        }
+        
+        let created = self.reason & USN_REASON_FILE_CREATE != 0;
+        let deleted = self.reason & USN_REASON_FILE_DELETE != 0;
+        match (created, deleted) {
+            // A temporary file that never outlived its handles
+            (true, true) => None,
+            (true, false) => Some(RawAction::Added),
+            (false, true) => Some(RawAction::Removed),
+            (false, false) if self.reason & USN_REASON_MODIFIED != 0 => Some(RawAction::Modified),
+            // The close record of a rename repeats the new name
+            (false, false) => None,
+        }
+    }
+}
+
+/// Parses the output of `FSCTL_READ_USN_JOURNAL`.
+///
+/// # Returns
+/// The USN to continue reading from and the `USN_RECORD_V2` records; records
+/// of other versions are skipped
+pub fn parse_usn_buffer(buffer: &[u8]) -> (i64, Vec<UsnRecord>) {
+    if buffer.len() < 8 {
+        return (0, Vec::new());
+    }
+    let next_usn = i64::from_ne_bytes(buffer[..8].try_into().unwrap());
+    let mut records = Vec::new();
+    let mut offset = 8;
+    
+    while offset + USN_RECORD_V2_HEADER_SIZE <= buffer.len() {
+        let length = read_u32(buffer, offset) as usize;
+        if length < USN_RECORD_V2_HEADER_SIZE || offset + length > buffer.len() {
+            break;
+        }
+        let major_version = read_u16(buffer, offset + 4);
+        if major_version == 2 {
+            let name_length = read_u16(buffer, offset + 56) as usize;
+            let name_offset = read_u16(buffer, offset + 58) as usize;
+            let name = buffer.get(offset + name_offset..offset + name_offset + name_length)
+                .map(decode_utf16)
+                .unwrap_or_default();
+            records.push(UsnRecord {
+                file_reference: read_u64(buffer, offset + 8),
+                parent_reference: read_u64(buffer, offset + 16),
+                reason: read_u32(buffer, offset + 40),
+                attributes: read_u32(buffer, offset + 52),
+                name,
+            });
+        }
+        offset += length;
+    }
+    (next_usn, records)
+}
+
+/// Returns `path` relative to `root`, comparing components without regard to case.
+pub fn strip_root(root: &Path, path: &Path) -> Option<PathBuf> {
+    let mut path_components = path.components();
+    for root_component in root.components() {
+        let path_component = path_components.next()?;
+        let same = root_component.as_os_str().to_string_lossy().to_lowercase()
+            == path_component.as_os_str().to_string_lossy().to_lowercase();
+        if !same {
+            return None;
+        }
+    }
+    Some(path_components.as_path().to_path_buf())
+}
+
+fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes(buffer[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buffer: &[u8], offset: usize) -> u64 {
+    u64::from_ne_bytes(buffer[offset..offset + 8].try_into().unwrap())
+}
+
+fn decode_utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2)
+        .map(|unit| u16::from_ne_bytes([unit[0], unit[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// A change covering the whole source tree.
+fn rescan_all() -> Vec<SourceChange> {
+    vec![SourceChange::rescan(ShadowPath::from("/"))]
+}
+
+fn to_shadow_error(error: WindowsError) -> ShadowError {
+    platform_error(ErrorPlatform::Windows, error.to_string(), None)
+}
+
+/// A running watch thread.
+struct Worker {
+    stop: Arc<AtomicBool>,
+    port: Option<windows::Win32::Foundation::HANDLE>,
+    thread: JoinHandle<()>,
+}
+
+/// [`SourceWatcher`] for Windows source directories.
+pub struct WindowsSourceWatcher {
+    source_root: PathBuf,
+    config: SourceWatchConfig,
+    worker: Option<Worker>,
+}
+
+impl WindowsSourceWatcher {
+    /// Creates a watcher for `source_root`.
+    pub fn new(source_root: impl Into<PathBuf>, config: SourceWatchConfig) -> Self {
+        Self {
+            source_root: source_root.into(),
+            config,
+            worker: None,
+        }
+    }
+    
+    /// Returns the backend changes are read from.
+    pub fn backend(&self) -> SourceWatchBackend {
+        self.config.backend
+    }
+    
+    /// Starts watching, like [`SourceWatcher::start`] but keeping the Windows error.
+    pub fn start_watching(&mut self, sink: Arc<dyn SourceChangeSink>) -> Result<(), WindowsError> {
+        SourceWatcher::stop(self);
+        let worker = match self.config.backend {
+            SourceWatchBackend::ReadDirectoryChanges => self.start_directory_changes(sink)?,
+            SourceWatchBackend::UsnJournal => self.start_usn_journal(sink)?,
+        };
+        
+        log::info!("Watching {} with {:?}", self.source_root.display(), self.config.backend);
+        self.worker = Some(worker);
+        Ok(())
+    }
+    
+    fn start_directory_changes(&self, sink: Arc<dyn SourceChangeSink>) -> Result<Worker, WindowsError> {
+        use std::os::windows::ffi::OsStrExt;
+        use windows::core::PCWSTR;
+        use windows::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+        use windows::Win32::Storage::FileSystem::{
+            CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OVERLAPPED, FILE_LIST_DIRECTORY,
+            FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+        };
+        use windows::Win32::System::IO::CreateIoCompletionPort;
+        
+        let wide_path: Vec<u16> = self.source_root.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+        let (directory, port) = unsafe {
+            let directory = CreateFileW(
+                PCWSTR(wide_path.as_ptr()),
+                FILE_LIST_DIRECTORY.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OVERLAPPED,
+                None,
+            )?;
+            let port = match CreateIoCompletionPort(INVALID_HANDLE_VALUE, None, 0, 1) {
+                Ok(port) => port,
+                Err(e) => {
+                    let _ = CloseHandle(directory);
+                    return Err(e.into());
+                }
+            };
+            if let Err(e) = CreateIoCompletionPort(directory, port, 0, 0) {
+                let _ = CloseHandle(directory);
+                let _ = CloseHandle(port);
+                return Err(e.into());
+            }
+            (directory, port)
+        };
+        
+        let stop = Arc::new(AtomicBool::new(false));
+        let source_root = self.source_root.clone();
+        let buffer_size = self.config.buffer_size;
+        let (directory_raw, port_raw) = (directory.0, port.0);
+        let thread = std::thread::Builder::new()
+            .name("shadowfs-source-watch".to_string())
+            .spawn(move || {
+                use windows::Win32::Foundation::HANDLE;
+                run_directory_changes(&source_root, HANDLE(directory_raw), HANDLE(port_raw), buffer_size, sink.as_ref());
+            })
+            .map_err(|e| WindowsError::ThreadCreation(e.to_string()))?;
+        
+        Ok(Worker {
+            stop,
+            port: Some(port),
+            thread,
+        })
+    }
+    
+    fn start_usn_journal(&self, sink: Arc<dyn SourceChangeSink>) -> Result<Worker, WindowsError> {
+        use std::os::windows::ffi::OsStrExt;
+        use windows::core::PCWSTR;
+        use windows::Win32::Storage::FileSystem::{
+            CreateFileW, FILE_GENERIC_READ, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+            FILE_FLAGS_AND_ATTRIBUTES, OPEN_EXISTING,
+        };
+        
+        // Paths resolved from the journal are in their final, verbatim form
+        let source_root = std::fs::canonicalize(&self.source_root)?;
+        let volume = volume_device_path(&source_root).ok_or_else(|| WindowsError::Unsupported {
+            message: format!(
+                "the change journal can only be read for sources on a local drive, not {}",
+                self.source_root.display()
+            ),
+        })?;
+        
+        let wide_volume: Vec<u16> = volume.encode_utf16().chain(std::iter::once(0)).collect();
+        let handle = unsafe {
+            CreateFileW(
+                PCWSTR(wide_volume.as_ptr()),
+                FILE_GENERIC_READ.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                OPEN_EXISTING,
+                FILE_FLAGS_AND_ATTRIBUTES(0),
+                None,
+            )
+            .map_err(|e| WindowsError::AccessDenied {
+                message: format!("cannot open {} to read its change journal: {}", volume, e.message()),
+            })?
+        };
+        let journal = match query_journal(handle) {
+            Ok(journal) => journal,
+            Err(code) => {
+                close_handle(handle);
+                return Err(WindowsError::Unsupported {
+                    message: format!("the change journal of {} is not available (error {})", volume, code),
+                });
+            }
+        };
+        
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker_stop = Arc::clone(&stop);
+        let poll_interval = self.config.poll_interval;
+        let handle_raw = handle.0;
+        let thread = std::thread::Builder::new()
+            .name("shadowfs-source-watch".to_string())
+            .spawn(move || {
+                let reader = UsnReader {
+                    volume: windows::Win32::Foundation::HANDLE(handle_raw),
+                    source_root,
+                    parents: HashMap::new(),
+                };
+                reader.run(journal, poll_interval, &worker_stop, sink.as_ref());
+            })
+            .map_err(|e| WindowsError::ThreadCreation(e.to_string()))?;
+        
+        Ok(Worker {
+            stop,
+            port: None,
+            thread,
+        })
+    }
+}
+
+impl SourceWatcher for WindowsSourceWatcher {
+    fn start(&mut self, sink: Arc<dyn SourceChangeSink>) -> Result<(), ShadowError> {
+        self.start_watching(sink).map_err(to_shadow_error)
+    }
+    
+    fn stop(&mut self) {
+        use windows::Win32::System::IO::PostQueuedCompletionStatus;
+        
+        let Some(worker) = self.worker.take() else {
+            return;
+        };
+        worker.stop.store(true, Ordering::Release);
+        if let Some(port) = worker.port {
+            unsafe {
+                let _ = PostQueuedCompletionStatus(port, 0, SHUTDOWN_KEY, None);
+            }
+        }
+        let _ = worker.thread.join();
+        if let Some(port) = worker.port {
+            close_handle(port);
+        }
+    }
+    
+    fn is_running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|worker| !worker.thread.is_finished())
+    }
+}
+
+impl Drop for WindowsSourceWatcher {
+    fn drop(&mut self) {
+        SourceWatcher::stop(self);
+    }
+}
+
+impl std::fmt::Debug for WindowsSourceWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WindowsSourceWatcher")
+            .field("source_root", &self.source_root)
+            .field("backend", &self.config.backend)
+            .field("running", &self.is_running())
+            .finish()
+    }
+}
+
+fn close_handle(handle: windows::Win32::Foundation::HANDLE) {
+    unsafe {
+        let _ = windows::Win32::Foundation::CloseHandle(handle);
+    }
+}
+
+/// Returns the `\\.\X:` device path of the drive `path` is on.
+fn volume_device_path(path: &Path) -> Option<String> {
+    match path.components().next()? {
+        Component::Prefix(prefix) => match prefix.kind() {
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                Some(format!("\\\\.\\{}:", letter as char))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Reads directory changes until the watcher is stopped.
+fn run_directory_changes(
+    source_root: &Path,
+    directory: windows::Win32::Foundation::HANDLE,
+    port: windows::Win32::Foundation::HANDLE,
+    buffer_size: usize,
+    sink: &dyn SourceChangeSink,
+) {
+    use windows::Win32::Foundation::GetLastError;
+    use windows::Win32::Storage::FileSystem::{
+        ReadDirectoryChangesW, FILE_NOTIFY_CHANGE_ATTRIBUTES, FILE_NOTIFY_CHANGE_CREATION,
+        FILE_NOTIFY_CHANGE_DIR_NAME, FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE,
+        FILE_NOTIFY_CHANGE_SIZE,
+    };
+    use windows::Win32::System::IO::{
+        CancelIoEx, GetOverlappedResult, GetQueuedCompletionStatus, OVERLAPPED,
+    };
+    
+    // The records are DWORD aligned
+    let mut buffer = vec![0u32; buffer_size.div_ceil(4)];
+    let mut overlapped = OVERLAPPED::default();
+    let filter = FILE_NOTIFY_CHANGE_FILE_NAME
+        | FILE_NOTIFY_CHANGE_DIR_NAME
+        | FILE_NOTIFY_CHANGE_ATTRIBUTES
+        | FILE_NOTIFY_CHANGE_SIZE
+        | FILE_NOTIFY_CHANGE_LAST_WRITE
+        | FILE_NOTIFY_CHANGE_CREATION;
+    
+    loop {
+        let issued = unsafe {
+            ReadDirectoryChangesW(
+                directory,
+                buffer.as_mut_ptr() as *mut _,
+                (buffer.len() * 4) as u32,
+                true,
+                filter,
+                None,
+                Some(&mut overlapped as *mut _),
+                None,
+            )
+        };
+        if !issued.as_bool() {
+            log::warn!(
+                "Stopped watching {}: ReadDirectoryChangesW failed with error {}",
+                source_root.display(),
+                unsafe { GetLastError().0 }
+            );
+            break;
+        }
+        
+        let mut bytes = 0u32;
+        let mut key = 0usize;
+        let mut completed: *mut OVERLAPPED = std::ptr::null_mut();
+        let result = unsafe {
+            GetQueuedCompletionStatus(port, &mut bytes, &mut key, &mut completed, u32::MAX)
+        };
+        
+        if key == SHUTDOWN_KEY {
+            // The kernel owns the buffer until the cancelled read completes
+            unsafe {
+                let _ = CancelIoEx(directory, Some(&overlapped as *const _));
+                let _ = GetOverlappedResult(directory, &overlapped, &mut bytes, true);
+            }
+            break;
+        }
+        
+        let changes = if !result.as_bool() {
+            match unsafe { GetLastError().0 } {
+                ERROR_OPERATION_ABORTED => break,
+                ERROR_NOTIFY_ENUM_DIR => {
+                    log::debug!("Change buffer for {} overflowed; rescanning", source_root.display());
+                    rescan_all()
+                }
+                code => {
+                    log::warn!("Watching {} failed with error {}; rescanning", source_root.display(), code);
+                    rescan_all()
+                }
+            }
+        } else if bytes == 0 {
+            // The kernel had more changes than fit in the buffer and dropped them all
+            log::debug!("Change buffer for {} overflowed; rescanning", source_root.display());
+            rescan_all()
+        } else {
+            let bytes = (bytes as usize).min(buffer.len() * 4);
+            let raw = unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, bytes) };
+            translate_changes(raw_changes_from_notify(source_root, parse_notify_buffer(raw)))
+        };
+        
+        if !changes.is_empty() {
+            sink.on_source_changes(changes);
+        }
+    }
+    
+    close_handle(directory);
+}
+
+/// Input buffer of `FSCTL_READ_USN_JOURNAL`
+#[repr(C)]
+#[derive(Default)]
+struct ReadUsnJournalData {
+    start_usn: i64,
+    reason_mask: u32,
+    return_only_on_close: u32,
+    timeout: u64,
+    bytes_to_wait_for: u64,
+    usn_journal_id: u64,
+}
+
+/// Output buffer of `FSCTL_QUERY_USN_JOURNAL`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct UsnJournalData {
+    usn_journal_id: u64,
+    first_usn: i64,
+    next_usn: i64,
+    lowest_valid_usn: i64,
+    max_usn: i64,
+    maximum_size: u64,
+    allocation_delta: u64,
+}
+
+fn query_journal(volume: windows::Win32::Foundation::HANDLE) -> Result<UsnJournalData, u32> {
+    use windows::Win32::Foundation::GetLastError;
+    use windows::Win32::System::IO::DeviceIoControl;
+    
+    let mut journal = UsnJournalData::default();
+    let mut returned = 0u32;
+    let succeeded = unsafe {
+        DeviceIoControl(
+            volume,
+            FSCTL_QUERY_USN_JOURNAL,
+            None,
+            0,
+            Some(&mut journal as *mut _ as *mut _),
+            std::mem::size_of::<UsnJournalData>() as u32,
+            Some(&mut returned as *mut _),
+            None,
+        )
+    };
+    if succeeded.as_bool() {
+        Ok(journal)
+    } else {
+        Err(unsafe { GetLastError().0 })
+    }
+}
+
+/// Reads the change journal and resolves its records to source paths.
+struct UsnReader {
+    volume: windows::Win32::Foundation::HANDLE,
+    source_root: PathBuf,
+    
+    /// Paths of parent directories by file reference; `None` for directories outside the source
+    parents: HashMap<u64, Option<PathBuf>>,
+}
+
+impl UsnReader {
+    fn run(mut self, mut journal: UsnJournalData, poll_interval: Duration, stop: &AtomicBool, sink: &dyn SourceChangeSink) {
+        let mut next_usn = journal.next_usn;
+        let mut buffer = vec![0u64; USN_BUFFER_SIZE / 8];
+        
+        while !stop.load(Ordering::Acquire) {
+            let bytes = match self.read(&journal, next_usn, &mut buffer) {
+                Ok(bytes) => bytes,
+                Err(code) => {
+                    match code {
+                        ERROR_JOURNAL_ENTRY_DELETED => log::warn!(
+                            "Change journal records for {} were overwritten before they were read; rescanning",
+                            self.source_root.display()
+                        ),
+                        ERROR_JOURNAL_NOT_ACTIVE | ERROR_JOURNAL_DELETE_IN_PROGRESS => log::warn!(
+                            "Change journal for {} was disabled; rescanning",
+                            self.source_root.display()
+                        ),
+                        code => log::warn!(
+                            "Reading the change journal for {} failed with error {}; rescanning",
+                            self.source_root.display(),
+                            code
+                        ),
+                    }
+                    sink.on_source_changes(rescan_all());
+                    self.parents.clear();
+                    
+                    // Start over from the current end of the journal, which may have been recreated
+                    match query_journal(self.volume) {
+                        Ok(current) => {
+                            journal = current;
+                            next_usn = current.next_usn;
+                        }
+                        Err(_) => std::thread::sleep(poll_interval),
+                    }
+                    continue;
+                }
+            };
+            
+            let raw = unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, bytes) };
+            let (next, records) = parse_usn_buffer(raw);
+            next_usn = next;
+            if records.is_empty() {
+                std::thread::sleep(poll_interval);
+                continue;
+            }
+            
+            let raw_changes = self.raw_changes(records);
+            let changes = translate_changes(raw_changes);
+            if !changes.is_empty() {
+                sink.on_source_changes(changes);
+            }
+        }
+        
+        close_handle(self.volume);
+    }
+    
+    fn read(&self, journal: &UsnJournalData, start_usn: i64, buffer: &mut [u64]) -> Result<usize, u32> {
+        use windows::Win32::Foundation::GetLastError;
+        use windows::Win32::System::IO::DeviceIoControl;
+        
+        let input = ReadUsnJournalData {
+            start_usn,
+            reason_mask: u32::MAX,
+            return_only_on_close: 0,
+            timeout: 0,
+            bytes_to_wait_for: 0,
+            usn_journal_id: journal.usn_journal_id,
+        };
+        let mut returned = 0u32;
+        let succeeded = unsafe {
+            DeviceIoControl(
+                self.volume,
+                FSCTL_READ_USN_JOURNAL,
+                Some(&input as *const _ as *const _),
+                std::mem::size_of::<ReadUsnJournalData>() as u32,
+                Some(buffer.as_mut_ptr() as *mut _),
+                (buffer.len() * 8) as u32,
+                Some(&mut returned as *mut _),
+                None,
+            )
+        };
+        if succeeded.as_bool() {
+            Ok(returned as usize)
+        } else {
+            Err(unsafe { GetLastError().0 })
+        }
+    }
+    
+    /// Keeps the records under the source root as raw changes.
+    fn raw_changes(&mut self, records: Vec<UsnRecord>) -> Vec<RawChange> {
+        let mut changes = Vec::new();
+        for record in records {
+            let Some(action) = record.action() else {
+                continue;
+            };
+            // Renaming or deleting a directory changes the paths of everything below it
+            if record.is_directory() && action != RawAction::Added && action != RawAction::Modified {
+                self.parents.clear();
+            }
+            let Some(parent) = self.parent_path(record.parent_reference) else {
+                continue;
+            };
+            changes.push(RawChange::new(action, parent.join(&record.name), record.is_directory()));
+        }
+        changes
+    }
+    
+    /// Resolves a parent directory to its path relative to the source root.
+    fn parent_path(&mut self, reference: u64) -> Option<PathBuf> {
+        if let Some(cached) = self.parents.get(&reference) {
+            return cached.clone();
+        }
+        let relative = self.resolve_reference(reference)
+            .and_then(|path| strip_root(&self.source_root, &path));
+        self.parents.insert(reference, relative.clone());
+        relative
+    }
+    
+    fn resolve_reference(&self, reference: u64) -> Option<PathBuf> {
+        use windows::Win32::Storage::FileSystem::{
+            GetFinalPathNameByHandleW, OpenFileById, FileIdType, FILE_FLAG_BACKUP_SEMANTICS,
+            FILE_ID_DESCRIPTOR, FILE_ID_DESCRIPTOR_0, FILE_READ_ATTRIBUTES, FILE_SHARE_DELETE,
+            FILE_SHARE_READ, FILE_SHARE_WRITE,
+        };
+        
+        let descriptor = FILE_ID_DESCRIPTOR {
+            dwSize: std::mem::size_of::<FILE_ID_DESCRIPTOR>() as u32,
+            Type: FileIdType,
+            Anonymous: FILE_ID_DESCRIPTOR_0 { FileId: reference as i64 },
+        };
+        unsafe {
+            let handle = OpenFileById(
+                self.volume,
+                &descriptor,
+                FILE_READ_ATTRIBUTES,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                FILE_FLAG_BACKUP_SEMANTICS,
+            )
+            .ok()?;
+            let mut path = vec![0u16; 1024];
+            let length = GetFinalPathNameByHandleW(handle, &mut path, Default::default()) as usize;
+            close_handle(handle);
+            if length == 0 || length > path.len() {
+                return None;
+            }
+            Some(PathBuf::from(String::from_utf16_lossy(&path[..length])))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn notify_record(action: u32, name: &str, last: bool) -> Vec<u8> {
+        let name: Vec<u8> = name.encode_utf16().flat_map(|unit| unit.to_ne_bytes()).collect();
+        let length = (NOTIFY_HEADER_SIZE + name.len()).div_ceil(4) * 4;
+        let mut record = Vec::with_capacity(length);
+        record.extend_from_slice(&(if last { 0 } else { length as u32 }).to_ne_bytes());
+        record.extend_from_slice(&action.to_ne_bytes());
+        record.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+        record.extend_from_slice(&name);
+        record.resize(length, 0);
+        record
+    }
+    
+    fn raw(action: RawAction, path: &str) -> RawChange {
+        RawChange::new(action, PathBuf::from(path), false)
+    }
+    
+    #[test]
+    fn test_parse_notify_buffer() {
+        let mut buffer = notify_record(FILE_ACTION_MODIFIED, "src\\main.rs", false);
+        buffer.extend(notify_record(FILE_ACTION_RENAMED_OLD_NAME, "a.txt", false));
+        buffer.extend(notify_record(FILE_ACTION_RENAMED_NEW_NAME, "b.txt", true));
+        
+        let records = parse_notify_buffer(&buffer);
+        assert_eq!(records, vec![
+            (FILE_ACTION_MODIFIED, PathBuf::from("src\\main.rs")),
+            (FILE_ACTION_RENAMED_OLD_NAME, PathBuf::from("a.txt")),
+            (FILE_ACTION_RENAMED_NEW_NAME, PathBuf::from("b.txt")),
+        ]);
+        // A truncated buffer yields the complete records only
+        assert_eq!(parse_notify_buffer(&buffer[..buffer.len() - 4]).len(), 2);
+    }
+    
+    #[test]
+    fn test_translate_pairs_renames_and_coalesces() {
+        let changes = translate_changes(vec![
+            raw(RawAction::Modified, "a.txt"),
+            raw(RawAction::Modified, "a.txt"),
+            raw(RawAction::RenamedFrom, "a.txt"),
+            raw(RawAction::RenamedTo, "b.txt"),
+            raw(RawAction::RenamedFrom, "moved-out.txt"),
+            raw(RawAction::Added, "c.txt"),
+            raw(RawAction::RenamedTo, "moved-in.txt"),
+        ]);
+        
+        let kinds: Vec<(String, ChangeKind)> = changes.into_iter().map(|c| (c.path.to_string(), c.kind)).collect();
+        assert_eq!(kinds, vec![
+            ("/a.txt".to_string(), ChangeKind::Modified),
+            ("/b.txt".to_string(), ChangeKind::Renamed { from: ShadowPath::from("/a.txt") }),
+            ("/moved-out.txt".to_string(), ChangeKind::Deleted),
+            ("/c.txt".to_string(), ChangeKind::Created),
+            ("/moved-in.txt".to_string(), ChangeKind::Created),
+        ]);
+    }
+    
+    #[test]
+    fn test_parse_usn_records() {
+        fn record(reference: u64, parent: u64, reason: u32, attributes: u32, name: &str) -> Vec<u8> {
+            let name: Vec<u8> = name.encode_utf16().flat_map(|unit| unit.to_ne_bytes()).collect();
+            let length = (USN_RECORD_V2_HEADER_SIZE + name.len()).div_ceil(8) * 8;
+            let mut record = vec![0u8; length];
+            record[0..4].copy_from_slice(&(length as u32).to_ne_bytes());
+            record[4..6].copy_from_slice(&2u16.to_ne_bytes());
+            record[8..16].copy_from_slice(&reference.to_ne_bytes());
+            record[16..24].copy_from_slice(&parent.to_ne_bytes());
+            record[40..44].copy_from_slice(&reason.to_ne_bytes());
+            record[52..56].copy_from_slice(&attributes.to_ne_bytes());
+            record[56..58].copy_from_slice(&(name.len() as u16).to_ne_bytes());
+            record[58..60].copy_from_slice(&(USN_RECORD_V2_HEADER_SIZE as u16).to_ne_bytes());
+            record[USN_RECORD_V2_HEADER_SIZE..USN_RECORD_V2_HEADER_SIZE + name.len()].copy_from_slice(&name);
+            record
+        }
+        
+        let mut buffer = 4096i64.to_ne_bytes().to_vec();
+        buffer.extend(record(7, 5, USN_REASON_DATA_EXTEND, 0x20, "notes.txt"));
+        buffer.extend(record(7, 5, USN_REASON_DATA_EXTEND | USN_REASON_CLOSE, 0x20, "notes.txt"));
+        buffer.extend(record(9, 5, USN_REASON_RENAME_OLD_NAME, FILE_ATTRIBUTE_DIRECTORY, "old"));
+        buffer.extend(record(9, 5, USN_REASON_RENAME_NEW_NAME | USN_REASON_CLOSE, FILE_ATTRIBUTE_DIRECTORY, "new"));
+        buffer.extend(record(11, 5, USN_REASON_FILE_CREATE | USN_REASON_FILE_DELETE | USN_REASON_CLOSE, 0x20, "~tmp"));
+        
+        let (next_usn, records) = parse_usn_buffer(&buffer);
+        assert_eq!(next_usn, 4096);
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0].name, "notes.txt");
+        assert_eq!(records[0].parent_reference, 5);
+        
+        let actions: Vec<Option<RawAction>> = records.iter().map(UsnRecord::action).collect();
+        assert_eq!(actions, vec![
+            None,
+            Some(RawAction::Modified),
+            Some(RawAction::RenamedFrom),
+            None,
+            None,
+        ]);
+        assert!(records[2].is_directory());
+    }
+    
+    #[test]
+    fn test_strip_root_ignores_case() {
+        let root = Path::new(r"\\?\C:\Users\dev\Project");
+        assert_eq!(
+            strip_root(root, Path::new(r"\\?\C:\users\DEV\project\src")),
+            Some(PathBuf::from("src"))
+        );
+        assert_eq!(strip_root(root, Path::new(r"\\?\C:\Users\dev\Project")), Some(PathBuf::new()));
+        assert_eq!(strip_root(root, Path::new(r"\\?\C:\Users\other")), None);
+        assert_eq!(volume_device_path(root).as_deref(), Some(r"\\.\C:"));
+    }
+}
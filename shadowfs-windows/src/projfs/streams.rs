@@ -0,0 +1,99 @@
+//! NTFS named streams of projected files.
+//!
+//! ProjFS hands stream paths to the provider like any other path: a
+//! placeholder request or a read for `notes.txt:meta` is for the `meta`
+//! stream of `notes.txt`. The provider turns named streams on in its
+//! override store, so the override of a stream is found under that same
+//! path, and the source side of a stream is reached through it below the
+//! source root, which NTFS opens as the stream. The streams of a file the
+//! overrides delete, or the mount hides, are missing whatever the source
+//! holds.
+//!
+//! [`source_streams`] lists the named streams of a source file, and
+//! [`ProjFSProvider::metadata`](super::ProjFSProvider::metadata) reports
+//! them, merged with the stream overrides, in the platform metadata of the
+//! file.
+
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use shadowfs_core::types::NamedStream;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::ERROR_HANDLE_EOF;
+use windows::Win32::Storage::FileSystem::{
+    FindClose, FindFileHandle, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard, WIN32_FIND_STREAM_DATA,
+};
+
+/// Lists the named streams of the file or directory at `path`, sorted by
+/// name. The unnamed stream, the content of the file, is left out.
+pub fn source_streams(path: &Path) -> windows::core::Result<Vec<NamedStream>> {
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+    let mut streams = Vec::new();
+    
+    unsafe {
+        let handle = match FindFirstStreamW(
+            PCWSTR(wide_path.as_ptr()),
+            FindStreamInfoStandard,
+            &mut data as *mut _ as *mut _,
+            0,
+        ) {
+            Ok(handle) => handle,
+            // Directories without named streams have no stream at all
+            Err(e) if e.code() == ERROR_HANDLE_EOF.to_hresult() => return Ok(streams),
+            Err(e) => return Err(e),
+        };
+        loop {
+            let len = data.cStreamName.iter().position(|&unit| unit == 0).unwrap_or(data.cStreamName.len());
+            let raw_name = String::from_utf16_lossy(&data.cStreamName[..len]);
+            if let Some(name) = stream_name(&raw_name) {
+                streams.push(NamedStream { name: name.to_string(), size: data.StreamSize as u64 });
+            }
+            if !FindNextStreamW(handle, &mut data as *mut _ as *mut _).as_bool() {
+                break;
+            }
+        }
+        let _ = FindClose(FindFileHandle(handle.0));
+    }
+    
+    streams.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(streams)
+}
+
+/// Returns the name of a stream as `FindFirstStreamW` reports it,
+/// `:meta:$DATA`, or `None` for the unnamed stream `::$DATA`.
+fn stream_name(raw_name: &str) -> Option<&str> {
+    let name = raw_name.strip_prefix(':')?;
+    let name = name.strip_suffix(":$DATA").unwrap_or(name);
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_stream_names() {
+        assert_eq!(stream_name(":Zone.Identifier:$DATA"), Some("Zone.Identifier"));
+        assert_eq!(stream_name("::$DATA"), None);
+        assert_eq!(stream_name(""), None);
+    }
+    
+    #[test]
+    fn test_source_streams_are_listed() {
+        let source = TempDir::new().unwrap();
+        let file = source.path().join("setup.exe");
+        std::fs::write(&file, "MZ").unwrap();
+        assert!(source_streams(&file).unwrap().is_empty());
+        
+        // NTFS opens the stream path as the stream
+        let mut stream_path = file.clone().into_os_string();
+        stream_path.push(":Zone.Identifier");
+        std::fs::write(&stream_path, "[ZoneTransfer]").unwrap();
+        assert_eq!(
+            source_streams(&file).unwrap(),
+            [NamedStream { name: "Zone.Identifier".to_string(), size: 14 }]
+        );
+        assert!(source_streams(source.path()).unwrap().is_empty());
+    }
+}
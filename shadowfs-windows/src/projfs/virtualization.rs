@@ -254,6 +254,9 @@ impl VirtualizationRoot {
             });
         }
         
+        // ProjFS placeholders cannot be nested inside a cloud files sync root
+        super::cloud_files::check_virtualization_root(path)?;
+        
         // Check if directory is empty or only contains compatible content
         let entries = fs::read_dir(path).map_err(|e| {
             WindowsError::IoError {
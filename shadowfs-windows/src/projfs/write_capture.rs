@@ -0,0 +1,192 @@
+//! Capture of writes under the virtualization root into the override store.
+//!
+//! ProjFS serves reads through callbacks, but writes go straight to the
+//! files under the virtualization root, which ProjFS stops projecting from
+//! then on. The provider registers for the post-operation notifications in
+//! [`CAPTURED_NOTIFICATIONS`] and [`WriteCapture`] turns each one into
+//! override store entries, so the store holds every change made through the
+//! projection and diffs, commits and exports see them. The source directory
+//! is never written.
+//!
+//! Modified files are read back from the virtualization root once their
+//! last handle closes, so a file written in many small writes is captured
+//! once. Files are read back only after ProjFS has made them full files,
+//! so capturing never calls back into the provider.
+
+use bytes::Bytes;
+use shadowfs_core::error::ShadowError;
+use shadowfs_core::mount_paths::{self, relative_shadow_path};
+use shadowfs_core::override_store::{OverrideStore, RenameOptions};
+use std::path::Path;
+use windows::Win32::Storage::ProjectedFileSystem::{
+    PRJ_NOTIFICATION, PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_DELETED,
+    PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_MODIFIED, PRJ_NOTIFICATION_FILE_OVERWRITTEN,
+    PRJ_NOTIFICATION_FILE_RENAMED, PRJ_NOTIFICATION_HARDLINK_CREATED, PRJ_NOTIFICATION_NEW_FILE_CREATED,
+    PRJ_NOTIFY_FILE_HANDLE_CLOSED_FILE_DELETED, PRJ_NOTIFY_FILE_HANDLE_CLOSED_FILE_MODIFIED,
+    PRJ_NOTIFY_FILE_OVERWRITTEN, PRJ_NOTIFY_FILE_RENAMED, PRJ_NOTIFY_HARDLINK_CREATED,
+    PRJ_NOTIFY_NEW_FILE_CREATED, PRJ_NOTIFY_TYPES,
+};
+
+/// Notifications the provider registers for on the virtualization root to capture writes
+pub const CAPTURED_NOTIFICATIONS: PRJ_NOTIFY_TYPES = PRJ_NOTIFY_TYPES(
+    PRJ_NOTIFY_NEW_FILE_CREATED.0
+        | PRJ_NOTIFY_FILE_OVERWRITTEN.0
+        | PRJ_NOTIFY_FILE_HANDLE_CLOSED_FILE_MODIFIED.0
+        | PRJ_NOTIFY_FILE_HANDLE_CLOSED_FILE_DELETED.0
+        | PRJ_NOTIFY_FILE_RENAMED.0
+        | PRJ_NOTIFY_HARDLINK_CREATED.0,
+);
+
+/// Converts post-operation notifications into override store entries
+pub struct WriteCapture<'a> {
+    store: &'a OverrideStore,
+    source_root: &'a Path,
+    virtualization_root: &'a Path,
+}
+
+impl<'a> WriteCapture<'a> {
+    /// Creates a capture writing into `store` for a projection of
+    /// `source_root` at `virtualization_root`
+    pub fn new(store: &'a OverrideStore, source_root: &'a Path, virtualization_root: &'a Path) -> Self {
+        Self { store, source_root, virtualization_root }
+    }
+    
+    /// Records the change a notification reports in the override store
+    ///
+    /// # Arguments
+    /// * `notification` - Notification type received by the notification callback
+    /// * `relative_path` - Path the notification is about, relative to the virtualization root
+    /// * `destination` - New path for renames and hard links
+    /// * `is_directory` - Whether the path is a directory
+    ///
+    /// # Returns
+    /// Whether the notification changed the store; notifications that do
+    /// not write, such as file opens, are ignored
+    pub fn capture(
+        &self,
+        notification: PRJ_NOTIFICATION,
+        relative_path: &Path,
+        destination: Option<&Path>,
+        is_directory: bool,
+    ) -> Result<bool, ShadowError> {
+        let path = relative_shadow_path(relative_path);
+        match notification {
+            PRJ_NOTIFICATION_NEW_FILE_CREATED => self.capture_tree(relative_path, is_directory)?,
+            PRJ_NOTIFICATION_FILE_OVERWRITTEN | PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_MODIFIED => {
+                if is_directory {
+                    return Ok(false);
+                }
+                self.capture_file(relative_path)?;
+            }
+            PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_DELETED => self.store.mark_deleted(path)?,
+            PRJ_NOTIFICATION_FILE_RENAMED => {
+                let Some(destination) = destination else {
+                    return Ok(false);
+                };
+                // Renames into the virtualization root from outside arrive without a source path
+                if relative_path.as_os_str().is_empty() {
+                    self.capture_tree(destination, is_directory)?;
+                    return Ok(true);
+                }
+                let options = RenameOptions::with_source(self.source_root);
+                if let Err(e) = self.store.rename(&path, relative_shadow_path(destination), options) {
+                    // The virtualization root already holds the result, so take it from there
+                    log::debug!("Capturing rename of {} from the projection: {}", path, e);
+                    self.capture_tree(destination, is_directory)?;
+                    self.store.mark_deleted(path)?;
+                }
+            }
+            PRJ_NOTIFICATION_HARDLINK_CREATED => {
+                let Some(destination) = destination else {
+                    return Ok(false);
+                };
+                let held = self.store.get(&path).is_some_and(|entry| !entry.is_deleted() && !entry.is_directory());
+                if !held {
+                    self.capture_file(relative_path)?;
+                }
+                self.store.link(&path, relative_shadow_path(destination))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+    
+    /// Stores the current content of the file at `relative_path` in the
+    /// virtualization root, keeping the source metadata already recorded
+    fn capture_file(&self, relative_path: &Path) -> Result<(), ShadowError> {
+        let path = relative_shadow_path(relative_path);
+        let content = std::fs::read(mount_paths::source_path(&self.virtualization_root, &path))
+            .map_err(|e| ShadowError::from_io_error_with_operation(e, &path, "capture"))?;
+        let original_metadata = self.store.get(&path).and_then(|entry| entry.original_metadata.clone());
+        self.store.insert_file(path, Bytes::from(content), original_metadata)
+    }
+    
+    /// Stores the file or directory tree at `relative_path` in the virtualization root
+    fn capture_tree(&self, relative_path: &Path, is_directory: bool) -> Result<(), ShadowError> {
+        if !is_directory {
+            return self.capture_file(relative_path);
+        }
+        
+        let mut pending = vec![relative_path.to_path_buf()];
+        while let Some(directory) = pending.pop() {
+            let path = relative_shadow_path(&directory);
+            if !self.store.get(&path).is_some_and(|entry| entry.is_directory()) {
+                self.store.insert_directory(path.clone(), None)?;
+            }
+            let entries = std::fs::read_dir(mount_paths::source_path(&self.virtualization_root, &path))
+                .map_err(|e| ShadowError::from_io_error_with_operation(e, &path, "capture"))?;
+            for entry in entries {
+                let entry = entry?;
+                let child = directory.join(entry.file_name());
+                if entry.file_type()?.is_dir() {
+                    pending.push(child);
+                } else {
+                    self.capture_file(&child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shadowfs_core::types::ShadowPath;
+    use tempfile::TempDir;
+    
+    fn file_data(store: &OverrideStore, path: &str) -> Option<Bytes> {
+        store.get(&ShadowPath::from(path))
+            .filter(|entry| !entry.is_deleted())
+            .and_then(|entry| entry.get_file_data().unwrap())
+    }
+    
+    #[test]
+    fn test_capture_writes() {
+        let source = TempDir::new().unwrap();
+        let root = TempDir::new().unwrap();
+        std::fs::write(source.path().join("a.txt"), "source").unwrap();
+        let store = OverrideStore::with_defaults();
+        let capture = WriteCapture::new(&store, source.path(), root.path());
+        
+        std::fs::write(root.path().join("a.txt"), "edited").unwrap();
+        assert!(capture.capture(PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_MODIFIED, Path::new("a.txt"), None, false).unwrap());
+        assert_eq!(file_data(&store, "/a.txt").unwrap(), Bytes::from("edited"));
+        assert_eq!(std::fs::read_to_string(source.path().join("a.txt")).unwrap(), "source");
+        
+        std::fs::create_dir(root.path().join("docs")).unwrap();
+        std::fs::write(root.path().join("docs/notes.md"), "notes").unwrap();
+        capture.capture(PRJ_NOTIFICATION_FILE_RENAMED, Path::new(""), Some(Path::new("docs")), true).unwrap();
+        assert_eq!(file_data(&store, "/docs/notes.md").unwrap(), Bytes::from("notes"));
+        
+        capture.capture(PRJ_NOTIFICATION_FILE_RENAMED, Path::new("a.txt"), Some(Path::new("b.txt")), false).unwrap();
+        assert!(file_data(&store, "/a.txt").is_none());
+        assert_eq!(file_data(&store, "/b.txt").unwrap(), Bytes::from("edited"));
+        
+        capture.capture(PRJ_NOTIFICATION_HARDLINK_CREATED, Path::new("b.txt"), Some(Path::new("c.txt")), false).unwrap();
+        assert_eq!(store.link_count(&ShadowPath::from("/b.txt")), 2);
+        
+        capture.capture(PRJ_NOTIFICATION_FILE_HANDLE_CLOSED_FILE_DELETED, Path::new("c.txt"), None, false).unwrap();
+        assert!(store.get(&ShadowPath::from("/c.txt")).unwrap().is_deleted());
+    }
+}
@@ -0,0 +1,359 @@
+//! Embedding ShadowFS in an application
+//!
+//! [`ShadowFs`] is the single entry point for programs that want a shadow
+//! layer without the provider plumbing of `shadowfs-core`: it owns the
+//! override store over a source directory, mounts it with this platform's
+//! provider when given a mount point, and reads, writes, lists and commits
+//! through it directly.
+//!
+//! ```rust,no_run
+//! use shadowfs::ShadowFs;
+//!
+//! # async fn example() -> Result<(), shadowfs::ShadowError> {
+//! let fs = ShadowFs::builder()
+//!     .source("/path/to/source")
+//!     .mount_at("/path/to/mount")
+//!     .memory_limit(512 * 1024 * 1024)
+//!     .build()
+//!     .await?;
+//!
+//! fs.write("/notes.txt", "draft")?;
+//! assert_eq!(fs.read("/notes.txt")?, "draft");
+//! for entry in fs.list("/")? {
+//!     println!("{}", entry.name);
+//! }
+//! fs.commit()?;
+//! fs.unmount().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Without a mount point nothing is mounted, and the overrides are only
+//! seen through the handle. Mounts are served by FUSE on Linux; other
+//! platforms have no provider yet and fail to mount with
+//! [`ShadowError::Unsupported`] unless one is supplied with
+//! [`ShadowFsBuilder::provider`].
+
+use bytes::Bytes;
+use shadowfs_core::mount_manager::{MountManager, ProviderFactory};
+use shadowfs_core::override_store::{CommitSummary, OverrideStore, OverrideStoreConfig};
+use shadowfs_core::passthrough::MountContext;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub use shadowfs_core::async_file::ShadowFile;
+pub use shadowfs_core::compat::ShadowStdFs;
+pub use shadowfs_core::error::{Result, ShadowError};
+pub use shadowfs_core::override_store::EvictionPolicy;
+pub use shadowfs_core::types::{DirectoryEntry, FileMetadata, MountOptions, OpenFlags, ShadowPath};
+
+/// Builder for a [`ShadowFs`], from [`ShadowFs::builder`].
+#[derive(Default)]
+pub struct ShadowFsBuilder {
+    source: Option<PathBuf>,
+    mount_point: Option<PathBuf>,
+    options: MountOptions,
+    eviction_policy: Option<EvictionPolicy>,
+    provider: Option<ProviderFactory>,
+}
+
+impl ShadowFsBuilder {
+    /// Sets the directory the shadow layer covers; required.
+    pub fn source(mut self, source: impl Into<PathBuf>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+    
+    /// Mounts the shadow layer at `mount_point`, so other processes see it.
+    pub fn mount_at(mut self, mount_point: impl Into<PathBuf>) -> Self {
+        self.mount_point = Some(mount_point.into());
+        self
+    }
+    
+    /// Limits the memory the overrides may use, in bytes.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.options.override_config.max_memory_bytes = bytes;
+        self
+    }
+    
+    /// Refuses every change when `read_only` is true.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.options.read_only = read_only;
+        self
+    }
+    
+    /// Sets which overrides are evicted first once the memory limit is reached.
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.eviction_policy = Some(policy);
+        self
+    }
+    
+    /// Replaces the mount options set so far, including the memory limit
+    /// and read-only flag.
+    pub fn options(mut self, options: MountOptions) -> Self {
+        self.options = options;
+        self
+    }
+    
+    /// Serves the mount with providers from `factory` instead of this
+    /// platform's.
+    pub fn provider(mut self, factory: ProviderFactory) -> Self {
+        self.provider = Some(factory);
+        self
+    }
+    
+    /// Creates the store and mounts it if a mount point was given.
+    ///
+    /// # Errors
+    /// [`ShadowError::InvalidConfiguration`] if no source was given or it is
+    /// not a directory, and whatever mounting fails with.
+    pub async fn build(self) -> Result<ShadowFs> {
+        let source = self.source.ok_or_else(|| ShadowError::InvalidConfiguration {
+            message: "a source directory is required".to_string(),
+        })?;
+        let source = std::fs::canonicalize(&source)
+            .ok()
+            .filter(|source| source.is_dir())
+            .ok_or_else(|| ShadowError::InvalidConfiguration {
+                message: format!("{} is not a directory", source.display()),
+            })?;
+        
+        let defaults = OverrideStoreConfig::default();
+        let store = Arc::new(OverrideStore::new(OverrideStoreConfig {
+            max_memory: self.options.override_config.max_memory_bytes,
+            eviction_policy: self.eviction_policy.unwrap_or(defaults.eviction_policy),
+            ..defaults
+        }));
+        let context = MountContext::new(Arc::clone(&store), &source, self.options.read_only);
+        
+        let Some(mount_point) = self.mount_point else {
+            // The manager applies these to the store when mounting
+            store.set_read_only(self.options.read_only);
+            store.set_case_sensitive(self.options.case_sensitive);
+            store.set_quota(self.options.limits);
+            store.set_path_limits(self.options.path_limits);
+            store.set_atime_policy(self.options.atime);
+            return Ok(ShadowFs { context, mount: None });
+        };
+        let factory = match self.provider {
+            Some(factory) => factory,
+            None => platform_provider()?,
+        };
+        let manager = MountManager::new(store, factory);
+        manager.mount(&source, &mount_point, self.options).await?;
+        Ok(ShadowFs { context, mount: Some((manager, mount_point)) })
+    }
+}
+
+/// Returns the factory creating this platform's provider.
+fn platform_provider() -> Result<ProviderFactory> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Arc::new(|store| {
+            Arc::new(shadowfs_linux::FuseProvider::new(store)) as Arc<dyn shadowfs_core::traits::FileSystemProvider>
+        }))
+    }
+    
+    #[cfg(not(target_os = "linux"))]
+    Err(shadowfs_core::error::unsupported("mounting on this platform"))
+}
+
+/// A shadow layer over a source directory, mounted or held in-process.
+///
+/// Paths are relative to the source, written with a leading `/`. The file
+/// operations block on the source tree, like the platform callbacks do.
+pub struct ShadowFs {
+    context: MountContext,
+    mount: Option<(MountManager, PathBuf)>,
+}
+
+impl ShadowFs {
+    /// Starts building a shadow layer.
+    pub fn builder() -> ShadowFsBuilder {
+        ShadowFsBuilder::default()
+    }
+    
+    /// Returns the source directory the layer covers.
+    pub fn source(&self) -> &Path {
+        &self.context.source
+    }
+    
+    /// Returns where the layer is mounted, if it is.
+    pub fn mount_point(&self) -> Option<&Path> {
+        self.mount.as_ref().map(|(_, mount_point)| mount_point.as_path())
+    }
+    
+    /// Returns the store holding the overrides, for everything the handle
+    /// does not cover.
+    pub fn store(&self) -> &Arc<OverrideStore> {
+        &self.context.store
+    }
+    
+    /// Reads the whole file at `path`, with overrides applied.
+    pub fn read(&self, path: impl Into<ShadowPath>) -> Result<Bytes> {
+        let path = path.into();
+        let size = self.context.metadata(&path)?.size;
+        self.context.read(&path, 0, usize::try_from(size).unwrap_or(usize::MAX))
+    }
+    
+    /// Replaces the content of the file at `path`, creating it if needed;
+    /// the source file is left untouched.
+    pub fn write(&self, path: impl Into<ShadowPath>, content: impl Into<Bytes>) -> Result<()> {
+        self.context.write_file(&path.into(), content.into())
+    }
+    
+    /// Creates the directory `path`.
+    pub fn create_dir(&self, path: impl Into<ShadowPath>) -> Result<()> {
+        self.context.create_directory(&path.into())
+    }
+    
+    /// Deletes the file or empty directory at `path`, hiding it from the
+    /// layer until the next commit removes it from the source.
+    pub fn remove(&self, path: impl Into<ShadowPath>) -> Result<()> {
+        self.context.delete(&path.into())
+    }
+    
+    /// Returns the metadata of `path`.
+    pub fn metadata(&self, path: impl Into<ShadowPath>) -> Result<FileMetadata> {
+        self.context.metadata(&path.into())
+    }
+    
+    /// Returns the metadata of each of `paths`, in order, looked up in one
+    /// batch for callers that stat many files at once.
+    pub fn metadata_many(&self, paths: &[ShadowPath]) -> Vec<Result<FileMetadata>> {
+        self.context.metadata_many(paths)
+    }
+    
+    /// Lists the directory at `path`, sorted by name.
+    pub fn list(&self, path: impl Into<ShadowPath>) -> Result<Vec<DirectoryEntry>> {
+        self.context.read_directory(&path.into())
+    }
+    
+    /// Opens the file at `path` as a Tokio file handle; see
+    /// [`MountContext::open`].
+    pub fn open(&self, path: impl Into<ShadowPath>, flags: OpenFlags) -> Result<ShadowFile> {
+        self.context.open(&path.into(), flags)
+    }
+    
+    /// Returns the layer behind an interface mirroring `std::fs`, for code
+    /// written against std's file functions.
+    pub fn std_fs(&self) -> ShadowStdFs {
+        ShadowStdFs::new(self.context.clone())
+    }
+    
+    /// Writes every override to the source directory.
+    ///
+    /// See [`OverrideStore::commit`] for journaling and backups.
+    pub fn commit(&self) -> Result<CommitSummary> {
+        self.store().commit_to_source(self.source())
+    }
+    
+    /// Unmounts the layer, if it is mounted, and drops the overrides that
+    /// were not committed.
+    pub async fn unmount(self) -> Result<()> {
+        if let Some((manager, mount_point)) = self.mount {
+            manager.unmount(&mount_point).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use shadowfs_core::traits::FileSystemProvider;
+    use shadowfs_core::types::Platform;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+    
+    /// Provider that records mount calls instead of touching the OS.
+    #[derive(Default)]
+    struct RecordingProvider {
+        mounted: Arc<Mutex<Vec<PathBuf>>>,
+    }
+    
+    #[async_trait]
+    impl FileSystemProvider for RecordingProvider {
+        fn platform(&self) -> Platform {
+            Platform::current()
+        }
+        
+        async fn mount(&self, _source: &Path, mount_point: &Path, _options: &MountOptions) -> Result<()> {
+            self.mounted.lock().unwrap().push(mount_point.to_path_buf());
+            Ok(())
+        }
+        
+        async fn unmount(&self, mount_point: &Path) -> Result<()> {
+            self.mounted.lock().unwrap().retain(|p| p != mount_point);
+            Ok(())
+        }
+    }
+    
+    fn source() -> TempDir {
+        let source = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("src")).unwrap();
+        std::fs::write(source.path().join("src/lib.rs"), "pub fn old() {}").unwrap();
+        source
+    }
+    
+    #[tokio::test]
+    async fn test_in_process_layer_reads_writes_and_commits() {
+        let source = source();
+        let fs = ShadowFs::builder().source(source.path()).memory_limit(1 << 20).build().await.unwrap();
+        assert!(fs.mount_point().is_none());
+        
+        assert_eq!(fs.read("/src/lib.rs").unwrap(), "pub fn old() {}");
+        fs.write("/src/lib.rs", "pub fn new() {}").unwrap();
+        fs.create_dir("/docs").unwrap();
+        fs.write("/docs/notes.md", "notes").unwrap();
+        fs.remove("/src/lib.rs").unwrap();
+        fs.write("/src/main.rs", "fn main() {}").unwrap();
+        let sizes: Vec<_> = fs
+            .metadata_many(&["/src/main.rs".into(), "/src/lib.rs".into(), "/docs".into()])
+            .into_iter()
+            .map(|metadata| metadata.ok().map(|metadata| metadata.size))
+            .collect();
+        assert_eq!(sizes[..2], [Some(12), None]);
+        assert!(sizes[2].is_some());
+        let names: Vec<String> = fs.list("/src").unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["main.rs"]);
+        assert_eq!(fs.std_fs().read_to_string("docs/notes.md").unwrap(), "notes");
+        let mut notes = fs.open("/docs/notes.md", OpenFlags::APPEND).unwrap();
+        tokio::io::AsyncWriteExt::write_all(&mut notes, b" and more").await.unwrap();
+        tokio::io::AsyncWriteExt::shutdown(&mut notes).await.unwrap();
+        assert_eq!(fs.read("/docs/notes.md").unwrap(), "notes and more");
+        assert!(source.path().join("src/lib.rs").exists());
+        
+        let summary = fs.commit().unwrap();
+        assert_eq!(summary.paths_removed, 1);
+        assert!(!source.path().join("src/lib.rs").exists());
+        assert_eq!(std::fs::read_to_string(source.path().join("docs/notes.md")).unwrap(), "notes and more");
+        fs.unmount().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_builder_validates_and_mounts() {
+        assert!(matches!(ShadowFs::builder().build().await, Err(ShadowError::InvalidConfiguration { .. })));
+        
+        let source = source();
+        let read_only = ShadowFs::builder().source(source.path()).read_only(true).build().await.unwrap();
+        assert!(read_only.write("/src/lib.rs", "x").is_err());
+        
+        let provider = RecordingProvider::default();
+        let mounted = Arc::clone(&provider.mounted);
+        let provider = Arc::new(provider);
+        let fs = ShadowFs::builder()
+            .source(source.path())
+            .mount_at("/mnt/shadow")
+            .provider(Arc::new(move |_store| Arc::clone(&provider) as Arc<dyn FileSystemProvider>))
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(fs.mount_point(), Some(Path::new("/mnt/shadow")));
+        assert_eq!(*mounted.lock().unwrap(), [PathBuf::from("/mnt/shadow")]);
+        
+        fs.unmount().await.unwrap();
+        assert!(mounted.lock().unwrap().is_empty());
+    }
+}